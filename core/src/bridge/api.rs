@@ -7,6 +7,9 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
+use super::error::{coded, coded_message};
+use crate::error::V8RayErrorCode;
+
 // ============================================================================
 // 数据类型定义
 // ============================================================================
@@ -43,6 +46,696 @@ pub struct ConnectionInfo {
     pub latency_ms: Option<u32>,
 }
 
+/// Xray 实例健康信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceHealthInfo {
+    /// 实例 ID
+    pub instance_id: String,
+    /// 进程状态（stopped / starting / running / stopping / error）
+    pub status: String,
+    /// 错误信息
+    pub error: Option<String>,
+    /// 进程 ID
+    pub pid: Option<u32>,
+    /// 运行时长（秒）
+    pub uptime: u64,
+}
+
+/// Xray 进程状态与资源占用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayProcessInfo {
+    /// 进程 ID
+    pub pid: Option<u32>,
+    /// 进程是否存在
+    pub alive: bool,
+    /// 运行时长（秒）
+    pub uptime: u64,
+    /// 经代理的请求是否正常
+    pub responsive: bool,
+    /// 常驻内存（字节）
+    pub memory_bytes: Option<u64>,
+    /// CPU 占用（单核百分比）
+    pub cpu_percent: Option<f64>,
+}
+
+/// Xray 进程资源限制
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayResourceLimitsInfo {
+    /// 进程优先级（normal / below_normal / idle）
+    pub priority: String,
+    /// 允许运行的 CPU 编号（从 0 开始），为空表示不限制；macOS 不支持
+    pub cpu_affinity: Vec<u32>,
+    /// 内存上限（MiB），常驻内存连续超过时自动重启 Xray；为空表示不限制
+    pub memory_limit_mb: Option<u64>,
+}
+
+/// 本地入站用户名和密码
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InboundAuthInfo {
+    /// 用户名（不能包含冒号）
+    pub username: String,
+    /// 密码
+    pub password: String,
+}
+
+/// 本地入站（局域网共享和认证）设置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InboundSettingsInfo {
+    /// 是否监听所有网卡，供局域网内其他设备使用（需同时设置 HTTP 和 SOCKS 认证）
+    pub allow_lan: bool,
+    /// HTTP 入站认证，为空时不需要认证
+    pub http_auth: Option<InboundAuthInfo>,
+    /// SOCKS 入站认证，为空时不需要认证
+    pub socks_auth: Option<InboundAuthInfo>,
+    /// SOCKS 入站是否启用 UDP
+    pub udp: bool,
+}
+
+/// 出站探测结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundHealthInfo {
+    /// 出站标签（其他实例的出站为“实例 ID/标签”）
+    pub tag: String,
+    /// 服务器地址
+    pub server: String,
+    /// 端口
+    pub port: u16,
+    /// 是否可达
+    pub reachable: bool,
+    /// 延迟（毫秒）
+    pub latency_ms: Option<u32>,
+    /// 错误信息
+    pub error: Option<String>,
+}
+
+/// 聚合健康状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregatedHealthInfo {
+    /// 综合状态（healthy / degraded / unhealthy / stopped）
+    pub summary: String,
+    /// 各实例状态
+    pub instances: Vec<InstanceHealthInfo>,
+    /// 各出站探测结果
+    pub outbounds: Vec<OutboundHealthInfo>,
+    /// 检查时间（Unix 时间戳）
+    pub checked_at: i64,
+}
+
+/// 会话事件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEventInfo {
+    /// 事件时间（Unix 时间戳，毫秒）
+    pub timestamp: i64,
+    /// 事件类型（connecting / connected / ready / failed / disconnected / route_changed /
+    /// degraded / recovered / reconnect_attempt / reconnected / proxy_applied / proxy_cleared /
+    /// network_changed）
+    pub kind: String,
+    /// 事件描述
+    pub message: String,
+}
+
+/// 连通性探测设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthProbeConfigInfo {
+    /// 是否启用
+    pub enabled: bool,
+    /// 经代理请求的 URL（如 http://www.gstatic.com/generate_204）
+    pub url: String,
+    /// 探测间隔（秒）
+    pub interval_secs: u32,
+    /// 单次探测超时（秒）
+    pub timeout_secs: u32,
+    /// 连续失败多少次后判定为降级并自动重连
+    pub failure_threshold: u32,
+}
+
+/// 延迟告警设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyAlertConfigInfo {
+    /// 延迟阈值（毫秒），为空时不告警
+    pub threshold_ms: Option<u32>,
+    /// 连续多少次探测超过阈值后告警
+    pub consecutive_probes: u32,
+}
+
+/// 当前连接的连通性探测结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthProbeStatusInfo {
+    /// 是否已降级（连续失败达到阈值）
+    pub degraded: bool,
+    /// 连续失败次数
+    pub consecutive_failures: u32,
+    /// 最近一次成功探测的延迟（毫秒）
+    pub last_latency_ms: Option<u64>,
+    /// 最近一次失败的错误信息
+    pub last_error: Option<String>,
+    /// 最近一次探测时间（Unix 时间戳，毫秒）
+    pub last_check: Option<i64>,
+    /// 探测总次数
+    pub total_checks: u64,
+    /// 失败总次数
+    pub total_failures: u64,
+}
+
+/// 某一时段的流量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficUsageInfo {
+    /// 时段（按日为 YYYY-MM-DD，按月为 YYYY-MM，本地时间）
+    pub period: String,
+    /// 上传流量（字节）
+    pub upload_bytes: u64,
+    /// 下载流量（字节）
+    pub download_bytes: u64,
+}
+
+/// 单个服务器的流量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerTrafficInfo {
+    /// 服务器 ID
+    pub server_id: String,
+    /// 上传流量（字节）
+    pub upload_bytes: u64,
+    /// 下载流量（字节）
+    pub download_bytes: u64,
+}
+
+/// 流量预算
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficBudgetInfo {
+    /// 预算 ID
+    pub id: String,
+    /// 只统计该订阅的服务器，为空时统计全部流量
+    pub subscription_id: Option<String>,
+    /// 周期：daily / monthly（本地时间）
+    pub period: String,
+    /// 每周期允许的流量（上传与下载合计，字节）
+    pub limit_bytes: u64,
+    /// 用尽时的动作：notify（仅通知）/ disconnect（断开）/ direct（切换到直连模式）
+    pub action: String,
+}
+
+/// 流量预算本周期的用量
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficBudgetUsageInfo {
+    /// 预算 ID
+    pub budget_id: String,
+    /// 本周期已用流量（字节）
+    pub used_bytes: u64,
+    /// 每周期允许的流量（字节）
+    pub limit_bytes: u64,
+    /// 已用百分比，可能超过 100
+    pub percent: f64,
+}
+
+/// 一次延迟测试结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencySampleInfo {
+    /// 测试时间（Unix 时间戳，毫秒）
+    pub tested_at: i64,
+    /// 延迟（毫秒），失败时为空
+    pub latency_ms: Option<u32>,
+}
+
+/// 服务器连接质量（基于最近的延迟测试）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerQualityInfo {
+    /// 服务器 ID
+    pub server_id: String,
+    /// 最近的测试结果（最早的在前），用于绘制走势图
+    pub samples: Vec<LatencySampleInfo>,
+    /// 丢包率（0.0 - 1.0）
+    pub loss_rate: f64,
+    /// 平均延迟（毫秒）
+    pub average_ms: Option<f64>,
+    /// P95 延迟（毫秒）
+    pub p95_ms: Option<u32>,
+    /// 抖动（相邻两次成功测试的平均差值，毫秒）
+    pub jitter_ms: Option<f64>,
+    /// 综合评分，越低越好；没有成功的测试时为空
+    pub score: Option<f64>,
+}
+
+/// 实时流量快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrafficSnapshotInfo {
+    /// 快照时间（Unix 时间戳，毫秒）
+    pub timestamp: i64,
+    /// 上传总流量（字节）
+    pub upload_bytes: u64,
+    /// 下载总流量（字节）
+    pub download_bytes: u64,
+    /// 上传速度（字节/秒）
+    pub upload_speed: u64,
+    /// 下载速度（字节/秒）
+    pub download_speed: u64,
+}
+
+/// 活动连接（来自 Xray 访问日志）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActiveConnectionInfo {
+    /// 连接 ID
+    pub id: u64,
+    /// 来源地址（ip:port）
+    pub source: String,
+    /// 网络类型（tcp / udp）
+    pub network: String,
+    /// 目标地址（host:port）
+    pub destination: String,
+    /// 入站标签
+    pub inbound_tag: Option<String>,
+    /// 出站标签（proxy / direct / block 等）
+    pub outbound_tag: String,
+    /// 建立时间（Unix 时间戳，毫秒）
+    pub started_at: i64,
+    /// 持续时间（秒）
+    pub duration_secs: u64,
+}
+
+/// 域名访问统计（来自 Xray 访问日志）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DomainStatInfo {
+    /// 域名或 IP
+    pub domain: String,
+    /// 连接次数
+    pub count: u64,
+    /// 最近一次访问时间（Unix 时间戳，毫秒）
+    pub last_seen: i64,
+}
+
+/// 按路由统计的连接数
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSplitInfo {
+    /// 走代理
+    pub proxy: u64,
+    /// 直连
+    pub direct: u64,
+    /// 被拦截
+    pub blocked: u64,
+    /// 其他出站（负载均衡、链式代理等）
+    pub other: u64,
+}
+
+/// 测速选项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedTestOptions {
+    /// 下载测速地址（为空时使用 Cloudflare）
+    pub download_url: Option<String>,
+    /// 上传测速地址（为空时使用 Cloudflare）
+    pub upload_url: Option<String>,
+    /// 是否测试上传
+    pub upload: bool,
+    /// 每个阶段的时长（秒，1-60）
+    pub duration_secs: u32,
+}
+
+/// 测速结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedTestInfo {
+    /// 服务器 ID
+    pub server_id: String,
+    /// 测试时间（Unix 时间戳，毫秒）
+    pub tested_at: i64,
+    /// 下载速度（Mbps）
+    pub download_mbps: f64,
+    /// 上传速度（Mbps，未测试上传时为空）
+    pub upload_mbps: Option<f64>,
+    /// 下载字节数
+    pub downloaded_bytes: u64,
+    /// 上传字节数
+    pub uploaded_bytes: u64,
+}
+
+/// 服务器测速排名
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpeedRankingInfo {
+    /// 服务器 ID
+    pub server_id: String,
+    /// 最近测速的下载速度中位数（Mbps）
+    pub download_mbps: f64,
+    /// 最近测速的上传速度中位数（Mbps）
+    pub upload_mbps: Option<f64>,
+    /// 参与统计的测速次数
+    pub tests: u32,
+    /// 最近一次测速时间（Unix 时间戳，毫秒）
+    pub last_tested: i64,
+}
+
+/// 流媒体解锁检测结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnlockResultInfo {
+    /// 服务（netflix / youtube_premium / chatgpt / disney_plus）
+    pub service: String,
+    /// 状态（unlocked / partial / blocked / unknown）
+    pub status: String,
+    /// 服务识别到的地区（国家代码）
+    pub region: Option<String>,
+    /// 说明（部分解锁、被拦截或未知时）
+    pub detail: Option<String>,
+    /// 检测时间（Unix 时间戳，毫秒）
+    pub checked_at: i64,
+}
+
+/// 出口 IP 信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitIpInfo {
+    /// 公网 IP
+    pub ip: String,
+    /// 国家名称
+    pub country: Option<String>,
+    /// 国家代码
+    pub country_code: Option<String>,
+    /// 城市
+    pub city: Option<String>,
+    /// 自治系统号
+    pub asn: Option<u32>,
+    /// 网络运营商
+    pub organization: Option<String>,
+    /// 风险标记（proxy / hosting），仅部分查询服务提供
+    pub risk_flags: Vec<String>,
+}
+
+/// 当前连接的出口信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitInfoResult {
+    /// 使用的查询服务
+    pub provider: String,
+    /// 经代理的出口
+    pub proxied: ExitIpInfo,
+    /// 直连的出口（未对比或失败时为空）
+    pub direct: Option<ExitIpInfo>,
+    /// 直连查询失败或跳过的原因
+    pub direct_error: Option<String>,
+    /// 代理是否生效（经代理与直连的出口 IP 不同）
+    pub proxy_effective: bool,
+}
+
+/// IP 信息查询服务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitInfoProviderInfo {
+    /// 服务 ID（用于 `get_exit_info`）
+    pub id: String,
+    /// 显示名称
+    pub name: String,
+    /// 隐私说明
+    pub privacy_note: String,
+}
+
+/// 诊断检查项结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticCheckInfo {
+    /// 检查项 ID（binary / config / ports / process / inbound / tcp_handshake /
+    /// tls_handshake / proxy_request / external_ip）
+    pub stage: String,
+    /// 检查项名称
+    pub title: String,
+    /// 结果（passed / failed / skipped）
+    pub status: String,
+    /// 检查发现
+    pub message: String,
+    /// 耗时（毫秒）
+    pub duration_ms: u64,
+    /// 修复建议（仅失败时）
+    pub hint: Option<String>,
+}
+
+/// 连接诊断报告
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiagnosticReportInfo {
+    /// 服务器 ID
+    pub server_id: String,
+    /// 是否全部通过
+    pub passed: bool,
+    /// 首个失败的检查项 ID
+    pub failed_stage: Option<String>,
+    /// 按顺序排列的检查项
+    pub checks: Vec<DiagnosticCheckInfo>,
+}
+
+/// 日志记录（内存中的最近日志）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRecordInfo {
+    /// 记录时间（Unix 时间戳，毫秒）
+    pub timestamp: i64,
+    /// 来源（core / xray）
+    pub source: String,
+    /// 级别（trace / debug / info / warn / error）
+    pub level: String,
+    /// 模块（Xray 日志为 xray）
+    pub target: String,
+    /// 内容
+    pub message: String,
+}
+
+/// 上次会话状态（启动时读取）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PreviousSessionInfo {
+    /// 上次是否未正常退出（仍处于连接状态或遗留系统代理 / TUN）
+    pub unclean_shutdown: bool,
+    /// 退出时是否处于连接状态
+    pub was_connected: bool,
+    /// 上次连接的服务器 ID
+    pub server_id: Option<String>,
+    /// 上次使用的代理模式
+    pub proxy_mode: Option<String>,
+    /// 是否遗留系统代理设置
+    pub system_proxy_applied: bool,
+    /// 是否遗留 TUN 路由
+    pub tun_enabled: bool,
+    /// 状态最后写入时间（Unix 时间戳，毫秒）
+    pub updated_at: i64,
+}
+
+/// 启动时恢复上次会话的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionRestoreInfo {
+    /// 是否已恢复（未启用启动时恢复或上次已断开时为 false）
+    pub restored: bool,
+    /// 重新连接的服务器 ID
+    pub server_id: Option<String>,
+    /// 使用的代理模式
+    pub proxy_mode: Option<String>,
+    /// 是否重新设置了系统代理
+    pub system_proxy: bool,
+}
+
+/// 路由建议（智能模式下直连持续失败的目标）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteSuggestionInfo {
+    /// 建议 ID
+    pub id: String,
+    /// 建议走代理的域名（包含子域名）
+    pub domain: String,
+    /// 建议描述（如 "Route *.example.com via proxy"）
+    pub description: String,
+    /// 统计窗口内的直连失败次数
+    pub failures: u32,
+    /// 最近一次失败时间（Unix 时间戳，毫秒）
+    pub last_failure: i64,
+}
+
+/// 路由规则（匹配条件 → 出站）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRuleInfo {
+    /// 域名匹配（如 geosite:google、domain:example.com）
+    pub domains: Vec<String>,
+    /// IP 匹配（如 geoip:private、10.0.0.0/8）
+    pub ips: Vec<String>,
+    /// 端口或端口范围（如 53,443,1000-2000）
+    pub port: Option<String>,
+    /// 进程名（如 chrome.exe）
+    pub processes: Vec<String>,
+    /// 嗅探到的协议（http / tls / bittorrent）
+    pub protocols: Vec<String>,
+    /// 目标出站（proxy / direct / block）
+    pub outbound: String,
+}
+
+/// 路由规则集（按顺序匹配，排在内置模式规则之前）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoutingRuleSetInfo {
+    /// 规则集 ID
+    pub id: String,
+    /// 名称
+    pub name: String,
+    /// 是否启用
+    pub enabled: bool,
+    /// 规则，按顺序匹配
+    pub rules: Vec<RoutingRuleInfo>,
+}
+
+/// 应用规则（分应用路由）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppRuleInfo {
+    /// 规则 ID
+    pub id: String,
+    /// 可执行文件名（如 chrome.exe、Telegram）
+    pub process_name: String,
+    /// 目标出站（proxy / direct / block）
+    pub outbound: String,
+    /// 是否启用
+    pub enabled: bool,
+}
+
+/// 配置档（服务器、代理模式、路由规则集、端口和系统代理行为的组合）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileInfo {
+    /// 配置档 ID
+    pub id: String,
+    /// 名称（如 Work、Streaming US）
+    pub name: String,
+    /// 服务器 ID，为空时保持当前服务器
+    pub server_id: Option<String>,
+    /// 代理模式（global / smart / direct）
+    pub mode: String,
+    /// 启用的路由规则集 ID（其余规则集停用），为空时保持当前规则集
+    pub routing_rule_set_ids: Option<Vec<String>>,
+    /// HTTP 入站端口，为空时保持当前端口
+    pub http_port: Option<u16>,
+    /// SOCKS 入站端口，为空时保持当前端口
+    pub socks_port: Option<u16>,
+    /// 系统代理行为（keep / enable / disable）
+    pub system_proxy: String,
+}
+
+/// 本地入站限速（字节/秒），为空表示不限速
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpeedLimitInfo {
+    /// 上传限速（本机应用发出的流量）
+    pub upload_bytes_per_sec: Option<u64>,
+    /// 下载限速（本机应用收到的流量）
+    pub download_bytes_per_sec: Option<u64>,
+}
+
+/// 上游 DNS 服务器
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsServerInfo {
+    /// 地址（IP、https:// DoH 地址、localhost 或 fakedns）
+    pub address: String,
+    /// 端口，为空时使用 53
+    pub port: Option<u16>,
+    /// 优先用该服务器解析的域名（如 geosite:cn）
+    pub domains: Vec<String>,
+    /// 只接受这些范围内的解析结果（如 geoip:cn）
+    pub expect_ips: Vec<String>,
+    /// 回退查询时跳过该服务器
+    pub skip_fallback: bool,
+}
+
+/// FakeDNS 设置
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FakeDnsInfo {
+    /// 虚假 IP 地址池（CIDR）
+    pub ip_pool: String,
+    /// 地址池保留的地址数
+    pub pool_size: u32,
+}
+
+/// DNS 设置（分域 DNS 规则单独设置，见 [`set_split_dns_rules`]）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DnsSettingsInfo {
+    /// 上游服务器，按优先级排列；为空时使用内置默认设置
+    pub servers: Vec<DnsServerInfo>,
+    /// 静态解析（域名 → 地址）
+    pub hosts: HashMap<String, Vec<String>>,
+    /// 查询策略（UseIP / UseIPv4 / UseIPv6）
+    pub query_strategy: String,
+    /// 通过 EDNS Client Subnet 发送的客户端 IP
+    pub client_ip: Option<String>,
+    /// FakeDNS 设置，为空时不启用
+    pub fake_dns: Option<FakeDnsInfo>,
+    /// 是否禁用 DNS 缓存
+    pub disable_cache: bool,
+}
+
+/// 分域 DNS 规则（如公司内网域名使用公司 DNS 并直连）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitDnsRuleInfo {
+    /// 域名及其子域名（如 corp.internal 或 *.corp.internal）
+    pub domains: Vec<String>,
+    /// 解析这些域名的 DNS 服务器 IP
+    pub servers: Vec<String>,
+}
+
+/// 直连优先设置（探测列表中直连可用且不明显变慢的域名改为直连）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectPreferenceSettingsInfo {
+    /// 是否启用
+    pub enabled: bool,
+    /// 允许探测的域名列表
+    pub probe_domains: Vec<String>,
+    /// 直连相比代理允许多出的延迟（毫秒）
+    pub max_slowdown_ms: u32,
+}
+
+/// 直连优先探测结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectProbeResultInfo {
+    /// 探测的域名
+    pub domain: String,
+    /// 直连耗时（毫秒），失败时为空
+    pub direct_ms: Option<u64>,
+    /// 经代理耗时（毫秒），失败时为空
+    pub proxy_ms: Option<u64>,
+    /// 是否改为直连
+    pub prefer_direct: bool,
+}
+
+/// 脚本路由设置（默认关闭，由 Rhai 脚本的 `route(conn)` 为每个连接选择出站）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptRoutingSettingsInfo {
+    /// 是否启用
+    pub enabled: bool,
+    /// Rhai 脚本，需定义 `fn route(conn)`
+    pub script: String,
+    /// 单次调用的时间上限（毫秒，1 ~ 1000）
+    pub timeout_ms: u32,
+}
+
+/// 路由测试结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteTestInfo {
+    /// 命中的规则序号（从 0 开始），为空表示未命中规则、使用默认出站
+    pub rule_index: Option<u32>,
+    /// 命中的规则（Xray 规则 JSON）
+    pub rule: Option<String>,
+    /// 出站标签（proxy / direct / block 等），路由到负载均衡时为空
+    pub outbound_tag: Option<String>,
+    /// 负载均衡标签
+    pub balancer_tag: Option<String>,
+    /// 按域名策略解析出的 IP
+    pub resolved_ips: Vec<String>,
+    /// 之前因依赖发起连接的应用（进程、来源地址等）而无法判断的规则序号
+    pub skipped_rules: Vec<u32>,
+}
+
+/// GFWList 设置
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GfwListSettingsInfo {
+    /// 智能模式是否按 GFWList 分流（代替 geosite/geoip 规则）
+    pub enabled: bool,
+    /// 下载地址
+    pub url: String,
+    /// 更新间隔（小时）
+    pub update_interval_hours: u32,
+}
+
+/// GFWList 更新状态
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GfwListStatusInfo {
+    /// 是否已加载列表
+    pub loaded: bool,
+    /// 走代理的域名和 IP 数
+    pub proxy_rules: u32,
+    /// 例外（直连）的域名和 IP 数
+    pub direct_rules: u32,
+    /// 无法转换而跳过的规则数（正则、通配符等）
+    pub skipped_rules: u32,
+    /// 最后更新时间（Unix 时间戳）
+    pub updated_at: Option<i64>,
+    /// 最近一次更新失败的原因
+    pub last_error: Option<String>,
+}
+
 /// 配置信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConfigInfo {
@@ -86,6 +779,37 @@ pub struct ProxyServerConfig {
     pub tags: Vec<String>,
 }
 
+/// 服务器字段错误
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerFieldError {
+    /// 字段名（协议设置以 `settings.` 开头，如 `settings.id`）
+    pub field: String,
+    /// 错误信息
+    pub message: String,
+}
+
+/// 服务器字段校验结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerValidationInfo {
+    /// 是否有效
+    pub valid: bool,
+    /// 规范化后的配置（去除首尾空白、统一大小写等）
+    pub normalized: ProxyServerConfig,
+    /// 各字段的错误
+    pub errors: Vec<ServerFieldError>,
+}
+
+/// 配置诊断
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigDiagnostic {
+    /// 字段路径（如 `port`、`settings.id`、`stream_settings.ws_settings.path`）
+    pub path: String,
+    /// 严重程度（error、warning）
+    pub severity: String,
+    /// 问题描述
+    pub message: String,
+}
+
 /// 订阅信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionInfo {
@@ -93,7 +817,7 @@ pub struct SubscriptionInfo {
     pub id: String,
     /// 订阅名称
     pub name: String,
-    /// 订阅 URL
+    /// 订阅 URL（本地分组为空）
     pub url: String,
     /// 最后更新时间（Unix 时间戳）
     pub last_update: Option<i64>,
@@ -103,6 +827,227 @@ pub struct SubscriptionInfo {
     pub status: String,
 }
 
+/// 订阅更新记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionUpdateInfo {
+    /// 开始时间（Unix 时间戳，毫秒）
+    pub timestamp: i64,
+    /// 耗时（毫秒）
+    pub duration_ms: u64,
+    /// 是否成功
+    pub success: bool,
+    /// 失败原因
+    pub error: Option<String>,
+    /// 服务器数量变化
+    pub server_count_delta: i64,
+}
+
+/// 订阅更新带来的服务器变化
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerDiffInfo {
+    /// 更新开始时间（Unix 时间戳，毫秒），与更新记录对应
+    pub timestamp: i64,
+    /// 新增的服务器名称
+    pub added: Vec<String>,
+    /// 移除的服务器名称
+    pub removed: Vec<String>,
+    /// 地址或配置有变化的服务器名称（服务器 ID 不变）
+    pub changed: Vec<String>,
+}
+
+/// 订阅请求选项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionRequestInfo {
+    /// User-Agent 预设（default / clash / v2rayn / shadowrocket / custom）
+    pub user_agent_preset: String,
+    /// 自定义 User-Agent，仅 custom
+    pub custom_user_agent: Option<String>,
+    /// 额外请求头
+    pub headers: HashMap<String, String>,
+    /// Basic 认证用户名，为空表示不认证
+    pub username: Option<String>,
+    /// Basic 认证密码
+    pub password: Option<String>,
+}
+
+/// 订阅下载的一次尝试
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionFetchAttemptInfo {
+    /// 所属更新的开始时间（Unix 时间戳，毫秒），与更新记录对应
+    pub timestamp: i64,
+    /// 线路（direct / proxy）
+    pub route: String,
+    /// 该线路上的第几次尝试，从 1 开始
+    pub attempt: u32,
+    /// 耗时（毫秒）
+    pub duration_ms: u64,
+    /// HTTP 状态码，未收到响应时为空
+    pub http_status: Option<u16>,
+    /// 错误信息，成功时为空
+    pub error: Option<String>,
+}
+
+/// 订阅下载设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionFetchSettings {
+    /// 请求超时（秒）
+    pub timeout_secs: u64,
+    /// 每条线路的最多尝试次数（至少 1）
+    pub max_attempts: u32,
+    /// 首次重试前的等待时间（毫秒），之后每次翻倍
+    pub initial_backoff_ms: u64,
+    /// 重试等待时间上限（毫秒）
+    pub max_backoff_ms: u64,
+    /// 需要重试的 HTTP 状态码；网络错误总会重试
+    pub retry_on_status: Vec<u16>,
+    /// 线路顺序（direct_only / proxy_only / direct_then_proxy / proxy_then_direct）
+    pub route_order: String,
+    /// 代理线路使用的代理地址，如本地 HTTP 入站 `http://127.0.0.1:10809`；为空时跳过代理线路
+    pub proxy_url: Option<String>,
+}
+
+/// 订阅链接健康检查结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionHealthInfo {
+    /// 订阅 ID
+    pub subscription_id: String,
+    /// 是否已失效（连续检查失败次数达到阈值）
+    pub stale: bool,
+    /// 连续失败次数
+    pub consecutive_failures: u32,
+    /// 最近一次检查时间（Unix 时间戳，毫秒）
+    pub checked_at: Option<i64>,
+    /// 最近一次检查耗时（毫秒）
+    pub duration_ms: Option<u64>,
+    /// 最终响应的 HTTP 状态码，未收到响应时为空
+    pub http_status: Option<u16>,
+    /// 重定向后的地址，未重定向时为空
+    pub redirected_to: Option<String>,
+    /// 失败原因，检查成功时为空
+    pub error: Option<String>,
+    /// 是否为 TLS 错误（证书或握手失败）
+    pub tls_error: bool,
+}
+
+/// 订阅自动更新计划
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionScheduleInfo {
+    /// 类型（default：默认间隔 / interval：自定义间隔 / cron：cron 表达式 / manual：仅手动）
+    pub kind: String,
+    /// 更新间隔（小时），仅 interval
+    pub interval_hours: Option<u32>,
+    /// cron 表达式（分 时 日 月 周，按本地时间），仅 cron
+    pub cron: Option<String>,
+    /// 下次更新时间（Unix 时间戳，毫秒），不自动更新时为空；设置时忽略
+    pub next_update: Option<i64>,
+}
+
+/// 系统代理协议开关
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemProxyProtocolsInfo {
+    /// 设置 HTTP 代理
+    pub http: bool,
+    /// 设置 HTTPS 代理
+    pub https: bool,
+    /// 设置 SOCKS 代理
+    pub socks: bool,
+}
+
+/// macOS 系统代理作用的网络服务范围
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkServiceScopeInfo {
+    /// 范围：active（所有已启用的非 VPN 服务）、primary（默认路由所在服务）、
+    /// selected（指定服务）
+    pub mode: String,
+    /// mode 为 selected 时的服务名称
+    pub services: Vec<String>,
+}
+
+/// macOS 网络服务
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkServiceInfo {
+    /// 服务名称
+    pub name: String,
+    /// 网络设备（如 en0）
+    pub device: String,
+    /// 是否已启用
+    pub enabled: bool,
+    /// 是否为 VPN 接口
+    pub vpn: bool,
+    /// 是否为默认路由所在服务
+    pub primary: bool,
+}
+
+/// 应用目录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppPathsInfo {
+    /// 配置目录
+    pub config_dir: String,
+    /// 数据目录（数据库、运行状态、Xray 配置）
+    pub data_dir: String,
+    /// 缓存目录（下载和临时文件）
+    pub cache_dir: String,
+    /// 日志目录
+    pub log_dir: String,
+}
+
+/// Windows 版本信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WindowsVersionInfo {
+    /// 主版本号（Windows 10 和 11 均为 10）
+    pub major: u32,
+    /// 次版本号
+    pub minor: u32,
+    /// 内部版本号
+    pub build: u32,
+    /// 更新修订号，未知时为 0
+    pub revision: u32,
+    /// 显示名称，例如 "Windows 11 (10.0.22631.3880)"
+    pub display_name: String,
+    /// 是否为 Windows 11 及以上
+    pub is_windows_11: bool,
+    /// 是否支持 WinTUN（TUN 模式）
+    pub supports_wintun: bool,
+    /// 是否支持系统通知中心的通知
+    pub supports_toast_notifications: bool,
+}
+
+/// 操作系统详情
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OsDetailsInfo {
+    /// 系统名称，例如 "macOS"、"Ubuntu"
+    pub name: String,
+    /// 系统版本，例如 "14.5"、"24.04"
+    pub version: Option<String>,
+    /// 构建号，例如 "23F79"
+    pub build: Option<String>,
+    /// 内核版本（Linux）
+    pub kernel: Option<String>,
+    /// 发行版标识，例如 "ubuntu"（Linux）
+    pub distro_id: Option<String>,
+    /// 上游发行版标识，例如 ["debian"]（Linux）
+    pub distro_like: Vec<String>,
+    /// 桌面环境名称，例如 "GNOME"、"KDE Plasma"（Linux）
+    pub desktop: Option<String>,
+    /// 桌面环境是否从 gsettings 读取代理设置（Linux）
+    pub desktop_uses_gsettings: bool,
+}
+
+/// 局域网分享服务信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanShareInfo {
+    /// 本机局域网地址
+    pub address: String,
+    /// 监听端口
+    pub port: u16,
+    /// 访问令牌（分享地址的第一段路径）
+    pub token: String,
+    /// 已分享的文件名
+    pub files: Vec<String>,
+    /// 放行防火墙的提示（如需要执行的命令）
+    pub firewall_hint: Option<String>,
+}
+
 /// 服务器信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerInfo {
@@ -120,21 +1065,174 @@ pub struct ServerInfo {
     pub protocol: String,
 }
 
-/// Xray Core 更新信息
+/// 服务器列表项（含用户数据）
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct XrayCoreUpdateInfo {
-    /// 是否有更新
-    pub has_update: bool,
-    /// 当前版本
-    pub current_version: String,
-    /// 最新版本
-    pub latest_version: String,
-    /// 下载 URL
+pub struct ServerListItem {
+    /// 服务器 ID
+    pub id: String,
+    /// 所属订阅 ID
+    pub subscription_id: String,
+    /// 显示名称（设置了别名时为别名）
+    pub name: String,
+    /// 订阅提供的名称
+    pub original_name: String,
+    /// 服务器地址
+    pub address: String,
+    /// 端口
+    pub port: i32,
+    /// 协议类型
+    pub protocol: String,
+    /// 是否收藏
+    pub favorite: bool,
+    /// 自定义别名
+    pub alias: Option<String>,
+    /// 是否隐藏
+    pub hidden: bool,
+    /// 置顶位置（未置顶为 None）
+    pub pinned_position: Option<u32>,
+}
+
+/// 无法导入的内容
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportFailure {
+    /// 所在行（从 1 开始，base64 内容按解码后计算）
+    pub line: u32,
+    /// 出错的内容（过长时截断）
+    pub input: String,
+    /// 失败原因
+    pub reason: String,
+}
+
+/// 从文本导入服务器的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportResult {
+    /// 导入到的本地分组 ID
+    pub group_id: String,
+    /// 新增的服务器
+    pub imported: Vec<ServerInfo>,
+    /// 因已存在而跳过的服务器名称
+    pub duplicates: Vec<String>,
+    /// 无法解析的内容
+    pub failures: Vec<ImportFailure>,
+}
+
+/// 服务器导出结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerExportInfo {
+    /// 导出的内容
+    pub content: String,
+    /// 导出的服务器数量
+    pub exported: u32,
+    /// 无法导出的服务器（"名称: 原因"）
+    pub skipped: Vec<String>,
+}
+
+/// 服务器重新测试结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerLatencyInfo {
+    /// 服务器 ID
+    pub server_id: String,
+    /// 是否可达
+    pub reachable: bool,
+    /// 延迟（毫秒）
+    pub latency_ms: Option<u32>,
+    /// 错误信息
+    pub error: Option<String>,
+}
+
+/// 存储占用统计
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageStatsInfo {
+    /// 订阅数量
+    pub subscription_count: u64,
+    /// 服务器数量
+    pub server_count: u64,
+    /// 收藏的服务器数量
+    pub favorite_count: u64,
+    /// 数据库文件大小（字节）
+    pub database_size: u64,
+    /// 流量和延迟历史数据库大小（字节）
+    pub history_database_size: u64,
+    /// 日志文件大小（字节）
+    pub log_size: u64,
+    /// 下载缓存大小（字节）
+    pub download_cache_size: u64,
+}
+
+/// Xray Core 更新信息
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayCoreUpdateInfo {
+    /// 是否有更新
+    pub has_update: bool,
+    /// 当前版本
+    pub current_version: String,
+    /// 最新版本
+    pub latest_version: String,
+    /// 下载 URL
     pub download_url: String,
     /// 文件大小（字节）
     pub file_size: u64,
 }
 
+/// Xray Core 发布版本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayReleaseInfo {
+    /// 版本号（不含 "v" 前缀）
+    pub version: String,
+    /// 发布时间（RFC 3339）
+    pub published_at: Option<String>,
+    /// 是否为预发布版本
+    pub prerelease: bool,
+    /// 是否已安装
+    pub installed: bool,
+}
+
+/// Xray Core 更新下载源
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayDownloadSourceInfo {
+    /// 类型：github（直连 GitHub）、proxy（经代理访问 GitHub）、
+    /// mirror（在 GitHub 地址前加前缀的镜像）、custom_base（与 GitHub 下载路径结构相同的服务器）
+    pub source_type: String,
+    /// mirror 的前缀或 custom_base 的基础地址，其他类型为空
+    pub url: Option<String>,
+}
+
+/// Xray Core 更新下载设置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct XrayDownloadSettingsInfo {
+    /// 按顺序尝试的下载源
+    pub sources: Vec<XrayDownloadSourceInfo>,
+    /// proxy 下载源使用的代理地址，为空时使用已连接时的本地 HTTP 入站
+    pub proxy_url: Option<String>,
+}
+
+/// 已安装的 Xray Core 版本
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledXrayVersionsInfo {
+    /// 已安装的版本，从新到旧
+    pub versions: Vec<String>,
+    /// 当前选中的版本，`None` 表示使用应用自带的 Xray
+    pub active: Option<String>,
+    /// 回滚时切换到的版本
+    pub previous: Option<String>,
+}
+
+/// 错误信息
+///
+/// 返回 `Err` 的 API 的错误消息均可通过 `parse_error` 解析为此结构
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct V8RayErrorInfo {
+    /// 错误码，按分类每 1000 个一段（1xxx 配置、2xxx 连接、3xxx 订阅、4xxx Xray、
+    /// 5xxx 平台、6xxx 网络、7xxx 存储、8xxx 控制接口、9xxx 其他）
+    pub code: u32,
+    /// 错误分类（config、connection、subscription 等）
+    pub category: String,
+    /// 本地化消息键，如 `error.connection.timeout`
+    pub message_key: String,
+    /// 原始错误消息
+    pub detail: String,
+}
+
 /// 事件类型
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum V8RayEvent {
@@ -148,13 +1246,190 @@ pub enum V8RayEvent {
         /// 错误消息
         message: String,
     },
-    /// 日志消息
+    /// 日志消息（Xray）
     Log {
         /// 日志级别
         level: String,
         /// 日志消息
         message: String,
     },
+    /// 核心日志
+    CoreLog {
+        /// 日志级别（trace / debug / info / warn / error）
+        level: String,
+        /// 模块
+        target: String,
+        /// 日志消息
+        message: String,
+    },
+    /// 本地端口被占用，已改用空闲端口
+    PortReassigned {
+        /// 入站协议（http / socks）
+        protocol: String,
+        /// 原端口
+        old_port: u16,
+        /// 新端口
+        new_port: u16,
+    },
+    /// 网络变化（如切换 Wi-Fi），已重新应用系统代理并重新检查连通性
+    NetworkChanged {
+        /// 默认路由所在网卡（Windows 上为网卡地址）
+        interface: Option<String>,
+        /// 默认网关
+        gateway: Option<String>,
+        /// 本机出口地址
+        local_address: Option<String>,
+        /// 是否有可用网络
+        online: bool,
+    },
+    /// 订阅已更新（手动或自动）
+    SubscriptionUpdated {
+        /// 订阅 ID
+        subscription_id: String,
+        /// 订阅名称
+        name: String,
+        /// 是否成功
+        success: bool,
+        /// 失败原因
+        error: Option<String>,
+        /// 新增的服务器名称
+        added: Vec<String>,
+        /// 移除的服务器名称
+        removed: Vec<String>,
+        /// 地址或配置有变化的服务器名称
+        changed: Vec<String>,
+    },
+    /// 连接中断，即将自动重连
+    Reconnecting {
+        /// 触发原因（crash / network_unreachable）
+        reason: String,
+        /// 第几次尝试（从 1 开始）
+        attempt: u32,
+        /// 距本次尝试的等待时间（毫秒）
+        delay_ms: u64,
+    },
+    /// 当前连接的延迟连续多次超过阈值（连接仍可用，但较慢）
+    DegradedConnection {
+        /// 服务器 ID
+        server_id: String,
+        /// 最近一次探测的延迟（毫秒）
+        latency_ms: u64,
+        /// 延迟阈值（毫秒）
+        threshold_ms: u64,
+    },
+    /// 当前连接的延迟恢复到阈值以内
+    LatencyRecovered {
+        /// 服务器 ID
+        server_id: String,
+        /// 最近一次探测的延迟（毫秒）
+        latency_ms: u64,
+    },
+    /// 流量预算用量达到阈值（每个阈值每周期一次）
+    TrafficBudgetAlert {
+        /// 预算 ID
+        budget_id: String,
+        /// 预算的订阅，为空表示全部流量
+        subscription_id: Option<String>,
+        /// 周期：daily / monthly
+        period: String,
+        /// 达到的阈值（百分比：80 / 100）
+        threshold_percent: u32,
+        /// 本周期已用流量（字节）
+        used_bytes: u64,
+        /// 每周期允许的流量（字节）
+        limit_bytes: u64,
+        /// 执行的动作：notify / disconnect / direct（仅 100% 时为预算设置的动作）
+        action: String,
+    },
+    /// 自动重连已放弃
+    ReconnectFailed {
+        /// 触发原因（crash / network_unreachable）
+        reason: String,
+        /// 已尝试次数
+        attempts: u32,
+        /// 放弃原因
+        message: String,
+    },
+    /// 另一个实例启动时发来消息（如 `show`，应将窗口显示到前台）
+    InstanceActivated {
+        /// 消息内容
+        message: String,
+    },
+    /// 已清理上次异常退出遗留的 Xray 进程、系统代理和 TUN 路由
+    PreviousSessionRecovered {
+        /// 被终止的遗留 Xray 进程 PID
+        xray_pid: Option<u32>,
+        /// 是否恢复了系统代理
+        system_proxy_cleared: bool,
+        /// 是否移除了 TUN 路由
+        tun_routes_removed: bool,
+    },
+    /// 测速进度（每 500 毫秒左右一次）
+    SpeedTestProgress {
+        /// 服务器 ID
+        server_id: String,
+        /// 阶段（download / upload）
+        phase: String,
+        /// 本阶段已传输字节数
+        bytes: u64,
+        /// 本阶段当前速度（Mbps）
+        mbps: f64,
+    },
+    /// 连接进度
+    ConnectPhaseChanged {
+        /// 服务器 ID
+        server_id: String,
+        /// 阶段（generating_config / starting_process / waiting_inbound /
+        /// testing_connectivity / connected）
+        phase: String,
+    },
+    /// 附加 Xray 实例状态变化
+    InstanceStatusChanged {
+        /// 实例 ID
+        instance_id: String,
+        /// 进程状态（stopped / starting / running / stopping / error）
+        status: String,
+        /// 错误信息
+        error: Option<String>,
+    },
+    /// 已切换配置档
+    ProfileSwitched {
+        /// 配置档 ID
+        profile_id: String,
+    },
+    /// 订阅链接连续多次检查失败，可能已失效
+    SubscriptionStale {
+        /// 订阅 ID
+        subscription_id: String,
+        /// 订阅名称
+        name: String,
+        /// 连续失败次数
+        consecutive_failures: u32,
+        /// 最近一次失败原因
+        error: Option<String>,
+    },
+    /// Xray 内存占用超过上限，已自动重启
+    XrayMemoryLimitExceeded {
+        /// 超限的进程 ID
+        pid: u32,
+        /// 常驻内存（字节）
+        memory_bytes: u64,
+        /// 内存上限（字节）
+        limit_bytes: u64,
+    },
+    /// 找不到可运行的 Xray Core（首次运行且未内置），可调用
+    /// `bootstrap_xray_core` 下载安装
+    XrayCoreMissing {
+        /// 要连接的服务器 ID
+        server_id: Option<String>,
+    },
+    /// Xray Core 首次下载进度
+    XrayCoreDownloadProgress {
+        /// 正在安装的版本
+        version: String,
+        /// 下载进度（0.0 - 1.0）
+        progress: f64,
+    },
 }
 
 // ============================================================================
@@ -169,18 +1444,36 @@ pub enum V8RayEvent {
 /// - `Ok(())`: 初始化成功
 /// - `Err(e)`: 初始化失败
 pub fn init_v8ray() -> Result<()> {
-    crate::bridge::init()
+    crate::bridge::init().map_err(coded)
 }
 
 /// 关闭 V8Ray Core
 ///
-/// 释放所有资源
+/// 断开连接，停止网络监测，移除 TUN 路由并清除应用设置的系统代理，释放所有资源
 ///
 /// # 返回
 /// - `Ok(())`: 关闭成功
 /// - `Err(e)`: 关闭失败
 pub fn shutdown_v8ray() -> Result<()> {
-    crate::bridge::shutdown()
+    crate::bridge::shutdown().map_err(coded)
+}
+
+/// 解析 API 返回的错误消息
+///
+/// # 参数
+/// - `message`: 错误消息
+///
+/// # 返回
+/// 错误码、分类、本地化消息键和原始消息；无法识别的消息返回错误码 9000
+#[flutter_rust_bridge::frb(sync)]
+pub fn parse_error(message: String) -> V8RayErrorInfo {
+    let info = crate::bridge::error::parse(&message);
+    V8RayErrorInfo {
+        code: info.code,
+        category: info.category.as_str().to_string(),
+        message_key: info.message_key,
+        detail: info.detail,
+    }
 }
 
 // ============================================================================
@@ -193,386 +1486,3139 @@ pub fn shutdown_v8ray() -> Result<()> {
 /// - `config_id`: 配置 ID
 ///
 /// # 返回
-/// - `Ok(config)`: 配置信息
-/// - `Err(e)`: 加载失败
-pub fn load_config(config_id: String) -> Result<ConfigInfo> {
-    crate::bridge::config::load_config(&config_id)
+/// - `Ok(config)`: 配置信息
+/// - `Err(e)`: 加载失败
+pub fn load_config(config_id: String) -> Result<ConfigInfo> {
+    crate::bridge::config::load_config(&config_id).map_err(coded)
+}
+
+/// 保存配置
+///
+/// # 参数
+/// - `config`: 配置信息
+///
+/// # 返回
+/// - `Ok(())`: 保存成功
+/// - `Err(e)`: 保存失败
+pub fn save_config(config: ConfigInfo) -> Result<()> {
+    crate::bridge::config::save_config(config).map_err(coded)
+}
+
+/// 删除配置
+///
+/// # 参数
+/// - `config_id`: 配置 ID
+///
+/// # 返回
+/// - `Ok(())`: 删除成功
+/// - `Err(e)`: 删除失败
+pub fn delete_config(config_id: String) -> Result<()> {
+    crate::bridge::config::delete_config(&config_id).map_err(coded)
+}
+
+/// 列出所有配置
+///
+/// # 返回
+/// - `Ok(configs)`: 配置列表
+/// - `Err(e)`: 列出失败
+pub fn list_configs() -> Result<Vec<ConfigInfo>> {
+    crate::bridge::config::list_configs().map_err(coded)
+}
+
+/// 验证配置
+///
+/// # 参数
+/// - `config`: 配置信息
+///
+/// # 返回
+/// - `Ok(true)`: 配置有效
+/// - `Ok(false)`: 配置无效
+/// - `Err(e)`: 验证失败
+pub fn validate_config(config: ConfigInfo) -> Result<bool> {
+    crate::bridge::config::validate_config(config).map_err(coded)
+}
+
+/// 校验并规范化手动编辑的服务器配置
+///
+/// 检查 UUID 格式、端口范围、地址有效性、Shadowsocks 加密方式和 VLESS flow 等，
+/// 返回规范化后的配置和逐字段错误，供 UI 直接展示。
+///
+/// # 参数
+/// - `config`: 代理服务器配置
+///
+/// # 返回
+/// - `Ok(result)`: 校验结果
+/// - `Err(e)`: 校验失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn validate_server_fields(config: ProxyServerConfig) -> Result<ServerValidationInfo> {
+    crate::bridge::config::validate_server_fields(config).map_err(coded)
+}
+
+/// 检查配置，按字段路径返回所有问题
+///
+/// # 参数
+/// - `config`: 配置信息
+///
+/// # 返回
+/// - `Ok(diagnostics)`: 诊断列表，没有错误级别的诊断即为有效
+/// - `Err(e)`: 检查失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn diagnose_config(config: ConfigInfo) -> Result<Vec<ConfigDiagnostic>> {
+    crate::bridge::config::diagnose_config(config).map_err(coded)
+}
+
+/// 检查服务器配置，按字段路径返回所有问题
+///
+/// 除字段格式外还检查各协议的必需设置和传输设置（WebSocket 路径、
+/// REALITY 密钥等）是否一致
+///
+/// # 参数
+/// - `config`: 服务器配置
+///
+/// # 返回
+/// - `Ok(diagnostics)`: 诊断列表，没有错误级别的诊断即为有效
+/// - `Err(e)`: 检查失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn diagnose_server(config: ProxyServerConfig) -> Result<Vec<ConfigDiagnostic>> {
+    crate::bridge::config::diagnose_server(config).map_err(coded)
+}
+
+// ============================================================================
+// 连接管理 API
+// ============================================================================
+
+/// 缓存代理配置（在连接前调用）
+///
+/// # 参数
+/// - `config_id`: 配置 ID
+/// - `config`: 代理服务器配置
+///
+/// # 返回
+/// - `Ok(())`: 缓存成功
+/// - `Err(e)`: 缓存失败
+pub fn cache_proxy_config(config_id: String, config: ProxyServerConfig) -> Result<()> {
+    crate::bridge::connection::cache_proxy_config(config_id, config).map_err(coded)
+}
+
+/// 设置代理模式
+///
+/// # 参数
+/// - `mode`: 代理模式 ("global", "smart", "direct")
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置失败
+pub fn set_proxy_mode(mode: String) -> Result<()> {
+    crate::bridge::connection::set_proxy_mode(mode).map_err(coded)
+}
+
+/// 设置本地端口冲突策略
+///
+/// 启动时若 HTTP/SOCKS 端口已被占用：`fail` 返回错误（包含占用进程 PID），
+/// `reassign` 自动改用空闲端口、保存到设置并发送 `PortReassigned` 事件。策略会被保存
+///
+/// # 参数
+/// - `policy`: 冲突策略 ("fail", "reassign")
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 策略无效或保存失败
+pub async fn set_port_conflict_policy(policy: String) -> Result<()> {
+    crate::bridge::connection::set_port_conflict_policy(&policy)
+        .await
+        .map_err(coded)
+}
+
+/// 获取本地入站设置
+pub async fn get_inbound_settings() -> InboundSettingsInfo {
+    crate::bridge::connection::get_inbound_settings().await
+}
+
+/// 修改本地入站设置
+///
+/// 设置会被保存，下次连接时生效。允许局域网访问时 HTTP 和 SOCKS 入站都必须设置认证
+///
+/// # 参数
+/// - `settings`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置无效或保存失败
+pub async fn set_inbound_settings(settings: InboundSettingsInfo) -> Result<()> {
+    crate::bridge::connection::set_inbound_settings(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 获取 Xray 进程资源限制
+///
+/// # 返回
+/// - `Ok(limits)`: 当前资源限制
+/// - `Err(e)`: 获取失败
+pub fn get_xray_resource_limits() -> Result<XrayResourceLimitsInfo> {
+    crate::bridge::connection::get_xray_resource_limits().map_err(coded)
+}
+
+/// 设置 Xray 进程资源限制
+///
+/// 适用于低内存路由器和旧电脑：降低 Xray 的进程优先级、限制可用 CPU，
+/// 内存超过上限时自动重启并发送 `XrayMemoryLimitExceeded` 事件。运行中的进程立即应用优先级和 CPU 限制；
+/// 平台不支持的设置只记录日志
+///
+/// # 参数
+/// - `limits`: 资源限制
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 优先级无效、CPU 不存在或内存上限过小
+pub fn set_xray_resource_limits(limits: XrayResourceLimitsInfo) -> Result<()> {
+    crate::bridge::connection::set_xray_resource_limits(limits).map_err(coded)
+}
+
+/// 获取引擎（Xray）日志级别
+///
+/// # 返回
+/// - `Ok(level)`: 日志级别 ("none", "error", "warning", "info", "debug")
+/// - `Err(e)`: 获取失败
+pub fn get_engine_log_level() -> Result<String> {
+    crate::bridge::connection::get_engine_log_level().map_err(coded)
+}
+
+/// 设置引擎（Xray）日志级别
+///
+/// 与应用自身的日志级别相互独立。已连接时会热重载 Xray 使其立即生效，
+/// 本地端口因此变化时发送 `PortReassigned` 事件；事件流中的 `Log` 事件也按此级别过滤。
+///
+/// # 参数
+/// - `level`: 日志级别 ("none", "error", "warning", "info", "debug")
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 级别无效或热重载失败
+pub fn set_engine_log_level(level: String) -> Result<()> {
+    crate::bridge::connection::set_engine_log_level(&level).map_err(coded)
+}
+
+/// 连接到服务器
+///
+/// Xray 启动后会等待本地入站可用，并通过代理完成一次连通性检查才返回；
+/// 期间通过事件流依次发送 `Connecting` 和 `Connected` 状态。会阻塞调用线程，
+/// 需要进度或取消时使用 `connect_async`。
+///
+/// # 参数
+/// - `config_id`: 配置 ID
+///
+/// # 返回
+/// - `Ok(())`: 连接成功，代理已可用
+/// - `Err(e)`: 连接失败或超时未就绪
+pub fn connect(config_id: String) -> Result<()> {
+    crate::bridge::connection::connect(&config_id).map_err(coded)
+}
+
+/// 断开连接
+///
+/// # 返回
+/// - `Ok(())`: 断开成功
+/// - `Err(e)`: 断开失败
+pub fn disconnect() -> Result<()> {
+    crate::bridge::connection::disconnect().map_err(coded)
+}
+
+/// 异步连接到服务器
+///
+/// 与 `connect` 相同，但不阻塞调用线程。期间通过事件流发送 `ConnectPhaseChanged`
+/// 事件报告进度，可调用 `cancel_connect` 取消；新的连接请求会取消尚未完成的请求。
+///
+/// # 参数
+/// - `config_id`: 配置 ID
+///
+/// # 返回
+/// - `Ok(())`: 连接成功，代理已可用
+/// - `Err(e)`: 连接失败、超时未就绪或已取消（错误码 2008）
+pub async fn connect_async(config_id: String) -> Result<()> {
+    crate::bridge::connection::connect_async(&config_id)
+        .await
+        .map_err(coded)
+}
+
+/// 按负载均衡策略连接到订阅中的所有服务器
+///
+/// Xray 通过观测探测各服务器健康状态，在可用的服务器之间分配流量
+///
+/// # 参数
+/// - `subscription_id`: 订阅（分组）ID
+/// - `strategy`: "least_ping"、"random"、"round_robin" 或 "manual"；
+///   manual 按 `reorder_servers` 保存的顺序使用第一台服务器，不可用时切换到第二台
+///
+/// # 返回
+/// - `Ok(())`: 连接成功
+/// - `Err(e)`: 策略无效、订阅中没有服务器或连接失败
+pub async fn connect_balanced(subscription_id: String, strategy: String) -> Result<()> {
+    crate::bridge::connection::connect_balanced(subscription_id, &strategy)
+        .await
+        .map_err(coded)
+}
+
+/// 按连接质量自动选择订阅中的服务器并连接
+///
+/// 按延迟历史中的 p95、抖动和丢包率选择最稳定的服务器，而不是最近一次最快的服务器。
+/// 还没有测试结果时先测试一遍延迟。需先调用 `init_latency_history`
+///
+/// # 参数
+/// - `subscription_id`: 订阅（分组）ID
+///
+/// # 返回
+/// - `Ok(server_id)`: 选中并已连接的服务器 ID
+/// - `Err(e)`: 订阅中没有可用的服务器、延迟历史未初始化或连接失败
+pub async fn connect_best_server(subscription_id: String) -> Result<String> {
+    crate::bridge::connection::connect_best_server(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 取消正在进行的连接
+///
+/// 已启动的 Xray 会被停止
+///
+/// # 返回
+/// - `true`: 有正在进行的连接被取消
+#[flutter_rust_bridge::frb(sync)]
+pub fn cancel_connect() -> bool {
+    crate::bridge::connection::cancel_connect()
+}
+
+/// 异步断开连接，正在进行的连接会先被取消
+///
+/// # 返回
+/// - `Ok(())`: 断开成功
+/// - `Err(e)`: 断开失败
+pub async fn disconnect_async() -> Result<()> {
+    crate::bridge::connection::disconnect_async()
+        .await
+        .map_err(coded)
+}
+
+/// 获取连接信息
+///
+/// # 返回
+/// - `Ok(info)`: 连接信息
+/// - `Err(e)`: 获取失败
+pub fn get_connection_info() -> Result<ConnectionInfo> {
+    crate::bridge::connection::get_connection_info().map_err(coded)
+}
+
+/// 获取聚合健康状态
+///
+/// 并行探测当前连接的所有出站，并汇总 Xray 实例状态。
+/// `get_connection_info` 保持不变以兼容旧版本。
+///
+/// # 返回
+/// - `Ok(info)`: 聚合健康状态
+/// - `Err(e)`: 获取失败
+pub fn get_aggregated_health() -> Result<AggregatedHealthInfo> {
+    crate::bridge::connection::get_aggregated_health().map_err(coded)
+}
+
+/// 启动附加的 Xray 实例（配置档）
+///
+/// 附加实例与主连接同时运行，使用独立的端口、配置文件和进程，
+/// 例如浏览器使用全局代理的实例，同时游戏使用直连实例。
+/// 路由、DNS 和入站设置沿用主连接。状态变化通过 `InstanceStatusChanged` 事件发送。
+///
+/// # 参数
+/// - `instance_id`: 实例 ID（不能为 "default"，该 ID 属于主连接）
+/// - `server_id`: 服务器 ID
+/// - `mode`: 代理模式（global / smart / direct）
+/// - `http_port`: HTTP 入站端口
+/// - `socks_port`: SOCKS 入站端口
+///
+/// # 返回
+/// - `Ok(())`: 启动成功
+/// - `Err(e)`: 端口已被主连接或其他实例占用、实例已在运行或启动失败
+pub async fn start_xray_instance(
+    instance_id: String,
+    server_id: String,
+    mode: String,
+    http_port: u16,
+    socks_port: u16,
+) -> Result<()> {
+    crate::bridge::connection::start_xray_instance(
+        &instance_id,
+        &server_id,
+        &mode,
+        http_port,
+        socks_port,
+    )
+    .await
+    .map_err(coded)
+}
+
+/// 停止附加的 Xray 实例
+///
+/// # 返回
+/// - `Ok(true)`: 已停止
+/// - `Ok(false)`: 没有该实例
+/// - `Err(e)`: 停止失败
+pub async fn stop_xray_instance(instance_id: String) -> Result<bool> {
+    crate::bridge::connection::stop_xray_instance(&instance_id)
+        .await
+        .map_err(coded)
+}
+
+/// 列出附加的 Xray 实例（不含主连接），按 ID 排序
+pub async fn list_xray_instances() -> Vec<InstanceHealthInfo> {
+    crate::bridge::connection::list_xray_instances().await
+}
+
+/// 获取 Xray 进程状态与资源占用
+///
+/// 每 5 秒更新一次，Xray 未运行时返回空
+///
+/// # 返回
+/// - `Ok(info)`: 进程信息
+/// - `Err(e)`: 获取失败
+pub fn get_xray_process_info() -> Result<Option<XrayProcessInfo>> {
+    crate::bridge::connection::get_xray_process_info().map_err(coded)
+}
+
+/// 获取连通性探测设置
+///
+/// # 返回
+/// - `Ok(config)`: 当前设置
+/// - `Err(e)`: 获取失败
+pub fn get_health_probe_config() -> Result<HealthProbeConfigInfo> {
+    crate::bridge::connection::get_health_probe_config().map_err(coded)
+}
+
+/// 设置连通性探测
+///
+/// 连接期间定期经本地代理请求探测 URL，连续失败达到阈值后判定为降级并触发自动重连
+///
+/// # 参数
+/// - `config`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置无效
+pub fn set_health_probe_config(config: HealthProbeConfigInfo) -> Result<()> {
+    crate::bridge::connection::set_health_probe_config(config).map_err(coded)
+}
+
+/// 获取延迟告警设置
+///
+/// # 返回
+/// - `Ok(config)`: 当前设置
+/// - `Err(e)`: 获取失败
+pub fn get_latency_alert_config() -> Result<LatencyAlertConfigInfo> {
+    crate::bridge::connection::get_latency_alert_config().map_err(coded)
+}
+
+/// 设置延迟告警
+///
+/// 连通性探测同时测量经代理的延迟（结果见 `get_connection_info` 的 `latency_ms`），
+/// 连续多次超过阈值时发送 `DegradedConnection` 事件，恢复后发送 `LatencyRecovered` 事件。
+/// 探测间隔与开关见 `set_health_probe_config`
+///
+/// # 参数
+/// - `config`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置无效
+pub fn set_latency_alert_config(config: LatencyAlertConfigInfo) -> Result<()> {
+    crate::bridge::connection::set_latency_alert_config(config).map_err(coded)
+}
+
+/// 获取当前连接的连通性探测结果
+///
+/// # 返回
+/// - `Ok(status)`: 探测结果
+/// - `Err(e)`: 获取失败
+pub fn get_health_probe_status() -> Result<HealthProbeStatusInfo> {
+    crate::bridge::connection::get_health_probe_status().map_err(coded)
+}
+
+/// 获取当前会话的事件时间线
+///
+/// 按时间顺序返回本次连接会话中发生的事件，断开后保留到下次连接
+///
+/// # 返回
+/// - `Ok(events)`: 事件列表（最早的在前）
+/// - `Err(e)`: 获取失败
+pub fn get_session_timeline() -> Result<Vec<SessionEventInfo>> {
+    crate::bridge::connection::get_session_timeline().map_err(coded)
+}
+
+/// 打开流量历史数据库
+///
+/// 打开后连接期间的流量按小时、按天及服务器累计到数据库中，并清理超出保留期的记录；
+/// 同时开始定期检查流量预算（见 `set_traffic_budgets`）
+///
+/// # 参数
+/// - `db_path`: 数据库路径
+///
+/// # 返回
+/// - `Ok(())`: 打开成功
+/// - `Err(e)`: 打开失败
+pub async fn init_traffic_history(db_path: String) -> Result<()> {
+    crate::bridge::connection::init_traffic_history(&db_path)
+        .await
+        .map_err(coded)
+}
+
+/// 设置流量预算（替换全部预算）
+///
+/// 用量按流量历史统计，需先调用 `init_traffic_history`。用量达到 80%、100% 时各发送一次
+/// `TrafficBudgetAlert` 事件，达到 100% 时执行预算的动作；订阅预算的动作只在使用该订阅的服务器时执行
+///
+/// # 参数
+/// - `budgets`: 全部预算
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 预算无效或 ID 重复
+pub async fn set_traffic_budgets(budgets: Vec<TrafficBudgetInfo>) -> Result<()> {
+    crate::bridge::budget::set_traffic_budgets(budgets)
+        .await
+        .map_err(coded)
+}
+
+/// 获取全部流量预算
+pub async fn list_traffic_budgets() -> Vec<TrafficBudgetInfo> {
+    crate::bridge::budget::list_traffic_budgets().await
+}
+
+/// 获取各流量预算本周期的用量
+///
+/// # 返回
+/// - `Ok(usage)`: 各预算的用量
+/// - `Err(e)`: 流量历史未打开或读取失败
+pub async fn get_traffic_budget_usage() -> Result<Vec<TrafficBudgetUsageInfo>> {
+    crate::bridge::budget::get_traffic_budget_usage()
+        .await
+        .map_err(coded)
+}
+
+/// 获取最近若干天的每日流量
+///
+/// 没有流量的日期不返回
+///
+/// # 参数
+/// - `days`: 天数（含今天）
+/// - `server_id`: 只统计该服务器，为空时统计全部
+///
+/// # 返回
+/// - `Ok(usage)`: 每日流量（最早的在前）
+/// - `Err(e)`: 获取失败
+pub async fn get_daily_usage(
+    days: u32,
+    server_id: Option<String>,
+) -> Result<Vec<TrafficUsageInfo>> {
+    crate::bridge::connection::get_daily_usage(days, server_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取最近若干个月的每月流量
+///
+/// # 参数
+/// - `months`: 月数（含本月）
+/// - `server_id`: 只统计该服务器，为空时统计全部
+///
+/// # 返回
+/// - `Ok(usage)`: 每月流量（最早的在前）
+/// - `Err(e)`: 获取失败
+pub async fn get_monthly_usage(
+    months: u32,
+    server_id: Option<String>,
+) -> Result<Vec<TrafficUsageInfo>> {
+    crate::bridge::connection::get_monthly_usage(months, server_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取最近若干天各服务器的流量
+///
+/// # 参数
+/// - `days`: 天数（含今天）
+///
+/// # 返回
+/// - `Ok(usage)`: 各服务器流量（流量最多的在前）
+/// - `Err(e)`: 获取失败
+pub async fn get_server_usage(days: u32) -> Result<Vec<ServerTrafficInfo>> {
+    crate::bridge::connection::get_server_usage(days)
+        .await
+        .map_err(coded)
+}
+
+/// 打开延迟历史数据库
+///
+/// 打开后每次重新测试服务器的结果（含失败）都会记录下来，并清理 30 天前的记录
+///
+/// # 参数
+/// - `db_path`: 数据库路径
+///
+/// # 返回
+/// - `Ok(())`: 打开成功
+/// - `Err(e)`: 打开失败
+pub async fn init_latency_history(db_path: String) -> Result<()> {
+    crate::bridge::connection::init_latency_history(&db_path)
+        .await
+        .map_err(coded)
+}
+
+/// 获取服务器的连接质量（抖动、丢包率、P95 及最近的延迟走势）
+///
+/// # 参数
+/// - `server_id`: 服务器 ID
+///
+/// # 返回
+/// - `Ok(quality)`: 基于最近 50 次测试的质量，未测试过时样本为空
+/// - `Err(e)`: 延迟历史未打开
+pub async fn get_server_quality(server_id: String) -> Result<ServerQualityInfo> {
+    crate::bridge::connection::get_server_quality(server_id)
+        .await
+        .map_err(coded)
+}
+
+/// 按连接质量排序服务器，稳定的服务器优先于偶尔很快的服务器
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+///
+/// # 返回
+/// - `Ok(qualities)`: 评分最好的在前，没有成功测试的排在最后
+/// - `Err(e)`: 延迟历史未打开
+pub async fn rank_servers_by_quality(server_ids: Vec<String>) -> Result<Vec<ServerQualityInfo>> {
+    crate::bridge::connection::rank_servers_by_quality(server_ids)
+        .await
+        .map_err(coded)
+}
+
+/// 获取活动连接列表
+///
+/// Xray 不提供连接列表接口，连接取自访问日志：日志不记录连接结束和流量，
+/// 因此列出的是最近一段时间内出现过的连接，且没有单个连接的流量
+///
+/// # 返回
+/// - `Ok(connections)`: 活动连接（最新的在前）
+/// - `Err(e)`: 获取失败
+pub fn get_active_connections() -> Result<Vec<ActiveConnectionInfo>> {
+    crate::bridge::connection::get_active_connections().map_err(coded)
+}
+
+/// 关闭所有连接
+///
+/// Xray 无法关闭单个连接，因此以相同配置重启 Xray，所有连接随之断开
+///
+/// # 返回
+/// - `Ok(())`: 已关闭
+/// - `Err(e)`: 重启失败
+pub fn close_all_connections() -> Result<()> {
+    crate::bridge::connection::close_all_connections().map_err(coded)
+}
+
+/// 打开访问日志数据库
+///
+/// 打开后访问日志记录会定期写入数据库，域名统计可跨越重启；未打开时只统计
+/// 内存中最近的记录。同时清理 30 天前的记录
+///
+/// # 参数
+/// - `db_path`: 数据库路径
+///
+/// # 返回
+/// - `Ok(())`: 打开成功
+/// - `Err(e)`: 打开失败
+pub async fn init_access_log_storage(db_path: String) -> Result<()> {
+    crate::bridge::connection::init_access_log_storage(&db_path)
+        .await
+        .map_err(coded)
+}
+
+/// 获取访问最多的域名
+///
+/// # 参数
+/// - `limit`: 最多返回的域名数
+/// - `hours`: 统计最近若干小时
+///
+/// # 返回
+/// - `Ok(domains)`: 域名统计（连接次数多的在前）
+/// - `Err(e)`: 获取失败
+pub async fn get_top_domains(limit: u32, hours: u32) -> Result<Vec<DomainStatInfo>> {
+    crate::bridge::connection::get_top_domains(limit, hours)
+        .await
+        .map_err(coded)
+}
+
+/// 获取被拦截最多的域名
+///
+/// # 参数
+/// - `limit`: 最多返回的域名数
+/// - `hours`: 统计最近若干小时
+///
+/// # 返回
+/// - `Ok(domains)`: 域名统计（拦截次数多的在前）
+/// - `Err(e)`: 获取失败
+pub async fn get_blocked_domains(limit: u32, hours: u32) -> Result<Vec<DomainStatInfo>> {
+    crate::bridge::connection::get_blocked_domains(limit, hours)
+        .await
+        .map_err(coded)
+}
+
+/// 获取代理、直连与拦截的连接数
+///
+/// # 参数
+/// - `hours`: 统计最近若干小时
+///
+/// # 返回
+/// - `Ok(split)`: 各路由的连接数
+/// - `Err(e)`: 获取失败
+pub async fn get_route_split(hours: u32) -> Result<RouteSplitInfo> {
+    crate::bridge::connection::get_route_split(hours)
+        .await
+        .map_err(coded)
+}
+
+/// 对当前服务器测速
+///
+/// 通过本地 HTTP 入站下载（及上传）一段时间并计算吞吐量，期间发送
+/// `SpeedTestProgress` 事件。结果会记录下来用于服务器排名
+///
+/// # 参数
+/// - `options`: 测速选项
+///
+/// # 返回
+/// - `Ok(result)`: 测速结果
+/// - `Err(e)`: 未连接或测速失败
+pub async fn run_speed_test(options: SpeedTestOptions) -> Result<SpeedTestInfo> {
+    crate::bridge::connection::run_speed_test(options)
+        .await
+        .map_err(coded)
+}
+
+/// 获取按测速结果排名的服务器
+///
+/// # 返回
+/// - `Ok(ranking)`: 排名（下载速度中位数高的在前）
+/// - `Err(e)`: 获取失败
+pub fn get_speed_ranking() -> Result<Vec<SpeedRankingInfo>> {
+    crate::bridge::connection::get_speed_ranking().map_err(coded)
+}
+
+/// 获取某服务器最近的测速结果
+///
+/// # 参数
+/// - `server_id`: 服务器 ID
+///
+/// # 返回
+/// - `Ok(results)`: 测速结果（旧的在前）
+/// - `Err(e)`: 获取失败
+pub fn get_speed_test_results(server_id: String) -> Result<Vec<SpeedTestInfo>> {
+    crate::bridge::connection::get_speed_test_results(&server_id).map_err(coded)
+}
+
+/// 检测当前服务器的流媒体解锁情况
+///
+/// 通过代理访问各服务的探测页面并判断是否可用及所在地区，结果按服务器缓存
+///
+/// # 参数
+/// - `services`: 要检测的服务（netflix / youtube_premium / chatgpt / disney_plus），为空时检测全部
+/// - `refresh`: 是否忽略缓存重新检测
+///
+/// # 返回
+/// - `Ok(results)`: 各服务的检测结果
+/// - `Err(e)`: 未连接或服务名无效
+pub async fn check_unlock(services: Vec<String>, refresh: bool) -> Result<Vec<UnlockResultInfo>> {
+    crate::bridge::connection::check_unlock(services, refresh)
+        .await
+        .map_err(coded)
+}
+
+/// 获取某服务器缓存的流媒体解锁结果
+///
+/// # 参数
+/// - `server_id`: 服务器 ID
+///
+/// # 返回
+/// - `Ok(results)`: 缓存的检测结果
+/// - `Err(e)`: 获取失败
+pub fn get_unlock_results(server_id: String) -> Result<Vec<UnlockResultInfo>> {
+    crate::bridge::connection::get_unlock_results(&server_id).map_err(coded)
+}
+
+/// 列出可用的 IP 信息查询服务
+///
+/// # 返回
+/// - `Ok(providers)`: 查询服务及其隐私说明
+/// - `Err(e)`: 获取失败
+pub fn list_exit_info_providers() -> Result<Vec<ExitInfoProviderInfo>> {
+    crate::bridge::connection::list_exit_info_providers().map_err(coded)
+}
+
+/// 获取当前连接的出口 IP 与地理位置
+///
+/// 经代理查询出口信息，并可与直连结果对比以确认代理生效。查询会将出口 IP
+/// 发送给所选服务
+///
+/// # 参数
+/// - `provider`: 查询服务 ID，为空时使用 ipinfo（HTTPS）
+/// - `compare_direct`: 是否同时直连查询并对比
+///
+/// # 返回
+/// - `Ok(info)`: 出口信息
+/// - `Err(e)`: 未连接或查询失败
+pub async fn get_exit_info(
+    provider: Option<String>,
+    compare_direct: bool,
+) -> Result<ExitInfoResult> {
+    crate::bridge::connection::get_exit_info(provider, compare_direct)
+        .await
+        .map_err(coded)
+}
+
+/// 运行连接诊断
+///
+/// 依次检查 Xray 程序、配置、本地端口、进程、本地入站、TCP 握手、TLS 握手、
+/// 经代理的请求和出口 IP，首个失败项之后的检查会跳过。未连接时会临时连接到
+/// 该服务器，结束后断开
+///
+/// # 参数
+/// - `server_id`: 服务器 ID，为空时诊断当前连接的服务器
+///
+/// # 返回
+/// - `Ok(report)`: 诊断报告（含失败阶段和修复建议）
+/// - `Err(e)`: 服务器不存在或未连接
+pub async fn run_diagnostics(server_id: Option<String>) -> Result<DiagnosticReportInfo> {
+    crate::bridge::connection::run_diagnostics(server_id)
+        .await
+        .map_err(coded)
+}
+
+/// 列出路由建议
+///
+/// 智能模式下，直连持续失败的域名会被建议改为走代理
+///
+/// # 返回
+/// - `Ok(suggestions)`: 建议列表（失败次数多的在前）
+/// - `Err(e)`: 获取失败
+pub fn list_route_suggestions() -> Result<Vec<RouteSuggestionInfo>> {
+    crate::bridge::routing::list_route_suggestions().map_err(coded)
+}
+
+/// 接受路由建议
+///
+/// 将对应的代理规则写入 "suggested" 路由规则集并保存，规则在下次连接时生效
+///
+/// # 参数
+/// - `suggestion_id`: 建议 ID
+///
+/// # 返回
+/// - `Ok(())`: 接受成功
+/// - `Err(e)`: 建议不存在或保存失败
+pub async fn accept_route_suggestion(suggestion_id: String) -> Result<()> {
+    crate::bridge::routing::accept_route_suggestion(&suggestion_id)
+        .await
+        .map_err(coded)
+}
+
+/// 忽略路由建议
+///
+/// 被忽略的域名在本次运行期间不再被建议
+///
+/// # 参数
+/// - `suggestion_id`: 建议 ID
+///
+/// # 返回
+/// - `Ok(())`: 忽略成功
+/// - `Err(e)`: 操作失败
+pub fn dismiss_route_suggestion(suggestion_id: String) -> Result<()> {
+    crate::bridge::routing::dismiss_route_suggestion(&suggestion_id).map_err(coded)
+}
+
+/// 列出路由规则集
+///
+/// # 返回
+/// - 路由规则集列表（按匹配顺序）
+pub async fn list_routing_rule_sets() -> Vec<RoutingRuleSetInfo> {
+    crate::bridge::routing::list_routing_rule_sets().await
+}
+
+/// 添加或更新路由规则集
+///
+/// 规则集保存在设置中，新规则集排在最后，更新时保持原位置；规则在下次连接时生效
+///
+/// # 参数
+/// - `rule_set`: 路由规则集
+///
+/// # 返回
+/// - `Ok(())`: 保存成功
+/// - `Err(e)`: 规则无效或保存失败
+pub async fn save_routing_rule_set(rule_set: RoutingRuleSetInfo) -> Result<()> {
+    crate::bridge::routing::save_routing_rule_set(rule_set)
+        .await
+        .map_err(coded)
+}
+
+/// 删除路由规则集
+///
+/// 同时从选择了它的配置档中移除
+///
+/// # 参数
+/// - `rule_set_id`: 规则集 ID
+///
+/// # 返回
+/// - `Ok(())`: 删除成功
+/// - `Err(e)`: 规则集不存在或保存失败
+pub async fn delete_routing_rule_set(rule_set_id: String) -> Result<()> {
+    crate::bridge::routing::delete_routing_rule_set(&rule_set_id)
+        .await
+        .map_err(coded)
+}
+
+/// 当前平台是否支持分应用路由
+///
+/// 仅 Windows 和 macOS 支持按进程名路由
+#[flutter_rust_bridge::frb(sync)]
+pub fn is_app_rules_supported() -> bool {
+    crate::bridge::routing::is_supported()
+}
+
+/// 列出所有应用规则
+pub async fn list_app_rules() -> Vec<AppRuleInfo> {
+    crate::bridge::routing::list_app_rules().await
+}
+
+/// 添加或更新应用规则
+///
+/// 规则保存在设置中，在下次连接时生效
+///
+/// # 参数
+/// - `rule`: 应用规则
+///
+/// # 返回
+/// - `Ok(())`: 保存成功
+/// - `Err(e)`: 保存失败
+pub async fn save_app_rule(rule: AppRuleInfo) -> Result<()> {
+    crate::bridge::routing::save_app_rule(rule)
+        .await
+        .map_err(coded)
+}
+
+/// 删除应用规则
+///
+/// # 参数
+/// - `rule_id`: 规则 ID
+///
+/// # 返回
+/// - `Ok(())`: 删除成功
+/// - `Err(e)`: 删除失败
+pub async fn delete_app_rule(rule_id: String) -> Result<()> {
+    crate::bridge::routing::delete_app_rule(&rule_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取 DNS 设置
+pub async fn get_dns_settings() -> DnsSettingsInfo {
+    crate::bridge::routing::get_dns_settings().await
+}
+
+/// 修改 DNS 设置
+///
+/// 设置会被保存，分域 DNS 规则保持不变；下次连接时生效
+///
+/// # 参数
+/// - `settings`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置无效或保存失败
+pub async fn set_dns_settings(settings: DnsSettingsInfo) -> Result<()> {
+    crate::bridge::routing::set_dns_settings(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 列出分域 DNS 规则
+pub async fn list_split_dns_rules() -> Vec<SplitDnsRuleInfo> {
+    crate::bridge::routing::list_split_dns_rules().await
+}
+
+/// 设置分域 DNS 规则
+///
+/// 规则中的域名由指定 DNS 服务器解析并直连，TUN 模式下对整个系统生效。
+/// 规则保存在设置中，下次连接时生效
+///
+/// # 参数
+/// - `rules`: 新的规则列表，替换现有规则
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 规则无效
+pub async fn set_split_dns_rules(rules: Vec<SplitDnsRuleInfo>) -> Result<()> {
+    crate::bridge::routing::set_split_dns_rules(rules)
+        .await
+        .map_err(coded)
+}
+
+/// 获取直连优先设置
+pub async fn get_direct_preference_settings() -> DirectPreferenceSettingsInfo {
+    crate::bridge::routing::get_direct_preference_settings().await
+}
+
+/// 设置直连优先
+///
+/// 设置保存在应用设置中，重启后保留
+///
+/// # 参数
+/// - `settings`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 域名无效或保存失败
+pub async fn set_direct_preference_settings(settings: DirectPreferenceSettingsInfo) -> Result<()> {
+    crate::bridge::routing::set_direct_preference_settings(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 探测直连优先域名
+///
+/// 分别直连和经代理请求探测列表中的域名，直连可用且不明显变慢的域名写入
+/// "direct-preferred" 路由规则集并保存，规则在下次连接时生效。TUN 模式下不可用
+///
+/// # 返回
+/// - `Ok(results)`: 每个域名的探测结果
+/// - `Err(e)`: 未启用、未连接或处于 TUN 模式
+pub async fn probe_direct_preference() -> Result<Vec<DirectProbeResultInfo>> {
+    crate::bridge::routing::probe_direct_preference()
+        .await
+        .map_err(coded)
+}
+
+/// 获取脚本路由设置
+pub async fn get_script_routing_settings() -> ScriptRoutingSettingsInfo {
+    crate::bridge::routing::get_script_routing_settings().await
+}
+
+/// 设置脚本路由
+///
+/// 设置保存在应用设置中，重启后保留。连接中修改脚本对新连接立即生效；
+/// 开启脚本路由在下次连接时生效
+///
+/// # 参数
+/// - `settings`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 脚本无法编译、未定义 `route(conn)`、时间上限无效或保存失败
+pub async fn set_script_routing_settings(settings: ScriptRoutingSettingsInfo) -> Result<()> {
+    crate::bridge::routing::set_script_routing_settings(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 用脚本测试目标会走哪个出站，不发送流量
+///
+/// # 参数
+/// - `script`: Rhai 脚本
+/// - `host`: 域名或 IP
+/// - `port`: 目标端口
+///
+/// # 返回
+/// - `Ok(Some(tag))`: 脚本选择的出站标签
+/// - `Ok(None)`: 脚本未选择出站、出错或超时，按路由规则处理
+/// - `Err(e)`: 脚本无法编译
+pub fn test_routing_script(script: String, host: String, port: u16) -> Result<Option<String>> {
+    crate::bridge::routing::test_routing_script(&script, &host, port).map_err(coded)
+}
+
+/// 测试目标在当前路由规则下会走哪个出站
+///
+/// 按 Xray 的顺序在本地匹配正在使用的路由规则（包括 geosite、geoip），不发送流量。
+/// 用于排查某个网站为何没有走代理
+///
+/// # 参数
+/// - `target`: 域名或 IP
+/// - `port`: 目标端口
+/// - `protocol`: "tcp"、"udp"，或嗅探到的协议（"http"、"tls"、"bittorrent"），为空表示 TCP
+///
+/// # 返回
+/// - `Ok(result)`: 命中的规则和出站
+/// - `Err(e)`: 未连接、缺少 geo 数据文件或规则无效
+pub fn test_route(target: String, port: u16, protocol: Option<String>) -> Result<RouteTestInfo> {
+    crate::bridge::routing::test_route(&target, port, protocol).map_err(coded)
+}
+
+/// 加载缓存的 GFWList
+///
+/// 应在启动时加载设置之后调用；缓存不存在时不报错，已启用时开始定期更新
+///
+/// # 参数
+/// - `data_dir`: 应用数据目录
+///
+/// # 返回
+/// - `Ok(())`: 加载成功
+/// - `Err(e)`: 读取失败或缓存无效
+pub async fn init_gfwlist(data_dir: String) -> Result<()> {
+    crate::bridge::gfwlist::init_gfwlist(&data_dir)
+        .await
+        .map_err(coded)
+}
+
+/// 获取 GFWList 设置
+pub async fn get_gfwlist_settings() -> GfwListSettingsInfo {
+    crate::bridge::gfwlist::get_gfwlist_settings().await
+}
+
+/// 修改 GFWList 设置
+///
+/// 设置会被保存。启用后智能模式按 GFWList 分流，用户规则仍然优先，并按间隔定期更新；
+/// 按智能模式连接时规则立即应用到当前连接
+///
+/// # 参数
+/// - `settings`: 新设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 地址无效或间隔为 0
+pub async fn set_gfwlist_settings(settings: GfwListSettingsInfo) -> Result<()> {
+    crate::bridge::gfwlist::set_gfwlist_settings(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 获取 GFWList 更新状态
+pub fn get_gfwlist_status() -> GfwListStatusInfo {
+    crate::bridge::gfwlist::get_gfwlist_status()
+}
+
+/// 立即更新 GFWList
+///
+/// 按订阅的重试和线路设置下载；按智能模式连接时规则立即应用到当前连接
+///
+/// # 返回
+/// - `Ok(status)`: 更新后的状态
+/// - `Err(e)`: 下载失败或列表无效
+pub async fn update_gfwlist() -> Result<GfwListStatusInfo> {
+    crate::bridge::gfwlist::update_gfwlist()
+        .await
+        .map_err(coded)
+}
+
+/// 初始化本地控制接口访问令牌
+///
+/// 令牌保存在数据目录中，仅当前用户可读；不存在时自动生成
+///
+/// # 参数
+/// - `data_dir`: 应用数据目录
+///
+/// # 返回
+/// - `Ok(token)`: 当前访问令牌
+/// - `Err(e)`: 初始化失败
+pub fn init_access_token(data_dir: String) -> Result<String> {
+    crate::bridge::control::init_access_token(&data_dir).map_err(coded)
+}
+
+/// 轮换本地控制接口访问令牌
+///
+/// 旧令牌立即失效
+///
+/// # 返回
+/// - `Ok(token)`: 新的访问令牌
+/// - `Err(e)`: 轮换失败
+pub fn rotate_access_token() -> Result<String> {
+    crate::bridge::control::rotate_access_token().map_err(coded)
+}
+
+/// 获取单实例锁
+///
+/// 应在启动时最先调用。获取成功后，后续启动的实例发来的消息以 `InstanceActivated`
+/// 事件发送；锁已被其他实例持有时，将 `message`（如 `show`）发送给该实例，
+/// 当前进程随后应退出
+///
+/// # 参数
+/// - `data_dir`: 应用数据目录
+/// - `message`: 锁被占用时发送给正在运行实例的消息
+///
+/// # 返回
+/// - `Ok(true)`: 当前进程为唯一实例
+/// - `Ok(false)`: 已有实例在运行，并已收到消息
+/// - `Err(e)`: 获取失败，或已有实例在运行但无响应
+pub async fn try_acquire_instance_lock(data_dir: String, message: String) -> Result<bool> {
+    crate::bridge::control::try_acquire_instance_lock(&data_dir, &message)
+        .await
+        .map_err(coded)
+}
+
+/// 启动本地 REST/WebSocket 控制接口
+///
+/// 仅监听回环地址，请求需以 `Authorization: Bearer <令牌>` 携带访问令牌；
+/// 需先调用 `init_access_token`
+///
+/// # 参数
+/// - `port`: 监听端口，0 表示自动选择
+///
+/// # 返回
+/// - `Ok(port)`: 实际监听的端口
+/// - `Err(e)`: 启动失败
+#[cfg(feature = "control-api")]
+pub async fn start_control_server(port: u16) -> Result<u16> {
+    crate::bridge::controller::start_control_server(port)
+        .await
+        .map_err(coded)
+}
+
+/// 停止本地控制接口
+#[cfg(feature = "control-api")]
+pub fn stop_control_server() {
+    crate::bridge::controller::stop_control_server()
+}
+
+/// 初始化运行状态文件并返回上次会话状态
+///
+/// 应在启动时调用。若 `unclean_shutdown` 为真，可提示用户恢复上次会话
+/// （缓存配置后以 `server_id` 调用 `connect`，或调用 `restore_last_session`），
+/// 并应调用 `cleanup_previous_session` 清理遗留的系统代理和 TUN 路由
+///
+/// # 参数
+/// - `data_dir`: 应用数据目录
+///
+/// # 返回
+/// - `Ok(info)`: 上次会话状态
+/// - `Err(e)`: 初始化失败
+pub fn init_runtime_state(data_dir: String) -> Result<PreviousSessionInfo> {
+    crate::bridge::session::init_runtime_state(&data_dir).map_err(coded)
+}
+
+/// 清理上次会话遗留的 Xray 进程、系统代理和 TUN 路由
+///
+/// 有遗留时在事件流中发送 `PreviousSessionRecovered` 事件
+///
+/// # 返回
+/// - `Ok(())`: 清理完成
+/// - `Err(e)`: 清理失败
+pub async fn cleanup_previous_session() -> Result<()> {
+    crate::bridge::session::cleanup_previous_session()
+        .await
+        .map_err(coded)
+}
+
+/// 恢复上次会话
+///
+/// 启用了启动时恢复且上次退出时仍处于连接状态（而非由用户断开）时，以上次的
+/// 服务器和代理模式重新连接，上次设置了系统代理时一并恢复。应在
+/// `cleanup_previous_session` 和订阅管理器初始化之后调用。
+///
+/// # 返回
+/// - `Ok(info)`: 恢复结果，无需恢复时 `restored` 为 false
+/// - `Err(e)`: 运行状态未初始化、服务器已不存在或连接失败
+pub async fn restore_last_session() -> Result<SessionRestoreInfo> {
+    crate::bridge::session::restore_last_session()
+        .await
+        .map_err(coded)
+}
+
+/// 设置启动时是否恢复上次会话（保存在运行状态文件中）
+///
+/// # 参数
+/// - `enabled`: 是否启用
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 运行状态未初始化或写入失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_reconnect_on_launch(enabled: bool) -> Result<()> {
+    crate::bridge::session::set_reconnect_on_launch(enabled).map_err(coded)
+}
+
+/// 获取启动时是否恢复上次会话
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_reconnect_on_launch() -> bool {
+    crate::bridge::session::get_reconnect_on_launch()
+}
+
+/// 加载应用设置
+///
+/// 应在启动时调用。配置档等设置保存在配置目录的 `config.json` 中，
+/// 调用前的修改只保存在内存中
+///
+/// # 参数
+/// - `config_dir`: 应用配置目录
+///
+/// # 返回
+/// - `Ok(())`: 加载成功（文件不存在时使用默认设置）
+/// - `Err(e)`: 配置文件无法读取或无效
+pub async fn init_settings(config_dir: String) -> Result<()> {
+    crate::bridge::settings::init_settings(&config_dir)
+        .await
+        .map_err(coded)
+}
+
+/// 用加密密码解锁设置
+///
+/// 配置文件已加密而系统凭据库中没有保存密钥时，[`init_settings`] 会失败，
+/// 此时用密码解锁
+///
+/// # 参数
+/// - `password`: 加密密码
+/// - `remember`: 是否把密钥保存到系统凭据库，下次启动时自动解锁
+///
+/// # 返回
+/// - `Ok(())`: 解锁成功
+/// - `Err(e)`: 密码错误或凭据库不可用
+pub async fn unlock_settings(password: String, remember: bool) -> Result<()> {
+    crate::bridge::settings::unlock_settings(&password, remember)
+        .await
+        .map_err(coded)
+}
+
+/// 设置或更换加密密码
+///
+/// 配置文件和订阅数据库中的凭据改用新密码加密
+///
+/// # 参数
+/// - `password`: 新的加密密码
+/// - `remember`: 是否把密钥保存到系统凭据库（否则删除已保存的密钥）
+///
+/// # 返回
+/// - `Ok(())`: 已重新加密
+/// - `Err(e)`: 保存失败或凭据库不可用
+pub async fn set_encryption_password(password: String, remember: bool) -> Result<()> {
+    crate::bridge::settings::set_encryption_password(&password, remember)
+        .await
+        .map_err(coded)
+}
+
+/// 列出所有配置档
+pub async fn list_profiles() -> Vec<ProfileInfo> {
+    crate::bridge::profile::list_profiles().await
+}
+
+/// 添加或更新配置档
+///
+/// # 参数
+/// - `profile`: 配置档
+///
+/// # 返回
+/// - `Ok(())`: 保存成功
+/// - `Err(e)`: 配置档无效
+pub async fn save_profile(profile: ProfileInfo) -> Result<()> {
+    crate::bridge::profile::save_profile(profile)
+        .await
+        .map_err(coded)
+}
+
+/// 删除配置档
+///
+/// # 参数
+/// - `profile_id`: 配置档 ID
+///
+/// # 返回
+/// - `Ok(())`: 删除成功
+/// - `Err(e)`: 配置档不存在
+pub async fn delete_profile(profile_id: String) -> Result<()> {
+    crate::bridge::profile::delete_profile(&profile_id)
+        .await
+        .map_err(coded)
+}
+
+/// 切换到配置档
+///
+/// 一次应用配置档的端口、路由规则集、服务器和代理模式以及系统代理设置。
+/// 未指定服务器时，已连接则以新设置重连当前服务器。成功后发送 `ProfileSwitched` 事件
+///
+/// # 参数
+/// - `profile_id`: 配置档 ID
+///
+/// # 返回
+/// - `Ok(())`: 切换成功
+/// - `Err(e)`: 配置档不存在、连接失败或设置系统代理失败
+pub async fn switch_profile(profile_id: String) -> Result<()> {
+    crate::bridge::profile::switch_profile(&profile_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取当前配置档 ID，未切换过时为空
+pub async fn get_active_profile() -> Option<String> {
+    crate::bridge::profile::get_active_profile().await
+}
+
+/// 设置配置档使用的 Xray Core 版本
+///
+/// 切换到该配置档时使用此版本，其他配置档不受影响
+///
+/// # 参数
+/// - `profile_id`: 配置档 ID
+/// - `version`: 已安装的版本，`None` 表示使用当前选中的版本
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 配置档不存在或版本未安装
+pub async fn set_profile_xray_version(profile_id: String, version: Option<String>) -> Result<()> {
+    crate::bridge::profile::set_profile_xray_version(&profile_id, version)
+        .await
+        .map_err(coded)
+}
+
+/// 获取配置档使用的 Xray Core 版本
+///
+/// # 返回
+/// - `Ok(Some(version))`: 配置档固定的版本
+/// - `Ok(None)`: 使用当前选中的版本
+/// - `Err(e)`: 配置档不存在
+pub async fn get_profile_xray_version(profile_id: String) -> Result<Option<String>> {
+    crate::bridge::profile::get_profile_xray_version(&profile_id)
+        .await
+        .map_err(coded)
+}
+
+/// 设置配置档使用的代理核心
+///
+/// 切换到该配置档时生效。Hysteria 2、TUIC 服务器只能在 sing-box 上运行，不受此设置影响
+///
+/// # 参数
+/// - `profile_id`: 配置档 ID
+/// - `core`: "xray" 或 "sing_box"（也接受 "sing-box"）
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 配置档不存在或核心名称无效
+pub async fn set_profile_core(profile_id: String, core: String) -> Result<()> {
+    crate::bridge::profile::set_profile_core(&profile_id, &core)
+        .await
+        .map_err(coded)
+}
+
+/// 获取配置档使用的代理核心
+///
+/// # 返回
+/// - `Ok(core)`: "xray" 或 "sing_box"
+/// - `Err(e)`: 配置档不存在
+pub async fn get_profile_core(profile_id: String) -> Result<String> {
+    crate::bridge::profile::get_profile_core(&profile_id)
+        .await
+        .map_err(coded)
+}
+
+/// 设置配置档的本地入站限速
+///
+/// 切换到该配置档时生效。限速作用于 HTTP 和 SOCKS 入站的全部 TCP 流量，
+/// SOCKS 转发的 UDP 不受限制
+///
+/// # 参数
+/// - `profile_id`: 配置档 ID
+/// - `limit`: 上传、下载限速（字节/秒），为空表示不限速
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 配置档不存在或限速为 0
+pub async fn set_profile_speed_limit(profile_id: String, limit: SpeedLimitInfo) -> Result<()> {
+    crate::bridge::profile::set_profile_speed_limit(&profile_id, limit)
+        .await
+        .map_err(coded)
+}
+
+/// 获取配置档的本地入站限速
+///
+/// # 返回
+/// - `Ok(limit)`: 上传、下载限速（字节/秒）
+/// - `Err(e)`: 配置档不存在
+pub async fn get_profile_speed_limit(profile_id: String) -> Result<SpeedLimitInfo> {
+    crate::bridge::profile::get_profile_speed_limit(&profile_id)
+        .await
+        .map_err(coded)
+}
+
+/// 测试连接延迟
+///
+/// 测量到服务器的 TCP 连接耗时，已调用 `init_latency_history` 时结果记入延迟历史
+///
+/// # 参数
+/// - `config_id`: 配置 ID
+///
+/// # 返回
+/// - `Ok(latency_ms)`: 延迟（毫秒）
+/// - `Err(e)`: 测试失败
+pub fn test_latency(config_id: String) -> Result<u32> {
+    crate::bridge::connection::test_latency(&config_id).map_err(coded)
+}
+
+// ============================================================================
+// 流量统计 API
+// ============================================================================
+
+/// 创建实时流量流
+///
+/// 立即发送一次快照，此后按间隔发送，供速度曲线使用而无需轮询。取消订阅即停止。
+///
+/// # 参数
+/// - `interval_ms`: 发送间隔（毫秒），小于 100 时按 100 处理
+///
+/// # 返回
+/// - 流量快照流（总流量和当前速度）
+pub fn create_traffic_stream(interval_ms: u32) -> impl futures::Stream<Item = TrafficSnapshotInfo> {
+    crate::bridge::connection::create_traffic_stream(interval_ms)
+}
+
+// ============================================================================
+// 事件流 API
+// ============================================================================
+
+/// 创建事件流
+///
+/// 返回一个事件流，用于接收 V8Ray 事件
+///
+/// # 返回
+/// - 事件流
+pub fn create_event_stream() -> impl futures::Stream<Item = V8RayEvent> {
+    crate::bridge::events::create_event_stream()
+}
+
+// ============================================================================
+// 订阅管理 API
+// ============================================================================
+
+/// 初始化订阅管理器
+///
+/// # 参数
+/// - `db_path`: 数据库路径
+///
+/// # 返回
+/// - `Ok(())`: 初始化成功
+/// - `Err(e)`: 初始化失败
+pub async fn init_subscription_manager(db_path: String) -> Result<()> {
+    crate::bridge::subscription::init_subscription_manager(db_path)
+        .await
+        .map_err(coded)
+}
+
+/// 添加订阅
+///
+/// # 参数
+/// - `name`: 订阅名称
+/// - `url`: 订阅 URL
+///
+/// # 返回
+/// - `Ok(id)`: 订阅 ID
+/// - `Err(e)`: 添加失败
+pub async fn add_subscription(name: String, url: String) -> Result<String> {
+    crate::bridge::subscription::add_subscription(name, url)
+        .await
+        .map_err(coded)
+}
+
+/// 删除订阅
+///
+/// # 参数
+/// - `id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(())`: 删除成功
+/// - `Err(e)`: 删除失败
+pub async fn remove_subscription(id: String) -> Result<()> {
+    crate::bridge::subscription::remove_subscription(id)
+        .await
+        .map_err(coded)
+}
+
+/// 创建本地分组，用于存放手动添加的服务器
+///
+/// 本地分组与订阅一起列出（URL 为空），不会被更新；删除方式与订阅相同
+///
+/// # 参数
+/// - `name`: 分组名称
+///
+/// # 返回
+/// - `Ok(id)`: 分组 ID
+/// - `Err(e)`: 创建失败
+pub async fn create_server_group(name: String) -> Result<String> {
+    crate::bridge::subscription::create_server_group(name)
+        .await
+        .map_err(coded)
+}
+
+/// 从分享链接添加服务器到本地分组
+///
+/// # 参数
+/// - `group_id`: 本地分组 ID
+/// - `links`: 分享链接（如 vmess://），每行一个，也可以是 base64 编码的订阅内容
+///
+/// # 返回
+/// - `Ok(ids)`: 新增服务器的 ID
+/// - `Err(e)`: 链接无法解析、分组不存在或不是本地分组
+pub async fn add_servers_from_links(group_id: String, links: String) -> Result<Vec<String>> {
+    crate::bridge::subscription::add_servers_from_links(group_id, links)
+        .await
+        .map_err(coded)
+}
+
+/// 添加表单填写的服务器到本地分组
+///
+/// 配置先经过 `validate_server_fields` 校验，`config.id` 会被忽略
+///
+/// # 参数
+/// - `group_id`: 本地分组 ID
+/// - `config`: 服务器配置
+///
+/// # 返回
+/// - `Ok(id)`: 新增服务器的 ID
+/// - `Err(e)`: 配置无效、分组不存在或不是本地分组
+pub async fn add_server(group_id: String, config: ProxyServerConfig) -> Result<String> {
+    crate::bridge::subscription::add_server(group_id, config)
+        .await
+        .map_err(coded)
+}
+
+/// 从粘贴的文本导入服务器
+///
+/// 支持单个分享链接、夹杂其他文字的多行链接、base64 编码内容以及完整的 V2Ray JSON / Clash YAML 订阅。
+/// 服务器导入到第一个本地分组，没有时自动创建；与已有服务器（任意分组）重复的会被跳过
+///
+/// # 参数
+/// - `content`: 粘贴的文本
+///
+/// # 返回
+/// - `Ok(result)`: 导入结果，包括新增、重复和失败的条目
+/// - `Err(e)`: 保存失败
+pub async fn import_from_text(content: String) -> Result<ImportResult> {
+    crate::bridge::subscription::import_from_text(content)
+        .await
+        .map_err(coded)
+}
+
+/// 编辑本地分组中的服务器
+///
+/// 服务器 ID 和收藏等用户数据保持不变；订阅中的服务器每次更新都会被覆盖，因此不能编辑
+///
+/// # 参数
+/// - `config`: 服务器配置，`config.id` 为要编辑的服务器 ID
+///
+/// # 返回
+/// - `Ok(())`: 编辑成功
+/// - `Err(e)`: 配置无效、服务器不存在或不在本地分组中
+pub async fn edit_server(config: ProxyServerConfig) -> Result<()> {
+    crate::bridge::subscription::edit_server(config)
+        .await
+        .map_err(coded)
+}
+
+/// 更新订阅
+///
+/// # 参数
+/// - `id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(())`: 更新成功
+/// - `Err(e)`: 更新失败
+pub async fn update_subscription(id: String) -> Result<()> {
+    crate::bridge::subscription::update_subscription(id)
+        .await
+        .map_err(coded)
+}
+
+/// 更新所有订阅
+///
+/// # 返回
+/// - `Ok(())`: 更新成功
+/// - `Err(e)`: 更新失败
+pub async fn update_all_subscriptions() -> Result<()> {
+    crate::bridge::subscription::update_all_subscriptions()
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅最近的更新记录
+///
+/// 每个订阅保留最近 20 次更新尝试（耗时、结果、服务器数量变化），可用于判断是订阅源不稳定还是本地网络问题
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(history)`: 更新记录，最新的在前
+/// - `Err(e)`: 获取失败
+pub async fn get_subscription_update_history(
+    subscription_id: String,
+) -> Result<Vec<SubscriptionUpdateInfo>> {
+    crate::bridge::subscription::get_subscription_update_history(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅最近几次成功更新的服务器变化
+///
+/// 未变化的服务器在更新后保留原有 ID，收藏和延迟记录不会丢失
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(diffs)`: 服务器变化，最新的在前；失败的更新不包含在内
+/// - `Err(e)`: 获取失败
+pub async fn get_subscription_update_diffs(subscription_id: String) -> Result<Vec<ServerDiffInfo>> {
+    crate::bridge::subscription::get_subscription_update_diffs(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 设置订阅的自动更新计划
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+/// - `schedule`: 更新计划，`next_update` 被忽略
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 计划无效（如 cron 表达式错误）或设置失败
+pub async fn set_subscription_schedule(
+    subscription_id: String,
+    schedule: SubscriptionScheduleInfo,
+) -> Result<()> {
+    crate::bridge::subscription::set_subscription_schedule(subscription_id, schedule)
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅的自动更新计划及下次更新时间
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(schedule)`: 更新计划
+/// - `Err(e)`: 订阅不存在或获取失败
+pub async fn get_subscription_schedule(
+    subscription_id: String,
+) -> Result<SubscriptionScheduleInfo> {
+    crate::bridge::subscription::get_subscription_schedule(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅的请求选项
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(request)`: 请求选项
+/// - `Err(e)`: 订阅不存在或订阅管理器未初始化
+pub async fn get_subscription_request(subscription_id: String) -> Result<SubscriptionRequestInfo> {
+    crate::bridge::subscription::get_subscription_request(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 设置订阅的请求选项
+///
+/// 部分订阅商根据 User-Agent 返回不同格式，或要求认证请求头。选项随订阅保存，启用存储加密时与订阅链接一同加密
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+/// - `request`: 请求选项
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 选项无效、订阅不存在或为本地分组
+pub async fn set_subscription_request(
+    subscription_id: String,
+    request: SubscriptionRequestInfo,
+) -> Result<()> {
+    crate::bridge::subscription::set_subscription_request(subscription_id, request)
+        .await
+        .map_err(coded)
+}
+
+/// 检查订阅链接是否仍可访问
+///
+/// 发送 HEAD 请求（服务器不支持时改用 GET），跟随重定向，记录 HTTP 状态码和 TLS 错误，不下载订阅内容。
+/// 连续失败次数达到阈值时订阅标记为失效，并发送 `SubscriptionStale` 事件
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(health)`: 检查结果
+/// - `Err(e)`: 订阅不存在、为本地分组或订阅管理器未初始化
+pub async fn check_subscription_health(subscription_id: String) -> Result<SubscriptionHealthInfo> {
+    crate::bridge::subscription::check_subscription_health(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅最近一次健康检查结果
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(Some(health))`: 检查结果
+/// - `Ok(None)`: 尚未检查过
+/// - `Err(e)`: 获取失败
+pub async fn get_subscription_health(
+    subscription_id: String,
+) -> Result<Option<SubscriptionHealthInfo>> {
+    crate::bridge::subscription::get_subscription_health(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 配置订阅健康检查
+///
+/// # 参数
+/// - `stale_after`: 连续失败多少次后标记为失效（至少 1，默认 3）
+/// - `notify`: 标记为失效时是否发送 `SubscriptionStale` 事件
+///
+/// # 返回
+/// - `Ok(())`: 配置成功
+/// - `Err(e)`: 订阅管理器未初始化
+pub async fn configure_subscription_health(stale_after: u32, notify: bool) -> Result<()> {
+    crate::bridge::subscription::configure_subscription_health(stale_after, notify)
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅最近几次更新的下载尝试
+///
+/// 包含每次尝试的线路、耗时和状态码，用于排查订阅源缓慢或不稳定的问题
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(attempts)`: 下载尝试，最新的更新在前，同一次更新内按尝试顺序排列
+/// - `Err(e)`: 获取失败
+pub async fn get_subscription_fetch_attempts(
+    subscription_id: String,
+) -> Result<Vec<SubscriptionFetchAttemptInfo>> {
+    crate::bridge::subscription::get_subscription_fetch_attempts(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取订阅下载设置
+///
+/// # 返回
+/// - `Ok(settings)`: 当前设置
+/// - `Err(e)`: 订阅管理器未初始化
+pub async fn get_subscription_fetch_settings() -> Result<SubscriptionFetchSettings> {
+    crate::bridge::subscription::get_subscription_fetch_settings()
+        .await
+        .map_err(coded)
+}
+
+/// 配置订阅下载的重试和线路
+///
+/// 网络错误和指定状态码按指数退避重试；一条线路失败后换下一条线路（直连 → 代理或代理 → 直连）
+///
+/// # 参数
+/// - `settings`: 下载设置
+///
+/// # 返回
+/// - `Ok(())`: 配置成功
+/// - `Err(e)`: 设置无效或订阅管理器未初始化
+pub async fn configure_subscription_fetch(settings: SubscriptionFetchSettings) -> Result<()> {
+    crate::bridge::subscription::configure_subscription_fetch(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 配置订阅自动更新
+///
+/// # 参数
+/// - `interval_hours`: 默认更新间隔（小时），0 表示只自动更新设置了自己计划的订阅
+/// - `update_on_start`: 启动自动更新时是否立即更新所有订阅
+/// - `skip_when_metered`: 按流量计费的网络下是否推迟更新
+/// - `skip_when_offline`: 离线时是否推迟更新
+///
+/// # 返回
+/// - `Ok(())`: 配置成功
+/// - `Err(e)`: 订阅管理器未初始化
+pub async fn configure_auto_update(
+    interval_hours: u32,
+    update_on_start: bool,
+    skip_when_metered: bool,
+    skip_when_offline: bool,
+) -> Result<()> {
+    crate::bridge::subscription::configure_auto_update(
+        interval_hours,
+        update_on_start,
+        skip_when_metered,
+        skip_when_offline,
+    )
+    .await
+    .map_err(coded)
+}
+
+/// 开始自动更新订阅
+///
+/// 每分钟检查一次到期的订阅，更新后发送 `SubscriptionUpdated` 事件。已启动时不做任何操作
+#[flutter_rust_bridge::frb(sync)]
+pub fn start_auto_update() {
+    crate::bridge::subscription::start_auto_update()
+}
+
+/// 停止自动更新订阅
+#[flutter_rust_bridge::frb(sync)]
+pub fn stop_auto_update() {
+    crate::bridge::subscription::stop_auto_update()
+}
+
+/// 报告当前网络是否按流量计费
+///
+/// 各平台的计费网络状态只有应用层能获取，变化时由应用调用
+///
+/// # 参数
+/// - `metered`: 是否按流量计费
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_metered_network(metered: bool) {
+    crate::bridge::subscription::set_metered_network(metered)
+}
+
+/// 获取所有订阅
+///
+/// # 返回
+/// - `Ok(subscriptions)`: 订阅列表
+/// - `Err(e)`: 获取失败
+pub async fn get_subscriptions() -> Result<Vec<SubscriptionInfo>> {
+    crate::bridge::subscription::get_subscriptions()
+        .await
+        .map_err(coded)
+}
+
+/// 获取所有服务器
+///
+/// # 返回
+/// - `Ok(servers)`: 服务器列表
+/// - `Err(e)`: 获取失败
+pub async fn get_servers() -> Result<Vec<ServerInfo>> {
+    crate::bridge::subscription::get_servers()
+        .await
+        .map_err(coded)
+}
+
+/// 获取指定订阅的服务器
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+///
+/// # 返回
+/// - `Ok(servers)`: 服务器列表
+/// - `Err(e)`: 获取失败
+pub async fn get_servers_for_subscription(subscription_id: String) -> Result<Vec<ServerInfo>> {
+    crate::bridge::subscription::get_servers_for_subscription(subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 设置订阅内服务器的手动排序
+///
+/// 排序会持久化，订阅更新后仍然保留；负载均衡策略为 manual 时按此顺序选择服务器
+///
+/// # 参数
+/// - `subscription_id`: 订阅 ID
+/// - `server_ids`: 按目标顺序排列的服务器 ID（未列出的服务器排在后面）
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 服务器不存在或保存失败
+pub async fn reorder_servers(subscription_id: String, server_ids: Vec<String>) -> Result<()> {
+    crate::bridge::subscription::reorder_servers(subscription_id, server_ids)
+        .await
+        .map_err(coded)
+}
+
+/// 导出服务器手动排序
+///
+/// # 返回
+/// - `Ok(json)`: 排序 JSON（订阅 ID -> 服务器排序键列表）
+/// - `Err(e)`: 导出失败
+pub async fn export_server_order() -> Result<String> {
+    crate::bridge::subscription::export_server_order()
+        .await
+        .map_err(coded)
+}
+
+/// 导入服务器手动排序
+///
+/// # 参数
+/// - `json`: 由 `export_server_order` 导出的排序 JSON
+///
+/// # 返回
+/// - `Ok(())`: 导入成功
+/// - `Err(e)`: 导入失败
+pub async fn import_server_order(json: String) -> Result<()> {
+    crate::bridge::subscription::import_server_order(json)
+        .await
+        .map_err(coded)
+}
+
+/// 批量删除服务器
+///
+/// 在单个事务中删除，避免逐个调用
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+///
+/// # 返回
+/// - `Ok(count)`: 删除的服务器数量
+/// - `Err(e)`: 删除失败
+pub async fn delete_servers(server_ids: Vec<String>) -> Result<u32> {
+    crate::bridge::subscription::delete_servers(server_ids)
+        .await
+        .map_err(coded)
+}
+
+/// 批量移动服务器到本地分组
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+/// - `subscription_id`: 目标分组 ID（订阅的服务器每次更新都会被替换，不能作为目标）
+///
+/// # 返回
+/// - `Ok(count)`: 移动的服务器数量
+/// - `Err(e)`: 目标分组不存在、是订阅或保存失败
+pub async fn move_servers(server_ids: Vec<String>, subscription_id: String) -> Result<u32> {
+    crate::bridge::subscription::move_servers(server_ids, subscription_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取服务器列表（含收藏、别名、隐藏和置顶信息）
+///
+/// 用户数据按服务器指纹（协议、地址、端口和凭据）保存，与订阅更新无关，服务器改名或重新出现后仍然保留。
+/// 置顶的服务器按置顶顺序排在最前，其余保持原有顺序
+///
+/// # 参数
+/// - `include_hidden`: 是否包含已隐藏的服务器
+///
+/// # 返回
+/// - `Ok(servers)`: 服务器列表
+/// - `Err(e)`: 获取失败
+pub async fn get_server_list(include_hidden: bool) -> Result<Vec<ServerListItem>> {
+    crate::bridge::subscription::get_server_list(include_hidden)
+        .await
+        .map_err(coded)
+}
+
+/// 批量收藏或取消收藏服务器
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+/// - `favorite`: 是否收藏
+///
+/// # 返回
+/// - `Ok(count)`: 状态发生变化的服务器数量
+/// - `Err(e)`: 保存失败
+pub async fn set_servers_favorite(server_ids: Vec<String>, favorite: bool) -> Result<u32> {
+    crate::bridge::subscription::set_servers_favorite(server_ids, favorite)
+        .await
+        .map_err(coded)
+}
+
+/// 批量隐藏或显示服务器
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+/// - `hidden`: 是否隐藏
+///
+/// # 返回
+/// - `Ok(count)`: 状态发生变化的服务器数量
+/// - `Err(e)`: 保存失败
+pub async fn set_servers_hidden(server_ids: Vec<String>, hidden: bool) -> Result<u32> {
+    crate::bridge::subscription::set_servers_hidden(server_ids, hidden)
+        .await
+        .map_err(coded)
+}
+
+/// 设置服务器的自定义别名
+///
+/// # 参数
+/// - `server_id`: 服务器 ID
+/// - `alias`: 别名，None 或空白表示恢复订阅提供的名称
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 服务器不存在或保存失败
+pub async fn set_server_alias(server_id: String, alias: Option<String>) -> Result<()> {
+    crate::bridge::subscription::set_server_alias(server_id, alias)
+        .await
+        .map_err(coded)
+}
+
+/// 设置置顶的服务器及其顺序
+///
+/// 未列出的服务器取消置顶，传入空列表即取消全部置顶
+///
+/// # 参数
+/// - `server_ids`: 按目标顺序排列的置顶服务器 ID
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 保存失败
+pub async fn pin_servers(server_ids: Vec<String>) -> Result<()> {
+    crate::bridge::subscription::pin_servers(server_ids)
+        .await
+        .map_err(coded)
+}
+
+/// 批量为服务器添加标签
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+/// - `tag`: 标签
+///
+/// # 返回
+/// - `Ok(count)`: 新增标签的服务器数量（已有该标签的不计）
+/// - `Err(e)`: 保存失败
+pub async fn add_server_tag(server_ids: Vec<String>, tag: String) -> Result<u32> {
+    crate::bridge::subscription::add_server_tag(server_ids, tag)
+        .await
+        .map_err(coded)
+}
+
+/// 批量重新测试服务器（TCP 连接延迟，并发执行）
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+///
+/// # 返回
+/// - `Ok(results)`: 每个服务器的测试结果
+/// - `Err(e)`: 测试失败
+pub async fn retest_servers(server_ids: Vec<String>) -> Result<Vec<ServerLatencyInfo>> {
+    crate::bridge::subscription::retest_servers(server_ids)
+        .await
+        .map_err(coded)
+}
+
+/// 批量导出服务器分享链接
+///
+/// 不支持分享链接的协议（HTTP / SOCKS）会被跳过
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+///
+/// # 返回
+/// - `Ok(links)`: 分享链接列表
+/// - `Err(e)`: 导出失败
+pub async fn export_server_links(server_ids: Vec<String>) -> Result<Vec<String>> {
+    crate::bridge::subscription::export_server_links(server_ids)
+        .await
+        .map_err(coded)
+}
+
+/// 导出服务器，用于分享到其他设备
+///
+/// # 参数
+/// - `server_ids`: 服务器 ID 列表
+/// - `format`: 导出格式（links：每行一个分享链接 / base64：base64 编码的订阅内容 / clash：Clash YAML 配置）
+///
+/// # 返回
+/// - `Ok(export)`: 导出结果；格式不支持的服务器（如 Clash 不支持的 REALITY）列在 `skipped` 中
+/// - `Err(e)`: 格式无效
+pub async fn export_servers(server_ids: Vec<String>, format: String) -> Result<ServerExportInfo> {
+    crate::bridge::subscription::export_servers(server_ids, format)
+        .await
+        .map_err(coded)
+}
+
+/// 获取服务器配置
+///
+/// # 参数
+/// - `server_id`: 服务器 ID
+///
+/// # 返回
+/// - `Ok(config)`: 服务器配置
+/// - `Err(e)`: 获取失败
+pub async fn get_server_config(server_id: String) -> Result<ProxyServerConfig> {
+    crate::bridge::subscription::get_server_config(server_id)
+        .await
+        .map_err(coded)
+}
+
+/// 获取存储占用统计
+///
+/// 用于设置页的“存储占用”面板和清理任务的报告
+///
+/// # 返回
+/// - `Ok(stats)`: 存储统计
+/// - `Err(e)`: 获取失败
+pub async fn get_storage_stats() -> Result<StorageStatsInfo> {
+    crate::bridge::subscription::get_storage_stats()
+        .await
+        .map_err(coded)
+}
+
+/// 从存储加载订阅
+///
+/// # 返回
+/// - `Ok(())`: 加载成功
+/// - `Err(e)`: 加载失败
+pub async fn load_subscriptions_from_storage() -> Result<()> {
+    crate::bridge::subscription::load_subscriptions_from_storage()
+        .await
+        .map_err(coded)
+}
+
+// ============================================================================
+// 平台相关 API
+// ============================================================================
+
+/// 设置系统代理
+///
+/// 仅设置通过 `set_system_proxy_protocols` 启用的协议（默认 HTTP、HTTPS 和 SOCKS 全部启用）
+///
+/// # 参数
+/// - `http_port`: HTTP 代理端口
+/// - `socks_port`: SOCKS 代理端口
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_system_proxy(http_port: u16, socks_port: u16) -> Result<(), String> {
+    tracing::info!(
+        "FFI: set_system_proxy called with http_port={}, socks_port={}",
+        http_port,
+        socks_port
+    );
+    let result = crate::bridge::platform::set_system_proxy(http_port, socks_port);
+    if let Err(ref e) = result {
+        tracing::error!("FFI: set_system_proxy failed: {}", e);
+    } else {
+        tracing::info!("FFI: set_system_proxy succeeded");
+        crate::bridge::session::record_system_proxy_applied(format!(
+            "System proxy set (HTTP {}, SOCKS {})",
+            http_port, socks_port
+        ));
+    }
+    result.map_err(|e| coded_message(V8RayErrorCode::SystemProxy, e))
+}
+
+/// 通过 PAC 设置系统代理（自动模式）
+///
+/// 根据当前路由规则生成 PAC 脚本，由本地 HTTP 服务提供，并将系统代理指向其地址；
+/// 无法在 PAC 中表达的规则（如 geosite）交由 Xray 处理。再次调用会重新生成脚本。
+///
+/// # 参数
+/// - `http_port`: HTTP 代理端口
+/// - `socks_port`: SOCKS 代理端口
+///
+/// # 返回
+/// - `Ok(url)`: PAC 地址
+/// - `Err(e)`: 设置失败
+pub async fn set_system_proxy_pac(http_port: u16, socks_port: u16) -> Result<String, String> {
+    let url = crate::bridge::platform::set_system_proxy_pac(http_port, socks_port)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::SystemProxy, e))?;
+    crate::bridge::session::record_system_proxy_applied(format!("System proxy set (PAC {})", url));
+    Ok(url)
+}
+
+/// 清除系统代理
+///
+/// 恢复设置代理前的系统代理配置（如公司代理），无保存的配置时直接关闭代理
+///
+/// # 返回
+/// - `Ok(())`: 清除成功
+/// - `Err(e)`: 清除失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn clear_system_proxy() -> Result<(), String> {
+    let result = crate::bridge::platform::clear_system_proxy();
+    if result.is_ok() {
+        crate::bridge::session::record_system_proxy_cleared();
+    }
+    result.map_err(|e| coded_message(V8RayErrorCode::SystemProxy, e))
+}
+
+/// 获取设置系统代理时启用的协议
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_system_proxy_protocols() -> SystemProxyProtocolsInfo {
+    let protocols = crate::bridge::platform::get_system_proxy_protocols();
+    SystemProxyProtocolsInfo {
+        http: protocols.http,
+        https: protocols.https,
+        socks: protocols.socks,
+    }
+}
+
+/// 设置系统代理时启用的协议（默认全部启用），下次设置系统代理时生效
+///
+/// # 参数
+/// - `protocols`: 各协议开关，至少启用一个
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 未启用任何协议
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_system_proxy_protocols(protocols: SystemProxyProtocolsInfo) -> Result<(), String> {
+    crate::bridge::platform::set_system_proxy_protocols(crate::platform::SystemProxyProtocols {
+        http: protocols.http,
+        https: protocols.https,
+        socks: protocols.socks,
+    })
+    .map_err(|e| coded_message(V8RayErrorCode::ConfigValidation, e))
+}
+
+/// 获取 macOS 系统代理作用的网络服务范围
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_network_service_scope() -> NetworkServiceScopeInfo {
+    use crate::platform::NetworkServiceScope;
+
+    match crate::bridge::platform::get_network_service_scope() {
+        NetworkServiceScope::Active => NetworkServiceScopeInfo {
+            mode: "active".to_string(),
+            services: vec![],
+        },
+        NetworkServiceScope::Primary => NetworkServiceScopeInfo {
+            mode: "primary".to_string(),
+            services: vec![],
+        },
+        NetworkServiceScope::Selected(services) => NetworkServiceScopeInfo {
+            mode: "selected".to_string(),
+            services,
+        },
+    }
+}
+
+/// 设置 macOS 系统代理作用的网络服务范围（默认 active），下次设置系统代理时生效
+///
+/// # 参数
+/// - `scope`: 服务范围，mode 为 selected 时至少指定一个服务
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 范围无效
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_network_service_scope(scope: NetworkServiceScopeInfo) -> Result<(), String> {
+    use crate::platform::NetworkServiceScope;
+
+    let scope = match scope.mode.as_str() {
+        "active" => NetworkServiceScope::Active,
+        "primary" => NetworkServiceScope::Primary,
+        "selected" => NetworkServiceScope::Selected(scope.services),
+        other => {
+            return Err(coded_message(
+                V8RayErrorCode::ConfigValidation,
+                format!("Unknown network service scope: {}", other),
+            ))
+        }
+    };
+    crate::bridge::platform::set_network_service_scope(scope)
+        .map_err(|e| coded_message(V8RayErrorCode::ConfigValidation, e))
+}
+
+/// 列出 macOS 网络服务（按服务顺序），其他平台返回空列表
+///
+/// # 返回
+/// - `Ok(services)`: 网络服务列表
+/// - `Err(e)`: 获取失败
+pub fn list_network_services() -> Result<Vec<NetworkServiceInfo>, String> {
+    crate::bridge::platform::list_network_services()
+        .map_err(|e| coded_message(V8RayErrorCode::CommandFailed, e))
+}
+
+/// 检查系统代理是否已设置
+///
+/// # 返回
+/// - `Ok(true)`: 代理已设置
+/// - `Ok(false)`: 代理未设置
+/// - `Err(e)`: 检查失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn is_system_proxy_set() -> Result<bool, String> {
+    crate::bridge::platform::is_system_proxy_set()
+        .map_err(|e| coded_message(V8RayErrorCode::SystemProxy, e))
+}
+
+/// 启动局域网分享服务
+///
+/// 在所有网卡上提供 HTTP 服务，供电视盒子、游戏机等只能填写 URL 的设备获取
+/// PAC 脚本或配置文件；所有地址都需要带随机令牌。已启动时返回当前服务信息。
+///
+/// # 参数
+/// - `port`: 监听端口，0 表示自动选择
+///
+/// # 返回
+/// - `Ok(info)`: 服务地址、端口、令牌和防火墙提示
+/// - `Err(e)`: 启动失败
+pub async fn start_lan_share(port: u16) -> Result<LanShareInfo, String> {
+    crate::bridge::platform::start_lan_share(port)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::Io, e))
+}
+
+/// 停止局域网分享服务
+///
+/// 已分享的地址随之失效，重新启动后令牌会变化
+#[flutter_rust_bridge::frb(sync)]
+pub fn stop_lan_share() {
+    crate::bridge::platform::stop_lan_share()
+}
+
+/// 启动网络变化监测
+///
+/// 默认路由变化（如切换 Wi-Fi）后重新应用系统代理、重新检查连通性，
+/// 并在事件流中发送 NetworkChanged 事件。已启动时不做任何操作
+pub async fn start_network_monitor() {
+    crate::bridge::platform::start_network_monitor().await
+}
+
+/// 停止网络变化监测
+#[flutter_rust_bridge::frb(sync)]
+pub fn stop_network_monitor() {
+    crate::bridge::platform::stop_network_monitor()
+}
+
+/// 在局域网分享 PAC 脚本
+///
+/// 根据当前路由规则生成指向本机局域网地址的 PAC 脚本，需要先启动分享服务并允许局域网连接。
+/// 再次调用会重新生成脚本。
+///
+/// # 参数
+/// - `http_port`: HTTP 代理端口
+/// - `socks_port`: SOCKS 代理端口
+///
+/// # 返回
+/// - `Ok(url)`: PAC 地址
+/// - `Err(e)`: 分享失败
+pub async fn share_pac_on_lan(http_port: u16, socks_port: u16) -> Result<String, String> {
+    crate::bridge::platform::share_pac_on_lan(http_port, socks_port)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::ConfigValidation, e))
+}
+
+/// 在局域网分享文件（如导出的配置）
+///
+/// # 参数
+/// - `name`: 文件名（用于 URL，不能包含 `/`）
+/// - `content_type`: MIME 类型
+/// - `content`: 文件内容
+///
+/// # 返回
+/// - `Ok(url)`: 文件地址
+/// - `Err(e)`: 分享失败
+#[flutter_rust_bridge::frb(sync)]
+pub fn share_file_on_lan(
+    name: String,
+    content_type: String,
+    content: String,
+) -> Result<String, String> {
+    crate::bridge::platform::share_file_on_lan(name, content_type, content)
+        .map_err(|e| coded_message(V8RayErrorCode::ConfigValidation, e))
+}
+
+/// 取消分享文件
+///
+/// # 返回
+/// - `true`: 文件此前已分享
+#[flutter_rust_bridge::frb(sync)]
+pub fn unshare_file_on_lan(name: String) -> bool {
+    crate::bridge::platform::unshare_file_on_lan(name)
+}
+
+/// 启用 TUN 模式
+///
+/// 通过 TUN 网卡接管整机流量并转发到本地 SOCKS 入站，需要管理员权限，
+/// 或已安装特权助手服务。
+/// 应在连接前启用，Xray 出站从下一次连接或切换节点起绑定到物理网卡。
+///
+/// # 参数
+/// - `socks_port`: 本地 SOCKS 入站端口
+/// - `bypass_hosts`: 代理服务器地址，不经过 TUN 网卡
+///
+/// # 返回
+/// - `Ok(())`: 启用成功
+/// - `Err(e)`: 启用失败
+pub async fn enable_tun_mode(socks_port: u16, bypass_hosts: Vec<String>) -> Result<(), String> {
+    let result = crate::bridge::platform::enable_tun_mode(socks_port, bypass_hosts).await;
+    if let Err(ref e) = result {
+        tracing::error!("FFI: enable_tun_mode failed: {}", e);
+    } else {
+        crate::bridge::connection::record_session_event(
+            crate::connection::timeline::SessionEventKind::ProxyApplied,
+            format!("TUN mode enabled (SOCKS {})", socks_port),
+        );
+    }
+    result.map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
+}
+
+/// 关闭 TUN 模式并恢复路由和 DNS
+///
+/// # 返回
+/// - `Ok(())`: 关闭成功
+/// - `Err(e)`: 关闭失败
+pub async fn disable_tun_mode() -> Result<(), String> {
+    let result = crate::bridge::platform::disable_tun_mode().await;
+    if result.is_ok() {
+        crate::bridge::connection::record_session_event(
+            crate::connection::timeline::SessionEventKind::ProxyCleared,
+            "TUN mode disabled".to_string(),
+        );
+    }
+    result.map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
+}
+
+/// 检查 TUN 模式是否已启用
+///
+/// # 返回
+/// - `true`: TUN 网卡已启用
+pub async fn is_tun_mode_enabled() -> bool {
+    crate::bridge::platform::is_tun_mode_enabled().await
+}
+
+/// 获取安装特权助手服务的命令
+///
+/// 助手以系统服务运行，之后启用 TUN 模式无需应用本身具有管理员权限。
+/// 返回的命令需要由应用以管理员权限执行（pkexec、管理员授权或 UAC）。
+///
+/// # 返回
+/// - `Ok(command)`: 程序路径及参数
+/// - `Err(e)`: 找不到助手程序或无法创建令牌
+#[flutter_rust_bridge::frb(sync)]
+pub fn privileged_helper_install_command() -> Result<Vec<String>, String> {
+    crate::bridge::platform::privileged_helper_install_command()
+        .map_err(|e| coded_message(V8RayErrorCode::PrivilegedHelper, e))
+}
+
+/// 获取卸载特权助手服务的命令，需要以管理员权限执行
+///
+/// # 返回
+/// - `Ok(command)`: 程序路径及参数
+/// - `Err(e)`: 找不到助手程序
+#[flutter_rust_bridge::frb(sync)]
+pub fn privileged_helper_uninstall_command() -> Result<Vec<String>, String> {
+    crate::bridge::platform::privileged_helper_uninstall_command()
+        .map_err(|e| coded_message(V8RayErrorCode::PrivilegedHelper, e))
+}
+
+/// 检查特权助手服务是否可用
+///
+/// # 返回
+/// - `true`: 助手正在运行并接受应用的令牌
+pub async fn is_privileged_helper_available() -> bool {
+    crate::bridge::platform::is_privileged_helper_available().await
+}
+
+/// 检查 Xray Core 更新
+///
+/// # 返回
+/// - `Ok(info)`: 更新信息
+/// - `Err(e)`: 检查失败
+pub async fn check_xray_core_update() -> Result<XrayCoreUpdateInfo> {
+    crate::bridge::connection::check_xray_core_update()
+        .await
+        .map_err(coded)
+}
+
+/// 获取 sing-box 版本
+///
+/// sing-box 不随应用内置，需放在数据目录的 `sing-box` 文件夹或程序目录下
+///
+/// # 返回
+/// - `Ok(version)`: 版本号
+/// - `Err(e)`: 未找到 sing-box 或无法运行
+pub async fn get_sing_box_version() -> Result<String> {
+    crate::bridge::connection::get_sing_box_version()
+        .await
+        .map_err(coded)
+}
+
+/// 是否有可运行的 Xray Core
+///
+/// # 返回
+/// - `false`: 首次运行且未内置 Xray Core，需先调用 `bootstrap_xray_core`
+pub async fn is_xray_core_available() -> bool {
+    crate::bridge::connection::is_xray_core_available().await
+}
+
+/// 首次运行时下载安装最新版 Xray Core
+///
+/// 下载进度通过 `XrayCoreDownloadProgress` 事件发送。安装完成后若指定了服务器则继续连接
+///
+/// # 参数
+/// - `server_id`: 安装后要连接的服务器 ID，`None` 表示只安装
+///
+/// # 返回
+/// - `Ok(version)`: 已安装的版本
+/// - `Err(e)`: 下载、校验、安装或连接失败
+pub async fn bootstrap_xray_core(server_id: Option<String>) -> Result<String> {
+    crate::bridge::connection::bootstrap_xray_core(server_id)
+        .await
+        .map_err(coded)
+}
+
+/// 下载并安装 Xray Core 更新
+///
+/// 安装前校验下载文件，校验失败时拒绝安装（错误码 4007）
+///
+/// # 参数
+/// - `version`: 要更新的版本号
+///
+/// # 返回
+/// - `Ok(())`: 更新成功
+/// - `Err(e)`: 更新失败
+pub async fn update_xray_core(version: String) -> Result<()> {
+    crate::bridge::connection::update_xray_core(version)
+        .await
+        .map_err(coded)
+}
+
+/// 列出 Xray Core 的发布版本
+///
+/// # 参数
+/// - `limit`: 最多返回的版本数（1 到 100）
+///
+/// # 返回
+/// - `Ok(releases)`: 从新到旧的发布版本
+/// - `Err(e)`: 获取失败
+pub async fn list_xray_core_releases(limit: u32) -> Result<Vec<XrayReleaseInfo>> {
+    crate::bridge::connection::list_xray_core_releases(limit)
+        .await
+        .map_err(coded)
+}
+
+/// 列出已安装的 Xray Core 版本
+///
+/// # 返回
+/// 已安装的版本以及当前和上一个选中的版本
+pub async fn list_installed_xray_versions() -> InstalledXrayVersionsInfo {
+    crate::bridge::connection::list_installed_xray_versions().await
+}
+
+/// 下载并安装指定版本的 Xray Core，不切换到该版本
+///
+/// 各版本并存安装，已安装的版本不会重复下载
+///
+/// # 参数
+/// - `version`: 版本号，如 "1.8.24"
+///
+/// # 返回
+/// - `Ok(())`: 安装成功
+/// - `Err(e)`: 版本无效、下载或解压失败
+pub async fn install_xray_version(version: String) -> Result<()> {
+    crate::bridge::connection::install_xray_version(version)
+        .await
+        .map_err(coded)
+}
+
+/// 更新 geoip/geosite 数据文件
+///
+/// 下载经过下载缓存，已下载过的文件不会重复下载；数据文件在下次连接时生效
+///
+/// # 返回
+/// - `Ok(())`: 更新成功
+/// - `Err(e)`: 找不到 Xray、下载失败或校验失败
+pub async fn update_geo_assets() -> Result<()> {
+    crate::bridge::connection::update_geo_assets()
+        .await
+        .map_err(coded)
+}
+
+/// 选择运行的 Xray Core 版本
+///
+/// 只切换选择，不重新下载，下次启动 Xray 时生效
+///
+/// # 参数
+/// - `version`: 已安装的版本，`None` 表示使用应用自带的 Xray
+///
+/// # 返回
+/// - `Ok(())`: 切换成功
+/// - `Err(e)`: 版本未安装
+pub async fn select_xray_version(version: Option<String>) -> Result<()> {
+    crate::bridge::connection::select_xray_version(version)
+        .await
+        .map_err(coded)
+}
+
+/// 回滚到上一个选中的 Xray Core 版本
+///
+/// # 返回
+/// - `Ok(version)`: 回滚后运行的版本，`None` 表示应用自带的 Xray
+/// - `Err(e)`: 没有可回滚的版本
+pub async fn rollback_xray_core() -> Result<Option<String>> {
+    crate::bridge::connection::rollback_xray_core()
+        .await
+        .map_err(coded)
+}
+
+/// 删除已安装的 Xray Core 版本
+///
+/// # 参数
+/// - `version`: 未被选中的已安装版本
+///
+/// # 返回
+/// - `Ok(())`: 删除成功
+/// - `Err(e)`: 版本未安装或正被选中
+pub async fn remove_xray_version(version: String) -> Result<()> {
+    crate::bridge::connection::remove_xray_version(version)
+        .await
+        .map_err(coded)
+}
+
+/// 获取 Xray Core 更新下载设置
+///
+/// # 返回
+/// 下载源及其尝试顺序
+pub async fn get_xray_download_settings() -> XrayDownloadSettingsInfo {
+    crate::bridge::connection::get_xray_download_settings().await
+}
+
+/// 设置 Xray Core 更新下载源
+///
+/// 下载时按顺序尝试各下载源，镜像下载的文件会与 GitHub 上发布的校验和比对
+///
+/// # 参数
+/// - `settings`: 下载设置
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 下载源类型或地址无效
+pub async fn set_xray_download_settings(settings: XrayDownloadSettingsInfo) -> Result<()> {
+    crate::bridge::connection::set_xray_download_settings(settings)
+        .await
+        .map_err(coded)
+}
+
+/// 设置 Xray Core 发布版本的 minisign 公钥
+///
+/// 设置后，下载的版本除了通过 `.dgst` 校验和校验外，还必须带有该公钥签名的 `.minisig` 文件，
+/// 否则拒绝安装（错误码 4007）
+///
+/// # 参数
+/// - `public_key`: 公钥（`.pub` 文件内容或其中的 base64 行），`None` 表示不要求签名
+///
+/// # 返回
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 公钥无效
+pub async fn set_xray_release_public_key(public_key: Option<String>) -> Result<()> {
+    crate::bridge::connection::set_xray_release_public_key(public_key)
+        .await
+        .map_err(coded)
+}
+
+/// 获取 Xray Core 发布版本的 minisign 公钥
+///
+/// # 返回
+/// - `Some(key)`: 公钥的 base64 行
+/// - `None`: 不要求签名
+pub async fn get_xray_release_public_key() -> Option<String> {
+    crate::bridge::connection::get_xray_release_public_key().await
+}
+
+/// 获取 Xray Core 下载进度
+///
+/// # 返回
+/// - 下载进度 (0.0 到 1.0)
+pub async fn get_xray_core_update_progress() -> Result<f64> {
+    crate::bridge::connection::get_xray_core_update_progress()
+        .await
+        .map_err(coded)
+}
+
+/// 获取 Xray Core 下载缓存大小
+///
+/// # 返回
+/// - `Ok(bytes)`: 缓存占用的字节数
+/// - `Err(e)`: 获取失败
+pub async fn get_xray_download_cache_size() -> Result<u64> {
+    crate::bridge::connection::get_xray_download_cache_size()
+        .await
+        .map_err(coded)
 }
 
-/// 保存配置
-///
-/// # 参数
-/// - `config`: 配置信息
+/// 清空 Xray Core 下载缓存
 ///
 /// # 返回
-/// - `Ok(())`: 保存成功
-/// - `Err(e)`: 保存失败
-pub fn save_config(config: ConfigInfo) -> Result<()> {
-    crate::bridge::config::save_config(config)
+/// - `Ok(bytes)`: 释放的字节数
+/// - `Err(e)`: 清空失败
+pub async fn purge_xray_download_cache() -> Result<u64> {
+    crate::bridge::connection::purge_xray_download_cache()
+        .await
+        .map_err(coded)
 }
 
-/// 删除配置
+/// 获取平台信息
 ///
-/// # 参数
-/// - `config_id`: 配置 ID
+/// # 返回
+/// 平台信息，包括操作系统、架构、版本和功能支持
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_platform_info() -> crate::platform::PlatformInfo {
+    crate::bridge::platform::get_platform_information()
+}
+
+/// 获取操作系统详情，用于诊断和按发行版调整行为
 ///
 /// # 返回
-/// - `Ok(())`: 删除成功
-/// - `Err(e)`: 删除失败
-pub fn delete_config(config_id: String) -> Result<()> {
-    crate::bridge::config::delete_config(&config_id)
+/// 系统名称、版本、构建号，Linux 上还包括内核、发行版和桌面环境
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_os_details() -> OsDetailsInfo {
+    crate::bridge::platform::get_os_details()
 }
 
-/// 列出所有配置
+/// 获取 Windows 版本信息
 ///
 /// # 返回
-/// - `Ok(configs)`: 配置列表
-/// - `Err(e)`: 列出失败
-pub fn list_configs() -> Result<Vec<ConfigInfo>> {
-    crate::bridge::config::list_configs()
+/// - `Some(info)`: 当前系统的版本及其支持的功能
+/// - `None`: 非 Windows 系统或无法读取版本
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_windows_version_info() -> Option<WindowsVersionInfo> {
+    crate::bridge::platform::get_windows_version_info()
 }
 
-/// 验证配置
+/// 启用网络命名空间隔离（Linux）
+///
+/// 创建一个仅能通过代理访问网络的命名空间，其中启动的应用全部走代理，
+/// 系统其他部分不受影响。需要 root 权限
 ///
 /// # 参数
-/// - `config`: 配置信息
+/// - `socks_port`: 本地 SOCKS 入站端口
 ///
 /// # 返回
-/// - `Ok(true)`: 配置有效
-/// - `Ok(false)`: 配置无效
-/// - `Err(e)`: 验证失败
-pub fn validate_config(config: ConfigInfo) -> Result<bool> {
-    crate::bridge::config::validate_config(config)
+/// - `Ok(())`: 启用成功
+/// - `Err(e)`: 启用失败
+pub async fn enable_netns_isolation(socks_port: u16) -> Result<(), String> {
+    crate::bridge::platform::enable_netns_isolation(socks_port)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-// ============================================================================
-// 连接管理 API
-// ============================================================================
-
-/// 缓存代理配置（在连接前调用）
+/// 关闭网络命名空间隔离
 ///
-/// # 参数
-/// - `config_id`: 配置 ID
-/// - `config`: 代理服务器配置
+/// 命名空间中仍在运行的应用将失去网络连接
 ///
 /// # 返回
-/// - `Ok(())`: 缓存成功
-/// - `Err(e)`: 缓存失败
-pub fn cache_proxy_config(config_id: String, config: ProxyServerConfig) -> Result<()> {
-    crate::bridge::connection::cache_proxy_config(config_id, config)
+/// - `Ok(())`: 关闭成功
+/// - `Err(e)`: 关闭失败
+pub async fn disable_netns_isolation() -> Result<(), String> {
+    crate::bridge::platform::disable_netns_isolation()
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 设置代理模式
+/// 在代理网络命名空间中启动应用
+///
+/// 通过 sudo 运行时，应用以原用户身份启动
 ///
 /// # 参数
-/// - `mode`: 代理模式 ("global", "smart", "direct")
+/// - `program`: 可执行文件
+/// - `args`: 启动参数
 ///
 /// # 返回
-/// - `Ok(())`: 设置成功
-/// - `Err(e)`: 设置失败
-pub fn set_proxy_mode(mode: String) -> Result<()> {
-    crate::bridge::connection::set_proxy_mode(mode)
+/// - `Ok(pid)`: 应用进程 ID
+/// - `Err(e)`: 启动失败
+pub async fn launch_in_netns(program: String, args: Vec<String>) -> Result<u32, String> {
+    crate::bridge::platform::launch_in_netns(program, args)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::CommandFailed, e))
 }
 
-/// 连接到服务器
+/// 启动 Android VpnService 隧道
+///
+/// Core 接管 `fd`（由 `ParcelFileDescriptor.detachFd()` 获得），隧道停止时关闭。
 ///
 /// # 参数
-/// - `config_id`: 配置 ID
+/// - `fd`: `VpnService.Builder.establish()` 返回的 TUN 文件描述符
+/// - `socks_port`: 本地 SOCKS 入站端口
+/// - `mtu`: TUN 网卡 MTU
+/// - `tun2socks_path`: tun2socks 可执行文件路径（通常位于 native library 目录）
+/// - `protect_path`: 供辅助进程使用的 protect socket 路径
 ///
 /// # 返回
-/// - `Ok(())`: 连接成功
-/// - `Err(e)`: 连接失败
-pub fn connect(config_id: String) -> Result<()> {
-    crate::bridge::connection::connect(&config_id)
+/// - `Ok(())`: 启动成功
+/// - `Err(e)`: 启动失败
+pub async fn start_vpn_tunnel(
+    fd: i32,
+    socks_port: u16,
+    mtu: u32,
+    tun2socks_path: Option<String>,
+    protect_path: Option<String>,
+) -> Result<(), String> {
+    crate::bridge::platform::start_vpn_tunnel(fd, socks_port, mtu, tun2socks_path, protect_path)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 断开连接
+/// 停止 Android VpnService 隧道
 ///
 /// # 返回
-/// - `Ok(())`: 断开成功
-/// - `Err(e)`: 断开失败
-pub fn disconnect() -> Result<()> {
-    crate::bridge::connection::disconnect()
+/// - `Ok(())`: 停止成功
+/// - `Err(e)`: 停止失败
+pub async fn stop_vpn_tunnel() -> Result<(), String> {
+    crate::bridge::platform::stop_vpn_tunnel()
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 获取连接信息
+/// 注册 socket 保护回调
 ///
-/// # 返回
-/// - `Ok(info)`: 连接信息
-/// - `Err(e)`: 获取失败
-pub fn get_connection_info() -> Result<ConnectionInfo> {
-    crate::bridge::connection::get_connection_info()
+/// 回调中调用 `VpnService.protect()`，使 Core 自身的连接绕过 VPN，避免回环
+///
+/// # 参数
+/// - `callback`: 保护指定文件描述符，返回是否成功
+pub fn set_vpn_protect_callback(
+    callback: impl Fn(i32) -> flutter_rust_bridge::DartFnFuture<bool> + Send + Sync + 'static,
+) {
+    crate::bridge::platform::set_vpn_protect_callback(callback)
 }
 
-/// 测试连接延迟
+/// 清除 socket 保护回调
+pub fn clear_vpn_protect_callback() {
+    crate::bridge::platform::clear_vpn_protect_callback()
+}
+
+/// 开启或关闭内存受限模式（iOS 网络扩展，内存上限 50MB）
 ///
-/// # 参数
-/// - `config_id`: 配置 ID
+/// 减少内存中保留的日志，停止收集连接与流量历史，之后启动的数据包流使用更小的缓冲区。
+/// 应在扩展的 `startTunnel` 中最先调用
 ///
-/// # 返回
-/// - `Ok(latency_ms)`: 延迟（毫秒）
-/// - `Err(e)`: 测试失败
-pub fn test_latency(config_id: String) -> Result<u32> {
-    crate::bridge::connection::test_latency(&config_id)
+/// # 参数
+/// - `enabled`: 是否开启
+pub async fn set_memory_constrained(enabled: bool) {
+    crate::bridge::platform::set_memory_constrained(enabled).await
 }
 
-// ============================================================================
-// 流量统计 API
-// ============================================================================
-
-// ============================================================================
-// 事件流 API
-// ============================================================================
+/// 检查是否处于内存受限模式
+#[flutter_rust_bridge::frb(sync)]
+pub fn is_memory_constrained() -> bool {
+    crate::bridge::platform::is_memory_constrained()
+}
 
-/// 创建事件流
-///
-/// 返回一个事件流，用于接收 V8Ray 事件
+/// 查找 Packet Tunnel Provider 的 `packetFlow` 背后的 utun 文件描述符
 ///
 /// # 返回
-/// - 事件流
-pub fn create_event_stream() -> impl futures::Stream<Item = V8RayEvent> {
-    crate::bridge::events::create_event_stream()
+/// - `Some(fd)`: 找到的 utun 文件描述符
+/// - `None`: 未找到（不在 iOS 网络扩展中）
+#[flutter_rust_bridge::frb(sync)]
+pub fn find_utun_descriptor() -> Option<i32> {
+    crate::bridge::platform::find_utun_descriptor()
 }
 
-// ============================================================================
-// 订阅管理 API
-// ============================================================================
-
-/// 初始化订阅管理器
+/// 启动 iOS 网络扩展的数据包隧道
+///
+/// 传入 `fd` 时 Core 直接读写该 utun 文件描述符（隧道停止后不会关闭它）；不传时通过
+/// [`write_tunnel_packets`] 和 [`read_tunnel_packets`] 交换数据包。已有隧道会被替换。
+/// tun2socks 在扩展进程内运行，启动前需通过 `v8ray_register_tun2socks` 注册
 ///
 /// # 参数
-/// - `db_path`: 数据库路径
+/// - `fd`: `find_utun_descriptor` 返回的文件描述符，`None` 表示使用数据包流
+/// - `socks_port`: 本地 SOCKS 入站端口
+/// - `mtu`: 隧道网络设置中的 MTU
 ///
 /// # 返回
-/// - `Ok(())`: 初始化成功
-/// - `Err(e)`: 初始化失败
-pub async fn init_subscription_manager(db_path: String) -> Result<()> {
-    crate::bridge::subscription::init_subscription_manager(db_path).await
+/// - `Ok(())`: 启动成功
+/// - `Err(e)`: 启动失败
+pub async fn start_packet_tunnel(fd: Option<i32>, socks_port: u16, mtu: u32) -> Result<(), String> {
+    crate::bridge::platform::start_packet_tunnel(fd, socks_port, mtu)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 添加订阅
-///
-/// # 参数
-/// - `name`: 订阅名称
-/// - `url`: 订阅 URL
+/// 停止数据包隧道，等待中的读取随之失败
 ///
 /// # 返回
-/// - `Ok(id)`: 订阅 ID
-/// - `Err(e)`: 添加失败
-pub async fn add_subscription(name: String, url: String) -> Result<String> {
-    crate::bridge::subscription::add_subscription(name, url).await
+/// - `Ok(())`: 停止成功
+/// - `Err(e)`: 停止失败
+pub async fn stop_packet_tunnel() -> Result<(), String> {
+    crate::bridge::platform::stop_packet_tunnel()
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 删除订阅
+/// 将从 `packetFlow` 读取的数据包交给隧道
 ///
 /// # 参数
-/// - `id`: 订阅 ID
+/// - `packets`: `packetFlow.readPackets` 返回的 IP 数据包
 ///
 /// # 返回
-/// - `Ok(())`: 删除成功
-/// - `Err(e)`: 删除失败
-pub async fn remove_subscription(id: String) -> Result<()> {
-    crate::bridge::subscription::remove_subscription(id).await
+/// - `Ok(())`: 写入成功
+/// - `Err(e)`: 没有运行中的数据包流隧道
+pub async fn write_tunnel_packets(packets: Vec<Vec<u8>>) -> Result<(), String> {
+    crate::bridge::platform::write_tunnel_packets(packets)
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 更新订阅
-///
-/// # 参数
-/// - `id`: 订阅 ID
+/// 等待需要写回 `packetFlow` 的数据包
 ///
 /// # 返回
-/// - `Ok(())`: 更新成功
-/// - `Err(e)`: 更新失败
-pub async fn update_subscription(id: String) -> Result<()> {
-    crate::bridge::subscription::update_subscription(id).await
+/// - `Ok(packets)`: 至少一个 IP 数据包
+/// - `Err(e)`: 隧道已停止
+pub async fn read_tunnel_packets() -> Result<Vec<Vec<u8>>, String> {
+    crate::bridge::platform::read_tunnel_packets()
+        .await
+        .map_err(|e| coded_message(V8RayErrorCode::VpnSetupFailed, e))
 }
 
-/// 更新所有订阅
+/// 获取应用标识
 ///
 /// # 返回
-/// - `Ok(())`: 更新成功
-/// - `Err(e)`: 更新失败
-pub async fn update_all_subscriptions() -> Result<()> {
-    crate::bridge::subscription::update_all_subscriptions().await
+/// 应用名称、版本、操作系统和架构
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_app_identity() -> crate::version::AppIdentity {
+    crate::version::AppIdentity::current()
 }
 
-/// 获取所有订阅
+/// 调整日志级别（立即生效）
+///
+/// # 参数
+/// - `level`: trace / debug / info / warn / error
 ///
 /// # 返回
-/// - `Ok(subscriptions)`: 订阅列表
-/// - `Err(e)`: 获取失败
-pub async fn get_subscriptions() -> Result<Vec<SubscriptionInfo>> {
-    crate::bridge::subscription::get_subscriptions().await
+/// - `Ok(())`: 调整成功
+/// - `Err(e)`: 级别无效或日志未初始化
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_log_level(level: String) -> Result<()> {
+    crate::bridge::logs::set_log_level(&level).map_err(coded)
 }
 
-/// 获取所有服务器
+/// 获取当前日志级别
 ///
 /// # 返回
-/// - `Ok(servers)`: 服务器列表
-/// - `Err(e)`: 获取失败
-pub async fn get_servers() -> Result<Vec<ServerInfo>> {
-    crate::bridge::subscription::get_servers().await
+/// 当前级别，日志未初始化时为空
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_log_level() -> Option<String> {
+    crate::bridge::logs::get_log_level()
 }
 
-/// 获取指定订阅的服务器
+/// 设置实时推送到事件流的核心日志最低级别（默认 info）
+///
+/// 核心日志以 `CoreLog` 事件、Xray 日志以 `Log` 事件推送，两者共用限流，
+/// 超出部分被丢弃并以一条警告说明丢弃的条数
 ///
 /// # 参数
-/// - `subscription_id`: 订阅 ID
+/// - `level`: trace / debug / info / warn / error
 ///
 /// # 返回
-/// - `Ok(servers)`: 服务器列表
-/// - `Err(e)`: 获取失败
-pub async fn get_servers_for_subscription(subscription_id: String) -> Result<Vec<ServerInfo>> {
-    crate::bridge::subscription::get_servers_for_subscription(subscription_id).await
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 级别无效
+#[flutter_rust_bridge::frb(sync)]
+pub fn set_log_stream_level(level: String) -> Result<()> {
+    crate::bridge::logs::set_log_stream_level(&level).map_err(coded)
 }
 
-/// 获取服务器配置
+/// 将日志写入文件
+///
+/// 日志按天轮转，只保留最近几个文件，并删除 7 天前的文件
 ///
 /// # 参数
-/// - `server_id`: 服务器 ID
+/// - `log_dir`: 日志目录
 ///
 /// # 返回
-/// - `Ok(config)`: 服务器配置
-/// - `Err(e)`: 获取失败
-pub async fn get_server_config(server_id: String) -> Result<ProxyServerConfig> {
-    crate::bridge::subscription::get_server_config(server_id).await
+/// - `Ok(())`: 开启成功
+/// - `Err(e)`: 目录无法创建或写入
+#[flutter_rust_bridge::frb(sync)]
+pub fn init_log_files(log_dir: String) -> Result<()> {
+    crate::bridge::logs::init_log_files(&log_dir).map_err(coded)
 }
 
-/// 从存储加载订阅
+/// 查询内存中的最近日志
+///
+/// # 参数
+/// - `source`: 只返回该来源（core / xray），为空时返回全部
+/// - `min_level`: 只返回不低于该级别的日志，为空时返回全部
+/// - `keyword`: 只返回包含该关键字的日志（不区分大小写）
+/// - `limit`: 最多返回的条数（保留最新的）
 ///
 /// # 返回
-/// - `Ok(())`: 加载成功
-/// - `Err(e)`: 加载失败
-pub async fn load_subscriptions_from_storage() -> Result<()> {
-    crate::bridge::subscription::load_subscriptions_from_storage().await
+/// - `Ok(logs)`: 日志（最早的在前）
+/// - `Err(e)`: 来源或级别无效
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_recent_logs(
+    source: Option<String>,
+    min_level: Option<String>,
+    keyword: Option<String>,
+    limit: u32,
+) -> Result<Vec<LogRecordInfo>> {
+    crate::bridge::logs::get_recent_logs(source, min_level, keyword, limit).map_err(coded)
 }
 
-// ============================================================================
-// 平台相关 API
-// ============================================================================
-
-/// 设置系统代理
+/// 导出日志包，用于反馈问题
+///
+/// 日志包为一个文本文件，包含平台信息、最近的核心与 Xray 日志以及日志文件内容
 ///
 /// # 参数
-/// - `http_port`: HTTP 代理端口
-/// - `socks_port`: SOCKS 代理端口
+/// - `path`: 导出文件路径
 ///
 /// # 返回
-/// - `Ok(())`: 设置成功
-/// - `Err(e)`: 设置失败
-#[flutter_rust_bridge::frb(sync)]
-pub fn set_system_proxy(http_port: u16, socks_port: u16) -> Result<(), String> {
-    tracing::info!(
-        "FFI: set_system_proxy called with http_port={}, socks_port={}",
-        http_port,
-        socks_port
-    );
-    let result = crate::bridge::platform::set_system_proxy(http_port, socks_port);
-    if let Err(ref e) = result {
-        tracing::error!("FFI: set_system_proxy failed: {}", e);
-    } else {
-        tracing::info!("FFI: set_system_proxy succeeded");
-    }
-    result
+/// - `Ok(())`: 导出成功
+/// - `Err(e)`: 写入失败
+pub fn export_logs(path: String) -> Result<()> {
+    crate::bridge::logs::export_logs(&path).map_err(coded)
 }
 
-/// 清除系统代理
+/// 导出支持包，用于反馈问题
+///
+/// 在日志包之外附带当前运行的 Xray 配置（主连接及附加实例）。
+/// 导出内容中的 UUID、密码、密钥和分享链接均被屏蔽
+///
+/// # 参数
+/// - `path`: 导出文件路径
 ///
 /// # 返回
-/// - `Ok(())`: 清除成功
-/// - `Err(e)`: 清除失败
-#[flutter_rust_bridge::frb(sync)]
-pub fn clear_system_proxy() -> Result<(), String> {
-    crate::bridge::platform::clear_system_proxy()
+/// - `Ok(())`: 导出成功
+/// - `Err(e)`: 写入失败
+pub async fn export_support_bundle(path: String) -> Result<()> {
+    crate::bridge::logs::export_support_bundle(&path)
+        .await
+        .map_err(coded)
 }
 
-/// 检查系统代理是否已设置
+/// 开启或关闭日志中敏感信息（UUID、密码、分享链接等）的屏蔽
 ///
-/// # 返回
-/// - `Ok(true)`: 代理已设置
-/// - `Ok(false)`: 代理未设置
-/// - `Err(e)`: 检查失败
+/// 默认开启；仅在本地排查问题时关闭。导出的日志包与支持包始终屏蔽敏感信息
+///
+/// # 参数
+/// - `enabled`: 是否屏蔽
 #[flutter_rust_bridge::frb(sync)]
-pub fn is_system_proxy_set() -> Result<bool, String> {
-    crate::bridge::platform::is_system_proxy_set()
+pub fn set_log_redaction(enabled: bool) {
+    crate::bridge::logs::set_log_redaction(enabled)
 }
 
-/// 检查 Xray Core 更新
+/// 获取当前 HTTP 请求使用的 User-Agent
 ///
 /// # 返回
-/// - `Ok(info)`: 更新信息
-/// - `Err(e)`: 检查失败
-pub async fn check_xray_core_update() -> Result<XrayCoreUpdateInfo> {
-    // 获取 ConnectionManager 实例
-    let connection_manager = crate::bridge::connection::get_core_connection_manager()?;
-
-    // 获取 XrayCore 实例
-    let xray_core = connection_manager.get_xray();
-
-    // 获取 updater
-    let updater = xray_core.get_updater();
-    let update_info = updater
-        .check_update()
-        .await
-        .map_err(|e| anyhow::anyhow!("Failed to check update: {}", e))?;
-
-    Ok(XrayCoreUpdateInfo {
-        has_update: update_info.has_update,
-        current_version: update_info.current_version,
-        latest_version: update_info.latest_version,
-        download_url: update_info.download_url,
-        file_size: update_info.file_size,
-    })
+/// 已配置的 User-Agent，未配置时为默认值
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_user_agent() -> String {
+    crate::version::user_agent()
 }
 
-/// 下载并安装 Xray Core 更新
+/// 设置所有 HTTP 请求（订阅更新、Xray Core 更新检查等）使用的 User-Agent
 ///
 /// # 参数
-/// - `version`: 要更新的版本号
+/// - `user_agent`: 自定义 User-Agent，为空时恢复默认值
 ///
 /// # 返回
-/// - `Ok(())`: 更新成功
-/// - `Err(e)`: 更新失败
-pub async fn update_xray_core(version: String) -> Result<()> {
-    // 获取 ConnectionManager 实例
-    let connection_manager = crate::bridge::connection::get_core_connection_manager()?;
-
-    // 获取 XrayCore 实例
-    let xray_core = connection_manager.get_xray();
-
-    // 获取 updater
-    let updater = xray_core.get_updater();
-    updater
-        .update(&version)
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 设置失败
+pub async fn set_user_agent(user_agent: Option<String>) -> Result<()> {
+    crate::version::set_user_agent(user_agent);
+    crate::bridge::subscription::apply_user_agent()
         .await
-        .map_err(|e| anyhow::anyhow!("Failed to update Xray Core: {}", e))?;
-
-    Ok(())
+        .map_err(coded)
 }
 
-/// 获取 Xray Core 下载进度
+/// 获取应用使用的配置、数据、缓存和日志目录
 ///
 /// # 返回
-/// - 下载进度 (0.0 到 1.0)
-pub async fn get_xray_core_update_progress() -> Result<f64> {
-    // 获取 ConnectionManager 实例
-    let connection_manager = crate::bridge::connection::get_core_connection_manager()?;
-
-    // 获取 XrayCore 实例
-    let xray_core = connection_manager.get_xray();
-
-    // 获取 updater
-    let updater = xray_core.get_updater();
-    Ok(updater.get_progress().await)
+/// 通过 `set_app_paths` 设置的目录，未设置时为按平台约定和环境变量确定的目录
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_app_paths() -> AppPathsInfo {
+    crate::bridge::platform::get_app_paths()
 }
 
-/// 获取平台信息
+/// 设置应用目录（移动端等无平台约定目录时使用），并创建这些目录
+///
+/// # 参数
+/// - `paths`: 应用目录
 ///
 /// # 返回
-/// 平台信息，包括操作系统、架构、版本和功能支持
-#[flutter_rust_bridge::frb(sync)]
-pub fn get_platform_info() -> crate::platform::PlatformInfo {
-    crate::bridge::platform::get_platform_information()
+/// - `Ok(())`: 设置成功
+/// - `Err(e)`: 目录无法创建或不可写
+pub fn set_app_paths(paths: AppPathsInfo) -> Result<()> {
+    crate::bridge::platform::set_app_paths(paths).map_err(coded)
 }
 
 /// 检查是否有管理员权限
@@ -584,4 +4630,5 @@ pub fn get_platform_info() -> crate::platform::PlatformInfo {
 #[flutter_rust_bridge::frb(sync)]
 pub fn has_admin_privileges() -> Result<bool, String> {
     crate::bridge::platform::has_admin_privileges()
+        .map_err(|e| coded_message(V8RayErrorCode::CommandFailed, e))
 }
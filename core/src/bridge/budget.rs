@@ -0,0 +1,317 @@
+//! 流量预算 Bridge 模块
+//!
+//! 按天或按月为全部流量或某个订阅的服务器设置流量预算，用量来自流量历史数据库。
+//! 用量达到 80%、100% 时各发送一次 `TrafficBudgetAlert` 事件，达到 100% 时执行预算的动作，
+//! 之后每次检查都再次执行，直到周期结束。
+//!
+//! 预算保存在设置中，已发送的告警保存在数据目录的 [`ALERT_STATE_FILE_NAME`] 中，
+//! 重启后不会重复告警
+
+use anyhow::{anyhow, Result};
+use chrono::Local;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::api::{TrafficBudgetInfo, TrafficBudgetUsageInfo, V8RayEvent};
+use super::settings::{save_settings, settings};
+use crate::connection::traffic_budget::{BudgetAction, BudgetAlert, BudgetTracker, TrafficBudget};
+use crate::connection::traffic_history::TrafficHistory;
+
+/// 预算检查间隔
+const BUDGET_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// 数据目录中保存已发送告警的文件名
+pub const ALERT_STATE_FILE_NAME: &str = "traffic_budget_alerts.json";
+
+static BUDGET_CHECKER: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    static ref TRACKER: Mutex<BudgetTracker> = Mutex::new(load_tracker());
+}
+
+/// 告警状态文件路径
+fn alert_state_path() -> PathBuf {
+    crate::paths::app_paths()
+        .data_dir
+        .join(ALERT_STATE_FILE_NAME)
+}
+
+/// 读取已发送的告警，文件不存在或无法解析时从头开始
+fn load_tracker() -> BudgetTracker {
+    let Ok(content) = std::fs::read(alert_state_path()) else {
+        return BudgetTracker::new();
+    };
+    serde_json::from_slice(&content).unwrap_or_else(|e| {
+        tracing::warn!("Ignoring unreadable traffic budget alert state: {}", e);
+        BudgetTracker::new()
+    })
+}
+
+/// 保存已发送的告警
+async fn save_tracker(tracker: &BudgetTracker) {
+    let path = alert_state_path();
+    let result = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(&path, serde_json::to_vec(tracker)?).await?;
+        anyhow::Ok(())
+    }
+    .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to save traffic budget alert state: {}", e);
+    }
+}
+
+/// 修改告警状态，有变化时保存
+async fn update_tracker(f: impl FnOnce(&mut BudgetTracker)) {
+    let changed = {
+        let mut tracker = TRACKER.lock().unwrap_or_else(|e| e.into_inner());
+        let before = tracker.clone();
+        f(&mut tracker);
+        (*tracker != before).then(|| tracker.clone())
+    };
+    if let Some(tracker) = changed {
+        save_tracker(&tracker).await;
+    }
+}
+
+/// 设置中保存的流量预算
+async fn budgets() -> Vec<TrafficBudget> {
+    settings().await.get_config().await.traffic_budgets
+}
+
+/// 将 FFI 类型转换为核心类型
+fn convert_to_core_budget(budget: TrafficBudgetInfo) -> Result<TrafficBudget> {
+    Ok(TrafficBudget {
+        id: budget.id,
+        subscription_id: budget.subscription_id.filter(|id| !id.is_empty()),
+        period: budget.period.parse()?,
+        limit_bytes: budget.limit_bytes,
+        action: budget.action.parse()?,
+    })
+}
+
+/// 将核心类型转换为 FFI 类型
+fn convert_from_core_budget(budget: &TrafficBudget) -> TrafficBudgetInfo {
+    TrafficBudgetInfo {
+        id: budget.id.clone(),
+        subscription_id: budget.subscription_id.clone(),
+        period: budget.period.as_str().to_string(),
+        limit_bytes: budget.limit_bytes,
+        action: budget.action.as_str().to_string(),
+    }
+}
+
+/// 替换全部流量预算
+pub async fn set_traffic_budgets(budgets: Vec<TrafficBudgetInfo>) -> Result<()> {
+    let budgets = budgets
+        .into_iter()
+        .map(convert_to_core_budget)
+        .collect::<Result<Vec<_>>>()?;
+    for (i, budget) in budgets.iter().enumerate() {
+        budget.validate()?;
+        if budgets[..i].iter().any(|b| b.id == budget.id) {
+            return Err(anyhow!("Duplicate budget ID: {}", budget.id));
+        }
+    }
+
+    // 修改过的预算重新计算告警
+    let current = self::budgets().await;
+    let unchanged: Vec<&str> = budgets
+        .iter()
+        .filter(|b| current.contains(b))
+        .map(|b| b.id.as_str())
+        .collect();
+    update_tracker(|tracker| tracker.retain(&unchanged)).await;
+
+    settings()
+        .await
+        .update_config(|config| config.traffic_budgets = budgets)
+        .await?;
+    save_settings().await
+}
+
+/// 列出流量预算
+pub async fn list_traffic_budgets() -> Vec<TrafficBudgetInfo> {
+    budgets()
+        .await
+        .iter()
+        .map(convert_from_core_budget)
+        .collect()
+}
+
+/// 获取已打开的流量历史
+async fn traffic_history() -> Result<Arc<TrafficHistory>> {
+    super::connection::core_connection_manager()
+        .await
+        .get_traffic_history()
+        .await
+        .ok_or_else(|| anyhow!("Traffic history not initialized"))
+}
+
+/// 预算计入的服务器，`None` 表示全部
+async fn budget_servers(budget: &TrafficBudget) -> Result<Option<Vec<String>>> {
+    match &budget.subscription_id {
+        Some(id) => Ok(Some(
+            super::subscription::subscription_server_ids(id).await?,
+        )),
+        None => Ok(None),
+    }
+}
+
+/// 获取各预算本周期的用量
+pub async fn get_traffic_budget_usage() -> Result<Vec<TrafficBudgetUsageInfo>> {
+    let history = traffic_history().await?;
+    let budgets = budgets().await;
+    let now = Local::now();
+
+    let mut usage = Vec::with_capacity(budgets.len());
+    for budget in budgets {
+        let servers = budget_servers(&budget).await?;
+        let used_bytes = history
+            .get_total_usage_since(budget.period.start(now), servers.as_deref())
+            .await?;
+        usage.push(TrafficBudgetUsageInfo {
+            budget_id: budget.id,
+            used_bytes,
+            limit_bytes: budget.limit_bytes,
+            percent: used_bytes as f64 * 100.0 / budget.limit_bytes as f64,
+        });
+    }
+    Ok(usage)
+}
+
+/// 检查全部预算，发送告警并执行用尽预算的动作
+pub async fn check_traffic_budgets() -> Result<()> {
+    let history = traffic_history().await?;
+    let budgets = budgets().await;
+    let now = Local::now();
+
+    for budget in budgets {
+        let servers = budget_servers(&budget).await?;
+        let used_bytes = history
+            .get_total_usage_since(budget.period.start(now), servers.as_deref())
+            .await?;
+        let mut alert = None;
+        update_tracker(|tracker| alert = tracker.check(&budget, used_bytes, now)).await;
+        if let Some(alert) = alert {
+            handle_alert(alert, servers.as_deref()).await;
+        }
+    }
+    Ok(())
+}
+
+/// 发送告警事件并执行动作
+///
+/// 重复的告警不再发送事件，只再次执行动作。订阅预算的动作只在当前连接使用该订阅的
+/// 服务器时执行
+async fn handle_alert(alert: BudgetAlert, servers: Option<&[String]>) {
+    if !alert.repeated {
+        tracing::warn!(
+            "Traffic budget {} at {}% ({} of {} bytes)",
+            alert.budget_id,
+            alert.threshold_percent,
+            alert.used_bytes,
+            alert.limit_bytes
+        );
+        let _ = super::events::send_event(V8RayEvent::TrafficBudgetAlert {
+            budget_id: alert.budget_id.clone(),
+            subscription_id: alert.subscription_id.clone(),
+            period: alert.period.as_str().to_string(),
+            threshold_percent: alert.threshold_percent,
+            used_bytes: alert.used_bytes,
+            limit_bytes: alert.limit_bytes,
+            action: alert.action.as_str().to_string(),
+        });
+    }
+
+    if alert.action == BudgetAction::Notify {
+        return;
+    }
+    let core_manager = super::connection::core_connection_manager().await;
+    if !core_manager.is_connected().await {
+        return;
+    }
+    if let Some(servers) = servers {
+        let current = core_manager.get_current_config().await.map(|c| c.id);
+        if !current.is_some_and(|id| servers.contains(&id)) {
+            return;
+        }
+    }
+
+    let result = match alert.action {
+        BudgetAction::Notify => Ok(()),
+        BudgetAction::Disconnect => {
+            tracing::info!("Traffic budget {} used up, disconnecting", alert.budget_id);
+            super::connection::disconnect_async().await
+        }
+        BudgetAction::Direct if super::connection::current_proxy_mode().await == "direct" => Ok(()),
+        BudgetAction::Direct => {
+            tracing::info!(
+                "Traffic budget {} used up, switching to direct mode",
+                alert.budget_id
+            );
+            super::connection::apply_proxy_mode("direct".to_string()).await
+        }
+    };
+    if let Err(e) = result {
+        tracing::error!("Failed to apply traffic budget action: {}", e);
+    }
+}
+
+/// 启动定期预算检查（只启动一次）
+pub(crate) fn start_budget_checker() {
+    if BUDGET_CHECKER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    tokio::spawn(async {
+        loop {
+            if let Err(e) = check_traffic_budgets().await {
+                tracing::debug!("Traffic budget check failed: {}", e);
+            }
+            tokio::time::sleep(BUDGET_CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    fn budget(id: &str) -> TrafficBudgetInfo {
+        TrafficBudgetInfo {
+            id: id.to_string(),
+            subscription_id: None,
+            period: "monthly".to_string(),
+            limit_bytes: 100 * 1024 * 1024 * 1024,
+            action: "direct".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_set_traffic_budgets() {
+        set_traffic_budgets(vec![budget("month")]).await.unwrap();
+        assert_eq!(list_traffic_budgets().await[0].action, "direct");
+        // Kept in the settings, so they are saved with them
+        assert_eq!(
+            settings().await.get_config().await.traffic_budgets[0].id,
+            "month"
+        );
+
+        let mut invalid = budget("week");
+        invalid.period = "weekly".to_string();
+        assert!(set_traffic_budgets(vec![invalid]).await.is_err());
+        assert!(set_traffic_budgets(vec![budget("a"), budget("a")])
+            .await
+            .is_err());
+        assert_eq!(list_traffic_budgets().await.len(), 1);
+
+        set_traffic_budgets(vec![]).await.unwrap();
+        assert!(list_traffic_budgets().await.is_empty());
+    }
+}
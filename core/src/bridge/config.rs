@@ -4,7 +4,12 @@ use anyhow::{anyhow, Result};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use super::api::ConfigInfo;
+use super::api::{
+    ConfigDiagnostic, ConfigInfo, ProxyServerConfig, ServerFieldError, ServerValidationInfo,
+};
+use crate::config::validator::{ConfigValidator, Diagnostic, Severity};
+use crate::config::{ProxyProtocol, ProxyServerConfig as CoreProxyServerConfig};
+use chrono::Utc;
 
 lazy_static::lazy_static! {
     static ref CONFIG_MANAGER: Arc<RwLock<ConfigManager>> = Arc::new(RwLock::new(ConfigManager::new()));
@@ -55,23 +60,6 @@ impl ConfigManager {
     fn list(&self) -> Vec<ConfigInfo> {
         self.configs.clone()
     }
-
-    fn validate(&self, config: &ConfigInfo) -> Result<bool> {
-        // 基本验证
-        if config.name.is_empty() {
-            return Ok(false);
-        }
-        if config.server.is_empty() {
-            return Ok(false);
-        }
-        if config.port == 0 {
-            return Ok(false);
-        }
-        if config.protocol.is_empty() {
-            return Ok(false);
-        }
-        Ok(true)
-    }
 }
 
 /// 初始化配置管理器
@@ -104,16 +92,202 @@ pub fn list_configs() -> Result<Vec<ConfigInfo>> {
     Ok(manager.list())
 }
 
-/// 验证配置
+/// 验证配置（没有错误级别的诊断即为有效）
 pub fn validate_config(config: ConfigInfo) -> Result<bool> {
-    let manager = CONFIG_MANAGER.blocking_read();
-    manager.validate(&config)
+    Ok(!diagnose_config(config)?
+        .iter()
+        .any(|d| d.severity == Severity::Error.as_str()))
+}
+
+/// 检查配置，按字段路径返回所有问题
+///
+/// `ConfigInfo` 不含协议设置，只检查名称、地址、端口和协议
+pub fn diagnose_config(config: ConfigInfo) -> Result<Vec<ConfigDiagnostic>> {
+    let Some(protocol) = parse_protocol(&config.protocol) else {
+        return Ok(vec![unsupported_protocol(&config.protocol)]);
+    };
+    let core_config = CoreProxyServerConfig {
+        id: config.id,
+        name: config.name,
+        raw_name: None,
+        server: config.server,
+        port: config.port,
+        protocol,
+        settings: Default::default(),
+        stream_settings: None,
+        tags: vec![],
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    Ok(ConfigValidator::diagnose_proxy_config(&core_config)
+        .into_iter()
+        .filter(|d| !d.path.starts_with("settings."))
+        .map(to_api_diagnostic)
+        .collect())
+}
+
+/// 检查服务器配置，按字段路径返回所有问题（含协议设置和传输设置）
+pub fn diagnose_server(config: ProxyServerConfig) -> Result<Vec<ConfigDiagnostic>> {
+    let Some(protocol) = parse_protocol(&config.protocol) else {
+        return Ok(vec![unsupported_protocol(&config.protocol)]);
+    };
+    let stream_settings = match config.stream_settings.map(serde_json::from_value) {
+        Some(Ok(stream)) => Some(stream),
+        Some(Err(e)) => {
+            return Ok(vec![ConfigDiagnostic {
+                path: "stream_settings".to_string(),
+                severity: Severity::Error.as_str().to_string(),
+                message: format!("Invalid stream settings: {}", e),
+            }]);
+        }
+        None => None,
+    };
+    let core_config = CoreProxyServerConfig {
+        id: config.id,
+        name: config.name,
+        raw_name: None,
+        server: config.address,
+        port: config.port,
+        protocol,
+        settings: config.settings,
+        stream_settings,
+        tags: config.tags,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    Ok(ConfigValidator::diagnose_proxy_config(&core_config)
+        .into_iter()
+        .map(to_api_diagnostic)
+        .collect())
+}
+
+/// 解析协议名（忽略大小写和首尾空白）
+fn parse_protocol(protocol: &str) -> Option<ProxyProtocol> {
+    serde_json::from_value(serde_json::json!(protocol.trim().to_lowercase())).ok()
+}
+
+/// 不支持的协议对应的诊断
+fn unsupported_protocol(protocol: &str) -> ConfigDiagnostic {
+    ConfigDiagnostic {
+        path: "protocol".to_string(),
+        severity: Severity::Error.as_str().to_string(),
+        message: format!("Unsupported protocol: {}", protocol),
+    }
+}
+
+/// 转换为 API 诊断
+fn to_api_diagnostic(diagnostic: Diagnostic) -> ConfigDiagnostic {
+    ConfigDiagnostic {
+        path: diagnostic.path,
+        severity: diagnostic.severity.as_str().to_string(),
+        message: diagnostic.message,
+    }
+}
+
+/// 校验并规范化服务器配置
+pub fn validate_server_fields(config: ProxyServerConfig) -> Result<ServerValidationInfo> {
+    let protocol = match parse_protocol(&config.protocol) {
+        Some(protocol) => protocol,
+        None => {
+            let message = format!("Unsupported protocol: {}", config.protocol);
+            return Ok(ServerValidationInfo {
+                valid: false,
+                normalized: config,
+                errors: vec![ServerFieldError {
+                    field: "protocol".to_string(),
+                    message,
+                }],
+            });
+        }
+    };
+
+    let mut errors = Vec::new();
+    let stream_settings = match config.stream_settings.clone() {
+        Some(value) => match serde_json::from_value(value) {
+            Ok(stream) => Some(stream),
+            Err(e) => {
+                errors.push(ServerFieldError {
+                    field: "stream_settings".to_string(),
+                    message: format!("Invalid stream settings: {}", e),
+                });
+                None
+            }
+        },
+        None => None,
+    };
+
+    let core_config = CoreProxyServerConfig {
+        id: config.id.clone(),
+        name: config.name.clone(),
+        raw_name: None,
+        server: config.address.clone(),
+        port: config.port,
+        protocol,
+        settings: config.settings.clone(),
+        stream_settings,
+        tags: config.tags.clone(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+    let validation = ConfigValidator::validate_server_fields(&core_config);
+    errors.extend(validation.errors.into_iter().map(|e| ServerFieldError {
+        field: e.field,
+        message: e.message,
+    }));
+
+    let normalized = validation.normalized;
+    Ok(ServerValidationInfo {
+        valid: errors.is_empty(),
+        normalized: ProxyServerConfig {
+            id: config.id,
+            name: normalized.name,
+            address: normalized.server,
+            port: normalized.port,
+            protocol: config.protocol.trim().to_lowercase(),
+            settings: normalized.settings,
+            stream_settings: config.stream_settings,
+            tags: config.tags,
+        },
+        errors,
+    })
+}
+
+/// 校验表单填写的服务器配置并转换为核心配置
+///
+/// 校验失败时返回所有字段错误
+pub(crate) fn to_core_server_config(config: ProxyServerConfig) -> Result<CoreProxyServerConfig> {
+    let validation = validate_server_fields(config)?;
+    if !validation.valid {
+        let errors: Vec<String> = validation
+            .errors
+            .iter()
+            .map(|e| format!("{}: {}", e.field, e.message))
+            .collect();
+        return Err(anyhow!("Invalid server: {}", errors.join("; ")));
+    }
+
+    let config = validation.normalized;
+    Ok(CoreProxyServerConfig {
+        id: config.id,
+        name: config.name,
+        raw_name: None,
+        server: config.address,
+        port: config.port,
+        protocol: serde_json::from_value(serde_json::json!(config.protocol))?,
+        settings: config.settings,
+        stream_settings: config
+            .stream_settings
+            .map(serde_json::from_value)
+            .transpose()?,
+        tags: config.tags,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Utc;
     use serial_test::serial;
 
     fn create_test_config() -> ConfigInfo {
@@ -175,4 +349,90 @@ mod tests {
         invalid_config.name = String::new();
         assert!(!validate_config(invalid_config).unwrap());
     }
+
+    #[test]
+    fn test_diagnose_config() {
+        let mut config = create_test_config();
+        config.port = 0;
+        config.server = "bad host".to_string();
+        let paths: Vec<String> = diagnose_config(config)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.path)
+            .collect();
+        assert_eq!(paths, ["server", "port"]);
+
+        let mut config = create_test_config();
+        config.protocol = "wireguard".to_string();
+        assert!(!validate_config(config).unwrap());
+    }
+
+    #[test]
+    fn test_diagnose_server() {
+        let config = ProxyServerConfig {
+            id: "server-1".to_string(),
+            name: "Server".to_string(),
+            address: "example.com".to_string(),
+            port: 443,
+            protocol: "trojan".to_string(),
+            settings: std::collections::HashMap::new(),
+            stream_settings: Some(serde_json::json!({
+                "network": "grpc",
+                "security": "tls",
+                "tls_settings": null,
+                "tcp_settings": null,
+                "ws_settings": null,
+                "http_settings": null,
+                "quic_settings": null,
+                "grpc_settings": {"service_name": "", "multi_mode": false}
+            })),
+            tags: vec![],
+        };
+        let diagnostics = diagnose_server(config).unwrap();
+        let found: Vec<(&str, &str)> = diagnostics
+            .iter()
+            .map(|d| (d.path.as_str(), d.severity.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("settings.password", "error"),
+                ("stream_settings.tls_settings", "error"),
+                ("stream_settings.grpc_settings.service_name", "warning")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_server_fields() {
+        let mut settings = std::collections::HashMap::new();
+        settings.insert(
+            "id".to_string(),
+            serde_json::json!("550E8400-E29B-41D4-A716-446655440000"),
+        );
+        let mut config = ProxyServerConfig {
+            id: "server-1".to_string(),
+            name: "Server".to_string(),
+            address: " Example.COM ".to_string(),
+            port: 443,
+            protocol: "VLESS".to_string(),
+            settings,
+            stream_settings: Some(serde_json::json!({"network": "tcp", "security": "none"})),
+            tags: vec![],
+        };
+
+        let result = validate_server_fields(config.clone()).unwrap();
+        assert!(result.valid);
+        assert_eq!(result.normalized.address, "example.com");
+        assert_eq!(result.normalized.protocol, "vless");
+        assert_eq!(
+            result.normalized.settings["id"],
+            "550e8400-e29b-41d4-a716-446655440000"
+        );
+
+        config.protocol = "wireguard".to_string();
+        let result = validate_server_fields(config).unwrap();
+        assert!(!result.valid);
+        assert_eq!(result.errors[0].field, "protocol");
+    }
 }
@@ -1,20 +1,75 @@
 //! 连接管理 Bridge 模块
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use futures::stream::{Stream, StreamExt};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
 
-use super::api::{ConnectionInfo, ConnectionStatus, ProxyServerConfig};
-use crate::config::{ProxyProtocol, ProxyServerConfig as CoreProxyServerConfig};
+use super::api::{
+    ActiveConnectionInfo, AggregatedHealthInfo, ConnectionInfo, ConnectionStatus,
+    DiagnosticCheckInfo, DiagnosticReportInfo, DomainStatInfo, ExitInfoProviderInfo,
+    ExitInfoResult, ExitIpInfo, HealthProbeConfigInfo, HealthProbeStatusInfo, InboundAuthInfo,
+    InboundSettingsInfo, InstalledXrayVersionsInfo, InstanceHealthInfo, LatencyAlertConfigInfo,
+    LatencySampleInfo, OutboundHealthInfo, ProxyServerConfig, RouteSplitInfo, ServerQualityInfo,
+    ServerTrafficInfo, SessionEventInfo, SpeedRankingInfo, SpeedTestInfo, SpeedTestOptions,
+    TrafficSnapshotInfo, TrafficUsageInfo, UnlockResultInfo, V8RayEvent, XrayCoreUpdateInfo,
+    XrayDownloadSettingsInfo, XrayDownloadSourceInfo, XrayProcessInfo, XrayReleaseInfo,
+    XrayResourceLimitsInfo,
+};
+use crate::config::{
+    EngineLogLevel, InboundAuth, InboundSettings, PortConflictPolicy, ProxyProtocol,
+    ProxyServerConfig as CoreProxyServerConfig,
+};
+use crate::connection::access_analytics::{AccessLogStorage, DomainCount};
+use crate::connection::exit_info::{ExitInfo, ExitInfoProvider};
+use crate::connection::health_probe::{HealthProbeConfig, LatencyAlert};
+use crate::connection::latency_history::{
+    LatencyHistory, ServerQuality, DEFAULT_QUALITY_WINDOW, DEFAULT_RETENTION_DAYS,
+};
+use crate::connection::reconnect::ReconnectEvent;
+use crate::connection::speed_test::{
+    SpeedTestConfig, SpeedTestResult, DEFAULT_DOWNLOAD_URL, DEFAULT_UPLOAD_URL,
+};
+use crate::connection::timeline::{SessionEvent, SessionEventKind};
+use crate::connection::traffic_history::{RetentionPolicy, TrafficHistory, UsageRecord};
+use crate::connection::unlock_checker::{UnlockResult, UnlockService};
 use crate::connection::ConnectionManager as CoreConnectionManager;
+use crate::xray::health::{probe_outbounds, DEFAULT_PROBE_TIMEOUT};
+use crate::xray::instances::DEFAULT_INSTANCE_ID;
+use crate::xray::{
+    AggregatedHealth, BalancerStrategy, DownloadSettings, DownloadSource, HealthSummary,
+    InstanceEvent, InstanceHealth, PortReassignment, ProbeTarget, ResourceLimits, XrayCore,
+    XrayEvent, XrayStatus,
+};
 use chrono::Utc;
 
+/// Xray 日志是否已转发到事件流
+static ENGINE_LOG_FORWARDER: AtomicBool = AtomicBool::new(false);
+
+/// Xray 进程 PID 是否已记录到运行状态
+static XRAY_PROCESS_TRACKER: AtomicBool = AtomicBool::new(false);
+
+/// 自动重连进度是否已转发到事件流
+static RECONNECT_EVENT_FORWARDER: AtomicBool = AtomicBool::new(false);
+static LATENCY_ALERT_FORWARDER: AtomicBool = AtomicBool::new(false);
+
+/// 附加 Xray 实例状态是否已转发到事件流
+static INSTANCE_EVENT_FORWARDER: AtomicBool = AtomicBool::new(false);
+
+/// 连接请求序号，用于识别正在进行的连接
+static CONNECT_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+
 lazy_static::lazy_static! {
     static ref CONNECTION_MANAGER: Arc<RwLock<BridgeConnectionManager>> =
         Arc::new(RwLock::new(BridgeConnectionManager::new()));
 
+    /// 正在进行的连接（序号和取消令牌）
+    static ref PENDING_CONNECT: Mutex<Option<(u64, CancellationToken)>> = Mutex::new(None);
+
     pub(crate) static ref TOKIO_RUNTIME: tokio::runtime::Runtime = {
         tokio::runtime::Builder::new_multi_thread()
             .enable_all()
@@ -38,6 +93,11 @@ pub(crate) fn get_core_connection_manager() -> Result<Arc<CoreConnectionManager>
     Ok(Arc::clone(&manager.core_manager))
 }
 
+/// 在异步上下文中获取核心连接管理器实例 (内部使用)
+pub(crate) async fn core_connection_manager() -> Arc<CoreConnectionManager> {
+    Arc::clone(&CONNECTION_MANAGER.read().await.core_manager)
+}
+
 /// 将简化配置转换为核心配置
 fn convert_to_core_config(config: &ProxyServerConfig) -> CoreProxyServerConfig {
     let protocol = match config.protocol.as_str() {
@@ -47,6 +107,8 @@ fn convert_to_core_config(config: &ProxyServerConfig) -> CoreProxyServerConfig {
         "shadowsocks" => ProxyProtocol::Shadowsocks,
         "socks" => ProxyProtocol::Socks,
         "http" => ProxyProtocol::Http,
+        "hysteria2" => ProxyProtocol::Hysteria2,
+        "tuic" => ProxyProtocol::Tuic,
         _ => ProxyProtocol::Vless, // 默认值
     };
 
@@ -59,6 +121,7 @@ fn convert_to_core_config(config: &ProxyServerConfig) -> CoreProxyServerConfig {
     CoreProxyServerConfig {
         id: config.id.clone(),
         name: config.name.clone(),
+        raw_name: None,
         server: config.address.clone(),
         port: config.port,
         protocol,
@@ -70,6 +133,251 @@ fn convert_to_core_config(config: &ProxyServerConfig) -> CoreProxyServerConfig {
     }
 }
 
+/// 将核心聚合健康状态转换为 FFI 类型
+/// 转换 Xray 进程状态，返回状态名和错误信息
+fn convert_status(status: XrayStatus) -> (&'static str, Option<String>) {
+    match status {
+        XrayStatus::Stopped => ("stopped", None),
+        XrayStatus::Starting => ("starting", None),
+        XrayStatus::Running => ("running", None),
+        XrayStatus::Stopping => ("stopping", None),
+        XrayStatus::Error(e) => ("error", Some(e)),
+    }
+}
+
+/// 转换实例健康信息
+fn convert_instance(instance: InstanceHealth) -> InstanceHealthInfo {
+    let (status, error) = convert_status(instance.status);
+    InstanceHealthInfo {
+        instance_id: instance.instance_id,
+        status: status.to_string(),
+        error,
+        pid: instance.health.as_ref().and_then(|h| h.pid),
+        uptime: instance.health.as_ref().map(|h| h.uptime).unwrap_or(0),
+    }
+}
+
+fn convert_health(health: AggregatedHealth) -> AggregatedHealthInfo {
+    let summary = match health.summary {
+        HealthSummary::Healthy => "healthy",
+        HealthSummary::Degraded => "degraded",
+        HealthSummary::Unhealthy => "unhealthy",
+        HealthSummary::Stopped => "stopped",
+    };
+
+    let instances = health.instances.into_iter().map(convert_instance).collect();
+
+    let outbounds = health
+        .outbounds
+        .into_iter()
+        .map(|o| OutboundHealthInfo {
+            tag: if o.instance_id == DEFAULT_INSTANCE_ID {
+                o.tag
+            } else {
+                format!("{}/{}", o.instance_id, o.tag)
+            },
+            server: o.server,
+            port: o.port,
+            reachable: o.reachable,
+            latency_ms: o.latency_ms.map(|ms| ms.min(u32::MAX as u64) as u32),
+            error: o.error,
+        })
+        .collect();
+
+    let checked_at = health
+        .checked_at
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+
+    AggregatedHealthInfo {
+        summary: summary.to_string(),
+        instances,
+        outbounds,
+        checked_at,
+    }
+}
+
+/// 发送端口重新分配事件
+fn send_port_reassigned(reassignment: PortReassignment) {
+    let _ = super::events::send_event(V8RayEvent::PortReassigned {
+        protocol: reassignment.protocol,
+        old_port: reassignment.old_port,
+        new_port: reassignment.new_port,
+    });
+}
+
+/// 将 Xray 日志按引擎日志级别过滤后转发到事件流（只启动一次）
+///
+/// 级别在每条日志上重新读取，修改后立即生效；热重载期间旧进程仍按原级别输出的日志也会被过滤
+fn forward_engine_logs(core_manager: &CoreConnectionManager) {
+    if ENGINE_LOG_FORWARDER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let xray = core_manager.get_xray();
+    let mut events = xray.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(XrayEvent::LogReceived(log)) => {
+                    if xray.engine_log_level().allows(&log.level) {
+                        super::logs::send_log_event(V8RayEvent::Log {
+                            level: log.level.to_lowercase(),
+                            message: log.message,
+                        });
+                    }
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        ENGINE_LOG_FORWARDER.store(false, Ordering::SeqCst);
+    });
+}
+
+/// 将 Xray 进程 PID 写入运行状态，并转发端口重新分配和内存超限重启事件（只启动一次）
+///
+/// 热重载、自动重连和内存超限重启会更换进程，因此跟随事件更新；应用崩溃后下次启动据此终止遗留进程。
+/// 端口重新分配后，设置中的代理端口和应用设置的系统代理（或 PAC）随之改用新端口；
+/// 限速或脚本路由前置转发器后面的内部端口不对外通知
+fn track_xray_process(core_manager: &Arc<CoreConnectionManager>) {
+    if XRAY_PROCESS_TRACKER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let manager = Arc::clone(core_manager);
+    let mut events = core_manager.subscribe_xray_events();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(XrayEvent::ProcessStarted(pid)) => {
+                    super::session::update_runtime_state(|s| s.xray_pid = Some(pid));
+                }
+                Ok(XrayEvent::StatusChanged(XrayStatus::Stopped | XrayStatus::Error(_))) => {
+                    super::session::update_runtime_state(|s| s.xray_pid = None);
+                }
+                Ok(XrayEvent::PortReassigned(reassignment)) => {
+                    let internal = |port| manager.public_inbound_port(port) != port;
+                    if internal(reassignment.old_port) || internal(reassignment.new_port) {
+                        continue;
+                    }
+                    super::platform::follow_port_reassignment(
+                        &reassignment.protocol,
+                        reassignment.old_port,
+                        reassignment.new_port,
+                    )
+                    .await;
+                    save_port_reassignment(&reassignment).await;
+                    send_port_reassigned(reassignment);
+                }
+                Ok(XrayEvent::MemoryLimitExceeded(exceeded)) => {
+                    let _ = super::events::send_event(V8RayEvent::XrayMemoryLimitExceeded {
+                        pid: exceeded.pid,
+                        memory_bytes: exceeded.memory_bytes,
+                        limit_bytes: exceeded.limit_bytes,
+                    });
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        XRAY_PROCESS_TRACKER.store(false, Ordering::SeqCst);
+    });
+}
+
+/// 将延迟告警转发到事件流（只启动一次）
+fn forward_latency_alerts(core_manager: &CoreConnectionManager) {
+    if LATENCY_ALERT_FORWARDER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut alerts = core_manager.subscribe_latency_alerts();
+    tokio::spawn(async move {
+        loop {
+            let event = match alerts.recv().await {
+                Ok(LatencyAlert::Degraded {
+                    server_id,
+                    latency_ms,
+                    threshold_ms,
+                }) => V8RayEvent::DegradedConnection {
+                    server_id,
+                    latency_ms,
+                    threshold_ms,
+                },
+                Ok(LatencyAlert::Recovered {
+                    server_id,
+                    latency_ms,
+                }) => V8RayEvent::LatencyRecovered {
+                    server_id,
+                    latency_ms,
+                },
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            };
+            let _ = super::events::send_event(event);
+        }
+        LATENCY_ALERT_FORWARDER.store(false, Ordering::SeqCst);
+    });
+}
+
+/// 将自动重连进度转发到事件流（只启动一次）
+fn forward_reconnect_events(core_manager: &CoreConnectionManager) {
+    if RECONNECT_EVENT_FORWARDER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut events = core_manager.subscribe_reconnect_events();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(ReconnectEvent::Reconnecting {
+                    class,
+                    attempt,
+                    delay,
+                }) => {
+                    let _ = super::events::send_event(V8RayEvent::Reconnecting {
+                        reason: class.as_str().to_string(),
+                        attempt,
+                        delay_ms: delay.as_millis() as u64,
+                    });
+                }
+                Ok(ReconnectEvent::Reconnected { .. }) => {
+                    let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+                        status: ConnectionStatus::Connected,
+                    });
+                }
+                Ok(ReconnectEvent::ReconnectFailed {
+                    class,
+                    attempts,
+                    reason,
+                }) => {
+                    let _ = super::events::send_event(V8RayEvent::ReconnectFailed {
+                        reason: class.as_str().to_string(),
+                        attempts,
+                        message: reason,
+                    });
+                    let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+                        status: ConnectionStatus::Error,
+                    });
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        RECONNECT_EVENT_FORWARDER.store(false, Ordering::SeqCst);
+    });
+}
+
+/// 将会话事件转换为 FFI 类型
+fn convert_session_event(event: SessionEvent) -> SessionEventInfo {
+    SessionEventInfo {
+        timestamp: event.timestamp.timestamp_millis(),
+        kind: event.kind.as_str().to_string(),
+        message: event.message,
+    }
+}
+
 /// Bridge 连接管理器
 struct BridgeConnectionManager {
     core_manager: Arc<CoreConnectionManager>,
@@ -80,15 +388,19 @@ struct BridgeConnectionManager {
 
 impl BridgeConnectionManager {
     fn new() -> Self {
+        let core_manager = Arc::new(CoreConnectionManager::new());
+        // 启用 Xray API，切换服务器时尽量不重启进程，并读取流量统计
+        core_manager.get_xray().set_api_enabled(true);
+
         Self {
-            core_manager: Arc::new(CoreConnectionManager::new()),
+            core_manager,
             config_cache: HashMap::new(),
             connected_at: None,
             proxy_mode: "smart".to_string(), // Default to smart mode
         }
     }
 
-    async fn connect(&mut self, config_id: &str) -> Result<()> {
+    async fn connect(&mut self, config_id: &str, cancel: &CancellationToken) -> Result<()> {
         // 从缓存获取配置
         let config = self
             .config_cache
@@ -98,11 +410,53 @@ impl BridgeConnectionManager {
         // 转换为核心配置
         let core_config = convert_to_core_config(config);
 
+        // 首次运行且没有 Xray Core 时提示下载，而不是直接失败
+        if !self.core_manager.get_xray().binary_available() {
+            let _ = super::events::send_event(V8RayEvent::XrayCoreMissing {
+                server_id: Some(config_id.to_string()),
+            });
+            return Err(crate::xray::XrayError::NotFound.into());
+        }
+
         // 使用核心管理器连接，传递代理模式
-        self.core_manager
-            .connect_with_config_and_mode(core_config, &self.proxy_mode)
-            .await?;
+        let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+            status: ConnectionStatus::Connecting,
+        });
+        forward_engine_logs(&self.core_manager);
+        track_xray_process(&self.core_manager);
+        forward_reconnect_events(&self.core_manager);
+        forward_latency_alerts(&self.core_manager);
+        // Xray 已启动，但要等代理真正可用后才算连接成功
+        let result = self
+            .core_manager
+            .connect_with_progress(core_config, &self.proxy_mode, cancel, |phase| {
+                let _ = super::events::send_event(V8RayEvent::ConnectPhaseChanged {
+                    server_id: config_id.to_string(),
+                    phase: phase.as_str().to_string(),
+                });
+            })
+            .await;
+
+        if let Err(e) = result {
+            // 取消后已断开，不视为错误状态
+            let status = if cancel.is_cancelled() {
+                ConnectionStatus::Disconnected
+            } else {
+                ConnectionStatus::Error
+            };
+            let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged { status });
+            return Err(e.into());
+        }
         self.connected_at = Some(Instant::now());
+        let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+            status: ConnectionStatus::Connected,
+        });
+        super::session::update_runtime_state(|s| {
+            s.connected = true;
+            s.server_id = Some(config_id.to_string());
+            s.mode = Some(self.proxy_mode.clone());
+            s.last_session.connected_to(config_id, &self.proxy_mode);
+        });
 
         tracing::info!(
             "Connected to config: {} with mode: {}",
@@ -112,7 +466,67 @@ impl BridgeConnectionManager {
         Ok(())
     }
 
+    /// 按负载均衡策略同时连接多台服务器，第一台作为主服务器记录
+    async fn connect_balanced(
+        &mut self,
+        configs: Vec<CoreProxyServerConfig>,
+        strategy: BalancerStrategy,
+    ) -> Result<()> {
+        let primary_id = configs
+            .first()
+            .map(|config| config.id.clone())
+            .ok_or_else(|| anyhow!("No servers to connect to"))?;
+        if !self.core_manager.get_xray().binary_available() {
+            let _ = super::events::send_event(V8RayEvent::XrayCoreMissing {
+                server_id: Some(primary_id),
+            });
+            return Err(crate::xray::XrayError::NotFound.into());
+        }
+
+        let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+            status: ConnectionStatus::Connecting,
+        });
+        forward_engine_logs(&self.core_manager);
+        track_xray_process(&self.core_manager);
+        forward_reconnect_events(&self.core_manager);
+        forward_latency_alerts(&self.core_manager);
+        let result = self
+            .core_manager
+            .connect_with_configs_and_strategy(configs, &self.proxy_mode, strategy)
+            .await;
+        if let Err(e) = result {
+            let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+                status: ConnectionStatus::Error,
+            });
+            return Err(e.into());
+        }
+
+        self.connected_at = Some(Instant::now());
+        let _ = super::events::send_event(V8RayEvent::ConnectionStatusChanged {
+            status: ConnectionStatus::Connected,
+        });
+        super::session::update_runtime_state(|s| {
+            s.connected = true;
+            s.server_id = Some(primary_id.clone());
+            s.mode = Some(self.proxy_mode.clone());
+            s.last_session.connected_to(&primary_id, &self.proxy_mode);
+        });
+        tracing::info!(
+            "Connected to balanced servers ({:?}) with mode: {}",
+            strategy,
+            self.proxy_mode
+        );
+        Ok(())
+    }
+
     fn set_proxy_mode(&mut self, mode: String) {
+        if self.connected_at.is_some() && mode != self.proxy_mode {
+            // 新模式在下次连接时生效
+            self.core_manager.record_event(
+                SessionEventKind::RouteChanged,
+                format!("Proxy mode changed from {} to {}", self.proxy_mode, mode),
+            );
+        }
         self.proxy_mode = mode;
         tracing::info!("Proxy mode set to: {}", self.proxy_mode);
     }
@@ -120,6 +534,10 @@ impl BridgeConnectionManager {
     async fn disconnect(&mut self) -> Result<()> {
         self.core_manager.disconnect().await?;
         self.connected_at = None;
+        super::session::update_runtime_state(|s| {
+            s.connected = false;
+            s.xray_pid = None;
+        });
         tracing::info!("Disconnected");
         Ok(())
     }
@@ -135,6 +553,10 @@ impl BridgeConnectionManager {
         let status = match state {
             crate::connection::ConnectionState::Disconnected => ConnectionStatus::Disconnected,
             crate::connection::ConnectionState::Connecting => ConnectionStatus::Connecting,
+            // 代理就绪前仍视为连接中
+            crate::connection::ConnectionState::Connected if self.connected_at.is_none() => {
+                ConnectionStatus::Connecting
+            }
             crate::connection::ConnectionState::Connected => ConnectionStatus::Connected,
             crate::connection::ConnectionState::Disconnecting => ConnectionStatus::Disconnecting,
             crate::connection::ConnectionState::Reconnecting => ConnectionStatus::Connecting,
@@ -147,25 +569,25 @@ impl BridgeConnectionManager {
             .await
             .map(|c| c.server.clone());
 
+        // 最近一次连通性探测经代理测得的延迟
+        let latency_ms = (status == ConnectionStatus::Connected)
+            .then(|| self.core_manager.get_health_probe_state().last_latency_ms)
+            .flatten()
+            .map(|ms| ms.min(u32::MAX as u64) as u32);
+
         ConnectionInfo {
             status,
             server_address,
             duration,
             upload_bytes,
             download_bytes,
-            latency_ms: None,
+            latency_ms,
         }
     }
 
     fn cache_config(&mut self, config_id: String, config: ProxyServerConfig) {
         self.config_cache.insert(config_id, config);
     }
-
-    fn test_latency(&self, _config_id: &str) -> Result<u32> {
-        // TODO: 实际的延迟测试逻辑
-        // 这里只是模拟
-        Ok(50)
-    }
 }
 
 /// 初始化连接管理器
@@ -176,9 +598,11 @@ pub fn init() -> Result<()> {
 
 /// 关闭连接管理器
 pub fn shutdown() -> Result<()> {
+    cancel_connect();
     tokio::runtime::Runtime::new()?.block_on(async {
         let mut manager = CONNECTION_MANAGER.write().await;
         manager.disconnect().await?;
+        manager.core_manager.instances().stop_all().await;
         tracing::info!("Connection manager shutdown");
         Ok(())
     })
@@ -191,6 +615,135 @@ pub fn set_proxy_mode(mode: String) -> Result<()> {
     Ok(())
 }
 
+/// 设置端口冲突策略（"fail" 或 "reassign"）并保存设置
+pub async fn set_port_conflict_policy(policy: &str) -> Result<()> {
+    let policy = match policy {
+        "fail" => PortConflictPolicy::Fail,
+        "reassign" => PortConflictPolicy::Reassign,
+        _ => return Err(anyhow!("Invalid port conflict policy: {}", policy)),
+    };
+    super::settings::settings()
+        .await
+        .update_config(|config| config.proxy.port_conflict = policy)
+        .await?;
+    super::settings::save_settings().await?;
+    core_connection_manager()
+        .await
+        .get_xray()
+        .set_port_conflict_policy(policy);
+    Ok(())
+}
+
+/// 将重新分配的端口写入设置中的代理端口，之后的连接和配置档沿用新端口
+///
+/// 只有设置中的端口被替换时才保存，配置档自己的端口不写入设置
+async fn save_port_reassignment(reassignment: &PortReassignment) {
+    let settings = super::settings::settings().await;
+    let mut moved = false;
+    let result = settings
+        .update_config(|config| {
+            moved = config.proxy.apply_port_reassignment(
+                &reassignment.protocol,
+                reassignment.old_port,
+                reassignment.new_port,
+            );
+        })
+        .await;
+    if let Err(e) = result {
+        tracing::warn!("Failed to update the reassigned port: {}", e);
+    } else if moved {
+        if let Err(e) = super::settings::save_settings().await {
+            tracing::warn!("Failed to save the reassigned port: {}", e);
+        }
+    }
+}
+
+/// 获取本地入站设置
+pub async fn get_inbound_settings() -> InboundSettingsInfo {
+    let settings = super::settings::settings()
+        .await
+        .get_inbound_settings()
+        .await;
+    let convert = |auth: InboundAuth| InboundAuthInfo {
+        username: auth.username,
+        password: auth.password,
+    };
+    InboundSettingsInfo {
+        allow_lan: settings.allow_lan,
+        http_auth: settings.http_auth.map(convert),
+        socks_auth: settings.socks_auth.map(convert),
+        udp: settings.udp,
+    }
+}
+
+/// 修改本地入站设置并保存（下次连接时生效）
+pub async fn set_inbound_settings(info: InboundSettingsInfo) -> Result<()> {
+    let convert = |auth: InboundAuthInfo| InboundAuth {
+        username: auth.username,
+        password: auth.password,
+    };
+    let inbound = InboundSettings {
+        allow_lan: info.allow_lan,
+        http_auth: info.http_auth.map(convert),
+        socks_auth: info.socks_auth.map(convert),
+        udp: info.udp,
+    };
+    let settings = super::settings::settings().await;
+    settings.set_inbound_settings(inbound).await?;
+    super::settings::save_settings().await?;
+    super::settings::apply_inbound_settings(&settings).await;
+    Ok(())
+}
+
+/// 获取 Xray 进程资源限制
+pub fn get_xray_resource_limits() -> Result<XrayResourceLimitsInfo> {
+    let limits = get_core_connection_manager()?.get_xray().resource_limits();
+    Ok(XrayResourceLimitsInfo {
+        priority: limits.priority.as_str().to_string(),
+        cpu_affinity: limits.cpu_affinity.iter().map(|cpu| *cpu as u32).collect(),
+        memory_limit_mb: limits.memory_limit_bytes.map(|bytes| bytes / (1024 * 1024)),
+    })
+}
+
+/// 设置 Xray 进程资源限制，运行中的进程立即应用优先级和 CPU 限制
+pub fn set_xray_resource_limits(limits: XrayResourceLimitsInfo) -> Result<()> {
+    let limits = ResourceLimits {
+        priority: limits.priority.parse().map_err(|e: String| anyhow!(e))?,
+        cpu_affinity: limits
+            .cpu_affinity
+            .iter()
+            .map(|cpu| *cpu as usize)
+            .collect(),
+        memory_limit_bytes: limits
+            .memory_limit_mb
+            .map(|mb| {
+                mb.checked_mul(1024 * 1024)
+                    .ok_or_else(|| anyhow!("Memory limit too large: {} MiB", mb))
+            })
+            .transpose()?,
+    };
+    let xray = get_core_connection_manager()?.get_xray();
+    TOKIO_RUNTIME.block_on(xray.apply_resource_limits(limits))?;
+    Ok(())
+}
+
+/// 获取引擎（Xray）日志级别
+pub fn get_engine_log_level() -> Result<String> {
+    let level = get_core_connection_manager()?.get_xray().engine_log_level();
+    Ok(level.to_string())
+}
+
+/// 设置引擎日志级别（"none"、"error"、"warning"、"info"、"debug"），运行中会热重载 Xray
+pub fn set_engine_log_level(level: &str) -> Result<()> {
+    let level: EngineLogLevel = level.parse()?;
+    let manager = get_core_connection_manager()?;
+    // 热重载后前置转发器跟随新的内部端口，本地端口的变化由 track_xray_process 通知
+    let reassignments = TOKIO_RUNTIME.block_on(manager.get_xray().apply_engine_log_level(level))?;
+    manager.follow_port_reassignments(&reassignments);
+    tracing::info!("Engine log level set to: {}", level);
+    Ok(())
+}
+
 /// 缓存配置（在连接前调用）
 pub fn cache_proxy_config(config_id: String, config: ProxyServerConfig) -> Result<()> {
     let mut manager = CONNECTION_MANAGER.blocking_write();
@@ -200,32 +753,1180 @@ pub fn cache_proxy_config(config_id: String, config: ProxyServerConfig) -> Resul
 
 /// 连接到服务器
 pub fn connect(config_id: &str) -> Result<()> {
-    TOKIO_RUNTIME.block_on(async {
+    TOKIO_RUNTIME.block_on(connect_async(config_id))
+}
+
+/// 在异步上下文中连接到服务器，可通过 `cancel_connect` 取消
+pub async fn connect_async(config_id: &str) -> Result<()> {
+    let attempt = CONNECT_ATTEMPTS.fetch_add(1, Ordering::SeqCst);
+    let cancel = CancellationToken::new();
+    // 新的连接请求取代尚未完成的请求
+    let previous = PENDING_CONNECT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace((attempt, cancel.clone()));
+    if let Some((_, previous)) = previous {
+        previous.cancel();
+    }
+
+    let result = CONNECTION_MANAGER
+        .write()
+        .await
+        .connect(config_id, &cancel)
+        .await;
+
+    let mut pending = PENDING_CONNECT.lock().unwrap_or_else(|e| e.into_inner());
+    if pending.as_ref().is_some_and(|(id, _)| *id == attempt) {
+        *pending = None;
+    }
+    result
+}
+
+/// 按负载均衡策略连接到订阅中的所有服务器
+///
+/// 策略为 "least_ping"、"random"、"round_robin" 或 "manual"；manual 按保存的手动排序
+/// 使用第一台服务器，不可用时切换到第二台
+pub async fn connect_balanced(subscription_id: String, strategy: &str) -> Result<()> {
+    let strategy = BalancerStrategy::parse(strategy)
+        .ok_or_else(|| anyhow!("Invalid balancer strategy: {}", strategy))?;
+    // 服务器已按手动排序返回
+    let servers = super::subscription::get_servers_for_subscription(subscription_id).await?;
+    let mut configs = Vec::with_capacity(servers.len());
+    for server in servers {
+        let config = super::subscription::get_server_config(server.id).await?;
+        configs.push(convert_to_core_config(&config));
+    }
+
+    CONNECTION_MANAGER
+        .write()
+        .await
+        .connect_balanced(configs, strategy)
+        .await
+}
+
+/// 取消正在进行的连接，返回是否有连接被取消
+pub fn cancel_connect() -> bool {
+    let pending = PENDING_CONNECT
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take();
+    match pending {
+        Some((_, cancel)) => {
+            tracing::info!("Cancelling pending connection");
+            cancel.cancel();
+            true
+        }
+        None => false,
+    }
+}
+
+/// 在异步上下文中连接到订阅中的服务器，可同时切换代理模式（内部使用）
+pub(crate) async fn connect_server(server_id: &str, mode: Option<String>) -> Result<()> {
+    let config = super::subscription::get_server_config(server_id.to_string()).await?;
+    {
         let mut manager = CONNECTION_MANAGER.write().await;
-        manager.connect(config_id).await
-    })
+        manager.cache_config(server_id.to_string(), config);
+        if let Some(mode) = mode {
+            manager.set_proxy_mode(mode);
+        }
+    }
+    connect_async(server_id).await
+}
+
+/// 当前代理模式（内部使用）
+pub(crate) async fn current_proxy_mode() -> String {
+    CONNECTION_MANAGER.read().await.proxy_mode.clone()
+}
+
+/// 在异步上下文中切换代理模式，已连接时按新模式重新连接当前服务器（内部使用）
+pub(crate) async fn apply_proxy_mode(mode: String) -> Result<()> {
+    CONNECTION_MANAGER.write().await.set_proxy_mode(mode);
+    reconnect_current().await
+}
+
+/// 已连接时按当前设置重新连接当前服务器（内部使用）
+///
+/// 运行中的 Xray 优先通过 API 替换路由，不中断代理
+pub(crate) async fn reconnect_current() -> Result<()> {
+    let server_id = {
+        let manager = CONNECTION_MANAGER.read().await;
+        if manager.core_manager.is_connected().await {
+            manager
+                .core_manager
+                .get_current_connection()
+                .await
+                .map(|c| c.config_id)
+        } else {
+            None
+        }
+    };
+    match server_id {
+        Some(server_id) => connect_async(&server_id).await,
+        None => Ok(()),
+    }
+}
+
+/// 启动附加的 Xray 实例
+pub async fn start_xray_instance(
+    instance_id: &str,
+    server_id: &str,
+    mode: &str,
+    http_port: u16,
+    socks_port: u16,
+) -> Result<()> {
+    let config = super::subscription::get_server_config(server_id.to_string()).await?;
+    let core_manager = core_connection_manager().await;
+    forward_instance_events(&core_manager);
+    core_manager
+        .start_instance(
+            instance_id,
+            &convert_to_core_config(&config),
+            mode,
+            http_port,
+            socks_port,
+        )
+        .await
+        .with_context(|| format!("Failed to start Xray instance {}", instance_id))?;
+    Ok(())
+}
+
+/// 停止附加的 Xray 实例，返回实例是否存在
+pub async fn stop_xray_instance(instance_id: &str) -> Result<bool> {
+    Ok(core_connection_manager()
+        .await
+        .stop_instance(instance_id)
+        .await?)
+}
+
+/// 列出附加的 Xray 实例
+pub async fn list_xray_instances() -> Vec<InstanceHealthInfo> {
+    core_connection_manager()
+        .await
+        .instances()
+        .list()
+        .await
+        .into_iter()
+        .map(convert_instance)
+        .collect()
+}
+
+/// 将附加 Xray 实例的状态变化转发到事件流（只启动一次）
+fn forward_instance_events(core_manager: &CoreConnectionManager) {
+    if INSTANCE_EVENT_FORWARDER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut events = core_manager.instances().subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(InstanceEvent {
+                    instance_id,
+                    event: XrayEvent::StatusChanged(status),
+                }) => {
+                    let (status, error) = convert_status(status);
+                    let _ = super::events::send_event(V8RayEvent::InstanceStatusChanged {
+                        instance_id,
+                        status: status.to_string(),
+                        error,
+                    });
+                }
+                Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+        INSTANCE_EVENT_FORWARDER.store(false, Ordering::SeqCst);
+    });
+}
+
+/// 当前连接的本地 HTTP 和 SOCKS 入站端口（内部使用）
+///
+/// 入站限速时返回应用程序连接的端口，而非 Xray 的内部端口
+pub(crate) async fn local_proxy_ports() -> Option<(u16, u16)> {
+    let manager = core_connection_manager().await;
+    let config = manager.get_xray().running_config().await?;
+    let port = |protocol: &str| {
+        config
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == protocol)
+            .map(|inbound| manager.public_inbound_port(inbound.port))
+    };
+    Some((port("http")?, port("socks")?))
 }
 
 /// 断开连接
 pub fn disconnect() -> Result<()> {
-    TOKIO_RUNTIME.block_on(async {
-        let mut manager = CONNECTION_MANAGER.write().await;
-        manager.disconnect().await
-    })
+    TOKIO_RUNTIME.block_on(disconnect_async())
+}
+
+/// 在异步上下文中断开连接，正在进行的连接会先被取消
+pub async fn disconnect_async() -> Result<()> {
+    cancel_connect();
+    let mut manager = CONNECTION_MANAGER.write().await;
+    manager.disconnect().await?;
+    // 用户主动断开，下次启动时不再恢复
+    super::session::update_runtime_state(|s| s.last_session.connected = false);
+    Ok(())
 }
 
 /// 获取连接信息
 pub fn get_connection_info() -> Result<ConnectionInfo> {
+    Ok(TOKIO_RUNTIME.block_on(connection_info()))
+}
+
+/// 在异步上下文中获取连接信息（内部使用）
+pub(crate) async fn connection_info() -> ConnectionInfo {
+    let manager = CONNECTION_MANAGER.read().await;
+    manager.get_info().await
+}
+
+/// 获取聚合健康状态
+pub fn get_aggregated_health() -> Result<AggregatedHealthInfo> {
     TOKIO_RUNTIME.block_on(async {
         let manager = CONNECTION_MANAGER.read().await;
-        Ok(manager.get_info().await)
+        let health = manager.core_manager.get_aggregated_health().await;
+        Ok(convert_health(health))
     })
 }
 
+/// 获取 Xray 进程状态与资源占用
+pub fn get_xray_process_info() -> Result<Option<XrayProcessInfo>> {
+    Ok(TOKIO_RUNTIME.block_on(xray_process_info()))
+}
+
+/// 在异步上下文中获取 Xray 进程状态（内部使用）
+pub(crate) async fn xray_process_info() -> Option<XrayProcessInfo> {
+    let health = core_connection_manager()
+        .await
+        .get_xray()
+        .get_health()
+        .await;
+    health.map(|health| XrayProcessInfo {
+        pid: health.pid,
+        alive: health
+            .pid
+            .is_some_and(crate::xray::process::is_process_alive),
+        uptime: health.uptime,
+        responsive: health.is_responsive,
+        memory_bytes: health.memory_bytes,
+        cpu_percent: health.cpu_percent,
+    })
+}
+
+/// 获取连通性探测设置
+pub fn get_health_probe_config() -> Result<HealthProbeConfigInfo> {
+    let manager = get_core_connection_manager()?;
+    let config = TOKIO_RUNTIME.block_on(manager.get_health_probe_config());
+    Ok(HealthProbeConfigInfo {
+        enabled: config.enabled,
+        url: config.url,
+        interval_secs: config.interval.as_secs() as u32,
+        timeout_secs: config.timeout.as_secs() as u32,
+        failure_threshold: config.failure_threshold,
+    })
+}
+
+/// 设置连通性探测（立即生效）
+pub fn set_health_probe_config(config: HealthProbeConfigInfo) -> Result<()> {
+    let manager = get_core_connection_manager()?;
+    let current = TOKIO_RUNTIME.block_on(manager.get_health_probe_config());
+    let config = HealthProbeConfig {
+        enabled: config.enabled,
+        url: config.url.trim().to_string(),
+        interval: std::time::Duration::from_secs(config.interval_secs.into()),
+        timeout: std::time::Duration::from_secs(config.timeout_secs.into()),
+        failure_threshold: config.failure_threshold,
+        ..current
+    };
+    config.validate()?;
+    TOKIO_RUNTIME.block_on(manager.set_health_probe_config(config));
+    Ok(())
+}
+
+/// 获取延迟告警设置
+pub fn get_latency_alert_config() -> Result<LatencyAlertConfigInfo> {
+    let manager = get_core_connection_manager()?;
+    let config = TOKIO_RUNTIME.block_on(manager.get_health_probe_config());
+    Ok(LatencyAlertConfigInfo {
+        threshold_ms: config
+            .latency_threshold_ms
+            .map(|ms| ms.min(u32::MAX as u64) as u32),
+        consecutive_probes: config.latency_alert_count,
+    })
+}
+
+/// 设置延迟告警（立即生效）
+pub fn set_latency_alert_config(config: LatencyAlertConfigInfo) -> Result<()> {
+    let manager = get_core_connection_manager()?;
+    let current = TOKIO_RUNTIME.block_on(manager.get_health_probe_config());
+    let config = HealthProbeConfig {
+        latency_threshold_ms: config.threshold_ms.map(u64::from),
+        latency_alert_count: config.consecutive_probes,
+        ..current
+    };
+    config.validate()?;
+    TOKIO_RUNTIME.block_on(manager.set_health_probe_config(config));
+    Ok(())
+}
+
+/// 获取当前连接的连通性探测结果
+pub fn get_health_probe_status() -> Result<HealthProbeStatusInfo> {
+    let manager = get_core_connection_manager()?;
+    let state = manager.get_health_probe_state();
+    Ok(HealthProbeStatusInfo {
+        degraded: state.degraded,
+        consecutive_failures: state.consecutive_failures,
+        last_latency_ms: state.last_latency_ms,
+        last_error: state.last_error,
+        last_check: state.last_check.map(|t| t.timestamp_millis()),
+        total_checks: state.total_checks,
+        total_failures: state.total_failures,
+    })
+}
+
+/// 创建实时流量流
+pub fn create_traffic_stream(interval_ms: u32) -> impl Stream<Item = TrafficSnapshotInfo> {
+    let interval = std::time::Duration::from_millis(interval_ms.into());
+    futures::stream::once(core_connection_manager())
+        .flat_map(move |manager| manager.get_stats_collector().snapshot_stream(interval))
+        .map(|snapshot| TrafficSnapshotInfo {
+            timestamp: snapshot.timestamp.timestamp_millis(),
+            upload_bytes: snapshot.upload_bytes,
+            download_bytes: snapshot.download_bytes,
+            upload_speed: snapshot.upload_speed,
+            download_speed: snapshot.download_speed,
+        })
+        .boxed()
+}
+
+/// 打开流量历史数据库并清理过期记录
+pub async fn init_traffic_history(db_path: &str) -> Result<()> {
+    let history = TrafficHistory::new(db_path).await?;
+    history.prune(&RetentionPolicy::default()).await?;
+    core_connection_manager()
+        .await
+        .set_traffic_history(Arc::new(history))
+        .await;
+    super::budget::start_budget_checker();
+    Ok(())
+}
+
+/// 获取已打开的流量历史
+async fn traffic_history() -> Result<Arc<TrafficHistory>> {
+    core_connection_manager()
+        .await
+        .get_traffic_history()
+        .await
+        .ok_or_else(|| anyhow!("Traffic history not initialized"))
+}
+
+/// 将流量记录转换为 FFI 类型
+fn convert_usage(records: Vec<UsageRecord>) -> Vec<TrafficUsageInfo> {
+    records
+        .into_iter()
+        .map(|record| TrafficUsageInfo {
+            period: record.period,
+            upload_bytes: record.upload_bytes,
+            download_bytes: record.download_bytes,
+        })
+        .collect()
+}
+
+/// 获取每日流量
+pub async fn get_daily_usage(
+    days: u32,
+    server_id: Option<String>,
+) -> Result<Vec<TrafficUsageInfo>> {
+    let records = traffic_history()
+        .await?
+        .get_daily_usage(days, server_id.as_deref())
+        .await?;
+    Ok(convert_usage(records))
+}
+
+/// 获取每月流量
+pub async fn get_monthly_usage(
+    months: u32,
+    server_id: Option<String>,
+) -> Result<Vec<TrafficUsageInfo>> {
+    let records = traffic_history()
+        .await?
+        .get_monthly_usage(months, server_id.as_deref())
+        .await?;
+    Ok(convert_usage(records))
+}
+
+/// 获取各服务器流量
+pub async fn get_server_usage(days: u32) -> Result<Vec<ServerTrafficInfo>> {
+    let usage = traffic_history().await?.get_usage_by_server(days).await?;
+    Ok(usage
+        .into_iter()
+        .map(|usage| ServerTrafficInfo {
+            server_id: usage.server_id,
+            upload_bytes: usage.upload_bytes,
+            download_bytes: usage.download_bytes,
+        })
+        .collect())
+}
+
+/// 打开延迟历史数据库并清理过期记录
+pub async fn init_latency_history(db_path: &str) -> Result<()> {
+    let history = LatencyHistory::new(db_path).await?;
+    history.prune(DEFAULT_RETENTION_DAYS).await?;
+    core_connection_manager()
+        .await
+        .set_latency_history(Arc::new(history))
+        .await;
+    Ok(())
+}
+
+/// 获取已打开的延迟历史
+async fn latency_history() -> Result<Arc<LatencyHistory>> {
+    core_connection_manager()
+        .await
+        .get_latency_history()
+        .await
+        .ok_or_else(|| anyhow!("Latency history not initialized"))
+}
+
+/// 将服务器质量转换为 FFI 类型
+fn convert_quality(quality: ServerQuality) -> ServerQualityInfo {
+    let score = quality.score();
+    ServerQualityInfo {
+        server_id: quality.server_id,
+        samples: quality
+            .samples
+            .into_iter()
+            .map(|sample| LatencySampleInfo {
+                tested_at: sample.tested_at.timestamp_millis(),
+                latency_ms: sample.latency_ms.map(|ms| ms.min(u32::MAX as u64) as u32),
+            })
+            .collect(),
+        loss_rate: quality.loss_rate,
+        average_ms: quality.average_ms,
+        p95_ms: quality.p95_ms.map(|ms| ms.min(u32::MAX as u64) as u32),
+        jitter_ms: quality.jitter_ms,
+        score,
+    }
+}
+
+/// 获取服务器连接质量
+pub async fn get_server_quality(server_id: String) -> Result<ServerQualityInfo> {
+    let quality = latency_history()
+        .await?
+        .quality(&server_id, DEFAULT_QUALITY_WINDOW)
+        .await?;
+    Ok(convert_quality(quality))
+}
+
+/// 按连接质量排序服务器
+pub async fn rank_servers_by_quality(server_ids: Vec<String>) -> Result<Vec<ServerQualityInfo>> {
+    let ranked = latency_history()
+        .await?
+        .rank(&server_ids, DEFAULT_QUALITY_WINDOW)
+        .await?;
+    Ok(ranked.into_iter().map(convert_quality).collect())
+}
+
+/// 按连接质量连接到订阅中最稳定的服务器，返回选中的服务器 ID
+///
+/// 按延迟历史的质量评分（p95、抖动和丢包率）选择，而不是只看最近一次延迟。
+/// 还没有任何服务器测试成功时先测试一遍
+pub async fn connect_best_server(subscription_id: String) -> Result<String> {
+    let server_ids: Vec<String> =
+        super::subscription::get_servers_for_subscription(subscription_id)
+            .await?
+            .into_iter()
+            .map(|server| server.id)
+            .collect();
+    if server_ids.is_empty() {
+        return Err(anyhow!("No servers in the subscription"));
+    }
+
+    let history = latency_history().await?;
+    let mut best = best_ranked_server(&history, &server_ids).await?;
+    if best.is_none() {
+        super::subscription::retest_servers(server_ids.clone()).await?;
+        best = best_ranked_server(&history, &server_ids).await?;
+    }
+    let server_id = best.ok_or_else(|| anyhow!("No server in the subscription is reachable"))?;
+    tracing::info!("Auto-selected server {} by connection quality", server_id);
+    connect_server(&server_id, None).await?;
+    Ok(server_id)
+}
+
+/// 质量评分最好的服务器，没有测试成功的服务器时返回 None
+async fn best_ranked_server(
+    history: &LatencyHistory,
+    server_ids: &[String],
+) -> Result<Option<String>> {
+    Ok(history
+        .rank(server_ids, DEFAULT_QUALITY_WINDOW)
+        .await?
+        .into_iter()
+        .find(|quality| quality.score().is_some())
+        .map(|quality| quality.server_id))
+}
+
+/// 获取活动连接
+pub fn get_active_connections() -> Result<Vec<ActiveConnectionInfo>> {
+    let manager = get_core_connection_manager()?;
+    let now = Utc::now();
+    Ok(manager
+        .get_active_connections()
+        .into_iter()
+        .map(|connection| ActiveConnectionInfo {
+            id: connection.id,
+            source: connection.source,
+            network: connection.network,
+            destination: connection.destination,
+            inbound_tag: connection.inbound_tag,
+            outbound_tag: connection.outbound_tag,
+            started_at: connection.started_at.timestamp_millis(),
+            duration_secs: (now - connection.started_at).num_seconds().max(0) as u64,
+        })
+        .collect())
+}
+
+/// 关闭所有连接（重启 Xray）
+pub fn close_all_connections() -> Result<()> {
+    let manager = get_core_connection_manager()?;
+    TOKIO_RUNTIME.block_on(manager.close_all_connections())?;
+    Ok(())
+}
+
+/// 访问日志数据库保留天数
+const ACCESS_LOG_RETENTION_DAYS: i64 = 30;
+
+/// 打开访问日志数据库并清理过期记录
+pub async fn init_access_log_storage(db_path: &str) -> Result<()> {
+    let storage = AccessLogStorage::new(db_path).await?;
+    storage
+        .prune(Utc::now() - chrono::Duration::days(ACCESS_LOG_RETENTION_DAYS))
+        .await?;
+    core_connection_manager()
+        .await
+        .set_access_log_storage(Arc::new(storage))
+        .await;
+    Ok(())
+}
+
+/// 将域名统计转换为 FFI 类型
+fn convert_domain_counts(domains: Vec<DomainCount>) -> Vec<DomainStatInfo> {
+    domains
+        .into_iter()
+        .map(|domain| DomainStatInfo {
+            domain: domain.domain,
+            count: domain.count,
+            last_seen: domain.last_seen.timestamp_millis(),
+        })
+        .collect()
+}
+
+/// 统计起始时间
+fn hours_ago(hours: u32) -> chrono::DateTime<Utc> {
+    Utc::now() - chrono::Duration::hours(i64::from(hours))
+}
+
+/// 获取访问最多的域名
+pub async fn get_top_domains(limit: u32, hours: u32) -> Result<Vec<DomainStatInfo>> {
+    let domains = core_connection_manager()
+        .await
+        .get_top_domains(hours_ago(hours), limit as usize)
+        .await?;
+    Ok(convert_domain_counts(domains))
+}
+
+/// 获取被拦截最多的域名
+pub async fn get_blocked_domains(limit: u32, hours: u32) -> Result<Vec<DomainStatInfo>> {
+    let domains = core_connection_manager()
+        .await
+        .get_blocked_domains(hours_ago(hours), limit as usize)
+        .await?;
+    Ok(convert_domain_counts(domains))
+}
+
+/// 获取各路由的连接数
+pub async fn get_route_split(hours: u32) -> Result<RouteSplitInfo> {
+    let split = core_connection_manager()
+        .await
+        .get_route_split(hours_ago(hours))
+        .await?;
+    Ok(RouteSplitInfo {
+        proxy: split.proxy,
+        direct: split.direct,
+        blocked: split.blocked,
+        other: split.other,
+    })
+}
+
+/// 获取当前会话事件时间线
+pub fn get_session_timeline() -> Result<Vec<SessionEventInfo>> {
+    let manager = get_core_connection_manager()?;
+    Ok(manager
+        .get_timeline()
+        .into_iter()
+        .map(convert_session_event)
+        .collect())
+}
+
+/// 记录会话事件（供其他 Bridge 模块使用）
+pub(crate) fn record_session_event(kind: SessionEventKind, message: String) {
+    match get_core_connection_manager() {
+        Ok(manager) => manager.record_event(kind, message),
+        Err(e) => tracing::debug!("Failed to record session event: {}", e),
+    }
+}
+
+/// 将测速结果转换为 FFI 类型
+fn convert_speed_result(result: SpeedTestResult) -> SpeedTestInfo {
+    SpeedTestInfo {
+        server_id: result.server_id,
+        tested_at: result.tested_at.timestamp_millis(),
+        download_mbps: result.download_mbps,
+        upload_mbps: result.upload_mbps,
+        downloaded_bytes: result.downloaded_bytes,
+        uploaded_bytes: result.uploaded_bytes,
+    }
+}
+
+/// 对当前服务器测速，进度通过事件流发送
+pub async fn run_speed_test(options: SpeedTestOptions) -> Result<SpeedTestInfo> {
+    // 空地址使用默认测速服务器
+    let url_or = |url: Option<String>, default: &str| {
+        url.map(|url| url.trim().to_string())
+            .filter(|url| !url.is_empty())
+            .unwrap_or_else(|| default.to_string())
+    };
+    let config = SpeedTestConfig {
+        download_url: url_or(options.download_url, DEFAULT_DOWNLOAD_URL),
+        upload_url: options
+            .upload
+            .then(|| url_or(options.upload_url, DEFAULT_UPLOAD_URL)),
+        duration: std::time::Duration::from_secs(options.duration_secs.into()),
+    };
+
+    let manager = core_connection_manager().await;
+    let server_id = manager
+        .get_current_config()
+        .await
+        .map(|config| config.id)
+        .unwrap_or_default();
+    let result = manager
+        .run_speed_test(config, |progress| {
+            let _ = super::events::send_event(V8RayEvent::SpeedTestProgress {
+                server_id: server_id.clone(),
+                phase: progress.phase.as_str().to_string(),
+                bytes: progress.bytes,
+                mbps: progress.mbps,
+            });
+        })
+        .await?;
+    Ok(convert_speed_result(result))
+}
+
+/// 获取按测速结果排名的服务器
+pub fn get_speed_ranking() -> Result<Vec<SpeedRankingInfo>> {
+    let manager = get_core_connection_manager()?;
+    Ok(manager
+        .get_speed_ranking()
+        .into_iter()
+        .map(|ranking| SpeedRankingInfo {
+            server_id: ranking.server_id,
+            download_mbps: ranking.download_mbps,
+            upload_mbps: ranking.upload_mbps,
+            tests: ranking.tests as u32,
+            last_tested: ranking.last_tested.timestamp_millis(),
+        })
+        .collect())
+}
+
+/// 获取某服务器最近的测速结果
+pub fn get_speed_test_results(server_id: &str) -> Result<Vec<SpeedTestInfo>> {
+    let manager = get_core_connection_manager()?;
+    Ok(manager
+        .get_speed_results(server_id)
+        .into_iter()
+        .map(convert_speed_result)
+        .collect())
+}
+
+/// 将解锁检测结果转换为 FFI 类型
+fn convert_unlock_result(result: UnlockResult) -> UnlockResultInfo {
+    UnlockResultInfo {
+        service: result.service.as_str().to_string(),
+        status: result.status.as_str().to_string(),
+        region: result.region,
+        detail: result.detail,
+        checked_at: result.checked_at.timestamp_millis(),
+    }
+}
+
+/// 检测当前服务器的流媒体解锁情况
+pub async fn check_unlock(services: Vec<String>, refresh: bool) -> Result<Vec<UnlockResultInfo>> {
+    // 未指定服务时检测全部
+    let services = if services.is_empty() {
+        UnlockService::ALL.to_vec()
+    } else {
+        services
+            .iter()
+            .map(|service| service.parse())
+            .collect::<Result<Vec<UnlockService>, _>>()?
+    };
+    let results = core_connection_manager()
+        .await
+        .check_unlock(&services, refresh)
+        .await?;
+    Ok(results.into_iter().map(convert_unlock_result).collect())
+}
+
+/// 获取某服务器缓存的流媒体解锁结果
+pub fn get_unlock_results(server_id: &str) -> Result<Vec<UnlockResultInfo>> {
+    let manager = get_core_connection_manager()?;
+    Ok(manager
+        .get_unlock_results(server_id)
+        .into_iter()
+        .map(convert_unlock_result)
+        .collect())
+}
+
+/// 列出可用的 IP 信息查询服务
+pub fn list_exit_info_providers() -> Result<Vec<ExitInfoProviderInfo>> {
+    Ok(ExitInfoProvider::ALL
+        .iter()
+        .map(|provider| ExitInfoProviderInfo {
+            id: provider.as_str().to_string(),
+            name: provider.name().to_string(),
+            privacy_note: provider.privacy_note().to_string(),
+        })
+        .collect())
+}
+
+/// 将出口信息转换为 FFI 类型
+fn convert_exit_info(info: ExitInfo) -> ExitIpInfo {
+    ExitIpInfo {
+        ip: info.ip,
+        country: info.country,
+        country_code: info.country_code,
+        city: info.city,
+        asn: info.asn,
+        organization: info.organization,
+        risk_flags: info
+            .risks
+            .iter()
+            .map(|risk| risk.as_str().to_string())
+            .collect(),
+    }
+}
+
+/// 获取当前连接的出口信息
+pub async fn get_exit_info(
+    provider: Option<String>,
+    compare_direct: bool,
+) -> Result<ExitInfoResult> {
+    let provider = match provider.filter(|provider| !provider.trim().is_empty()) {
+        Some(provider) => provider.parse()?,
+        None => ExitInfoProvider::default(),
+    };
+    let report = core_connection_manager()
+        .await
+        .get_exit_info(provider, compare_direct)
+        .await?;
+    let proxy_effective = report.proxy_effective();
+    Ok(ExitInfoResult {
+        provider: report.provider.as_str().to_string(),
+        proxied: convert_exit_info(report.proxied),
+        direct: report.direct.map(convert_exit_info),
+        direct_error: report.direct_error,
+        proxy_effective,
+    })
+}
+
+/// 运行连接诊断（未指定服务器时诊断当前连接）
+pub async fn run_diagnostics(server_id: Option<String>) -> Result<DiagnosticReportInfo> {
+    let manager = core_connection_manager().await;
+    let config = match server_id {
+        Some(server_id) => {
+            convert_to_core_config(&super::subscription::get_server_config(server_id).await?)
+        }
+        None => manager
+            .get_current_config()
+            .await
+            .ok_or_else(|| anyhow!("Not connected"))?,
+    };
+
+    let report = manager.run_diagnostics(config).await;
+    Ok(DiagnosticReportInfo {
+        passed: report.passed(),
+        failed_stage: report
+            .failed_stage()
+            .map(|stage| stage.as_str().to_string()),
+        server_id: report.server_id,
+        checks: report
+            .checks
+            .into_iter()
+            .map(|check| DiagnosticCheckInfo {
+                stage: check.stage.as_str().to_string(),
+                title: check.stage.title().to_string(),
+                status: check.status.as_str().to_string(),
+                message: check.message,
+                duration_ms: check.duration.as_millis() as u64,
+                hint: check.hint,
+            })
+            .collect(),
+    })
+}
+
+/// 获取 Xray 更新器所属的 Xray 管理器，已连接时 proxy 下载源经本地 HTTP 入站下载
+async fn xray_for_update() -> Arc<XrayCore> {
+    let xray = core_connection_manager().await.get_xray();
+    let proxy = xray.running_config().await.and_then(|config| {
+        config
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == "http")
+            .and_then(|inbound| crate::connection::readiness::proxy_for_inbound(inbound).ok())
+    });
+    xray.get_updater().set_active_proxy(proxy);
+    xray
+}
+
+/// 获取 Xray Core 更新下载设置
+pub async fn get_xray_download_settings() -> XrayDownloadSettingsInfo {
+    let settings = core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .download_settings();
+    XrayDownloadSettingsInfo {
+        sources: settings
+            .sources
+            .into_iter()
+            .map(|source| {
+                let (source_type, url) = match source {
+                    DownloadSource::Github => ("github", None),
+                    DownloadSource::Proxy => ("proxy", None),
+                    DownloadSource::Mirror { prefix } => ("mirror", Some(prefix)),
+                    DownloadSource::CustomBase { base_url } => ("custom_base", Some(base_url)),
+                };
+                XrayDownloadSourceInfo {
+                    source_type: source_type.to_string(),
+                    url,
+                }
+            })
+            .collect(),
+        proxy_url: settings.proxy_url,
+    }
+}
+
+/// 设置 Xray Core 更新下载源
+pub async fn set_xray_download_settings(settings: XrayDownloadSettingsInfo) -> Result<()> {
+    let sources = settings
+        .sources
+        .into_iter()
+        .map(|source| {
+            let url = source.url.filter(|url| !url.trim().is_empty());
+            let url = || {
+                url.clone()
+                    .ok_or_else(|| anyhow!("Download source {} needs a URL", source.source_type))
+            };
+            Ok(match source.source_type.as_str() {
+                "github" => DownloadSource::Github,
+                "proxy" => DownloadSource::Proxy,
+                "mirror" => DownloadSource::Mirror { prefix: url()? },
+                "custom_base" => DownloadSource::CustomBase { base_url: url()? },
+                other => return Err(anyhow!("Unknown download source: {}", other)),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .set_download_settings(DownloadSettings {
+            sources,
+            proxy_url: settings.proxy_url.filter(|url| !url.trim().is_empty()),
+        })?;
+    Ok(())
+}
+
+/// 设置 Xray Core 发布版本的 minisign 公钥
+pub async fn set_xray_release_public_key(public_key: Option<String>) -> Result<()> {
+    let public_key = public_key.filter(|key| !key.trim().is_empty());
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .set_minisign_public_key(public_key.as_deref())?;
+    Ok(())
+}
+
+/// 获取 Xray Core 发布版本的 minisign 公钥
+pub async fn get_xray_release_public_key() -> Option<String> {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .minisign_public_key()
+        .map(|key| key.encode())
+}
+
+/// 首次下载 Xray Core 时发送进度事件的间隔
+const BOOTSTRAP_PROGRESS_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// sing-box 版本，未安装时返回错误
+pub async fn get_sing_box_version() -> Result<String> {
+    Ok(core_connection_manager()
+        .await
+        .get_sing_box()
+        .version()
+        .await?)
+}
+
+/// 是否有可运行的 Xray Core
+pub async fn is_xray_core_available() -> bool {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .binary_available()
+}
+
+/// 首次运行时下载安装最新版 Xray Core，之后连接到 `server_id`
+pub async fn bootstrap_xray_core(server_id: Option<String>) -> Result<String> {
+    let xray = xray_for_update().await;
+    let updater = xray.get_updater();
+
+    // 下载期间定期发送进度
+    let done = CancellationToken::new();
+    let reporter = tokio::spawn({
+        let done = done.clone();
+        let xray = Arc::clone(&xray);
+        async move {
+            let mut interval = tokio::time::interval(BOOTSTRAP_PROGRESS_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = done.cancelled() => break,
+                }
+                let updater = xray.get_updater();
+                let Some(version) = updater.installing_version() else {
+                    continue;
+                };
+                let progress = updater.get_progress().await;
+                let _ = super::events::send_event(V8RayEvent::XrayCoreDownloadProgress {
+                    version,
+                    progress,
+                });
+            }
+        }
+    });
+    let result = updater.bootstrap().await;
+    done.cancel();
+    let _ = reporter.await;
+    let version = result.context("Failed to install Xray Core")?;
+    let _ = super::events::send_event(V8RayEvent::XrayCoreDownloadProgress {
+        version: version.clone(),
+        progress: 1.0,
+    });
+
+    if let Some(server_id) = server_id {
+        connect_async(&server_id).await?;
+    }
+    Ok(version)
+}
+
+/// 检查 Xray Core 更新
+pub async fn check_xray_core_update() -> Result<XrayCoreUpdateInfo> {
+    let xray = xray_for_update().await;
+    let updater = xray.get_updater();
+    let update_info = updater
+        .check_update()
+        .await
+        .context("Failed to check update")?;
+
+    Ok(XrayCoreUpdateInfo {
+        has_update: update_info.has_update,
+        current_version: update_info.current_version,
+        latest_version: update_info.latest_version,
+        download_url: update_info.download_url,
+        file_size: update_info.file_size,
+    })
+}
+
+/// 下载并安装 Xray Core 更新
+pub async fn update_xray_core(version: String) -> Result<()> {
+    let xray = xray_for_update().await;
+    let updater = xray.get_updater();
+    updater
+        .update(&version)
+        .await
+        .context("Failed to update Xray Core")?;
+
+    Ok(())
+}
+
+/// 列出 Xray Core 发布版本，标记已安装的版本
+pub async fn list_xray_core_releases(limit: u32) -> Result<Vec<XrayReleaseInfo>> {
+    let xray = xray_for_update().await;
+    let updater = xray.get_updater();
+    let releases = updater
+        .fetch_releases(limit as usize)
+        .await
+        .context("Failed to fetch Xray Core releases")?;
+
+    Ok(releases
+        .into_iter()
+        .map(|release| XrayReleaseInfo {
+            installed: updater.versions().is_installed(&release.version),
+            version: release.version,
+            published_at: release.published_at,
+            prerelease: release.prerelease,
+        })
+        .collect())
+}
+
+/// 列出已安装的 Xray Core 版本
+pub async fn list_installed_xray_versions() -> InstalledXrayVersionsInfo {
+    let xray = core_connection_manager().await.get_xray();
+    let versions = xray.get_updater().versions();
+    let selection = versions.selection();
+    InstalledXrayVersionsInfo {
+        versions: versions.installed(),
+        active: selection.active,
+        previous: selection.previous,
+    }
+}
+
+/// 安装指定版本的 Xray Core
+pub async fn install_xray_version(version: String) -> Result<()> {
+    xray_for_update()
+        .await
+        .get_updater()
+        .install_version(&version)
+        .await
+        .with_context(|| format!("Failed to install Xray Core {}", version))
+}
+
+/// 更新 geoip/geosite 数据文件
+pub async fn update_geo_assets() -> Result<()> {
+    xray_for_update()
+        .await
+        .update_geo_assets()
+        .await
+        .context("Failed to update geo data files")
+}
+
+/// 选择运行的 Xray Core 版本
+pub async fn select_xray_version(version: Option<String>) -> Result<()> {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .select_version(version.as_deref())
+        .await?;
+    Ok(())
+}
+
+/// 回滚到上一个选中的 Xray Core 版本
+pub async fn rollback_xray_core() -> Result<Option<String>> {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .rollback()
+        .await
+        .context("Failed to roll back Xray Core")
+}
+
+/// 删除已安装的 Xray Core 版本
+pub async fn remove_xray_version(version: String) -> Result<()> {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .versions()
+        .remove(&version)
+        .await
+        .with_context(|| format!("Failed to remove Xray Core {}", version))
+}
+
+/// 获取 Xray Core 下载进度
+pub async fn get_xray_core_update_progress() -> Result<f64> {
+    let manager = core_connection_manager().await;
+    let xray = manager.get_xray();
+    let updater = xray.get_updater();
+    Ok(updater.get_progress().await)
+}
+
+/// 获取 Xray Core 下载缓存大小
+pub async fn get_xray_download_cache_size() -> Result<u64> {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .cache()
+        .total_size()
+        .await
+        .context("Failed to get cache size")
+}
+
+/// 清空 Xray Core 下载缓存
+pub async fn purge_xray_download_cache() -> Result<u64> {
+    core_connection_manager()
+        .await
+        .get_xray()
+        .get_updater()
+        .purge_cache()
+        .await
+        .context("Failed to purge download cache")
+}
+
 /// 测试延迟
 pub fn test_latency(config_id: &str) -> Result<u32> {
-    let manager = CONNECTION_MANAGER.blocking_read();
-    manager.test_latency(config_id)
+    TOKIO_RUNTIME.block_on(test_latency_async(config_id))
+}
+
+/// 测试服务器的 TCP 连接延迟，结果记入延迟历史（若已打开）
+pub async fn test_latency_async(config_id: &str) -> Result<u32> {
+    let cached = CONNECTION_MANAGER
+        .read()
+        .await
+        .config_cache
+        .get(config_id)
+        .cloned();
+    let config = match cached {
+        Some(config) => config,
+        None => super::subscription::get_server_config(config_id.to_string())
+            .await
+            .map_err(|_| anyhow!("Config not found: {}", config_id))?,
+    };
+    let target = ProbeTarget {
+        tag: config_id.to_string(),
+        server: config.address,
+        port: config.port,
+    };
+    let result = probe_outbounds(&[target], DEFAULT_PROBE_TIMEOUT)
+        .await
+        .pop()
+        .ok_or_else(|| anyhow!("Latency test of {} returned no result", config_id))?;
+
+    if let Some(history) = core_connection_manager().await.get_latency_history().await {
+        if let Err(e) = history
+            .record(config_id, Utc::now(), result.latency_ms)
+            .await
+        {
+            tracing::warn!("Failed to record latency of {}: {}", config_id, e);
+        }
+    }
+    match result.latency_ms {
+        Some(latency_ms) => Ok(latency_ms.min(u32::MAX as u64) as u32),
+        None => Err(anyhow!(
+            "{} is unreachable: {}",
+            config_id,
+            result.error.unwrap_or_default()
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -275,11 +1976,263 @@ mod tests {
         assert_eq!(info.status, ConnectionStatus::Disconnected);
     }
 
+    #[test]
+    fn test_memory_limit_overflow() {
+        let limits = XrayResourceLimitsInfo {
+            priority: "normal".to_string(),
+            cpu_affinity: vec![],
+            memory_limit_mb: Some(u64::MAX),
+        };
+        assert!(set_xray_resource_limits(limits).is_err());
+    }
+
+    #[test]
+    #[serial]
+    fn test_get_aggregated_health() {
+        let health = get_aggregated_health().unwrap();
+        // 未连接时应为 stopped
+        assert_eq!(health.summary, "stopped");
+        assert_eq!(health.instances.len(), 1);
+        assert_eq!(health.instances[0].status, "stopped");
+    }
+
+    #[test]
+    #[serial]
+    fn test_engine_log_level() {
+        assert_eq!(get_engine_log_level().unwrap(), "warning");
+
+        // 未连接时只更新级别，下次启动生效
+        set_engine_log_level("debug").unwrap();
+        assert_eq!(get_engine_log_level().unwrap(), "debug");
+        assert!(set_engine_log_level("verbose").is_err());
+        assert_eq!(get_engine_log_level().unwrap(), "debug");
+
+        set_engine_log_level("warning").unwrap();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_inbound_settings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let xray = core_connection_manager().await.get_xray();
+        let auth = InboundAuthInfo {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        };
+        let mut info = get_inbound_settings().await;
+        assert!(!info.allow_lan);
+        info.allow_lan = true;
+        info.http_auth = Some(auth.clone());
+        // 共享到局域网需要两个入站都设置认证
+        assert!(set_inbound_settings(info.clone()).await.is_err());
+        info.socks_auth = Some(auth);
+        info.udp = true;
+        set_inbound_settings(info.clone()).await.unwrap();
+        assert_eq!(get_inbound_settings().await, info);
+        assert!(xray.inbound_settings().allow_lan);
+
+        // 重新加载设置后应用到 Xray
+        xray.set_inbound_settings(InboundSettings::default());
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert!(xray.inbound_settings().allow_lan);
+        assert!(xray.inbound_settings().udp);
+
+        super::super::settings::reset_settings().await;
+        xray.set_inbound_settings(InboundSettings::default());
+    }
+
+    #[test]
+    #[serial]
+    fn test_get_session_timeline() {
+        record_session_event(SessionEventKind::ProxyApplied, "test".to_string());
+        let timeline = get_session_timeline().unwrap();
+        let last = timeline.last().unwrap();
+        assert_eq!(last.kind, "proxy_applied");
+        assert_eq!(last.message, "test");
+    }
+
+    #[test]
+    fn test_connect_balanced_rejects_invalid_strategy() {
+        let result = TOKIO_RUNTIME.block_on(connect_balanced(
+            uuid::Uuid::new_v4().to_string(),
+            "fastest",
+        ));
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid balancer strategy"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_set_port_conflict_policy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let xray = core_connection_manager().await.get_xray();
+        assert!(set_port_conflict_policy("reassign").await.is_ok());
+        assert!(set_port_conflict_policy("ignore").await.is_err());
+        assert_eq!(xray.port_conflict_policy(), PortConflictPolicy::Reassign);
+
+        // 策略和重新分配的端口保存在设置中，重新加载后应用到 Xray
+        save_port_reassignment(&PortReassignment {
+            protocol: "http".to_string(),
+            old_port: 8080,
+            new_port: 18080,
+        })
+        .await;
+        xray.set_port_conflict_policy(PortConflictPolicy::Fail);
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(xray.port_conflict_policy(), PortConflictPolicy::Reassign);
+        assert_eq!(xray.inbound_ports(), (18080, 1080));
+
+        assert!(set_port_conflict_policy("fail").await.is_ok());
+        super::super::settings::reset_settings().await;
+        xray.set_inbound_ports(8080, 1080);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_xray_download_settings() {
+        let settings = XrayDownloadSettingsInfo {
+            sources: vec![
+                XrayDownloadSourceInfo {
+                    source_type: "mirror".to_string(),
+                    url: Some("https://ghproxy.net/".to_string()),
+                },
+                XrayDownloadSourceInfo {
+                    source_type: "github".to_string(),
+                    url: None,
+                },
+            ],
+            proxy_url: Some(" ".to_string()),
+        };
+        set_xray_download_settings(settings).await.unwrap();
+        let settings = get_xray_download_settings().await;
+        assert_eq!(settings.sources.len(), 2);
+        assert_eq!(settings.sources[0].source_type, "mirror");
+        assert_eq!(settings.proxy_url, None);
+
+        for (source_type, url) in [("mirror", None), ("ftp", None), ("custom_base", Some("x"))] {
+            let invalid = XrayDownloadSettingsInfo {
+                sources: vec![XrayDownloadSourceInfo {
+                    source_type: source_type.to_string(),
+                    url: url.map(str::to_string),
+                }],
+                proxy_url: None,
+            };
+            assert!(set_xray_download_settings(invalid).await.is_err());
+        }
+
+        set_xray_download_settings(XrayDownloadSettingsInfo {
+            sources: vec![
+                XrayDownloadSourceInfo {
+                    source_type: "github".to_string(),
+                    url: None,
+                },
+                XrayDownloadSourceInfo {
+                    source_type: "proxy".to_string(),
+                    url: None,
+                },
+            ],
+            proxy_url: None,
+        })
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_xray_resource_limits() {
+        let limits = XrayResourceLimitsInfo {
+            priority: "below_normal".to_string(),
+            cpu_affinity: vec![0],
+            memory_limit_mb: Some(256),
+        };
+        set_xray_resource_limits(limits).unwrap();
+        let limits = get_xray_resource_limits().unwrap();
+        assert_eq!(limits.priority, "below_normal");
+        assert_eq!(limits.cpu_affinity, vec![0]);
+        assert_eq!(limits.memory_limit_mb, Some(256));
+
+        let invalid = XrayResourceLimitsInfo {
+            priority: "realtime".to_string(),
+            ..limits.clone()
+        };
+        assert!(set_xray_resource_limits(invalid).is_err());
+        let invalid = XrayResourceLimitsInfo {
+            memory_limit_mb: Some(1),
+            ..limits
+        };
+        assert!(set_xray_resource_limits(invalid).is_err());
+
+        set_xray_resource_limits(XrayResourceLimitsInfo {
+            priority: "normal".to_string(),
+            cpu_affinity: Vec::new(),
+            memory_limit_mb: None,
+        })
+        .unwrap();
+    }
+
+    #[test]
+    #[serial]
+    fn test_health_probe_config() {
+        let original = get_health_probe_config().unwrap();
+        assert_eq!(original.failure_threshold, 3);
+
+        let mut config = original.clone();
+        config.interval_secs = 10;
+        set_health_probe_config(config.clone()).unwrap();
+        assert_eq!(get_health_probe_config().unwrap().interval_secs, 10);
+
+        config.failure_threshold = 0;
+        assert!(set_health_probe_config(config).is_err());
+        assert!(!get_health_probe_status().unwrap().degraded);
+
+        // The probe config is global, leave it as other tests expect
+        set_health_probe_config(original.clone()).unwrap();
+        assert_eq!(
+            get_health_probe_config().unwrap().interval_secs,
+            original.interval_secs
+        );
+    }
+
     #[test]
     #[serial]
     fn test_test_latency() {
-        let config_id = "test-config";
-        let latency = test_latency(config_id).unwrap();
-        assert!(latency > 0);
+        // Unknown servers cannot be tested
+        assert!(test_latency("test-config").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_best_ranked_server() {
+        let history = LatencyHistory::new_in_memory().await.unwrap();
+        let ids = vec!["spiky".to_string(), "stable".to_string(), "new".to_string()];
+        assert_eq!(best_ranked_server(&history, &ids).await.unwrap(), None);
+
+        let now = Utc::now();
+        for latency_ms in [20, 300, 25, 350] {
+            history
+                .record("spiky", now, Some(latency_ms))
+                .await
+                .unwrap();
+        }
+        for latency_ms in [80, 85, 82, 84] {
+            history
+                .record("stable", now, Some(latency_ms))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            best_ranked_server(&history, &ids).await.unwrap().as_deref(),
+            Some("stable")
+        );
     }
 }
@@ -0,0 +1,117 @@
+//! 本地控制接口 Bridge 模块（访问令牌、单实例锁）
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use super::api::V8RayEvent;
+use crate::control::{notify_running_instance, AccessTokenStore, InstanceLock, TOKEN_FILE_NAME};
+
+lazy_static::lazy_static! {
+    static ref ACCESS_TOKEN: Arc<RwLock<Option<Arc<AccessTokenStore>>>> = Arc::new(RwLock::new(None));
+    static ref INSTANCE_LOCK: std::sync::Mutex<Option<InstanceLock>> = std::sync::Mutex::new(None);
+}
+
+/// 加载或生成访问令牌
+pub fn init_access_token(data_dir: &str) -> Result<String> {
+    let store = AccessTokenStore::load_or_create(Path::new(data_dir).join(TOKEN_FILE_NAME))?;
+    let token = store.token();
+    *ACCESS_TOKEN.blocking_write() = Some(Arc::new(store));
+    Ok(token)
+}
+
+/// 获取单实例锁
+///
+/// 获取成功后监听后续实例发来的消息，并以 `InstanceActivated` 事件转发；
+/// 锁已被其他实例持有时，将 `message` 发送给该实例。
+/// 返回 true 表示当前进程是唯一实例，false 表示已通知正在运行的实例
+pub async fn try_acquire_instance_lock(data_dir: &str, message: &str) -> Result<bool> {
+    if INSTANCE_LOCK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some()
+    {
+        return Ok(true);
+    }
+
+    let token = {
+        let mut current = ACCESS_TOKEN.write().await;
+        match current.as_ref() {
+            Some(store) => Arc::clone(store),
+            None => {
+                let store = Arc::new(AccessTokenStore::load_or_create(
+                    Path::new(data_dir).join(TOKEN_FILE_NAME),
+                )?);
+                *current = Some(Arc::clone(&store));
+                store
+            }
+        }
+    };
+
+    let Some(mut lock) = InstanceLock::try_acquire(data_dir)? else {
+        tracing::info!("Another instance is running, sending: {}", message);
+        notify_running_instance(data_dir, &token.token(), message).await?;
+        return Ok(false);
+    };
+    lock.listen(token, |message| {
+        tracing::info!("Message from another instance: {}", message);
+        let _ = super::events::send_event(V8RayEvent::InstanceActivated { message });
+    })
+    .await?;
+    *INSTANCE_LOCK.lock().unwrap_or_else(|e| e.into_inner()) = Some(lock);
+    Ok(true)
+}
+
+/// 获取已加载的访问令牌（内部使用）
+#[cfg(feature = "control-api")]
+pub(crate) async fn access_token_store() -> Result<Arc<AccessTokenStore>> {
+    ACCESS_TOKEN
+        .read()
+        .await
+        .clone()
+        .ok_or_else(|| anyhow!("Access token not initialized"))
+}
+
+/// 轮换访问令牌
+pub fn rotate_access_token() -> Result<String> {
+    let store = ACCESS_TOKEN.blocking_read();
+    let store = store
+        .as_ref()
+        .ok_or_else(|| anyhow!("Access token not initialized"))?;
+    Ok(store.rotate()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[test]
+    #[serial]
+    fn test_init_and_rotate() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+
+        let token = init_access_token(data_dir).unwrap();
+        assert_eq!(init_access_token(data_dir).unwrap(), token);
+
+        let rotated = rotate_access_token().unwrap();
+        assert_ne!(rotated, token);
+        assert_eq!(init_access_token(data_dir).unwrap(), rotated);
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_instance_lock() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+
+        assert!(try_acquire_instance_lock(data_dir, "show").await.unwrap());
+        // 已持有锁时再次调用直接成功
+        assert!(try_acquire_instance_lock(data_dir, "show").await.unwrap());
+
+        *INSTANCE_LOCK.lock().unwrap() = None;
+        *ACCESS_TOKEN.write().await = None;
+    }
+}
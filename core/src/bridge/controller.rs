@@ -0,0 +1,280 @@
+//! 本地 REST/WebSocket 控制接口 Bridge 模块
+//!
+//! 在回环地址上提供与 Bridge 函数对应的 HTTP 接口，供脚本、浏览器扩展等
+//! 第三方工具控制核心。请求需携带访问令牌（见 [`super::control`]）。
+//!
+//! | 方法 | 路径 | 说明 |
+//! |------|------|------|
+//! | GET | `/v1/status` | 连接信息 |
+//! | GET | `/v1/stats` | 流量与 Xray 进程占用 |
+//! | POST | `/v1/connect` | 连接服务器，请求体 `{"server_id": "..."}` |
+//! | POST | `/v1/disconnect` | 断开连接 |
+//! | GET | `/v1/servers` | 服务器列表 |
+//! | GET | `/v1/subscriptions` | 订阅列表 |
+//! | GET | `/v1/events` | WebSocket 事件流 |
+//! | GET | `/metrics` | Prometheus 指标 |
+
+use anyhow::{anyhow, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use super::api::{ConnectionInfo, ConnectionStatus, XrayProcessInfo};
+use crate::control::server::{
+    ControlHandler, ControlRequest, ControlResponse, ControlServer, EventSource,
+};
+use crate::metrics::MetricsSnapshot;
+
+lazy_static::lazy_static! {
+    static ref CONTROL_SERVER: Mutex<Option<ControlServer>> = Mutex::new(None);
+}
+
+/// 连接请求体
+#[derive(Debug, Deserialize)]
+struct ConnectRequest {
+    /// 服务器 ID
+    server_id: String,
+}
+
+/// 统计信息
+#[derive(Debug, Serialize)]
+struct StatsResponse {
+    /// 上传流量（字节）
+    upload_bytes: u64,
+    /// 下载流量（字节）
+    download_bytes: u64,
+    /// 连接时长（秒）
+    duration: u64,
+    /// Xray 进程状态
+    process: Option<XrayProcessInfo>,
+}
+
+/// 启动控制接口，返回实际监听的端口（`port` 为 0 时自动选择）
+///
+/// 需要先调用 `init_access_token`；已在运行时先停止旧的服务
+pub async fn start_control_server(port: u16) -> Result<u16> {
+    let token = super::control::access_token_store().await?;
+    let handler: ControlHandler = Arc::new(|request| Box::pin(handle_request(request)));
+    let events: EventSource = Arc::new(|| {
+        super::events::create_event_stream()
+            .filter_map(|event| async move { serde_json::to_string(&event).ok() })
+            .boxed()
+    });
+
+    stop_control_server();
+    let server = ControlServer::start(
+        SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+        token,
+        handler,
+        events,
+    )
+    .await?;
+    let port = server.addr().port();
+    *CONTROL_SERVER.lock().unwrap_or_else(|e| e.into_inner()) = Some(server);
+    Ok(port)
+}
+
+/// 停止控制接口
+pub fn stop_control_server() {
+    if let Some(server) = CONTROL_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        server.stop();
+    }
+}
+
+/// 控制接口是否在运行
+pub fn is_control_server_running() -> bool {
+    CONTROL_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some()
+}
+
+/// 当前监听的端口
+pub fn control_server_port() -> Result<u16> {
+    CONTROL_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|server| server.addr().port())
+        .ok_or_else(|| anyhow!("Control API not running"))
+}
+
+/// 分发请求
+async fn handle_request(request: ControlRequest) -> ControlResponse {
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/v1/status") => Ok(ControlResponse::ok(
+            super::connection::connection_info().await,
+        )),
+        ("GET", "/v1/stats") => Ok(ControlResponse::ok(stats().await)),
+        ("POST", "/v1/connect") => connect(&request.body).await,
+        ("POST", "/v1/disconnect") => super::connection::disconnect_async()
+            .await
+            .map(|_| ControlResponse::ok(serde_json::json!({}))),
+        ("GET", "/v1/servers") => super::subscription::get_servers()
+            .await
+            .map(ControlResponse::ok),
+        ("GET", "/v1/subscriptions") => super::subscription::get_subscriptions()
+            .await
+            .map(ControlResponse::ok),
+        ("GET", "/metrics") => Ok(ControlResponse::text(
+            crate::metrics::CONTENT_TYPE,
+            crate::metrics::render(&metrics().await),
+        )),
+        (
+            _,
+            "/v1/status" | "/v1/stats" | "/v1/connect" | "/v1/disconnect" | "/v1/servers"
+            | "/v1/subscriptions" | "/metrics",
+        ) => Ok(ControlResponse::error(405, "Method not allowed")),
+        _ => Ok(ControlResponse::error(404, "Not found")),
+    };
+
+    result.unwrap_or_else(|e| {
+        tracing::warn!(
+            "Control request {} {} failed: {}",
+            request.method,
+            request.path,
+            e
+        );
+        ControlResponse::error(500, e)
+    })
+}
+
+/// 连接请求体中的服务器
+async fn connect(body: &[u8]) -> Result<ControlResponse> {
+    let request: ConnectRequest = match serde_json::from_slice(body) {
+        Ok(request) => request,
+        Err(e) => return Ok(ControlResponse::error(400, e)),
+    };
+    super::connection::connect_server(&request.server_id, None).await?;
+    Ok(ControlResponse::ok(
+        super::connection::connection_info().await,
+    ))
+}
+
+/// 汇总流量与进程占用
+async fn stats() -> StatsResponse {
+    let ConnectionInfo {
+        upload_bytes,
+        download_bytes,
+        duration,
+        ..
+    } = super::connection::connection_info().await;
+    StatsResponse {
+        upload_bytes,
+        download_bytes,
+        duration,
+        process: super::connection::xray_process_info().await,
+    }
+}
+
+/// 采集 Prometheus 指标
+async fn metrics() -> MetricsSnapshot {
+    let info = super::connection::connection_info().await;
+    let manager = super::connection::core_connection_manager().await;
+    let (upload_speed, download_speed) = manager.get_traffic_speeds().await;
+    let connected = info.status == ConnectionStatus::Connected;
+    MetricsSnapshot {
+        connected,
+        upload_bytes: info.upload_bytes,
+        download_bytes: info.download_bytes,
+        upload_speed,
+        download_speed,
+        latency_ms: connected
+            .then(|| manager.get_health_probe_state().last_latency_ms)
+            .flatten(),
+        counters: crate::metrics::counters(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    async fn test_control_server_routes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap().to_string();
+        let token = tokio::task::spawn_blocking(move || {
+            super::super::control::init_access_token(&data_dir)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+        let port = start_control_server(0).await.unwrap();
+        assert!(is_control_server_running());
+        assert_eq!(control_server_port().unwrap(), port);
+
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let url = |path: &str| format!("http://127.0.0.1:{}{}", port, path);
+
+        let response = client.get(url("/v1/status")).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = client
+            .get(url("/v1/status"))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let info: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(info["status"], "Disconnected");
+
+        let response = client
+            .get(url("/v1/stats"))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+
+        let response = client
+            .post(url("/v1/connect"))
+            .bearer_auth(&token)
+            .body("not json")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+
+        let response = client
+            .get(url("/v1/connect"))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 405);
+
+        let response = client
+            .get(url("/metrics"))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        assert!(response
+            .text()
+            .await
+            .unwrap()
+            .contains("\nv8ray_connected 0\n"));
+
+        let response = client
+            .get(url("/v1/unknown"))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        stop_control_server();
+        assert!(!is_control_server_running());
+    }
+}
@@ -0,0 +1,78 @@
+//! 错误码 Bridge 模块
+//!
+//! API 返回的错误消息为单行 JSON（错误码、分类、本地化消息键和原始消息），
+//! Flutter 端通过 `parse_error` 解析后按错误码处理，无需匹配错误文本。
+
+use crate::error::{ErrorInfo, V8RayErrorCode};
+use std::fmt;
+
+/// 带错误码的错误，显示为 JSON
+#[derive(Debug)]
+pub(crate) struct CodedError(ErrorInfo);
+
+impl fmt::Display for CodedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match serde_json::to_string(&self.0) {
+            Ok(json) => f.write_str(&json),
+            Err(_) => f.write_str(&self.0.detail),
+        }
+    }
+}
+
+impl std::error::Error for CodedError {}
+
+/// 将错误转换为带错误码的错误，已转换的错误保持不变
+pub(crate) fn coded(error: anyhow::Error) -> anyhow::Error {
+    if error.is::<CodedError>() {
+        return error;
+    }
+    CodedError(ErrorInfo::from_anyhow(&error)).into()
+}
+
+/// 为字符串错误加上错误码
+pub(crate) fn coded_message(code: V8RayErrorCode, message: String) -> String {
+    CodedError(ErrorInfo::new(code, message)).to_string()
+}
+
+/// 解析 API 返回的错误消息
+///
+/// 不是带错误码的消息时返回 `Unknown` 错误码，原消息作为详情
+pub(crate) fn parse(message: &str) -> ErrorInfo {
+    // FRB 以 Debug 格式输出 anyhow 错误，JSON 位于第一行
+    let first_line = message.lines().next().unwrap_or_default().trim();
+    serde_json::from_str(first_line)
+        .unwrap_or_else(|_| ErrorInfo::new(V8RayErrorCode::Unknown, message.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{ConnectionError, ErrorCategory, V8RayError};
+
+    #[test]
+    fn test_coded_round_trip() {
+        let error = coded(anyhow::Error::from(V8RayError::from(
+            ConnectionError::NotConnected,
+        )));
+        // 重复转换不改变错误
+        let error = coded(error);
+
+        let info = parse(&format!("{:?}", error));
+        assert_eq!(info.code, V8RayErrorCode::NotConnected.code());
+        assert_eq!(info.category, ErrorCategory::Connection);
+        assert_eq!(info.message_key, "error.connection.not_connected");
+        assert_eq!(info.detail, "Connection error: Not connected");
+    }
+
+    #[test]
+    fn test_parse_plain_message() {
+        let info = parse("Server not found");
+        assert_eq!(info.code, V8RayErrorCode::Unknown.code());
+        assert_eq!(info.detail, "Server not found");
+
+        let message = coded_message(V8RayErrorCode::SystemProxy, "gsettings failed".into());
+        let info = parse(&message);
+        assert_eq!(info.code, 5003);
+        assert_eq!(info.detail, "gsettings failed");
+    }
+}
@@ -0,0 +1,332 @@
+//! GFWList 规则 Bridge 模块
+//!
+//! 启用后智能模式按 GFWList 分流（列表中的域名走代理，其余直连），代替 geosite/geoip 规则；
+//! 用户规则仍然优先。列表按设置的间隔定期下载，并缓存在数据目录中，离线时使用缓存。
+//!
+//! 设置保存在设置中。规则有变化时，按智能模式连接的当前连接立即重新应用路由
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use super::api::{GfwListSettingsInfo, GfwListStatusInfo};
+use super::settings::{save_settings, settings};
+use crate::config::{GfwList, GfwListSettings};
+use crate::subscription::SubscriptionHttpClient;
+
+/// 缓存文件名
+const CACHE_FILE_NAME: &str = "gfwlist.txt";
+
+/// 检查是否需要更新的间隔
+const UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// 更新失败后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// 已加载的列表和更新状态
+#[derive(Default)]
+struct GfwListState {
+    cache_path: Option<PathBuf>,
+    list: Option<GfwList>,
+    updated_at: Option<DateTime<Utc>>,
+    last_attempt: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+lazy_static::lazy_static! {
+    static ref STATE: RwLock<GfwListState> = RwLock::new(GfwListState::default());
+    static ref UPDATE_TASK: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
+}
+
+/// 设置中保存的 GFWList 设置
+async fn gfwlist_settings() -> GfwListSettings {
+    settings().await.get_config().await.gfwlist
+}
+
+/// 将列表同步到 Xray 配置生成器
+///
+/// 规则有变化且按智能模式连接时重新连接当前服务器，否则下次连接时生效
+async fn apply() {
+    let list = gfwlist_settings()
+        .await
+        .enabled
+        .then(|| STATE.read().unwrap_or_else(|e| e.into_inner()).list.clone())
+        .flatten();
+    let changed = super::connection::core_connection_manager()
+        .await
+        .get_xray()
+        .set_gfwlist(list);
+    if changed && super::connection::current_proxy_mode().await == "smart" {
+        if let Err(e) = super::connection::reconnect_current().await {
+            tracing::warn!("Failed to apply GFWList to the current connection: {}", e);
+        }
+    }
+}
+
+/// 加载数据目录中缓存的列表
+///
+/// 应在加载设置之后调用。缓存不存在时不报错，已启用时开始定期更新
+pub async fn init_gfwlist(data_dir: &str) -> Result<()> {
+    let cache_path = Path::new(data_dir).join(CACHE_FILE_NAME);
+    let cached = match std::fs::read_to_string(&cache_path) {
+        Ok(content) => {
+            let list = GfwList::parse(&content)?;
+            let updated_at = std::fs::metadata(&cache_path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(DateTime::<Utc>::from);
+            Some((list, updated_at))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    {
+        let mut state = STATE.write().unwrap_or_else(|e| e.into_inner());
+        state.cache_path = Some(cache_path);
+        if let Some((list, updated_at)) = cached {
+            state.list = Some(list);
+            state.updated_at = updated_at;
+        }
+    }
+    apply().await;
+    if gfwlist_settings().await.enabled {
+        start_auto_update();
+    }
+    Ok(())
+}
+
+/// 获取 GFWList 设置
+pub async fn get_gfwlist_settings() -> GfwListSettingsInfo {
+    let settings = gfwlist_settings().await;
+    GfwListSettingsInfo {
+        enabled: settings.enabled,
+        url: settings.url,
+        update_interval_hours: settings.update_interval_hours,
+    }
+}
+
+/// 修改 GFWList 设置
+///
+/// 启用时开始定期更新，禁用时停止；URL 改变后重新下载
+pub async fn set_gfwlist_settings(settings: GfwListSettingsInfo) -> Result<()> {
+    let settings = GfwListSettings {
+        enabled: settings.enabled,
+        url: settings.url.trim().to_string(),
+        update_interval_hours: settings.update_interval_hours,
+    };
+    settings.validate()?;
+
+    let url_changed = gfwlist_settings().await.url != settings.url;
+    let enabled = settings.enabled;
+    self::settings()
+        .await
+        .update_config(|config| config.gfwlist = settings)
+        .await?;
+    save_settings().await?;
+    if url_changed {
+        let mut state = STATE.write().unwrap_or_else(|e| e.into_inner());
+        state.updated_at = None;
+        state.last_attempt = None;
+    }
+
+    apply().await;
+    if enabled {
+        start_auto_update();
+    } else {
+        stop_auto_update();
+    }
+    Ok(())
+}
+
+/// 获取 GFWList 更新状态
+pub fn get_gfwlist_status() -> GfwListStatusInfo {
+    let state = STATE.read().unwrap_or_else(|e| e.into_inner());
+    let list = state.list.as_ref();
+    GfwListStatusInfo {
+        loaded: list.is_some(),
+        proxy_rules: list.map_or(0, |l| (l.proxy_domains.len() + l.proxy_ips.len()) as u32),
+        direct_rules: list.map_or(0, |l| (l.direct_domains.len() + l.direct_ips.len()) as u32),
+        skipped_rules: list.map_or(0, |l| l.skipped as u32),
+        updated_at: state.updated_at.map(|t| t.timestamp()),
+        last_error: state.last_error.clone(),
+    }
+}
+
+/// 立即下载并应用 GFWList
+///
+/// 下载按订阅的重试和线路设置进行；成功后写入缓存
+pub async fn update_gfwlist() -> Result<GfwListStatusInfo> {
+    let url = gfwlist_settings().await.url;
+    STATE
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .last_attempt = Some(Utc::now());
+
+    let result = download(&url).await;
+    let cache_path = {
+        let mut state = STATE.write().unwrap_or_else(|e| e.into_inner());
+        match &result {
+            Ok((_, list)) => {
+                state.list = Some(list.clone());
+                state.updated_at = Some(Utc::now());
+                state.last_error = None;
+            }
+            Err(e) => state.last_error = Some(e.to_string()),
+        }
+        state.cache_path.clone()
+    };
+    let (content, list) = result?;
+    tracing::info!(
+        "GFWList updated: {} rules, {} skipped",
+        list.len(),
+        list.skipped
+    );
+
+    if let Some(cache_path) = cache_path {
+        if let Err(e) = tokio::fs::write(&cache_path, content).await {
+            tracing::warn!("Failed to cache GFWList: {}", e);
+        }
+    }
+    apply().await;
+    Ok(get_gfwlist_status())
+}
+
+/// 下载并解析列表
+async fn download(url: &str) -> Result<(String, GfwList)> {
+    let config = super::subscription::fetch_http_config().await;
+    let content = SubscriptionHttpClient::with_config(config)?
+        .fetch_subscription(url)
+        .await?;
+    let list = GfwList::parse(&content)?;
+    if list.is_empty() {
+        anyhow::bail!("GFWList contains no usable rules");
+    }
+    Ok((content, list))
+}
+
+/// 按 `interval_hours` 的更新间隔，列表是否需要更新
+fn is_update_due(now: DateTime<Utc>, interval_hours: u32) -> bool {
+    let state = STATE.read().unwrap_or_else(|e| e.into_inner());
+    let stale = state
+        .updated_at
+        .is_none_or(|t| now - t >= chrono::Duration::hours(interval_hours.into()));
+    let retry_allowed = state.last_attempt.is_none_or(|t| {
+        state.updated_at.is_some_and(|u| u >= t)
+            || now - t >= chrono::Duration::from_std(RETRY_INTERVAL).unwrap_or_default()
+    });
+    stale && retry_allowed
+}
+
+/// 开始定期更新 GFWList，已开始时不做任何事
+pub fn start_auto_update() {
+    let mut task = UPDATE_TASK.lock().unwrap_or_else(|e| e.into_inner());
+    if task.as_ref().is_some_and(|task| !task.is_finished()) {
+        return;
+    }
+
+    *task = Some(super::connection::TOKIO_RUNTIME.spawn(async {
+        let mut interval = tokio::time::interval(UPDATE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let interval_hours = gfwlist_settings().await.update_interval_hours;
+            if is_update_due(Utc::now(), interval_hours) {
+                if let Err(e) = update_gfwlist().await {
+                    tracing::warn!("Failed to update GFWList: {}", e);
+                }
+            }
+        }
+    }));
+}
+
+/// 停止定期更新 GFWList
+pub fn stop_auto_update() {
+    if let Some(task) = UPDATE_TASK.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    async fn reset() {
+        stop_auto_update();
+        settings()
+            .await
+            .update_config(|config| config.gfwlist = GfwListSettings::default())
+            .await
+            .unwrap();
+        *STATE.write().unwrap() = GfwListState::default();
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_init_loads_cache() {
+        reset().await;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(CACHE_FILE_NAME),
+            "[AutoProxy 0.2.9]\n||google.com\n@@||cn.google.com\n",
+        )
+        .unwrap();
+
+        init_gfwlist(dir.path().to_str().unwrap()).await.unwrap();
+        let status = get_gfwlist_status();
+        assert!(status.loaded);
+        assert_eq!(status.proxy_rules, 1);
+        assert_eq!(status.direct_rules, 1);
+        assert!(status.updated_at.is_some());
+        reset().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_settings_validation() {
+        reset().await;
+        let mut info = get_gfwlist_settings().await;
+        info.update_interval_hours = 0;
+        assert!(set_gfwlist_settings(info).await.is_err());
+
+        let mut info = get_gfwlist_settings().await;
+        info.update_interval_hours = 12;
+        set_gfwlist_settings(info).await.unwrap();
+        assert_eq!(get_gfwlist_settings().await.update_interval_hours, 12);
+        // Kept in the settings
+        assert_eq!(
+            settings()
+                .await
+                .get_config()
+                .await
+                .gfwlist
+                .update_interval_hours,
+            12
+        );
+        reset().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_update_due() {
+        reset().await;
+        let now = Utc::now();
+        assert!(is_update_due(now, 24));
+
+        // Updated recently
+        STATE.write().unwrap().updated_at = Some(now - chrono::Duration::hours(1));
+        assert!(!is_update_due(now, 24));
+        assert!(is_update_due(now + chrono::Duration::hours(24), 24));
+        assert!(is_update_due(now, 1));
+
+        // Failed attempt after the last update
+        let later = now + chrono::Duration::hours(24);
+        STATE.write().unwrap().last_attempt = Some(later - chrono::Duration::minutes(10));
+        assert!(!is_update_due(later, 24));
+        assert!(is_update_due(later + chrono::Duration::minutes(20), 24));
+        reset().await;
+    }
+}
@@ -0,0 +1,276 @@
+//! 日志 Bridge 模块
+//!
+//! 运行时调整日志级别、开启日志文件、查询内存中的最近日志、导出日志包与支持包，
+//! 以及将核心日志实时推送到事件流（Xray 日志由连接模块按引擎日志级别推送）。
+//! 推送到事件流的日志共用一个限流器，超出部分被丢弃并汇总为一条提示
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::api::{LogRecordInfo, V8RayEvent};
+use crate::platform::os_info::OsDetails;
+use crate::utils::log_buffer::{self, LogQuery, LogSource};
+use crate::utils::logger::{self, LogConfig, LogLevel};
+use crate::utils::redact;
+
+/// 每秒推送到事件流的日志条数
+const LOG_EVENTS_PER_SECOND: f64 = 50.0;
+
+/// 突发时最多连续推送的日志条数
+const LOG_EVENT_BURST: f64 = 200.0;
+
+/// 核心日志是否已转发到事件流
+static CORE_LOG_FORWARDER: AtomicBool = AtomicBool::new(false);
+
+lazy_static::lazy_static! {
+    /// 推送到事件流的核心日志最低级别
+    static ref STREAM_LEVEL: Mutex<LogLevel> = Mutex::new(LogLevel::Info);
+    static ref LOG_EVENT_LIMITER: Mutex<RateLimiter> =
+        Mutex::new(RateLimiter::new(LOG_EVENTS_PER_SECOND, LOG_EVENT_BURST));
+}
+
+/// 令牌桶限流器
+#[derive(Debug)]
+struct RateLimiter {
+    /// 每秒补充的令牌数
+    rate: f64,
+    /// 令牌上限
+    burst: f64,
+    /// 当前令牌数
+    tokens: f64,
+    /// 上次补充时间
+    last: Instant,
+    /// 自上次放行以来丢弃的条数
+    dropped: u64,
+}
+
+impl RateLimiter {
+    fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            last: Instant::now(),
+            dropped: 0,
+        }
+    }
+
+    /// 尝试放行一条，放行时返回此前丢弃的条数
+    fn acquire(&mut self, now: Instant) -> Option<u64> {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+        if self.tokens < 1.0 {
+            self.dropped += 1;
+            return None;
+        }
+        self.tokens -= 1.0;
+        Some(std::mem::take(&mut self.dropped))
+    }
+}
+
+/// 经限流后将日志事件发送到事件流
+pub(crate) fn send_log_event(event: V8RayEvent) {
+    let dropped = LOG_EVENT_LIMITER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .acquire(Instant::now());
+    let Some(dropped) = dropped else {
+        return;
+    };
+    if dropped > 0 {
+        let _ = super::events::send_event(V8RayEvent::CoreLog {
+            level: LogLevel::Warn.to_string(),
+            target: module_path!().to_string(),
+            message: format!("{} log records dropped (rate limited)", dropped),
+        });
+    }
+    let _ = super::events::send_event(event);
+}
+
+/// 设置推送到事件流的核心日志最低级别
+pub fn set_log_stream_level(level: &str) -> Result<()> {
+    *STREAM_LEVEL.lock().unwrap_or_else(|e| e.into_inner()) = level.parse()?;
+    Ok(())
+}
+
+/// 将核心日志转发到事件流（只启动一次）
+pub(crate) fn forward_core_logs() {
+    if CORE_LOG_FORWARDER.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let mut records = log_buffer::subscribe();
+    super::connection::TOKIO_RUNTIME.spawn(async move {
+        loop {
+            match records.recv().await {
+                Ok(record) if record.source == LogSource::Core => {
+                    let min_level = *STREAM_LEVEL.lock().unwrap_or_else(|e| e.into_inner());
+                    if record.level >= min_level {
+                        send_log_event(V8RayEvent::CoreLog {
+                            level: record.level.to_string(),
+                            target: record.target,
+                            message: record.message,
+                        });
+                    }
+                }
+                Ok(_) | Err(RecvError::Lagged(_)) => {}
+                Err(RecvError::Closed) => break,
+            }
+        }
+        CORE_LOG_FORWARDER.store(false, Ordering::SeqCst);
+    });
+}
+
+/// 调整日志级别
+pub fn set_log_level(level: &str) -> Result<()> {
+    logger::set_log_level(level.parse()?)
+}
+
+/// 获取当前日志级别
+pub fn get_log_level() -> Option<String> {
+    logger::log_level().map(|level| level.to_string())
+}
+
+/// 将日志写入目录中的文件（按天轮转，保留默认数量与天数）
+pub fn init_log_files(log_dir: &str) -> Result<()> {
+    logger::open_log_file(Path::new(log_dir), &LogConfig::default())
+}
+
+/// 解析日志来源
+fn parse_source(source: &str) -> Result<LogSource> {
+    match source.to_ascii_lowercase().as_str() {
+        "core" => Ok(LogSource::Core),
+        "xray" => Ok(LogSource::Xray),
+        _ => Err(anyhow!("Unknown log source: {}", source)),
+    }
+}
+
+/// 查询最近日志
+pub fn get_recent_logs(
+    source: Option<String>,
+    min_level: Option<String>,
+    keyword: Option<String>,
+    limit: u32,
+) -> Result<Vec<LogRecordInfo>> {
+    let query = LogQuery {
+        source: source.as_deref().map(parse_source).transpose()?,
+        min_level: min_level
+            .as_deref()
+            .map(str::parse::<LogLevel>)
+            .transpose()?,
+        contains: keyword.filter(|k| !k.is_empty()),
+        limit: Some(limit as usize),
+    };
+    Ok(log_buffer::recent_logs(&query)
+        .into_iter()
+        .map(|record| LogRecordInfo {
+            timestamp: record.timestamp.timestamp_millis(),
+            source: record.source.to_string(),
+            level: record.level.to_string(),
+            target: record.target,
+            message: record.message,
+        })
+        .collect())
+}
+
+/// 导出日志包
+pub fn export_logs(path: &str) -> Result<()> {
+    logger::export_logs(Path::new(path))
+}
+
+/// 开启或关闭日志中敏感信息的屏蔽
+pub fn set_log_redaction(enabled: bool) {
+    redact::set_enabled(enabled);
+    tracing::info!(
+        "Log redaction {}",
+        if enabled { "enabled" } else { "disabled" }
+    );
+}
+
+/// 导出支持包：日志包附带系统信息和主连接与附加实例正在使用的 Xray 配置，敏感信息一律屏蔽
+pub async fn export_support_bundle(path: &str) -> Result<()> {
+    let manager = super::connection::core_connection_manager().await;
+    let mut sections = vec![(
+        "System".to_string(),
+        serde_json::to_string_pretty(&OsDetails::current())?,
+    )];
+    if let Some(config) = manager.get_xray().running_config().await {
+        sections.push((
+            "Xray configuration".to_string(),
+            redact::redacted_json(&config)?,
+        ));
+    }
+    let instances = manager.instances();
+    for health in instances.list().await {
+        let Some(core) = instances.get(&health.instance_id).await else {
+            continue;
+        };
+        if let Some(config) = core.running_config().await {
+            sections.push((
+                format!("Xray configuration of instance {}", health.instance_id),
+                redact::redacted_json(&config)?,
+            ));
+        }
+    }
+    logger::export_logs_with(Path::new(path), &sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(10.0, 2.0);
+        let start = limiter.last;
+        assert_eq!(limiter.acquire(start), Some(0));
+        assert_eq!(limiter.acquire(start), Some(0));
+        assert_eq!(limiter.acquire(start), None);
+        assert_eq!(limiter.acquire(start), None);
+
+        // One token after 100ms, reporting the two dropped
+        assert_eq!(limiter.acquire(start + Duration::from_millis(100)), Some(2));
+        assert_eq!(limiter.acquire(start + Duration::from_millis(100)), None);
+    }
+
+    #[test]
+    fn test_set_log_stream_level() {
+        set_log_stream_level("warning").unwrap();
+        assert_eq!(
+            *STREAM_LEVEL.lock().unwrap_or_else(|e| e.into_inner()),
+            LogLevel::Warn
+        );
+        assert!(set_log_stream_level("loud").is_err());
+        set_log_stream_level("info").unwrap();
+    }
+
+    #[test]
+    fn test_get_recent_logs() {
+        log_buffer::record(
+            LogSource::Xray,
+            LogLevel::Error,
+            "xray",
+            "bridge log query test",
+        );
+
+        let logs = get_recent_logs(
+            Some("xray".to_string()),
+            Some("warning".to_string()),
+            Some("bridge log query".to_string()),
+            10,
+        )
+        .unwrap();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].source, "xray");
+        assert_eq!(logs[0].level, "error");
+
+        assert!(get_recent_logs(Some("kernel".to_string()), None, None, 10).is_err());
+        assert!(get_recent_logs(None, Some("loud".to_string()), None, 10).is_err());
+    }
+}
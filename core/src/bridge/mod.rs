@@ -10,14 +10,35 @@ use tokio::sync::RwLock;
 // 子模块
 /// API 定义模块
 pub mod api;
+/// 流量预算模块
+pub mod budget;
 /// 配置管理模块
 pub mod config;
 /// 连接管理模块
 pub mod connection;
+/// 本地控制接口访问令牌模块
+pub mod control;
+/// 本地 REST/WebSocket 控制接口模块
+#[cfg(feature = "control-api")]
+pub mod controller;
+/// 错误码模块
+pub mod error;
 /// 事件流模块
 pub mod events;
+/// GFWList 规则模块
+pub mod gfwlist;
+/// 日志模块
+pub mod logs;
 /// 平台相关模块
 pub mod platform;
+/// 配置档模块
+pub mod profile;
+/// 分应用路由模块
+pub mod routing;
+/// 运行状态持久化模块
+pub mod session;
+/// 应用设置模块
+pub mod settings;
 /// 订阅管理模块
 pub mod subscription;
 
@@ -62,6 +83,9 @@ pub fn init() -> Result<()> {
     // 初始化事件系统
     events::init()?;
 
+    // 将核心日志实时推送到事件流
+    logs::forward_core_logs();
+
     state.initialized = true;
     tracing::info!("V8Ray Bridge initialized successfully");
     Ok(())
@@ -77,6 +101,13 @@ pub fn shutdown() -> Result<()> {
     // 关闭连接
     connection::shutdown()?;
 
+    // 停止本地控制接口
+    #[cfg(feature = "control-api")]
+    controller::stop_control_server();
+
+    // 撤销系统代理、TUN 路由等系统改动
+    connection::TOKIO_RUNTIME.block_on(platform::shutdown());
+
     // 关闭事件系统
     events::shutdown()?;
 
@@ -2,7 +2,146 @@
 //!
 //! This module provides FFI bindings for platform-specific operations.
 
-use crate::platform::{get_platform, get_platform_info, PlatformInfo};
+use super::api::{
+    AppPathsInfo, LanShareInfo, NetworkServiceInfo, OsDetailsInfo, V8RayEvent, WindowsVersionInfo,
+};
+use crate::config::routing::RoutingRule;
+use crate::connection::timeline::SessionEventKind;
+use crate::control::AccessTokenStore;
+use crate::paths::AppPaths;
+use crate::platform::network_monitor::{NetworkChange, NetworkMonitor, DEFAULT_POLL_INTERVAL};
+use crate::platform::os_info::OsDetails;
+use crate::platform::pac::{
+    generate_pac, generate_pac_for_host, PacServer, PAC_CONTENT_TYPE, PAC_PATH,
+};
+use crate::platform::service::{
+    find_helper_binary, HelperClient, DEFAULT_HELPER_PORT, HELPER_TOKEN_FILE_NAME,
+};
+use crate::platform::share::{firewall_hint, lan_address, ShareServer};
+use crate::platform::tun::{TunConfig, TunDevice};
+use crate::platform::windows_version::WindowsVersion;
+use crate::platform::{
+    get_platform, get_platform_info, NetworkServiceScope, PlatformInfo, SystemProxyProtocols,
+    SystemProxySnapshot,
+};
+use std::net::IpAddr;
+use tokio::sync::Mutex;
+
+/// TUN device started by [`enable_tun_mode`]
+enum ActiveTun {
+    /// Started by the app, which has administrator privileges
+    Local(Box<TunDevice>),
+    /// Started by the privileged helper
+    Helper(HelperClient),
+}
+
+lazy_static::lazy_static! {
+    /// Running TUN device, if TUN mode is enabled
+    static ref TUN_DEVICE: Mutex<Option<ActiveTun>> = Mutex::new(None);
+}
+
+lazy_static::lazy_static! {
+    /// Protocols configured by [`set_system_proxy`]
+    static ref SYSTEM_PROXY_PROTOCOLS: std::sync::RwLock<SystemProxyProtocols> =
+        std::sync::RwLock::new(SystemProxyProtocols::default());
+}
+
+lazy_static::lazy_static! {
+    /// System proxy configuration from before the app set its own
+    static ref PROXY_SNAPSHOT: std::sync::Mutex<Option<SystemProxySnapshot>> =
+        std::sync::Mutex::new(None);
+}
+
+lazy_static::lazy_static! {
+    /// Local server publishing the PAC script while the system proxy uses it
+    static ref PAC_SERVER: std::sync::Mutex<Option<PacServer>> = std::sync::Mutex::new(None);
+}
+
+/// System proxy last applied by the app, re-applied after network changes
+#[derive(Debug, Clone, Copy)]
+enum AppliedSystemProxy {
+    /// Fixed HTTP/SOCKS proxy
+    Ports { http_port: u16, socks_port: u16 },
+    /// PAC script
+    Pac { http_port: u16, socks_port: u16 },
+}
+
+impl AppliedSystemProxy {
+    /// The same proxy with `protocol`'s port moved from `old_port` to
+    /// `new_port`, or None if it does not use that port
+    fn with_reassigned_port(self, protocol: &str, old_port: u16, new_port: u16) -> Option<Self> {
+        let (mut http, mut socks) = match self {
+            AppliedSystemProxy::Ports {
+                http_port,
+                socks_port,
+            }
+            | AppliedSystemProxy::Pac {
+                http_port,
+                socks_port,
+            } => (http_port, socks_port),
+        };
+        match protocol {
+            "http" if http == old_port => http = new_port,
+            "socks" if socks == old_port => socks = new_port,
+            _ => return None,
+        }
+        Some(match self {
+            AppliedSystemProxy::Ports { .. } => AppliedSystemProxy::Ports {
+                http_port: http,
+                socks_port: socks,
+            },
+            AppliedSystemProxy::Pac { .. } => AppliedSystemProxy::Pac {
+                http_port: http,
+                socks_port: socks,
+            },
+        })
+    }
+}
+
+lazy_static::lazy_static! {
+    /// System proxy applied by [`set_system_proxy`] or [`set_system_proxy_pac`]
+    static ref APPLIED_SYSTEM_PROXY: std::sync::Mutex<Option<AppliedSystemProxy>> =
+        std::sync::Mutex::new(None);
+}
+
+lazy_static::lazy_static! {
+    /// Running network change monitor
+    static ref NETWORK_MONITOR: std::sync::Mutex<Option<NetworkMonitor>> =
+        std::sync::Mutex::new(None);
+}
+
+lazy_static::lazy_static! {
+    /// Server sharing files with other devices on the LAN
+    static ref SHARE_SERVER: std::sync::Mutex<Option<ShareServer>> = std::sync::Mutex::new(None);
+}
+
+#[cfg(target_os = "linux")]
+lazy_static::lazy_static! {
+    /// Network namespace routed through the proxy (Linux)
+    static ref NETNS: Mutex<Option<crate::platform::netns::NetnsIsolation>> = Mutex::new(None);
+}
+
+#[cfg(unix)]
+lazy_static::lazy_static! {
+    /// Running VpnService tunnel (Android)
+    static ref VPN_TUNNEL: Mutex<Option<crate::platform::android::VpnTunnel>> = Mutex::new(None);
+}
+
+#[cfg(target_os = "ios")]
+lazy_static::lazy_static! {
+    /// Running packet tunnel of the network extension (iOS)
+    static ref PACKET_TUNNEL: Mutex<Option<crate::platform::ios::PacketTunnel>> = Mutex::new(None);
+    /// Packet flow of [`PACKET_TUNNEL`], used without holding its lock
+    static ref PACKET_FLOW: std::sync::RwLock<Option<std::sync::Arc<crate::platform::ios::PacketFlow>>> =
+        std::sync::RwLock::new(None);
+}
+
+/// Whether the core runs within the network extension's memory limit
+static MEMORY_CONSTRAINED: std::sync::atomic::AtomicBool =
+    std::sync::atomic::AtomicBool::new(false);
+
+/// Recent log records kept in memory-constrained mode
+const CONSTRAINED_LOG_CAPACITY: usize = 200;
 
 /// Check if the application has administrator/root privileges
 ///
@@ -18,6 +157,9 @@ pub fn has_admin_privileges() -> Result<bool, String> {
 
 /// Set system proxy
 ///
+/// Only the protocols enabled with [`set_system_proxy_protocols`] are
+/// configured; by default HTTP, HTTPS and SOCKS all are.
+///
 /// # Arguments
 /// * `http_port` - HTTP proxy port
 /// * `socks_port` - SOCKS proxy port
@@ -32,27 +174,507 @@ pub fn set_system_proxy(http_port: u16, socks_port: u16) -> Result<(), String> {
         http_port,
         socks_port
     );
+    let protocols = get_system_proxy_protocols();
+    snapshot_system_proxy();
     let platform = get_platform();
     let result = platform
-        .set_system_proxy(http_port, socks_port)
+        .set_system_proxy_with_protocols(http_port, socks_port, protocols)
         .map_err(|e| e.to_string());
     if let Err(ref e) = result {
         tracing::error!("Platform bridge: set_system_proxy failed: {}", e);
     } else {
         tracing::info!("Platform bridge: set_system_proxy succeeded");
+        set_applied_system_proxy(Some(AppliedSystemProxy::Ports {
+            http_port,
+            socks_port,
+        }));
     }
     result
 }
 
+/// Get the protocols configured by [`set_system_proxy`]
+pub fn get_system_proxy_protocols() -> SystemProxyProtocols {
+    *SYSTEM_PROXY_PROTOCOLS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Choose the protocols configured by [`set_system_proxy`]
+///
+/// Takes effect the next time the system proxy is set.
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` if no protocol is enabled
+pub fn set_system_proxy_protocols(protocols: SystemProxyProtocols) -> Result<(), String> {
+    protocols.validate().map_err(|e| e.to_string())?;
+    *SYSTEM_PROXY_PROTOCOLS
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = protocols;
+    Ok(())
+}
+
+/// Get the macOS network services targeted by the system proxy
+pub fn get_network_service_scope() -> NetworkServiceScope {
+    crate::platform::get_network_service_scope()
+}
+
+/// Choose the macOS network services targeted by the system proxy
+///
+/// Takes effect the next time the system proxy is set.
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` if no service is selected
+pub fn set_network_service_scope(scope: NetworkServiceScope) -> Result<(), String> {
+    crate::platform::set_network_service_scope(scope).map_err(|e| e.to_string())
+}
+
+/// List the macOS network services in service order
+///
+/// # Returns
+/// * `Ok(Vec<NetworkServiceInfo>)` with the services (empty on other platforms)
+/// * `Err(String)` if networksetup failed
+pub fn list_network_services() -> Result<Vec<NetworkServiceInfo>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        use crate::platform::MacOSPlatform;
+
+        let primary = MacOSPlatform::primary_device();
+        let services = MacOSPlatform::list_network_services().map_err(|e| e.to_string())?;
+        Ok(services
+            .into_iter()
+            .map(|service| NetworkServiceInfo {
+                vpn: service.is_vpn(),
+                primary: primary.as_deref() == Some(service.device.as_str()),
+                name: service.name,
+                device: service.device,
+                enabled: service.enabled,
+            })
+            .collect())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        Ok(Vec::new())
+    }
+}
+
+/// Point the system proxy at a PAC script generated from the routing rules
+///
+/// The script is served from a local HTTP server, which keeps running
+/// until [`clear_system_proxy`]. Calling this again regenerates the script,
+/// e.g. after the rules or ports changed.
+///
+/// # Arguments
+/// * `http_port` - HTTP proxy port
+/// * `socks_port` - SOCKS proxy port
+///
+/// # Returns
+/// * `Ok(String)` with the PAC URL
+/// * `Err(String)` with error message if failed
+pub async fn set_system_proxy_pac(http_port: u16, socks_port: u16) -> Result<String, String> {
+    let script = generate_pac(&enabled_routing_rules().await, http_port, socks_port);
+
+    let running_url = PAC_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|server| {
+            server.update(script.clone());
+            server.url()
+        });
+    let url = match running_url {
+        Some(url) => url,
+        None => {
+            let server = PacServer::start(0, script)
+                .await
+                .map_err(|e| e.to_string())?;
+            let url = server.url();
+            *PAC_SERVER.lock().unwrap_or_else(|e| e.into_inner()) = Some(server);
+            url
+        }
+    };
+
+    snapshot_system_proxy();
+    get_platform()
+        .set_system_proxy_pac(&url)
+        .map_err(|e| e.to_string())?;
+    set_applied_system_proxy(Some(AppliedSystemProxy::Pac {
+        http_port,
+        socks_port,
+    }));
+    Ok(url)
+}
+
+/// Remember the system proxy applied by the app
+fn set_applied_system_proxy(applied: Option<AppliedSystemProxy>) {
+    *APPLIED_SYSTEM_PROXY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = applied;
+}
+
+/// Remember the system proxy configuration before the app first changes it
+///
+/// Kept until the app's proxy is cleared, so applying it again (e.g. with
+/// other ports) does not overwrite the original. Also persisted in the
+/// runtime state, so it can be restored after a crash.
+fn snapshot_system_proxy() {
+    let mut saved = PROXY_SNAPSHOT.lock().unwrap_or_else(|e| e.into_inner());
+    if saved.is_some() {
+        return;
+    }
+    match get_platform().snapshot_system_proxy() {
+        Ok(snapshot) => {
+            super::session::update_runtime_state(|s| s.proxy_snapshot = snapshot.clone());
+            *saved = snapshot;
+        }
+        Err(e) => tracing::warn!("Failed to snapshot system proxy: {}", e),
+    }
+}
+
+/// Use `snapshot` when the system proxy is next cleared, unless one is
+/// already held
+///
+/// Used to restore the configuration saved by a session that crashed.
+pub(crate) fn restore_system_proxy_snapshot(snapshot: Option<SystemProxySnapshot>) {
+    let mut saved = PROXY_SNAPSHOT.lock().unwrap_or_else(|e| e.into_inner());
+    if saved.is_none() {
+        *saved = snapshot;
+    }
+}
+
 /// Clear system proxy
 ///
+/// Restores the configuration from before the app set its proxy if one was
+/// captured, and stops the PAC server, if any.
+///
 /// # Returns
 /// * `Ok(())` if successful
 /// * `Err(String)` with error message if failed
 #[flutter_rust_bridge::frb(sync)]
 pub fn clear_system_proxy() -> Result<(), String> {
     let platform = get_platform();
-    platform.clear_system_proxy().map_err(|e| e.to_string())
+    let mut saved = PROXY_SNAPSHOT.lock().unwrap_or_else(|e| e.into_inner());
+    match saved.as_ref() {
+        Some(snapshot) => platform.restore_system_proxy(snapshot),
+        None => platform.clear_system_proxy(),
+    }
+    .map_err(|e| e.to_string())?;
+    *saved = None;
+    drop(saved);
+    set_applied_system_proxy(None);
+    super::session::update_runtime_state(|s| s.proxy_snapshot = None);
+    if let Some(server) = PAC_SERVER.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        server.stop();
+    }
+    Ok(())
+}
+
+/// Undo the app's system changes before exit
+///
+/// Stops the network monitor, removes the TUN routes and clears the system
+/// proxy applied by the app, so the runtime state is left clean and the
+/// next start has nothing to recover.
+pub(crate) async fn shutdown() {
+    stop_network_monitor();
+    if let Err(e) = disable_tun_mode().await {
+        tracing::warn!("Failed to disable TUN mode on shutdown: {}", e);
+    }
+
+    let applied = APPLIED_SYSTEM_PROXY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .is_some();
+    if applied {
+        match clear_system_proxy() {
+            Ok(()) => super::session::update_runtime_state(|s| s.system_proxy_applied = false),
+            Err(e) => tracing::warn!("Failed to clear system proxy on shutdown: {}", e),
+        }
+    }
+}
+
+/// Point the system proxy applied by the app at a reassigned local port
+///
+/// A hot reload starts Xray on new ports and stops the previous process
+/// after its drain delay, so the system proxy or PAC script must follow
+/// before then. Does nothing if the app's proxy does not use the port.
+pub(crate) async fn follow_port_reassignment(protocol: &str, old_port: u16, new_port: u16) {
+    let applied = *APPLIED_SYSTEM_PROXY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let Some(updated) =
+        applied.and_then(|applied| applied.with_reassigned_port(protocol, old_port, new_port))
+    else {
+        return;
+    };
+
+    let result = match updated {
+        AppliedSystemProxy::Ports {
+            http_port,
+            socks_port,
+        } => tokio::task::spawn_blocking(move || set_system_proxy(http_port, socks_port))
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result),
+        AppliedSystemProxy::Pac {
+            http_port,
+            socks_port,
+        } => set_system_proxy_pac(http_port, socks_port)
+            .await
+            .map(|_| ()),
+    };
+    match result {
+        Ok(()) => tracing::info!(
+            "System proxy moved to {} port {} (was {})",
+            protocol,
+            new_port,
+            old_port
+        ),
+        Err(e) => tracing::warn!("Failed to move system proxy to new port: {}", e),
+    }
+}
+
+/// Rules of the enabled routing rule sets, in evaluation order
+async fn enabled_routing_rules() -> Vec<RoutingRule> {
+    super::connection::core_connection_manager()
+        .await
+        .get_xray()
+        .get_routing_rule_sets()
+        .into_iter()
+        .filter(|rule_set| rule_set.enabled)
+        .flat_map(|rule_set| rule_set.rules)
+        .collect()
+}
+
+/// Start the LAN share server
+///
+/// The server listens on all interfaces and serves published files to
+/// other devices under a random token. Returns the running server's
+/// details if it is already started.
+///
+/// # Arguments
+/// * `port` - Port to listen on, 0 for any free port
+///
+/// # Returns
+/// * `Ok(LanShareInfo)` with the address, port and token
+/// * `Err(String)` with error message if failed
+pub async fn start_lan_share(port: u16) -> Result<LanShareInfo, String> {
+    let address = lan_address().ok_or_else(|| "No LAN address found".to_string())?;
+    if let Some(server) = SHARE_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+    {
+        return Ok(lan_share_info(server, address));
+    }
+
+    let server = ShareServer::start(port).await.map_err(|e| e.to_string())?;
+    let info = lan_share_info(&server, address);
+    let mut running = SHARE_SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    // Started concurrently by another call; keep the first server
+    if let Some(existing) = running.as_ref() {
+        return Ok(lan_share_info(existing, address));
+    }
+    *running = Some(server);
+    Ok(info)
+}
+
+/// Stop the LAN share server
+///
+/// URLs handed out earlier stop working; a restarted server uses a new
+/// token.
+#[flutter_rust_bridge::frb(sync)]
+pub fn stop_lan_share() {
+    if let Some(server) = SHARE_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        server.stop();
+    }
+}
+
+/// Start watching for network changes
+///
+/// When the default route changes (e.g. another Wi-Fi network is joined),
+/// the system proxy applied by the app is applied again, the connection is
+/// re-checked and a `NetworkChanged` event is sent. Does nothing if the
+/// monitor is already running.
+pub async fn start_network_monitor() {
+    let mut monitor = NETWORK_MONITOR.lock().unwrap_or_else(|e| e.into_inner());
+    if monitor.is_none() {
+        *monitor = Some(NetworkMonitor::start(
+            DEFAULT_POLL_INTERVAL,
+            handle_network_change,
+        ));
+    }
+}
+
+/// Stop watching for network changes
+#[flutter_rust_bridge::frb(sync)]
+pub fn stop_network_monitor() {
+    if let Some(monitor) = NETWORK_MONITOR
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        monitor.stop();
+    }
+}
+
+/// Re-apply the system proxy and re-check the connection after a network
+/// change
+async fn handle_network_change(change: NetworkChange) {
+    let current = change.current;
+    let manager = super::connection::core_connection_manager().await;
+    manager.record_event(
+        SessionEventKind::NetworkChanged,
+        format!(
+            "Network changed to {}",
+            current.interface.as_deref().unwrap_or("none")
+        ),
+    );
+    let _ = super::events::send_event(V8RayEvent::NetworkChanged {
+        interface: current.interface.clone(),
+        gateway: current.gateway.map(|ip| ip.to_string()),
+        local_address: current.local_address.map(|ip| ip.to_string()),
+        online: current.is_online(),
+    });
+    if !current.is_online() {
+        return;
+    }
+
+    let applied = *APPLIED_SYSTEM_PROXY
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let reapplied = match applied {
+        Some(AppliedSystemProxy::Ports {
+            http_port,
+            socks_port,
+        }) => Some(
+            tokio::task::spawn_blocking(move || set_system_proxy(http_port, socks_port))
+                .await
+                .map_err(|e| e.to_string())
+                .and_then(|result| result),
+        ),
+        Some(AppliedSystemProxy::Pac {
+            http_port,
+            socks_port,
+        }) => Some(
+            set_system_proxy_pac(http_port, socks_port)
+                .await
+                .map(|_| ()),
+        ),
+        None => None,
+    };
+    match reapplied {
+        Some(Ok(())) => manager.record_event(
+            SessionEventKind::ProxyApplied,
+            "System proxy re-applied after network change",
+        ),
+        Some(Err(e)) => tracing::warn!("Failed to re-apply system proxy: {}", e),
+        None => {}
+    }
+
+    if manager.is_connected().await {
+        // Records whether the proxy still works in the timeline
+        let _ = manager.wait_until_ready().await;
+    }
+}
+
+/// Publish a PAC script for other LAN devices
+///
+/// The script is generated from the routing rules like the system PAC, but
+/// points at this machine's LAN address, so LAN access must be enabled for
+/// the local inbounds. Calling this again regenerates the script.
+///
+/// # Arguments
+/// * `http_port` - HTTP proxy port
+/// * `socks_port` - SOCKS proxy port
+///
+/// # Returns
+/// * `Ok(String)` with the PAC URL
+/// * `Err(String)` with error message if failed
+pub async fn share_pac_on_lan(http_port: u16, socks_port: u16) -> Result<String, String> {
+    let manager = super::connection::core_connection_manager().await;
+    if !manager.get_xray().inbound_settings().allow_lan {
+        return Err("LAN access is disabled for the local inbounds".to_string());
+    }
+    let address = lan_address().ok_or_else(|| "No LAN address found".to_string())?;
+    let script = generate_pac_for_host(
+        &enabled_routing_rules().await,
+        address,
+        http_port,
+        socks_port,
+    );
+    share_on_lan(
+        address,
+        PAC_PATH.trim_start_matches('/'),
+        PAC_CONTENT_TYPE,
+        script,
+    )
+}
+
+/// Publish a file, such as an exported profile, for other LAN devices
+///
+/// # Arguments
+/// * `name` - File name used in the URL
+/// * `content_type` - MIME type sent with the file
+/// * `content` - File content
+///
+/// # Returns
+/// * `Ok(String)` with the file URL
+/// * `Err(String)` with error message if failed
+#[flutter_rust_bridge::frb(sync)]
+pub fn share_file_on_lan(
+    name: String,
+    content_type: String,
+    content: String,
+) -> Result<String, String> {
+    if name.is_empty() || name.contains('/') {
+        return Err(format!("Invalid file name: {:?}", name));
+    }
+    let address = lan_address().ok_or_else(|| "No LAN address found".to_string())?;
+    share_on_lan(address, &name, &content_type, content)
+}
+
+/// Stop publishing a file on the LAN share server
+///
+/// # Returns
+/// * `true` if the file was published
+#[flutter_rust_bridge::frb(sync)]
+pub fn unshare_file_on_lan(name: String) -> bool {
+    SHARE_SERVER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .is_some_and(|server| server.unpublish(&name))
+}
+
+/// Publish `body` on the running share server, returning its URL
+fn share_on_lan(
+    address: IpAddr,
+    name: &str,
+    content_type: &str,
+    body: String,
+) -> Result<String, String> {
+    let server = SHARE_SERVER.lock().unwrap_or_else(|e| e.into_inner());
+    let server = server
+        .as_ref()
+        .ok_or_else(|| "LAN share server is not running".to_string())?;
+    server.publish(name, content_type, body);
+    Ok(server.url(address, name))
+}
+
+/// Convert to the FFI structure
+fn lan_share_info(server: &ShareServer, address: IpAddr) -> LanShareInfo {
+    LanShareInfo {
+        address: address.to_string(),
+        port: server.port(),
+        token: server.token().to_string(),
+        files: server.files(),
+        firewall_hint: firewall_hint(server.port()),
+    }
 }
 
 /// Check if system proxy is set
@@ -67,6 +689,34 @@ pub fn is_system_proxy_set() -> Result<bool, String> {
     platform.is_system_proxy_set().map_err(|e| e.to_string())
 }
 
+/// Get the directories the app uses
+pub fn get_app_paths() -> AppPathsInfo {
+    let paths = crate::paths::app_paths();
+    let display = |dir: &std::path::Path| dir.to_string_lossy().into_owned();
+    AppPathsInfo {
+        config_dir: display(&paths.config_dir),
+        data_dir: display(&paths.data_dir),
+        cache_dir: display(&paths.cache_dir),
+        log_dir: display(&paths.log_dir),
+    }
+}
+
+/// Use the given directories instead of the detected ones
+///
+/// The directories are created first, so an unusable location is reported
+/// here rather than on the first write.
+pub fn set_app_paths(info: AppPathsInfo) -> anyhow::Result<()> {
+    let paths = AppPaths {
+        config_dir: info.config_dir.into(),
+        data_dir: info.data_dir.into(),
+        cache_dir: info.cache_dir.into(),
+        log_dir: info.log_dir.into(),
+    };
+    paths.ensure()?;
+    crate::paths::set_app_paths(Some(paths));
+    Ok(())
+}
+
 /// Get platform information
 ///
 /// # Returns
@@ -76,6 +726,42 @@ pub fn get_platform_information() -> PlatformInfo {
     get_platform_info()
 }
 
+/// Operating system name and version, plus the kernel, distribution and
+/// desktop environment on Linux
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_os_details() -> OsDetailsInfo {
+    let details = OsDetails::current();
+    OsDetailsInfo {
+        name: details.name,
+        version: details.version,
+        build: details.build,
+        kernel: details.kernel,
+        distro_id: details.distro.as_ref().map(|d| d.id.clone()),
+        distro_like: details.distro.map(|d| d.id_like).unwrap_or_default(),
+        desktop_uses_gsettings: details.desktop.as_ref().is_some_and(|d| d.uses_gsettings()),
+        desktop: details.desktop.map(|d| d.name().to_string()),
+    }
+}
+
+/// Windows version and the features it supports
+///
+/// # Returns
+/// * `Some(WindowsVersionInfo)` on Windows
+/// * `None` on other platforms or if the version cannot be read
+#[flutter_rust_bridge::frb(sync)]
+pub fn get_windows_version_info() -> Option<WindowsVersionInfo> {
+    WindowsVersion::current().map(|version| WindowsVersionInfo {
+        major: version.major,
+        minor: version.minor,
+        build: version.build,
+        revision: version.revision,
+        display_name: version.to_string(),
+        is_windows_11: version.is_windows_11_or_later(),
+        supports_wintun: version.supports_wintun(),
+        supports_toast_notifications: version.supports_toast_notifications(),
+    })
+}
+
 /// Enable auto start
 ///
 /// # Returns
@@ -110,6 +796,465 @@ pub fn is_auto_start_enabled() -> Result<bool, String> {
     platform.is_auto_start_enabled().map_err(|e| e.to_string())
 }
 
+/// Enable TUN mode
+///
+/// Routes all traffic of the device through a TUN interface forwarding to
+/// the local SOCKS inbound. Xray's outbounds are bound to the physical
+/// interface from the next connection or server switch on, so it should be
+/// enabled before connecting.
+///
+/// Without administrator privileges the tunnel is started by the privileged
+/// helper (see [`privileged_helper_install_command`]).
+///
+/// # Arguments
+/// * `socks_port` - Local SOCKS inbound port
+/// * `bypass_hosts` - Proxy server hosts, routed around the tunnel
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn enable_tun_mode(socks_port: u16, bypass_hosts: Vec<String>) -> Result<(), String> {
+    let mut device = TUN_DEVICE.lock().await;
+    if device.is_some() {
+        return Ok(());
+    }
+
+    let mut bypass_addresses: Vec<IpAddr> = Vec::new();
+    for host in &bypass_hosts {
+        match tokio::net::lookup_host((host.as_str(), 0)).await {
+            Ok(addresses) => bypass_addresses.extend(addresses.map(|a| a.ip())),
+            Err(e) => tracing::warn!("Failed to resolve {} for TUN bypass: {}", host, e),
+        }
+    }
+    bypass_addresses.sort();
+    bypass_addresses.dedup();
+
+    let config = TunConfig::new(socks_port).with_bypass_addresses(bypass_addresses);
+    let (started, interface) = if get_platform().has_admin_privileges().unwrap_or(false) {
+        let started = TunDevice::start(config).await.map_err(|e| e.to_string())?;
+        let teardown = started.teardown_commands().to_vec();
+        crate::bridge::session::update_runtime_state(|s| s.tun_teardown = teardown);
+        let interface = started.default_route().interface.clone();
+        (ActiveTun::Local(Box::new(started)), interface)
+    } else {
+        let client = helper_client()?;
+        let route = client.start_tun(&config).await.map_err(|e| {
+            format!(
+                "TUN mode requires administrator privileges or the privileged helper: {}",
+                e
+            )
+        })?;
+        (ActiveTun::Helper(client), route.interface)
+    };
+
+    if let Ok(manager) = crate::bridge::connection::get_core_connection_manager() {
+        manager.get_xray().set_outbound_interface(Some(interface));
+    }
+    *device = Some(started);
+    Ok(())
+}
+
+/// Disable TUN mode, restoring routes and DNS
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn disable_tun_mode() -> Result<(), String> {
+    let Some(device) = TUN_DEVICE.lock().await.take() else {
+        return Ok(());
+    };
+    match device {
+        ActiveTun::Local(device) => {
+            device.stop().await;
+            crate::bridge::session::update_runtime_state(|s| s.tun_teardown.clear());
+        }
+        ActiveTun::Helper(client) => client.stop_tun().await.map_err(|e| e.to_string())?,
+    }
+
+    if let Ok(manager) = crate::bridge::connection::get_core_connection_manager() {
+        manager.get_xray().set_outbound_interface(None);
+    }
+    Ok(())
+}
+
+/// Check if TUN mode is enabled
+///
+/// # Returns
+/// * `true` if the TUN device is up
+pub async fn is_tun_mode_enabled() -> bool {
+    TUN_DEVICE.lock().await.is_some()
+}
+
+/// Client for the privileged helper, creating the shared token if needed
+fn helper_client() -> Result<HelperClient, String> {
+    let token_path = crate::paths::app_paths()
+        .data_dir
+        .join(HELPER_TOKEN_FILE_NAME);
+    let token = AccessTokenStore::load_or_create(token_path).map_err(|e| e.to_string())?;
+    Ok(HelperClient::new(DEFAULT_HELPER_PORT, token.token()))
+}
+
+/// Command installing the privileged helper used for TUN mode
+///
+/// Creates the token shared with the helper first. The app runs the
+/// command elevated (pkexec, an administrator prompt or UAC), which is the
+/// only time administrator privileges are asked for.
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with the program and its arguments
+/// * `Err(String)` with error message if failed
+pub fn privileged_helper_install_command() -> Result<Vec<String>, String> {
+    helper_client()?;
+    let binary = find_helper_binary().map_err(|e| e.to_string())?;
+    Ok(vec![
+        binary.display().to_string(),
+        "--data-dir".to_string(),
+        crate::paths::app_paths().data_dir.display().to_string(),
+        "helper".to_string(),
+        "install".to_string(),
+    ])
+}
+
+/// Command removing the privileged helper, to be run elevated
+///
+/// # Returns
+/// * `Ok(Vec<String>)` with the program and its arguments
+/// * `Err(String)` with error message if failed
+pub fn privileged_helper_uninstall_command() -> Result<Vec<String>, String> {
+    let binary = find_helper_binary().map_err(|e| e.to_string())?;
+    Ok(vec![
+        binary.display().to_string(),
+        "helper".to_string(),
+        "uninstall".to_string(),
+    ])
+}
+
+/// Check if the privileged helper is installed and accepts the app's token
+///
+/// # Returns
+/// * `true` if TUN mode can be enabled without administrator privileges
+pub async fn is_privileged_helper_available() -> bool {
+    match helper_client() {
+        Ok(client) => client.ping().await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Create a network namespace whose only route goes through the proxy
+///
+/// Apps launched with [`launch_in_netns`] are proxied regardless of their
+/// own proxy settings, while the rest of the system is left alone.
+///
+/// # Arguments
+/// * `socks_port` - Local SOCKS inbound port
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn enable_netns_isolation(socks_port: u16) -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    {
+        use crate::platform::netns::{NetnsConfig, NetnsIsolation};
+
+        let mut netns = NETNS.lock().await;
+        if netns.is_some() {
+            return Ok(());
+        }
+        let config = NetnsConfig::new(socks_port);
+        *netns = Some(
+            NetnsIsolation::start(config)
+                .await
+                .map_err(|e| e.to_string())?,
+        );
+        Ok(())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = socks_port;
+        Err("Network namespace isolation is only supported on Linux".to_string())
+    }
+}
+
+/// Delete the proxy network namespace
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn disable_netns_isolation() -> Result<(), String> {
+    #[cfg(target_os = "linux")]
+    if let Some(netns) = NETNS.lock().await.take() {
+        netns.stop().await;
+    }
+    Ok(())
+}
+
+/// Launch an application inside the proxy network namespace
+///
+/// # Arguments
+/// * `program` - Program to run
+/// * `args` - Program arguments
+///
+/// # Returns
+/// * `Ok(pid)` with the process ID of the launched application
+/// * `Err(String)` with error message if failed
+pub async fn launch_in_netns(program: String, args: Vec<String>) -> Result<u32, String> {
+    #[cfg(target_os = "linux")]
+    {
+        let netns = NETNS.lock().await;
+        let netns = netns
+            .as_ref()
+            .ok_or_else(|| "Network namespace isolation is not enabled".to_string())?;
+        netns.launch(&program, &args).map_err(|e| e.to_string())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (program, args);
+        Err("Network namespace isolation is only supported on Linux".to_string())
+    }
+}
+
+/// Start forwarding packets from an Android VpnService descriptor
+///
+/// The core takes ownership of `fd` (from `ParcelFileDescriptor.detachFd()`)
+/// and closes it when the tunnel stops. Any running tunnel is replaced.
+///
+/// # Arguments
+/// * `fd` - TUN descriptor from `VpnService.Builder.establish()`
+/// * `socks_port` - Local SOCKS inbound port
+/// * `mtu` - MTU the interface was established with
+/// * `tun2socks_path` - tun2socks binary, usually in the native library dir
+/// * `protect_path` - Path of a protect socket for helper processes
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn start_vpn_tunnel(
+    fd: i32,
+    socks_port: u16,
+    mtu: u32,
+    tun2socks_path: Option<String>,
+    protect_path: Option<String>,
+) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use crate::platform::android::{VpnTunnel, VpnTunnelConfig};
+
+        let mut tunnel = VPN_TUNNEL.lock().await;
+        if let Some(previous) = tunnel.take() {
+            previous.stop().await;
+        }
+
+        let mut config = VpnTunnelConfig::new(fd, socks_port).with_mtu(mtu);
+        if let Some(path) = tun2socks_path {
+            config = config.with_tun2socks_path(path.into());
+        }
+        if let Some(path) = protect_path {
+            config = config.with_protect_path(path.into());
+        }
+        *tunnel = Some(VpnTunnel::start(config).await.map_err(|e| e.to_string())?);
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (fd, socks_port, mtu, tun2socks_path, protect_path);
+        Err("VpnService tunnels are only supported on Android".to_string())
+    }
+}
+
+/// Stop the VpnService tunnel and close its descriptor
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn stop_vpn_tunnel() -> Result<(), String> {
+    #[cfg(unix)]
+    if let Some(tunnel) = VPN_TUNNEL.lock().await.take() {
+        tunnel.stop().await;
+    }
+    Ok(())
+}
+
+/// Run within the memory limit of the iOS network extension (50 MB)
+///
+/// Keeps fewer recent log records, stops collecting connection and traffic
+/// history, and makes later packet flows use smaller buffers. Should be
+/// turned on first thing in `startTunnel`.
+///
+/// # Arguments
+/// * `enabled` - Whether memory-constrained mode is on
+pub async fn set_memory_constrained(enabled: bool) {
+    use std::sync::atomic::Ordering;
+
+    MEMORY_CONSTRAINED.store(enabled, Ordering::SeqCst);
+    crate::utils::log_buffer::set_capacity(if enabled {
+        CONSTRAINED_LOG_CAPACITY
+    } else {
+        crate::utils::log_buffer::DEFAULT_CAPACITY
+    });
+    crate::bridge::connection::core_connection_manager()
+        .await
+        .set_low_memory(enabled)
+        .await;
+}
+
+/// Check if memory-constrained mode is on
+pub fn is_memory_constrained() -> bool {
+    MEMORY_CONSTRAINED.load(std::sync::atomic::Ordering::SeqCst)
+}
+
+/// Find the utun descriptor behind the packet tunnel provider's `packetFlow`
+///
+/// # Returns
+/// * `Some(fd)` on iOS inside a packet tunnel provider
+/// * `None` if no utun descriptor is open
+pub fn find_utun_descriptor() -> Option<i32> {
+    #[cfg(target_os = "ios")]
+    {
+        crate::platform::ios::find_utun_fd()
+    }
+    #[cfg(not(target_os = "ios"))]
+    {
+        None
+    }
+}
+
+/// Start the packet tunnel of the iOS network extension
+///
+/// With `fd` the core serves the utun descriptor directly, leaving it open
+/// when the tunnel stops; without, packets are exchanged with
+/// [`write_tunnel_packets`] and [`read_tunnel_packets`]. Any running packet
+/// tunnel is replaced. tun2socks runs in the extension's process and must
+/// be registered first with `v8ray_register_tun2socks`.
+///
+/// # Arguments
+/// * `fd` - utun descriptor from [`find_utun_descriptor`], None for a packet flow
+/// * `socks_port` - Local SOCKS inbound port
+/// * `mtu` - MTU from the tunnel network settings
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn start_packet_tunnel(fd: Option<i32>, socks_port: u16, mtu: u32) -> Result<(), String> {
+    #[cfg(target_os = "ios")]
+    {
+        use crate::platform::ios::{PacketFlowConfig, PacketTunnel};
+
+        let mut tunnel = PACKET_TUNNEL.lock().await;
+        if let Some(previous) = tunnel.take() {
+            previous.stop().await;
+        }
+        *PACKET_FLOW.write().unwrap_or_else(|e| e.into_inner()) = None;
+
+        let started = match fd {
+            Some(fd) => PacketTunnel::start_with_fd(fd, socks_port, mtu).await,
+            None => {
+                let config = if is_memory_constrained() {
+                    PacketFlowConfig::constrained(mtu)
+                } else {
+                    PacketFlowConfig::new(mtu)
+                };
+                PacketTunnel::start_with_flow(config, socks_port).await
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        *PACKET_FLOW.write().unwrap_or_else(|e| e.into_inner()) = started.flow();
+        *tunnel = Some(started);
+        Ok(())
+    }
+    #[cfg(not(target_os = "ios"))]
+    {
+        let _ = (fd, socks_port, mtu);
+        Err("Packet tunnels are only supported on iOS".to_string())
+    }
+}
+
+/// Stop the packet tunnel, failing pending packet reads
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` with error message if failed
+pub async fn stop_packet_tunnel() -> Result<(), String> {
+    #[cfg(target_os = "ios")]
+    {
+        *PACKET_FLOW.write().unwrap_or_else(|e| e.into_inner()) = None;
+        if let Some(tunnel) = PACKET_TUNNEL.lock().await.take() {
+            tunnel.stop().await;
+        }
+    }
+    Ok(())
+}
+
+/// Packet flow of the running packet tunnel
+#[cfg(target_os = "ios")]
+fn packet_flow() -> Result<std::sync::Arc<crate::platform::ios::PacketFlow>, String> {
+    PACKET_FLOW
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .ok_or_else(|| "No packet flow tunnel is running".to_string())
+}
+
+/// Pass packets read from `packetFlow` to the tunnel
+///
+/// # Arguments
+/// * `packets` - IP packets from `packetFlow.readPackets`
+///
+/// # Returns
+/// * `Ok(())` if successful
+/// * `Err(String)` if no packet flow tunnel is running
+pub async fn write_tunnel_packets(packets: Vec<Vec<u8>>) -> Result<(), String> {
+    #[cfg(target_os = "ios")]
+    {
+        packet_flow()?
+            .write_packets(&packets)
+            .await
+            .map_err(|e| e.to_string())
+    }
+    #[cfg(not(target_os = "ios"))]
+    {
+        let _ = packets;
+        Err("Packet tunnels are only supported on iOS".to_string())
+    }
+}
+
+/// Wait for packets to write to `packetFlow`
+///
+/// # Returns
+/// * `Ok(Vec<Vec<u8>>)` with at least one IP packet
+/// * `Err(String)` once the packet tunnel is stopped
+pub async fn read_tunnel_packets() -> Result<Vec<Vec<u8>>, String> {
+    #[cfg(target_os = "ios")]
+    {
+        packet_flow()?
+            .read_packets()
+            .await
+            .map_err(|e| e.to_string())
+    }
+    #[cfg(not(target_os = "ios"))]
+    {
+        Err("Packet tunnels are only supported on iOS".to_string())
+    }
+}
+
+/// Register the callback calling `VpnService.protect()` for a socket
+///
+/// # Arguments
+/// * `callback` - Protects the given descriptor, returning whether it succeeded
+pub fn set_vpn_protect_callback(
+    callback: impl Fn(i32) -> flutter_rust_bridge::DartFnFuture<bool> + Send + Sync + 'static,
+) {
+    #[cfg(unix)]
+    crate::platform::android::set_protect_callback(Some(std::sync::Arc::new(callback)));
+    #[cfg(not(unix))]
+    let _ = callback;
+}
+
+/// Clear the protect callback, e.g. when the VpnService is destroyed
+pub fn clear_vpn_protect_callback() {
+    #[cfg(unix)]
+    crate::platform::android::set_protect_callback(None);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,4 +1278,65 @@ mod tests {
         // Don't actually set/clear proxy in tests to avoid
         // affecting the development environment
     }
+
+    #[test]
+    fn test_applied_proxy_follows_reassigned_port() {
+        let applied = AppliedSystemProxy::Pac {
+            http_port: 8080,
+            socks_port: 1080,
+        };
+        assert!(matches!(
+            applied.with_reassigned_port("socks", 1080, 40000),
+            Some(AppliedSystemProxy::Pac {
+                http_port: 8080,
+                socks_port: 40000
+            })
+        ));
+
+        let applied = AppliedSystemProxy::Ports {
+            http_port: 8080,
+            socks_port: 1080,
+        };
+        assert!(matches!(
+            applied.with_reassigned_port("http", 8080, 40001),
+            Some(AppliedSystemProxy::Ports {
+                http_port: 40001,
+                socks_port: 1080
+            })
+        ));
+        // Ports the app's proxy does not use
+        assert!(applied.with_reassigned_port("http", 8081, 40001).is_none());
+        assert!(applied.with_reassigned_port("dns", 8080, 40001).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_vpn_tunnel_rejects_invalid_descriptor() {
+        assert!(start_vpn_tunnel(-1, 1080, 1500, None, None).await.is_err());
+        assert!(stop_vpn_tunnel().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tun_mode_disabled_by_default() {
+        assert!(!is_tun_mode_enabled().await);
+        assert!(disable_tun_mode().await.is_ok());
+    }
+
+    #[test]
+    fn test_set_app_paths() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let paths = AppPaths::portable(temp_dir.path());
+        let display = |dir: &std::path::Path| dir.to_string_lossy().into_owned();
+        set_app_paths(AppPathsInfo {
+            config_dir: display(&paths.config_dir),
+            data_dir: display(&paths.data_dir),
+            cache_dir: display(&paths.cache_dir),
+            log_dir: display(&paths.log_dir),
+        })
+        .unwrap();
+
+        let info = get_app_paths();
+        crate::paths::set_app_paths(None);
+        assert_eq!(info.data_dir, display(&paths.data_dir));
+        assert!(paths.log_dir.is_dir());
+    }
 }
@@ -0,0 +1,388 @@
+//! 配置档 Bridge 模块
+//!
+//! 配置档将服务器、代理模式、路由规则集、入站端口和系统代理行为打包，一次操作即可切换。
+//! 配置档和当前配置档保存在应用设置中（见 [`super::settings`]）
+
+use anyhow::{anyhow, Result};
+
+use super::api::{ProfileInfo, SpeedLimitInfo, V8RayEvent};
+use super::settings::{apply_routing_rule_sets, save_settings, settings};
+use crate::config::{Profile, SystemProxyBehavior};
+use crate::connection::rate_limit::RateLimit;
+use crate::error::{PlatformError, V8RayError};
+use crate::proxy_core::CoreKind;
+
+/// 将 FFI 类型转换为核心类型
+fn convert_to_core_profile(profile: ProfileInfo) -> Result<Profile> {
+    Ok(Profile {
+        id: profile.id,
+        name: profile.name,
+        server_id: profile.server_id,
+        mode: profile.mode,
+        routing_rule_sets: profile.routing_rule_set_ids,
+        http_port: profile.http_port,
+        socks_port: profile.socks_port,
+        xray_version: None,
+        core: Default::default(),
+        rate_limit: Default::default(),
+        system_proxy: profile.system_proxy.parse()?,
+    })
+}
+
+/// 将核心类型转换为 FFI 类型
+fn convert_from_core_profile(profile: &Profile) -> ProfileInfo {
+    ProfileInfo {
+        id: profile.id.clone(),
+        name: profile.name.clone(),
+        server_id: profile.server_id.clone(),
+        mode: profile.mode.clone(),
+        routing_rule_set_ids: profile.routing_rule_sets.clone(),
+        http_port: profile.http_port,
+        socks_port: profile.socks_port,
+        system_proxy: profile.system_proxy.as_str().to_string(),
+    }
+}
+
+/// 列出所有配置档
+pub async fn list_profiles() -> Vec<ProfileInfo> {
+    settings()
+        .await
+        .get_all_profiles()
+        .await
+        .iter()
+        .map(convert_from_core_profile)
+        .collect()
+}
+
+/// 添加或更新配置档
+///
+/// 选择的路由规则集必须存在。更新时保留配置档的 Xray 版本、代理核心和限速
+/// （见 [`set_profile_xray_version`]、[`set_profile_core`]、[`set_profile_speed_limit`]）
+pub async fn save_profile(profile: ProfileInfo) -> Result<()> {
+    let mut profile = convert_to_core_profile(profile)?;
+    let settings = settings().await;
+    match settings.get_profile(&profile.id).await {
+        Ok(existing) => {
+            profile.xray_version = existing.xray_version;
+            profile.core = existing.core;
+            profile.rate_limit = existing.rate_limit;
+            let id = profile.id.clone();
+            settings.update_profile(&id, profile).await?;
+        }
+        Err(_) => {
+            settings.add_profile(profile).await?;
+        }
+    }
+    save_settings().await
+}
+
+/// 修改已保存的配置档并保存设置
+async fn update_profile(id: &str, f: impl FnOnce(&mut Profile)) -> Result<()> {
+    let settings = settings().await;
+    let mut profile = settings.get_profile(id).await?;
+    f(&mut profile);
+    settings.update_profile(id, profile).await?;
+    save_settings().await
+}
+
+/// 已保存的配置档
+async fn find_profile(id: &str) -> Result<Profile> {
+    settings()
+        .await
+        .get_profile(id)
+        .await
+        .map_err(|_| anyhow!("Profile not found: {}", id))
+}
+
+/// 设置配置档使用的 Xray 版本，`None` 表示使用当前选中的版本
+///
+/// 版本在切换到配置档时生效，必须已安装
+pub async fn set_profile_xray_version(id: &str, version: Option<String>) -> Result<()> {
+    if let Some(version) = &version {
+        let manager = super::connection::core_connection_manager().await;
+        if !manager
+            .get_xray()
+            .updater()
+            .versions()
+            .is_installed(version)
+        {
+            return Err(anyhow!("Xray Core {} is not installed", version));
+        }
+    }
+
+    update_profile(id, |profile| profile.xray_version = version).await
+}
+
+/// 配置档使用的 Xray 版本
+pub async fn get_profile_xray_version(id: &str) -> Result<Option<String>> {
+    Ok(find_profile(id).await?.xray_version)
+}
+
+/// 设置配置档使用的代理核心（"xray" 或 "sing_box"）
+///
+/// 核心在切换到配置档时生效；仅另一核心支持的协议（如 Hysteria 2）始终在该核心上运行
+pub async fn set_profile_core(id: &str, core: &str) -> Result<()> {
+    let core = CoreKind::parse(core).ok_or_else(|| anyhow!("Unknown proxy core: {}", core))?;
+    update_profile(id, |profile| profile.core = core).await
+}
+
+/// 配置档使用的代理核心
+pub async fn get_profile_core(id: &str) -> Result<String> {
+    Ok(find_profile(id).await?.core.as_str().to_string())
+}
+
+/// 设置配置档的本地入站上传、下载限速，`None` 表示不限速
+///
+/// 限速在切换到配置档时生效
+pub async fn set_profile_speed_limit(id: &str, limit: SpeedLimitInfo) -> Result<()> {
+    let limit = RateLimit {
+        upload_bytes_per_sec: limit.upload_bytes_per_sec,
+        download_bytes_per_sec: limit.download_bytes_per_sec,
+    };
+    limit.validate()?;
+
+    update_profile(id, |profile| profile.rate_limit = limit).await
+}
+
+/// 配置档的本地入站限速
+pub async fn get_profile_speed_limit(id: &str) -> Result<SpeedLimitInfo> {
+    let limit = find_profile(id).await?.rate_limit;
+    Ok(SpeedLimitInfo {
+        upload_bytes_per_sec: limit.upload_bytes_per_sec,
+        download_bytes_per_sec: limit.download_bytes_per_sec,
+    })
+}
+
+/// 删除配置档
+pub async fn delete_profile(id: &str) -> Result<()> {
+    settings()
+        .await
+        .delete_profile(id)
+        .await
+        .map_err(|_| anyhow!("Profile not found: {}", id))?;
+    save_settings().await
+}
+
+/// 当前配置档 ID
+pub async fn get_active_profile() -> Option<String> {
+    settings().await.get_active_profile().await
+}
+
+/// 切换到配置档
+///
+/// 依次应用代理核心、限速、Xray 版本、入站端口和路由规则集，按配置档的模式连接其服务器（未指定服务器时，
+/// 已连接则以新模式重连当前服务器），最后按配置档设置或清除系统代理。
+/// 路由规则集的启用状态保存在设置中；配置档未指定的端口使用设置中的端口，配置档的端口不会写入设置
+pub async fn switch_profile(id: &str) -> Result<()> {
+    let profile = find_profile(id).await?;
+    tracing::info!("Switching to profile: {}", profile.name);
+    let settings = settings().await;
+    let proxy = settings.get_config().await.proxy;
+
+    let core_manager = super::connection::core_connection_manager().await;
+    core_manager.set_core_kind(profile.core);
+    core_manager.set_rate_limit(profile.rate_limit);
+    let xray = core_manager.get_xray();
+    xray.set_pinned_version(profile.xray_version.clone());
+    let (http_port, socks_port) = profile.inbound_ports(&proxy);
+    xray.set_inbound_ports(http_port, socks_port);
+    settings
+        .update_config(|config| profile.apply_rule_sets(&mut config.routing_rules))
+        .await?;
+    apply_routing_rule_sets(&settings).await;
+
+    match &profile.server_id {
+        Some(server_id) => {
+            super::connection::connect_server(server_id, Some(profile.mode.clone())).await?
+        }
+        None => super::connection::apply_proxy_mode(profile.mode.clone()).await?,
+    }
+
+    match profile.system_proxy {
+        SystemProxyBehavior::Keep => {}
+        SystemProxyBehavior::Enable => {
+            let (http_port, socks_port) = super::connection::local_proxy_ports()
+                .await
+                .ok_or_else(|| anyhow!("No local HTTP and SOCKS inbounds for the system proxy"))?;
+            super::platform::set_system_proxy(http_port, socks_port)
+                .map_err(|e| V8RayError::from(PlatformError::SystemProxy(e)))?;
+            super::session::record_system_proxy_applied(format!(
+                "System proxy set by profile {} (HTTP {}, SOCKS {})",
+                profile.name, http_port, socks_port
+            ));
+        }
+        SystemProxyBehavior::Disable => {
+            super::platform::clear_system_proxy()
+                .map_err(|e| V8RayError::from(PlatformError::SystemProxy(e)))?;
+            super::session::record_system_proxy_cleared();
+        }
+    }
+
+    settings.switch_profile(&profile.id).await?;
+    save_settings().await?;
+    let _ = super::events::send_event(V8RayEvent::ProfileSwitched {
+        profile_id: profile.id,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    fn profile(id: &str) -> ProfileInfo {
+        ProfileInfo {
+            id: id.to_string(),
+            name: "Direct".to_string(),
+            server_id: None,
+            mode: "direct".to_string(),
+            routing_rule_set_ids: None,
+            http_port: None,
+            socks_port: None,
+            system_proxy: "keep".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_profile_crud_and_switch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let proxy_mode = super::super::connection::current_proxy_mode().await;
+        let xray = super::super::connection::core_connection_manager()
+            .await
+            .get_xray();
+        let ports = xray.inbound_ports();
+
+        save_profile(profile("direct")).await.unwrap();
+        let mut invalid = profile("invalid");
+        invalid.system_proxy = "on".to_string();
+        assert!(save_profile(invalid).await.is_err());
+        // 选择的路由规则集必须存在
+        let mut unknown_rules = profile("rules");
+        unknown_rules.routing_rule_set_ids = Some(vec!["missing".to_string()]);
+        assert!(save_profile(unknown_rules).await.is_err());
+        assert_eq!(list_profiles().await.len(), 1);
+
+        // 未连接时只切换模式；配置档的端口只在其生效期间使用
+        let mut custom_ports = profile("ports");
+        custom_ports.http_port = Some(18080);
+        custom_ports.socks_port = Some(11080);
+        save_profile(custom_ports).await.unwrap();
+        switch_profile("ports").await.unwrap();
+        assert_eq!(xray.inbound_ports(), (18080, 11080));
+        switch_profile("direct").await.unwrap();
+        assert_eq!(xray.inbound_ports(), (8080, 1080));
+        assert_eq!(get_active_profile().await.as_deref(), Some("direct"));
+        assert!(switch_profile("missing").await.is_err());
+        delete_profile("ports").await.unwrap();
+
+        // 保存的路由规则集在重新加载设置后仍可选择，切换时按配置档启用
+        let rule_set = crate::config::RoutingRuleSet {
+            id: "corp".to_string(),
+            name: "Corp".to_string(),
+            enabled: false,
+            rules: vec![],
+        };
+        settings()
+            .await
+            .add_routing_rule_set(rule_set)
+            .await
+            .unwrap();
+        save_settings().await.unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let mut rules = profile("rules");
+        rules.routing_rule_set_ids = Some(vec!["corp".to_string()]);
+        save_profile(rules).await.unwrap();
+        switch_profile("rules").await.unwrap();
+        assert!(xray.get_routing_rule_sets()[0].enabled);
+        assert!(
+            settings()
+                .await
+                .get_routing_rule_set("corp")
+                .await
+                .unwrap()
+                .enabled
+        );
+        delete_profile("rules").await.unwrap();
+        settings()
+            .await
+            .delete_routing_rule_set("corp")
+            .await
+            .unwrap();
+        switch_profile("direct").await.unwrap();
+
+        // 未安装的 Xray 版本不能设置，已有的版本在保存时保留
+        assert!(
+            set_profile_xray_version("direct", Some("1.0.0".to_string()))
+                .await
+                .is_err()
+        );
+        update_profile("direct", |profile| {
+            profile.xray_version = Some("1.0.0".to_string())
+        })
+        .await
+        .unwrap();
+        save_profile(profile("direct")).await.unwrap();
+        assert_eq!(
+            get_profile_xray_version("direct").await.unwrap().as_deref(),
+            Some("1.0.0")
+        );
+        set_profile_xray_version("direct", None).await.unwrap();
+        assert_eq!(get_profile_xray_version("direct").await.unwrap(), None);
+
+        // 代理核心同样在保存时保留
+        assert!(set_profile_core("direct", "clash").await.is_err());
+        set_profile_core("direct", "sing-box").await.unwrap();
+        save_profile(profile("direct")).await.unwrap();
+        assert_eq!(get_profile_core("direct").await.unwrap(), "sing_box");
+
+        // 限速同样在保存时保留
+        let limit = SpeedLimitInfo {
+            upload_bytes_per_sec: None,
+            download_bytes_per_sec: Some(512 * 1024),
+        };
+        assert!(set_profile_speed_limit(
+            "direct",
+            SpeedLimitInfo {
+                upload_bytes_per_sec: Some(0),
+                download_bytes_per_sec: None,
+            }
+        )
+        .await
+        .is_err());
+        set_profile_speed_limit("direct", limit.clone())
+            .await
+            .unwrap();
+        save_profile(profile("direct")).await.unwrap();
+        assert_eq!(get_profile_speed_limit("direct").await.unwrap(), limit);
+
+        // 配置档、各项设置和当前配置档保存在配置文件中
+        let saved = crate::config::manager::ConfigManager::new(
+            temp_dir
+                .path()
+                .join(super::super::settings::SETTINGS_FILE_NAME),
+        );
+        saved.load().await.unwrap();
+        let direct = saved.get_profile("direct").await.unwrap();
+        assert_eq!(direct.core, CoreKind::SingBox);
+        assert_eq!(direct.rate_limit.download_bytes_per_sec, Some(512 * 1024));
+        assert_eq!(saved.get_active_profile().await.as_deref(), Some("direct"));
+
+        delete_profile("direct").await.unwrap();
+        assert!(get_active_profile().await.is_none());
+        assert!(list_profiles().await.is_empty());
+        assert!(delete_profile("direct").await.is_err());
+
+        super::super::connection::apply_proxy_mode(proxy_mode)
+            .await
+            .unwrap();
+        xray.set_inbound_ports(ports.0, ports.1);
+        super::super::settings::reset_settings().await;
+    }
+}
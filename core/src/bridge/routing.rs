@@ -0,0 +1,723 @@
+//! 路由规则集、分应用路由、路由建议与路由测试 Bridge 模块
+//!
+//! 路由规则集、应用规则、DNS、直连优先和脚本路由设置保存在应用设置中（见 [`super::settings`]），
+//! 修改后同步到 Xray 配置生成器
+
+use anyhow::{anyhow, Result};
+
+use super::api::{
+    AppRuleInfo, DirectPreferenceSettingsInfo, DirectProbeResultInfo, DnsServerInfo,
+    DnsSettingsInfo, FakeDnsInfo, RouteSuggestionInfo, RouteTestInfo, RoutingRuleInfo,
+    RoutingRuleSetInfo, ScriptRoutingSettingsInfo, SplitDnsRuleInfo,
+};
+use super::settings::{
+    apply_app_rules, apply_dns_settings, apply_routing_rule_sets, save_settings, settings,
+};
+use crate::config::dns::{DnsServer, FakeDnsSettings, SplitDnsRule};
+use crate::config::routing::PROCESS_RULES_SUPPORTED;
+use crate::config::{AppRule, DirectPreferenceSettings, RoutingRule, RoutingRuleSet};
+use crate::connection::script_routing::{
+    ConnectionMeta, ScriptRoutingSettings, DEFAULT_SCRIPT_TIMEOUT_MS,
+};
+use crate::connection::suggestions::RouteSuggestion;
+
+/// 将 FFI 类型转换为核心类型
+fn convert_to_core_rule_set(rule_set: RoutingRuleSetInfo) -> RoutingRuleSet {
+    RoutingRuleSet {
+        id: rule_set.id,
+        name: rule_set.name,
+        enabled: rule_set.enabled,
+        rules: rule_set
+            .rules
+            .into_iter()
+            .map(|rule| RoutingRule {
+                domains: rule.domains,
+                ips: rule.ips,
+                port: rule.port,
+                processes: rule.processes,
+                protocols: rule.protocols,
+                outbound_tag: rule.outbound,
+            })
+            .collect(),
+    }
+}
+
+/// 将核心类型转换为 FFI 类型
+fn convert_from_core_rule_set(rule_set: &RoutingRuleSet) -> RoutingRuleSetInfo {
+    RoutingRuleSetInfo {
+        id: rule_set.id.clone(),
+        name: rule_set.name.clone(),
+        enabled: rule_set.enabled,
+        rules: rule_set
+            .rules
+            .iter()
+            .map(|rule| RoutingRuleInfo {
+                domains: rule.domains.clone(),
+                ips: rule.ips.clone(),
+                port: rule.port.clone(),
+                processes: rule.processes.clone(),
+                protocols: rule.protocols.clone(),
+                outbound: rule.outbound_tag.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// 列出路由规则集（按匹配顺序）
+pub async fn list_routing_rule_sets() -> Vec<RoutingRuleSetInfo> {
+    settings()
+        .await
+        .get_all_routing_rule_sets()
+        .await
+        .iter()
+        .map(convert_from_core_rule_set)
+        .collect()
+}
+
+/// 添加或更新路由规则集并保存设置（下次连接时生效）
+///
+/// 新规则集排在最后，更新时保持原位置
+pub async fn save_routing_rule_set(rule_set: RoutingRuleSetInfo) -> Result<()> {
+    let rule_set = convert_to_core_rule_set(rule_set);
+    let settings = settings().await;
+    if settings.get_routing_rule_set(&rule_set.id).await.is_ok() {
+        let id = rule_set.id.clone();
+        settings.update_routing_rule_set(&id, rule_set).await?;
+    } else {
+        settings.add_routing_rule_set(rule_set).await?;
+    }
+    save_settings().await?;
+    apply_routing_rule_sets(&settings).await;
+    Ok(())
+}
+
+/// 删除路由规则集并保存设置，选择了它的配置档不再引用它
+pub async fn delete_routing_rule_set(id: &str) -> Result<()> {
+    let settings = settings().await;
+    settings
+        .delete_routing_rule_set(id)
+        .await
+        .map_err(|_| anyhow!("Routing rule set not found: {}", id))?;
+    save_settings().await?;
+    apply_routing_rule_sets(&settings).await;
+    Ok(())
+}
+
+/// 将 FFI 类型转换为核心类型
+fn convert_to_core_rule(rule: AppRuleInfo) -> AppRule {
+    AppRule {
+        id: rule.id,
+        process_name: rule.process_name,
+        outbound_tag: rule.outbound,
+        enabled: rule.enabled,
+    }
+}
+
+/// 将核心类型转换为 FFI 类型
+fn convert_from_core_rule(rule: &AppRule) -> AppRuleInfo {
+    AppRuleInfo {
+        id: rule.id.clone(),
+        process_name: rule.process_name.clone(),
+        outbound: rule.outbound_tag.clone(),
+        enabled: rule.enabled,
+    }
+}
+
+/// 当前平台是否支持分应用路由
+pub fn is_supported() -> bool {
+    PROCESS_RULES_SUPPORTED
+}
+
+/// 列出所有应用规则
+pub async fn list_app_rules() -> Vec<AppRuleInfo> {
+    settings()
+        .await
+        .get_app_rules()
+        .await
+        .iter()
+        .map(convert_from_core_rule)
+        .collect()
+}
+
+/// 添加或更新应用规则并保存设置（下次连接时生效）
+pub async fn save_app_rule(rule: AppRuleInfo) -> Result<()> {
+    let settings = settings().await;
+    settings.set_app_rule(convert_to_core_rule(rule)).await?;
+    save_settings().await?;
+    apply_app_rules(&settings).await;
+    Ok(())
+}
+
+/// 删除应用规则并保存设置
+pub async fn delete_app_rule(id: &str) -> Result<()> {
+    let settings = settings().await;
+    settings
+        .delete_app_rule(id)
+        .await
+        .map_err(|_| anyhow!("App rule not found: {}", id))?;
+    save_settings().await?;
+    apply_app_rules(&settings).await;
+    Ok(())
+}
+
+/// 将核心路由建议转换为 FFI 类型
+fn convert_suggestion(suggestion: RouteSuggestion) -> RouteSuggestionInfo {
+    RouteSuggestionInfo {
+        description: suggestion.description(),
+        id: suggestion.id,
+        domain: suggestion.domain,
+        failures: suggestion.failures,
+        last_failure: suggestion.last_failure.timestamp_millis(),
+    }
+}
+
+/// 列出路由建议
+pub fn list_route_suggestions() -> Result<Vec<RouteSuggestionInfo>> {
+    let manager = super::connection::get_core_connection_manager()?;
+    Ok(manager
+        .get_route_suggestions()
+        .into_iter()
+        .map(convert_suggestion)
+        .collect())
+}
+
+/// 接受路由建议，规则写入设置中的 "suggested" 路由规则集并保存
+pub async fn accept_route_suggestion(id: &str) -> Result<()> {
+    let manager = super::connection::core_connection_manager().await;
+    let settings = settings().await;
+    let mut accepted = false;
+    settings
+        .update_config(|config| {
+            accepted = manager
+                .accept_route_suggestion(id, &mut config.routing_rules)
+                .is_some();
+        })
+        .await?;
+    if !accepted {
+        return Err(anyhow!("Route suggestion not found: {}", id));
+    }
+    save_settings().await?;
+    apply_routing_rule_sets(&settings).await;
+    Ok(())
+}
+
+/// 忽略路由建议
+pub fn dismiss_route_suggestion(id: &str) -> Result<()> {
+    let manager = super::connection::get_core_connection_manager()?;
+    manager.dismiss_route_suggestion(id);
+    Ok(())
+}
+
+/// 获取 DNS 设置
+pub async fn get_dns_settings() -> DnsSettingsInfo {
+    let dns = settings().await.get_dns_settings().await;
+    DnsSettingsInfo {
+        servers: dns
+            .servers
+            .into_iter()
+            .map(|server| DnsServerInfo {
+                address: server.address,
+                port: server.port,
+                domains: server.domains,
+                expect_ips: server.expect_ips,
+                skip_fallback: server.skip_fallback,
+            })
+            .collect(),
+        hosts: dns.hosts,
+        query_strategy: dns.query_strategy.as_xray_str().to_string(),
+        client_ip: dns.client_ip,
+        fake_dns: dns.fake_dns.map(|fake_dns| FakeDnsInfo {
+            ip_pool: fake_dns.ip_pool,
+            pool_size: fake_dns.pool_size,
+        }),
+        disable_cache: dns.disable_cache,
+    }
+}
+
+/// 修改 DNS 设置并保存（下次连接时生效），分域 DNS 规则保持不变
+pub async fn set_dns_settings(info: DnsSettingsInfo) -> Result<()> {
+    let settings = settings().await;
+    let mut dns = settings.get_dns_settings().await;
+    dns.servers = info
+        .servers
+        .into_iter()
+        .map(|server| DnsServer {
+            address: server.address.trim().to_string(),
+            port: server.port,
+            domains: server.domains,
+            expect_ips: server.expect_ips,
+            skip_fallback: server.skip_fallback,
+        })
+        .collect();
+    dns.hosts = info.hosts;
+    dns.query_strategy = info.query_strategy.parse()?;
+    dns.client_ip = info.client_ip.map(|ip| ip.trim().to_string());
+    dns.fake_dns = info.fake_dns.map(|fake_dns| FakeDnsSettings {
+        ip_pool: fake_dns.ip_pool.trim().to_string(),
+        pool_size: fake_dns.pool_size,
+    });
+    dns.disable_cache = info.disable_cache;
+
+    settings.set_dns_settings(dns).await?;
+    save_settings().await?;
+    apply_dns_settings(&settings).await;
+    Ok(())
+}
+
+/// 列出分域 DNS 规则
+pub async fn list_split_dns_rules() -> Vec<SplitDnsRuleInfo> {
+    settings()
+        .await
+        .get_dns_settings()
+        .await
+        .split_rules
+        .iter()
+        .map(|rule| SplitDnsRuleInfo {
+            domains: rule.domains.clone(),
+            servers: rule.servers.iter().map(|ip| ip.to_string()).collect(),
+        })
+        .collect()
+}
+
+/// 替换分域 DNS 规则并保存设置（下次连接时生效）
+pub async fn set_split_dns_rules(rules: Vec<SplitDnsRuleInfo>) -> Result<()> {
+    let mut split_rules = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let servers = rule
+            .servers
+            .iter()
+            .map(|server| {
+                server
+                    .trim()
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid DNS server address: {}", server))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        split_rules.push(SplitDnsRule::new(rule.domains, servers));
+    }
+
+    let settings = settings().await;
+    let mut dns = settings.get_dns_settings().await;
+    dns.split_rules = split_rules;
+    settings.set_dns_settings(dns).await?;
+    save_settings().await?;
+    apply_dns_settings(&settings).await;
+    Ok(())
+}
+
+/// 获取直连优先设置
+pub async fn get_direct_preference_settings() -> DirectPreferenceSettingsInfo {
+    let settings = settings().await.get_direct_preference().await;
+    DirectPreferenceSettingsInfo {
+        enabled: settings.enabled,
+        probe_domains: settings.probe_domains,
+        max_slowdown_ms: settings.max_slowdown_ms,
+    }
+}
+
+/// 设置直连优先并保存
+pub async fn set_direct_preference_settings(settings: DirectPreferenceSettingsInfo) -> Result<()> {
+    let direct_preference = DirectPreferenceSettings {
+        enabled: settings.enabled,
+        probe_domains: settings
+            .probe_domains
+            .iter()
+            .map(|domain| domain.trim().to_lowercase())
+            .filter(|domain| !domain.is_empty())
+            .collect(),
+        max_slowdown_ms: settings.max_slowdown_ms,
+    };
+    super::settings::settings()
+        .await
+        .set_direct_preference(direct_preference)
+        .await?;
+    save_settings().await?;
+    Ok(())
+}
+
+/// 探测直连优先域名，结果写入设置中的 "direct-preferred" 路由规则集（下次连接时生效）
+pub async fn probe_direct_preference() -> Result<Vec<DirectProbeResultInfo>> {
+    let settings = settings().await;
+    let manager = super::connection::core_connection_manager().await;
+    let results = manager
+        .probe_direct_preference(&settings.get_direct_preference().await)
+        .await?;
+
+    let mut changed = false;
+    settings
+        .update_config(|config| {
+            changed = manager.apply_direct_preference(&results, &mut config.routing_rules);
+        })
+        .await?;
+    if changed {
+        save_settings().await?;
+        apply_routing_rule_sets(&settings).await;
+    }
+
+    Ok(results
+        .into_iter()
+        .map(|result| DirectProbeResultInfo {
+            domain: result.domain,
+            direct_ms: result.direct_ms,
+            proxy_ms: result.proxy_ms,
+            prefer_direct: result.prefer_direct,
+        })
+        .collect())
+}
+
+/// 获取脚本路由设置
+pub async fn get_script_routing_settings() -> ScriptRoutingSettingsInfo {
+    let settings = settings().await.get_script_routing().await;
+    ScriptRoutingSettingsInfo {
+        enabled: settings.enabled,
+        script: settings.script,
+        timeout_ms: settings.timeout_ms.min(u32::MAX as u64) as u32,
+    }
+}
+
+/// 设置脚本路由并保存（连接中修改脚本立即生效，开启在下次连接时生效）
+pub async fn set_script_routing_settings(settings: ScriptRoutingSettingsInfo) -> Result<()> {
+    let script_routing = ScriptRoutingSettings {
+        enabled: settings.enabled,
+        script: settings.script,
+        timeout_ms: settings.timeout_ms.into(),
+    };
+    super::connection::core_connection_manager()
+        .await
+        .set_script_routing(script_routing.clone())?;
+    super::settings::settings()
+        .await
+        .set_script_routing(script_routing)
+        .await;
+    save_settings().await?;
+    Ok(())
+}
+
+/// 用脚本测试目标会走哪个出站（为空表示按路由规则处理）
+pub fn test_routing_script(script: &str, host: &str, port: u16) -> Result<Option<String>> {
+    let script = ScriptRoutingSettings {
+        enabled: true,
+        script: script.to_string(),
+        timeout_ms: DEFAULT_SCRIPT_TIMEOUT_MS,
+    }
+    .compile()?;
+    Ok(script.route(&ConnectionMeta {
+        host: host.trim().to_string(),
+        port,
+        inbound: "socks".to_string(),
+    }))
+}
+
+/// 测试目标（域名或 IP）在当前路由规则下会走哪个出站
+pub fn test_route(target: &str, port: u16, protocol: Option<String>) -> Result<RouteTestInfo> {
+    let manager = super::connection::get_core_connection_manager()?;
+    let result = super::connection::TOKIO_RUNTIME.block_on(async {
+        let engine = manager.get_xray().routing_engine().await?;
+        engine.match_target(target, port, protocol.as_deref()).await
+    })?;
+    Ok(RouteTestInfo {
+        rule_index: result.rule_index.map(|i| i as u32),
+        rule: result.rule.map(|rule| rule.to_string()),
+        outbound_tag: result.outbound_tag,
+        balancer_tag: result.balancer_tag,
+        resolved_ips: result
+            .resolved_ips
+            .iter()
+            .map(|ip| ip.to_string())
+            .collect(),
+        skipped_rules: result.skipped_rules.iter().map(|&i| i as u32).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_routing_rule_set_crud() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let xray = super::super::connection::core_connection_manager()
+            .await
+            .get_xray();
+        let mut rule_set = RoutingRuleSetInfo {
+            id: "corp".to_string(),
+            name: "Corp".to_string(),
+            enabled: true,
+            rules: vec![RoutingRuleInfo {
+                domains: vec!["domain:corp.example.com".to_string()],
+                ips: vec![],
+                port: None,
+                processes: vec![],
+                protocols: vec![],
+                outbound: "direct".to_string(),
+            }],
+        };
+
+        save_routing_rule_set(rule_set.clone()).await.unwrap();
+        rule_set.enabled = false;
+        save_routing_rule_set(rule_set.clone()).await.unwrap();
+        assert_eq!(list_routing_rule_sets().await, vec![rule_set.clone()]);
+        assert_eq!(xray.get_routing_rule_sets().len(), 1);
+        assert!(!xray.get_routing_rule_sets()[0].enabled);
+        let mut invalid = rule_set.clone();
+        invalid.rules[0].domains.clear();
+        assert!(save_routing_rule_set(invalid).await.is_err());
+
+        // 重新加载设置后规则集仍会应用到 Xray
+        xray.set_routing_rule_sets(Vec::new());
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(xray.get_routing_rule_sets()[0].id, "corp");
+
+        delete_routing_rule_set("corp").await.unwrap();
+        assert!(delete_routing_rule_set("corp").await.is_err());
+        assert!(xray.get_routing_rule_sets().is_empty());
+        super::super::settings::reset_settings().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_app_rule_crud() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let rule = AppRuleInfo {
+            id: "test-telegram".to_string(),
+            process_name: "Telegram".to_string(),
+            outbound: "proxy".to_string(),
+            enabled: true,
+        };
+
+        save_app_rule(rule.clone()).await.unwrap();
+        assert!(list_app_rules()
+            .await
+            .iter()
+            .any(|r| r.id == "test-telegram"));
+
+        // 重新加载设置后规则仍在
+        super::super::settings::reset_settings().await;
+        assert!(list_app_rules().await.is_empty());
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(list_app_rules().await.len(), 1);
+
+        delete_app_rule("test-telegram").await.unwrap();
+        assert!(delete_app_rule("test-telegram").await.is_err());
+        super::super::settings::reset_settings().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_dns_settings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let xray = super::super::connection::core_connection_manager()
+            .await
+            .get_xray();
+        let mut info = get_dns_settings().await;
+        assert_eq!(info.query_strategy, "UseIP");
+        info.servers.push(DnsServerInfo {
+            address: "1.1.1.1".to_string(),
+            port: None,
+            domains: vec![],
+            expect_ips: vec![],
+            skip_fallback: false,
+        });
+        info.query_strategy = "UseIPv4".to_string();
+        set_dns_settings(info.clone()).await.unwrap();
+        assert_eq!(get_dns_settings().await, info);
+        assert_eq!(xray.dns_settings().servers[0].address, "1.1.1.1");
+
+        let mut invalid = info.clone();
+        invalid.query_strategy = "ipv4".to_string();
+        assert!(set_dns_settings(invalid).await.is_err());
+        let mut invalid = info.clone();
+        invalid.client_ip = Some("not-an-ip".to_string());
+        assert!(set_dns_settings(invalid).await.is_err());
+
+        // 重新加载设置后应用到 Xray
+        xray.set_dns_settings(Default::default());
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert!(xray.dns_settings().is_configured());
+
+        super::super::settings::reset_settings().await;
+        xray.set_dns_settings(Default::default());
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_split_dns_rules() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let rule = SplitDnsRuleInfo {
+            domains: vec!["*.corp.internal".to_string()],
+            servers: vec!["10.0.0.53".to_string()],
+        };
+        set_split_dns_rules(vec![rule]).await.unwrap();
+        let rules = list_split_dns_rules().await;
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].servers, vec!["10.0.0.53"]);
+
+        let invalid = SplitDnsRuleInfo {
+            domains: vec!["corp.internal".to_string()],
+            servers: vec!["dns.corp.internal".to_string()],
+        };
+        assert!(set_split_dns_rules(vec![invalid]).await.is_err());
+        assert_eq!(list_split_dns_rules().await.len(), 1);
+
+        // 规则保存在设置中，重新加载后应用到 Xray
+        let xray = super::super::connection::core_connection_manager()
+            .await
+            .get_xray();
+        xray.set_dns_settings(Default::default());
+        super::super::settings::reset_settings().await;
+        assert!(list_split_dns_rules().await.is_empty());
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(list_split_dns_rules().await.len(), 1);
+        assert_eq!(xray.dns_settings().split_rules.len(), 1);
+
+        set_split_dns_rules(vec![]).await.unwrap();
+        assert!(list_split_dns_rules().await.is_empty());
+        assert!(xray.dns_settings().split_rules.is_empty());
+        super::super::settings::reset_settings().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[serial]
+    async fn test_unknown_route_suggestion() {
+        assert!(accept_route_suggestion("unknown.example").await.is_err());
+        assert!(settings().await.get_config().await.routing_rules.is_empty());
+        assert!(dismiss_route_suggestion("unknown.example").is_ok());
+        assert!(list_route_suggestions()
+            .unwrap()
+            .iter()
+            .all(|s| s.id != "unknown.example"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_direct_preference_settings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        let settings = DirectPreferenceSettingsInfo {
+            enabled: true,
+            probe_domains: vec![" Example.COM ".to_string(), "".to_string()],
+            max_slowdown_ms: 30,
+        };
+        set_direct_preference_settings(settings.clone())
+            .await
+            .unwrap();
+        let stored = get_direct_preference_settings().await;
+        assert!(stored.enabled);
+        assert_eq!(stored.probe_domains, vec!["example.com"]);
+
+        let invalid = DirectPreferenceSettingsInfo {
+            probe_domains: vec!["not a domain".to_string()],
+            ..settings
+        };
+        assert!(set_direct_preference_settings(invalid).await.is_err());
+        assert_eq!(get_direct_preference_settings().await.max_slowdown_ms, 30);
+
+        // 设置在重新加载后保留
+        super::super::settings::reset_settings().await;
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            get_direct_preference_settings().await.probe_domains,
+            vec!["example.com"]
+        );
+
+        set_direct_preference_settings(DirectPreferenceSettingsInfo {
+            enabled: false,
+            probe_domains: vec![],
+            max_slowdown_ms: 50,
+        })
+        .await
+        .unwrap();
+        assert!(probe_direct_preference().await.is_err());
+        super::super::settings::reset_settings().await;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_script_routing_settings() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert!(!get_script_routing_settings().await.enabled);
+        let script = r#"fn route(conn) { if conn.host == "nas.lan" { "direct" } }"#;
+        set_script_routing_settings(ScriptRoutingSettingsInfo {
+            enabled: true,
+            script: script.to_string(),
+            timeout_ms: 20,
+        })
+        .await
+        .unwrap();
+        assert_eq!(get_script_routing_settings().await.timeout_ms, 20);
+        assert!(set_script_routing_settings(ScriptRoutingSettingsInfo {
+            enabled: true,
+            script: "fn other() {}".to_string(),
+            timeout_ms: 20,
+        })
+        .await
+        .is_err());
+        assert_eq!(get_script_routing_settings().await.script, script);
+
+        // 重新加载设置后脚本应用到连接管理器
+        let manager = super::super::connection::core_connection_manager().await;
+        manager
+            .set_script_routing(ScriptRoutingSettings::default())
+            .unwrap();
+        super::super::settings::init_settings(temp_dir.path().to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(manager.script_routing().script, script);
+
+        assert_eq!(
+            test_routing_script(script, "nas.lan", 80)
+                .unwrap()
+                .as_deref(),
+            Some("direct")
+        );
+        assert_eq!(
+            test_routing_script(script, "example.com", 80).unwrap(),
+            None
+        );
+        assert!(test_routing_script("fn route(", "example.com", 80).is_err());
+
+        set_script_routing_settings(ScriptRoutingSettingsInfo {
+            enabled: false,
+            script: String::new(),
+            timeout_ms: DEFAULT_SCRIPT_TIMEOUT_MS as u32,
+        })
+        .await
+        .unwrap();
+        super::super::settings::reset_settings().await;
+    }
+
+    #[test]
+    #[serial]
+    fn test_route_requires_connection() {
+        assert!(test_route("example.com", 443, None).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+//! 运行状态持久化 Bridge 模块
+//!
+//! 连接、Xray 进程、系统代理和 TUN 状态变化时写入状态文件，启动时据此判断上次是否异常退出，
+//! 并可按用户上次留下的状态（服务器、代理模式、连接和系统代理）恢复会话
+
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use std::sync::RwLock;
+
+use super::api::{PreviousSessionInfo, SessionRestoreInfo, V8RayEvent};
+use crate::connection::runtime_state::{RuntimeState, RuntimeStateStore, STATE_FILE_NAME};
+use crate::connection::timeline::SessionEventKind;
+use crate::error::{PlatformError, V8RayError};
+use crate::xray::XrayCore;
+
+lazy_static::lazy_static! {
+    static ref RUNTIME_STATE: RwLock<Option<RuntimeStateStore>> = RwLock::new(None);
+}
+
+/// 打开状态文件，返回上次会话的状态
+pub fn init_runtime_state(data_dir: &str) -> Result<PreviousSessionInfo> {
+    let store = RuntimeStateStore::open(Path::new(data_dir).join(STATE_FILE_NAME));
+    let previous = store.state();
+    if !previous.is_clean() {
+        tracing::warn!("Previous session did not shut down cleanly: {:?}", previous);
+    }
+    *RUNTIME_STATE.write().unwrap_or_else(|e| e.into_inner()) = Some(store);
+    Ok(to_previous_session_info(&previous))
+}
+
+/// 终止上次会话遗留的 Xray 进程，撤销遗留的系统代理和 TUN 路由
+///
+/// 记录的 PID 仅在仍为 Xray 进程时才终止（PID 可能已被其他进程复用）；
+/// 系统代理恢复为上次会话设置前保存的配置；保留服务器和代理模式，以便恢复上次会话。
+/// 有遗留时发送 `PreviousSessionRecovered` 事件
+pub async fn cleanup_previous_session() -> Result<()> {
+    let state = current_state()?;
+
+    let orphan_pid = state
+        .xray_pid
+        .filter(|pid| crate::xray::process::is_xray_process(*pid));
+    if let Some(pid) = orphan_pid {
+        tracing::info!("Killing Xray process left by the previous session");
+        XrayCore::kill_process(pid).await;
+    }
+    if state.system_proxy_applied {
+        tracing::info!("Clearing system proxy left by the previous session");
+        super::platform::restore_system_proxy_snapshot(state.proxy_snapshot.clone());
+        super::platform::clear_system_proxy().map_err(|e| anyhow!(e))?;
+    }
+    if state.tun_enabled() {
+        tracing::info!("Removing TUN routes left by the previous session");
+        crate::platform::tun::run_teardown(&state.tun_teardown).await;
+    }
+
+    update_runtime_state(|s| {
+        s.connected = false;
+        s.xray_pid = None;
+        s.system_proxy_applied = false;
+        s.tun_teardown.clear();
+        s.proxy_snapshot = None;
+    });
+
+    if !state.is_clean() {
+        let _ = super::events::send_event(V8RayEvent::PreviousSessionRecovered {
+            xray_pid: orphan_pid,
+            system_proxy_cleared: state.system_proxy_applied,
+            tun_routes_removed: state.tun_enabled(),
+        });
+    }
+    Ok(())
+}
+
+/// 按上次会话重新连接，并恢复系统代理
+pub async fn restore_last_session() -> Result<SessionRestoreInfo> {
+    let state = current_state()?;
+    let Some(plan) = state.last_session.restore_plan(state.reconnect_on_launch) else {
+        return Ok(SessionRestoreInfo {
+            restored: false,
+            server_id: None,
+            proxy_mode: None,
+            system_proxy: false,
+        });
+    };
+
+    tracing::info!("Restoring last session: {:?}", plan);
+    super::connection::connect_server(&plan.server_id, Some(plan.mode.clone())).await?;
+    if plan.system_proxy {
+        let (http_port, socks_port) = super::connection::local_proxy_ports()
+            .await
+            .ok_or_else(|| anyhow!("No local HTTP and SOCKS inbounds for the system proxy"))?;
+        super::platform::set_system_proxy(http_port, socks_port)
+            .map_err(|e| V8RayError::from(PlatformError::SystemProxy(e)))?;
+        record_system_proxy_applied(format!(
+            "System proxy restored (HTTP {}, SOCKS {})",
+            http_port, socks_port
+        ));
+    }
+
+    Ok(SessionRestoreInfo {
+        restored: true,
+        server_id: Some(plan.server_id),
+        proxy_mode: Some(plan.mode),
+        system_proxy: plan.system_proxy,
+    })
+}
+
+/// 设置启动时是否恢复上次会话
+pub fn set_reconnect_on_launch(enabled: bool) -> Result<()> {
+    let store = RUNTIME_STATE.read().unwrap_or_else(|e| e.into_inner());
+    store
+        .as_ref()
+        .ok_or_else(|| anyhow!("Runtime state not initialized"))?
+        .update(|s| s.reconnect_on_launch = enabled)?;
+    Ok(())
+}
+
+/// 获取启动时是否恢复上次会话，未初始化时为 false
+pub fn get_reconnect_on_launch() -> bool {
+    current_state().is_ok_and(|state| state.reconnect_on_launch)
+}
+
+/// 记录系统代理已设置
+pub(crate) fn record_system_proxy_applied(message: String) {
+    update_runtime_state(|s| {
+        s.system_proxy_applied = true;
+        s.last_session.system_proxy = true;
+    });
+    super::connection::record_session_event(SessionEventKind::ProxyApplied, message);
+}
+
+/// 记录系统代理已由用户清除
+pub(crate) fn record_system_proxy_cleared() {
+    update_runtime_state(|s| {
+        s.system_proxy_applied = false;
+        s.last_session.system_proxy = false;
+    });
+    super::connection::record_session_event(
+        SessionEventKind::ProxyCleared,
+        "System proxy cleared".to_string(),
+    );
+}
+
+/// 当前运行状态
+fn current_state() -> Result<RuntimeState> {
+    let store = RUNTIME_STATE.read().unwrap_or_else(|e| e.into_inner());
+    Ok(store
+        .as_ref()
+        .ok_or_else(|| anyhow!("Runtime state not initialized"))?
+        .state())
+}
+
+/// 更新并写入运行状态，未初始化时忽略
+pub(crate) fn update_runtime_state(f: impl FnOnce(&mut RuntimeState)) {
+    let store = RUNTIME_STATE.read().unwrap_or_else(|e| e.into_inner());
+    if let Some(store) = store.as_ref() {
+        if let Err(e) = store.update(f) {
+            tracing::warn!("Failed to write runtime state: {}", e);
+        }
+    }
+}
+
+/// 转换为 FFI 结构
+fn to_previous_session_info(state: &RuntimeState) -> PreviousSessionInfo {
+    PreviousSessionInfo {
+        unclean_shutdown: !state.is_clean(),
+        was_connected: state.connected,
+        server_id: state.server_id.clone(),
+        proxy_mode: state.mode.clone(),
+        system_proxy_applied: state.system_proxy_applied,
+        tun_enabled: state.tun_enabled(),
+        updated_at: state.updated_at.timestamp_millis(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serial_test::serial;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_previous_session() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+
+        let previous = init_runtime_state(data_dir).unwrap();
+        assert!(!previous.unclean_shutdown);
+
+        update_runtime_state(|s| {
+            s.connected = true;
+            s.server_id = Some("server-1".to_string());
+            s.mode = Some("global".to_string());
+        });
+
+        // 未断开就重新启动
+        let previous = init_runtime_state(data_dir).unwrap();
+        assert!(previous.unclean_shutdown);
+        assert!(previous.was_connected);
+        assert_eq!(previous.server_id.as_deref(), Some("server-1"));
+
+        // 记录的 PID 已被其他进程复用，不应终止
+        #[cfg(unix)]
+        {
+            let mut other = std::process::Command::new("sleep")
+                .arg("30")
+                .spawn()
+                .unwrap();
+            update_runtime_state(|s| s.xray_pid = Some(other.id()));
+
+            cleanup_previous_session().await.unwrap();
+            assert!(other.try_wait().unwrap().is_none());
+            other.kill().unwrap();
+            other.wait().unwrap();
+        }
+        #[cfg(not(unix))]
+        cleanup_previous_session().await.unwrap();
+        let previous = init_runtime_state(data_dir).unwrap();
+        assert!(!previous.unclean_shutdown);
+        assert_eq!(previous.proxy_mode.as_deref(), Some("global"));
+
+        *RUNTIME_STATE.write().unwrap() = None;
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_restore_last_session() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let data_dir = temp_dir.path().to_str().unwrap();
+        init_runtime_state(data_dir).unwrap();
+        assert!(!get_reconnect_on_launch());
+
+        // 正常退出后连接已断开，但用户留下的状态仍在
+        update_runtime_state(|s| {
+            s.last_session.connected_to("server-1", "global");
+            s.last_session.system_proxy = true;
+        });
+        let restore = restore_last_session().await.unwrap();
+        assert!(!restore.restored);
+
+        set_reconnect_on_launch(true).unwrap();
+        init_runtime_state(data_dir).unwrap();
+        assert!(get_reconnect_on_launch());
+
+        // 用户主动断开后不恢复
+        update_runtime_state(|s| s.last_session.connected = false);
+        let restore = restore_last_session().await.unwrap();
+        assert!(!restore.restored);
+
+        *RUNTIME_STATE.write().unwrap() = None;
+        assert!(set_reconnect_on_launch(true).is_err());
+        assert!(restore_last_session().await.is_err());
+    }
+}
@@ -0,0 +1,283 @@
+//! 应用设置 Bridge 模块
+//!
+//! 配置档、路由规则等设置由核心 [`ConfigManager`] 保存在配置目录的配置文件中，
+//! 设置是唯一的数据来源：加载时和每次修改后同步到 Xray 配置生成器。
+//! 调用 [`init_settings`] 之前设置只保存在内存中。
+//!
+//! 设置了加密密码时，配置文件和订阅数据库中的凭据用同一个密钥加密，
+//! 密钥可以保存在系统凭据库中，启动时无需再输入密码
+
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+
+use crate::config::backup::{self, BACKUP_DIR_NAME};
+use crate::config::manager::ConfigManager;
+use crate::platform::credentials::{self, CredentialStore};
+
+/// 配置目录中的配置文件名
+pub const SETTINGS_FILE_NAME: &str = "config.json";
+
+lazy_static::lazy_static! {
+    static ref SETTINGS: RwLock<ConfigManager> = RwLock::new(ConfigManager::new(
+        crate::paths::app_paths().config_dir.join(SETTINGS_FILE_NAME),
+    ));
+    static ref BACKUP_TASK: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
+}
+
+/// 是否已加载配置文件，之后的修改写回该文件
+static PERSISTENT: AtomicBool = AtomicBool::new(false);
+
+/// 加载配置目录中的配置文件，文件不存在时使用默认设置
+///
+/// 系统凭据库中保存有加密密钥时用它解密配置文件和订阅凭据。
+/// 配置文件已加密而没有保存的密钥时加载失败，需调用 [`unlock_settings`]。
+/// 加载后按备份设置定时备份到数据目录的备份目录
+pub async fn init_settings(config_dir: &str) -> Result<()> {
+    init_settings_with_store(config_dir, &*credentials::system_store()).await
+}
+
+async fn init_settings_with_store(config_dir: &str, store: &dyn CredentialStore) -> Result<()> {
+    let mut manager = ConfigManager::new(Path::new(config_dir).join(SETTINGS_FILE_NAME));
+    manager.set_backup_dir(crate::paths::app_paths().data_dir.join(BACKUP_DIR_NAME));
+    if let Some(db_path) = SETTINGS.read().await.subscription_db() {
+        manager.set_subscription_db(db_path);
+    }
+    if let Err(e) = manager.unlock_from_store(store) {
+        tracing::warn!(
+            "Failed to read the encryption key from the credential store: {}",
+            e
+        );
+    }
+    PERSISTENT.store(false, Ordering::SeqCst);
+    *SETTINGS.write().await = manager.clone();
+    manager.load().await?;
+    PERSISTENT.store(true, Ordering::SeqCst);
+    apply_settings(&manager).await;
+    restart_scheduled_backups(&manager);
+    apply_storage_key(&manager, false).await
+}
+
+/// 用加密密码解锁配置文件
+///
+/// 用于凭据库中没有保存密钥时，`remember` 为真时把密钥保存到凭据库
+pub async fn unlock_settings(password: &str, remember: bool) -> Result<()> {
+    unlock_settings_with_store(password, remember, &*credentials::system_store()).await
+}
+
+async fn unlock_settings_with_store(
+    password: &str,
+    remember: bool,
+    store: &dyn CredentialStore,
+) -> Result<()> {
+    let mut settings = SETTINGS.write().await;
+    let mut manager = settings.clone();
+    manager.set_encryption_password(password.to_string());
+    manager.load().await?;
+    *settings = manager.clone();
+    PERSISTENT.store(true, Ordering::SeqCst);
+    drop(settings);
+
+    apply_settings(&manager).await;
+    restart_scheduled_backups(&manager);
+    apply_storage_key(&manager, false).await?;
+    if remember {
+        manager.remember_key(store)?;
+    }
+    Ok(())
+}
+
+/// 设置或更换加密密码
+///
+/// 配置文件和订阅凭据改用新密码加密。`remember` 为真时把新密钥保存到
+/// 凭据库，否则删除凭据库中的密钥
+pub async fn set_encryption_password(password: &str, remember: bool) -> Result<()> {
+    set_encryption_password_with_store(password, remember, &*credentials::system_store()).await
+}
+
+async fn set_encryption_password_with_store(
+    password: &str,
+    remember: bool,
+    store: &dyn CredentialStore,
+) -> Result<()> {
+    let mut settings = SETTINGS.write().await;
+    if PERSISTENT.load(Ordering::SeqCst) {
+        settings.rotate_encryption_password(password).await?;
+    } else {
+        settings.set_encryption_password(password.to_string());
+    }
+    let manager = settings.clone();
+    drop(settings);
+
+    // Scheduled backups are encrypted with the new key from now on
+    restart_scheduled_backups(&manager);
+    apply_storage_key(&manager, true).await?;
+    if remember {
+        manager.remember_key(store)?;
+    } else {
+        store.delete(credentials::ENCRYPTION_KEY_ACCOUNT)?;
+    }
+    Ok(())
+}
+
+/// 用设置的加密密钥加密订阅数据库中的凭据
+///
+/// `rotate` 为真时把用旧密钥加密的凭据改用新密钥
+async fn apply_storage_key(manager: &ConfigManager, rotate: bool) -> Result<()> {
+    match manager.encryption_key() {
+        Some(key) => super::subscription::set_storage_key(key, rotate).await,
+        None => Ok(()),
+    }
+}
+
+/// 设置订阅数据库路径，备份时一并保存（内部使用）
+pub(crate) async fn set_subscription_db(db_path: &str) {
+    let mut settings = SETTINGS.write().await;
+    settings.set_subscription_db(db_path);
+    restart_scheduled_backups(&settings);
+}
+
+/// 按备份设置重新开始定时备份，未加载配置文件时不备份
+fn restart_scheduled_backups(manager: &ConfigManager) {
+    let mut task = BACKUP_TASK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(task) = task.take() {
+        task.abort();
+    }
+    if PERSISTENT.load(Ordering::SeqCst) {
+        let _runtime = super::connection::TOKIO_RUNTIME.enter();
+        *task = Some(backup::spawn_scheduled_backups(Arc::new(manager.clone())));
+    }
+}
+
+/// 当前的加密密钥，供初始化订阅数据库时使用
+pub(crate) async fn encryption_key() -> Option<[u8; 32]> {
+    SETTINGS.read().await.encryption_key()
+}
+
+/// 设置管理器，与全局设置共享同一份配置
+pub async fn settings() -> ConfigManager {
+    SETTINGS.read().await.clone()
+}
+
+/// 保存设置，未调用 [`init_settings`] 时不写文件
+pub async fn save_settings() -> Result<()> {
+    if PERSISTENT.load(Ordering::SeqCst) {
+        SETTINGS.read().await.save().await?;
+    }
+    Ok(())
+}
+
+/// 将加载的设置同步到 Xray 配置生成器和连接管理器（下次连接时生效）
+async fn apply_settings(manager: &ConfigManager) {
+    super::connection::core_connection_manager()
+        .await
+        .apply_config(&manager.get_config().await);
+}
+
+/// 将设置中的路由规则集同步到 Xray 配置生成器（下次连接时生效）
+pub(crate) async fn apply_routing_rule_sets(manager: &ConfigManager) {
+    let rule_sets = manager.get_all_routing_rule_sets().await;
+    super::connection::core_connection_manager()
+        .await
+        .get_xray()
+        .set_routing_rule_sets(rule_sets);
+}
+
+/// 将设置中的应用规则同步到 Xray 配置生成器（下次连接时生效）
+pub(crate) async fn apply_app_rules(manager: &ConfigManager) {
+    let app_rules = manager.get_app_rules().await;
+    super::connection::core_connection_manager()
+        .await
+        .get_xray()
+        .set_app_rules(app_rules);
+}
+
+/// 将设置中的 DNS 设置同步到 Xray 配置生成器（下次连接时生效）
+pub(crate) async fn apply_dns_settings(manager: &ConfigManager) {
+    let dns = manager.get_dns_settings().await;
+    super::connection::core_connection_manager()
+        .await
+        .get_xray()
+        .set_dns_settings(dns);
+}
+
+/// 将设置中的本地入站设置同步到 Xray 配置生成器（下次连接时生效）
+pub(crate) async fn apply_inbound_settings(manager: &ConfigManager) {
+    let inbound = manager.get_inbound_settings().await;
+    super::connection::core_connection_manager()
+        .await
+        .get_xray()
+        .set_inbound_settings(inbound);
+}
+
+/// 恢复为内存中的默认设置，不再写回配置文件
+#[cfg(test)]
+pub(crate) async fn reset_settings() {
+    PERSISTENT.store(false, Ordering::SeqCst);
+    if let Some(task) = BACKUP_TASK.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        task.abort();
+    }
+    *SETTINGS.write().await = ConfigManager::new(
+        crate::paths::app_paths()
+            .config_dir
+            .join(SETTINGS_FILE_NAME),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::credentials::MemoryStore;
+    use serial_test::serial;
+
+    #[tokio::test]
+    #[serial]
+    async fn test_encryption_password_and_key_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().to_str().unwrap();
+        let store = MemoryStore::default();
+
+        init_settings_with_store(config_dir, &store).await.unwrap();
+        assert!(BACKUP_TASK
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|task| !task.is_finished()));
+        set_encryption_password_with_store("secret", true, &store)
+            .await
+            .unwrap();
+        let key = encryption_key().await.unwrap();
+        assert_eq!(credentials::load_key(&store).unwrap(), Some(key));
+        let content = std::fs::read(dir.path().join(SETTINGS_FILE_NAME)).unwrap();
+        assert!(serde_json::from_slice::<serde_json::Value>(&content).is_err());
+
+        // The remembered key unlocks the next start
+        reset_settings().await;
+        init_settings_with_store(config_dir, &store).await.unwrap();
+        assert_eq!(encryption_key().await, Some(key));
+
+        // Without it the password is needed
+        reset_settings().await;
+        let empty = MemoryStore::default();
+        assert!(init_settings_with_store(config_dir, &empty).await.is_err());
+        assert!(unlock_settings_with_store("wrong", false, &empty)
+            .await
+            .is_err());
+        unlock_settings_with_store("secret", true, &empty)
+            .await
+            .unwrap();
+        assert_eq!(credentials::load_key(&empty).unwrap(), Some(key));
+
+        // Not remembering a new password removes the stored key
+        set_encryption_password_with_store("other", false, &empty)
+            .await
+            .unwrap();
+        assert_eq!(credentials::load_key(&empty).unwrap(), None);
+        assert_ne!(encryption_key().await, Some(key));
+
+        reset_settings().await;
+    }
+}
@@ -2,29 +2,58 @@
 //!
 //! This module provides FFI interfaces for subscription management.
 
-use crate::bridge::api::{ProxyServerConfig, ServerInfo, SubscriptionInfo};
+use crate::bridge::api::{
+    ImportFailure, ImportResult, ProxyServerConfig, ServerDiffInfo, ServerExportInfo, ServerInfo,
+    ServerLatencyInfo, ServerListItem, StorageStatsInfo, SubscriptionFetchAttemptInfo,
+    SubscriptionFetchSettings, SubscriptionHealthInfo, SubscriptionInfo, SubscriptionRequestInfo,
+    SubscriptionScheduleInfo, SubscriptionUpdateInfo, V8RayEvent,
+};
+use crate::config::backup::{self, BACKUP_DIR_NAME};
+use crate::config::{BackupReason, ExportFormat, ProxyServerConfig as CoreProxyServerConfig};
+use crate::platform::network_monitor::NetworkFingerprint;
 use crate::subscription::{
-    SchedulerConfig, SubscriptionManager, SubscriptionScheduler, SubscriptionStatus,
-    SubscriptionStorage,
+    BasicAuth, HttpClientConfig, NetworkConditions, RouteOrder, SchedulerConfig, ServerDiff,
+    ServerUserData, SubscriptionHealth, SubscriptionManager, SubscriptionParser,
+    SubscriptionRequest, SubscriptionScheduler, SubscriptionStatus, SubscriptionStorage,
+    UpdateSchedule, UserAgentPreset,
 };
-use anyhow::Result;
+use crate::xray::health::{probe_outbounds, ProbeTarget, DEFAULT_PROBE_TIMEOUT};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+/// How often the auto-update task checks for due subscriptions
+const AUTO_UPDATE_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Whether the current network is metered, as reported by the app
+static METERED_NETWORK: AtomicBool = AtomicBool::new(false);
+
+/// Whether a `SubscriptionStale` event is sent when a subscription becomes stale
+static NOTIFY_STALE: AtomicBool = AtomicBool::new(true);
+
 lazy_static::lazy_static! {
     static ref SUBSCRIPTION_MANAGER: Arc<RwLock<Option<SubscriptionManager>>> = Arc::new(RwLock::new(None));
     static ref SUBSCRIPTION_STORAGE: Arc<RwLock<Option<SubscriptionStorage>>> = Arc::new(RwLock::new(None));
     static ref SUBSCRIPTION_SCHEDULER: Arc<RwLock<Option<SubscriptionScheduler>>> = Arc::new(RwLock::new(None));
+    static ref AUTO_UPDATE_TASK: std::sync::Mutex<Option<JoinHandle<()>>> = std::sync::Mutex::new(None);
 }
 
 /// Initialize subscription manager
 pub async fn init_subscription_manager(db_path: String) -> Result<()> {
     tracing::info!("Initializing subscription manager");
 
-    // Create storage
-    let storage = SubscriptionStorage::new(&db_path).await?;
+    // Create storage, encrypted with the settings key if there is one
+    let mut storage = SubscriptionStorage::new(&db_path).await?;
+    if let Some(key) = super::settings::encryption_key().await {
+        storage.enable_encryption_with_key(key).await?;
+    }
     *SUBSCRIPTION_STORAGE.write().await = Some(storage);
+    super::settings::set_subscription_db(&db_path).await;
 
     // Create manager
     let manager = SubscriptionManager::new();
@@ -39,6 +68,22 @@ pub async fn init_subscription_manager(db_path: String) -> Result<()> {
     Ok(())
 }
 
+/// Encrypt stored credentials with `key`
+///
+/// With `rotate`, credentials encrypted with the previous key are
+/// re-encrypted; otherwise `key` must match the one already in use.
+/// Nothing happens before the storage is initialized.
+pub(crate) async fn set_storage_key(key: [u8; 32], rotate: bool) -> Result<()> {
+    if let Some(storage) = SUBSCRIPTION_STORAGE.write().await.as_mut() {
+        if rotate {
+            storage.rotate_key(key).await?;
+        } else {
+            storage.enable_encryption_with_key(key).await?;
+        }
+    }
+    Ok(())
+}
+
 /// Add a new subscription
 pub async fn add_subscription(name: String, url: String) -> Result<String> {
     tracing::info!("Adding subscription: {}", name);
@@ -49,6 +94,7 @@ pub async fn add_subscription(name: String, url: String) -> Result<String> {
         .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
 
     let id = manager.add_subscription(name, url).await?;
+    save_update_outcomes(manager).await?;
 
     // Save to storage
     if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
@@ -61,6 +107,148 @@ pub async fn add_subscription(name: String, url: String) -> Result<String> {
     Ok(id.to_string())
 }
 
+/// Create a local group for manually added servers
+pub async fn create_server_group(name: String) -> Result<String> {
+    tracing::info!("Creating server group: {}", name);
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let id = manager.add_local_group(name);
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        if let Some(group) = manager.get_subscriptions().iter().find(|s| s.id == id) {
+            storage.save_subscription(group).await?;
+        }
+    }
+
+    Ok(id.to_string())
+}
+
+/// Add servers to a local group and persist them
+async fn add_local_servers(
+    group_id: Uuid,
+    configs: Vec<CoreProxyServerConfig>,
+) -> Result<Vec<String>> {
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let added = manager.add_local_servers(group_id, configs)?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        for server in &added {
+            storage.save_server(server).await?;
+        }
+        if let Some(group) = manager
+            .get_subscriptions()
+            .iter()
+            .find(|s| s.id == group_id)
+        {
+            storage.save_subscription(group).await?;
+        }
+    }
+
+    Ok(added.iter().map(|s| s.id.to_string()).collect())
+}
+
+/// Add servers from share links to a local group
+///
+/// `links` may hold several links, one per line, or base64 encoded like a
+/// subscription body.
+pub async fn add_servers_from_links(group_id: String, links: String) -> Result<Vec<String>> {
+    let group_id = Uuid::parse_str(&group_id)?;
+    let configs = SubscriptionParser::parse(&links)?;
+    add_local_servers(group_id, configs).await
+}
+
+/// Add a server filled in by form to a local group
+pub async fn add_server(group_id: String, config: ProxyServerConfig) -> Result<String> {
+    let group_id = Uuid::parse_str(&group_id)?;
+    let config = crate::bridge::config::to_core_server_config(config)?;
+    let mut ids = add_local_servers(group_id, vec![config]).await?;
+    ids.pop().ok_or_else(|| anyhow!("Server was not added"))
+}
+
+/// Import servers from pasted text into the default local group
+pub async fn import_from_text(content: String) -> Result<ImportResult> {
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let group_id = manager.default_local_group();
+    let report = manager.import_text(group_id, &content)?;
+    tracing::info!(
+        "Imported {} servers from text ({} duplicates, {} failures)",
+        report.imported.len(),
+        report.duplicates.len(),
+        report.failures.len()
+    );
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        for server in &report.imported {
+            storage.save_server(server).await?;
+        }
+        if let Some(group) = manager
+            .get_subscriptions()
+            .iter()
+            .find(|s| s.id == group_id)
+        {
+            storage.save_subscription(group).await?;
+        }
+    }
+
+    Ok(ImportResult {
+        group_id: group_id.to_string(),
+        imported: report
+            .imported
+            .iter()
+            .map(|s| ServerInfo {
+                id: s.id.to_string(),
+                subscription_id: s.subscription_id.to_string(),
+                name: s.display_name(),
+                address: s.address.clone(),
+                port: s.port as i32,
+                protocol: s.protocol.clone(),
+            })
+            .collect(),
+        duplicates: report.duplicates,
+        failures: report
+            .failures
+            .into_iter()
+            .map(|e| ImportFailure {
+                line: e.line as u32,
+                input: e.input,
+                reason: e.reason,
+            })
+            .collect(),
+    })
+}
+
+/// Edit a server of a local group; `config.id` selects the server
+pub async fn edit_server(config: ProxyServerConfig) -> Result<()> {
+    let server_id = Uuid::parse_str(&config.id)?;
+    let config = crate::bridge::config::to_core_server_config(config)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let (server, user_data) = manager.edit_local_server(server_id, config)?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage.save_server(&server).await?;
+        storage.save_server_user_data(&user_data).await?;
+    }
+
+    Ok(())
+}
+
 /// Remove a subscription
 pub async fn remove_subscription(id: String) -> Result<()> {
     tracing::info!("Removing subscription: {}", id);
@@ -78,6 +266,9 @@ pub async fn remove_subscription(id: String) -> Result<()> {
     if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
         storage.delete_subscription(subscription_id).await?;
     }
+    if let Some(scheduler) = SUBSCRIPTION_SCHEDULER.read().await.as_ref() {
+        scheduler.remove(subscription_id).await;
+    }
 
     Ok(())
 }
@@ -87,13 +278,40 @@ pub async fn update_subscription(id: String) -> Result<()> {
     tracing::info!("Updating subscription: {}", id);
 
     let subscription_id = Uuid::parse_str(&id)?;
+    update_and_save(subscription_id).await.map(|_| ())
+}
 
+/// Update a subscription, persist the result and send a
+/// `SubscriptionUpdated` event
+async fn update_and_save(subscription_id: Uuid) -> Result<ServerDiff> {
     let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
     let manager = manager_guard
         .as_mut()
         .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
 
-    manager.update_subscription(subscription_id).await?;
+    let result = manager.update_subscription(subscription_id).await;
+    save_update_outcomes(manager).await?;
+    let name = manager
+        .get_subscriptions()
+        .iter()
+        .find(|s| s.id == subscription_id)
+        .map(|s| s.name.clone())
+        .unwrap_or_default();
+    let diff = match result {
+        Ok(diff) => diff,
+        Err(e) => {
+            let _ = super::events::send_event(V8RayEvent::SubscriptionUpdated {
+                subscription_id: subscription_id.to_string(),
+                name,
+                success: false,
+                error: Some(e.to_string()),
+                added: Vec::new(),
+                removed: Vec::new(),
+                changed: Vec::new(),
+            });
+            return Err(e.into());
+        }
+    };
 
     // Save to storage
     if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
@@ -102,7 +320,10 @@ pub async fn update_subscription(id: String) -> Result<()> {
             storage.save_subscription(subscription).await?;
         }
 
-        // Save servers
+        // Replace the servers; unchanged ones keep their IDs
+        storage
+            .delete_servers_for_subscription(subscription_id)
+            .await?;
         let servers = manager.get_servers_for_subscription(subscription_id);
         for server in servers {
             storage.save_server(server).await?;
@@ -114,10 +335,22 @@ pub async fn update_subscription(id: String) -> Result<()> {
         scheduler.mark_updated(subscription_id).await;
     }
 
-    Ok(())
+    let _ = super::events::send_event(V8RayEvent::SubscriptionUpdated {
+        subscription_id: subscription_id.to_string(),
+        name,
+        success: true,
+        error: None,
+        added: diff.added.clone(),
+        removed: diff.removed.clone(),
+        changed: diff.changed.clone(),
+    });
+    Ok(diff)
 }
 
 /// Update all subscriptions
+///
+/// Unless automatic backups are off, the subscription database is backed
+/// up to the backup directory in the data directory first.
 pub async fn update_all_subscriptions() -> Result<()> {
     tracing::info!("Updating all subscriptions");
 
@@ -126,7 +359,25 @@ pub async fn update_all_subscriptions() -> Result<()> {
         .as_mut()
         .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
 
+    let backup_settings = super::settings::settings().await.get_config().await.backup;
+    if backup_settings.auto_backup {
+        if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+            let backup_dir = crate::paths::app_paths().data_dir.join(BACKUP_DIR_NAME);
+            if let Err(e) = backup::backup_subscription_storage(
+                storage,
+                &backup_dir,
+                BackupReason::SubscriptionUpdate,
+                backup_settings.keep,
+            )
+            .await
+            {
+                tracing::warn!("Failed to back up subscriptions before updating: {}", e);
+            }
+        }
+    }
+
     manager.update_all_subscriptions().await?;
+    save_update_outcomes(manager).await?;
 
     // Save to storage
     if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
@@ -142,6 +393,364 @@ pub async fn update_all_subscriptions() -> Result<()> {
     Ok(())
 }
 
+/// Persist the update outcomes recorded by the manager
+async fn save_update_outcomes(manager: &mut SubscriptionManager) -> Result<()> {
+    let outcomes = manager.take_update_outcomes();
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        for outcome in &outcomes {
+            storage.record_update_outcome(outcome).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Get the latest update outcomes of a subscription, newest first
+pub async fn get_subscription_update_history(
+    subscription_id: String,
+) -> Result<Vec<SubscriptionUpdateInfo>> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let storage_guard = SUBSCRIPTION_STORAGE.read().await;
+    let storage = storage_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription storage not initialized"))?;
+
+    Ok(storage
+        .load_update_history(subscription_id)
+        .await?
+        .into_iter()
+        .map(|outcome| SubscriptionUpdateInfo {
+            timestamp: outcome.started_at.timestamp_millis(),
+            duration_ms: outcome.duration_ms,
+            success: outcome.is_success(),
+            error: outcome.error,
+            server_count_delta: outcome.server_count_delta,
+        })
+        .collect())
+}
+
+/// Get the server changes of the latest successful updates of a
+/// subscription, newest first
+pub async fn get_subscription_update_diffs(subscription_id: String) -> Result<Vec<ServerDiffInfo>> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let storage_guard = SUBSCRIPTION_STORAGE.read().await;
+    let storage = storage_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription storage not initialized"))?;
+
+    Ok(storage
+        .load_update_history(subscription_id)
+        .await?
+        .into_iter()
+        .filter(|outcome| outcome.is_success())
+        .map(|outcome| ServerDiffInfo {
+            timestamp: outcome.started_at.timestamp_millis(),
+            added: outcome.diff.added,
+            removed: outcome.diff.removed,
+            changed: outcome.diff.changed,
+        })
+        .collect())
+}
+
+/// Get the fetch attempts of the latest updates of a subscription, newest
+/// update first
+pub async fn get_subscription_fetch_attempts(
+    subscription_id: String,
+) -> Result<Vec<SubscriptionFetchAttemptInfo>> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let storage_guard = SUBSCRIPTION_STORAGE.read().await;
+    let storage = storage_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription storage not initialized"))?;
+
+    Ok(storage
+        .load_update_history(subscription_id)
+        .await?
+        .into_iter()
+        .flat_map(|outcome| {
+            let timestamp = outcome.started_at.timestamp_millis();
+            outcome
+                .attempts
+                .into_iter()
+                .map(move |attempt| SubscriptionFetchAttemptInfo {
+                    timestamp,
+                    route: attempt.route.as_str().to_string(),
+                    attempt: attempt.attempt,
+                    duration_ms: attempt.duration_ms,
+                    http_status: attempt.http_status,
+                    error: attempt.error,
+                })
+        })
+        .collect())
+}
+
+/// Get the retry and route settings of subscription fetches
+pub async fn get_subscription_fetch_settings() -> Result<SubscriptionFetchSettings> {
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+    Ok(fetch_settings(manager.http_config()))
+}
+
+/// Change the retry and route settings of subscription fetches
+pub async fn configure_subscription_fetch(settings: SubscriptionFetchSettings) -> Result<()> {
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+    let config = http_config(settings, manager.http_config())?;
+    manager.set_http_config(config)?;
+    Ok(())
+}
+
+/// Retry and route settings of subscription fetches, for other downloads
+pub(super) async fn fetch_http_config() -> HttpClientConfig {
+    SUBSCRIPTION_MANAGER
+        .read()
+        .await
+        .as_ref()
+        .map(|manager| manager.http_config().clone())
+        .unwrap_or_default()
+}
+
+fn fetch_settings(config: &HttpClientConfig) -> SubscriptionFetchSettings {
+    SubscriptionFetchSettings {
+        timeout_secs: config.timeout.as_secs(),
+        max_attempts: config.max_retries,
+        initial_backoff_ms: config.initial_backoff.as_millis() as u64,
+        max_backoff_ms: config.max_backoff.as_millis() as u64,
+        retry_on_status: config.retry_on_status.clone(),
+        route_order: config.route_order.as_str().to_string(),
+        proxy_url: config.proxy_url.clone(),
+    }
+}
+
+/// Apply fetch settings to `current`, keeping its User-Agent and headers
+fn http_config(
+    settings: SubscriptionFetchSettings,
+    current: &HttpClientConfig,
+) -> Result<HttpClientConfig> {
+    if settings.timeout_secs == 0 {
+        return Err(anyhow!("Timeout must be at least 1 second"));
+    }
+    if settings.max_attempts == 0 {
+        return Err(anyhow!("At least one attempt is needed"));
+    }
+    if let Some(status) = settings
+        .retry_on_status
+        .iter()
+        .find(|s| !(100..=599).contains(*s))
+    {
+        return Err(anyhow!("Invalid HTTP status: {}", status));
+    }
+    let route_order = RouteOrder::ALL
+        .into_iter()
+        .find(|order| order.as_str() == settings.route_order)
+        .ok_or_else(|| anyhow!("Unknown route order: {}", settings.route_order))?;
+
+    Ok(HttpClientConfig {
+        timeout: Duration::from_secs(settings.timeout_secs),
+        max_retries: settings.max_attempts,
+        initial_backoff: Duration::from_millis(settings.initial_backoff_ms),
+        max_backoff: Duration::from_millis(settings.max_backoff_ms),
+        retry_on_status: settings.retry_on_status,
+        route_order,
+        proxy_url: settings.proxy_url.filter(|url| !url.trim().is_empty()),
+        ..current.clone()
+    })
+}
+
+/// Get the request options of a subscription
+pub async fn get_subscription_request(subscription_id: String) -> Result<SubscriptionRequestInfo> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+    let subscription = manager
+        .get_subscriptions()
+        .iter()
+        .find(|s| s.id == subscription_id)
+        .ok_or_else(|| anyhow!("Subscription not found: {}", subscription_id))?;
+
+    let request = &subscription.request;
+    let (user_agent_preset, custom_user_agent) = match &request.user_agent {
+        UserAgentPreset::Default => ("default", None),
+        UserAgentPreset::Clash => ("clash", None),
+        UserAgentPreset::V2rayN => ("v2rayn", None),
+        UserAgentPreset::Shadowrocket => ("shadowrocket", None),
+        UserAgentPreset::Custom(value) => ("custom", Some(value.clone())),
+    };
+    Ok(SubscriptionRequestInfo {
+        user_agent_preset: user_agent_preset.to_string(),
+        custom_user_agent,
+        headers: request.headers.clone().into_iter().collect(),
+        username: request.basic_auth.as_ref().map(|a| a.username.clone()),
+        password: request.basic_auth.as_ref().map(|a| a.password.clone()),
+    })
+}
+
+/// Set the request options of a subscription and persist them
+pub async fn set_subscription_request(
+    subscription_id: String,
+    request: SubscriptionRequestInfo,
+) -> Result<()> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let user_agent = match request.user_agent_preset.as_str() {
+        "default" => UserAgentPreset::Default,
+        "clash" => UserAgentPreset::Clash,
+        "v2rayn" => UserAgentPreset::V2rayN,
+        "shadowrocket" => UserAgentPreset::Shadowrocket,
+        "custom" => UserAgentPreset::Custom(request.custom_user_agent.unwrap_or_default()),
+        other => return Err(anyhow!("Unknown User-Agent preset: {}", other)),
+    };
+    let basic_auth = request
+        .username
+        .filter(|username| !username.is_empty())
+        .map(|username| BasicAuth {
+            username,
+            password: request.password.unwrap_or_default(),
+        });
+    let request = SubscriptionRequest {
+        user_agent,
+        headers: request.headers.into_iter().collect(),
+        basic_auth,
+    };
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+    manager.set_subscription_request(subscription_id, request)?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        if let Some(subscription) = manager
+            .get_subscriptions()
+            .iter()
+            .find(|s| s.id == subscription_id)
+        {
+            storage.save_subscription(subscription).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that a subscription's URL still answers and persist the result
+///
+/// Sends a `SubscriptionStale` event when the subscription becomes stale,
+/// unless notifications are turned off (see
+/// [`configure_subscription_health`]).
+pub async fn check_subscription_health(subscription_id: String) -> Result<SubscriptionHealthInfo> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let (health, became_stale) = manager.check_health(subscription_id).await?;
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage
+            .save_subscription_health(subscription_id, &health)
+            .await?;
+    }
+
+    if became_stale && NOTIFY_STALE.load(Ordering::SeqCst) {
+        let name = manager
+            .get_subscriptions()
+            .iter()
+            .find(|s| s.id == subscription_id)
+            .map(|s| s.name.clone())
+            .unwrap_or_default();
+        let _ = super::events::send_event(V8RayEvent::SubscriptionStale {
+            subscription_id: subscription_id.to_string(),
+            name,
+            consecutive_failures: health.consecutive_failures,
+            error: health.last_check.as_ref().and_then(|c| c.error.clone()),
+        });
+    }
+    Ok(health_info(subscription_id, &health))
+}
+
+/// Get the latest health check results of a subscription, None if it was
+/// never checked
+pub async fn get_subscription_health(
+    subscription_id: String,
+) -> Result<Option<SubscriptionHealthInfo>> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    Ok(manager
+        .subscription_health(subscription_id)
+        .map(|health| health_info(subscription_id, health)))
+}
+
+/// Configure after how many failed checks in a row a subscription is stale,
+/// and whether a `SubscriptionStale` event is sent then
+pub async fn configure_subscription_health(stale_after: u32, notify: bool) -> Result<()> {
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+    manager.set_stale_after(stale_after);
+    NOTIFY_STALE.store(notify, Ordering::SeqCst);
+    Ok(())
+}
+
+fn health_info(subscription_id: Uuid, health: &SubscriptionHealth) -> SubscriptionHealthInfo {
+    let check = health.last_check.as_ref();
+    SubscriptionHealthInfo {
+        subscription_id: subscription_id.to_string(),
+        stale: health.stale,
+        consecutive_failures: health.consecutive_failures,
+        checked_at: check.map(|c| c.checked_at.timestamp_millis()),
+        duration_ms: check.map(|c| c.duration_ms),
+        http_status: check.and_then(|c| c.http_status),
+        redirected_to: check.and_then(|c| c.redirected_to.clone()),
+        error: check.and_then(|c| c.error.clone()),
+        tls_error: check.is_some_and(|c| c.tls_error),
+    }
+}
+
+/// Get storage usage statistics
+///
+/// Database figures are zero if the subscription storage or the histories
+/// are not initialized.
+pub async fn get_storage_stats() -> Result<StorageStatsInfo> {
+    let stats = match SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        Some(storage) => storage.get_stats().await?,
+        None => Default::default(),
+    };
+
+    let connection_manager = crate::bridge::connection::get_core_connection_manager()?;
+    let download_cache_size = connection_manager
+        .get_xray()
+        .get_updater()
+        .cache()
+        .total_size()
+        .await?;
+
+    let mut history_database_size = 0;
+    if let Some(history) = connection_manager.get_traffic_history().await {
+        history_database_size += history.database_size().await?;
+    }
+    if let Some(history) = connection_manager.get_latency_history().await {
+        history_database_size += history.database_size().await?;
+    }
+
+    Ok(StorageStatsInfo {
+        subscription_count: stats.subscription_count,
+        server_count: stats.server_count,
+        favorite_count: stats.favorite_count,
+        database_size: stats.database_size,
+        history_database_size,
+        log_size: crate::utils::logger::log_files_size()?,
+        download_cache_size,
+    })
+}
+
 /// Get all subscriptions
 pub async fn get_subscriptions() -> Result<Vec<SubscriptionInfo>> {
     let manager_guard = SUBSCRIPTION_MANAGER.read().await;
@@ -183,7 +792,7 @@ pub async fn get_servers() -> Result<Vec<ServerInfo>> {
         .map(|s| ServerInfo {
             id: s.id.to_string(),
             subscription_id: s.subscription_id.to_string(),
-            name: s.name.clone(),
+            name: s.display_name(),
             address: s.address.clone(),
             port: s.port as i32,
             protocol: s.protocol.clone(),
@@ -221,7 +830,7 @@ pub async fn get_server_config(server_id: String) -> Result<ProxyServerConfig> {
             .stream_settings
             .as_ref()
             .and_then(|s| serde_json::to_value(s).ok()),
-        tags: vec![],
+        tags: server.tags.clone(),
     })
 }
 
@@ -240,7 +849,7 @@ pub async fn get_servers_for_subscription(subscription_id: String) -> Result<Vec
         .map(|s| ServerInfo {
             id: s.id.to_string(),
             subscription_id: s.subscription_id.to_string(),
-            name: s.name.clone(),
+            name: s.display_name(),
             address: s.address.clone(),
             port: s.port as i32,
             protocol: s.protocol.clone(),
@@ -250,6 +859,323 @@ pub async fn get_servers_for_subscription(subscription_id: String) -> Result<Vec
     Ok(servers)
 }
 
+/// Set the manual order of a subscription's servers and persist it
+pub async fn reorder_servers(subscription_id: String, server_ids: Vec<String>) -> Result<()> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let server_ids = server_ids
+        .iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let keys = manager.reorder_servers(subscription_id, &server_ids)?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage.save_server_order(subscription_id, &keys).await?;
+    }
+
+    Ok(())
+}
+
+/// Export the manual server order of all subscriptions as JSON
+///
+/// The result maps subscription IDs to server order keys.
+pub async fn export_server_order() -> Result<String> {
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    Ok(serde_json::to_string_pretty(manager.server_orders())?)
+}
+
+/// Import a manual server order previously produced by [`export_server_order`]
+pub async fn import_server_order(json: String) -> Result<()> {
+    let orders: HashMap<Uuid, Vec<String>> = serde_json::from_str(&json)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+    let storage_guard = SUBSCRIPTION_STORAGE.read().await;
+
+    for (subscription_id, keys) in orders {
+        if let Some(storage) = storage_guard.as_ref() {
+            storage.save_server_order(subscription_id, &keys).await?;
+        }
+        manager.set_server_order(subscription_id, keys);
+    }
+
+    Ok(())
+}
+
+/// Parse a list of server IDs
+fn parse_server_ids(server_ids: &[String]) -> Result<Vec<Uuid>> {
+    Ok(server_ids
+        .iter()
+        .map(|id| Uuid::parse_str(id))
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Delete several servers in one storage transaction
+pub async fn delete_servers(server_ids: Vec<String>) -> Result<u32> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage.delete_servers(&server_ids).await?;
+    }
+
+    Ok(manager.delete_servers(&server_ids) as u32)
+}
+
+/// Move several servers to a local group in one storage transaction
+pub async fn move_servers(server_ids: Vec<String>, subscription_id: String) -> Result<u32> {
+    let server_ids = parse_server_ids(&server_ids)?;
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let moved = manager.move_servers(&server_ids, subscription_id)?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage.move_servers(&server_ids, subscription_id).await?;
+    }
+
+    Ok(moved as u32)
+}
+
+/// Add a tag to several servers in one storage transaction
+pub async fn add_server_tag(server_ids: Vec<String>, tag: String) -> Result<u32> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let changed = manager.add_server_tag(&server_ids, &tag);
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage.save_server_tags(&changed).await?;
+    }
+
+    Ok(changed.len() as u32)
+}
+
+/// Persist changed server user data entries
+async fn save_user_data(changed: &[(String, ServerUserData)]) -> Result<()> {
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage.save_server_user_data(changed).await?;
+    }
+    Ok(())
+}
+
+/// Get the server list with user data, pinned servers first
+pub async fn get_server_list(include_hidden: bool) -> Result<Vec<ServerListItem>> {
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    Ok(manager
+        .server_list(include_hidden)
+        .into_iter()
+        .map(|(s, data)| ServerListItem {
+            id: s.id.to_string(),
+            subscription_id: s.subscription_id.to_string(),
+            name: data.alias.clone().unwrap_or_else(|| s.display_name()),
+            original_name: s.display_name(),
+            address: s.address.clone(),
+            port: s.port as i32,
+            protocol: s.protocol.clone(),
+            favorite: data.favorite,
+            alias: data.alias,
+            hidden: data.hidden,
+            pinned_position: data.pinned_position,
+        })
+        .collect())
+}
+
+/// Mark or unmark several servers as favorites
+pub async fn set_servers_favorite(server_ids: Vec<String>, favorite: bool) -> Result<u32> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let changed = manager.update_user_data(&server_ids, |data| data.favorite = favorite);
+    save_user_data(&changed).await?;
+    Ok(changed.len() as u32)
+}
+
+/// Hide or show several servers
+pub async fn set_servers_hidden(server_ids: Vec<String>, hidden: bool) -> Result<u32> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let changed = manager.update_user_data(&server_ids, |data| data.hidden = hidden);
+    save_user_data(&changed).await?;
+    Ok(changed.len() as u32)
+}
+
+/// IDs of the servers of a subscription, empty if the subscription manager
+/// is not initialized
+pub(crate) async fn subscription_server_ids(subscription_id: &str) -> Result<Vec<String>> {
+    let id = Uuid::parse_str(subscription_id)?;
+    Ok(SUBSCRIPTION_MANAGER
+        .read()
+        .await
+        .as_ref()
+        .map(|manager| {
+            manager
+                .get_servers_for_subscription(id)
+                .into_iter()
+                .map(|server| server.id.to_string())
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
+/// Set or clear the custom alias of a server
+pub async fn set_server_alias(server_id: String, alias: Option<String>) -> Result<()> {
+    let id = Uuid::parse_str(&server_id)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    if !manager.get_servers().iter().any(|s| s.id == id) {
+        anyhow::bail!("Server not found: {}", server_id);
+    }
+    let changed = manager.update_user_data(&[id], |data| data.set_alias(alias.as_deref()));
+    save_user_data(&changed).await
+}
+
+/// Pin servers in the given order, unpinning all others
+pub async fn pin_servers(server_ids: Vec<String>) -> Result<()> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
+    let manager = manager_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let changed = manager.pin_servers(&server_ids);
+    save_user_data(&changed).await
+}
+
+/// Re-test several servers concurrently by TCP connect latency, recording
+/// the results in the latency history if it is open
+pub async fn retest_servers(server_ids: Vec<String>) -> Result<Vec<ServerLatencyInfo>> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let targets: Vec<ProbeTarget> = {
+        let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+        let manager = manager_guard
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+        manager
+            .get_servers()
+            .iter()
+            .filter(|s| server_ids.contains(&s.id))
+            .map(|s| ProbeTarget {
+                tag: s.id.to_string(),
+                server: s.address.clone(),
+                port: s.port,
+            })
+            .collect()
+    };
+
+    let results = probe_outbounds(&targets, DEFAULT_PROBE_TIMEOUT).await;
+
+    // Keep the results for quality metrics if the latency history is open
+    let history = super::connection::core_connection_manager()
+        .await
+        .get_latency_history()
+        .await;
+    if let Some(history) = history {
+        let now = chrono::Utc::now();
+        for result in &results {
+            if let Err(e) = history.record(&result.tag, now, result.latency_ms).await {
+                tracing::warn!("Failed to record latency of {}: {}", result.tag, e);
+            }
+        }
+    }
+
+    Ok(results
+        .into_iter()
+        .map(|result| ServerLatencyInfo {
+            server_id: result.tag,
+            reachable: result.reachable,
+            latency_ms: result.latency_ms.map(|ms| ms as u32),
+            error: result.error,
+        })
+        .collect())
+}
+
+/// Export several servers as share links
+pub async fn export_server_links(server_ids: Vec<String>) -> Result<Vec<String>> {
+    let server_ids = parse_server_ids(&server_ids)?;
+
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    Ok(manager.export_server_links(&server_ids))
+}
+
+/// Export several servers as share links, a base64 subscription or a Clash
+/// profile
+pub async fn export_servers(server_ids: Vec<String>, format: String) -> Result<ServerExportInfo> {
+    let server_ids = parse_server_ids(&server_ids)?;
+    let format: ExportFormat = format.parse()?;
+
+    let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+    let manager = manager_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?;
+
+    let export = manager.export_servers(&server_ids, format);
+    Ok(ServerExportInfo {
+        content: export.content,
+        exported: export.exported as u32,
+        skipped: export
+            .skipped
+            .into_iter()
+            .map(|s| format!("{}: {}", s.name, s.reason))
+            .collect(),
+    })
+}
+
+/// Apply the current application User-Agent to subscription fetches
+pub async fn apply_user_agent() -> Result<()> {
+    if let Some(manager) = SUBSCRIPTION_MANAGER.write().await.as_mut() {
+        manager.set_user_agent(crate::version::user_agent())?;
+    }
+    Ok(())
+}
+
 /// Check if a subscription should be updated
 pub async fn should_update_subscription(subscription_id: String) -> Result<bool> {
     let id = Uuid::parse_str(&subscription_id)?;
@@ -262,6 +1188,216 @@ pub async fn should_update_subscription(subscription_id: String) -> Result<bool>
     Ok(scheduler.should_update(id).await)
 }
 
+/// Convert a schedule to its FFI representation
+fn schedule_info(schedule: UpdateSchedule, next_update: Option<i64>) -> SubscriptionScheduleInfo {
+    let (kind, interval_hours, cron) = match schedule {
+        UpdateSchedule::Default => ("default", None, None),
+        UpdateSchedule::Interval { hours } => ("interval", Some(hours), None),
+        UpdateSchedule::Cron { expression } => ("cron", None, Some(expression)),
+        UpdateSchedule::Manual => ("manual", None, None),
+    };
+    SubscriptionScheduleInfo {
+        kind: kind.to_string(),
+        interval_hours,
+        cron,
+        next_update,
+    }
+}
+
+/// Parse the FFI representation of a schedule
+fn parse_schedule(info: SubscriptionScheduleInfo) -> Result<UpdateSchedule> {
+    let schedule = match info.kind.as_str() {
+        "default" => UpdateSchedule::Default,
+        "interval" => UpdateSchedule::Interval {
+            hours: info
+                .interval_hours
+                .ok_or_else(|| anyhow!("Interval schedule needs interval_hours"))?,
+        },
+        "cron" => UpdateSchedule::Cron {
+            expression: info
+                .cron
+                .ok_or_else(|| anyhow!("Cron schedule needs an expression"))?,
+        },
+        "manual" => UpdateSchedule::Manual,
+        kind => return Err(anyhow!("Unknown schedule kind: {}", kind)),
+    };
+    Ok(schedule)
+}
+
+/// Set the automatic update schedule of a subscription and persist it
+pub async fn set_subscription_schedule(
+    subscription_id: String,
+    schedule: SubscriptionScheduleInfo,
+) -> Result<()> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let schedule = parse_schedule(schedule)?;
+
+    let scheduler_guard = SUBSCRIPTION_SCHEDULER.read().await;
+    let scheduler = scheduler_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription scheduler not initialized"))?;
+    scheduler
+        .set_schedule(subscription_id, schedule.clone())
+        .await?;
+
+    if let Some(storage) = SUBSCRIPTION_STORAGE.read().await.as_ref() {
+        storage
+            .save_update_schedule(subscription_id, &schedule)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Get the automatic update schedule of a subscription and its next due time
+pub async fn get_subscription_schedule(
+    subscription_id: String,
+) -> Result<SubscriptionScheduleInfo> {
+    let subscription_id = Uuid::parse_str(&subscription_id)?;
+    let last_update = SUBSCRIPTION_MANAGER
+        .read()
+        .await
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription manager not initialized"))?
+        .get_subscriptions()
+        .iter()
+        .find(|s| s.id == subscription_id)
+        .ok_or_else(|| anyhow!("Subscription not found: {}", subscription_id))?
+        .last_update;
+
+    let scheduler_guard = SUBSCRIPTION_SCHEDULER.read().await;
+    let scheduler = scheduler_guard
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Subscription scheduler not initialized"))?;
+    let next_update = scheduler
+        .next_update(subscription_id, last_update)
+        .await
+        .map(|time| time.max(chrono::Utc::now()).timestamp_millis());
+    Ok(schedule_info(
+        scheduler.schedule(subscription_id).await,
+        next_update,
+    ))
+}
+
+/// Configure automatic updates
+///
+/// `interval_hours` is the default interval (0 = only subscriptions with
+/// their own schedule are updated automatically).
+pub async fn configure_auto_update(
+    interval_hours: u32,
+    update_on_start: bool,
+    skip_when_metered: bool,
+    skip_when_offline: bool,
+) -> Result<()> {
+    let mut scheduler_guard = SUBSCRIPTION_SCHEDULER.write().await;
+    let scheduler = scheduler_guard
+        .as_mut()
+        .ok_or_else(|| anyhow::anyhow!("Subscription scheduler not initialized"))?;
+    scheduler.set_config(SchedulerConfig {
+        update_interval_hours: interval_hours as u64,
+        update_on_startup: update_on_start,
+        skip_when_metered,
+        skip_when_offline,
+        ..scheduler.config().clone()
+    });
+    Ok(())
+}
+
+/// Report whether the current network is metered
+///
+/// The platforms' metered network APIs are only available to the app, so
+/// it reports changes; updates are postponed while metered if configured.
+pub fn set_metered_network(metered: bool) {
+    METERED_NETWORK.store(metered, Ordering::SeqCst);
+}
+
+/// Start updating subscriptions automatically
+///
+/// Due subscriptions are checked every minute; on start, all subscriptions
+/// are updated if `update_on_start` is configured. Does nothing if already
+/// started.
+pub fn start_auto_update() {
+    let mut task = AUTO_UPDATE_TASK.lock().unwrap_or_else(|e| e.into_inner());
+    if task.as_ref().is_some_and(|task| !task.is_finished()) {
+        return;
+    }
+
+    *task = Some(super::connection::TOKIO_RUNTIME.spawn(async {
+        let mut startup = true;
+        let mut interval = tokio::time::interval(AUTO_UPDATE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            // A startup update postponed by the network conditions is made
+            // once they allow it
+            if run_due_updates(startup).await {
+                startup = false;
+            }
+        }
+    }));
+}
+
+/// Stop updating subscriptions automatically
+pub fn stop_auto_update() {
+    if let Some(task) = AUTO_UPDATE_TASK
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+    {
+        task.abort();
+    }
+}
+
+/// Update the due subscriptions
+///
+/// Returns false if updates were postponed because of the network.
+async fn run_due_updates(startup: bool) -> bool {
+    let due = {
+        let manager_guard = SUBSCRIPTION_MANAGER.read().await;
+        let scheduler_guard = SUBSCRIPTION_SCHEDULER.read().await;
+        let (Some(manager), Some(scheduler)) = (manager_guard.as_ref(), scheduler_guard.as_ref())
+        else {
+            return true;
+        };
+        let due = scheduler
+            .due_subscriptions(manager.get_subscriptions(), chrono::Utc::now(), startup)
+            .await;
+        if due.is_empty() {
+            return true;
+        }
+
+        let online = tokio::task::spawn_blocking(|| NetworkFingerprint::current().is_online())
+            .await
+            .unwrap_or(true);
+        let conditions = NetworkConditions {
+            online,
+            metered: METERED_NETWORK.load(Ordering::SeqCst),
+        };
+        if let Some(reason) = scheduler.skip_reason(conditions) {
+            tracing::debug!("Postponing {} subscription updates: {}", due.len(), reason);
+            return false;
+        }
+        due
+    };
+
+    for subscription_id in due {
+        tracing::info!("Auto-updating subscription: {}", subscription_id);
+        match update_and_save(subscription_id).await {
+            Ok(diff) if !diff.is_empty() => tracing::info!(
+                "Subscription {}: {} servers added, {} removed",
+                subscription_id,
+                diff.added.len(),
+                diff.removed.len()
+            ),
+            Ok(_) => {}
+            Err(e) => tracing::warn!(
+                "Auto-update of subscription {} failed: {}",
+                subscription_id,
+                e
+            ),
+        }
+    }
+    true
+}
+
 /// Load subscriptions from storage
 pub async fn load_subscriptions_from_storage() -> Result<()> {
     tracing::info!("Loading subscriptions from storage");
@@ -273,10 +1409,22 @@ pub async fn load_subscriptions_from_storage() -> Result<()> {
 
     let subscriptions = storage.load_subscriptions().await?;
     let servers = storage.load_servers().await?;
+    let server_orders = storage.load_server_orders().await?;
+    let schedules = storage.load_update_schedules().await?;
+    let user_data = storage.load_server_user_data().await?;
+    let health = storage.load_subscription_health().await?;
 
     // Release storage guard before acquiring manager lock
     drop(storage_guard);
 
+    if let Some(scheduler) = SUBSCRIPTION_SCHEDULER.read().await.as_ref() {
+        for (subscription_id, schedule) in schedules {
+            if let Err(e) = scheduler.set_schedule(subscription_id, schedule).await {
+                tracing::warn!("Ignoring schedule of {}: {}", subscription_id, e);
+            }
+        }
+    }
+
     // Load subscriptions and servers into manager
     let mut manager_guard = SUBSCRIPTION_MANAGER.write().await;
     let manager = manager_guard
@@ -287,6 +1435,11 @@ pub async fn load_subscriptions_from_storage() -> Result<()> {
     // This is a workaround since we don't have a public API to add existing subscriptions
     manager.subscriptions = subscriptions;
     manager.servers = servers.clone();
+    for (subscription_id, keys) in server_orders {
+        manager.set_server_order(subscription_id, keys);
+    }
+    manager.set_user_data(user_data);
+    manager.set_health(health);
 
     tracing::info!(
         "Loaded {} subscriptions and {} servers from storage",
@@ -306,4 +1459,37 @@ mod tests {
         let result = init_subscription_manager(":memory:".to_string()).await;
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_fetch_settings() {
+        let current = HttpClientConfig::default();
+        let settings = SubscriptionFetchSettings {
+            route_order: "proxy_then_direct".to_string(),
+            proxy_url: Some("http://127.0.0.1:10809".to_string()),
+            max_attempts: 2,
+            ..fetch_settings(&current)
+        };
+        let config = http_config(settings, &current).unwrap();
+        assert_eq!(config.route_order, RouteOrder::ProxyThenDirect);
+        assert_eq!(config.max_retries, 2);
+        assert_eq!(config.user_agent, current.user_agent);
+        assert_eq!(fetch_settings(&config).route_order, "proxy_then_direct");
+
+        for invalid in [
+            SubscriptionFetchSettings {
+                route_order: "sideways".to_string(),
+                ..fetch_settings(&current)
+            },
+            SubscriptionFetchSettings {
+                max_attempts: 0,
+                ..fetch_settings(&current)
+            },
+            SubscriptionFetchSettings {
+                retry_on_status: vec![1000],
+                ..fetch_settings(&current)
+            },
+        ] {
+            assert!(http_config(invalid, &current).is_err());
+        }
+    }
 }
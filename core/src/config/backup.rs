@@ -0,0 +1,378 @@
+//! Configuration backups
+//!
+//! A backup is a single JSON archive holding the configuration, the proxy
+//! servers and a snapshot of the subscription database. Besides on demand,
+//! backups are made automatically before operations that overwrite data
+//! (importing or restoring a configuration, updating all subscriptions,
+//! upgrading the configuration schema) and on a schedule. Automatic backups
+//! prune the backup directory to the configured retention.
+
+use super::manager::ConfigManager;
+use super::ProxyServerConfig;
+use crate::error::{ConfigError, ConfigResult};
+use crate::subscription::SubscriptionStorage;
+use crate::utils::crypto::{decrypt_aes256, encrypt_aes256};
+use crate::utils::preflight::preflight;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Name of the backup directory inside the data directory
+pub const BACKUP_DIR_NAME: &str = "backups";
+
+/// Version of the archive format written by this release
+pub const BACKUP_ARCHIVE_VERSION: u32 = 1;
+
+/// File name prefix of backups
+const BACKUP_PREFIX: &str = "config_backup_";
+
+/// Why a backup was made
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackupReason {
+    /// Requested by the user
+    Manual,
+    /// Before importing a configuration
+    Import,
+    /// Before restoring another backup
+    Restore,
+    /// Before updating all subscriptions
+    SubscriptionUpdate,
+    /// Before upgrading the configuration schema
+    Migration,
+    /// Periodic backup
+    Scheduled,
+}
+
+/// Automatic backup settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct BackupSettings {
+    /// Back up before operations that overwrite data
+    pub auto_backup: bool,
+    /// Hours between scheduled backups, 0 to disable them
+    pub interval_hours: u32,
+    /// Backups kept when pruning after an automatic backup
+    pub keep: usize,
+}
+
+impl Default for BackupSettings {
+    fn default() -> Self {
+        Self {
+            auto_backup: true,
+            interval_hours: 24,
+            keep: 10,
+        }
+    }
+}
+
+impl BackupSettings {
+    /// Validate the settings
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.keep == 0 {
+            return Err(ConfigError::Validation(
+                "At least one backup must be kept".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Contents of a backup file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupArchive {
+    /// Archive format version, 0 for a backup of the configuration alone
+    /// as written by older releases
+    pub archive_version: u32,
+    /// When the backup was made
+    pub created_at: DateTime<Utc>,
+    /// Why the backup was made
+    pub reason: BackupReason,
+    /// Configuration as saved, possibly at an older schema version
+    #[serde(default)]
+    pub config: Option<serde_json::Value>,
+    /// Proxy servers
+    #[serde(default)]
+    pub proxy_configs: Vec<ProxyServerConfig>,
+    /// Base64-encoded snapshot of the subscription database
+    #[serde(default)]
+    pub subscription_db: Option<String>,
+}
+
+impl BackupArchive {
+    /// Empty archive
+    pub fn new(reason: BackupReason) -> Self {
+        Self {
+            archive_version: BACKUP_ARCHIVE_VERSION,
+            created_at: Utc::now(),
+            reason,
+            config: None,
+            proxy_configs: Vec::new(),
+            subscription_db: None,
+        }
+    }
+
+    /// Parse an archive, or a plain configuration backup of older releases
+    pub fn from_json(json: &str) -> ConfigResult<Self> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        if value.get("archive_version").is_none() {
+            return Ok(Self {
+                archive_version: 0,
+                config: Some(value),
+                ..Self::new(BackupReason::Manual)
+            });
+        }
+
+        let archive: Self = serde_json::from_value(value)?;
+        if archive.archive_version > BACKUP_ARCHIVE_VERSION {
+            return Err(ConfigError::UnsupportedVersion {
+                found: archive.archive_version,
+                supported: BACKUP_ARCHIVE_VERSION,
+            });
+        }
+        Ok(archive)
+    }
+
+    /// Read a backup file, decrypting it with `key` if it is not plain JSON
+    pub fn read(path: &Path, key: Option<&[u8; 32]>) -> ConfigResult<Self> {
+        let content = std::fs::read_to_string(path)?;
+        if content.trim_start().starts_with('{') {
+            return Self::from_json(&content);
+        }
+        let key = key.ok_or_else(|| {
+            ConfigError::Validation("Backup is encrypted and no key is set".to_string())
+        })?;
+        let decrypted = decrypt_aes256(&content, key)
+            .map_err(|e| ConfigError::Validation(format!("Decryption failed: {}", e)))?;
+        let json = String::from_utf8(decrypted)
+            .map_err(|e| ConfigError::Validation(format!("UTF-8 decode failed: {}", e)))?;
+        Self::from_json(&json)
+    }
+
+    /// Write the archive to a new backup file in `dir`, encrypted with
+    /// `key` if set
+    pub fn write(&self, dir: &Path, key: Option<&[u8; 32]>) -> ConfigResult<PathBuf> {
+        let json = serde_json::to_string_pretty(self)?;
+        let content = match key {
+            Some(key) => encrypt_aes256(json.as_bytes(), key)
+                .map_err(|e| ConfigError::Validation(format!("Encryption failed: {}", e)))?,
+            None => json,
+        };
+
+        // Microseconds keep backups made in quick succession apart
+        let timestamp = Utc::now().format("%Y%m%d_%H%M%S_%6f");
+        let path = dir.join(format!("{}{}.json", BACKUP_PREFIX, timestamp));
+        preflight(dir, content.len() as u64)?;
+        std::fs::write(&path, content)?;
+
+        info!("Backup ({:?}) created at {:?}", self.reason, path);
+        Ok(path)
+    }
+
+    /// Add a snapshot of the subscription database at `db_path`
+    pub async fn add_subscription_db(&mut self, db_path: &Path, dir: &Path) -> ConfigResult<()> {
+        let snapshot = snapshot_path(dir);
+        preflight(dir, std::fs::metadata(db_path)?.len())?;
+        SubscriptionStorage::snapshot_file(db_path, &snapshot).await?;
+        self.take_snapshot(&snapshot)
+    }
+
+    /// Add a snapshot of an open subscription database
+    pub async fn add_subscription_storage(
+        &mut self,
+        storage: &SubscriptionStorage,
+        dir: &Path,
+    ) -> ConfigResult<()> {
+        let snapshot = snapshot_path(dir);
+        preflight(dir, 0)?;
+        storage.snapshot(&snapshot).await?;
+        self.take_snapshot(&snapshot)
+    }
+
+    /// Move the snapshot file at `path` into the archive
+    fn take_snapshot(&mut self, path: &Path) -> ConfigResult<()> {
+        let bytes = std::fs::read(path);
+        let _ = std::fs::remove_file(path);
+        self.subscription_db = Some(BASE64.encode(bytes?));
+        Ok(())
+    }
+
+    /// Subscription database snapshot, if the archive has one
+    pub fn subscription_db(&self) -> ConfigResult<Option<Vec<u8>>> {
+        self.subscription_db
+            .as_deref()
+            .map(|encoded| {
+                BASE64.decode(encoded).map_err(|e| {
+                    ConfigError::Validation(format!("Invalid database snapshot: {}", e))
+                })
+            })
+            .transpose()
+    }
+}
+
+/// Temporary file for a database snapshot in `dir`
+fn snapshot_path(dir: &Path) -> PathBuf {
+    dir.join(format!(".snapshot_{}.db", uuid::Uuid::new_v4()))
+}
+
+/// Where a restored subscription database waits until the next start
+///
+/// The database cannot be replaced while it is open, so restoring a backup
+/// writes the snapshot here and [`SubscriptionStorage::new`] swaps it in.
+pub fn pending_restore_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.as_os_str().to_owned();
+    name.push(".restore");
+    PathBuf::from(name)
+}
+
+/// Backups in `dir`, newest first
+pub fn list_backups(dir: &Path) -> ConfigResult<Vec<PathBuf>> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_backup = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .is_some_and(|name| name.starts_with(BACKUP_PREFIX) && name.ends_with(".json"));
+        if path.is_file() && is_backup {
+            backups.push(path);
+        }
+    }
+
+    // Sort by modification time (newest first)
+    backups.sort_by(|a, b| {
+        let a_time = std::fs::metadata(a).and_then(|m| m.modified()).ok();
+        let b_time = std::fs::metadata(b).and_then(|m| m.modified()).ok();
+        b_time.cmp(&a_time)
+    });
+
+    Ok(backups)
+}
+
+/// Delete all but the `keep` newest backups in `dir`
+pub fn cleanup_backups(dir: &Path, keep: usize) -> ConfigResult<usize> {
+    let backups = list_backups(dir)?;
+
+    let mut deleted_count = 0;
+    for backup in backups.iter().skip(keep) {
+        std::fs::remove_file(backup)?;
+        deleted_count += 1;
+        debug!("Deleted old backup: {:?}", backup);
+    }
+
+    if deleted_count > 0 {
+        info!("Cleaned up {} old backups", deleted_count);
+    }
+    Ok(deleted_count)
+}
+
+/// Back up an open subscription database to `dir`, e.g. before updating
+/// all subscriptions, and prune the directory to `keep` backups
+pub async fn backup_subscription_storage(
+    storage: &SubscriptionStorage,
+    dir: &Path,
+    reason: BackupReason,
+    keep: usize,
+) -> ConfigResult<PathBuf> {
+    let mut archive = BackupArchive::new(reason);
+    archive.add_subscription_storage(storage, dir).await?;
+    let path = archive.write(dir, None)?;
+    cleanup_backups(dir, keep)?;
+    Ok(path)
+}
+
+/// Back up `manager` periodically, as set in its backup settings
+///
+/// The interval is read when the task starts; the task ends right away if
+/// scheduled backups are disabled.
+pub fn spawn_scheduled_backups(manager: Arc<ConfigManager>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let hours = manager.get_config().await.backup.interval_hours;
+        if hours == 0 {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(u64::from(hours) * 3600));
+        // The first tick completes immediately
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if let Err(e) = manager.scheduled_backup().await {
+                warn!("Scheduled backup failed: {}", e);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let key = [7u8; 32];
+        let mut archive = BackupArchive::new(BackupReason::Import);
+        archive.config = Some(serde_json::json!({"schema_version": 1}));
+        archive.subscription_db = Some(BASE64.encode(b"SQLite format 3"));
+
+        let path = archive.write(dir.path(), Some(&key)).unwrap();
+        assert!(BackupArchive::read(&path, None).is_err());
+        let read = BackupArchive::read(&path, Some(&key)).unwrap();
+        assert_eq!(read.reason, BackupReason::Import);
+        assert_eq!(read.config, archive.config);
+        assert_eq!(read.subscription_db().unwrap().unwrap(), b"SQLite format 3");
+        assert_eq!(list_backups(dir.path()).unwrap(), vec![path]);
+    }
+
+    #[test]
+    fn test_legacy_backup() {
+        let archive = BackupArchive::from_json(r#"{"proxy": {"http_port": 7070}}"#).unwrap();
+        assert_eq!(archive.archive_version, 0);
+        assert_eq!(archive.config.unwrap()["proxy"]["http_port"], 7070);
+
+        let newer = format!(
+            r#"{{"archive_version": {}, "created_at": "2024-01-01T00:00:00Z", "reason": "manual"}}"#,
+            BACKUP_ARCHIVE_VERSION + 1
+        );
+        assert!(matches!(
+            BackupArchive::from_json(&newer),
+            Err(ConfigError::UnsupportedVersion { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_backup_subscription_storage() {
+        let dir = TempDir::new().unwrap();
+        let db_path = dir.path().join("subscriptions.db");
+        let storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        let backup_dir = dir.path().join(BACKUP_DIR_NAME);
+
+        for _ in 0..3 {
+            backup_subscription_storage(&storage, &backup_dir, BackupReason::SubscriptionUpdate, 2)
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let backups = list_backups(&backup_dir).unwrap();
+        assert_eq!(backups.len(), 2);
+        let archive = BackupArchive::read(&backups[0], None).unwrap();
+        assert!(archive.config.is_none());
+        assert!(archive
+            .subscription_db()
+            .unwrap()
+            .unwrap()
+            .starts_with(b"SQLite format 3"));
+        // No snapshot files left behind
+        assert_eq!(std::fs::read_dir(&backup_dir).unwrap().count(), 2);
+    }
+}
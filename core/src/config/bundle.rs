@@ -0,0 +1,523 @@
+//! State bundles
+//!
+//! A bundle packages everything needed to move to another device: the
+//! configuration (settings, routing rule sets, profiles), the proxy
+//! servers, subscriptions with their servers, manual server order, update
+//! schedules and per-server preferences such as favorites and aliases.
+//! Bundles are versioned JSON files. Secrets can be left out, e.g. when
+//! sharing a setup rather than moving it; such bundles bring no proxy
+//! servers or remote subscriptions along, as their masked URLs and
+//! credentials could not be used.
+
+use super::{Config, ProxyServerConfig, StreamSettings};
+use crate::error::{ConfigError, ConfigResult, StorageResult};
+use crate::subscription::{
+    Server, ServerUserData, Subscription, SubscriptionStorage, UpdateSchedule,
+};
+use crate::utils::redact;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Version of the bundle format written by this release
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// What importing a bundle does with items that already exist
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    /// Keep the existing item and the current settings
+    #[default]
+    Skip,
+    /// Replace the existing item and take the settings from the bundle
+    Overwrite,
+    /// Import the item under a new ID next to the existing one
+    KeepBoth,
+}
+
+/// Counts of imported items
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Items added, including those imported under a new ID
+    pub added: usize,
+    /// Existing items replaced
+    pub replaced: usize,
+    /// Items left out because they already exist
+    pub skipped: usize,
+    /// Items left out because the bundle holds no credentials for them
+    pub without_credentials: usize,
+}
+
+/// Exported app state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateBundle {
+    /// Bundle format version
+    pub bundle_version: u32,
+    /// When the bundle was made
+    pub created_at: DateTime<Utc>,
+    /// Whether credentials and subscription URLs are included
+    pub includes_secrets: bool,
+    /// Configuration, possibly at an older schema version
+    pub config: serde_json::Value,
+    /// Proxy servers
+    #[serde(default)]
+    pub proxy_configs: Vec<ProxyServerConfig>,
+    /// Subscriptions and local groups
+    #[serde(default)]
+    pub subscriptions: Vec<Subscription>,
+    /// Servers of the subscriptions
+    #[serde(default)]
+    pub servers: Vec<Server>,
+    /// Manual server order per subscription
+    #[serde(default)]
+    pub server_orders: HashMap<Uuid, Vec<String>>,
+    /// Update schedules differing from the default
+    #[serde(default)]
+    pub update_schedules: HashMap<Uuid, UpdateSchedule>,
+    /// Per-server preferences by server fingerprint
+    #[serde(default)]
+    pub user_data: HashMap<String, ServerUserData>,
+}
+
+impl StateBundle {
+    /// Bundle of `config` and `proxy_configs`, without subscriptions
+    pub fn new(config: &Config, proxy_configs: Vec<ProxyServerConfig>) -> ConfigResult<Self> {
+        Ok(Self {
+            bundle_version: BUNDLE_FORMAT_VERSION,
+            created_at: Utc::now(),
+            includes_secrets: true,
+            config: serde_json::to_value(config)?,
+            proxy_configs,
+            subscriptions: Vec::new(),
+            servers: Vec::new(),
+            server_orders: HashMap::new(),
+            update_schedules: HashMap::new(),
+            user_data: HashMap::new(),
+        })
+    }
+
+    /// Parse a bundle
+    pub fn from_json(json: &str) -> ConfigResult<Self> {
+        let bundle: Self = serde_json::from_str(json)?;
+        if bundle.bundle_version > BUNDLE_FORMAT_VERSION {
+            return Err(ConfigError::UnsupportedVersion {
+                found: bundle.bundle_version,
+                supported: BUNDLE_FORMAT_VERSION,
+            });
+        }
+        Ok(bundle)
+    }
+
+    /// Add the subscriptions, servers and preferences in `storage`
+    pub async fn add_subscriptions(&mut self, storage: &SubscriptionStorage) -> StorageResult<()> {
+        self.subscriptions = storage.load_subscriptions().await?;
+        self.servers = storage.load_servers().await?;
+        self.server_orders = storage.load_server_orders().await?;
+        self.update_schedules = storage.load_update_schedules().await?;
+        self.user_data = storage.load_server_user_data().await?;
+        Ok(())
+    }
+
+    /// Mask credentials and subscription URLs
+    pub fn strip_secrets(&mut self) {
+        self.includes_secrets = false;
+        redact::redact_json_fields(&mut self.config);
+        for proxy in &mut self.proxy_configs {
+            strip_settings(&mut proxy.settings, &mut proxy.stream_settings);
+        }
+        for server in &mut self.servers {
+            strip_settings(&mut server.config, &mut server.stream_settings);
+        }
+        for subscription in self.subscriptions.iter_mut().filter(|s| !s.is_local()) {
+            subscription.url = redact::MASK.to_string();
+            subscription.request = subscription.request.without_secrets();
+        }
+    }
+
+    /// Merge the bundle's configuration into `current`
+    ///
+    /// Routing rule sets and profiles are merged by ID; the other settings
+    /// are taken from the bundle only with [`ConflictResolution::Overwrite`].
+    pub fn merge_config(
+        &self,
+        current: &mut Config,
+        resolution: ConflictResolution,
+        summary: &mut ImportSummary,
+    ) -> ConfigResult<()> {
+        let (incoming, _) = Config::from_json(&self.config.to_string())?;
+        let mut merged = match resolution {
+            ConflictResolution::Overwrite => incoming.clone(),
+            _ => current.clone(),
+        };
+        merged.routing_rules = current.routing_rules.clone();
+        merged.profiles = current.profiles.clone();
+
+        let renamed = merge_by_id(
+            &mut merged.routing_rules,
+            incoming.routing_rules,
+            |r| r.id.clone(),
+            |r, id| r.id = id,
+            resolution,
+            summary,
+        );
+        let mut profiles = incoming.profiles;
+        for profile in &mut profiles {
+            for id in profile.routing_rule_sets.iter_mut().flatten() {
+                if let Some(new_id) = renamed.get(id) {
+                    *id = new_id.clone();
+                }
+            }
+        }
+        merge_by_id(
+            &mut merged.profiles,
+            profiles,
+            |p| p.id.clone(),
+            |p, id| p.id = id,
+            resolution,
+            summary,
+        );
+
+        merged.validate()?;
+        *current = merged;
+        Ok(())
+    }
+
+    /// Merge the bundle's proxy servers into `current`
+    ///
+    /// Without secrets in the bundle, no servers are imported.
+    pub fn merge_proxy_configs(
+        &self,
+        current: &mut HashMap<String, ProxyServerConfig>,
+        resolution: ConflictResolution,
+        summary: &mut ImportSummary,
+    ) {
+        if !self.includes_secrets {
+            summary.without_credentials += self.proxy_configs.len();
+            return;
+        }
+        let mut proxies: Vec<_> = current.drain().map(|(_, proxy)| proxy).collect();
+        merge_by_id(
+            &mut proxies,
+            self.proxy_configs.clone(),
+            |p| p.id.clone(),
+            |p, id| p.id = id,
+            resolution,
+            summary,
+        );
+        current.extend(proxies.into_iter().map(|proxy| (proxy.id.clone(), proxy)));
+    }
+
+    /// Import the bundle's subscriptions, servers and preferences into
+    /// `storage`
+    ///
+    /// A subscription brings its servers, manual order and update schedule
+    /// along; imported under a new ID, its servers get new IDs as well.
+    /// Without secrets in the bundle, only local groups are imported,
+    /// without their servers, and existing groups are never replaced.
+    pub async fn import_subscriptions(
+        &self,
+        storage: &SubscriptionStorage,
+        resolution: ConflictResolution,
+        summary: &mut ImportSummary,
+    ) -> StorageResult<()> {
+        let resolution = self.credential_resolution(resolution);
+        let existing = storage.load_subscriptions().await?;
+
+        for subscription in &self.subscriptions {
+            if !self.includes_secrets && !subscription.is_local() {
+                summary.without_credentials += 1;
+                continue;
+            }
+            let exists = existing.iter().any(|s| s.id == subscription.id);
+            let id = match (exists, resolution) {
+                (false, _) => subscription.id,
+                (true, ConflictResolution::Skip) => {
+                    summary.skipped += 1;
+                    continue;
+                }
+                (true, ConflictResolution::Overwrite) => {
+                    storage
+                        .delete_servers_for_subscription(subscription.id)
+                        .await?;
+                    subscription.id
+                }
+                (true, ConflictResolution::KeepBoth) => Uuid::new_v4(),
+            };
+            if exists && resolution == ConflictResolution::Overwrite {
+                summary.replaced += 1;
+            } else {
+                summary.added += 1;
+            }
+
+            storage
+                .save_subscription(&Subscription {
+                    id,
+                    ..subscription.clone()
+                })
+                .await?;
+            for server in self
+                .servers
+                .iter()
+                .filter(|s| s.subscription_id == subscription.id)
+            {
+                if !self.includes_secrets {
+                    summary.without_credentials += 1;
+                    continue;
+                }
+                let mut server = server.clone();
+                if id != subscription.id {
+                    server.id = Uuid::new_v4();
+                }
+                server.subscription_id = id;
+                storage.save_server(&server).await?;
+            }
+            if let Some(order) = self.server_orders.get(&subscription.id) {
+                storage.save_server_order(id, order).await?;
+            }
+            if let Some(schedule) = self.update_schedules.get(&subscription.id) {
+                storage.save_update_schedule(id, schedule).await?;
+            }
+        }
+
+        // Preferences are keyed by server fingerprint and cannot be kept twice
+        let existing = storage.load_server_user_data().await?;
+        let entries: Vec<_> = self
+            .user_data
+            .iter()
+            .filter(|(fingerprint, _)| {
+                resolution == ConflictResolution::Overwrite || !existing.contains_key(*fingerprint)
+            })
+            .map(|(fingerprint, data)| (fingerprint.clone(), data.clone()))
+            .collect();
+        storage.save_server_user_data(&entries).await
+    }
+
+    /// Resolution for items that held credentials, which a bundle without
+    /// secrets must not overwrite
+    fn credential_resolution(&self, resolution: ConflictResolution) -> ConflictResolution {
+        match resolution {
+            ConflictResolution::Overwrite if !self.includes_secrets => ConflictResolution::Skip,
+            resolution => resolution,
+        }
+    }
+}
+
+/// Mask the credentials in protocol and stream settings
+fn strip_settings(
+    settings: &mut HashMap<String, serde_json::Value>,
+    stream_settings: &mut Option<StreamSettings>,
+) {
+    // The user ID of VMess/VLESS
+    if let Some(id) = settings.get_mut("id") {
+        *id = serde_json::Value::from(redact::MASK);
+    }
+    let mut value = serde_json::json!(settings);
+    redact::redact_json_fields(&mut value);
+    if let Ok(stripped) = serde_json::from_value(value) {
+        *settings = stripped;
+    }
+
+    if let Some(stream) = stream_settings.as_ref() {
+        let stripped = serde_json::to_value(stream).ok().and_then(|mut value| {
+            redact::redact_json_fields(&mut value);
+            serde_json::from_value(value).ok()
+        });
+        *stream_settings = stripped;
+    }
+}
+
+/// Merge `incoming` into `existing` by ID, returning the IDs given to items
+/// imported under a new ID
+fn merge_by_id<T>(
+    existing: &mut Vec<T>,
+    incoming: Vec<T>,
+    id: impl Fn(&T) -> String,
+    set_id: impl Fn(&mut T, String),
+    resolution: ConflictResolution,
+    summary: &mut ImportSummary,
+) -> HashMap<String, String> {
+    let mut renamed = HashMap::new();
+    for mut item in incoming {
+        let item_id = id(&item);
+        let Some(pos) = existing.iter().position(|e| id(e) == item_id) else {
+            existing.push(item);
+            summary.added += 1;
+            continue;
+        };
+        match resolution {
+            ConflictResolution::Skip => summary.skipped += 1,
+            ConflictResolution::Overwrite => {
+                existing[pos] = item;
+                summary.replaced += 1;
+            }
+            ConflictResolution::KeepBoth => {
+                let new_id = Uuid::new_v4().to_string();
+                set_id(&mut item, new_id.clone());
+                renamed.insert(item_id, new_id);
+                existing.push(item);
+                summary.added += 1;
+            }
+        }
+    }
+    renamed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Profile, RoutingRuleSet, SystemProxyBehavior};
+
+    fn rule_set(id: &str, name: &str) -> RoutingRuleSet {
+        RoutingRuleSet {
+            id: id.to_string(),
+            name: name.to_string(),
+            enabled: true,
+            rules: vec![],
+        }
+    }
+
+    fn bundle() -> StateBundle {
+        let mut config = Config::default();
+        config.proxy.http_port = 7070;
+        config.routing_rules = vec![rule_set("office", "Office (bundle)")];
+        config.profiles = vec![Profile {
+            id: "work".to_string(),
+            name: "Work".to_string(),
+            server_id: None,
+            mode: "smart".to_string(),
+            routing_rule_sets: Some(vec!["office".to_string()]),
+            http_port: None,
+            socks_port: None,
+            xray_version: None,
+            core: Default::default(),
+            rate_limit: Default::default(),
+            system_proxy: SystemProxyBehavior::Keep,
+        }];
+        StateBundle::new(&config, vec![]).unwrap()
+    }
+
+    #[test]
+    fn test_merge_config() {
+        let current = Config {
+            routing_rules: vec![rule_set("office", "Office")],
+            ..Default::default()
+        };
+
+        let mut summary = ImportSummary::default();
+        let mut skipped = current.clone();
+        bundle()
+            .merge_config(&mut skipped, ConflictResolution::Skip, &mut summary)
+            .unwrap();
+        assert_eq!(skipped.proxy.http_port, 8080);
+        assert_eq!(skipped.routing_rules[0].name, "Office");
+        assert_eq!(skipped.profiles.len(), 1);
+        assert_eq!((summary.added, summary.skipped), (1, 1));
+
+        let mut overwritten = current.clone();
+        bundle()
+            .merge_config(
+                &mut overwritten,
+                ConflictResolution::Overwrite,
+                &mut ImportSummary::default(),
+            )
+            .unwrap();
+        assert_eq!(overwritten.proxy.http_port, 7070);
+        assert_eq!(overwritten.routing_rules[0].name, "Office (bundle)");
+
+        // The imported profile follows its renamed rule set
+        let mut both = current.clone();
+        bundle()
+            .merge_config(
+                &mut both,
+                ConflictResolution::KeepBoth,
+                &mut ImportSummary::default(),
+            )
+            .unwrap();
+        assert_eq!(both.routing_rules.len(), 2);
+        let renamed = &both.routing_rules[1].id;
+        assert_eq!(
+            both.profiles[0].routing_rule_sets.as_ref().unwrap(),
+            &vec![renamed.clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_subscriptions() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name: "Provider".to_string(),
+            url: "https://example.com/sub?token=secret".to_string(),
+            last_update: None,
+            server_count: 1,
+            status: crate::subscription::SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+        let server = Server {
+            id: Uuid::new_v4(),
+            name: "Tokyo".to_string(),
+            raw_name: None,
+            address: "tokyo.example.com".to_string(),
+            port: 443,
+            protocol: "vless".to_string(),
+            config: [("id".to_string(), serde_json::json!("user-id"))].into(),
+            stream_settings: None,
+            subscription_id: subscription.id,
+            tags: vec![],
+        };
+        storage.save_subscription(&subscription).await.unwrap();
+        storage.save_server(&server).await.unwrap();
+
+        let mut exported = bundle();
+        exported.add_subscriptions(&storage).await.unwrap();
+        let mut stripped = exported.clone();
+        stripped.strip_secrets();
+        assert_eq!(stripped.subscriptions[0].url, redact::MASK);
+        assert_eq!(stripped.servers[0].config["id"], redact::MASK);
+
+        // Without secrets, existing subscriptions are kept even on overwrite
+        let mut summary = ImportSummary::default();
+        stripped
+            .import_subscriptions(&storage, ConflictResolution::Overwrite, &mut summary)
+            .await
+            .unwrap();
+        assert_eq!(summary.without_credentials, 1);
+        assert_eq!(
+            storage.load_subscriptions().await.unwrap()[0].url,
+            subscription.url
+        );
+
+        // and new ones are not added with masked credentials
+        let empty = SubscriptionStorage::new_in_memory().await.unwrap();
+        let mut summary = ImportSummary::default();
+        stripped
+            .import_subscriptions(&empty, ConflictResolution::KeepBoth, &mut summary)
+            .await
+            .unwrap();
+        assert_eq!((summary.added, summary.without_credentials), (0, 1));
+        assert!(empty.load_subscriptions().await.unwrap().is_empty());
+        assert!(empty.load_servers().await.unwrap().is_empty());
+
+        let mut summary = ImportSummary::default();
+        exported
+            .import_subscriptions(&storage, ConflictResolution::KeepBoth, &mut summary)
+            .await
+            .unwrap();
+        assert_eq!(summary.added, 1);
+        let servers = storage.load_servers().await.unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_ne!(servers[0].id, servers[1].id);
+        assert_eq!(storage.load_subscriptions().await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_newer_bundle_rejected() {
+        let mut bundle = serde_json::to_value(bundle()).unwrap();
+        bundle["bundle_version"] = serde_json::json!(BUNDLE_FORMAT_VERSION + 1);
+        assert!(matches!(
+            StateBundle::from_json(&bundle.to_string()),
+            Err(ConfigError::UnsupportedVersion { .. })
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+//! DNS settings
+//!
+//! User-editable DNS configuration. An empty server list means "use the
+//! built-in defaults"; otherwise the settings are emitted as a full Xray
+//! `dns` object (plus `fakedns` when enabled).
+
+use crate::error::ConfigError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// DNS query strategy
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum QueryStrategy {
+    /// Query both A and AAAA records
+    #[default]
+    UseIP,
+    /// Query A records only
+    UseIPv4,
+    /// Query AAAA records only
+    UseIPv6,
+}
+
+impl QueryStrategy {
+    /// Strategy name as used in Xray configuration
+    pub fn as_xray_str(&self) -> &'static str {
+        match self {
+            QueryStrategy::UseIP => "UseIP",
+            QueryStrategy::UseIPv4 => "UseIPv4",
+            QueryStrategy::UseIPv6 => "UseIPv6",
+        }
+    }
+}
+
+impl std::str::FromStr for QueryStrategy {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "UseIP" => Ok(QueryStrategy::UseIP),
+            "UseIPv4" => Ok(QueryStrategy::UseIPv4),
+            "UseIPv6" => Ok(QueryStrategy::UseIPv6),
+            _ => Err(ConfigError::Validation(format!(
+                "Invalid DNS query strategy: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// A single upstream DNS server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DnsServer {
+    /// Server address (IP, "https://..." DoH URL, "localhost" or "fakedns")
+    pub address: String,
+    /// Server port (defaults to 53)
+    #[serde(default)]
+    pub port: Option<u16>,
+    /// Domains this server is preferred for (e.g. "geosite:cn")
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// Only accept answers within these IP ranges (e.g. "geoip:cn")
+    #[serde(default)]
+    pub expect_ips: Vec<String>,
+    /// Skip this server when falling back
+    #[serde(default)]
+    pub skip_fallback: bool,
+}
+
+impl DnsServer {
+    /// Create a plain server entry
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            port: None,
+            domains: Vec::new(),
+            expect_ips: Vec::new(),
+            skip_fallback: false,
+        }
+    }
+
+    /// Whether the server only has an address (emitted as a plain string)
+    pub fn is_simple(&self) -> bool {
+        self.port.is_none()
+            && self.domains.is_empty()
+            && self.expect_ips.is_empty()
+            && !self.skip_fallback
+    }
+}
+
+/// Per-domain resolver rule (split DNS)
+///
+/// Names under the rule's domains are resolved by its servers, and both the
+/// lookups and the resulting connections go direct. This keeps internal
+/// names (e.g. a company's `corp.internal`) working while the proxy is on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SplitDnsRule {
+    /// Domains and their subdomains, e.g. "corp.internal" or "*.corp.internal"
+    pub domains: Vec<String>,
+    /// Resolvers for these domains
+    pub servers: Vec<IpAddr>,
+}
+
+impl SplitDnsRule {
+    /// Create a rule sending `domains` to `servers`
+    pub fn new(domains: Vec<String>, servers: Vec<IpAddr>) -> Self {
+        Self { domains, servers }
+    }
+
+    /// Domain suffixes, without wildcard prefix and in lowercase
+    pub fn suffixes(&self) -> Vec<String> {
+        self.domains
+            .iter()
+            .map(|domain| {
+                let domain = domain.trim();
+                let domain = domain.strip_prefix("*.").unwrap_or(domain);
+                domain.trim_matches('.').to_lowercase()
+            })
+            .collect()
+    }
+
+    /// Domains in Xray syntax, matching subdomains too
+    pub fn xray_domains(&self) -> Vec<String> {
+        self.suffixes()
+            .into_iter()
+            .map(|suffix| format!("domain:{}", suffix))
+            .collect()
+    }
+
+    /// Validate the rule
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.domains.is_empty() || self.servers.is_empty() {
+            return Err(ConfigError::Validation(
+                "Split DNS rule needs at least one domain and one server".to_string(),
+            ));
+        }
+        for (domain, suffix) in self.domains.iter().zip(self.suffixes()) {
+            if suffix.is_empty() || suffix.contains(|c: char| c.is_whitespace() || c == '*') {
+                return Err(ConfigError::Validation(format!(
+                    "Invalid split DNS domain: {}",
+                    domain
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// FakeDNS settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FakeDnsSettings {
+    /// Fake IP pool (CIDR)
+    pub ip_pool: String,
+    /// Number of addresses kept in the pool
+    pub pool_size: u32,
+}
+
+impl Default for FakeDnsSettings {
+    fn default() -> Self {
+        Self {
+            ip_pool: "198.18.0.0/15".to_string(),
+            pool_size: 65535,
+        }
+    }
+}
+
+/// DNS settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct DnsSettings {
+    /// Upstream servers, in priority order
+    #[serde(default)]
+    pub servers: Vec<DnsServer>,
+    /// Static host overrides (domain -> addresses)
+    #[serde(default)]
+    pub hosts: HashMap<String, Vec<String>>,
+    /// Query strategy
+    #[serde(default)]
+    pub query_strategy: QueryStrategy,
+    /// Client IP sent via EDNS Client Subnet
+    #[serde(default)]
+    pub client_ip: Option<String>,
+    /// FakeDNS settings (disabled if none)
+    #[serde(default)]
+    pub fake_dns: Option<FakeDnsSettings>,
+    /// Disable the DNS cache
+    #[serde(default)]
+    pub disable_cache: bool,
+    /// Per-domain resolvers, applied even with the built-in defaults
+    #[serde(default)]
+    pub split_rules: Vec<SplitDnsRule>,
+}
+
+impl DnsSettings {
+    /// Whether user settings are configured (otherwise built-in defaults apply)
+    pub fn is_configured(&self) -> bool {
+        !self.servers.is_empty()
+    }
+
+    /// Validate the settings
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for server in &self.servers {
+            if server.address.trim().is_empty() {
+                return Err(ConfigError::Validation(
+                    "DNS server address is empty".to_string(),
+                ));
+            }
+            if server.port == Some(0) {
+                return Err(ConfigError::InvalidPort(0));
+            }
+        }
+
+        for (domain, addresses) in &self.hosts {
+            if domain.trim().is_empty() || addresses.is_empty() {
+                return Err(ConfigError::Validation(format!(
+                    "Invalid DNS host override: {}",
+                    domain
+                )));
+            }
+        }
+
+        for rule in &self.split_rules {
+            rule.validate()?;
+        }
+
+        if let Some(ref client_ip) = self.client_ip {
+            if client_ip.parse::<IpAddr>().is_err() {
+                return Err(ConfigError::Validation(format!(
+                    "Invalid DNS client IP: {}",
+                    client_ip
+                )));
+            }
+        }
+
+        if let Some(ref fake_dns) = self.fake_dns {
+            let valid_pool = fake_dns
+                .ip_pool
+                .split_once('/')
+                .map(|(ip, prefix)| ip.parse::<IpAddr>().is_ok() && prefix.parse::<u8>().is_ok())
+                .unwrap_or(false);
+            if !valid_pool || fake_dns.pool_size == 0 {
+                return Err(ConfigError::Validation(format!(
+                    "Invalid FakeDNS pool: {}",
+                    fake_dns.ip_pool
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_is_unconfigured() {
+        let settings = DnsSettings::default();
+        assert!(!settings.is_configured());
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_split_dns_rule() {
+        let rule = SplitDnsRule::new(
+            vec![
+                "*.Corp.Internal".to_string(),
+                "intranet.example.".to_string(),
+            ],
+            vec!["10.0.0.53".parse().unwrap()],
+        );
+        assert!(rule.validate().is_ok());
+        assert_eq!(
+            rule.xray_domains(),
+            vec!["domain:corp.internal", "domain:intranet.example"]
+        );
+
+        assert!(SplitDnsRule::new(vec!["corp.internal".to_string()], vec![])
+            .validate()
+            .is_err());
+        assert!(
+            SplitDnsRule::new(vec!["*.".to_string()], vec!["10.0.0.53".parse().unwrap()])
+                .validate()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_query_strategy_names() {
+        for strategy in [
+            QueryStrategy::UseIP,
+            QueryStrategy::UseIPv4,
+            QueryStrategy::UseIPv6,
+        ] {
+            assert_eq!(
+                strategy.as_xray_str().parse::<QueryStrategy>().unwrap(),
+                strategy
+            );
+        }
+        assert!("ipv4".parse::<QueryStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut settings = DnsSettings {
+            servers: vec![DnsServer::new("8.8.8.8")],
+            client_ip: Some("1.2.3.4".to_string()),
+            fake_dns: Some(FakeDnsSettings::default()),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+
+        settings.client_ip = Some("not-an-ip".to_string());
+        assert!(settings.validate().is_err());
+
+        settings.client_ip = None;
+        settings.fake_dns = Some(FakeDnsSettings {
+            ip_pool: "198.18.0.0".to_string(),
+            pool_size: 10,
+        });
+        assert!(settings.validate().is_err());
+    }
+}
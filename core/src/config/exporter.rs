@@ -0,0 +1,404 @@
+//! Configuration Exporter
+//!
+//! The reverse of parsing: serializes proxy configurations for sharing with
+//! other devices, as share links, a base64 subscription body or a Clash YAML
+//! profile.
+
+use super::parser::ConfigParser;
+use super::{ProxyProtocol, ProxyServerConfig};
+use crate::error::{ConfigError, ConfigResult};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_yaml::{Mapping, Value};
+use std::str::FromStr;
+
+/// Name of the selector group in exported Clash profiles
+const CLASH_GROUP_NAME: &str = "Proxy";
+
+/// Export format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Share links, one per line
+    Links,
+    /// Share links as a base64 subscription body
+    Base64,
+    /// Clash YAML profile
+    ClashYaml,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "links" => Ok(Self::Links),
+            "base64" => Ok(Self::Base64),
+            "clash" | "clash_yaml" => Ok(Self::ClashYaml),
+            other => Err(ConfigError::Validation(format!(
+                "Unknown export format: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A server left out of an export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedServer {
+    /// Server name
+    pub name: String,
+    /// Why it could not be exported
+    pub reason: String,
+}
+
+/// Result of exporting several servers
+#[derive(Debug, Clone, Default)]
+pub struct Export {
+    /// Exported content
+    pub content: String,
+    /// Number of servers included
+    pub exported: usize,
+    /// Servers that the format cannot represent
+    pub skipped: Vec<SkippedServer>,
+}
+
+/// Configuration exporter
+pub struct ConfigExporter;
+
+impl ConfigExporter {
+    /// Encode one server as a share link, see [`ConfigParser::to_url`]
+    pub fn to_link(config: &ProxyServerConfig) -> ConfigResult<String> {
+        ConfigParser::to_url(config)
+    }
+
+    /// Export servers in the given format
+    pub fn export(configs: &[ProxyServerConfig], format: ExportFormat) -> Export {
+        match format {
+            ExportFormat::Links => Self::to_links(configs),
+            ExportFormat::Base64 => Self::to_base64_subscription(configs),
+            ExportFormat::ClashYaml => Self::to_clash_yaml(configs),
+        }
+    }
+
+    /// Export servers as share links, one per line
+    pub fn to_links(configs: &[ProxyServerConfig]) -> Export {
+        let (links, skipped) = Self::convert(configs, Self::to_link);
+        Export {
+            content: links.join("\n"),
+            exported: links.len(),
+            skipped,
+        }
+    }
+
+    /// Export servers as a base64 subscription body
+    ///
+    /// This is the format [`crate::subscription::SubscriptionParser`] reads
+    /// by default, and most clients accept it as a subscription URL body.
+    pub fn to_base64_subscription(configs: &[ProxyServerConfig]) -> Export {
+        let links = Self::to_links(configs);
+        Export {
+            content: BASE64.encode(links.content),
+            ..links
+        }
+    }
+
+    /// Export servers as a Clash YAML profile
+    ///
+    /// Besides the proxies the profile has one selector group holding all
+    /// of them and a rule sending all traffic through it, so it can be used
+    /// as is.
+    pub fn to_clash_yaml(configs: &[ProxyServerConfig]) -> Export {
+        let (proxies, skipped) = Self::convert(configs, Self::clash_proxy);
+        let names: Vec<Value> = proxies
+            .iter()
+            .filter_map(|proxy| proxy.get("name").cloned())
+            .collect();
+
+        let mut group = Mapping::new();
+        group.insert("name".into(), CLASH_GROUP_NAME.into());
+        group.insert("type".into(), "select".into());
+        group.insert("proxies".into(), Value::Sequence(names));
+
+        let mut profile = Mapping::new();
+        profile.insert("proxies".into(), Value::Sequence(proxies.clone()));
+        profile.insert(
+            "proxy-groups".into(),
+            Value::Sequence(vec![Value::Mapping(group)]),
+        );
+        profile.insert(
+            "rules".into(),
+            Value::Sequence(vec![format!("MATCH,{}", CLASH_GROUP_NAME).into()]),
+        );
+
+        Export {
+            // A mapping of plain values always serializes
+            content: serde_yaml::to_string(&profile).unwrap_or_default(),
+            exported: proxies.len(),
+            skipped,
+        }
+    }
+
+    /// Convert each server, collecting the ones that fail
+    fn convert<T>(
+        configs: &[ProxyServerConfig],
+        convert: impl Fn(&ProxyServerConfig) -> ConfigResult<T>,
+    ) -> (Vec<T>, Vec<SkippedServer>) {
+        let mut converted = Vec::with_capacity(configs.len());
+        let mut skipped = Vec::new();
+        for config in configs {
+            match convert(config) {
+                Ok(value) => converted.push(value),
+                Err(e) => skipped.push(SkippedServer {
+                    name: config.name.clone(),
+                    reason: e.to_string(),
+                }),
+            }
+        }
+        (converted, skipped)
+    }
+
+    /// Read a string setting
+    fn setting<'a>(config: &'a ProxyServerConfig, key: &str) -> Option<&'a str> {
+        config.settings.get(key).and_then(|v| v.as_str())
+    }
+
+    /// Read a required string setting
+    fn required<'a>(config: &'a ProxyServerConfig, key: &str) -> ConfigResult<&'a str> {
+        Self::setting(config, key).ok_or_else(|| ConfigError::MissingField(key.to_string()))
+    }
+
+    /// Clash proxy entry of a server
+    fn clash_proxy(config: &ProxyServerConfig) -> ConfigResult<Value> {
+        let mut proxy = Mapping::new();
+        proxy.insert("name".into(), config.name.as_str().into());
+        let proxy_type = match config.protocol {
+            ProxyProtocol::Vmess => "vmess",
+            ProxyProtocol::Vless => "vless",
+            ProxyProtocol::Trojan => "trojan",
+            ProxyProtocol::Shadowsocks => "ss",
+            ProxyProtocol::Socks => "socks5",
+            ProxyProtocol::Http => "http",
+            ProxyProtocol::Hysteria2 => "hysteria2",
+            ProxyProtocol::Tuic => "tuic",
+        };
+        proxy.insert("type".into(), proxy_type.into());
+        proxy.insert("server".into(), config.server.as_str().into());
+        proxy.insert("port".into(), config.port.into());
+
+        match config.protocol {
+            ProxyProtocol::Vmess => {
+                proxy.insert("uuid".into(), Self::required(config, "id")?.into());
+                let alter_id = config
+                    .settings
+                    .get("alterId")
+                    .and_then(|v| v.as_u64())
+                    .unwrap_or(0);
+                proxy.insert("alterId".into(), alter_id.into());
+                let cipher = Self::setting(config, "security").unwrap_or("auto");
+                proxy.insert("cipher".into(), cipher.into());
+            }
+            ProxyProtocol::Vless => {
+                proxy.insert("uuid".into(), Self::required(config, "id")?.into());
+                if let Some(flow) = Self::setting(config, "flow") {
+                    proxy.insert("flow".into(), flow.into());
+                }
+            }
+            ProxyProtocol::Trojan => {
+                proxy.insert(
+                    "password".into(),
+                    Self::required(config, "password")?.into(),
+                );
+            }
+            ProxyProtocol::Shadowsocks => {
+                proxy.insert("cipher".into(), Self::required(config, "method")?.into());
+                proxy.insert(
+                    "password".into(),
+                    Self::required(config, "password")?.into(),
+                );
+            }
+            ProxyProtocol::Socks | ProxyProtocol::Http => {
+                for key in ["username", "password"] {
+                    if let Some(value) = Self::setting(config, key) {
+                        proxy.insert(key.into(), value.into());
+                    }
+                }
+            }
+            ProxyProtocol::Hysteria2 => {
+                proxy.insert(
+                    "password".into(),
+                    Self::required(config, "password")?.into(),
+                );
+                if let Some(obfs) = Self::setting(config, "obfs") {
+                    proxy.insert("obfs".into(), obfs.into());
+                    if let Some(password) = Self::setting(config, "obfsPassword") {
+                        proxy.insert("obfs-password".into(), password.into());
+                    }
+                }
+            }
+            ProxyProtocol::Tuic => {
+                proxy.insert("uuid".into(), Self::required(config, "id")?.into());
+                proxy.insert(
+                    "password".into(),
+                    Self::required(config, "password")?.into(),
+                );
+                if let Some(congestion) = Self::setting(config, "congestionControl") {
+                    proxy.insert("congestion-controller".into(), congestion.into());
+                }
+                if let Some(mode) = Self::setting(config, "udpRelayMode") {
+                    proxy.insert("udp-relay-mode".into(), mode.into());
+                }
+            }
+        }
+        // Hysteria 2 and TUIC always run over QUIC with TLS
+        let quic = matches!(
+            config.protocol,
+            ProxyProtocol::Hysteria2 | ProxyProtocol::Tuic
+        );
+        if config.protocol != ProxyProtocol::Http {
+            proxy.insert("udp".into(), true.into());
+        }
+
+        if let Some(stream) = &config.stream_settings {
+            if stream.security == "reality" {
+                return Err(ConfigError::Validation(
+                    "REALITY servers cannot be exported to Clash".to_string(),
+                ));
+            }
+            if stream.network != "tcp" && !quic {
+                proxy.insert("network".into(), stream.network.as_str().into());
+            }
+
+            if stream.security == "tls" {
+                if !quic {
+                    proxy.insert("tls".into(), true.into());
+                }
+                if let Some(tls) = &stream.tls_settings {
+                    if let Some(sni) = &tls.server_name {
+                        // Trojan and the QUIC protocols name the SNI `sni`,
+                        // the V2Ray protocols `servername`
+                        let key = if config.protocol == ProxyProtocol::Trojan || quic {
+                            "sni"
+                        } else {
+                            "servername"
+                        };
+                        proxy.insert(key.into(), sni.as_str().into());
+                    }
+                    if tls.allow_insecure {
+                        proxy.insert("skip-cert-verify".into(), true.into());
+                    }
+                    if !tls.alpn.is_empty() {
+                        let alpn = tls.alpn.iter().map(|a| a.as_str().into()).collect();
+                        proxy.insert("alpn".into(), Value::Sequence(alpn));
+                    }
+                    if let Some(fingerprint) = &tls.fingerprint {
+                        proxy.insert("client-fingerprint".into(), fingerprint.as_str().into());
+                    }
+                }
+            }
+
+            if let Some(ws) = &stream.ws_settings {
+                let mut opts = Mapping::new();
+                opts.insert("path".into(), ws.path.as_str().into());
+                if let Some(host) = ws.headers.get("Host") {
+                    let mut headers = Mapping::new();
+                    headers.insert("Host".into(), host.as_str().into());
+                    opts.insert("headers".into(), Value::Mapping(headers));
+                }
+                proxy.insert("ws-opts".into(), Value::Mapping(opts));
+            }
+
+            if let Some(grpc) = &stream.grpc_settings {
+                let mut opts = Mapping::new();
+                opts.insert(
+                    "grpc-service-name".into(),
+                    grpc.service_name.as_str().into(),
+                );
+                proxy.insert("grpc-opts".into(), Value::Mapping(opts));
+            }
+
+            if let Some(mux) = stream.mux.as_ref().filter(|mux| mux.enabled) {
+                let mut smux = Mapping::new();
+                smux.insert("enabled".into(), true.into());
+                smux.insert("max-streams".into(), mux.concurrency.into());
+                proxy.insert("smux".into(), Value::Mapping(smux));
+            }
+        }
+
+        Ok(Value::Mapping(proxy))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subscription::SubscriptionParser;
+
+    fn configs() -> Vec<ProxyServerConfig> {
+        [
+            "vless://uuid-here@example.com:443?encryption=none&flow=xtls-rprx-vision&type=ws&security=tls&sni=sni.example.com&path=/ws&host=cdn.example.com&fp=chrome#VLESS",
+            "trojan://secret@example.org:443?type=grpc&security=tls&sni=t.example.org&serviceName=svc#Trojan",
+            "ss://YWVzLTI1Ni1nY206c2VjcmV0@1.2.3.4:8388#SS",
+        ]
+        .iter()
+        .map(|link| ConfigParser::parse_url(link).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn test_base64_subscription_round_trip() {
+        let mut configs = configs();
+        let mut socks = configs[2].clone();
+        socks.protocol = ProxyProtocol::Socks;
+        configs.push(socks);
+
+        let export = ConfigExporter::to_base64_subscription(&configs);
+        assert_eq!(export.exported, 3);
+        assert_eq!(export.skipped.len(), 1);
+
+        let parsed = SubscriptionParser::parse(&export.content).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].settings, configs[0].settings);
+        assert_eq!(parsed[1].name, "Trojan");
+    }
+
+    #[test]
+    fn test_clash_yaml_round_trip() {
+        let configs = configs();
+        let export = ConfigExporter::to_clash_yaml(&configs);
+        assert_eq!(export.exported, 3);
+        assert!(export.skipped.is_empty());
+        assert!(export.content.contains("MATCH,Proxy"));
+
+        let parsed = SubscriptionParser::parse(&export.content).unwrap();
+        assert_eq!(parsed.len(), 3);
+        for (parsed, config) in parsed.iter().zip(&configs) {
+            assert_eq!(parsed.name, config.name);
+            assert_eq!(parsed.server, config.server);
+            assert_eq!(parsed.port, config.port);
+            assert_eq!(parsed.protocol, config.protocol);
+        }
+
+        let vless = parsed[0].stream_settings.as_ref().unwrap();
+        let tls = vless.tls_settings.as_ref().unwrap();
+        assert_eq!(tls.server_name.as_deref(), Some("sni.example.com"));
+        assert_eq!(tls.fingerprint.as_deref(), Some("chrome"));
+        assert_eq!(vless.ws_settings.as_ref().unwrap().path, "/ws");
+        assert_eq!(parsed[0].settings["flow"], "xtls-rprx-vision");
+
+        let trojan = parsed[1].stream_settings.as_ref().unwrap();
+        assert_eq!(trojan.grpc_settings.as_ref().unwrap().service_name, "svc");
+        assert_eq!(parsed[2].settings, configs[2].settings);
+    }
+
+    #[test]
+    fn test_export_format() {
+        assert_eq!(
+            "Clash".parse::<ExportFormat>().unwrap(),
+            ExportFormat::ClashYaml
+        );
+        assert_eq!(
+            "base64".parse::<ExportFormat>().unwrap(),
+            ExportFormat::Base64
+        );
+        assert!("xml".parse::<ExportFormat>().is_err());
+    }
+}
@@ -0,0 +1,337 @@
+//! GFWList import
+//!
+//! GFWList is published in the AutoProxy format, usually base64 encoded.
+//! The rules are reduced to the hosts they match so that smart mode can use
+//! them instead of the geosite/geoip lists: exceptions (`@@`) go direct,
+//! listed hosts go through the proxy and everything else goes direct.
+//! Regular expression and wildcard rules cannot be expressed as Xray
+//! domain matchers and are skipped.
+
+use super::routing::{RoutingRule, OUTBOUND_DIRECT, OUTBOUND_PROXY};
+use crate::error::ConfigError;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+/// Default GFWList download URL
+pub const DEFAULT_GFWLIST_URL: &str =
+    "https://raw.githubusercontent.com/gfwlist/gfwlist/master/gfwlist.txt";
+
+/// Default interval between GFWList updates, in hours
+pub const DEFAULT_GFWLIST_UPDATE_INTERVAL_HOURS: u32 = 24;
+
+fn default_url() -> String {
+    DEFAULT_GFWLIST_URL.to_string()
+}
+
+fn default_update_interval_hours() -> u32 {
+    DEFAULT_GFWLIST_UPDATE_INTERVAL_HOURS
+}
+
+/// GFWList settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GfwListSettings {
+    /// Whether smart mode routes by GFWList instead of geosite/geoip
+    #[serde(default)]
+    pub enabled: bool,
+    /// Download URL
+    #[serde(default = "default_url")]
+    pub url: String,
+    /// Interval between updates, in hours
+    #[serde(default = "default_update_interval_hours")]
+    pub update_interval_hours: u32,
+}
+
+impl Default for GfwListSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: default_url(),
+            update_interval_hours: DEFAULT_GFWLIST_UPDATE_INTERVAL_HOURS,
+        }
+    }
+}
+
+impl GfwListSettings {
+    /// Validate the URL and update interval
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(ConfigError::Validation(format!(
+                "Invalid GFWList URL: {}",
+                self.url
+            )));
+        }
+        if self.update_interval_hours == 0 {
+            return Err(ConfigError::Validation(
+                "GFWList update interval must be at least one hour".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Hosts and IPs extracted from a GFWList
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GfwList {
+    /// Domains routed through the proxy, subdomains included
+    pub proxy_domains: Vec<String>,
+    /// IP addresses routed through the proxy
+    pub proxy_ips: Vec<String>,
+    /// Exception domains routed direct, subdomains included
+    pub direct_domains: Vec<String>,
+    /// Exception IP addresses routed direct
+    pub direct_ips: Vec<String>,
+    /// Number of rules that could not be converted
+    pub skipped: usize,
+}
+
+/// Host extracted from a single rule
+enum RuleHost {
+    Domain(String),
+    Ip(IpAddr),
+}
+
+impl GfwList {
+    /// Parse a GFWList, either base64 encoded or plain text
+    pub fn parse(content: &str) -> Result<Self, ConfigError> {
+        let text = decode(content)?;
+        if !text.trim_start().starts_with("[AutoProxy") {
+            return Err(ConfigError::Validation(
+                "Not an AutoProxy rule list".to_string(),
+            ));
+        }
+
+        let mut proxy_domains = BTreeSet::new();
+        let mut proxy_ips = BTreeSet::new();
+        let mut direct_domains = BTreeSet::new();
+        let mut direct_ips = BTreeSet::new();
+        let mut skipped = 0;
+
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+                continue;
+            }
+            let (rule, exception) = match line.strip_prefix("@@") {
+                Some(rule) => (rule, true),
+                None => (line, false),
+            };
+            let (domains, ips) = if exception {
+                (&mut direct_domains, &mut direct_ips)
+            } else {
+                (&mut proxy_domains, &mut proxy_ips)
+            };
+            match parse_rule(rule) {
+                Some(RuleHost::Domain(domain)) => {
+                    domains.insert(domain);
+                }
+                Some(RuleHost::Ip(ip)) => {
+                    ips.insert(ip.to_string());
+                }
+                None => {
+                    tracing::debug!("Skipping GFWList rule: {}", line);
+                    skipped += 1;
+                }
+            }
+        }
+
+        Ok(Self {
+            proxy_domains: proxy_domains.into_iter().collect(),
+            proxy_ips: proxy_ips.into_iter().collect(),
+            direct_domains: direct_domains.into_iter().collect(),
+            direct_ips: direct_ips.into_iter().collect(),
+            skipped,
+        })
+    }
+
+    /// Number of converted hosts and IPs
+    pub fn len(&self) -> usize {
+        self.proxy_domains.len()
+            + self.proxy_ips.len()
+            + self.direct_domains.len()
+            + self.direct_ips.len()
+    }
+
+    /// Whether no rule could be converted
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Convert to routing rules, exceptions first
+    ///
+    /// Traffic matching none of the rules is left to the caller.
+    pub fn to_routing_rules(&self) -> Vec<RoutingRule> {
+        let rule = |domains: &[String], ips: &[String], outbound_tag: &str| RoutingRule {
+            domains: domains.iter().map(|d| format!("domain:{}", d)).collect(),
+            ips: ips.to_vec(),
+            port: None,
+            processes: vec![],
+            protocols: vec![],
+            outbound_tag: outbound_tag.to_string(),
+        };
+
+        [
+            rule(&self.direct_domains, &[], OUTBOUND_DIRECT),
+            rule(&[], &self.direct_ips, OUTBOUND_DIRECT),
+            rule(&self.proxy_domains, &[], OUTBOUND_PROXY),
+            rule(&[], &self.proxy_ips, OUTBOUND_PROXY),
+        ]
+        .into_iter()
+        .filter(|rule| !rule.is_empty())
+        .collect()
+    }
+}
+
+/// Decode the list if it is base64 encoded
+fn decode(content: &str) -> Result<String, ConfigError> {
+    if content.trim_start().starts_with('[') {
+        return Ok(content.to_string());
+    }
+    let compact: String = content.split_whitespace().collect();
+    let bytes = BASE64
+        .decode(compact)
+        .map_err(|e| ConfigError::Validation(format!("Invalid GFWList encoding: {}", e)))?;
+    String::from_utf8(bytes)
+        .map_err(|e| ConfigError::Validation(format!("Invalid GFWList encoding: {}", e)))
+}
+
+/// Extract the host matched by a rule
+///
+/// Returns None for rules that cannot be expressed as a host matcher.
+fn parse_rule(rule: &str) -> Option<RuleHost> {
+    if rule.len() > 1 && rule.starts_with('/') && rule.ends_with('/') {
+        return None;
+    }
+
+    let rest = if let Some(rest) = rule.strip_prefix("||") {
+        rest
+    } else if let Some(rest) = rule.strip_prefix('|') {
+        rest
+    } else if let Some(rest) = rule.strip_prefix('.') {
+        rest
+    } else {
+        rule
+    };
+    let rest = rest
+        .strip_prefix("http://")
+        .or_else(|| rest.strip_prefix("https://"))
+        .unwrap_or(rest);
+
+    let host = rest
+        .split(['/', '^', '?', '#', '|'])
+        .next()
+        .unwrap_or_default();
+    let host = match host.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => host,
+    };
+    let host = host.strip_prefix("*.").unwrap_or(host);
+    let host = host.trim_end_matches('.').to_lowercase();
+
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(RuleHost::Ip(ip));
+    }
+    // Bare keywords without a dot would match far more than intended
+    if host.contains('*') || !host.contains('.') {
+        return None;
+    }
+    crate::utils::network::is_valid_hostname(&host).then_some(RuleHost::Domain(host))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "[AutoProxy 0.2.9]
+! Checksum: abc
+! Comment
+||google.com
+||google.com^
+|https://www.example.org/path
+.twitter.com
+blogspot.com/search
+@@||cn.example.org
+@@|http://direct.example.net
+|http://85.17.73.31/
+/^https?:\\/\\/[^\\/]+blogspot\\.(.*)/
+|http://*.wikipedia.org
+|http://foo*bar.com
+keyword
+";
+
+    #[test]
+    fn test_parse_rules() {
+        let list = GfwList::parse(SAMPLE).unwrap();
+
+        assert_eq!(
+            list.proxy_domains,
+            vec![
+                "blogspot.com",
+                "google.com",
+                "twitter.com",
+                "wikipedia.org",
+                "www.example.org"
+            ]
+        );
+        assert_eq!(list.proxy_ips, vec!["85.17.73.31"]);
+        assert_eq!(
+            list.direct_domains,
+            vec!["cn.example.org", "direct.example.net"]
+        );
+        assert!(list.direct_ips.is_empty());
+        // Regex, inner wildcard and bare keyword
+        assert_eq!(list.skipped, 3);
+        assert_eq!(list.len(), 8);
+    }
+
+    #[test]
+    fn test_parse_base64() {
+        let encoded = BASE64.encode(SAMPLE);
+        // Published lists are wrapped at 64 columns
+        let wrapped: Vec<String> = encoded
+            .as_bytes()
+            .chunks(64)
+            .map(|chunk| String::from_utf8(chunk.to_vec()).unwrap())
+            .collect();
+
+        let list = GfwList::parse(&wrapped.join("\n")).unwrap();
+        assert_eq!(list, GfwList::parse(SAMPLE).unwrap());
+    }
+
+    #[test]
+    fn test_parse_rejects_other_content() {
+        assert!(GfwList::parse("not base64!").is_err());
+        assert!(GfwList::parse(&BASE64.encode("hello")).is_err());
+    }
+
+    #[test]
+    fn test_to_routing_rules() {
+        let list = GfwList::parse(SAMPLE).unwrap();
+        let rules = list.to_routing_rules();
+
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].outbound_tag, OUTBOUND_DIRECT);
+        assert_eq!(rules[0].domains[0], "domain:cn.example.org");
+        assert_eq!(rules[1].outbound_tag, OUTBOUND_PROXY);
+        assert!(rules[1].domains.contains(&"domain:google.com".to_string()));
+        assert_eq!(rules[2].outbound_tag, OUTBOUND_PROXY);
+        assert_eq!(rules[2].ips, vec!["85.17.73.31"]);
+    }
+
+    #[test]
+    fn test_settings_validation() {
+        assert!(GfwListSettings::default().validate().is_ok());
+
+        let settings = GfwListSettings {
+            url: "ftp://example.com/gfwlist.txt".to_string(),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = GfwListSettings {
+            update_interval_hours: 0,
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
@@ -0,0 +1,125 @@
+//! Local inbound settings
+//!
+//! Controls how the local HTTP and SOCKS inbounds are exposed: loopback only
+//! (the default) or shared with other devices on the LAN, optionally behind
+//! username/password authentication.
+
+use crate::error::ConfigError;
+use serde::{Deserialize, Serialize};
+
+/// Listen address used when LAN access is disabled
+pub const LISTEN_LOCALHOST: &str = "127.0.0.1";
+/// Listen address used when LAN access is enabled
+pub const LISTEN_ALL: &str = "0.0.0.0";
+
+/// Username/password credentials for an inbound
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct InboundAuth {
+    /// Username
+    pub username: String,
+    /// Password
+    pub password: String,
+}
+
+impl InboundAuth {
+    /// Validate the credentials
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.username.is_empty() || self.password.is_empty() {
+            return Err(ConfigError::Validation(
+                "Inbound username and password must not be empty".to_string(),
+            ));
+        }
+        if self.username.contains(':') {
+            return Err(ConfigError::Validation(
+                "Inbound username must not contain ':'".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Local inbound settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct InboundSettings {
+    /// Listen on all interfaces so other LAN devices can use the proxy
+    #[serde(default)]
+    pub allow_lan: bool,
+    /// Credentials required by the HTTP inbound
+    #[serde(default)]
+    pub http_auth: Option<InboundAuth>,
+    /// Credentials required by the SOCKS inbound
+    #[serde(default)]
+    pub socks_auth: Option<InboundAuth>,
+    /// Enable UDP on the SOCKS inbound
+    #[serde(default)]
+    pub udp: bool,
+}
+
+impl InboundSettings {
+    /// Address the inbounds listen on
+    pub fn listen_address(&self) -> &'static str {
+        if self.allow_lan {
+            LISTEN_ALL
+        } else {
+            LISTEN_LOCALHOST
+        }
+    }
+
+    /// Validate the settings
+    ///
+    /// Sharing to the LAN requires both inbounds to be authenticated, so the
+    /// proxy is never open to every device on the network.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for auth in [&self.http_auth, &self.socks_auth].into_iter().flatten() {
+            auth.validate()?;
+        }
+
+        if self.allow_lan && (self.http_auth.is_none() || self.socks_auth.is_none()) {
+            return Err(ConfigError::Validation(
+                "Allowing LAN access requires a username and password for both HTTP and SOCKS"
+                    .to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> InboundAuth {
+        InboundAuth {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_default_is_localhost() {
+        let settings = InboundSettings::default();
+        assert_eq!(settings.listen_address(), LISTEN_LOCALHOST);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_lan_requires_auth() {
+        let mut settings = InboundSettings {
+            allow_lan: true,
+            http_auth: Some(auth()),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_err());
+
+        settings.socks_auth = Some(auth());
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.listen_address(), LISTEN_ALL);
+
+        settings.socks_auth = Some(InboundAuth {
+            username: "a:b".to_string(),
+            password: "secret".to_string(),
+        });
+        assert!(settings.validate().is_err());
+    }
+}
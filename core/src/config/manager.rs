@@ -2,9 +2,19 @@
 //!
 //! This module provides the configuration management functionality.
 
-use super::{Config, ProxyServerConfig};
-use crate::error::{ConfigError, ConfigResult};
+use super::backup::{self, BackupArchive, BackupReason};
+use super::bundle::{ConflictResolution, ImportSummary, StateBundle};
+use super::{
+    AppRule, Config, DirectPreferenceSettings, DnsSettings, EngineLogLevel, InboundSettings,
+    Profile, ProxyServerConfig, RoutingRuleSet,
+};
+use crate::connection::script_routing::ScriptRoutingSettings;
+use crate::error::{ConfigError, ConfigResult, PlatformResult};
+use crate::platform::credentials::{self, CredentialStore};
+use crate::subscription::SubscriptionStorage;
 use crate::utils::crypto::{decrypt_aes256, derive_key_from_password, encrypt_aes256};
+use crate::utils::preflight::preflight;
+use crate::utils::redact;
 use chrono::Utc;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
@@ -12,6 +22,10 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info, warn};
 
+/// Key of the proxy servers in the configuration file, next to the
+/// [`Config`] fields
+const PROXY_SERVERS_KEY: &str = "proxy_servers";
+
 /// Configuration manager
 #[derive(Clone)]
 pub struct ConfigManager {
@@ -21,10 +35,12 @@ pub struct ConfigManager {
     config: Arc<RwLock<Config>>,
     /// Proxy server configurations
     proxy_configs: Arc<RwLock<HashMap<String, ProxyServerConfig>>>,
-    /// Encryption password (optional)
-    encryption_password: Option<String>,
+    /// Encryption key, derived from a password or kept in a credential store
+    encryption_key: Option<[u8; 32]>,
     /// Backup directory
     backup_dir: Option<PathBuf>,
+    /// Subscription database included in backups
+    subscription_db: Option<PathBuf>,
 }
 
 impl ConfigManager {
@@ -34,8 +50,9 @@ impl ConfigManager {
             config_path: config_path.as_ref().to_path_buf(),
             config: Arc::new(RwLock::new(Config::default())),
             proxy_configs: Arc::new(RwLock::new(HashMap::new())),
-            encryption_password: None,
+            encryption_key: None,
             backup_dir: None,
+            subscription_db: None,
         }
     }
 
@@ -45,8 +62,9 @@ impl ConfigManager {
             config_path: config_path.as_ref().to_path_buf(),
             config: Arc::new(RwLock::new(Config::default())),
             proxy_configs: Arc::new(RwLock::new(HashMap::new())),
-            encryption_password: Some(password),
+            encryption_key: Some(derive_key_from_password(&password)),
             backup_dir: None,
+            subscription_db: None,
         }
     }
 
@@ -55,14 +73,75 @@ impl ConfigManager {
         self.backup_dir = Some(backup_dir.as_ref().to_path_buf());
     }
 
+    /// Set the subscription database to include in backups
+    pub fn set_subscription_db<P: AsRef<Path>>(&mut self, db_path: P) {
+        self.subscription_db = Some(db_path.as_ref().to_path_buf());
+    }
+
+    /// Subscription database included in backups
+    pub fn subscription_db(&self) -> Option<&Path> {
+        self.subscription_db.as_deref()
+    }
+
     /// Set encryption password
     pub fn set_encryption_password(&mut self, password: String) {
-        self.encryption_password = Some(password);
+        self.encryption_key = Some(derive_key_from_password(&password));
     }
 
     /// Clear encryption password
     pub fn clear_encryption_password(&mut self) {
-        self.encryption_password = None;
+        self.encryption_key = None;
+    }
+
+    /// Set the encryption key directly
+    pub fn set_encryption_key(&mut self, key: [u8; 32]) {
+        self.encryption_key = Some(key);
+    }
+
+    /// Encryption key in use, e.g. to encrypt the subscription database with
+    pub fn encryption_key(&self) -> Option<[u8; 32]> {
+        self.encryption_key
+    }
+
+    /// Take the encryption key from a credential store
+    ///
+    /// Returns whether the store held a key; without one the current key
+    /// is kept.
+    pub fn unlock_from_store(&mut self, store: &dyn CredentialStore) -> PlatformResult<bool> {
+        match credentials::load_key(store)? {
+            Some(key) => {
+                self.encryption_key = Some(key);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Keep the current encryption key in a credential store, so later
+    /// starts can unlock without the password
+    ///
+    /// Removes the stored key if encryption is off.
+    pub fn remember_key(&self, store: &dyn CredentialStore) -> PlatformResult<()> {
+        match self.encryption_key {
+            Some(ref key) => credentials::save_key(store, key),
+            None => store.delete(credentials::ENCRYPTION_KEY_ACCOUNT),
+        }
+    }
+
+    /// Re-encrypt the configuration file with a new password
+    ///
+    /// The loaded configuration is saved with the key derived from
+    /// `new_password`. Backups made before keep the old key.
+    pub async fn rotate_encryption_password(&mut self, new_password: &str) -> ConfigResult<()> {
+        let previous = self
+            .encryption_key
+            .replace(derive_key_from_password(new_password));
+        if let Err(e) = self.save().await {
+            self.encryption_key = previous;
+            return Err(e);
+        }
+        info!("Configuration re-encrypted with a new password");
+        Ok(())
     }
 
     /// Load configuration from file
@@ -76,22 +155,35 @@ impl ConfigManager {
 
         let content = std::fs::read_to_string(&self.config_path)?;
 
-        // Decrypt if password is set
-        let json_str = if let Some(ref password) = self.encryption_password {
-            let key = derive_key_from_password(password);
-            let decrypted = decrypt_aes256(&content, &key)
+        // Decrypt if a key is set
+        let json_str = if let Some(ref key) = self.encryption_key {
+            let decrypted = decrypt_aes256(&content, key)
                 .map_err(|e| ConfigError::Validation(format!("Decryption failed: {}", e)))?;
             String::from_utf8(decrypted)
                 .map_err(|e| ConfigError::Validation(format!("UTF-8 decode failed: {}", e)))?
         } else {
-            content
+            content.clone()
         };
 
-        let config: Config = serde_json::from_str(&json_str)?;
-        config.validate()?;
-
-        let mut current_config = self.config.write().await;
-        *current_config = config;
+        let (config, migrated_from) = Config::from_json(&json_str)?;
+        let proxy_configs = Self::parse_proxy_configs(&json_str)?;
+
+        *self.config.write().await = config;
+        *self.proxy_configs.write().await = proxy_configs;
+
+        if let Some(version) = migrated_from {
+            let original = serde_json::from_str(&json_str)?;
+            self.auto_backup_of(BackupReason::Migration, original)
+                .await?;
+            // Keep the file as it was, still encrypted if it was
+            let backup = super::migration::backup_path(&self.config_path, version);
+            std::fs::write(&backup, &content)?;
+            self.save().await?;
+            info!(
+                "Configuration upgraded from schema version {}, original kept at {:?}",
+                version, backup
+            );
+        }
 
         info!("Configuration loaded successfully");
         Ok(())
@@ -101,31 +193,47 @@ impl ConfigManager {
     pub async fn save(&self) -> ConfigResult<()> {
         info!("Saving configuration to {:?}", self.config_path);
 
-        // Ensure parent directory exists
-        if let Some(parent) = self.config_path.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
-
         let config = self.config.read().await;
         config.validate()?;
 
-        let json_str = serde_json::to_string_pretty(&*config)?;
+        let mut value = serde_json::to_value(&*config)?;
+        let mut proxy_configs: Vec<_> = self.proxy_configs.read().await.values().cloned().collect();
+        proxy_configs.sort_by(|a, b| a.id.cmp(&b.id));
+        value[PROXY_SERVERS_KEY] = serde_json::to_value(proxy_configs)?;
+        let json_str = serde_json::to_string_pretty(&value)?;
 
-        // Encrypt if password is set
-        let content = if let Some(ref password) = self.encryption_password {
-            let key = derive_key_from_password(password);
-            encrypt_aes256(json_str.as_bytes(), &key)
+        // Encrypt if a key is set
+        let content = if let Some(ref key) = self.encryption_key {
+            encrypt_aes256(json_str.as_bytes(), key)
                 .map_err(|e| ConfigError::Validation(format!("Encryption failed: {}", e)))?
         } else {
             json_str
         };
 
+        // Ensure parent directory exists and can hold the file
+        if let Some(parent) = self.config_path.parent() {
+            preflight(parent, content.len() as u64)?;
+        }
+
         std::fs::write(&self.config_path, content)?;
 
         info!("Configuration saved successfully");
         Ok(())
     }
 
+    /// Proxy servers saved in a configuration file
+    fn parse_proxy_configs(json: &str) -> ConfigResult<HashMap<String, ProxyServerConfig>> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let proxy_configs: Vec<ProxyServerConfig> = match value.get_mut(PROXY_SERVERS_KEY) {
+            Some(proxy_configs) => serde_json::from_value(proxy_configs.take())?,
+            None => Vec::new(),
+        };
+        Ok(proxy_configs
+            .into_iter()
+            .map(|proxy| (proxy.id.clone(), proxy))
+            .collect())
+    }
+
     /// Get current configuration
     pub async fn get_config(&self) -> Config {
         self.config.read().await.clone()
@@ -143,6 +251,9 @@ impl ConfigManager {
     }
 
     /// Add a proxy server configuration
+    ///
+    /// Proxy servers are saved to the configuration file by
+    /// [`save`](Self::save).
     pub async fn add_proxy_config(&self, config: ProxyServerConfig) -> ConfigResult<String> {
         let id = config.id.clone();
 
@@ -173,16 +284,20 @@ impl ConfigManager {
     }
 
     /// Update a proxy server configuration
+    ///
+    /// The creation time is kept and the update time set to now.
     pub async fn update_proxy_config(
         &self,
         id: &str,
-        config: ProxyServerConfig,
+        mut config: ProxyServerConfig,
     ) -> ConfigResult<()> {
         let mut configs = self.proxy_configs.write().await;
 
-        if !configs.contains_key(id) {
-            return Err(ConfigError::NotFound(id.to_string()));
-        }
+        let existing = configs
+            .get(id)
+            .ok_or_else(|| ConfigError::NotFound(id.to_string()))?;
+        config.created_at = existing.created_at;
+        config.updated_at = Utc::now();
 
         configs.insert(id.to_string(), config);
         debug!("Updated proxy config: {}", id);
@@ -228,98 +343,423 @@ impl ConfigManager {
         configs.len()
     }
 
-    /// Create a backup of the current configuration
-    pub async fn create_backup(&self) -> ConfigResult<PathBuf> {
-        let backup_dir = self
-            .backup_dir
-            .as_ref()
-            .ok_or_else(|| ConfigError::Validation("Backup directory not set".to_string()))?;
+    /// Add a routing rule set
+    ///
+    /// Rule sets are part of the main configuration and are persisted by
+    /// [`save`](Self::save).
+    pub async fn add_routing_rule_set(&self, rule_set: RoutingRuleSet) -> ConfigResult<String> {
+        rule_set.validate()?;
+        let id = rule_set.id.clone();
 
-        // Create backup directory if it doesn't exist
-        std::fs::create_dir_all(backup_dir)?;
+        let mut config = self.config.write().await;
+        if config.routing_rules.iter().any(|r| r.id == id) {
+            return Err(ConfigError::AlreadyExists(id));
+        }
 
-        // Generate backup filename with timestamp (including microseconds for uniqueness)
-        let timestamp = Utc::now().format("%Y%m%d_%H%M%S_%6f");
-        let backup_filename = format!("config_backup_{}.json", timestamp);
-        let backup_path = backup_dir.join(backup_filename);
+        config.routing_rules.push(rule_set);
+        debug!("Added routing rule set: {}", id);
 
-        // Save current config to backup file
+        Ok(id)
+    }
+
+    /// Get a routing rule set
+    pub async fn get_routing_rule_set(&self, id: &str) -> ConfigResult<RoutingRuleSet> {
         let config = self.config.read().await;
-        config.validate()?;
+        config
+            .routing_rules
+            .iter()
+            .find(|r| r.id == id)
+            .cloned()
+            .ok_or_else(|| ConfigError::NotFound(id.to_string()))
+    }
 
-        let json_str = serde_json::to_string_pretty(&*config)?;
-        std::fs::write(&backup_path, json_str)?;
+    /// Get all routing rule sets in evaluation order
+    pub async fn get_all_routing_rule_sets(&self) -> Vec<RoutingRuleSet> {
+        let config = self.config.read().await;
+        config.routing_rules.clone()
+    }
 
-        info!("Backup created at {:?}", backup_path);
-        Ok(backup_path)
+    /// Update a routing rule set, keeping its position
+    pub async fn update_routing_rule_set(
+        &self,
+        id: &str,
+        rule_set: RoutingRuleSet,
+    ) -> ConfigResult<()> {
+        rule_set.validate()?;
+
+        let mut config = self.config.write().await;
+        let existing = config
+            .routing_rules
+            .iter_mut()
+            .find(|r| r.id == id)
+            .ok_or_else(|| ConfigError::NotFound(id.to_string()))?;
+
+        *existing = rule_set;
+        debug!("Updated routing rule set: {}", id);
+
+        Ok(())
     }
 
-    /// Restore configuration from a backup file
-    pub async fn restore_from_backup<P: AsRef<Path>>(&self, backup_path: P) -> ConfigResult<()> {
-        info!("Restoring configuration from {:?}", backup_path.as_ref());
+    /// Delete a routing rule set
+    pub async fn delete_routing_rule_set(&self, id: &str) -> ConfigResult<()> {
+        let mut config = self.config.write().await;
 
-        let content = std::fs::read_to_string(backup_path)?;
-        let config: Config = serde_json::from_str(&content)?;
-        config.validate()?;
+        let len = config.routing_rules.len();
+        config.routing_rules.retain(|r| r.id != id);
+        if config.routing_rules.len() == len {
+            return Err(ConfigError::NotFound(id.to_string()));
+        }
+        for ids in config
+            .profiles
+            .iter_mut()
+            .filter_map(|p| p.routing_rule_sets.as_mut())
+        {
+            ids.retain(|r| r != id);
+        }
 
-        let mut current_config = self.config.write().await;
-        *current_config = config;
+        debug!("Deleted routing rule set: {}", id);
+        Ok(())
+    }
+
+    /// Add or replace an app rule
+    pub async fn set_app_rule(&self, rule: AppRule) -> ConfigResult<()> {
+        rule.validate()?;
+
+        let mut config = self.config.write().await;
+        match config.app_rules.iter_mut().find(|r| r.id == rule.id) {
+            Some(existing) => *existing = rule,
+            None => config.app_rules.push(rule),
+        }
 
-        info!("Configuration restored successfully");
         Ok(())
     }
 
-    /// List all available backups
-    pub fn list_backups(&self) -> ConfigResult<Vec<PathBuf>> {
+    /// Get all app rules
+    pub async fn get_app_rules(&self) -> Vec<AppRule> {
+        let config = self.config.read().await;
+        config.app_rules.clone()
+    }
+
+    /// Delete an app rule
+    pub async fn delete_app_rule(&self, id: &str) -> ConfigResult<()> {
+        let mut config = self.config.write().await;
+
+        let len = config.app_rules.len();
+        config.app_rules.retain(|r| r.id != id);
+        if config.app_rules.len() == len {
+            return Err(ConfigError::NotFound(id.to_string()));
+        }
+
+        debug!("Deleted app rule: {}", id);
+        Ok(())
+    }
+
+    /// Get DNS settings
+    pub async fn get_dns_settings(&self) -> DnsSettings {
+        let config = self.config.read().await;
+        config.dns.clone()
+    }
+
+    /// Replace DNS settings
+    pub async fn set_dns_settings(&self, dns: DnsSettings) -> ConfigResult<()> {
+        dns.validate()?;
+
+        let mut config = self.config.write().await;
+        config.dns = dns;
+        debug!("Updated DNS settings");
+
+        Ok(())
+    }
+
+    /// Get local inbound settings
+    pub async fn get_inbound_settings(&self) -> InboundSettings {
+        let config = self.config.read().await;
+        config.inbound.clone()
+    }
+
+    /// Replace local inbound settings
+    pub async fn set_inbound_settings(&self, inbound: InboundSettings) -> ConfigResult<()> {
+        inbound.validate()?;
+
+        let mut config = self.config.write().await;
+        config.inbound = inbound;
+        debug!("Updated inbound settings");
+
+        Ok(())
+    }
+
+    /// Get the Xray engine log level
+    pub async fn get_engine_log_level(&self) -> EngineLogLevel {
+        self.config.read().await.engine_log_level
+    }
+
+    /// Set the Xray engine log level
+    pub async fn set_engine_log_level(&self, level: EngineLogLevel) {
+        self.config.write().await.engine_log_level = level;
+        debug!("Updated engine log level: {}", level);
+    }
+
+    /// Get the direct preference settings
+    pub async fn get_direct_preference(&self) -> DirectPreferenceSettings {
+        self.config.read().await.direct_preference.clone()
+    }
+
+    /// Replace the direct preference settings
+    pub async fn set_direct_preference(
+        &self,
+        settings: DirectPreferenceSettings,
+    ) -> ConfigResult<()> {
+        settings.validate()?;
+        self.config.write().await.direct_preference = settings;
+        debug!("Updated direct preference settings");
+        Ok(())
+    }
+
+    /// Get the script routing settings
+    pub async fn get_script_routing(&self) -> ScriptRoutingSettings {
+        self.config.read().await.script_routing.clone()
+    }
+
+    /// Replace the script routing settings
+    ///
+    /// The script is not compiled here; it is checked when applied to the
+    /// connection manager.
+    pub async fn set_script_routing(&self, settings: ScriptRoutingSettings) {
+        self.config.write().await.script_routing = settings;
+        debug!("Updated script routing settings");
+    }
+
+    /// Add a profile
+    pub async fn add_profile(&self, profile: Profile) -> ConfigResult<String> {
+        let mut config = self.config.write().await;
+        Self::check_profile(&config, &profile)?;
+        let id = profile.id.clone();
+        if config.profiles.iter().any(|p| p.id == id) {
+            return Err(ConfigError::AlreadyExists(id));
+        }
+
+        config.profiles.push(profile);
+        debug!("Added profile: {}", id);
+
+        Ok(id)
+    }
+
+    /// Get a profile
+    pub async fn get_profile(&self, id: &str) -> ConfigResult<Profile> {
+        let config = self.config.read().await;
+        config
+            .profiles
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| ConfigError::NotFound(id.to_string()))
+    }
+
+    /// Get all profiles
+    pub async fn get_all_profiles(&self) -> Vec<Profile> {
+        let config = self.config.read().await;
+        config.profiles.clone()
+    }
+
+    /// Update a profile, keeping its position
+    pub async fn update_profile(&self, id: &str, profile: Profile) -> ConfigResult<()> {
+        let mut config = self.config.write().await;
+        Self::check_profile(&config, &profile)?;
+        let existing = config
+            .profiles
+            .iter_mut()
+            .find(|p| p.id == id)
+            .ok_or_else(|| ConfigError::NotFound(id.to_string()))?;
+
+        *existing = profile;
+        debug!("Updated profile: {}", id);
+
+        Ok(())
+    }
+
+    /// Delete a profile
+    pub async fn delete_profile(&self, id: &str) -> ConfigResult<()> {
+        let mut config = self.config.write().await;
+
+        let len = config.profiles.len();
+        config.profiles.retain(|p| p.id != id);
+        if config.profiles.len() == len {
+            return Err(ConfigError::NotFound(id.to_string()));
+        }
+        if config.active_profile.as_deref() == Some(id) {
+            config.active_profile = None;
+        }
+
+        debug!("Deleted profile: {}", id);
+        Ok(())
+    }
+
+    /// Switch to a profile
+    ///
+    /// Applies the profile's mode, system proxy setting and rule set
+    /// selection to the configuration and marks it active. Connecting to
+    /// the profile's server and using its ports (see
+    /// [`Profile::inbound_ports`]) is up to the caller. Returns the profile.
+    pub async fn switch_profile(&self, id: &str) -> ConfigResult<Profile> {
+        let mut config = self.config.write().await;
+        let profile = config
+            .profiles
+            .iter()
+            .find(|p| p.id == id)
+            .cloned()
+            .ok_or_else(|| ConfigError::NotFound(id.to_string()))?;
+
+        profile.apply_proxy(&mut config.proxy);
+        profile.apply_rule_sets(&mut config.routing_rules);
+        config.active_profile = Some(profile.id.clone());
+        info!("Switched to profile: {}", profile.name);
+
+        Ok(profile)
+    }
+
+    /// Get the profile last switched to
+    pub async fn get_active_profile(&self) -> Option<String> {
+        self.config.read().await.active_profile.clone()
+    }
+
+    /// Validate a profile against the configuration it is added to
+    fn check_profile(config: &Config, profile: &Profile) -> ConfigResult<()> {
+        profile.validate()?;
+        for id in profile.routing_rule_sets.iter().flatten() {
+            if !config.routing_rules.iter().any(|r| r.id == *id) {
+                return Err(ConfigError::NotFound(id.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a backup of the configuration, the proxy servers and the
+    /// subscription database
+    pub async fn create_backup(&self) -> ConfigResult<PathBuf> {
+        self.create_backup_with(BackupReason::Manual).await
+    }
+
+    /// Create a backup, recording why it was made
+    pub async fn create_backup_with(&self, reason: BackupReason) -> ConfigResult<PathBuf> {
+        let config = self.current_config_value().await?;
+        self.write_backup(reason, config).await
+    }
+
+    /// Back up before an operation that overwrites data
+    ///
+    /// Does nothing if no backup directory is set or automatic backups are
+    /// off. Old backups beyond the configured retention are deleted.
+    pub async fn auto_backup(&self, reason: BackupReason) -> ConfigResult<Option<PathBuf>> {
+        let config = self.current_config_value().await?;
+        self.auto_backup_of(reason, config).await
+    }
+
+    /// Make a scheduled backup and delete old backups beyond the retention
+    pub async fn scheduled_backup(&self) -> ConfigResult<PathBuf> {
+        let keep = self.config.read().await.backup.keep;
+        let path = self.create_backup_with(BackupReason::Scheduled).await?;
+        self.cleanup_old_backups(keep)?;
+        Ok(path)
+    }
+
+    /// Automatic backup holding `config` as the configuration
+    async fn auto_backup_of(
+        &self,
+        reason: BackupReason,
+        config: serde_json::Value,
+    ) -> ConfigResult<Option<PathBuf>> {
+        let settings = self.config.read().await.backup.clone();
+        if self.backup_dir.is_none() || !settings.auto_backup {
+            return Ok(None);
+        }
+        let path = self.write_backup(reason, config).await?;
+        self.cleanup_old_backups(settings.keep)?;
+        Ok(Some(path))
+    }
+
+    /// Validated current configuration as JSON
+    async fn current_config_value(&self) -> ConfigResult<serde_json::Value> {
+        let config = self.config.read().await;
+        config.validate()?;
+        Ok(serde_json::to_value(&*config)?)
+    }
+
+    /// Write a backup archive with `config` as the configuration
+    async fn write_backup(
+        &self,
+        reason: BackupReason,
+        config: serde_json::Value,
+    ) -> ConfigResult<PathBuf> {
         let backup_dir = self
             .backup_dir
             .as_ref()
             .ok_or_else(|| ConfigError::Validation("Backup directory not set".to_string()))?;
 
-        if !backup_dir.exists() {
-            return Ok(vec![]);
+        let mut archive = BackupArchive::new(reason);
+        archive.config = Some(config);
+        archive.proxy_configs = self.proxy_configs.read().await.values().cloned().collect();
+        archive.proxy_configs.sort_by(|a, b| a.id.cmp(&b.id));
+        if let Some(db_path) = self.subscription_db.as_ref().filter(|p| p.exists()) {
+            archive.add_subscription_db(db_path, backup_dir).await?;
         }
 
-        let mut backups = Vec::new();
-        for entry in std::fs::read_dir(backup_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                    if filename.starts_with("config_backup_") {
-                        backups.push(path);
-                    }
-                }
+        archive.write(backup_dir, self.encryption_key.as_ref())
+    }
+
+    /// Restore configuration, proxy servers and subscription database from
+    /// a backup file
+    ///
+    /// The current state is backed up first. The subscription database is
+    /// open while the app runs, so it is replaced on the next start (see
+    /// [`backup::pending_restore_path`]). Plain configuration backups of
+    /// older releases leave the proxy servers as they are.
+    pub async fn restore_from_backup<P: AsRef<Path>>(&self, backup_path: P) -> ConfigResult<()> {
+        info!("Restoring configuration from {:?}", backup_path.as_ref());
+
+        let archive = BackupArchive::read(backup_path.as_ref(), self.encryption_key.as_ref())?;
+        let config = match &archive.config {
+            Some(config) => Some(Config::from_json(&config.to_string())?.0),
+            None => None,
+        };
+        let subscription_db = archive.subscription_db()?;
+
+        self.auto_backup(BackupReason::Restore).await?;
+
+        if let Some(config) = config {
+            *self.config.write().await = config;
+            if archive.archive_version > 0 {
+                *self.proxy_configs.write().await = archive
+                    .proxy_configs
+                    .into_iter()
+                    .map(|proxy| (proxy.id.clone(), proxy))
+                    .collect();
             }
         }
+        if let (Some(bytes), Some(db_path)) = (subscription_db, &self.subscription_db) {
+            std::fs::write(backup::pending_restore_path(db_path), bytes)?;
+            info!("Subscription database will be restored on the next start");
+        }
 
-        // Sort by modification time (newest first)
-        backups.sort_by(|a, b| {
-            let a_time = std::fs::metadata(a).and_then(|m| m.modified()).ok();
-            let b_time = std::fs::metadata(b).and_then(|m| m.modified()).ok();
-            b_time.cmp(&a_time)
-        });
+        info!("Configuration restored successfully");
+        Ok(())
+    }
 
-        Ok(backups)
+    /// List all available backups, newest first
+    pub fn list_backups(&self) -> ConfigResult<Vec<PathBuf>> {
+        let backup_dir = self
+            .backup_dir
+            .as_ref()
+            .ok_or_else(|| ConfigError::Validation("Backup directory not set".to_string()))?;
+        backup::list_backups(backup_dir)
     }
 
     /// Delete old backups, keeping only the specified number of recent backups
     pub fn cleanup_old_backups(&self, keep_count: usize) -> ConfigResult<usize> {
-        let backups = self.list_backups()?;
-
-        if backups.len() <= keep_count {
-            return Ok(0);
-        }
-
-        let mut deleted_count = 0;
-        for backup in backups.iter().skip(keep_count) {
-            std::fs::remove_file(backup)?;
-            deleted_count += 1;
-            debug!("Deleted old backup: {:?}", backup);
-        }
-
-        info!("Cleaned up {} old backups", deleted_count);
-        Ok(deleted_count)
+        let backup_dir = self
+            .backup_dir
+            .as_ref()
+            .ok_or_else(|| ConfigError::Validation("Backup directory not set".to_string()))?;
+        backup::cleanup_backups(backup_dir, keep_count)
     }
 
     /// Export configuration to a file
@@ -334,11 +774,113 @@ impl ConfigManager {
         Ok(())
     }
 
+    /// Export configuration and proxy servers with all secrets masked
+    ///
+    /// Meant for support bundles: passwords, user IDs, keys and share links
+    /// are replaced, addresses and settings are kept. The result cannot be
+    /// imported back.
+    pub async fn export_config_redacted<P: AsRef<Path>>(&self, export_path: P) -> ConfigResult<()> {
+        let mut proxies: Vec<_> = self.proxy_configs.read().await.values().cloned().collect();
+        proxies.sort_by(|a, b| a.id.cmp(&b.id));
+        let proxies = proxies
+            .into_iter()
+            .map(|mut proxy| {
+                // The user ID of VMess/VLESS need not be a UUID
+                if let Some(id) = proxy.settings.get_mut("id") {
+                    *id = serde_json::Value::from(redact::MASK);
+                }
+                serde_json::to_value(proxy)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut export = serde_json::json!({
+            "config": &*self.config.read().await,
+            "proxy_configs": proxies,
+        });
+        redact::redact_json(&mut export);
+        std::fs::write(export_path.as_ref(), serde_json::to_string_pretty(&export)?)?;
+
+        info!(
+            "Redacted configuration exported to {:?}",
+            export_path.as_ref()
+        );
+        Ok(())
+    }
+
+    /// Export the whole app state to a bundle, e.g. to move to another device
+    ///
+    /// The bundle holds the configuration, the proxy servers and, if
+    /// `storage` is given, the subscriptions with their servers and
+    /// preferences. Without `include_secrets`, credentials and subscription
+    /// URLs are masked.
+    pub async fn export_bundle<P: AsRef<Path>>(
+        &self,
+        export_path: P,
+        include_secrets: bool,
+        storage: Option<&SubscriptionStorage>,
+    ) -> ConfigResult<()> {
+        let mut proxies: Vec<_> = self.proxy_configs.read().await.values().cloned().collect();
+        proxies.sort_by(|a, b| a.id.cmp(&b.id));
+        let mut bundle = {
+            let config = self.config.read().await;
+            config.validate()?;
+            StateBundle::new(&config, proxies)?
+        };
+        if let Some(storage) = storage {
+            bundle.add_subscriptions(storage).await?;
+        }
+        if !include_secrets {
+            bundle.strip_secrets();
+        }
+
+        std::fs::write(export_path.as_ref(), serde_json::to_string_pretty(&bundle)?)?;
+        info!("State bundle exported to {:?}", export_path.as_ref());
+        Ok(())
+    }
+
+    /// Import a bundle written by [`Self::export_bundle`]
+    ///
+    /// Items that already exist are handled as `resolution` says; the
+    /// current state is backed up first. Subscriptions are imported into
+    /// `storage`, if given, and have to be reloaded from it afterwards.
+    pub async fn import_bundle<P: AsRef<Path>>(
+        &self,
+        import_path: P,
+        resolution: ConflictResolution,
+        storage: Option<&SubscriptionStorage>,
+    ) -> ConfigResult<ImportSummary> {
+        let bundle = StateBundle::from_json(&std::fs::read_to_string(import_path.as_ref())?)?;
+        self.auto_backup(BackupReason::Import).await?;
+
+        let mut summary = ImportSummary::default();
+        {
+            let mut config = self.config.write().await;
+            bundle.merge_config(&mut config, resolution, &mut summary)?;
+        }
+        bundle.merge_proxy_configs(
+            &mut *self.proxy_configs.write().await,
+            resolution,
+            &mut summary,
+        );
+        if let Some(storage) = storage {
+            bundle
+                .import_subscriptions(storage, resolution, &mut summary)
+                .await?;
+        }
+
+        info!(
+            "State bundle imported: {} added, {} replaced, {} skipped, {} without credentials",
+            summary.added, summary.replaced, summary.skipped, summary.without_credentials
+        );
+        Ok(summary)
+    }
+
     /// Import configuration from a file
     pub async fn import_config<P: AsRef<Path>>(&self, import_path: P) -> ConfigResult<()> {
         let content = std::fs::read_to_string(import_path)?;
-        let config: Config = serde_json::from_str(&content)?;
-        config.validate()?;
+        let (config, _) = Config::from_json(&content)?;
+
+        self.auto_backup(BackupReason::Import).await?;
 
         let mut current_config = self.config.write().await;
         *current_config = config;
@@ -366,6 +908,7 @@ mod tests {
             tags: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            raw_name: None,
         }
     }
 
@@ -412,6 +955,45 @@ mod tests {
 
         let retrieved = manager.get_proxy_config("test-1").await.unwrap();
         assert_eq!(retrieved.name, "Updated Server");
+        assert!(retrieved.updated_at > retrieved.created_at);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_configs_persisted() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.json");
+
+        let manager = ConfigManager::new_with_encryption(&config_file, "secret".to_string());
+        let mut proxy_config = create_test_proxy_config("test-1", "Test Server");
+        proxy_config.created_at = Utc::now() - chrono::Duration::days(1);
+        proxy_config.updated_at = proxy_config.created_at;
+        manager.add_proxy_config(proxy_config).await.unwrap();
+        manager.save().await.unwrap();
+
+        let manager = ConfigManager::new_with_encryption(&config_file, "secret".to_string());
+        manager.load().await.unwrap();
+        let loaded = manager.get_proxy_config("test-1").await.unwrap();
+        assert_eq!(loaded.name, "Test Server");
+
+        manager
+            .update_proxy_config("test-1", create_test_proxy_config("test-1", "Renamed"))
+            .await
+            .unwrap();
+        let updated = manager.get_proxy_config("test-1").await.unwrap();
+        assert_eq!(updated.created_at, loaded.created_at);
+        assert!(updated.updated_at > loaded.updated_at);
+
+        // Files written before proxy servers were saved still load
+        std::fs::write(
+            &config_file,
+            serde_json::to_string(&Config::default()).unwrap(),
+        )
+        .unwrap();
+        let manager = ConfigManager::new(&config_file);
+        manager.load().await.unwrap();
+        assert_eq!(manager.get_proxy_configs_count().await, 0);
     }
 
     #[tokio::test]
@@ -474,6 +1056,36 @@ mod tests {
         assert_eq!(config.proxy.http_port, 9090);
     }
 
+    #[tokio::test]
+    async fn test_key_rotation_and_credential_store() {
+        use crate::platform::credentials::MemoryStore;
+
+        let temp_file = NamedTempFile::new().unwrap();
+        let store = MemoryStore::default();
+        let mut manager = ConfigManager::new_with_encryption(temp_file.path(), "old".to_string());
+        manager
+            .update_config(|config| config.proxy.http_port = 9191)
+            .await
+            .unwrap();
+        manager.save().await.unwrap();
+
+        manager.rotate_encryption_password("new").await.unwrap();
+        manager.remember_key(&store).unwrap();
+
+        let old = ConfigManager::new_with_encryption(temp_file.path(), "old".to_string());
+        assert!(old.load().await.is_err());
+
+        // A later start unlocks from the store without the password
+        let mut manager2 = ConfigManager::new(temp_file.path());
+        assert!(manager2.unlock_from_store(&store).unwrap());
+        manager2.load().await.unwrap();
+        assert_eq!(manager2.get_config().await.proxy.http_port, 9191);
+
+        manager2.clear_encryption_password();
+        manager2.remember_key(&store).unwrap();
+        assert!(!manager2.unlock_from_store(&store).unwrap());
+    }
+
     #[tokio::test]
     async fn test_backup_and_restore() {
         use tempfile::TempDir;
@@ -512,6 +1124,118 @@ mod tests {
         assert_eq!(config.proxy.http_port, 7070);
     }
 
+    #[tokio::test]
+    async fn test_auto_backups() {
+        use crate::subscription::SubscriptionStorage;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let backup_dir = temp_dir.path().join("backups");
+        let db_path = temp_dir.path().join("subscriptions.db");
+        drop(SubscriptionStorage::new(&db_path).await.unwrap());
+
+        let mut manager = ConfigManager::new(temp_dir.path().join("config.json"));
+        manager.set_backup_dir(&backup_dir);
+        manager.set_subscription_db(&db_path);
+        manager
+            .add_proxy_config(create_test_proxy_config("server-1", "Server 1"))
+            .await
+            .unwrap();
+        let backup_path = manager.create_backup().await.unwrap();
+        manager.delete_proxy_config("server-1").await.unwrap();
+
+        // Importing backs up first
+        let export_file = temp_dir.path().join("export.json");
+        manager.export_config(&export_file).await.unwrap();
+        manager.import_config(&export_file).await.unwrap();
+        assert_eq!(manager.list_backups().unwrap().len(), 2);
+
+        // Restoring brings back the proxy servers and stages the database
+        manager.restore_from_backup(&backup_path).await.unwrap();
+        assert!(manager.get_proxy_config("server-1").await.is_ok());
+        assert!(backup::pending_restore_path(&db_path).exists());
+        assert_eq!(manager.list_backups().unwrap().len(), 3);
+        drop(SubscriptionStorage::new(&db_path).await.unwrap());
+        assert!(!backup::pending_restore_path(&db_path).exists());
+
+        // Retention applies to automatic backups
+        manager
+            .update_config(|config| config.backup.keep = 2)
+            .await
+            .unwrap();
+        manager.scheduled_backup().await.unwrap();
+        assert_eq!(manager.list_backups().unwrap().len(), 2);
+
+        manager
+            .update_config(|config| config.backup.auto_backup = false)
+            .await
+            .unwrap();
+        assert!(manager
+            .auto_backup(BackupReason::Import)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bundle_round_trip() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let bundle_file = temp_dir.path().join("bundle.json");
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+
+        let manager = ConfigManager::new(temp_dir.path().join("config.json"));
+        manager
+            .update_config(|config| config.proxy.http_port = 7070)
+            .await
+            .unwrap();
+        let mut proxy = create_test_proxy_config("server-1", "Server 1");
+        proxy
+            .settings
+            .insert("password".to_string(), serde_json::json!("hunter2"));
+        manager.add_proxy_config(proxy).await.unwrap();
+        manager
+            .export_bundle(&bundle_file, true, Some(&storage))
+            .await
+            .unwrap();
+
+        // Another device
+        let other = ConfigManager::new(temp_dir.path().join("other.json"));
+        let summary = other
+            .import_bundle(&bundle_file, ConflictResolution::Overwrite, Some(&storage))
+            .await
+            .unwrap();
+        assert_eq!(summary.added, 1);
+        assert_eq!(other.get_config().await.proxy.http_port, 7070);
+        let proxy = other.get_proxy_config("server-1").await.unwrap();
+        assert_eq!(proxy.settings["password"], "hunter2");
+
+        // Without secrets
+        manager
+            .export_bundle(&bundle_file, false, None)
+            .await
+            .unwrap();
+        let content = std::fs::read_to_string(&bundle_file).unwrap();
+        assert!(!content.contains("hunter2"));
+        let summary = other
+            .import_bundle(&bundle_file, ConflictResolution::Overwrite, None)
+            .await
+            .unwrap();
+        assert_eq!(summary.without_credentials, 1);
+        let proxy = other.get_proxy_config("server-1").await.unwrap();
+        assert_eq!(proxy.settings["password"], "hunter2");
+
+        // New servers are not added with masked credentials either
+        let fresh = ConfigManager::new(temp_dir.path().join("fresh.json"));
+        let summary = fresh
+            .import_bundle(&bundle_file, ConflictResolution::KeepBoth, None)
+            .await
+            .unwrap();
+        assert_eq!((summary.added, summary.without_credentials), (0, 1));
+        assert!(fresh.get_proxy_config("server-1").await.is_err());
+    }
+
     #[tokio::test]
     async fn test_list_backups() {
         use tempfile::TempDir;
@@ -588,4 +1312,152 @@ mod tests {
         let config = manager2.get_config().await;
         assert_eq!(config.proxy.http_port, 6060);
     }
+
+    #[tokio::test]
+    async fn test_export_redacted() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let export_file = temp_dir.path().join("support.json");
+        let manager = ConfigManager::new(temp_dir.path().join("config.json"));
+
+        let mut vless = create_test_proxy_config("vless", "VLESS");
+        vless
+            .settings
+            .insert("id".to_string(), serde_json::json!("my-custom-user-id"));
+        let mut trojan = create_test_proxy_config("trojan", "Trojan");
+        trojan.protocol = super::super::ProxyProtocol::Trojan;
+        trojan
+            .settings
+            .insert("password".to_string(), serde_json::json!("hunter2"));
+        manager.add_proxy_config(vless).await.unwrap();
+        manager.add_proxy_config(trojan).await.unwrap();
+
+        manager.export_config_redacted(&export_file).await.unwrap();
+        let export = std::fs::read_to_string(&export_file).unwrap();
+        assert!(!export.contains("my-custom-user-id"));
+        assert!(!export.contains("hunter2"));
+        assert!(export.contains("example.com"));
+        assert!(export.contains("\"http_port\": 8080"));
+    }
+
+    #[tokio::test]
+    async fn test_routing_rule_set_crud_and_persistence() {
+        use super::super::RoutingRule;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.json");
+        let manager = ConfigManager::new(&config_file);
+
+        let rule_set = RoutingRuleSet {
+            id: "work".to_string(),
+            name: "Work".to_string(),
+            enabled: true,
+            rules: vec![RoutingRule {
+                domains: vec!["domain:corp.example.com".to_string()],
+                ips: vec![],
+                port: None,
+                processes: vec![],
+                protocols: vec![],
+                outbound_tag: "direct".to_string(),
+            }],
+        };
+
+        manager
+            .add_routing_rule_set(rule_set.clone())
+            .await
+            .unwrap();
+        assert!(manager
+            .add_routing_rule_set(rule_set.clone())
+            .await
+            .is_err());
+
+        let mut updated = rule_set.clone();
+        updated.enabled = false;
+        manager
+            .update_routing_rule_set("work", updated)
+            .await
+            .unwrap();
+
+        // Persist and reload
+        manager.save().await.unwrap();
+        let manager2 = ConfigManager::new(&config_file);
+        manager2.load().await.unwrap();
+        let loaded = manager2.get_routing_rule_set("work").await.unwrap();
+        assert!(!loaded.enabled);
+        assert_eq!(loaded.rules, rule_set.rules);
+
+        manager2.delete_routing_rule_set("work").await.unwrap();
+        assert!(manager2.get_all_routing_rule_sets().await.is_empty());
+        assert!(manager2.delete_routing_rule_set("work").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_profile_crud_and_switch() {
+        use super::super::SystemProxyBehavior;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let config_file = temp_dir.path().join("config.json");
+        let manager = ConfigManager::new(&config_file);
+
+        for (id, enabled) in [("office", false), ("streaming", true)] {
+            manager
+                .add_routing_rule_set(RoutingRuleSet {
+                    id: id.to_string(),
+                    name: id.to_string(),
+                    enabled,
+                    rules: vec![],
+                })
+                .await
+                .unwrap();
+        }
+
+        let profile = Profile {
+            id: "work".to_string(),
+            name: "Work".to_string(),
+            server_id: Some("server-1".to_string()),
+            mode: "global".to_string(),
+            routing_rule_sets: Some(vec!["office".to_string()]),
+            http_port: Some(18080),
+            socks_port: Some(11080),
+            xray_version: None,
+            core: Default::default(),
+            rate_limit: Default::default(),
+            system_proxy: SystemProxyBehavior::Enable,
+        };
+        manager.add_profile(profile.clone()).await.unwrap();
+        assert!(manager.add_profile(profile.clone()).await.is_err());
+
+        // Unknown rule sets are rejected
+        let mut unknown = profile.clone();
+        unknown.routing_rule_sets = Some(vec!["missing".to_string()]);
+        assert!(manager.update_profile("work", unknown).await.is_err());
+
+        let switched = manager.switch_profile("work").await.unwrap();
+        assert_eq!(switched, profile);
+        assert_eq!(manager.get_active_profile().await.as_deref(), Some("work"));
+        let config = manager.get_config().await;
+        // The profile's ports apply while it is active, not to the settings
+        assert_eq!(config.proxy.http_port, 8080);
+        assert_eq!(switched.inbound_ports(&config.proxy), (18080, 11080));
+        assert!(config.proxy.system_proxy);
+        assert!(config.routing_rules[0].enabled);
+        assert!(!config.routing_rules[1].enabled);
+
+        // Persist and reload
+        manager.save().await.unwrap();
+        let manager2 = ConfigManager::new(&config_file);
+        manager2.load().await.unwrap();
+        assert_eq!(manager2.get_profile("work").await.unwrap(), profile);
+
+        manager2.delete_routing_rule_set("office").await.unwrap();
+        let loaded = manager2.get_profile("work").await.unwrap();
+        assert_eq!(loaded.routing_rule_sets, Some(vec![]));
+
+        manager2.delete_profile("work").await.unwrap();
+        assert!(manager2.get_active_profile().await.is_none());
+        assert!(manager2.switch_profile("work").await.is_err());
+    }
 }
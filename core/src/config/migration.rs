@@ -0,0 +1,160 @@
+//! Configuration schema migration
+//!
+//! Every saved configuration records the schema version it was written with.
+//! Files from older releases are upgraded step by step (v1 → v2 → …) when
+//! they are loaded, and files from newer releases are rejected instead of
+//! being misread or overwritten.
+//!
+//! A step upgrades the raw JSON by exactly one version, so adding a version
+//! means appending one function to [`MIGRATIONS`].
+
+use super::Config;
+use crate::error::ConfigError;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Schema version written by this release
+pub const CONFIG_SCHEMA_VERSION: u32 = 2;
+
+/// Version of configurations saved before the version was recorded
+pub const UNVERSIONED_SCHEMA_VERSION: u32 = 1;
+
+/// Key holding the schema version
+const VERSION_KEY: &str = "schema_version";
+
+/// Upgrade of the raw configuration by one version
+type Migration = fn(&mut Map<String, Value>);
+
+/// Steps in order; the step at index `i` upgrades version `i + 1`
+const MIGRATIONS: &[Migration] = &[v1_fill_defaults];
+
+/// Schema version of a raw configuration
+pub fn schema_version(config: &Value) -> Result<u32, ConfigError> {
+    match config.get(VERSION_KEY) {
+        None | Some(Value::Null) => Ok(UNVERSIONED_SCHEMA_VERSION),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= UNVERSIONED_SCHEMA_VERSION)
+            .ok_or_else(|| ConfigError::Validation(format!("Invalid schema version: {}", version))),
+    }
+}
+
+/// Upgrade a raw configuration to [`CONFIG_SCHEMA_VERSION`]
+///
+/// Returns the version the configuration had if it was upgraded, or `None`
+/// if it was already current.
+pub fn migrate(config: &mut Value) -> Result<Option<u32>, ConfigError> {
+    let found = schema_version(config)?;
+    if found > CONFIG_SCHEMA_VERSION {
+        return Err(ConfigError::UnsupportedVersion {
+            found,
+            supported: CONFIG_SCHEMA_VERSION,
+        });
+    }
+    if found == CONFIG_SCHEMA_VERSION {
+        return Ok(None);
+    }
+
+    let object = config
+        .as_object_mut()
+        .ok_or_else(|| ConfigError::Validation("Configuration is not an object".to_string()))?;
+    for (index, step) in MIGRATIONS
+        .iter()
+        .enumerate()
+        .skip((found - UNVERSIONED_SCHEMA_VERSION) as usize)
+    {
+        step(object);
+        object.insert(VERSION_KEY.to_string(), Value::from(index as u32 + 2));
+    }
+    Ok(Some(found))
+}
+
+/// Where the original of a configuration upgraded from `version` is kept
+pub fn backup_path(path: &Path, version: u32) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".v{}.bak", version));
+    PathBuf::from(backup)
+}
+
+/// v1 → v2: fill in sections and settings missing from early releases
+///
+/// The first releases required every setting to be present, so files from
+/// them lack anything added later, such as the `inbound` or `dns`
+/// sections. Missing values are taken from the defaults.
+fn v1_fill_defaults(config: &mut Map<String, Value>) {
+    if let Ok(Value::Object(defaults)) = serde_json::to_value(Config::default()) {
+        fill_missing(config, defaults);
+    }
+}
+
+/// Insert keys of `defaults` that `target` lacks, recursing into objects
+fn fill_missing(target: &mut Map<String, Value>, defaults: Map<String, Value>) {
+    for (key, default) in defaults {
+        match (target.get_mut(&key), default) {
+            (None, default) => {
+                target.insert(key, default);
+            }
+            (Some(Value::Object(existing)), Value::Object(default)) => {
+                fill_missing(existing, default);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate_unversioned() {
+        let mut config = json!({
+            "app": {"mode": "Advanced", "language": "de", "theme": "Dark", "auto_start": true},
+            "proxy": {"mode": "Proxy", "system_proxy": true, "http_port": 8888, "socks_port": 1088},
+            "subscription": {"auto_update_interval": 12, "user_agent": "old", "timeout": 15}
+        });
+        assert_eq!(migrate(&mut config).unwrap(), Some(1));
+        assert_eq!(schema_version(&config).unwrap(), CONFIG_SCHEMA_VERSION);
+
+        // Existing settings are kept, missing ones filled in
+        assert_eq!(config["app"]["language"], "de");
+        assert_eq!(config["proxy"]["http_port"], 8888);
+        assert_eq!(config["inbound"]["allow_lan"], false);
+        assert!(config["proxy"].get("port_conflict").is_some());
+        assert!(config.get("dns").is_some());
+
+        let config: Config = serde_json::from_value(config).unwrap();
+        assert_eq!(config.schema_version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.subscription.user_agent, "old");
+
+        // Current configurations are left alone
+        let mut current = serde_json::to_value(Config::default()).unwrap();
+        assert_eq!(migrate(&mut current).unwrap(), None);
+    }
+
+    #[test]
+    fn test_reject_newer_version() {
+        let mut config = json!({"schema_version": CONFIG_SCHEMA_VERSION + 1});
+        match migrate(&mut config) {
+            Err(ConfigError::UnsupportedVersion { found, supported }) => {
+                assert_eq!(found, CONFIG_SCHEMA_VERSION + 1);
+                assert_eq!(supported, CONFIG_SCHEMA_VERSION);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        assert!(migrate(&mut json!({"schema_version": "two"})).is_err());
+        assert!(migrate(&mut json!({"schema_version": 0})).is_err());
+        assert!(migrate(&mut json!([])).is_err());
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(
+            backup_path(Path::new("/data/config.json"), 1),
+            PathBuf::from("/data/config.json.v1.bak")
+        );
+    }
+}
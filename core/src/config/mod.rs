@@ -3,25 +3,88 @@
 //! This module handles all configuration-related functionality including
 //! loading, saving, validation, and conversion of configuration data.
 
+pub mod backup;
+pub mod bundle;
+pub mod dns;
+pub mod exporter;
+pub mod gfwlist;
+pub mod inbound;
 pub mod manager;
+pub mod migration;
 pub mod parser;
+pub mod profile;
+pub mod routing;
 pub mod validator;
 
+pub use backup::{BackupReason, BackupSettings};
+pub use bundle::{ConflictResolution, ImportSummary, StateBundle};
+pub use dns::DnsSettings;
+pub use exporter::{ConfigExporter, Export, ExportFormat, SkippedServer};
+pub use gfwlist::{GfwList, GfwListSettings};
+pub use inbound::{InboundAuth, InboundSettings};
+pub use migration::CONFIG_SCHEMA_VERSION;
+pub use profile::{Profile, SystemProxyBehavior};
+pub use routing::{AppRule, DirectPreferenceSettings, RoutingRule, RoutingRuleSet};
+
 use crate::error::ConfigError;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
+use tracing::info;
 
 /// Main configuration structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Schema version the configuration was written with
+    #[serde(default = "unversioned")]
+    pub schema_version: u32,
     /// Application settings
     pub app: AppConfig,
     /// Proxy settings
     pub proxy: ProxyConfig,
     /// Subscription settings
     pub subscription: SubscriptionConfig,
+    /// User-defined routing rule sets, applied in order
+    #[serde(default)]
+    pub routing_rules: Vec<RoutingRuleSet>,
+    /// Per-application split tunneling rules
+    #[serde(default)]
+    pub app_rules: Vec<AppRule>,
+    /// DNS settings
+    #[serde(default)]
+    pub dns: DnsSettings,
+    /// Local inbound (LAN sharing and authentication) settings
+    #[serde(default)]
+    pub inbound: InboundSettings,
+    /// Log level of the Xray engine, separate from the app's own log level
+    #[serde(default)]
+    pub engine_log_level: EngineLogLevel,
+    /// Direct preference heuristic for proxied modes
+    #[serde(default)]
+    pub direct_preference: DirectPreferenceSettings,
+    /// Script routing settings
+    #[serde(default)]
+    pub script_routing: crate::connection::script_routing::ScriptRoutingSettings,
+    /// GFWList routing for smart mode
+    #[serde(default)]
+    pub gfwlist: GfwListSettings,
+    /// Saved profiles
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    /// Profile last switched to
+    #[serde(default)]
+    pub active_profile: Option<String>,
+    /// Automatic backup settings
+    #[serde(default)]
+    pub backup: BackupSettings,
+    /// Daily and monthly traffic budgets
+    #[serde(default)]
+    pub traffic_budgets: Vec<crate::connection::traffic_budget::TrafficBudget>,
+}
+
+fn unversioned() -> u32 {
+    migration::UNVERSIONED_SCHEMA_VERSION
 }
 
 /// Application configuration
@@ -68,6 +131,43 @@ pub struct ProxyConfig {
     pub http_port: u16,
     /// Local SOCKS port
     pub socks_port: u16,
+    /// What to do when a local port is already in use at start
+    #[serde(default)]
+    pub port_conflict: PortConflictPolicy,
+}
+
+/// Policy for local inbound ports that are already in use
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum PortConflictPolicy {
+    /// Refuse to start and report the conflicting port
+    #[default]
+    Fail,
+    /// Pick a free port instead and report the change
+    Reassign,
+}
+
+impl ProxyConfig {
+    /// Update the configured port of an inbound that was reassigned
+    ///
+    /// Only a port that is still configured is moved, so a port set by a
+    /// profile is never written here. Returns whether the port changed.
+    pub fn apply_port_reassignment(
+        &mut self,
+        protocol: &str,
+        old_port: u16,
+        new_port: u16,
+    ) -> bool {
+        let port = match protocol {
+            "http" => &mut self.http_port,
+            "socks" => &mut self.socks_port,
+            _ => return false,
+        };
+        if *port != old_port {
+            return false;
+        }
+        *port = new_port;
+        true
+    }
 }
 
 /// Proxy mode
@@ -81,6 +181,74 @@ pub enum ProxyMode {
     Auto,
 }
 
+/// Log level of the Xray engine
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineLogLevel {
+    /// No engine logs
+    None,
+    /// Errors only
+    Error,
+    /// Errors and warnings
+    #[default]
+    Warning,
+    /// Informational messages
+    Info,
+    /// Everything, including per-connection details
+    Debug,
+}
+
+impl EngineLogLevel {
+    /// Level name as used in the Xray `log.loglevel` setting
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EngineLogLevel::None => "none",
+            EngineLogLevel::Error => "error",
+            EngineLogLevel::Warning => "warning",
+            EngineLogLevel::Info => "info",
+            EngineLogLevel::Debug => "debug",
+        }
+    }
+
+    /// Whether an engine log entry with the given level passes this level
+    ///
+    /// Entry levels are matched case-insensitively (Xray writes `[Warning]`);
+    /// unrecognized levels are treated as info.
+    pub fn allows(&self, entry_level: &str) -> bool {
+        let entry = match entry_level.to_ascii_lowercase().as_str() {
+            "error" => EngineLogLevel::Error,
+            "warning" | "warn" => EngineLogLevel::Warning,
+            "debug" => EngineLogLevel::Debug,
+            _ => EngineLogLevel::Info,
+        };
+        *self != EngineLogLevel::None && entry <= *self
+    }
+}
+
+impl std::fmt::Display for EngineLogLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for EngineLogLevel {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(EngineLogLevel::None),
+            "error" => Ok(EngineLogLevel::Error),
+            "warning" => Ok(EngineLogLevel::Warning),
+            "info" => Ok(EngineLogLevel::Info),
+            "debug" => Ok(EngineLogLevel::Debug),
+            _ => Err(ConfigError::Validation(format!(
+                "Invalid engine log level: {}",
+                s
+            ))),
+        }
+    }
+}
+
 /// Subscription configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionConfig {
@@ -99,6 +267,9 @@ pub struct ProxyServerConfig {
     pub id: String,
     /// Display name
     pub name: String,
+    /// Name as provided by the subscription, if normalization changed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_name: Option<String>,
     /// Server address
     pub server: String,
     /// Server port
@@ -117,6 +288,25 @@ pub struct ProxyServerConfig {
     pub updated_at: DateTime<Utc>,
 }
 
+impl ProxyServerConfig {
+    /// Normalize the name for sorting, grouping and storage, keeping the
+    /// original in `raw_name` if it changed
+    ///
+    /// Names with nothing printable fall back to `server:port`.
+    pub fn normalize_name(&mut self) {
+        let mut name = crate::utils::names::normalize_name(&self.name);
+        if name.is_empty() {
+            name = format!("{}:{}", self.server, self.port);
+        }
+        if name != self.name {
+            if self.raw_name.is_none() {
+                self.raw_name = Some(std::mem::take(&mut self.name));
+            }
+            self.name = name;
+        }
+    }
+}
+
 /// Proxy protocol types
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -133,6 +323,10 @@ pub enum ProxyProtocol {
     Http,
     /// SOCKS proxy
     Socks,
+    /// Hysteria 2, run by the sing-box core
+    Hysteria2,
+    /// TUIC v5, run by the sing-box core
+    Tuic,
 }
 
 /// Stream settings for transport
@@ -154,6 +348,12 @@ pub struct StreamSettings {
     pub quic_settings: Option<serde_json::Value>,
     /// gRPC settings
     pub grpc_settings: Option<GrpcSettings>,
+    /// Multiplexing settings (emitted on the outbound itself)
+    #[serde(default)]
+    pub mux: Option<MuxSettings>,
+    /// TLS fragment settings
+    #[serde(default)]
+    pub fragment: Option<FragmentSettings>,
 }
 
 /// TLS settings
@@ -187,9 +387,97 @@ pub struct GrpcSettings {
     pub multi_mode: bool,
 }
 
+/// Mux (connection multiplexing) settings
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MuxSettings {
+    /// Enable multiplexing
+    pub enabled: bool,
+    /// Maximum concurrent TCP sub-connections per connection
+    pub concurrency: i32,
+    /// Maximum concurrent UDP (XUDP) sub-connections per connection
+    pub xudp_concurrency: i32,
+}
+
+impl Default for MuxSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            concurrency: 8,
+            xudp_concurrency: 16,
+        }
+    }
+}
+
+impl MuxSettings {
+    /// Parse share-link query parameters (`mux`, `muxConcurrency`, `xudpConcurrency`)
+    pub fn from_query(query: &HashMap<String, String>) -> Option<Self> {
+        let enabled = matches!(query.get("mux").map(|s| s.as_str()), Some("1" | "true"));
+        if !enabled {
+            return None;
+        }
+
+        let defaults = Self::default();
+        Some(Self {
+            enabled,
+            concurrency: query
+                .get("muxConcurrency")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.concurrency),
+            xudp_concurrency: query
+                .get("xudpConcurrency")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.xudp_concurrency),
+        })
+    }
+}
+
+/// TLS fragment settings
+///
+/// Splits the TLS ClientHello (or the first packets) into small pieces sent
+/// with delays, which gets past some SNI-based filters.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FragmentSettings {
+    /// Packets to fragment ("tlshello" or a range such as "1-3")
+    pub packets: String,
+    /// Fragment length range in bytes (e.g. "100-200")
+    pub length: String,
+    /// Delay between fragments in milliseconds (e.g. "10-20")
+    pub interval: String,
+}
+
+impl Default for FragmentSettings {
+    fn default() -> Self {
+        Self {
+            packets: "tlshello".to_string(),
+            length: "100-200".to_string(),
+            interval: "10-20".to_string(),
+        }
+    }
+}
+
+impl FragmentSettings {
+    /// Parse the share-link form `packets,length,interval`
+    pub fn parse(value: &str) -> Option<Self> {
+        let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+        match parts.as_slice() {
+            [packets, length, interval]
+                if !packets.is_empty() && !length.is_empty() && !interval.is_empty() =>
+            {
+                Some(Self {
+                    packets: packets.to_string(),
+                    length: length.to_string(),
+                    interval: interval.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
+            schema_version: CONFIG_SCHEMA_VERSION,
             app: AppConfig {
                 mode: AppMode::Simple,
                 language: "en".to_string(),
@@ -201,25 +489,61 @@ impl Default for Config {
                 system_proxy: false,
                 http_port: 8080,
                 socks_port: 1080,
+                port_conflict: PortConflictPolicy::default(),
             },
             subscription: SubscriptionConfig {
                 auto_update_interval: 24,
                 user_agent: crate::version::user_agent(),
                 timeout: 30,
             },
+            routing_rules: Vec::new(),
+            app_rules: Vec::new(),
+            dns: DnsSettings::default(),
+            inbound: InboundSettings::default(),
+            engine_log_level: EngineLogLevel::default(),
+            direct_preference: DirectPreferenceSettings::default(),
+            script_routing: Default::default(),
+            gfwlist: GfwListSettings::default(),
+            profiles: Vec::new(),
+            active_profile: None,
+            backup: BackupSettings::default(),
+            traffic_budgets: Vec::new(),
         }
     }
 }
 
 impl Config {
     /// Load configuration from file
+    ///
+    /// Files from older releases are upgraded and saved back, with the
+    /// original kept next to them (see [`migration::backup_path`]).
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
         let content = std::fs::read_to_string(path)?;
-        let config: Config = serde_json::from_str(&content)?;
-        config.validate()?;
+        let (config, migrated_from) = Self::from_json(&content)?;
+        if let Some(version) = migrated_from {
+            let backup = migration::backup_path(path, version);
+            std::fs::write(&backup, &content)?;
+            config.save(path)?;
+            info!(
+                "Configuration upgraded from schema version {} to {}, original kept at {:?}",
+                version, CONFIG_SCHEMA_VERSION, backup
+            );
+        }
         Ok(config)
     }
 
+    /// Parse and validate configuration JSON, upgrading older schemas
+    ///
+    /// Also returns the schema version the JSON was upgraded from, if any.
+    pub fn from_json(json: &str) -> Result<(Self, Option<u32>), ConfigError> {
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        let migrated_from = migration::migrate(&mut value)?;
+        let config: Config = serde_json::from_value(value)?;
+        config.validate()?;
+        Ok((config, migrated_from))
+    }
+
     /// Save configuration to file
     pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
         self.validate()?;
@@ -239,6 +563,18 @@ impl Config {
         if self.subscription.timeout == 0 {
             return Err(ConfigError::Validation("Invalid timeout".to_string()));
         }
+        for rule_set in &self.routing_rules {
+            rule_set.validate()?;
+        }
+        for rule in &self.app_rules {
+            rule.validate()?;
+        }
+        self.dns.validate()?;
+        self.inbound.validate()?;
+        for profile in &self.profiles {
+            profile.validate()?;
+        }
+        self.backup.validate()?;
         Ok(())
     }
 }
@@ -266,10 +602,66 @@ mod tests {
         assert_eq!(config.proxy.http_port, loaded_config.proxy.http_port);
     }
 
+    #[test]
+    fn test_load_migrates_old_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let old = r#"{
+            "app": {"mode": "Simple", "language": "en", "theme": "System", "auto_start": false},
+            "proxy": {"mode": "Auto", "system_proxy": false, "http_port": 8118, "socks_port": 1080},
+            "subscription": {"auto_update_interval": 24, "user_agent": "v8ray", "timeout": 30}
+        }"#;
+        std::fs::write(&path, old).unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.schema_version, CONFIG_SCHEMA_VERSION);
+        assert_eq!(config.proxy.http_port, 8118);
+        let backup = migration::backup_path(&path, 1);
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), old);
+
+        // Saved back in the current schema
+        let (_, migrated_from) =
+            Config::from_json(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(migrated_from, None);
+
+        std::fs::write(&path, r#"{"schema_version": 99}"#).unwrap();
+        assert!(matches!(
+            Config::load(&path),
+            Err(ConfigError::UnsupportedVersion { found: 99, .. })
+        ));
+    }
+
     #[test]
     fn test_config_validation() {
         let mut config = Config::default();
         config.proxy.http_port = 0;
         assert!(config.validate().is_err());
     }
+
+    #[test]
+    fn test_apply_port_reassignment() {
+        let mut proxy = Config::default().proxy;
+        assert!(proxy.apply_port_reassignment("http", 8080, 8081));
+        assert_eq!(proxy.http_port, 8081);
+        // A port the settings do not hold, such as a profile's, is left alone
+        assert!(!proxy.apply_port_reassignment("socks", 11080, 11081));
+        assert!(!proxy.apply_port_reassignment("api", 10085, 10086));
+        assert_eq!(proxy.socks_port, 1080);
+    }
+
+    #[test]
+    fn test_engine_log_level() {
+        assert_eq!(Config::default().engine_log_level, EngineLogLevel::Warning);
+        assert_eq!(
+            "info".parse::<EngineLogLevel>().unwrap(),
+            EngineLogLevel::Info
+        );
+        assert!("verbose".parse::<EngineLogLevel>().is_err());
+
+        assert!(EngineLogLevel::Warning.allows("Error"));
+        assert!(EngineLogLevel::Warning.allows("Warning"));
+        assert!(!EngineLogLevel::Warning.allows("Info"));
+        assert!(EngineLogLevel::Debug.allows("Debug"));
+        assert!(!EngineLogLevel::None.allows("Error"));
+    }
 }
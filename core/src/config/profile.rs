@@ -0,0 +1,264 @@
+//! Profiles
+//!
+//! A profile is a named bundle of the settings users flip between together:
+//! the server, the proxy mode, which routing rule sets are on, the local
+//! inbound ports, the proxy core, the speed limit and what happens to the
+//! system proxy. Switching to a profile ("Work", "Streaming US", "Direct", …)
+//! applies all of them in one action. Settings a profile leaves unset keep
+//! their current value, except the Xray version, the speed limit and the
+//! ports: a profile without a version runs the selected one, one without
+//! limits is unlimited, and one without ports uses the configured ones. A
+//! profile's ports only apply while it is active and are never written to
+//! the proxy settings.
+
+use super::{ProxyConfig, ProxyMode, RoutingRuleSet};
+use crate::connection::rate_limit::RateLimit;
+use crate::error::ConfigError;
+use crate::proxy_core::CoreKind;
+use serde::{Deserialize, Serialize};
+
+/// Proxy modes a profile can select
+pub const PROFILE_MODES: &[&str] = &["global", "smart", "direct"];
+
+/// What switching to a profile does to the system proxy
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SystemProxyBehavior {
+    /// Leave the system proxy as it is
+    #[default]
+    Keep,
+    /// Point the system proxy at the app
+    Enable,
+    /// Clear the system proxy
+    Disable,
+}
+
+impl SystemProxyBehavior {
+    /// Name as used over FFI
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SystemProxyBehavior::Keep => "keep",
+            SystemProxyBehavior::Enable => "enable",
+            SystemProxyBehavior::Disable => "disable",
+        }
+    }
+}
+
+impl std::str::FromStr for SystemProxyBehavior {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(SystemProxyBehavior::Keep),
+            "enable" => Ok(SystemProxyBehavior::Enable),
+            "disable" => Ok(SystemProxyBehavior::Disable),
+            _ => Err(ConfigError::Validation(format!(
+                "Invalid system proxy behavior: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Named bundle of server, mode, rules and ports
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Profile {
+    /// Unique identifier
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// Server to connect to, or `None` to keep the current one
+    #[serde(default)]
+    pub server_id: Option<String>,
+    /// Proxy mode (global, smart or direct)
+    pub mode: String,
+    /// IDs of the routing rule sets to enable, all others being disabled,
+    /// or `None` to leave the rule sets as they are
+    #[serde(default)]
+    pub routing_rule_sets: Option<Vec<String>>,
+    /// Local HTTP port, or `None` for the configured one
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    /// Local SOCKS port, or `None` for the configured one
+    #[serde(default)]
+    pub socks_port: Option<u16>,
+    /// Installed Xray Core version to run, or `None` for the selected one
+    #[serde(default)]
+    pub xray_version: Option<String>,
+    /// Core running the profile's connections
+    #[serde(default)]
+    pub core: CoreKind,
+    /// Upload and download limits of the local inbounds
+    #[serde(default)]
+    pub rate_limit: RateLimit,
+    /// What to do with the system proxy
+    #[serde(default)]
+    pub system_proxy: SystemProxyBehavior,
+}
+
+impl Profile {
+    /// Validate the profile
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.id.is_empty() {
+            return Err(ConfigError::Validation("Profile ID is empty".to_string()));
+        }
+        if self.name.trim().is_empty() {
+            return Err(ConfigError::Validation("Profile name is empty".to_string()));
+        }
+        if !PROFILE_MODES.contains(&self.mode.as_str()) {
+            return Err(ConfigError::Validation(format!(
+                "Invalid profile proxy mode: {}",
+                self.mode
+            )));
+        }
+        if let Some(version) = &self.xray_version {
+            if !crate::xray::versions::is_valid_version(version) {
+                return Err(ConfigError::Validation(format!(
+                    "Invalid profile Xray version: {}",
+                    version
+                )));
+            }
+        }
+        if self.http_port == Some(0) || self.socks_port == Some(0) {
+            return Err(ConfigError::Validation(
+                "Profile ports must not be 0".to_string(),
+            ));
+        }
+        if self.http_port.is_some() && self.http_port == self.socks_port {
+            return Err(ConfigError::Validation(
+                "Profile HTTP and SOCKS ports must differ".to_string(),
+            ));
+        }
+        self.rate_limit.validate()
+    }
+
+    /// Enable exactly the rule sets the profile selects
+    pub fn apply_rule_sets(&self, rule_sets: &mut [RoutingRuleSet]) {
+        if let Some(ids) = &self.routing_rule_sets {
+            for rule_set in rule_sets {
+                rule_set.enabled = ids.contains(&rule_set.id);
+            }
+        }
+    }
+
+    /// HTTP and SOCKS ports while the profile is active, falling back to
+    /// the ports configured in `proxy`
+    pub fn inbound_ports(&self, proxy: &ProxyConfig) -> (u16, u16) {
+        (
+            self.http_port.unwrap_or(proxy.http_port),
+            self.socks_port.unwrap_or(proxy.socks_port),
+        )
+    }
+
+    /// Apply the mode and system proxy setting to `proxy`
+    ///
+    /// The ports are left alone, see [`Self::inbound_ports`].
+    pub fn apply_proxy(&self, proxy: &mut ProxyConfig) {
+        proxy.mode = match self.mode.as_str() {
+            "global" => ProxyMode::Proxy,
+            "direct" => ProxyMode::Direct,
+            _ => ProxyMode::Auto,
+        };
+        match self.system_proxy {
+            SystemProxyBehavior::Keep => {}
+            SystemProxyBehavior::Enable => proxy.system_proxy = true,
+            SystemProxyBehavior::Disable => proxy.system_proxy = false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn profile() -> Profile {
+        Profile {
+            id: "work".to_string(),
+            name: "Work".to_string(),
+            server_id: Some("server-1".to_string()),
+            mode: "global".to_string(),
+            routing_rule_sets: Some(vec!["office".to_string()]),
+            http_port: Some(18080),
+            socks_port: None,
+            xray_version: None,
+            core: Default::default(),
+            rate_limit: Default::default(),
+            system_proxy: SystemProxyBehavior::Enable,
+        }
+    }
+
+    fn rule_set(id: &str, enabled: bool) -> RoutingRuleSet {
+        RoutingRuleSet {
+            id: id.to_string(),
+            name: id.to_string(),
+            enabled,
+            rules: vec![],
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(profile().validate().is_ok());
+
+        let mut invalid = profile();
+        invalid.mode = "pac".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = profile();
+        invalid.socks_port = Some(18080);
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = profile();
+        invalid.name = " ".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = profile();
+        invalid.xray_version = Some("latest".to_string());
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = profile();
+        invalid.rate_limit.upload_bytes_per_sec = Some(0);
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let profile = profile();
+        let mut rule_sets = vec![rule_set("office", false), rule_set("streaming", true)];
+        profile.apply_rule_sets(&mut rule_sets);
+        assert!(rule_sets[0].enabled);
+        assert!(!rule_sets[1].enabled);
+
+        let mut config = Config::default();
+        profile.apply_proxy(&mut config.proxy);
+        assert!(matches!(config.proxy.mode, ProxyMode::Proxy));
+        assert!(config.proxy.system_proxy);
+        // Profile ports do not replace the configured ones
+        assert_eq!(config.proxy.http_port, 8080);
+        assert_eq!(profile.inbound_ports(&config.proxy), (18080, 1080));
+
+        // Unselected rule sets are left alone
+        let keep = Profile {
+            routing_rule_sets: None,
+            ..profile.clone()
+        };
+        keep.apply_rule_sets(&mut rule_sets);
+        assert!(rule_sets[0].enabled);
+    }
+
+    #[test]
+    fn test_system_proxy_behavior() {
+        for behavior in [
+            SystemProxyBehavior::Keep,
+            SystemProxyBehavior::Enable,
+            SystemProxyBehavior::Disable,
+        ] {
+            assert_eq!(
+                behavior.as_str().parse::<SystemProxyBehavior>().unwrap(),
+                behavior
+            );
+        }
+        assert!("on".parse::<SystemProxyBehavior>().is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! User-defined routing rules
+//!
+//! Routing rules map traffic matched by domain, IP, port, process or
+//! protocol to an outbound tag. They are stored in [`Config`](super::Config)
+//! and merged ahead of the built-in mode rules when generating the Xray
+//! configuration. App rules are a simpler per-application form used for
+//! split tunneling.
+
+use crate::error::ConfigError;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Outbound tag for traffic sent through the proxy
+pub const OUTBOUND_PROXY: &str = "proxy";
+/// Outbound tag for traffic sent directly
+pub const OUTBOUND_DIRECT: &str = "direct";
+/// Outbound tag for blocked traffic
+pub const OUTBOUND_BLOCK: &str = "block";
+/// Outbound tag prefix used for proxies in load-balanced configurations,
+/// followed by the index of the server
+pub const MULTI_PROXY_TAG_PREFIX: &str = "proxy-";
+
+/// Whether the generated configuration can have an outbound tagged `tag`
+///
+/// Besides proxy, direct and block, load-balanced configurations tag each
+/// proxy server with [`MULTI_PROXY_TAG_PREFIX`] and its index.
+pub fn is_known_outbound(tag: &str) -> bool {
+    [OUTBOUND_PROXY, OUTBOUND_DIRECT, OUTBOUND_BLOCK].contains(&tag)
+        || tag
+            .strip_prefix(MULTI_PROXY_TAG_PREFIX)
+            .is_some_and(|index| index.parse::<usize>().is_ok())
+}
+
+/// Whether Xray supports process-name routing on this platform
+pub const PROCESS_RULES_SUPPORTED: bool = cfg!(any(target_os = "windows", target_os = "macos"));
+
+/// A single routing rule
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingRule {
+    /// Domain matchers (e.g. "geosite:google", "domain:example.com")
+    #[serde(default)]
+    pub domains: Vec<String>,
+    /// IP matchers (e.g. "geoip:private", "10.0.0.0/8")
+    #[serde(default)]
+    pub ips: Vec<String>,
+    /// Port or port range list (e.g. "53,443,1000-2000")
+    #[serde(default)]
+    pub port: Option<String>,
+    /// Process names (e.g. "chrome.exe")
+    #[serde(default)]
+    pub processes: Vec<String>,
+    /// Sniffed protocols (http, tls, bittorrent)
+    #[serde(default)]
+    pub protocols: Vec<String>,
+    /// Target outbound tag (proxy, direct, block)
+    pub outbound_tag: String,
+}
+
+/// Named, orderable set of routing rules
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RoutingRuleSet {
+    /// Unique identifier
+    pub id: String,
+    /// Display name
+    pub name: String,
+    /// Whether the rule set is applied
+    pub enabled: bool,
+    /// Rules, evaluated in order
+    pub rules: Vec<RoutingRule>,
+}
+
+/// Per-application split tunneling rule
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AppRule {
+    /// Unique identifier
+    pub id: String,
+    /// Executable name (e.g. "chrome.exe", "Telegram")
+    pub process_name: String,
+    /// Target outbound tag (proxy, direct, block)
+    pub outbound_tag: String,
+    /// Whether the rule is applied
+    pub enabled: bool,
+}
+
+impl AppRule {
+    /// Validate the rule
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.id.is_empty() {
+            return Err(ConfigError::Validation("App rule ID is empty".to_string()));
+        }
+        if self.process_name.trim().is_empty() {
+            return Err(ConfigError::Validation(
+                "App rule process name is empty".to_string(),
+            ));
+        }
+        if ![OUTBOUND_PROXY, OUTBOUND_DIRECT, OUTBOUND_BLOCK].contains(&self.outbound_tag.as_str())
+        {
+            return Err(ConfigError::Validation(format!(
+                "Invalid app rule outbound tag: {}",
+                self.outbound_tag
+            )));
+        }
+        Ok(())
+    }
+
+    /// Convert to an Xray routing rule
+    pub fn to_xray_rule(&self) -> Value {
+        json!({
+            "type": "field",
+            "process": [self.process_name],
+            "outboundTag": self.outbound_tag
+        })
+    }
+}
+
+impl RoutingRule {
+    /// Check whether the rule has no matchers
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+            && self.ips.is_empty()
+            && self.port.is_none()
+            && self.processes.is_empty()
+            && self.protocols.is_empty()
+    }
+
+    /// Validate the rule
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !is_known_outbound(&self.outbound_tag) {
+            return Err(ConfigError::Validation(format!(
+                "Unknown routing rule outbound tag: {}",
+                self.outbound_tag
+            )));
+        }
+        if self.is_empty() {
+            return Err(ConfigError::Validation(
+                "Routing rule has no matchers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Convert to an Xray routing rule
+    pub fn to_xray_rule(&self) -> Value {
+        let mut rule = Map::new();
+        rule.insert("type".to_string(), json!("field"));
+        rule.insert("outboundTag".to_string(), json!(self.outbound_tag));
+
+        if !self.domains.is_empty() {
+            rule.insert("domain".to_string(), json!(self.domains));
+        }
+        if !self.ips.is_empty() {
+            rule.insert("ip".to_string(), json!(self.ips));
+        }
+        if let Some(ref port) = self.port {
+            rule.insert("port".to_string(), json!(port));
+        }
+        if !self.processes.is_empty() {
+            rule.insert("process".to_string(), json!(self.processes));
+        }
+        if !self.protocols.is_empty() {
+            rule.insert("protocol".to_string(), json!(self.protocols));
+        }
+
+        Value::Object(rule)
+    }
+}
+
+impl RoutingRuleSet {
+    /// Validate all rules in the set
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.id.is_empty() {
+            return Err(ConfigError::Validation(
+                "Routing rule set ID is empty".to_string(),
+            ));
+        }
+        for rule in &self.rules {
+            rule.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Convert enabled rules to Xray routing rules
+    pub fn to_xray_rules(&self) -> Vec<Value> {
+        if !self.enabled {
+            return vec![];
+        }
+        self.rules.iter().map(RoutingRule::to_xray_rule).collect()
+    }
+}
+
+/// Default extra latency direct connections may have and still be preferred
+pub const DEFAULT_MAX_DIRECT_SLOWDOWN_MS: u32 = 50;
+
+fn default_max_direct_slowdown_ms() -> u32 {
+    DEFAULT_MAX_DIRECT_SLOWDOWN_MS
+}
+
+/// Settings of the direct preference heuristic
+///
+/// In proxied modes, domains on the probe list are reached both directly
+/// and through the proxy; those that work directly without being
+/// noticeably slower are routed direct.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DirectPreferenceSettings {
+    /// Whether the heuristic is enabled
+    #[serde(default)]
+    pub enabled: bool,
+    /// Domains that may be probed; the resulting rules cover subdomains
+    #[serde(default)]
+    pub probe_domains: Vec<String>,
+    /// How many milliseconds slower than the proxy a direct connection may
+    /// be and still be preferred
+    #[serde(default = "default_max_direct_slowdown_ms")]
+    pub max_slowdown_ms: u32,
+}
+
+impl Default for DirectPreferenceSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            probe_domains: Vec::new(),
+            max_slowdown_ms: DEFAULT_MAX_DIRECT_SLOWDOWN_MS,
+        }
+    }
+}
+
+impl DirectPreferenceSettings {
+    /// Validate the probe list
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for domain in &self.probe_domains {
+            if !crate::utils::network::is_valid_hostname(domain) {
+                return Err(ConfigError::Validation(format!(
+                    "Invalid probe domain: {}",
+                    domain
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_rule() -> RoutingRule {
+        RoutingRule {
+            domains: vec!["geosite:google".to_string()],
+            ips: vec![],
+            port: Some("443".to_string()),
+            processes: vec![],
+            protocols: vec![],
+            outbound_tag: OUTBOUND_PROXY.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_to_xray_rule() {
+        let rule = create_test_rule().to_xray_rule();
+        assert_eq!(rule["type"], "field");
+        assert_eq!(rule["outboundTag"], "proxy");
+        assert_eq!(rule["domain"][0], "geosite:google");
+        assert_eq!(rule["port"], "443");
+        assert!(rule.get("ip").is_none());
+    }
+
+    #[test]
+    fn test_rule_validation() {
+        assert!(create_test_rule().validate().is_ok());
+
+        let mut rule = create_test_rule();
+        rule.domains.clear();
+        rule.port = None;
+        assert!(rule.validate().is_err());
+
+        let mut rule = create_test_rule();
+        rule.outbound_tag = "proxy-2".to_string();
+        assert!(rule.validate().is_ok());
+        for tag in ["", "prxy", "proxy-", "proxy-x", "dns-out"] {
+            rule.outbound_tag = tag.to_string();
+            assert!(rule.validate().is_err(), "{} accepted", tag);
+        }
+    }
+
+    #[test]
+    fn test_app_rule() {
+        let rule = AppRule {
+            id: "chrome".to_string(),
+            process_name: "chrome.exe".to_string(),
+            outbound_tag: OUTBOUND_DIRECT.to_string(),
+            enabled: true,
+        };
+        assert!(rule.validate().is_ok());
+        let typo = AppRule {
+            outbound_tag: "drect".to_string(),
+            ..rule.clone()
+        };
+        assert!(typo.validate().is_err());
+
+        let value = rule.to_xray_rule();
+        assert_eq!(value["process"][0], "chrome.exe");
+        assert_eq!(value["outboundTag"], "direct");
+    }
+
+    #[test]
+    fn test_disabled_rule_set() {
+        let set = RoutingRuleSet {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            enabled: false,
+            rules: vec![create_test_rule()],
+        };
+        assert!(set.to_xray_rules().is_empty());
+    }
+}
@@ -2,8 +2,12 @@
 //!
 //! This module provides validation functionality for configurations.
 
-use super::{Config, ProxyProtocol, ProxyServerConfig};
+use super::{Config, ProxyProtocol, ProxyServerConfig, StreamSettings};
+use crate::error::ConfigError;
 use crate::utils::network::{is_valid_hostname, is_valid_ip, is_valid_port};
+use base64::Engine;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
 use tracing::debug;
 
 /// Validation result
@@ -51,6 +55,151 @@ impl ValidationResult {
     pub fn is_valid(&self) -> bool {
         self.valid
     }
+
+    /// Collect diagnostics as `path: message` errors and warnings
+    pub fn from_diagnostics(diagnostics: &[Diagnostic]) -> Self {
+        let mut result = Self::success();
+        for diagnostic in diagnostics {
+            let message = diagnostic.to_string();
+            match diagnostic.severity {
+                Severity::Error => result.add_error(message),
+                Severity::Warning => result.add_warning(message),
+            }
+        }
+        result
+    }
+}
+
+/// How serious a diagnostic is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The configuration cannot be used
+    Error,
+    /// The configuration works but is likely not what was meant
+    Warning,
+}
+
+impl Severity {
+    /// Name as used over FFI
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// Problem found at a field of a configuration
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// Path of the field, e.g. `proxy.http_port`, `profiles[0].mode` or
+    /// `stream_settings.ws_settings.path`
+    pub path: String,
+    /// How serious the problem is
+    pub severity: Severity,
+    /// Description of the problem
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(path: impl Into<String>, message: String) -> Self {
+        Self {
+            path: path.into(),
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(path: impl Into<String>, message: String) -> Self {
+        Self {
+            path: path.into(),
+            severity: Severity::Warning,
+            message,
+        }
+    }
+
+    /// Whether the problem makes the configuration unusable
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// Shadowsocks methods supported by Xray
+pub const SHADOWSOCKS_METHODS: &[&str] = &[
+    "aes-128-gcm",
+    "aes-256-gcm",
+    "chacha20-poly1305",
+    "chacha20-ietf-poly1305",
+    "xchacha20-poly1305",
+    "xchacha20-ietf-poly1305",
+    "2022-blake3-aes-128-gcm",
+    "2022-blake3-aes-256-gcm",
+    "2022-blake3-chacha20-poly1305",
+    "none",
+    "plain",
+];
+
+/// Longest custom user ID that Xray maps to a UUID for VLESS and VMess
+pub const MAX_CUSTOM_ID_LEN: usize = 30;
+
+/// VLESS flow values supported by Xray (an empty flow is omitted)
+pub const VLESS_FLOWS: &[&str] = &["xtls-rprx-vision", "xtls-rprx-vision-udp443"];
+
+/// Transport networks supported by Xray
+pub const STREAM_NETWORKS: &[&str] = &[
+    "tcp",
+    "raw",
+    "kcp",
+    "ws",
+    "http",
+    "h2",
+    "quic",
+    "grpc",
+    "httpupgrade",
+    "splithttp",
+    "xhttp",
+];
+
+/// Error in a single field of a server configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// Field name; protocol settings are prefixed with `settings.`
+    pub field: String,
+    /// Error message
+    pub message: String,
+}
+
+impl FieldError {
+    fn new(field: &str, message: String) -> Self {
+        Self {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+/// Result of validating a server configuration field by field
+#[derive(Debug, Clone)]
+pub struct FieldValidation {
+    /// Configuration with normalized values (trimmed, lowercased where
+    /// case does not matter)
+    pub normalized: ProxyServerConfig,
+    /// Errors by field, empty if the configuration is valid
+    pub errors: Vec<FieldError>,
+}
+
+impl FieldValidation {
+    /// Check if validation passed
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
 }
 
 /// Configuration validator
@@ -59,131 +208,598 @@ pub struct ConfigValidator;
 impl ConfigValidator {
     /// Validate application configuration
     pub fn validate_config(config: &Config) -> ValidationResult {
-        let mut result = ValidationResult::success();
+        let result = ValidationResult::from_diagnostics(&Self::diagnose_config(config));
+        debug!("Config validation result: {:?}", result);
+        result
+    }
+
+    /// Validate proxy server configuration
+    pub fn validate_proxy_config(config: &ProxyServerConfig) -> ValidationResult {
+        let result = ValidationResult::from_diagnostics(&Self::diagnose_proxy_config(config));
+        debug!("Proxy config validation result: {:?}", result);
+        result
+    }
+
+    /// Check application configuration, reporting every problem by field path
+    pub fn diagnose_config(config: &Config) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
 
-        // Validate proxy ports
-        if !is_valid_port(config.proxy.http_port) {
-            result.add_error(format!("Invalid HTTP port: {}", config.proxy.http_port));
+        let proxy = &config.proxy;
+        if !is_valid_port(proxy.http_port) {
+            diagnostics.push(Diagnostic::error(
+                "proxy.http_port",
+                format!("Invalid HTTP port: {}", proxy.http_port),
+            ));
+        }
+        if !is_valid_port(proxy.socks_port) {
+            diagnostics.push(Diagnostic::error(
+                "proxy.socks_port",
+                format!("Invalid SOCKS port: {}", proxy.socks_port),
+            ));
+        }
+        if proxy.http_port == proxy.socks_port {
+            diagnostics.push(Diagnostic::error(
+                "proxy.socks_port",
+                "HTTP and SOCKS ports cannot be the same".to_string(),
+            ));
+        }
+        for (path, port) in [
+            ("proxy.http_port", proxy.http_port),
+            ("proxy.socks_port", proxy.socks_port),
+        ] {
+            if port != 0 && port < 1024 {
+                diagnostics.push(Diagnostic::warning(
+                    path,
+                    format!("Port {} is privileged and may need elevated rights", port),
+                ));
+            }
         }
 
-        if !is_valid_port(config.proxy.socks_port) {
-            result.add_error(format!("Invalid SOCKS port: {}", config.proxy.socks_port));
+        if config.subscription.timeout == 0 {
+            diagnostics.push(Diagnostic::error(
+                "subscription.timeout",
+                "Subscription timeout cannot be zero".to_string(),
+            ));
+        } else if config.subscription.timeout > 300 {
+            diagnostics.push(Diagnostic::warning(
+                "subscription.timeout",
+                "Subscription timeout is very high (>300s)".to_string(),
+            ));
+        }
+        if config.subscription.auto_update_interval == 0 {
+            diagnostics.push(Diagnostic::warning(
+                "subscription.auto_update_interval",
+                "Auto update is disabled".to_string(),
+            ));
         }
 
-        // Check for port conflicts
-        if config.proxy.http_port == config.proxy.socks_port {
-            result.add_error("HTTP and SOCKS ports cannot be the same".to_string());
+        let mut rule_set_ids = HashSet::new();
+        for (i, rule_set) in config.routing_rules.iter().enumerate() {
+            let path = format!("routing_rules[{}]", i);
+            if rule_set.id.is_empty() {
+                diagnostics.push(Diagnostic::error(
+                    format!("{}.id", path),
+                    "Routing rule set ID is empty".to_string(),
+                ));
+            } else if !rule_set_ids.insert(rule_set.id.as_str()) {
+                diagnostics.push(Diagnostic::error(
+                    format!("{}.id", path),
+                    format!("Duplicate routing rule set ID: {}", rule_set.id),
+                ));
+            }
+            for (j, rule) in rule_set.rules.iter().enumerate() {
+                Self::check(
+                    &mut diagnostics,
+                    format!("{}.rules[{}]", path, j),
+                    rule.validate(),
+                );
+            }
         }
 
-        // Validate subscription settings
-        if config.subscription.timeout == 0 {
-            result.add_error("Subscription timeout cannot be zero".to_string());
+        let mut app_rule_ids = HashSet::new();
+        for (i, rule) in config.app_rules.iter().enumerate() {
+            let path = format!("app_rules[{}]", i);
+            Self::check(&mut diagnostics, path.clone(), rule.validate());
+            if !rule.id.is_empty() && !app_rule_ids.insert(rule.id.as_str()) {
+                diagnostics.push(Diagnostic::error(
+                    format!("{}.id", path),
+                    format!("Duplicate app rule ID: {}", rule.id),
+                ));
+            }
         }
 
-        if config.subscription.timeout > 300 {
-            result.add_warning("Subscription timeout is very high (>300s)".to_string());
+        Self::check(&mut diagnostics, "dns", config.dns.validate());
+        for (path, auth) in [
+            ("inbound.http_auth", &config.inbound.http_auth),
+            ("inbound.socks_auth", &config.inbound.socks_auth),
+        ] {
+            if let Some(auth) = auth {
+                Self::check(&mut diagnostics, path, auth.validate());
+            }
         }
+        Self::check(
+            &mut diagnostics,
+            "direct_preference",
+            config.direct_preference.validate(),
+        );
 
-        if config.subscription.auto_update_interval == 0 {
-            result.add_warning("Auto update is disabled".to_string());
+        let mut profile_ids = HashSet::new();
+        for (i, profile) in config.profiles.iter().enumerate() {
+            let path = format!("profiles[{}]", i);
+            Self::check(&mut diagnostics, path.clone(), profile.validate());
+            if !profile.id.is_empty() && !profile_ids.insert(profile.id.as_str()) {
+                diagnostics.push(Diagnostic::error(
+                    format!("{}.id", path),
+                    format!("Duplicate profile ID: {}", profile.id),
+                ));
+            }
+            for id in profile.routing_rule_sets.iter().flatten() {
+                if !rule_set_ids.contains(id.as_str()) {
+                    diagnostics.push(Diagnostic::warning(
+                        format!("{}.routing_rule_sets", path),
+                        format!("Unknown routing rule set: {}", id),
+                    ));
+                }
+            }
+        }
+        if let Some(active) = &config.active_profile {
+            if !profile_ids.contains(active.as_str()) {
+                diagnostics.push(Diagnostic::warning(
+                    "active_profile",
+                    format!("Unknown profile: {}", active),
+                ));
+            }
         }
 
-        debug!("Config validation result: {:?}", result);
-        result
+        Self::check(&mut diagnostics, "backup", config.backup.validate());
+
+        diagnostics
     }
 
-    /// Validate proxy server configuration
-    pub fn validate_proxy_config(config: &ProxyServerConfig) -> ValidationResult {
-        let mut result = ValidationResult::success();
+    /// Check a proxy server configuration, reporting every problem by field
+    /// path
+    pub fn diagnose_proxy_config(config: &ProxyServerConfig) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
 
-        // Validate server address
+        if config.name.trim().is_empty() {
+            diagnostics.push(Diagnostic::error(
+                "name",
+                "Server name cannot be empty".to_string(),
+            ));
+        }
         if config.server.is_empty() {
-            result.add_error("Server address cannot be empty".to_string());
+            diagnostics.push(Diagnostic::error(
+                "server",
+                "Server address cannot be empty".to_string(),
+            ));
         } else if !is_valid_ip(&config.server) && !is_valid_hostname(&config.server) {
-            result.add_error(format!("Invalid server address: {}", config.server));
+            diagnostics.push(Diagnostic::error(
+                "server",
+                format!("Invalid server address: {}", config.server),
+            ));
         }
-
-        // Validate port
         if !is_valid_port(config.port) {
-            result.add_error(format!("Invalid port: {}", config.port));
-        }
-
-        // Validate name
-        if config.name.is_empty() {
-            result.add_error("Server name cannot be empty".to_string());
+            diagnostics.push(Diagnostic::error(
+                "port",
+                format!("Invalid port: {}", config.port),
+            ));
         }
 
-        // Protocol-specific validation
+        let setting = |key: &str| config.settings.get(key).and_then(|v| v.as_str());
         match config.protocol {
             ProxyProtocol::Vless => {
-                Self::validate_vless_settings(config, &mut result);
+                Self::check_uuid(setting("id"), "VLESS", true, &mut diagnostics);
+                match setting("flow") {
+                    None | Some("") | Some("none") => {}
+                    Some(flow) if VLESS_FLOWS.contains(&flow) => {
+                        let security = config.stream_settings.as_ref().map(|s| s.security.as_str());
+                        if !matches!(security, Some("tls" | "reality")) {
+                            diagnostics.push(Diagnostic::error(
+                                "settings.flow",
+                                format!("VLESS flow {} requires TLS or REALITY", flow),
+                            ));
+                        }
+                    }
+                    Some(flow) => diagnostics.push(Diagnostic::error(
+                        "settings.flow",
+                        format!("Unsupported VLESS flow: {}", flow),
+                    )),
+                }
             }
             ProxyProtocol::Vmess => {
-                Self::validate_vmess_settings(config, &mut result);
+                Self::check_uuid(setting("id"), "VMess", true, &mut diagnostics);
+                if !config.settings.contains_key("alterId") {
+                    diagnostics.push(Diagnostic::warning(
+                        "settings.alterId",
+                        "VMess should have 'alterId' in settings".to_string(),
+                    ));
+                }
             }
             ProxyProtocol::Trojan => {
-                Self::validate_trojan_settings(config, &mut result);
+                Self::check_password(setting("password"), "Trojan", &mut diagnostics);
             }
             ProxyProtocol::Shadowsocks => {
-                Self::validate_shadowsocks_settings(config, &mut result);
+                match setting("method").map(str::to_ascii_lowercase).as_deref() {
+                    None | Some("") => diagnostics.push(Diagnostic::error(
+                        "settings.method",
+                        "Shadowsocks requires 'method' in settings".to_string(),
+                    )),
+                    Some(method) if SHADOWSOCKS_METHODS.contains(&method) => {}
+                    Some(method) => diagnostics.push(Diagnostic::error(
+                        "settings.method",
+                        format!("Unsupported Shadowsocks method: {}", method),
+                    )),
+                }
+                Self::check_password(setting("password"), "Shadowsocks", &mut diagnostics);
             }
             ProxyProtocol::Http | ProxyProtocol::Socks => {
-                // Basic validation is sufficient
+                if setting("username").is_some() != setting("password").is_some() {
+                    diagnostics.push(Diagnostic::warning(
+                        "settings.password",
+                        "Username and password should be set together".to_string(),
+                    ));
+                }
+            }
+            ProxyProtocol::Hysteria2 => {
+                Self::check_password(setting("password"), "Hysteria2", &mut diagnostics);
+            }
+            ProxyProtocol::Tuic => {
+                Self::check_uuid(setting("id"), "TUIC", false, &mut diagnostics);
+                Self::check_password(setting("password"), "TUIC", &mut diagnostics);
             }
         }
 
-        // Validate stream settings
-        if let Some(ref stream) = config.stream_settings {
-            if stream.security == "tls" && stream.tls_settings.is_none() {
-                result.add_error("TLS security requires TLS settings".to_string());
-            }
+        if let Some(stream) = &config.stream_settings {
+            Self::diagnose_stream_settings(stream, config, &mut diagnostics);
+        }
 
-            if stream.network == "ws" && stream.ws_settings.is_none() {
-                result.add_warning("WebSocket network should have WS settings".to_string());
-            }
+        diagnostics
+    }
 
-            if stream.network == "grpc" && stream.grpc_settings.is_none() {
-                result.add_warning("gRPC network should have gRPC settings".to_string());
+    /// Check transport and security settings fit together
+    fn diagnose_stream_settings(
+        stream: &StreamSettings,
+        config: &ProxyServerConfig,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if !STREAM_NETWORKS.contains(&stream.network.as_str()) {
+            diagnostics.push(Diagnostic::error(
+                "stream_settings.network",
+                format!("Unsupported network: {}", stream.network),
+            ));
+        }
+
+        match stream.security.as_str() {
+            "" | "none" => {}
+            "tls" => {
+                if stream.tls_settings.is_none() {
+                    diagnostics.push(Diagnostic::error(
+                        "stream_settings.tls_settings",
+                        "TLS security requires TLS settings".to_string(),
+                    ));
+                }
             }
+            "reality" => Self::diagnose_reality(stream, config, diagnostics),
+            security => diagnostics.push(Diagnostic::error(
+                "stream_settings.security",
+                format!("Unsupported security: {}", security),
+            )),
         }
 
-        debug!("Proxy config validation result: {:?}", result);
-        result
+        match stream.network.as_str() {
+            "ws" => match &stream.ws_settings {
+                None => diagnostics.push(Diagnostic::warning(
+                    "stream_settings.ws_settings",
+                    "WebSocket network should have WS settings".to_string(),
+                )),
+                Some(ws) if !ws.path.starts_with('/') => diagnostics.push(Diagnostic::error(
+                    "stream_settings.ws_settings.path",
+                    format!("WebSocket path must start with '/': {}", ws.path),
+                )),
+                Some(_) => {}
+            },
+            "grpc" => match &stream.grpc_settings {
+                None => diagnostics.push(Diagnostic::warning(
+                    "stream_settings.grpc_settings",
+                    "gRPC network should have gRPC settings".to_string(),
+                )),
+                Some(grpc) if grpc.service_name.trim().is_empty() => {
+                    diagnostics.push(Diagnostic::warning(
+                        "stream_settings.grpc_settings.service_name",
+                        "gRPC service name is empty".to_string(),
+                    ))
+                }
+                Some(_) => {}
+            },
+            _ => {}
+        }
     }
 
-    /// Validate VLESS-specific settings
-    fn validate_vless_settings(config: &ProxyServerConfig, result: &mut ValidationResult) {
-        if !config.settings.contains_key("id") {
-            result.add_error("VLESS requires 'id' (UUID) in settings".to_string());
+    /// Check REALITY needs: a server name to borrow, the server's public key
+    /// and a short ID
+    ///
+    /// The keys use the Xray field names (`publicKey`, `shortId`) in the
+    /// protocol settings.
+    fn diagnose_reality(
+        stream: &StreamSettings,
+        config: &ProxyServerConfig,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        if !matches!(
+            config.protocol,
+            ProxyProtocol::Vless | ProxyProtocol::Trojan
+        ) {
+            diagnostics.push(Diagnostic::error(
+                "stream_settings.security",
+                "REALITY is only supported with VLESS and Trojan".to_string(),
+            ));
+        }
+
+        let server_name = stream
+            .tls_settings
+            .as_ref()
+            .and_then(|tls| tls.server_name.as_deref())
+            .unwrap_or("");
+        if server_name.is_empty() {
+            diagnostics.push(Diagnostic::error(
+                "stream_settings.tls_settings.server_name",
+                "REALITY requires a server name".to_string(),
+            ));
+        }
+
+        let setting = |key: &str| config.settings.get(key).and_then(|v| v.as_str());
+        match setting("publicKey") {
+            None | Some("") => diagnostics.push(Diagnostic::warning(
+                "settings.publicKey",
+                "REALITY requires the server's public key".to_string(),
+            )),
+            Some(key) => {
+                let valid = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                    .decode(key)
+                    .is_ok_and(|bytes| bytes.len() == 32);
+                if !valid {
+                    diagnostics.push(Diagnostic::error(
+                        "settings.publicKey",
+                        "REALITY public key must be 32 bytes of URL-safe base64".to_string(),
+                    ));
+                }
+            }
+        }
+        if let Some(short_id) = setting("shortId") {
+            let valid = short_id.len() <= 16
+                && short_id.len() % 2 == 0
+                && short_id.chars().all(|c| c.is_ascii_hexdigit());
+            if !valid {
+                diagnostics.push(Diagnostic::error(
+                    "settings.shortId",
+                    "REALITY short ID must be up to 16 hex digits, even in length".to_string(),
+                ));
+            }
+        }
+    }
+
+    /// Check `id` is present and a UUID
+    ///
+    /// With `custom_ids`, IDs of up to [`MAX_CUSTOM_ID_LEN`] bytes are
+    /// accepted as well, as Xray maps them to a UUID.
+    fn check_uuid(
+        id: Option<&str>,
+        protocol: &str,
+        custom_ids: bool,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        match id.map(str::trim) {
+            None | Some("") => diagnostics.push(Diagnostic::error(
+                "settings.id",
+                format!("{} requires 'id' (UUID) in settings", protocol),
+            )),
+            Some(id) if uuid::Uuid::parse_str(id).is_ok() => {}
+            Some(id) if custom_ids && id.len() <= MAX_CUSTOM_ID_LEN => {}
+            Some(id) if custom_ids => diagnostics.push(Diagnostic::error(
+                "settings.id",
+                format!(
+                    "Invalid ID: {} (neither a UUID nor up to {} characters)",
+                    id, MAX_CUSTOM_ID_LEN
+                ),
+            )),
+            Some(id) => diagnostics.push(Diagnostic::error(
+                "settings.id",
+                format!("Invalid UUID: {}", id),
+            )),
         }
     }
 
-    /// Validate VMess-specific settings
-    fn validate_vmess_settings(config: &ProxyServerConfig, result: &mut ValidationResult) {
-        if !config.settings.contains_key("id") {
-            result.add_error("VMess requires 'id' (UUID) in settings".to_string());
+    /// Check `password` is present and not empty
+    fn check_password(password: Option<&str>, protocol: &str, diagnostics: &mut Vec<Diagnostic>) {
+        if password.is_none_or(str::is_empty) {
+            diagnostics.push(Diagnostic::error(
+                "settings.password",
+                format!("{} requires 'password' in settings", protocol),
+            ));
         }
+    }
 
-        if !config.settings.contains_key("alterId") {
-            result.add_warning("VMess should have 'alterId' in settings".to_string());
+    /// Record the error of a section validator at `path`
+    fn check(
+        diagnostics: &mut Vec<Diagnostic>,
+        path: impl Into<String>,
+        result: Result<(), ConfigError>,
+    ) {
+        if let Err(e) = result {
+            let message = match e {
+                ConfigError::Validation(message) => message,
+                e => e.to_string(),
+            };
+            diagnostics.push(Diagnostic::error(path, message));
         }
     }
 
-    /// Validate Trojan-specific settings
-    fn validate_trojan_settings(config: &ProxyServerConfig, result: &mut ValidationResult) {
-        if !config.settings.contains_key("password") {
-            result.add_error("Trojan requires 'password' in settings".to_string());
+    /// Validate and normalize a server configuration entered by hand
+    ///
+    /// Unlike [`ConfigValidator::validate_proxy_config`], errors are
+    /// reported per field and values are checked for format, not just
+    /// presence.
+    pub fn validate_server_fields(config: &ProxyServerConfig) -> FieldValidation {
+        let mut normalized = config.clone();
+        let mut errors = Vec::new();
+
+        normalized.name = crate::utils::names::normalize_name(&config.name);
+        if normalized.name.is_empty() {
+            errors.push(FieldError::new(
+                "name",
+                "Server name cannot be empty".to_string(),
+            ));
+        }
+
+        // IPv6 addresses may be entered in URL form
+        let server = config.server.trim();
+        let server = server
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(server);
+        normalized.server = server.to_ascii_lowercase();
+        if normalized.server.is_empty() {
+            errors.push(FieldError::new(
+                "server",
+                "Server address cannot be empty".to_string(),
+            ));
+        } else if !is_valid_ip(&normalized.server) && !is_valid_hostname(&normalized.server) {
+            errors.push(FieldError::new(
+                "server",
+                format!("Invalid server address: {}", config.server),
+            ));
+        }
+
+        if !is_valid_port(config.port) {
+            errors.push(FieldError::new(
+                "port",
+                format!("Invalid port: {}", config.port),
+            ));
+        }
+
+        let settings = &mut normalized.settings;
+        match config.protocol {
+            ProxyProtocol::Vless => {
+                Self::normalize_uuid(settings, "VLESS", &mut errors);
+                let flow = Self::normalize_string(settings, "flow", false);
+                match flow.as_deref() {
+                    None | Some("") | Some("none") => {
+                        settings.remove("flow");
+                    }
+                    Some(flow) if VLESS_FLOWS.contains(&flow) => {}
+                    Some(flow) => errors.push(FieldError::new(
+                        "settings.flow",
+                        format!("Unsupported VLESS flow: {}", flow),
+                    )),
+                }
+            }
+            ProxyProtocol::Vmess => {
+                Self::normalize_uuid(settings, "VMess", &mut errors);
+            }
+            ProxyProtocol::Trojan => {
+                Self::require_password(settings, "Trojan", &mut errors);
+            }
+            ProxyProtocol::Shadowsocks => {
+                let method = Self::normalize_string(settings, "method", true);
+                match method.as_deref() {
+                    None | Some("") => errors.push(FieldError::new(
+                        "settings.method",
+                        "Shadowsocks requires 'method' in settings".to_string(),
+                    )),
+                    Some(method) if SHADOWSOCKS_METHODS.contains(&method) => {}
+                    Some(method) => errors.push(FieldError::new(
+                        "settings.method",
+                        format!("Unsupported Shadowsocks method: {}", method),
+                    )),
+                }
+                Self::require_password(settings, "Shadowsocks", &mut errors);
+            }
+            ProxyProtocol::Http | ProxyProtocol::Socks => {}
+            ProxyProtocol::Hysteria2 => {
+                Self::require_password(settings, "Hysteria2", &mut errors);
+            }
+            ProxyProtocol::Tuic => {
+                Self::normalize_uuid(settings, "TUIC", &mut errors);
+                Self::require_password(settings, "TUIC", &mut errors);
+            }
+        }
+
+        if let Some(stream) = &config.stream_settings {
+            let mut diagnostics = Vec::new();
+            Self::diagnose_stream_settings(stream, config, &mut diagnostics);
+            errors.extend(
+                diagnostics
+                    .into_iter()
+                    .filter(Diagnostic::is_error)
+                    .map(|d| FieldError {
+                        field: d.path,
+                        message: d.message,
+                    }),
+            );
         }
+
+        debug!("Server field validation errors: {:?}", errors);
+        FieldValidation { normalized, errors }
     }
 
-    /// Validate Shadowsocks-specific settings
-    fn validate_shadowsocks_settings(config: &ProxyServerConfig, result: &mut ValidationResult) {
-        if !config.settings.contains_key("method") {
-            result.add_error("Shadowsocks requires 'method' in settings".to_string());
+    /// Trim (and optionally lowercase) a string setting in place, returning it
+    fn normalize_string(
+        settings: &mut HashMap<String, serde_json::Value>,
+        key: &str,
+        lowercase: bool,
+    ) -> Option<String> {
+        let value = settings.get(key)?.as_str()?.trim();
+        let value = if lowercase {
+            value.to_ascii_lowercase()
+        } else {
+            value.to_string()
+        };
+        settings.insert(key.to_string(), serde_json::Value::String(value.clone()));
+        Some(value)
+    }
+
+    /// Check the `id` setting is a UUID and store it in canonical form
+    fn normalize_uuid(
+        settings: &mut HashMap<String, serde_json::Value>,
+        protocol: &str,
+        errors: &mut Vec<FieldError>,
+    ) {
+        let Some(id) = Self::normalize_string(settings, "id", true) else {
+            errors.push(FieldError::new(
+                "settings.id",
+                format!("{} requires 'id' (UUID) in settings", protocol),
+            ));
+            return;
+        };
+        match uuid::Uuid::parse_str(&id) {
+            Ok(uuid) => {
+                settings.insert(
+                    "id".to_string(),
+                    serde_json::Value::String(uuid.hyphenated().to_string()),
+                );
+            }
+            Err(_) => errors.push(FieldError::new(
+                "settings.id",
+                format!("Invalid UUID: {}", id),
+            )),
         }
+    }
 
-        if !config.settings.contains_key("password") {
-            result.add_error("Shadowsocks requires 'password' in settings".to_string());
+    /// Check the `password` setting is present and not empty
+    fn require_password(
+        settings: &HashMap<String, serde_json::Value>,
+        protocol: &str,
+        errors: &mut Vec<FieldError>,
+    ) {
+        let empty = settings
+            .get("password")
+            .and_then(|p| p.as_str())
+            .is_none_or(str::is_empty);
+        if empty {
+            errors.push(FieldError::new(
+                "settings.password",
+                format!("{} requires 'password' in settings", protocol),
+            ));
         }
     }
 }
@@ -192,7 +808,6 @@ impl ConfigValidator {
 mod tests {
     use super::*;
     use chrono::Utc;
-    use std::collections::HashMap;
 
     #[test]
     fn test_validate_config_success() {
@@ -237,6 +852,7 @@ mod tests {
             tags: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            raw_name: None,
         };
 
         let result = ConfigValidator::validate_proxy_config(&config);
@@ -256,6 +872,7 @@ mod tests {
             tags: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            raw_name: None,
         };
 
         let result = ConfigValidator::validate_proxy_config(&config);
@@ -275,9 +892,253 @@ mod tests {
             tags: vec![],
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            raw_name: None,
         };
 
         let result = ConfigValidator::validate_proxy_config(&config);
         assert!(!result.is_valid());
     }
+
+    #[test]
+    fn test_validate_server_fields() {
+        let mut settings = HashMap::new();
+        settings.insert(
+            "id".to_string(),
+            serde_json::json!(" 550E8400E29B41D4A716446655440000 "),
+        );
+        settings.insert("flow".to_string(), serde_json::json!("none"));
+        let mut config = ProxyServerConfig {
+            id: "test".to_string(),
+            name: "  Test\u{200B}  Server\n".to_string(),
+            server: "[2001:DB8::1]".to_string(),
+            port: 443,
+            protocol: ProxyProtocol::Vless,
+            settings,
+            stream_settings: None,
+            tags: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            raw_name: None,
+        };
+
+        let validation = ConfigValidator::validate_server_fields(&config);
+        assert!(validation.is_valid(), "{:?}", validation.errors);
+        let normalized = validation.normalized;
+        assert_eq!(normalized.name, "Test Server");
+        assert_eq!(normalized.server, "2001:db8::1");
+        assert_eq!(
+            normalized.settings["id"],
+            "550e8400-e29b-41d4-a716-446655440000"
+        );
+        assert!(!normalized.settings.contains_key("flow"));
+
+        config.port = 0;
+        config.server = "bad host".to_string();
+        config
+            .settings
+            .insert("id".to_string(), serde_json::json!("not-a-uuid"));
+        config
+            .settings
+            .insert("flow".to_string(), serde_json::json!("xtls-rprx-direct"));
+        let fields: Vec<String> = ConfigValidator::validate_server_fields(&config)
+            .errors
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(fields, ["server", "port", "settings.id", "settings.flow"]);
+
+        config.protocol = ProxyProtocol::Shadowsocks;
+        config.port = 8388;
+        config.server = "example.com".to_string();
+        config.settings.clear();
+        config
+            .settings
+            .insert("method".to_string(), serde_json::json!("AES-256-GCM"));
+        config
+            .settings
+            .insert("password".to_string(), serde_json::json!("secret"));
+        let validation = ConfigValidator::validate_server_fields(&config);
+        assert!(validation.is_valid());
+        assert_eq!(validation.normalized.settings["method"], "aes-256-gcm");
+
+        config
+            .settings
+            .insert("method".to_string(), serde_json::json!("rc4-md5"));
+        let errors = ConfigValidator::validate_server_fields(&config).errors;
+        assert_eq!(errors[0].field, "settings.method");
+    }
+
+    fn paths(diagnostics: &[Diagnostic], severity: Severity) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .filter(|d| d.severity == severity)
+            .map(|d| d.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_diagnose_config() {
+        use crate::config::{Profile, RoutingRuleSet, SystemProxyBehavior};
+
+        assert!(ConfigValidator::diagnose_config(&Config::default()).is_empty());
+
+        let rule_set = RoutingRuleSet {
+            id: "office".to_string(),
+            name: "Office".to_string(),
+            enabled: true,
+            rules: vec![],
+        };
+        let profile = Profile {
+            id: "work".to_string(),
+            name: "Work".to_string(),
+            server_id: None,
+            mode: "pac".to_string(),
+            routing_rule_sets: Some(vec!["streaming".to_string()]),
+            http_port: None,
+            socks_port: None,
+            xray_version: None,
+            core: Default::default(),
+            rate_limit: Default::default(),
+            system_proxy: SystemProxyBehavior::Keep,
+        };
+        let mut config = Config {
+            routing_rules: vec![rule_set.clone(), rule_set],
+            profiles: vec![profile],
+            active_profile: Some("home".to_string()),
+            ..Default::default()
+        };
+        config.proxy.http_port = 1080;
+        config.subscription.timeout = 0;
+
+        let diagnostics = ConfigValidator::diagnose_config(&config);
+        assert_eq!(
+            paths(&diagnostics, Severity::Error),
+            [
+                "proxy.socks_port",
+                "subscription.timeout",
+                "routing_rules[1].id",
+                "profiles[0]"
+            ]
+        );
+        assert_eq!(
+            paths(&diagnostics, Severity::Warning),
+            ["profiles[0].routing_rule_sets", "active_profile"]
+        );
+
+        let result = ConfigValidator::validate_config(&config);
+        assert!(!result.is_valid());
+        assert_eq!(
+            result.errors[1],
+            "subscription.timeout: Subscription timeout cannot be zero"
+        );
+    }
+
+    #[test]
+    fn test_diagnose_proxy_config() {
+        use crate::config::{StreamSettings, TlsSettings, WsSettings};
+
+        let stream = StreamSettings {
+            network: "ws".to_string(),
+            security: "none".to_string(),
+            tls_settings: None,
+            tcp_settings: None,
+            ws_settings: Some(WsSettings {
+                path: "ws".to_string(),
+                headers: HashMap::new(),
+            }),
+            http_settings: None,
+            quic_settings: None,
+            grpc_settings: None,
+            mux: None,
+            fragment: None,
+        };
+        let mut settings = HashMap::new();
+        settings.insert(
+            "id".to_string(),
+            serde_json::json!("not-a-uuid-and-longer-than-thirty-bytes"),
+        );
+        settings.insert("flow".to_string(), serde_json::json!("xtls-rprx-vision"));
+        let mut config = ProxyServerConfig {
+            id: "test".to_string(),
+            name: "Test Server".to_string(),
+            server: "example.com".to_string(),
+            port: 443,
+            protocol: ProxyProtocol::Vless,
+            settings,
+            stream_settings: Some(stream.clone()),
+            tags: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            raw_name: None,
+        };
+
+        let diagnostics = ConfigValidator::diagnose_proxy_config(&config);
+        assert_eq!(
+            paths(&diagnostics, Severity::Error),
+            [
+                "settings.id",
+                "settings.flow",
+                "stream_settings.ws_settings.path"
+            ]
+        );
+
+        // REALITY over TCP
+        config.settings.insert(
+            "id".to_string(),
+            serde_json::json!("b831381d-6324-4d53-ad4f-8cda48b30811"),
+        );
+        config.settings.insert(
+            "publicKey".to_string(),
+            serde_json::json!("Z84J2IelR9ch3k8VtlVhhs5ycBUlXA7wHBWcBrjqnAw"),
+        );
+        config
+            .settings
+            .insert("shortId".to_string(), serde_json::json!("6ba85179e30d4fc2"));
+        config.stream_settings = Some(StreamSettings {
+            network: "tcp".to_string(),
+            security: "reality".to_string(),
+            tls_settings: Some(TlsSettings {
+                server_name: Some("www.microsoft.com".to_string()),
+                allow_insecure: false,
+                alpn: vec![],
+                fingerprint: Some("chrome".to_string()),
+            }),
+            ws_settings: None,
+            ..stream
+        });
+        assert!(ConfigValidator::diagnose_proxy_config(&config).is_empty());
+
+        // Xray maps short custom IDs to a UUID
+        config
+            .settings
+            .insert("id".to_string(), serde_json::json!("my-custom-id"));
+        assert!(ConfigValidator::diagnose_proxy_config(&config).is_empty());
+
+        config
+            .settings
+            .insert("publicKey".to_string(), serde_json::json!("short"));
+        config
+            .settings
+            .insert("shortId".to_string(), serde_json::json!("xyz"));
+        if let Some(stream) = config.stream_settings.as_mut() {
+            stream.tls_settings = None;
+        }
+        let diagnostics = ConfigValidator::diagnose_proxy_config(&config);
+        assert_eq!(
+            paths(&diagnostics, Severity::Error),
+            [
+                "stream_settings.tls_settings.server_name",
+                "settings.publicKey",
+                "settings.shortId"
+            ]
+        );
+
+        // Stream errors are reported as field errors too
+        let errors: Vec<String> = ConfigValidator::validate_server_fields(&config)
+            .errors
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert!(errors.contains(&"settings.publicKey".to_string()));
+    }
 }
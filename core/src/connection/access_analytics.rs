@@ -0,0 +1,465 @@
+//! Visited-domain analytics
+//!
+//! Xray access log lines are turned into [`AccessLogRecord`]s, kept in a
+//! ring buffer and optionally persisted to SQLite, and summarized into the
+//! most visited domains, the most blocked domains and the split between
+//! proxied, direct and blocked connections.
+
+use super::active_connections::parse_access_log;
+use crate::config::routing::{OUTBOUND_BLOCK, OUTBOUND_DIRECT, OUTBOUND_PROXY};
+use crate::error::StorageResult;
+use crate::utils::preflight::check_writable;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool},
+    Row,
+};
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{debug, info};
+
+/// Records kept in memory
+pub const DEFAULT_BUFFER_CAPACITY: usize = 5000;
+
+/// Connection to a domain seen in the access log
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessLogRecord {
+    /// Time the connection was logged
+    pub timestamp: DateTime<Utc>,
+    /// Target domain or IP, without port
+    pub domain: String,
+    /// Outbound tag the connection was routed to
+    pub outbound_tag: String,
+    /// Whether Xray accepted the connection
+    pub accepted: bool,
+}
+
+impl AccessLogRecord {
+    /// Parse an Xray access log message seen at `now`
+    pub fn parse(message: &str, now: DateTime<Utc>) -> Option<Self> {
+        let record = parse_access_log(message)?;
+        Some(Self {
+            timestamp: now,
+            domain: host_of(&record.destination).to_ascii_lowercase(),
+            outbound_tag: record.outbound_tag,
+            accepted: record.accepted,
+        })
+    }
+
+    /// Whether the connection was blocked, by rejection or the block outbound
+    pub fn is_blocked(&self) -> bool {
+        !self.accepted || self.outbound_tag == OUTBOUND_BLOCK
+    }
+}
+
+/// Host part of `host:port` or `[ipv6]:port`
+fn host_of(destination: &str) -> &str {
+    let host = destination
+        .rsplit_once(':')
+        .map_or(destination, |(host, _)| host);
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Connections to one domain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainCount {
+    /// Domain or IP
+    pub domain: String,
+    /// Number of connections
+    pub count: u64,
+    /// Time of the latest connection
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Connections by route
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteSplit {
+    /// Routed through the proxy
+    pub proxy: u64,
+    /// Routed direct
+    pub direct: u64,
+    /// Blocked
+    pub blocked: u64,
+    /// Routed to any other outbound (e.g. a balancer or chain)
+    pub other: u64,
+}
+
+impl RouteSplit {
+    /// Count one record
+    fn add(&mut self, record: &AccessLogRecord) {
+        if record.is_blocked() {
+            self.blocked += 1;
+        } else if record.outbound_tag == OUTBOUND_PROXY {
+            self.proxy += 1;
+        } else if record.outbound_tag == OUTBOUND_DIRECT {
+            self.direct += 1;
+        } else {
+            self.other += 1;
+        }
+    }
+}
+
+/// Ring buffer of recent access log records
+#[derive(Debug)]
+pub struct AccessAnalytics {
+    /// Recent records, oldest first
+    records: VecDeque<AccessLogRecord>,
+    /// Most records kept
+    capacity: usize,
+    /// Records not yet written to storage
+    pending: Vec<AccessLogRecord>,
+    /// Whether records are queued for storage
+    persist: bool,
+}
+
+impl Default for AccessAnalytics {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUFFER_CAPACITY)
+    }
+}
+
+impl AccessAnalytics {
+    /// Create a buffer keeping the latest `capacity` records
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::with_capacity(capacity.min(DEFAULT_BUFFER_CAPACITY)),
+            capacity,
+            pending: Vec::new(),
+            persist: false,
+        }
+    }
+
+    /// Queue new records for [`Self::take_pending`]
+    pub fn set_persist(&mut self, persist: bool) {
+        self.persist = persist;
+        if !persist {
+            self.pending.clear();
+        }
+    }
+
+    /// Feed an Xray log message seen at `now`
+    pub fn record_log(&mut self, message: &str, now: DateTime<Utc>) {
+        if let Some(record) = AccessLogRecord::parse(message, now) {
+            self.push(record);
+        }
+    }
+
+    /// Add a record, dropping the oldest if the buffer is full
+    pub fn push(&mut self, record: AccessLogRecord) {
+        if self.persist {
+            self.pending.push(record.clone());
+        }
+        self.records.push_back(record);
+        while self.records.len() > self.capacity {
+            self.records.pop_front();
+        }
+    }
+
+    /// Take the records queued for storage
+    pub fn take_pending(&mut self) -> Vec<AccessLogRecord> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Records logged at or after `since`
+    pub fn records_since(&self, since: DateTime<Utc>) -> impl Iterator<Item = &AccessLogRecord> {
+        self.records.iter().filter(move |r| r.timestamp >= since)
+    }
+
+    /// Most visited domains since `since`, most connections first
+    pub fn top_domains(&self, since: DateTime<Utc>, limit: usize) -> Vec<DomainCount> {
+        top_domains(self.records_since(since), limit)
+    }
+
+    /// Most blocked domains since `since`, most connections first
+    pub fn blocked_domains(&self, since: DateTime<Utc>, limit: usize) -> Vec<DomainCount> {
+        blocked_domains(self.records_since(since), limit)
+    }
+
+    /// Connections by route since `since`
+    pub fn route_split(&self, since: DateTime<Utc>) -> RouteSplit {
+        route_split(self.records_since(since))
+    }
+
+    /// Forget all records
+    pub fn clear(&mut self) {
+        self.records.clear();
+        self.pending.clear();
+    }
+}
+
+/// Most visited domains among `records`, most connections first
+pub fn top_domains<'a>(
+    records: impl IntoIterator<Item = &'a AccessLogRecord>,
+    limit: usize,
+) -> Vec<DomainCount> {
+    count_domains(records.into_iter().filter(|r| !r.is_blocked()), limit)
+}
+
+/// Most blocked domains among `records`, most connections first
+pub fn blocked_domains<'a>(
+    records: impl IntoIterator<Item = &'a AccessLogRecord>,
+    limit: usize,
+) -> Vec<DomainCount> {
+    count_domains(records.into_iter().filter(|r| r.is_blocked()), limit)
+}
+
+/// Connections by route among `records`
+pub fn route_split<'a>(records: impl IntoIterator<Item = &'a AccessLogRecord>) -> RouteSplit {
+    let mut split = RouteSplit::default();
+    for record in records {
+        split.add(record);
+    }
+    split
+}
+
+/// Count records per domain, most connections first
+fn count_domains<'a>(
+    records: impl Iterator<Item = &'a AccessLogRecord>,
+    limit: usize,
+) -> Vec<DomainCount> {
+    let mut counts: HashMap<&str, DomainCount> = HashMap::new();
+    for record in records {
+        let entry = counts
+            .entry(record.domain.as_str())
+            .or_insert_with(|| DomainCount {
+                domain: record.domain.clone(),
+                count: 0,
+                last_seen: record.timestamp,
+            });
+        entry.count += 1;
+        entry.last_seen = entry.last_seen.max(record.timestamp);
+    }
+
+    let mut counts: Vec<DomainCount> = counts.into_values().collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.domain.cmp(&b.domain)));
+    counts.truncate(limit);
+    counts
+}
+
+/// SQLite persistence of access log records
+pub struct AccessLogStorage {
+    /// SQLite connection pool
+    pool: SqlitePool,
+}
+
+impl AccessLogStorage {
+    /// Open the database at `db_path`, creating it if missing
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> StorageResult<Self> {
+        let path = db_path.as_ref();
+        info!("Opening access log database: {}", path.display());
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            check_writable(parent)?;
+        }
+
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        let storage = Self { pool };
+        storage.init_tables().await?;
+        Ok(storage)
+    }
+
+    /// Create an in-memory storage (for testing)
+    pub async fn new_in_memory() -> StorageResult<Self> {
+        let pool = SqlitePool::connect("sqlite::memory:").await?;
+
+        let storage = Self { pool };
+        storage.init_tables().await?;
+        Ok(storage)
+    }
+
+    /// Initialize database tables
+    async fn init_tables(&self) -> StorageResult<()> {
+        debug!("Initializing access log tables");
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS access_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                domain TEXT NOT NULL,
+                outbound_tag TEXT NOT NULL,
+                accepted INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_access_log_timestamp ON access_log(timestamp)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Append records
+    pub async fn append(&self, records: &[AccessLogRecord]) -> StorageResult<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        for record in records {
+            sqlx::query(
+                "INSERT INTO access_log (timestamp, domain, outbound_tag, accepted) \
+                 VALUES (?, ?, ?, ?)",
+            )
+            .bind(record.timestamp.to_rfc3339())
+            .bind(&record.domain)
+            .bind(&record.outbound_tag)
+            .bind(record.accepted)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Load records logged at or after `since`, oldest first
+    pub async fn load_since(&self, since: DateTime<Utc>) -> StorageResult<Vec<AccessLogRecord>> {
+        let rows = sqlx::query(
+            "SELECT timestamp, domain, outbound_tag, accepted FROM access_log \
+             WHERE timestamp >= ? ORDER BY id",
+        )
+        .bind(since.to_rfc3339())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                let timestamp: String = row.get("timestamp");
+                Some(AccessLogRecord {
+                    timestamp: DateTime::parse_from_rfc3339(&timestamp)
+                        .ok()?
+                        .with_timezone(&Utc),
+                    domain: row.get("domain"),
+                    outbound_tag: row.get("outbound_tag"),
+                    accepted: row.get("accepted"),
+                })
+            })
+            .collect())
+    }
+
+    /// Delete records logged before `before`, returning how many were deleted
+    pub async fn prune(&self, before: DateTime<Utc>) -> StorageResult<u64> {
+        let deleted = sqlx::query("DELETE FROM access_log WHERE timestamp < ?")
+            .bind(before.to_rfc3339())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        if deleted > 0 {
+            info!("Pruned {} access log records", deleted);
+        }
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn analytics_with_log(now: DateTime<Utc>) -> AccessAnalytics {
+        let mut analytics = AccessAnalytics::new(100);
+        for line in [
+            "from 127.0.0.1:1 accepted tcp:www.Google.com:443 [http-in -> proxy]",
+            "from 127.0.0.1:2 accepted tcp:www.google.com:443 [http-in -> proxy]",
+            "from 127.0.0.1:3 accepted tcp:baidu.com:443 [http-in -> direct]",
+            "from 127.0.0.1:4 accepted tcp:ads.example.com:443 [http-in -> block]",
+            "from 127.0.0.1:5 rejected tcp:ads.example.com:80 [http-in -> proxy]",
+            "from 127.0.0.1:6 accepted udp:[2001:db8::1]:53 [http-in -> balancer]",
+            "[Warning] unrelated message",
+        ] {
+            analytics.record_log(line, now);
+        }
+        analytics
+    }
+
+    #[test]
+    fn test_queries() {
+        let now = Utc::now();
+        let analytics = analytics_with_log(now);
+        let since = now - Duration::hours(1);
+
+        let top = analytics.top_domains(since, 10);
+        assert_eq!(top[0].domain, "www.google.com");
+        assert_eq!(top[0].count, 2);
+        assert_eq!(top.len(), 3);
+        assert!(top.iter().any(|d| d.domain == "2001:db8::1"));
+
+        let blocked = analytics.blocked_domains(since, 10);
+        assert_eq!(blocked.len(), 1);
+        assert_eq!(blocked[0].domain, "ads.example.com");
+        assert_eq!(blocked[0].count, 2);
+
+        assert_eq!(
+            analytics.route_split(since),
+            RouteSplit {
+                proxy: 2,
+                direct: 1,
+                blocked: 2,
+                other: 1,
+            }
+        );
+        assert_eq!(
+            analytics.route_split(now + Duration::seconds(1)),
+            RouteSplit::default()
+        );
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let now = Utc::now();
+        let mut analytics = AccessAnalytics::new(2);
+        analytics.set_persist(true);
+        for port in 1..=3 {
+            analytics.record_log(
+                &format!(
+                    "from 127.0.0.1:{} accepted tcp:a{}.com:443 [proxy]",
+                    port, port
+                ),
+                now,
+            );
+        }
+
+        let domains: Vec<String> = analytics
+            .records_since(now)
+            .map(|r| r.domain.clone())
+            .collect();
+        assert_eq!(domains, vec!["a2.com", "a3.com"]);
+        // Everything is queued for storage, even if dropped from memory
+        assert_eq!(analytics.take_pending().len(), 3);
+        assert!(analytics.take_pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_storage() {
+        let now = Utc::now();
+        let storage = AccessLogStorage::new_in_memory().await.unwrap();
+        let mut analytics = analytics_with_log(now);
+        let records: Vec<AccessLogRecord> = analytics.records_since(now).cloned().collect();
+        storage.append(&records).await.unwrap();
+
+        let mut old = records[0].clone();
+        old.timestamp = now - Duration::days(40);
+        storage.append(&[old]).await.unwrap();
+
+        let loaded = storage.load_since(now - Duration::days(1)).await.unwrap();
+        assert_eq!(loaded, records);
+
+        assert_eq!(storage.prune(now - Duration::days(30)).await.unwrap(), 1);
+
+        // Queries work the same over loaded records
+        let loaded = storage.load_since(now - Duration::days(1)).await.unwrap();
+        assert_eq!(route_split(&loaded), analytics.route_split(now));
+        analytics.clear();
+        assert_eq!(analytics.route_split(now), RouteSplit::default());
+    }
+}
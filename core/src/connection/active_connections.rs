@@ -0,0 +1,276 @@
+//! Active connections
+//!
+//! Xray has no API listing its connections, but writes an access log line
+//! for every connection it accepts:
+//!
+//! ```text
+//! 2024/01/01 12:00:00.123456 from 127.0.0.1:54321 accepted tcp:www.google.com:443 [http-in -> proxy]
+//! ```
+//!
+//! The tracker turns these lines into a table of recent connections. The
+//! access log reports neither the end of a connection nor its traffic, so a
+//! connection is listed until it has not been seen for the idle timeout,
+//! and byte counts are not available per connection.
+
+use crate::xray::logs::is_log_time;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Time after which a connection that was not seen again is dropped
+pub const DEFAULT_IDLE_TIMEOUT_SECS: i64 = 120;
+
+/// Most connections kept; the least recently seen are dropped first
+pub const MAX_TRACKED_CONNECTIONS: usize = 1000;
+
+/// Connection parsed from an access log line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessRecord {
+    /// Client address (ip:port)
+    pub source: String,
+    /// Network (tcp / udp)
+    pub network: String,
+    /// Destination (host:port)
+    pub destination: String,
+    /// Inbound tag, if logged
+    pub inbound_tag: Option<String>,
+    /// Outbound tag the connection was routed to
+    pub outbound_tag: String,
+    /// Whether Xray accepted the connection (rejected ones were blocked)
+    pub accepted: bool,
+}
+
+/// Parse an Xray access log message
+///
+/// Accepts the message with or without the leading timestamp; returns
+/// `None` for lines that are not access log entries.
+pub fn parse_access_log(message: &str) -> Option<AccessRecord> {
+    let mut words = message.split_whitespace().peekable();
+    // Skip the timestamp and the "from" keyword of newer Xray versions
+    while let Some(word) = words.peek() {
+        if (word.contains('/') && !word.contains(':')) || *word == "from" || is_log_time(word) {
+            words.next();
+        } else {
+            break;
+        }
+    }
+
+    let source = words.next()?.to_string();
+    let accepted = match words.next()? {
+        "accepted" => true,
+        "rejected" => false,
+        _ => return None,
+    };
+    let (network, destination) = words.next()?.split_once(':')?;
+
+    // "[inbound -> outbound]", "[inbound >> outbound]" or "[outbound]"
+    let rest: Vec<&str> = words.collect();
+    let rest = rest.join(" ");
+    let tags = rest.strip_prefix('[')?.split(']').next()?;
+    let (inbound_tag, outbound_tag) = match tags.split_once(" -> ").or(tags.split_once(" >> ")) {
+        Some((inbound, outbound)) => (Some(inbound.trim().to_string()), outbound.trim()),
+        None => (None, tags.trim()),
+    };
+
+    Some(AccessRecord {
+        source,
+        network: network.to_string(),
+        destination: destination.to_string(),
+        inbound_tag,
+        outbound_tag: outbound_tag.to_string(),
+        accepted,
+    })
+}
+
+/// Connection listed in the active connections table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActiveConnection {
+    /// Tracker-assigned ID
+    pub id: u64,
+    /// Client address (ip:port)
+    pub source: String,
+    /// Network (tcp / udp)
+    pub network: String,
+    /// Destination (host:port)
+    pub destination: String,
+    /// Inbound tag, if logged
+    pub inbound_tag: Option<String>,
+    /// Outbound tag the connection was routed to
+    pub outbound_tag: String,
+    /// Time the connection was accepted
+    pub started_at: DateTime<Utc>,
+    /// Time the connection was last seen in the log
+    pub last_seen: DateTime<Utc>,
+}
+
+/// Table of recent connections fed from Xray access logs
+#[derive(Debug)]
+pub struct ConnectionTracker {
+    /// Connections keyed by source and destination
+    connections: HashMap<(String, String), ActiveConnection>,
+    /// Next connection ID
+    next_id: u64,
+    /// Time after which an unseen connection is dropped
+    idle_timeout: Duration,
+}
+
+impl Default for ConnectionTracker {
+    fn default() -> Self {
+        Self::new(Duration::seconds(DEFAULT_IDLE_TIMEOUT_SECS))
+    }
+}
+
+impl ConnectionTracker {
+    /// Create a tracker dropping connections unseen for `idle_timeout`
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            connections: HashMap::new(),
+            next_id: 1,
+            idle_timeout,
+        }
+    }
+
+    /// Feed an Xray log message seen at `now`
+    pub fn record_log(&mut self, message: &str, now: DateTime<Utc>) {
+        let Some(record) = parse_access_log(message) else {
+            return;
+        };
+        if !record.accepted {
+            return;
+        }
+
+        let key = (record.source.clone(), record.destination.clone());
+        if let Some(connection) = self.connections.get_mut(&key) {
+            connection.last_seen = now;
+            connection.outbound_tag = record.outbound_tag;
+            return;
+        }
+
+        if self.connections.len() >= MAX_TRACKED_CONNECTIONS {
+            self.expire(now);
+        }
+        if self.connections.len() >= MAX_TRACKED_CONNECTIONS {
+            if let Some(oldest) = self
+                .connections
+                .iter()
+                .min_by_key(|(_, c)| c.last_seen)
+                .map(|(key, _)| key.clone())
+            {
+                self.connections.remove(&oldest);
+            }
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.connections.insert(
+            key,
+            ActiveConnection {
+                id,
+                source: record.source,
+                network: record.network,
+                destination: record.destination,
+                inbound_tag: record.inbound_tag,
+                outbound_tag: record.outbound_tag,
+                started_at: now,
+                last_seen: now,
+            },
+        );
+    }
+
+    /// Connections seen within the idle timeout, newest first
+    pub fn list(&mut self, now: DateTime<Utc>) -> Vec<ActiveConnection> {
+        self.expire(now);
+        let mut connections: Vec<ActiveConnection> = self.connections.values().cloned().collect();
+        connections.sort_by(|a, b| b.started_at.cmp(&a.started_at).then(b.id.cmp(&a.id)));
+        connections
+    }
+
+    /// Forget all connections, e.g. after Xray was stopped
+    pub fn clear(&mut self) {
+        self.connections.clear();
+    }
+
+    /// Drop connections not seen within the idle timeout
+    fn expire(&mut self, now: DateTime<Utc>) {
+        let idle_timeout = self.idle_timeout;
+        self.connections
+            .retain(|_, connection| now - connection.last_seen < idle_timeout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_access_log() {
+        let record = parse_access_log(
+            "2024/01/01 12:00:00.123456 from 127.0.0.1:54321 accepted tcp:www.google.com:443 [http-in -> proxy]",
+        )
+        .unwrap();
+        assert_eq!(
+            record,
+            AccessRecord {
+                source: "127.0.0.1:54321".to_string(),
+                network: "tcp".to_string(),
+                destination: "www.google.com:443".to_string(),
+                inbound_tag: Some("http-in".to_string()),
+                outbound_tag: "proxy".to_string(),
+                accepted: true,
+            }
+        );
+
+        // Older format, message only, without inbound tag
+        let record = parse_access_log("127.0.0.1:5000 accepted udp:8.8.8.8:53 [direct]").unwrap();
+        assert_eq!(record.network, "udp");
+        assert_eq!(record.inbound_tag, None);
+        assert_eq!(record.outbound_tag, "direct");
+
+        let record = parse_access_log(
+            "from 127.0.0.1:5001 rejected tcp:ads.example.com:443 [socks-in >> block] email: a@b",
+        )
+        .unwrap();
+        assert!(!record.accepted);
+        assert_eq!(record.outbound_tag, "block");
+
+        assert!(parse_access_log("proxy/freedom: failed to open connection to x").is_none());
+        assert!(parse_access_log("Xray 1.8.7 started").is_none());
+    }
+
+    #[test]
+    fn test_tracker() {
+        let mut tracker = ConnectionTracker::new(Duration::seconds(60));
+        let start = Utc::now();
+
+        tracker.record_log(
+            "from 127.0.0.1:1000 accepted tcp:a.com:443 [http-in -> proxy]",
+            start,
+        );
+        tracker.record_log(
+            "from 127.0.0.1:1001 accepted tcp:b.com:443 [http-in -> direct]",
+            start + Duration::seconds(1),
+        );
+        tracker.record_log(
+            "from 127.0.0.1:1002 rejected tcp:ads.com:443 [http-in -> block]",
+            start,
+        );
+
+        let connections = tracker.list(start + Duration::seconds(2));
+        assert_eq!(connections.len(), 2);
+        assert_eq!(connections[0].destination, "b.com:443");
+        assert_eq!(connections[1].id, 1);
+
+        // Seen again: kept past the original timeout
+        tracker.record_log(
+            "from 127.0.0.1:1000 accepted tcp:a.com:443 [http-in -> proxy]",
+            start + Duration::seconds(50),
+        );
+        let connections = tracker.list(start + Duration::seconds(70));
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].id, 1);
+        assert_eq!(connections[0].started_at, start);
+
+        tracker.clear();
+        assert!(tracker.list(start).is_empty());
+    }
+}
@@ -0,0 +1,617 @@
+//! Connection diagnostics
+//!
+//! Runs an ordered checklist against a server, from the local setup (Xray
+//! binary, configuration, ports) through the network path (TCP and TLS
+//! handshakes) to requests through the proxy. The first failing check is
+//! reported as the failure stage together with a remediation hint; the
+//! checks after it are skipped, since they depend on it.
+//!
+//! If the manager is disconnected, a temporary connection to the server is
+//! made for the checks and closed afterwards. While connected to the same
+//! server, the running connection is examined instead. While connected to
+//! another server, the checks needing the proxy are skipped so the active
+//! connection is left alone.
+
+use super::exit_info::{self, ExitInfoProvider};
+use super::readiness::{self, DEFAULT_CHECK_URL, DEFAULT_READINESS_TIMEOUT};
+use super::ConnectionManager;
+use crate::config::validator::ConfigValidator;
+use crate::config::{PortConflictPolicy, ProxyServerConfig};
+use crate::xray::InboundConfig;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// Timeout of each network check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Proxy mode used for temporary connections, so every request is proxied
+const DIAGNOSTICS_MODE: &str = "global";
+
+/// Checks, in the order they run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DiagnosticStage {
+    /// The Xray binary is installed
+    Binary,
+    /// The server configuration is valid
+    Config,
+    /// The local inbound ports are free
+    Ports,
+    /// Xray starts with the configuration
+    Process,
+    /// The local inbound accepts connections
+    Inbound,
+    /// The server accepts TCP connections
+    TcpHandshake,
+    /// The server completes a TLS handshake for the configured SNI
+    TlsHandshake,
+    /// A request through the proxy succeeds, so the server accepted the
+    /// credentials
+    ProxyRequest,
+    /// The exit IP can be looked up through the proxy
+    ExternalIp,
+}
+
+impl DiagnosticStage {
+    /// All stages, in the order they run
+    pub const ALL: [Self; 9] = [
+        Self::Binary,
+        Self::Config,
+        Self::Ports,
+        Self::Process,
+        Self::Inbound,
+        Self::TcpHandshake,
+        Self::TlsHandshake,
+        Self::ProxyRequest,
+        Self::ExternalIp,
+    ];
+
+    /// Identifier used in the bridge and CLI output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Binary => "binary",
+            Self::Config => "config",
+            Self::Ports => "ports",
+            Self::Process => "process",
+            Self::Inbound => "inbound",
+            Self::TcpHandshake => "tcp_handshake",
+            Self::TlsHandshake => "tls_handshake",
+            Self::ProxyRequest => "proxy_request",
+            Self::ExternalIp => "external_ip",
+        }
+    }
+
+    /// Display name
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Binary => "Xray binary present",
+            Self::Config => "Configuration valid",
+            Self::Ports => "Local ports free",
+            Self::Process => "Xray running",
+            Self::Inbound => "Local inbound reachable",
+            Self::TcpHandshake => "TCP handshake with server",
+            Self::TlsHandshake => "TLS handshake with server",
+            Self::ProxyRequest => "Authenticated request through proxy",
+            Self::ExternalIp => "External IP lookup",
+        }
+    }
+
+    /// What to try when this check fails
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::Binary => {
+                "Reinstall V8Ray or place the Xray binary in the bin directory next to it"
+            }
+            Self::Config => "Edit the server or update its subscription to fix the listed fields",
+            Self::Ports => {
+                "Close the program using the port, change the local ports in the inbound \
+                 settings, or let V8Ray reassign conflicting ports"
+            }
+            Self::Process => {
+                "Check the Xray log for the startup error; the server may use settings this \
+                 Xray version does not support"
+            }
+            Self::Inbound => "Security software or a firewall may block local connections",
+            Self::TcpHandshake => {
+                "The server cannot be reached: check the address and port, your network, and \
+                 whether the server is down or blocked"
+            }
+            Self::TlsHandshake => {
+                "Check the SNI and certificate settings; the server may expect another domain \
+                 or use a self-signed certificate"
+            }
+            Self::ProxyRequest => {
+                "The server refused the proxy: check the user ID or password, encryption, \
+                 transport and path settings, and that the system clock is correct"
+            }
+            Self::ExternalIp => {
+                "The proxy works, but the IP lookup service may be blocked by the server"
+            }
+        }
+    }
+
+    /// Whether the check needs the Xray process of the diagnosed server
+    fn needs_process(&self) -> bool {
+        matches!(
+            self,
+            Self::Process | Self::Inbound | Self::ProxyRequest | Self::ExternalIp
+        )
+    }
+}
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CheckStatus {
+    /// The check succeeded
+    Passed,
+    /// The check failed
+    Failed,
+    /// The check did not run
+    Skipped,
+}
+
+impl CheckStatus {
+    /// Lowercase name used in the bridge and CLI output
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Passed => "passed",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// Result of a single check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    /// Checked stage
+    pub stage: DiagnosticStage,
+    /// Outcome
+    pub status: CheckStatus,
+    /// What was found
+    pub message: String,
+    /// Time the check took
+    pub duration: Duration,
+    /// What to try, for failed checks
+    pub hint: Option<String>,
+}
+
+/// Result of a diagnostics run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticReport {
+    /// Diagnosed server
+    pub server_id: String,
+    /// Checks in the order they ran
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticReport {
+    /// First failed stage, if any
+    pub fn failed_stage(&self) -> Option<DiagnosticStage> {
+        self.checks
+            .iter()
+            .find(|check| check.status == CheckStatus::Failed)
+            .map(|check| check.stage)
+    }
+
+    /// Whether no check failed
+    pub fn passed(&self) -> bool {
+        self.failed_stage().is_none()
+    }
+}
+
+/// Outcome of running one stage
+enum Outcome {
+    Passed(String),
+    Skipped(String),
+    Failed(String),
+}
+
+/// State shared by the stages of one run
+struct Run<'a> {
+    manager: &'a ConnectionManager,
+    config: ProxyServerConfig,
+    /// Whether a temporary connection was made for the run
+    connected_here: bool,
+    /// Whether the manager is connected to another server
+    other_server: bool,
+    /// HTTP inbound of the running Xray, once known
+    inbound: Option<InboundConfig>,
+}
+
+/// Run the diagnostics checklist for `config`
+pub async fn run_diagnostics(
+    manager: &ConnectionManager,
+    config: ProxyServerConfig,
+) -> DiagnosticReport {
+    info!("Running diagnostics for {}", config.name);
+    let current = match manager.get_xray().is_running().await {
+        true => manager.get_current_config().await,
+        false => None,
+    };
+    let other_server = current
+        .as_ref()
+        .is_some_and(|current| current.id != config.id);
+
+    let mut run = Run {
+        manager,
+        config,
+        connected_here: false,
+        other_server,
+        inbound: None,
+    };
+    if current.is_some() && !other_server {
+        run.inbound = run.http_inbound().await;
+    }
+
+    let mut checks = Vec::new();
+    let mut failed = false;
+    for stage in DiagnosticStage::ALL {
+        let start = Instant::now();
+        let outcome = if failed {
+            Outcome::Skipped("Skipped after an earlier failure".to_string())
+        } else if run.other_server && stage.needs_process() {
+            Outcome::Skipped(
+                "Connected to another server; disconnect to run this check".to_string(),
+            )
+        } else {
+            run.stage(stage).await
+        };
+
+        let (status, message) = match outcome {
+            Outcome::Passed(message) => (CheckStatus::Passed, message),
+            Outcome::Skipped(message) => (CheckStatus::Skipped, message),
+            Outcome::Failed(message) => {
+                warn!("Diagnostics failed at {}: {}", stage.as_str(), message);
+                failed = true;
+                (CheckStatus::Failed, message)
+            }
+        };
+        checks.push(DiagnosticCheck {
+            stage,
+            status,
+            message,
+            duration: start.elapsed(),
+            hint: (status == CheckStatus::Failed).then(|| stage.hint().to_string()),
+        });
+    }
+
+    if run.connected_here {
+        if let Err(e) = manager.disconnect().await {
+            warn!("Failed to close diagnostics connection: {}", e);
+        }
+    }
+    DiagnosticReport {
+        server_id: run.config.id,
+        checks,
+    }
+}
+
+impl Run<'_> {
+    async fn stage(&mut self, stage: DiagnosticStage) -> Outcome {
+        match stage {
+            DiagnosticStage::Binary => self.check_binary(),
+            DiagnosticStage::Config => self.check_config(),
+            DiagnosticStage::Ports => self.check_ports(),
+            DiagnosticStage::Process => self.check_process().await,
+            DiagnosticStage::Inbound => self.check_inbound().await,
+            DiagnosticStage::TcpHandshake => tcp_handshake(&self.config.server, self.config.port)
+                .await
+                .into(),
+            DiagnosticStage::TlsHandshake => self.check_tls().await,
+            DiagnosticStage::ProxyRequest => self.check_proxy_request().await,
+            DiagnosticStage::ExternalIp => self.check_external_ip().await,
+        }
+    }
+
+    /// Whether the diagnosed server's Xray is already running
+    fn running(&self) -> bool {
+        self.inbound.is_some()
+    }
+
+    async fn http_inbound(&self) -> Option<InboundConfig> {
+        self.manager
+            .get_xray()
+            .running_config()
+            .await?
+            .inbounds
+            .into_iter()
+            .find(|inbound| inbound.protocol == "http")
+    }
+
+    fn check_binary(&self) -> Outcome {
+        match self.manager.get_xray().find_xray_binary() {
+            Ok(path) => Outcome::Passed(format!("Found {}", path)),
+            Err(e) => Outcome::Failed(e.to_string()),
+        }
+    }
+
+    fn check_config(&self) -> Outcome {
+        let result = ConfigValidator::validate_proxy_config(&self.config);
+        if result.is_valid() {
+            Outcome::Passed(match result.warnings.is_empty() {
+                true => "No problems found".to_string(),
+                false => result.warnings.join("; "),
+            })
+        } else {
+            Outcome::Failed(result.errors.join("; "))
+        }
+    }
+
+    fn check_ports(&self) -> Outcome {
+        if self.running() {
+            return Outcome::Passed("Used by the current connection".to_string());
+        }
+        if self.other_server {
+            return Outcome::Skipped("Used by the current connection".to_string());
+        }
+
+        let xray = self.manager.get_xray();
+        let xray_config = xray.generate_config_with_mode(&self.config, DIAGNOSTICS_MODE);
+        let busy: Vec<String> = xray_config
+            .inbounds
+            .iter()
+            .filter(|inbound| {
+                inbound.port != 0
+                    && !crate::utils::ports::is_port_available(
+                        inbound.listen.as_deref(),
+                        inbound.port,
+                    )
+            })
+            .map(|inbound| format!("{} port {}", inbound.protocol, inbound.port))
+            .collect();
+        match (busy.is_empty(), xray.port_conflict_policy()) {
+            (true, _) => Outcome::Passed("All inbound ports are free".to_string()),
+            (false, PortConflictPolicy::Reassign) => {
+                Outcome::Passed(format!("In use, will be reassigned: {}", busy.join(", ")))
+            }
+            (false, PortConflictPolicy::Fail) => {
+                Outcome::Failed(format!("In use: {}", busy.join(", ")))
+            }
+        }
+    }
+
+    async fn check_process(&mut self) -> Outcome {
+        if self.running() {
+            return Outcome::Passed("Running for the current connection".to_string());
+        }
+        if let Err(e) = self
+            .manager
+            .connect_with_config_and_mode(self.config.clone(), DIAGNOSTICS_MODE)
+            .await
+        {
+            return Outcome::Failed(e.to_string());
+        }
+        self.connected_here = true;
+        self.inbound = self.http_inbound().await;
+        match &self.inbound {
+            Some(_) => Outcome::Passed("Started".to_string()),
+            None => Outcome::Failed("Started without an HTTP inbound".to_string()),
+        }
+    }
+
+    async fn check_inbound(&self) -> Outcome {
+        let Some(inbound) = &self.inbound else {
+            return Outcome::Failed("No HTTP inbound".to_string());
+        };
+        let deadline = Instant::now() + DEFAULT_READINESS_TIMEOUT;
+        match readiness::wait_for_inbound(inbound.port, deadline).await {
+            Ok(elapsed) => Outcome::Passed(format!(
+                "Port {} accepted a connection after {} ms",
+                inbound.port,
+                elapsed.as_millis()
+            )),
+            Err(e) => Outcome::Failed(format!("Port {}: {}", inbound.port, e)),
+        }
+    }
+
+    async fn check_tls(&self) -> Outcome {
+        let Some(stream) = &self.config.stream_settings else {
+            return Outcome::Skipped("The server does not use TLS".to_string());
+        };
+        match stream.security.as_str() {
+            "tls" => {
+                let (sni, insecure) = match &stream.tls_settings {
+                    Some(tls) => (tls.server_name.as_deref(), tls.allow_insecure),
+                    None => (None, false),
+                };
+                let sni = sni
+                    .filter(|sni| !sni.is_empty())
+                    .unwrap_or(&self.config.server);
+                tls_handshake(&self.config.server, self.config.port, sni, insecure)
+                    .await
+                    .into()
+            }
+            "reality" => Outcome::Skipped(
+                "REALITY servers cannot be checked with a standard TLS handshake".to_string(),
+            ),
+            _ => Outcome::Skipped("The server does not use TLS".to_string()),
+        }
+    }
+
+    async fn check_proxy_request(&self) -> Outcome {
+        let Some(inbound) = &self.inbound else {
+            return Outcome::Failed("No HTTP inbound".to_string());
+        };
+        match readiness::check_connectivity(inbound, DEFAULT_CHECK_URL, CHECK_TIMEOUT).await {
+            Ok(elapsed) => Outcome::Passed(format!(
+                "{} answered in {} ms",
+                DEFAULT_CHECK_URL,
+                elapsed.as_millis()
+            )),
+            Err(e) => Outcome::Failed(e.to_string()),
+        }
+    }
+
+    async fn check_external_ip(&self) -> Outcome {
+        let Some(inbound) = &self.inbound else {
+            return Outcome::Failed("No HTTP inbound".to_string());
+        };
+        let client = readiness::proxy_for_inbound(inbound).and_then(|proxy| {
+            crate::version::http_client_builder()
+                .proxy(proxy)
+                .timeout(CHECK_TIMEOUT)
+                .build()
+        });
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => return Outcome::Failed(e.to_string()),
+        };
+        match exit_info::lookup(&client, ExitInfoProvider::default()).await {
+            Ok(info) => {
+                let location = [info.country_code, info.organization]
+                    .into_iter()
+                    .flatten()
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Outcome::Passed(if location.is_empty() {
+                    format!("Exit IP {}", info.ip)
+                } else {
+                    format!("Exit IP {} ({})", info.ip, location)
+                })
+            }
+            Err(e) => Outcome::Failed(e.to_string()),
+        }
+    }
+}
+
+impl From<Result<String, String>> for Outcome {
+    fn from(result: Result<String, String>) -> Self {
+        match result {
+            Ok(message) => Outcome::Passed(message),
+            Err(message) => Outcome::Failed(message),
+        }
+    }
+}
+
+/// Open a TCP connection to the server directly
+async fn tcp_handshake(host: &str, port: u16) -> Result<String, String> {
+    let start = Instant::now();
+    match tokio::time::timeout(CHECK_TIMEOUT, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => Ok(format!(
+            "Connected to {}:{} in {} ms",
+            host,
+            port,
+            start.elapsed().as_millis()
+        )),
+        Ok(Err(e)) => Err(format!("{}:{}: {}", host, port, e)),
+        Err(_) => Err(format!("{}:{}: timed out", host, port)),
+    }
+}
+
+/// Complete a TLS handshake with the server directly, presenting `sni`
+///
+/// An HTTPS request is made to the server's address under the SNI name.
+/// Any answer, or a failure after the connection was set up (proxy servers
+/// often do not speak HTTP), means the handshake succeeded; only connect
+/// errors, which include handshake and certificate errors, fail the check.
+async fn tls_handshake(host: &str, port: u16, sni: &str, insecure: bool) -> Result<String, String> {
+    let addr = tokio::net::lookup_host((host, port))
+        .await
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or_else(|| format!("Cannot resolve {}", host))?;
+    let client = crate::version::http_client_builder()
+        .no_proxy()
+        .resolve(sni, addr)
+        .danger_accept_invalid_certs(insecure)
+        .timeout(CHECK_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let url = format!("https://{}:{}/", sni, port);
+    match client.get(&url).send().await {
+        Err(e) if e.is_connect() => {
+            Err(format!("Handshake for {} failed: {}", sni, error_chain(&e)))
+        }
+        Err(e) if e.is_timeout() => Err(format!("Handshake for {} timed out", sni)),
+        _ => Ok(format!(
+            "Handshake for {} succeeded{}",
+            sni,
+            if insecure {
+                " (certificate not verified)"
+            } else {
+                ""
+            }
+        )),
+    }
+}
+
+/// An error with its causes, which carry the actual TLS failure
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        let cause_message = cause.to_string();
+        if !message.contains(&cause_message) {
+            message = format!("{}: {}", message, cause_message);
+        }
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProxyProtocol;
+    use std::collections::HashMap;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_handshakes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Accept and drop connections without answering TLS
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                drop(stream);
+            }
+        });
+
+        assert!(tcp_handshake("127.0.0.1", port).await.is_ok());
+        let error = tls_handshake("127.0.0.1", port, "example.com", false)
+            .await
+            .unwrap_err();
+        assert!(error.contains("example.com"));
+
+        let closed = crate::utils::ports::find_free_port(None).unwrap();
+        assert!(tcp_handshake("127.0.0.1", closed).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failure_skips_later_checks() {
+        let config = ProxyServerConfig {
+            id: "server".to_string(),
+            name: "Broken".to_string(),
+            server: String::new(),
+            port: 443,
+            protocol: ProxyProtocol::Trojan,
+            settings: HashMap::new(),
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            raw_name: None,
+        };
+        let report = run_diagnostics(&ConnectionManager::new(), config).await;
+
+        let stages: Vec<_> = report.checks.iter().map(|check| check.stage).collect();
+        assert_eq!(stages, DiagnosticStage::ALL);
+        // Without an address the config check fails, if the binary check
+        // has not already
+        let failed = report.failed_stage().unwrap();
+        assert!(matches!(
+            failed,
+            DiagnosticStage::Binary | DiagnosticStage::Config
+        ));
+        assert!(!report.passed());
+
+        let failed_at = stages.iter().position(|stage| *stage == failed).unwrap();
+        assert!(report.checks[failed_at].hint.is_some());
+        assert!(report.checks[failed_at + 1..]
+            .iter()
+            .all(|check| check.status == CheckStatus::Skipped && check.hint.is_none()));
+    }
+}
@@ -0,0 +1,205 @@
+//! Direct Preference
+//!
+//! In proxied modes, traffic goes through the proxy even when the
+//! destination is not blocked and a direct connection would be just as
+//! fast. This module probes the domains on a user-chosen list both directly
+//! and through the local HTTP inbound, and routes those that work directly
+//! without being noticeably slower to the direct outbound, saving proxy
+//! latency and quota.
+//!
+//! Only listed domains are probed, so no destination is contacted without
+//! the user opting in.
+
+use super::readiness::check_connectivity;
+use super::suggestions::SUGGESTED_RULE_SET_ID;
+use crate::config::routing::OUTBOUND_DIRECT;
+use crate::config::{DirectPreferenceSettings, RoutingRule, RoutingRuleSet};
+use crate::xray::InboundConfig;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// ID of the rule set holding the direct preference rules
+pub const DIRECT_PREFERRED_RULE_SET_ID: &str = "direct-preferred";
+
+/// Time allowed for each probe
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Outcome of probing one domain
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirectProbeResult {
+    /// Probed domain
+    pub domain: String,
+    /// Direct request duration, `None` if it failed
+    pub direct_ms: Option<u64>,
+    /// Request duration through the proxy, `None` if it failed
+    pub proxy_ms: Option<u64>,
+    /// Whether the domain should be routed direct
+    pub prefer_direct: bool,
+}
+
+impl DirectProbeResult {
+    /// Build a result, deciding whether direct is preferred
+    ///
+    /// Direct is preferred when it works and is at most `max_slowdown_ms`
+    /// slower than the proxy (or the proxy failed).
+    pub fn new(
+        domain: String,
+        direct_ms: Option<u64>,
+        proxy_ms: Option<u64>,
+        max_slowdown_ms: u32,
+    ) -> Self {
+        let prefer_direct = match (direct_ms, proxy_ms) {
+            (Some(direct), Some(proxy)) => direct <= proxy + u64::from(max_slowdown_ms),
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        Self {
+            domain,
+            direct_ms,
+            proxy_ms,
+            prefer_direct,
+        }
+    }
+
+    /// Routing rule sending the domain direct
+    pub fn to_rule(&self) -> RoutingRule {
+        RoutingRule {
+            domains: vec![format!("domain:{}", self.domain)],
+            ips: vec![],
+            port: None,
+            processes: vec![],
+            protocols: vec![],
+            outbound_tag: OUTBOUND_DIRECT.to_string(),
+        }
+    }
+}
+
+/// Request `https://<domain>/` directly, returning how long it took
+///
+/// Any HTTP response counts: it proves the domain is reachable and its TLS
+/// handshake is not interfered with.
+pub async fn probe_direct(domain: &str, timeout: Duration) -> Option<Duration> {
+    let client = crate::version::http_client_builder()
+        .no_proxy()
+        .timeout(timeout)
+        .build()
+        .ok()?;
+
+    let start = Instant::now();
+    client
+        .get(format!("https://{}/", domain))
+        .send()
+        .await
+        .ok()?;
+    Some(start.elapsed())
+}
+
+/// Probe `domain` directly and through the HTTP `inbound`
+pub async fn probe_domain(
+    domain: &str,
+    inbound: &InboundConfig,
+    settings: &DirectPreferenceSettings,
+    timeout: Duration,
+) -> DirectProbeResult {
+    let url = format!("https://{}/", domain);
+    let (direct, proxy) = tokio::join!(
+        probe_direct(domain, timeout),
+        check_connectivity(inbound, &url, timeout)
+    );
+
+    DirectProbeResult::new(
+        domain.to_string(),
+        direct.map(|d| d.as_millis() as u64),
+        proxy.ok().map(|d| d.as_millis() as u64),
+        settings.max_slowdown_ms,
+    )
+}
+
+/// Replace the direct preference rule set with rules for the preferred
+/// domains, returning whether the rules changed
+///
+/// A new set is placed after the route suggestions, which send domains
+/// failing directly through the proxy, and ahead of the other user rules.
+/// The set is removed when no domain prefers direct.
+pub fn apply_results(results: &[DirectProbeResult], rule_sets: &mut Vec<RoutingRuleSet>) -> bool {
+    let rules: Vec<RoutingRule> = results
+        .iter()
+        .filter(|result| result.prefer_direct)
+        .map(DirectProbeResult::to_rule)
+        .collect();
+
+    let existing = rule_sets
+        .iter()
+        .position(|set| set.id == DIRECT_PREFERRED_RULE_SET_ID);
+    let previous = existing.map(|index| rule_sets.remove(index));
+
+    if !rules.is_empty() {
+        let index = existing.unwrap_or_else(|| {
+            rule_sets
+                .iter()
+                .position(|set| set.id == SUGGESTED_RULE_SET_ID)
+                .map_or(0, |index| index + 1)
+        });
+        rule_sets.insert(
+            index,
+            RoutingRuleSet {
+                id: DIRECT_PREFERRED_RULE_SET_ID.to_string(),
+                name: "Direct preferred".to_string(),
+                enabled: true,
+                rules: rules.clone(),
+            },
+        );
+    }
+
+    previous.map(|set| set.rules) != (!rules.is_empty()).then_some(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_set(id: &str) -> RoutingRuleSet {
+        RoutingRuleSet {
+            id: id.to_string(),
+            name: id.to_string(),
+            enabled: true,
+            rules: vec![],
+        }
+    }
+
+    #[test]
+    fn test_prefer_direct() {
+        let result = |direct, proxy| DirectProbeResult::new("a".to_string(), direct, proxy, 50);
+        assert!(result(Some(120), Some(100)).prefer_direct);
+        assert!(!result(Some(200), Some(100)).prefer_direct);
+        assert!(result(Some(200), None).prefer_direct);
+        assert!(!result(None, Some(100)).prefer_direct);
+        assert!(!result(None, None).prefer_direct);
+    }
+
+    #[test]
+    fn test_apply_results() {
+        let mut rule_sets = vec![rule_set(SUGGESTED_RULE_SET_ID), rule_set("user")];
+        let results = vec![
+            DirectProbeResult::new("fast.example".to_string(), Some(30), Some(80), 50),
+            DirectProbeResult::new("blocked.example".to_string(), None, Some(80), 50),
+        ];
+
+        assert!(apply_results(&results, &mut rule_sets));
+        assert_eq!(rule_sets[1].id, DIRECT_PREFERRED_RULE_SET_ID);
+        assert_eq!(rule_sets[1].rules.len(), 1);
+        assert_eq!(rule_sets[1].rules[0].domains, vec!["domain:fast.example"]);
+        assert_eq!(rule_sets[1].rules[0].outbound_tag, OUTBOUND_DIRECT);
+        assert_eq!(rule_sets[2].id, "user");
+
+        // Same outcome, nothing changes
+        assert!(!apply_results(&results, &mut rule_sets));
+        assert_eq!(rule_sets.len(), 3);
+
+        // No domain prefers direct any more
+        assert!(apply_results(&results[1..], &mut rule_sets));
+        assert!(rule_sets
+            .iter()
+            .all(|set| set.id != DIRECT_PREFERRED_RULE_SET_ID));
+    }
+}
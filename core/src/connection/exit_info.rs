@@ -0,0 +1,336 @@
+//! Exit IP and geolocation lookup
+//!
+//! Asks a public IP-info service which address and network a request comes
+//! from, once through the proxy and optionally once directly. Comparing the
+//! two confirms the proxy is actually in effect.
+//!
+//! Every lookup discloses the queried address to the chosen provider, so each
+//! provider carries a privacy note for the UI to show.
+
+use crate::error::{ConfigError, ConnectionError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Timeout of a lookup
+pub const EXIT_INFO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// IP-info service queried for exit information
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ExitInfoProvider {
+    /// ip-api.com, which also reports proxy and hosting flags but is only
+    /// reachable over plain HTTP
+    IpApi,
+    /// ipinfo.io, the default
+    #[default]
+    IpInfo,
+    /// ip.sb
+    IpSb,
+}
+
+impl ExitInfoProvider {
+    /// All providers
+    pub const ALL: [Self; 3] = [Self::IpInfo, Self::IpSb, Self::IpApi];
+
+    /// Identifier used in the bridge
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::IpApi => "ip-api",
+            Self::IpInfo => "ipinfo",
+            Self::IpSb => "ip.sb",
+        }
+    }
+
+    /// Display name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::IpApi => "ip-api.com",
+            Self::IpInfo => "ipinfo.io",
+            Self::IpSb => "IP.SB",
+        }
+    }
+
+    /// What using the provider discloses
+    pub fn privacy_note(&self) -> &'static str {
+        match self {
+            Self::IpApi => {
+                "Sends the exit IP to ip-api.com over plain HTTP; the free API \
+                 does not support HTTPS, so the lookup is visible on the network path"
+            }
+            Self::IpInfo => "Sends the exit IP to ipinfo.io over HTTPS",
+            Self::IpSb => "Sends the exit IP to ip.sb over HTTPS",
+        }
+    }
+
+    /// Lookup URL
+    fn url(&self) -> &'static str {
+        match self {
+            Self::IpApi => {
+                "http://ip-api.com/json/?fields=status,message,query,country,countryCode,city,as,org,isp,proxy,hosting"
+            }
+            Self::IpInfo => "https://ipinfo.io/json",
+            Self::IpSb => "https://api.ip.sb/geoip",
+        }
+    }
+
+    /// Parse a lookup response body
+    pub fn parse(&self, body: &str) -> Result<ExitInfo, ConnectionError> {
+        let invalid = |reason: &str| {
+            ConnectionError::Failed(format!("Invalid {} response: {}", self.name(), reason))
+        };
+        let json: Value = serde_json::from_str(body).map_err(|e| invalid(&e.to_string()))?;
+        let text = |key: &str| {
+            json.get(key)
+                .and_then(Value::as_str)
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        let info = match self {
+            Self::IpApi => {
+                if text("status").as_deref() == Some("fail") {
+                    return Err(invalid(&text("message").unwrap_or_default()));
+                }
+                let (asn, organization) = split_as(text("as").as_deref());
+                let mut risks = Vec::new();
+                if json["proxy"].as_bool() == Some(true) {
+                    risks.push(ExitRisk::Proxy);
+                }
+                if json["hosting"].as_bool() == Some(true) {
+                    risks.push(ExitRisk::Hosting);
+                }
+                ExitInfo {
+                    ip: text("query").unwrap_or_default(),
+                    country: text("country"),
+                    country_code: text("countryCode"),
+                    city: text("city"),
+                    asn,
+                    organization: organization.or_else(|| text("org")).or_else(|| text("isp")),
+                    risks,
+                }
+            }
+            Self::IpInfo => {
+                let (asn, organization) = split_as(text("org").as_deref());
+                ExitInfo {
+                    ip: text("ip").unwrap_or_default(),
+                    // ipinfo only reports the code
+                    country: None,
+                    country_code: text("country"),
+                    city: text("city"),
+                    asn,
+                    organization,
+                    risks: Vec::new(),
+                }
+            }
+            Self::IpSb => ExitInfo {
+                ip: text("ip").unwrap_or_default(),
+                country: text("country"),
+                country_code: text("country_code"),
+                city: text("city"),
+                asn: json["asn"].as_u64().and_then(|asn| u32::try_from(asn).ok()),
+                organization: text("asn_organization").or_else(|| text("isp")),
+                risks: Vec::new(),
+            },
+        };
+
+        if info.ip.is_empty() {
+            return Err(invalid("no IP address"));
+        }
+        Ok(info)
+    }
+}
+
+impl FromStr for ExitInfoProvider {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "ip-api" | "ip-api.com" => Ok(Self::IpApi),
+            "ipinfo" | "ipinfo.io" => Ok(Self::IpInfo),
+            "ip.sb" | "ipsb" => Ok(Self::IpSb),
+            other => Err(ConfigError::Validation(format!(
+                "Unknown IP info provider: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Split "AS13335 Cloudflare, Inc." into number and organization
+fn split_as(value: Option<&str>) -> (Option<u32>, Option<String>) {
+    let Some(value) = value else {
+        return (None, None);
+    };
+    let (first, rest) = value.split_once(' ').unwrap_or((value, ""));
+    match first.strip_prefix("AS").and_then(|asn| asn.parse().ok()) {
+        Some(asn) => (
+            Some(asn),
+            Some(rest.trim().to_string()).filter(|rest| !rest.is_empty()),
+        ),
+        None => (None, Some(value.to_string())),
+    }
+}
+
+/// Reason a service may treat the exit address with suspicion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitRisk {
+    /// Known proxy, VPN or Tor exit
+    Proxy,
+    /// Datacenter or hosting network
+    Hosting,
+}
+
+impl ExitRisk {
+    /// Lowercase name used in the bridge
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Proxy => "proxy",
+            Self::Hosting => "hosting",
+        }
+    }
+}
+
+/// Address and network requests come from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExitInfo {
+    /// Public IP address
+    pub ip: String,
+    /// Country name
+    pub country: Option<String>,
+    /// Two-letter country code
+    pub country_code: Option<String>,
+    /// City
+    pub city: Option<String>,
+    /// Autonomous system number
+    pub asn: Option<u32>,
+    /// Network operator
+    pub organization: Option<String>,
+    /// Risk flags, if the provider reports them
+    pub risks: Vec<ExitRisk>,
+}
+
+/// Exit information through the proxy, compared with a direct lookup
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExitInfoReport {
+    /// Provider queried
+    pub provider: ExitInfoProvider,
+    /// Exit through the proxy
+    pub proxied: ExitInfo,
+    /// Exit without the proxy, if compared
+    pub direct: Option<ExitInfo>,
+    /// Why the direct lookup failed or was skipped
+    pub direct_error: Option<String>,
+}
+
+impl ExitInfoReport {
+    /// Whether requests leave through a different address than without
+    /// the proxy
+    ///
+    /// True when no direct lookup is available to compare with.
+    pub fn proxy_effective(&self) -> bool {
+        self.direct
+            .as_ref()
+            .is_none_or(|direct| direct.ip != self.proxied.ip)
+    }
+}
+
+/// Look up exit information with `client`
+pub async fn lookup(
+    client: &reqwest::Client,
+    provider: ExitInfoProvider,
+) -> Result<ExitInfo, ConnectionError> {
+    let failed = |e: reqwest::Error| {
+        if e.is_timeout() {
+            ConnectionError::Timeout
+        } else {
+            ConnectionError::Failed(format!("{} lookup failed: {}", provider.name(), e))
+        }
+    };
+    let body = client
+        .get(provider.url())
+        .timeout(EXIT_INFO_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(failed)?
+        .text()
+        .await
+        .map_err(failed)?;
+    provider.parse(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_providers() {
+        let body = r#"{"status":"success","country":"Japan","countryCode":"JP","city":"Tokyo",
+            "isp":"Example","org":"","as":"AS2516 KDDI CORPORATION","proxy":false,
+            "hosting":true,"query":"203.0.113.7"}"#;
+        let info = ExitInfoProvider::IpApi.parse(body).unwrap();
+        assert_eq!(info.ip, "203.0.113.7");
+        assert_eq!(info.country_code.as_deref(), Some("JP"));
+        assert_eq!(info.asn, Some(2516));
+        assert_eq!(info.organization.as_deref(), Some("KDDI CORPORATION"));
+        assert_eq!(info.risks, vec![ExitRisk::Hosting]);
+
+        let body = r#"{"ip":"198.51.100.1","city":"Frankfurt","country":"DE",
+            "org":"AS24940 Hetzner Online GmbH"}"#;
+        let info = ExitInfoProvider::IpInfo.parse(body).unwrap();
+        assert_eq!(info.country_code.as_deref(), Some("DE"));
+        assert_eq!(info.asn, Some(24940));
+        assert_eq!(info.organization.as_deref(), Some("Hetzner Online GmbH"));
+
+        let body = r#"{"ip":"192.0.2.5","country":"Singapore","country_code":"SG",
+            "asn":16509,"asn_organization":"Amazon.com, Inc."}"#;
+        let info = ExitInfoProvider::IpSb.parse(body).unwrap();
+        assert_eq!(info.asn, Some(16509));
+        assert_eq!(info.country.as_deref(), Some("Singapore"));
+
+        let failed = r#"{"status":"fail","message":"reserved range"}"#;
+        assert!(ExitInfoProvider::IpApi.parse(failed).is_err());
+        assert!(ExitInfoProvider::IpInfo.parse("{}").is_err());
+        assert!(ExitInfoProvider::IpSb.parse("<html>").is_err());
+    }
+
+    #[test]
+    fn test_provider_names() {
+        for provider in ExitInfoProvider::ALL {
+            assert_eq!(
+                provider.as_str().parse::<ExitInfoProvider>().unwrap(),
+                provider
+            );
+        }
+        assert!("example".parse::<ExitInfoProvider>().is_err());
+        // The default lookup is not readable on the network path
+        assert!(ExitInfoProvider::default().url().starts_with("https://"));
+    }
+
+    #[test]
+    fn test_proxy_effective() {
+        let info = |ip: &str| ExitInfo {
+            ip: ip.to_string(),
+            country: None,
+            country_code: None,
+            city: None,
+            asn: None,
+            organization: None,
+            risks: Vec::new(),
+        };
+        let mut report = ExitInfoReport {
+            provider: ExitInfoProvider::IpApi,
+            proxied: info("203.0.113.7"),
+            direct: None,
+            direct_error: Some("skipped".to_string()),
+        };
+        assert!(report.proxy_effective());
+
+        report.direct = Some(info("198.51.100.1"));
+        assert!(report.proxy_effective());
+        report.direct = Some(info("203.0.113.7"));
+        assert!(!report.proxy_effective());
+    }
+}
@@ -0,0 +1,292 @@
+//! Connectivity health probe
+//!
+//! Xray staying up does not mean the proxy still works: the server may go
+//! down or the network path to it may break. While connected, a request is
+//! periodically sent through the local HTTP inbound; after a number of
+//! consecutive failures the connection is marked degraded, which triggers
+//! an automatic reconnect.
+//!
+//! Probes also measure latency through the proxy. With a latency threshold
+//! set, a number of consecutive probes above it raises a latency alert:
+//! the server still works, but slowly enough that users may want to switch.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::readiness::DEFAULT_CHECK_URL;
+
+/// Default time between two probes
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Default time allowed for a probe request
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default consecutive failures before the connection is degraded
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+/// Default consecutive slow probes before a latency alert
+pub const DEFAULT_LATENCY_ALERT_COUNT: u32 = 3;
+
+/// Health probe settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthProbeConfig {
+    /// Whether probes are sent
+    pub enabled: bool,
+    /// URL requested through the proxy; any HTTP response counts as success
+    pub url: String,
+    /// Time between two probes
+    pub interval: Duration,
+    /// Time allowed for a probe request
+    pub timeout: Duration,
+    /// Consecutive failures before the connection is degraded
+    pub failure_threshold: u32,
+    /// Latency above which a probe counts as slow, None for no latency
+    /// alerts
+    #[serde(default)]
+    pub latency_threshold_ms: Option<u64>,
+    /// Consecutive slow probes before a latency alert
+    #[serde(default = "default_latency_alert_count")]
+    pub latency_alert_count: u32,
+}
+
+fn default_latency_alert_count() -> u32 {
+    DEFAULT_LATENCY_ALERT_COUNT
+}
+
+impl Default for HealthProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: DEFAULT_CHECK_URL.to_string(),
+            interval: DEFAULT_PROBE_INTERVAL,
+            timeout: DEFAULT_PROBE_TIMEOUT,
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            latency_threshold_ms: None,
+            latency_alert_count: DEFAULT_LATENCY_ALERT_COUNT,
+        }
+    }
+}
+
+impl HealthProbeConfig {
+    /// Validate the settings
+    pub fn validate(&self) -> Result<(), crate::error::ConfigError> {
+        if !self.url.starts_with("http://") && !self.url.starts_with("https://") {
+            return Err(crate::error::ConfigError::Validation(format!(
+                "Invalid probe URL: {}",
+                self.url
+            )));
+        }
+        if self.interval.is_zero() || self.timeout.is_zero() {
+            return Err(crate::error::ConfigError::Validation(
+                "Probe interval and timeout must be positive".to_string(),
+            ));
+        }
+        if self.failure_threshold == 0 {
+            return Err(crate::error::ConfigError::Validation(
+                "Probe failure threshold must be at least 1".to_string(),
+            ));
+        }
+        if self.latency_threshold_ms == Some(0) || self.latency_alert_count == 0 {
+            return Err(crate::error::ConfigError::Validation(
+                "Latency threshold and alert count must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Change of the degraded state caused by a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeTransition {
+    /// Failure threshold reached
+    Degraded,
+    /// First success after being degraded
+    Recovered,
+}
+
+/// Change of the slow state caused by a probe's latency
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LatencyTransition {
+    /// Latency alert count reached
+    Slow,
+    /// First probe at or below the threshold after being slow
+    Normal,
+}
+
+/// Latency alert of the current connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LatencyAlert {
+    /// Latency stayed above the threshold for the alert count
+    Degraded {
+        /// Server of the connection
+        server_id: String,
+        /// Latency of the last probe
+        latency_ms: u64,
+        /// Threshold exceeded
+        threshold_ms: u64,
+    },
+    /// Latency back at or below the threshold
+    Recovered {
+        /// Server of the connection
+        server_id: String,
+        /// Latency of the last probe
+        latency_ms: u64,
+    },
+}
+
+/// Results of the probes of the current connection
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HealthProbeState {
+    /// Whether the failure threshold has been reached
+    pub degraded: bool,
+    /// Failures since the last success
+    pub consecutive_failures: u32,
+    /// Latency of the last successful probe
+    pub last_latency_ms: Option<u64>,
+    /// Error of the last failed probe
+    pub last_error: Option<String>,
+    /// Time of the last probe
+    pub last_check: Option<DateTime<Utc>>,
+    /// Probes sent
+    pub total_checks: u64,
+    /// Probes failed
+    pub total_failures: u64,
+    /// Successful probes above the latency threshold since the last one
+    /// below it
+    pub consecutive_slow: u32,
+    /// Whether the latency alert count has been reached
+    pub slow: bool,
+}
+
+impl HealthProbeState {
+    /// Record a probe outcome, returning the resulting transition, if any
+    pub fn record(
+        &mut self,
+        result: Result<Duration, String>,
+        failure_threshold: u32,
+    ) -> Option<ProbeTransition> {
+        self.total_checks += 1;
+        self.last_check = Some(Utc::now());
+
+        match result {
+            Ok(latency) => {
+                self.consecutive_failures = 0;
+                self.last_latency_ms = Some(latency.as_millis() as u64);
+                self.last_error = None;
+                std::mem::take(&mut self.degraded).then_some(ProbeTransition::Recovered)
+            }
+            Err(e) => {
+                self.total_failures += 1;
+                self.consecutive_failures += 1;
+                self.last_error = Some(e);
+                if self.degraded || self.consecutive_failures < failure_threshold {
+                    return None;
+                }
+                self.degraded = true;
+                Some(ProbeTransition::Degraded)
+            }
+        }
+    }
+
+    /// Check the latency of a successful probe against `threshold_ms`,
+    /// returning the resulting transition, if any
+    pub fn record_latency(
+        &mut self,
+        latency_ms: u64,
+        threshold_ms: Option<u64>,
+        alert_count: u32,
+    ) -> Option<LatencyTransition> {
+        if threshold_ms.is_some_and(|threshold| latency_ms > threshold) {
+            self.consecutive_slow += 1;
+            if self.slow || self.consecutive_slow < alert_count {
+                return None;
+            }
+            self.slow = true;
+            return Some(LatencyTransition::Slow);
+        }
+        self.consecutive_slow = 0;
+        std::mem::take(&mut self.slow).then_some(LatencyTransition::Normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_transitions() {
+        let mut state = HealthProbeState::default();
+        let ok = || Ok(Duration::from_millis(120));
+        let failed = || Err("timeout".to_string());
+
+        assert_eq!(state.record(ok(), 3), None);
+        assert_eq!(state.last_latency_ms, Some(120));
+
+        assert_eq!(state.record(failed(), 3), None);
+        assert_eq!(state.record(failed(), 3), None);
+        assert_eq!(state.record(failed(), 3), Some(ProbeTransition::Degraded));
+        assert_eq!(state.record(failed(), 3), None);
+        assert!(state.degraded);
+        assert_eq!(state.consecutive_failures, 4);
+        assert_eq!(state.last_error.as_deref(), Some("timeout"));
+
+        assert_eq!(state.record(ok(), 3), Some(ProbeTransition::Recovered));
+        assert!(!state.degraded);
+        assert_eq!(state.total_checks, 6);
+        assert_eq!(state.total_failures, 4);
+    }
+
+    #[test]
+    fn test_latency_transitions() {
+        let mut state = HealthProbeState::default();
+
+        assert_eq!(state.record_latency(900, None, 2), None);
+        assert_eq!(state.record_latency(900, Some(500), 2), None);
+        assert_eq!(state.record_latency(100, Some(500), 2), None);
+        assert_eq!(state.record_latency(600, Some(500), 2), None);
+        assert_eq!(
+            state.record_latency(700, Some(500), 2),
+            Some(LatencyTransition::Slow)
+        );
+        assert_eq!(state.record_latency(800, Some(500), 2), None);
+        assert!(state.slow);
+        assert_eq!(state.consecutive_slow, 3);
+
+        assert_eq!(
+            state.record_latency(500, Some(500), 2),
+            Some(LatencyTransition::Normal)
+        );
+        assert!(!state.slow);
+
+        // Turning the threshold off ends the alert
+        state.record_latency(900, Some(500), 1);
+        assert_eq!(
+            state.record_latency(900, None, 1),
+            Some(LatencyTransition::Normal)
+        );
+    }
+
+    #[test]
+    fn test_probe_config_validation() {
+        assert!(HealthProbeConfig::default().validate().is_ok());
+
+        let config = HealthProbeConfig {
+            url: "gstatic.com".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = HealthProbeConfig {
+            failure_threshold: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = HealthProbeConfig {
+            latency_threshold_ms: Some(0),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
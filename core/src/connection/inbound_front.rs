@@ -0,0 +1,286 @@
+//! Fronts of local inbounds
+//!
+//! The speed limit (see [`rate_limit`](super::rate_limit)) and scripted
+//! routing (see [`script_routing`](super::script_routing)) put a listener on
+//! the public port of an inbound and move Xray's inbound to an internal
+//! loopback port the listener relays to. [`FrontRegistry`] holds these
+//! fronts by public port: fronts already running for a port are kept along
+//! with their internal port, so switching servers does not drop
+//! connections, and internal ports follow the ports a hot reload moves
+//! Xray's inbounds to.
+
+use crate::xray::{InboundConfig, PortReassignment, XrayConfig};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Listener on a public inbound port relaying to Xray, stopped when dropped
+#[derive(Debug)]
+pub struct InboundFront {
+    /// Address local applications connect to
+    public: SocketAddr,
+    /// Internal loopback port Xray listens on
+    upstream_port: Arc<AtomicU16>,
+    /// Stops the accept loop and all relayed connections
+    cancel: CancellationToken,
+}
+
+impl InboundFront {
+    /// Listen on `public` and pass each connection to `handle`, along with
+    /// the internal port Xray listens on at the time
+    ///
+    /// Must be called within a Tokio runtime.
+    pub fn start<H, F>(public: SocketAddr, upstream_port: u16, handle: H) -> std::io::Result<Self>
+    where
+        H: Fn(TcpStream, u16) -> F + Send + 'static,
+        F: Future<Output = std::io::Result<()>> + Send + 'static,
+    {
+        let listener = std::net::TcpListener::bind(public)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let public = listener.local_addr()?;
+        let upstream_port = Arc::new(AtomicU16::new(upstream_port));
+        let cancel = CancellationToken::new();
+
+        let (token, upstream) = (cancel.clone(), upstream_port.clone());
+        tokio::spawn(async move {
+            loop {
+                let client = tokio::select! {
+                    _ = token.cancelled() => break,
+                    accepted = listener.accept() => match accepted {
+                        Ok((client, _)) => client,
+                        Err(e) => {
+                            warn!("Inbound front on {} failed to accept: {}", public, e);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                    },
+                };
+                let connection = handle(client, upstream.load(Ordering::Relaxed));
+                let token = token.clone();
+                tokio::spawn(async move {
+                    tokio::select! {
+                        _ = token.cancelled() => {}
+                        result = connection => {
+                            if let Err(e) = result {
+                                debug!("Connection on {} ended: {}", public, e);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            public,
+            upstream_port,
+            cancel,
+        })
+    }
+
+    /// Address local applications connect to
+    pub fn public_addr(&self) -> SocketAddr {
+        self.public
+    }
+
+    /// Internal port Xray listens on
+    pub fn upstream_port(&self) -> u16 {
+        self.upstream_port.load(Ordering::Relaxed)
+    }
+
+    /// Relay new connections to `port` from now on
+    pub fn set_upstream_port(&self, port: u16) {
+        self.upstream_port.store(port, Ordering::Relaxed);
+    }
+}
+
+impl Drop for InboundFront {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Running fronts by public port
+#[derive(Debug, Default)]
+pub struct FrontRegistry {
+    /// Fronts by public port
+    fronts: Mutex<HashMap<u16, InboundFront>>,
+}
+
+impl FrontRegistry {
+    /// Create a registry without fronts
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the inbounds of `config` picked by `select` behind fronts
+    ///
+    /// `start` starts the front of an inbound on its public address,
+    /// relaying to a free internal port. An inbound whose front cannot
+    /// start is left as it is, and fronts of ports no longer fronted are
+    /// stopped. `purpose` names the fronts in the logs. Returns the
+    /// indices of the fronted inbounds.
+    pub fn apply<S, F>(
+        &self,
+        config: &mut XrayConfig,
+        purpose: &str,
+        select: S,
+        start: F,
+    ) -> Vec<usize>
+    where
+        S: Fn(&InboundConfig) -> bool,
+        F: Fn(&InboundConfig, SocketAddr, u16) -> std::io::Result<InboundFront>,
+    {
+        let mut fronts = self.fronts.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut fronted, mut used) = (Vec::new(), Vec::new());
+        for (index, inbound) in config.inbounds.iter_mut().enumerate() {
+            if !select(inbound) {
+                continue;
+            }
+            let public_port = inbound.port;
+            let front = match fronts.entry(public_port) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let listen = inbound
+                        .listen
+                        .as_deref()
+                        .and_then(|addr| addr.parse().ok())
+                        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST));
+                    let started = crate::utils::ports::find_free_port(None).and_then(|upstream| {
+                        start(inbound, SocketAddr::new(listen, public_port), upstream)
+                    });
+                    match started {
+                        Ok(front) => {
+                            info!(
+                                "Fronting {} inbound on {} for {} through internal port {}",
+                                inbound.protocol,
+                                front.public_addr(),
+                                purpose,
+                                front.upstream_port()
+                            );
+                            entry.insert(front)
+                        }
+                        Err(e) => {
+                            warn!(
+                                "Cannot front {} inbound on port {} for {}: {}",
+                                inbound.protocol, public_port, purpose, e
+                            );
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            inbound.port = front.upstream_port();
+            inbound.listen = Some(Ipv4Addr::LOCALHOST.to_string());
+            fronted.push(index);
+            used.push(public_port);
+        }
+        fronts.retain(|port, _| used.contains(port));
+        fronted
+    }
+
+    /// Point the fronts at the ports Xray moved its inbounds to, see
+    /// [`XrayCore::reload`](crate::xray::XrayCore::reload)
+    pub fn follow(&self, reassignments: &[PortReassignment]) {
+        for front in self
+            .fronts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+        {
+            if let Some(reassignment) = reassignments
+                .iter()
+                .find(|reassignment| reassignment.old_port == front.upstream_port())
+            {
+                front.set_upstream_port(reassignment.new_port);
+            }
+        }
+    }
+
+    /// Public port local applications use for Xray's inbound `port`
+    ///
+    /// Internal ports of fronts map to their public port, any other port is
+    /// returned as is.
+    pub fn public_port(&self, port: u16) -> u16 {
+        self.fronts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .find(|front| front.upstream_port() == port)
+            .map_or(port, |front| front.public_addr().port())
+    }
+
+    /// Whether no front is running
+    pub fn is_empty(&self) -> bool {
+        self.fronts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+    }
+
+    /// Stop all fronts
+    pub fn stop(&self) {
+        self.fronts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_registry_follows_reassignments() {
+        let registry = FrontRegistry::new();
+        let mut config = XrayConfig {
+            inbounds: vec![InboundConfig {
+                port: crate::utils::ports::find_free_port(None).unwrap(),
+                protocol: "http".to_string(),
+                listen: None,
+                settings: None,
+                sniffing: None,
+                tag: None,
+            }],
+            ..Default::default()
+        };
+        let public_port = config.inbounds[0].port;
+
+        let fronted = registry.apply(
+            &mut config,
+            "test",
+            |_| true,
+            |_, public, upstream| {
+                InboundFront::start(public, upstream, |mut client, _| async move {
+                    client.shutdown().await
+                })
+            },
+        );
+        assert_eq!(fronted, vec![0]);
+        let internal = config.inbounds[0].port;
+        assert_ne!(internal, public_port);
+        assert_eq!(registry.public_port(internal), public_port);
+
+        registry.follow(&[PortReassignment {
+            protocol: "http".to_string(),
+            old_port: internal,
+            new_port: internal + 1,
+        }]);
+        assert_eq!(registry.public_port(internal + 1), public_port);
+
+        // Inbounds no longer selected lose their front
+        let fronted = registry.apply(&mut config, "test", |_| false, |_, _, _| unreachable!());
+        assert!(fronted.is_empty());
+        assert!(registry.is_empty());
+    }
+}
@@ -0,0 +1,340 @@
+//! Latency history and server quality
+//!
+//! A single latency test says little about a server: one answering in 40 ms
+//! now may time out on every third request. Every test result is kept per
+//! server in SQLite, failed tests included, so quality can be judged from
+//! the loss rate, jitter and 95th percentile of recent samples rather than
+//! from the last result alone.
+
+use crate::error::StorageResult;
+use crate::utils::preflight::check_writable;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool},
+    Row,
+};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{debug, info};
+
+/// Samples quality is computed from by default
+pub const DEFAULT_QUALITY_WINDOW: u32 = 50;
+
+/// Days of samples kept by default
+pub const DEFAULT_RETENTION_DAYS: u32 = 30;
+
+/// Result of one latency test
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LatencySample {
+    /// When the test ran
+    pub tested_at: DateTime<Utc>,
+    /// Latency in milliseconds, None if the server did not answer
+    pub latency_ms: Option<u64>,
+}
+
+/// Quality of a server over its recent latency samples
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerQuality {
+    /// Server ID
+    pub server_id: String,
+    /// Samples, oldest first
+    pub samples: Vec<LatencySample>,
+    /// Share of failed tests, 0.0 to 1.0
+    pub loss_rate: f64,
+    /// Average latency of successful tests
+    pub average_ms: Option<f64>,
+    /// 95th percentile latency of successful tests
+    pub p95_ms: Option<u64>,
+    /// Average difference between consecutive successful tests
+    pub jitter_ms: Option<f64>,
+}
+
+impl ServerQuality {
+    /// Compute the quality of `server_id` from `samples`, oldest first
+    pub fn from_samples(server_id: impl Into<String>, samples: Vec<LatencySample>) -> Self {
+        let latencies: Vec<u64> = samples.iter().filter_map(|s| s.latency_ms).collect();
+        let loss_rate = if samples.is_empty() {
+            0.0
+        } else {
+            (samples.len() - latencies.len()) as f64 / samples.len() as f64
+        };
+        let average_ms = (!latencies.is_empty())
+            .then(|| latencies.iter().sum::<u64>() as f64 / latencies.len() as f64);
+        let p95_ms = percentile(&latencies, 95);
+        let jitter_ms = (latencies.len() > 1).then(|| {
+            latencies
+                .windows(2)
+                .map(|pair| pair[0].abs_diff(pair[1]))
+                .sum::<u64>() as f64
+                / (latencies.len() - 1) as f64
+        });
+
+        Self {
+            server_id: server_id.into(),
+            samples,
+            loss_rate,
+            average_ms,
+            p95_ms,
+            jitter_ms,
+        }
+    }
+
+    /// Ranking score, lower is better: the 95th percentile plus twice the
+    /// jitter, scaled up by the loss rate
+    ///
+    /// A server that is consistently reasonable beats one that is fast on
+    /// average but spiky or lossy. None if no test succeeded.
+    pub fn score(&self) -> Option<f64> {
+        let p95 = self.p95_ms? as f64;
+        let jitter = self.jitter_ms.unwrap_or(0.0);
+        Some((p95 + 2.0 * jitter) / (1.0 - self.loss_rate).max(0.01))
+    }
+}
+
+/// SQLite-backed latency history
+pub struct LatencyHistory {
+    /// SQLite connection pool
+    pool: SqlitePool,
+}
+
+impl LatencyHistory {
+    /// Open the history database at `db_path`, creating it if missing
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> StorageResult<Self> {
+        let path = db_path.as_ref();
+        info!("Opening latency history database: {}", path.display());
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            check_writable(parent)?;
+        }
+
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        let history = Self { pool };
+        history.init_tables().await?;
+        Ok(history)
+    }
+
+    /// Size of the history database in bytes
+    pub async fn database_size(&self) -> StorageResult<u64> {
+        crate::subscription::database_size(&self.pool).await
+    }
+
+    /// Create an in-memory history (for testing)
+    pub async fn new_in_memory() -> StorageResult<Self> {
+        let pool = SqlitePool::connect("sqlite::memory:").await?;
+
+        let history = Self { pool };
+        history.init_tables().await?;
+        Ok(history)
+    }
+
+    /// Initialize database tables
+    async fn init_tables(&self) -> StorageResult<()> {
+        debug!("Initializing latency history tables");
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS latency_samples (
+                server_id TEXT NOT NULL,
+                tested_at INTEGER NOT NULL,
+                latency_ms INTEGER
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_latency_server \
+             ON latency_samples (server_id, tested_at)",
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a test of `server_id` at `at`, None for a failed test
+    pub async fn record(
+        &self,
+        server_id: &str,
+        at: DateTime<Utc>,
+        latency_ms: Option<u64>,
+    ) -> StorageResult<()> {
+        sqlx::query(
+            "INSERT INTO latency_samples (server_id, tested_at, latency_ms) VALUES (?, ?, ?)",
+        )
+        .bind(server_id)
+        .bind(at.timestamp_millis())
+        .bind(latency_ms.map(|ms| ms as i64))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The last `limit` samples of `server_id`, oldest first
+    pub async fn recent(&self, server_id: &str, limit: u32) -> StorageResult<Vec<LatencySample>> {
+        let rows = sqlx::query(
+            "SELECT tested_at, latency_ms FROM latency_samples WHERE server_id = ? \
+             ORDER BY tested_at DESC, rowid DESC LIMIT ?",
+        )
+        .bind(server_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut samples: Vec<LatencySample> = rows
+            .iter()
+            .map(|row| LatencySample {
+                tested_at: Utc
+                    .timestamp_millis_opt(row.get("tested_at"))
+                    .single()
+                    .unwrap_or_default(),
+                latency_ms: row.get::<Option<i64>, _>("latency_ms").map(|ms| ms as u64),
+            })
+            .collect();
+        samples.reverse();
+        Ok(samples)
+    }
+
+    /// Quality of `server_id` over its last `window` samples
+    pub async fn quality(&self, server_id: &str, window: u32) -> StorageResult<ServerQuality> {
+        let samples = self.recent(server_id, window).await?;
+        Ok(ServerQuality::from_samples(server_id, samples))
+    }
+
+    /// Quality of each of `server_ids`, best score first
+    ///
+    /// Servers without a successful test come last, in the given order.
+    pub async fn rank(
+        &self,
+        server_ids: &[String],
+        window: u32,
+    ) -> StorageResult<Vec<ServerQuality>> {
+        let mut qualities = Vec::with_capacity(server_ids.len());
+        for server_id in server_ids {
+            qualities.push(self.quality(server_id, window).await?);
+        }
+        qualities.sort_by(|a, b| match (a.score(), b.score()) {
+            (Some(a), Some(b)) => a.total_cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        });
+        Ok(qualities)
+    }
+
+    /// Delete samples older than `days` days
+    ///
+    /// Returns the number of deleted samples.
+    pub async fn prune(&self, days: u32) -> StorageResult<u64> {
+        let cutoff = Utc::now() - chrono::Duration::days(i64::from(days));
+        let deleted = sqlx::query("DELETE FROM latency_samples WHERE tested_at < ?")
+            .bind(cutoff.timestamp_millis())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if deleted > 0 {
+            info!("Pruned {} latency samples", deleted);
+        }
+        Ok(deleted)
+    }
+}
+
+/// Nearest-rank `pct` percentile of `values`
+fn percentile(values: &[u64], pct: usize) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(latencies: &[Option<u64>]) -> Vec<LatencySample> {
+        let start = Utc::now();
+        latencies
+            .iter()
+            .enumerate()
+            .map(|(i, &latency_ms)| LatencySample {
+                tested_at: start + chrono::Duration::seconds(i as i64),
+                latency_ms,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quality_metrics() {
+        let quality = ServerQuality::from_samples(
+            "a",
+            samples(&[Some(100), Some(120), None, Some(80), Some(100)]),
+        );
+        assert_eq!(quality.loss_rate, 0.2);
+        assert_eq!(quality.average_ms, Some(100.0));
+        assert_eq!(quality.p95_ms, Some(120));
+        // |100-120| + |120-80| + |80-100| over 3 pairs
+        assert!((quality.jitter_ms.unwrap() - 80.0 / 3.0).abs() < 1e-9);
+
+        let empty = ServerQuality::from_samples("b", vec![]);
+        assert_eq!(empty.loss_rate, 0.0);
+        assert_eq!(empty.score(), None);
+        let lost = ServerQuality::from_samples("c", samples(&[None, None]));
+        assert_eq!(lost.loss_rate, 1.0);
+        assert_eq!(lost.score(), None);
+    }
+
+    #[tokio::test]
+    async fn test_record_and_rank() {
+        let history = LatencyHistory::new_in_memory().await.unwrap();
+        let now = Utc::now();
+
+        // Fast at the moment but spiky and lossy
+        for (i, latency) in [Some(30), Some(400), None, Some(35), Some(30)]
+            .into_iter()
+            .enumerate()
+        {
+            let at = now + chrono::Duration::seconds(i as i64);
+            history.record("spiky", at, latency).await.unwrap();
+        }
+        // Slower but steady
+        for i in 0..5 {
+            let at = now + chrono::Duration::seconds(i);
+            history
+                .record("steady", at, Some(90 + i as u64))
+                .await
+                .unwrap();
+        }
+        history
+            .record("old", now - chrono::Duration::days(40), Some(10))
+            .await
+            .unwrap();
+
+        let recent = history.recent("spiky", 2).await.unwrap();
+        assert_eq!(
+            recent.iter().map(|s| s.latency_ms).collect::<Vec<_>>(),
+            vec![Some(35), Some(30)]
+        );
+
+        let ids = ["spiky", "missing", "steady"].map(String::from);
+        let ranked = history.rank(&ids, DEFAULT_QUALITY_WINDOW).await.unwrap();
+        assert_eq!(
+            ranked
+                .iter()
+                .map(|q| q.server_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["steady", "spiky", "missing"]
+        );
+        assert_eq!(ranked[1].samples.len(), 5);
+
+        assert_eq!(history.prune(DEFAULT_RETENTION_DAYS).await.unwrap(), 1);
+        assert!(history.recent("old", 10).await.unwrap().is_empty());
+    }
+}
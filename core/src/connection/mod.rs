@@ -3,20 +3,72 @@
 //! This module handles proxy connections, including connection state management,
 //! statistics collection, and connection lifecycle.
 
+pub mod access_analytics;
+pub mod active_connections;
+pub mod diagnostics;
+pub mod direct_preference;
+pub mod exit_info;
+pub mod health_probe;
+pub mod inbound_front;
+pub mod latency_history;
+pub mod rate_limit;
+pub mod readiness;
 pub mod reconnect;
+pub mod runtime_state;
+pub mod script_routing;
+pub mod session;
+pub mod speed_test;
 pub mod stats;
-
-use crate::config::ProxyServerConfig;
-use crate::xray::{XrayCore, XrayEvent, XrayStatus};
-use reconnect::ReconnectConfig;
+pub mod suggestions;
+pub mod timeline;
+pub mod traffic_budget;
+pub mod traffic_history;
+pub mod unlock_checker;
+
+use crate::config::{
+    Config, DirectPreferenceSettings, ProxyServerConfig, RoutingRule, RoutingRuleSet,
+};
+use crate::proxy_core::{CoreKind, ProxyCore, SingBoxCore};
+use crate::xray::health::{probe_instance_outbounds, DEFAULT_PROBE_TIMEOUT};
+use crate::xray::instances::DEFAULT_INSTANCE_ID;
+use crate::xray::{
+    AggregatedHealth, BalancerStrategy, PortReassignment, ProbeTarget, XrayApiClient, XrayConfig,
+    XrayCore, XrayError, XrayEvent, XrayInstances, XrayStatus,
+};
+use access_analytics::{
+    AccessAnalytics, AccessLogRecord, AccessLogStorage, DomainCount, RouteSplit,
+};
+use active_connections::{ActiveConnection, ConnectionTracker};
+use direct_preference::DirectProbeResult;
+use health_probe::{
+    HealthProbeConfig, HealthProbeState, LatencyAlert, LatencyTransition, ProbeTransition,
+};
+use latency_history::LatencyHistory;
+use rate_limit::{InboundThrottle, RateLimit};
+use readiness::{ReadinessConfig, ReadinessReport};
+use reconnect::{ErrorBurstDetector, FailureClass, ReconnectConfig, ReconnectEvent};
+use script_routing::{ScriptRouter, ScriptRoutingSettings};
 use serde::{Deserialize, Serialize};
+use speed_test::{
+    SpeedRanking, SpeedTestConfig, SpeedTestHistory, SpeedTestProgress, SpeedTestResult,
+};
 use stats::TrafficStatsCollector;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use suggestions::{RouteSuggestion, RouteSuggestionTracker};
+use timeline::{SessionEvent, SessionEventKind, SessionTimeline};
 use tokio::sync::{broadcast, RwLock};
+use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
+use traffic_history::TrafficHistory;
+use unlock_checker::{UnlockCache, UnlockResult, UnlockService};
 use uuid::Uuid;
 
+/// How often access log records are written to the persistent access log
+const ACCESS_LOG_FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
 /// Connection state
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ConnectionState {
@@ -34,6 +86,34 @@ pub enum ConnectionState {
     Error(String),
 }
 
+/// Phase of establishing a connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectPhase {
+    /// Generating the Xray configuration
+    GeneratingConfig,
+    /// Starting Xray, or switching the running instance
+    StartingProcess,
+    /// Waiting for the local inbound to accept connections
+    WaitingInbound,
+    /// Sending a first request through the proxy
+    TestingConnectivity,
+    /// Proxy serves traffic
+    Connected,
+}
+
+impl ConnectPhase {
+    /// Lowercase name used in the bridge
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::GeneratingConfig => "generating_config",
+            Self::StartingProcess => "starting_process",
+            Self::WaitingInbound => "waiting_inbound",
+            Self::TestingConnectivity => "testing_connectivity",
+            Self::Connected => "connected",
+        }
+    }
+}
+
 /// Connection error type
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ConnectionError {
@@ -97,10 +177,66 @@ pub struct ConnectionManager {
     current_config: Arc<RwLock<Option<ProxyServerConfig>>>,
     /// Reconnect configuration
     reconnect_config: Arc<RwLock<ReconnectConfig>>,
+    /// Readiness check configuration
+    readiness_config: Arc<RwLock<ReadinessConfig>>,
     /// Reconnect task cancellation sender
     reconnect_cancel_tx: Arc<RwLock<Option<broadcast::Sender<()>>>>,
     /// Traffic statistics collector
     stats_collector: Arc<TrafficStatsCollector>,
+    /// Outbounds of the current connection, used for health probes
+    probe_targets: Arc<RwLock<Vec<ProbeTarget>>>,
+    /// Event timeline of the current session
+    timeline: Arc<std::sync::RwLock<SessionTimeline>>,
+    /// Direct-failure tracker for smart mode route suggestions
+    route_suggestions: Arc<std::sync::RwLock<RouteSuggestionTracker>>,
+    /// Whether the log watcher feeding `route_suggestions` is running
+    suggestion_watcher: Arc<AtomicBool>,
+    /// Connectivity health probe settings
+    health_probe_config: Arc<RwLock<HealthProbeConfig>>,
+    /// Connectivity health probe results of the current connection
+    health_probe: Arc<std::sync::RwLock<HealthProbeState>>,
+    /// Whether the health probe loop is running
+    health_probe_running: Arc<AtomicBool>,
+    /// Whether the Xray traffic counter poll loop is running
+    traffic_poller: Arc<AtomicBool>,
+    /// Persistent traffic history, if enabled
+    traffic_history: Arc<RwLock<Option<Arc<TrafficHistory>>>>,
+    /// Persistent latency history, if enabled
+    latency_history: Arc<RwLock<Option<Arc<LatencyHistory>>>>,
+    /// Connections seen in Xray access logs
+    connection_tracker: Arc<std::sync::RwLock<ConnectionTracker>>,
+    /// Whether the log watcher feeding `connection_tracker` is running
+    connection_watcher: Arc<AtomicBool>,
+    /// Recent access log records for domain analytics
+    access_analytics: Arc<std::sync::RwLock<AccessAnalytics>>,
+    /// Persistent access log, if enabled
+    access_log_storage: Arc<RwLock<Option<Arc<AccessLogStorage>>>>,
+    /// Whether the crash and error monitor is running
+    reconnect_monitor: Arc<AtomicBool>,
+    /// Start of the current series of reconnect attempts
+    retry_started: Arc<RwLock<Option<Instant>>>,
+    /// Auto-reconnect progress sender
+    reconnect_events: broadcast::Sender<ReconnectEvent>,
+    /// Latency alert sender
+    latency_alerts: broadcast::Sender<LatencyAlert>,
+    /// Recent speed test results per server
+    speed_results: Arc<std::sync::RwLock<SpeedTestHistory>>,
+    /// Cached streaming unlock results per server
+    unlock_results: Arc<std::sync::RwLock<UnlockCache>>,
+    /// Xray instances running next to the main connection
+    instances: Arc<XrayInstances>,
+    /// Whether history collectors are off to save memory
+    low_memory: Arc<AtomicBool>,
+    /// Core of the active profile
+    core_kind: Arc<std::sync::RwLock<CoreKind>>,
+    /// sing-box core, running connections whose core is sing-box
+    sing_box: Arc<SingBoxCore>,
+    /// Core running the current connection
+    active_core: Arc<std::sync::RwLock<Option<Arc<dyn ProxyCore>>>>,
+    /// Speed limit of the local inbounds
+    throttle: Arc<InboundThrottle>,
+    /// Scripted routing of the local inbounds
+    script_router: Arc<ScriptRouter>,
 }
 
 impl Default for ConnectionManager {
@@ -120,8 +256,36 @@ impl ConnectionManager {
             xray: Arc::new(XrayCore::new()),
             current_config: Arc::new(RwLock::new(None)),
             reconnect_config: Arc::new(RwLock::new(ReconnectConfig::default())),
+            readiness_config: Arc::new(RwLock::new(ReadinessConfig::default())),
             reconnect_cancel_tx: Arc::new(RwLock::new(None)),
             stats_collector,
+            probe_targets: Arc::new(RwLock::new(Vec::new())),
+            timeline: Arc::new(std::sync::RwLock::new(SessionTimeline::new())),
+            route_suggestions: Arc::new(std::sync::RwLock::new(RouteSuggestionTracker::new())),
+            suggestion_watcher: Arc::new(AtomicBool::new(false)),
+            health_probe_config: Arc::new(RwLock::new(HealthProbeConfig::default())),
+            health_probe: Arc::new(std::sync::RwLock::new(HealthProbeState::default())),
+            health_probe_running: Arc::new(AtomicBool::new(false)),
+            traffic_poller: Arc::new(AtomicBool::new(false)),
+            traffic_history: Arc::new(RwLock::new(None)),
+            latency_history: Arc::new(RwLock::new(None)),
+            connection_tracker: Arc::new(std::sync::RwLock::new(ConnectionTracker::default())),
+            connection_watcher: Arc::new(AtomicBool::new(false)),
+            access_analytics: Arc::new(std::sync::RwLock::new(AccessAnalytics::default())),
+            access_log_storage: Arc::new(RwLock::new(None)),
+            reconnect_monitor: Arc::new(AtomicBool::new(false)),
+            retry_started: Arc::new(RwLock::new(None)),
+            reconnect_events: broadcast::channel(16).0,
+            latency_alerts: broadcast::channel(16).0,
+            speed_results: Arc::new(std::sync::RwLock::new(SpeedTestHistory::default())),
+            unlock_results: Arc::new(std::sync::RwLock::new(UnlockCache::default())),
+            instances: Arc::new(XrayInstances::new()),
+            low_memory: Arc::new(AtomicBool::new(false)),
+            core_kind: Arc::new(std::sync::RwLock::new(CoreKind::default())),
+            sing_box: Arc::new(SingBoxCore::new()),
+            active_core: Arc::new(std::sync::RwLock::new(None)),
+            throttle: Arc::new(InboundThrottle::new()),
+            script_router: Arc::new(ScriptRouter::new()),
         }
     }
 
@@ -135,8 +299,36 @@ impl ConnectionManager {
             xray,
             current_config: Arc::new(RwLock::new(None)),
             reconnect_config: Arc::new(RwLock::new(ReconnectConfig::default())),
+            readiness_config: Arc::new(RwLock::new(ReadinessConfig::default())),
             reconnect_cancel_tx: Arc::new(RwLock::new(None)),
             stats_collector,
+            probe_targets: Arc::new(RwLock::new(Vec::new())),
+            timeline: Arc::new(std::sync::RwLock::new(SessionTimeline::new())),
+            route_suggestions: Arc::new(std::sync::RwLock::new(RouteSuggestionTracker::new())),
+            suggestion_watcher: Arc::new(AtomicBool::new(false)),
+            health_probe_config: Arc::new(RwLock::new(HealthProbeConfig::default())),
+            health_probe: Arc::new(std::sync::RwLock::new(HealthProbeState::default())),
+            health_probe_running: Arc::new(AtomicBool::new(false)),
+            traffic_poller: Arc::new(AtomicBool::new(false)),
+            traffic_history: Arc::new(RwLock::new(None)),
+            latency_history: Arc::new(RwLock::new(None)),
+            connection_tracker: Arc::new(std::sync::RwLock::new(ConnectionTracker::default())),
+            connection_watcher: Arc::new(AtomicBool::new(false)),
+            access_analytics: Arc::new(std::sync::RwLock::new(AccessAnalytics::default())),
+            access_log_storage: Arc::new(RwLock::new(None)),
+            reconnect_monitor: Arc::new(AtomicBool::new(false)),
+            retry_started: Arc::new(RwLock::new(None)),
+            reconnect_events: broadcast::channel(16).0,
+            latency_alerts: broadcast::channel(16).0,
+            speed_results: Arc::new(std::sync::RwLock::new(SpeedTestHistory::default())),
+            unlock_results: Arc::new(std::sync::RwLock::new(UnlockCache::default())),
+            instances: Arc::new(XrayInstances::new()),
+            low_memory: Arc::new(AtomicBool::new(false)),
+            core_kind: Arc::new(std::sync::RwLock::new(CoreKind::default())),
+            sing_box: Arc::new(SingBoxCore::new()),
+            active_core: Arc::new(std::sync::RwLock::new(None)),
+            throttle: Arc::new(InboundThrottle::new()),
+            script_router: Arc::new(ScriptRouter::new()),
         }
     }
 
@@ -150,8 +342,36 @@ impl ConnectionManager {
             xray: Arc::new(XrayCore::new()),
             current_config: Arc::new(RwLock::new(None)),
             reconnect_config: Arc::new(RwLock::new(reconnect_config)),
+            readiness_config: Arc::new(RwLock::new(ReadinessConfig::default())),
             reconnect_cancel_tx: Arc::new(RwLock::new(None)),
             stats_collector,
+            probe_targets: Arc::new(RwLock::new(Vec::new())),
+            timeline: Arc::new(std::sync::RwLock::new(SessionTimeline::new())),
+            route_suggestions: Arc::new(std::sync::RwLock::new(RouteSuggestionTracker::new())),
+            suggestion_watcher: Arc::new(AtomicBool::new(false)),
+            health_probe_config: Arc::new(RwLock::new(HealthProbeConfig::default())),
+            health_probe: Arc::new(std::sync::RwLock::new(HealthProbeState::default())),
+            health_probe_running: Arc::new(AtomicBool::new(false)),
+            traffic_poller: Arc::new(AtomicBool::new(false)),
+            traffic_history: Arc::new(RwLock::new(None)),
+            latency_history: Arc::new(RwLock::new(None)),
+            connection_tracker: Arc::new(std::sync::RwLock::new(ConnectionTracker::default())),
+            connection_watcher: Arc::new(AtomicBool::new(false)),
+            access_analytics: Arc::new(std::sync::RwLock::new(AccessAnalytics::default())),
+            access_log_storage: Arc::new(RwLock::new(None)),
+            reconnect_monitor: Arc::new(AtomicBool::new(false)),
+            retry_started: Arc::new(RwLock::new(None)),
+            reconnect_events: broadcast::channel(16).0,
+            latency_alerts: broadcast::channel(16).0,
+            speed_results: Arc::new(std::sync::RwLock::new(SpeedTestHistory::default())),
+            unlock_results: Arc::new(std::sync::RwLock::new(UnlockCache::default())),
+            instances: Arc::new(XrayInstances::new()),
+            low_memory: Arc::new(AtomicBool::new(false)),
+            core_kind: Arc::new(std::sync::RwLock::new(CoreKind::default())),
+            sing_box: Arc::new(SingBoxCore::new()),
+            active_core: Arc::new(std::sync::RwLock::new(None)),
+            throttle: Arc::new(InboundThrottle::new()),
+            script_router: Arc::new(ScriptRouter::new()),
         }
     }
 
@@ -180,12 +400,281 @@ impl ConnectionManager {
             config.name, mode
         );
 
-        // Disconnect existing connection if any
+        // Generate Xray configuration with mode
+        let xray_config = self.xray.generate_config_with_mode(&config, mode);
+        if self.core_kind().for_protocol(&config.protocol) == CoreKind::SingBox {
+            return self
+                .start_sing_box_connection(config, &xray_config, mode)
+                .await;
+        }
+        let targets = single_probe_target(&config);
+
+        self.start_connection(config, xray_config, targets, mode)
+            .await
+    }
+
+    /// Connect and wait until the proxy serves traffic, reporting each phase
+    ///
+    /// Cancelling `cancel` aborts the attempt before Xray starts or while
+    /// waiting for readiness; anything already started is disconnected
+    /// again and [`ConnectionError::Cancelled`](crate::error::ConnectionError::Cancelled)
+    /// is returned. Starting Xray itself is not interrupted.
+    pub async fn connect_with_progress(
+        &self,
+        config: ProxyServerConfig,
+        mode: &str,
+        cancel: &CancellationToken,
+        mut progress: impl FnMut(ConnectPhase),
+    ) -> crate::V8RayResult<ReadinessReport> {
+        info!(
+            "Starting connection to: {} with mode: {}",
+            config.name, mode
+        );
+
+        progress(ConnectPhase::GeneratingConfig);
+        let xray_config = self.xray.generate_config_with_mode(&config, mode);
+        let sing_box = self.core_kind().for_protocol(&config.protocol) == CoreKind::SingBox;
+        let targets = single_probe_target(&config);
+        if cancel.is_cancelled() {
+            return Err(crate::error::ConnectionError::Cancelled.into());
+        }
+
+        progress(ConnectPhase::StartingProcess);
+        if sing_box {
+            // sing-box serves the inbounds of the Xray configuration
+            self.start_sing_box_connection(config, &xray_config, mode)
+                .await?;
+        } else {
+            self.start_connection(config, xray_config, targets, mode)
+                .await?;
+        }
+
+        let ready = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(crate::error::ConnectionError::Cancelled.into()),
+            ready = self.wait_for_readiness(&mut progress) => ready,
+        };
+        match ready {
+            Ok(report) => {
+                progress(ConnectPhase::Connected);
+                Ok(report)
+            }
+            Err(e) => {
+                if cancel.is_cancelled() {
+                    info!("Connection cancelled");
+                    self.record_event(SessionEventKind::Failed, "Connection cancelled");
+                }
+                let _ = self.disconnect().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Start a load-balanced connection over multiple proxy configurations
+    ///
+    /// The first configuration is used as the primary one for connection
+    /// bookkeeping and as the balancer's fallback outbound; with
+    /// [`BalancerStrategy::Manual`] the configurations are used in order.
+    pub async fn connect_with_configs_and_strategy(
+        &self,
+        configs: Vec<ProxyServerConfig>,
+        mode: &str,
+        strategy: BalancerStrategy,
+    ) -> crate::V8RayResult<()> {
+        let primary = configs.first().cloned().ok_or_else(|| {
+            crate::error::ConfigError::Validation(
+                "At least one proxy configuration is required".to_string(),
+            )
+        })?;
+
+        info!(
+            "Starting balanced connection over {} servers with mode: {}, strategy: {:?}",
+            configs.len(),
+            mode,
+            strategy
+        );
+
+        let xray_config = self
+            .xray
+            .generate_multi_config_with_mode(&configs, strategy, mode);
+        let targets = configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| ProbeTarget {
+                tag: format!("proxy-{}", index),
+                server: config.server.clone(),
+                port: config.port,
+            })
+            .collect();
+
+        self.start_connection(primary, xray_config, targets, mode)
+            .await
+    }
+
+    /// Record a new connection for `config` and start Xray with `xray_config`
+    async fn start_connection(
+        &self,
+        config: ProxyServerConfig,
+        mut xray_config: XrayConfig,
+        targets: Vec<ProbeTarget>,
+        mode: &str,
+    ) -> crate::V8RayResult<()> {
+        // Switch in place while connected so the proxy is never dropped,
+        // otherwise disconnect the existing connection if any
+        let reloaded =
+            if self.is_connected().await && self.xray.get_status().await == XrayStatus::Running {
+                self.front_inbounds(&mut xray_config);
+                // Prefer swapping outbounds through the Xray API, which keeps
+                // the process and its ports; reload if that is not possible
+                let applied = match self.xray.apply_live(xray_config.clone()).await {
+                    Ok(applied) => applied,
+                    Err(e) => {
+                        warn!("Live switch failed, reloading Xray instead: {}", e);
+                        false
+                    }
+                };
+                if !applied {
+                    debug!("Reloading Xray for new connection");
+                    match self.xray.reload(xray_config.clone()).await {
+                        Ok(reassignments) => self.follow_port_reassignments(&reassignments),
+                        Err(e) => {
+                            error!("Failed to reload Xray, keeping current connection: {}", e);
+                            self.record_event(
+                                SessionEventKind::Failed,
+                                format!("Switching to {} failed: {}", config.name, e),
+                            );
+                            return Err(crate::error::V8RayError::Xray(
+                                crate::error::XrayError::Process(e.to_string()),
+                            ));
+                        }
+                    }
+                }
+                self.archive_current_connection().await;
+                true
+            } else {
+                if self.get_state().await != ConnectionState::Disconnected {
+                    debug!("Disconnecting existing connection");
+                    self.disconnect().await?;
+                }
+                self.front_inbounds(&mut xray_config);
+                false
+            };
+
+        self.begin_connection(&config, targets).await;
+        self.set_active_core(Some(self.xray.clone()));
+
+        // Start Xray with configuration (already running after a reload)
+        let result = if reloaded {
+            Ok(())
+        } else {
+            self.xray.start(xray_config).await
+        };
+        match result {
+            Ok(_) => {
+                info!("Xray started successfully");
+                self.record_event(
+                    SessionEventKind::Connected,
+                    if reloaded {
+                        "Connected (switched without restart)"
+                    } else {
+                        "Connected"
+                    },
+                );
+                {
+                    let mut current = self.current_connection.write().await;
+                    if let Some(ref mut conn) = *current {
+                        conn.state = ConnectionState::Connected;
+                    }
+                }
+
+                // 启动流量统计收集 (每秒收集一次)
+                if !reloaded {
+                    self.start_stats_collection(Duration::from_secs(1)).await;
+                }
+                self.watch_route_suggestions(mode == "smart");
+                self.watch_active_connections();
+                self.start_monitoring_for_reconnect().await;
+                self.start_health_probe().await;
+
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to start Xray: {}", e);
+                Err(self.fail_connection(e).await)
+            }
+        }
+    }
+
+    /// Record a new connection for `config` and run it on sing-box, serving
+    /// the inbounds of `xray_config`
+    ///
+    /// The routing rule sets, app rules, DNS settings and GFWList set for
+    /// Xray are translated into the sing-box configuration, which fails if
+    /// a rule cannot be expressed there. Live switching, error bursts and
+    /// health probes rely on the Xray API and logs, so they are not
+    /// available on sing-box; a sing-box exit still triggers auto-reconnect.
+    async fn start_sing_box_connection(
+        &self,
+        config: ProxyServerConfig,
+        xray_config: &XrayConfig,
+        mode: &str,
+    ) -> crate::V8RayResult<()> {
         if self.get_state().await != ConnectionState::Disconnected {
             debug!("Disconnecting existing connection");
             self.disconnect().await?;
         }
 
+        let mut xray_config = xray_config.clone();
+        self.throttle.apply(&mut xray_config);
+        self.sing_box.set_inbounds(xray_config.inbounds);
+        self.sing_box.set_routing(
+            self.xray.get_routing_rule_sets(),
+            self.xray.app_rules(),
+            self.xray.dns_settings(),
+            self.xray.gfwlist(),
+        );
+        let sing_box_config =
+            ProxyCore::generate_config(&*self.sing_box, &config, mode).map_err(|e| {
+                crate::error::V8RayError::Xray(crate::error::XrayError::InvalidConfig(
+                    e.to_string(),
+                ))
+            })?;
+
+        self.begin_connection(&config, single_probe_target(&config))
+            .await;
+        self.set_active_core(Some(self.sing_box.clone()));
+        match self.sing_box.start(sing_box_config).await {
+            Ok(()) => {
+                info!("sing-box started successfully");
+                self.record_event(SessionEventKind::Connected, "Connected (sing-box)");
+                if let Some(ref mut conn) = *self.current_connection.write().await {
+                    conn.state = ConnectionState::Connected;
+                }
+                self.start_monitoring_for_reconnect().await;
+                Ok(())
+            }
+            Err(e) => {
+                error!("Failed to start sing-box: {}", e);
+                Err(self.fail_connection(e).await)
+            }
+        }
+    }
+
+    /// Start the session timeline and record a connecting connection for
+    /// `config`
+    async fn begin_connection(&self, config: &ProxyServerConfig, targets: Vec<ProbeTarget>) {
+        {
+            let mut timeline = self.timeline_mut();
+            timeline.start_session();
+            timeline.record(
+                SessionEventKind::Connecting,
+                format!(
+                    "Connecting to {} ({}:{})",
+                    config.name, config.server, config.port
+                ),
+            );
+        }
+
         // Create new connection
         let connection = Connection {
             id: Uuid::new_v4(),
@@ -213,37 +702,19 @@ impl ConnectionManager {
             let mut current_config = self.current_config.write().await;
             *current_config = Some(config.clone());
         }
+        *self.probe_targets.write().await = targets;
+    }
 
-        // Generate Xray configuration with mode
-        let xray_config = self.xray.generate_config_with_mode(&config, mode);
-
-        // Start Xray with configuration
-        match self.xray.start(xray_config).await {
-            Ok(_) => {
-                info!("Xray started successfully");
-                let mut current = self.current_connection.write().await;
-                if let Some(ref mut conn) = *current {
-                    conn.state = ConnectionState::Connected;
-                }
-
-                // 启动流量统计收集 (每秒收集一次)
-                self.start_stats_collection(Duration::from_secs(1)).await;
-
-                Ok(())
-            }
-            Err(e) => {
-                error!("Failed to start Xray: {}", e);
-                let error_msg = e.to_string();
-                let mut current = self.current_connection.write().await;
-                if let Some(ref mut conn) = *current {
-                    conn.state = ConnectionState::Error(error_msg.clone());
-                    conn.last_error = Some(ConnectionError::XrayStartFailed(error_msg.clone()));
-                }
-                Err(crate::error::V8RayError::Xray(
-                    crate::error::XrayError::Process(error_msg),
-                ))
-            }
+    /// Mark the current connection as failed to start with `e`
+    async fn fail_connection(&self, e: XrayError) -> crate::error::V8RayError {
+        let error_msg = e.to_string();
+        self.record_event(SessionEventKind::Failed, &error_msg);
+        let mut current = self.current_connection.write().await;
+        if let Some(ref mut conn) = *current {
+            conn.state = ConnectionState::Error(error_msg.clone());
+            conn.last_error = Some(ConnectionError::XrayStartFailed(error_msg.clone()));
         }
+        crate::error::V8RayError::Xray(crate::error::XrayError::Process(error_msg))
     }
 
     /// Start a new connection (legacy method for compatibility)
@@ -266,6 +737,7 @@ impl ConnectionManager {
             tags: vec![],
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            raw_name: None,
         };
 
         self.connect_with_config(config).await
@@ -275,6 +747,10 @@ impl ConnectionManager {
     pub async fn disconnect(&self) -> crate::V8RayResult<()> {
         info!("Disconnecting current connection");
 
+        // A user disconnect ends any reconnect series
+        self.cancel_reconnect_task().await;
+        *self.retry_started.write().await = None;
+
         // Update state to disconnecting
         {
             let mut current = self.current_connection.write().await;
@@ -283,40 +759,58 @@ impl ConnectionManager {
             }
         }
 
-        // Stop Xray
-        match self.xray.stop().await {
+        // Stop the core, Xray unless another one runs the connection
+        let core = self
+            .active_core
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .unwrap_or_else(|| self.xray.clone());
+        match core.stop().await {
             Ok(_) => {
-                info!("Xray stopped successfully");
+                info!("{} stopped successfully", core.kind());
             }
             Err(e) => {
-                warn!("Error stopping Xray: {}", e);
+                warn!("Error stopping {}: {}", core.kind(), e);
             }
         }
+        self.throttle.stop();
+        self.script_router.stop();
 
         // Update connection state and move to history
+        self.archive_current_connection().await;
+
+        // Clear current config
+        {
+            let mut current_config = self.current_config.write().await;
+            *current_config = None;
+        }
+        self.probe_targets.write().await.clear();
+        self.suggestions_mut().set_active(false);
+        self.connection_tracker
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+
+        Ok(())
+    }
+
+    /// Mark the current connection as disconnected and move it to history
+    async fn archive_current_connection(&self) {
         let mut current = self.current_connection.write().await;
-        if let Some(ref mut conn) = *current {
+        if let Some(mut conn) = current.take() {
             conn.state = ConnectionState::Disconnected;
+            self.record_event(SessionEventKind::Disconnected, "Disconnected");
 
             // Move to history
             let mut history = self.history.write().await;
-            history.push(conn.clone());
+            history.push(conn);
 
             // Keep only last 100 connections in history
             if history.len() > 100 {
                 history.remove(0);
             }
         }
-
-        *current = None;
-
-        // Clear current config
-        {
-            let mut current_config = self.current_config.write().await;
-            *current_config = None;
-        }
-
-        Ok(())
     }
 
     /// Get current connection info
@@ -344,14 +838,421 @@ impl ConnectionManager {
         // Update stats collector
         self.stats_collector.update_traffic(upload, download).await;
 
+        // Attribute the traffic to the current server in the history
+        let history = self.traffic_history.read().await.clone();
+        if let Some(history) = history {
+            let server_id = self
+                .current_config
+                .read()
+                .await
+                .as_ref()
+                .map(|c| c.id.clone());
+            if let Some(server_id) = server_id {
+                if let Err(e) = history
+                    .record(&server_id, chrono::Utc::now(), upload, download)
+                    .await
+                {
+                    warn!("Failed to record traffic history: {}", e);
+                }
+            }
+        }
+
         Ok(())
     }
 
+    /// Wait until the current connection serves traffic
+    ///
+    /// Xray being started does not mean the proxy works yet; this waits for
+    /// the local inbound and a first request through it, bounded by the
+    /// readiness timeout.
+    pub async fn wait_until_ready(&self) -> crate::V8RayResult<ReadinessReport> {
+        self.wait_for_readiness(|_| {}).await
+    }
+
+    /// Wait until ready, calling `on_phase` as each check starts
+    async fn wait_for_readiness(
+        &self,
+        on_phase: impl FnMut(ConnectPhase),
+    ) -> crate::V8RayResult<ReadinessReport> {
+        let xray_config = match self.xray.running_config().await {
+            Some(config) => config,
+            // sing-box serves the inbounds Xray would, which is all
+            // readiness checks look at
+            None if self.active_core_kind() == Some(CoreKind::SingBox) => {
+                let config = self
+                    .get_current_config()
+                    .await
+                    .ok_or(crate::error::ConnectionError::NotConnected)?;
+                self.xray.generate_config(&config)
+            }
+            None => return Err(crate::error::ConnectionError::NotConnected.into()),
+        };
+        let readiness = self.readiness_config.read().await.clone();
+
+        match readiness::wait_until_ready_with_phases(&xray_config, &readiness, on_phase).await {
+            Ok(report) => {
+                info!("Proxy ready: {:?}", report);
+                self.record_event(SessionEventKind::Ready, "Proxy ready");
+                Ok(report)
+            }
+            Err(e) => {
+                warn!("Proxy readiness check failed: {}", e);
+                self.record_event(SessionEventKind::Failed, format!("Proxy not ready: {}", e));
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Set readiness check configuration
+    pub async fn set_readiness_config(&self, config: ReadinessConfig) {
+        *self.readiness_config.write().await = config;
+    }
+
     /// Get current proxy configuration
     pub async fn get_current_config(&self) -> Option<ProxyServerConfig> {
         self.current_config.read().await.clone()
     }
 
+    /// Get aggregated health of the Xray instance and all active outbounds
+    ///
+    /// Outbounds are probed in parallel. This complements
+    /// [`get_xray_status`](Self::get_xray_status), which only reports the
+    /// process status.
+    pub async fn get_aggregated_health(&self) -> AggregatedHealth {
+        let instance = self.xray.get_instance_health().await;
+        let main_stopped = matches!(instance.status, XrayStatus::Stopped);
+
+        let mut targets = Vec::new();
+        if !main_stopped {
+            for target in self.probe_targets.read().await.iter() {
+                targets.push((DEFAULT_INSTANCE_ID.to_string(), target.clone()));
+            }
+        }
+        targets.extend(self.instances.probe_targets().await);
+        let outbounds = probe_instance_outbounds(&targets, DEFAULT_PROBE_TIMEOUT).await;
+
+        // A stopped main connection does not degrade instances of other profiles
+        let others = self.instances.list().await;
+        let mut instances = Vec::with_capacity(1 + others.len());
+        if !main_stopped || others.is_empty() {
+            instances.push(instance);
+        }
+        instances.extend(others);
+
+        let health = AggregatedHealth::new(instances, outbounds);
+        self.timeline_mut().record_health(health.summary);
+        health
+    }
+
+    /// Get the event timeline of the current (or last) session, oldest first
+    pub fn get_timeline(&self) -> Vec<SessionEvent> {
+        self.timeline
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .events()
+    }
+
+    /// Append an event to the session timeline
+    ///
+    /// Used for events originating outside the connection manager, such as
+    /// proxy mode changes or the system proxy being re-applied.
+    pub fn record_event(&self, kind: SessionEventKind, message: impl Into<String>) {
+        self.timeline_mut().record(kind, message);
+    }
+
+    /// Lock the timeline for writing
+    fn timeline_mut(&self) -> std::sync::RwLockWriteGuard<'_, SessionTimeline> {
+        self.timeline.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Get current route suggestions (smart mode only), most failures first
+    pub fn get_route_suggestions(&self) -> Vec<RouteSuggestion> {
+        self.route_suggestions
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .suggestions()
+    }
+
+    /// Accept a route suggestion
+    ///
+    /// Writes the rule into the "suggested" set of `rule_sets` and returns
+    /// it. The caller saves the rule sets and applies them to Xray, so the
+    /// rule outlives the session.
+    pub fn accept_route_suggestion(
+        &self,
+        id: &str,
+        rule_sets: &mut Vec<RoutingRuleSet>,
+    ) -> Option<RoutingRule> {
+        let suggestion = self.suggestions_mut().take(id)?;
+        let rule = suggestion.apply_to(rule_sets);
+
+        info!("Accepted route suggestion: {}", suggestion.description());
+        self.record_event(SessionEventKind::RouteChanged, suggestion.description());
+        Some(rule)
+    }
+
+    /// Probe the direct preference domains
+    ///
+    /// Each domain is requested directly and through the HTTP inbound; pass
+    /// the results to [`apply_direct_preference`](Self::apply_direct_preference)
+    /// to update the routing rules. Not available in TUN mode, where direct
+    /// requests would be captured by the tunnel.
+    pub async fn probe_direct_preference(
+        &self,
+        settings: &DirectPreferenceSettings,
+    ) -> crate::V8RayResult<Vec<DirectProbeResult>> {
+        if !settings.enabled {
+            return Err(crate::error::ConnectionError::Failed(
+                "Direct preference is disabled".to_string(),
+            )
+            .into());
+        }
+        if self.xray.outbound_interface().is_some() {
+            return Err(crate::error::ConnectionError::Failed(
+                "Direct preference is not available in TUN mode".to_string(),
+            )
+            .into());
+        }
+
+        let xray_config = self
+            .xray
+            .running_config()
+            .await
+            .ok_or(crate::error::ConnectionError::NotConnected)?;
+        let inbound = xray_config
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == "http")
+            .ok_or_else(|| {
+                crate::error::ConnectionError::Failed("No HTTP inbound to probe".to_string())
+            })?;
+
+        let results = futures::future::join_all(settings.probe_domains.iter().map(|domain| {
+            direct_preference::probe_domain(
+                domain,
+                inbound,
+                settings,
+                direct_preference::DEFAULT_PROBE_TIMEOUT,
+            )
+        }))
+        .await;
+        Ok(results)
+    }
+
+    /// Write direct preference probe results into `rule_sets`
+    ///
+    /// Domains that work directly without being slower than allowed go to
+    /// the "direct-preferred" rule set. The caller saves the rule sets and
+    /// applies them to Xray. Returns whether the rule sets changed.
+    pub fn apply_direct_preference(
+        &self,
+        results: &[DirectProbeResult],
+        rule_sets: &mut Vec<RoutingRuleSet>,
+    ) -> bool {
+        if !direct_preference::apply_results(results, rule_sets) {
+            return false;
+        }
+        let direct = results.iter().filter(|result| result.prefer_direct).count();
+        info!("Direct preference updated: {} domain(s) direct", direct);
+        self.record_event(
+            SessionEventKind::RouteChanged,
+            format!("Direct preference: {} domain(s) routed direct", direct),
+        );
+        true
+    }
+
+    /// Dismiss a route suggestion so it is not suggested again
+    pub fn dismiss_route_suggestion(&self, id: &str) -> bool {
+        self.suggestions_mut().dismiss(id)
+    }
+
+    /// Lock the route suggestion tracker for writing
+    fn suggestions_mut(&self) -> std::sync::RwLockWriteGuard<'_, RouteSuggestionTracker> {
+        self.route_suggestions
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Enable or disable direct-failure tracking and make sure Xray logs are
+    /// fed to the tracker
+    fn watch_route_suggestions(&self, active: bool) {
+        self.suggestions_mut().set_active(active);
+        if !active || self.suggestion_watcher.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let mut event_rx = self.subscribe_xray_events();
+        let tracker = Arc::clone(&self.route_suggestions);
+        let watcher = Arc::clone(&self.suggestion_watcher);
+        tokio::spawn(async move {
+            loop {
+                match event_rx.recv().await {
+                    Ok(XrayEvent::LogReceived(log)) => tracker
+                        .write()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .record_log(&log.message),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+            watcher.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Make sure Xray access logs are fed to the connection tracker and the
+    /// domain analytics
+    fn watch_active_connections(&self) {
+        if self.is_low_memory() || self.connection_watcher.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        let mut event_rx = self.subscribe_xray_events();
+        let manager = self.handle();
+        tokio::spawn(async move {
+            let mut flush = tokio::time::interval(ACCESS_LOG_FLUSH_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = event_rx.recv() => match event {
+                        Ok(XrayEvent::LogReceived(_)) if manager.is_low_memory() => {}
+                        Ok(XrayEvent::LogReceived(log)) => {
+                            let now = chrono::Utc::now();
+                            manager
+                                .connection_tracker
+                                .write()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .record_log(&log.message, now);
+                            manager
+                                .access_analytics
+                                .write()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .record_log(&log.message, now);
+                        }
+                        Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = flush.tick() => {
+                        if let Err(e) = manager.flush_access_log().await {
+                            warn!("Failed to persist access log: {}", e);
+                        }
+                    }
+                }
+            }
+            manager.connection_watcher.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Persist access log records to `storage` as they are seen
+    ///
+    /// Records are written in batches by the log watcher.
+    pub async fn set_access_log_storage(&self, storage: Arc<AccessLogStorage>) {
+        *self.access_log_storage.write().await = Some(storage);
+        self.access_analytics
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .set_persist(true);
+    }
+
+    /// Get the persistent access log, if enabled
+    pub async fn get_access_log_storage(&self) -> Option<Arc<AccessLogStorage>> {
+        self.access_log_storage.read().await.clone()
+    }
+
+    /// Write access log records not yet persisted
+    pub async fn flush_access_log(&self) -> crate::V8RayResult<()> {
+        let storage = self.access_log_storage.read().await.clone();
+        let Some(storage) = storage else {
+            return Ok(());
+        };
+        let pending = self
+            .access_analytics
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .take_pending();
+        storage.append(&pending).await?;
+        Ok(())
+    }
+
+    /// Access log records seen at or after `since`
+    ///
+    /// Reads the persistent access log if enabled, so history survives
+    /// restarts; otherwise only the in-memory buffer is available.
+    async fn access_log_records(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> crate::V8RayResult<Vec<AccessLogRecord>> {
+        let storage = self.access_log_storage.read().await.clone();
+        if let Some(storage) = storage {
+            self.flush_access_log().await?;
+            return Ok(storage.load_since(since).await?);
+        }
+        Ok(self
+            .access_analytics
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .records_since(since)
+            .cloned()
+            .collect())
+    }
+
+    /// Most visited domains since `since`
+    pub async fn get_top_domains(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> crate::V8RayResult<Vec<DomainCount>> {
+        let records = self.access_log_records(since).await?;
+        Ok(access_analytics::top_domains(&records, limit))
+    }
+
+    /// Most blocked domains since `since`
+    pub async fn get_blocked_domains(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+        limit: usize,
+    ) -> crate::V8RayResult<Vec<DomainCount>> {
+        let records = self.access_log_records(since).await?;
+        Ok(access_analytics::blocked_domains(&records, limit))
+    }
+
+    /// Connections by route (proxy / direct / blocked) since `since`
+    pub async fn get_route_split(
+        &self,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> crate::V8RayResult<RouteSplit> {
+        let records = self.access_log_records(since).await?;
+        Ok(access_analytics::route_split(&records))
+    }
+
+    /// Connections of the current session seen recently in Xray access logs
+    pub fn get_active_connections(&self) -> Vec<ActiveConnection> {
+        self.connection_tracker
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .list(chrono::Utc::now())
+    }
+
+    /// Close all proxied connections
+    ///
+    /// Xray cannot close a single connection, so the process is restarted
+    /// with the same configuration, which drops every open connection.
+    pub async fn close_all_connections(&self) -> crate::V8RayResult<()> {
+        if !self.xray.is_running().await {
+            return Ok(());
+        }
+        self.xray.restart().await.map_err(|e| {
+            crate::error::V8RayError::Xray(crate::error::XrayError::Process(e.to_string()))
+        })?;
+        self.connection_tracker
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.record_event(
+            SessionEventKind::RouteChanged,
+            "Closed all connections".to_string(),
+        );
+        Ok(())
+    }
+
     /// Get Xray status
     pub async fn get_xray_status(&self) -> XrayStatus {
         self.xray.get_status().await
@@ -424,22 +1325,189 @@ impl ConnectionManager {
         Arc::clone(&self.xray)
     }
 
-    /// Set reconnect configuration
-    pub async fn set_reconnect_config(&self, config: ReconnectConfig) {
-        let mut reconnect_config = self.reconnect_config.write().await;
-        *reconnect_config = config;
-        info!("Reconnect configuration updated: {:?}", reconnect_config);
+    /// Get the sing-box core
+    pub fn get_sing_box(&self) -> Arc<SingBoxCore> {
+        Arc::clone(&self.sing_box)
     }
 
-    /// Get reconnect configuration
-    pub async fn get_reconnect_config(&self) -> ReconnectConfig {
-        self.reconnect_config.read().await.clone()
+    /// Core running the current connection, None while disconnected
+    pub fn active_core(&self) -> Option<Arc<dyn ProxyCore>> {
+        self.active_core
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
     }
 
-    /// Enable auto-reconnect
-    pub async fn enable_auto_reconnect(&self) {
-        let mut config = self.reconnect_config.write().await;
-        config.enabled = true;
+    /// Kind of [`Self::active_core`]
+    pub fn active_core_kind(&self) -> Option<CoreKind> {
+        self.active_core().map(|core| core.kind())
+    }
+
+    /// Set the core running the current connection
+    fn set_active_core(&self, core: Option<Arc<dyn ProxyCore>>) {
+        *self.active_core.write().unwrap_or_else(|e| e.into_inner()) = core;
+    }
+
+    /// Run following connections on `kind`, except servers only the other
+    /// core supports
+    pub fn set_core_kind(&self, kind: CoreKind) {
+        *self.core_kind.write().unwrap_or_else(|e| e.into_inner()) = kind;
+    }
+
+    /// Core following connections run on
+    pub fn core_kind(&self) -> CoreKind {
+        *self.core_kind.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the upload and download limits of the local inbounds
+    ///
+    /// New rates apply at once; turning limiting on or off takes effect
+    /// with the next connection (see [`InboundThrottle::set_limit`]).
+    pub fn set_rate_limit(&self, limit: RateLimit) {
+        self.throttle.set_limit(limit);
+    }
+
+    /// Upload and download limits of the local inbounds
+    pub fn rate_limit(&self) -> RateLimit {
+        self.throttle.limit()
+    }
+
+    /// Apply the saved proxy, inbound, DNS and routing settings of `config`
+    /// to subsequent connections
+    ///
+    /// A routing script that does not compile is skipped with a warning.
+    pub fn apply_config(&self, config: &Config) {
+        self.xray
+            .set_inbound_ports(config.proxy.http_port, config.proxy.socks_port);
+        self.xray
+            .set_port_conflict_policy(config.proxy.port_conflict);
+        self.xray.set_inbound_settings(config.inbound.clone());
+        self.xray.set_dns_settings(config.dns.clone());
+        self.xray
+            .set_routing_rule_sets(config.routing_rules.clone());
+        self.xray.set_app_rules(config.app_rules.clone());
+        if let Err(e) = self.set_script_routing(config.script_routing.clone()) {
+            warn!("Failed to apply the saved routing script: {}", e);
+        }
+    }
+
+    /// Set the routing script settings
+    ///
+    /// See [`ScriptRouter::set_settings`] for when the change applies.
+    pub fn set_script_routing(
+        &self,
+        settings: ScriptRoutingSettings,
+    ) -> Result<(), crate::error::ConfigError> {
+        self.script_router.set_settings(settings)
+    }
+
+    /// Routing script settings
+    pub fn script_routing(&self) -> ScriptRoutingSettings {
+        self.script_router.settings()
+    }
+
+    /// Front the local inbounds of `xray_config` with the script router and
+    /// the speed limit forwarders
+    ///
+    /// The forwarders go behind the router, so traffic the script sends to
+    /// its internal inbounds is limited as well.
+    fn front_inbounds(&self, xray_config: &mut XrayConfig) {
+        self.script_router.apply(xray_config);
+        self.throttle.apply(xray_config);
+    }
+
+    /// Point the inbound fronts at the ports a hot reload moved Xray's
+    /// inbounds to
+    pub fn follow_port_reassignments(&self, reassignments: &[PortReassignment]) {
+        // Xray kept the moved port of a fronted inbound for later configs,
+        // which must keep the public port the front listens on instead
+        let (mut http_port, mut socks_port) = self.xray.inbound_ports();
+        for reassignment in reassignments {
+            let port = match reassignment.protocol.as_str() {
+                "http" => &mut http_port,
+                "socks" => &mut socks_port,
+                _ => continue,
+            };
+            let public = self.public_inbound_port(reassignment.old_port);
+            if *port == reassignment.new_port && public != reassignment.old_port {
+                *port = public;
+            }
+        }
+        self.xray.set_inbound_ports(http_port, socks_port);
+
+        self.throttle.follow(reassignments);
+        self.script_router.follow(reassignments);
+    }
+
+    /// Port local applications use for the inbound Xray serves on `port`,
+    /// which differs while the inbound is speed limited or routed by script
+    pub fn public_inbound_port(&self, port: u16) -> u16 {
+        self.script_router
+            .public_port(self.throttle.public_port(port))
+    }
+
+    /// Xray instances running next to the main connection
+    pub fn instances(&self) -> Arc<XrayInstances> {
+        Arc::clone(&self.instances)
+    }
+
+    /// Start an Xray instance for `config` next to the main connection
+    ///
+    /// The instance gets its own HTTP and SOCKS ports and proxy `mode`, and
+    /// otherwise the routing, DNS, inbound, engine log and resource limit
+    /// settings of the main connection. Its ports must differ from those of
+    /// the running main connection and of the other instances.
+    pub async fn start_instance(
+        &self,
+        instance_id: &str,
+        config: &ProxyServerConfig,
+        mode: &str,
+        http_port: u16,
+        socks_port: u16,
+    ) -> Result<(), XrayError> {
+        let core = XrayCore::for_instance(instance_id);
+        core.set_routing_rule_sets(self.xray.get_routing_rule_sets());
+        core.set_dns_settings(self.xray.dns_settings());
+        core.set_inbound_settings(self.xray.inbound_settings());
+        core.set_engine_log_level(self.xray.engine_log_level());
+        core.set_outbound_interface(self.xray.outbound_interface());
+        core.set_port_conflict_policy(self.xray.port_conflict_policy());
+        core.set_resource_limits(self.xray.resource_limits());
+        core.set_inbound_ports(http_port, socks_port);
+        let xray_config = core.generate_config_with_mode(config, mode);
+
+        let reserved = match self.xray.running_config().await {
+            Some(running) => crate::xray::instances::inbound_ports(&running),
+            None => Vec::new(),
+        };
+        self.instances
+            .start(core, xray_config, &reserved, single_probe_target(config))
+            .await
+    }
+
+    /// Stop an Xray instance started with [`start_instance`](Self::start_instance)
+    ///
+    /// Returns whether the instance existed.
+    pub async fn stop_instance(&self, instance_id: &str) -> Result<bool, XrayError> {
+        self.instances.stop(instance_id).await
+    }
+
+    /// Set reconnect configuration
+    pub async fn set_reconnect_config(&self, config: ReconnectConfig) {
+        let mut reconnect_config = self.reconnect_config.write().await;
+        *reconnect_config = config;
+        info!("Reconnect configuration updated: {:?}", reconnect_config);
+    }
+
+    /// Get reconnect configuration
+    pub async fn get_reconnect_config(&self) -> ReconnectConfig {
+        self.reconnect_config.read().await.clone()
+    }
+
+    /// Enable auto-reconnect
+    pub async fn enable_auto_reconnect(&self) {
+        let mut config = self.reconnect_config.write().await;
+        config.enabled = true;
         info!("Auto-reconnect enabled");
     }
 
@@ -462,8 +1530,14 @@ impl ConnectionManager {
         }
     }
 
-    /// Start auto-reconnect task
-    pub async fn start_auto_reconnect(&self) {
+    /// Start auto-reconnect after a failure of `class`
+    ///
+    /// Attempts continue the current series: the attempt counter and the
+    /// retry window are only reset once a reconnected connection has been
+    /// up for the configured stable time. Progress is sent to
+    /// [`subscribe_reconnect_events`](Self::subscribe_reconnect_events)
+    /// subscribers.
+    pub async fn start_auto_reconnect(&self, class: FailureClass) {
         // Cancel any existing reconnect task
         self.cancel_reconnect_task().await;
 
@@ -473,15 +1547,12 @@ impl ConnectionManager {
             return;
         }
 
-        let current_config = self.current_config.read().await.clone();
-        if current_config.is_none() {
+        if self.current_config.read().await.is_none() {
             warn!("No configuration available for reconnection");
             return;
         }
 
-        let _proxy_config = current_config.unwrap();
-
-        info!("Starting auto-reconnect task");
+        info!("Starting auto-reconnect task ({})", class.as_str());
 
         // Create cancellation channel
         let (cancel_tx, mut cancel_rx) = broadcast::channel::<()>(1);
@@ -490,135 +1561,700 @@ impl ConnectionManager {
             *tx = Some(cancel_tx);
         }
 
-        // Clone necessary data for the task
-        let current_connection = Arc::clone(&self.current_connection);
-        let xray = Arc::clone(&self.xray);
-        let current_config_arc = Arc::clone(&self.current_config);
-        let reconnect_config_arc = Arc::clone(&self.reconnect_config);
+        let series_start = *self
+            .retry_started
+            .write()
+            .await
+            .get_or_insert_with(Instant::now);
+        let manager = self.handle();
 
         // Spawn reconnect task with loop
         tokio::spawn(async move {
             loop {
                 // Get current attempts
                 let attempts = {
-                    let current = current_connection.read().await;
+                    let current = manager.current_connection.read().await;
                     current.as_ref().map(|c| c.reconnect_attempts).unwrap_or(0)
                 };
 
                 // Check if we should continue
-                let config = reconnect_config_arc.read().await.clone();
-                if !config.should_reconnect(attempts) {
-                    warn!("Reconnection not allowed (attempts: {})", attempts);
-                    break;
+                let config = manager.reconnect_config.read().await.clone();
+                if let Some(reason) = config.retry_denial(class, attempts, series_start.elapsed()) {
+                    warn!("Giving up reconnecting: {}", reason);
+                    manager.record_event(
+                        SessionEventKind::Failed,
+                        format!("Auto-reconnect gave up: {}", reason),
+                    );
+                    *manager.retry_started.write().await = None;
+                    let _ = manager
+                        .reconnect_events
+                        .send(ReconnectEvent::ReconnectFailed {
+                            class,
+                            attempts,
+                            reason,
+                        });
+                    return;
                 }
 
-                let delay = config.calculate_delay(attempts);
+                let delay = config.delay_for(class, attempts);
                 info!(
                     "Waiting {:?} before reconnection attempt {}",
                     delay,
                     attempts + 1
                 );
+                let _ = manager.reconnect_events.send(ReconnectEvent::Reconnecting {
+                    class,
+                    attempt: attempts + 1,
+                    delay,
+                });
 
                 // Wait for delay or cancellation
                 tokio::select! {
-                    _ = tokio::time::sleep(delay) => {
-                        info!("Attempting to reconnect (attempt {})...", attempts + 1);
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = cancel_rx.recv() => {
+                        info!("Reconnect task cancelled");
+                        return;
+                    }
+                }
 
-                        // Increment reconnect attempts
+                info!("Attempting to reconnect (attempt {})...", attempts + 1);
+                manager.record_event(
+                    SessionEventKind::ReconnectAttempt,
+                    format!("Reconnect attempt {}", attempts + 1),
+                );
+                crate::metrics::record_reconnect_attempt();
+
+                // Increment reconnect attempts
+                {
+                    let mut current = manager.current_connection.write().await;
+                    if let Some(ref mut conn) = *current {
+                        conn.reconnect_attempts += 1;
+                        conn.state = ConnectionState::Reconnecting;
+                    }
+                }
+
+                let Some(cfg) = manager.current_config.read().await.clone() else {
+                    warn!("No configuration available for reconnection");
+                    return;
+                };
+
+                // Attempt to start the core again
+                crate::metrics::record_xray_restart();
+                let restarted = if manager.active_core_kind() == Some(CoreKind::SingBox) {
+                    manager.sing_box.restart().await
+                } else {
+                    let mut xray_config = manager.xray.generate_config(&cfg);
+                    manager.front_inbounds(&mut xray_config);
+                    manager.xray.start(xray_config).await
+                };
+                match restarted {
+                    Ok(_) => {
+                        info!("Reconnection successful");
+                        manager.record_event(SessionEventKind::Reconnected, "Reconnected");
                         {
-                            let mut current = current_connection.write().await;
+                            let mut current = manager.current_connection.write().await;
                             if let Some(ref mut conn) = *current {
-                                conn.reconnect_attempts += 1;
-                                conn.state = ConnectionState::Reconnecting;
+                                conn.state = ConnectionState::Connected;
+                                conn.last_error = None;
                             }
                         }
-
-                        // Get current config
-                        let config_to_use = {
-                            let cfg = current_config_arc.read().await;
-                            cfg.clone()
-                        };
-
-                        if let Some(cfg) = config_to_use {
-                            // Generate Xray configuration
-                            let xray_config = {
-                                let temp_xray = XrayCore::new();
-                                temp_xray.generate_config(&cfg)
-                            };
-
-                            // Attempt to start Xray
-                            match xray.start(xray_config).await {
-                                Ok(_) => {
-                                    info!("Reconnection successful");
-                                    // Reset attempts and update state
-                                    let mut current = current_connection.write().await;
-                                    if let Some(ref mut conn) = *current {
-                                        conn.reconnect_attempts = 0;
-                                        conn.state = ConnectionState::Connected;
-                                        conn.last_error = None;
-                                    }
-                                    break; // Exit loop on success
-                                }
-                                Err(e) => {
-                                    error!("Reconnection failed: {}", e);
-                                    let mut current = current_connection.write().await;
-                                    if let Some(ref mut conn) = *current {
-                                        conn.state = ConnectionState::Error(e.to_string());
-                                        conn.last_error = Some(ConnectionError::XrayStartFailed(e.to_string()));
-                                    }
-                                    // Continue loop to try again
-                                }
-                            }
-                        } else {
-                            warn!("No configuration available for reconnection");
-                            break;
+                        let _ = manager.reconnect_events.send(ReconnectEvent::Reconnected {
+                            attempts: attempts + 1,
+                        });
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Reconnection failed: {}", e);
+                        manager.record_event(
+                            SessionEventKind::Failed,
+                            format!("Reconnection failed: {}", e),
+                        );
+                        let mut current = manager.current_connection.write().await;
+                        if let Some(ref mut conn) = *current {
+                            conn.state = ConnectionState::Error(e.to_string());
+                            conn.last_error = Some(ConnectionError::XrayStartFailed(e.to_string()));
                         }
+                        // Continue loop to try again
                     }
-                    _ = cancel_rx.recv() => {
-                        info!("Reconnect task cancelled");
-                        break;
+                }
+            }
+
+            // Only a connection that stays up ends the series
+            let stable_after = manager.reconnect_config.read().await.stable_reset_after;
+            tokio::select! {
+                _ = tokio::time::sleep(stable_after) => {
+                    if manager.get_state().await == ConnectionState::Connected {
+                        debug!("Connection stable for {:?}, resetting reconnect attempts", stable_after);
+                        manager.reset_reconnect_attempts().await;
+                        *manager.retry_started.write().await = None;
                     }
                 }
+                _ = cancel_rx.recv() => {}
             }
         });
     }
 
-    /// Monitor Xray events and trigger auto-reconnect on errors
+    /// Subscribe to auto-reconnect progress
+    pub fn subscribe_reconnect_events(&self) -> broadcast::Receiver<ReconnectEvent> {
+        self.reconnect_events.subscribe()
+    }
+
+    /// Subscribe to latency alerts of the health probe
+    pub fn subscribe_latency_alerts(&self) -> broadcast::Receiver<LatencyAlert> {
+        self.latency_alerts.subscribe()
+    }
+
+    /// Monitor core events and trigger auto-reconnect on errors
+    ///
+    /// While connected, an Xray crash or sing-box exit moves the connection
+    /// to the error state and reconnects, and so does a burst of upstream
+    /// failures in the Xray logs (see [`ErrorBurstDetector`]). Started once;
+    /// later calls do nothing.
     pub async fn start_monitoring_for_reconnect(&self) {
+        if self.reconnect_monitor.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
         let mut event_rx = self.subscribe_xray_events();
+        let mut exit_rx = self.sing_box.subscribe_exits();
+        let manager = self.handle();
 
-        let manager = Self {
+        tokio::spawn(async move {
+            let mut errors = ErrorBurstDetector::default();
+            loop {
+                let event = tokio::select! {
+                    event = event_rx.recv() => event,
+                    exit = exit_rx.recv() => {
+                        if let Ok(details) = exit {
+                            if manager.active_core_kind() == Some(CoreKind::SingBox)
+                                && manager.mark_crashed(&details).await
+                            {
+                                warn!("sing-box crashed, triggering auto-reconnect: {}", details);
+                                manager.record_event(
+                                    SessionEventKind::Failed,
+                                    format!("sing-box crashed: {}", details),
+                                );
+                                errors.reset();
+                                manager.start_auto_reconnect(FailureClass::Crash).await;
+                            }
+                        }
+                        continue;
+                    }
+                };
+                match event {
+                    Ok(XrayEvent::StatusChanged(XrayStatus::Error(details))) => {
+                        if manager.mark_crashed(&details).await {
+                            warn!("Xray crashed, triggering auto-reconnect: {}", details);
+                            manager.record_event(
+                                SessionEventKind::Failed,
+                                format!("Xray crashed: {}", details),
+                            );
+                            errors.reset();
+                            manager.start_auto_reconnect(FailureClass::Crash).await;
+                        }
+                    }
+                    Ok(XrayEvent::ErrorDetected(error)) => {
+                        debug!("Xray reported {:?}: {}", error.kind, error.message);
+                        if errors.record_error(&error, std::time::Instant::now())
+                            && manager.is_connected().await
+                        {
+                            warn!(
+                                "Repeated upstream errors in Xray logs ({:?}), triggering auto-reconnect",
+                                error.kind
+                            );
+                            manager.record_event(
+                                SessionEventKind::Failed,
+                                format!("Repeated upstream errors: {}", error.message),
+                            );
+                            manager
+                                .start_auto_reconnect(FailureClass::NetworkUnreachable)
+                                .await;
+                        }
+                    }
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+            manager.reconnect_monitor.store(false, Ordering::SeqCst);
+        });
+    }
+
+    /// Move a connected connection to the error state after an Xray crash
+    ///
+    /// Returns false if there was no established connection, e.g. when a
+    /// reconnect attempt failed; that case is handled by the reconnect task.
+    async fn mark_crashed(&self, details: &str) -> bool {
+        let mut current = self.current_connection.write().await;
+        match current.as_mut() {
+            Some(conn) if conn.state == ConnectionState::Connected => {
+                conn.state = ConnectionState::Error(details.to_string());
+                conn.last_error = Some(ConnectionError::XrayCrashed(details.to_string()));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Handle sharing this manager's state, for background tasks
+    fn handle(&self) -> Self {
+        Self {
             current_connection: Arc::clone(&self.current_connection),
             history: Arc::clone(&self.history),
             xray: Arc::clone(&self.xray),
             current_config: Arc::clone(&self.current_config),
             reconnect_config: Arc::clone(&self.reconnect_config),
+            readiness_config: Arc::clone(&self.readiness_config),
             reconnect_cancel_tx: Arc::clone(&self.reconnect_cancel_tx),
             stats_collector: Arc::clone(&self.stats_collector),
+            probe_targets: Arc::clone(&self.probe_targets),
+            timeline: Arc::clone(&self.timeline),
+            route_suggestions: Arc::clone(&self.route_suggestions),
+            suggestion_watcher: Arc::clone(&self.suggestion_watcher),
+            health_probe_config: Arc::clone(&self.health_probe_config),
+            health_probe: Arc::clone(&self.health_probe),
+            health_probe_running: Arc::clone(&self.health_probe_running),
+            traffic_poller: Arc::clone(&self.traffic_poller),
+            traffic_history: Arc::clone(&self.traffic_history),
+            latency_history: Arc::clone(&self.latency_history),
+            connection_tracker: Arc::clone(&self.connection_tracker),
+            connection_watcher: Arc::clone(&self.connection_watcher),
+            access_analytics: Arc::clone(&self.access_analytics),
+            access_log_storage: Arc::clone(&self.access_log_storage),
+            reconnect_monitor: Arc::clone(&self.reconnect_monitor),
+            retry_started: Arc::clone(&self.retry_started),
+            reconnect_events: self.reconnect_events.clone(),
+            latency_alerts: self.latency_alerts.clone(),
+            speed_results: Arc::clone(&self.speed_results),
+            unlock_results: Arc::clone(&self.unlock_results),
+            instances: Arc::clone(&self.instances),
+            low_memory: Arc::clone(&self.low_memory),
+            core_kind: Arc::clone(&self.core_kind),
+            sing_box: Arc::clone(&self.sing_box),
+            active_core: Arc::clone(&self.active_core),
+            throttle: Arc::clone(&self.throttle),
+            script_router: Arc::clone(&self.script_router),
+        }
+    }
+
+    /// Measure download and upload throughput of the current server
+    ///
+    /// Runs through the local HTTP inbound, so the result reflects the whole
+    /// path through the proxy. `progress` receives intermediate results.
+    /// The result is recorded for [`Self::get_speed_ranking`].
+    pub async fn run_speed_test(
+        &self,
+        config: SpeedTestConfig,
+        progress: impl FnMut(SpeedTestProgress),
+    ) -> crate::V8RayResult<SpeedTestResult> {
+        config.validate()?;
+        let server_id = self
+            .current_config
+            .read()
+            .await
+            .as_ref()
+            .map(|config| config.id.clone())
+            .ok_or(crate::error::ConnectionError::NotConnected)?;
+        let xray_config = self
+            .xray
+            .running_config()
+            .await
+            .ok_or(crate::error::ConnectionError::NotConnected)?;
+        let inbound = xray_config
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == "http")
+            .ok_or_else(|| {
+                crate::error::ConnectionError::Failed(
+                    "No HTTP inbound to run the speed test through".to_string(),
+                )
+            })?;
+
+        info!("Running speed test for server {}", server_id);
+        let result = speed_test::SpeedTester::through_inbound(inbound, config)?
+            .run(&server_id, progress)
+            .await?;
+        info!(
+            "Speed test finished: {:.1} Mbps down, {:?} Mbps up",
+            result.download_mbps, result.upload_mbps
+        );
+        self.speed_results
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(result.clone());
+        Ok(result)
+    }
+
+    /// Servers ranked by median download throughput of recent speed tests
+    pub fn get_speed_ranking(&self) -> Vec<SpeedRanking> {
+        self.speed_results
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .ranking()
+    }
+
+    /// Recent speed test results of a server, oldest first
+    pub fn get_speed_results(&self, server_id: &str) -> Vec<SpeedTestResult> {
+        self.speed_results
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .results(server_id)
+    }
+
+    /// Check which streaming services the current server unblocks
+    ///
+    /// Results younger than [`unlock_checker::UNLOCK_CACHE_TTL`] are reused
+    /// unless `refresh` is set; the others are probed concurrently through
+    /// the local HTTP inbound and cached for the server.
+    pub async fn check_unlock(
+        &self,
+        services: &[UnlockService],
+        refresh: bool,
+    ) -> crate::V8RayResult<Vec<UnlockResult>> {
+        let server_id = self
+            .current_config
+            .read()
+            .await
+            .as_ref()
+            .map(|config| config.id.clone())
+            .ok_or(crate::error::ConnectionError::NotConnected)?;
+
+        let cached: HashMap<UnlockService, UnlockResult> = if refresh {
+            HashMap::new()
+        } else {
+            let cache = self
+                .unlock_results
+                .read()
+                .unwrap_or_else(|e| e.into_inner());
+            services
+                .iter()
+                .filter_map(|service| {
+                    cache
+                        .fresh(&server_id, *service, unlock_checker::UNLOCK_CACHE_TTL)
+                        .map(|result| (*service, result))
+                })
+                .collect()
         };
+        let missing: Vec<UnlockService> = services
+            .iter()
+            .filter(|service| !cached.contains_key(service))
+            .copied()
+            .collect();
+
+        let mut checked = Vec::new();
+        if !missing.is_empty() {
+            let xray_config = self
+                .xray
+                .running_config()
+                .await
+                .ok_or(crate::error::ConnectionError::NotConnected)?;
+            let inbound = xray_config
+                .inbounds
+                .iter()
+                .find(|inbound| inbound.protocol == "http")
+                .ok_or_else(|| {
+                    crate::error::ConnectionError::Failed(
+                        "No HTTP inbound to check services through".to_string(),
+                    )
+                })?;
+            checked = unlock_checker::UnlockChecker::through_inbound(
+                inbound,
+                unlock_checker::DEFAULT_UNLOCK_TIMEOUT,
+            )?
+            .check_all(&missing)
+            .await;
+            self.unlock_results
+                .write()
+                .unwrap_or_else(|e| e.into_inner())
+                .record(&server_id, &checked);
+        }
 
+        Ok(services
+            .iter()
+            .filter_map(|service| {
+                cached
+                    .get(service)
+                    .or_else(|| checked.iter().find(|r| r.service == *service))
+                    .cloned()
+            })
+            .collect())
+    }
+
+    /// Cached streaming unlock results of a server
+    pub fn get_unlock_results(&self, server_id: &str) -> Vec<UnlockResult> {
+        self.unlock_results
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .results(server_id)
+    }
+
+    /// Look up the exit IP and location of the current connection
+    ///
+    /// Queries `provider` through the local HTTP inbound and, if
+    /// `compare_direct` is set, once more without the proxy. The direct
+    /// lookup is skipped in TUN mode, where it would be captured as well.
+    pub async fn get_exit_info(
+        &self,
+        provider: exit_info::ExitInfoProvider,
+        compare_direct: bool,
+    ) -> crate::V8RayResult<exit_info::ExitInfoReport> {
+        let xray_config = self
+            .xray
+            .running_config()
+            .await
+            .ok_or(crate::error::ConnectionError::NotConnected)?;
+        let inbound = xray_config
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == "http")
+            .ok_or_else(|| {
+                crate::error::ConnectionError::Failed(
+                    "No HTTP inbound to query through".to_string(),
+                )
+            })?;
+        let failed = |e: reqwest::Error| {
+            crate::error::ConnectionError::Failed(format!("Exit info lookup failed: {}", e))
+        };
+
+        let proxied_client = crate::version::http_client_builder()
+            .proxy(readiness::proxy_for_inbound(inbound).map_err(failed)?)
+            .build()
+            .map_err(failed)?;
+        let proxied = exit_info::lookup(&proxied_client, provider);
+
+        let direct = async {
+            if !compare_direct {
+                return None;
+            }
+            if self.xray.outbound_interface().is_some() {
+                return Some(Err(
+                    "Direct comparison is not available in TUN mode".to_string()
+                ));
+            }
+            let client = crate::version::http_client_builder()
+                .no_proxy()
+                .build()
+                .map_err(|e| e.to_string());
+            Some(match client {
+                Ok(client) => exit_info::lookup(&client, provider)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e),
+            })
+        };
+
+        let (proxied, direct) = tokio::join!(proxied, direct);
+        let (direct, direct_error) = match direct {
+            Some(Ok(info)) => (Some(info), None),
+            Some(Err(e)) => (None, Some(e)),
+            None => (None, None),
+        };
+        Ok(exit_info::ExitInfoReport {
+            provider,
+            proxied: proxied?,
+            direct,
+            direct_error,
+        })
+    }
+
+    /// Run the diagnostics checklist for a server
+    ///
+    /// See [`diagnostics`] for how an active connection is treated.
+    pub async fn run_diagnostics(
+        &self,
+        config: ProxyServerConfig,
+    ) -> diagnostics::DiagnosticReport {
+        diagnostics::run_diagnostics(self, config).await
+    }
+
+    /// Set connectivity health probe configuration
+    pub async fn set_health_probe_config(&self, config: HealthProbeConfig) {
+        *self.health_probe_config.write().await = config;
+    }
+
+    /// Get connectivity health probe configuration
+    pub async fn get_health_probe_config(&self) -> HealthProbeConfig {
+        self.health_probe_config.read().await.clone()
+    }
+
+    /// Get connectivity health probe results of the current connection
+    pub fn get_health_probe_state(&self) -> HealthProbeState {
+        self.health_probe
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Start the connectivity health probe loop (once per connection)
+    ///
+    /// While connected, a request is sent through the HTTP inbound every
+    /// probe interval. Reaching the failure threshold marks Xray
+    /// unresponsive, records a degraded event and starts an automatic
+    /// reconnect; the next success records the recovery. The loop ends
+    /// when the connection is closed, unless a new connection started
+    /// while it was stopping.
+    async fn start_health_probe(&self) {
+        *self.health_probe.write().unwrap_or_else(|e| e.into_inner()) = HealthProbeState::default();
+        self.xray.set_responsive(true).await;
+        if self
+            .health_probe_running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return;
+        }
+
+        let manager = self.handle();
         tokio::spawn(async move {
-            while let Ok(event) = event_rx.recv().await {
-                match event {
-                    XrayEvent::StatusChanged(status) => {
-                        debug!("Xray status changed: {:?}", status);
-                        if matches!(status, XrayStatus::Error(_)) {
-                            warn!("Xray error detected, triggering auto-reconnect");
-                            manager.start_auto_reconnect().await;
+            loop {
+                let config = manager.get_health_probe_config().await;
+                tokio::time::sleep(config.interval).await;
+
+                let state = match manager.current_connection.read().await.as_ref() {
+                    Some(conn) => conn.state.clone(),
+                    None => {
+                        // A connection started before the flag is cleared
+                        // relies on this loop, keep probing for it unless
+                        // it already started its own
+                        manager.health_probe_running.store(false, Ordering::SeqCst);
+                        let connected = manager.current_connection.read().await.is_some();
+                        if connected
+                            && manager
+                                .health_probe_running
+                                .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                                .is_ok()
+                        {
+                            continue;
                         }
+                        return;
                     }
-                    XrayEvent::LogReceived(log) => {
-                        // Check for critical errors in logs
-                        if log.message.contains("failed") || log.message.contains("error") {
-                            debug!("Potential error in Xray log: {}", log.message);
-                        }
+                };
+                if !config.enabled || state != ConnectionState::Connected {
+                    continue;
+                }
+
+                let inbound = manager.xray.running_config().await.and_then(|xray_config| {
+                    xray_config
+                        .inbounds
+                        .into_iter()
+                        .find(|inbound| inbound.protocol == "http")
+                });
+                let Some(inbound) = inbound else {
+                    continue;
+                };
+
+                let result = readiness::check_connectivity(&inbound, &config.url, config.timeout)
+                    .await
+                    .map_err(|e| e.to_string());
+                if let Err(ref e) = result {
+                    debug!("Health probe failed: {}", e);
+                }
+                let latency_ms = result.as_ref().ok().map(|l| l.as_millis() as u64);
+                let (transition, latency_transition) = {
+                    let mut state = manager
+                        .health_probe
+                        .write()
+                        .unwrap_or_else(|e| e.into_inner());
+                    let transition = state.record(result, config.failure_threshold);
+                    let latency_transition = latency_ms.and_then(|latency_ms| {
+                        state.record_latency(
+                            latency_ms,
+                            config.latency_threshold_ms,
+                            config.latency_alert_count,
+                        )
+                    });
+                    (transition, latency_transition)
+                };
+                manager
+                    .record_probe_latency(latency_ms, latency_transition, &config)
+                    .await;
+
+                match transition {
+                    Some(ProbeTransition::Degraded) => {
+                        warn!(
+                            "Proxy unresponsive after {} failed probes, reconnecting",
+                            config.failure_threshold
+                        );
+                        manager.xray.set_responsive(false).await;
+                        manager.record_event(
+                            SessionEventKind::Degraded,
+                            format!(
+                                "Proxy unresponsive ({} failed probes)",
+                                config.failure_threshold
+                            ),
+                        );
+                        manager
+                            .start_auto_reconnect(FailureClass::NetworkUnreachable)
+                            .await;
+                    }
+                    Some(ProbeTransition::Recovered) => {
+                        info!("Proxy responsive again");
+                        manager.xray.set_responsive(true).await;
+                        manager.record_event(SessionEventKind::Recovered, "Proxy responsive again");
                     }
-                    _ => {}
+                    None => {}
                 }
             }
         });
     }
 
+    /// Keep a probe's latency in the latency history and publish the
+    /// latency `transition`, if any
+    async fn record_probe_latency(
+        &self,
+        latency_ms: Option<u64>,
+        transition: Option<LatencyTransition>,
+        config: &HealthProbeConfig,
+    ) {
+        let Some(server_id) = self.get_current_config().await.map(|c| c.id) else {
+            return;
+        };
+        if let Some(history) = self.get_latency_history().await {
+            if let Err(e) = history
+                .record(&server_id, chrono::Utc::now(), latency_ms)
+                .await
+            {
+                warn!("Failed to record probe latency: {}", e);
+            }
+        }
+
+        let (Some(transition), Some(latency_ms)) = (transition, latency_ms) else {
+            return;
+        };
+        let alert = match transition {
+            LatencyTransition::Slow => {
+                let threshold_ms = config.latency_threshold_ms.unwrap_or_default();
+                warn!(
+                    "Latency {} ms above {} ms for {} probes",
+                    latency_ms, threshold_ms, config.latency_alert_count
+                );
+                self.record_event(
+                    SessionEventKind::Degraded,
+                    format!(
+                        "Latency above {} ms ({} ms over {} probes)",
+                        threshold_ms, latency_ms, config.latency_alert_count
+                    ),
+                );
+                LatencyAlert::Degraded {
+                    server_id,
+                    latency_ms,
+                    threshold_ms,
+                }
+            }
+            LatencyTransition::Normal => {
+                info!("Latency back to normal: {} ms", latency_ms);
+                self.record_event(
+                    SessionEventKind::Recovered,
+                    format!("Latency back to normal ({} ms)", latency_ms),
+                );
+                LatencyAlert::Recovered {
+                    server_id,
+                    latency_ms,
+                }
+            }
+        };
+        let _ = self.latency_alerts.send(alert);
+    }
+
     /// Get traffic statistics collector
     pub fn get_stats_collector(&self) -> Arc<TrafficStatsCollector> {
         Arc::clone(&self.stats_collector)
@@ -634,13 +2270,145 @@ impl ConnectionManager {
         self.stats_collector.get_current_speeds().await
     }
 
+    /// Values exported to Prometheus, see [`crate::metrics`]
+    pub async fn metrics_snapshot(&self) -> crate::metrics::MetricsSnapshot {
+        let connected = self.get_state().await == ConnectionState::Connected;
+        let (upload_bytes, download_bytes) = self.get_traffic_totals().await;
+        let (upload_speed, download_speed) = self.get_traffic_speeds().await;
+        crate::metrics::MetricsSnapshot {
+            connected,
+            upload_bytes,
+            download_bytes,
+            upload_speed,
+            download_speed,
+            latency_ms: connected
+                .then(|| self.get_health_probe_state().last_latency_ms)
+                .flatten(),
+            counters: crate::metrics::counters(),
+        }
+    }
+
     /// Start automatic traffic statistics collection
+    ///
+    /// Besides the snapshots, the traffic counters of the running Xray are
+    /// polled every `interval` and passed to [`Self::update_stats`] while
+    /// connected. Like the health probe loop, the poll loop runs once and
+    /// ends when the connection is closed.
     pub async fn start_stats_collection(&self, interval: Duration) {
         info!(
             "Starting traffic statistics collection with interval: {:?}",
             interval
         );
         self.stats_collector.start_auto_snapshot(interval).await;
+
+        if self
+            .traffic_poller
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return;
+        }
+        let manager = self.handle();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+
+                if manager.current_connection.read().await.is_none() {
+                    manager.traffic_poller.store(false, Ordering::SeqCst);
+                    let connected = manager.current_connection.read().await.is_some();
+                    if connected
+                        && manager
+                            .traffic_poller
+                            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                            .is_ok()
+                    {
+                        continue;
+                    }
+                    return;
+                }
+                if let Some(client) = manager.xray.api_client().await {
+                    manager.poll_traffic(&client).await;
+                }
+            }
+        });
+    }
+
+    /// Read Xray's proxy outbound counters once and add them to the stats
+    ///
+    /// Only outbounds of proxy servers count, so direct and blocked traffic
+    /// is not attributed to the server.
+    async fn poll_traffic(&self, client: &XrayApiClient) {
+        let traffic = match client.query_outbound_traffic().await {
+            Ok(traffic) => traffic,
+            Err(e) => {
+                debug!("Failed to query Xray traffic stats: {}", e);
+                return;
+            }
+        };
+        let (upload, download) = traffic
+            .iter()
+            .filter(|(tag, _)| crate::xray::is_proxy_outbound(tag))
+            .fold((0, 0), |(up, down), (_, (u, d))| (up + u, down + d));
+        if upload == 0 && download == 0 {
+            return;
+        }
+        if let Err(e) = self.update_stats(upload, download).await {
+            warn!("Failed to update traffic stats: {}", e);
+        }
+    }
+
+    /// Persist traffic reported through [`Self::update_stats`] to `history`
+    ///
+    /// Ignored in low-memory mode.
+    pub async fn set_traffic_history(&self, history: Arc<TrafficHistory>) {
+        if self.is_low_memory() {
+            return;
+        }
+        *self.traffic_history.write().await = Some(history);
+    }
+
+    /// Turn low-memory mode on or off
+    ///
+    /// Meant for the iOS network extension, which is killed above 50 MB:
+    /// access log collection for active connections and domain analytics
+    /// stops and its buffers are emptied, and traffic is no longer written
+    /// to the persistent history. Turning it off resumes collection from
+    /// the next connection.
+    pub async fn set_low_memory(&self, enabled: bool) {
+        self.low_memory.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            return;
+        }
+        *self.traffic_history.write().await = None;
+        self.connection_tracker
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.access_analytics
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        info!("Low-memory mode enabled");
+    }
+
+    /// Whether low-memory mode is on
+    pub fn is_low_memory(&self) -> bool {
+        self.low_memory.load(Ordering::SeqCst)
+    }
+
+    /// Get the persistent traffic history, if enabled
+    pub async fn get_traffic_history(&self) -> Option<Arc<TrafficHistory>> {
+        self.traffic_history.read().await.clone()
+    }
+
+    /// Keep latency test results in `history`
+    pub async fn set_latency_history(&self, history: Arc<LatencyHistory>) {
+        *self.latency_history.write().await = Some(history);
+    }
+
+    /// Get the latency history, if enabled
+    pub async fn get_latency_history(&self) -> Option<Arc<LatencyHistory>> {
+        self.latency_history.read().await.clone()
     }
 
     /// Reset traffic statistics
@@ -649,6 +2417,15 @@ impl ConnectionManager {
     }
 }
 
+/// Probe target of a connection through a single server
+fn single_probe_target(config: &ProxyServerConfig) -> Vec<ProbeTarget> {
+    vec![ProbeTarget {
+        tag: "proxy".to_string(),
+        server: config.server.clone(),
+        port: config.port,
+    }]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -670,6 +2447,7 @@ mod tests {
             tags: vec![],
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            raw_name: None,
         }
     }
 
@@ -682,6 +2460,133 @@ mod tests {
         assert!(!manager.is_connected().await);
     }
 
+    #[tokio::test]
+    async fn test_quic_servers_run_on_sing_box() {
+        let manager = ConnectionManager::new();
+        assert_eq!(manager.core_kind(), CoreKind::Xray);
+        manager
+            .get_sing_box()
+            .set_binary_path(Some(std::path::PathBuf::from("/nonexistent/sing-box")));
+
+        let mut config = create_test_config();
+        config.protocol = ProxyProtocol::Hysteria2;
+        config
+            .settings
+            .insert("password".to_string(), serde_json::json!("secret"));
+        let err = manager.connect_with_config(config).await.unwrap_err();
+        assert!(err.to_string().contains("sing-box not found"));
+        assert!(matches!(
+            manager.get_state().await,
+            ConnectionState::Error(_)
+        ));
+        assert_eq!(manager.active_core_kind(), Some(CoreKind::SingBox));
+
+        manager.disconnect().await.unwrap();
+        assert_eq!(manager.get_state().await, ConnectionState::Disconnected);
+        assert!(manager.active_core().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_connect_cancelled_before_start() {
+        let manager = ConnectionManager::new();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let mut phases = Vec::new();
+        let result = manager
+            .connect_with_progress(create_test_config(), "global", &cancel, |phase| {
+                phases.push(phase)
+            })
+            .await;
+        assert!(matches!(
+            result,
+            Err(crate::error::V8RayError::Connection(
+                crate::error::ConnectionError::Cancelled
+            ))
+        ));
+        assert_eq!(phases, vec![ConnectPhase::GeneratingConfig]);
+        assert_eq!(manager.get_state().await, ConnectionState::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn test_aggregated_health_when_stopped() {
+        let manager = ConnectionManager::new();
+
+        let health = manager.get_aggregated_health().await;
+        assert_eq!(health.summary, crate::xray::HealthSummary::Stopped);
+        assert_eq!(health.instances.len(), 1);
+        assert!(health.outbounds.is_empty());
+
+        // The legacy status API is unchanged
+        assert_eq!(manager.get_xray_status().await, XrayStatus::Stopped);
+    }
+
+    #[tokio::test]
+    async fn test_session_timeline() {
+        let manager = ConnectionManager::new();
+        let config = create_test_config();
+
+        {
+            let mut current = manager.current_connection.write().await;
+            *current = Some(Connection {
+                id: Uuid::new_v4(),
+                name: config.name.clone(),
+                server: format!("{}:{}", config.server, config.port),
+                state: ConnectionState::Connected,
+                stats: None,
+                config_id: config.id.clone(),
+                last_error: None,
+                reconnect_attempts: 0,
+            });
+        }
+
+        manager.record_event(SessionEventKind::RouteChanged, "Proxy mode set to smart");
+        // Health checks only add events on transitions to a degraded state
+        manager.get_aggregated_health().await;
+        manager.disconnect().await.unwrap();
+
+        let kinds: Vec<_> = manager.get_timeline().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SessionEventKind::RouteChanged,
+                SessionEventKind::Disconnected
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accept_route_suggestion() {
+        let manager = ConnectionManager::new();
+        manager.watch_route_suggestions(true);
+
+        let log = "proxy/freedom: failed to open connection to tcp:git.corp.example.com:443";
+        for _ in 0..3 {
+            manager.suggestions_mut().record_log(log);
+        }
+
+        let suggestions = manager.get_route_suggestions();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].domain, "example.com");
+
+        let mut rule_sets = Vec::new();
+        let rule = manager
+            .accept_route_suggestion("example.com", &mut rule_sets)
+            .unwrap();
+        assert_eq!(rule.domains, vec!["domain:example.com"]);
+        assert!(manager.get_route_suggestions().is_empty());
+        assert!(manager
+            .accept_route_suggestion("example.com", &mut rule_sets)
+            .is_none());
+
+        assert_eq!(rule_sets[0].id, suggestions::SUGGESTED_RULE_SET_ID);
+        assert_eq!(rule_sets[0].rules, vec![rule]);
+
+        // Leaving smart mode stops tracking
+        manager.disconnect().await.unwrap();
+        assert!(!manager.route_suggestions.read().unwrap().is_active());
+    }
+
     #[tokio::test]
     async fn test_connection_state_transitions() {
         let manager = ConnectionManager::new();
@@ -948,4 +2853,227 @@ mod tests {
         assert_eq!(up, 500);
         assert_eq!(down, 1000);
     }
+
+    #[tokio::test]
+    async fn test_traffic_history_attribution() {
+        let manager = ConnectionManager::new();
+        let history = Arc::new(TrafficHistory::new_in_memory().await.unwrap());
+        manager.set_traffic_history(Arc::clone(&history)).await;
+
+        // Not connected: nothing to attribute the traffic to
+        manager.update_stats(1, 1).await.unwrap();
+        assert!(history.get_usage_by_server(1).await.unwrap().is_empty());
+
+        let config = create_test_config();
+        *manager.current_config.write().await = Some(config.clone());
+        manager.update_stats(500, 1000).await.unwrap();
+
+        let usage = history.get_usage_by_server(1).await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].server_id, config.id);
+        assert_eq!(usage[0].download_bytes, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_access_analytics_storage() {
+        let manager = ConnectionManager::new();
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+        let log = "from 127.0.0.1:1000 accepted tcp:example.com:443 [http-in -> proxy]";
+        manager
+            .access_analytics
+            .write()
+            .unwrap()
+            .record_log(log, chrono::Utc::now());
+        assert_eq!(manager.get_route_split(since).await.unwrap().proxy, 1);
+
+        // Only records seen after the storage was set are persisted
+        let storage = Arc::new(AccessLogStorage::new_in_memory().await.unwrap());
+        manager.set_access_log_storage(Arc::clone(&storage)).await;
+        manager
+            .access_analytics
+            .write()
+            .unwrap()
+            .record_log(log, chrono::Utc::now());
+
+        let top = manager.get_top_domains(since, 10).await.unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].domain, "example.com");
+        assert_eq!(top[0].count, 1);
+        assert_eq!(storage.load_since(since).await.unwrap().len(), 1);
+        assert!(manager
+            .get_blocked_domains(since, 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_low_memory() {
+        let manager = ConnectionManager::new();
+        let history = Arc::new(TrafficHistory::new_in_memory().await.unwrap());
+        manager.set_traffic_history(Arc::clone(&history)).await;
+        manager.access_analytics.write().unwrap().record_log(
+            "from 127.0.0.1:1000 accepted tcp:example.com:443 [http-in -> proxy]",
+            chrono::Utc::now(),
+        );
+
+        manager.set_low_memory(true).await;
+        assert!(manager.is_low_memory());
+        assert!(manager.get_traffic_history().await.is_none());
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+        assert!(manager.get_top_domains(since, 10).await.unwrap().is_empty());
+
+        // History cannot be attached again until low-memory mode is off
+        manager.set_traffic_history(Arc::clone(&history)).await;
+        assert!(manager.get_traffic_history().await.is_none());
+        manager.set_low_memory(false).await;
+        manager.set_traffic_history(history).await;
+        assert!(manager.get_traffic_history().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_mark_crashed() {
+        let manager = ConnectionManager::new();
+        // Nothing to mark without an established connection
+        assert!(!manager.mark_crashed("exited with code 1").await);
+
+        let config = create_test_config();
+        *manager.current_connection.write().await = Some(Connection {
+            id: Uuid::new_v4(),
+            name: config.name.clone(),
+            server: format!("{}:{}", config.server, config.port),
+            state: ConnectionState::Connected,
+            stats: None,
+            config_id: config.id.clone(),
+            last_error: None,
+            reconnect_attempts: 0,
+        });
+
+        assert!(manager.mark_crashed("exited with code 1").await);
+        let connection = manager.get_current_connection().await.unwrap();
+        assert_eq!(
+            connection.state,
+            ConnectionState::Error("exited with code 1".to_string())
+        );
+        assert!(matches!(
+            connection.last_error,
+            Some(ConnectionError::XrayCrashed(_))
+        ));
+        // Already in the error state: left to the reconnect task
+        assert!(!manager.mark_crashed("exited again").await);
+    }
+
+    #[tokio::test]
+    async fn test_auto_reconnect_gives_up() {
+        let manager = ConnectionManager::with_reconnect_config(ReconnectConfig::immediate(2));
+        let config = create_test_config();
+        *manager.current_config.write().await = Some(config.clone());
+        *manager.current_connection.write().await = Some(Connection {
+            id: Uuid::new_v4(),
+            name: config.name.clone(),
+            server: format!("{}:{}", config.server, config.port),
+            state: ConnectionState::Error("crashed".to_string()),
+            stats: None,
+            config_id: config.id.clone(),
+            last_error: None,
+            reconnect_attempts: 2,
+        });
+
+        let mut events = manager.subscribe_reconnect_events();
+        manager.start_auto_reconnect(FailureClass::Crash).await;
+        let event = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match event {
+            ReconnectEvent::ReconnectFailed {
+                class, attempts, ..
+            } => {
+                assert_eq!(class, FailureClass::Crash);
+                assert_eq!(attempts, 2);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(manager.retry_started.read().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_health_probe_loop_ends_without_connection() {
+        let manager = ConnectionManager::new();
+        manager
+            .set_health_probe_config(HealthProbeConfig {
+                interval: Duration::from_millis(10),
+                ..Default::default()
+            })
+            .await;
+
+        manager.start_health_probe().await;
+        assert!(manager.health_probe_running.load(Ordering::SeqCst));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!manager.health_probe_running.load(Ordering::SeqCst));
+
+        // The next connection starts a new loop
+        manager.start_health_probe().await;
+        assert!(manager.health_probe_running.load(Ordering::SeqCst));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_poll_traffic_records_history() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // Stand-in for `xray api statsquery`
+        let dir = tempfile::tempdir().unwrap();
+        let binary = dir.path().join("xray");
+        std::fs::write(
+            &binary,
+            r#"#!/bin/sh
+echo '{"stat":[
+  {"name":"outbound>>>proxy>>>traffic>>>uplink","value":"1000"},
+  {"name":"outbound>>>proxy>>>traffic>>>downlink","value":"5000"},
+  {"name":"outbound>>>direct>>>traffic>>>downlink","value":"700"}
+]}'
+"#,
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let client = XrayApiClient::new(binary, 10085);
+
+        let manager = ConnectionManager::new();
+        let history = Arc::new(TrafficHistory::new_in_memory().await.unwrap());
+        manager.set_traffic_history(Arc::clone(&history)).await;
+        let config = create_test_config();
+        *manager.current_config.write().await = Some(config.clone());
+        *manager.current_connection.write().await = Some(Connection {
+            id: Uuid::new_v4(),
+            name: config.name.clone(),
+            server: format!("{}:{}", config.server, config.port),
+            state: ConnectionState::Connected,
+            stats: Some(ConnectionStats {
+                upload: 0,
+                download: 0,
+                start_time: chrono::Utc::now(),
+                last_activity: chrono::Utc::now(),
+            }),
+            config_id: config.id.clone(),
+            last_error: None,
+            reconnect_attempts: 0,
+        });
+
+        manager.poll_traffic(&client).await;
+        manager.poll_traffic(&client).await;
+
+        // Direct traffic is not counted
+        let stats = manager
+            .get_current_connection()
+            .await
+            .unwrap()
+            .stats
+            .unwrap();
+        assert_eq!((stats.upload, stats.download), (2000, 10000));
+        let usage = history.get_hourly_usage(1, Some(&config.id)).await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].upload_bytes, 2000);
+        assert_eq!(usage[0].download_bytes, 10000);
+    }
 }
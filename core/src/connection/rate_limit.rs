@@ -0,0 +1,415 @@
+//! Speed limiting of local inbounds
+//!
+//! Xray has no bandwidth limit of its own, so limited HTTP and SOCKS
+//! inbounds are fronted by a local forwarder: the forwarder listens on the
+//! inbound's public port and relays each TCP connection to Xray on an
+//! internal loopback port, passing the bytes through a token bucket per
+//! direction. The buckets are shared by all connections, so the limit caps
+//! the total bandwidth of the proxy. UDP relayed by the SOCKS inbound goes
+//! to Xray directly and is not limited.
+//!
+//! Behind a forwarder Xray sees every connection coming from loopback, so
+//! the addresses of LAN clients are lost to source IP routing rules and the
+//! access log, and the UDP relay address Xray hands out would be unreachable
+//! from other devices. SOCKS inbounds shared on the LAN are therefore not
+//! fronted and stay unlimited; shared HTTP inbounds are limited but lose
+//! the client addresses.
+
+use super::inbound_front::{FrontRegistry, InboundFront};
+use crate::xray::{PortReassignment, XrayConfig};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::info;
+
+/// Size of the relay buffer, and so of the chunks taken from a bucket
+const RELAY_BUFFER_SIZE: usize = 16 * 1024;
+
+/// Inbound protocols that can be limited
+const LIMITED_PROTOCOLS: &[&str] = &["http", "socks"];
+
+/// Upload and download limits in bytes per second, None for unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Limit of traffic sent by local applications
+    #[serde(default)]
+    pub upload_bytes_per_sec: Option<u64>,
+    /// Limit of traffic received by local applications
+    #[serde(default)]
+    pub download_bytes_per_sec: Option<u64>,
+}
+
+impl RateLimit {
+    /// Whether neither direction is limited
+    pub fn is_unlimited(&self) -> bool {
+        self.upload_bytes_per_sec.is_none() && self.download_bytes_per_sec.is_none()
+    }
+
+    /// Validate the limits
+    pub fn validate(&self) -> Result<(), crate::error::ConfigError> {
+        if self.upload_bytes_per_sec == Some(0) || self.download_bytes_per_sec == Some(0) {
+            return Err(crate::error::ConfigError::Validation(
+                "Speed limit must be above 0 bytes per second".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket holding up to one second of traffic
+///
+/// Taking more than is available puts the bucket in debt and waits until
+/// the debt is paid off, so chunks larger than the rate still pass.
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// Bytes per second, 0 for unlimited
+    rate: AtomicU64,
+    /// Available bytes, negative when in debt, and when they were refilled
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    /// Create a bucket refilling at `rate` bytes per second, None for
+    /// unlimited
+    pub fn new(rate: Option<u64>) -> Self {
+        let rate = rate.unwrap_or(0);
+        Self {
+            rate: AtomicU64::new(rate),
+            state: Mutex::new((rate as f64, Instant::now())),
+        }
+    }
+
+    /// Change the rate, starting with a full bucket
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let rate = rate.unwrap_or(0);
+        self.rate.store(rate, Ordering::Relaxed);
+        *self.state.lock().unwrap_or_else(|e| e.into_inner()) = (rate as f64, Instant::now());
+    }
+
+    /// Take `bytes` from the bucket and return how long to wait before
+    /// sending them
+    fn reserve(&self, bytes: usize) -> Duration {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = rate as f64;
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, refilled) = &mut *state;
+        let now = Instant::now();
+        *tokens = (*tokens + now.duration_since(*refilled).as_secs_f64() * rate).min(rate);
+        *refilled = now;
+        *tokens -= bytes as f64;
+
+        if *tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-*tokens / rate)
+        }
+    }
+
+    /// Wait until `bytes` may be sent
+    pub async fn take(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Relay from `reader` to `writer` through `bucket` until end of stream
+async fn relay(
+    mut reader: tokio::net::tcp::OwnedReadHalf,
+    mut writer: tokio::net::tcp::OwnedWriteHalf,
+    bucket: &TokenBucket,
+) -> std::io::Result<()> {
+    let mut buf = vec![0u8; RELAY_BUFFER_SIZE];
+    loop {
+        let n = reader.read(&mut buf).await?;
+        if n == 0 {
+            return writer.shutdown().await;
+        }
+        bucket.take(n).await;
+        writer.write_all(&buf[..n]).await?;
+    }
+}
+
+/// Forward one client connection to `upstream`
+async fn forward(
+    client: TcpStream,
+    upstream: SocketAddr,
+    upload: Arc<TokenBucket>,
+    download: Arc<TokenBucket>,
+) -> std::io::Result<()> {
+    let server = TcpStream::connect(upstream).await?;
+    let _ = client.set_nodelay(true);
+    let _ = server.set_nodelay(true);
+    let (client_read, client_write) = client.into_split();
+    let (server_read, server_write) = server.into_split();
+    tokio::try_join!(
+        relay(client_read, server_write, &upload),
+        relay(server_read, client_write, &download)
+    )?;
+    Ok(())
+}
+
+/// Start a front on `public` forwarding to `upstream_port` on localhost
+/// through the buckets
+///
+/// Must be called within a Tokio runtime.
+pub fn start_forwarder(
+    public: SocketAddr,
+    upstream_port: u16,
+    upload: Arc<TokenBucket>,
+    download: Arc<TokenBucket>,
+) -> std::io::Result<InboundFront> {
+    InboundFront::start(public, upstream_port, move |client, port| {
+        let upstream = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port);
+        forward(client, upstream, upload.clone(), download.clone())
+    })
+}
+
+/// Speed limit of the local inbounds of the main connection
+#[derive(Debug)]
+pub struct InboundThrottle {
+    /// Current limits
+    limit: Mutex<RateLimit>,
+    /// Bucket of traffic sent by local applications
+    upload: Arc<TokenBucket>,
+    /// Bucket of traffic received by local applications
+    download: Arc<TokenBucket>,
+    /// Running forwarders
+    fronts: FrontRegistry,
+}
+
+impl Default for InboundThrottle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InboundThrottle {
+    /// Create an unlimited throttle
+    pub fn new() -> Self {
+        Self {
+            limit: Mutex::new(RateLimit::default()),
+            upload: Arc::new(TokenBucket::new(None)),
+            download: Arc::new(TokenBucket::new(None)),
+            fronts: FrontRegistry::new(),
+        }
+    }
+
+    /// Current limits
+    pub fn limit(&self) -> RateLimit {
+        *self.limit.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Change the limits
+    ///
+    /// New rates apply to running forwarders at once. Whether the inbounds
+    /// are fronted by a forwarder at all is decided by [`apply`](Self::apply)
+    /// when Xray starts, so turning limiting on or off takes effect with the
+    /// next connection.
+    pub fn set_limit(&self, limit: RateLimit) {
+        *self.limit.lock().unwrap_or_else(|e| e.into_inner()) = limit;
+        self.upload.set_rate(limit.upload_bytes_per_sec);
+        self.download.set_rate(limit.download_bytes_per_sec);
+    }
+
+    /// Front the HTTP and SOCKS inbounds of `config` with forwarders when
+    /// limited, see [`FrontRegistry::apply`]
+    ///
+    /// SOCKS inbounds shared on the LAN are left unlimited, see the module
+    /// documentation.
+    pub fn apply(&self, config: &mut XrayConfig) {
+        if self.limit().is_unlimited() {
+            self.fronts.stop();
+            return;
+        }
+
+        self.fronts.apply(
+            config,
+            "speed limit",
+            |inbound| {
+                if !LIMITED_PROTOCOLS.contains(&inbound.protocol.as_str()) {
+                    return false;
+                }
+                if inbound.protocol == "socks" && inbound.listens_beyond_loopback() {
+                    info!(
+                        "SOCKS inbound on port {} is shared on the LAN and left unlimited",
+                        inbound.port
+                    );
+                    return false;
+                }
+                true
+            },
+            |_, public, upstream| {
+                start_forwarder(public, upstream, self.upload.clone(), self.download.clone())
+            },
+        );
+    }
+
+    /// Point the forwarders at the ports Xray moved its inbounds to
+    pub fn follow(&self, reassignments: &[PortReassignment]) {
+        self.fronts.follow(reassignments);
+    }
+
+    /// Public port local applications use for Xray's inbound `port`, see
+    /// [`FrontRegistry::public_port`]
+    pub fn public_port(&self, port: u16) -> u16 {
+        self.fronts.public_port(port)
+    }
+
+    /// Stop all forwarders
+    pub fn stop(&self) {
+        self.fronts.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_validate() {
+        assert!(RateLimit::default().is_unlimited());
+        let limit = RateLimit {
+            upload_bytes_per_sec: Some(1024),
+            download_bytes_per_sec: None,
+        };
+        assert!(!limit.is_unlimited());
+        assert!(limit.validate().is_ok());
+        let zero = RateLimit {
+            download_bytes_per_sec: Some(0),
+            ..limit
+        };
+        assert!(zero.validate().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_debt() {
+        let bucket = TokenBucket::new(Some(1000));
+        // A full bucket passes one second of traffic at once
+        assert_eq!(bucket.reserve(1000), Duration::ZERO);
+        let wait = bucket.reserve(500);
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+
+        bucket.set_rate(None);
+        assert_eq!(bucket.reserve(1_000_000), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_forwarder_limits_download() {
+        // Upstream sending 40 KiB to every client
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = upstream.accept().await {
+                tokio::spawn(async move {
+                    let _ = stream.write_all(&[7u8; 40 * 1024]).await;
+                });
+            }
+        });
+
+        let download = Arc::new(TokenBucket::new(Some(20 * 1024)));
+        let forwarder = start_forwarder(
+            "127.0.0.1:0".parse().unwrap(),
+            upstream_port,
+            Arc::new(TokenBucket::new(None)),
+            download,
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let mut client = TcpStream::connect(forwarder.public_addr()).await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        assert_eq!(received.len(), 40 * 1024);
+        // One second of burst, then 20 KiB at 20 KiB/s
+        assert!(started.elapsed() >= Duration::from_millis(800));
+
+        let public = forwarder.public_addr();
+        drop(forwarder);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(TcpStream::connect(public).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_apply_rewrites_inbounds() {
+        let server = crate::config::ProxyServerConfig {
+            id: "server".to_string(),
+            name: "Server".to_string(),
+            server: "example.com".to_string(),
+            port: 443,
+            protocol: crate::config::ProxyProtocol::Vless,
+            settings: HashMap::from([("id".to_string(), serde_json::json!("test-uuid"))]),
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            raw_name: None,
+        };
+        let mut config = crate::xray::XrayCore::new().generate_config(&server);
+        let ports = |config: &XrayConfig| -> Vec<u16> {
+            config.inbounds.iter().map(|inbound| inbound.port).collect()
+        };
+        let public_ports = ports(&config);
+
+        let throttle = InboundThrottle::new();
+        throttle.apply(&mut config.clone());
+        assert_eq!(throttle.public_port(public_ports[0]), public_ports[0]);
+
+        throttle.set_limit(RateLimit {
+            upload_bytes_per_sec: None,
+            download_bytes_per_sec: Some(1024 * 1024),
+        });
+        // Use free ports so the test does not depend on the default ones
+        for inbound in config.inbounds.iter_mut() {
+            inbound.port = crate::utils::ports::find_free_port(None).unwrap();
+        }
+        let expected = ports(&config);
+        let mut limited = config.clone();
+        throttle.apply(&mut limited);
+        for (inbound, public) in limited.inbounds.iter().zip(&expected) {
+            if LIMITED_PROTOCOLS.contains(&inbound.protocol.as_str()) {
+                assert_ne!(inbound.port, *public);
+                assert_eq!(throttle.public_port(inbound.port), *public);
+            }
+        }
+
+        // Applying again keeps the internal ports
+        let mut again = config.clone();
+        throttle.apply(&mut again);
+        assert_eq!(ports(&again), ports(&limited));
+
+        // A SOCKS inbound shared on the LAN keeps its address
+        let mut shared = config.clone();
+        let socks = shared
+            .inbounds
+            .iter_mut()
+            .find(|inbound| inbound.protocol == "socks")
+            .unwrap();
+        socks.port = crate::utils::ports::find_free_port(None).unwrap();
+        socks.listen = Some("0.0.0.0".to_string());
+        let socks_port = socks.port;
+        throttle.apply(&mut shared);
+        let socks = shared
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == "socks")
+            .unwrap();
+        assert_eq!(socks.port, socks_port);
+        assert_eq!(socks.listen.as_deref(), Some("0.0.0.0"));
+
+        throttle.stop();
+        let internal = limited.inbounds[0].port;
+        assert_eq!(throttle.public_port(internal), internal);
+    }
+}
@@ -0,0 +1,255 @@
+//! Proxy readiness checks
+//!
+//! Xray returning from start does not mean the proxy serves traffic yet:
+//! the inbounds may still be binding, and the outbound may be unusable.
+//! A connection counts as ready once the local inbound accepts connections
+//! and a first request through it succeeds.
+
+use super::ConnectPhase;
+use crate::error::ConnectionError;
+use crate::xray::{InboundConfig, XrayConfig};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+/// Default time allowed for a connection to become ready
+pub const DEFAULT_READINESS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default URL requested through the proxy to confirm connectivity
+pub const DEFAULT_CHECK_URL: &str = "http://www.gstatic.com/generate_204";
+
+/// Interval between inbound readiness polls
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Readiness check settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadinessConfig {
+    /// Time allowed for both phases together
+    pub timeout: Duration,
+    /// URL fetched through the proxy, None to only wait for the inbound
+    pub check_url: Option<String>,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_READINESS_TIMEOUT,
+            check_url: Some(DEFAULT_CHECK_URL.to_string()),
+        }
+    }
+}
+
+/// Outcome of a successful readiness check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReadinessReport {
+    /// Time until the local inbound accepted connections
+    pub inbound_ms: u64,
+    /// Duration of the first request through the proxy, if one was made
+    pub connectivity_ms: Option<u64>,
+}
+
+/// Wait until `config`'s local inbound serves traffic through the proxy
+///
+/// Prefers the HTTP inbound, which the connectivity request is sent
+/// through; without one, only waits for the first local inbound to accept
+/// connections.
+pub async fn wait_until_ready(
+    config: &XrayConfig,
+    readiness: &ReadinessConfig,
+) -> Result<ReadinessReport, ConnectionError> {
+    wait_until_ready_with_phases(config, readiness, |_| {}).await
+}
+
+/// Like [`wait_until_ready`], calling `on_phase` as each check starts
+pub async fn wait_until_ready_with_phases(
+    config: &XrayConfig,
+    readiness: &ReadinessConfig,
+    mut on_phase: impl FnMut(ConnectPhase),
+) -> Result<ReadinessReport, ConnectionError> {
+    let deadline = Instant::now() + readiness.timeout;
+    let http = config.inbounds.iter().find(|i| i.protocol == "http");
+    let inbound = http
+        .or_else(|| config.inbounds.iter().find(|i| i.protocol == "socks"))
+        .ok_or_else(|| ConnectionError::Failed("No local inbound configured".to_string()))?;
+
+    on_phase(ConnectPhase::WaitingInbound);
+    let inbound_ms = wait_for_inbound(inbound.port, deadline).await?.as_millis() as u64;
+
+    let connectivity_ms = match (http, readiness.check_url.as_deref()) {
+        (Some(http), Some(url)) => {
+            on_phase(ConnectPhase::TestingConnectivity);
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            Some(check_connectivity(http, url, remaining).await?.as_millis() as u64)
+        }
+        _ => None,
+    };
+
+    Ok(ReadinessReport {
+        inbound_ms,
+        connectivity_ms,
+    })
+}
+
+/// Poll the loopback `port` until it accepts a TCP connection
+pub async fn wait_for_inbound(port: u16, deadline: Instant) -> Result<Duration, ConnectionError> {
+    let start = Instant::now();
+    loop {
+        if TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            return Ok(start.elapsed());
+        }
+        if Instant::now() + POLL_INTERVAL > deadline {
+            return Err(ConnectionError::Timeout);
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+/// Proxy settings for sending requests through the HTTP `inbound`
+///
+/// Uses the first account of the inbound, if it requires authentication.
+pub(crate) fn proxy_for_inbound(inbound: &InboundConfig) -> reqwest::Result<reqwest::Proxy> {
+    let mut proxy = reqwest::Proxy::all(format!("http://127.0.0.1:{}", inbound.port))?;
+    let account = inbound
+        .settings
+        .as_ref()
+        .and_then(|settings| settings["accounts"].get(0));
+    if let Some(account) = account {
+        proxy = proxy.basic_auth(
+            account["user"].as_str().unwrap_or_default(),
+            account["pass"].as_str().unwrap_or_default(),
+        );
+    }
+    Ok(proxy)
+}
+
+/// Fetch `url` through the HTTP `inbound`, returning how long it took
+///
+/// Any HTTP response counts as success: it proves the proxy reached the
+/// remote end.
+pub async fn check_connectivity(
+    inbound: &InboundConfig,
+    url: &str,
+    timeout: Duration,
+) -> Result<Duration, ConnectionError> {
+    let failed = |e: reqwest::Error| ConnectionError::Failed(format!("Proxy check failed: {}", e));
+
+    let client = crate::version::http_client_builder()
+        .proxy(proxy_for_inbound(inbound).map_err(failed)?)
+        .timeout(timeout)
+        .build()
+        .map_err(failed)?;
+
+    let start = Instant::now();
+    client.get(url).send().await.map_err(|e| {
+        if e.is_timeout() {
+            ConnectionError::Timeout
+        } else {
+            failed(e)
+        }
+    })?;
+    Ok(start.elapsed())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn inbound(protocol: &str, port: u16, settings: Option<serde_json::Value>) -> InboundConfig {
+        InboundConfig {
+            port,
+            protocol: protocol.to_string(),
+            listen: Some("127.0.0.1".to_string()),
+            settings,
+            sniffing: None,
+            tag: None,
+        }
+    }
+
+    /// Minimal HTTP proxy answering every request with 204, recording them
+    async fn fake_proxy() -> (u16, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = vec![0u8; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let _ = tx.send(String::from_utf8_lossy(&buf[..n]).to_string());
+                let _ = stream
+                    .write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n")
+                    .await;
+            }
+        });
+        (port, rx)
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_inbound() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let deadline = Instant::now() + Duration::from_secs(1);
+        assert!(wait_for_inbound(port, deadline).await.is_ok());
+
+        drop(listener);
+        let deadline = Instant::now() + Duration::from_millis(300);
+        assert!(matches!(
+            wait_for_inbound(port, deadline).await,
+            Err(ConnectionError::Timeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_connectivity() {
+        let (port, mut requests) = fake_proxy().await;
+        let settings = serde_json::json!({ "accounts": [{ "user": "user", "pass": "secret" }] });
+        let http = inbound("http", port, Some(settings));
+
+        let result = check_connectivity(&http, DEFAULT_CHECK_URL, Duration::from_secs(5)).await;
+        assert!(result.is_ok());
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with(&format!("GET {}", DEFAULT_CHECK_URL)));
+        // base64("user:secret")
+        assert!(request.contains("dXNlcjpzZWNyZXQ="));
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_ready() {
+        let (port, _requests) = fake_proxy().await;
+        let mut config: XrayConfig = serde_json::from_value(serde_json::json!({
+            "log": { "level": "warning", "access": null, "error": null },
+            "inbounds": [],
+            "outbounds": [],
+            "routing": null
+        }))
+        .unwrap();
+        config.inbounds = vec![inbound("http", port, None)];
+
+        let mut phases = Vec::new();
+        let report = wait_until_ready_with_phases(&config, &ReadinessConfig::default(), |phase| {
+            phases.push(phase)
+        })
+        .await
+        .unwrap();
+        assert!(report.connectivity_ms.is_some());
+        assert_eq!(
+            phases,
+            vec![
+                ConnectPhase::WaitingInbound,
+                ConnectPhase::TestingConnectivity
+            ]
+        );
+
+        let readiness = ReadinessConfig {
+            timeout: Duration::from_secs(1),
+            check_url: None,
+        };
+        let report = wait_until_ready(&config, &readiness).await.unwrap();
+        assert!(report.connectivity_ms.is_none());
+
+        config.inbounds.clear();
+        assert!(wait_until_ready(&config, &readiness).await.is_err());
+    }
+}
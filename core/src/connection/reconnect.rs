@@ -2,10 +2,93 @@
 //!
 //! This module provides automatic reconnection functionality with exponential backoff,
 //! maximum retry limits, and configurable strategies.
-
+//!
+//! Delays are randomized by a jitter fraction so that many clients losing
+//! the same server do not retry in lockstep. A series of reconnects is
+//! bounded by both an attempt limit and a total retry window, and the
+//! attempt counter is only reset once the connection has been stable for a
+//! while, so a process that crashes right after starting does not retry
+//! forever. Crashes and network failures can use different policies.
+
+use crate::xray::{DetectedError, LogErrorKind};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tracing::{debug, info, warn};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// Upstream failures within [`ERROR_BURST_WINDOW`] that trigger a reconnect
+pub const ERROR_BURST_THRESHOLD: usize = 10;
+
+/// Time window in which upstream failures are counted
+pub const ERROR_BURST_WINDOW: Duration = Duration::from_secs(30);
+
+/// Default fraction by which delays are randomized
+pub const DEFAULT_JITTER: f64 = 0.2;
+
+/// Default total time a series of reconnect attempts may take
+pub const DEFAULT_MAX_RETRY_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// Default time a connection must stay up before the attempt counter resets
+pub const DEFAULT_STABLE_RESET_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// Kind of failure that triggered a reconnect
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum FailureClass {
+    /// The Xray process exited unexpectedly
+    Crash,
+    /// The server could not be reached (failed health probes or repeated
+    /// upstream errors in the Xray logs)
+    NetworkUnreachable,
+}
+
+impl FailureClass {
+    /// Stable snake_case name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::Crash => "crash",
+            FailureClass::NetworkUnreachable => "network_unreachable",
+        }
+    }
+}
+
+/// Reconnect strategy and attempt limit for one failure class
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Reconnect strategy
+    pub strategy: ReconnectStrategy,
+    /// Maximum number of reconnection attempts (0 = unlimited)
+    pub max_attempts: u32,
+}
+
+/// Progress of an auto-reconnect series, as reported to subscribers
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReconnectEvent {
+    /// A reconnect attempt is scheduled after `delay`
+    Reconnecting {
+        /// Failure that started the series
+        class: FailureClass,
+        /// Attempt number, starting at 1
+        attempt: u32,
+        /// Delay before the attempt
+        delay: Duration,
+    },
+    /// A reconnect attempt succeeded
+    Reconnected {
+        /// Attempts made in the series so far
+        attempts: u32,
+    },
+    /// Auto-reconnect gave up
+    ReconnectFailed {
+        /// Failure that started the series
+        class: FailureClass,
+        /// Attempts made
+        attempts: u32,
+        /// Why no further attempt is made
+        reason: String,
+    },
+}
 
 /// Reconnect strategy
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -37,6 +120,33 @@ impl Default for ReconnectStrategy {
     }
 }
 
+impl ReconnectStrategy {
+    /// Delay before attempt `attempt` (0-based), without jitter
+    pub fn delay(&self, attempt: u32) -> Duration {
+        match self {
+            ReconnectStrategy::Disabled | ReconnectStrategy::Immediate => Duration::ZERO,
+            ReconnectStrategy::FixedDelay(delay) => *delay,
+            ReconnectStrategy::ExponentialBackoff {
+                initial_delay,
+                max_delay,
+                multiplier,
+            } => {
+                let exponent = attempt.min(i32::MAX as u32) as i32;
+                let delay_secs = initial_delay.as_secs_f64() * multiplier.powi(exponent);
+                Duration::from_secs_f64(delay_secs.min(max_delay.as_secs_f64()).max(0.0))
+            }
+        }
+    }
+
+    /// Longest delay the strategy allows, if capped
+    pub fn max_delay(&self) -> Option<Duration> {
+        match self {
+            ReconnectStrategy::ExponentialBackoff { max_delay, .. } => Some(*max_delay),
+            _ => None,
+        }
+    }
+}
+
 /// Reconnect configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReconnectConfig {
@@ -46,6 +156,48 @@ pub struct ReconnectConfig {
     pub max_attempts: u32,
     /// Whether to enable auto-reconnect
     pub enabled: bool,
+    /// Fraction by which each delay is randomized up or down (0 = none)
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+    /// Total time a series of attempts may take before giving up
+    /// (0 = unlimited)
+    #[serde(default = "default_max_retry_window")]
+    pub max_retry_window: Duration,
+    /// Time a reconnected connection must stay up before the attempt
+    /// counter and retry window reset
+    #[serde(default = "default_stable_reset_after")]
+    pub stable_reset_after: Duration,
+    /// Policies overriding `strategy` and `max_attempts` per failure class
+    #[serde(default = "default_policies")]
+    pub policies: HashMap<FailureClass, ReconnectPolicy>,
+}
+
+fn default_jitter() -> f64 {
+    DEFAULT_JITTER
+}
+
+fn default_max_retry_window() -> Duration {
+    DEFAULT_MAX_RETRY_WINDOW
+}
+
+fn default_stable_reset_after() -> Duration {
+    DEFAULT_STABLE_RESET_AFTER
+}
+
+/// Network outages usually outlast a few quick retries: back off further
+/// and let the retry window bound the attempts instead
+fn default_policies() -> HashMap<FailureClass, ReconnectPolicy> {
+    HashMap::from([(
+        FailureClass::NetworkUnreachable,
+        ReconnectPolicy {
+            strategy: ReconnectStrategy::ExponentialBackoff {
+                initial_delay: Duration::from_secs(5),
+                max_delay: Duration::from_secs(120),
+                multiplier: 2.0,
+            },
+            max_attempts: 0,
+        },
+    )])
 }
 
 impl Default for ReconnectConfig {
@@ -54,6 +206,10 @@ impl Default for ReconnectConfig {
             strategy: ReconnectStrategy::default(),
             max_attempts: 5,
             enabled: true,
+            jitter: DEFAULT_JITTER,
+            max_retry_window: DEFAULT_MAX_RETRY_WINDOW,
+            stable_reset_after: DEFAULT_STABLE_RESET_AFTER,
+            policies: default_policies(),
         }
     }
 }
@@ -65,6 +221,8 @@ impl ReconnectConfig {
             strategy: ReconnectStrategy::Disabled,
             max_attempts: 0,
             enabled: false,
+            policies: HashMap::new(),
+            ..Default::default()
         }
     }
 
@@ -74,6 +232,8 @@ impl ReconnectConfig {
             strategy: ReconnectStrategy::Immediate,
             max_attempts,
             enabled: true,
+            policies: HashMap::new(),
+            ..Default::default()
         }
     }
 
@@ -83,6 +243,8 @@ impl ReconnectConfig {
             strategy: ReconnectStrategy::FixedDelay(delay),
             max_attempts,
             enabled: true,
+            policies: HashMap::new(),
+            ..Default::default()
         }
     }
 
@@ -101,6 +263,59 @@ impl ReconnectConfig {
             },
             max_attempts,
             enabled: true,
+            policies: HashMap::new(),
+            ..Default::default()
+        }
+    }
+
+    /// Policy for failures of `class`
+    pub fn policy_for(&self, class: FailureClass) -> ReconnectPolicy {
+        self.policies
+            .get(&class)
+            .cloned()
+            .unwrap_or_else(|| ReconnectPolicy {
+                strategy: self.strategy.clone(),
+                max_attempts: self.max_attempts,
+            })
+    }
+
+    /// Why no further attempt should be made for a series started by a
+    /// failure of `class`, after `attempts` attempts over `elapsed`
+    ///
+    /// Returns `None` if another attempt is allowed.
+    pub fn retry_denial(
+        &self,
+        class: FailureClass,
+        attempts: u32,
+        elapsed: Duration,
+    ) -> Option<String> {
+        let policy = self.policy_for(class);
+        if !self.enabled || policy.strategy == ReconnectStrategy::Disabled {
+            return Some("Auto-reconnect is disabled".to_string());
+        }
+        if policy.max_attempts > 0 && attempts >= policy.max_attempts {
+            return Some(format!(
+                "Maximum reconnection attempts reached: {}/{}",
+                attempts, policy.max_attempts
+            ));
+        }
+        if !self.max_retry_window.is_zero() && elapsed >= self.max_retry_window {
+            return Some(format!(
+                "Gave up after retrying for {:?}",
+                self.max_retry_window
+            ));
+        }
+        None
+    }
+
+    /// Delay before attempt `attempt` (0-based) for a failure of `class`,
+    /// randomized by the jitter fraction and kept below the strategy's cap
+    pub fn delay_for(&self, class: FailureClass, attempt: u32) -> Duration {
+        let strategy = self.policy_for(class).strategy;
+        let delay = apply_jitter(strategy.delay(attempt), self.jitter, random_unit());
+        match strategy.max_delay() {
+            Some(max_delay) => delay.min(max_delay),
+            None => delay,
         }
     }
 
@@ -129,27 +344,102 @@ impl ReconnectConfig {
 
     /// Calculate delay before next reconnection attempt
     pub fn calculate_delay(&self, attempt: u32) -> Duration {
-        match &self.strategy {
-            ReconnectStrategy::Disabled => Duration::from_secs(0),
-            ReconnectStrategy::Immediate => Duration::from_secs(0),
-            ReconnectStrategy::FixedDelay(delay) => {
-                info!("Using fixed delay: {:?}", delay);
-                *delay
-            }
-            ReconnectStrategy::ExponentialBackoff {
-                initial_delay,
-                max_delay,
-                multiplier,
-            } => {
-                let delay_secs = initial_delay.as_secs_f64() * multiplier.powi(attempt as i32);
-                let delay = Duration::from_secs_f64(delay_secs.min(max_delay.as_secs_f64()));
-                info!(
-                    "Exponential backoff delay for attempt {}: {:?}",
-                    attempt, delay
-                );
-                delay
-            }
+        let delay = self.strategy.delay(attempt);
+        debug!("Reconnect delay for attempt {}: {:?}", attempt, delay);
+        delay
+    }
+}
+
+/// Scale `delay` by a factor in `[1 - jitter, 1 + jitter]` picked by
+/// `sample` in `[0, 1)`
+fn apply_jitter(delay: Duration, jitter: f64, sample: f64) -> Duration {
+    let jitter = jitter.clamp(0.0, 1.0);
+    let factor = 1.0 + jitter * (2.0 * sample - 1.0);
+    delay.mul_f64(factor.max(0.0))
+}
+
+/// Random number in `[0, 1)`
+///
+/// Jitter needs no strong randomness; the per-instance random keys of the
+/// standard hasher are enough.
+fn random_unit() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos(),
+    );
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Detects bursts of upstream failures reported in Xray logs
+///
+/// A single failed handshake or refused connection is normal noise; many
+/// within a short window mean the server connection is broken even though
+/// the Xray process is still running.
+#[derive(Debug)]
+pub struct ErrorBurstDetector {
+    /// Failures needed within the window
+    threshold: usize,
+    /// Time window in which failures are counted
+    window: Duration,
+    /// Times of recent upstream failures, oldest first
+    failures: VecDeque<Instant>,
+}
+
+impl Default for ErrorBurstDetector {
+    fn default() -> Self {
+        Self::new(ERROR_BURST_THRESHOLD, ERROR_BURST_WINDOW)
+    }
+}
+
+impl ErrorBurstDetector {
+    /// Create a detector firing after `threshold` failures within `window`
+    pub fn new(threshold: usize, window: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            failures: VecDeque::new(),
+        }
+    }
+
+    /// Record a failure of `kind` at `now`
+    ///
+    /// Returns true when the threshold is reached; the count then starts
+    /// over. Failures not caused by the server are ignored.
+    pub fn record(&mut self, kind: LogErrorKind, now: Instant) -> bool {
+        if !kind.is_upstream() {
+            return false;
+        }
+
+        while self
+            .failures
+            .front()
+            .is_some_and(|t| now.saturating_duration_since(*t) > self.window)
+        {
+            self.failures.pop_front();
         }
+        self.failures.push_back(now);
+
+        if self.failures.len() >= self.threshold {
+            self.failures.clear();
+            return true;
+        }
+        false
+    }
+
+    /// Record a failure detected in the Xray logs at `now`
+    ///
+    /// Like [`Self::record`], but failures of connections that did not go
+    /// through a proxy server, such as direct ones, are ignored.
+    pub fn record_error(&mut self, error: &DetectedError, now: Instant) -> bool {
+        error.is_proxy_failure() && self.record(error.kind, now)
+    }
+
+    /// Forget recorded failures, e.g. after reconnecting
+    pub fn reset(&mut self) {
+        self.failures.clear();
     }
 }
 
@@ -157,6 +447,49 @@ impl ReconnectConfig {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_error_burst_detector() {
+        let mut detector = ErrorBurstDetector::new(3, Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert!(!detector.record(LogErrorKind::DnsFailure, start));
+        assert!(!detector.record(LogErrorKind::Rejected, start));
+        assert!(!detector.record(LogErrorKind::HandshakeFailure, start));
+        assert!(!detector.record(LogErrorKind::Timeout, start + Duration::from_secs(5)));
+        // The first failure falls out of the window
+        assert!(!detector.record(
+            LogErrorKind::ConnectionRefused,
+            start + Duration::from_secs(11)
+        ));
+        assert!(detector.record(
+            LogErrorKind::ConnectionRefused,
+            start + Duration::from_secs(12)
+        ));
+        // Counting starts over
+        assert!(!detector.record(
+            LogErrorKind::ConnectionRefused,
+            start + Duration::from_secs(12)
+        ));
+    }
+
+    #[test]
+    fn test_error_burst_ignores_direct_failures() {
+        let mut detector = ErrorBurstDetector::new(2, Duration::from_secs(10));
+        let now = Instant::now();
+        let error = |outbound: &str| DetectedError {
+            kind: LogErrorKind::Timeout,
+            component: Some("transport/internet/tcp".to_string()),
+            outbound: Some(outbound.to_string()),
+            message: "dial tcp 1.2.3.4:443: i/o timeout".to_string(),
+        };
+
+        for _ in 0..5 {
+            assert!(!detector.record_error(&error("direct"), now));
+        }
+        assert!(!detector.record_error(&error("proxy"), now));
+        assert!(detector.record_error(&error("proxy-1"), now));
+    }
+
     #[test]
     fn test_reconnect_config_default() {
         let config = ReconnectConfig::default();
@@ -261,6 +594,98 @@ mod tests {
         assert_eq!(config.calculate_delay(10), Duration::from_secs(60));
     }
 
+    #[test]
+    fn test_apply_jitter() {
+        let delay = Duration::from_secs(10);
+        assert_eq!(apply_jitter(delay, 0.2, 0.0), Duration::from_secs(8));
+        assert_eq!(apply_jitter(delay, 0.2, 0.5), delay);
+        assert_eq!(apply_jitter(delay, 0.0, 0.9), delay);
+        assert!(apply_jitter(delay, 0.2, 0.999) <= Duration::from_secs(12));
+
+        for _ in 0..100 {
+            let sample = random_unit();
+            assert!((0.0..1.0).contains(&sample));
+        }
+    }
+
+    #[test]
+    fn test_delay_for_class() {
+        let config = ReconnectConfig::default();
+
+        // Crashes use the top-level strategy
+        for attempt in 0..4 {
+            let delay = config.delay_for(FailureClass::Crash, attempt);
+            let base = config.calculate_delay(attempt).as_secs_f64();
+            assert!(delay.as_secs_f64() >= base * 0.8 - 1e-9);
+            assert!(delay.as_secs_f64() <= base * 1.2 + 1e-9);
+        }
+
+        // Network failures back off further; jitter never exceeds the cap
+        assert!(config.delay_for(FailureClass::NetworkUnreachable, 0) >= Duration::from_secs(4));
+        for _ in 0..20 {
+            assert!(
+                config.delay_for(FailureClass::NetworkUnreachable, 10) <= Duration::from_secs(120)
+            );
+        }
+
+        let large = ReconnectConfig::exponential_backoff(
+            Duration::from_secs(1),
+            Duration::from_secs(60),
+            2.0,
+            0,
+        );
+        assert_eq!(large.calculate_delay(u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_retry_denial() {
+        let config = ReconnectConfig::default();
+        let minute = Duration::from_secs(60);
+
+        assert_eq!(config.retry_denial(FailureClass::Crash, 4, minute), None);
+        assert!(config
+            .retry_denial(FailureClass::Crash, 5, minute)
+            .is_some());
+
+        // Unlimited attempts for network failures, bounded by the window
+        assert_eq!(
+            config.retry_denial(FailureClass::NetworkUnreachable, 50, minute),
+            None
+        );
+        assert!(config
+            .retry_denial(
+                FailureClass::NetworkUnreachable,
+                1,
+                DEFAULT_MAX_RETRY_WINDOW
+            )
+            .is_some());
+
+        let unbounded = ReconnectConfig {
+            max_retry_window: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(
+            unbounded.retry_denial(FailureClass::NetworkUnreachable, 1, 100 * minute),
+            None
+        );
+
+        let disabled = ReconnectConfig::disabled();
+        assert!(disabled
+            .retry_denial(FailureClass::NetworkUnreachable, 0, Duration::ZERO)
+            .is_some());
+    }
+
+    #[test]
+    fn test_reconnect_config_legacy_json() {
+        let json = r#"{"strategy":"Immediate","max_attempts":3,"enabled":true}"#;
+        let config: ReconnectConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.jitter, DEFAULT_JITTER);
+        assert_eq!(config.stable_reset_after, DEFAULT_STABLE_RESET_AFTER);
+        assert!(config
+            .policies
+            .contains_key(&FailureClass::NetworkUnreachable));
+    }
+
     #[test]
     fn test_reconnect_strategy_serialization() {
         let strategy = ReconnectStrategy::ExponentialBackoff {
@@ -0,0 +1,234 @@
+//! Runtime state file
+//!
+//! Minimal record of what the app has changed outside itself (connection,
+//! Xray process, system proxy, TUN routes), rewritten on every change. If
+//! the app exits without clearing it, the next start knows the previous
+//! session ended uncleanly: it can offer to resume it, stop the orphaned
+//! Xray process and undo leftover system changes.
+//!
+//! Writes go to a temporary file that is synced and then renamed over the
+//! state file, so a crash leaves either the old or the new state, never a
+//! partial one.
+
+use super::session::LastSession;
+use crate::error::{StorageError, StorageResult};
+use crate::platform::SystemProxySnapshot;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Default state file name inside the app data directory
+pub const STATE_FILE_NAME: &str = "runtime_state.json";
+
+/// Runtime intent of the current session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeState {
+    /// Whether a proxy connection is up
+    #[serde(default)]
+    pub connected: bool,
+    /// Server of the last connection
+    #[serde(default)]
+    pub server_id: Option<String>,
+    /// Proxy mode of the last connection
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// PID of the running Xray process
+    #[serde(default)]
+    pub xray_pid: Option<u32>,
+    /// Whether the system proxy points at the app
+    #[serde(default)]
+    pub system_proxy_applied: bool,
+    /// Commands undoing the TUN routes and DNS, empty when TUN is off
+    #[serde(default)]
+    pub tun_teardown: Vec<Vec<String>>,
+    /// System proxy configuration from before the app set its own,
+    /// restored when the app's proxy is cleared
+    #[serde(default)]
+    pub proxy_snapshot: Option<SystemProxySnapshot>,
+    /// What the user left running, kept through a clean shutdown
+    #[serde(default)]
+    pub last_session: LastSession,
+    /// Whether to restore the last session at launch
+    #[serde(default)]
+    pub reconnect_on_launch: bool,
+    /// Last write time
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Default for RuntimeState {
+    fn default() -> Self {
+        Self {
+            connected: false,
+            server_id: None,
+            mode: None,
+            xray_pid: None,
+            system_proxy_applied: false,
+            tun_teardown: Vec::new(),
+            proxy_snapshot: None,
+            last_session: LastSession::default(),
+            reconnect_on_launch: false,
+            updated_at: Utc::now(),
+        }
+    }
+}
+
+impl RuntimeState {
+    /// Whether TUN mode is active
+    pub fn tun_enabled(&self) -> bool {
+        !self.tun_teardown.is_empty()
+    }
+
+    /// Whether nothing is left to undo
+    ///
+    /// A state file that is not clean at startup means the previous session
+    /// did not shut down properly.
+    pub fn is_clean(&self) -> bool {
+        !self.connected
+            && self.xray_pid.is_none()
+            && !self.system_proxy_applied
+            && !self.tun_enabled()
+    }
+}
+
+/// Runtime state persisted to a file
+pub struct RuntimeStateStore {
+    /// State file path
+    path: PathBuf,
+    /// Current state
+    state: Mutex<RuntimeState>,
+}
+
+impl RuntimeStateStore {
+    /// Open the state file at `path`, starting from its last saved state
+    ///
+    /// A missing or unreadable file yields the default (clean) state.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let state = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring corrupt runtime state file: {}", e);
+                RuntimeState::default()
+            }),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to read runtime state file: {}", e);
+                }
+                RuntimeState::default()
+            }
+        };
+
+        Self {
+            path,
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Get the state file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the current state
+    pub fn state(&self) -> RuntimeState {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Apply `f` to the state and persist the result
+    ///
+    /// The in-memory state is only changed once the write succeeded.
+    pub fn update(&self, f: impl FnOnce(&mut RuntimeState)) -> StorageResult<RuntimeState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut next = state.clone();
+        f(&mut next);
+        next.updated_at = Utc::now();
+
+        write_state(&self.path, &next)?;
+        *state = next.clone();
+        Ok(next)
+    }
+}
+
+/// Write the state atomically
+fn write_state(path: &Path, state: &RuntimeState) -> StorageResult<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let content =
+        serde_json::to_vec_pretty(state).map_err(|e| StorageError::Serialization(e.to_string()))?;
+    let temp_path = path.with_extension("tmp");
+    {
+        let mut file = std::fs::File::create(&temp_path)?;
+        file.write_all(&content)?;
+        file.sync_all()?;
+    }
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_update_and_reopen() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(STATE_FILE_NAME);
+
+        let store = RuntimeStateStore::open(&path);
+        assert!(store.state().is_clean());
+
+        store
+            .update(|s| {
+                s.connected = true;
+                s.server_id = Some("server-1".to_string());
+                s.mode = Some("smart".to_string());
+                s.xray_pid = Some(4242);
+                s.system_proxy_applied = true;
+                s.proxy_snapshot = Some(SystemProxySnapshot::Linux {
+                    settings: vec![(
+                        "org.gnome.system.proxy".to_string(),
+                        "mode".to_string(),
+                        "'auto'".to_string(),
+                    )],
+                    kde: vec![],
+                });
+            })
+            .unwrap();
+        assert!(!path.with_extension("tmp").exists());
+
+        // Simulates a restart after a crash
+        let reopened = RuntimeStateStore::open(&path);
+        let state = reopened.state();
+        assert!(!state.is_clean());
+        assert_eq!(state.server_id.as_deref(), Some("server-1"));
+        assert_eq!(state.mode.as_deref(), Some("smart"));
+        assert_eq!(state.xray_pid, Some(4242));
+        assert_eq!(state, store.state());
+
+        reopened
+            .update(|s| {
+                s.connected = false;
+                s.xray_pid = None;
+                s.system_proxy_applied = false;
+            })
+            .unwrap();
+        assert!(RuntimeStateStore::open(&path).state().is_clean());
+    }
+
+    #[test]
+    fn test_corrupt_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(STATE_FILE_NAME);
+        std::fs::write(&path, "{\"connected\": tr").unwrap();
+
+        let store = RuntimeStateStore::open(&path);
+        assert!(!store.state().connected);
+        assert!(store.update(|s| s.connected = true).is_ok());
+        assert!(RuntimeStateStore::open(&path).state().connected);
+    }
+}
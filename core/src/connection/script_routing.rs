@@ -0,0 +1,966 @@
+//! Scripted routing of local connections
+//!
+//! For decisions the rule model cannot express, a Rhai script can pick the
+//! outbound of each connection. Scripting is off by default. When on, the
+//! HTTP and SOCKS inbounds are fronted by a local router (see
+//! [`inbound_front`](super::inbound_front)): the router reads
+//! the target of each connection, calls the script's `route(conn)` function
+//! and relays the connection to an internal inbound of the same protocol
+//! whose traffic Xray sends to the returned outbound tag. When the script
+//! returns `()` or an unknown tag, the connection goes to the original
+//! inbound and follows the routing rules.
+//!
+//! `conn` is a map with `host` (requested domain or IP), `ip` (the IP if
+//! the target is an address, otherwise empty), `port` and `inbound`
+//! (`http` or `socks`):
+//!
+//! ```text
+//! fn route(conn) {
+//!     if conn.host.ends_with(".lan") { return "direct"; }
+//!     if conn.port == 25 { return "block"; }
+//! }
+//! ```
+//!
+//! Each call runs with an operation limit and a time limit; a script that
+//! fails or runs out of time counts as returning `()`. The script sees the
+//! first request of an HTTP connection and SOCKS CONNECT requests; later
+//! requests on a kept-alive HTTP connection reuse its outbound and UDP
+//! follows the rules. Inbounds requiring authentication are not fronted.
+//!
+//! As with the speed limit, fronted inbounds reach Xray from loopback, so
+//! LAN client addresses are lost to source IP rules. SOCKS inbounds shared
+//! on the LAN are not fronted, keeping their UDP relay reachable from other
+//! devices, and follow the routing rules only.
+
+use super::inbound_front::{FrontRegistry, InboundFront};
+use crate::error::ConfigError;
+use crate::xray::{InboundConfig, PortReassignment, RoutingConfig, XrayConfig};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::{debug, warn};
+
+/// Prefix of the tags of the internal inbounds, followed by the protocol
+/// and the outbound tag
+pub const SCRIPT_INBOUND_TAG_PREFIX: &str = "script-";
+
+/// Default time limit of one script call in milliseconds
+pub const DEFAULT_SCRIPT_TIMEOUT_MS: u64 = 10;
+
+/// Longest time limit of one script call in milliseconds
+const MAX_SCRIPT_TIMEOUT_MS: u64 = 1000;
+
+/// Operations one script call may run
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// Inbound protocols that can be fronted
+const ROUTED_PROTOCOLS: &[&str] = &["http", "socks"];
+
+/// Largest HTTP request head read to find the target
+const MAX_HTTP_HEAD: usize = 16 * 1024;
+
+/// Time a client has to send its request
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+thread_local! {
+    /// Deadline of the script call running on this thread
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Routing script settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptRoutingSettings {
+    /// Whether connections are routed by the script
+    #[serde(default)]
+    pub enabled: bool,
+    /// Rhai source defining `fn route(conn)`
+    #[serde(default)]
+    pub script: String,
+    /// Time limit of one call in milliseconds
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_SCRIPT_TIMEOUT_MS
+}
+
+impl Default for ScriptRoutingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            script: String::new(),
+            timeout_ms: DEFAULT_SCRIPT_TIMEOUT_MS,
+        }
+    }
+}
+
+impl ScriptRoutingSettings {
+    /// Compile the script with the time limit
+    pub fn compile(&self) -> Result<RoutingScript, ConfigError> {
+        if !(1..=MAX_SCRIPT_TIMEOUT_MS).contains(&self.timeout_ms) {
+            return Err(ConfigError::Validation(format!(
+                "Routing script time limit must be 1 to {} ms",
+                MAX_SCRIPT_TIMEOUT_MS
+            )));
+        }
+        RoutingScript::compile(&self.script, Duration::from_millis(self.timeout_ms))
+    }
+
+    /// Validate the settings, compiling the script when enabled
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.enabled {
+            self.compile()?;
+        }
+        Ok(())
+    }
+}
+
+/// Connection passed to the script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionMeta {
+    /// Requested domain or IP
+    pub host: String,
+    /// Requested port
+    pub port: u16,
+    /// Protocol of the inbound the connection came in on
+    pub inbound: String,
+}
+
+impl ConnectionMeta {
+    fn to_map(&self) -> Map {
+        let ip = self
+            .host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map(|ip| ip.to_string())
+            .unwrap_or_default();
+        let mut map = Map::new();
+        map.insert("host".into(), self.host.clone().into());
+        map.insert("ip".into(), ip.into());
+        map.insert("port".into(), Dynamic::from_int(self.port.into()));
+        map.insert("inbound".into(), self.inbound.clone().into());
+        map
+    }
+}
+
+/// Compiled routing script
+pub struct RoutingScript {
+    engine: Engine,
+    ast: AST,
+    timeout: Duration,
+}
+
+impl std::fmt::Debug for RoutingScript {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RoutingScript")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RoutingScript {
+    /// Compile `source`, which must define `fn route(conn)`
+    pub fn compile(source: &str, timeout: Duration) -> Result<Self, ConfigError> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(MAX_OPERATIONS)
+            .set_max_call_levels(32)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(4096)
+            .set_max_array_size(1024)
+            .set_max_map_size(256)
+            .set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new())
+            .disable_symbol("eval")
+            .on_print(|text| debug!("Routing script: {}", text))
+            .on_debug(|text, _, _| debug!("Routing script: {}", text))
+            .on_progress(|_| {
+                let expired = DEADLINE.with(|deadline| {
+                    deadline
+                        .get()
+                        .is_some_and(|deadline| Instant::now() >= deadline)
+                });
+                expired.then(|| Dynamic::from("timeout"))
+            });
+
+        let ast = engine
+            .compile(source)
+            .map_err(|e| ConfigError::Validation(format!("Invalid routing script: {}", e)))?;
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "route" && f.params.len() == 1)
+        {
+            return Err(ConfigError::Validation(
+                "Routing script must define fn route(conn)".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            engine,
+            ast,
+            timeout,
+        })
+    }
+
+    /// Outbound tag the script picks for `conn`, None to follow the rules
+    ///
+    /// Runs the script on the calling thread for up to the time limit.
+    pub fn route(&self, conn: &ConnectionMeta) -> Option<String> {
+        DEADLINE.with(|deadline| deadline.set(Some(Instant::now() + self.timeout)));
+        let result =
+            self.engine
+                .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, "route", (conn.to_map(),));
+        DEADLINE.with(|deadline| deadline.set(None));
+
+        match result {
+            Ok(tag) if tag.is_unit() => None,
+            Ok(tag) => match tag.into_string() {
+                Ok(tag) => Some(tag),
+                Err(kind) => {
+                    warn!("Routing script returned {} instead of a tag", kind);
+                    None
+                }
+            },
+            Err(e) => {
+                warn!(
+                    "Routing script failed for {}:{}: {}",
+                    conn.host, conn.port, e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Tag of the internal inbound for `protocol` connections to `outbound`
+fn script_inbound_tag(protocol: &str, outbound: &str) -> String {
+    format!("{}{}-{}", SCRIPT_INBOUND_TAG_PREFIX, protocol, outbound)
+}
+
+/// Whether an inbound requires authentication
+fn requires_auth(inbound: &InboundConfig) -> bool {
+    inbound.settings.as_ref().is_some_and(|settings| {
+        settings.get("auth").and_then(|auth| auth.as_str()) == Some("password")
+            || settings
+                .get("accounts")
+                .and_then(|accounts| accounts.as_array())
+                .is_some_and(|accounts| !accounts.is_empty())
+    })
+}
+
+/// Routed protocol of an inbound selected for fronting
+fn routed_protocol(protocol: &str) -> &'static str {
+    ROUTED_PROTOCOLS
+        .iter()
+        .copied()
+        .find(|routed| *routed == protocol)
+        .unwrap_or("socks")
+}
+
+/// Script and internal ports shared by the fronts
+#[derive(Debug, Default)]
+struct RouterState {
+    /// Script of the running connection
+    script: Option<Arc<RoutingScript>>,
+    /// Internal inbound ports by inbound tag
+    ports: HashMap<String, u16>,
+}
+
+impl RouterState {
+    /// Internal port for `protocol` connections the script sends to `tag`
+    fn port(&self, protocol: &str, tag: &str) -> Option<u16> {
+        self.ports.get(&script_inbound_tag(protocol, tag)).copied()
+    }
+}
+
+/// Target requested by a client, and the bytes read to find it
+struct Handshake {
+    /// Target, None for requests that are not routed by the script
+    target: Option<(String, u16)>,
+    /// Bytes to replay to the chosen inbound
+    replay: Vec<u8>,
+}
+
+/// Read the SOCKS request of `client`, answering its greeting
+///
+/// The replay holds a no-authentication greeting followed by the request.
+/// SOCKS4 requests are replayed as read, without a target.
+async fn socks_handshake(client: &mut TcpStream) -> std::io::Result<Handshake> {
+    let version = client.read_u8().await?;
+    if version != 5 {
+        return Ok(Handshake {
+            target: None,
+            replay: vec![version],
+        });
+    }
+    let methods = client.read_u8().await?;
+    let mut buf = vec![0u8; methods.into()];
+    client.read_exact(&mut buf).await?;
+    if !buf.contains(&0) {
+        client.write_all(&[5, 0xff]).await?;
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            "client requires SOCKS authentication",
+        ));
+    }
+    client.write_all(&[5, 0]).await?;
+
+    let mut request = vec![0u8; 4];
+    client.read_exact(&mut request).await?;
+    let host = match request[3] {
+        1 => {
+            let mut addr = [0u8; 4];
+            client.read_exact(&mut addr).await?;
+            request.extend_from_slice(&addr);
+            Ipv4Addr::from(addr).to_string()
+        }
+        3 => {
+            let len = client.read_u8().await?;
+            let mut name = vec![0u8; len.into()];
+            client.read_exact(&mut name).await?;
+            request.push(len);
+            request.extend_from_slice(&name);
+            String::from_utf8_lossy(&name).into_owned()
+        }
+        4 => {
+            let mut addr = [0u8; 16];
+            client.read_exact(&mut addr).await?;
+            request.extend_from_slice(&addr);
+            std::net::Ipv6Addr::from(addr).to_string()
+        }
+        kind => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unknown SOCKS address type {}", kind),
+            ))
+        }
+    };
+    let port = client.read_u16().await?;
+    request.extend_from_slice(&port.to_be_bytes());
+
+    let mut replay = vec![5, 1, 0];
+    replay.extend_from_slice(&request);
+    Ok(Handshake {
+        // Only CONNECT is routed by the script
+        target: (request[1] == 1).then_some((host, port)),
+        replay,
+    })
+}
+
+/// Split `authority` into host and port, with `default_port` if it has none
+fn split_authority(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        let port = match rest.strip_prefix(':') {
+            Some(port) => port.parse().ok()?,
+            None => default_port,
+        };
+        return Some((host.to_string(), port));
+    }
+    match authority.rsplit_once(':') {
+        Some((host, port)) => Some((host.to_string(), port.parse().ok()?)),
+        None => Some((authority.to_string(), default_port)),
+    }
+}
+
+/// Target of an HTTP proxy request head
+fn http_target(head: &[u8]) -> Option<(String, u16)> {
+    let head = std::str::from_utf8(head).ok()?;
+    let mut parts = head.lines().next()?.split_whitespace();
+    let method = parts.next()?;
+    let target = parts.next()?;
+    if method.eq_ignore_ascii_case("CONNECT") {
+        return split_authority(target, 443);
+    }
+    let (scheme, rest) = target.split_once("://")?;
+    let default_port = if scheme.eq_ignore_ascii_case("https") {
+        443
+    } else {
+        80
+    };
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let authority = authority.rsplit('@').next()?;
+    split_authority(authority, default_port).filter(|(host, _)| !host.is_empty())
+}
+
+/// Read the request head of `client`
+async fn http_handshake(client: &mut TcpStream) -> std::io::Result<Handshake> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 2048];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() >= MAX_HTTP_HEAD {
+            break;
+        }
+        let n = client.read(&mut buf).await?;
+        if n == 0 {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buf[..n]);
+    }
+    Ok(Handshake {
+        target: http_target(&head),
+        replay: head,
+    })
+}
+
+/// Route one client connection of a `protocol` front
+async fn route_connection(
+    mut client: TcpStream,
+    protocol: &'static str,
+    default_port: u16,
+    state: Arc<RwLock<RouterState>>,
+) -> std::io::Result<()> {
+    let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, async {
+        match protocol {
+            "socks" => socks_handshake(&mut client).await,
+            _ => http_handshake(&mut client).await,
+        }
+    })
+    .await
+    .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+    let script = state
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .script
+        .clone();
+    let mut upstream_port = default_port;
+    if let (Some((host, port)), Some(script)) = (handshake.target, script) {
+        let conn = ConnectionMeta {
+            host,
+            port,
+            inbound: protocol.to_string(),
+        };
+        let (tag, conn) = tokio::task::spawn_blocking(move || (script.route(&conn), conn))
+            .await
+            .map_err(std::io::Error::other)?;
+        if let Some(tag) = tag {
+            match state
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .port(protocol, &tag)
+            {
+                Some(port) => {
+                    debug!("Routing script sent {}:{} to {}", conn.host, conn.port, tag);
+                    upstream_port = port;
+                }
+                None => warn!("Routing script returned unknown outbound {}", tag),
+            }
+        }
+    }
+
+    let mut server = TcpStream::connect(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::LOCALHOST),
+        upstream_port,
+    ))
+    .await?;
+    let _ = client.set_nodelay(true);
+    let _ = server.set_nodelay(true);
+    if protocol == "socks" && handshake.replay.first() == Some(&5) {
+        // Answer the replayed greeting here, Xray's reply to the request
+        // goes to the client
+        let (greeting, request) = handshake.replay.split_at(3);
+        server.write_all(greeting).await?;
+        let mut method = [0u8; 2];
+        server.read_exact(&mut method).await?;
+        server.write_all(request).await?;
+    } else {
+        server.write_all(&handshake.replay).await?;
+    }
+    tokio::io::copy_bidirectional(&mut client, &mut server).await?;
+    Ok(())
+}
+
+/// Start a front on `public` routing `protocol` connections, by default to
+/// `upstream_port` on localhost
+///
+/// Must be called within a Tokio runtime.
+fn start_router(
+    public: SocketAddr,
+    protocol: &'static str,
+    upstream_port: u16,
+    state: Arc<RwLock<RouterState>>,
+) -> std::io::Result<InboundFront> {
+    InboundFront::start(public, upstream_port, move |client, default_port| {
+        route_connection(client, protocol, default_port, state.clone())
+    })
+}
+
+/// Scripted routing of the local inbounds of the main connection
+#[derive(Debug, Default)]
+pub struct ScriptRouter {
+    /// Current settings
+    settings: Mutex<ScriptRoutingSettings>,
+    /// Script and internal ports used by the fronts
+    state: Arc<RwLock<RouterState>>,
+    /// Running fronts
+    fronts: FrontRegistry,
+}
+
+impl ScriptRouter {
+    /// Create a router with scripting off
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Current settings
+    pub fn settings(&self) -> ScriptRoutingSettings {
+        self.settings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Change the settings
+    ///
+    /// While the inbounds are fronted, the new script (or none, when
+    /// disabled) applies to new connections at once. Whether the inbounds
+    /// are fronted at all is decided by [`apply`](Self::apply) when Xray
+    /// starts, so turning scripting on takes effect with the next
+    /// connection.
+    pub fn set_settings(&self, settings: ScriptRoutingSettings) -> Result<(), ConfigError> {
+        let script = if settings.enabled {
+            Some(Arc::new(settings.compile()?))
+        } else {
+            None
+        };
+        if !self.fronts.is_empty() {
+            self.state.write().unwrap_or_else(|e| e.into_inner()).script = script;
+        }
+        *self.settings.lock().unwrap_or_else(|e| e.into_inner()) = settings;
+        Ok(())
+    }
+
+    /// Front the HTTP and SOCKS inbounds of `config` when scripting is on,
+    /// see [`FrontRegistry::apply`]
+    ///
+    /// For each outbound an internal inbound per fronted protocol is added
+    /// along with a leading rule sending its traffic to that outbound.
+    /// Internal ports already in use are kept. Inbounds requiring
+    /// authentication and SOCKS inbounds shared on the LAN are left as they
+    /// are.
+    pub fn apply(&self, config: &mut XrayConfig) {
+        let settings = self.settings();
+        let script = if settings.enabled {
+            match settings.compile() {
+                Ok(script) => Some(Arc::new(script)),
+                Err(e) => {
+                    warn!("Routing script disabled: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let Some(script) = script else {
+            self.stop();
+            return;
+        };
+
+        let fronted = self.fronts.apply(
+            config,
+            "script routing",
+            |inbound| {
+                ROUTED_PROTOCOLS.contains(&inbound.protocol.as_str())
+                    && inbound.tag.is_none()
+                    && !requires_auth(inbound)
+                    && !(inbound.protocol == "socks" && inbound.listens_beyond_loopback())
+            },
+            |inbound, public, upstream| {
+                start_router(
+                    public,
+                    routed_protocol(&inbound.protocol),
+                    upstream,
+                    self.state.clone(),
+                )
+            },
+        );
+        let mut protocols = Vec::new();
+        let mut sniffing = None;
+        for inbound in fronted.iter().map(|&index| &config.inbounds[index]) {
+            let protocol = routed_protocol(&inbound.protocol);
+            if !protocols.contains(&protocol) {
+                protocols.push(protocol);
+            }
+            sniffing = sniffing.or_else(|| inbound.sniffing.clone());
+        }
+
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+        if fronted.is_empty() {
+            *state = RouterState::default();
+            return;
+        }
+        state.script = Some(script);
+
+        let outbounds: Vec<String> = config
+            .outbounds
+            .iter()
+            .filter_map(|outbound| outbound.tag.clone())
+            .filter(|tag| tag != crate::xray::api::API_TAG)
+            .collect();
+        let mut ports = HashMap::new();
+        let mut rules = Vec::new();
+        for protocol in &protocols {
+            for outbound in &outbounds {
+                let tag = script_inbound_tag(protocol, outbound);
+                let port = match state.ports.get(&tag) {
+                    Some(&port) => port,
+                    None => match crate::utils::ports::find_free_port(None) {
+                        Ok(port) => port,
+                        Err(e) => {
+                            warn!("No free port for the {} inbound: {}", tag, e);
+                            continue;
+                        }
+                    },
+                };
+                config.inbounds.push(InboundConfig {
+                    port,
+                    protocol: protocol.to_string(),
+                    listen: Some(Ipv4Addr::LOCALHOST.to_string()),
+                    settings: Some(match *protocol {
+                        "socks" => serde_json::json!({"auth": "noauth", "udp": false}),
+                        _ => serde_json::json!({}),
+                    }),
+                    sniffing: sniffing.clone(),
+                    tag: Some(tag.clone()),
+                });
+                rules.push(serde_json::json!({
+                    "type": "field",
+                    "inboundTag": [tag.clone()],
+                    "outboundTag": outbound,
+                }));
+                ports.insert(tag, port);
+            }
+        }
+        state.ports = ports;
+
+        let routing = config.routing.get_or_insert_with(|| RoutingConfig {
+            domain_strategy: None,
+            rules: Vec::new(),
+            balancers: Vec::new(),
+        });
+        routing.rules.splice(0..0, rules);
+    }
+
+    /// Point the router at the ports Xray moved its inbounds to, see
+    /// [`XrayCore::reload`](crate::xray::XrayCore::reload)
+    pub fn follow(&self, reassignments: &[PortReassignment]) {
+        self.fronts.follow(reassignments);
+        for port in self
+            .state
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .ports
+            .values_mut()
+        {
+            if let Some(reassignment) = reassignments
+                .iter()
+                .find(|reassignment| reassignment.old_port == *port)
+            {
+                *port = reassignment.new_port;
+            }
+        }
+    }
+
+    /// Public port local applications use for Xray's inbound `port`, see
+    /// [`FrontRegistry::public_port`]
+    pub fn public_port(&self, port: u16) -> u16 {
+        self.fronts.public_port(port)
+    }
+
+    /// Stop all fronts
+    pub fn stop(&self) {
+        self.fronts.stop();
+        *self.state.write().unwrap_or_else(|e| e.into_inner()) = RouterState::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncRead;
+    use tokio::net::TcpListener;
+
+    fn conn(host: &str, port: u16) -> ConnectionMeta {
+        ConnectionMeta {
+            host: host.to_string(),
+            port,
+            inbound: "socks".to_string(),
+        }
+    }
+
+    fn settings(script: &str) -> ScriptRoutingSettings {
+        ScriptRoutingSettings {
+            enabled: true,
+            script: script.to_string(),
+            timeout_ms: DEFAULT_SCRIPT_TIMEOUT_MS,
+        }
+    }
+
+    #[test]
+    fn test_route() {
+        let script = settings(
+            r#"
+            fn route(conn) {
+                if conn.host.ends_with(".lan") { return "direct"; }
+                if conn.ip != "" && conn.port == 25 { return "block"; }
+            }
+            "#,
+        )
+        .compile()
+        .unwrap();
+        assert_eq!(
+            script.route(&conn("nas.lan", 443)).as_deref(),
+            Some("direct")
+        );
+        assert_eq!(script.route(&conn("1.2.3.4", 25)).as_deref(), Some("block"));
+        assert_eq!(script.route(&conn("mail.example.com", 25)), None);
+        assert_eq!(script.route(&conn("example.com", 443)), None);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(ScriptRoutingSettings::default().validate().is_ok());
+        assert!(settings("fn other(conn) {}").validate().is_err());
+        assert!(settings("fn route(conn) {").validate().is_err());
+        assert!(settings(r#"fn route(conn) { eval("1") }"#)
+            .validate()
+            .is_err());
+        let slow = ScriptRoutingSettings {
+            timeout_ms: 5000,
+            ..settings("fn route(conn) {}")
+        };
+        assert!(slow.validate().is_err());
+    }
+
+    #[test]
+    fn test_time_limit() {
+        let script = ScriptRoutingSettings {
+            timeout_ms: 20,
+            ..settings(
+                r#"fn route(conn) { let x = 0; loop { x += 1; if x < 0 { return "direct"; } } }"#,
+            )
+        };
+        let script = script.compile().unwrap();
+        let started = Instant::now();
+        // The operation limit or the deadline stops the loop
+        assert_eq!(script.route(&conn("example.com", 443)), None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // Errors count as no decision
+        let failing = settings(r#"fn route(conn) { throw "no"; }"#)
+            .compile()
+            .unwrap();
+        assert_eq!(failing.route(&conn("example.com", 443)), None);
+    }
+
+    #[test]
+    fn test_http_target() {
+        assert_eq!(
+            http_target(b"CONNECT example.com:8443 HTTP/1.1\r\nHost: example.com\r\n\r\n"),
+            Some(("example.com".to_string(), 8443))
+        );
+        assert_eq!(
+            http_target(b"GET http://example.com/path HTTP/1.1\r\n\r\n"),
+            Some(("example.com".to_string(), 80))
+        );
+        assert_eq!(
+            http_target(b"CONNECT [::1]:443 HTTP/1.1\r\n\r\n"),
+            Some(("::1".to_string(), 443))
+        );
+        assert_eq!(http_target(b"GET /path HTTP/1.1\r\n\r\n"), None);
+    }
+
+    fn test_config() -> XrayConfig {
+        let server = crate::config::ProxyServerConfig {
+            id: "server".to_string(),
+            name: "Server".to_string(),
+            server: "example.com".to_string(),
+            port: 443,
+            protocol: crate::config::ProxyProtocol::Vless,
+            settings: HashMap::from([("id".to_string(), serde_json::json!("test-uuid"))]),
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            raw_name: None,
+        };
+        let mut config = crate::xray::XrayCore::new().generate_config(&server);
+        // Use free ports so the test does not depend on the default ones
+        for inbound in config.inbounds.iter_mut() {
+            inbound.port = crate::utils::ports::find_free_port(None).unwrap();
+        }
+        config
+    }
+
+    #[tokio::test]
+    async fn test_apply_rewrites_inbounds() {
+        let router = ScriptRouter::new();
+        let config = test_config();
+        let mut unchanged = config.clone();
+        router.apply(&mut unchanged);
+        assert_eq!(unchanged.inbounds.len(), config.inbounds.len());
+
+        router
+            .set_settings(settings(r#"fn route(conn) { "direct" }"#))
+            .unwrap();
+        let mut routed = config.clone();
+        router.apply(&mut routed);
+
+        for (inbound, original) in routed.inbounds.iter().zip(&config.inbounds) {
+            if ROUTED_PROTOCOLS.contains(&inbound.protocol.as_str()) {
+                assert_ne!(inbound.port, original.port);
+                assert_eq!(router.public_port(inbound.port), original.port);
+            }
+        }
+        let direct = routed
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.tag.as_deref() == Some("script-socks-direct"))
+            .expect("internal inbound for direct");
+        assert_eq!(direct.listen.as_deref(), Some("127.0.0.1"));
+        // The internal inbounds are routed before any other rule
+        let rules = &routed.routing.as_ref().unwrap().rules;
+        assert!(rules[0]["inboundTag"][0]
+            .as_str()
+            .unwrap()
+            .starts_with(SCRIPT_INBOUND_TAG_PREFIX));
+        assert!(rules.iter().any(|rule| {
+            rule["inboundTag"][0] == "script-http-direct" && rule["outboundTag"] == "direct"
+        }));
+
+        // Applying again keeps the internal ports
+        let mut again = config.clone();
+        router.apply(&mut again);
+        let ports = |config: &XrayConfig| -> Vec<u16> {
+            config.inbounds.iter().map(|inbound| inbound.port).collect()
+        };
+        assert_eq!(ports(&again), ports(&routed));
+
+        // A SOCKS inbound shared on the LAN keeps its address
+        let mut shared = config.clone();
+        let socks = shared
+            .inbounds
+            .iter_mut()
+            .find(|inbound| inbound.protocol == "socks")
+            .unwrap();
+        socks.port = crate::utils::ports::find_free_port(None).unwrap();
+        socks.listen = Some("0.0.0.0".to_string());
+        let socks_port = socks.port;
+        router.apply(&mut shared);
+        let socks = shared
+            .inbounds
+            .iter()
+            .find(|inbound| inbound.protocol == "socks")
+            .unwrap();
+        assert_eq!(socks.port, socks_port);
+        assert_eq!(socks.listen.as_deref(), Some("0.0.0.0"));
+        assert!(!shared
+            .inbounds
+            .iter()
+            .any(|inbound| inbound.tag.as_deref() == Some("script-socks-direct")));
+
+        router.stop();
+        let internal = routed.inbounds[0].port;
+        assert_eq!(router.public_port(internal), internal);
+    }
+
+    /// Read a SOCKS5 reply, returning the bytes read
+    async fn read_socks_reply<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<Vec<u8>> {
+        let mut reply = vec![0u8; 4];
+        reader.read_exact(&mut reply).await?;
+        let len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => usize::from(reader.read_u8().await?),
+            kind => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("unknown SOCKS address type {}", kind),
+                ))
+            }
+        };
+        if reply[3] == 3 {
+            reply.push(len as u8);
+        }
+        let mut rest = vec![0u8; len + 2];
+        reader.read_exact(&mut rest).await?;
+        reply.extend_from_slice(&rest);
+        Ok(reply)
+    }
+
+    /// Accept one SOCKS5 connection and return the requested target
+    async fn fake_socks(listener: TcpListener) -> (String, u16) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [5, 1, 0]);
+        stream.write_all(&[5, 0]).await.unwrap();
+        let mut request = [0u8; 5];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request[..4], &[5, 1, 0, 3]);
+        let mut name = vec![0u8; request[4].into()];
+        stream.read_exact(&mut name).await.unwrap();
+        let port = stream.read_u16().await.unwrap();
+        stream
+            .write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 80])
+            .await
+            .unwrap();
+        stream.write_all(b"pong").await.unwrap();
+        (String::from_utf8(name).unwrap(), port)
+    }
+
+    async fn socks_connect(public: SocketAddr, host: &str) -> Vec<u8> {
+        let mut client = TcpStream::connect(public).await.unwrap();
+        client.write_all(&[5, 1, 0]).await.unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).await.unwrap();
+        assert_eq!(method, [5, 0]);
+        let mut request = vec![5, 1, 0, 3, host.len() as u8];
+        request.extend_from_slice(host.as_bytes());
+        request.extend_from_slice(&443u16.to_be_bytes());
+        client.write_all(&request).await.unwrap();
+        let reply = read_socks_reply(&mut client).await.unwrap();
+        assert_eq!(reply[1], 0);
+        let mut data = vec![0u8; 4];
+        client.read_exact(&mut data).await.unwrap();
+        data
+    }
+
+    #[tokio::test]
+    async fn test_socks_front_routes_by_script() {
+        let default = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let direct = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let default_port = default.local_addr().unwrap().port();
+        let direct_port = direct.local_addr().unwrap().port();
+
+        let state = Arc::new(RwLock::new(RouterState {
+            script: Some(Arc::new(
+                settings(r#"fn route(conn) { if conn.host == "nas.lan" { "direct" } }"#)
+                    .compile()
+                    .unwrap(),
+            )),
+            ports: HashMap::from([(script_inbound_tag("socks", "direct"), direct_port)]),
+        }));
+        let front =
+            start_router("127.0.0.1:0".parse().unwrap(), "socks", default_port, state).unwrap();
+
+        let routed = tokio::spawn(fake_socks(direct));
+        assert_eq!(socks_connect(front.public_addr(), "nas.lan").await, b"pong");
+        assert_eq!(routed.await.unwrap(), ("nas.lan".to_string(), 443));
+
+        let unrouted = tokio::spawn(fake_socks(default));
+        assert_eq!(
+            socks_connect(front.public_addr(), "example.com").await,
+            b"pong"
+        );
+        assert_eq!(unrouted.await.unwrap(), ("example.com".to_string(), 443));
+    }
+}
@@ -0,0 +1,104 @@
+//! Last session
+//!
+//! What the user left running when the app last exited: the selected
+//! server, the proxy mode, and whether the connection and the system proxy
+//! were on. Unlike the rest of the runtime state, this survives a clean
+//! shutdown (which disconnects and clears the system proxy), so with
+//! reconnect on launch enabled the next start can bring the same state back,
+//! for example after a reboot.
+
+use serde::{Deserialize, Serialize};
+
+/// Proxy mode restored when the last session did not record one
+pub const DEFAULT_RESTORE_MODE: &str = "smart";
+
+/// State the user last chose
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LastSession {
+    /// Last selected server
+    #[serde(default)]
+    pub server_id: Option<String>,
+    /// Last proxy mode
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Whether the connection was left up, rather than disconnected by the user
+    #[serde(default)]
+    pub connected: bool,
+    /// Whether the system proxy was left pointing at the app
+    #[serde(default)]
+    pub system_proxy: bool,
+}
+
+impl LastSession {
+    /// Record a successful connection
+    pub fn connected_to(&mut self, server_id: &str, mode: &str) {
+        self.server_id = Some(server_id.to_string());
+        self.mode = Some(mode.to_string());
+        self.connected = true;
+    }
+
+    /// What to restore at launch, if anything
+    ///
+    /// Nothing is restored unless `reconnect_on_launch` is set and the user
+    /// left a connection up.
+    pub fn restore_plan(&self, reconnect_on_launch: bool) -> Option<SessionRestore> {
+        if !reconnect_on_launch || !self.connected {
+            return None;
+        }
+        let server_id = self.server_id.clone().filter(|id| !id.is_empty())?;
+        Some(SessionRestore {
+            server_id,
+            mode: self
+                .mode
+                .clone()
+                .unwrap_or_else(|| DEFAULT_RESTORE_MODE.to_string()),
+            system_proxy: self.system_proxy,
+        })
+    }
+}
+
+/// Connection to bring back at launch
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionRestore {
+    /// Server to connect to
+    pub server_id: String,
+    /// Proxy mode to connect with
+    pub mode: String,
+    /// Whether to set the system proxy once connected
+    pub system_proxy: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restore_plan() {
+        let mut session = LastSession::default();
+        assert_eq!(session.restore_plan(true), None);
+
+        session.connected_to("server-1", "global");
+        session.system_proxy = true;
+        assert_eq!(session.restore_plan(false), None);
+        assert_eq!(
+            session.restore_plan(true),
+            Some(SessionRestore {
+                server_id: "server-1".to_string(),
+                mode: "global".to_string(),
+                system_proxy: true,
+            })
+        );
+
+        // Disconnected by the user
+        session.connected = false;
+        assert_eq!(session.restore_plan(true), None);
+
+        let session = LastSession {
+            server_id: Some("server-2".to_string()),
+            mode: None,
+            connected: true,
+            system_proxy: false,
+        };
+        assert_eq!(session.restore_plan(true).unwrap().mode, "smart");
+    }
+}
@@ -0,0 +1,519 @@
+//! Throughput speed tests
+//!
+//! Latency probes say little about how fast a server is. A speed test
+//! downloads from, and optionally uploads to, an HTTP endpoint through the
+//! local proxy inbound for a fixed time and reports the throughput. Any
+//! endpoint serving a large body on GET and accepting POST bodies works, such
+//! as Cloudflare's speed test or a LibreSpeed backend.
+//!
+//! Results are kept per server so servers can be ranked by throughput.
+
+use super::readiness::proxy_for_inbound;
+use crate::error::ConnectionError;
+use crate::xray::InboundConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Default download endpoint (Cloudflare, 100 MB body)
+pub const DEFAULT_DOWNLOAD_URL: &str = "https://speed.cloudflare.com/__down?bytes=100000000";
+
+/// Default upload endpoint (Cloudflare)
+pub const DEFAULT_UPLOAD_URL: &str = "https://speed.cloudflare.com/__up";
+
+/// Default duration of each phase
+pub const DEFAULT_PHASE_DURATION: Duration = Duration::from_secs(10);
+
+/// Longest allowed phase duration
+const MAX_PHASE_DURATION: Duration = Duration::from_secs(60);
+
+/// Size of each upload request body
+const UPLOAD_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Minimum interval between progress reports
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time allowed for a request to be answered, on top of the phase duration
+const REQUEST_GRACE: Duration = Duration::from_secs(10);
+
+/// Results kept per server
+const RESULTS_PER_SERVER: usize = 10;
+
+/// Speed test settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeedTestConfig {
+    /// URL downloaded from; fetched repeatedly if it ends early
+    pub download_url: String,
+    /// URL uploaded to with POST requests, None to skip the upload phase
+    pub upload_url: Option<String>,
+    /// Duration of each phase
+    pub duration: Duration,
+}
+
+impl Default for SpeedTestConfig {
+    fn default() -> Self {
+        Self {
+            download_url: DEFAULT_DOWNLOAD_URL.to_string(),
+            upload_url: Some(DEFAULT_UPLOAD_URL.to_string()),
+            duration: DEFAULT_PHASE_DURATION,
+        }
+    }
+}
+
+impl SpeedTestConfig {
+    /// Check the URLs and duration
+    pub fn validate(&self) -> Result<(), crate::error::ConfigError> {
+        for url in std::iter::once(&self.download_url).chain(&self.upload_url) {
+            let parsed = url::Url::parse(url).map_err(|e| {
+                crate::error::ConfigError::Validation(format!("Invalid speed test URL: {}", e))
+            })?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                return Err(crate::error::ConfigError::Validation(format!(
+                    "Speed test URL must be http or https: {}",
+                    url
+                )));
+            }
+        }
+        if self.duration.is_zero() || self.duration > MAX_PHASE_DURATION {
+            return Err(crate::error::ConfigError::Validation(format!(
+                "Speed test duration must be between 1 and {} seconds",
+                MAX_PHASE_DURATION.as_secs()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Phase of a speed test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpeedTestPhase {
+    /// Downloading
+    Download,
+    /// Uploading
+    Upload,
+}
+
+impl SpeedTestPhase {
+    /// Lowercase name used in events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Download => "download",
+            Self::Upload => "upload",
+        }
+    }
+}
+
+/// Intermediate result reported while a phase runs
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpeedTestProgress {
+    /// Running phase
+    pub phase: SpeedTestPhase,
+    /// Bytes transferred so far in this phase
+    pub bytes: u64,
+    /// Time elapsed in this phase
+    pub elapsed: Duration,
+    /// Throughput so far in megabits per second
+    pub mbps: f64,
+}
+
+/// Result of a speed test
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeedTestResult {
+    /// Server the test ran through
+    pub server_id: String,
+    /// When the test finished
+    pub tested_at: DateTime<Utc>,
+    /// Download throughput in megabits per second
+    pub download_mbps: f64,
+    /// Upload throughput in megabits per second, if uploads were tested
+    pub upload_mbps: Option<f64>,
+    /// Bytes downloaded
+    pub downloaded_bytes: u64,
+    /// Bytes uploaded
+    pub uploaded_bytes: u64,
+}
+
+/// Throughput of one server across its recent tests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeedRanking {
+    /// Server ID
+    pub server_id: String,
+    /// Median download throughput in megabits per second
+    pub download_mbps: f64,
+    /// Median upload throughput, if any test measured it
+    pub upload_mbps: Option<f64>,
+    /// Number of tests considered
+    pub tests: usize,
+    /// When the server was last tested
+    pub last_tested: DateTime<Utc>,
+}
+
+/// Recent speed test results per server
+#[derive(Debug, Clone, Default)]
+pub struct SpeedTestHistory {
+    results: HashMap<String, VecDeque<SpeedTestResult>>,
+}
+
+impl SpeedTestHistory {
+    /// Record a result, keeping the latest [`RESULTS_PER_SERVER`] per server
+    pub fn record(&mut self, result: SpeedTestResult) {
+        let results = self.results.entry(result.server_id.clone()).or_default();
+        if results.len() == RESULTS_PER_SERVER {
+            results.pop_front();
+        }
+        results.push_back(result);
+    }
+
+    /// Results of a server, oldest first
+    pub fn results(&self, server_id: &str) -> Vec<SpeedTestResult> {
+        self.results
+            .get(server_id)
+            .map(|results| results.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Tested servers ranked by median download throughput, fastest first
+    ///
+    /// Medians keep one unlucky or lucky run from deciding the order.
+    pub fn ranking(&self) -> Vec<SpeedRanking> {
+        let mut ranking: Vec<SpeedRanking> = self
+            .results
+            .iter()
+            .filter_map(|(server_id, results)| {
+                let last = results.back()?;
+                Some(SpeedRanking {
+                    server_id: server_id.clone(),
+                    download_mbps: median(results.iter().map(|r| r.download_mbps))?,
+                    upload_mbps: median(results.iter().filter_map(|r| r.upload_mbps)),
+                    tests: results.len(),
+                    last_tested: last.tested_at,
+                })
+            })
+            .collect();
+        ranking.sort_by(|a, b| b.download_mbps.total_cmp(&a.download_mbps));
+        ranking
+    }
+
+    /// Forget the results of a server
+    pub fn remove(&mut self, server_id: &str) {
+        self.results.remove(server_id);
+    }
+}
+
+/// Median of some values, None if there are none
+fn median(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let mut values: Vec<f64> = values.collect();
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len() % 2 == 1 {
+        values[middle]
+    } else {
+        (values[middle - 1] + values[middle]) / 2.0
+    })
+}
+
+/// Throughput in megabits per second
+pub fn mbps(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs == 0.0 {
+        return 0.0;
+    }
+    bytes as f64 * 8.0 / secs / 1_000_000.0
+}
+
+/// Runs speed tests through an HTTP client
+pub struct SpeedTester {
+    client: reqwest::Client,
+    config: SpeedTestConfig,
+}
+
+impl SpeedTester {
+    /// Create a tester sending requests through a local HTTP inbound
+    pub fn through_inbound(
+        inbound: &InboundConfig,
+        config: SpeedTestConfig,
+    ) -> Result<Self, ConnectionError> {
+        let failed =
+            |e: reqwest::Error| ConnectionError::Failed(format!("Speed test setup failed: {}", e));
+        let client = crate::version::http_client_builder()
+            .proxy(proxy_for_inbound(inbound).map_err(failed)?)
+            .build()
+            .map_err(failed)?;
+        Ok(Self::with_client(client, config))
+    }
+
+    /// Create a tester using `client` as is
+    pub fn with_client(client: reqwest::Client, config: SpeedTestConfig) -> Self {
+        Self { client, config }
+    }
+
+    /// Run the download phase and, if configured, the upload phase
+    ///
+    /// `progress` is called at most every 500 ms while a phase runs.
+    pub async fn run(
+        &self,
+        server_id: &str,
+        mut progress: impl FnMut(SpeedTestProgress),
+    ) -> Result<SpeedTestResult, ConnectionError> {
+        let (downloaded_bytes, download_time) = self.download(&mut progress).await?;
+        let upload = match &self.config.upload_url {
+            Some(url) => Some(self.upload(url, &mut progress).await?),
+            None => None,
+        };
+
+        Ok(SpeedTestResult {
+            server_id: server_id.to_string(),
+            tested_at: Utc::now(),
+            download_mbps: mbps(downloaded_bytes, download_time),
+            upload_mbps: upload.map(|(bytes, elapsed)| mbps(bytes, elapsed)),
+            downloaded_bytes,
+            uploaded_bytes: upload.map(|(bytes, _)| bytes).unwrap_or(0),
+        })
+    }
+
+    /// Download for the phase duration, returning bytes and time taken
+    async fn download(
+        &self,
+        progress: &mut impl FnMut(SpeedTestProgress),
+    ) -> Result<(u64, Duration), ConnectionError> {
+        let mut reporter = Reporter::new(SpeedTestPhase::Download);
+        let start = Instant::now();
+        let deadline = start + self.config.duration;
+
+        'requests: while Instant::now() < deadline {
+            let mut response = self
+                .client
+                .get(&self.config.download_url)
+                .timeout(self.config.duration + REQUEST_GRACE)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| request_failed(SpeedTestPhase::Download, e))?;
+
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match tokio::time::timeout(remaining, response.chunk()).await {
+                    Ok(Ok(Some(chunk))) => {
+                        reporter.add(chunk.len() as u64, start.elapsed(), progress)
+                    }
+                    // The body ended early; fetch it again
+                    Ok(Ok(None)) => break,
+                    // A connection dropped mid-test still measured something
+                    Ok(Err(_)) if reporter.bytes > 0 => break 'requests,
+                    Ok(Err(e)) => return Err(request_failed(SpeedTestPhase::Download, e)),
+                    Err(_) => break 'requests,
+                }
+            }
+        }
+
+        Ok((reporter.bytes, start.elapsed()))
+    }
+
+    /// Upload for the phase duration, returning bytes and time taken
+    async fn upload(
+        &self,
+        url: &str,
+        progress: &mut impl FnMut(SpeedTestProgress),
+    ) -> Result<(u64, Duration), ConnectionError> {
+        let mut reporter = Reporter::new(SpeedTestPhase::Upload);
+        let chunk = vec![0u8; UPLOAD_CHUNK_SIZE];
+        let start = Instant::now();
+        let deadline = start + self.config.duration;
+
+        while Instant::now() < deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let sent = self
+                .client
+                .post(url)
+                .body(chunk.clone())
+                .timeout(remaining)
+                .send()
+                .await
+                .and_then(|r| r.error_for_status());
+            match sent {
+                Ok(_) => reporter.add(chunk.len() as u64, start.elapsed(), progress),
+                // The last request was cut off by the deadline
+                Err(e) if e.is_timeout() && reporter.bytes > 0 => break,
+                Err(e) => return Err(request_failed(SpeedTestPhase::Upload, e)),
+            }
+        }
+
+        Ok((reporter.bytes, start.elapsed()))
+    }
+}
+
+/// Map a request error of a phase
+fn request_failed(phase: SpeedTestPhase, e: reqwest::Error) -> ConnectionError {
+    if e.is_timeout() {
+        ConnectionError::Timeout
+    } else {
+        ConnectionError::Failed(format!("Speed test {} failed: {}", phase.as_str(), e))
+    }
+}
+
+/// Counts bytes of a phase and rate-limits progress reports
+struct Reporter {
+    phase: SpeedTestPhase,
+    bytes: u64,
+    last_report: Option<Duration>,
+}
+
+impl Reporter {
+    fn new(phase: SpeedTestPhase) -> Self {
+        Self {
+            phase,
+            bytes: 0,
+            last_report: None,
+        }
+    }
+
+    fn add(&mut self, bytes: u64, elapsed: Duration, progress: &mut impl FnMut(SpeedTestProgress)) {
+        self.bytes += bytes;
+        if self
+            .last_report
+            .is_some_and(|last| elapsed < last + PROGRESS_INTERVAL)
+        {
+            return;
+        }
+        self.last_report = Some(elapsed);
+        progress(SpeedTestProgress {
+            phase: self.phase,
+            bytes: self.bytes,
+            elapsed,
+            mbps: mbps(self.bytes, elapsed),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// HTTP server answering GET with `body_size` bytes and POST with 200
+    async fn speed_server(body_size: usize) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    loop {
+                        // Read the head, then any body announced in it
+                        let mut head = Vec::new();
+                        let mut byte = [0u8; 1];
+                        while !head.ends_with(b"\r\n\r\n") {
+                            if stream.read(&mut byte).await.unwrap_or(0) == 0 {
+                                return;
+                            }
+                            head.push(byte[0]);
+                        }
+                        let head = String::from_utf8_lossy(&head).to_lowercase();
+                        let length = head
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .and_then(|v| v.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        let mut body = vec![0u8; length];
+                        if stream.read_exact(&mut body).await.is_err() {
+                            return;
+                        }
+
+                        let response = if head.starts_with("get") {
+                            let mut response =
+                                format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body_size)
+                                    .into_bytes();
+                            response.extend(std::iter::repeat_n(b'x', body_size));
+                            response
+                        } else {
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n".to_vec()
+                        };
+                        if stream.write_all(&response).await.is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn test_speed_test_run() {
+        let port = speed_server(256 * 1024).await;
+        let config = SpeedTestConfig {
+            download_url: format!("http://127.0.0.1:{}/down", port),
+            upload_url: Some(format!("http://127.0.0.1:{}/up", port)),
+            duration: Duration::from_millis(600),
+        };
+        assert!(config.validate().is_ok());
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let tester = SpeedTester::with_client(client, config);
+
+        let mut reports = Vec::new();
+        let result = tester
+            .run("server", |progress| reports.push(progress))
+            .await
+            .unwrap();
+
+        assert_eq!(result.server_id, "server");
+        // The short body is fetched repeatedly
+        assert!(result.downloaded_bytes > 256 * 1024);
+        assert!(result.download_mbps > 0.0);
+        assert!(result.uploaded_bytes >= UPLOAD_CHUNK_SIZE as u64);
+        assert!(result.upload_mbps.unwrap() > 0.0);
+        assert_eq!(reports[0].phase, SpeedTestPhase::Download);
+        assert!(reports.iter().any(|r| r.phase == SpeedTestPhase::Upload));
+    }
+
+    #[test]
+    fn test_config_validate() {
+        assert!(SpeedTestConfig::default().validate().is_ok());
+        let config = SpeedTestConfig {
+            download_url: "ftp://example.com/file".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = SpeedTestConfig {
+            duration: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_history_ranking() {
+        let result = |server_id: &str, download_mbps: f64| SpeedTestResult {
+            server_id: server_id.to_string(),
+            tested_at: Utc::now(),
+            download_mbps,
+            upload_mbps: None,
+            downloaded_bytes: 0,
+            uploaded_bytes: 0,
+        };
+
+        let mut history = SpeedTestHistory::default();
+        for mbps in [10.0, 900.0, 12.0] {
+            history.record(result("a", mbps));
+        }
+        history.record(result("b", 50.0));
+        for _ in 0..RESULTS_PER_SERVER + 2 {
+            history.record(result("c", 1.0));
+        }
+
+        let ranking = history.ranking();
+        let order: Vec<_> = ranking.iter().map(|r| r.server_id.as_str()).collect();
+        // The outlier does not put "a" ahead of "b"
+        assert_eq!(order, vec!["b", "a", "c"]);
+        assert_eq!(ranking[1].download_mbps, 12.0);
+        assert_eq!(ranking[2].tests, RESULTS_PER_SERVER);
+
+        history.remove("b");
+        assert!(history.results("b").is_empty());
+        assert_eq!(mbps(1_000_000, Duration::from_secs(8)), 1.0);
+    }
+}
@@ -3,13 +3,18 @@
 //! This module provides functionality for collecting and tracking connection statistics
 //! including traffic data, speed measurements, and historical data.
 
+use futures::Stream;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
+use tokio::time::{Interval, MissedTickBehavior};
 use tracing::{debug, info};
 
+/// Shortest interval of a snapshot stream
+pub const MIN_SNAPSHOT_INTERVAL: Duration = Duration::from_millis(100);
+
 /// Traffic statistics snapshot
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrafficSnapshot {
@@ -102,7 +107,8 @@ impl TrafficStatsCollector {
         let (upload_speed, download_speed) = {
             let last = self.last_snapshot.read().await;
             if let Some(ref last_snap) = *last {
-                let time_diff = (now - last_snap.timestamp).num_seconds() as f64;
+                // Millisecond precision so sub-second intervals report speeds
+                let time_diff = (now - last_snap.timestamp).num_milliseconds() as f64 / 1000.0;
                 if time_diff > 0.0 {
                     let upload_diff = upload.saturating_sub(last_snap.upload_bytes);
                     let download_diff = download.saturating_sub(last_snap.download_bytes);
@@ -202,6 +208,29 @@ impl TrafficStatsCollector {
         (max_upload, max_download)
     }
 
+    /// Stream of snapshots taken every `interval`, starting immediately
+    ///
+    /// The interval is raised to [`MIN_SNAPSHOT_INTERVAL`]. Snapshots go into
+    /// the shared history like any other, and collection stops when the
+    /// stream is dropped.
+    pub fn snapshot_stream(
+        self: Arc<Self>,
+        interval: Duration,
+    ) -> impl Stream<Item = TrafficSnapshot> + Send {
+        let interval = interval.max(MIN_SNAPSHOT_INTERVAL);
+        // The timer is created on first poll, which happens inside a runtime
+        futures::stream::unfold((self, None), move |(collector, timer)| async move {
+            let mut timer: Interval = timer.unwrap_or_else(|| {
+                let mut timer = tokio::time::interval(interval);
+                timer.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                timer
+            });
+            timer.tick().await;
+            let snapshot = collector.take_snapshot().await;
+            Some((snapshot, (collector, Some(timer))))
+        })
+    }
+
     /// Start automatic snapshot collection
     pub async fn start_auto_snapshot(&self, interval: Duration) {
         let collector = Self {
@@ -314,4 +343,25 @@ mod tests {
         assert!(snapshot.upload_speed >= 900 && snapshot.upload_speed <= 1100);
         assert!(snapshot.download_speed >= 1900 && snapshot.download_speed <= 2100);
     }
+
+    #[tokio::test]
+    async fn test_snapshot_stream() {
+        use futures::StreamExt;
+
+        let collector = Arc::new(TrafficStatsCollector::new(10));
+        let mut stream =
+            Box::pin(Arc::clone(&collector).snapshot_stream(Duration::from_millis(200)));
+
+        let first = stream.next().await.unwrap();
+        assert_eq!(first.upload_bytes, 0);
+
+        collector.update_traffic(1000, 2000).await;
+        let second = stream.next().await.unwrap();
+        assert_eq!(second.upload_bytes, 1000);
+        assert_eq!(second.download_bytes, 2000);
+        // About 5000 and 10000 bytes per second over 200 ms
+        assert!(second.upload_speed > 2500 && second.upload_speed < 5500);
+        assert!(second.download_speed > second.upload_speed);
+        assert_eq!(collector.get_snapshots().await.len(), 2);
+    }
 }
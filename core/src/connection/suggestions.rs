@@ -0,0 +1,329 @@
+//! Route Suggestions
+//!
+//! In smart mode some destinations are routed direct although they are only
+//! reachable through the proxy (e.g. a domain missing from the geosite lists).
+//! This module watches Xray logs for repeated direct-connection failures and
+//! turns them into "route via proxy" rule suggestions that can be accepted
+//! with a single call.
+
+use crate::config::routing::OUTBOUND_PROXY;
+use crate::config::{RoutingRule, RoutingRuleSet};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+
+/// Failures within [`FAILURE_WINDOW_MINUTES`] needed before suggesting a rule
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+/// Time window in which failures are counted
+pub const FAILURE_WINDOW_MINUTES: i64 = 10;
+
+/// ID of the rule set accepted suggestions are written to
+pub const SUGGESTED_RULE_SET_ID: &str = "suggested";
+
+/// Xray log marker for a failed direct (freedom) connection
+const DIRECT_FAILURE_MARKER: &str = "proxy/freedom: failed to open connection to ";
+
+/// Second-level labels that are part of a public suffix (e.g. "co.uk")
+const SECOND_LEVEL_SUFFIXES: &[&str] = &["ac", "co", "com", "edu", "gov", "net", "org"];
+
+/// Suggested routing rule
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RouteSuggestion {
+    /// Suggestion ID (the suggested domain)
+    pub id: String,
+    /// Domain to route via the proxy, including subdomains
+    pub domain: String,
+    /// Number of failed direct connections in the current window
+    pub failures: u32,
+    /// Time of the most recent failure
+    pub last_failure: DateTime<Utc>,
+}
+
+impl RouteSuggestion {
+    /// Human-readable description
+    pub fn description(&self) -> String {
+        format!("Route *.{} via proxy", self.domain)
+    }
+
+    /// Routing rule implementing the suggestion
+    pub fn to_rule(&self) -> RoutingRule {
+        RoutingRule {
+            domains: vec![format!("domain:{}", self.domain)],
+            ips: vec![],
+            port: None,
+            processes: vec![],
+            protocols: vec![],
+            outbound_tag: OUTBOUND_PROXY.to_string(),
+        }
+    }
+
+    /// Add the suggested rule to the "suggested" rule set, creating it first
+    /// in the list if needed so it takes precedence over other user rules
+    pub fn apply_to(&self, rule_sets: &mut Vec<RoutingRuleSet>) -> RoutingRule {
+        let rule = self.to_rule();
+
+        let index = match rule_sets
+            .iter()
+            .position(|set| set.id == SUGGESTED_RULE_SET_ID)
+        {
+            Some(index) => index,
+            None => {
+                rule_sets.insert(
+                    0,
+                    RoutingRuleSet {
+                        id: SUGGESTED_RULE_SET_ID.to_string(),
+                        name: "Suggested rules".to_string(),
+                        enabled: true,
+                        rules: Vec::new(),
+                    },
+                );
+                0
+            }
+        };
+
+        let set = &mut rule_sets[index];
+        if !set.rules.contains(&rule) {
+            set.rules.push(rule.clone());
+        }
+        rule
+    }
+}
+
+/// Failure counter for a single domain
+#[derive(Debug, Clone)]
+struct FailureRecord {
+    /// Failure times within the window
+    times: Vec<DateTime<Utc>>,
+}
+
+/// Tracks direct-connection failures and derives route suggestions
+#[derive(Debug, Clone)]
+pub struct RouteSuggestionTracker {
+    /// Whether failures are currently recorded (smart mode only)
+    active: bool,
+    /// Failures needed within the window before suggesting
+    threshold: u32,
+    /// Failures per suggested domain
+    failures: HashMap<String, FailureRecord>,
+    /// Suggestions that were accepted or dismissed
+    resolved: HashSet<String>,
+}
+
+impl Default for RouteSuggestionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RouteSuggestionTracker {
+    /// Create an inactive tracker with the default threshold
+    pub fn new() -> Self {
+        Self {
+            active: false,
+            threshold: DEFAULT_FAILURE_THRESHOLD,
+            failures: HashMap::new(),
+            resolved: HashSet::new(),
+        }
+    }
+
+    /// Set the failure threshold
+    pub fn with_threshold(mut self, threshold: u32) -> Self {
+        self.threshold = threshold.max(1);
+        self
+    }
+
+    /// Enable or disable recording
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    /// Whether failures are currently recorded
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Inspect an Xray log message for a failed direct connection
+    pub fn record_log(&mut self, message: &str) {
+        if let Some(host) = parse_direct_failure(message) {
+            self.record_failure(&host, Utc::now());
+        }
+    }
+
+    /// Record a failed direct connection to `host`
+    pub fn record_failure(&mut self, host: &str, at: DateTime<Utc>) {
+        if !self.active {
+            return;
+        }
+        let Some(domain) = suggestion_domain(host) else {
+            return;
+        };
+        if self.resolved.contains(&domain) {
+            return;
+        }
+
+        let cutoff = at - Duration::minutes(FAILURE_WINDOW_MINUTES);
+        let record = self
+            .failures
+            .entry(domain)
+            .or_insert_with(|| FailureRecord { times: Vec::new() });
+        record.times.retain(|t| *t >= cutoff);
+        record.times.push(at);
+    }
+
+    /// Current suggestions, most failures first
+    pub fn suggestions(&self) -> Vec<RouteSuggestion> {
+        let cutoff = Utc::now() - Duration::minutes(FAILURE_WINDOW_MINUTES);
+
+        let mut suggestions: Vec<RouteSuggestion> = self
+            .failures
+            .iter()
+            .filter_map(|(domain, record)| {
+                let failures = record.times.iter().filter(|t| **t >= cutoff).count() as u32;
+                (failures >= self.threshold).then(|| RouteSuggestion {
+                    id: domain.clone(),
+                    domain: domain.clone(),
+                    failures,
+                    last_failure: record.times.iter().max().copied().unwrap_or_else(Utc::now),
+                })
+            })
+            .collect();
+
+        suggestions.sort_by(|a, b| b.failures.cmp(&a.failures).then(a.id.cmp(&b.id)));
+        suggestions
+    }
+
+    /// Remove a suggestion so it can be accepted, returning it if present
+    pub fn take(&mut self, id: &str) -> Option<RouteSuggestion> {
+        let suggestion = self.suggestions().into_iter().find(|s| s.id == id)?;
+        self.failures.remove(id);
+        self.resolved.insert(id.to_string());
+        Some(suggestion)
+    }
+
+    /// Dismiss a suggestion; it will not be suggested again
+    pub fn dismiss(&mut self, id: &str) -> bool {
+        self.resolved.insert(id.to_string());
+        self.failures.remove(id).is_some()
+    }
+}
+
+/// Extract the destination host from a failed direct connection log message
+///
+/// Example: `... proxy/freedom: failed to open connection to tcp:corp.example.com:443 > ...`
+pub fn parse_direct_failure(message: &str) -> Option<String> {
+    let start = message.find(DIRECT_FAILURE_MARKER)? + DIRECT_FAILURE_MARKER.len();
+    let destination = message[start..].split_whitespace().next()?;
+    let destination = destination
+        .strip_prefix("tcp:")
+        .or_else(|| destination.strip_prefix("udp:"))
+        .unwrap_or(destination);
+    let (host, _port) = destination.rsplit_once(':')?;
+
+    (!host.is_empty()).then(|| host.to_string())
+}
+
+/// Domain to suggest for a host: its registrable domain, so the rule also
+/// covers sibling subdomains
+///
+/// IP addresses and single-label names are not suggested.
+fn suggestion_domain(host: &str) -> Option<String> {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    if host.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|l| l.is_empty()) {
+        return None;
+    }
+
+    let n = labels.len();
+    let keep =
+        if n >= 3 && labels[n - 1].len() == 2 && SECOND_LEVEL_SUFFIXES.contains(&labels[n - 2]) {
+            3
+        } else {
+            2
+        };
+
+    Some(labels[n.saturating_sub(keep)..].join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_direct_failure() {
+        let message = "app/proxyman/outbound: failed to process outbound traffic > proxy/freedom: failed to open connection to tcp:git.corp.example.com:443 > common/retry: all retry attempts failed";
+        assert_eq!(
+            parse_direct_failure(message).as_deref(),
+            Some("git.corp.example.com")
+        );
+        assert!(parse_direct_failure("app/dispatcher: taking detour [direct]").is_none());
+    }
+
+    #[test]
+    fn test_suggestion_domain() {
+        assert_eq!(
+            suggestion_domain("git.corp.example.com").as_deref(),
+            Some("example.com")
+        );
+        assert_eq!(
+            suggestion_domain("www.example.co.uk").as_deref(),
+            Some("example.co.uk")
+        );
+        assert!(suggestion_domain("10.0.0.1").is_none());
+        assert!(suggestion_domain("intranet").is_none());
+    }
+
+    #[test]
+    fn test_threshold_and_accept() {
+        let mut tracker = RouteSuggestionTracker::new();
+        let now = Utc::now();
+
+        // Inactive trackers ignore failures
+        tracker.record_failure("a.example.com", now);
+        assert!(tracker.suggestions().is_empty());
+
+        tracker.set_active(true);
+        tracker.record_failure("a.example.com", now);
+        tracker.record_failure("b.example.com", now);
+        assert!(tracker.suggestions().is_empty());
+        tracker.record_failure("a.example.com", now);
+
+        let suggestions = tracker.suggestions();
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].domain, "example.com");
+        assert_eq!(suggestions[0].failures, 3);
+
+        let suggestion = tracker.take("example.com").unwrap();
+        assert!(tracker.suggestions().is_empty());
+
+        // Accepted domains are not suggested again
+        for _ in 0..3 {
+            tracker.record_failure("a.example.com", now);
+        }
+        assert!(tracker.suggestions().is_empty());
+
+        let mut rule_sets = Vec::new();
+        let rule = suggestion.apply_to(&mut rule_sets);
+        suggestion.apply_to(&mut rule_sets);
+        assert_eq!(rule_sets[0].id, SUGGESTED_RULE_SET_ID);
+        assert_eq!(rule_sets[0].rules, vec![rule.clone()]);
+        assert_eq!(rule.domains, vec!["domain:example.com"]);
+        assert_eq!(rule.outbound_tag, OUTBOUND_PROXY);
+    }
+
+    #[test]
+    fn test_old_failures_expire() {
+        let mut tracker = RouteSuggestionTracker::new().with_threshold(2);
+        tracker.set_active(true);
+
+        let old = Utc::now() - Duration::minutes(FAILURE_WINDOW_MINUTES + 1);
+        tracker.record_failure("example.com", old);
+        tracker.record_failure("example.com", Utc::now());
+        assert!(tracker.suggestions().is_empty());
+    }
+}
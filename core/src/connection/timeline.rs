@@ -0,0 +1,199 @@
+//! Session Event Timeline
+//!
+//! Ordered, timestamped record of what happened during the current connection
+//! session (connect, route changes, degradation, reconnect attempts, system
+//! proxy changes), so the UI can show an activity log rather than only the
+//! final state. The timeline is reset when a new session starts and kept
+//! after disconnecting until the next one.
+
+use crate::xray::HealthSummary;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Maximum number of events kept per session
+pub const MAX_TIMELINE_EVENTS: usize = 500;
+
+/// Kind of session event
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SessionEventKind {
+    /// Connection is being established
+    Connecting,
+    /// Connection established
+    Connected,
+    /// Proxy confirmed to serve traffic
+    Ready,
+    /// Connection or reconnection attempt failed
+    Failed,
+    /// Connection closed
+    Disconnected,
+    /// Proxy mode or routing rules changed
+    RouteChanged,
+    /// Some or all outbounds became unreachable
+    Degraded,
+    /// All outbounds reachable again
+    Recovered,
+    /// Automatic reconnect attempt started
+    ReconnectAttempt,
+    /// Automatic reconnect succeeded
+    Reconnected,
+    /// System proxy (re)applied
+    ProxyApplied,
+    /// System proxy cleared
+    ProxyCleared,
+    /// Default network route changed (e.g. Wi-Fi switched)
+    NetworkChanged,
+}
+
+impl SessionEventKind {
+    /// Stable snake_case name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionEventKind::Connecting => "connecting",
+            SessionEventKind::Connected => "connected",
+            SessionEventKind::Ready => "ready",
+            SessionEventKind::Failed => "failed",
+            SessionEventKind::Disconnected => "disconnected",
+            SessionEventKind::RouteChanged => "route_changed",
+            SessionEventKind::Degraded => "degraded",
+            SessionEventKind::Recovered => "recovered",
+            SessionEventKind::ReconnectAttempt => "reconnect_attempt",
+            SessionEventKind::Reconnected => "reconnected",
+            SessionEventKind::ProxyApplied => "proxy_applied",
+            SessionEventKind::ProxyCleared => "proxy_cleared",
+            SessionEventKind::NetworkChanged => "network_changed",
+        }
+    }
+}
+
+/// A single timeline entry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionEvent {
+    /// Event time
+    pub timestamp: DateTime<Utc>,
+    /// Event kind
+    pub kind: SessionEventKind,
+    /// Human-readable detail
+    pub message: String,
+}
+
+/// Event timeline of the current session
+#[derive(Debug, Clone, Default)]
+pub struct SessionTimeline {
+    /// Events, oldest first
+    events: VecDeque<SessionEvent>,
+    /// Last health summary seen, used to detect transitions
+    last_health: Option<HealthSummary>,
+}
+
+impl SessionTimeline {
+    /// Create an empty timeline
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new session, discarding the previous one
+    pub fn start_session(&mut self) {
+        self.events.clear();
+        self.last_health = None;
+    }
+
+    /// Append an event
+    pub fn record(&mut self, kind: SessionEventKind, message: impl Into<String>) {
+        if self.events.len() >= MAX_TIMELINE_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(SessionEvent {
+            timestamp: Utc::now(),
+            kind,
+            message: message.into(),
+        });
+    }
+
+    /// Record a health check result, adding an event only on transitions
+    pub fn record_health(&mut self, summary: HealthSummary) {
+        let previous = self.last_health.replace(summary);
+        let was_bad = matches!(
+            previous,
+            Some(HealthSummary::Degraded | HealthSummary::Unhealthy)
+        );
+
+        match summary {
+            HealthSummary::Degraded | HealthSummary::Unhealthy if previous != Some(summary) => {
+                self.record(
+                    SessionEventKind::Degraded,
+                    format!("Health check reported {:?}", summary),
+                );
+            }
+            HealthSummary::Healthy if was_bad => {
+                self.record(SessionEventKind::Recovered, "All outbounds reachable");
+            }
+            _ => {}
+        }
+    }
+
+    /// Get all events, oldest first
+    pub fn events(&self) -> Vec<SessionEvent> {
+        self.events.iter().cloned().collect()
+    }
+
+    /// Number of events
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Whether the timeline is empty
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_reset() {
+        let mut timeline = SessionTimeline::new();
+        timeline.record(SessionEventKind::Connecting, "a");
+        timeline.record(SessionEventKind::Connected, "b");
+
+        let events = timeline.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].kind, SessionEventKind::Connecting);
+        assert!(events[0].timestamp <= events[1].timestamp);
+
+        timeline.start_session();
+        assert!(timeline.is_empty());
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut timeline = SessionTimeline::new();
+        for i in 0..MAX_TIMELINE_EVENTS + 10 {
+            timeline.record(SessionEventKind::ReconnectAttempt, i.to_string());
+        }
+        assert_eq!(timeline.len(), MAX_TIMELINE_EVENTS);
+        assert_eq!(timeline.events()[0].message, "10");
+    }
+
+    #[test]
+    fn test_health_transitions() {
+        let mut timeline = SessionTimeline::new();
+        timeline.record_health(HealthSummary::Healthy);
+        timeline.record_health(HealthSummary::Degraded);
+        timeline.record_health(HealthSummary::Degraded);
+        timeline.record_health(HealthSummary::Unhealthy);
+        timeline.record_health(HealthSummary::Healthy);
+
+        let kinds: Vec<_> = timeline.events().iter().map(|e| e.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SessionEventKind::Degraded,
+                SessionEventKind::Degraded,
+                SessionEventKind::Recovered
+            ]
+        );
+    }
+}
@@ -0,0 +1,325 @@
+//! Traffic budgets
+//!
+//! Users on metered plans set a daily or monthly traffic budget, for all
+//! traffic or for the servers of one subscription. Usage is read from the
+//! persistent [`TrafficHistory`](super::traffic_history::TrafficHistory);
+//! an alert is raised once per period as usage crosses each of
+//! [`ALERT_THRESHOLDS`], and on reaching the budget its action runs:
+//! nothing, disconnecting, or switching to direct mode. The action is
+//! enforced again on every check while usage stays over the budget, so
+//! reconnecting does not get around it.
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Percentages of a budget that raise an alert
+pub const ALERT_THRESHOLDS: &[u32] = &[80, 100];
+
+/// Period a budget applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetPeriod {
+    /// Calendar day, local time
+    Daily,
+    /// Calendar month, local time
+    Monthly,
+}
+
+impl BudgetPeriod {
+    /// Name used in settings and the FFI
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetPeriod::Daily => "daily",
+            BudgetPeriod::Monthly => "monthly",
+        }
+    }
+
+    /// First day of the period containing `now`
+    pub fn start(&self, now: DateTime<Local>) -> NaiveDate {
+        let today = now.date_naive();
+        match self {
+            BudgetPeriod::Daily => today,
+            BudgetPeriod::Monthly => today.with_day(1).unwrap_or(today),
+        }
+    }
+}
+
+impl std::str::FromStr for BudgetPeriod {
+    type Err = crate::error::ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "daily" => Ok(BudgetPeriod::Daily),
+            "monthly" => Ok(BudgetPeriod::Monthly),
+            _ => Err(crate::error::ConfigError::Validation(format!(
+                "Invalid budget period: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// What happens when a budget is used up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BudgetAction {
+    /// Only raise the alert
+    #[default]
+    Notify,
+    /// Disconnect the proxy
+    Disconnect,
+    /// Switch to direct mode
+    Direct,
+}
+
+impl BudgetAction {
+    /// Name used in settings and the FFI
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BudgetAction::Notify => "notify",
+            BudgetAction::Disconnect => "disconnect",
+            BudgetAction::Direct => "direct",
+        }
+    }
+}
+
+impl std::str::FromStr for BudgetAction {
+    type Err = crate::error::ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "notify" => Ok(BudgetAction::Notify),
+            "disconnect" => Ok(BudgetAction::Disconnect),
+            "direct" => Ok(BudgetAction::Direct),
+            _ => Err(crate::error::ConfigError::Validation(format!(
+                "Invalid budget action: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Traffic budget
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrafficBudget {
+    /// Unique identifier
+    pub id: String,
+    /// Subscription whose servers count, None for all traffic
+    #[serde(default)]
+    pub subscription_id: Option<String>,
+    /// Period the budget applies to
+    pub period: BudgetPeriod,
+    /// Bytes allowed per period, upload and download together
+    pub limit_bytes: u64,
+    /// What happens when the budget is used up
+    #[serde(default)]
+    pub action: BudgetAction,
+}
+
+impl TrafficBudget {
+    /// Validate the budget
+    pub fn validate(&self) -> Result<(), crate::error::ConfigError> {
+        if self.id.is_empty() {
+            return Err(crate::error::ConfigError::Validation(
+                "Budget ID is empty".to_string(),
+            ));
+        }
+        if self.limit_bytes == 0 {
+            return Err(crate::error::ConfigError::Validation(format!(
+                "Budget {} must allow some traffic",
+                self.id
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Usage crossing a threshold of a budget
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetAlert {
+    /// Budget ID
+    pub budget_id: String,
+    /// Subscription of the budget, None for all traffic
+    pub subscription_id: Option<String>,
+    /// Period of the budget
+    pub period: BudgetPeriod,
+    /// Threshold crossed, in percent of the limit
+    pub threshold_percent: u32,
+    /// Bytes used this period
+    pub used_bytes: u64,
+    /// Bytes allowed per period
+    pub limit_bytes: u64,
+    /// Action to run: the budget's action at 100%, otherwise notify
+    pub action: BudgetAction,
+    /// Whether the threshold was alerted before and only the action is
+    /// enforced again
+    pub repeated: bool,
+}
+
+/// Thresholds already alerted per budget, so each alert is raised once per
+/// period
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BudgetTracker {
+    /// Budget ID to its period start and highest alerted threshold
+    alerted: HashMap<String, (NaiveDate, u32)>,
+}
+
+impl BudgetTracker {
+    /// Create an empty tracker
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check `used_bytes` of `budget` at `now`, returning an alert for the
+    /// highest threshold newly crossed this period, if any
+    ///
+    /// Once the budget is used up, every check returns a repeated alert
+    /// for its action until the period ends, unless the action is
+    /// [`BudgetAction::Notify`].
+    pub fn check(
+        &mut self,
+        budget: &TrafficBudget,
+        used_bytes: u64,
+        now: DateTime<Local>,
+    ) -> Option<BudgetAlert> {
+        let start = budget.period.start(now);
+        let alerted = match self.alerted.get(&budget.id) {
+            Some(&(period, threshold)) if period == start => threshold,
+            _ => 0,
+        };
+
+        let percent = used_bytes.saturating_mul(100) / budget.limit_bytes;
+        if alerted >= 100 && percent >= 100 {
+            return (budget.action != BudgetAction::Notify).then(|| BudgetAlert {
+                budget_id: budget.id.clone(),
+                subscription_id: budget.subscription_id.clone(),
+                period: budget.period,
+                threshold_percent: alerted,
+                used_bytes,
+                limit_bytes: budget.limit_bytes,
+                action: budget.action,
+                repeated: true,
+            });
+        }
+        let threshold = ALERT_THRESHOLDS
+            .iter()
+            .copied()
+            .filter(|&threshold| u64::from(threshold) <= percent)
+            .max()
+            .filter(|&threshold| threshold > alerted)?;
+        self.alerted.insert(budget.id.clone(), (start, threshold));
+
+        Some(BudgetAlert {
+            budget_id: budget.id.clone(),
+            subscription_id: budget.subscription_id.clone(),
+            period: budget.period,
+            threshold_percent: threshold,
+            used_bytes,
+            limit_bytes: budget.limit_bytes,
+            action: if threshold >= 100 {
+                budget.action
+            } else {
+                BudgetAction::Notify
+            },
+            repeated: false,
+        })
+    }
+
+    /// Forget the alerts of budgets other than `budget_ids`, e.g. after the
+    /// budgets changed
+    pub fn retain(&mut self, budget_ids: &[&str]) {
+        self.alerted
+            .retain(|id, _| budget_ids.contains(&id.as_str()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn budget(period: BudgetPeriod) -> TrafficBudget {
+        TrafficBudget {
+            id: "monthly".to_string(),
+            subscription_id: None,
+            period,
+            limit_bytes: 1000,
+            action: BudgetAction::Disconnect,
+        }
+    }
+
+    #[test]
+    fn test_period_start() {
+        let now = Local.with_ymd_and_hms(2024, 5, 17, 13, 0, 0).unwrap();
+        assert_eq!(
+            BudgetPeriod::Monthly.start(now),
+            NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()
+        );
+        assert_eq!(
+            BudgetPeriod::Daily.start(now),
+            NaiveDate::from_ymd_opt(2024, 5, 17).unwrap()
+        );
+        assert!("weekly".parse::<BudgetPeriod>().is_err());
+        assert_eq!(
+            "direct".parse::<BudgetAction>().unwrap(),
+            BudgetAction::Direct
+        );
+    }
+
+    #[test]
+    fn test_alerts_once_per_threshold_and_period() {
+        let budget = budget(BudgetPeriod::Monthly);
+        let mut tracker = BudgetTracker::new();
+        let may = Local.with_ymd_and_hms(2024, 5, 17, 13, 0, 0).unwrap();
+
+        assert_eq!(tracker.check(&budget, 500, may), None);
+        let alert = tracker.check(&budget, 850, may).unwrap();
+        assert_eq!(alert.threshold_percent, 80);
+        assert_eq!(alert.action, BudgetAction::Notify);
+        assert_eq!(tracker.check(&budget, 900, may), None);
+
+        let alert = tracker.check(&budget, 1200, may).unwrap();
+        assert_eq!(alert.threshold_percent, 100);
+        assert_eq!(alert.action, BudgetAction::Disconnect);
+        assert!(!alert.repeated);
+
+        // The action is enforced again while the budget stays used up
+        let alert = tracker.check(&budget, 1300, may).unwrap();
+        assert!(alert.repeated);
+        assert_eq!(alert.action, BudgetAction::Disconnect);
+        let notify = TrafficBudget {
+            action: BudgetAction::Notify,
+            ..budget.clone()
+        };
+        assert_eq!(tracker.check(&notify, 1300, may), None);
+
+        // Alerted thresholds survive a restart
+        let saved = serde_json::to_string(&tracker).unwrap();
+        let mut restored: BudgetTracker = serde_json::from_str(&saved).unwrap();
+        assert!(restored.check(&budget, 1300, may).unwrap().repeated);
+
+        // A new month starts over, jumping straight to the highest threshold
+        let june = Local.with_ymd_and_hms(2024, 6, 1, 0, 30, 0).unwrap();
+        assert_eq!(
+            tracker
+                .check(&budget, 1000, june)
+                .unwrap()
+                .threshold_percent,
+            100
+        );
+
+        tracker.retain(&[]);
+        assert!(tracker.check(&budget, 1000, june).is_some());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(budget(BudgetPeriod::Daily).validate().is_ok());
+        let empty = TrafficBudget {
+            limit_bytes: 0,
+            ..budget(BudgetPeriod::Daily)
+        };
+        assert!(empty.validate().is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Persistent traffic history
+//!
+//! [`TrafficStatsCollector`](super::stats::TrafficStatsCollector) only keeps
+//! recent snapshots in memory. Traffic reported to the connection manager is
+//! also added to hourly and daily totals per server in SQLite, so usage
+//! charts survive restarts. Buckets use local time, so a "day" matches the
+//! user's calendar.
+
+use crate::error::StorageResult;
+use crate::utils::preflight::check_writable;
+use chrono::{DateTime, Datelike, Local, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool},
+    Row,
+};
+use std::path::Path;
+use std::str::FromStr;
+use tracing::{debug, info};
+
+/// Hour bucket format, e.g. `2024-05-01 13`
+const HOUR_FORMAT: &str = "%Y-%m-%d %H";
+
+/// Day bucket format, e.g. `2024-05-01`
+const DAY_FORMAT: &str = "%Y-%m-%d";
+
+/// How long aggregated traffic is kept
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// Days of hourly totals to keep
+    pub hourly_days: u32,
+    /// Days of daily totals to keep
+    pub daily_days: u32,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            hourly_days: 30,
+            daily_days: 730,
+        }
+    }
+}
+
+/// Traffic of one period (hour, day or month)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Period key: `YYYY-MM-DD HH`, `YYYY-MM-DD` or `YYYY-MM`
+    pub period: String,
+    /// Bytes sent
+    pub upload_bytes: u64,
+    /// Bytes received
+    pub download_bytes: u64,
+}
+
+/// Traffic of one server over a range of days
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerUsage {
+    /// Server ID
+    pub server_id: String,
+    /// Bytes sent
+    pub upload_bytes: u64,
+    /// Bytes received
+    pub download_bytes: u64,
+}
+
+/// SQLite-backed traffic history
+pub struct TrafficHistory {
+    /// SQLite connection pool
+    pool: SqlitePool,
+}
+
+impl TrafficHistory {
+    /// Open the history database at `db_path`, creating it if missing
+    pub async fn new<P: AsRef<Path>>(db_path: P) -> StorageResult<Self> {
+        let path = db_path.as_ref();
+        info!("Opening traffic history database: {}", path.display());
+
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            check_writable(parent)?;
+        }
+
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await?;
+
+        let history = Self { pool };
+        history.init_tables().await?;
+        Ok(history)
+    }
+
+    /// Size of the history database in bytes
+    pub async fn database_size(&self) -> StorageResult<u64> {
+        crate::subscription::database_size(&self.pool).await
+    }
+
+    /// Create an in-memory history (for testing)
+    pub async fn new_in_memory() -> StorageResult<Self> {
+        let pool = SqlitePool::connect("sqlite::memory:").await?;
+
+        let history = Self { pool };
+        history.init_tables().await?;
+        Ok(history)
+    }
+
+    /// Initialize database tables
+    async fn init_tables(&self) -> StorageResult<()> {
+        debug!("Initializing traffic history tables");
+
+        for table in ["traffic_hourly", "traffic_daily"] {
+            sqlx::query(&format!(
+                r#"
+                CREATE TABLE IF NOT EXISTS {} (
+                    period TEXT NOT NULL,
+                    server_id TEXT NOT NULL,
+                    upload_bytes INTEGER NOT NULL DEFAULT 0,
+                    download_bytes INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (period, server_id)
+                )
+                "#,
+                table
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Add traffic of `server_id` at time `at` to its hour and day
+    pub async fn record(
+        &self,
+        server_id: &str,
+        at: DateTime<Utc>,
+        upload: u64,
+        download: u64,
+    ) -> StorageResult<()> {
+        if upload == 0 && download == 0 {
+            return Ok(());
+        }
+        let local = at.with_timezone(&Local);
+
+        let mut tx = self.pool.begin().await?;
+        for (table, period) in [
+            ("traffic_hourly", local.format(HOUR_FORMAT).to_string()),
+            ("traffic_daily", local.format(DAY_FORMAT).to_string()),
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO {} (period, server_id, upload_bytes, download_bytes) \
+                 VALUES (?, ?, ?, ?) \
+                 ON CONFLICT(period, server_id) DO UPDATE SET \
+                 upload_bytes = upload_bytes + excluded.upload_bytes, \
+                 download_bytes = download_bytes + excluded.download_bytes",
+                table
+            ))
+            .bind(period)
+            .bind(server_id)
+            .bind(upload as i64)
+            .bind(download as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Hourly traffic of the last `hours` hours, oldest first
+    ///
+    /// Hours without traffic are omitted.
+    pub async fn get_hourly_usage(
+        &self,
+        hours: u32,
+        server_id: Option<&str>,
+    ) -> StorageResult<Vec<UsageRecord>> {
+        let since = Local::now() - chrono::Duration::hours(i64::from(hours.max(1)) - 1);
+        self.query_usage(
+            "traffic_hourly",
+            "period",
+            &since.format(HOUR_FORMAT).to_string(),
+            server_id,
+        )
+        .await
+    }
+
+    /// Daily traffic of the last `days` days (today included), oldest first
+    ///
+    /// Days without traffic are omitted.
+    pub async fn get_daily_usage(
+        &self,
+        days: u32,
+        server_id: Option<&str>,
+    ) -> StorageResult<Vec<UsageRecord>> {
+        self.query_usage("traffic_daily", "period", &first_day(days), server_id)
+            .await
+    }
+
+    /// Monthly traffic of the last `months` months (this month included),
+    /// oldest first
+    ///
+    /// Months without traffic are omitted.
+    pub async fn get_monthly_usage(
+        &self,
+        months: u32,
+        server_id: Option<&str>,
+    ) -> StorageResult<Vec<UsageRecord>> {
+        let today = Local::now().date_naive();
+        let since = today
+            .with_day(1)
+            .and_then(|first| first.checked_sub_months(Months::new(months.max(1) - 1)))
+            .unwrap_or(today);
+        self.query_usage(
+            "traffic_daily",
+            "substr(period, 1, 7)",
+            &since.format(DAY_FORMAT).to_string(),
+            server_id,
+        )
+        .await
+    }
+
+    /// Traffic per server over the last `days` days, largest first
+    pub async fn get_usage_by_server(&self, days: u32) -> StorageResult<Vec<ServerUsage>> {
+        let rows = sqlx::query(
+            "SELECT server_id, SUM(upload_bytes) AS upload, SUM(download_bytes) AS download \
+             FROM traffic_daily WHERE period >= ? \
+             GROUP BY server_id ORDER BY upload + download DESC",
+        )
+        .bind(first_day(days))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ServerUsage {
+                server_id: row.get("server_id"),
+                upload_bytes: row.get::<i64, _>("upload") as u64,
+                download_bytes: row.get::<i64, _>("download") as u64,
+            })
+            .collect())
+    }
+
+    /// Bytes sent and received since the day `since`, by `server_ids` or by
+    /// all servers for None
+    pub async fn get_total_usage_since(
+        &self,
+        since: NaiveDate,
+        server_ids: Option<&[String]>,
+    ) -> StorageResult<u64> {
+        let filter = match server_ids {
+            Some([]) => return Ok(0),
+            Some(ids) => format!(" AND server_id IN ({})", vec!["?"; ids.len()].join(", ")),
+            None => String::new(),
+        };
+        let sql = format!(
+            "SELECT COALESCE(SUM(upload_bytes + download_bytes), 0) AS total \
+             FROM traffic_daily WHERE period >= ?{}",
+            filter
+        );
+        let mut query = sqlx::query(&sql).bind(since.format(DAY_FORMAT).to_string());
+        for id in server_ids.unwrap_or_default() {
+            query = query.bind(id);
+        }
+        let row = query.fetch_one(&self.pool).await?;
+        Ok(row.get::<i64, _>("total") as u64)
+    }
+
+    /// Delete totals older than the retention policy allows
+    ///
+    /// Returns the number of deleted rows.
+    pub async fn prune(&self, policy: &RetentionPolicy) -> StorageResult<u64> {
+        let now = Local::now();
+        let hourly_cutoff = now - chrono::Duration::days(i64::from(policy.hourly_days));
+        let daily_cutoff = now - chrono::Duration::days(i64::from(policy.daily_days));
+
+        let hourly = sqlx::query("DELETE FROM traffic_hourly WHERE period < ?")
+            .bind(hourly_cutoff.format(HOUR_FORMAT).to_string())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        let daily = sqlx::query("DELETE FROM traffic_daily WHERE period < ?")
+            .bind(daily_cutoff.format(DAY_FORMAT).to_string())
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+
+        if hourly + daily > 0 {
+            info!("Pruned {} hourly and {} daily traffic rows", hourly, daily);
+        }
+        Ok(hourly + daily)
+    }
+
+    /// Sum traffic per `group` expression over rows from `since` on
+    async fn query_usage(
+        &self,
+        table: &str,
+        group: &str,
+        since: &str,
+        server_id: Option<&str>,
+    ) -> StorageResult<Vec<UsageRecord>> {
+        let rows = sqlx::query(&format!(
+            "SELECT {group} AS bucket, SUM(upload_bytes) AS upload, \
+             SUM(download_bytes) AS download FROM {table} \
+             WHERE period >= ? AND (? IS NULL OR server_id = ?) \
+             GROUP BY bucket ORDER BY bucket",
+        ))
+        .bind(since)
+        .bind(server_id)
+        .bind(server_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| UsageRecord {
+                period: row.get("bucket"),
+                upload_bytes: row.get::<i64, _>("upload") as u64,
+                download_bytes: row.get::<i64, _>("download") as u64,
+            })
+            .collect())
+    }
+}
+
+/// Day key of the first of the last `days` days
+fn first_day(days: u32) -> String {
+    let since = Local::now() - chrono::Duration::days(i64::from(days.max(1)) - 1);
+    since.format(DAY_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(at: DateTime<Utc>) -> String {
+        at.with_timezone(&Local).format(DAY_FORMAT).to_string()
+    }
+
+    #[tokio::test]
+    async fn test_record_and_query() {
+        let history = TrafficHistory::new_in_memory().await.unwrap();
+        let now = Utc::now();
+        let three_days_ago = now - chrono::Duration::days(3);
+
+        history.record("a", now, 100, 1000).await.unwrap();
+        history.record("a", now, 50, 500).await.unwrap();
+        history.record("b", now, 10, 20).await.unwrap();
+        history.record("a", three_days_ago, 1, 2).await.unwrap();
+        history.record("a", now, 0, 0).await.unwrap();
+        assert!(history.database_size().await.unwrap() > 0);
+
+        let daily = history.get_daily_usage(7, None).await.unwrap();
+        assert_eq!(
+            daily,
+            vec![
+                UsageRecord {
+                    period: day(three_days_ago),
+                    upload_bytes: 1,
+                    download_bytes: 2,
+                },
+                UsageRecord {
+                    period: day(now),
+                    upload_bytes: 160,
+                    download_bytes: 1520,
+                },
+            ]
+        );
+
+        // Older days fall outside the range
+        let daily = history.get_daily_usage(1, Some("b")).await.unwrap();
+        assert_eq!(daily.len(), 1);
+        assert_eq!(daily[0].upload_bytes, 10);
+
+        let hourly = history.get_hourly_usage(1, Some("a")).await.unwrap();
+        assert_eq!(hourly.len(), 1);
+        assert_eq!(hourly[0].download_bytes, 1500);
+
+        let monthly = history.get_monthly_usage(12, None).await.unwrap();
+        assert_eq!(monthly.iter().map(|m| m.upload_bytes).sum::<u64>(), 161);
+        assert!(monthly.iter().all(|m| m.period.len() == 7));
+
+        let by_server = history.get_usage_by_server(30).await.unwrap();
+        assert_eq!(by_server[0].server_id, "a");
+        assert_eq!(by_server[0].upload_bytes, 151);
+        assert_eq!(by_server[1].server_id, "b");
+
+        let today = Local::now().date_naive();
+        assert_eq!(
+            history.get_total_usage_since(today, None).await.unwrap(),
+            1680
+        );
+        let ids = ["b".to_string()];
+        assert_eq!(
+            history
+                .get_total_usage_since(today, Some(&ids))
+                .await
+                .unwrap(),
+            30
+        );
+        assert_eq!(
+            history
+                .get_total_usage_since(today, Some(&[]))
+                .await
+                .unwrap(),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let history = TrafficHistory::new_in_memory().await.unwrap();
+        let now = Utc::now();
+
+        history.record("a", now, 1, 1).await.unwrap();
+        history
+            .record("a", now - chrono::Duration::days(60), 1, 1)
+            .await
+            .unwrap();
+        history
+            .record("a", now - chrono::Duration::days(800), 1, 1)
+            .await
+            .unwrap();
+
+        // Hourly rows of 60 and 800 days ago, daily row of 800 days ago
+        let deleted = history.prune(&RetentionPolicy::default()).await.unwrap();
+        assert_eq!(deleted, 3);
+
+        let daily = history.get_daily_usage(1000, None).await.unwrap();
+        assert_eq!(daily.len(), 2);
+        assert_eq!(
+            history
+                .get_hourly_usage(24 * 1000, None)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -0,0 +1,565 @@
+//! Streaming service unlock detection
+//!
+//! Many services restrict content by the region of the client IP, and often
+//! block known datacenter ranges entirely. This module requests well-known
+//! probe pages through the local proxy inbound and classifies the responses,
+//! so users can see which services a server unblocks and in which region.
+//!
+//! The probes mirror what the services serve to browsers and may change on
+//! the service side; classification is therefore kept in pure functions.
+
+use super::readiness::proxy_for_inbound;
+use crate::error::{ConfigError, ConnectionError};
+use crate::xray::InboundConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Default timeout of a single probe
+pub const DEFAULT_UNLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long cached results are reused
+pub const UNLOCK_CACHE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Largest response body inspected
+const MAX_BODY_SIZE: usize = 512 * 1024;
+
+/// Services answer probes from non-browser clients differently
+const BROWSER_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 \
+     (KHTML, like Gecko) Chrome/124.0.0.0 Safari/537.36";
+
+/// Netflix title only licensed in some regions (not a Netflix original)
+const NETFLIX_LICENSED_TITLE: &str = "https://www.netflix.com/title/70143836";
+
+/// Service whose availability can be checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum UnlockService {
+    /// Netflix, including licensed (non-original) titles
+    Netflix,
+    /// YouTube Premium
+    YouTubePremium,
+    /// ChatGPT / OpenAI
+    ChatGpt,
+    /// Disney+
+    DisneyPlus,
+}
+
+impl UnlockService {
+    /// All services, in display order
+    pub const ALL: [Self; 4] = [
+        Self::Netflix,
+        Self::YouTubePremium,
+        Self::ChatGpt,
+        Self::DisneyPlus,
+    ];
+
+    /// Identifier used in configuration and the bridge
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Netflix => "netflix",
+            Self::YouTubePremium => "youtube_premium",
+            Self::ChatGpt => "chatgpt",
+            Self::DisneyPlus => "disney_plus",
+        }
+    }
+
+    /// URL requested to check the service
+    fn probe_url(&self) -> &'static str {
+        match self {
+            Self::Netflix => NETFLIX_LICENSED_TITLE,
+            Self::YouTubePremium => "https://www.youtube.com/premium",
+            Self::ChatGpt => "https://api.openai.com/compliance/cookie_requirements",
+            Self::DisneyPlus => "https://www.disneyplus.com/",
+        }
+    }
+}
+
+impl FromStr for UnlockService {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "netflix" => Ok(Self::Netflix),
+            "youtube_premium" | "youtube" => Ok(Self::YouTubePremium),
+            "chatgpt" | "openai" => Ok(Self::ChatGpt),
+            "disney_plus" | "disney" => Ok(Self::DisneyPlus),
+            other => Err(ConfigError::Validation(format!(
+                "Unknown unlock service: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Availability of a service through a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UnlockStatus {
+    /// The service works
+    Unlocked,
+    /// Only part of the catalogue is available (Netflix originals only)
+    Partial,
+    /// The service refuses the region or IP
+    Blocked,
+    /// The probe failed or the response was not recognized
+    Unknown,
+}
+
+impl UnlockStatus {
+    /// Lowercase name used in the bridge
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unlocked => "unlocked",
+            Self::Partial => "partial",
+            Self::Blocked => "blocked",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+/// Result of checking one service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UnlockResult {
+    /// Checked service
+    pub service: UnlockService,
+    /// Availability
+    pub status: UnlockStatus,
+    /// Region the service sees, as an uppercase country code, if known
+    pub region: Option<String>,
+    /// Explanation for partial, blocked and unknown results
+    pub detail: Option<String>,
+    /// When the check ran
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Response to a probe request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Final URL after redirects
+    pub url: String,
+    /// Start of the body
+    pub body: String,
+}
+
+/// Classify a probe response as status, region and detail
+pub fn classify(
+    service: UnlockService,
+    response: &ProbeResponse,
+) -> (UnlockStatus, Option<String>, Option<String>) {
+    if response.status == 429 || response.status >= 500 {
+        return (
+            UnlockStatus::Unknown,
+            None,
+            Some(format!("HTTP {}", response.status)),
+        );
+    }
+    match service {
+        UnlockService::Netflix => classify_netflix(response),
+        UnlockService::YouTubePremium => classify_youtube(response),
+        UnlockService::ChatGpt => classify_chatgpt(response),
+        UnlockService::DisneyPlus => classify_disney(response),
+    }
+}
+
+/// Licensed titles 404 where only originals are available
+fn classify_netflix(response: &ProbeResponse) -> (UnlockStatus, Option<String>, Option<String>) {
+    match response.status {
+        200 => {
+            // Regional sites redirect to /xx/title/... or /xx-en/title/...;
+            // the US site does not, so without either the region is unknown
+            let region = path_segments(&response.url)
+                .next()
+                .and_then(|segment| segment.split('-').next())
+                .and_then(country_code)
+                .or_else(|| json_string_after(&response.body, "\"countryCode\""));
+            (UnlockStatus::Unlocked, region, None)
+        }
+        404 => (
+            UnlockStatus::Partial,
+            None,
+            Some("Only Netflix originals are available".to_string()),
+        ),
+        403 => (
+            UnlockStatus::Blocked,
+            None,
+            Some("Netflix blocks this IP".to_string()),
+        ),
+        status => (
+            UnlockStatus::Unknown,
+            None,
+            Some(format!("HTTP {}", status)),
+        ),
+    }
+}
+
+fn classify_youtube(response: &ProbeResponse) -> (UnlockStatus, Option<String>, Option<String>) {
+    let body = &response.body;
+    if body.contains("www.google.cn") {
+        return (
+            UnlockStatus::Blocked,
+            Some("CN".to_string()),
+            Some("YouTube is not available".to_string()),
+        );
+    }
+    let region =
+        json_string_after(body, "\"countryCode\"").or_else(|| json_string_after(body, "\"GL\""));
+    if body.contains("Premium is not available in your country") {
+        return (
+            UnlockStatus::Blocked,
+            region,
+            Some("YouTube Premium is not available in this region".to_string()),
+        );
+    }
+    if response.status == 200 && (body.contains("ad-free") || region.is_some()) {
+        return (UnlockStatus::Unlocked, region, None);
+    }
+    (
+        UnlockStatus::Unknown,
+        region,
+        Some(format!("HTTP {}", response.status)),
+    )
+}
+
+fn classify_chatgpt(response: &ProbeResponse) -> (UnlockStatus, Option<String>, Option<String>) {
+    if response.body.contains("unsupported_country") {
+        return (
+            UnlockStatus::Blocked,
+            None,
+            Some("OpenAI does not serve this region".to_string()),
+        );
+    }
+    match response.status {
+        200 => (UnlockStatus::Unlocked, None, None),
+        // Cloudflare challenges datacenter IPs it distrusts
+        403 => (
+            UnlockStatus::Blocked,
+            None,
+            Some("OpenAI blocks this IP".to_string()),
+        ),
+        status => (
+            UnlockStatus::Unknown,
+            None,
+            Some(format!("HTTP {}", status)),
+        ),
+    }
+}
+
+fn classify_disney(response: &ProbeResponse) -> (UnlockStatus, Option<String>, Option<String>) {
+    let mut segments = path_segments(&response.url);
+    let first = segments.next();
+    if first == Some("unavailable") || response.url.contains("preview") {
+        return (
+            UnlockStatus::Blocked,
+            None,
+            Some("Disney+ is not available in this region".to_string()),
+        );
+    }
+    match response.status {
+        200 => {
+            // Regional sites redirect to /xx-yy (language-country)
+            let region = first
+                .and_then(|segment| segment.split('-').nth(1))
+                .and_then(country_code)
+                .or_else(|| json_string_after(&response.body, "\"region\""));
+            (UnlockStatus::Unlocked, region, None)
+        }
+        403 => (
+            UnlockStatus::Blocked,
+            None,
+            Some("Disney+ blocks this IP".to_string()),
+        ),
+        status => (
+            UnlockStatus::Unknown,
+            None,
+            Some(format!("HTTP {}", status)),
+        ),
+    }
+}
+
+/// Non-empty path segments of a URL
+fn path_segments(url: &str) -> impl Iterator<Item = &str> {
+    let path = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(url)
+        .split_once('/')
+        .map(|(_, path)| path)
+        .unwrap_or_default();
+    path.split(['/', '?', '#'])
+        .take_while(|segment| !segment.is_empty())
+}
+
+/// Two-letter country code, uppercased
+fn country_code(value: &str) -> Option<String> {
+    (value.len() == 2 && value.chars().all(|c| c.is_ascii_alphabetic()))
+        .then(|| value.to_ascii_uppercase())
+}
+
+/// Two-letter code in `"key":"XX"` inside a page
+fn json_string_after(body: &str, key: &str) -> Option<String> {
+    let rest = &body[body.find(key)? + key.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start();
+    let value = rest.strip_prefix('"')?.split('"').next()?;
+    country_code(value)
+}
+
+/// Checks services through an HTTP client
+pub struct UnlockChecker {
+    client: reqwest::Client,
+}
+
+impl UnlockChecker {
+    /// Create a checker sending requests through a local HTTP inbound
+    pub fn through_inbound(
+        inbound: &InboundConfig,
+        timeout: Duration,
+    ) -> Result<Self, ConnectionError> {
+        let failed = |e: reqwest::Error| {
+            ConnectionError::Failed(format!("Unlock check setup failed: {}", e))
+        };
+        let client = crate::version::http_client_builder()
+            .proxy(proxy_for_inbound(inbound).map_err(failed)?)
+            .timeout(timeout)
+            .build()
+            .map_err(failed)?;
+        Ok(Self::with_client(client))
+    }
+
+    /// Create a checker using `client` as is
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+
+    /// Check several services concurrently
+    pub async fn check_all(&self, services: &[UnlockService]) -> Vec<UnlockResult> {
+        futures::future::join_all(services.iter().map(|service| self.check(*service))).await
+    }
+
+    /// Check one service
+    ///
+    /// Probe failures give an [`UnlockStatus::Unknown`] result rather than
+    /// an error, so one unreachable service does not hide the others.
+    pub async fn check(&self, service: UnlockService) -> UnlockResult {
+        let (status, region, detail) = match self.probe(service.probe_url()).await {
+            Ok(response) => classify(service, &response),
+            Err(e) => (UnlockStatus::Unknown, None, Some(e.to_string())),
+        };
+        UnlockResult {
+            service,
+            status,
+            region,
+            detail,
+            checked_at: Utc::now(),
+        }
+    }
+
+    /// Request `url` like a browser and read the start of the body
+    async fn probe(&self, url: &str) -> Result<ProbeResponse, ConnectionError> {
+        let failed = |e: reqwest::Error| {
+            if e.is_timeout() {
+                ConnectionError::Timeout
+            } else {
+                ConnectionError::Failed(format!("Probe request failed: {}", e))
+            }
+        };
+        let mut response = self
+            .client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, BROWSER_USER_AGENT)
+            .header(reqwest::header::ACCEPT_LANGUAGE, "en-US,en;q=0.9")
+            .send()
+            .await
+            .map_err(failed)?;
+
+        let status = response.status().as_u16();
+        let final_url = response.url().to_string();
+        let mut body = Vec::new();
+        while body.len() < MAX_BODY_SIZE {
+            match response.chunk().await.map_err(failed)? {
+                Some(chunk) => body.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        Ok(ProbeResponse {
+            status,
+            url: final_url,
+            body: String::from_utf8_lossy(&body).into_owned(),
+        })
+    }
+}
+
+/// Cached unlock results per server
+#[derive(Debug, Clone, Default)]
+pub struct UnlockCache {
+    results: HashMap<String, HashMap<UnlockService, UnlockResult>>,
+}
+
+impl UnlockCache {
+    /// Store results of a server, replacing older results of those services
+    pub fn record(&mut self, server_id: &str, results: &[UnlockResult]) {
+        let cached = self.results.entry(server_id.to_string()).or_default();
+        for result in results {
+            cached.insert(result.service, result.clone());
+        }
+    }
+
+    /// Result of a service younger than `ttl`
+    pub fn fresh(
+        &self,
+        server_id: &str,
+        service: UnlockService,
+        ttl: Duration,
+    ) -> Option<UnlockResult> {
+        let result = self.results.get(server_id)?.get(&service)?;
+        let age = (Utc::now() - result.checked_at)
+            .to_std()
+            .unwrap_or_default();
+        (age < ttl).then(|| result.clone())
+    }
+
+    /// All cached results of a server, in [`UnlockService::ALL`] order
+    pub fn results(&self, server_id: &str) -> Vec<UnlockResult> {
+        let Some(cached) = self.results.get(server_id) else {
+            return Vec::new();
+        };
+        UnlockService::ALL
+            .iter()
+            .filter_map(|service| cached.get(service).cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, url: &str, body: &str) -> ProbeResponse {
+        ProbeResponse {
+            status,
+            url: url.to_string(),
+            body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_classify_netflix() {
+        let full = response(200, "https://www.netflix.com/jp-en/title/70143836", "");
+        assert_eq!(
+            classify(UnlockService::Netflix, &full),
+            (UnlockStatus::Unlocked, Some("JP".to_string()), None)
+        );
+        let us = response(
+            200,
+            "https://www.netflix.com/title/70143836",
+            r#"{"countryCode":"US"}"#,
+        );
+        assert_eq!(
+            classify(UnlockService::Netflix, &us).1.as_deref(),
+            Some("US")
+        );
+        // No redirect and no country in the page: region unknown
+        let unknown = response(200, "https://www.netflix.com/title/70143836", "");
+        assert_eq!(
+            classify(UnlockService::Netflix, &unknown),
+            (UnlockStatus::Unlocked, None, None)
+        );
+        let originals = response(404, NETFLIX_LICENSED_TITLE, "");
+        assert_eq!(
+            classify(UnlockService::Netflix, &originals).0,
+            UnlockStatus::Partial
+        );
+        let blocked = response(403, NETFLIX_LICENSED_TITLE, "");
+        assert_eq!(
+            classify(UnlockService::Netflix, &blocked).0,
+            UnlockStatus::Blocked
+        );
+        let limited = response(429, NETFLIX_LICENSED_TITLE, "");
+        assert_eq!(
+            classify(UnlockService::Netflix, &limited).0,
+            UnlockStatus::Unknown
+        );
+    }
+
+    #[test]
+    fn test_classify_other_services() {
+        let url = "https://www.youtube.com/premium";
+        let premium = response(200, url, r#"{"countryCode": "gb"} ad-free"#);
+        assert_eq!(
+            classify(UnlockService::YouTubePremium, &premium),
+            (UnlockStatus::Unlocked, Some("GB".to_string()), None)
+        );
+        let unavailable = response(200, url, "Premium is not available in your country");
+        assert_eq!(
+            classify(UnlockService::YouTubePremium, &unavailable).0,
+            UnlockStatus::Blocked
+        );
+
+        let url = UnlockService::ChatGpt.probe_url();
+        let blocked = response(403, url, r#"{"error":{"code":"unsupported_country"}}"#);
+        assert_eq!(
+            classify(UnlockService::ChatGpt, &blocked).0,
+            UnlockStatus::Blocked
+        );
+        assert_eq!(
+            classify(UnlockService::ChatGpt, &response(200, url, "{}")).0,
+            UnlockStatus::Unlocked
+        );
+
+        let disney = response(200, "https://www.disneyplus.com/en-gb", "");
+        assert_eq!(
+            classify(UnlockService::DisneyPlus, &disney),
+            (UnlockStatus::Unlocked, Some("GB".to_string()), None)
+        );
+        let unavailable = response(200, "https://www.disneyplus.com/unavailable", "");
+        assert_eq!(
+            classify(UnlockService::DisneyPlus, &unavailable).0,
+            UnlockStatus::Blocked
+        );
+    }
+
+    #[test]
+    fn test_service_names() {
+        for service in UnlockService::ALL {
+            assert_eq!(service.as_str().parse::<UnlockService>().unwrap(), service);
+        }
+        assert_eq!(
+            "Disney".parse::<UnlockService>().unwrap(),
+            UnlockService::DisneyPlus
+        );
+        assert!("hulu".parse::<UnlockService>().is_err());
+    }
+
+    #[test]
+    fn test_unlock_cache() {
+        let result = |service, checked_at| UnlockResult {
+            service,
+            status: UnlockStatus::Unlocked,
+            region: None,
+            detail: None,
+            checked_at,
+        };
+        let ttl = Duration::from_secs(60);
+        let stale = Utc::now() - chrono::Duration::minutes(5);
+
+        let mut cache = UnlockCache::default();
+        cache.record(
+            "a",
+            &[
+                result(UnlockService::DisneyPlus, Utc::now()),
+                result(UnlockService::Netflix, stale),
+            ],
+        );
+        assert!(cache.fresh("a", UnlockService::DisneyPlus, ttl).is_some());
+        assert!(cache.fresh("a", UnlockService::Netflix, ttl).is_none());
+        assert!(cache.fresh("b", UnlockService::DisneyPlus, ttl).is_none());
+
+        let services: Vec<_> = cache.results("a").iter().map(|r| r.service).collect();
+        assert_eq!(
+            services,
+            vec![UnlockService::Netflix, UnlockService::DisneyPlus]
+        );
+    }
+}
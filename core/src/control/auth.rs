@@ -0,0 +1,240 @@
+//! Access token authentication
+//!
+//! Each install has a random access token stored in a file readable only by
+//! the current user. Control requests must present it as
+//! `Authorization: Bearer <token>`; rotating the token invalidates every
+//! client that still holds the old one.
+
+use crate::error::{ControlError, ControlResult, StorageError};
+use crate::utils::crypto::generate_key;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+/// Default token file name inside the app data directory
+pub const TOKEN_FILE_NAME: &str = "control_token";
+
+/// Header carrying the access token
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+
+/// Authorization scheme prefix
+const BEARER_PREFIX: &str = "Bearer ";
+
+/// Per-install access token, persisted on disk
+pub struct AccessTokenStore {
+    /// Token file path
+    path: PathBuf,
+    /// Current token
+    token: RwLock<String>,
+}
+
+impl AccessTokenStore {
+    /// Load the token from `path`, generating a new one if it does not exist
+    pub fn load_or_create<P: AsRef<Path>>(path: P) -> ControlResult<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        let token = match std::fs::read_to_string(&path) {
+            Ok(content) if !content.trim().is_empty() => {
+                restrict_permissions(&path)?;
+                content.trim().to_string()
+            }
+            Ok(_) => {
+                warn!("Access token file is empty, generating a new token");
+                let token = generate_token();
+                write_token(&path, &token)?;
+                token
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("Creating access token at {}", path.display());
+                let token = generate_token();
+                write_token(&path, &token)?;
+                token
+            }
+            Err(e) => return Err(StorageError::Io(e).into()),
+        };
+
+        Ok(Self {
+            path,
+            token: RwLock::new(token),
+        })
+    }
+
+    /// Get the token file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the current token
+    pub fn token(&self) -> String {
+        self.token.read().unwrap().clone()
+    }
+
+    /// Replace the token with a new random one and return it
+    pub fn rotate(&self) -> ControlResult<String> {
+        let token = generate_token();
+        write_token(&self.path, &token)?;
+        *self.token.write().unwrap() = token.clone();
+
+        info!("Access token rotated");
+        Ok(token)
+    }
+
+    /// Check a presented token against the current one
+    pub fn verify(&self, presented: &str) -> bool {
+        constant_time_eq(presented.as_bytes(), self.token.read().unwrap().as_bytes())
+    }
+
+    /// Check an `Authorization` header value
+    pub fn authorize(&self, header: Option<&str>) -> ControlResult<()> {
+        let presented = header
+            .and_then(|value| value.strip_prefix(BEARER_PREFIX))
+            .ok_or(ControlError::Unauthorized)?;
+
+        if self.verify(presented.trim()) {
+            Ok(())
+        } else {
+            Err(ControlError::Unauthorized)
+        }
+    }
+}
+
+/// Reject listen addresses that are reachable from other hosts
+pub fn ensure_loopback(addr: &SocketAddr) -> ControlResult<()> {
+    if addr.ip().is_loopback() {
+        Ok(())
+    } else {
+        Err(ControlError::NonLoopbackAddress(addr.to_string()))
+    }
+}
+
+/// Generate a random 256-bit token as hex
+fn generate_token() -> String {
+    hex::encode(generate_key())
+}
+
+/// Compare without short-circuiting on the first differing byte
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Write the token atomically, readable by the current user only
+fn write_token(path: &Path, token: &str) -> ControlResult<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        crate::utils::preflight::check_writable(parent)?;
+    }
+
+    // Created private, so the token is never readable by others; a stale
+    // temporary file may have other permissions
+    let temp_path = path.with_extension("tmp");
+    match std::fs::remove_file(&temp_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(StorageError::Io(e).into())
+        }
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp_path).map_err(StorageError::Io)?;
+    std::io::Write::write_all(&mut file, token.as_bytes()).map_err(StorageError::Io)?;
+    file.sync_all().map_err(StorageError::Io)?;
+    drop(file);
+
+    std::fs::rename(&temp_path, path).map_err(StorageError::Io)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path) -> ControlResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let metadata = std::fs::metadata(path).map_err(StorageError::Io)?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .map_err(StorageError::Io)?;
+    }
+    Ok(())
+}
+
+/// On Windows files in the user profile are already private to the user
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path) -> ControlResult<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_token_persistence_and_rotation() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(TOKEN_FILE_NAME);
+
+        let store = AccessTokenStore::load_or_create(&path).unwrap();
+        let token = store.token();
+        assert_eq!(token.len(), 64);
+
+        let reloaded = AccessTokenStore::load_or_create(&path).unwrap();
+        assert_eq!(reloaded.token(), token);
+
+        let rotated = store.rotate().unwrap();
+        assert_ne!(rotated, token);
+        assert!(!store.verify(&token));
+        assert!(store.verify(&rotated));
+        assert_eq!(
+            AccessTokenStore::load_or_create(&path).unwrap().token(),
+            rotated
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+
+            // A stale temporary file does not leak its permissions
+            let temp_path = path.with_extension("tmp");
+            std::fs::write(&temp_path, "stale").unwrap();
+            std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            store.rotate().unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_authorize_header() {
+        let temp_dir = TempDir::new().unwrap();
+        let store =
+            AccessTokenStore::load_or_create(temp_dir.path().join(TOKEN_FILE_NAME)).unwrap();
+        let header = format!("Bearer {}", store.token());
+
+        assert!(store.authorize(Some(&header)).is_ok());
+        assert!(matches!(
+            store.authorize(Some(&store.token())),
+            Err(ControlError::Unauthorized)
+        ));
+        assert!(store.authorize(Some("Bearer wrong")).is_err());
+        assert!(store.authorize(None).is_err());
+    }
+
+    #[test]
+    fn test_ensure_loopback() {
+        assert!(ensure_loopback(&"127.0.0.1:7890".parse().unwrap()).is_ok());
+        assert!(ensure_loopback(&"[::1]:7890".parse().unwrap()).is_ok());
+        assert!(matches!(
+            ensure_loopback(&"0.0.0.0:7890".parse().unwrap()),
+            Err(ControlError::NonLoopbackAddress(_))
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+//! Single-instance enforcement
+//!
+//! The running instance holds an exclusive lock on a file in the app data
+//! directory and listens on a loopback port recorded next to it. A second
+//! copy that cannot take the lock connects to that port and sends a short
+//! message (e.g. `show`) asking the running instance to take over, then
+//! exits. Messages carry the per-install access token like every other
+//! control endpoint.
+//!
+//! The OS releases the lock when the process exits, so a crash never leaves
+//! a stale lock behind.
+
+use super::auth::AccessTokenStore;
+use crate::error::{ControlError, ControlResult, StorageError};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Lock file name inside the app data directory
+pub const LOCK_FILE_NAME: &str = "instance.lock";
+
+/// File holding the port the running instance listens on
+pub const PORT_FILE_NAME: &str = "instance.port";
+
+/// Message asking the running instance to show its window
+pub const SHOW_MESSAGE: &str = "show";
+
+/// Time allowed to reach the running instance
+///
+/// Covers the short window between the lock being taken and the port file
+/// being written when both copies start at the same time.
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Time allowed for a client to send its message
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Longest accepted message line, token included
+const MAX_LINE_LENGTH: u64 = 1024;
+
+/// Reply to an accepted message
+const ACK: &str = "ok";
+
+/// Exclusive lock held by the running instance
+pub struct InstanceLock {
+    /// Locked file, unlocked when closed
+    _file: File,
+    /// Port file, written once listening
+    port_path: PathBuf,
+    /// Message listener
+    listener: Option<JoinHandle<()>>,
+}
+
+impl InstanceLock {
+    /// Take the lock in `data_dir`, `None` if another instance holds it
+    pub fn try_acquire<P: AsRef<Path>>(data_dir: P) -> ControlResult<Option<Self>> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir).map_err(StorageError::Io)?;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(data_dir.join(LOCK_FILE_NAME))
+            .map_err(StorageError::Io)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(None),
+            Err(TryLockError::Error(e)) => return Err(StorageError::Io(e).into()),
+        }
+
+        info!("Acquired instance lock in {}", data_dir.display());
+        Ok(Some(Self {
+            _file: file,
+            port_path: data_dir.join(PORT_FILE_NAME),
+            listener: None,
+        }))
+    }
+
+    /// Accept messages from later instances, passing each to `on_message`
+    ///
+    /// Returns the loopback port listened on. Calling it again replaces the
+    /// previous listener.
+    pub async fn listen<F>(
+        &mut self,
+        token: Arc<AccessTokenStore>,
+        on_message: F,
+    ) -> ControlResult<u16>
+    where
+        F: Fn(String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))
+            .await
+            .map_err(StorageError::Io)?;
+        let port = listener.local_addr().map_err(StorageError::Io)?.port();
+        write_port(&self.port_path, port)?;
+
+        let on_message = Arc::new(on_message);
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let token = Arc::clone(&token);
+                let on_message = Arc::clone(&on_message);
+                tokio::spawn(async move {
+                    match tokio::time::timeout(READ_TIMEOUT, handle_client(stream, &token)).await {
+                        Ok(Ok(message)) => on_message(message),
+                        Ok(Err(e)) => warn!("Rejected instance message: {}", e),
+                        Err(_) => warn!("Instance message timed out"),
+                    }
+                });
+            }
+        });
+        if let Some(previous) = self.listener.replace(task) {
+            previous.abort();
+        }
+
+        info!("Listening for instance messages on port {}", port);
+        Ok(port)
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if let Some(listener) = self.listener.take() {
+            listener.abort();
+            let _ = std::fs::remove_file(&self.port_path);
+        }
+    }
+}
+
+/// Send `message` to the instance running in `data_dir`
+///
+/// Fails with [`ControlError::InstanceUnreachable`] if no instance answers,
+/// e.g. because it is still starting or has hung.
+pub async fn notify_running_instance<P: AsRef<Path>>(
+    data_dir: P,
+    token: &str,
+    message: &str,
+) -> ControlResult<()> {
+    let port_path = data_dir.as_ref().join(PORT_FILE_NAME);
+    let deadline = tokio::time::Instant::now() + NOTIFY_TIMEOUT;
+
+    let mut last_error;
+    loop {
+        match tokio::time::timeout_at(deadline, send_message(&port_path, token, message)).await {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(ControlError::Unauthorized)) => return Err(ControlError::Unauthorized),
+            Ok(Err(e)) => last_error = e.to_string(),
+            Err(_) => last_error = "timed out".to_string(),
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(ControlError::InstanceUnreachable(last_error));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// Deliver one message over a new connection
+async fn send_message(port_path: &Path, token: &str, message: &str) -> ControlResult<()> {
+    let port = std::fs::read_to_string(port_path)
+        .map_err(StorageError::Io)?
+        .trim()
+        .parse::<u16>()
+        .map_err(|e| StorageError::Parse(e.to_string()))?;
+
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+        .await
+        .map_err(StorageError::Io)?;
+    stream
+        .write_all(format!("{} {}\n", token, message).as_bytes())
+        .await
+        .map_err(StorageError::Io)?;
+
+    let mut reply = String::new();
+    BufReader::new(stream)
+        .take(MAX_LINE_LENGTH)
+        .read_line(&mut reply)
+        .await
+        .map_err(StorageError::Io)?;
+    if reply.trim() == ACK {
+        Ok(())
+    } else {
+        Err(ControlError::Unauthorized)
+    }
+}
+
+/// Read and authenticate one `<token> <message>` line
+async fn handle_client(stream: TcpStream, token: &AccessTokenStore) -> ControlResult<String> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader)
+        .take(MAX_LINE_LENGTH)
+        .read_line(&mut line)
+        .await
+        .map_err(StorageError::Io)?;
+
+    let (presented, message) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+    if !token.verify(presented) {
+        let _ = writer.write_all(b"unauthorized\n").await;
+        return Err(ControlError::Unauthorized);
+    }
+    writer
+        .write_all(format!("{}\n", ACK).as_bytes())
+        .await
+        .map_err(StorageError::Io)?;
+    Ok(message.to_string())
+}
+
+/// Write the port atomically, so readers never see a partial file
+fn write_port(path: &Path, port: u16) -> ControlResult<()> {
+    let temp_path = path.with_extension("tmp");
+    std::fs::write(&temp_path, port.to_string()).map_err(StorageError::Io)?;
+    std::fs::rename(&temp_path, path).map_err(StorageError::Io)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::TOKEN_FILE_NAME;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_instance_lock_and_takeover() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let token = Arc::new(AccessTokenStore::load_or_create(dir.join(TOKEN_FILE_NAME)).unwrap());
+
+        let mut lock = InstanceLock::try_acquire(dir).unwrap().unwrap();
+        assert!(InstanceLock::try_acquire(dir).unwrap().is_none());
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        lock.listen(Arc::clone(&token), move |message| {
+            let _ = tx.send(message);
+        })
+        .await
+        .unwrap();
+
+        notify_running_instance(dir, &token.token(), SHOW_MESSAGE)
+            .await
+            .unwrap();
+        assert_eq!(rx.recv().await.unwrap(), SHOW_MESSAGE);
+
+        assert!(matches!(
+            notify_running_instance(dir, "wrong", SHOW_MESSAGE).await,
+            Err(ControlError::Unauthorized)
+        ));
+        assert!(rx.try_recv().is_err());
+
+        // Released on exit; a later start takes over
+        drop(lock);
+        assert!(!dir.join(PORT_FILE_NAME).exists());
+        assert!(InstanceLock::try_acquire(dir).unwrap().is_some());
+    }
+}
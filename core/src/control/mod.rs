@@ -0,0 +1,13 @@
+//! Local control surface
+//!
+//! Shared pieces for local control endpoints (IPC or REST) that let other
+//! processes query and reconfigure the core. Every endpoint must listen on a
+//! loopback address and require the per-install access token.
+
+pub mod auth;
+pub mod instance;
+#[cfg(feature = "control-api")]
+pub mod server;
+
+pub use auth::{ensure_loopback, AccessTokenStore, AUTHORIZATION_HEADER, TOKEN_FILE_NAME};
+pub use instance::{notify_running_instance, InstanceLock, SHOW_MESSAGE};
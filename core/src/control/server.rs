@@ -0,0 +1,567 @@
+//! Local control API server
+//!
+//! Serves a small JSON API on a loopback address so third-party tools,
+//! browser extensions and scripts can control the core without FFI.
+//! Requests are passed to a [`ControlHandler`], which usually answers with
+//! JSON; `GET /v1/events` is upgraded to a WebSocket that streams events as
+//! JSON text messages.
+//!
+//! Every request must present the access token as a bearer
+//! `Authorization` header. Browsers cannot set headers on WebSocket
+//! requests, so the events endpoint also accepts it as a `token` query
+//! parameter.
+
+use super::auth::{ensure_loopback, AccessTokenStore, AUTHORIZATION_HEADER};
+use crate::error::{ControlError, ControlResult, StorageError};
+use futures::future::BoxFuture;
+use futures::stream::{BoxStream, StreamExt};
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Path of the WebSocket event stream
+pub const EVENTS_PATH: &str = "/v1/events";
+
+/// Largest accepted request head and body
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+
+/// Time a client has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Largest accepted WebSocket frame from a client
+const MAX_FRAME_SIZE: u64 = 4 * 1024;
+
+/// Appended to the client key to compute `Sec-WebSocket-Accept` (RFC 6455)
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// WebSocket opcodes
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// An authenticated API request
+#[derive(Debug, Clone)]
+pub struct ControlRequest {
+    /// HTTP method
+    pub method: String,
+    /// Path without the query string
+    pub path: String,
+    /// Request body
+    pub body: Vec<u8>,
+}
+
+/// Content type of JSON responses
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// Response to an API request
+#[derive(Debug, Clone)]
+pub struct ControlResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// `Content-Type` header
+    pub content_type: &'static str,
+    /// Response body
+    pub body: String,
+}
+
+impl ControlResponse {
+    /// `200 OK` with `body` as JSON
+    pub fn ok<T: Serialize>(body: T) -> Self {
+        match serde_json::to_string(&body) {
+            Ok(body) => Self {
+                status: 200,
+                content_type: JSON_CONTENT_TYPE,
+                body,
+            },
+            Err(e) => Self::error(500, e),
+        }
+    }
+
+    /// `200 OK` with a non-JSON body, e.g. metrics
+    pub fn text(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    /// Error `status` with `{"error": message}`
+    pub fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            content_type: JSON_CONTENT_TYPE,
+            body: serde_json::json!({ "error": message.to_string() }).to_string(),
+        }
+    }
+}
+
+/// Answers API requests
+pub type ControlHandler =
+    Arc<dyn Fn(ControlRequest) -> BoxFuture<'static, ControlResponse> + Send + Sync>;
+
+/// Creates a stream of JSON events for each WebSocket client
+pub type EventSource = Arc<dyn Fn() -> BoxStream<'static, String> + Send + Sync>;
+
+/// Local HTTP/WebSocket control server
+pub struct ControlServer {
+    /// Address the server listens on
+    addr: SocketAddr,
+    /// Accept loop
+    task: JoinHandle<()>,
+}
+
+impl ControlServer {
+    /// Listen on the loopback `addr` (port 0 picks a free port)
+    pub async fn start(
+        addr: SocketAddr,
+        token: Arc<AccessTokenStore>,
+        handler: ControlHandler,
+        events: EventSource,
+    ) -> ControlResult<Self> {
+        ensure_loopback(&addr)?;
+        let listener = TcpListener::bind(addr).await.map_err(StorageError::Io)?;
+        let addr = listener.local_addr().map_err(StorageError::Io)?;
+
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(
+                    stream,
+                    Arc::clone(&token),
+                    Arc::clone(&handler),
+                    Arc::clone(&events),
+                ));
+            }
+        });
+
+        tracing::info!("Control API listening on {}", addr);
+        Ok(Self { addr, task })
+    }
+
+    /// Address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop serving
+    pub fn stop(self) {
+        tracing::info!("Stopping control API on {}", self.addr);
+        self.task.abort();
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Parsed HTTP request
+struct HttpRequest {
+    /// HTTP method
+    method: String,
+    /// Path without the query string
+    path: String,
+    /// Query parameters
+    query: HashMap<String, String>,
+    /// Headers with lowercase names
+    headers: HashMap<String, String>,
+    /// Request body
+    body: Vec<u8>,
+}
+
+/// Answer one connection
+async fn serve(
+    mut stream: TcpStream,
+    token: Arc<AccessTokenStore>,
+    handler: ControlHandler,
+    events: EventSource,
+) {
+    let request = tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut stream))
+        .await
+        .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()));
+    let request = match request {
+        Ok(Some(request)) => request,
+        Ok(None) => return,
+        Err(e) => {
+            tracing::debug!("Invalid control request: {}", e);
+            let response = ControlResponse::error(400, "Bad request");
+            let _ = write_response(&mut stream, &response).await;
+            return;
+        }
+    };
+
+    let header = request
+        .headers
+        .get(&AUTHORIZATION_HEADER.to_ascii_lowercase())
+        .map(String::as_str);
+    let query_token = request
+        .query
+        .get("token")
+        .filter(|_| request.path == EVENTS_PATH);
+    let authorized = match query_token {
+        Some(presented) => token.verify(presented),
+        None => token.authorize(header).is_ok(),
+    };
+    if !authorized {
+        let response = ControlResponse::error(401, ControlError::Unauthorized);
+        let _ = write_response(&mut stream, &response).await;
+        return;
+    }
+
+    if request.path == EVENTS_PATH && request.method == "GET" {
+        match request.headers.get("sec-websocket-key") {
+            Some(key) if is_websocket_upgrade(&request.headers) => {
+                stream_events(stream, key, events()).await
+            }
+            _ => {
+                let response = ControlResponse::error(426, "WebSocket upgrade required");
+                let _ = write_response(&mut stream, &response).await;
+            }
+        }
+        return;
+    }
+
+    let response = handler(ControlRequest {
+        method: request.method,
+        path: request.path,
+        body: request.body,
+    })
+    .await;
+    let _ = write_response(&mut stream, &response).await;
+}
+
+/// Read a request head and its body, `None` if the client sent nothing
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<HttpRequest>> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message);
+
+    let mut buf = Vec::new();
+    let head_end = loop {
+        if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        }
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err(invalid("Request too large"));
+        }
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return if buf.is_empty() {
+                Ok(None)
+            } else {
+                Err(invalid("Incomplete request"))
+            };
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let length: usize = headers
+        .get("content-length")
+        .map(|value| value.parse())
+        .transpose()
+        .map_err(|_| invalid("Invalid Content-Length"))?
+        .unwrap_or(0);
+    if length > MAX_REQUEST_SIZE {
+        return Err(invalid("Request too large"));
+    }
+    let mut body = buf.split_off(head_end + 4);
+    if body.len() < length {
+        let start = body.len();
+        body.resize(length, 0);
+        stream.read_exact(&mut body[start..]).await?;
+    }
+    body.truncate(length);
+
+    Ok(Some(HttpRequest {
+        method,
+        path: path.to_string(),
+        query: parse_query(query),
+        headers,
+        body,
+    }))
+}
+
+/// Parse `a=1&b=2`, percent-decoding values
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| {
+            let value = urlencoding::decode(value)
+                .map(|value| value.into_owned())
+                .unwrap_or_else(|_| value.to_string());
+            (name.to_string(), value)
+        })
+        .collect()
+}
+
+/// Send a response and close the connection
+async fn write_response(stream: &mut TcpStream, response: &ControlResponse) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Reason phrase of the status codes the server sends
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        426 => "Upgrade Required",
+        500 => "Internal Server Error",
+        _ => "Error",
+    }
+}
+
+/// Whether the headers ask for a WebSocket upgrade
+fn is_websocket_upgrade(headers: &HashMap<String, String>) -> bool {
+    let has = |name: &str, value: &str| {
+        headers.get(name).is_some_and(|header| {
+            header
+                .split(',')
+                .any(|part| part.trim().eq_ignore_ascii_case(value))
+        })
+    };
+    has("upgrade", "websocket") && has("connection", "upgrade")
+}
+
+/// `Sec-WebSocket-Accept` value for a client key
+fn websocket_accept(key: &str) -> String {
+    use base64::Engine;
+
+    let mut hasher = Sha1::new();
+    hasher.update(key.trim().as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Complete the WebSocket handshake and send events until the client leaves
+async fn stream_events(stream: TcpStream, key: &str, mut events: BoxStream<'static, String>) {
+    let (mut reader, mut writer) = stream.into_split();
+    let handshake = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket_accept(key)
+    );
+    if writer.write_all(handshake.as_bytes()).await.is_err() {
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else {
+                    let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &[])).await;
+                    break;
+                };
+                if writer.write_all(&encode_frame(OPCODE_TEXT, event.as_bytes())).await.is_err() {
+                    break;
+                }
+            }
+            frame = read_frame(&mut reader) => match frame {
+                Ok((OPCODE_PING, payload)) => {
+                    if writer.write_all(&encode_frame(OPCODE_PONG, &payload)).await.is_err() {
+                        break;
+                    }
+                }
+                Ok((OPCODE_CLOSE, _)) | Err(_) => {
+                    let _ = writer.write_all(&encode_frame(OPCODE_CLOSE, &[])).await;
+                    break;
+                }
+                // Clients have nothing to send; other frames are ignored
+                Ok(_) => {}
+            },
+        }
+    }
+}
+
+/// Build an unmasked, unfragmented server frame
+fn encode_frame(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Read one client frame, returning its opcode and unmasked payload
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    reader.read_exact(&mut header).await?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+
+    let length = match header[1] & 0x7F {
+        126 => u64::from(reader.read_u16().await?),
+        127 => reader.read_u64().await?,
+        len => u64::from(len),
+    };
+    if length > MAX_FRAME_SIZE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Frame too large",
+        ));
+    }
+
+    let mut mask = [0u8; 4];
+    if masked {
+        reader.read_exact(&mut mask).await?;
+    }
+    let mut payload = vec![0u8; length as usize];
+    reader.read_exact(&mut payload).await?;
+    if masked {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::TOKEN_FILE_NAME;
+    use std::net::Ipv4Addr;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_websocket_accept() {
+        // Example from RFC 6455, section 1.3
+        assert_eq!(
+            websocket_accept("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_masked_frame() {
+        let mask = [1u8, 2, 3, 4];
+        let mut frame = vec![0x80 | OPCODE_TEXT, 0x80 | 5];
+        frame.extend_from_slice(&mask);
+        frame.extend(b"hello".iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+
+        let (opcode, payload) = read_frame(&mut frame.as_slice()).await.unwrap();
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(payload, b"hello");
+
+        let long = encode_frame(OPCODE_TEXT, &[0u8; 300]);
+        assert_eq!(&long[..4], &[0x81, 126, 0x01, 0x2C]);
+    }
+
+    #[tokio::test]
+    async fn test_control_server() {
+        let temp_dir = TempDir::new().unwrap();
+        let token = Arc::new(
+            AccessTokenStore::load_or_create(temp_dir.path().join(TOKEN_FILE_NAME)).unwrap(),
+        );
+        let handler: ControlHandler = Arc::new(|request: ControlRequest| {
+            Box::pin(async move {
+                ControlResponse::ok(serde_json::json!({
+                    "method": request.method,
+                    "path": request.path,
+                    "body": String::from_utf8_lossy(&request.body),
+                }))
+            })
+        });
+        let events: EventSource =
+            Arc::new(|| futures::stream::iter(vec!["{\"n\":1}".to_string()]).boxed());
+
+        assert!(ControlServer::start(
+            "0.0.0.0:0".parse().unwrap(),
+            Arc::clone(&token),
+            Arc::clone(&handler),
+            Arc::clone(&events),
+        )
+        .await
+        .is_err());
+        let server = ControlServer::start(
+            SocketAddr::from((Ipv4Addr::LOCALHOST, 0)),
+            Arc::clone(&token),
+            handler,
+            events,
+        )
+        .await
+        .unwrap();
+
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let url = format!("http://{}/v1/connect", server.addr());
+        let response = client.post(&url).body("{}").send().await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let response = client
+            .post(&url)
+            .bearer_auth(token.token())
+            .body("{\"server_id\":\"a\"}")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["method"], "POST");
+        assert_eq!(body["path"], "/v1/connect");
+        assert_eq!(body["body"], "{\"server_id\":\"a\"}");
+
+        // Event stream, authenticated through the query string
+        let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+        let request = format!(
+            "GET {}?token={} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            EVENTS_PATH,
+            token.token()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = Vec::new();
+        while !response.ends_with(b"\r\n\r\n") {
+            response.push(stream.read_u8().await.unwrap());
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101"));
+        assert!(response.contains("s3pPLMBiTxaQ9kYGzzhZRbK+xOo="));
+
+        let (opcode, payload) = read_frame(&mut stream).await.unwrap();
+        assert_eq!(opcode, OPCODE_TEXT);
+        assert_eq!(payload, b"{\"n\":1}");
+        // The stream ended, so the server closes
+        let (opcode, _) = read_frame(&mut stream).await.unwrap();
+        assert_eq!(opcode, OPCODE_CLOSE);
+
+        server.stop();
+    }
+}
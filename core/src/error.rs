@@ -3,6 +3,7 @@
 //! This module defines all error types used throughout the V8Ray core library.
 //! It uses `thiserror` for custom error types and `anyhow` for error handling.
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 /// Main error type for V8Ray Core
@@ -36,6 +37,10 @@ pub enum V8RayError {
     #[error("Storage error: {0}")]
     Storage(#[from] StorageError),
 
+    /// Local control API errors
+    #[error("Control error: {0}")]
+    Control(#[from] ControlError),
+
     /// Generic error
     #[error("{0}")]
     Generic(String),
@@ -77,6 +82,12 @@ pub enum ConfigError {
 
     #[error("Config already exists: {0}")]
     AlreadyExists(String),
+
+    #[error("Configuration schema version {found} is newer than the supported version {supported}. Update the app to use this configuration")]
+    UnsupportedVersion { found: u32, supported: u32 },
+
+    #[error("{0}")]
+    Storage(#[from] StorageError),
 }
 
 /// Connection errors
@@ -103,6 +114,9 @@ pub enum ConnectionError {
 
     #[error("Network unreachable")]
     NetworkUnreachable,
+
+    #[error("Connection cancelled")]
+    Cancelled,
 }
 
 /// Subscription errors
@@ -129,6 +143,9 @@ pub enum SubscriptionError {
 
     #[error("Update failed: {0}")]
     UpdateFailed(String),
+
+    #[error("Invalid request header: {0}")]
+    InvalidHeader(String),
 }
 
 /// Xray Core errors
@@ -154,6 +171,33 @@ pub enum XrayError {
     Api(String),
 }
 
+/// Downloaded release failed verification
+#[derive(Error, Debug)]
+#[allow(missing_docs)]
+pub enum IntegrityError {
+    #[error("No checksum available for {0}")]
+    ChecksumUnavailable(String),
+
+    #[error("No checksum from GitHub for {0}, and a mirror's checksum needs a minisign key")]
+    UntrustedChecksum(String),
+
+    #[error("Checksum mismatch for {file}: expected {expected}, got {actual}")]
+    ChecksumMismatch {
+        file: String,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("No signature available for {0}")]
+    SignatureUnavailable(String),
+
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+}
+
 /// Platform specific errors
 #[derive(Error, Debug)]
 #[allow(missing_docs)]
@@ -175,6 +219,12 @@ pub enum PlatformError {
 
     #[error("Command execution failed: {0}")]
     Command(String),
+
+    #[error("Credential store error: {0}")]
+    CredentialStore(String),
+
+    #[error("Privileged helper error: {0}")]
+    Helper(String),
 }
 
 /// Network errors
@@ -221,6 +271,654 @@ pub enum StorageError {
 
     #[error("Encryption error: {0}")]
     Encryption(String),
+
+    #[error("Insufficient disk space at {path}: {required} bytes required, {available} bytes available. Free up disk space and try again")]
+    InsufficientSpace {
+        path: String,
+        required: u64,
+        available: u64,
+    },
+
+    #[error("Directory {path} is not writable ({reason}). Check its permissions or choose another location")]
+    NotWritable { path: String, reason: String },
+
+    #[error("Database schema version {found} is newer than the supported version {supported}. Update the app to open this database")]
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+/// Local control API errors
+#[derive(Error, Debug)]
+#[allow(missing_docs)]
+pub enum ControlError {
+    #[error("Missing or invalid access token")]
+    Unauthorized,
+
+    #[error("Control API must listen on a loopback address, got {0}")]
+    NonLoopbackAddress(String),
+
+    #[error("Token storage error: {0}")]
+    Storage(#[from] StorageError),
+
+    #[error("Another instance is running but did not respond: {0}")]
+    InstanceUnreachable(String),
+}
+
+/// Category of an error, for grouping in user interfaces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// Configuration
+    Config,
+    /// Proxy connection
+    Connection,
+    /// Subscriptions
+    Subscription,
+    /// Xray Core
+    Xray,
+    /// Operating system integration
+    Platform,
+    /// Network requests
+    Network,
+    /// Local storage
+    Storage,
+    /// Local control API
+    Control,
+    /// Anything else
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Lowercase name, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Config => "config",
+            Self::Connection => "connection",
+            Self::Subscription => "subscription",
+            Self::Xray => "xray",
+            Self::Platform => "platform",
+            Self::Network => "network",
+            Self::Storage => "storage",
+            Self::Control => "control",
+            Self::Internal => "internal",
+        }
+    }
+}
+
+/// Stable numeric error code
+///
+/// Codes are grouped by category in blocks of a thousand and are never
+/// reused or renumbered, so clients can match on them instead of on
+/// message text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum V8RayErrorCode {
+    /// Configuration file could not be read or written
+    ConfigIo = 1001,
+    /// Configuration could not be parsed or serialized
+    ConfigSerialization = 1002,
+    /// Configuration is invalid
+    ConfigValidation = 1003,
+    /// Invalid URL in a configuration
+    ConfigInvalidUrl = 1004,
+    /// Unsupported proxy protocol
+    ConfigInvalidProtocol = 1005,
+    /// Required configuration field missing
+    ConfigMissingField = 1006,
+    /// Invalid port
+    ConfigInvalidPort = 1007,
+    /// Configuration not found
+    ConfigNotFound = 1008,
+    /// Configuration already exists
+    ConfigAlreadyExists = 1009,
+    /// Configuration written by a newer release
+    ConfigUnsupportedVersion = 1010,
+    /// Connection failed
+    ConnectionFailed = 2001,
+    /// Connection timed out
+    ConnectionTimeout = 2002,
+    /// Connection refused
+    ConnectionRefused = 2003,
+    /// Already connected
+    AlreadyConnected = 2004,
+    /// Not connected
+    NotConnected = 2005,
+    /// Operation not possible in the current connection state
+    ConnectionInvalidState = 2006,
+    /// Network unreachable
+    NetworkUnreachable = 2007,
+    /// Connecting was cancelled
+    ConnectionCancelled = 2008,
+    /// Subscription download failed
+    SubscriptionHttp = 3001,
+    /// Invalid subscription URL
+    SubscriptionInvalidUrl = 3002,
+    /// Subscription content could not be parsed
+    SubscriptionParse = 3003,
+    /// Unsupported subscription format
+    SubscriptionUnsupportedFormat = 3004,
+    /// Subscription contains no servers
+    SubscriptionEmpty = 3005,
+    /// Subscription not found
+    SubscriptionNotFound = 3006,
+    /// Subscription update failed
+    SubscriptionUpdateFailed = 3007,
+    /// Invalid subscription request header or credentials
+    SubscriptionInvalidHeader = 3008,
+    /// Xray process error
+    XrayProcess = 4001,
+    /// Xray binary not found
+    XrayNotFound = 4002,
+    /// Xray failed to start
+    XrayStartFailed = 4003,
+    /// Xray failed to stop
+    XrayStopFailed = 4004,
+    /// Xray rejected the configuration
+    XrayInvalidConfig = 4005,
+    /// Xray API request failed
+    XrayApi = 4006,
+    /// Downloaded Xray release failed checksum or signature verification
+    XrayIntegrity = 4007,
+    /// VPN permission denied
+    VpnPermissionDenied = 5001,
+    /// VPN or TUN setup failed
+    VpnSetupFailed = 5002,
+    /// System proxy could not be changed
+    SystemProxy = 5003,
+    /// Not supported on this platform
+    PlatformNotSupported = 5004,
+    /// Missing system permission
+    PermissionDenied = 5005,
+    /// System command failed
+    CommandFailed = 5006,
+    /// OS credential store unavailable or failed
+    CredentialStore = 5007,
+    /// Privileged helper service unreachable or refused the request
+    PrivilegedHelper = 5008,
+    /// HTTP request failed
+    NetworkHttp = 6001,
+    /// DNS resolution failed
+    DnsResolution = 6002,
+    /// Network request timed out
+    NetworkTimeout = 6003,
+    /// Network unavailable
+    NetworkUnavailable = 6004,
+    /// Invalid network address
+    InvalidAddress = 6005,
+    /// Database error
+    Database = 7001,
+    /// File could not be read or written
+    StorageIo = 7002,
+    /// Stored data could not be serialized
+    StorageSerialization = 7003,
+    /// Stored data could not be parsed
+    StorageParse = 7004,
+    /// Stored item not found
+    StorageNotFound = 7005,
+    /// Stored item already exists
+    StorageAlreadyExists = 7006,
+    /// Encryption or decryption failed
+    Encryption = 7007,
+    /// Not enough disk space
+    InsufficientSpace = 7008,
+    /// Directory not writable
+    NotWritable = 7009,
+    /// Database written by a newer release
+    StorageUnsupportedVersion = 7010,
+    /// Missing or invalid access token
+    Unauthorized = 8001,
+    /// Control API address is not a loopback address
+    NonLoopbackAddress = 8002,
+    /// Running instance did not respond
+    InstanceUnreachable = 8003,
+    /// Error without a more specific code
+    Unknown = 9000,
+    /// Error reported as plain text
+    Generic = 9001,
+    /// Operating system I/O error
+    Io = 9002,
+}
+
+impl V8RayErrorCode {
+    /// All codes
+    pub const ALL: &'static [Self] = &[
+        Self::ConfigIo,
+        Self::ConfigSerialization,
+        Self::ConfigValidation,
+        Self::ConfigInvalidUrl,
+        Self::ConfigInvalidProtocol,
+        Self::ConfigMissingField,
+        Self::ConfigInvalidPort,
+        Self::ConfigNotFound,
+        Self::ConfigAlreadyExists,
+        Self::ConfigUnsupportedVersion,
+        Self::ConnectionFailed,
+        Self::ConnectionTimeout,
+        Self::ConnectionRefused,
+        Self::AlreadyConnected,
+        Self::NotConnected,
+        Self::ConnectionInvalidState,
+        Self::NetworkUnreachable,
+        Self::ConnectionCancelled,
+        Self::SubscriptionHttp,
+        Self::SubscriptionInvalidUrl,
+        Self::SubscriptionParse,
+        Self::SubscriptionUnsupportedFormat,
+        Self::SubscriptionEmpty,
+        Self::SubscriptionNotFound,
+        Self::SubscriptionUpdateFailed,
+        Self::SubscriptionInvalidHeader,
+        Self::XrayProcess,
+        Self::XrayNotFound,
+        Self::XrayStartFailed,
+        Self::XrayStopFailed,
+        Self::XrayInvalidConfig,
+        Self::XrayApi,
+        Self::XrayIntegrity,
+        Self::VpnPermissionDenied,
+        Self::VpnSetupFailed,
+        Self::SystemProxy,
+        Self::PlatformNotSupported,
+        Self::PermissionDenied,
+        Self::CommandFailed,
+        Self::CredentialStore,
+        Self::PrivilegedHelper,
+        Self::NetworkHttp,
+        Self::DnsResolution,
+        Self::NetworkTimeout,
+        Self::NetworkUnavailable,
+        Self::InvalidAddress,
+        Self::Database,
+        Self::StorageIo,
+        Self::StorageSerialization,
+        Self::StorageParse,
+        Self::StorageNotFound,
+        Self::StorageAlreadyExists,
+        Self::Encryption,
+        Self::InsufficientSpace,
+        Self::NotWritable,
+        Self::StorageUnsupportedVersion,
+        Self::Unauthorized,
+        Self::NonLoopbackAddress,
+        Self::InstanceUnreachable,
+        Self::Unknown,
+        Self::Generic,
+        Self::Io,
+    ];
+
+    /// Numeric code
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Code for a number, if it is known
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.iter().copied().find(|known| known.code() == code)
+    }
+
+    /// Category, given by the thousands of the code
+    pub fn category(self) -> ErrorCategory {
+        match self.code() / 1000 {
+            1 => ErrorCategory::Config,
+            2 => ErrorCategory::Connection,
+            3 => ErrorCategory::Subscription,
+            4 => ErrorCategory::Xray,
+            5 => ErrorCategory::Platform,
+            6 => ErrorCategory::Network,
+            7 => ErrorCategory::Storage,
+            8 => ErrorCategory::Control,
+            _ => ErrorCategory::Internal,
+        }
+    }
+
+    /// Key of the localized message describing the error
+    pub fn message_key(self) -> &'static str {
+        match self {
+            Self::ConfigIo => "error.config.io",
+            Self::ConfigSerialization => "error.config.serialization",
+            Self::ConfigValidation => "error.config.validation",
+            Self::ConfigInvalidUrl => "error.config.invalid_url",
+            Self::ConfigInvalidProtocol => "error.config.invalid_protocol",
+            Self::ConfigMissingField => "error.config.missing_field",
+            Self::ConfigInvalidPort => "error.config.invalid_port",
+            Self::ConfigNotFound => "error.config.not_found",
+            Self::ConfigAlreadyExists => "error.config.already_exists",
+            Self::ConfigUnsupportedVersion => "error.config.unsupported_version",
+            Self::ConnectionFailed => "error.connection.failed",
+            Self::ConnectionTimeout => "error.connection.timeout",
+            Self::ConnectionRefused => "error.connection.refused",
+            Self::AlreadyConnected => "error.connection.already_connected",
+            Self::NotConnected => "error.connection.not_connected",
+            Self::ConnectionInvalidState => "error.connection.invalid_state",
+            Self::NetworkUnreachable => "error.connection.network_unreachable",
+            Self::ConnectionCancelled => "error.connection.cancelled",
+            Self::SubscriptionHttp => "error.subscription.http",
+            Self::SubscriptionInvalidUrl => "error.subscription.invalid_url",
+            Self::SubscriptionParse => "error.subscription.parse",
+            Self::SubscriptionUnsupportedFormat => "error.subscription.unsupported_format",
+            Self::SubscriptionEmpty => "error.subscription.empty",
+            Self::SubscriptionNotFound => "error.subscription.not_found",
+            Self::SubscriptionUpdateFailed => "error.subscription.update_failed",
+            Self::SubscriptionInvalidHeader => "error.subscription.invalid_header",
+            Self::XrayProcess => "error.xray.process",
+            Self::XrayNotFound => "error.xray.not_found",
+            Self::XrayStartFailed => "error.xray.start_failed",
+            Self::XrayStopFailed => "error.xray.stop_failed",
+            Self::XrayInvalidConfig => "error.xray.invalid_config",
+            Self::XrayApi => "error.xray.api",
+            Self::XrayIntegrity => "error.xray.integrity",
+            Self::VpnPermissionDenied => "error.platform.vpn_permission_denied",
+            Self::VpnSetupFailed => "error.platform.vpn_setup_failed",
+            Self::SystemProxy => "error.platform.system_proxy",
+            Self::PlatformNotSupported => "error.platform.not_supported",
+            Self::PermissionDenied => "error.platform.permission_denied",
+            Self::CommandFailed => "error.platform.command_failed",
+            Self::CredentialStore => "error.platform.credential_store",
+            Self::PrivilegedHelper => "error.platform.privileged_helper",
+            Self::NetworkHttp => "error.network.http",
+            Self::DnsResolution => "error.network.dns_resolution",
+            Self::NetworkTimeout => "error.network.timeout",
+            Self::NetworkUnavailable => "error.network.unavailable",
+            Self::InvalidAddress => "error.network.invalid_address",
+            Self::Database => "error.storage.database",
+            Self::StorageIo => "error.storage.io",
+            Self::StorageSerialization => "error.storage.serialization",
+            Self::StorageParse => "error.storage.parse",
+            Self::StorageNotFound => "error.storage.not_found",
+            Self::StorageAlreadyExists => "error.storage.already_exists",
+            Self::Encryption => "error.storage.encryption",
+            Self::InsufficientSpace => "error.storage.insufficient_space",
+            Self::NotWritable => "error.storage.not_writable",
+            Self::StorageUnsupportedVersion => "error.storage.unsupported_version",
+            Self::Unauthorized => "error.control.unauthorized",
+            Self::NonLoopbackAddress => "error.control.non_loopback_address",
+            Self::InstanceUnreachable => "error.control.instance_unreachable",
+            Self::Unknown => "error.internal.unknown",
+            Self::Generic => "error.internal.generic",
+            Self::Io => "error.internal.io",
+        }
+    }
+}
+
+impl V8RayError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Config(e) => e.code(),
+            Self::Connection(e) => e.code(),
+            Self::Subscription(e) => e.code(),
+            Self::Xray(e) => e.code(),
+            Self::Platform(e) => e.code(),
+            Self::Network(e) => e.code(),
+            Self::Storage(e) => e.code(),
+            Self::Control(e) => e.code(),
+            Self::Generic(_) => V8RayErrorCode::Generic,
+        }
+    }
+}
+
+impl ConfigError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Io(_) => V8RayErrorCode::ConfigIo,
+            Self::JsonSerialization(_)
+            | Self::YamlSerialization(_)
+            | Self::TomlSerialization(_) => V8RayErrorCode::ConfigSerialization,
+            Self::Validation(_) => V8RayErrorCode::ConfigValidation,
+            Self::InvalidUrl(_) => V8RayErrorCode::ConfigInvalidUrl,
+            Self::InvalidProtocol(_) => V8RayErrorCode::ConfigInvalidProtocol,
+            Self::MissingField(_) => V8RayErrorCode::ConfigMissingField,
+            Self::InvalidPort(_) => V8RayErrorCode::ConfigInvalidPort,
+            Self::NotFound(_) => V8RayErrorCode::ConfigNotFound,
+            Self::AlreadyExists(_) => V8RayErrorCode::ConfigAlreadyExists,
+            Self::UnsupportedVersion { .. } => V8RayErrorCode::ConfigUnsupportedVersion,
+            Self::Storage(e) => e.code(),
+        }
+    }
+}
+
+impl ConnectionError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Failed(_) => V8RayErrorCode::ConnectionFailed,
+            Self::Timeout => V8RayErrorCode::ConnectionTimeout,
+            Self::Refused => V8RayErrorCode::ConnectionRefused,
+            Self::AlreadyConnected => V8RayErrorCode::AlreadyConnected,
+            Self::NotConnected => V8RayErrorCode::NotConnected,
+            Self::InvalidState(_) => V8RayErrorCode::ConnectionInvalidState,
+            Self::NetworkUnreachable => V8RayErrorCode::NetworkUnreachable,
+            Self::Cancelled => V8RayErrorCode::ConnectionCancelled,
+        }
+    }
+}
+
+impl SubscriptionError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::HttpRequest(_) => V8RayErrorCode::SubscriptionHttp,
+            Self::InvalidUrl(_) => V8RayErrorCode::SubscriptionInvalidUrl,
+            Self::Parse(_) => V8RayErrorCode::SubscriptionParse,
+            Self::UnsupportedFormat(_) => V8RayErrorCode::SubscriptionUnsupportedFormat,
+            Self::Empty => V8RayErrorCode::SubscriptionEmpty,
+            Self::NotFound(_) => V8RayErrorCode::SubscriptionNotFound,
+            Self::UpdateFailed(_) => V8RayErrorCode::SubscriptionUpdateFailed,
+            Self::InvalidHeader(_) => V8RayErrorCode::SubscriptionInvalidHeader,
+        }
+    }
+}
+
+impl XrayError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Process(_) => V8RayErrorCode::XrayProcess,
+            Self::NotFound => V8RayErrorCode::XrayNotFound,
+            Self::StartFailed(_) => V8RayErrorCode::XrayStartFailed,
+            Self::StopFailed(_) => V8RayErrorCode::XrayStopFailed,
+            Self::InvalidConfig(_) => V8RayErrorCode::XrayInvalidConfig,
+            Self::Api(_) => V8RayErrorCode::XrayApi,
+        }
+    }
+}
+
+impl IntegrityError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        V8RayErrorCode::XrayIntegrity
+    }
+}
+
+impl PlatformError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::VpnPermissionDenied => V8RayErrorCode::VpnPermissionDenied,
+            Self::VpnSetupFailed(_) => V8RayErrorCode::VpnSetupFailed,
+            Self::SystemProxy(_) => V8RayErrorCode::SystemProxy,
+            Self::NotSupported(_) => V8RayErrorCode::PlatformNotSupported,
+            Self::Permission(_) => V8RayErrorCode::PermissionDenied,
+            Self::Command(_) => V8RayErrorCode::CommandFailed,
+            Self::CredentialStore(_) => V8RayErrorCode::CredentialStore,
+            Self::Helper(_) => V8RayErrorCode::PrivilegedHelper,
+        }
+    }
+}
+
+impl NetworkError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Http(e) if e.is_timeout() => V8RayErrorCode::NetworkTimeout,
+            Self::Http(_) => V8RayErrorCode::NetworkHttp,
+            Self::DnsResolution(_) => V8RayErrorCode::DnsResolution,
+            Self::Timeout => V8RayErrorCode::NetworkTimeout,
+            Self::Unavailable => V8RayErrorCode::NetworkUnavailable,
+            Self::InvalidAddress(_) => V8RayErrorCode::InvalidAddress,
+        }
+    }
+}
+
+impl StorageError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Database(_) => V8RayErrorCode::Database,
+            Self::Io(_) => V8RayErrorCode::StorageIo,
+            Self::Serialization(_) => V8RayErrorCode::StorageSerialization,
+            Self::Parse(_) => V8RayErrorCode::StorageParse,
+            Self::NotFound(_) => V8RayErrorCode::StorageNotFound,
+            Self::AlreadyExists(_) => V8RayErrorCode::StorageAlreadyExists,
+            Self::Encryption(_) => V8RayErrorCode::Encryption,
+            Self::InsufficientSpace { .. } => V8RayErrorCode::InsufficientSpace,
+            Self::NotWritable { .. } => V8RayErrorCode::NotWritable,
+            Self::UnsupportedVersion { .. } => V8RayErrorCode::StorageUnsupportedVersion,
+        }
+    }
+}
+
+impl ControlError {
+    /// Error code
+    pub fn code(&self) -> V8RayErrorCode {
+        match self {
+            Self::Unauthorized => V8RayErrorCode::Unauthorized,
+            Self::NonLoopbackAddress(_) => V8RayErrorCode::NonLoopbackAddress,
+            Self::Storage(e) => e.code(),
+            Self::InstanceUnreachable(_) => V8RayErrorCode::InstanceUnreachable,
+        }
+    }
+}
+
+/// Error as reported to clients: code, category, message key and detail
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorInfo {
+    /// Numeric code, see [`V8RayErrorCode`]
+    pub code: u32,
+    /// Category of the code
+    pub category: ErrorCategory,
+    /// Key of the localized message
+    pub message_key: String,
+    /// Original error message, for logs and as a fallback
+    pub detail: String,
+}
+
+impl ErrorInfo {
+    /// Error info for a code
+    pub fn new(code: V8RayErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code: code.code(),
+            category: code.category(),
+            message_key: code.message_key().to_string(),
+            detail: detail.into(),
+        }
+    }
+
+    /// Error info for an error passed around as [`anyhow::Error`]
+    ///
+    /// The code comes from the first typed error in the chain, so context
+    /// added on top does not hide it.
+    pub fn from_anyhow(error: &anyhow::Error) -> Self {
+        let code = error
+            .chain()
+            .find_map(error_code)
+            .unwrap_or(V8RayErrorCode::Unknown);
+
+        // Wrapping variants already print their source, so skip causes
+        // whose text is part of the message
+        let mut detail = String::new();
+        for cause in error.chain() {
+            let text = cause.to_string();
+            if detail.contains(&text) {
+                continue;
+            }
+            if !detail.is_empty() {
+                detail.push_str(": ");
+            }
+            detail.push_str(&text);
+        }
+        Self::new(code, detail)
+    }
+}
+
+impl From<&V8RayError> for ErrorInfo {
+    fn from(error: &V8RayError) -> Self {
+        Self::new(error.code(), error.to_string())
+    }
+}
+
+/// Code of a typed error, if it is one this crate knows
+fn error_code(error: &(dyn std::error::Error + 'static)) -> Option<V8RayErrorCode> {
+    if let Some(e) = error.downcast_ref::<V8RayError>() {
+        // Generic errors may wrap a typed cause further down the chain
+        return match e {
+            V8RayError::Generic(_) => None,
+            e => Some(e.code()),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<ConfigError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<ConnectionError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<SubscriptionError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<XrayError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<IntegrityError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<PlatformError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<NetworkError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<StorageError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<ControlError>() {
+        return Some(e.code());
+    }
+    if let Some(e) = error.downcast_ref::<crate::xray::XrayError>() {
+        return Some(match e {
+            crate::xray::XrayError::NotFound => V8RayErrorCode::XrayNotFound,
+            crate::xray::XrayError::PortInUse { .. } => V8RayErrorCode::XrayStartFailed,
+            crate::xray::XrayError::Config(_) => V8RayErrorCode::XrayInvalidConfig,
+            crate::xray::XrayError::Api(_) => V8RayErrorCode::XrayApi,
+            crate::xray::XrayError::Storage(e) => e.code(),
+            crate::xray::XrayError::Integrity(e) => e.code(),
+            crate::xray::XrayError::Io(_) | crate::xray::XrayError::Process(_) => {
+                V8RayErrorCode::XrayProcess
+            }
+        });
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        return Some(match e.is_timeout() {
+            true => V8RayErrorCode::NetworkTimeout,
+            false => V8RayErrorCode::NetworkHttp,
+        });
+    }
+    if error.downcast_ref::<sqlx::Error>().is_some() {
+        return Some(V8RayErrorCode::Database);
+    }
+    if error.downcast_ref::<serde_json::Error>().is_some() {
+        return Some(V8RayErrorCode::ConfigSerialization);
+    }
+    if error.downcast_ref::<uuid::Error>().is_some() {
+        return Some(V8RayErrorCode::ConfigValidation);
+    }
+    if let Some(e) = error.downcast_ref::<std::io::Error>() {
+        return Some(match e.kind() {
+            std::io::ErrorKind::TimedOut => V8RayErrorCode::NetworkTimeout,
+            std::io::ErrorKind::PermissionDenied => V8RayErrorCode::PermissionDenied,
+            _ => V8RayErrorCode::Io,
+        });
+    }
+    None
 }
 
 /// Result type alias for V8Ray operations
@@ -247,10 +945,16 @@ pub type NetworkResult<T> = std::result::Result<T, NetworkError>;
 /// Result type alias for storage operations
 pub type StorageResult<T> = std::result::Result<T, StorageError>;
 
+/// Result type alias for local control API operations
+pub type ControlResult<T> = std::result::Result<T, ControlError>;
+
 impl From<anyhow::Error> for V8RayError {
     fn from(err: anyhow::Error) -> Self {
-        // 简单地将 anyhow::Error 转换为通用错误
-        V8RayError::Generic(err.to_string())
+        // 保留被 anyhow 包装的 V8RayError，其余转换为通用错误
+        match err.downcast::<V8RayError>() {
+            Ok(err) => err,
+            Err(err) => V8RayError::Generic(err.to_string()),
+        }
     }
 }
 
@@ -277,6 +981,60 @@ mod tests {
         assert_eq!(err.to_string(), "Connection timeout");
     }
 
+    #[test]
+    fn test_preflight_error_display() {
+        let err = StorageError::InsufficientSpace {
+            path: "/tmp".to_string(),
+            required: 100,
+            available: 10,
+        };
+        assert!(err.to_string().contains("100 bytes required"));
+
+        let config_err: ConfigError = err.into();
+        assert!(config_err
+            .to_string()
+            .starts_with("Insufficient disk space"));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        // Codes are unique and fall into their category's block
+        for (i, code) in V8RayErrorCode::ALL.iter().enumerate() {
+            assert_eq!(V8RayErrorCode::from_code(code.code()), Some(*code));
+            assert!(V8RayErrorCode::ALL[i + 1..]
+                .iter()
+                .all(|other| other.code() != code.code()));
+            assert!(code
+                .message_key()
+                .starts_with(&format!("error.{}.", code.category().as_str())));
+        }
+
+        let err: V8RayError = ConnectionError::Timeout.into();
+        assert_eq!(err.code(), V8RayErrorCode::ConnectionTimeout);
+        assert_eq!(err.code().code(), 2002);
+        let err: V8RayError = ConfigError::Storage(StorageError::Encryption("x".into())).into();
+        assert_eq!(err.code(), V8RayErrorCode::Encryption);
+    }
+
+    #[test]
+    fn test_error_info_from_anyhow() {
+        let err = anyhow::Error::from(V8RayError::from(SubscriptionError::Empty))
+            .context("Updating subscription");
+        let info = ErrorInfo::from_anyhow(&err);
+        assert_eq!(info.code, 3005);
+        assert_eq!(info.category, ErrorCategory::Subscription);
+        assert_eq!(info.message_key, "error.subscription.empty");
+        assert!(info.detail.starts_with("Updating subscription: "));
+
+        let info = ErrorInfo::from_anyhow(&anyhow::anyhow!("Server not found"));
+        assert_eq!(info.code, V8RayErrorCode::Unknown.code());
+        assert_eq!(info.detail, "Server not found");
+
+        // Typed errors survive a round trip through anyhow
+        let err: V8RayError = anyhow::Error::from(V8RayError::from(XrayError::NotFound)).into();
+        assert!(matches!(err, V8RayError::Xray(XrayError::NotFound)));
+    }
+
     #[test]
     fn test_subscription_error() {
         let err = SubscriptionError::Empty;
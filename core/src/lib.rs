@@ -9,8 +9,12 @@
 pub mod bridge;
 pub mod config;
 pub mod connection;
+pub mod control;
 pub mod error;
+pub mod metrics;
+pub mod paths;
 pub mod platform;
+pub mod proxy_core;
 pub mod subscription;
 pub mod utils;
 pub mod version;
@@ -28,9 +32,10 @@ pub type Value = serde_json::Value;
 pub use config::Config;
 pub use connection::{Connection, ConnectionManager, ConnectionState};
 pub use error::{
-    ConfigError, ConfigResult, ConnectionError, ConnectionResult, NetworkError, NetworkResult,
-    PlatformError, PlatformResult, StorageError, StorageResult, SubscriptionError,
-    SubscriptionResult, V8RayError, XrayError, XrayResult,
+    ConfigError, ConfigResult, ConnectionError, ConnectionResult, ControlError, ControlResult,
+    ErrorCategory, ErrorInfo, NetworkError, NetworkResult, PlatformError, PlatformResult,
+    StorageError, StorageResult, SubscriptionError, SubscriptionResult, V8RayError, V8RayErrorCode,
+    XrayError, XrayResult,
 };
 pub use subscription::{Subscription, SubscriptionManager};
 pub use utils::{init_logger, LogConfig, LogLevel};
@@ -1,48 +1,922 @@
 //! V8Ray Core Binary
 //!
-//! This is the main binary for V8Ray Core, providing a command-line interface
-//! for testing and development purposes.
+//! Command-line interface for running V8Ray Core headless on servers and in
+//! scripts. It uses the same connection and subscription code as the app
+//! and, by default, the same data directory (see `v8ray_core::paths`), so
+//! subscriptions added in one are visible in the other.
+//!
+//! `run` and `connect` stay in the foreground until interrupted or until
+//! `disconnect` is run from another shell; only one of them can run per
+//! data directory. They connect with the app's saved proxy, inbound, DNS
+//! and routing settings.
 
-use anyhow::Result;
-// TODO: Enable when clap is properly configured
-// use clap::{Arg, Command};
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tracing::info;
-use v8ray_core::{init, version};
+use v8ray_core::bridge::settings::SETTINGS_FILE_NAME;
+use v8ray_core::bridge::subscription as subscriptions;
+use v8ray_core::config::manager::ConfigManager;
+use v8ray_core::config::ProxyServerConfig;
+use v8ray_core::connection::runtime_state::{RuntimeStateStore, STATE_FILE_NAME};
+use v8ray_core::control::{
+    notify_running_instance, AccessTokenStore, InstanceLock, TOKEN_FILE_NAME,
+};
+use v8ray_core::metrics::{MetricsServer, SnapshotSource, METRICS_PATH};
+use v8ray_core::paths::{app_paths, set_app_paths};
+use v8ray_core::platform::credentials;
+use v8ray_core::platform::service::{
+    self, HelperServer, ServiceDefinition, DEFAULT_HELPER_PORT, HELPER_TOKEN_FILE_NAME,
+};
+use v8ray_core::platform::tun::TunPlatform;
+use v8ray_core::subscription::SubscriptionStorage;
+use v8ray_core::xray::{RoutingEngine, XrayCore, XrayEvent};
+use v8ray_core::{init, version, ConnectionManager, LogConfig, LogLevel};
+
+/// Subscription database file name, shared with the app
+const DATABASE_FILE_NAME: &str = "v8ray_subscriptions.db";
+
+/// Message asking the running instance to disconnect and exit
+const DISCONNECT_MESSAGE: &str = "disconnect";
+
+/// V8Ray Core - Rust backend for V8Ray cross-platform proxy client
+#[derive(Parser)]
+#[command(name = "v8ray-core", version = version(), author)]
+struct Cli {
+    /// Data directory holding subscriptions and runtime state
+    /// [default: the platform data directory, or $V8RAY_DATA_DIR]
+    #[arg(long, global = true, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Print results as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Enable verbose logging (repeat for debug output)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Connect using a server configuration file
+    Run {
+        /// Server configuration (JSON)
+        #[arg(short, long, value_name = "FILE")]
+        config: PathBuf,
+        #[command(flatten)]
+        mode: ModeArgs,
+        #[command(flatten)]
+        settings: SettingsArgs,
+        #[command(flatten)]
+        serve: ServeArgs,
+    },
+    /// Connect to a server from the subscriptions
+    Connect {
+        /// Server ID, as shown by `sub list --servers`
+        server_id: String,
+        #[command(flatten)]
+        mode: ModeArgs,
+        #[command(flatten)]
+        settings: SettingsArgs,
+        #[command(flatten)]
+        serve: ServeArgs,
+    },
+    /// Stop the connection started by `run` or `connect`
+    Disconnect,
+    /// Manage subscriptions
+    #[command(subcommand)]
+    Sub(SubCommand),
+    /// Show the connection status
+    Status,
+    /// Measure TCP connect latency to servers
+    LatencyTest {
+        /// Server IDs [default: all servers]
+        server_ids: Vec<String>,
+    },
+    /// Check step by step why a server does not work
+    Diagnose {
+        /// Server ID, as shown by `sub list --servers`
+        server_id: String,
+    },
+    /// Show which routing rule and outbound the running connection uses
+    /// for a domain or IP
+    Route {
+        /// Domain or IP address
+        target: String,
+        /// Destination port
+        #[arg(short, long, default_value_t = 443)]
+        port: u16,
+        /// "tcp", "udp", or a sniffed protocol (http, tls, bittorrent)
+        #[arg(long)]
+        protocol: Option<String>,
+    },
+    /// Manage the privileged helper used for TUN mode
+    #[command(subcommand)]
+    Helper(HelperCommand),
+}
+
+#[derive(Args)]
+struct ModeArgs {
+    /// Proxy mode: global, smart or direct
+    #[arg(short, long, default_value = "smart")]
+    mode: String,
+}
+
+#[derive(Args)]
+struct SettingsArgs {
+    /// App settings with the proxy, inbound, DNS and routing settings to
+    /// connect with [default: the app's settings file]
+    #[arg(long, value_name = "FILE")]
+    settings: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// Serve Prometheus metrics at /metrics on ADDR, without
+    /// authentication (e.g. 0.0.0.0:9100)
+    #[arg(long, value_name = "ADDR")]
+    metrics_listen: Option<SocketAddr>,
+    /// Serve the control API on the loopback ADDR, using the access token
+    /// in the data directory (e.g. 127.0.0.1:7891)
+    #[cfg(feature = "control-api")]
+    #[arg(long, value_name = "ADDR")]
+    control_listen: Option<SocketAddr>,
+}
+
+#[derive(Subcommand)]
+enum SubCommand {
+    /// Add a subscription and fetch its servers
+    Add {
+        /// Subscription name
+        name: String,
+        /// Subscription URL
+        url: String,
+    },
+    /// Fetch the servers of a subscription
+    Update {
+        /// Subscription ID [default: all subscriptions]
+        id: Option<String>,
+    },
+    /// List subscriptions
+    List {
+        /// List servers instead of subscriptions
+        #[arg(long)]
+        servers: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum HelperCommand {
+    /// Serve privileged requests (run by the installed service)
+    Serve {
+        /// Loopback port to listen on
+        #[arg(long, default_value_t = DEFAULT_HELPER_PORT)]
+        port: u16,
+        /// File holding the token shared with the app
+        #[arg(long, value_name = "FILE")]
+        token_file: PathBuf,
+        /// tun2socks binary installed with the helper
+        #[arg(long, value_name = "FILE")]
+        tun2socks: Option<PathBuf>,
+    },
+    /// Install the helper as a system service (needs root/administrator)
+    Install {
+        /// Loopback port the helper listens on
+        #[arg(long, default_value_t = DEFAULT_HELPER_PORT)]
+        port: u16,
+    },
+    /// Remove the helper service (needs root/administrator)
+    Uninstall,
+}
+
+/// Output of `status`
+#[derive(Serialize)]
+struct Status {
+    /// Whether `run` or `connect` (or the app) is running
+    running: bool,
+    /// Whether a proxy connection is up
+    connected: bool,
+    /// Server of the last connection
+    server_id: Option<String>,
+    /// Proxy mode of the last connection
+    mode: Option<String>,
+    /// PID of the Xray process
+    xray_pid: Option<u32>,
+    /// Whether the system proxy points at V8Ray
+    system_proxy_applied: bool,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    // TODO: Enable when clap is properly configured
-    /*
-    let matches = Command::new("v8ray-core")
-        .version(version())
-        .author("V8Ray Team <team@v8ray.com>")
-        .about("V8Ray Core - Rust backend for V8Ray cross-platform proxy client")
-        .arg(
-            Arg::new("config")
-                .short('c')
-                .long("config")
-                .value_name("FILE")
-                .help("Sets a custom config file")
-                .num_args(1),
-        )
-        .arg(
-            Arg::new("verbose")
-                .short('v')
-                .long("verbose")
-                .help("Enable verbose logging")
-                .action(clap::ArgAction::SetTrue),
-        )
-        .get_matches();
-    */
-
-    // Initialize the core library
-    init(None)?;
-
-    // TODO: Parse command line arguments properly
-    info!("V8Ray Core v{} started", version());
-
-    // TODO: Implement main application logic
-    info!("Core functionality not yet implemented");
+    let cli = Cli::parse();
+
+    let level = match cli.verbose {
+        0 => LogLevel::Warn,
+        1 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    };
+    init(Some(LogConfig {
+        level,
+        ..Default::default()
+    }))?;
+
+    let mut paths = app_paths();
+    if let Some(dir) = cli.data_dir {
+        // Also used for the Xray configuration
+        paths.data_dir = dir;
+        set_app_paths(Some(paths.clone()));
+    }
+    let data_dir = paths.data_dir;
+    info!(
+        "V8Ray Core v{} using data directory {}",
+        version(),
+        data_dir.display()
+    );
+
+    match cli.command {
+        Command::Run {
+            config,
+            mode,
+            settings,
+            serve,
+        } => {
+            let content = std::fs::read_to_string(&config)
+                .with_context(|| format!("Failed to read {}", config.display()))?;
+            let config: ProxyServerConfig = serde_json::from_str(&content)
+                .with_context(|| format!("Invalid server configuration in {}", config.display()))?;
+            let settings = load_settings(settings.settings).await?;
+            run_connection(&data_dir, config, &mode.mode, &settings, &serve).await
+        }
+        Command::Connect {
+            server_id,
+            mode,
+            settings,
+            serve,
+        } => {
+            let config = load_server_config(&data_dir, &server_id).await?;
+            let settings = load_settings(settings.settings).await?;
+            run_connection(&data_dir, config, &mode.mode, &settings, &serve).await
+        }
+        Command::Disconnect => disconnect(&data_dir).await,
+        Command::Sub(command) => {
+            init_subscriptions(&data_dir).await?;
+            run_sub_command(command, cli.json).await
+        }
+        Command::Status => status(&data_dir, cli.json),
+        Command::LatencyTest { server_ids } => {
+            init_subscriptions(&data_dir).await?;
+            latency_test(server_ids, cli.json).await
+        }
+        Command::Diagnose { server_id } => {
+            let config = load_server_config(&data_dir, &server_id).await?;
+            diagnose(config, cli.json).await
+        }
+        Command::Route {
+            target,
+            port,
+            protocol,
+        } => route(&target, port, protocol.as_deref(), cli.json).await,
+        Command::Helper(command) => run_helper_command(command, &data_dir).await,
+    }
+}
+
+/// Load the app settings from `path`, or from the app's settings file
+///
+/// A missing file gives the default settings. An encrypted file is
+/// decrypted with the key the app saved in the system credential store.
+async fn load_settings(path: Option<PathBuf>) -> Result<ConfigManager> {
+    let path = path.unwrap_or_else(|| app_paths().config_dir.join(SETTINGS_FILE_NAME));
+    let mut settings = ConfigManager::new(&path);
+    if let Err(e) = settings.unlock_from_store(&*credentials::system_store()) {
+        tracing::warn!(
+            "Failed to read the encryption key from the credential store: {}",
+            e
+        );
+    }
+    settings
+        .load()
+        .await
+        .with_context(|| format!("Failed to load settings from {}", path.display()))?;
+    Ok(settings)
+}
+
+/// Connect with `settings` and stay connected until interrupted or asked
+/// to disconnect
+async fn run_connection(
+    data_dir: &Path,
+    config: ProxyServerConfig,
+    mode: &str,
+    settings: &ConfigManager,
+    serve: &ServeArgs,
+) -> Result<()> {
+    let Some(mut lock) = InstanceLock::try_acquire(data_dir)? else {
+        bail!(
+            "V8Ray is already running with data directory {}",
+            data_dir.display()
+        );
+    };
+    let token = Arc::new(AccessTokenStore::load_or_create(
+        data_dir.join(TOKEN_FILE_NAME),
+    )?);
+    let (message_tx, mut messages) = tokio::sync::mpsc::unbounded_channel();
+    #[cfg(feature = "control-api")]
+    let control_tx = message_tx.clone();
+    #[cfg(feature = "control-api")]
+    let control_token = Arc::clone(&token);
+    lock.listen(token, move |message| {
+        let _ = message_tx.send(message);
+    })
+    .await?;
+
+    let state = Arc::new(RuntimeStateStore::open(data_dir.join(STATE_FILE_NAME)));
+    let manager = Arc::new(ConnectionManager::new());
+    manager.apply_config(&settings.get_config().await);
+    // The API serves the traffic counters behind the connection stats
+    manager.get_xray().set_api_enabled(true);
+    track_xray_process(&manager, Arc::clone(&state));
+
+    let server_id = config.id.clone();
+    let name = config.name.clone();
+    manager.connect_with_config_and_mode(config, mode).await?;
+    if let Err(e) = manager.wait_until_ready().await {
+        let _ = manager.disconnect().await;
+        bail!("Proxy not ready: {}", e);
+    }
+    state.update(|s| {
+        s.connected = true;
+        s.server_id = Some(server_id);
+        s.mode = Some(mode.to_string());
+    })?;
+
+    println!("Connected to {} ({} mode)", name, mode);
+    if let Some(xray_config) = manager.get_xray().running_config().await {
+        for inbound in xray_config.inbounds.iter().filter(|inbound| {
+            matches!(inbound.protocol.as_str(), "http" | "socks") && inbound.tag.is_none()
+        }) {
+            println!(
+                "{} proxy listening on {}:{}",
+                inbound.protocol.to_uppercase(),
+                inbound.listen.as_deref().unwrap_or("127.0.0.1"),
+                manager.public_inbound_port(inbound.port)
+            );
+        }
+    }
 
+    let _metrics_server = match serve.metrics_listen {
+        Some(addr) => {
+            let source_manager = Arc::clone(&manager);
+            let source: SnapshotSource = Arc::new(move || {
+                let manager = Arc::clone(&source_manager);
+                Box::pin(async move { manager.metrics_snapshot().await })
+            });
+            let server = MetricsServer::start(addr, source)
+                .await
+                .with_context(|| format!("Failed to serve metrics on {}", addr))?;
+            println!("Metrics served on http://{}{}", server.addr(), METRICS_PATH);
+            Some(server)
+        }
+        None => None,
+    };
+    #[cfg(feature = "control-api")]
+    let _control_server = match serve.control_listen {
+        Some(addr) => {
+            let server = control::start(
+                addr,
+                control_token,
+                Arc::clone(&manager),
+                Arc::clone(&state),
+                control_tx,
+            )
+            .await?;
+            println!("Control API served on http://{}", server.addr());
+            Some(server)
+        }
+        None => None,
+    };
+
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            message = messages.recv() => match message.as_deref() {
+                Some(DISCONNECT_MESSAGE) | None => break,
+                Some(other) => info!("Ignoring message: {}", other),
+            },
+        }
+    }
+
+    manager.disconnect().await?;
+    state.update(|s| {
+        s.connected = false;
+        s.xray_pid = None;
+    })?;
+    println!("Disconnected");
+    Ok(())
+}
+
+/// Keep the Xray PID in the runtime state, so a crash can be recovered from
+fn track_xray_process(manager: &ConnectionManager, state: Arc<RuntimeStateStore>) {
+    let mut events = manager.subscribe_xray_events();
+    tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            if let XrayEvent::ProcessStarted(pid) = event {
+                let _ = state.update(|s| s.xray_pid = Some(pid));
+            }
+        }
+    });
+}
+
+/// Ask the running `run` or `connect` to disconnect
+async fn disconnect(data_dir: &Path) -> Result<()> {
+    if InstanceLock::try_acquire(data_dir)?.is_some() {
+        bail!("V8Ray is not running");
+    }
+    let token = AccessTokenStore::load_or_create(data_dir.join(TOKEN_FILE_NAME))?;
+    notify_running_instance(data_dir, &token.token(), DISCONNECT_MESSAGE).await?;
+    println!("Disconnect requested");
     Ok(())
 }
+
+/// Serve, install or remove the privileged helper
+async fn run_helper_command(command: HelperCommand, data_dir: &Path) -> Result<()> {
+    let platform = TunPlatform::current()
+        .ok_or_else(|| anyhow!("The helper is not supported on this platform"))?;
+    let token_path = data_dir.join(HELPER_TOKEN_FILE_NAME);
+
+    match command {
+        HelperCommand::Serve {
+            port,
+            token_file,
+            tun2socks,
+        } => {
+            let listener = HelperServer::bind(port).await?;
+            let mut server = HelperServer::new(token_file);
+            if let Some(path) = tun2socks {
+                server = server.with_tun2socks(path);
+            }
+            let server = Arc::new(server);
+            tokio::select! {
+                result = Arc::clone(&server).serve(listener) => result?,
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            server.shutdown().await;
+        }
+        HelperCommand::Install { port } => {
+            // Created by the app as the user, so root never owns it
+            if !token_path.exists() {
+                bail!(
+                    "{} does not exist; start the app once before installing the helper",
+                    token_path.display()
+                );
+            }
+            // The service runs copies in a root-owned directory, never the
+            // app's own files
+            let definition = ServiceDefinition::new(platform, port, &token_path)
+                .with_bundled_binaries(platform)?;
+            service::install(&definition).await?;
+            println!("Helper installed");
+        }
+        HelperCommand::Uninstall => {
+            let definition = ServiceDefinition::new(platform, DEFAULT_HELPER_PORT, &token_path);
+            service::uninstall(&definition).await?;
+            println!("Helper removed");
+        }
+    }
+    Ok(())
+}
+
+/// Server configuration of a subscription server
+async fn load_server_config(data_dir: &Path, server_id: &str) -> Result<ProxyServerConfig> {
+    let storage = SubscriptionStorage::new(data_dir.join(DATABASE_FILE_NAME)).await?;
+    let server = storage
+        .load_servers()
+        .await?
+        .into_iter()
+        .find(|server| server.id.to_string() == server_id)
+        .ok_or_else(|| anyhow!("Server not found: {}", server_id))?;
+    server
+        .to_proxy_config()
+        .ok_or_else(|| anyhow!("Unsupported protocol: {}", server.protocol))
+}
+
+/// Open the subscription database the way the app does
+async fn init_subscriptions(data_dir: &Path) -> Result<()> {
+    let db_path = data_dir.join(DATABASE_FILE_NAME);
+    subscriptions::init_subscription_manager(db_path.to_string_lossy().into_owned()).await?;
+    subscriptions::load_subscriptions_from_storage().await?;
+    subscriptions::apply_user_agent().await
+}
+
+/// Run a `sub` subcommand
+async fn run_sub_command(command: SubCommand, json: bool) -> Result<()> {
+    match command {
+        SubCommand::Add { name, url } => {
+            let id = subscriptions::add_subscription(name, url).await?;
+            subscriptions::update_subscription(id.clone()).await?;
+            let count = subscriptions::get_servers_for_subscription(id.clone())
+                .await?
+                .len();
+            if json {
+                print_json(&serde_json::json!({ "id": id, "server_count": count }))?;
+            } else {
+                println!("Added subscription {} with {} servers", id, count);
+            }
+        }
+        SubCommand::Update { id } => {
+            match id {
+                Some(id) => subscriptions::update_subscription(id).await?,
+                None => subscriptions::update_all_subscriptions().await?,
+            }
+            if !json {
+                println!("Updated");
+            }
+        }
+        SubCommand::List { servers: false } => {
+            let subscriptions = subscriptions::get_subscriptions().await?;
+            if json {
+                return print_json(&subscriptions);
+            }
+            for subscription in subscriptions {
+                println!(
+                    "{}  {}  {} servers  {}  {}",
+                    subscription.id,
+                    subscription.name,
+                    subscription.server_count,
+                    subscription.status,
+                    subscription.url
+                );
+            }
+        }
+        SubCommand::List { servers: true } => {
+            let servers = subscriptions::get_servers().await?;
+            if json {
+                return print_json(&servers);
+            }
+            for server in servers {
+                println!(
+                    "{}  {}  {}  {}:{}",
+                    server.id, server.name, server.protocol, server.address, server.port
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runtime state of the running (or last) connection
+fn load_status(data_dir: &Path) -> Result<Status> {
+    let running = InstanceLock::try_acquire(data_dir)?.is_none();
+    let state = RuntimeStateStore::open(data_dir.join(STATE_FILE_NAME)).state();
+    Ok(Status {
+        running,
+        connected: running && state.connected,
+        server_id: state.server_id,
+        mode: state.mode,
+        xray_pid: state.xray_pid.filter(|_| running),
+        system_proxy_applied: state.system_proxy_applied,
+    })
+}
+
+/// Print the runtime state of the running (or last) connection
+fn status(data_dir: &Path, json: bool) -> Result<()> {
+    let status = load_status(data_dir)?;
+    if json {
+        return print_json(&status);
+    }
+    if !status.connected {
+        println!("Disconnected");
+        return Ok(());
+    }
+    println!(
+        "Connected to {} ({} mode)",
+        status.server_id.as_deref().unwrap_or("-"),
+        status.mode.as_deref().unwrap_or("-")
+    );
+    if let Some(pid) = status.xray_pid {
+        println!("Xray PID: {}", pid);
+    }
+    Ok(())
+}
+
+/// Measure and print server latencies
+async fn latency_test(server_ids: Vec<String>, json: bool) -> Result<()> {
+    let server_ids = if server_ids.is_empty() {
+        subscriptions::get_servers()
+            .await?
+            .into_iter()
+            .map(|server| server.id)
+            .collect()
+    } else {
+        server_ids
+    };
+
+    let results = subscriptions::retest_servers(server_ids).await?;
+    if json {
+        return print_json(&results);
+    }
+    for result in results {
+        match result.latency_ms {
+            Some(ms) if result.reachable => println!("{}  {} ms", result.server_id, ms),
+            _ => println!(
+                "{}  unreachable  {}",
+                result.server_id,
+                result.error.unwrap_or_default()
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// Run the diagnostics checklist and print the report
+async fn diagnose(config: ProxyServerConfig, json: bool) -> Result<()> {
+    let report = ConnectionManager::new().run_diagnostics(config).await;
+    if json {
+        return print_json(&report);
+    }
+    for check in &report.checks {
+        println!(
+            "[{:<7}] {}: {}",
+            check.status.as_str(),
+            check.stage.title(),
+            check.message
+        );
+        if let Some(hint) = &check.hint {
+            println!("          Hint: {}", hint);
+        }
+    }
+    match report.failed_stage() {
+        Some(stage) => bail!("Diagnostics failed at: {}", stage.title()),
+        None => {
+            println!("All checks passed");
+            Ok(())
+        }
+    }
+}
+
+/// Evaluate the routing rules of the running connection for `target`
+async fn route(target: &str, port: u16, protocol: Option<&str>, json: bool) -> Result<()> {
+    let engine =
+        RoutingEngine::load_running(&app_paths().xray_config_dir(), XrayCore::new().asset_dir())
+            .context("Not connected; `run` or `connect` first")?;
+    let result = engine.match_target(target, port, protocol).await?;
+    if json {
+        return print_json(&result);
+    }
+
+    let destination = match (&result.outbound_tag, &result.balancer_tag) {
+        (_, Some(balancer)) => format!("balancer {}", balancer),
+        (Some(outbound), None) => format!("outbound {}", outbound),
+        (None, None) => "no outbound".to_string(),
+    };
+    match (&result.rule_index, &result.rule) {
+        (Some(index), Some(rule)) => {
+            println!("{} -> {} (rule {}: {})", target, destination, index, rule)
+        }
+        _ => println!(
+            "{} -> {} (no rule matched, default outbound)",
+            target, destination
+        ),
+    }
+    if !result.resolved_ips.is_empty() {
+        let ips: Vec<String> = result
+            .resolved_ips
+            .iter()
+            .map(|ip| ip.to_string())
+            .collect();
+        println!("Resolved to: {}", ips.join(", "));
+    }
+    if !result.skipped_rules.is_empty() {
+        let rules: Vec<String> = result.skipped_rules.iter().map(|i| i.to_string()).collect();
+        println!(
+            "Rules {} depend on the connecting application and were not evaluated",
+            rules.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Print `value` as pretty JSON
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Control API of `run` and `connect`
+///
+/// Serves the connection status, disconnect, metrics and an event stream
+/// of Xray status changes for the CLI's own connection.
+#[cfg(feature = "control-api")]
+mod control {
+    use super::{Status, DISCONNECT_MESSAGE};
+    use anyhow::Result;
+    use futures::StreamExt;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use tokio::sync::mpsc::UnboundedSender;
+    use v8ray_core::connection::runtime_state::RuntimeStateStore;
+    use v8ray_core::control::server::{
+        ControlHandler, ControlRequest, ControlResponse, ControlServer, EventSource,
+    };
+    use v8ray_core::control::AccessTokenStore;
+    use v8ray_core::xray::XrayEvent;
+    use v8ray_core::ConnectionManager;
+
+    /// Start the control API on `addr`, sending disconnect requests to
+    /// `messages` like `disconnect` does
+    pub async fn start(
+        addr: SocketAddr,
+        token: Arc<AccessTokenStore>,
+        manager: Arc<ConnectionManager>,
+        state: Arc<RuntimeStateStore>,
+        messages: UnboundedSender<String>,
+    ) -> Result<ControlServer> {
+        let handler_manager = Arc::clone(&manager);
+        let handler: ControlHandler = Arc::new(move |request| {
+            let (manager, state, messages) = (
+                Arc::clone(&handler_manager),
+                Arc::clone(&state),
+                messages.clone(),
+            );
+            Box::pin(handle(request, manager, state, messages))
+        });
+        let events: EventSource = Arc::new(move || {
+            futures::stream::unfold(manager.subscribe_xray_events(), |mut events| async move {
+                loop {
+                    match events.recv().await {
+                        Ok(XrayEvent::StatusChanged(status)) => {
+                            let event = serde_json::json!({ "xray_status": status });
+                            return Some((event.to_string(), events));
+                        }
+                        Ok(_) | Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            })
+            .boxed()
+        });
+        Ok(ControlServer::start(addr, token, handler, events).await?)
+    }
+
+    /// Answer one request
+    async fn handle(
+        request: ControlRequest,
+        manager: Arc<ConnectionManager>,
+        state: Arc<RuntimeStateStore>,
+        messages: UnboundedSender<String>,
+    ) -> ControlResponse {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/v1/status") => {
+                let state = state.state();
+                ControlResponse::ok(Status {
+                    running: true,
+                    connected: state.connected,
+                    server_id: state.server_id,
+                    mode: state.mode,
+                    xray_pid: state.xray_pid,
+                    system_proxy_applied: state.system_proxy_applied,
+                })
+            }
+            ("POST", "/v1/disconnect") => {
+                let _ = messages.send(DISCONNECT_MESSAGE.to_string());
+                ControlResponse::ok(serde_json::json!({}))
+            }
+            ("GET", "/metrics") => ControlResponse::text(
+                v8ray_core::metrics::CONTENT_TYPE,
+                v8ray_core::metrics::render(&manager.metrics_snapshot().await),
+            ),
+            (_, "/v1/status" | "/v1/disconnect" | "/metrics") => {
+                ControlResponse::error(405, "Method not allowed")
+            }
+            _ => ControlResponse::error(404, "Not found"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse_run() {
+        let cli = Cli::try_parse_from([
+            "v8ray-core",
+            "run",
+            "-c",
+            "server.json",
+            "--mode",
+            "global",
+            "--settings",
+            "settings.json",
+            "--json",
+        ])
+        .unwrap();
+        assert!(cli.json);
+        let Command::Run {
+            config,
+            mode,
+            settings,
+            ..
+        } = cli.command
+        else {
+            panic!("expected run");
+        };
+        assert_eq!(config, PathBuf::from("server.json"));
+        assert_eq!(mode.mode, "global");
+        assert_eq!(settings.settings, Some(PathBuf::from("settings.json")));
+
+        // The server configuration is required
+        assert!(Cli::try_parse_from(["v8ray-core", "run"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_connect_and_route() {
+        let cli = Cli::try_parse_from(["v8ray-core", "-vv", "connect", "server-1"]).unwrap();
+        assert_eq!(cli.verbose, 2);
+        let Command::Connect {
+            server_id,
+            mode,
+            settings,
+            ..
+        } = cli.command
+        else {
+            panic!("expected connect");
+        };
+        assert_eq!(server_id, "server-1");
+        assert_eq!(mode.mode, "smart");
+        assert!(settings.settings.is_none());
+
+        let cli = Cli::try_parse_from(["v8ray-core", "route", "example.com"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Route {
+                port: 443,
+                protocol: None,
+                ..
+            }
+        ));
+        assert!(Cli::try_parse_from(["v8ray-core", "sub", "list", "--servers"]).is_ok());
+        assert!(Cli::try_parse_from(["v8ray-core", "bogus"]).is_err());
+    }
+
+    #[test]
+    fn test_status_json_shape() {
+        let dir = tempfile::tempdir().unwrap();
+        let status = serde_json::to_value(load_status(dir.path()).unwrap()).unwrap();
+        let mut keys: Vec<&str> = status
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(
+            keys,
+            vec![
+                "connected",
+                "mode",
+                "running",
+                "server_id",
+                "system_proxy_applied",
+                "xray_pid"
+            ]
+        );
+        assert_eq!(status["running"], false);
+        assert_eq!(status["connected"], false);
+        assert!(status["xray_pid"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_load_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(SETTINGS_FILE_NAME);
+        let saved = ConfigManager::new(&path);
+        saved
+            .update_config(|config| config.proxy.http_port = 18080)
+            .await
+            .unwrap();
+        saved.save().await.unwrap();
+
+        let settings = load_settings(Some(path)).await.unwrap();
+        let manager = ConnectionManager::new();
+        manager.apply_config(&settings.get_config().await);
+        assert_eq!(manager.get_xray().inbound_ports().0, 18080);
+
+        // A missing file gives the default settings
+        let settings = load_settings(Some(dir.path().join("missing.json")))
+            .await
+            .unwrap();
+        assert_eq!(settings.get_config().await.proxy.http_port, 8080);
+    }
+}
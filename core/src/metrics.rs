@@ -0,0 +1,344 @@
+//! Prometheus metrics
+//!
+//! Process-wide counters for events that happen deep inside the core
+//! (reconnects, Xray restarts, failed subscription updates), plus a
+//! renderer for the Prometheus text exposition format. Gauges such as
+//! traffic and latency are read from their owners when a scrape comes in
+//! and passed in as a [`MetricsSnapshot`]. They are served on the control
+//! API, or on their own port by [`MetricsServer`].
+
+use futures::future::BoxFuture;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Path served by [`MetricsServer`]
+pub const METRICS_PATH: &str = "/metrics";
+
+/// Largest accepted request head
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+/// Time a scraper has to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Automatic reconnect attempts
+static RECONNECT_ATTEMPTS: AtomicU64 = AtomicU64::new(0);
+
+/// Xray restarts, excluding the first start of a connection
+static XRAY_RESTARTS: AtomicU64 = AtomicU64::new(0);
+
+/// Failed subscription updates
+static SUBSCRIPTION_UPDATE_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Record an automatic reconnect attempt
+pub fn record_reconnect_attempt() {
+    RECONNECT_ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record an Xray restart
+pub fn record_xray_restart() {
+    XRAY_RESTARTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Record a failed subscription update
+pub fn record_subscription_update_failure() {
+    SUBSCRIPTION_UPDATE_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Counters since the process started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// Automatic reconnect attempts
+    pub reconnect_attempts: u64,
+    /// Xray restarts
+    pub xray_restarts: u64,
+    /// Failed subscription updates
+    pub subscription_update_failures: u64,
+}
+
+/// Read the current counters
+pub fn counters() -> Counters {
+    Counters {
+        reconnect_attempts: RECONNECT_ATTEMPTS.load(Ordering::Relaxed),
+        xray_restarts: XRAY_RESTARTS.load(Ordering::Relaxed),
+        subscription_update_failures: SUBSCRIPTION_UPDATE_FAILURES.load(Ordering::Relaxed),
+    }
+}
+
+/// Values exported on a scrape
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Whether a connection is up
+    pub connected: bool,
+    /// Bytes sent through the proxy in the current connection
+    pub upload_bytes: u64,
+    /// Bytes received through the proxy in the current connection
+    pub download_bytes: u64,
+    /// Current upload speed in bytes per second
+    pub upload_speed: u64,
+    /// Current download speed in bytes per second
+    pub download_speed: u64,
+    /// Latency of the last successful probe of the active server
+    pub latency_ms: Option<u64>,
+    /// Process-wide counters
+    pub counters: Counters,
+}
+
+/// Render a snapshot in the Prometheus text exposition format
+pub fn render(snapshot: &MetricsSnapshot) -> String {
+    let counters = &snapshot.counters;
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: Option<u64>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        // Metrics without a value are declared but have no sample
+        if let Some(value) = value {
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+    };
+
+    metric(
+        "v8ray_connected",
+        "gauge",
+        "Whether a proxy connection is up.",
+        Some(u64::from(snapshot.connected)),
+    );
+    metric(
+        "v8ray_upload_bytes",
+        "gauge",
+        "Bytes sent through the proxy in the current connection.",
+        Some(snapshot.upload_bytes),
+    );
+    metric(
+        "v8ray_download_bytes",
+        "gauge",
+        "Bytes received through the proxy in the current connection.",
+        Some(snapshot.download_bytes),
+    );
+    metric(
+        "v8ray_upload_speed_bytes_per_second",
+        "gauge",
+        "Current upload speed.",
+        Some(snapshot.upload_speed),
+    );
+    metric(
+        "v8ray_download_speed_bytes_per_second",
+        "gauge",
+        "Current download speed.",
+        Some(snapshot.download_speed),
+    );
+    metric(
+        "v8ray_active_server_latency_milliseconds",
+        "gauge",
+        "Latency of the last successful probe of the active server.",
+        snapshot.latency_ms,
+    );
+    metric(
+        "v8ray_reconnect_attempts_total",
+        "counter",
+        "Automatic reconnect attempts.",
+        Some(counters.reconnect_attempts),
+    );
+    metric(
+        "v8ray_xray_restarts_total",
+        "counter",
+        "Xray process restarts.",
+        Some(counters.xray_restarts),
+    );
+    metric(
+        "v8ray_subscription_update_failures_total",
+        "counter",
+        "Failed subscription updates.",
+        Some(counters.subscription_update_failures),
+    );
+    out
+}
+
+/// Reads the current values on each scrape
+pub type SnapshotSource = Arc<dyn Fn() -> BoxFuture<'static, MetricsSnapshot> + Send + Sync>;
+
+/// HTTP server answering `GET /metrics`, stopped when dropped
+///
+/// For scrapers that cannot present the control API token: it serves the
+/// metrics only, without authentication, and may listen on any address.
+pub struct MetricsServer {
+    /// Address the server listens on
+    addr: SocketAddr,
+    /// Accept loop
+    task: JoinHandle<()>,
+}
+
+impl MetricsServer {
+    /// Listen on `addr` (port 0 picks a free port)
+    pub async fn start(addr: SocketAddr, source: SnapshotSource) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let source = Arc::clone(&source);
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, source).await {
+                        tracing::debug!("Metrics request failed: {}", e);
+                    }
+                });
+            }
+        });
+
+        tracing::info!("Metrics listening on {}", addr);
+        Ok(Self { addr, task })
+    }
+
+    /// Address the server listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer one request on `stream`
+async fn serve(mut stream: TcpStream, source: SnapshotSource) -> std::io::Result<()> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+            if head.len() >= MAX_REQUEST_HEAD {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "request head too large",
+                ));
+            }
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            head.extend_from_slice(&buf[..n]);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default();
+    let (status, content_type, body) = match (method, path) {
+        ("GET", METRICS_PATH) => ("200 OK", CONTENT_TYPE, render(&source().await)),
+        (_, METRICS_PATH) => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_string(),
+        ),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let before = counters();
+        record_reconnect_attempt();
+        record_xray_restart();
+        record_subscription_update_failure();
+        let after = counters();
+        assert!(after.reconnect_attempts > before.reconnect_attempts);
+        assert!(after.xray_restarts > before.xray_restarts);
+        assert!(after.subscription_update_failures > before.subscription_update_failures);
+    }
+
+    #[test]
+    fn test_render() {
+        let snapshot = MetricsSnapshot {
+            connected: true,
+            upload_bytes: 1024,
+            download_bytes: 4096,
+            upload_speed: 10,
+            download_speed: 20,
+            latency_ms: None,
+            counters: Counters {
+                reconnect_attempts: 2,
+                xray_restarts: 1,
+                subscription_update_failures: 3,
+            },
+        };
+        let text = render(&snapshot);
+
+        assert!(text.contains("# TYPE v8ray_connected gauge\nv8ray_connected 1\n"));
+        assert!(text.contains("\nv8ray_download_bytes 4096\n"));
+        assert!(text.contains("# TYPE v8ray_reconnect_attempts_total counter\n"));
+        assert!(text.contains("\nv8ray_reconnect_attempts_total 2\n"));
+        assert!(text.contains("\nv8ray_subscription_update_failures_total 3\n"));
+        // No probe yet: declared without a sample
+        assert!(text.contains("# TYPE v8ray_active_server_latency_milliseconds gauge\n"));
+        assert!(!text.contains("\nv8ray_active_server_latency_milliseconds "));
+
+        let text = render(&MetricsSnapshot {
+            latency_ms: Some(85),
+            ..snapshot
+        });
+        assert!(text.contains("\nv8ray_active_server_latency_milliseconds 85\n"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_server() {
+        let source: SnapshotSource = Arc::new(|| {
+            Box::pin(async {
+                MetricsSnapshot {
+                    connected: true,
+                    upload_bytes: 512,
+                    ..Default::default()
+                }
+            })
+        });
+        let server = MetricsServer::start("127.0.0.1:0".parse().unwrap(), source)
+            .await
+            .unwrap();
+        let client = reqwest::Client::builder().no_proxy().build().unwrap();
+        let url = |path: &str| format!("http://{}{}", server.addr(), path);
+
+        let response = client.get(url("/metrics")).send().await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(
+            response.headers()["content-type"].to_str().unwrap(),
+            CONTENT_TYPE
+        );
+        let text = response.text().await.unwrap();
+        assert!(text.contains("\nv8ray_connected 1\n"));
+        assert!(text.contains("\nv8ray_upload_bytes 512\n"));
+
+        let response = client.post(url("/metrics")).send().await.unwrap();
+        assert_eq!(response.status(), 405);
+        let response = client.get(url("/v1/status")).send().await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,270 @@
+//! Application directories
+//!
+//! Where the app keeps its configuration, data, caches and logs, following
+//! the conventions of each OS:
+//!
+//! - Linux and other Unix systems: the XDG base directories, e.g.
+//!   `~/.config/v8ray`, `~/.local/share/v8ray`, `~/.cache/v8ray` and
+//!   `~/.local/state/v8ray/logs`
+//! - macOS: `~/Library/Application Support/V8Ray` for configuration and
+//!   data, `~/Library/Caches/V8Ray` and `~/Library/Logs/V8Ray`
+//! - Windows: `%APPDATA%\V8Ray` for configuration and `%LOCALAPPDATA%\V8Ray`
+//!   for data, with `cache` and `logs` below it
+//!
+//! `V8RAY_HOME` puts every directory under one root (a portable install),
+//! and `V8RAY_CONFIG_DIR`, `V8RAY_DATA_DIR`, `V8RAY_CACHE_DIR` and
+//! `V8RAY_LOG_DIR` override single directories. Mobile apps, where these
+//! conventions do not apply, set the directories with [`set_app_paths`].
+
+use crate::error::StorageResult;
+use crate::utils::preflight::check_writable;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Environment variable holding a root for all directories
+pub const HOME_ENV: &str = "V8RAY_HOME";
+/// Environment variable overriding the configuration directory
+pub const CONFIG_DIR_ENV: &str = "V8RAY_CONFIG_DIR";
+/// Environment variable overriding the data directory
+pub const DATA_DIR_ENV: &str = "V8RAY_DATA_DIR";
+/// Environment variable overriding the cache directory
+pub const CACHE_DIR_ENV: &str = "V8RAY_CACHE_DIR";
+/// Environment variable overriding the log directory
+pub const LOG_DIR_ENV: &str = "V8RAY_LOG_DIR";
+
+/// Directory name on Linux and other Unix systems
+const UNIX_DIR_NAME: &str = "v8ray";
+
+lazy_static::lazy_static! {
+    /// Directories set by the app, replacing the detected ones
+    static ref APP_PATHS_OVERRIDE: RwLock<Option<AppPaths>> = RwLock::new(None);
+}
+
+/// Directories the app reads and writes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppPaths {
+    /// User configuration
+    pub config_dir: PathBuf,
+    /// Databases, runtime state and the generated Xray configuration
+    pub data_dir: PathBuf,
+    /// Downloads and temporary files, safe to delete
+    pub cache_dir: PathBuf,
+    /// Log files
+    pub log_dir: PathBuf,
+}
+
+impl AppPaths {
+    /// Every directory under `root`
+    pub fn portable<P: AsRef<Path>>(root: P) -> Self {
+        let root = root.as_ref();
+        Self {
+            config_dir: root.join("config"),
+            data_dir: root.join("data"),
+            cache_dir: root.join("cache"),
+            log_dir: root.join("logs"),
+        }
+    }
+
+    /// Directories from the environment and the conventions of the running OS
+    ///
+    /// Falls back to a portable layout next to the executable when the
+    /// home directory cannot be determined.
+    pub fn detect() -> Self {
+        Self::resolve(std::env::consts::OS, |name| {
+            std::env::var_os(name)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        })
+    }
+
+    /// Directories for `os`, reading environment variables with `var`
+    fn resolve(os: &str, var: impl Fn(&str) -> Option<PathBuf>) -> Self {
+        let mut paths = match var(HOME_ENV) {
+            Some(root) => Self::portable(root),
+            None => Self::platform(os, &var).unwrap_or_else(Self::beside_executable),
+        };
+        for (name, dir) in [
+            (CONFIG_DIR_ENV, &mut paths.config_dir),
+            (DATA_DIR_ENV, &mut paths.data_dir),
+            (CACHE_DIR_ENV, &mut paths.cache_dir),
+            (LOG_DIR_ENV, &mut paths.log_dir),
+        ] {
+            if let Some(value) = var(name) {
+                *dir = value;
+            }
+        }
+        paths
+    }
+
+    /// Conventional directories of `os`, if its base directories are known
+    fn platform(os: &str, var: &impl Fn(&str) -> Option<PathBuf>) -> Option<Self> {
+        let app = crate::version::APP_NAME;
+        match os {
+            "windows" => {
+                let roaming = var("APPDATA")?;
+                let local = var("LOCALAPPDATA").unwrap_or_else(|| roaming.clone());
+                let local = local.join(app);
+                Some(Self {
+                    config_dir: roaming.join(app),
+                    cache_dir: local.join("cache"),
+                    log_dir: local.join("logs"),
+                    data_dir: local,
+                })
+            }
+            "macos" => {
+                let library = var("HOME")?.join("Library");
+                let support = library.join("Application Support").join(app);
+                Some(Self {
+                    config_dir: support.clone(),
+                    data_dir: support,
+                    cache_dir: library.join("Caches").join(app),
+                    log_dir: library.join("Logs").join(app),
+                })
+            }
+            "android" | "ios" => None,
+            _ => {
+                let home = var("HOME");
+                let base = |name: &str, default: &str| {
+                    var(name)
+                        .filter(|dir| dir.is_absolute())
+                        .or_else(|| home.as_ref().map(|home| home.join(default)))
+                        .map(|dir| dir.join(UNIX_DIR_NAME))
+                };
+                Some(Self {
+                    config_dir: base("XDG_CONFIG_HOME", ".config")?,
+                    data_dir: base("XDG_DATA_HOME", ".local/share")?,
+                    cache_dir: base("XDG_CACHE_HOME", ".cache")?,
+                    log_dir: base("XDG_STATE_HOME", ".local/state")?.join("logs"),
+                })
+            }
+        }
+    }
+
+    /// Portable layout next to the executable, or in the temporary directory
+    fn beside_executable() -> Self {
+        let root = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(Path::to_path_buf))
+            .unwrap_or_else(|| std::env::temp_dir().join(UNIX_DIR_NAME));
+        Self::portable(root)
+    }
+
+    /// Directory of Xray Core binaries installed by the updater
+    pub fn xray_bin_dir(&self) -> PathBuf {
+        self.data_dir.join("bin")
+    }
+
+    /// Directory of the configuration files Xray processes are started with
+    pub fn xray_config_dir(&self) -> PathBuf {
+        self.data_dir.join("xray")
+    }
+
+    /// Directory of the sing-box binary installed by the user and the
+    /// configuration it runs with
+    pub fn sing_box_dir(&self) -> PathBuf {
+        self.data_dir.join("sing-box")
+    }
+
+    /// Directory for short-lived files
+    pub fn temp_dir(&self) -> PathBuf {
+        self.cache_dir.join("tmp")
+    }
+
+    /// Create all directories and check they are writable
+    pub fn ensure(&self) -> StorageResult<()> {
+        for dir in [
+            &self.config_dir,
+            &self.data_dir,
+            &self.cache_dir,
+            &self.log_dir,
+        ] {
+            check_writable(dir)?;
+        }
+        Ok(())
+    }
+}
+
+/// Directories in use
+///
+/// Returns the directories set with [`set_app_paths`] if any, otherwise
+/// [`AppPaths::detect`].
+pub fn app_paths() -> AppPaths {
+    APP_PATHS_OVERRIDE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_else(AppPaths::detect)
+}
+
+/// Use `paths` instead of the detected directories (`None` restores detection)
+pub fn set_app_paths(paths: Option<AppPaths>) {
+    *APP_PATHS_OVERRIDE
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = paths;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<PathBuf> {
+        let vars: HashMap<String, PathBuf> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), PathBuf::from(value)))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_platform_conventions() {
+        let paths = AppPaths::resolve(
+            "linux",
+            env(&[("HOME", "/home/u"), ("XDG_CACHE_HOME", "/var/cache/u")]),
+        );
+        assert_eq!(paths.config_dir, PathBuf::from("/home/u/.config/v8ray"));
+        assert_eq!(paths.data_dir, PathBuf::from("/home/u/.local/share/v8ray"));
+        assert_eq!(paths.cache_dir, PathBuf::from("/var/cache/u/v8ray"));
+        assert_eq!(
+            paths.log_dir,
+            PathBuf::from("/home/u/.local/state/v8ray/logs")
+        );
+
+        let paths = AppPaths::resolve("macos", env(&[("HOME", "/Users/u")]));
+        assert_eq!(
+            paths.data_dir,
+            PathBuf::from("/Users/u/Library/Application Support/V8Ray")
+        );
+        assert_eq!(paths.log_dir, PathBuf::from("/Users/u/Library/Logs/V8Ray"));
+
+        let paths = AppPaths::resolve(
+            "windows",
+            env(&[("APPDATA", "C:/Roaming"), ("LOCALAPPDATA", "C:/Local")]),
+        );
+        assert_eq!(paths.config_dir, PathBuf::from("C:/Roaming/V8Ray"));
+        assert_eq!(paths.data_dir, PathBuf::from("C:/Local/V8Ray"));
+        assert_eq!(paths.cache_dir, PathBuf::from("C:/Local/V8Ray/cache"));
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let paths = AppPaths::resolve(
+            "linux",
+            env(&[("HOME", "/home/u"), (HOME_ENV, "/opt/v8ray")]),
+        );
+        assert_eq!(paths, AppPaths::portable("/opt/v8ray"));
+
+        let paths = AppPaths::resolve(
+            "linux",
+            env(&[("HOME", "/home/u"), (DATA_DIR_ENV, "/srv/v8ray")]),
+        );
+        assert_eq!(paths.data_dir, PathBuf::from("/srv/v8ray"));
+        assert_eq!(paths.config_dir, PathBuf::from("/home/u/.config/v8ray"));
+        assert_eq!(paths.xray_config_dir(), PathBuf::from("/srv/v8ray/xray"));
+
+        // Without a home directory everything stays next to the executable
+        let paths = AppPaths::resolve("android", env(&[]));
+        assert_eq!(paths, AppPaths::beside_executable());
+    }
+}
@@ -0,0 +1,410 @@
+//! Android VpnService Integration
+//!
+//! On Android the VPN interface is established by `VpnService` on the
+//! Flutter side, which hands its file descriptor to the core. The core serves
+//! the descriptor with tun2socks (`fd://`), forwarding every flow to the
+//! local SOCKS inbound of Xray.
+//!
+//! Sockets that must bypass the VPN have to be passed to
+//! `VpnService.protect()`, which only the Android side can call. It registers
+//! a protect callback with [`set_protect_callback`]; the core protects its
+//! own sockets through [`protect_socket`], and helper processes can send
+//! theirs to a [`ProtectServer`] socket. Processes that cannot do either,
+//! such as a stock Xray binary, run under the app's UID and must be excluded
+//! from the VPN with `addDisallowedApplication` to avoid routing loops.
+//!
+//! Everything here only needs Unix APIs, so it also builds and is tested on
+//! desktop Unix.
+
+use futures::future::BoxFuture;
+use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags};
+use std::io::{self, IoSliceMut};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, Interest};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::process::{Child, Command};
+
+use super::tun::{find_tun2socks, DEFAULT_TUN_MTU};
+use crate::error::PlatformError;
+
+/// How long tun2socks must stay up before the tunnel counts as started
+const STARTUP_GRACE_PERIOD: Duration = Duration::from_millis(500);
+
+/// Callback protecting a socket from the VPN, returning whether it succeeded
+pub type ProtectCallback = Arc<dyn Fn(RawFd) -> BoxFuture<'static, bool> + Send + Sync>;
+
+lazy_static::lazy_static! {
+    /// Protect callback registered by the Android side
+    static ref PROTECT_CALLBACK: RwLock<Option<ProtectCallback>> = RwLock::new(None);
+}
+
+/// Register or clear the protect callback
+pub fn set_protect_callback(callback: Option<ProtectCallback>) {
+    *PROTECT_CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = callback;
+}
+
+/// Protect a socket so its traffic bypasses the VPN
+///
+/// Returns false if no callback is registered or protecting failed.
+pub async fn protect_socket(fd: RawFd) -> bool {
+    let callback = PROTECT_CALLBACK
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    match callback {
+        Some(callback) => callback(fd).await,
+        None => {
+            tracing::warn!(
+                "No protect callback registered, socket {} not protected",
+                fd
+            );
+            false
+        }
+    }
+}
+
+/// Unix socket protecting descriptors sent by helper processes
+///
+/// Speaks the `protect_path` protocol used by Android proxy tools: the
+/// client sends a socket as `SCM_RIGHTS` ancillary data with a one-byte
+/// payload, and the server replies with one byte, 0 if the socket was
+/// protected.
+pub struct ProtectServer {
+    /// Socket path
+    path: PathBuf,
+    /// Accept loop
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl ProtectServer {
+    /// Listen on `path`, replacing any stale socket file
+    pub fn start(path: PathBuf) -> io::Result<Self> {
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+
+        let task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(serve_protect(stream));
+                    }
+                    Err(e) => {
+                        tracing::warn!("Protect server stopped: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+
+        tracing::info!("Protect server listening on {:?}", path);
+        Ok(Self { path, task })
+    }
+
+    /// Socket path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for ProtectServer {
+    fn drop(&mut self) {
+        self.task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Protect every descriptor a client sends until it disconnects
+async fn serve_protect(mut stream: UnixStream) {
+    loop {
+        let fds = match receive_fds(&stream).await {
+            Ok(Some(fds)) => fds,
+            Ok(None) => return,
+            Err(e) => {
+                tracing::debug!("Protect client error: {}", e);
+                return;
+            }
+        };
+
+        let protected = match fds.first() {
+            Some(&fd) => protect_socket(fd).await,
+            None => false,
+        };
+        for fd in fds {
+            // SAFETY: the descriptors were received by this process and are not used elsewhere
+            unsafe { libc::close(fd) };
+        }
+
+        if stream
+            .write_all(&[if protected { 0 } else { 1 }])
+            .await
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Receive one message, returning the descriptors it carried or None at EOF
+async fn receive_fds(stream: &UnixStream) -> io::Result<Option<Vec<RawFd>>> {
+    loop {
+        stream.readable().await?;
+        let result = stream.try_io(Interest::READABLE, || {
+            let mut buf = [0u8; 1];
+            let mut iov = [IoSliceMut::new(&mut buf)];
+            let mut cmsg = nix::cmsg_space!([RawFd; 4]);
+            let msg = recvmsg::<()>(
+                stream.as_raw_fd(),
+                &mut iov,
+                Some(&mut cmsg),
+                MsgFlags::empty(),
+            )
+            .map_err(io::Error::from)?;
+            if msg.bytes == 0 {
+                return Ok(None);
+            }
+            let fds = msg
+                .cmsgs()
+                .filter_map(|cmsg| match cmsg {
+                    ControlMessageOwned::ScmRights(fds) => Some(fds),
+                    _ => None,
+                })
+                .flatten()
+                .collect();
+            Ok(Some(fds))
+        });
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            result => return result,
+        }
+    }
+}
+
+/// VPN tunnel configuration
+#[derive(Debug, Clone)]
+pub struct VpnTunnelConfig {
+    /// TUN descriptor from `VpnService.Builder.establish()`, owned by the tunnel
+    pub fd: RawFd,
+    /// Local SOCKS inbound port packets are forwarded to
+    pub socks_port: u16,
+    /// MTU the interface was established with
+    pub mtu: u32,
+    /// tun2socks binary, usually `libtun2socks.so` in the native library dir
+    pub tun2socks_path: Option<PathBuf>,
+    /// Path of the protect socket offered to helper processes
+    pub protect_path: Option<PathBuf>,
+}
+
+impl VpnTunnelConfig {
+    /// Create a configuration serving `fd` and forwarding to `socks_port`
+    pub fn new(fd: RawFd, socks_port: u16) -> Self {
+        Self {
+            fd,
+            socks_port,
+            mtu: DEFAULT_TUN_MTU,
+            tun2socks_path: None,
+            protect_path: None,
+        }
+    }
+
+    /// Set the interface MTU
+    pub fn with_mtu(mut self, mtu: u32) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Set the tun2socks binary
+    pub fn with_tun2socks_path(mut self, path: PathBuf) -> Self {
+        self.tun2socks_path = Some(path);
+        self
+    }
+
+    /// Offer a protect socket at `path`
+    pub fn with_protect_path(mut self, path: PathBuf) -> Self {
+        self.protect_path = Some(path);
+        self
+    }
+}
+
+/// Running VPN tunnel over a VpnService descriptor
+///
+/// Dropping the tunnel kills tun2socks and closes the descriptor, which
+/// tears down the VPN interface.
+pub struct VpnTunnel {
+    /// TUN descriptor
+    fd: RawFd,
+    /// tun2socks process
+    process: Option<Child>,
+    /// Protect socket, if offered
+    protect_server: Option<ProtectServer>,
+}
+
+impl VpnTunnel {
+    /// Start forwarding packets from the VpnService descriptor
+    ///
+    /// The tunnel takes ownership of `config.fd`; on failure it is closed.
+    pub async fn start(config: VpnTunnelConfig) -> crate::V8RayResult<Self> {
+        let mut tunnel = Self {
+            fd: config.fd,
+            process: None,
+            protect_server: None,
+        };
+
+        // SAFETY: fcntl only inspects and updates descriptor flags
+        let flags = unsafe { libc::fcntl(config.fd, libc::F_GETFD) };
+        if config.fd < 0 || flags < 0 {
+            tunnel.fd = -1;
+            return Err(PlatformError::VpnSetupFailed(format!(
+                "Invalid VPN descriptor: {}",
+                config.fd
+            ))
+            .into());
+        }
+        // tun2socks inherits the descriptor, so it must survive exec
+        // SAFETY: as above
+        unsafe { libc::fcntl(config.fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) };
+
+        if let Some(ref path) = config.protect_path {
+            tunnel.protect_server = Some(ProtectServer::start(path.clone()).map_err(|e| {
+                PlatformError::VpnSetupFailed(format!("Failed to start protect server: {}", e))
+            })?);
+        }
+
+        let binary = match config.tun2socks_path {
+            Some(ref path) => path.clone(),
+            None => find_tun2socks()?,
+        };
+        let args = super::tun::tun2socks_args(
+            &format!("fd://{}", config.fd),
+            config.socks_port,
+            config.mtu,
+        );
+        let mut process = Command::new(&binary)
+            .args(args)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                PlatformError::VpnSetupFailed(format!("Failed to start tun2socks: {}", e))
+            })?;
+        // Keeps logging for as long as tun2socks runs
+        let stderr = super::tun::drain_stderr(&mut process);
+        let process = tunnel.process.insert(process);
+
+        tokio::time::sleep(STARTUP_GRACE_PERIOD).await;
+        if let Ok(Some(status)) = process.try_wait() {
+            let stderr = match stderr {
+                Some(task) => task.await.unwrap_or_default(),
+                None => String::new(),
+            };
+            return Err(PlatformError::VpnSetupFailed(format!(
+                "tun2socks exited with {}: {}",
+                status,
+                stderr.trim()
+            ))
+            .into());
+        }
+
+        tracing::info!(
+            "VPN tunnel started on fd {} (SOCKS {})",
+            config.fd,
+            config.socks_port
+        );
+        Ok(tunnel)
+    }
+
+    /// Path of the protect socket, if offered
+    pub fn protect_path(&self) -> Option<&Path> {
+        self.protect_server.as_ref().map(|server| server.path())
+    }
+
+    /// Stop tun2socks and close the descriptor
+    pub async fn stop(mut self) {
+        tracing::info!("Stopping VPN tunnel on fd {}", self.fd);
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill().await;
+        }
+    }
+}
+
+impl Drop for VpnTunnel {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            let _ = process.start_kill();
+        }
+        if self.fd >= 0 {
+            // SAFETY: the tunnel owns the descriptor
+            unsafe { libc::close(self.fd) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nix::sys::socket::{sendmsg, ControlMessage};
+    use std::io::{IoSlice, Read};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Send `fd` to the protect server and return its reply
+    fn send_for_protection(client: &mut std::os::unix::net::UnixStream, fd: RawFd) -> u8 {
+        let fds = [fd];
+        sendmsg::<()>(
+            client.as_raw_fd(),
+            &[IoSlice::new(&[1])],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )
+        .unwrap();
+        let mut reply = [0u8; 1];
+        client.read_exact(&mut reply).unwrap();
+        reply[0]
+    }
+
+    #[tokio::test]
+    async fn test_protect_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = ProtectServer::start(dir.path().join("protect_path")).unwrap();
+
+        let protected = Arc::new(AtomicUsize::new(0));
+        let counter = protected.clone();
+        set_protect_callback(Some(Arc::new(move |fd| {
+            let counter = counter.clone();
+            Box::pin(async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+                fd >= 0
+            })
+        })));
+
+        let path = server.path().to_path_buf();
+        let replies = tokio::task::spawn_blocking(move || {
+            let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            let mut client = std::os::unix::net::UnixStream::connect(path).unwrap();
+            let first = send_for_protection(&mut client, socket.as_raw_fd());
+            let second = send_for_protection(&mut client, socket.as_raw_fd());
+            (first, second)
+        })
+        .await
+        .unwrap();
+        assert_eq!(replies, (0, 0));
+        assert_eq!(protected.load(Ordering::SeqCst), 2);
+
+        // Without a callback nothing can be protected
+        set_protect_callback(None);
+        assert!(!protect_socket(0).await);
+
+        let path = server.path().to_path_buf();
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_descriptor() {
+        let config = VpnTunnelConfig::new(-1, 1080).with_mtu(1400);
+        assert!(VpnTunnel::start(config).await.is_err());
+    }
+}
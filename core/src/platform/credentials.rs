@@ -0,0 +1,410 @@
+//! OS credential stores
+//!
+//! Keeps secrets such as the configuration encryption key in the credential
+//! store of the OS, so users do not have to enter a password on every start:
+//!
+//! - Windows: Credential Manager (generic credentials)
+//! - macOS: the login keychain, through the `security` tool
+//! - Linux: the Secret Service (GNOME Keyring, KWallet), through `secret-tool`
+//!   from libsecret
+//!
+//! Mobile platforms have no store here; their apps keep secrets themselves.
+
+use crate::error::{PlatformError, PlatformResult};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Account under which the encryption key of configuration and database is kept
+pub const ENCRYPTION_KEY_ACCOUNT: &str = "encryption-key";
+
+/// Store of secrets by account name
+pub trait CredentialStore: Send + Sync {
+    /// Secret of `account`, or `None` if there is none
+    fn get(&self, account: &str) -> PlatformResult<Option<String>>;
+
+    /// Store `secret` for `account`, replacing any previous one
+    fn set(&self, account: &str, secret: &str) -> PlatformResult<()>;
+
+    /// Remove the secret of `account` (nothing happens if there is none)
+    fn delete(&self, account: &str) -> PlatformResult<()>;
+}
+
+/// Load the encryption key from `store`
+pub fn load_key(store: &dyn CredentialStore) -> PlatformResult<Option<[u8; 32]>> {
+    let Some(secret) = store.get(ENCRYPTION_KEY_ACCOUNT)? else {
+        return Ok(None);
+    };
+    let key = hex::decode(secret.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| {
+            PlatformError::CredentialStore("Stored encryption key is invalid".to_string())
+        })?;
+    Ok(Some(key))
+}
+
+/// Save the encryption key to `store`
+pub fn save_key(store: &dyn CredentialStore, key: &[u8; 32]) -> PlatformResult<()> {
+    store.set(ENCRYPTION_KEY_ACCOUNT, &hex::encode(key))
+}
+
+/// Credential store of the running OS
+pub fn system_store() -> Box<dyn CredentialStore> {
+    #[cfg(target_os = "windows")]
+    return Box::new(windows::CredentialManager);
+
+    #[cfg(target_os = "macos")]
+    return Box::new(KeychainStore);
+
+    #[cfg(target_os = "linux")]
+    return Box::new(SecretServiceStore);
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    return Box::new(UnsupportedStore);
+}
+
+/// In-memory store, for tests and for apps that keep the key themselves
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl CredentialStore for MemoryStore {
+    fn get(&self, account: &str) -> PlatformResult<Option<String>> {
+        let secrets = self.secrets.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(secrets.get(account).cloned())
+    }
+
+    fn set(&self, account: &str, secret: &str) -> PlatformResult<()> {
+        let mut secrets = self.secrets.lock().unwrap_or_else(|e| e.into_inner());
+        secrets.insert(account.to_string(), secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self, account: &str) -> PlatformResult<()> {
+        let mut secrets = self.secrets.lock().unwrap_or_else(|e| e.into_inner());
+        secrets.remove(account);
+        Ok(())
+    }
+}
+
+/// Store for platforms without a supported credential store
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+struct UnsupportedStore;
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+impl CredentialStore for UnsupportedStore {
+    fn get(&self, _account: &str) -> PlatformResult<Option<String>> {
+        Err(unsupported())
+    }
+
+    fn set(&self, _account: &str, _secret: &str) -> PlatformResult<()> {
+        Err(unsupported())
+    }
+
+    fn delete(&self, _account: &str) -> PlatformResult<()> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn unsupported() -> PlatformError {
+    PlatformError::NotSupported(format!("No credential store on {}", std::env::consts::OS))
+}
+
+/// Run a credential tool, feeding `input` on stdin
+///
+/// Returns the exit status, stdout and stderr.
+#[cfg_attr(not(any(target_os = "macos", target_os = "linux")), allow(dead_code))]
+fn run_tool(
+    program: &str,
+    args: &[&str],
+    input: Option<&str>,
+) -> PlatformResult<(std::process::ExitStatus, String, String)> {
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| PlatformError::CredentialStore(format!("Failed to run {}: {}", program, e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Some(input) = input {
+            stdin
+                .write_all(input.as_bytes())
+                .map_err(|e| PlatformError::CredentialStore(e.to_string()))?;
+        }
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| PlatformError::CredentialStore(e.to_string()))?;
+    Ok((
+        output.status,
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    ))
+}
+
+/// macOS login keychain
+#[cfg(target_os = "macos")]
+struct KeychainStore;
+
+/// Exit code of `security` when the item does not exist
+#[cfg(target_os = "macos")]
+const SECURITY_ITEM_NOT_FOUND: i32 = 44;
+
+#[cfg(target_os = "macos")]
+impl CredentialStore for KeychainStore {
+    fn get(&self, account: &str) -> PlatformResult<Option<String>> {
+        let service = crate::version::APP_NAME;
+        let (status, stdout, _) = run_tool(
+            "security",
+            &["find-generic-password", "-s", service, "-a", account, "-w"],
+            None,
+        )?;
+        match status.code() {
+            Some(0) => Ok(Some(stdout.trim_end_matches('\n').to_string())),
+            Some(SECURITY_ITEM_NOT_FOUND) => Ok(None),
+            _ => Err(PlatformError::CredentialStore(format!(
+                "security find-generic-password failed: {}",
+                status
+            ))),
+        }
+    }
+
+    fn set(&self, account: &str, secret: &str) -> PlatformResult<()> {
+        let service = crate::version::APP_NAME;
+        // With `-w` last and no value, security prompts for the password
+        // (twice) on stdin, which keeps the secret out of the process list
+        let input = format!("{}\n{}\n", secret, secret);
+        let (status, _, _) = run_tool(
+            "security",
+            &[
+                "add-generic-password",
+                "-U",
+                "-s",
+                service,
+                "-a",
+                account,
+                "-w",
+            ],
+            Some(&input),
+        )?;
+        if !status.success() {
+            return Err(PlatformError::CredentialStore(format!(
+                "security add-generic-password failed: {}",
+                status
+            )));
+        }
+        Ok(())
+    }
+
+    fn delete(&self, account: &str) -> PlatformResult<()> {
+        let service = crate::version::APP_NAME;
+        let (status, _, _) = run_tool(
+            "security",
+            &["delete-generic-password", "-s", service, "-a", account],
+            None,
+        )?;
+        match status.code() {
+            Some(0) | Some(SECURITY_ITEM_NOT_FOUND) => Ok(()),
+            _ => Err(PlatformError::CredentialStore(format!(
+                "security delete-generic-password failed: {}",
+                status
+            ))),
+        }
+    }
+}
+
+/// Secret Service through libsecret's `secret-tool`
+#[cfg(target_os = "linux")]
+struct SecretServiceStore;
+
+#[cfg(target_os = "linux")]
+impl CredentialStore for SecretServiceStore {
+    fn get(&self, account: &str) -> PlatformResult<Option<String>> {
+        let service = crate::version::APP_NAME;
+        let (status, stdout, stderr) = run_tool(
+            "secret-tool",
+            &["lookup", "service", service, "account", account],
+            None,
+        )?;
+        // secret-tool exits with 1 both when nothing matches and when the
+        // lookup fails; only a failed lookup prints a reason
+        if !status.success() {
+            if stderr.is_empty() {
+                return Ok(None);
+            }
+            return Err(PlatformError::CredentialStore(format!(
+                "secret-tool lookup failed: {}",
+                stderr
+            )));
+        }
+        if stdout.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(stdout.trim_end_matches('\n').to_string()))
+    }
+
+    fn set(&self, account: &str, secret: &str) -> PlatformResult<()> {
+        let service = crate::version::APP_NAME;
+        let label = format!("--label={} {}", service, account);
+        let (status, _, stderr) = run_tool(
+            "secret-tool",
+            &["store", &label, "service", service, "account", account],
+            Some(secret),
+        )?;
+        if !status.success() {
+            return Err(PlatformError::CredentialStore(format!(
+                "secret-tool store failed: {} {}",
+                status, stderr
+            )));
+        }
+        Ok(())
+    }
+
+    fn delete(&self, account: &str) -> PlatformResult<()> {
+        let service = crate::version::APP_NAME;
+        // Clearing a missing secret is not an error for secret-tool either
+        let (status, _, stderr) = run_tool(
+            "secret-tool",
+            &["clear", "service", service, "account", account],
+            None,
+        )?;
+        if !status.success() {
+            return Err(PlatformError::CredentialStore(format!(
+                "secret-tool clear failed: {} {}",
+                status, stderr
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Windows Credential Manager
+#[cfg(target_os = "windows")]
+mod windows {
+    use super::CredentialStore;
+    use crate::error::{PlatformError, PlatformResult};
+    use std::ptr;
+    use winapi::shared::winerror::ERROR_NOT_FOUND;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::wincred::{
+        CredDeleteW, CredFree, CredReadW, CredWriteW, CREDENTIALW, CRED_PERSIST_LOCAL_MACHINE,
+        CRED_TYPE_GENERIC, PCREDENTIALW,
+    };
+
+    /// Generic credentials in the Windows Credential Manager
+    pub struct CredentialManager;
+
+    /// Credential name of `account`
+    fn target(account: &str) -> Vec<u16> {
+        wide(&format!("{}/{}", crate::version::APP_NAME, account))
+    }
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn last_error(operation: &str) -> PlatformError {
+        // SAFETY: GetLastError has no preconditions
+        let code = unsafe { GetLastError() };
+        PlatformError::CredentialStore(format!("{} failed with error {}", operation, code))
+    }
+
+    impl CredentialStore for CredentialManager {
+        fn get(&self, account: &str) -> PlatformResult<Option<String>> {
+            let target = target(account);
+            let mut credential: PCREDENTIALW = ptr::null_mut();
+            // SAFETY: target is NUL-terminated; the credential is freed below
+            unsafe {
+                if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+                    if GetLastError() == ERROR_NOT_FOUND {
+                        return Ok(None);
+                    }
+                    return Err(last_error("CredReadW"));
+                }
+                let blob = std::slice::from_raw_parts(
+                    (*credential).CredentialBlob,
+                    (*credential).CredentialBlobSize as usize,
+                )
+                .to_vec();
+                CredFree(credential as *mut _);
+                String::from_utf8(blob)
+                    .map(Some)
+                    .map_err(|e| PlatformError::CredentialStore(e.to_string()))
+            }
+        }
+
+        fn set(&self, account: &str, secret: &str) -> PlatformResult<()> {
+            let mut target = target(account);
+            let mut user_name = wide(account);
+            let mut blob = secret.as_bytes().to_vec();
+            // SAFETY: all pointers stay valid for the duration of the call
+            unsafe {
+                let mut credential: CREDENTIALW = std::mem::zeroed();
+                credential.Type = CRED_TYPE_GENERIC;
+                credential.TargetName = target.as_mut_ptr();
+                credential.CredentialBlobSize = blob.len() as u32;
+                credential.CredentialBlob = blob.as_mut_ptr();
+                credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+                credential.UserName = user_name.as_mut_ptr();
+                if CredWriteW(&mut credential, 0) == 0 {
+                    return Err(last_error("CredWriteW"));
+                }
+            }
+            Ok(())
+        }
+
+        fn delete(&self, account: &str) -> PlatformResult<()> {
+            let target = target(account);
+            // SAFETY: target is NUL-terminated
+            unsafe {
+                if CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) == 0
+                    && GetLastError() != ERROR_NOT_FOUND
+                {
+                    return Err(last_error("CredDeleteW"));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_round_trip() {
+        let store = MemoryStore::default();
+        assert_eq!(load_key(&store).unwrap(), None);
+
+        let key = crate::utils::crypto::generate_key();
+        save_key(&store, &key).unwrap();
+        assert_eq!(load_key(&store).unwrap(), Some(key));
+
+        store.set(ENCRYPTION_KEY_ACCOUNT, "not a key").unwrap();
+        assert!(load_key(&store).is_err());
+
+        store.delete(ENCRYPTION_KEY_ACCOUNT).unwrap();
+        assert_eq!(load_key(&store).unwrap(), None);
+    }
+
+    #[cfg(any(target_os = "macos", target_os = "linux"))]
+    #[test]
+    fn test_run_tool_reads_stdin_and_stderr() {
+        let (status, stdout, stderr) = run_tool(
+            "sh",
+            &["-c", "read line; echo \"$line\"; echo failed >&2; exit 1"],
+            Some("secret\n"),
+        )
+        .unwrap();
+        assert!(!status.success());
+        assert_eq!(stdout, "secret\n");
+        assert_eq!(stderr, "failed");
+    }
+}
@@ -0,0 +1,419 @@
+//! iOS NetworkExtension Integration
+//!
+//! On iOS the tunnel belongs to the `NEPacketTunnelProvider` of the app's
+//! network extension. The provider hands its packets to the core in one of
+//! two ways:
+//!
+//! - The utun descriptor behind `packetFlow`, located with
+//!   [`find_utun_fd`]. tun2socks then reads and writes it directly, which is
+//!   the cheapest option.
+//! - A [`PacketFlow`]: the provider passes packets read from `packetFlow`
+//!   to [`PacketFlow::write_packets`] and writes back what
+//!   [`PacketFlow::read_packets`] returns. Packets travel over a datagram
+//!   socket pair whose other end tun2socks serves like a TUN descriptor.
+//!
+//! Extensions may not start processes, so tun2socks runs in the extension's
+//! process: the app links a tun2socks library and registers it as a
+//! [`Tun2Socks`], from Rust with [`set_tun2socks`] or from Swift through the
+//! C entry point [`v8ray_register_tun2socks`]. Extensions are killed above
+//! 50 MB, so the core can run in a memory-constrained mode with smaller
+//! socket buffers and batches (see [`PacketFlowConfig::constrained`]).
+
+use std::io;
+use std::os::raw::c_int;
+use std::os::unix::io::{IntoRawFd, RawFd};
+use std::sync::{Arc, RwLock};
+use tokio::net::UnixDatagram;
+use tokio_util::sync::CancellationToken;
+
+/// Packets returned by one [`PacketFlow::read_packets`] call
+pub const DEFAULT_PACKET_BATCH: usize = 64;
+
+/// Packets returned by one read in memory-constrained mode
+pub const CONSTRAINED_PACKET_BATCH: usize = 16;
+
+/// Socket buffer size in memory-constrained mode
+///
+/// Holds a few dozen full-size packets in each direction.
+pub const CONSTRAINED_SOCKET_BUFFER: usize = 128 * 1024;
+
+/// Packet flow settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketFlowConfig {
+    /// Interface MTU, the largest packet passed in either direction
+    pub mtu: u32,
+    /// Most packets returned by one read
+    pub batch: usize,
+    /// Send and receive buffer size of each socket, None for the system
+    /// default
+    pub socket_buffer: Option<usize>,
+}
+
+impl PacketFlowConfig {
+    /// Settings for an interface with `mtu`
+    pub fn new(mtu: u32) -> Self {
+        Self {
+            mtu,
+            batch: DEFAULT_PACKET_BATCH,
+            socket_buffer: None,
+        }
+    }
+
+    /// Settings for an interface with `mtu` within the extension's memory
+    /// limit
+    pub fn constrained(mtu: u32) -> Self {
+        Self {
+            mtu,
+            batch: CONSTRAINED_PACKET_BATCH,
+            socket_buffer: Some(CONSTRAINED_SOCKET_BUFFER),
+        }
+    }
+}
+
+/// Packets exchanged with the provider's `packetFlow`
+pub struct PacketFlow {
+    /// Provider end of the socket pair
+    socket: UnixDatagram,
+    /// Settings
+    config: PacketFlowConfig,
+    /// Cancelled when the flow is closed
+    closed: CancellationToken,
+}
+
+impl PacketFlow {
+    /// Create a flow, returning it with the descriptor to serve as the TUN
+    /// device
+    ///
+    /// The caller owns the descriptor, which is blocking like a real TUN
+    /// device.
+    pub fn pair(config: PacketFlowConfig) -> io::Result<(Self, RawFd)> {
+        let (socket, device) = std::os::unix::net::UnixDatagram::pair()?;
+        if let Some(size) = config.socket_buffer {
+            for socket in [&socket, &device] {
+                set_buffer_size(socket, size)?;
+            }
+        }
+        socket.set_nonblocking(true)?;
+        let flow = Self {
+            socket: UnixDatagram::from_std(socket)?,
+            config,
+            closed: CancellationToken::new(),
+        };
+        Ok((flow, device.into_raw_fd()))
+    }
+
+    /// Pass packets read from `packetFlow` to the tunnel
+    ///
+    /// Packets larger than the MTU are dropped.
+    pub async fn write_packets(&self, packets: &[Vec<u8>]) -> io::Result<()> {
+        if self.is_closed() {
+            return Err(closed_error());
+        }
+        for packet in packets {
+            if packet.is_empty() || packet.len() > self.config.mtu as usize {
+                tracing::debug!("Dropping {} byte packet", packet.len());
+                continue;
+            }
+            tokio::select! {
+                result = self.socket.send(packet) => { result?; }
+                _ = self.closed.cancelled() => return Err(closed_error()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for packets to write to `packetFlow`
+    ///
+    /// Returns at least one packet and at most the configured batch; fails
+    /// once the flow is closed.
+    pub async fn read_packets(&self) -> io::Result<Vec<Vec<u8>>> {
+        let mut buf = vec![0u8; self.config.mtu as usize];
+        let len = tokio::select! {
+            result = self.socket.recv(&mut buf) => result?,
+            _ = self.closed.cancelled() => return Err(closed_error()),
+        };
+        let mut packets = vec![buf[..len].to_vec()];
+        while packets.len() < self.config.batch {
+            match self.socket.try_recv(&mut buf) {
+                Ok(len) => packets.push(buf[..len].to_vec()),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(packets)
+    }
+
+    /// Close the flow, failing pending and later reads and writes
+    pub fn close(&self) {
+        self.closed.cancel();
+    }
+
+    /// Whether the flow is closed
+    pub fn is_closed(&self) -> bool {
+        self.closed.is_cancelled()
+    }
+}
+
+/// Error returned once the flow is closed
+fn closed_error() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Packet flow closed")
+}
+
+/// Set the send and receive buffer sizes of `socket`
+fn set_buffer_size(socket: &std::os::unix::net::UnixDatagram, size: usize) -> io::Result<()> {
+    use nix::sys::socket::{setsockopt, sockopt};
+    setsockopt(socket, sockopt::SndBuf, &size).map_err(io::Error::from)?;
+    setsockopt(socket, sockopt::RcvBuf, &size).map_err(io::Error::from)?;
+    Ok(())
+}
+
+/// tun2socks running in the extension's process
+pub trait Tun2Socks: Send + Sync {
+    /// Serve the TUN descriptor `fd`, forwarding its flows to the SOCKS
+    /// proxy on `socks_port`
+    ///
+    /// Blocks until [`Tun2Socks::stop`] is called. The descriptor stays
+    /// owned by the caller.
+    fn run(&self, fd: RawFd, socks_port: u16, mtu: u32) -> Result<(), String>;
+
+    /// Make a running [`Tun2Socks::run`] return
+    fn stop(&self);
+}
+
+/// Runs tun2socks until it is stopped: `(fd, socks_port, mtu)`, returning
+/// 0 on success
+pub type Tun2SocksRunFn = extern "C" fn(fd: c_int, socks_port: u16, mtu: u32) -> c_int;
+
+/// Stops a running [`Tun2SocksRunFn`]
+pub type Tun2SocksStopFn = extern "C" fn();
+
+/// tun2socks library linked into the extension, called through its C
+/// entry points
+struct FfiTun2Socks {
+    run: Tun2SocksRunFn,
+    stop: Tun2SocksStopFn,
+}
+
+impl Tun2Socks for FfiTun2Socks {
+    fn run(&self, fd: RawFd, socks_port: u16, mtu: u32) -> Result<(), String> {
+        match (self.run)(fd, socks_port, mtu) {
+            0 => Ok(()),
+            code => Err(format!("tun2socks exited with code {}", code)),
+        }
+    }
+
+    fn stop(&self) {
+        (self.stop)()
+    }
+}
+
+lazy_static::lazy_static! {
+    /// Registered tun2socks
+    static ref TUN2SOCKS: RwLock<Option<Arc<dyn Tun2Socks>>> = RwLock::new(None);
+}
+
+/// Register the tun2socks packet tunnels run, None to remove it
+pub fn set_tun2socks(tun2socks: Option<Arc<dyn Tun2Socks>>) {
+    *TUN2SOCKS.write().unwrap_or_else(|e| e.into_inner()) = tun2socks;
+}
+
+/// Register a tun2socks library by its C entry points, e.g. from the
+/// packet tunnel provider before starting the tunnel
+///
+/// Passing a null pointer for either function removes the registration.
+#[no_mangle]
+pub extern "C" fn v8ray_register_tun2socks(
+    run: Option<Tun2SocksRunFn>,
+    stop: Option<Tun2SocksStopFn>,
+) {
+    let tun2socks = match (run, stop) {
+        (Some(run), Some(stop)) => Some(Arc::new(FfiTun2Socks { run, stop }) as Arc<dyn Tun2Socks>),
+        _ => None,
+    };
+    set_tun2socks(tun2socks);
+}
+
+/// How long a starting tun2socks may fail before the tunnel counts as up
+const STARTUP_CHECK: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Packet tunnel started by the extension
+pub struct PacketTunnel {
+    /// tun2socks serving the descriptor
+    tun2socks: Arc<dyn Tun2Socks>,
+    /// Thread running tun2socks
+    worker: tokio::task::JoinHandle<Result<(), String>>,
+    /// Descriptor served, owned by the tunnel
+    fd: RawFd,
+    /// Packet flow, None when serving the utun descriptor
+    flow: Option<std::sync::Arc<PacketFlow>>,
+}
+
+impl PacketTunnel {
+    /// Serve the utun descriptor `fd`
+    ///
+    /// The descriptor stays owned by `packetFlow`; the tunnel serves and
+    /// closes a duplicate, so it can be restarted on the same descriptor.
+    pub async fn start_with_fd(fd: RawFd, socks_port: u16, mtu: u32) -> crate::V8RayResult<Self> {
+        // SAFETY: dup only creates a new descriptor for the same file
+        let fd = unsafe { libc::dup(fd) };
+        if fd < 0 {
+            return Err(crate::error::PlatformError::VpnSetupFailed(format!(
+                "Failed to duplicate utun descriptor: {}",
+                io::Error::last_os_error()
+            ))
+            .into());
+        }
+        Self::start(fd, socks_port, mtu, None).await
+    }
+
+    /// Serve a new packet flow
+    pub async fn start_with_flow(
+        config: PacketFlowConfig,
+        socks_port: u16,
+    ) -> crate::V8RayResult<Self> {
+        let (flow, fd) = PacketFlow::pair(config).map_err(|e| {
+            crate::error::PlatformError::VpnSetupFailed(format!(
+                "Failed to create packet flow: {}",
+                e
+            ))
+        })?;
+        Self::start(fd, socks_port, config.mtu, Some(std::sync::Arc::new(flow))).await
+    }
+
+    /// Run the registered tun2socks on `fd`, which the tunnel then owns
+    async fn start(
+        fd: RawFd,
+        socks_port: u16,
+        mtu: u32,
+        flow: Option<std::sync::Arc<PacketFlow>>,
+    ) -> crate::V8RayResult<Self> {
+        let registered = TUN2SOCKS.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let Some(tun2socks) = registered else {
+            close_fd(fd);
+            return Err(crate::error::PlatformError::VpnSetupFailed(
+                "No tun2socks is registered".to_string(),
+            )
+            .into());
+        };
+
+        let runner = Arc::clone(&tun2socks);
+        let mut worker = tokio::task::spawn_blocking(move || runner.run(fd, socks_port, mtu));
+        if let Ok(result) = tokio::time::timeout(STARTUP_CHECK, &mut worker).await {
+            close_fd(fd);
+            let message = match result {
+                Ok(Ok(())) => "tun2socks exited right away".to_string(),
+                Ok(Err(e)) => e,
+                Err(e) => e.to_string(),
+            };
+            return Err(crate::error::PlatformError::VpnSetupFailed(message).into());
+        }
+        tracing::info!("Packet tunnel started on descriptor {}", fd);
+        Ok(Self {
+            tun2socks,
+            worker,
+            fd,
+            flow,
+        })
+    }
+
+    /// Packet flow, None when serving the utun descriptor
+    pub fn flow(&self) -> Option<std::sync::Arc<PacketFlow>> {
+        self.flow.clone()
+    }
+
+    /// Close the packet flow and stop tun2socks
+    pub async fn stop(self) {
+        if let Some(flow) = &self.flow {
+            flow.close();
+        }
+        self.tun2socks.stop();
+        match self.worker.await {
+            Ok(Err(e)) => tracing::warn!("tun2socks failed: {}", e),
+            Err(e) => tracing::warn!("tun2socks thread failed: {}", e),
+            Ok(Ok(())) => {}
+        }
+        close_fd(self.fd);
+        tracing::info!("Packet tunnel stopped");
+    }
+}
+
+/// Close a descriptor owned by the tunnel
+fn close_fd(fd: RawFd) {
+    // SAFETY: the tunnel owns `fd` and nothing uses it any more
+    unsafe {
+        libc::close(fd);
+    }
+}
+
+/// Find the utun descriptor behind the provider's `packetFlow`
+///
+/// `NEPacketTunnelFlow` does not expose its descriptor, but it is the only
+/// open utun control socket in the extension.
+pub fn find_utun_fd() -> Option<RawFd> {
+    /// `SYSPROTO_CONTROL` from `<sys/sys_domain.h>`
+    const SYSPROTO_CONTROL: libc::c_int = 2;
+    /// `UTUN_OPT_IFNAME` from `<net/if_utun.h>`
+    const UTUN_OPT_IFNAME: libc::c_int = 2;
+
+    (0..1024).find(|&fd| {
+        let mut name = [0u8; libc::IFNAMSIZ];
+        let mut len = name.len() as libc::socklen_t;
+        // SAFETY: getsockopt writes at most `len` bytes into `name`
+        let result = unsafe {
+            libc::getsockopt(
+                fd,
+                SYSPROTO_CONTROL,
+                UTUN_OPT_IFNAME,
+                name.as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        result == 0 && name.starts_with(b"utun")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::io::FromRawFd;
+
+    #[tokio::test]
+    async fn test_packet_flow() {
+        let (flow, fd) = PacketFlow::pair(PacketFlowConfig {
+            batch: 2,
+            ..PacketFlowConfig::constrained(1500)
+        })
+        .unwrap();
+        // SAFETY: the descriptor was just created and is owned here
+        let device = unsafe { std::os::unix::net::UnixDatagram::from_raw_fd(fd) };
+
+        // Provider to tunnel, oversized packets dropped
+        flow.write_packets(&[vec![1, 2, 3], vec![0; 2000], vec![4]])
+            .await
+            .unwrap();
+        let mut buf = [0u8; 1500];
+        assert_eq!(device.recv(&mut buf).unwrap(), 3);
+        assert_eq!(device.recv(&mut buf).unwrap(), 1);
+
+        // Tunnel to provider, in batches
+        for packet in [&[5u8][..], &[6, 7], &[8]] {
+            device.send(packet).unwrap();
+        }
+        assert_eq!(
+            flow.read_packets().await.unwrap(),
+            vec![vec![5], vec![6, 7]]
+        );
+        assert_eq!(flow.read_packets().await.unwrap(), vec![vec![8]]);
+
+        // Closing wakes up a pending read
+        let flow = std::sync::Arc::new(flow);
+        let reader = tokio::spawn({
+            let flow = std::sync::Arc::clone(&flow);
+            async move { flow.read_packets().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        flow.close();
+        assert!(reader.await.unwrap().is_err());
+        assert!(flow.write_packets(&[vec![1]]).await.is_err());
+    }
+}
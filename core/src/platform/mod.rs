@@ -5,6 +5,21 @@
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(unix)]
+pub mod android;
+pub mod credentials;
+#[cfg(target_os = "ios")]
+pub mod ios;
+#[cfg(target_os = "linux")]
+pub mod netns;
+pub mod network_monitor;
+pub mod os_info;
+pub mod pac;
+pub mod service;
+pub mod share;
+pub mod tun;
+pub mod windows_version;
+
 /// Platform information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlatformInfo {
@@ -31,14 +46,291 @@ pub struct PlatformCapabilities {
     pub auto_start: bool,
 }
 
+/// Protocols configured when setting the system proxy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SystemProxyProtocols {
+    /// HTTP (web) proxy, using the HTTP inbound
+    pub http: bool,
+    /// HTTPS (secure web) proxy, using the HTTP inbound
+    pub https: bool,
+    /// SOCKS proxy, using the SOCKS inbound
+    pub socks: bool,
+}
+
+impl Default for SystemProxyProtocols {
+    fn default() -> Self {
+        Self {
+            http: true,
+            https: true,
+            socks: true,
+        }
+    }
+}
+
+impl SystemProxyProtocols {
+    /// Check that at least one protocol is enabled
+    pub fn validate(&self) -> crate::V8RayResult<()> {
+        if !self.http && !self.https && !self.socks {
+            return Err(crate::error::PlatformError::SystemProxy(
+                "At least one system proxy protocol must be enabled".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Protocol names with the local port each one uses, `None` if disabled
+    pub fn ports(&self, http_port: u16, socks_port: u16) -> [(&'static str, Option<u16>); 3] {
+        [
+            ("http", self.http.then_some(http_port)),
+            ("https", self.https.then_some(http_port)),
+            ("socks", self.socks.then_some(socks_port)),
+        ]
+    }
+}
+
+/// System proxy configuration captured before the app changes it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "platform", rename_all = "lowercase")]
+pub enum SystemProxySnapshot {
+    /// WinInet settings of the LAN connection
+    Windows {
+        /// Whether the proxy server was enabled
+        proxy_enabled: bool,
+        /// Proxy server list
+        proxy_server: String,
+        /// Addresses bypassing the proxy
+        proxy_bypass: String,
+        /// PAC URL, empty if none
+        pac_url: String,
+    },
+    /// networksetup settings of each network service
+    Macos {
+        /// Settings by network service
+        services: Vec<MacProxyServiceSnapshot>,
+    },
+    /// GNOME and KDE proxy settings
+    Linux {
+        /// `(schema, key, value)` with values in GVariant text form
+        settings: Vec<(String, String, String)>,
+        /// `(key, value)` in the proxy group of KDE's `kioslaverc`
+        #[serde(default)]
+        kde: Vec<(String, String)>,
+    },
+}
+
+/// Proxy settings of one macOS network service
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacProxyServiceSnapshot {
+    /// Network service name
+    pub service: String,
+    /// Web, secure web and SOCKS proxies
+    pub proxies: Vec<MacProxySetting>,
+    /// PAC URL, empty if none
+    pub pac_url: String,
+    /// Whether the PAC URL was enabled
+    pub pac_enabled: bool,
+}
+
+/// One networksetup proxy setting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacProxySetting {
+    /// networksetup proxy kind (`webproxy`, `securewebproxy` or
+    /// `socksfirewallproxy`)
+    pub kind: String,
+    /// Whether the proxy was enabled
+    pub enabled: bool,
+    /// Proxy server, empty if none
+    pub server: String,
+    /// Proxy port
+    pub port: u16,
+}
+
+/// Parse `Key: value` lines printed by networksetup `-get...` commands
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_networksetup_info(output: &str) -> std::collections::HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect()
+}
+
+/// macOS network services the system proxy is applied to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "services", rename_all = "lowercase")]
+pub enum NetworkServiceScope {
+    /// Every enabled service except VPN interfaces
+    #[default]
+    Active,
+    /// The enabled service carrying the default route, falling back to
+    /// [`NetworkServiceScope::Active`] when it cannot be determined
+    Primary,
+    /// The named services, if enabled
+    Selected(Vec<String>),
+}
+
+lazy_static::lazy_static! {
+    /// Services targeted when setting the macOS system proxy
+    static ref NETWORK_SERVICE_SCOPE: std::sync::RwLock<NetworkServiceScope> =
+        std::sync::RwLock::new(NetworkServiceScope::default());
+}
+
+/// Get the macOS network services targeted by the system proxy
+pub fn get_network_service_scope() -> NetworkServiceScope {
+    NETWORK_SERVICE_SCOPE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Choose the macOS network services targeted by the system proxy
+///
+/// Takes effect the next time the system proxy is set.
+pub fn set_network_service_scope(scope: NetworkServiceScope) -> crate::V8RayResult<()> {
+    if matches!(&scope, NetworkServiceScope::Selected(services) if services.is_empty()) {
+        return Err(crate::error::PlatformError::SystemProxy(
+            "At least one network service must be selected".to_string(),
+        )
+        .into());
+    }
+    *NETWORK_SERVICE_SCOPE
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = scope;
+    Ok(())
+}
+
+/// macOS network service from `networksetup -listnetworkserviceorder`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkService {
+    /// Service name (e.g. "Wi-Fi")
+    pub name: String,
+    /// Hardware port (e.g. "Wi-Fi", or a bundle ID for VPN apps)
+    pub hardware_port: String,
+    /// BSD device (e.g. "en0"), empty for most VPNs
+    pub device: String,
+    /// Whether the service is enabled
+    pub enabled: bool,
+}
+
+impl NetworkService {
+    /// Whether the service is a VPN interface
+    ///
+    /// VPN services have no device or a tunnel device, and their hardware
+    /// port is a VPN type or the bundle ID of the VPN app.
+    pub fn is_vpn(&self) -> bool {
+        const TUNNEL_DEVICES: [&str; 3] = ["utun", "ppp", "ipsec"];
+        const VPN_PORTS: [&str; 4] = ["VPN", "IPSec", "L2TP", "PPTP"];
+
+        self.device.is_empty()
+            || TUNNEL_DEVICES
+                .iter()
+                .any(|prefix| self.device.starts_with(prefix))
+            || VPN_PORTS
+                .iter()
+                .any(|port| self.hardware_port.contains(port))
+            || self.hardware_port.starts_with("com.")
+    }
+}
+
+/// Parse the output of `networksetup -listnetworkserviceorder`
+///
+/// Services are listed in order as `(1) Wi-Fi` followed by
+/// `(Hardware Port: Wi-Fi, Device: en0)`; disabled services are numbered
+/// `(*)`.
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_network_service_order(output: &str) -> Vec<NetworkService> {
+    let mut services = Vec::new();
+    let mut current: Option<(String, bool)> = None;
+
+    for line in output.lines().map(str::trim) {
+        let Some(rest) = line.strip_prefix('(') else {
+            continue;
+        };
+        if let Some(hardware) = rest.strip_prefix("Hardware Port:") {
+            let Some((name, enabled)) = current.take() else {
+                continue;
+            };
+            let hardware = hardware.trim_end_matches(')');
+            let (hardware_port, device) =
+                hardware.rsplit_once(", Device:").unwrap_or((hardware, ""));
+            services.push(NetworkService {
+                name,
+                hardware_port: hardware_port.trim().to_string(),
+                device: device.trim().to_string(),
+                enabled,
+            });
+        } else if let Some((index, name)) = rest.split_once(") ") {
+            current = Some((name.trim().to_string(), index != "*"));
+        }
+    }
+    services
+}
+
+/// Field of the default route (e.g. "interface" or "gateway") from
+/// `route -n get default`
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_route_get_field(output: &str, field: &str) -> Option<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .filter(|(key, _)| *key == field)
+        .map(|(_, value)| value.trim().to_string())
+        .find(|value| !value.is_empty())
+}
+
+/// Names of the `services` targeted by `scope`
+///
+/// `primary_device` is the interface carrying the default route.
+pub fn select_network_services(
+    services: &[NetworkService],
+    scope: &NetworkServiceScope,
+    primary_device: Option<&str>,
+) -> Vec<String> {
+    let enabled = services.iter().filter(|service| service.enabled);
+    let active = || {
+        enabled
+            .clone()
+            .filter(|service| !service.is_vpn())
+            .map(|service| service.name.clone())
+            .collect()
+    };
+
+    match scope {
+        NetworkServiceScope::Active => active(),
+        NetworkServiceScope::Primary => enabled
+            .clone()
+            .find(|service| !service.is_vpn() && Some(service.device.as_str()) == primary_device)
+            .map(|service| vec![service.name.clone()])
+            .unwrap_or_else(active),
+        NetworkServiceScope::Selected(names) => enabled
+            .filter(|service| names.contains(&service.name))
+            .map(|service| service.name.clone())
+            .collect(),
+    }
+}
+
 /// Platform-specific operations
 pub trait PlatformOps {
     /// Check if the application has sufficient permissions to modify system settings
     /// Returns true if running with admin/root privileges
     fn has_admin_privileges(&self) -> crate::V8RayResult<bool>;
 
-    /// Set system proxy
-    fn set_system_proxy(&self, http_port: u16, socks_port: u16) -> crate::V8RayResult<()>;
+    /// Set system proxy for all protocols
+    fn set_system_proxy(&self, http_port: u16, socks_port: u16) -> crate::V8RayResult<()> {
+        self.set_system_proxy_with_protocols(http_port, socks_port, SystemProxyProtocols::default())
+    }
+
+    /// Set system proxy for the enabled protocols, turning the others off
+    fn set_system_proxy_with_protocols(
+        &self,
+        http_port: u16,
+        socks_port: u16,
+        protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()>;
+
+    /// Point the system proxy at a PAC script URL (automatic proxy configuration)
+    fn set_system_proxy_pac(&self, pac_url: &str) -> crate::V8RayResult<()>;
 
     /// Clear system proxy
     fn clear_system_proxy(&self) -> crate::V8RayResult<()>;
@@ -46,6 +338,21 @@ pub trait PlatformOps {
     /// Check if system proxy is set
     fn is_system_proxy_set(&self) -> crate::V8RayResult<bool>;
 
+    /// Capture the current system proxy configuration so it can be restored
+    /// after the app's proxy is cleared
+    ///
+    /// Returns `None` where there is nothing to restore.
+    fn snapshot_system_proxy(&self) -> crate::V8RayResult<Option<SystemProxySnapshot>> {
+        Ok(None)
+    }
+
+    /// Restore a configuration captured by
+    /// [`PlatformOps::snapshot_system_proxy`], replacing the app's proxy
+    fn restore_system_proxy(&self, snapshot: &SystemProxySnapshot) -> crate::V8RayResult<()> {
+        tracing::warn!("Cannot restore {:?} here, clearing instead", snapshot);
+        self.clear_system_proxy()
+    }
+
     /// Enable auto start
     fn enable_auto_start(&self) -> crate::V8RayResult<()>;
 
@@ -61,7 +368,7 @@ pub fn get_platform_info() -> PlatformInfo {
     let os = std::env::consts::OS.to_string();
     let arch = std::env::consts::ARCH.to_string();
 
-    // Get OS version (simplified)
+    // OS version, with the distribution and desktop on Linux
     let version = match os.as_str() {
         #[cfg(target_os = "windows")]
         "windows" => get_windows_version(),
@@ -85,12 +392,8 @@ pub fn get_platform_info() -> PlatformInfo {
 /// Get platform capabilities based on OS
 fn get_platform_capabilities(os: &str) -> PlatformCapabilities {
     match os {
-        "windows" => PlatformCapabilities {
-            system_proxy: true,
-            vpn_mode: false, // TODO: Implement WinTUN support
-            tun_mode: false,
-            auto_start: true,
-        },
+        // WinTUN via tun2socks, depending on the release
+        "windows" => windows_version::capabilities(windows_version::WindowsVersion::current()),
         "macos" => PlatformCapabilities {
             system_proxy: true,
             vpn_mode: true, // NetworkExtension
@@ -132,20 +435,25 @@ fn get_platform_capabilities(os: &str) -> PlatformCapabilities {
 
 #[cfg(target_os = "windows")]
 fn get_windows_version() -> String {
-    // TODO: Implement proper Windows version detection
-    "Windows 10+".to_string()
+    windows_version::WindowsVersion::current()
+        .map(|version| version.to_string())
+        .unwrap_or_else(|| "Windows".to_string())
 }
 
 #[cfg(target_os = "macos")]
 fn get_macos_version() -> String {
-    // TODO: Implement proper macOS version detection
-    "macOS 12+".to_string()
+    os_info::MacOSVersion::current()
+        .map(|version| version.to_string())
+        .unwrap_or_else(|| "macOS".to_string())
 }
 
 #[cfg(target_os = "linux")]
 fn get_linux_version() -> String {
-    // TODO: Implement proper Linux version detection
-    "Linux".to_string()
+    os_info::linux_version_summary(
+        os_info::LinuxDistro::current().as_ref(),
+        os_info::kernel_release().as_deref(),
+        os_info::DesktopEnvironment::current().as_ref(),
+    )
 }
 
 #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
@@ -161,6 +469,104 @@ fn get_linux_version() -> String {
     "unknown".to_string()
 }
 
+/// Argument passed to the app when launched at login
+pub const AUTO_START_ARG: &str = "--minimized";
+
+/// Registry key of per-user programs started at login
+#[cfg(target_os = "windows")]
+const WINDOWS_RUN_KEY: &str = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
+
+/// Command line launching `exe` at login
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn auto_start_command(exe: &std::path::Path) -> String {
+    format!("\"{}\" {}", exe.display(), AUTO_START_ARG)
+}
+
+/// launchd label of the login item, matching the app's bundle identifier
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+const LAUNCH_AGENT_LABEL: &str = "com.v8ray.v8rayApp";
+
+/// Options of the macOS LaunchAgent starting the app at login
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaunchAgentOptions {
+    /// Start the app when the agent is loaded at login
+    pub run_at_load: bool,
+    /// Restart the app if it exits abnormally
+    pub keep_alive: bool,
+}
+
+impl Default for LaunchAgentOptions {
+    fn default() -> Self {
+        Self {
+            run_at_load: true,
+            keep_alive: false,
+        }
+    }
+}
+
+/// LaunchAgent property list launching `exe` at login
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn launch_agent_plist(label: &str, exe: &std::path::Path, options: &LaunchAgentOptions) -> String {
+    fn escape(value: &str) -> String {
+        value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+    fn bool_tag(value: bool) -> &'static str {
+        if value {
+            "<true/>"
+        } else {
+            "<false/>"
+        }
+    }
+
+    // KeepAlive only restarts after a crash, so quitting the app stays quit
+    let keep_alive = if options.keep_alive {
+        "<dict>\n\t\t<key>SuccessfulExit</key>\n\t\t<false/>\n\t</dict>"
+    } else {
+        "<false/>"
+    };
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>Label</key>
+	<string>{}</string>
+	<key>ProgramArguments</key>
+	<array>
+		<string>{}</string>
+		<string>{}</string>
+	</array>
+	<key>RunAtLoad</key>
+	{}
+	<key>KeepAlive</key>
+	{}
+	<key>ProcessType</key>
+	<string>Interactive</string>
+</dict>
+</plist>
+"#,
+        escape(label),
+        escape(&exe.display().to_string()),
+        AUTO_START_ARG,
+        bool_tag(options.run_at_load),
+        keep_alive
+    )
+}
+
+/// Whether `launchctl print-disabled` output marks `label` as disabled
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn launchctl_is_disabled(output: &str, label: &str) -> bool {
+    let quoted = format!("\"{}\"", label);
+    output.lines().any(|line| {
+        let line = line.trim();
+        line.starts_with(&quoted) && (line.ends_with("disabled") || line.ends_with("true"))
+    })
+}
+
 /// Windows platform implementation
 #[cfg(target_os = "windows")]
 pub struct WindowsPlatform;
@@ -210,15 +616,31 @@ impl PlatformOps for WindowsPlatform {
         }
     }
 
-    fn set_system_proxy(&self, _http_port: u16, socks_port: u16) -> crate::V8RayResult<()> {
+    fn set_system_proxy_with_protocols(
+        &self,
+        http_port: u16,
+        socks_port: u16,
+        protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
+        protocols.validate()?;
         tracing::info!(
-            "Setting Windows system proxy using WinInet API: SOCKS={}",
-            socks_port
+            "Setting Windows system proxy using WinInet API: SOCKS={}, protocols={:?}",
+            socks_port,
+            protocols
         );
 
         // 使用 WinInet API 设置系统代理（参考 v2rayN 的实现）
-        // 代理格式：127.0.0.1:端口（不带任何协议前缀）
-        let proxy_server = format!("127.0.0.1:{}", socks_port);
+        // 全部启用时格式为 127.0.0.1:端口（不带任何协议前缀），否则按协议分别指定
+        let proxy_server = if protocols == SystemProxyProtocols::default() {
+            format!("127.0.0.1:{}", socks_port)
+        } else {
+            protocols
+                .ports(http_port, socks_port)
+                .into_iter()
+                .filter_map(|(name, port)| port.map(|port| format!("{}=127.0.0.1:{}", name, port)))
+                .collect::<Vec<_>>()
+                .join(";")
+        };
         let proxy_bypass = "localhost;127.*;10.*;172.16.*;192.168.*;*.local;<local>";
 
         Self::set_internet_proxy(&proxy_server, &proxy_bypass)?;
@@ -227,6 +649,13 @@ impl PlatformOps for WindowsPlatform {
         Ok(())
     }
 
+    fn set_system_proxy_pac(&self, pac_url: &str) -> crate::V8RayResult<()> {
+        tracing::info!("Setting Windows system proxy PAC: {}", pac_url);
+        Self::set_internet_pac(pac_url)?;
+        tracing::info!("Windows system proxy PAC set successfully");
+        Ok(())
+    }
+
     fn clear_system_proxy(&self) -> crate::V8RayResult<()> {
         tracing::info!("Clearing Windows system proxy using WinInet API");
 
@@ -259,29 +688,234 @@ impl PlatformOps for WindowsPlatform {
         Ok(proxy_enable == 1)
     }
 
+    fn snapshot_system_proxy(&self) -> crate::V8RayResult<Option<SystemProxySnapshot>> {
+        use winreg::enums::*;
+        use winreg::RegKey;
+
+        let hkcu = RegKey::predef(HKEY_CURRENT_USER);
+        let internet_settings = hkcu
+            .open_subkey_with_flags(
+                "Software\\Microsoft\\Windows\\CurrentVersion\\Internet Settings",
+                KEY_READ,
+            )
+            .map_err(|e| {
+                crate::error::PlatformError::SystemProxy(format!(
+                    "Failed to open registry key: {}",
+                    e
+                ))
+            })?;
+
+        let proxy_enable: u32 = internet_settings.get_value("ProxyEnable").unwrap_or(0);
+        Ok(Some(SystemProxySnapshot::Windows {
+            proxy_enabled: proxy_enable == 1,
+            proxy_server: internet_settings
+                .get_value("ProxyServer")
+                .unwrap_or_default(),
+            proxy_bypass: internet_settings
+                .get_value("ProxyOverride")
+                .unwrap_or_default(),
+            pac_url: internet_settings
+                .get_value("AutoConfigURL")
+                .unwrap_or_default(),
+        }))
+    }
+
+    fn restore_system_proxy(&self, snapshot: &SystemProxySnapshot) -> crate::V8RayResult<()> {
+        const INTERNET_PER_CONN_PROXY_SERVER: u32 = 2;
+        const INTERNET_PER_CONN_PROXY_BYPASS: u32 = 3;
+        const INTERNET_PER_CONN_AUTOCONFIG_URL: u32 = 4;
+
+        const PROXY_TYPE_DIRECT: u32 = 0x00000001;
+        const PROXY_TYPE_PROXY: u32 = 0x00000002;
+        const PROXY_TYPE_AUTO_PROXY_URL: u32 = 0x00000004;
+
+        let SystemProxySnapshot::Windows {
+            proxy_enabled,
+            proxy_server,
+            proxy_bypass,
+            pac_url,
+        } = snapshot
+        else {
+            return self.clear_system_proxy();
+        };
+        tracing::info!("Restoring previous Windows system proxy settings");
+
+        let mut flags = PROXY_TYPE_DIRECT;
+        if *proxy_enabled {
+            flags |= PROXY_TYPE_PROXY;
+        }
+        if !pac_url.is_empty() {
+            flags |= PROXY_TYPE_AUTO_PROXY_URL;
+        }
+        Self::set_internet_options(
+            flags,
+            &[
+                (INTERNET_PER_CONN_PROXY_SERVER, proxy_server),
+                (INTERNET_PER_CONN_PROXY_BYPASS, proxy_bypass),
+                (INTERNET_PER_CONN_AUTOCONFIG_URL, pac_url),
+            ],
+        )
+    }
+
     fn enable_auto_start(&self) -> crate::V8RayResult<()> {
-        // TODO: Implement Windows auto start
-        tracing::info!("Enabling Windows auto start");
+        let command = auto_start_command(&Self::current_exe()?);
+        tracing::info!("Enabling Windows auto start: {}", command);
+
+        // 以管理员身份运行时使用计划任务，登录后以最高权限启动（TUN 模式需要）；
+        // 否则写入当前用户的 Run 键
+        if self.has_admin_privileges()? {
+            Self::run_schtasks(&[
+                "/Create",
+                "/TN",
+                crate::version::APP_NAME,
+                "/TR",
+                &command,
+                "/SC",
+                "ONLOGON",
+                "/RL",
+                "HIGHEST",
+                "/F",
+            ])?;
+            Self::delete_run_value()?;
+        } else {
+            Self::open_run_key(winreg::enums::KEY_SET_VALUE)?
+                .set_value(crate::version::APP_NAME, &command)
+                .map_err(|e| {
+                    crate::error::PlatformError::Command(format!(
+                        "Failed to write Run registry value: {}",
+                        e
+                    ))
+                })?;
+        }
         Ok(())
     }
 
     fn disable_auto_start(&self) -> crate::V8RayResult<()> {
-        // TODO: Implement Windows auto start disable
         tracing::info!("Disabling Windows auto start");
+        Self::delete_run_value()?;
+        if Self::auto_start_task_exists() {
+            Self::run_schtasks(&["/Delete", "/TN", crate::version::APP_NAME, "/F"])?;
+        }
         Ok(())
     }
 
     fn is_auto_start_enabled(&self) -> crate::V8RayResult<bool> {
-        // TODO: Implement Windows auto start check
-        Ok(false)
+        let exe = Self::current_exe()?;
+        let value: Option<String> = Self::open_run_key(winreg::enums::KEY_READ)
+            .ok()
+            .and_then(|key| key.get_value(crate::version::APP_NAME).ok());
+
+        // 安装目录变化后旧的启动项已失效
+        let registered = value.is_some_and(|command| {
+            command
+                .to_lowercase()
+                .contains(&exe.display().to_string().to_lowercase())
+        });
+        Ok(registered || Self::auto_start_task_exists())
     }
 }
 
 #[cfg(target_os = "windows")]
 impl WindowsPlatform {
+    /// 当前可执行文件路径
+    fn current_exe() -> crate::V8RayResult<std::path::PathBuf> {
+        std::env::current_exe().map_err(|e| {
+            crate::error::PlatformError::Command(format!("Failed to locate executable: {}", e))
+                .into()
+        })
+    }
+
+    /// 打开当前用户的 Run 注册表键
+    fn open_run_key(flags: u32) -> crate::V8RayResult<winreg::RegKey> {
+        let hkcu = winreg::RegKey::predef(winreg::enums::HKEY_CURRENT_USER);
+        let (key, _) = hkcu
+            .create_subkey_with_flags(WINDOWS_RUN_KEY, flags)
+            .map_err(|e| {
+                crate::error::PlatformError::Command(format!(
+                    "Failed to open Run registry key: {}",
+                    e
+                ))
+            })?;
+        Ok(key)
+    }
+
+    /// 删除 Run 键中的启动项（不存在时忽略）
+    fn delete_run_value() -> crate::V8RayResult<()> {
+        match Self::open_run_key(winreg::enums::KEY_SET_VALUE)?
+            .delete_value(crate::version::APP_NAME)
+        {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(crate::error::PlatformError::Command(format!(
+                "Failed to delete Run registry value: {}",
+                e
+            ))
+            .into()),
+        }
+    }
+
+    /// 检查计划任务启动项是否存在
+    fn auto_start_task_exists() -> bool {
+        Self::run_schtasks(&["/Query", "/TN", crate::version::APP_NAME]).is_ok()
+    }
+
+    /// 运行 schtasks 命令
+    fn run_schtasks(args: &[&str]) -> crate::V8RayResult<()> {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+        let output = std::process::Command::new("schtasks")
+            .args(args)
+            .creation_flags(CREATE_NO_WINDOW)
+            .output()
+            .map_err(|e| {
+                crate::error::PlatformError::Command(format!("Failed to run schtasks: {}", e))
+            })?;
+        if !output.status.success() {
+            return Err(crate::error::PlatformError::Command(format!(
+                "schtasks {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
     /// 使用 WinInet API 设置系统代理
     /// 参考 v2rayN 的实现：ProxySettingWindows.cs
     fn set_internet_proxy(proxy_server: &str, proxy_bypass: &str) -> crate::V8RayResult<()> {
+        const INTERNET_PER_CONN_PROXY_SERVER: u32 = 2;
+        const INTERNET_PER_CONN_PROXY_BYPASS: u32 = 3;
+
+        const PROXY_TYPE_DIRECT: u32 = 0x00000001;
+        const PROXY_TYPE_PROXY: u32 = 0x00000002;
+
+        // 启用直连和代理，并设置代理服务器地址和绕过列表
+        Self::set_internet_options(
+            PROXY_TYPE_DIRECT | PROXY_TYPE_PROXY,
+            &[
+                (INTERNET_PER_CONN_PROXY_SERVER, proxy_server),
+                (INTERNET_PER_CONN_PROXY_BYPASS, proxy_bypass),
+            ],
+        )
+    }
+
+    /// 使用 WinInet API 设置 PAC 地址
+    fn set_internet_pac(pac_url: &str) -> crate::V8RayResult<()> {
+        const INTERNET_PER_CONN_AUTOCONFIG_URL: u32 = 4;
+
+        const PROXY_TYPE_DIRECT: u32 = 0x00000001;
+        const PROXY_TYPE_AUTO_PROXY_URL: u32 = 0x00000004;
+
+        Self::set_internet_options(
+            PROXY_TYPE_DIRECT | PROXY_TYPE_AUTO_PROXY_URL,
+            &[(INTERNET_PER_CONN_AUTOCONFIG_URL, pac_url)],
+        )
+    }
+
+    /// 使用 WinInet API 设置连接标志和字符串选项，并通知系统设置已更改
+    fn set_internet_options(flags: u32, values: &[(u32, &str)]) -> crate::V8RayResult<()> {
         use std::ffi::OsStr;
         use std::iter::once;
         use std::mem;
@@ -294,11 +928,6 @@ impl WindowsPlatform {
         const INTERNET_OPTION_REFRESH: u32 = 37;
 
         const INTERNET_PER_CONN_FLAGS: u32 = 1;
-        const INTERNET_PER_CONN_PROXY_SERVER: u32 = 2;
-        const INTERNET_PER_CONN_PROXY_BYPASS: u32 = 3;
-
-        const PROXY_TYPE_DIRECT: u32 = 0x00000001;
-        const PROXY_TYPE_PROXY: u32 = 0x00000002;
 
         // WinInet API 结构体定义
         #[repr(C)]
@@ -334,41 +963,36 @@ impl WindowsPlatform {
         }
 
         unsafe {
-            // 准备代理服务器字符串（宽字符）
-            let proxy_server_wide: Vec<u16> = OsStr::new(proxy_server)
-                .encode_wide()
-                .chain(once(0))
+            // 准备字符串选项（宽字符），需在调用结束前保持有效
+            let wide_values: Vec<Vec<u16>> = values
+                .iter()
+                .map(|(_, value)| OsStr::new(value).encode_wide().chain(once(0)).collect())
                 .collect();
-            let proxy_bypass_wide: Vec<u16> = OsStr::new(proxy_bypass)
-                .encode_wide()
-                .chain(once(0))
-                .collect();
-
-            // 创建选项数组
-            let mut options: [InternetPerConnOptionW; 3] = mem::zeroed();
 
-            // 选项 1: 设置代理标志（启用直连和代理）
-            options[0].dw_option = INTERNET_PER_CONN_FLAGS;
-            options[0].value.dw_value = PROXY_TYPE_DIRECT | PROXY_TYPE_PROXY;
-
-            // 选项 2: 设置代理服务器地址
-            options[1].dw_option = INTERNET_PER_CONN_PROXY_SERVER;
-            options[1].value.psz_value = proxy_server_wide.as_ptr() as *mut _;
-
-            // 选项 3: 设置代理绕过列表
-            options[2].dw_option = INTERNET_PER_CONN_PROXY_BYPASS;
-            options[2].value.psz_value = proxy_bypass_wide.as_ptr() as *mut _;
+            // 创建选项数组：首项为代理标志，其后为字符串选项
+            let mut options: Vec<InternetPerConnOptionW> = Vec::with_capacity(values.len() + 1);
+            let mut flags_option: InternetPerConnOptionW = mem::zeroed();
+            flags_option.dw_option = INTERNET_PER_CONN_FLAGS;
+            flags_option.value.dw_value = flags;
+            options.push(flags_option);
+
+            for ((option, _), wide) in values.iter().zip(&wide_values) {
+                let mut string_option: InternetPerConnOptionW = mem::zeroed();
+                string_option.dw_option = *option;
+                string_option.value.psz_value = wide.as_ptr() as *mut _;
+                options.push(string_option);
+            }
 
             // 创建选项列表
             let mut option_list = InternetPerConnOptionListW {
                 dw_size: mem::size_of::<InternetPerConnOptionListW>() as u32,
                 psz_connection: ptr::null_mut(), // NULL = LAN connection
-                dw_option_count: 3,
+                dw_option_count: options.len() as u32,
                 dw_option_error: 0,
                 p_options: options.as_mut_ptr(),
             };
 
-            // 调用 InternetSetOptionW 设置代理
+            // 调用 InternetSetOptionW 写入设置
             let result = InternetSetOptionW(
                 ptr::null_mut(),
                 INTERNET_OPTION_PER_CONNECTION_OPTION,
@@ -533,43 +1157,67 @@ impl PlatformOps for MacOSPlatform {
         }
     }
 
-    fn set_system_proxy(&self, http_port: u16, socks_port: u16) -> crate::V8RayResult<()> {
+    fn set_system_proxy_with_protocols(
+        &self,
+        http_port: u16,
+        socks_port: u16,
+        protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
+        protocols.validate()?;
+        tracing::info!(
+            "Setting macOS system proxy: HTTP={}, SOCKS={}, protocols={:?}",
+            http_port,
+            socks_port,
+            protocols
+        );
+
+        // Get active network services
+        let services = Self::get_network_services()?;
+
+        for service in services {
+            tracing::info!("Setting proxy for network service: {}", service);
+
+            for (protocol, port) in protocols.ports(http_port, socks_port) {
+                Self::set_service_proxy(&service, protocol, port)?;
+            }
+        }
+
+        tracing::info!("macOS system proxy set successfully");
+        Ok(())
+    }
+
+    fn set_system_proxy_pac(&self, pac_url: &str) -> crate::V8RayResult<()> {
         use std::process::Command;
 
-        tracing::info!("Setting macOS system proxy: SOCKS={}", socks_port);
+        tracing::info!("Setting macOS system proxy PAC: {}", pac_url);
 
-        // Get active network services
         let services = Self::get_network_services()?;
 
         for service in services {
-            tracing::info!("Setting SOCKS proxy for network service: {}", service);
+            tracing::info!("Setting PAC for network service: {}", service);
+
+            // 固定的代理优先于 PAC，先关闭
+            for (protocol, _) in SystemProxyProtocols::default().ports(0, 0) {
+                Self::set_service_proxy(&service, protocol, None)?;
+            }
 
-            // Set SOCKS proxy
             let output = Command::new("networksetup")
-                .args([
-                    "-setsocksfirewallproxy",
-                    &service,
-                    "127.0.0.1",
-                    &socks_port.to_string(),
-                ])
+                .args(["-setautoproxyurl", &service, pac_url])
                 .output()
                 .map_err(|e| {
-                    crate::error::PlatformError::SystemProxy(format!(
-                        "Failed to set SOCKS proxy: {}",
-                        e
-                    ))
+                    crate::error::PlatformError::SystemProxy(format!("Failed to set PAC: {}", e))
                 })?;
 
             if !output.status.success() {
                 tracing::warn!(
-                    "Failed to set SOCKS proxy for {}: {}",
+                    "Failed to set PAC for {}: {}",
                     service,
                     String::from_utf8_lossy(&output.stderr)
                 );
             }
         }
 
-        tracing::info!("macOS system proxy (SOCKS) set successfully");
+        tracing::info!("macOS system proxy (PAC) set successfully");
         Ok(())
     }
 
@@ -582,16 +1230,21 @@ impl PlatformOps for MacOSPlatform {
         let services = Self::get_network_services()?;
 
         for service in services {
-            tracing::info!("Clearing SOCKS proxy for network service: {}", service);
+            tracing::info!("Clearing proxy for network service: {}", service);
+
+            // Clear HTTP, HTTPS and SOCKS proxies
+            for (protocol, _) in SystemProxyProtocols::default().ports(0, 0) {
+                Self::set_service_proxy(&service, protocol, None).ok();
+            }
 
-            // Clear SOCKS proxy
+            // Clear PAC
             Command::new("networksetup")
-                .args(["-setsocksfirewallproxystate", &service, "off"])
+                .args(["-setautoproxystate", &service, "off"])
                 .output()
                 .ok();
         }
 
-        tracing::info!("macOS system proxy (SOCKS) cleared successfully");
+        tracing::info!("macOS system proxy cleared successfully");
         Ok(())
     }
 
@@ -602,21 +1255,28 @@ impl PlatformOps for MacOSPlatform {
         let services = Self::get_network_services()?;
 
         for service in services {
-            // Check HTTP proxy state
-            let output = Command::new("networksetup")
-                .args(["-getwebproxy", &service])
-                .output()
-                .map_err(|e| {
-                    crate::error::PlatformError::SystemProxy(format!(
-                        "Failed to get HTTP proxy state: {}",
-                        e
-                    ))
-                })?;
-
-            if output.status.success() {
-                let output_str = String::from_utf8_lossy(&output.stdout);
-                if output_str.contains("Enabled: Yes") {
-                    return Ok(true);
+            // Check HTTP, HTTPS, SOCKS and PAC states
+            for query in [
+                "-getwebproxy",
+                "-getsecurewebproxy",
+                "-getsocksfirewallproxy",
+                "-getautoproxyurl",
+            ] {
+                let output = Command::new("networksetup")
+                    .args([query, &service])
+                    .output()
+                    .map_err(|e| {
+                        crate::error::PlatformError::SystemProxy(format!(
+                            "Failed to get proxy state: {}",
+                            e
+                        ))
+                    })?;
+
+                if output.status.success() {
+                    let output_str = String::from_utf8_lossy(&output.stdout);
+                    if output_str.contains("Enabled: Yes") {
+                        return Ok(true);
+                    }
                 }
             }
         }
@@ -624,54 +1284,301 @@ impl PlatformOps for MacOSPlatform {
         Ok(false)
     }
 
-    fn enable_auto_start(&self) -> crate::V8RayResult<()> {
-        // TODO: Implement macOS auto start
-        tracing::info!("Enabling macOS auto start");
+    fn snapshot_system_proxy(&self) -> crate::V8RayResult<Option<SystemProxySnapshot>> {
+        let mut services = Vec::new();
+        for service in Self::get_network_services()? {
+            let mut proxies = Vec::new();
+            for kind in MAC_PROXY_KINDS {
+                let query = format!("-get{}", kind);
+                let info = parse_networksetup_info(&Self::networksetup(&[&query, &service])?);
+                proxies.push(MacProxySetting {
+                    kind: kind.to_string(),
+                    enabled: info.get("Enabled").is_some_and(|v| v == "Yes"),
+                    server: info.get("Server").cloned().unwrap_or_default(),
+                    port: info
+                        .get("Port")
+                        .and_then(|p| p.parse().ok())
+                        .unwrap_or_default(),
+                });
+            }
+
+            let pac =
+                parse_networksetup_info(&Self::networksetup(&["-getautoproxyurl", &service])?);
+            let pac_url = pac
+                .get("URL")
+                .filter(|url| url.as_str() != "(null)")
+                .cloned()
+                .unwrap_or_default();
+            services.push(MacProxyServiceSnapshot {
+                service,
+                proxies,
+                pac_url,
+                pac_enabled: pac.get("Enabled").is_some_and(|v| v == "Yes"),
+            });
+        }
+        Ok(Some(SystemProxySnapshot::Macos { services }))
+    }
+
+    fn restore_system_proxy(&self, snapshot: &SystemProxySnapshot) -> crate::V8RayResult<()> {
+        let SystemProxySnapshot::Macos { services } = snapshot else {
+            return self.clear_system_proxy();
+        };
+        tracing::info!("Restoring previous macOS system proxy settings");
+
+        for saved in services {
+            let service = saved.service.as_str();
+            let mut commands: Vec<Vec<String>> = Vec::new();
+            for proxy in &saved.proxies {
+                // Setting the server also enables the proxy, so the state goes last
+                if !proxy.server.is_empty() {
+                    commands.push(tun::cmd(&[
+                        format!("-set{}", proxy.kind).as_str(),
+                        service,
+                        &proxy.server,
+                        &proxy.port.to_string(),
+                    ]));
+                }
+                commands.push(tun::cmd(&[
+                    format!("-set{}state", proxy.kind).as_str(),
+                    service,
+                    if proxy.enabled { "on" } else { "off" },
+                ]));
+            }
+            if !saved.pac_url.is_empty() {
+                commands.push(tun::cmd(&["-setautoproxyurl", service, &saved.pac_url]));
+            }
+            commands.push(tun::cmd(&[
+                "-setautoproxystate",
+                service,
+                if saved.pac_enabled { "on" } else { "off" },
+            ]));
+
+            for args in commands {
+                let args: Vec<&str> = args.iter().map(String::as_str).collect();
+                if let Err(e) = Self::networksetup(&args) {
+                    tracing::warn!("Failed to restore proxy for {}: {}", service, e);
+                }
+            }
+        }
         Ok(())
     }
 
+    fn enable_auto_start(&self) -> crate::V8RayResult<()> {
+        self.install_launch_agent(&LaunchAgentOptions::default())
+    }
+
     fn disable_auto_start(&self) -> crate::V8RayResult<()> {
-        // TODO: Implement macOS auto start disable
         tracing::info!("Disabling macOS auto start");
-        Ok(())
+
+        // 卸载已加载的任务（未加载时忽略错误）
+        let _ = Self::launchctl(&["bootout", &Self::launch_agent_target()]);
+
+        let path = Self::launch_agent_path()?;
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(crate::error::PlatformError::Command(format!(
+                "Failed to remove {}: {}",
+                path.display(),
+                e
+            ))
+            .into()),
+        }
     }
 
     fn is_auto_start_enabled(&self) -> crate::V8RayResult<bool> {
-        // TODO: Implement macOS auto start check
-        Ok(false)
+        let exe = std::env::current_exe().map_err(|e| {
+            crate::error::PlatformError::Command(format!("Failed to locate executable: {}", e))
+        })?;
+
+        // 安装位置变化后旧的 plist 已失效
+        let installed = std::fs::read_to_string(Self::launch_agent_path()?)
+            .map(|plist| plist.contains(&exe.display().to_string()))
+            .unwrap_or(false);
+        if !installed {
+            return Ok(false);
+        }
+
+        // 用户可能在系统设置的登录项中关闭了它
+        let domain = format!("gui/{}", unsafe { libc::getuid() });
+        let disabled = Self::launchctl(&["print-disabled", &domain])
+            .map(|output| launchctl_is_disabled(&output, LAUNCH_AGENT_LABEL))
+            .unwrap_or(false);
+        Ok(!disabled)
     }
 }
 
 #[cfg(target_os = "macos")]
 impl MacOSPlatform {
-    fn get_network_services() -> crate::V8RayResult<Vec<String>> {
-        use std::process::Command;
+    /// Write the LaunchAgent plist and enable it in launchd
+    ///
+    /// The agent is not bootstrapped right away: with `RunAtLoad` launchd
+    /// would start a second instance immediately. It is loaded at the next
+    /// login.
+    pub fn install_launch_agent(&self, options: &LaunchAgentOptions) -> crate::V8RayResult<()> {
+        let exe = std::env::current_exe().map_err(|e| {
+            crate::error::PlatformError::Command(format!("Failed to locate executable: {}", e))
+        })?;
+        let path = Self::launch_agent_path()?;
+        tracing::info!("Enabling macOS auto start: {}", path.display());
+
+        let plist = launch_agent_plist(LAUNCH_AGENT_LABEL, &exe, options);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| {
+                crate::error::PlatformError::Command(format!(
+                    "Failed to create {}: {}",
+                    parent.display(),
+                    e
+                ))
+            })?;
+        }
+        std::fs::write(&path, plist).map_err(|e| {
+            crate::error::PlatformError::Command(format!(
+                "Failed to write {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        // 替换已加载的旧版本，并清除此前的禁用标记
+        let target = Self::launch_agent_target();
+        let _ = Self::launchctl(&["bootout", &target]);
+        Self::launchctl(&["enable", &target])?;
+        Ok(())
+    }
+
+    /// ~/Library/LaunchAgents/<label>.plist
+    fn launch_agent_path() -> crate::V8RayResult<std::path::PathBuf> {
+        let home = std::env::var("HOME")
+            .map_err(|_| crate::error::PlatformError::Command("HOME is not set".to_string()))?;
+        Ok(std::path::Path::new(&home)
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", LAUNCH_AGENT_LABEL)))
+    }
 
-        let output = Command::new("networksetup")
-            .arg("-listallnetworkservices")
+    /// launchd service target of the agent in the user's GUI domain
+    fn launch_agent_target() -> String {
+        format!("gui/{}/{}", unsafe { libc::getuid() }, LAUNCH_AGENT_LABEL)
+    }
+
+    /// Run launchctl, returning its stdout
+    fn launchctl(args: &[&str]) -> crate::V8RayResult<String> {
+        let output = std::process::Command::new("launchctl")
+            .args(args)
             .output()
             .map_err(|e| {
-                crate::error::PlatformError::SystemProxy(format!(
-                    "Failed to list network services: {}",
-                    e
-                ))
+                crate::error::PlatformError::Command(format!("Failed to run launchctl: {}", e))
             })?;
+        if !output.status.success() {
+            return Err(crate::error::PlatformError::Command(format!(
+                "launchctl {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
 
+/// networksetup proxy kinds of the web, secure web and SOCKS proxies
+#[cfg(target_os = "macos")]
+const MAC_PROXY_KINDS: [&str; 3] = ["webproxy", "securewebproxy", "socksfirewallproxy"];
+
+#[cfg(target_os = "macos")]
+impl MacOSPlatform {
+    /// 运行 networksetup 命令，返回标准输出
+    fn networksetup(args: &[&str]) -> crate::V8RayResult<String> {
+        let output = std::process::Command::new("networksetup")
+            .args(args)
+            .output()
+            .map_err(|e| crate::error::PlatformError::SystemProxy(e.to_string()))?;
         if !output.status.success() {
-            return Err(crate::error::PlatformError::SystemProxy(
-                "Failed to get network services".to_string(),
-            )
+            return Err(crate::error::PlatformError::SystemProxy(format!(
+                "networksetup {} failed: {}",
+                args.first().unwrap_or(&""),
+                String::from_utf8_lossy(&output.stderr)
+            ))
             .into());
         }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
 
-        let output_str = String::from_utf8_lossy(&output.stdout);
-        let services: Vec<String> = output_str
-            .lines()
-            .skip(1) // Skip the first line (header)
-            .filter(|line| !line.starts_with('*')) // Skip disabled services
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect();
+    /// 为网络服务设置指定协议的代理，`port` 为 `None` 时关闭
+    fn set_service_proxy(
+        service: &str,
+        protocol: &str,
+        port: Option<u16>,
+    ) -> crate::V8RayResult<()> {
+        use std::process::Command;
+
+        let kind = match protocol {
+            "http" => "webproxy",
+            "https" => "securewebproxy",
+            _ => "socksfirewallproxy",
+        };
+        let output = match port {
+            Some(port) => Command::new("networksetup")
+                .args([
+                    format!("-set{}", kind).as_str(),
+                    service,
+                    "127.0.0.1",
+                    &port.to_string(),
+                ])
+                .output(),
+            None => Command::new("networksetup")
+                .args([format!("-set{}state", kind).as_str(), service, "off"])
+                .output(),
+        }
+        .map_err(|e| {
+            crate::error::PlatformError::SystemProxy(format!(
+                "Failed to set {} proxy: {}",
+                protocol, e
+            ))
+        })?;
+
+        if !output.status.success() {
+            tracing::warn!(
+                "Failed to set {} proxy for {}: {}",
+                protocol,
+                service,
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        Ok(())
+    }
+
+    /// List network services in service order
+    pub fn list_network_services() -> crate::V8RayResult<Vec<NetworkService>> {
+        let output = Self::networksetup(&["-listnetworkserviceorder"])?;
+        Ok(parse_network_service_order(&output))
+    }
+
+    /// BSD device carrying the default route
+    pub fn primary_device() -> Option<String> {
+        let output = std::process::Command::new("route")
+            .args(["-n", "get", "default"])
+            .output()
+            .ok()?;
+        parse_route_get_field(&String::from_utf8_lossy(&output.stdout), "interface")
+    }
+
+    /// Network services targeted by the configured [`NetworkServiceScope`]
+    fn get_network_services() -> crate::V8RayResult<Vec<String>> {
+        let scope = get_network_service_scope();
+        let primary = match scope {
+            NetworkServiceScope::Primary => Self::primary_device(),
+            _ => None,
+        };
+        let services =
+            select_network_services(&Self::list_network_services()?, &scope, primary.as_deref());
+        if services.is_empty() {
+            return Err(crate::error::PlatformError::SystemProxy(format!(
+                "No network service matches {:?}",
+                scope
+            ))
+            .into());
+        }
 
         tracing::debug!("Found network services: {:?}", services);
         Ok(services)
@@ -700,42 +1607,100 @@ impl PlatformOps for LinuxPlatform {
         }
     }
 
-    fn set_system_proxy(&self, _http_port: u16, socks_port: u16) -> crate::V8RayResult<()> {
-        tracing::info!("Setting Linux system proxy: SOCKS={}", socks_port);
+    fn set_system_proxy_with_protocols(
+        &self,
+        http_port: u16,
+        socks_port: u16,
+        protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
+        protocols.validate()?;
+        tracing::info!(
+            "Setting Linux system proxy: HTTP={}, SOCKS={}, protocols={:?}",
+            http_port,
+            socks_port,
+            protocols
+        );
 
-        let socks_proxy = format!("socks5://127.0.0.1:{}", socks_port);
+        // KDE keeps its own settings in kioslaverc, while GTK apps there
+        // still follow gsettings, so both are set
+        let kde_set = Self::is_kde()
+            && Self::set_kde_proxy(http_port, socks_port, protocols)
+                .map_err(|e| tracing::warn!("Failed to set KDE proxy settings: {}", e))
+                .is_ok();
 
         // Try to set proxy using gsettings (GNOME/Ubuntu)
-        let gsettings_result = Self::set_gsettings_socks_proxy(&socks_proxy);
+        let gsettings_result = Self::set_gsettings_proxy(http_port, socks_port, protocols);
 
         if gsettings_result.is_ok() {
             tracing::info!("Successfully set system proxy using gsettings");
+            if let Some(desktop) =
+                os_info::DesktopEnvironment::current().filter(|d| !kde_set && !d.uses_gsettings())
+            {
+                tracing::warn!(
+                    "{} does not read its proxy settings from gsettings; apps following the desktop settings may not use the proxy",
+                    desktop.name()
+                );
+            }
+            return Ok(());
+        }
+        if kde_set {
+            tracing::info!("Successfully set system proxy in kioslaverc");
             return Ok(());
         }
 
         // Fallback: Set environment variables
         tracing::warn!("gsettings not available, using environment variables");
-        std::env::set_var("all_proxy", &socks_proxy);
-        std::env::set_var("ALL_PROXY", &socks_proxy);
+        Self::clear_proxy_env();
+        for (protocol, port) in protocols.ports(http_port, socks_port) {
+            let Some(port) = port else {
+                continue;
+            };
+            let (name, url) = match protocol {
+                "socks" => ("all_proxy", format!("socks5://127.0.0.1:{}", port)),
+                "https" => ("https_proxy", format!("http://127.0.0.1:{}", port)),
+                _ => ("http_proxy", format!("http://127.0.0.1:{}", port)),
+            };
+            std::env::set_var(name, &url);
+            std::env::set_var(name.to_uppercase(), &url);
+        }
 
         Ok(())
     }
 
+    fn set_system_proxy_pac(&self, pac_url: &str) -> crate::V8RayResult<()> {
+        tracing::info!("Setting Linux system proxy PAC: {}", pac_url);
+
+        // 环境变量无法表达 PAC，仅支持 gsettings 和 KDE
+        let kde_result = if Self::is_kde() {
+            Some(Self::set_kde_pac(pac_url))
+        } else {
+            None
+        };
+        match (Self::set_gsettings_pac(pac_url), kde_result) {
+            (Ok(()), _) | (Err(_), Some(Ok(()))) => Ok(()),
+            (Err(e), _) => Err(e),
+        }
+    }
+
     fn clear_system_proxy(&self) -> crate::V8RayResult<()> {
         tracing::info!("Clearing Linux system proxy");
 
+        let kde_cleared = Self::is_kde()
+            && Self::write_kde_proxy(&[("ProxyType", KDE_PROXY_TYPE_NONE)])
+                .map_err(|e| tracing::warn!("Failed to clear KDE proxy settings: {}", e))
+                .is_ok();
+
         // Try to clear proxy using gsettings
         let gsettings_result = Self::clear_gsettings_proxy();
 
-        if gsettings_result.is_ok() {
-            tracing::info!("Successfully cleared system proxy using gsettings");
+        if gsettings_result.is_ok() || kde_cleared {
+            tracing::info!("Successfully cleared system proxy");
             return Ok(());
         }
 
         // Fallback: Clear environment variables
         tracing::warn!("gsettings not available, clearing environment variables");
-        std::env::remove_var("all_proxy");
-        std::env::remove_var("ALL_PROXY");
+        Self::clear_proxy_env();
 
         Ok(())
     }
@@ -743,6 +1708,12 @@ impl PlatformOps for LinuxPlatform {
     fn is_system_proxy_set(&self) -> crate::V8RayResult<bool> {
         use std::process::Command;
 
+        if Self::is_kde() {
+            if let Ok(proxy_type) = Self::read_kde_proxy("ProxyType") {
+                return Ok(proxy_type != KDE_PROXY_TYPE_NONE && !proxy_type.is_empty());
+            }
+        }
+
         // Check gsettings first
         let output = Command::new("gsettings")
             .args(["get", "org.gnome.system.proxy", "mode"])
@@ -751,7 +1722,7 @@ impl PlatformOps for LinuxPlatform {
         if let Ok(output) = output {
             if output.status.success() {
                 let mode = String::from_utf8_lossy(&output.stdout);
-                return Ok(mode.trim().contains("manual"));
+                return Ok(mode.trim().contains("manual") || mode.trim().contains("auto"));
             }
         }
 
@@ -759,6 +1730,60 @@ impl PlatformOps for LinuxPlatform {
         Ok(std::env::var("http_proxy").is_ok() || std::env::var("HTTP_PROXY").is_ok())
     }
 
+    fn snapshot_system_proxy(&self) -> crate::V8RayResult<Option<SystemProxySnapshot>> {
+        let mut settings = Vec::new();
+        for (schema, key) in GSETTINGS_PROXY_KEYS {
+            match Self::run_gsettings(&["get", schema, key]) {
+                Ok(value) => settings.push((
+                    schema.to_string(),
+                    key.to_string(),
+                    value.trim().to_string(),
+                )),
+                // 环境变量仅对当前进程有效，无需恢复
+                Err(e) => {
+                    tracing::debug!("gsettings not available, nothing to snapshot: {}", e);
+                    settings.clear();
+                    break;
+                }
+            }
+        }
+
+        let mut kde = Vec::new();
+        if Self::is_kde() {
+            for key in KDE_PROXY_KEYS {
+                match Self::read_kde_proxy(key) {
+                    Ok(value) => kde.push((key.to_string(), value)),
+                    Err(e) => {
+                        tracing::debug!("kreadconfig not available: {}", e);
+                        kde.clear();
+                        break;
+                    }
+                }
+            }
+        }
+
+        if settings.is_empty() && kde.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SystemProxySnapshot::Linux { settings, kde }))
+    }
+
+    fn restore_system_proxy(&self, snapshot: &SystemProxySnapshot) -> crate::V8RayResult<()> {
+        let SystemProxySnapshot::Linux { settings, kde } = snapshot else {
+            return self.clear_system_proxy();
+        };
+        tracing::info!("Restoring previous Linux system proxy settings");
+
+        for (schema, key, value) in settings {
+            Self::run_gsettings(&["set", schema, key, value])?;
+        }
+        let kde: Vec<_> = kde.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        if !kde.is_empty() {
+            Self::write_kde_proxy(&kde)?;
+        }
+        Ok(())
+    }
+
     fn enable_auto_start(&self) -> crate::V8RayResult<()> {
         // TODO: Implement Linux auto start
         tracing::info!("Enabling Linux auto start");
@@ -777,64 +1802,239 @@ impl PlatformOps for LinuxPlatform {
     }
 }
 
+/// GNOME proxy settings changed by the app; `mode` comes last so it is
+/// restored after the values it enables
+#[cfg(target_os = "linux")]
+const GSETTINGS_PROXY_KEYS: [(&str, &str); 8] = [
+    ("org.gnome.system.proxy", "autoconfig-url"),
+    ("org.gnome.system.proxy.http", "host"),
+    ("org.gnome.system.proxy.http", "port"),
+    ("org.gnome.system.proxy.https", "host"),
+    ("org.gnome.system.proxy.https", "port"),
+    ("org.gnome.system.proxy.socks", "host"),
+    ("org.gnome.system.proxy.socks", "port"),
+    ("org.gnome.system.proxy", "mode"),
+];
+
+/// Group of KDE's `kioslaverc` holding the proxy settings
+#[cfg(target_os = "linux")]
+const KDE_PROXY_GROUP: &str = "Proxy Settings";
+
+/// KDE proxy settings changed by the app; `ProxyType` comes last so it is
+/// restored after the values it enables
+#[cfg(target_os = "linux")]
+const KDE_PROXY_KEYS: [&str; 5] = [
+    "Proxy Config Script",
+    "httpProxy",
+    "httpsProxy",
+    "socksProxy",
+    "ProxyType",
+];
+
+/// KDE `ProxyType` without a proxy
+#[cfg(target_os = "linux")]
+const KDE_PROXY_TYPE_NONE: &str = "0";
+
+/// KDE `ProxyType` with manually set proxies
+#[cfg(target_os = "linux")]
+const KDE_PROXY_TYPE_MANUAL: &str = "1";
+
+/// KDE `ProxyType` with a PAC script
+#[cfg(target_os = "linux")]
+const KDE_PROXY_TYPE_PAC: &str = "2";
+
+/// `kioslaverc` entries for proxies on the enabled protocols, disabled
+/// protocols cleared; KDE separates host and port with a space
+#[cfg(target_os = "linux")]
+fn kde_proxy_entries(
+    http_port: u16,
+    socks_port: u16,
+    protocols: SystemProxyProtocols,
+) -> Vec<(String, String)> {
+    let mut entries: Vec<_> = protocols
+        .ports(http_port, socks_port)
+        .into_iter()
+        .map(|(protocol, port)| {
+            let scheme = if protocol == "socks" { "socks" } else { "http" };
+            let value = port
+                .map(|port| format!("{}://127.0.0.1 {}", scheme, port))
+                .unwrap_or_default();
+            (format!("{}Proxy", protocol), value)
+        })
+        .collect();
+    entries.push(("ProxyType".to_string(), KDE_PROXY_TYPE_MANUAL.to_string()));
+    entries
+}
+
 #[cfg(target_os = "linux")]
 impl LinuxPlatform {
-    fn set_gsettings_socks_proxy(socks_proxy: &str) -> crate::V8RayResult<()> {
+    /// 运行桌面设置命令，通过 sudo 运行时以实际用户的身份运行
+    fn user_command(program: &str, args: &[&str]) -> std::io::Result<std::process::Output> {
         use std::process::Command;
 
         // 获取实际用户（如果是通过 sudo 运行的）
-        let actual_user = std::env::var("SUDO_USER").ok();
+        match std::env::var("SUDO_USER") {
+            Ok(user) => Command::new("sudo")
+                .arg("-u")
+                .arg(user)
+                .arg(program)
+                .args(args)
+                .output(),
+            Err(_) => Command::new(program).args(args).output(),
+        }
+    }
 
-        // 创建 gsettings 命令的辅助函数
-        let run_gsettings = |args: &[&str]| -> crate::V8RayResult<()> {
-            let output = if let Some(ref user) = actual_user {
-                // 如果是通过 sudo 运行的，使用实际用户的权限运行 gsettings
-                Command::new("sudo")
-                    .arg("-u")
-                    .arg(user)
-                    .arg("gsettings")
-                    .args(args)
-                    .output()
-            } else {
-                // 否则直接运行 gsettings
-                Command::new("gsettings").args(args).output()
-            }
+    /// 运行 gsettings 命令，返回标准输出
+    fn run_gsettings(args: &[&str]) -> crate::V8RayResult<String> {
+        let output = Self::user_command("gsettings", args)
             .map_err(|e| crate::error::PlatformError::SystemProxy(e.to_string()))?;
 
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(crate::error::PlatformError::SystemProxy(format!(
-                    "gsettings command failed: {}",
-                    stderr
-                ))
-                .into());
-            }
-            Ok(())
-        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(crate::error::PlatformError::SystemProxy(format!(
+                "gsettings command failed: {}",
+                stderr
+            ))
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
 
+    /// 设置各协议的 gsettings 代理，未启用的协议清空地址
+    fn set_gsettings_proxy(
+        http_port: u16,
+        socks_port: u16,
+        protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
         // Set proxy mode to manual
-        run_gsettings(&["set", "org.gnome.system.proxy", "mode", "manual"])?;
+        Self::run_gsettings(&["set", "org.gnome.system.proxy", "mode", "manual"])?;
         tracing::info!("Successfully set proxy mode to manual");
 
-        // Extract host and port from socks_proxy
-        let socks_url = socks_proxy
-            .trim_start_matches("socks5://")
-            .trim_start_matches("socks://");
-        let socks_parts: Vec<&str> = socks_url.split(':').collect();
-        if socks_parts.len() != 2 {
-            return Err(crate::error::PlatformError::SystemProxy(
-                "Invalid SOCKS proxy URL format".to_string(),
-            )
-            .into());
+        for (protocol, port) in protocols.ports(http_port, socks_port) {
+            let schema = format!("org.gnome.system.proxy.{}", protocol);
+            let (host, port) = match port {
+                Some(port) => ("127.0.0.1", port),
+                None => ("", 0),
+            };
+            Self::run_gsettings(&["set", &schema, "host", host])?;
+            Self::run_gsettings(&["set", &schema, "port", &port.to_string()])?;
+        }
+
+        tracing::info!("Successfully set proxy settings");
+        Ok(())
+    }
+
+    /// 是否为 KDE Plasma 桌面
+    fn is_kde() -> bool {
+        matches!(
+            os_info::DesktopEnvironment::current(),
+            Some(os_info::DesktopEnvironment::Kde)
+        )
+    }
+
+    /// 运行 Plasma 6 或 Plasma 5 版本的 kwriteconfig/kreadconfig，返回标准输出
+    fn run_kconfig(tool: &str, args: &[&str]) -> crate::V8RayResult<String> {
+        let mut last_error = String::new();
+        for version in ["6", "5"] {
+            let program = format!("{}{}", tool, version);
+            match Self::user_command(&program, args) {
+                Ok(output) if output.status.success() => {
+                    return Ok(String::from_utf8_lossy(&output.stdout).to_string());
+                }
+                Ok(output) => {
+                    last_error = format!(
+                        "{} failed: {}",
+                        program,
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+                Err(e) => last_error = format!("{}: {}", program, e),
+            }
+        }
+        Err(crate::error::PlatformError::SystemProxy(last_error).into())
+    }
+
+    /// 读取 kioslaverc 中的代理设置
+    fn read_kde_proxy(key: &str) -> crate::V8RayResult<String> {
+        let value = Self::run_kconfig(
+            "kreadconfig",
+            &[
+                "--file",
+                "kioslaverc",
+                "--group",
+                KDE_PROXY_GROUP,
+                "--key",
+                key,
+            ],
+        )?;
+        Ok(value.trim_end_matches('\n').to_string())
+    }
+
+    /// 写入 kioslaverc 中的代理设置，并通知 KIO 重新读取
+    fn write_kde_proxy(entries: &[(&str, &str)]) -> crate::V8RayResult<()> {
+        for (key, value) in entries {
+            Self::run_kconfig(
+                "kwriteconfig",
+                &[
+                    "--file",
+                    "kioslaverc",
+                    "--group",
+                    KDE_PROXY_GROUP,
+                    "--key",
+                    key,
+                    value,
+                ],
+            )?;
+        }
+        // 已运行的应用收到信号后立即使用新设置，失败时在下次启动时生效
+        if let Err(e) = Self::user_command(
+            "dbus-send",
+            &[
+                "--type=signal",
+                "/KIO/Scheduler",
+                "org.kde.KIO.Scheduler.reparseSlaveConfiguration",
+                "string:",
+            ],
+        ) {
+            tracing::debug!("Failed to notify KIO of the proxy change: {}", e);
         }
-        let socks_host = socks_parts[0];
-        let socks_port = socks_parts[1];
+        Ok(())
+    }
+
+    /// 设置 KDE 各协议的代理
+    fn set_kde_proxy(
+        http_port: u16,
+        socks_port: u16,
+        protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
+        let entries = kde_proxy_entries(http_port, socks_port, protocols);
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        Self::write_kde_proxy(&entries)
+    }
+
+    /// 设置 KDE 的 PAC 地址
+    fn set_kde_pac(pac_url: &str) -> crate::V8RayResult<()> {
+        Self::write_kde_proxy(&[
+            ("Proxy Config Script", pac_url),
+            ("ProxyType", KDE_PROXY_TYPE_PAC),
+        ])
+    }
 
-        // Set SOCKS proxy
-        run_gsettings(&["set", "org.gnome.system.proxy.socks", "host", socks_host])?;
-        run_gsettings(&["set", "org.gnome.system.proxy.socks", "port", socks_port])?;
+    /// 清除代理环境变量
+    fn clear_proxy_env() {
+        for name in ["http_proxy", "https_proxy", "all_proxy"] {
+            std::env::remove_var(name);
+            std::env::remove_var(name.to_uppercase());
+        }
+    }
 
-        tracing::info!("Successfully set SOCKS proxy settings");
+    fn set_gsettings_pac(pac_url: &str) -> crate::V8RayResult<()> {
+        Self::run_gsettings(&["set", "org.gnome.system.proxy", "autoconfig-url", pac_url])?;
+        Self::run_gsettings(&["set", "org.gnome.system.proxy", "mode", "auto"])?;
+        tracing::info!("Successfully set proxy mode to auto");
         Ok(())
     }
 
@@ -886,7 +2086,19 @@ impl PlatformOps for IOSPlatform {
         Ok(false)
     }
 
-    fn set_system_proxy(&self, _http_port: u16, _socks_port: u16) -> crate::V8RayResult<()> {
+    fn set_system_proxy_with_protocols(
+        &self,
+        _http_port: u16,
+        _socks_port: u16,
+        _protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
+        Err(crate::error::PlatformError::SystemProxy(
+            "iOS does not support system proxy. Use VPN mode instead.".to_string(),
+        )
+        .into())
+    }
+
+    fn set_system_proxy_pac(&self, _pac_url: &str) -> crate::V8RayResult<()> {
         Err(crate::error::PlatformError::SystemProxy(
             "iOS does not support system proxy. Use VPN mode instead.".to_string(),
         )
@@ -935,7 +2147,19 @@ impl PlatformOps for AndroidPlatform {
         Ok(false)
     }
 
-    fn set_system_proxy(&self, _http_port: u16, _socks_port: u16) -> crate::V8RayResult<()> {
+    fn set_system_proxy_with_protocols(
+        &self,
+        _http_port: u16,
+        _socks_port: u16,
+        _protocols: SystemProxyProtocols,
+    ) -> crate::V8RayResult<()> {
+        Err(crate::error::PlatformError::SystemProxy(
+            "Android does not support system proxy. Use VPN mode instead.".to_string(),
+        )
+        .into())
+    }
+
+    fn set_system_proxy_pac(&self, _pac_url: &str) -> crate::V8RayResult<()> {
         Err(crate::error::PlatformError::SystemProxy(
             "Android does not support system proxy. Use VPN mode instead.".to_string(),
         )
@@ -1010,6 +2234,42 @@ mod tests {
         assert!(!info.arch.is_empty());
     }
 
+    #[test]
+    fn test_auto_start_command() {
+        let exe = std::path::Path::new("C:\\Program Files\\V8Ray\\v8ray.exe");
+        assert_eq!(
+            auto_start_command(exe),
+            "\"C:\\Program Files\\V8Ray\\v8ray.exe\" --minimized"
+        );
+    }
+
+    #[test]
+    fn test_launch_agent_plist() {
+        let exe = std::path::Path::new("/Applications/V8Ray & Co.app/Contents/MacOS/v8ray");
+        let plist = launch_agent_plist(LAUNCH_AGENT_LABEL, exe, &LaunchAgentOptions::default());
+        assert!(plist.contains("<string>com.v8ray.v8rayApp</string>"));
+        assert!(plist
+            .contains("<string>/Applications/V8Ray &amp; Co.app/Contents/MacOS/v8ray</string>"));
+        assert!(plist.contains("<string>--minimized</string>"));
+        assert!(plist.contains("<key>RunAtLoad</key>\n\t<true/>"));
+        assert!(plist.contains("<key>KeepAlive</key>\n\t<false/>"));
+
+        let options = LaunchAgentOptions {
+            run_at_load: true,
+            keep_alive: true,
+        };
+        let plist = launch_agent_plist(LAUNCH_AGENT_LABEL, exe, &options);
+        assert!(plist.contains("<key>SuccessfulExit</key>"));
+    }
+
+    #[test]
+    fn test_launchctl_is_disabled() {
+        let output = "disabled services = {\n\t\"com.v8ray.v8rayApp\" => disabled\n\t\"com.other\" => enabled\n}\n";
+        assert!(launchctl_is_disabled(output, "com.v8ray.v8rayApp"));
+        assert!(!launchctl_is_disabled(output, "com.other"));
+        assert!(!launchctl_is_disabled(output, "com.missing"));
+    }
+
     #[test]
     fn test_platform_capabilities() {
         let info = get_platform_info();
@@ -1021,4 +2281,123 @@ mod tests {
                 || info.capabilities.auto_start
         );
     }
+
+    #[test]
+    fn test_system_proxy_protocols() {
+        let all = SystemProxyProtocols::default();
+        assert!(all.validate().is_ok());
+        assert_eq!(
+            all.ports(8080, 1080),
+            [
+                ("http", Some(8080)),
+                ("https", Some(8080)),
+                ("socks", Some(1080))
+            ]
+        );
+
+        let socks_only = SystemProxyProtocols {
+            http: false,
+            https: false,
+            socks: true,
+        };
+        assert_eq!(socks_only.ports(8080, 1080)[0], ("http", None));
+        assert_eq!(socks_only.ports(8080, 1080)[2], ("socks", Some(1080)));
+
+        let none = SystemProxyProtocols {
+            socks: false,
+            ..socks_only
+        };
+        assert!(none.validate().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_kde_proxy_entries() {
+        let socks_only = SystemProxyProtocols {
+            http: false,
+            https: false,
+            socks: true,
+        };
+        let entries = kde_proxy_entries(8080, 1080, socks_only);
+        let entry = |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(entry("httpProxy"), Some(""));
+        assert_eq!(entry("socksProxy"), Some("socks://127.0.0.1 1080"));
+        assert_eq!(
+            entries.last().unwrap(),
+            &("ProxyType".to_string(), "1".to_string())
+        );
+        assert_eq!(
+            kde_proxy_entries(8080, 1080, SystemProxyProtocols::default())[1].1,
+            "http://127.0.0.1 8080"
+        );
+    }
+
+    #[test]
+    fn test_parse_networksetup_info() {
+        let info = parse_networksetup_info(
+            "Enabled: Yes\nServer: proxy.corp.example\nPort: 3128\nAuthenticated Proxy Enabled: 0\n",
+        );
+        assert_eq!(info["Enabled"], "Yes");
+        assert_eq!(info["Server"], "proxy.corp.example");
+        assert_eq!(info["Port"], "3128");
+
+        // URLs contain colons after the key
+        let pac = parse_networksetup_info("URL: http://wpad.corp.example/proxy.pac\nEnabled: No\n");
+        assert_eq!(pac["URL"], "http://wpad.corp.example/proxy.pac");
+    }
+
+    #[test]
+    fn test_select_network_services() {
+        let services = parse_network_service_order(
+            "An asterisk (*) denotes that a network service is disabled.\n\
+             (1) Wi-Fi\n\
+             (Hardware Port: Wi-Fi, Device: en0)\n\
+             \n\
+             (*) Thunderbolt Ethernet\n\
+             (Hardware Port: Thunderbolt Ethernet Slot 1, Device: en5)\n\
+             \n\
+             (2) USB 10/100/1000 LAN\n\
+             (Hardware Port: USB 10/100/1000 LAN, Device: en7)\n\
+             \n\
+             (3) Corp VPN (Tunnel)\n\
+             (Hardware Port: com.wireguard.macos, Device: )\n",
+        );
+        assert_eq!(services.len(), 4);
+        assert_eq!(services[0].device, "en0");
+        assert!(!services[1].enabled);
+        assert_eq!(services[2].name, "USB 10/100/1000 LAN");
+        assert_eq!(services[3].name, "Corp VPN (Tunnel)");
+        assert!(services[3].is_vpn());
+        assert!(!services[0].is_vpn());
+
+        let active = select_network_services(&services, &NetworkServiceScope::Active, None);
+        assert_eq!(active, vec!["Wi-Fi", "USB 10/100/1000 LAN"]);
+
+        let primary =
+            select_network_services(&services, &NetworkServiceScope::Primary, Some("en7"));
+        assert_eq!(primary, vec!["USB 10/100/1000 LAN"]);
+        // Default route through the VPN falls back to the active services
+        let primary =
+            select_network_services(&services, &NetworkServiceScope::Primary, Some("utun3"));
+        assert_eq!(primary, active);
+
+        let selected = NetworkServiceScope::Selected(vec![
+            "Thunderbolt Ethernet".to_string(),
+            "Corp VPN (Tunnel)".to_string(),
+        ]);
+        assert_eq!(
+            select_network_services(&services, &selected, None),
+            vec!["Corp VPN (Tunnel)"]
+        );
+
+        assert_eq!(
+            parse_route_get_field("   route to: default\n  interface: en7\n", "interface"),
+            Some("en7".to_string())
+        );
+    }
 }
@@ -0,0 +1,299 @@
+//! Network namespace isolation (Linux)
+//!
+//! Per-app proxying without touching the host's routes: apps launched into a
+//! dedicated network namespace have a TUN interface as their only route,
+//! while everything else on the host keeps using the normal network.
+//!
+//! tun2socks runs in the host namespace and creates the interface there;
+//! the interface is then moved into the namespace. The process keeps
+//! serving it, so packets from the namespace reach the loopback SOCKS
+//! inbound without exposing it on any other address. Xray itself stays in
+//! the host namespace, so its outbound traffic cannot loop back into the
+//! tunnel.
+//!
+//! DNS inside the namespace is plain UDP to public resolvers, so the SOCKS
+//! inbound must have UDP enabled.
+
+use super::tun::{cmd, find_tun2socks, run_command, TunConfig};
+use crate::error::PlatformError;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::process::{Child, Command};
+
+/// Default namespace name
+pub const DEFAULT_NETNS_NAME: &str = "v8ray";
+
+/// Default TUN interface name inside the namespace
+pub const DEFAULT_NETNS_DEVICE: &str = "v8rayns0";
+
+/// Directory `ip netns exec` bind-mounts per-namespace `/etc` files from
+const NETNS_ETC_DIR: &str = "/etc/netns";
+
+/// How long to wait for tun2socks to create the interface
+const DEVICE_STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between interface readiness checks
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Namespace isolation configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetnsConfig {
+    /// Namespace name
+    pub namespace: String,
+    /// Interface served by tun2socks; `bypass_addresses` is unused since the
+    /// host routes are left alone
+    pub tun: TunConfig,
+}
+
+impl NetnsConfig {
+    /// Create a configuration forwarding to the SOCKS inbound on `socks_port`
+    pub fn new(socks_port: u16) -> Self {
+        Self {
+            namespace: DEFAULT_NETNS_NAME.to_string(),
+            tun: TunConfig::new(socks_port).with_name(DEFAULT_NETNS_DEVICE.to_string()),
+        }
+    }
+
+    /// Set the namespace name
+    pub fn with_namespace(mut self, namespace: String) -> Self {
+        self.namespace = namespace;
+        self
+    }
+
+    /// Set the tun2socks binary path
+    pub fn with_tun2socks_path(mut self, path: PathBuf) -> Self {
+        self.tun.tun2socks_path = Some(path);
+        self
+    }
+
+    /// Commands configuring the interface once it is inside the namespace
+    pub fn setup_commands(&self) -> Vec<Vec<String>> {
+        let ns = self.namespace.as_str();
+        let device = self.tun.name.as_str();
+        let address = format!("{}/{}", self.tun.address, self.tun.prefix_len);
+        let mtu = self.tun.mtu.to_string();
+
+        vec![
+            cmd(&["ip", "link", "set", device, "netns", ns]),
+            cmd(&["ip", "-n", ns, "link", "set", "lo", "up"]),
+            cmd(&["ip", "-n", ns, "addr", "add", &address, "dev", device]),
+            cmd(&["ip", "-n", ns, "link", "set", device, "mtu", &mtu, "up"]),
+            cmd(&["ip", "-n", ns, "route", "add", "default", "dev", device]),
+        ]
+    }
+
+    /// `resolv.conf` used inside the namespace
+    pub fn resolv_conf(&self) -> String {
+        self.tun
+            .dns_servers
+            .iter()
+            .map(|server| format!("nameserver {}\n", server))
+            .collect()
+    }
+
+    /// Directory holding the namespace's `/etc` overrides
+    fn etc_dir(&self) -> PathBuf {
+        Path::new(NETNS_ETC_DIR).join(&self.namespace)
+    }
+}
+
+/// Command line running `program` inside `namespace`
+///
+/// `ip netns exec` needs root; with `user` set, the program is run as that
+/// user again so it does not inherit root privileges.
+pub fn exec_command(
+    namespace: &str,
+    user: Option<&str>,
+    program: &str,
+    args: &[String],
+) -> Vec<String> {
+    let mut command = cmd(&["ip", "netns", "exec", namespace]);
+    if let Some(user) = user {
+        command.extend(cmd(&["runuser", "-u", user, "--"]));
+    }
+    command.push(program.to_string());
+    command.extend(args.iter().cloned());
+    command
+}
+
+/// Running namespace whose only route goes through the proxy
+pub struct NetnsIsolation {
+    /// Configuration the namespace was created with
+    config: NetnsConfig,
+    /// tun2socks process serving the namespace's interface
+    process: Option<Child>,
+}
+
+impl NetnsIsolation {
+    /// Create the namespace and route it through tun2socks
+    ///
+    /// Requires root. A namespace left over from an earlier run with the
+    /// same name is replaced.
+    pub async fn start(config: NetnsConfig) -> crate::V8RayResult<Self> {
+        if !super::get_platform().has_admin_privileges()? {
+            return Err(PlatformError::Permission(
+                "Network namespace isolation requires root privileges".to_string(),
+            )
+            .into());
+        }
+
+        let ns = config.namespace.clone();
+        let _ = run_command(&cmd(&["ip", "netns", "del", &ns])).await;
+        run_command(&cmd(&["ip", "netns", "add", &ns])).await?;
+
+        let mut isolation = Self {
+            config,
+            process: None,
+        };
+        if let Err(e) = isolation.configure().await {
+            isolation.stop().await;
+            return Err(e);
+        }
+
+        tracing::info!("Network namespace {} is up", ns);
+        Ok(isolation)
+    }
+
+    /// Start tun2socks, move its interface into the namespace and set up DNS
+    async fn configure(&mut self) -> crate::V8RayResult<()> {
+        let binary = match self.config.tun.tun2socks_path {
+            Some(ref path) => path.clone(),
+            None => find_tun2socks()?,
+        };
+        let process = Command::new(&binary)
+            .args(self.config.tun.tun2socks_args())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                PlatformError::VpnSetupFailed(format!("Failed to start tun2socks: {}", e))
+            })?;
+        self.process = Some(process);
+
+        let device = Path::new("/sys/class/net").join(&self.config.tun.name);
+        let deadline = tokio::time::Instant::now() + DEVICE_STARTUP_TIMEOUT;
+        while !device.exists() {
+            if tokio::time::Instant::now() >= deadline {
+                return Err(PlatformError::VpnSetupFailed(format!(
+                    "tun2socks did not create {}",
+                    self.config.tun.name
+                ))
+                .into());
+            }
+            tokio::time::sleep(DEVICE_POLL_INTERVAL).await;
+        }
+
+        for args in self.config.setup_commands() {
+            run_command(&args).await?;
+        }
+
+        let etc_dir = self.config.etc_dir();
+        let written = async {
+            tokio::fs::create_dir_all(&etc_dir).await?;
+            tokio::fs::write(etc_dir.join("resolv.conf"), self.config.resolv_conf()).await
+        };
+        written.await.map_err(|e| {
+            PlatformError::VpnSetupFailed(format!("Failed to write namespace DNS: {}", e)).into()
+        })
+    }
+
+    /// Namespace name
+    pub fn namespace(&self) -> &str {
+        &self.config.namespace
+    }
+
+    /// Configuration the namespace was created with
+    pub fn config(&self) -> &NetnsConfig {
+        &self.config
+    }
+
+    /// Launch `program` inside the namespace, returning its process ID
+    ///
+    /// When running under sudo, the program runs as the invoking user.
+    pub fn launch(&self, program: &str, args: &[String]) -> crate::V8RayResult<u32> {
+        let user = std::env::var("SUDO_USER").ok();
+        let command = exec_command(&self.config.namespace, user.as_deref(), program, args);
+        tracing::info!("Launching in namespace: {}", command.join(" "));
+
+        let mut child = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .map_err(|e| PlatformError::Command(format!("Failed to launch {}: {}", program, e)))?;
+
+        let pid = child.id();
+        // Reap the process when it exits
+        std::thread::spawn(move || child.wait());
+        Ok(pid)
+    }
+
+    /// Stop tun2socks and delete the namespace
+    ///
+    /// Apps still running inside lose network access.
+    pub async fn stop(mut self) {
+        tracing::info!("Removing network namespace {}", self.config.namespace);
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill().await;
+        }
+        if let Err(e) = run_command(&cmd(&["ip", "netns", "del", &self.config.namespace])).await {
+            tracing::warn!("Failed to delete network namespace: {}", e);
+        }
+        let _ = tokio::fs::remove_dir_all(self.config.etc_dir()).await;
+    }
+}
+
+impl Drop for NetnsIsolation {
+    fn drop(&mut self) {
+        // Already stopped
+        let Some(mut process) = self.process.take() else {
+            return;
+        };
+        let _ = process.start_kill();
+        let _ = std::process::Command::new("ip")
+            .args(["netns", "del", &self.config.namespace])
+            .output();
+        let _ = std::fs::remove_dir_all(self.config.etc_dir());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setup_commands() {
+        let config = NetnsConfig::new(10808);
+        let commands: Vec<String> = config
+            .setup_commands()
+            .iter()
+            .map(|c| c.join(" "))
+            .collect();
+
+        assert_eq!(commands[0], "ip link set v8rayns0 netns v8ray");
+        assert!(commands.contains(&"ip -n v8ray addr add 198.18.0.1/15 dev v8rayns0".to_string()));
+        assert_eq!(
+            commands.last().unwrap(),
+            "ip -n v8ray route add default dev v8rayns0"
+        );
+        assert_eq!(
+            config.resolv_conf(),
+            "nameserver 1.1.1.1\nnameserver 8.8.8.8\n"
+        );
+    }
+
+    #[test]
+    fn test_exec_command() {
+        let args = vec!["--new-window".to_string()];
+        assert_eq!(
+            exec_command("v8ray", Some("alice"), "firefox", &args).join(" "),
+            "ip netns exec v8ray runuser -u alice -- firefox --new-window"
+        );
+        assert_eq!(
+            exec_command("v8ray", None, "curl", &[]).join(" "),
+            "ip netns exec v8ray curl"
+        );
+    }
+}
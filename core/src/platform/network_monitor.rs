@@ -0,0 +1,604 @@
+//! Network change detection
+//!
+//! Switching Wi-Fi networks or plugging in a cable can reset the system
+//! proxy (macOS applies it per network service) and breaks connections
+//! through the old interface. This module takes a fingerprint of the
+//! default route — interface, gateway and local address — whenever the OS
+//! reports a network change (netlink on Linux, SCDynamicStore on macOS,
+//! NetworkListManager on Windows) and reports when it differs, so the proxy
+//! can be re-applied and connectivity re-checked. Where notifications are
+//! unavailable, the fingerprint is polled instead.
+//!
+//! A change is only reported once the new fingerprint has been seen twice
+//! in a row, so the brief offline period of a network switch is not
+//! reported as a change of its own.
+
+use std::future::Future;
+use std::net::IpAddr;
+#[cfg(target_os = "linux")]
+use std::net::Ipv4Addr;
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
+
+/// Default time between two fingerprints when the OS gives no change
+/// notifications
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time for routes to settle after a notification, and between the two
+/// fingerprints confirming a change
+const SETTLE_DELAY: Duration = Duration::from_secs(1);
+
+/// Identifies the network the machine is connected to
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkFingerprint {
+    /// Interface carrying the default route
+    pub interface: Option<String>,
+    /// Default gateway
+    pub gateway: Option<IpAddr>,
+    /// Source address of outgoing traffic
+    pub local_address: Option<IpAddr>,
+}
+
+impl NetworkFingerprint {
+    /// Fingerprint of the current network
+    ///
+    /// Runs system commands on macOS and Windows; call it off the async
+    /// executor.
+    pub fn current() -> Self {
+        let (interface, gateway) = default_route();
+        Self {
+            interface,
+            gateway,
+            local_address: super::share::lan_address(),
+        }
+    }
+
+    /// Whether there is a usable network
+    pub fn is_online(&self) -> bool {
+        self.local_address.is_some()
+    }
+}
+
+/// A reported network change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkChange {
+    /// Fingerprint before the change
+    pub previous: NetworkFingerprint,
+    /// Fingerprint after the change
+    pub current: NetworkFingerprint,
+}
+
+/// Turns successive fingerprints into debounced changes
+#[derive(Debug, Default)]
+pub struct ChangeDetector {
+    /// Last reported (or initial) fingerprint
+    reported: NetworkFingerprint,
+    /// Differing fingerprint seen once, awaiting confirmation
+    pending: Option<NetworkFingerprint>,
+}
+
+impl ChangeDetector {
+    /// Start from the `initial` fingerprint
+    pub fn new(initial: NetworkFingerprint) -> Self {
+        Self {
+            reported: initial,
+            pending: None,
+        }
+    }
+
+    /// Whether a differing fingerprint awaits confirmation
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Record a fingerprint, returning a change once it is confirmed
+    pub fn observe(&mut self, fingerprint: NetworkFingerprint) -> Option<NetworkChange> {
+        if fingerprint == self.reported {
+            self.pending = None;
+            return None;
+        }
+        if self.pending.as_ref() != Some(&fingerprint) {
+            self.pending = Some(fingerprint);
+            return None;
+        }
+
+        self.pending = None;
+        let previous = std::mem::replace(&mut self.reported, fingerprint.clone());
+        Some(NetworkChange {
+            previous,
+            current: fingerprint,
+        })
+    }
+}
+
+/// Background task reporting network changes
+pub struct NetworkMonitor {
+    /// Monitoring loop
+    task: JoinHandle<()>,
+}
+
+impl NetworkMonitor {
+    /// Watch for changes, awaiting `on_change` for each change
+    ///
+    /// The fingerprint is taken after each OS notification, or every
+    /// `interval` when notifications are unavailable.
+    pub fn start<F, Fut>(interval: Duration, on_change: F) -> Self
+    where
+        F: Fn(NetworkChange) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let task = tokio::spawn(async move {
+            let Ok(initial) = tokio::task::spawn_blocking(NetworkFingerprint::current).await else {
+                return;
+            };
+            let (sender, mut notifications) = tokio::sync::mpsc::unbounded_channel();
+            let mut subscription = match subscribe(sender) {
+                Ok(subscription) => {
+                    tracing::info!("Monitoring network changes from {:?}", initial);
+                    Some(subscription)
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Network change notifications unavailable, polling every {:?}: {}",
+                        interval,
+                        e
+                    );
+                    None
+                }
+            };
+            let mut detector = ChangeDetector::new(initial);
+
+            loop {
+                if subscription.is_none() {
+                    tokio::time::sleep(interval).await;
+                } else if detector.is_pending() {
+                    tokio::time::sleep(SETTLE_DELAY).await;
+                } else if notifications.recv().await.is_some() {
+                    tokio::time::sleep(SETTLE_DELAY).await;
+                    while notifications.try_recv().is_ok() {}
+                } else {
+                    tracing::warn!(
+                        "Network change notifications stopped, polling every {:?}",
+                        interval
+                    );
+                    subscription = None;
+                    continue;
+                }
+
+                let Ok(fingerprint) =
+                    tokio::task::spawn_blocking(NetworkFingerprint::current).await
+                else {
+                    continue;
+                };
+                if let Some(change) = detector.observe(fingerprint) {
+                    tracing::info!(
+                        "Network changed: {:?} -> {:?}",
+                        change.previous,
+                        change.current
+                    );
+                    on_change(change).await;
+                }
+            }
+        });
+        Self { task }
+    }
+
+    /// Stop monitoring
+    pub fn stop(self) {
+        tracing::info!("Stopping network monitor");
+        self.task.abort();
+    }
+}
+
+impl Drop for NetworkMonitor {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Subscription to the OS's network change notifications, cancelled when
+/// dropped
+struct Subscription {
+    /// Cancels the subscription
+    cancel: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(cancel) = self.cancel.take() {
+            cancel();
+        }
+    }
+}
+
+/// Send to `notifications` on link, address and route changes, read from a
+/// netlink socket
+///
+/// Must be called within a Tokio runtime.
+#[cfg(target_os = "linux")]
+fn subscribe(notifications: UnboundedSender<()>) -> std::io::Result<Subscription> {
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use tokio::io::unix::AsyncFd;
+
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups = (libc::RTMGRP_LINK
+        | libc::RTMGRP_IPV4_IFADDR
+        | libc::RTMGRP_IPV4_ROUTE
+        | libc::RTMGRP_IPV6_IFADDR
+        | libc::RTMGRP_IPV6_ROUTE) as u32;
+    let bound = unsafe {
+        libc::bind(
+            fd.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if bound < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let socket = AsyncFd::new(fd)?;
+    let task = tokio::spawn(async move {
+        let mut buf = [0u8; 8192];
+        loop {
+            let Ok(mut ready) = socket.readable().await else {
+                break;
+            };
+            // Drain all queued messages, they count as one notification
+            loop {
+                let received = ready.try_io(|socket| {
+                    let n = unsafe {
+                        libc::recv(
+                            socket.get_ref().as_raw_fd(),
+                            buf.as_mut_ptr().cast(),
+                            buf.len(),
+                            0,
+                        )
+                    };
+                    if n < 0 {
+                        Err(std::io::Error::last_os_error())
+                    } else {
+                        Ok(())
+                    }
+                });
+                match received {
+                    Ok(Ok(())) => continue,
+                    // Messages were dropped on overflow, still a change
+                    Ok(Err(e)) if e.raw_os_error() == Some(libc::ENOBUFS) => continue,
+                    Ok(Err(e)) => {
+                        tracing::warn!("Netlink notifications failed: {}", e);
+                        return;
+                    }
+                    Err(_would_block) => break,
+                }
+            }
+            if notifications.send(()).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(Subscription {
+        cancel: Some(Box::new(move || task.abort())),
+    })
+}
+
+/// Send to `notifications` on changes of the global and interface IPv4
+/// and IPv6 state in the SCDynamicStore
+///
+/// The store is watched on its own run loop thread, which ends with the
+/// first notification after the subscription is dropped.
+#[cfg(target_os = "macos")]
+fn subscribe(notifications: UnboundedSender<()>) -> std::io::Result<Subscription> {
+    use core_foundation::array::CFArray;
+    use core_foundation::runloop::{kCFRunLoopCommonModes, CFRunLoop};
+    use core_foundation::string::CFString;
+    use system_configuration::dynamic_store::{
+        SCDynamicStore, SCDynamicStoreBuilder, SCDynamicStoreCallBackContext,
+    };
+
+    fn notify(_: SCDynamicStore, _: CFArray<CFString>, notifications: &mut UnboundedSender<()>) {
+        if notifications.send(()).is_err() {
+            CFRunLoop::get_current().stop();
+        }
+    }
+
+    let (ready, watching) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("network-monitor".to_string())
+        .spawn(move || {
+            let store = SCDynamicStoreBuilder::new(crate::version::APP_NAME)
+                .callback_context(SCDynamicStoreCallBackContext {
+                    callout: notify,
+                    info: notifications,
+                })
+                .build();
+            let keys = CFArray::from_CFTypes(&[
+                CFString::new("State:/Network/Global/IPv4"),
+                CFString::new("State:/Network/Global/IPv6"),
+            ]);
+            let patterns =
+                CFArray::from_CFTypes(&[CFString::new("State:/Network/Interface/[^/]+/IPv[46]")]);
+            if !store.set_notification_keys(&keys, &patterns) {
+                let _ = ready.send(false);
+                return;
+            }
+            let source = store.create_run_loop_source();
+            CFRunLoop::get_current().add_source(&source, unsafe { kCFRunLoopCommonModes });
+            let _ = ready.send(true);
+            CFRunLoop::run_current();
+        })?;
+
+    match watching.recv() {
+        Ok(true) => Ok(Subscription { cancel: None }),
+        _ => Err(std::io::Error::other(
+            "SCDynamicStore rejected the notification keys",
+        )),
+    }
+}
+
+/// Send to `notifications` when NetworkListManager reports a connectivity
+/// change
+///
+/// The event sink is registered from its own COM thread, which unregisters
+/// it when the subscription is dropped.
+#[cfg(target_os = "windows")]
+fn subscribe(notifications: UnboundedSender<()>) -> std::io::Result<Subscription> {
+    use windows::core::{implement, ComInterface};
+    use windows::Win32::Networking::NetworkListManager::{
+        INetworkListManager, INetworkListManagerEvents, INetworkListManagerEvents_Impl,
+        NetworkListManager, NLM_CONNECTIVITY,
+    };
+    use windows::Win32::System::Com::{
+        CoCreateInstance, CoInitializeEx, CoUninitialize, IConnectionPointContainer, CLSCTX_ALL,
+        COINIT_MULTITHREADED,
+    };
+
+    #[implement(INetworkListManagerEvents)]
+    struct Sink(UnboundedSender<()>);
+
+    impl INetworkListManagerEvents_Impl for Sink {
+        fn ConnectivityChanged(&self, _: NLM_CONNECTIVITY) -> windows::core::Result<()> {
+            let _ = self.0.send(());
+            Ok(())
+        }
+    }
+
+    let (ready, registered) = std::sync::mpsc::channel();
+    let (cancel, cancelled) = std::sync::mpsc::channel::<()>();
+    std::thread::Builder::new()
+        .name("network-monitor".to_string())
+        .spawn(move || unsafe {
+            if let Err(e) = CoInitializeEx(None, COINIT_MULTITHREADED) {
+                let _ = ready.send(Err(e));
+                return;
+            }
+            let advise = || -> windows::core::Result<_> {
+                let manager: INetworkListManager =
+                    CoCreateInstance(&NetworkListManager, None, CLSCTX_ALL)?;
+                let point = manager
+                    .cast::<IConnectionPointContainer>()?
+                    .FindConnectionPoint(&INetworkListManagerEvents::IID)?;
+                let sink: INetworkListManagerEvents = Sink(notifications).into();
+                let cookie = point.Advise(&sink)?;
+                Ok((point, cookie))
+            };
+            match advise() {
+                Ok((point, cookie)) => {
+                    let _ = ready.send(Ok(()));
+                    // Events arrive on COM's threads until cancelled
+                    let _ = cancelled.recv();
+                    let _ = point.Unadvise(cookie);
+                }
+                Err(e) => {
+                    let _ = ready.send(Err(e));
+                }
+            }
+            CoUninitialize();
+        })?;
+
+    registered
+        .recv()
+        .map_err(std::io::Error::other)?
+        .map_err(std::io::Error::other)?;
+    Ok(Subscription {
+        cancel: Some(Box::new(move || drop(cancel))),
+    })
+}
+
+/// Network change notifications are not available on this platform
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn subscribe(_notifications: UnboundedSender<()>) -> std::io::Result<Subscription> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// Interface and gateway of the default route
+#[cfg(target_os = "linux")]
+fn default_route() -> (Option<String>, Option<IpAddr>) {
+    std::fs::read_to_string("/proc/net/route")
+        .ok()
+        .and_then(|content| parse_proc_net_route(&content))
+        .map_or((None, None), |(interface, gateway)| {
+            (Some(interface), Some(IpAddr::V4(gateway)))
+        })
+}
+
+/// Interface and gateway of the default route
+#[cfg(target_os = "macos")]
+fn default_route() -> (Option<String>, Option<IpAddr>) {
+    let Ok(output) = std::process::Command::new("route")
+        .args(["-n", "get", "default"])
+        .output()
+    else {
+        return (None, None);
+    };
+    let output = String::from_utf8_lossy(&output.stdout);
+    (
+        super::parse_route_get_field(&output, "interface"),
+        super::parse_route_get_field(&output, "gateway").and_then(|gateway| gateway.parse().ok()),
+    )
+}
+
+/// Interface and gateway of the default route
+///
+/// The interface is identified by its address, as printed by `route print`.
+#[cfg(target_os = "windows")]
+fn default_route() -> (Option<String>, Option<IpAddr>) {
+    use std::os::windows::process::CommandExt;
+    const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+    let Ok(output) = std::process::Command::new("route")
+        .args(["print", "-4", "0.0.0.0"])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+    else {
+        return (None, None);
+    };
+    parse_route_print(&String::from_utf8_lossy(&output.stdout))
+        .map_or((None, None), |(interface, gateway)| {
+            (Some(interface), Some(gateway))
+        })
+}
+
+/// Interface and gateway of the default route
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+fn default_route() -> (Option<String>, Option<IpAddr>) {
+    (None, None)
+}
+
+/// Default route with the lowest metric from `/proc/net/route`
+///
+/// Addresses are printed as hexadecimal numbers of their bytes in network
+/// order read as a native integer.
+#[cfg(target_os = "linux")]
+fn parse_proc_net_route(content: &str) -> Option<(String, Ipv4Addr)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (interface, destination, gateway, metric, mask) = (
+                fields.first()?,
+                fields.get(1)?,
+                fields.get(2)?,
+                fields.get(6)?,
+                fields.get(7)?,
+            );
+            if *destination != "00000000" || *mask != "00000000" {
+                return None;
+            }
+            let gateway = u32::from_str_radix(gateway, 16).ok()?;
+            let metric: u32 = metric.parse().ok()?;
+            Some((
+                metric,
+                interface.to_string(),
+                Ipv4Addr::from(gateway.to_ne_bytes()),
+            ))
+        })
+        .min_by_key(|(metric, _, _)| *metric)
+        .map(|(_, interface, gateway)| (interface, gateway))
+}
+
+/// Default route with the lowest metric from `route print -4 0.0.0.0`,
+/// as (interface address, gateway)
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn parse_route_print(output: &str) -> Option<(String, IpAddr)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["0.0.0.0", "0.0.0.0", gateway, interface, metric] => Some((
+                    metric.parse::<u32>().ok()?,
+                    interface.to_string(),
+                    gateway.parse().ok()?,
+                )),
+                _ => None,
+            }
+        })
+        .min_by_key(|(metric, _, _)| *metric)
+        .map(|(_, interface, gateway)| (interface, gateway))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(interface: &str) -> NetworkFingerprint {
+        NetworkFingerprint {
+            interface: Some(interface.to_string()),
+            gateway: None,
+            local_address: Some("192.168.1.2".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_change_detector() {
+        let mut detector = ChangeDetector::new(fingerprint("en0"));
+        assert!(detector.observe(fingerprint("en0")).is_none());
+
+        // Offline for a single poll while switching
+        assert!(detector.observe(NetworkFingerprint::default()).is_none());
+        assert!(detector.observe(fingerprint("en1")).is_none());
+        let change = detector.observe(fingerprint("en1")).unwrap();
+        assert_eq!(change.previous, fingerprint("en0"));
+        assert_eq!(change.current, fingerprint("en1"));
+        assert!(change.current.is_online());
+        assert!(detector.observe(fingerprint("en1")).is_none());
+
+        // Flapping back before confirmation is not a change
+        assert!(detector.observe(fingerprint("en0")).is_none());
+        assert!(detector.observe(fingerprint("en1")).is_none());
+        assert!(detector.observe(fingerprint("en1")).is_none());
+    }
+
+    #[test]
+    fn test_parse_routes() {
+        let windows = "\
+===========================================================================
+Active Routes:
+Network Destination        Netmask          Gateway       Interface  Metric
+          0.0.0.0          0.0.0.0      192.168.1.1    192.168.1.23     35
+          0.0.0.0          0.0.0.0         10.0.0.1       10.0.0.7     25
+===========================================================================";
+        assert_eq!(
+            parse_route_print(windows),
+            Some(("10.0.0.7".to_string(), "10.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(parse_route_print("Active Routes:\nNone\n"), None);
+
+        // Kernel output of a little-endian host
+        #[cfg(all(target_os = "linux", target_endian = "little"))]
+        {
+            let linux = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\t0\t0\t0
+eth0\t00000000\t0100000A\t0003\t0\t0\t100\t00000000\t0\t0\t0
+eth0\t0000000A\t00000000\t0001\t0\t0\t100\t000000FF\t0\t0\t0";
+            assert_eq!(
+                parse_proc_net_route(linux),
+                Some(("eth0".to_string(), Ipv4Addr::new(10, 0, 0, 1)))
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_netlink_subscription() {
+        let (sender, _notifications) = tokio::sync::mpsc::unbounded_channel();
+        assert!(subscribe(sender).is_ok());
+    }
+}
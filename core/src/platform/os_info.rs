@@ -0,0 +1,498 @@
+//! macOS and Linux version detection
+//!
+//! macOS reports its version in `SystemVersion.plist`, with `sw_vers` as
+//! fallback. On Linux the distribution comes from `os-release`, the kernel
+//! from `/proc`, and the desktop environment from the session's
+//! `XDG_CURRENT_DESKTOP`, which decides where the system proxy lives:
+//! GNOME and its derivatives read gsettings, while KDE keeps its own in
+//! kioslaverc.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// macOS version file
+#[cfg(target_os = "macos")]
+const SYSTEM_VERSION_PLIST: &str = "/System/Library/CoreServices/SystemVersion.plist";
+
+/// os-release locations, in order of precedence
+#[cfg(target_os = "linux")]
+const OS_RELEASE_PATHS: &[&str] = &["/etc/os-release", "/usr/lib/os-release"];
+
+/// Running kernel release
+#[cfg(target_os = "linux")]
+const KERNEL_RELEASE_PATH: &str = "/proc/sys/kernel/osrelease";
+
+/// macOS version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MacOSVersion {
+    /// Product name, "macOS" (or "Mac OS X" on old releases)
+    pub product_name: String,
+    /// Version, e.g. "14.5"
+    pub version: String,
+    /// Build, e.g. "23F79"
+    pub build: Option<String>,
+}
+
+impl MacOSVersion {
+    /// Version of the running system
+    #[cfg(target_os = "macos")]
+    pub fn current() -> Option<Self> {
+        std::fs::read_to_string(SYSTEM_VERSION_PLIST)
+            .ok()
+            .and_then(|plist| Self::from_plist(&plist))
+            .or_else(|| {
+                let output = std::process::Command::new("sw_vers").output().ok()?;
+                Self::from_sw_vers(&String::from_utf8_lossy(&output.stdout))
+            })
+    }
+
+    /// Version of the running system
+    #[cfg(not(target_os = "macos"))]
+    pub fn current() -> Option<Self> {
+        None
+    }
+
+    /// Parse `SystemVersion.plist`
+    pub fn from_plist(plist: &str) -> Option<Self> {
+        let values = plist_strings(plist);
+        Self::from_values(
+            values.get("ProductName").cloned(),
+            values.get("ProductVersion").cloned(),
+            values.get("ProductBuildVersion").cloned(),
+        )
+    }
+
+    /// Parse the output of `sw_vers`
+    pub fn from_sw_vers(output: &str) -> Option<Self> {
+        let values: HashMap<&str, String> = output
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| (key.trim(), value.trim().to_string()))
+            .collect();
+        Self::from_values(
+            values.get("ProductName").cloned(),
+            values.get("ProductVersion").cloned(),
+            values.get("BuildVersion").cloned(),
+        )
+    }
+
+    /// Version from the product name, version and build, None without a
+    /// version
+    fn from_values(
+        product_name: Option<String>,
+        version: Option<String>,
+        build: Option<String>,
+    ) -> Option<Self> {
+        Some(Self {
+            product_name: product_name.unwrap_or_else(|| "macOS".to_string()),
+            version: version.filter(|v| !v.is_empty())?,
+            build: build.filter(|b| !b.is_empty()),
+        })
+    }
+}
+
+impl std::fmt::Display for MacOSVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.product_name, self.version)?;
+        if let Some(build) = &self.build {
+            write!(f, " ({})", build)?;
+        }
+        Ok(())
+    }
+}
+
+/// `<key>`/`<string>` pairs at any level of a property list
+fn plist_strings(plist: &str) -> HashMap<String, String> {
+    let mut values = HashMap::new();
+    let mut rest = plist;
+    while let Some(start) = rest.find("<key>") {
+        rest = &rest[start + "<key>".len()..];
+        let Some(end) = rest.find("</key>") else {
+            break;
+        };
+        let key = rest[..end].trim().to_string();
+        rest = rest[end + "</key>".len()..].trim_start();
+        if let Some(value) = rest.strip_prefix("<string>") {
+            if let Some(end) = value.find("</string>") {
+                values.insert(key, xml_unescape(value[..end].trim()));
+                rest = &value[end..];
+            }
+        }
+    }
+    values
+}
+
+/// Undo the XML escapes used in property lists
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Linux distribution, from os-release
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinuxDistro {
+    /// Lower-case identifier, e.g. "ubuntu", "fedora"
+    pub id: String,
+    /// Identifiers of related distributions, e.g. ["debian"] for Ubuntu
+    pub id_like: Vec<String>,
+    /// Name, e.g. "Ubuntu"
+    pub name: String,
+    /// Version, e.g. "24.04", None on rolling releases
+    pub version_id: Option<String>,
+    /// Name and version for display, e.g. "Ubuntu 24.04 LTS"
+    pub pretty_name: String,
+}
+
+impl LinuxDistro {
+    /// Distribution of the running system
+    #[cfg(target_os = "linux")]
+    pub fn current() -> Option<Self> {
+        OS_RELEASE_PATHS
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+            .map(|content| Self::parse(&content))
+    }
+
+    /// Distribution of the running system
+    #[cfg(not(target_os = "linux"))]
+    pub fn current() -> Option<Self> {
+        None
+    }
+
+    /// Parse the content of an os-release file
+    ///
+    /// Missing fields take the defaults of the os-release specification.
+    pub fn parse(content: &str) -> Self {
+        let values: HashMap<&str, String> = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim(), unquote(value.trim())))
+            .collect();
+        let get = |key: &str| values.get(key).filter(|v| !v.is_empty()).cloned();
+
+        let name = get("NAME").unwrap_or_else(|| "Linux".to_string());
+        Self {
+            id: get("ID").unwrap_or_else(|| "linux".to_string()),
+            id_like: get("ID_LIKE")
+                .map(|like| like.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            pretty_name: get("PRETTY_NAME").unwrap_or_else(|| name.clone()),
+            name,
+            version_id: get("VERSION_ID"),
+        }
+    }
+
+    /// Whether this is `id` or derived from it
+    pub fn is_like(&self, id: &str) -> bool {
+        self.id == id || self.id_like.iter().any(|like| like == id)
+    }
+}
+
+/// Strip shell quoting from an os-release value
+fn unquote(value: &str) -> String {
+    let quoted = value.len() >= 2
+        && ((value.starts_with('"') && value.ends_with('"'))
+            || (value.starts_with('\'') && value.ends_with('\'')));
+    if !quoted {
+        return value.to_string();
+    }
+    let inner = &value[1..value.len() - 1];
+    let mut unescaped = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                unescaped.push(next);
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+/// Linux desktop environment
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DesktopEnvironment {
+    /// GNOME, including Ubuntu's and Pop!_OS's sessions
+    Gnome,
+    /// KDE Plasma
+    Kde,
+    /// Xfce
+    Xfce,
+    /// Cinnamon
+    Cinnamon,
+    /// MATE
+    Mate,
+    /// Budgie
+    Budgie,
+    /// Unity
+    Unity,
+    /// LXQt
+    Lxqt,
+    /// Any other desktop, by its `XDG_CURRENT_DESKTOP` name
+    Other(String),
+}
+
+impl DesktopEnvironment {
+    /// Desktop of the current session, None without one (e.g. over SSH)
+    pub fn current() -> Option<Self> {
+        Self::detect(
+            std::env::var("XDG_CURRENT_DESKTOP").ok().as_deref(),
+            std::env::var("DESKTOP_SESSION").ok().as_deref(),
+        )
+    }
+
+    /// Detect the desktop from `XDG_CURRENT_DESKTOP` (a colon-separated
+    /// list such as `ubuntu:GNOME`) or, failing that, `DESKTOP_SESSION`
+    pub fn detect(current_desktop: Option<&str>, session: Option<&str>) -> Option<Self> {
+        let names: Vec<&str> = current_desktop
+            .into_iter()
+            .flat_map(|desktop| desktop.split(':'))
+            .chain(session)
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect();
+        names
+            .iter()
+            .find_map(|name| Self::known(name))
+            .or_else(|| names.first().map(|name| Self::Other(name.to_string())))
+    }
+
+    /// Desktop known by `name`
+    fn known(name: &str) -> Option<Self> {
+        let desktop = match name.to_ascii_lowercase().as_str() {
+            "gnome" | "gnome-classic" | "gnome-xorg" | "pop" => Self::Gnome,
+            "kde" | "plasma" | "plasmawayland" => Self::Kde,
+            "xfce" | "xfce4" => Self::Xfce,
+            "x-cinnamon" | "cinnamon" => Self::Cinnamon,
+            "mate" => Self::Mate,
+            "budgie" | "budgie-desktop" => Self::Budgie,
+            "unity" => Self::Unity,
+            "lxqt" => Self::Lxqt,
+            _ => return None,
+        };
+        Some(desktop)
+    }
+
+    /// Name for display
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Gnome => "GNOME",
+            Self::Kde => "KDE Plasma",
+            Self::Xfce => "Xfce",
+            Self::Cinnamon => "Cinnamon",
+            Self::Mate => "MATE",
+            Self::Budgie => "Budgie",
+            Self::Unity => "Unity",
+            Self::Lxqt => "LXQt",
+            Self::Other(name) => name,
+        }
+    }
+
+    /// Whether the desktop reads its proxy settings from gsettings
+    pub fn uses_gsettings(&self) -> bool {
+        matches!(
+            self,
+            Self::Gnome | Self::Cinnamon | Self::Mate | Self::Budgie | Self::Unity
+        )
+    }
+}
+
+/// Operating system details for diagnostics
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OsDetails {
+    /// Operating system name, e.g. "macOS", "Ubuntu"
+    pub name: String,
+    /// Version, e.g. "14.5", "24.04"
+    pub version: Option<String>,
+    /// Build, e.g. "23F79"
+    pub build: Option<String>,
+    /// Kernel release (Linux)
+    pub kernel: Option<String>,
+    /// Distribution (Linux)
+    pub distro: Option<LinuxDistro>,
+    /// Desktop environment (Linux)
+    pub desktop: Option<DesktopEnvironment>,
+}
+
+impl OsDetails {
+    /// Details of the running system
+    pub fn current() -> Self {
+        if let Some(version) = super::windows_version::WindowsVersion::current() {
+            return Self {
+                name: version.release_name().to_string(),
+                version: Some(format!("{}.{}", version.major, version.minor)),
+                build: Some(if version.revision > 0 {
+                    format!("{}.{}", version.build, version.revision)
+                } else {
+                    version.build.to_string()
+                }),
+                ..Default::default()
+            };
+        }
+        if let Some(version) = MacOSVersion::current() {
+            return Self {
+                name: version.product_name,
+                version: Some(version.version),
+                build: version.build,
+                ..Default::default()
+            };
+        }
+        let distro = LinuxDistro::current();
+        Self {
+            name: distro
+                .as_ref()
+                .map_or_else(|| std::env::consts::OS.to_string(), |d| d.name.clone()),
+            version: distro.as_ref().and_then(|d| d.version_id.clone()),
+            build: None,
+            kernel: kernel_release(),
+            desktop: if cfg!(target_os = "linux") {
+                DesktopEnvironment::current()
+            } else {
+                None
+            },
+            distro,
+        }
+    }
+}
+
+/// Version summary for [`super::PlatformInfo::version`], e.g.
+/// "Ubuntu 24.04 LTS (kernel 6.8.0-45-generic, GNOME)"
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+pub(crate) fn linux_version_summary(
+    distro: Option<&LinuxDistro>,
+    kernel: Option<&str>,
+    desktop: Option<&DesktopEnvironment>,
+) -> String {
+    let mut summary = distro.map_or_else(|| "Linux".to_string(), |d| d.pretty_name.clone());
+    let details: Vec<String> = kernel
+        .map(|kernel| format!("kernel {}", kernel))
+        .into_iter()
+        .chain(desktop.map(|desktop| desktop.name().to_string()))
+        .collect();
+    if !details.is_empty() {
+        summary.push_str(&format!(" ({})", details.join(", ")));
+    }
+    summary
+}
+
+/// Running kernel release
+#[cfg(target_os = "linux")]
+pub(crate) fn kernel_release() -> Option<String> {
+    std::fs::read_to_string(KERNEL_RELEASE_PATH)
+        .ok()
+        .map(|release| release.trim().to_string())
+        .filter(|release| !release.is_empty())
+}
+
+/// Running kernel release
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn kernel_release() -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plist() {
+        let plist = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>BuildID</key>
+	<string>6F6F5B7E-1234</string>
+	<key>ProductBuildVersion</key>
+	<string>23F79</string>
+	<key>ProductCopyright</key>
+	<string>1983-2024 Apple Inc.</string>
+	<key>ProductName</key>
+	<string>macOS</string>
+	<key>ProductVersion</key>
+	<string>14.5</string>
+</dict>
+</plist>"#;
+        let version = MacOSVersion::from_plist(plist).unwrap();
+        assert_eq!(version.to_string(), "macOS 14.5 (23F79)");
+        assert!(MacOSVersion::from_plist("<plist></plist>").is_none());
+    }
+
+    #[test]
+    fn test_parse_sw_vers() {
+        let output = "ProductName:\t\tmacOS\nProductVersion:\t\t13.6.7\nBuildVersion:\t\t22G720\n";
+        let version = MacOSVersion::from_sw_vers(output).unwrap();
+        assert_eq!(version.version, "13.6.7");
+        assert_eq!(version.build.as_deref(), Some("22G720"));
+    }
+
+    #[test]
+    fn test_parse_os_release() {
+        let distro = LinuxDistro::parse(
+            "PRETTY_NAME=\"Ubuntu 24.04 LTS\"\n\
+             NAME=\"Ubuntu\"\n\
+             VERSION_ID=\"24.04\"\n\
+             # comment\n\
+             ID=ubuntu\n\
+             ID_LIKE=debian\n",
+        );
+        assert_eq!(distro.id, "ubuntu");
+        assert_eq!(distro.pretty_name, "Ubuntu 24.04 LTS");
+        assert_eq!(distro.version_id.as_deref(), Some("24.04"));
+        assert!(distro.is_like("debian"));
+        assert!(!distro.is_like("fedora"));
+
+        // Rolling release without a version
+        let arch = LinuxDistro::parse("NAME='Arch Linux'\nID=arch\n");
+        assert_eq!(arch.pretty_name, "Arch Linux");
+        assert_eq!(arch.version_id, None);
+
+        let empty = LinuxDistro::parse("");
+        assert_eq!(empty.id, "linux");
+        assert_eq!(empty.name, "Linux");
+    }
+
+    #[test]
+    fn test_detect_desktop() {
+        assert_eq!(
+            DesktopEnvironment::detect(Some("ubuntu:GNOME"), None),
+            Some(DesktopEnvironment::Gnome)
+        );
+        assert_eq!(
+            DesktopEnvironment::detect(Some("KDE"), Some("plasma")),
+            Some(DesktopEnvironment::Kde)
+        );
+        assert_eq!(
+            DesktopEnvironment::detect(None, Some("xfce")),
+            Some(DesktopEnvironment::Xfce)
+        );
+        assert_eq!(
+            DesktopEnvironment::detect(Some("Hyprland"), None),
+            Some(DesktopEnvironment::Other("Hyprland".to_string()))
+        );
+        assert_eq!(DesktopEnvironment::detect(None, None), None);
+        assert!(DesktopEnvironment::Gnome.uses_gsettings());
+        assert!(!DesktopEnvironment::Kde.uses_gsettings());
+    }
+
+    #[test]
+    fn test_linux_version_summary() {
+        let distro = LinuxDistro::parse("PRETTY_NAME=\"Fedora Linux 40\"\nNAME=Fedora\n");
+        assert_eq!(
+            linux_version_summary(
+                Some(&distro),
+                Some("6.9.4-200.fc40.x86_64"),
+                Some(&DesktopEnvironment::Kde)
+            ),
+            "Fedora Linux 40 (kernel 6.9.4-200.fc40.x86_64, KDE Plasma)"
+        );
+        assert_eq!(linux_version_summary(None, None, None), "Linux");
+    }
+}
@@ -0,0 +1,387 @@
+//! PAC (proxy auto-config) support
+//!
+//! Backs the Auto proxy mode: instead of pointing the OS at a fixed proxy,
+//! a PAC script is served from a local HTTP server and the OS is pointed at
+//! its URL. Browsers and other PAC-aware apps then send matching traffic
+//! to the local inbounds and connect directly otherwise.
+//!
+//! Xray stays authoritative for routing: `geosite:`/`geoip:` matchers need
+//! Xray's data files and cannot be expressed in a PAC script, so anything
+//! the script does not send direct goes to Xray, which applies the full
+//! rule set. The script only lets traffic bypass Xray where the outcome is
+//! certain: local addresses and user rules up to the first one it cannot
+//! translate exactly.
+
+use crate::config::routing::{RoutingRule, OUTBOUND_DIRECT};
+use crate::error::PlatformError;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Path the PAC script is served at
+pub const PAC_PATH: &str = "/proxy.pac";
+
+/// MIME type of PAC scripts
+pub const PAC_CONTENT_TYPE: &str = "application/x-ns-proxy-autoconfig";
+
+/// Private and link-local IPv4 networks, always connected directly
+const PRIVATE_NETWORKS: &[(&str, &str)] = &[
+    ("10.0.0.0", "255.0.0.0"),
+    ("100.64.0.0", "255.192.0.0"),
+    ("127.0.0.0", "255.0.0.0"),
+    ("169.254.0.0", "255.255.0.0"),
+    ("172.16.0.0", "255.240.0.0"),
+    ("192.168.0.0", "255.255.0.0"),
+];
+
+/// Host matcher of a PAC rule
+#[derive(Debug, Clone, PartialEq)]
+enum PacMatcher {
+    /// Domain and its subdomains (`domain:`)
+    Suffix(String),
+    /// Exact domain (`full:`)
+    Full(String),
+    /// Substring of the domain (`keyword:` or no prefix)
+    Keyword(String),
+    /// IPv4 network, matched against IP literal hosts only
+    Network(Ipv4Addr, Ipv4Addr),
+}
+
+impl PacMatcher {
+    /// Translate an Xray domain matcher, None if PAC cannot express it
+    fn from_domain(domain: &str) -> Option<Self> {
+        let (prefix, value) = domain.split_once(':').unwrap_or(("", domain));
+        let value = value.trim().to_lowercase();
+        if value.is_empty() {
+            return None;
+        }
+        match prefix {
+            "domain" => Some(PacMatcher::Suffix(value)),
+            "full" => Some(PacMatcher::Full(value)),
+            "keyword" | "" => Some(PacMatcher::Keyword(value)),
+            // geosite:, regexp:, ext:
+            _ => None,
+        }
+    }
+
+    /// Translate an Xray IP matcher, None if PAC cannot express it
+    fn from_ip(ip: &str) -> Option<Vec<Self>> {
+        if ip == "geoip:private" {
+            return Some(
+                PRIVATE_NETWORKS
+                    .iter()
+                    .map(|(net, mask)| {
+                        PacMatcher::Network(net.parse().unwrap(), mask.parse().unwrap())
+                    })
+                    .collect(),
+            );
+        }
+
+        let (addr, prefix_len) = ip.split_once('/').unwrap_or((ip, "32"));
+        let addr: Ipv4Addr = addr.parse().ok()?;
+        let prefix_len: u32 = prefix_len.parse().ok().filter(|len| *len <= 32)?;
+        let mask = u32::MAX.checked_shl(32 - prefix_len).unwrap_or(0);
+        let net = Ipv4Addr::from(u32::from(addr) & mask);
+        Some(vec![PacMatcher::Network(net, Ipv4Addr::from(mask))])
+    }
+
+    /// JavaScript condition testing `host`
+    fn condition(&self) -> String {
+        match self {
+            PacMatcher::Suffix(domain) => format!(
+                "(host === {0} || dnsDomainIs(host, {1}))",
+                js_string(domain),
+                js_string(&format!(".{}", domain))
+            ),
+            PacMatcher::Full(domain) => format!("host === {}", js_string(domain)),
+            PacMatcher::Keyword(keyword) => {
+                format!("host.indexOf({}) !== -1", js_string(keyword))
+            }
+            PacMatcher::Network(net, mask) => {
+                format!("(isIpv4 && isInNet(host, \"{}\", \"{}\"))", net, mask)
+            }
+        }
+    }
+}
+
+/// Translate a routing rule into PAC matchers, None if PAC cannot express
+/// every condition of it
+fn translate_rule(rule: &RoutingRule) -> Option<Vec<PacMatcher>> {
+    // Ports, processes and protocols are not visible to PAC, and Xray
+    // requires domain and IP conditions to match together
+    if rule.port.is_some() || !rule.processes.is_empty() || !rule.protocols.is_empty() {
+        return None;
+    }
+    if !rule.domains.is_empty() && !rule.ips.is_empty() {
+        return None;
+    }
+
+    let mut matchers = Vec::new();
+    for domain in &rule.domains {
+        matchers.push(PacMatcher::from_domain(domain)?);
+    }
+    for ip in &rule.ips {
+        matchers.extend(PacMatcher::from_ip(ip)?);
+    }
+    Some(matchers)
+}
+
+/// Quote `value` as a JavaScript string literal
+fn js_string(value: &str) -> String {
+    serde_json::Value::String(value.to_string()).to_string()
+}
+
+/// Generate a PAC script for the local HTTP and SOCKS inbounds
+///
+/// `rules` are the enabled user routing rules in evaluation order. Rules
+/// are translated in order and translation stops at the first one PAC
+/// cannot express exactly, since later rules may only apply because it did
+/// not match. Everything not routed direct goes to Xray.
+pub fn generate_pac(rules: &[RoutingRule], http_port: u16, socks_port: u16) -> String {
+    generate_pac_for_host(rules, Ipv4Addr::LOCALHOST.into(), http_port, socks_port)
+}
+
+/// Generate a PAC script pointing at the inbounds on `host`
+///
+/// Used for other devices on the LAN, which reach the inbounds through this
+/// machine's LAN address; the inbounds must then accept LAN connections.
+pub fn generate_pac_for_host(
+    rules: &[RoutingRule],
+    host: IpAddr,
+    http_port: u16,
+    socks_port: u16,
+) -> String {
+    let proxy = format!(
+        "PROXY {}; SOCKS5 {}",
+        SocketAddr::new(host, http_port),
+        SocketAddr::new(host, socks_port)
+    );
+
+    let mut checks = String::new();
+    for rule in rules {
+        let Some(matchers) = translate_rule(rule) else {
+            break;
+        };
+        if matchers.is_empty() {
+            continue;
+        }
+        let conditions: Vec<String> = matchers.iter().map(PacMatcher::condition).collect();
+        // Blocked traffic goes to Xray, which rejects it
+        let result = if rule.outbound_tag == OUTBOUND_DIRECT {
+            "direct"
+        } else {
+            "proxy"
+        };
+        checks.push_str(&format!(
+            "  if ({}) return {};\n",
+            conditions.join(" ||\n      "),
+            result
+        ));
+    }
+
+    let private: Vec<String> = PRIVATE_NETWORKS
+        .iter()
+        .map(|(net, mask)| format!("isInNet(host, \"{}\", \"{}\")", net, mask))
+        .collect();
+
+    format!(
+        "// Generated by {app}\n\
+         var proxy = {proxy};\n\
+         var direct = \"DIRECT\";\n\
+         \n\
+         function FindProxyForURL(url, host) {{\n  \
+           host = host.toLowerCase();\n  \
+           if (isPlainHostName(host) || host === \"localhost\" || dnsDomainIs(host, \".local\")) return direct;\n  \
+           var isIpv4 = /^\\d+\\.\\d+\\.\\d+\\.\\d+$/.test(host);\n  \
+           if (isIpv4 && ({private})) return direct;\n\
+         {checks}  \
+           return proxy;\n\
+         }}\n",
+        app = crate::version::APP_NAME,
+        proxy = js_string(&proxy),
+        private = private.join(" || "),
+        checks = checks,
+    )
+}
+
+/// Local HTTP server serving the PAC script
+pub struct PacServer {
+    /// Address the server listens on
+    addr: SocketAddr,
+    /// Script served, replaceable while running
+    script: Arc<RwLock<String>>,
+    /// Accept loop
+    task: JoinHandle<()>,
+}
+
+impl PacServer {
+    /// Serve `script` on the loopback `port` (0 picks a free port)
+    pub async fn start(port: u16, script: String) -> crate::V8RayResult<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .await
+            .map_err(|e| {
+                PlatformError::SystemProxy(format!("Failed to start PAC server: {}", e))
+            })?;
+        let addr = listener.local_addr().map_err(|e| {
+            PlatformError::SystemProxy(format!("Failed to start PAC server: {}", e))
+        })?;
+
+        let script = Arc::new(RwLock::new(script));
+        let served = Arc::clone(&script);
+        let task = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let script = served.read().unwrap_or_else(|e| e.into_inner()).clone();
+                tokio::spawn(serve(stream, script));
+            }
+        });
+
+        tracing::info!("PAC server listening on {}", addr);
+        Ok(Self { addr, script, task })
+    }
+
+    /// URL the OS should load the script from
+    pub fn url(&self) -> String {
+        format!("http://{}{}", self.addr, PAC_PATH)
+    }
+
+    /// Port the server listens on
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Replace the served script
+    pub fn update(&self, script: String) {
+        *self.script.write().unwrap_or_else(|e| e.into_inner()) = script;
+    }
+
+    /// Stop serving
+    pub fn stop(self) {
+        tracing::info!("Stopping PAC server on {}", self.addr);
+        self.task.abort();
+    }
+}
+
+impl Drop for PacServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Answer one request with the script
+///
+/// Every path gets the script: some systems append query strings or
+/// request it under a different name.
+async fn serve(mut stream: tokio::net::TcpStream, script: String) {
+    let mut buf = [0u8; 1024];
+    let request_line = match stream.read(&mut buf).await {
+        Ok(n) if n > 0 => String::from_utf8_lossy(&buf[..n]).to_string(),
+        _ => return,
+    };
+
+    let response = if request_line.starts_with("GET ") || request_line.starts_with("HEAD ") {
+        let body = if request_line.starts_with("HEAD ") {
+            ""
+        } else {
+            script.as_str()
+        };
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n{}",
+            PAC_CONTENT_TYPE,
+            script.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string()
+    };
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(domains: &[&str], ips: &[&str], outbound: &str) -> RoutingRule {
+        RoutingRule {
+            domains: domains.iter().map(|d| d.to_string()).collect(),
+            ips: ips.iter().map(|i| i.to_string()).collect(),
+            port: None,
+            processes: vec![],
+            protocols: vec![],
+            outbound_tag: outbound.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_generate_pac() {
+        let rules = vec![
+            rule(&["domain:corp.example", "full:intranet"], &[], "direct"),
+            rule(&[], &["203.0.113.0/24"], "direct"),
+            rule(&["keyword:tracker"], &[], "block"),
+            // Stops translation, later rules are left to Xray
+            rule(&["geosite:cn"], &[], "direct"),
+            rule(&["domain:after.example"], &[], "direct"),
+        ];
+        let pac = generate_pac(&rules, 8080, 1080);
+
+        assert!(pac.contains("var proxy = \"PROXY 127.0.0.1:8080; SOCKS5 127.0.0.1:1080\";"));
+        assert!(pac.contains("dnsDomainIs(host, \".corp.example\")"));
+        assert!(pac.contains("host === \"intranet\""));
+        assert!(pac.contains("isInNet(host, \"203.0.113.0\", \"255.255.255.0\")"));
+        assert!(pac.contains("host.indexOf(\"tracker\") !== -1) return proxy;"));
+        assert!(!pac.contains("geosite"));
+        assert!(!pac.contains("after.example"));
+        assert!(pac.trim_end().ends_with("return proxy;\n}"));
+
+        let lan = generate_pac_for_host(&rules, Ipv4Addr::new(192, 168, 1, 2).into(), 8080, 1080);
+        assert!(lan.contains("var proxy = \"PROXY 192.168.1.2:8080; SOCKS5 192.168.1.2:1080\";"));
+    }
+
+    #[test]
+    fn test_translate_rule() {
+        assert_eq!(
+            PacMatcher::from_ip("10.1.2.3/8"),
+            Some(vec![PacMatcher::Network(
+                Ipv4Addr::new(10, 0, 0, 0),
+                Ipv4Addr::new(255, 0, 0, 0)
+            )])
+        );
+        assert!(PacMatcher::from_ip("geoip:cn").is_none());
+        assert!(PacMatcher::from_ip("2001:db8::/32").is_none());
+
+        let mut with_port = rule(&["domain:example.com"], &[], "direct");
+        with_port.port = Some("443".to_string());
+        assert!(translate_rule(&with_port).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_pac_server() {
+        let server = PacServer::start(0, "function FindProxyForURL() {}".to_string())
+            .await
+            .unwrap();
+        assert!(server.url().ends_with(PAC_PATH));
+
+        let fetch = || async {
+            let mut stream = tokio::net::TcpStream::connect(server.addr).await.unwrap();
+            stream
+                .write_all(b"GET /proxy.pac HTTP/1.1\r\nHost: localhost\r\n\r\n")
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+
+        let response = fetch().await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(PAC_CONTENT_TYPE));
+        assert!(response.ends_with("function FindProxyForURL() {}"));
+
+        server.update("updated".to_string());
+        assert!(fetch().await.ends_with("\r\n\r\nupdated"));
+        server.stop();
+    }
+}
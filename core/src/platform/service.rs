@@ -0,0 +1,1142 @@
+//! Privileged helper service
+//!
+//! TUN mode needs root/administrator rights to create the interface and
+//! change routes, and running the whole app elevated is not an option. The
+//! helper is this crate's own binary run as `v8ray-core helper serve` by
+//! the system: a systemd unit on Linux, a LaunchDaemon on macOS and a
+//! scheduled task running as SYSTEM at boot on Windows (a real Windows
+//! service would need the service control handshake, which the CLI does
+//! not implement). Installing it is the only step that needs elevation.
+//!
+//! The helper listens on a loopback port and speaks one JSON line each way
+//! per connection. It first sends a random challenge; the client answers
+//! with `sha256(token ":" challenge)` next to its request, so the token
+//! shared through a file in the app data directory never crosses the
+//! socket. Only a fixed set of actions is accepted: starting and stopping
+//! the tunnel with the bundled tun2socks, and adding or removing single
+//! routes.
+//!
+//! The app's own files can be replaced by the user, so installing copies
+//! the helper and tun2socks into a directory only root/administrators can
+//! write to (see [`helper_install_dir`]), and the service runs those copies.
+
+use super::tun::{
+    cmd, find_tun2socks, run_command, DefaultRoute, TunConfig, TunDevice, TunPlatform,
+};
+use crate::control::auth::constant_time_eq;
+use crate::error::PlatformError;
+use crate::utils::crypto::generate_key;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// systemd unit and Windows task name
+pub const HELPER_SERVICE_NAME: &str = "v8ray-helper";
+
+/// macOS LaunchDaemon label
+pub const HELPER_LAUNCHD_LABEL: &str = "com.v8ray.helper";
+
+/// Token file name inside the app data directory
+pub const HELPER_TOKEN_FILE_NAME: &str = "helper_token";
+
+/// Loopback port the helper listens on
+pub const DEFAULT_HELPER_PORT: u16 = 47391;
+
+/// Time allowed for one request, including starting the tunnel
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest accepted line
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+
+/// Longest accepted interface name
+const MAX_INTERFACE_NAME_LENGTH: usize = 64;
+
+/// Privileged action requested from the helper
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum HelperRequest {
+    /// Check that the helper is running and the token is accepted
+    Ping,
+    /// Start the tunnel, replacing one already running
+    StartTun {
+        /// Tunnel configuration; the bundled tun2socks is always used
+        config: TunConfig,
+    },
+    /// Stop the tunnel and restore routes and DNS
+    StopTun,
+    /// Add or replace a route
+    AddRoute {
+        /// Destination address or network (`10.0.0.0/8`)
+        destination: String,
+        /// Next hop
+        gateway: Option<IpAddr>,
+        /// Outgoing interface
+        interface: Option<String>,
+    },
+    /// Remove a route
+    DeleteRoute {
+        /// Destination address or network
+        destination: String,
+        /// Outgoing interface
+        interface: Option<String>,
+    },
+}
+
+impl HelperRequest {
+    /// Check the request before anything runs as root
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            HelperRequest::Ping | HelperRequest::StopTun => Ok(()),
+            HelperRequest::StartTun { config } => {
+                if config.tun2socks_path.is_some() {
+                    return Err("A custom tun2socks binary is not allowed".to_string());
+                }
+                validate_interface(&config.name)
+            }
+            HelperRequest::AddRoute {
+                destination,
+                gateway,
+                interface,
+            } => {
+                validate_destination(destination)?;
+                if gateway.is_none() && interface.is_none() {
+                    return Err("A route needs a gateway or an interface".to_string());
+                }
+                interface.as_deref().map_or(Ok(()), validate_interface)
+            }
+            HelperRequest::DeleteRoute {
+                destination,
+                interface,
+            } => {
+                validate_destination(destination)?;
+                interface.as_deref().map_or(Ok(()), validate_interface)
+            }
+        }
+    }
+}
+
+/// Check that `name` can only be read as an interface name by route tools
+fn validate_interface(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_INTERFACE_NAME_LENGTH
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, ' ' | '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid interface name: {}", name))
+    }
+}
+
+/// Check that `destination` is an address with an optional prefix length
+fn validate_destination(destination: &str) -> Result<(), String> {
+    let invalid = || format!("Invalid route destination: {}", destination);
+    let (address, prefix) = match destination.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (destination, None),
+    };
+    let address: IpAddr = address.parse().map_err(|_| invalid())?;
+    if let Some(prefix) = prefix {
+        let max = if address.is_ipv4() { 32 } else { 128 };
+        match prefix.parse::<u8>() {
+            Ok(prefix) if prefix <= max => {}
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(())
+}
+
+/// Helper reply
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "result", rename_all = "snake_case")]
+pub enum HelperResponse {
+    /// Reply to [`HelperRequest::Ping`]
+    Pong {
+        /// Helper version
+        version: String,
+    },
+    /// The tunnel is up
+    TunStarted {
+        /// Default route before the tunnel was up
+        default_route: DefaultRoute,
+    },
+    /// The action succeeded
+    Done,
+    /// The action failed or was refused
+    Error {
+        /// Reason
+        message: String,
+    },
+}
+
+/// Request line sent by the client
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    /// `sha256(token ":" challenge)` in hex
+    auth: String,
+    /// Requested action
+    request: HelperRequest,
+}
+
+/// Answer to `challenge` proving knowledge of `token`
+fn challenge_response(token: &str, challenge: &str) -> String {
+    hex::encode(Sha256::digest(format!("{}:{}", token, challenge)))
+}
+
+/// Wrap a failure in [`PlatformError::Helper`]
+fn helper_error(message: impl std::fmt::Display) -> crate::V8RayError {
+    PlatformError::Helper(message.to_string()).into()
+}
+
+/// Build the command changing one route
+///
+/// Windows routes go through an interface, so one is required there.
+pub fn route_command(
+    platform: TunPlatform,
+    add: bool,
+    destination: &str,
+    gateway: Option<IpAddr>,
+    interface: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let ipv6 = destination.contains(':');
+    let gateway = gateway.map(|ip| ip.to_string());
+    let args = match platform {
+        TunPlatform::Linux => {
+            let mut args = cmd(&[
+                "ip",
+                "route",
+                if add { "replace" } else { "del" },
+                destination,
+            ]);
+            if let Some(gateway) = gateway.filter(|_| add) {
+                args.extend(cmd(&["via", &gateway]));
+            }
+            if let Some(interface) = interface {
+                args.extend(cmd(&["dev", interface]));
+            }
+            args
+        }
+        TunPlatform::MacOS => {
+            let mut args = cmd(&["route", "-n", if add { "add" } else { "delete" }]);
+            if ipv6 {
+                args.push("-inet6".to_string());
+            }
+            args.extend(cmd(&["-net", destination]));
+            match (gateway.filter(|_| add), interface) {
+                (Some(gateway), _) => args.push(gateway),
+                (None, Some(interface)) => args.extend(cmd(&["-interface", interface])),
+                (None, None) => {}
+            }
+            args
+        }
+        TunPlatform::Windows => {
+            let interface =
+                interface.ok_or_else(|| "Routes on Windows need an interface".to_string())?;
+            let prefix = if destination.contains('/') {
+                destination.to_string()
+            } else if ipv6 {
+                format!("{}/128", destination)
+            } else {
+                format!("{}/32", destination)
+            };
+            let mut args = cmd(&[
+                "netsh",
+                "interface",
+                if ipv6 { "ipv6" } else { "ipv4" },
+                if add { "add" } else { "delete" },
+                "route",
+                &format!("prefix={}", prefix),
+                &format!("interface={}", interface),
+            ]);
+            if let Some(gateway) = gateway.filter(|_| add) {
+                args.push(format!("nexthop={}", gateway));
+            }
+            args
+        }
+    };
+    Ok(args)
+}
+
+/// Helper side of the protocol, running as root/administrator
+pub struct HelperServer {
+    /// Token file shared with the app, read on each connection so the app
+    /// can rotate it
+    token_path: PathBuf,
+    /// tun2socks binary installed with the helper, None for the one next
+    /// to the helper binary
+    tun2socks: Option<PathBuf>,
+    /// Tunnel started on behalf of the app
+    tun: Mutex<Option<TunDevice>>,
+}
+
+impl HelperServer {
+    /// Create a server authenticating clients with the token in `token_path`
+    pub fn new<P: AsRef<Path>>(token_path: P) -> Self {
+        Self {
+            token_path: token_path.as_ref().to_path_buf(),
+            tun2socks: None,
+            tun: Mutex::new(None),
+        }
+    }
+
+    /// Start tunnels with the tun2socks binary at `path`
+    pub fn with_tun2socks(mut self, path: PathBuf) -> Self {
+        self.tun2socks = Some(path);
+        self
+    }
+
+    /// Listen on the loopback `port` (0 for any)
+    pub async fn bind(port: u16) -> crate::V8RayResult<TcpListener> {
+        TcpListener::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))
+            .await
+            .map_err(|e| helper_error(format!("Failed to listen on port {}: {}", port, e)))
+    }
+
+    /// Serve connections on `listener` until it fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> crate::V8RayResult<()> {
+        if let Ok(address) = listener.local_addr() {
+            info!("Privileged helper listening on {}", address);
+        }
+        loop {
+            let (stream, _) = listener.accept().await.map_err(helper_error)?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                match tokio::time::timeout(REQUEST_TIMEOUT, server.handle_client(stream)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!("Helper connection failed: {}", e),
+                    Err(_) => warn!("Helper connection timed out"),
+                }
+            });
+        }
+    }
+
+    /// Stop the tunnel, if any, before the helper exits
+    pub async fn shutdown(&self) {
+        if let Some(device) = self.tun.lock().await.take() {
+            device.stop().await;
+        }
+    }
+
+    /// Challenge the client, then run its request
+    async fn handle_client(&self, stream: TcpStream) -> crate::V8RayResult<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader).take(MAX_LINE_LENGTH);
+
+        let challenge = hex::encode(generate_key());
+        writer
+            .write_all(format!("{}\n", challenge).as_bytes())
+            .await
+            .map_err(helper_error)?;
+
+        let mut line = String::new();
+        reader.read_line(&mut line).await.map_err(helper_error)?;
+        let response = match serde_json::from_str::<Envelope>(&line) {
+            Ok(envelope) => {
+                let token = std::fs::read_to_string(&self.token_path)
+                    .map(|token| token.trim().to_string())
+                    .unwrap_or_default();
+                let expected = challenge_response(&token, &challenge);
+                if token.is_empty()
+                    || !constant_time_eq(envelope.auth.as_bytes(), expected.as_bytes())
+                {
+                    warn!("Rejected helper request with an invalid token");
+                    HelperResponse::Error {
+                        message: "Unauthorized".to_string(),
+                    }
+                } else {
+                    self.execute(envelope.request).await
+                }
+            }
+            Err(e) => HelperResponse::Error {
+                message: format!("Invalid request: {}", e),
+            },
+        };
+
+        let mut reply = serde_json::to_string(&response).map_err(helper_error)?;
+        reply.push('\n');
+        writer
+            .write_all(reply.as_bytes())
+            .await
+            .map_err(helper_error)
+    }
+
+    /// Run an authenticated request
+    pub async fn execute(&self, request: HelperRequest) -> HelperResponse {
+        if let Err(message) = request.validate() {
+            return HelperResponse::Error { message };
+        }
+        info!("Helper request: {:?}", request);
+
+        let result = match request {
+            HelperRequest::Ping => Ok(HelperResponse::Pong {
+                version: crate::version::VERSION.to_string(),
+            }),
+            HelperRequest::StartTun { config } => {
+                let config = match &self.tun2socks {
+                    Some(path) => config.with_tun2socks_path(path.clone()),
+                    None => config,
+                };
+                let mut tun = self.tun.lock().await;
+                // The app may have restarted without stopping its tunnel
+                if let Some(device) = tun.take() {
+                    device.stop().await;
+                }
+                TunDevice::start(config).await.map(|device| {
+                    let default_route = device.default_route().clone();
+                    *tun = Some(device);
+                    HelperResponse::TunStarted { default_route }
+                })
+            }
+            HelperRequest::StopTun => {
+                self.shutdown().await;
+                Ok(HelperResponse::Done)
+            }
+            HelperRequest::AddRoute {
+                destination,
+                gateway,
+                interface,
+            } => change_route(true, &destination, gateway, interface.as_deref()).await,
+            HelperRequest::DeleteRoute {
+                destination,
+                interface,
+            } => change_route(false, &destination, None, interface.as_deref()).await,
+        };
+        result.unwrap_or_else(|e| HelperResponse::Error {
+            message: e.to_string(),
+        })
+    }
+}
+
+/// Add or remove one route
+async fn change_route(
+    add: bool,
+    destination: &str,
+    gateway: Option<IpAddr>,
+    interface: Option<&str>,
+) -> crate::V8RayResult<HelperResponse> {
+    let platform = TunPlatform::current().ok_or_else(|| {
+        PlatformError::NotSupported("Routes are not supported on this platform".to_string())
+    })?;
+    let args = route_command(platform, add, destination, gateway, interface)
+        .map_err(PlatformError::Command)?;
+    run_command(&args).await?;
+    Ok(HelperResponse::Done)
+}
+
+/// App side of the protocol
+#[derive(Debug, Clone)]
+pub struct HelperClient {
+    /// Loopback port of the helper
+    port: u16,
+    /// Token shared with the helper
+    token: String,
+}
+
+impl HelperClient {
+    /// Create a client for the helper on `port`
+    pub fn new(port: u16, token: String) -> Self {
+        Self { port, token }
+    }
+
+    /// Send `request`, turning error replies into errors
+    pub async fn request(&self, request: &HelperRequest) -> crate::V8RayResult<HelperResponse> {
+        tokio::time::timeout(REQUEST_TIMEOUT, self.exchange(request))
+            .await
+            .map_err(|_| helper_error("Request timed out"))?
+    }
+
+    /// Answer the challenge and read the reply
+    async fn exchange(&self, request: &HelperRequest) -> crate::V8RayResult<HelperResponse> {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, self.port))
+            .await
+            .map_err(|e| helper_error(format!("Helper is not reachable: {}", e)))?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader).take(MAX_LINE_LENGTH);
+
+        let mut challenge = String::new();
+        reader
+            .read_line(&mut challenge)
+            .await
+            .map_err(helper_error)?;
+        let envelope = Envelope {
+            auth: challenge_response(&self.token, challenge.trim()),
+            request: request.clone(),
+        };
+        let mut line = serde_json::to_string(&envelope).map_err(helper_error)?;
+        line.push('\n');
+        writer
+            .write_all(line.as_bytes())
+            .await
+            .map_err(helper_error)?;
+
+        let mut reply = String::new();
+        reader.read_line(&mut reply).await.map_err(helper_error)?;
+        match serde_json::from_str(&reply).map_err(helper_error)? {
+            HelperResponse::Error { message } => Err(helper_error(message)),
+            response => Ok(response),
+        }
+    }
+
+    /// Check that the helper answers, returning its version
+    pub async fn ping(&self) -> crate::V8RayResult<String> {
+        match self.request(&HelperRequest::Ping).await? {
+            HelperResponse::Pong { version } => Ok(version),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Start the tunnel, returning the default route before it was up
+    pub async fn start_tun(&self, config: &TunConfig) -> crate::V8RayResult<DefaultRoute> {
+        let request = HelperRequest::StartTun {
+            config: config.clone(),
+        };
+        match self.request(&request).await? {
+            HelperResponse::TunStarted { default_route } => Ok(default_route),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    /// Stop the tunnel
+    pub async fn stop_tun(&self) -> crate::V8RayResult<()> {
+        self.request(&HelperRequest::StopTun).await.map(|_| ())
+    }
+
+    /// Add or replace a route
+    pub async fn add_route(
+        &self,
+        destination: &str,
+        gateway: Option<IpAddr>,
+        interface: Option<&str>,
+    ) -> crate::V8RayResult<()> {
+        let request = HelperRequest::AddRoute {
+            destination: destination.to_string(),
+            gateway,
+            interface: interface.map(str::to_string),
+        };
+        self.request(&request).await.map(|_| ())
+    }
+
+    /// Remove a route
+    pub async fn delete_route(
+        &self,
+        destination: &str,
+        interface: Option<&str>,
+    ) -> crate::V8RayResult<()> {
+        let request = HelperRequest::DeleteRoute {
+            destination: destination.to_string(),
+            interface: interface.map(str::to_string),
+        };
+        self.request(&request).await.map(|_| ())
+    }
+}
+
+/// Error for a reply not matching the request
+fn unexpected(response: HelperResponse) -> crate::V8RayError {
+    helper_error(format!("Unexpected reply: {:?}", response))
+}
+
+/// Root-owned directory the helper and tun2socks are installed to
+pub fn helper_install_dir(platform: TunPlatform) -> PathBuf {
+    match platform {
+        TunPlatform::Linux | TunPlatform::MacOS => PathBuf::from("/usr/local/libexec/v8ray"),
+        TunPlatform::Windows => {
+            let program_files =
+                std::env::var("ProgramFiles").unwrap_or_else(|_| r"C:\Program Files".to_string());
+            PathBuf::from(format!(r"{}\V8Ray\helper", program_files))
+        }
+    }
+}
+
+/// Path of `file_name` in the helper install directory
+fn installed_path(platform: TunPlatform, file_name: &str) -> PathBuf {
+    let dir = helper_install_dir(platform);
+    match platform {
+        TunPlatform::Windows => PathBuf::from(format!(r"{}\{}", dir.display(), file_name)),
+        _ => dir.join(file_name),
+    }
+}
+
+/// File name of a binary on `platform`
+fn binary_name(platform: TunPlatform, name: &str) -> String {
+    match platform {
+        TunPlatform::Windows => format!("{}.exe", name),
+        _ => name.to_string(),
+    }
+}
+
+/// Files and commands installing the helper on one platform
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceDefinition {
+    /// Directory the binaries are installed to, removed on uninstall
+    pub install_dir: PathBuf,
+    /// Binaries copied into the install directory, as source and target
+    pub binaries: Vec<(PathBuf, PathBuf)>,
+    /// Service file and its content, None where a command registers it
+    pub file: Option<(PathBuf, String)>,
+    /// Commands registering and starting the service, run in order
+    pub install: Vec<Vec<String>>,
+    /// Commands stopping and removing the service, failures are ignored
+    pub uninstall: Vec<Vec<String>>,
+}
+
+impl ServiceDefinition {
+    /// Describe a helper installed to [`helper_install_dir`], listening on
+    /// `port` with the token in `token_path`
+    ///
+    /// Add the binaries to copy with [`Self::with_binaries`] before
+    /// installing.
+    pub fn new(platform: TunPlatform, port: u16, token_path: &Path) -> Self {
+        let install_dir = helper_install_dir(platform);
+        let args = vec![
+            installed_path(platform, &binary_name(platform, "v8ray-core"))
+                .display()
+                .to_string(),
+            "helper".to_string(),
+            "serve".to_string(),
+            "--port".to_string(),
+            port.to_string(),
+            "--token-file".to_string(),
+            token_path.display().to_string(),
+            "--tun2socks".to_string(),
+            installed_path(platform, &binary_name(platform, "tun2socks"))
+                .display()
+                .to_string(),
+        ];
+        let service = Self::service(platform, &args);
+        Self {
+            install_dir,
+            ..service
+        }
+    }
+
+    /// Copy `executable` and `tun2socks` into the install directory
+    pub fn with_binaries(
+        mut self,
+        platform: TunPlatform,
+        executable: &Path,
+        tun2socks: &Path,
+    ) -> Self {
+        self.binaries = vec![
+            (
+                executable.to_path_buf(),
+                installed_path(platform, &binary_name(platform, "v8ray-core")),
+            ),
+            (
+                tun2socks.to_path_buf(),
+                installed_path(platform, &binary_name(platform, "tun2socks")),
+            ),
+        ];
+        self
+    }
+
+    /// Copy the running executable and the bundled tun2socks
+    pub fn with_bundled_binaries(self, platform: TunPlatform) -> crate::V8RayResult<Self> {
+        let executable = std::env::current_exe()
+            .map_err(|e| helper_error(format!("Failed to locate the executable: {}", e)))?;
+        let tun2socks = find_tun2socks()?;
+        let mut definition = self.with_binaries(platform, &executable, &tun2socks);
+        // tun2socks loads WinTUN from its own directory
+        let wintun = tun2socks.with_file_name("wintun.dll");
+        if platform == TunPlatform::Windows && wintun.exists() {
+            definition
+                .binaries
+                .push((wintun, installed_path(platform, "wintun.dll")));
+        }
+        Ok(definition)
+    }
+
+    /// Service registration running `args`
+    fn service(platform: TunPlatform, args: &[String]) -> Self {
+        let empty = Self {
+            install_dir: PathBuf::new(),
+            binaries: Vec::new(),
+            file: None,
+            install: Vec::new(),
+            uninstall: Vec::new(),
+        };
+        match platform {
+            TunPlatform::Linux => Self {
+                file: Some((
+                    PathBuf::from(format!(
+                        "/etc/systemd/system/{}.service",
+                        HELPER_SERVICE_NAME
+                    )),
+                    systemd_unit(args),
+                )),
+                install: vec![
+                    cmd(&["systemctl", "daemon-reload"]),
+                    cmd(&["systemctl", "enable", "--now", HELPER_SERVICE_NAME]),
+                ],
+                uninstall: vec![
+                    cmd(&["systemctl", "disable", "--now", HELPER_SERVICE_NAME]),
+                    cmd(&["systemctl", "daemon-reload"]),
+                ],
+                ..empty
+            },
+            TunPlatform::MacOS => {
+                let path = format!("/Library/LaunchDaemons/{}.plist", HELPER_LAUNCHD_LABEL);
+                Self {
+                    install: vec![cmd(&["launchctl", "bootstrap", "system", &path])],
+                    uninstall: vec![cmd(&[
+                        "launchctl",
+                        "bootout",
+                        &format!("system/{}", HELPER_LAUNCHD_LABEL),
+                    ])],
+                    file: Some((PathBuf::from(path), launchd_plist(args))),
+                    ..empty
+                }
+            }
+            TunPlatform::Windows => {
+                let command_line = args
+                    .iter()
+                    .map(|arg| quote_windows_arg(arg))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                Self {
+                    file: None,
+                    install: vec![
+                        cmd(&[
+                            "schtasks",
+                            "/Create",
+                            "/F",
+                            "/TN",
+                            HELPER_SERVICE_NAME,
+                            "/SC",
+                            "ONSTART",
+                            "/RU",
+                            "SYSTEM",
+                            "/RL",
+                            "HIGHEST",
+                            "/TR",
+                            &command_line,
+                        ]),
+                        cmd(&["schtasks", "/Run", "/TN", HELPER_SERVICE_NAME]),
+                    ],
+                    uninstall: vec![
+                        cmd(&["schtasks", "/End", "/TN", HELPER_SERVICE_NAME]),
+                        cmd(&["schtasks", "/Delete", "/F", "/TN", HELPER_SERVICE_NAME]),
+                    ],
+                    ..empty
+                }
+            }
+        }
+    }
+}
+
+/// systemd unit running `args`
+fn systemd_unit(args: &[String]) -> String {
+    let exec_start = args
+        .iter()
+        .map(|arg| {
+            // systemd expands specifiers starting with %
+            let arg = arg.replace('%', "%%");
+            if arg.contains(char::is_whitespace) || arg.contains('"') {
+                format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                arg
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        "[Unit]\n\
+         Description=V8Ray privileged helper\n\
+         After=network.target\n\
+         \n\
+         [Service]\n\
+         Type=simple\n\
+         ExecStart={}\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=multi-user.target\n",
+        exec_start
+    )
+}
+
+/// LaunchDaemon property list running `args`
+fn launchd_plist(args: &[String]) -> String {
+    let arguments: String = args
+        .iter()
+        .map(|arg| format!("        <string>{}</string>\n", xml_escape(arg)))
+        .collect();
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n\
+         <dict>\n\
+         \x20   <key>Label</key>\n\
+         \x20   <string>{}</string>\n\
+         \x20   <key>ProgramArguments</key>\n\
+         \x20   <array>\n\
+         {}\
+         \x20   </array>\n\
+         \x20   <key>RunAtLoad</key>\n\
+         \x20   <true/>\n\
+         \x20   <key>KeepAlive</key>\n\
+         \x20   <true/>\n\
+         </dict>\n\
+         </plist>\n",
+        HELPER_LAUNCHD_LABEL, arguments
+    )
+}
+
+/// Escape text for an XML element
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Quote an argument for a Windows command line
+fn quote_windows_arg(arg: &str) -> String {
+    if arg.is_empty() || arg.contains(char::is_whitespace) {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Install and start the helper service
+///
+/// Requires administrator/root privileges.
+pub async fn install(definition: &ServiceDefinition) -> crate::V8RayResult<()> {
+    if !super::get_platform().has_admin_privileges()? {
+        return Err(PlatformError::Permission(
+            "Installing the helper requires administrator privileges".to_string(),
+        )
+        .into());
+    }
+
+    install_binaries(definition)?;
+    if let Some((path, content)) = &definition.file {
+        std::fs::write(path, content)
+            .map_err(|e| helper_error(format!("Failed to write {}: {}", path.display(), e)))?;
+    }
+    for args in &definition.install {
+        run_command(args).await?;
+    }
+    info!("Installed privileged helper");
+    Ok(())
+}
+
+/// Stop and remove the helper service
+///
+/// Steps that fail, e.g. because the service is not installed, are skipped.
+pub async fn uninstall(definition: &ServiceDefinition) -> crate::V8RayResult<()> {
+    if !super::get_platform().has_admin_privileges()? {
+        return Err(PlatformError::Permission(
+            "Removing the helper requires administrator privileges".to_string(),
+        )
+        .into());
+    }
+
+    for args in &definition.uninstall {
+        if let Err(e) = run_command(args).await {
+            warn!("Helper uninstall step failed: {}", e);
+        }
+    }
+    if let Some((path, _)) = &definition.file {
+        if path.exists() {
+            std::fs::remove_file(path)
+                .map_err(|e| helper_error(format!("Failed to remove {}: {}", path.display(), e)))?;
+        }
+    }
+    if definition.install_dir.exists() {
+        std::fs::remove_dir_all(&definition.install_dir).map_err(|e| {
+            helper_error(format!(
+                "Failed to remove {}: {}",
+                definition.install_dir.display(),
+                e
+            ))
+        })?;
+    }
+    info!("Removed privileged helper");
+    Ok(())
+}
+
+/// Copy the binaries into the install directory, writable only by the
+/// installing root/administrator
+fn install_binaries(definition: &ServiceDefinition) -> crate::V8RayResult<()> {
+    if definition.binaries.is_empty() {
+        return Err(helper_error("No helper binaries to install"));
+    }
+    std::fs::create_dir_all(&definition.install_dir).map_err(|e| {
+        helper_error(format!(
+            "Failed to create {}: {}",
+            definition.install_dir.display(),
+            e
+        ))
+    })?;
+    for (source, target) in &definition.binaries {
+        // Replacing rather than writing in place also works while an older
+        // helper runs the target
+        let _ = std::fs::remove_file(target);
+        std::fs::copy(source, target).map_err(|e| {
+            helper_error(format!(
+                "Failed to copy {} to {}: {}",
+                source.display(),
+                target.display(),
+                e
+            ))
+        })?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let paths = std::iter::once(&definition.install_dir)
+            .chain(definition.binaries.iter().map(|(_, target)| target));
+        for path in paths {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755)).map_err(
+                |e| helper_error(format!("Failed to protect {}: {}", path.display(), e)),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Find the bundled core binary serving as the helper
+pub(crate) fn find_helper_binary() -> crate::V8RayResult<PathBuf> {
+    let binary_name = if cfg!(windows) {
+        "v8ray-core.exe"
+    } else {
+        "v8ray-core"
+    };
+
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|path| path.parent().map(|dir| dir.to_path_buf()));
+    if let Some(exe_dir) = exe_dir {
+        for candidate in [
+            exe_dir.join("bin").join(binary_name),
+            exe_dir.join(binary_name),
+        ] {
+            if candidate.exists() {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    Err(helper_error("Bundled helper binary not found"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_request() {
+        assert!(HelperRequest::Ping.validate().is_ok());
+        assert!(HelperRequest::StartTun {
+            config: TunConfig::new(1080)
+        }
+        .validate()
+        .is_ok());
+        assert!(HelperRequest::StartTun {
+            config: TunConfig::new(1080).with_tun2socks_path("/tmp/evil".into())
+        }
+        .validate()
+        .is_err());
+        assert!(HelperRequest::StartTun {
+            config: TunConfig::new(1080).with_name("-f; rm".to_string())
+        }
+        .validate()
+        .is_err());
+
+        let route = |destination: &str, gateway: Option<&str>, interface: Option<&str>| {
+            HelperRequest::AddRoute {
+                destination: destination.to_string(),
+                gateway: gateway.map(|ip| ip.parse().unwrap()),
+                interface: interface.map(str::to_string),
+            }
+            .validate()
+        };
+        assert!(route("10.0.0.0/8", Some("192.168.1.1"), None).is_ok());
+        assert!(route("2001:db8::/32", None, Some("utun3")).is_ok());
+        assert!(route("1.2.3.4", None, Some("Wi-Fi 2")).is_ok());
+        assert!(route("10.0.0.0/33", Some("192.168.1.1"), None).is_err());
+        assert!(route("example.com", Some("192.168.1.1"), None).is_err());
+        assert!(route("10.0.0.0/8", None, None).is_err());
+        assert!(route("10.0.0.0/8", None, Some("--help")).is_err());
+    }
+
+    #[test]
+    fn test_route_command() {
+        let gateway = Some("192.168.1.1".parse().unwrap());
+        assert_eq!(
+            route_command(
+                TunPlatform::Linux,
+                true,
+                "10.0.0.0/8",
+                gateway,
+                Some("eth0")
+            )
+            .unwrap(),
+            cmd(&[
+                "ip",
+                "route",
+                "replace",
+                "10.0.0.0/8",
+                "via",
+                "192.168.1.1",
+                "dev",
+                "eth0"
+            ])
+        );
+        assert_eq!(
+            route_command(TunPlatform::Linux, false, "10.0.0.0/8", None, None).unwrap(),
+            cmd(&["ip", "route", "del", "10.0.0.0/8"])
+        );
+        assert_eq!(
+            route_command(TunPlatform::MacOS, true, "10.0.0.0/8", gateway, None).unwrap(),
+            cmd(&["route", "-n", "add", "-net", "10.0.0.0/8", "192.168.1.1"])
+        );
+        assert_eq!(
+            route_command(
+                TunPlatform::MacOS,
+                true,
+                "2001:db8::/32",
+                None,
+                Some("utun3")
+            )
+            .unwrap(),
+            cmd(&[
+                "route",
+                "-n",
+                "add",
+                "-inet6",
+                "-net",
+                "2001:db8::/32",
+                "-interface",
+                "utun3"
+            ])
+        );
+        assert_eq!(
+            route_command(
+                TunPlatform::Windows,
+                true,
+                "1.2.3.4",
+                gateway,
+                Some("Wi-Fi")
+            )
+            .unwrap(),
+            cmd(&[
+                "netsh",
+                "interface",
+                "ipv4",
+                "add",
+                "route",
+                "prefix=1.2.3.4/32",
+                "interface=Wi-Fi",
+                "nexthop=192.168.1.1"
+            ])
+        );
+        assert!(route_command(TunPlatform::Windows, true, "1.2.3.4", gateway, None).is_err());
+    }
+
+    #[test]
+    fn test_service_definition() {
+        let token = Path::new("/home/me/.local/share/v8ray/helper_token");
+
+        let linux = ServiceDefinition::new(TunPlatform::Linux, 47391, token).with_binaries(
+            TunPlatform::Linux,
+            Path::new("/home/me/V8Ray/bin/v8ray-core"),
+            Path::new("/home/me/V8Ray/bin/tun2socks"),
+        );
+        assert_eq!(linux.install_dir, Path::new("/usr/local/libexec/v8ray"));
+        assert_eq!(
+            linux.binaries[1],
+            (
+                PathBuf::from("/home/me/V8Ray/bin/tun2socks"),
+                PathBuf::from("/usr/local/libexec/v8ray/tun2socks")
+            )
+        );
+        let (path, unit) = linux.file.unwrap();
+        assert_eq!(path, Path::new("/etc/systemd/system/v8ray-helper.service"));
+        assert!(unit.contains(
+            "ExecStart=/usr/local/libexec/v8ray/v8ray-core helper serve --port 47391 \
+             --token-file /home/me/.local/share/v8ray/helper_token \
+             --tun2socks /usr/local/libexec/v8ray/tun2socks\n"
+        ));
+        assert_eq!(
+            linux.install.last().unwrap(),
+            &cmd(&["systemctl", "enable", "--now", "v8ray-helper"])
+        );
+
+        let macos = ServiceDefinition::new(
+            TunPlatform::MacOS,
+            47391,
+            Path::new("/Users/me & co/v8ray/helper_token"),
+        );
+        let (path, plist) = macos.file.unwrap();
+        assert_eq!(
+            path,
+            Path::new("/Library/LaunchDaemons/com.v8ray.helper.plist")
+        );
+        assert!(plist.contains("<string>/usr/local/libexec/v8ray/v8ray-core</string>"));
+        assert!(plist.contains("<string>/Users/me &amp; co/v8ray/helper_token</string>"));
+        assert!(plist.contains("<string>com.v8ray.helper</string>"));
+
+        let windows = ServiceDefinition::new(
+            TunPlatform::Windows,
+            47391,
+            Path::new(r"C:\Users\me\AppData\Roaming\v8ray\helper_token"),
+        );
+        assert!(windows.file.is_none());
+        let dir = helper_install_dir(TunPlatform::Windows)
+            .display()
+            .to_string();
+        assert!(dir.ends_with(r"\V8Ray\helper"));
+        let command_line = format!(
+            r#"{} helper serve --port 47391 --token-file C:\Users\me\AppData\Roaming\v8ray\helper_token --tun2socks {}"#,
+            quote_windows_arg(&format!(r"{}\v8ray-core.exe", dir)),
+            quote_windows_arg(&format!(r"{}\tun2socks.exe", dir)),
+        );
+        assert!(windows.install[0].contains(&command_line));
+    }
+
+    #[tokio::test]
+    async fn test_client_server() {
+        let dir = std::env::temp_dir().join(format!("v8ray_helper_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let token_path = dir.join(HELPER_TOKEN_FILE_NAME);
+        std::fs::write(&token_path, "secret-token\n").unwrap();
+
+        let listener = HelperServer::bind(0).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Arc::new(HelperServer::new(&token_path));
+        let task = tokio::spawn(server.serve(listener));
+
+        let client = HelperClient::new(port, "secret-token".to_string());
+        assert_eq!(client.ping().await.unwrap(), crate::version::VERSION);
+        client.stop_tun().await.unwrap();
+        // Refused before anything runs
+        assert!(client
+            .add_route("10.0.0.0/8", None, None)
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("gateway or an interface"));
+
+        let intruder = HelperClient::new(port, "wrong".to_string());
+        assert!(intruder
+            .ping()
+            .await
+            .unwrap_err()
+            .to_string()
+            .contains("Unauthorized"));
+
+        task.abort();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
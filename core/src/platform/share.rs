@@ -0,0 +1,326 @@
+//! LAN file sharing
+//!
+//! Devices such as TV boxes and consoles can often only be configured with
+//! a URL. This module runs a small HTTP server on all interfaces that
+//! serves published files (the PAC script, exported profiles) to them.
+//!
+//! Every URL carries a random token as its first path segment; requests
+//! without it are rejected, so only devices that were given a URL can read
+//! the files.
+
+use crate::control::auth::constant_time_eq;
+use crate::error::PlatformError;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// Default port of the share server
+pub const DEFAULT_SHARE_PORT: u16 = 18899;
+
+/// Longest request line read before the request is rejected
+const MAX_REQUEST_LINE: usize = 4 * 1024;
+
+/// Time a client has to send its request line
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A published file
+#[derive(Debug, Clone)]
+struct SharedFile {
+    /// MIME type sent with the file
+    content_type: String,
+    /// File content
+    body: String,
+}
+
+/// Published files by name
+type SharedFiles = Arc<RwLock<HashMap<String, SharedFile>>>;
+
+/// HTTP server sharing files with other devices on the LAN
+pub struct ShareServer {
+    /// Address the server listens on
+    addr: SocketAddr,
+    /// Token required as the first path segment
+    token: String,
+    /// Published files
+    files: SharedFiles,
+    /// Accept loop
+    task: JoinHandle<()>,
+}
+
+impl ShareServer {
+    /// Listen on all interfaces on `port` (0 picks a free port)
+    pub async fn start(port: u16) -> crate::V8RayResult<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))
+            .await
+            .map_err(|e| PlatformError::Command(format!("Failed to start share server: {}", e)))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| PlatformError::Command(format!("Failed to start share server: {}", e)))?;
+
+        let token = uuid::Uuid::new_v4().simple().to_string();
+        let files: SharedFiles = Arc::new(RwLock::new(HashMap::new()));
+        let served = Arc::clone(&files);
+        let expected = token.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                tracing::debug!("Share request from {}", peer);
+                tokio::spawn(serve(stream, expected.clone(), Arc::clone(&served)));
+            }
+        });
+
+        tracing::info!("LAN share server listening on {}", addr);
+        Ok(Self {
+            addr,
+            token,
+            files,
+            task,
+        })
+    }
+
+    /// Port the server listens on
+    pub fn port(&self) -> u16 {
+        self.addr.port()
+    }
+
+    /// Access token
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Publish (or replace) a file under `name`
+    pub fn publish(&self, name: &str, content_type: &str, body: String) {
+        let file = SharedFile {
+            content_type: content_type.to_string(),
+            body,
+        };
+        self.files
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(name.to_string(), file);
+    }
+
+    /// Stop serving a file, returning whether it was published
+    pub fn unpublish(&self, name: &str) -> bool {
+        self.files
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(name)
+            .is_some()
+    }
+
+    /// Names of the published files
+    pub fn files(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .files
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .keys()
+            .cloned()
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// URL other devices use to fetch `name` through `host`
+    pub fn url(&self, host: IpAddr, name: &str) -> String {
+        format!(
+            "http://{}/{}/{}",
+            SocketAddr::new(host, self.port()),
+            self.token,
+            name
+        )
+    }
+
+    /// Stop serving
+    pub fn stop(self) {
+        tracing::info!("Stopping LAN share server on {}", self.addr);
+        self.task.abort();
+    }
+}
+
+impl Drop for ShareServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Read the request line, giving up on slow or oversized requests
+async fn read_request_line(stream: &mut TcpStream) -> std::io::Result<String> {
+    let mut line = Vec::new();
+    let mut buf = [0u8; 1024];
+    tokio::time::timeout(REQUEST_TIMEOUT, async {
+        while !line.windows(2).any(|window| window == b"\r\n") {
+            if line.len() >= MAX_REQUEST_LINE {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "request line too long",
+                ));
+            }
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+            line.extend_from_slice(&buf[..n]);
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+    let line = String::from_utf8_lossy(&line);
+    Ok(line.lines().next().unwrap_or_default().to_string())
+}
+
+/// Answer one request
+async fn serve(mut stream: TcpStream, token: String, files: SharedFiles) {
+    let Ok(request) = read_request_line(&mut stream).await else {
+        return;
+    };
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    // Query strings are ignored
+    let path = path.split('?').next().unwrap_or_default();
+    let mut segments = path.trim_start_matches('/').splitn(2, '/');
+    let request_token = segments.next().unwrap_or_default();
+    let name = segments.next().unwrap_or_default();
+
+    let response = if method != "GET" && method != "HEAD" {
+        response("405 Method Not Allowed", "text/plain", "")
+    } else if !constant_time_eq(request_token.as_bytes(), token.as_bytes()) {
+        response("403 Forbidden", "text/plain", "")
+    } else {
+        let file = files
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(name)
+            .cloned();
+        match file {
+            Some(file) if method == "HEAD" => {
+                let mut head = response("200 OK", &file.content_type, &file.body);
+                head.truncate(head.len() - file.body.len());
+                head
+            }
+            Some(file) => response("200 OK", &file.content_type, &file.body),
+            None => response("404 Not Found", "text/plain", ""),
+        }
+    };
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Build an HTTP response closing the connection
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Address of this machine on the LAN
+///
+/// Picks the source address of the default route; no packet is sent.
+pub fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(8, 8, 8, 8), 53)).ok()?;
+    let ip = socket.local_addr().ok()?.ip();
+    (!ip.is_unspecified() && !ip.is_loopback()).then_some(ip)
+}
+
+/// How to let other devices reach `port` through the local firewall
+pub fn firewall_hint(port: u16) -> Option<String> {
+    if cfg!(target_os = "windows") {
+        Some(format!(
+            "netsh advfirewall firewall add rule name=\"{} LAN share\" dir=in action=allow protocol=TCP localport={}",
+            crate::version::APP_NAME,
+            port
+        ))
+    } else if cfg!(target_os = "macos") {
+        Some(format!(
+            "Allow incoming connections for {} in System Settings > Network > Firewall",
+            crate::version::APP_NAME
+        ))
+    } else if cfg!(target_os = "linux") {
+        Some(format!(
+            "sudo ufw allow {0}/tcp (ufw) or sudo firewall-cmd --add-port={0}/tcp (firewalld)",
+            port
+        ))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(port: u16, path: &str) -> String {
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_share_server() {
+        let server = ShareServer::start(0).await.unwrap();
+        server.publish(
+            "proxy.pac",
+            "application/x-ns-proxy-autoconfig",
+            "pac".to_string(),
+        );
+        assert_eq!(server.files(), vec!["proxy.pac"]);
+
+        let url = server.url(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 2)), "proxy.pac");
+        assert_eq!(
+            url,
+            format!(
+                "http://192.168.1.2:{}/{}/proxy.pac",
+                server.port(),
+                server.token()
+            )
+        );
+
+        let path = format!("/{}/proxy.pac", server.token());
+        let response = get(server.port(), &path).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\npac"));
+
+        assert!(get(server.port(), "/wrong/proxy.pac").await.contains("403"));
+        assert!(get(server.port(), "/proxy.pac").await.contains("403"));
+        let missing = format!("/{}/profile.yaml", server.token());
+        assert!(get(server.port(), &missing).await.contains("404"));
+
+        // A request line split across writes is read whole
+        let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, server.port()))
+            .await
+            .unwrap();
+        stream.write_all(b"GET /").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stream
+            .write_all(format!("{}/proxy.pac HTTP/1.1\r\n\r\n", server.token()).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+
+        assert!(server.unpublish("proxy.pac"));
+        assert!(get(server.port(), &path).await.contains("404"));
+        server.stop();
+    }
+}
@@ -0,0 +1,911 @@
+//! TUN Mode
+//!
+//! Captures all traffic of the device through a TUN interface so apps that
+//! ignore the system proxy are proxied too. The interface is created and
+//! served by a bundled `tun2socks` process (utun on macOS, /dev/net/tun on
+//! Linux, WinTUN on Windows), which forwards every TCP/UDP flow to the local
+//! SOCKS inbound of Xray.
+//!
+//! Routes for `0.0.0.0/1` and `128.0.0.0/1` point at the interface, which
+//! overrides the default route without replacing it. The proxy servers are
+//! routed through the original gateway so the tunnel does not capture its
+//! own traffic. Xray's direct outbound must be bound to the physical
+//! interface (see [`crate::xray::XrayCore::set_outbound_interface`]) for the
+//! same reason.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+// Windows-specific imports for hiding console window
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+use crate::error::PlatformError;
+
+/// Default TUN interface address, from the 198.18.0.0/15 benchmarking range
+pub const DEFAULT_TUN_ADDRESS: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 1);
+
+/// Default TUN interface prefix length
+pub const DEFAULT_TUN_PREFIX_LEN: u8 = 15;
+
+/// Default TUN interface MTU
+pub const DEFAULT_TUN_MTU: u32 = 1500;
+
+/// How long to wait for tun2socks to bring up the interface
+const DEVICE_STARTUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Interval between interface readiness checks
+const DEVICE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Default TUN interface name for the current platform
+///
+/// macOS only accepts `utun<N>` names.
+pub fn default_tun_name() -> String {
+    if cfg!(target_os = "macos") {
+        "utun233".to_string()
+    } else if cfg!(windows) {
+        "V8Ray".to_string()
+    } else {
+        "v8ray0".to_string()
+    }
+}
+
+/// TUN mode configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TunConfig {
+    /// Interface name
+    pub name: String,
+    /// Interface address
+    pub address: Ipv4Addr,
+    /// Interface prefix length
+    pub prefix_len: u8,
+    /// Interface MTU
+    pub mtu: u32,
+    /// DNS servers used while the tunnel is up, queried through the tunnel
+    pub dns_servers: Vec<IpAddr>,
+    /// Local SOCKS inbound port packets are forwarded to
+    pub socks_port: u16,
+    /// Addresses routed through the original gateway (the proxy servers)
+    pub bypass_addresses: Vec<IpAddr>,
+    /// tun2socks binary, searched next to the application if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tun2socks_path: Option<PathBuf>,
+}
+
+impl TunConfig {
+    /// Create a configuration forwarding to the SOCKS inbound on `socks_port`
+    pub fn new(socks_port: u16) -> Self {
+        Self {
+            name: default_tun_name(),
+            address: DEFAULT_TUN_ADDRESS,
+            prefix_len: DEFAULT_TUN_PREFIX_LEN,
+            mtu: DEFAULT_TUN_MTU,
+            dns_servers: vec![
+                IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)),
+                IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+            ],
+            socks_port,
+            bypass_addresses: Vec::new(),
+            tun2socks_path: None,
+        }
+    }
+
+    /// Set the interface name
+    pub fn with_name(mut self, name: String) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Set the interface address and prefix length
+    pub fn with_address(mut self, address: Ipv4Addr, prefix_len: u8) -> Self {
+        self.address = address;
+        self.prefix_len = prefix_len;
+        self
+    }
+
+    /// Set the interface MTU
+    pub fn with_mtu(mut self, mtu: u32) -> Self {
+        self.mtu = mtu;
+        self
+    }
+
+    /// Set the DNS servers used while the tunnel is up
+    pub fn with_dns_servers(mut self, dns_servers: Vec<IpAddr>) -> Self {
+        self.dns_servers = dns_servers;
+        self
+    }
+
+    /// Set the addresses routed around the tunnel
+    pub fn with_bypass_addresses(mut self, bypass_addresses: Vec<IpAddr>) -> Self {
+        self.bypass_addresses = bypass_addresses;
+        self
+    }
+
+    /// Set the tun2socks binary
+    pub fn with_tun2socks_path(mut self, path: PathBuf) -> Self {
+        self.tun2socks_path = Some(path);
+        self
+    }
+
+    /// Netmask of the interface address
+    pub fn netmask(&self) -> Ipv4Addr {
+        let bits = 32u32.saturating_sub(u32::from(self.prefix_len));
+        Ipv4Addr::from(u32::MAX.checked_shl(bits).unwrap_or(0))
+    }
+
+    /// Arguments for tun2socks
+    pub fn tun2socks_args(&self) -> Vec<String> {
+        tun2socks_args(&format!("tun://{}", self.name), self.socks_port, self.mtu)
+    }
+}
+
+/// Arguments for tun2socks serving `device` and forwarding to the SOCKS
+/// inbound on `socks_port`
+pub(crate) fn tun2socks_args(device: &str, socks_port: u16, mtu: u32) -> Vec<String> {
+    vec![
+        "-device".to_string(),
+        device.to_string(),
+        "-proxy".to_string(),
+        format!("socks5://127.0.0.1:{}", socks_port),
+        "-mtu".to_string(),
+        mtu.to_string(),
+        "-loglevel".to_string(),
+        "warning".to_string(),
+    ]
+}
+
+/// Number of stderr lines kept for reporting an early tun2socks exit
+const STDERR_TAIL_LINES: usize = 20;
+
+/// Forward the stderr of a tun2socks process to the log
+///
+/// The pipe is read until the process closes it, so tun2socks never blocks
+/// on a full pipe. The task returns the last lines, for reporting why the
+/// process exited.
+pub(crate) fn drain_stderr(process: &mut Child) -> Option<JoinHandle<String>> {
+    let stderr = process.stderr.take()?;
+    Some(tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        let mut tail = VecDeque::with_capacity(STDERR_TAIL_LINES);
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::warn!("tun2socks: {}", line);
+            if tail.len() == STDERR_TAIL_LINES {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+        Vec::from(tail).join("\n")
+    }))
+}
+
+/// Default route of the system before the tunnel is up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefaultRoute {
+    /// Gateway address
+    pub gateway: IpAddr,
+    /// Physical interface the default route goes through
+    pub interface: String,
+}
+
+/// Parse `ip -4 route show default` output
+///
+/// Example: `default via 192.168.1.1 dev eth0 proto dhcp metric 100`
+pub fn parse_linux_default_route(output: &str) -> Option<DefaultRoute> {
+    output.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() != Some(&"default") {
+            return None;
+        }
+        let value = |key: &str| {
+            fields
+                .iter()
+                .position(|f| *f == key)
+                .and_then(|i| fields.get(i + 1))
+        };
+        Some(DefaultRoute {
+            gateway: value("via")?.parse().ok()?,
+            interface: value("dev")?.to_string(),
+        })
+    })
+}
+
+/// Parse `route -n get default` output
+pub fn parse_macos_default_route(output: &str) -> Option<DefaultRoute> {
+    let value = |key: &str| {
+        output.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            (name.trim() == key).then(|| value.trim().to_string())
+        })
+    };
+    Some(DefaultRoute {
+        gateway: value("gateway")?.parse().ok()?,
+        interface: value("interface")?,
+    })
+}
+
+/// Parse the `<gateway> <interface alias>` line printed by the PowerShell
+/// default route query
+pub fn parse_windows_default_route(output: &str) -> Option<DefaultRoute> {
+    output.lines().find_map(|line| {
+        let (gateway, interface) = line.trim().split_once(' ')?;
+        Some(DefaultRoute {
+            gateway: gateway.parse().ok()?,
+            interface: interface.trim().to_string(),
+        })
+    })
+}
+
+/// PowerShell query printing the lowest-metric IPv4 default route
+const WINDOWS_DEFAULT_ROUTE_QUERY: &str = "Get-NetRoute -DestinationPrefix 0.0.0.0/0 \
+     | Sort-Object RouteMetric | Select-Object -First 1 \
+     | ForEach-Object { \"$($_.NextHop) $($_.InterfaceAlias)\" }";
+
+/// Desktop platforms with TUN support
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TunPlatform {
+    /// Linux (/dev/net/tun, iproute2, systemd-resolved)
+    Linux,
+    /// macOS (utun, route, networksetup)
+    MacOS,
+    /// Windows (WinTUN, netsh)
+    Windows,
+}
+
+/// Commands that bring the tunnel's routes and DNS up and down
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TunCommands {
+    /// Run in order once the interface exists
+    pub setup: Vec<Vec<String>>,
+    /// Run in order to restore the system, failures are ignored
+    pub teardown: Vec<Vec<String>>,
+}
+
+impl TunPlatform {
+    /// Platform of the running build, if TUN mode is supported on it
+    pub fn current() -> Option<Self> {
+        if cfg!(target_os = "linux") {
+            Some(TunPlatform::Linux)
+        } else if cfg!(target_os = "macos") {
+            Some(TunPlatform::MacOS)
+        } else if cfg!(windows) {
+            Some(TunPlatform::Windows)
+        } else {
+            None
+        }
+    }
+
+    /// Command printing the current default route
+    fn default_route_command(&self) -> Vec<String> {
+        let args: &[&str] = match self {
+            TunPlatform::Linux => &["ip", "-4", "route", "show", "default"],
+            TunPlatform::MacOS => &["route", "-n", "get", "default"],
+            TunPlatform::Windows => &[
+                "powershell",
+                "-NoProfile",
+                "-Command",
+                WINDOWS_DEFAULT_ROUTE_QUERY,
+            ],
+        };
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    /// Parse the output of [`TunPlatform::default_route_command`]
+    fn parse_default_route(&self, output: &str) -> Option<DefaultRoute> {
+        match self {
+            TunPlatform::Linux => parse_linux_default_route(output),
+            TunPlatform::MacOS => parse_macos_default_route(output),
+            TunPlatform::Windows => parse_windows_default_route(output),
+        }
+    }
+
+    /// Build the route and DNS commands for `config`
+    ///
+    /// DNS on macOS is per network service and set separately, since the
+    /// previous servers have to be read back first.
+    pub fn commands(&self, config: &TunConfig, route: &DefaultRoute) -> TunCommands {
+        let name = config.name.as_str();
+        let address = config.address.to_string();
+        let gateway = route.gateway.to_string();
+        // Only the gateway's address family can be routed around the tunnel
+        let bypass: Vec<String> = config
+            .bypass_addresses
+            .iter()
+            .filter(|ip| ip.is_ipv4() == route.gateway.is_ipv4())
+            .map(|ip| ip.to_string())
+            .collect();
+        let dns: Vec<String> = config.dns_servers.iter().map(|ip| ip.to_string()).collect();
+
+        let mut commands = TunCommands::default();
+        let setup = &mut commands.setup;
+        let teardown = &mut commands.teardown;
+
+        match self {
+            TunPlatform::Linux => {
+                let host =
+                    |ip: &str| format!("{}/{}", ip, if route.gateway.is_ipv4() { 32 } else { 128 });
+                setup.push(cmd(&[
+                    "ip",
+                    "addr",
+                    "add",
+                    &format!("{}/{}", address, config.prefix_len),
+                    "dev",
+                    name,
+                ]));
+                setup.push(cmd(&["ip", "link", "set", "dev", name, "up"]));
+                for ip in &bypass {
+                    setup.push(cmd(&["ip", "route", "add", &host(ip), "via", &gateway]));
+                    teardown.push(cmd(&["ip", "route", "del", &host(ip), "via", &gateway]));
+                }
+                for prefix in ["0.0.0.0/1", "128.0.0.0/1"] {
+                    setup.push(cmd(&["ip", "route", "add", prefix, "dev", name]));
+                    teardown.push(cmd(&["ip", "route", "del", prefix, "dev", name]));
+                }
+                if !dns.is_empty() {
+                    let mut set_dns = cmd(&["resolvectl", "dns", name]);
+                    set_dns.extend(dns.iter().cloned());
+                    setup.push(set_dns);
+                    // Send every lookup to the tunnel's DNS servers
+                    setup.push(cmd(&["resolvectl", "domain", name, "~."]));
+                    teardown.push(cmd(&["resolvectl", "revert", name]));
+                }
+            }
+            TunPlatform::MacOS => {
+                setup.push(cmd(&[
+                    "ifconfig",
+                    name,
+                    &address,
+                    &address,
+                    "netmask",
+                    &config.netmask().to_string(),
+                    "mtu",
+                    &config.mtu.to_string(),
+                    "up",
+                ]));
+                for ip in &bypass {
+                    setup.push(cmd(&["route", "-n", "add", "-host", ip, &gateway]));
+                    teardown.push(cmd(&["route", "-n", "delete", "-host", ip, &gateway]));
+                }
+                for prefix in ["0.0.0.0/1", "128.0.0.0/1"] {
+                    setup.push(cmd(&[
+                        "route",
+                        "-n",
+                        "add",
+                        "-net",
+                        prefix,
+                        "-interface",
+                        name,
+                    ]));
+                    teardown.push(cmd(&[
+                        "route",
+                        "-n",
+                        "delete",
+                        "-net",
+                        prefix,
+                        "-interface",
+                        name,
+                    ]));
+                }
+            }
+            TunPlatform::Windows => {
+                let interface = format!("name={}", name);
+                setup.push(cmd(&[
+                    "netsh",
+                    "interface",
+                    "ipv4",
+                    "set",
+                    "address",
+                    &interface,
+                    "source=static",
+                    &format!("addr={}", address),
+                    &format!("mask={}", config.netmask()),
+                ]));
+                for (index, server) in dns.iter().enumerate() {
+                    let address = format!("address={}", server);
+                    setup.push(if index == 0 {
+                        cmd(&[
+                            "netsh",
+                            "interface",
+                            "ipv4",
+                            "set",
+                            "dnsservers",
+                            &interface,
+                            "source=static",
+                            &address,
+                            "register=none",
+                            "validate=no",
+                        ])
+                    } else {
+                        cmd(&[
+                            "netsh",
+                            "interface",
+                            "ipv4",
+                            "add",
+                            "dnsservers",
+                            &interface,
+                            &address,
+                            &format!("index={}", index + 1),
+                            "validate=no",
+                        ])
+                    });
+                }
+                for ip in &bypass {
+                    setup.push(cmd(&[
+                        "route",
+                        "add",
+                        ip,
+                        "mask",
+                        "255.255.255.255",
+                        &gateway,
+                    ]));
+                    teardown.push(cmd(&["route", "delete", ip, "mask", "255.255.255.255"]));
+                }
+                for prefix in ["0.0.0.0/1", "128.0.0.0/1"] {
+                    setup.push(cmd(&[
+                        "netsh",
+                        "interface",
+                        "ipv4",
+                        "add",
+                        "route",
+                        &format!("prefix={}", prefix),
+                        &format!("interface={}", name),
+                        "nexthop=0.0.0.0",
+                        "metric=1",
+                        "store=active",
+                    ]));
+                    teardown.push(cmd(&[
+                        "netsh",
+                        "interface",
+                        "ipv4",
+                        "delete",
+                        "route",
+                        &format!("prefix={}", prefix),
+                        &format!("interface={}", name),
+                    ]));
+                }
+            }
+        }
+
+        // Undo in reverse order: default routes first, bypass routes last
+        teardown.reverse();
+        commands
+    }
+}
+
+/// Build an owned argument list
+pub(super) fn cmd(args: &[&str]) -> Vec<String> {
+    args.iter().map(|s| s.to_string()).collect()
+}
+
+/// Running TUN device
+///
+/// Dropping the device without calling [`TunDevice::stop`] still tears down
+/// the routes and kills tun2socks, but blocks while doing so.
+pub struct TunDevice {
+    /// Configuration the device was started with
+    config: TunConfig,
+    /// Default route before the tunnel was up
+    route: DefaultRoute,
+    /// tun2socks process
+    process: Option<Child>,
+    /// Task logging the tun2socks stderr
+    stderr: Option<JoinHandle<String>>,
+    /// Commands restoring routes and DNS
+    teardown: Vec<Vec<String>>,
+}
+
+impl TunDevice {
+    /// Start tun2socks and route all traffic through the new interface
+    ///
+    /// Requires administrator/root privileges. On failure, anything already
+    /// changed is rolled back.
+    pub async fn start(config: TunConfig) -> crate::V8RayResult<Self> {
+        let platform = TunPlatform::current().ok_or_else(|| {
+            PlatformError::NotSupported("TUN mode is not supported on this platform".to_string())
+        })?;
+        if !super::get_platform().has_admin_privileges()? {
+            return Err(PlatformError::Permission(
+                "TUN mode requires administrator privileges".to_string(),
+            )
+            .into());
+        }
+
+        let output = run_command(&platform.default_route_command()).await?;
+        let route = platform
+            .parse_default_route(&output)
+            .ok_or_else(|| PlatformError::VpnSetupFailed("No default route found".to_string()))?;
+        tracing::info!(
+            "Starting TUN device {} (gateway {} via {})",
+            config.name,
+            route.gateway,
+            route.interface
+        );
+
+        let binary = match config.tun2socks_path {
+            Some(ref path) => path.clone(),
+            None => find_tun2socks()?,
+        };
+        let mut command = Command::new(&binary);
+        command
+            .args(config.tun2socks_args())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        #[cfg(windows)]
+        {
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            command.creation_flags(CREATE_NO_WINDOW);
+        }
+        let mut process = command.spawn().map_err(|e| {
+            PlatformError::VpnSetupFailed(format!("Failed to start tun2socks: {}", e))
+        })?;
+        let stderr = drain_stderr(&mut process);
+
+        let mut device = Self {
+            config,
+            route,
+            process: Some(process),
+            stderr,
+            teardown: Vec::new(),
+        };
+        if let Err(e) = device.configure(platform).await {
+            device.stop().await;
+            return Err(e);
+        }
+
+        tracing::info!("TUN device {} is up", device.config.name);
+        Ok(device)
+    }
+
+    /// Wait for the interface, then apply routes and DNS
+    async fn configure(&mut self, platform: TunPlatform) -> crate::V8RayResult<()> {
+        self.wait_for_device().await?;
+
+        let commands = platform.commands(&self.config, &self.route);
+        self.teardown = commands.teardown;
+        for args in &commands.setup {
+            run_command(args).await?;
+        }
+
+        #[cfg(target_os = "macos")]
+        self.set_macos_dns().await?;
+
+        Ok(())
+    }
+
+    /// Wait until tun2socks has created the interface
+    async fn wait_for_device(&mut self) -> crate::V8RayResult<()> {
+        let deadline = tokio::time::Instant::now() + DEVICE_STARTUP_TIMEOUT;
+        loop {
+            tokio::time::sleep(DEVICE_POLL_INTERVAL).await;
+
+            if let Some(process) = self.process.as_mut() {
+                if let Ok(Some(status)) = process.try_wait() {
+                    let stderr = match self.stderr.take() {
+                        Some(task) => task.await.unwrap_or_default(),
+                        None => String::new(),
+                    };
+                    return Err(PlatformError::VpnSetupFailed(format!(
+                        "tun2socks exited with {}: {}",
+                        status,
+                        stderr.trim()
+                    ))
+                    .into());
+                }
+            }
+
+            if self.device_exists().await || tokio::time::Instant::now() >= deadline {
+                // Past the deadline, let the setup commands report the error
+                return Ok(());
+            }
+        }
+    }
+
+    /// Whether the interface exists yet
+    async fn device_exists(&self) -> bool {
+        let name = self.config.name.as_str();
+        if cfg!(target_os = "linux") {
+            std::path::Path::new("/sys/class/net").join(name).exists()
+        } else if cfg!(target_os = "macos") {
+            run_command(&cmd(&["ifconfig", name])).await.is_ok()
+        } else {
+            run_command(&cmd(&["netsh", "interface", "show", "interface", name]))
+                .await
+                .is_ok()
+        }
+    }
+
+    /// Point every network service at the tunnel's DNS servers
+    ///
+    /// The previous servers are restored by the teardown commands.
+    #[cfg(target_os = "macos")]
+    async fn set_macos_dns(&mut self) -> crate::V8RayResult<()> {
+        if self.config.dns_servers.is_empty() {
+            return Ok(());
+        }
+
+        for service in super::MacOSPlatform::get_network_services()? {
+            let current = run_command(&cmd(&["networksetup", "-getdnsservers", &service]))
+                .await
+                .unwrap_or_default();
+            let mut previous: Vec<String> = current
+                .lines()
+                .map(str::trim)
+                .filter(|line| line.parse::<IpAddr>().is_ok())
+                .map(str::to_string)
+                .collect();
+            if previous.is_empty() {
+                previous.push("Empty".to_string());
+            }
+
+            let mut set = cmd(&["networksetup", "-setdnsservers", &service]);
+            set.extend(self.config.dns_servers.iter().map(|ip| ip.to_string()));
+            run_command(&set).await?;
+
+            let mut restore = cmd(&["networksetup", "-setdnsservers", &service]);
+            restore.extend(previous);
+            self.teardown.insert(0, restore);
+        }
+        Ok(())
+    }
+
+    /// Interface name
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Configuration the device was started with
+    pub fn config(&self) -> &TunConfig {
+        &self.config
+    }
+
+    /// Default route before the tunnel was up
+    ///
+    /// Outbound connections that must bypass the tunnel should be bound to
+    /// its interface.
+    pub fn default_route(&self) -> &DefaultRoute {
+        &self.route
+    }
+
+    /// Commands restoring routes and DNS
+    ///
+    /// Persisting them lets [`run_teardown`] undo the changes after a crash.
+    pub fn teardown_commands(&self) -> &[Vec<String>] {
+        &self.teardown
+    }
+
+    /// Restore routes and DNS, then stop tun2socks
+    pub async fn stop(mut self) {
+        tracing::info!("Stopping TUN device {}", self.config.name);
+        run_teardown(&std::mem::take(&mut self.teardown)).await;
+        if let Some(mut process) = self.process.take() {
+            let _ = process.kill().await;
+        }
+    }
+}
+
+impl Drop for TunDevice {
+    fn drop(&mut self) {
+        for args in std::mem::take(&mut self.teardown) {
+            let mut command = std::process::Command::new(&args[0]);
+            command.args(&args[1..]);
+            #[cfg(windows)]
+            {
+                const CREATE_NO_WINDOW: u32 = 0x08000000;
+                command.creation_flags(CREATE_NO_WINDOW);
+            }
+            let _ = command.output();
+        }
+        if let Some(mut process) = self.process.take() {
+            let _ = process.start_kill();
+        }
+    }
+}
+
+/// Run teardown commands, continuing past failed steps
+///
+/// Also used at startup to undo a tunnel left behind by a crashed session,
+/// where some steps may already have no effect.
+pub async fn run_teardown(commands: &[Vec<String>]) {
+    for args in commands {
+        if let Err(e) = run_command(args).await {
+            tracing::warn!("TUN teardown step failed: {}", e);
+        }
+    }
+}
+
+/// Run a system command, returning its stdout
+pub(super) async fn run_command(args: &[String]) -> crate::V8RayResult<String> {
+    let (program, rest) = args
+        .split_first()
+        .ok_or_else(|| PlatformError::Command("Empty command".to_string()))?;
+    let mut command = Command::new(program);
+    command.args(rest);
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    tracing::debug!("Running: {}", args.join(" "));
+    let output = command
+        .output()
+        .await
+        .map_err(|e| PlatformError::Command(format!("{}: {}", program, e)))?;
+    if !output.status.success() {
+        return Err(PlatformError::Command(format!(
+            "{} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Find the bundled tun2socks binary in the application directory
+pub(crate) fn find_tun2socks() -> crate::V8RayResult<PathBuf> {
+    let binary_name = if cfg!(windows) {
+        "tun2socks.exe"
+    } else {
+        "tun2socks"
+    };
+
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|path| path.parent().map(|dir| dir.to_path_buf()));
+    if let Some(exe_dir) = exe_dir {
+        for candidate in [
+            exe_dir.join("bin").join(binary_name),
+            exe_dir.join(binary_name),
+        ] {
+            if candidate.exists() {
+                return Ok(candidate);
+            }
+        }
+    }
+
+    Err(PlatformError::VpnSetupFailed("Bundled tun2socks binary not found".to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route() -> DefaultRoute {
+        DefaultRoute {
+            gateway: "192.168.1.1".parse().unwrap(),
+            interface: "eth0".to_string(),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_drain_stderr_keeps_tail() {
+        let mut process = Command::new("sh")
+            .args([
+                "-c",
+                "for i in $(seq 1 30); do echo line $i >&2; done; exit 3",
+            ])
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let task = drain_stderr(&mut process).unwrap();
+        assert!(!process.wait().await.unwrap().success());
+
+        let tail = task.await.unwrap();
+        assert_eq!(tail.lines().count(), STDERR_TAIL_LINES);
+        assert!(tail.ends_with("line 30"));
+        assert!(drain_stderr(&mut process).is_none());
+    }
+
+    #[test]
+    fn test_parse_default_route() {
+        assert_eq!(
+            parse_linux_default_route("default via 192.168.1.1 dev eth0 proto dhcp metric 100\n"),
+            Some(route())
+        );
+        assert_eq!(
+            parse_linux_default_route("default dev wg0 scope link"),
+            None
+        );
+
+        let macos = "   route to: default\ndestination: default\n       mask: default\n    gateway: 192.168.1.1\n  interface: eth0\n      flags: <UP,GATEWAY,DONE,STATIC,PRCLONING>\n";
+        assert_eq!(parse_macos_default_route(macos), Some(route()));
+
+        assert_eq!(
+            parse_windows_default_route("192.168.1.1 Wi-Fi 2\r\n"),
+            Some(DefaultRoute {
+                gateway: "192.168.1.1".parse().unwrap(),
+                interface: "Wi-Fi 2".to_string(),
+            })
+        );
+        assert_eq!(parse_windows_default_route(""), None);
+    }
+
+    #[test]
+    fn test_tun_config() {
+        let config = TunConfig::new(1080).with_name("tun9".to_string());
+        assert_eq!(config.netmask(), Ipv4Addr::new(255, 254, 0, 0));
+        assert_eq!(
+            config
+                .clone()
+                .with_address(DEFAULT_TUN_ADDRESS, 0)
+                .netmask(),
+            Ipv4Addr::UNSPECIFIED
+        );
+
+        let args = config.tun2socks_args();
+        assert_eq!(args[1], "tun://tun9");
+        assert_eq!(args[3], "socks5://127.0.0.1:1080");
+    }
+
+    #[test]
+    fn test_linux_commands() {
+        let config = TunConfig::new(1080)
+            .with_name("v8ray0".to_string())
+            .with_bypass_addresses(vec![
+                "203.0.113.7".parse().unwrap(),
+                "2001:db8::1".parse().unwrap(),
+            ]);
+        let commands = TunPlatform::Linux.commands(&config, &route());
+        let setup: Vec<String> = commands.setup.iter().map(|c| c.join(" ")).collect();
+        let teardown: Vec<String> = commands.teardown.iter().map(|c| c.join(" ")).collect();
+
+        assert_eq!(setup[0], "ip addr add 198.18.0.1/15 dev v8ray0");
+        assert!(setup.contains(&"ip route add 203.0.113.7/32 via 192.168.1.1".to_string()));
+        // IPv6 servers cannot be routed through an IPv4 gateway
+        assert!(!setup.iter().any(|c| c.contains("2001:db8::1")));
+        assert!(setup.contains(&"resolvectl dns v8ray0 1.1.1.1 8.8.8.8".to_string()));
+
+        // Bypass routes must be in place before the default routes
+        let bypass = setup.iter().position(|c| c.contains("203.0.113.7"));
+        let default = setup.iter().position(|c| c.contains("0.0.0.0/1"));
+        assert!(bypass < default);
+
+        assert_eq!(teardown[0], "resolvectl revert v8ray0");
+        assert_eq!(
+            teardown.last().unwrap(),
+            "ip route del 203.0.113.7/32 via 192.168.1.1"
+        );
+    }
+
+    #[test]
+    fn test_macos_and_windows_commands() {
+        let config = TunConfig::new(1080)
+            .with_name("utun233".to_string())
+            .with_bypass_addresses(vec!["203.0.113.7".parse().unwrap()]);
+
+        let macos = TunPlatform::MacOS.commands(&config, &route());
+        assert_eq!(
+            macos.setup[0].join(" "),
+            "ifconfig utun233 198.18.0.1 198.18.0.1 netmask 255.254.0.0 mtu 1500 up"
+        );
+        assert!(macos.setup.contains(&cmd(&[
+            "route",
+            "-n",
+            "add",
+            "-host",
+            "203.0.113.7",
+            "192.168.1.1"
+        ])));
+        assert_eq!(macos.setup.len(), 4);
+        assert_eq!(macos.teardown.len(), 3);
+
+        let windows = TunPlatform::Windows.commands(&config, &route());
+        assert!(windows.setup[1].contains(&"address=1.1.1.1".to_string()));
+        assert!(windows.setup[2].contains(&"index=2".to_string()));
+        assert!(windows.teardown.contains(&cmd(&[
+            "route",
+            "delete",
+            "203.0.113.7",
+            "mask",
+            "255.255.255.255"
+        ])));
+    }
+}
@@ -0,0 +1,229 @@
+//! Windows version detection
+//!
+//! `GetVersionEx` reports Windows 8 to processes without a compatibility
+//! manifest, so the version comes from `RtlGetVersion`, which is not
+//! affected, with the registry as fallback. The update build revision
+//! (the `.3880` in `22631.3880`) is only in the registry.
+//!
+//! Capabilities depending on the Windows release are derived from the
+//! version here rather than assumed for every Windows build.
+
+use serde::{Deserialize, Serialize};
+
+/// First build of Windows 10
+pub const WINDOWS_10_BUILD: u32 = 10240;
+
+/// First build of Windows 11, which still reports itself as 10.0
+pub const WINDOWS_11_BUILD: u32 = 22000;
+
+/// Registry key holding the version of the running system
+#[cfg(target_os = "windows")]
+const CURRENT_VERSION_KEY: &str = "SOFTWARE\\Microsoft\\Windows NT\\CurrentVersion";
+
+/// Version of the running Windows system
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct WindowsVersion {
+    /// Major version (10 for Windows 10 and 11)
+    pub major: u32,
+    /// Minor version
+    pub minor: u32,
+    /// Build number
+    pub build: u32,
+    /// Update build revision, 0 if unknown
+    pub revision: u32,
+}
+
+impl WindowsVersion {
+    /// Version of the running system, None when not running on Windows or
+    /// when it cannot be read
+    #[cfg(target_os = "windows")]
+    pub fn current() -> Option<Self> {
+        let registry = registry_version();
+        let mut version = rtl_get_version().or(registry)?;
+        if version.revision == 0 {
+            version.revision = registry
+                .filter(|registry| registry.build == version.build)
+                .map_or(0, |registry| registry.revision);
+        }
+        Some(version)
+    }
+
+    /// Version of the running system, None when not running on Windows or
+    /// when it cannot be read
+    #[cfg(not(target_os = "windows"))]
+    pub fn current() -> Option<Self> {
+        None
+    }
+
+    /// Marketing name of the release, e.g. "Windows 11"
+    pub fn release_name(&self) -> &'static str {
+        match (self.major, self.minor) {
+            (10, _) if self.build >= WINDOWS_11_BUILD => "Windows 11",
+            (10, _) => "Windows 10",
+            (6, 3) => "Windows 8.1",
+            (6, 2) => "Windows 8",
+            (6, 1) => "Windows 7",
+            _ => "Windows",
+        }
+    }
+
+    /// Whether this is Windows 10 or later
+    pub fn is_windows_10_or_later(&self) -> bool {
+        self.major > 10 || (self.major == 10 && self.build >= WINDOWS_10_BUILD)
+    }
+
+    /// Whether this is Windows 11 or later
+    pub fn is_windows_11_or_later(&self) -> bool {
+        self.major > 10 || (self.major == 10 && self.build >= WINDOWS_11_BUILD)
+    }
+
+    /// Whether the bundled tun2socks and WinTUN driver run here
+    ///
+    /// tun2socks is built with a Go release that needs Windows 10.
+    pub fn supports_wintun(&self) -> bool {
+        self.is_windows_10_or_later()
+    }
+
+    /// Whether toast notifications from the Action Center are available
+    pub fn supports_toast_notifications(&self) -> bool {
+        self.is_windows_10_or_later()
+    }
+}
+
+impl std::fmt::Display for WindowsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}.{}.{}",
+            self.release_name(),
+            self.major,
+            self.minor,
+            self.build
+        )?;
+        if self.revision > 0 {
+            write!(f, ".{}", self.revision)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Capabilities of `version`, or of a recent Windows if it is unknown
+pub fn capabilities(version: Option<WindowsVersion>) -> super::PlatformCapabilities {
+    super::PlatformCapabilities {
+        system_proxy: true,
+        vpn_mode: false,
+        tun_mode: version.is_none_or(|version| version.supports_wintun()),
+        auto_start: true,
+    }
+}
+
+/// Version reported by `RtlGetVersion`
+#[cfg(target_os = "windows")]
+fn rtl_get_version() -> Option<WindowsVersion> {
+    use winapi::um::winnt::RTL_OSVERSIONINFOW;
+
+    #[link(name = "ntdll")]
+    extern "system" {
+        fn RtlGetVersion(info: *mut RTL_OSVERSIONINFOW) -> i32;
+    }
+
+    unsafe {
+        let mut info: RTL_OSVERSIONINFOW = std::mem::zeroed();
+        info.dwOSVersionInfoSize = std::mem::size_of::<RTL_OSVERSIONINFOW>() as u32;
+        if RtlGetVersion(&mut info) != 0 {
+            return None;
+        }
+        Some(WindowsVersion {
+            major: info.dwMajorVersion,
+            minor: info.dwMinorVersion,
+            build: info.dwBuildNumber,
+            revision: 0,
+        })
+    }
+}
+
+/// Version recorded in the registry
+///
+/// Windows 8.1 and earlier only have the `CurrentVersion` string.
+#[cfg(target_os = "windows")]
+fn registry_version() -> Option<WindowsVersion> {
+    let key = winreg::RegKey::predef(winreg::enums::HKEY_LOCAL_MACHINE)
+        .open_subkey(CURRENT_VERSION_KEY)
+        .ok()?;
+    let build: String = key.get_value("CurrentBuildNumber").ok()?;
+    let (major, minor) = match (
+        key.get_value::<u32, _>("CurrentMajorVersionNumber"),
+        key.get_value::<u32, _>("CurrentMinorVersionNumber"),
+    ) {
+        (Ok(major), Ok(minor)) => (major, minor),
+        _ => {
+            let version: String = key.get_value("CurrentVersion").ok()?;
+            parse_major_minor(&version)?
+        }
+    };
+    Some(WindowsVersion {
+        major,
+        minor,
+        build: build.trim().parse().ok()?,
+        revision: key.get_value::<u32, _>("UBR").unwrap_or(0),
+    })
+}
+
+/// Parse a "6.3" style version
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn parse_major_minor(version: &str) -> Option<(u32, u32)> {
+    let (major, minor) = version.trim().split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(major: u32, minor: u32, build: u32, revision: u32) -> WindowsVersion {
+        WindowsVersion {
+            major,
+            minor,
+            build,
+            revision,
+        }
+    }
+
+    #[test]
+    fn test_release_name() {
+        assert_eq!(
+            version(10, 0, 22631, 3880).to_string(),
+            "Windows 11 (10.0.22631.3880)"
+        );
+        assert_eq!(
+            version(10, 0, 19045, 0).to_string(),
+            "Windows 10 (10.0.19045)"
+        );
+        assert_eq!(version(6, 3, 9600, 0).release_name(), "Windows 8.1");
+        assert_eq!(version(6, 1, 7601, 0).release_name(), "Windows 7");
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let windows_11 = version(10, 0, 22631, 0);
+        assert!(windows_11.is_windows_11_or_later());
+        assert!(windows_11.supports_toast_notifications());
+        assert!(capabilities(Some(windows_11)).tun_mode);
+
+        let windows_7 = version(6, 1, 7601, 0);
+        assert!(!windows_7.is_windows_10_or_later());
+        assert!(!windows_7.supports_toast_notifications());
+        let caps = capabilities(Some(windows_7));
+        assert!(!caps.tun_mode);
+        assert!(caps.system_proxy);
+
+        // Unknown versions are assumed recent
+        assert!(capabilities(None).tun_mode);
+    }
+
+    #[test]
+    fn test_parse_major_minor() {
+        assert_eq!(parse_major_minor("6.3"), Some((6, 3)));
+        assert_eq!(parse_major_minor("10"), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Proxy cores
+//!
+//! Xray runs connections by default, but some protocols are better served
+//! by sing-box: Hysteria 2 and TUIC only run there. Profiles therefore pick
+//! their core, and servers speaking a protocol only one core supports always
+//! run on that core (see [`CoreKind::for_protocol`]).
+//!
+//! Both cores sit behind [`ProxyCore`]: generate a configuration for a
+//! server, start and stop the process, and report statistics and version.
+//! Errors of either core are reported as [`XrayError`], which covers process,
+//! configuration and binary failures alike.
+
+pub mod sing_box;
+pub mod sing_box_config;
+
+pub use sing_box::SingBoxCore;
+pub use sing_box_config::SingBoxConfigGenerator;
+
+use crate::config::{ProxyProtocol, ProxyServerConfig};
+use crate::xray::{XrayConfig, XrayCore, XrayError, XrayStatus};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by [`ProxyCore`] methods, boxed so cores can be used as
+/// trait objects
+pub type CoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, XrayError>> + Send + 'a>>;
+
+/// Proxy core running a connection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CoreKind {
+    /// Xray Core
+    #[default]
+    Xray,
+    /// sing-box
+    SingBox,
+}
+
+impl CoreKind {
+    /// Name used in settings and the FFI
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoreKind::Xray => "xray",
+            CoreKind::SingBox => "sing_box",
+        }
+    }
+
+    /// Parse a core name, accepting "sing-box" and "singbox" too
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "xray" => Some(CoreKind::Xray),
+            "sing_box" | "sing-box" | "singbox" => Some(CoreKind::SingBox),
+            _ => None,
+        }
+    }
+
+    /// Whether this core can connect to servers of `protocol`
+    pub fn supports(&self, protocol: &ProxyProtocol) -> bool {
+        match self {
+            CoreKind::Xray => !matches!(protocol, ProxyProtocol::Hysteria2 | ProxyProtocol::Tuic),
+            CoreKind::SingBox => true,
+        }
+    }
+
+    /// Core to run a server of `protocol` on: this one if it supports the
+    /// protocol, otherwise the other one
+    pub fn for_protocol(self, protocol: &ProxyProtocol) -> Self {
+        if self.supports(protocol) {
+            return self;
+        }
+        let other = match self {
+            CoreKind::Xray => CoreKind::SingBox,
+            CoreKind::SingBox => CoreKind::Xray,
+        };
+        tracing::info!(
+            "{:?} servers are not supported by {}, using {}",
+            protocol,
+            self.as_str(),
+            other.as_str()
+        );
+        other
+    }
+}
+
+impl std::fmt::Display for CoreKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Runtime statistics of a core
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoreStats {
+    /// Whether the process is running
+    pub running: bool,
+    /// Process ID
+    pub pid: Option<u32>,
+    /// Uptime in seconds
+    pub uptime_secs: u64,
+    /// Bytes uploaded through the core, None if it does not report traffic
+    pub upload: Option<u64>,
+    /// Bytes downloaded through the core, None if it does not report traffic
+    pub download: Option<u64>,
+}
+
+/// A proxy core process
+pub trait ProxyCore: Send + Sync {
+    /// Which core this is
+    fn kind(&self) -> CoreKind;
+
+    /// Configuration connecting to `server` in proxy `mode` (global, smart
+    /// or direct), in the core's own format
+    fn generate_config(
+        &self,
+        server: &ProxyServerConfig,
+        mode: &str,
+    ) -> Result<serde_json::Value, XrayError>;
+
+    /// Start the core with `config`, replacing a running process
+    fn start(&self, config: serde_json::Value) -> CoreFuture<'_, ()>;
+
+    /// Stop the core; stopping a stopped core succeeds
+    fn stop(&self) -> CoreFuture<'_, ()>;
+
+    /// Runtime statistics
+    fn stats(&self) -> CoreFuture<'_, CoreStats>;
+
+    /// Version of the core binary
+    fn version(&self) -> CoreFuture<'_, String>;
+}
+
+impl ProxyCore for XrayCore {
+    fn kind(&self) -> CoreKind {
+        CoreKind::Xray
+    }
+
+    fn generate_config(
+        &self,
+        server: &ProxyServerConfig,
+        mode: &str,
+    ) -> Result<serde_json::Value, XrayError> {
+        if !CoreKind::Xray.supports(&server.protocol) {
+            return Err(XrayError::Config(format!(
+                "{:?} servers are not supported by Xray",
+                server.protocol
+            )));
+        }
+        serde_json::to_value(self.generate_config_with_mode(server, mode))
+            .map_err(|e| XrayError::Config(e.to_string()))
+    }
+
+    fn start(&self, config: serde_json::Value) -> CoreFuture<'_, ()> {
+        Box::pin(async move {
+            let config: XrayConfig =
+                serde_json::from_value(config).map_err(|e| XrayError::Config(e.to_string()))?;
+            XrayCore::start(self, config).await
+        })
+    }
+
+    fn stop(&self) -> CoreFuture<'_, ()> {
+        Box::pin(XrayCore::stop(self))
+    }
+
+    fn stats(&self) -> CoreFuture<'_, CoreStats> {
+        Box::pin(async move {
+            let running = self.get_status().await == XrayStatus::Running;
+            let health = self.get_health().await;
+            Ok(CoreStats {
+                running,
+                pid: health.as_ref().and_then(|health| health.pid),
+                uptime_secs: health.map_or(0, |health| health.uptime),
+                // Traffic is counted by the connection manager's collector
+                upload: None,
+                download: None,
+            })
+        })
+    }
+
+    fn version(&self) -> CoreFuture<'_, String> {
+        Box::pin(self.binary_version())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_kind() {
+        assert_eq!(CoreKind::parse("sing-box"), Some(CoreKind::SingBox));
+        assert_eq!(CoreKind::parse(" Xray "), Some(CoreKind::Xray));
+        assert_eq!(CoreKind::parse("clash"), None);
+        assert_eq!(
+            serde_json::to_value(CoreKind::SingBox).unwrap(),
+            serde_json::json!("sing_box")
+        );
+
+        assert_eq!(
+            CoreKind::Xray.for_protocol(&ProxyProtocol::Vless),
+            CoreKind::Xray
+        );
+        assert_eq!(
+            CoreKind::Xray.for_protocol(&ProxyProtocol::Hysteria2),
+            CoreKind::SingBox
+        );
+        assert_eq!(
+            CoreKind::SingBox.for_protocol(&ProxyProtocol::Tuic),
+            CoreKind::SingBox
+        );
+    }
+
+    #[test]
+    fn test_xray_rejects_quic_protocols() {
+        let server = ProxyServerConfig {
+            id: "hy2".to_string(),
+            name: "Hy2".to_string(),
+            raw_name: None,
+            server: "example.com".to_string(),
+            port: 443,
+            protocol: ProxyProtocol::Hysteria2,
+            settings: Default::default(),
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        };
+        let xray = XrayCore::new();
+        assert!(ProxyCore::generate_config(&xray, &server, "global").is_err());
+
+        let trojan = ProxyServerConfig {
+            protocol: ProxyProtocol::Trojan,
+            ..server
+        };
+        let config = ProxyCore::generate_config(&xray, &trojan, "global").unwrap();
+        assert!(config["outbounds"].is_array());
+    }
+}
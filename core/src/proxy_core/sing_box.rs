@@ -0,0 +1,504 @@
+//! sing-box process
+//!
+//! sing-box is not bundled with the app. The binary is looked up in the data
+//! directory's `sing-box` folder, then next to the executable like the
+//! bundled Xray. Traffic totals come from sing-box's Clash-compatible API,
+//! which is enabled on a free loopback port unless the configuration already
+//! sets it.
+//!
+//! The process is watched while it runs: an exit that was not asked for is
+//! reported to [`SingBoxCore::subscribe_exits`] subscribers, and
+//! [`SingBoxCore::restart`] starts it again with the same configuration.
+
+use super::{CoreFuture, CoreKind, CoreStats, ProxyCore, SingBoxConfigGenerator};
+use crate::config::{AppRule, DnsSettings, GfwList, ProxyServerConfig, RoutingRuleSet};
+use crate::xray::{InboundConfig, XrayError};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+// Windows-specific imports for hiding console window
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+/// Lines of sing-box output kept to explain an early exit
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// Time sing-box gets to exit after SIGTERM before it is killed
+#[cfg(unix)]
+const STOP_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Timeout of Clash API requests
+const CLASH_API_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Running sing-box process
+struct SingBoxProcess {
+    /// Process ID
+    pid: Option<u32>,
+    /// Set once the process has exited
+    exited: Arc<AtomicBool>,
+    /// Asks the watcher to terminate the process
+    stop: CancellationToken,
+    /// Task owning the child, waiting for it to exit
+    watcher: tokio::task::JoinHandle<()>,
+    /// Configuration file it runs with
+    config_path: PathBuf,
+    /// Start time
+    started: Instant,
+    /// Clash API address, e.g. `127.0.0.1:9090`
+    clash_api: Option<String>,
+}
+
+/// sing-box core
+pub struct SingBoxCore {
+    /// Binary set by the user, None to look it up
+    binary_path: std::sync::RwLock<Option<PathBuf>>,
+    /// Configuration generator
+    generator: std::sync::RwLock<SingBoxConfigGenerator>,
+    /// Running process
+    process: tokio::sync::Mutex<Option<SingBoxProcess>>,
+    /// Configuration of the last start, for [`SingBoxCore::restart`]
+    last_config: std::sync::RwLock<Option<Value>>,
+    /// Details of exits that were not asked for
+    exits: broadcast::Sender<String>,
+}
+
+impl Default for SingBoxCore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SingBoxCore {
+    /// Create a stopped core
+    pub fn new() -> Self {
+        Self {
+            binary_path: std::sync::RwLock::new(None),
+            generator: std::sync::RwLock::new(SingBoxConfigGenerator::new()),
+            process: tokio::sync::Mutex::new(None),
+            last_config: std::sync::RwLock::new(None),
+            exits: broadcast::channel(4).0,
+        }
+    }
+
+    /// Run the binary at `path`, or look it up for None
+    pub fn set_binary_path(&self, path: Option<PathBuf>) {
+        *self.binary_path.write().unwrap_or_else(|e| e.into_inner()) = path;
+    }
+
+    /// Binary that runs on the next start
+    pub fn find_binary(&self) -> Result<PathBuf, XrayError> {
+        if let Some(path) = self
+            .binary_path
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+        {
+            return if path.is_file() {
+                Ok(path)
+            } else {
+                Err(not_found())
+            };
+        }
+
+        let mut candidates = vec![crate::paths::app_paths().sing_box_dir().join(binary_name())];
+        if let Some(exe_dir) = std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(PathBuf::from))
+        {
+            candidates.push(exe_dir.join("bin").join(binary_name()));
+            candidates.push(exe_dir.join(binary_name()));
+        }
+        candidates
+            .into_iter()
+            .find(|path| path.is_file())
+            .ok_or_else(not_found)
+    }
+
+    /// Whether a sing-box binary is installed
+    pub fn binary_available(&self) -> bool {
+        self.find_binary().is_ok()
+    }
+
+    /// Open the HTTP and SOCKS `inbounds` of an Xray configuration in
+    /// generated configurations
+    pub fn set_inbounds(&self, inbounds: Vec<InboundConfig>) {
+        let mut generator = self.generator.write().unwrap_or_else(|e| e.into_inner());
+        *generator = std::mem::take(&mut *generator).with_inbounds(inbounds);
+    }
+
+    /// Route by the user's rule sets, app rules, DNS settings and GFWList in
+    /// generated configurations
+    pub fn set_routing(
+        &self,
+        rule_sets: Vec<RoutingRuleSet>,
+        app_rules: Vec<AppRule>,
+        dns_settings: DnsSettings,
+        gfwlist: Option<GfwList>,
+    ) {
+        let mut generator = self.generator.write().unwrap_or_else(|e| e.into_inner());
+        *generator = std::mem::take(&mut *generator)
+            .with_routing_rule_sets(rule_sets)
+            .with_app_rules(app_rules)
+            .with_dns_settings(dns_settings)
+            .with_gfwlist(gfwlist);
+    }
+
+    /// Start sing-box with `config`, stopping a running process first
+    pub async fn start(&self, mut config: Value) -> Result<(), XrayError> {
+        self.stop().await?;
+        let binary = self.find_binary()?;
+        let clash_api = ensure_clash_api(&mut config)?;
+
+        let dir = crate::paths::app_paths().sing_box_dir();
+        tokio::fs::create_dir_all(&dir).await?;
+        let content =
+            serde_json::to_string_pretty(&config).map_err(|e| XrayError::Config(e.to_string()))?;
+        crate::utils::preflight::preflight(&dir, content.len() as u64)?;
+        let config_path = dir.join("config.json");
+        tokio::fs::write(&config_path, content).await?;
+        *self.last_config.write().unwrap_or_else(|e| e.into_inner()) = Some(config);
+
+        tracing::info!("Starting sing-box: {:?} run -c {:?}", binary, config_path);
+        let mut cmd = Command::new(&binary);
+        cmd.arg("run")
+            .arg("-c")
+            .arg(&config_path)
+            .arg("--disable-color")
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        // On Windows, hide the console window
+        #[cfg(windows)]
+        {
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            cmd.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .map_err(|e| XrayError::Process(format!("Failed to spawn sing-box: {}", e)))?;
+        let output_tail = Arc::new(Mutex::new(VecDeque::new()));
+        if let Some(stderr) = child.stderr.take() {
+            let output_tail = Arc::clone(&output_tail);
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    tracing::debug!(target: "sing-box", "{}", line);
+                    let mut tail = output_tail.lock().unwrap_or_else(|e| e.into_inner());
+                    if tail.len() == OUTPUT_TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line);
+                }
+            });
+        }
+
+        // Configuration errors make sing-box exit right away
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        if let Ok(Some(status)) = child.try_wait() {
+            let _ = tokio::fs::remove_file(&config_path).await;
+            let tail = output_tail.lock().unwrap_or_else(|e| e.into_inner());
+            return Err(XrayError::Process(format!(
+                "sing-box exited immediately with status {}: {}",
+                status,
+                tail.iter().cloned().collect::<Vec<_>>().join("\n")
+            )));
+        }
+
+        let pid = child.id();
+        tracing::info!("sing-box started with PID {:?}", pid);
+        let exited = Arc::new(AtomicBool::new(false));
+        let stop = CancellationToken::new();
+        let watcher = tokio::spawn({
+            let exited = Arc::clone(&exited);
+            let stop = stop.clone();
+            let exits = self.exits.clone();
+            async move {
+                tokio::select! {
+                    status = child.wait() => {
+                        exited.store(true, Ordering::SeqCst);
+                        let tail = output_tail.lock().unwrap_or_else(|e| e.into_inner());
+                        let details = match status {
+                            Ok(status) => format!(
+                                "sing-box exited with status {}: {}",
+                                status,
+                                tail.iter().cloned().collect::<Vec<_>>().join("\n")
+                            ),
+                            Err(e) => format!("Failed to wait for sing-box: {}", e),
+                        };
+                        tracing::warn!("{}", details);
+                        let _ = exits.send(details);
+                    }
+                    _ = stop.cancelled() => terminate(&mut child).await,
+                }
+            }
+        });
+        *self.process.lock().await = Some(SingBoxProcess {
+            pid,
+            exited,
+            stop,
+            watcher,
+            config_path,
+            started: Instant::now(),
+            clash_api,
+        });
+        Ok(())
+    }
+
+    /// Start sing-box again with the configuration of the last start, e.g.
+    /// after it exited
+    pub async fn restart(&self) -> Result<(), XrayError> {
+        let config = self
+            .last_config
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .ok_or_else(|| XrayError::Process("sing-box has not been started".to_string()))?;
+        self.start(config).await
+    }
+
+    /// Stop sing-box if it runs
+    pub async fn stop(&self) -> Result<(), XrayError> {
+        let Some(process) = self.process.lock().await.take() else {
+            return Ok(());
+        };
+        process.stop.cancel();
+        let _ = process.watcher.await;
+        let _ = tokio::fs::remove_file(&process.config_path).await;
+        tracing::info!("sing-box stopped");
+        Ok(())
+    }
+
+    /// Whether sing-box runs
+    pub async fn is_running(&self) -> bool {
+        match self.process.lock().await.as_ref() {
+            Some(process) => !process.exited.load(Ordering::SeqCst),
+            None => false,
+        }
+    }
+
+    /// Subscribe to exits of sing-box that were not asked for, with the
+    /// exit status and last output
+    pub fn subscribe_exits(&self) -> broadcast::Receiver<String> {
+        self.exits.subscribe()
+    }
+
+    /// Process statistics and traffic totals
+    pub async fn stats(&self) -> CoreStats {
+        let (pid, uptime_secs, clash_api) = {
+            let process = self.process.lock().await;
+            let Some(process) = process.as_ref() else {
+                return CoreStats::default();
+            };
+            if process.exited.load(Ordering::SeqCst) {
+                return CoreStats::default();
+            }
+            (
+                process.pid,
+                process.started.elapsed().as_secs(),
+                process.clash_api.clone(),
+            )
+        };
+        let traffic = match clash_api {
+            Some(address) => traffic_totals(&address).await,
+            None => None,
+        };
+        CoreStats {
+            running: true,
+            pid,
+            uptime_secs,
+            upload: traffic.map(|(upload, _)| upload),
+            download: traffic.map(|(_, download)| download),
+        }
+    }
+
+    /// Version of the sing-box binary
+    pub async fn version(&self) -> Result<String, XrayError> {
+        let binary = self.find_binary()?;
+        let mut cmd = Command::new(&binary);
+        cmd.arg("version");
+
+        // On Windows, hide the console window
+        #[cfg(windows)]
+        {
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            cmd.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        let output = cmd
+            .output()
+            .await
+            .map_err(|e| XrayError::Process(e.to_string()))?;
+        Ok(parse_version(&String::from_utf8_lossy(&output.stdout))
+            .unwrap_or_else(|| "unknown".to_string()))
+    }
+}
+
+impl ProxyCore for SingBoxCore {
+    fn kind(&self) -> CoreKind {
+        CoreKind::SingBox
+    }
+
+    fn generate_config(&self, server: &ProxyServerConfig, mode: &str) -> Result<Value, XrayError> {
+        self.generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .generate(server, mode)
+    }
+
+    fn start(&self, config: Value) -> CoreFuture<'_, ()> {
+        Box::pin(SingBoxCore::start(self, config))
+    }
+
+    fn stop(&self) -> CoreFuture<'_, ()> {
+        Box::pin(SingBoxCore::stop(self))
+    }
+
+    fn stats(&self) -> CoreFuture<'_, CoreStats> {
+        Box::pin(async move { Ok(SingBoxCore::stats(self).await) })
+    }
+
+    fn version(&self) -> CoreFuture<'_, String> {
+        Box::pin(SingBoxCore::version(self))
+    }
+}
+
+/// File name of the sing-box binary
+fn binary_name() -> &'static str {
+    #[cfg(windows)]
+    return "sing-box.exe";
+    #[cfg(not(windows))]
+    return "sing-box";
+}
+
+/// Error for a missing sing-box binary, saying where to put it
+fn not_found() -> XrayError {
+    XrayError::Process(format!(
+        "sing-box not found, place the binary in {}",
+        crate::paths::app_paths().sing_box_dir().display()
+    ))
+}
+
+/// Enable the Clash API on a free loopback port unless `config` sets it,
+/// returning its address
+fn ensure_clash_api(config: &mut Value) -> Result<Option<String>, XrayError> {
+    if let Some(address) = config["experimental"]["clash_api"]["external_controller"].as_str() {
+        return Ok(Some(address.to_string()));
+    }
+    if !config.is_object() {
+        return Err(XrayError::Config(
+            "sing-box configuration must be an object".to_string(),
+        ));
+    }
+    let port = crate::utils::ports::find_free_port(None)?;
+    let address = format!("127.0.0.1:{}", port);
+    config["experimental"]["clash_api"] = json!({ "external_controller": address });
+    Ok(Some(address))
+}
+
+/// Upload and download totals reported by the Clash API at `address`
+async fn traffic_totals(address: &str) -> Option<(u64, u64)> {
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .timeout(CLASH_API_TIMEOUT)
+        .build()
+        .ok()?;
+    let connections: Value = client
+        .get(format!("http://{}/connections", address))
+        .send()
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    Some((
+        connections["uploadTotal"].as_u64()?,
+        connections["downloadTotal"].as_u64()?,
+    ))
+}
+
+/// Version from `sing-box version` output like "sing-box version 1.11.4"
+fn parse_version(output: &str) -> Option<String> {
+    output
+        .lines()
+        .next()?
+        .trim()
+        .strip_prefix("sing-box version ")
+        .map(|version| version.trim().to_string())
+}
+
+/// Ask `child` to exit, killing it if it does not in time
+async fn terminate(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: kill only sends a signal to the child's PID
+        unsafe {
+            libc::kill(pid as i32, libc::SIGTERM);
+        }
+        if tokio::time::timeout(STOP_TIMEOUT, child.wait())
+            .await
+            .is_ok()
+        {
+            return;
+        }
+        tracing::warn!("sing-box did not exit in time, killing it");
+    }
+    if let Err(e) = child.kill().await {
+        tracing::warn!("Failed to kill sing-box: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        let output = "sing-box version 1.11.4\n\nEnvironment: go1.23.6 linux/amd64\n";
+        assert_eq!(parse_version(output).as_deref(), Some("1.11.4"));
+        assert_eq!(parse_version("Xray 1.8.24"), None);
+    }
+
+    #[test]
+    fn test_ensure_clash_api() {
+        let mut config = json!({ "log": {} });
+        let address = ensure_clash_api(&mut config).unwrap().unwrap();
+        assert!(address.starts_with("127.0.0.1:"));
+        assert_eq!(
+            config["experimental"]["clash_api"]["external_controller"],
+            address
+        );
+
+        let mut config = json!({
+            "experimental": { "clash_api": { "external_controller": "127.0.0.1:9090" } }
+        });
+        assert_eq!(
+            ensure_clash_api(&mut config).unwrap().as_deref(),
+            Some("127.0.0.1:9090")
+        );
+        assert!(ensure_clash_api(&mut json!([])).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stopped_core() {
+        let core = SingBoxCore::new();
+        core.set_binary_path(Some(PathBuf::from("/nonexistent/sing-box")));
+        assert!(!core.binary_available());
+        assert!(core.start(json!({})).await.is_err());
+        assert!(core.restart().await.is_err());
+        assert!(!core.is_running().await);
+        assert_eq!(core.stats().await, CoreStats::default());
+        core.stop().await.unwrap();
+    }
+}
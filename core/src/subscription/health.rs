@@ -0,0 +1,168 @@
+//! Subscription Health Checks
+//!
+//! A health check asks whether a subscription URL still answers, without
+//! downloading and parsing the server list: a HEAD request (GET if the
+//! server refuses HEAD) following redirects. Subscriptions failing several
+//! checks in a row are marked stale, which usually means the provider's
+//! link expired or died.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Consecutive failed checks after which a subscription is stale by default
+pub const DEFAULT_STALE_AFTER: u32 = 3;
+
+/// Result of one health check
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HealthCheck {
+    /// When the check started
+    pub checked_at: DateTime<Utc>,
+    /// How long the check took in milliseconds
+    pub duration_ms: u64,
+    /// HTTP status of the final response, None if none was received
+    pub http_status: Option<u16>,
+    /// URL the subscription redirected to, None if it was not redirected
+    pub redirected_to: Option<String>,
+    /// Error, None if the URL answered with a success status
+    pub error: Option<String>,
+    /// Whether the error was a TLS failure (certificate or handshake)
+    pub tls_error: bool,
+}
+
+impl HealthCheck {
+    /// Whether the URL answered with a success status
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Health of a subscription across checks
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SubscriptionHealth {
+    /// Failed checks since the last successful one
+    pub consecutive_failures: u32,
+    /// Whether the subscription failed too many checks in a row
+    pub stale: bool,
+    /// Latest check
+    pub last_check: Option<HealthCheck>,
+}
+
+impl SubscriptionHealth {
+    /// Record a check, marking the subscription stale once `stale_after`
+    /// checks in a row failed
+    ///
+    /// Returns whether the subscription just became stale.
+    pub fn record(&mut self, check: HealthCheck, stale_after: u32) -> bool {
+        let was_stale = self.stale;
+        if check.is_healthy() {
+            self.consecutive_failures = 0;
+            self.stale = false;
+        } else {
+            self.consecutive_failures += 1;
+            self.stale = self.consecutive_failures >= stale_after.max(1);
+        }
+        self.last_check = Some(check);
+        self.stale && !was_stale
+    }
+}
+
+/// Whether `error` or one of its sources is a TLS failure
+pub(crate) fn is_tls_error(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        let message = error.to_string().to_ascii_lowercase();
+        if ["certificate", "tls", "ssl", "handshake"]
+            .iter()
+            .any(|word| message.contains(word))
+        {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Message of `error` followed by those of its sources
+///
+/// HTTP client errors alone only say the request failed; the cause is in
+/// the sources.
+pub(crate) fn error_chain(error: &(dyn std::error::Error + 'static)) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        let cause = error.to_string();
+        if !message.contains(&cause) {
+            message.push_str(": ");
+            message.push_str(&cause);
+        }
+        source = error.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(error: Option<&str>) -> HealthCheck {
+        HealthCheck {
+            checked_at: Utc::now(),
+            duration_ms: 10,
+            http_status: Some(if error.is_some() { 404 } else { 200 }),
+            redirected_to: None,
+            error: error.map(str::to_string),
+            tls_error: false,
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let mut health = SubscriptionHealth::default();
+        assert!(!health.record(check(Some("HTTP error: 404")), 2));
+        assert!(!health.stale);
+        assert!(health.record(check(Some("HTTP error: 404")), 2));
+        assert!(health.stale);
+        // Only reported once
+        assert!(!health.record(check(Some("HTTP error: 404")), 2));
+        assert_eq!(health.consecutive_failures, 3);
+
+        assert!(!health.record(check(None), 2));
+        assert!(!health.stale);
+        assert_eq!(health.consecutive_failures, 0);
+        assert!(health.last_check.unwrap().is_healthy());
+    }
+
+    #[derive(Debug)]
+    struct Error(&'static str, Option<Box<Error>>);
+
+    impl std::fmt::Display for Error {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(self.0)
+        }
+    }
+
+    impl std::error::Error for Error {
+        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+            self.1.as_deref().map(|e| e as _)
+        }
+    }
+
+    #[test]
+    fn test_error_classification() {
+        let error = Error(
+            "error sending request",
+            Some(Box::new(Error("invalid peer certificate: Expired", None))),
+        );
+        assert!(is_tls_error(&error));
+        assert_eq!(
+            error_chain(&error),
+            "error sending request: invalid peer certificate: Expired"
+        );
+
+        let error = Error(
+            "error sending request",
+            Some(Box::new(Error("connection refused", None))),
+        );
+        assert!(!is_tls_error(&error));
+    }
+}
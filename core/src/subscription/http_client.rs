@@ -2,10 +2,24 @@
 //!
 //! This module provides a specialized HTTP client for fetching subscription data
 //! with features like timeout, retry, custom user-agent, and error handling.
-
-use crate::error::SubscriptionResult;
-use reqwest::{Client, ClientBuilder};
-use std::time::Duration;
+//!
+//! Some providers pick the subscription format by User-Agent (Clash YAML for
+//! Clash, share links for v2rayN) or require an Authorization header, so
+//! each subscription can carry its own [`SubscriptionRequest`] options.
+//!
+//! Fetches are retried with exponential backoff on network errors and
+//! retryable statuses, and can fall back between a direct connection and
+//! a local proxy (for providers blocked on the user's network, or only
+//! reachable directly). Every attempt is reported as a [`FetchAttempt`] so
+//! slow or flaky providers can be diagnosed.
+
+use super::health::{error_chain, is_tls_error, HealthCheck};
+use crate::error::{SubscriptionError, SubscriptionResult};
+use reqwest::header::{HeaderName, HeaderValue, USER_AGENT};
+use reqwest::{Client, Method, Proxy, RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
 use tracing::{debug, info, warn};
 
 /// Default timeout for HTTP requests (30 seconds)
@@ -14,17 +28,39 @@ const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 /// Default maximum number of retries
 const DEFAULT_MAX_RETRIES: u32 = 3;
 
+/// Default wait before the first retry
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Default longest wait between retries
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// HTTP statuses retried by default: timeouts, rate limiting and transient
+/// server errors
+pub const DEFAULT_RETRY_ON_STATUS: &[u16] = &[408, 429, 500, 502, 503, 504];
+
 /// HTTP client configuration
 #[derive(Debug, Clone)]
 pub struct HttpClientConfig {
     /// Request timeout
     pub timeout: Duration,
-    /// Maximum number of retries
+    /// Maximum number of attempts per route
     pub max_retries: u32,
     /// User agent string
     pub user_agent: String,
     /// Follow redirects
     pub follow_redirects: bool,
+    /// Headers sent with every request
+    pub headers: BTreeMap<String, String>,
+    /// Wait before the first retry, doubled for each further retry
+    pub initial_backoff: Duration,
+    /// Longest wait between retries
+    pub max_backoff: Duration,
+    /// HTTP statuses worth retrying; network errors are always retried
+    pub retry_on_status: Vec<u16>,
+    /// Routes tried in turn until one succeeds
+    pub route_order: RouteOrder,
+    /// Proxy for the proxy route, such as the app's local HTTP inbound
+    pub proxy_url: Option<String>,
 }
 
 impl Default for HttpClientConfig {
@@ -34,6 +70,205 @@ impl Default for HttpClientConfig {
             max_retries: DEFAULT_MAX_RETRIES,
             user_agent: crate::version::user_agent(),
             follow_redirects: true,
+            headers: BTreeMap::new(),
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            retry_on_status: DEFAULT_RETRY_ON_STATUS.to_vec(),
+            route_order: RouteOrder::default(),
+            proxy_url: None,
+        }
+    }
+}
+
+impl HttpClientConfig {
+    /// Wait before retry number `retry` (from 1) of a route
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// Way a subscription is fetched
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FetchRoute {
+    /// Without the proxy
+    Direct,
+    /// Through [`HttpClientConfig::proxy_url`]
+    Proxy,
+}
+
+impl FetchRoute {
+    /// Route name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FetchRoute::Direct => "direct",
+            FetchRoute::Proxy => "proxy",
+        }
+    }
+}
+
+/// Order in which fetch routes are tried
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RouteOrder {
+    /// Direct connection only
+    DirectOnly,
+    /// Proxy only
+    ProxyOnly,
+    /// Direct, then the proxy if the direct connection failed
+    #[default]
+    DirectThenProxy,
+    /// Proxy, then direct if the proxy failed
+    ProxyThenDirect,
+}
+
+impl RouteOrder {
+    /// All orders
+    pub const ALL: [RouteOrder; 4] = [
+        RouteOrder::DirectOnly,
+        RouteOrder::ProxyOnly,
+        RouteOrder::DirectThenProxy,
+        RouteOrder::ProxyThenDirect,
+    ];
+
+    /// Order name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteOrder::DirectOnly => "direct_only",
+            RouteOrder::ProxyOnly => "proxy_only",
+            RouteOrder::DirectThenProxy => "direct_then_proxy",
+            RouteOrder::ProxyThenDirect => "proxy_then_direct",
+        }
+    }
+
+    /// Routes in the order they are tried
+    pub fn routes(&self) -> &'static [FetchRoute] {
+        match self {
+            RouteOrder::DirectOnly => &[FetchRoute::Direct],
+            RouteOrder::ProxyOnly => &[FetchRoute::Proxy],
+            RouteOrder::DirectThenProxy => &[FetchRoute::Direct, FetchRoute::Proxy],
+            RouteOrder::ProxyThenDirect => &[FetchRoute::Proxy, FetchRoute::Direct],
+        }
+    }
+}
+
+/// One attempt to fetch a subscription
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FetchAttempt {
+    /// Route used
+    pub route: FetchRoute,
+    /// Attempt number on this route, from 1
+    pub attempt: u32,
+    /// How long the attempt took in milliseconds
+    pub duration_ms: u64,
+    /// HTTP status, None if no response was received
+    pub http_status: Option<u16>,
+    /// Error, None if the attempt succeeded
+    pub error: Option<String>,
+}
+
+/// User-Agent sent for a subscription
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum UserAgentPreset {
+    /// The client's User-Agent (see [`HttpClientConfig::user_agent`])
+    #[default]
+    Default,
+    /// Clash (Mihomo), for providers serving Clash YAML
+    Clash,
+    /// v2rayN, for providers serving share links
+    V2rayN,
+    /// Shadowrocket
+    Shadowrocket,
+    /// Any other value
+    Custom(String),
+}
+
+impl UserAgentPreset {
+    /// User-Agent value, None for the client's default
+    pub fn value(&self) -> Option<&str> {
+        match self {
+            UserAgentPreset::Default => None,
+            UserAgentPreset::Clash => Some("clash.meta/v1.19.0"),
+            UserAgentPreset::V2rayN => Some("v2rayN/7.0.0"),
+            UserAgentPreset::Shadowrocket => Some("Shadowrocket/2.2.50"),
+            UserAgentPreset::Custom(value) => Some(value),
+        }
+    }
+}
+
+/// Basic authentication credentials
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BasicAuth {
+    /// Username
+    pub username: String,
+    /// Password
+    pub password: String,
+}
+
+/// Per-subscription request options
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SubscriptionRequest {
+    /// User-Agent to send
+    #[serde(default)]
+    pub user_agent: UserAgentPreset,
+    /// Extra headers, overriding those of the client
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Basic authentication credentials
+    #[serde(default)]
+    pub basic_auth: Option<BasicAuth>,
+}
+
+impl SubscriptionRequest {
+    /// Whether all options are at their defaults
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Check that header names and values, and the User-Agent, can be sent
+    pub fn validate(&self) -> SubscriptionResult<()> {
+        if let UserAgentPreset::Custom(value) = &self.user_agent {
+            if value.trim().is_empty() || HeaderValue::from_str(value).is_err() {
+                return Err(SubscriptionError::InvalidHeader(format!(
+                    "Invalid User-Agent: {}",
+                    value
+                )));
+            }
+        }
+        for (name, value) in &self.headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                return Err(SubscriptionError::InvalidHeader(format!(
+                    "Invalid header name: {}",
+                    name
+                )));
+            }
+            if HeaderValue::from_str(value).is_err() {
+                return Err(SubscriptionError::InvalidHeader(format!(
+                    "Invalid value of header {}",
+                    name
+                )));
+            }
+        }
+        if let Some(auth) = &self.basic_auth {
+            if auth.username.is_empty() {
+                return Err(SubscriptionError::InvalidHeader(
+                    "Basic auth username is empty".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy without credentials (basic auth and extra headers)
+    pub fn without_secrets(&self) -> Self {
+        Self {
+            user_agent: self.user_agent.clone(),
+            headers: BTreeMap::new(),
+            basic_auth: None,
         }
     }
 }
@@ -42,6 +277,8 @@ impl Default for HttpClientConfig {
 pub struct SubscriptionHttpClient {
     /// Reqwest client
     client: Client,
+    /// Reqwest client going through the proxy, None if no proxy is set
+    proxy_client: Option<Client>,
     /// Client configuration
     config: HttpClientConfig,
 }
@@ -54,56 +291,127 @@ impl SubscriptionHttpClient {
 
     /// Create a new HTTP client with custom configuration
     pub fn with_config(config: HttpClientConfig) -> SubscriptionResult<Self> {
-        let client = ClientBuilder::new()
-            .timeout(config.timeout)
-            .user_agent(&config.user_agent)
-            .redirect(if config.follow_redirects {
-                reqwest::redirect::Policy::limited(10)
-            } else {
-                reqwest::redirect::Policy::none()
-            })
-            .build()?;
+        let builder = || {
+            crate::version::http_client_builder()
+                .timeout(config.timeout)
+                .user_agent(&config.user_agent)
+                .redirect(if config.follow_redirects {
+                    reqwest::redirect::Policy::limited(10)
+                } else {
+                    reqwest::redirect::Policy::none()
+                })
+        };
+        let client = builder().build()?;
+        let proxy_client = match &config.proxy_url {
+            Some(proxy_url) => Some(builder().proxy(Proxy::all(proxy_url)?).build()?),
+            None => None,
+        };
 
-        Ok(Self { client, config })
+        Ok(Self {
+            client,
+            proxy_client,
+            config,
+        })
     }
 
     /// Fetch subscription data from URL
     ///
     /// This method will automatically retry on failure according to the configuration.
     pub async fn fetch_subscription(&self, url: &str) -> SubscriptionResult<String> {
+        self.fetch_subscription_with(url, &SubscriptionRequest::default())
+            .await
+    }
+
+    /// Fetch subscription data from URL with per-subscription options
+    pub async fn fetch_subscription_with(
+        &self,
+        url: &str,
+        options: &SubscriptionRequest,
+    ) -> SubscriptionResult<String> {
+        self.fetch_with_attempts(url, options).await.0
+    }
+
+    /// Fetch subscription data, reporting every attempt made
+    ///
+    /// Each route of [`HttpClientConfig::route_order`] is tried in turn,
+    /// skipping the proxy route if no proxy is set. On a route, network
+    /// errors and statuses in [`HttpClientConfig::retry_on_status`] are
+    /// retried up to `max_retries` attempts with exponential backoff; other
+    /// failures move on to the next route straight away.
+    pub async fn fetch_with_attempts(
+        &self,
+        url: &str,
+        options: &SubscriptionRequest,
+    ) -> (SubscriptionResult<String>, Vec<FetchAttempt>) {
         info!("Fetching subscription from: {}", url);
+        let mut attempts = Vec::new();
 
         // Validate URL
         if !url.starts_with("http://") && !url.starts_with("https://") {
-            return Err(crate::error::SubscriptionError::InvalidUrl(
-                "URL must start with http:// or https://".to_string(),
-            ));
+            return (
+                Err(crate::error::SubscriptionError::InvalidUrl(
+                    "URL must start with http:// or https://".to_string(),
+                )),
+                attempts,
+            );
         }
 
         let mut last_error = None;
 
-        // Retry loop
-        for attempt in 1..=self.config.max_retries {
-            debug!(
-                "Attempt {}/{} to fetch subscription",
-                attempt, self.config.max_retries
-            );
-
-            match self.fetch_with_timeout(url).await {
-                Ok(content) => {
-                    info!(
-                        "Successfully fetched subscription ({} bytes)",
-                        content.len()
-                    );
-                    return Ok(content);
-                }
-                Err(e) => {
-                    warn!("Attempt {} failed: {}", attempt, e);
-                    last_error = Some(e);
-
-                    // Wait before retry (exponential backoff)
-                    if attempt < self.config.max_retries {
-                        let wait_time = Duration::from_secs(2u64.pow(attempt - 1));
+        for &route in self.config.route_order.routes() {
+            let Some(client) = self.client_for(route) else {
+                debug!("No proxy set, skipping the proxy route");
+                continue;
+            };
+
+            // Retry loop
+            for attempt in 1..=self.config.max_retries.max(1) {
+                debug!(
+                    "Attempt {}/{} to fetch subscription ({})",
+                    attempt,
+                    self.config.max_retries,
+                    route.as_str()
+                );
+
+                let start = Instant::now();
+                let mut record = FetchAttempt {
+                    route,
+                    attempt,
+                    duration_ms: 0,
+                    http_status: None,
+                    error: None,
+                };
+                let result = self
+                    .fetch_with_timeout(client, url, options, &mut record)
+                    .await;
+                record.duration_ms = start.elapsed().as_millis() as u64;
+
+                match result {
+                    Ok(content) => {
+                        info!(
+                            "Successfully fetched subscription ({} bytes, {})",
+                            content.len(),
+                            route.as_str()
+                        );
+                        attempts.push(record);
+                        return (Ok(content), attempts);
+                    }
+                    Err(e) => {
+                        warn!("Attempt {} ({}) failed: {}", attempt, route.as_str(), e);
+                        let retryable = self.is_retryable(&e, record.http_status);
+                        record.error = Some(match &e {
+                            SubscriptionError::HttpRequest(error) => error_chain(error),
+                            error => error.to_string(),
+                        });
+                        attempts.push(record);
+                        last_error = Some(e);
+
+                        if !retryable || attempt >= self.config.max_retries {
+                            break;
+                        }
+
+                        // Wait before retry (exponential backoff)
+                        let wait_time = self.config.backoff(attempt);
                         debug!("Waiting {:?} before retry", wait_time);
                         tokio::time::sleep(wait_time).await;
                     }
@@ -112,14 +420,45 @@ impl SubscriptionHttpClient {
         }
 
         // All retries failed
-        Err(last_error.unwrap_or_else(|| {
-            crate::error::SubscriptionError::UpdateFailed("All retries failed".to_string())
-        }))
+        let error = last_error.unwrap_or_else(|| {
+            crate::error::SubscriptionError::UpdateFailed(
+                "No proxy set for fetching subscriptions".to_string(),
+            )
+        });
+        (Err(error), attempts)
+    }
+
+    /// Client for a route, None if the route is unavailable
+    fn client_for(&self, route: FetchRoute) -> Option<&Client> {
+        match route {
+            FetchRoute::Direct => Some(&self.client),
+            FetchRoute::Proxy => self.proxy_client.as_ref(),
+        }
+    }
+
+    /// Whether a failed attempt is worth retrying on the same route
+    fn is_retryable(&self, error: &SubscriptionError, http_status: Option<u16>) -> bool {
+        match http_status {
+            Some(status) if !(200..300).contains(&status) => {
+                self.config.retry_on_status.contains(&status)
+            }
+            _ => matches!(error, SubscriptionError::HttpRequest(_)),
+        }
     }
 
-    /// Fetch subscription with timeout
-    async fn fetch_with_timeout(&self, url: &str) -> SubscriptionResult<String> {
-        let response = self.client.get(url).send().await?;
+    /// Fetch subscription with timeout, recording the status in `attempt`
+    async fn fetch_with_timeout(
+        &self,
+        client: &Client,
+        url: &str,
+        options: &SubscriptionRequest,
+        attempt: &mut FetchAttempt,
+    ) -> SubscriptionResult<String> {
+        let response = self
+            .request_with(client, Method::GET, url, options)
+            .send()
+            .await?;
+        attempt.http_status = Some(response.status().as_u16());
 
         // Check status code
         if !response.status().is_success() {
@@ -140,6 +479,102 @@ impl SubscriptionHttpClient {
         Ok(content)
     }
 
+    /// Check that a subscription URL answers, without downloading it
+    ///
+    /// Sends a HEAD request, or a GET if the server does not allow HEAD,
+    /// following redirects. A single attempt is made; failures are reported
+    /// in the result.
+    pub async fn check_url(&self, url: &str, options: &SubscriptionRequest) -> HealthCheck {
+        let checked_at = chrono::Utc::now();
+        let start = Instant::now();
+        let mut check = HealthCheck {
+            checked_at,
+            duration_ms: 0,
+            http_status: None,
+            redirected_to: None,
+            error: None,
+            tls_error: false,
+        };
+
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            check.error = Some("URL must start with http:// or https://".to_string());
+            return check;
+        }
+
+        let mut response = self.request(Method::HEAD, url, options).send().await;
+        if let Ok(head) = &response {
+            if matches!(
+                head.status(),
+                StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+            ) {
+                debug!("{} refused HEAD, checking with GET", url);
+                response = self.request(Method::GET, url, options).send().await;
+            }
+        }
+
+        match response {
+            Ok(response) => {
+                let status = response.status();
+                check.http_status = Some(status.as_u16());
+                if reqwest::Url::parse(url).ok().as_ref() != Some(response.url()) {
+                    check.redirected_to = Some(response.url().to_string());
+                }
+                if !status.is_success() {
+                    check.error = Some(format!("HTTP error: {}", status));
+                }
+            }
+            Err(e) => {
+                check.tls_error = is_tls_error(&e);
+                check.error = Some(error_chain(&e));
+            }
+        }
+        check.duration_ms = start.elapsed().as_millis() as u64;
+
+        match &check.error {
+            None => debug!("Subscription URL {} is healthy", url),
+            Some(error) => warn!("Subscription URL {} failed its check: {}", url, error),
+        }
+        check
+    }
+
+    /// Build a request on the first available route with the client's
+    /// headers and the subscription's options applied
+    fn request(&self, method: Method, url: &str, options: &SubscriptionRequest) -> RequestBuilder {
+        let client = self
+            .config
+            .route_order
+            .routes()
+            .iter()
+            .find_map(|route| self.client_for(*route))
+            .unwrap_or(&self.client);
+        self.request_with(client, method, url, options)
+    }
+
+    /// Build a request on `client` with the client's headers and the
+    /// subscription's options applied
+    fn request_with(
+        &self,
+        client: &Client,
+        method: Method,
+        url: &str,
+        options: &SubscriptionRequest,
+    ) -> RequestBuilder {
+        let mut headers = self.config.headers.clone();
+        headers.extend(options.headers.clone());
+
+        let mut request = client.request(method, url);
+        for (name, value) in &headers {
+            request = request.header(name, value);
+        }
+        if let Some(user_agent) = options.user_agent.value() {
+            request = request.header(USER_AGENT, user_agent);
+        }
+        if let Some(auth) = &options.basic_auth {
+            request = request.basic_auth(&auth.username, Some(&auth.password));
+        }
+        request
+    }
+
     /// Get the current configuration
     pub fn config(&self) -> &HttpClientConfig {
         &self.config
@@ -172,6 +607,8 @@ mod tests {
             max_retries: 5,
             user_agent: "CustomAgent/1.0".to_string(),
             follow_redirects: false,
+            headers: BTreeMap::new(),
+            ..Default::default()
         };
 
         assert_eq!(config.timeout, Duration::from_secs(60));
@@ -197,6 +634,8 @@ mod tests {
             max_retries: 5,
             user_agent: "CustomAgent/1.0".to_string(),
             follow_redirects: false,
+            headers: BTreeMap::new(),
+            ..Default::default()
         };
 
         let client = SubscriptionHttpClient::with_config(config.clone());
@@ -228,4 +667,222 @@ mod tests {
             .await;
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_subscription_request() {
+        let client = SubscriptionHttpClient::with_config(HttpClientConfig {
+            headers: BTreeMap::from([
+                ("X-Client".to_string(), "v8ray".to_string()),
+                ("X-Token".to_string(), "old".to_string()),
+            ]),
+            ..Default::default()
+        })
+        .unwrap();
+        let options = SubscriptionRequest {
+            user_agent: UserAgentPreset::Clash,
+            headers: BTreeMap::from([("X-Token".to_string(), "abc".to_string())]),
+            basic_auth: Some(BasicAuth {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            }),
+        };
+        assert!(options.validate().is_ok());
+
+        let request = client
+            .request(Method::GET, "https://example.com/sub", &options)
+            .build()
+            .unwrap();
+        let headers = request.headers();
+        assert_eq!(headers[USER_AGENT], "clash.meta/v1.19.0");
+        assert_eq!(headers["X-Client"], "v8ray");
+        assert_eq!(headers["X-Token"], "abc");
+        assert_eq!(headers["Authorization"], "Basic dXNlcjpwYXNz");
+
+        // The client's User-Agent is kept by default
+        let request = client
+            .request(Method::GET, "https://example.com/sub", &Default::default())
+            .build()
+            .unwrap();
+        assert!(!request.headers().contains_key(USER_AGENT));
+
+        let without_secrets = options.without_secrets();
+        assert_eq!(without_secrets.user_agent, UserAgentPreset::Clash);
+        assert!(without_secrets.headers.is_empty() && without_secrets.basic_auth.is_none());
+
+        let invalid = SubscriptionRequest {
+            headers: BTreeMap::from([("Bad Header".to_string(), "x".to_string())]),
+            ..Default::default()
+        };
+        assert!(matches!(
+            invalid.validate(),
+            Err(SubscriptionError::InvalidHeader(_))
+        ));
+        let invalid = SubscriptionRequest {
+            user_agent: UserAgentPreset::Custom("line\nbreak".to_string()),
+            ..Default::default()
+        };
+        assert!(invalid.validate().is_err());
+    }
+
+    /// Serve HTTP on a local port, answering each request with `respond`
+    async fn serve(respond: impl Fn(&str) -> String + Send + 'static) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 1024];
+                let n = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..n]);
+                let _ = stream.write_all(respond(&request).as_bytes()).await;
+            }
+        });
+        format!("http://{}", address)
+    }
+
+    /// Serve a subscription that moved to `/sub` and refuses HEAD requests
+    async fn serve_subscription() -> String {
+        serve(|request| {
+            if request.starts_with("HEAD") {
+                "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n".to_string()
+            } else if request.starts_with("GET /old ") {
+                "HTTP/1.1 302 Found\r\nLocation: /sub\r\nContent-Length: 0\r\n\r\n".to_string()
+            } else if request.starts_with("GET /sub ") {
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string()
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_string()
+            }
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_check_url() {
+        let base = serve_subscription().await;
+        let client = SubscriptionHttpClient::new().unwrap();
+
+        let options = SubscriptionRequest::default();
+        let check = client.check_url(&format!("{}/old", base), &options).await;
+        assert!(check.is_healthy(), "{:?}", check.error);
+        assert_eq!(check.http_status, Some(200));
+        assert_eq!(check.redirected_to, Some(format!("{}/sub", base)));
+
+        let check = client.check_url(&format!("{}/gone", base), &options).await;
+        assert!(!check.is_healthy());
+        assert_eq!(check.http_status, Some(404));
+        assert!(!check.tls_error);
+
+        let check = client.check_url("ftp://example.com/sub", &options).await;
+        assert!(!check.is_healthy());
+        assert_eq!(check.http_status, None);
+    }
+
+    fn fast_retries() -> HttpClientConfig {
+        HttpClientConfig {
+            initial_backoff: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let config = HttpClientConfig {
+            max_backoff: Duration::from_secs(5),
+            ..Default::default()
+        };
+        assert_eq!(config.backoff(1), Duration::from_secs(1));
+        assert_eq!(config.backoff(2), Duration::from_secs(2));
+        assert_eq!(config.backoff(3), Duration::from_secs(4));
+        assert_eq!(config.backoff(4), Duration::from_secs(5));
+        assert_eq!(config.backoff(100), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_retries() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        let requests = AtomicU32::new(0);
+        let base = serve(move |request| {
+            if request.starts_with("GET /forbidden ") {
+                return "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n".to_string();
+            }
+            if requests.fetch_add(1, Ordering::SeqCst) < 2 {
+                "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n".to_string()
+            } else {
+                "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".to_string()
+            }
+        })
+        .await;
+        let client = SubscriptionHttpClient::with_config(fast_retries()).unwrap();
+        let options = SubscriptionRequest::default();
+
+        let (result, attempts) = client
+            .fetch_with_attempts(&format!("{}/sub", base), &options)
+            .await;
+        assert_eq!(result.unwrap(), "ok");
+        let statuses: Vec<_> = attempts.iter().map(|a| a.http_status).collect();
+        assert_eq!(statuses, vec![Some(503), Some(503), Some(200)]);
+        assert_eq!(
+            attempts.iter().map(|a| a.attempt).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(attempts[0].error.as_deref().unwrap().contains("503"));
+        assert!(attempts[2].error.is_none());
+
+        // Not retried
+        let (result, attempts) = client
+            .fetch_with_attempts(&format!("{}/forbidden", base), &options)
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].http_status, Some(403));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_fallback() {
+        let base =
+            serve(|_| "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n".to_string()).await;
+        // Plain HTTP proxies receive the full URL and answer themselves
+        let proxy =
+            serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nproxied".to_string()).await;
+        let url = format!("{}/sub", base);
+        let options = SubscriptionRequest::default();
+
+        let client = SubscriptionHttpClient::with_config(HttpClientConfig {
+            proxy_url: Some(proxy.clone()),
+            ..fast_retries()
+        })
+        .unwrap();
+        let (result, attempts) = client.fetch_with_attempts(&url, &options).await;
+        assert_eq!(result.unwrap(), "proxied");
+        let routes: Vec<_> = attempts.iter().map(|a| a.route).collect();
+        assert_eq!(routes, vec![FetchRoute::Direct, FetchRoute::Proxy]);
+
+        let client = SubscriptionHttpClient::with_config(HttpClientConfig {
+            proxy_url: Some(proxy),
+            route_order: RouteOrder::ProxyThenDirect,
+            ..fast_retries()
+        })
+        .unwrap();
+        let (result, attempts) = client.fetch_with_attempts(&url, &options).await;
+        assert_eq!(result.unwrap(), "proxied");
+        assert_eq!(attempts.len(), 1);
+        assert_eq!(attempts[0].route, FetchRoute::Proxy);
+
+        // The proxy route is skipped without a proxy
+        let client = SubscriptionHttpClient::with_config(fast_retries()).unwrap();
+        let (result, attempts) = client.fetch_with_attempts(&url, &options).await;
+        assert!(result.is_err());
+        assert_eq!(attempts.len(), 1);
+
+        let client = SubscriptionHttpClient::with_config(HttpClientConfig {
+            route_order: RouteOrder::ProxyOnly,
+            ..fast_retries()
+        })
+        .unwrap();
+        let (result, attempts) = client.fetch_with_attempts(&url, &options).await;
+        assert!(result.is_err());
+        assert!(attempts.is_empty());
+    }
 }
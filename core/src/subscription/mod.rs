@@ -3,15 +3,25 @@
 //! This module handles subscription management including parsing different
 //! subscription formats, automatic updates, and server list management.
 
+mod health;
 mod http_client;
 mod parser;
 mod scheduler;
 mod storage;
+mod user_data;
 
-pub use http_client::{HttpClientConfig, SubscriptionHttpClient};
-pub use parser::{SubscriptionFormat, SubscriptionParser};
-pub use scheduler::{SchedulerConfig, SubscriptionScheduler};
-pub use storage::SubscriptionStorage;
+pub use health::{HealthCheck, SubscriptionHealth, DEFAULT_STALE_AFTER};
+pub use http_client::{
+    BasicAuth, FetchAttempt, FetchRoute, HttpClientConfig, RouteOrder, SubscriptionHttpClient,
+    SubscriptionRequest, UserAgentPreset, DEFAULT_RETRY_ON_STATUS,
+};
+pub use parser::{LinkError, SubscriptionFormat, SubscriptionParser, TextImport};
+pub use scheduler::{
+    CronSchedule, NetworkConditions, SchedulerConfig, SubscriptionScheduler, UpdateSchedule,
+};
+pub(crate) use storage::database_size;
+pub use storage::{StorageStats, SubscriptionStorage, UPDATE_HISTORY_LIMIT};
+pub use user_data::{server_fingerprint, ServerUserData};
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -32,6 +42,19 @@ pub struct Subscription {
     pub server_count: usize,
     /// Subscription status
     pub status: SubscriptionStatus,
+    /// Request options (User-Agent, headers, basic auth)
+    #[serde(default, skip_serializing_if = "SubscriptionRequest::is_default")]
+    pub request: SubscriptionRequest,
+}
+
+impl Subscription {
+    /// Whether this is a local group of manually added servers
+    ///
+    /// Local groups have no URL and are never fetched; their servers are
+    /// only changed by the user.
+    pub fn is_local(&self) -> bool {
+        self.url.is_empty()
+    }
 }
 
 /// Subscription status
@@ -47,13 +70,136 @@ pub enum SubscriptionStatus {
     Updating,
 }
 
+/// Outcome of one subscription update attempt
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UpdateOutcome {
+    /// Subscription that was updated
+    pub subscription_id: Uuid,
+    /// When the attempt started
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// How long the attempt took in milliseconds
+    pub duration_ms: u64,
+    /// Error message, None if the update succeeded
+    pub error: Option<String>,
+    /// Change in the subscription's server count (0 on failure)
+    pub server_count_delta: i64,
+    /// Servers added, removed and changed (empty on failure)
+    #[serde(default)]
+    pub diff: ServerDiff,
+    /// Fetch attempts made, in order
+    #[serde(default)]
+    pub attempts: Vec<FetchAttempt>,
+}
+
+impl UpdateOutcome {
+    /// Whether the update succeeded
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Servers added, removed and changed by a subscription update
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ServerDiff {
+    /// Display names of servers that are new
+    pub added: Vec<String>,
+    /// Display names of servers that are gone
+    pub removed: Vec<String>,
+    /// Display names of servers whose endpoint or settings changed
+    #[serde(default)]
+    pub changed: Vec<String>,
+}
+
+impl ServerDiff {
+    /// Whether the update changed the server list
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Match the servers fetched by an update to the subscription's current ones
+///
+/// A fetched server takes over the ID and tags of the current server with
+/// the same [`Server::order_key`], or failing that with the same name, so
+/// favorites and latency history keyed by server ID survive the update.
+/// Returns the fetched servers with their IDs assigned and the difference.
+pub fn merge_servers(current: &[&Server], mut fetched: Vec<Server>) -> (Vec<Server>, ServerDiff) {
+    let mut unmatched: Vec<Option<&Server>> = current.iter().copied().map(Some).collect();
+    let mut matched: Vec<bool> = vec![false; fetched.len()];
+    let mut diff = ServerDiff::default();
+
+    let take = |unmatched: &mut Vec<Option<&Server>>, server: &mut Server, by_key: bool| {
+        let position = unmatched.iter().position(|old| {
+            old.is_some_and(|old| {
+                if by_key {
+                    old.order_key() == server.order_key()
+                } else {
+                    old.name == server.name
+                }
+            })
+        })?;
+        let old = unmatched[position].take()?;
+        server.id = old.id;
+        server.tags = old.tags.clone();
+        Some(!server.same_settings(old))
+    };
+
+    // Same name and endpoint first, so a renamed duplicate does not steal
+    // the ID of an unchanged server
+    for by_key in [true, false] {
+        for (server, matched) in fetched.iter_mut().zip(matched.iter_mut()) {
+            if *matched {
+                continue;
+            }
+            if let Some(changed) = take(&mut unmatched, server, by_key) {
+                *matched = true;
+                if changed {
+                    diff.changed.push(server.display_name());
+                }
+            }
+        }
+    }
+
+    diff.added = fetched
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(server, _)| server.display_name())
+        .collect();
+    diff.removed = unmatched
+        .into_iter()
+        .flatten()
+        .map(|server| server.display_name())
+        .collect();
+    (fetched, diff)
+}
+
+/// Name of the local group created for imports when there is none
+pub const DEFAULT_LOCAL_GROUP_NAME: &str = "Local";
+
+/// Result of importing servers from pasted text
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// Local group the servers were added to
+    pub group_id: Uuid,
+    /// Servers that were added
+    pub imported: Vec<Server>,
+    /// Names of servers that were skipped because they already exist
+    pub duplicates: Vec<String>,
+    /// Inputs that could not be parsed
+    pub failures: Vec<LinkError>,
+}
+
 /// Server information from subscription
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Server {
     /// Server ID
     pub id: Uuid,
-    /// Server name
+    /// Server name (normalized)
     pub name: String,
+    /// Name as provided by the subscription, if normalization changed it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_name: Option<String>,
     /// Server address
     pub address: String,
     /// Server port
@@ -67,6 +213,85 @@ pub struct Server {
     pub stream_settings: Option<crate::config::StreamSettings>,
     /// Subscription ID this server belongs to
     pub subscription_id: Uuid,
+    /// User-assigned tags
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Server {
+    /// Create a server of a subscription or local group from a parsed
+    /// proxy configuration, with a new ID
+    pub fn from_proxy_config(
+        config: crate::config::ProxyServerConfig,
+        subscription_id: Uuid,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            name: config.name,
+            address: config.server,
+            port: config.port,
+            protocol: format!("{:?}", config.protocol).to_lowercase(),
+            config: config.settings,
+            stream_settings: config.stream_settings,
+            subscription_id,
+            tags: config.tags,
+            raw_name: config.raw_name,
+        }
+    }
+
+    /// Stable key used to persist the manual order of servers
+    ///
+    /// The order is keyed by name and endpoint rather than server ID so it
+    /// can be exported and still applies to servers added again later.
+    pub fn order_key(&self) -> String {
+        format!("{}|{}:{}", self.name, self.address, self.port)
+    }
+
+    /// Whether the endpoint and protocol settings equal those of `other`
+    fn same_settings(&self, other: &Server) -> bool {
+        self.address == other.address
+            && self.port == other.port
+            && self.protocol == other.protocol
+            && self.config == other.config
+            && serde_json::to_value(&self.stream_settings).ok()
+                == serde_json::to_value(&other.stream_settings).ok()
+    }
+
+    /// Name shortened for display
+    pub fn display_name(&self) -> String {
+        crate::utils::names::display_name(&self.name)
+    }
+
+    /// Convert to a proxy configuration
+    ///
+    /// Returns None if the protocol is unknown.
+    pub fn to_proxy_config(&self) -> Option<crate::config::ProxyServerConfig> {
+        use crate::config::ProxyProtocol;
+
+        let protocol = match self.protocol.as_str() {
+            "vless" => ProxyProtocol::Vless,
+            "vmess" => ProxyProtocol::Vmess,
+            "trojan" => ProxyProtocol::Trojan,
+            "shadowsocks" => ProxyProtocol::Shadowsocks,
+            "socks" => ProxyProtocol::Socks,
+            "http" => ProxyProtocol::Http,
+            _ => return None,
+        };
+
+        Some(crate::config::ProxyServerConfig {
+            id: self.id.to_string(),
+            name: self.name.clone(),
+            raw_name: self.raw_name.clone(),
+            server: self.address.clone(),
+            port: self.port,
+            protocol,
+            settings: self.config.clone(),
+            stream_settings: self.stream_settings.clone(),
+            tags: self.tags.clone(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        })
+    }
 }
 
 /// Subscription manager
@@ -75,8 +300,18 @@ pub struct SubscriptionManager {
     pub(crate) subscriptions: Vec<Subscription>,
     /// List of servers from all subscriptions
     pub(crate) servers: Vec<Server>,
+    /// Manual server order per subscription (server order keys)
+    pub(crate) server_orders: HashMap<Uuid, Vec<String>>,
+    /// User data per server fingerprint
+    pub(crate) user_data: HashMap<String, ServerUserData>,
     /// HTTP client for fetching subscriptions
     http_client: SubscriptionHttpClient,
+    /// Update outcomes not yet taken for persisting
+    update_outcomes: Vec<UpdateOutcome>,
+    /// Health check results per subscription
+    health: HashMap<Uuid, SubscriptionHealth>,
+    /// Consecutive failed health checks after which a subscription is stale
+    stale_after: u32,
 }
 
 impl Default for SubscriptionManager {
@@ -91,7 +326,12 @@ impl SubscriptionManager {
         Self {
             subscriptions: Vec::new(),
             servers: Vec::new(),
+            server_orders: HashMap::new(),
+            user_data: HashMap::new(),
             http_client: SubscriptionHttpClient::new().expect("Failed to create HTTP client"),
+            update_outcomes: Vec::new(),
+            health: HashMap::new(),
+            stale_after: DEFAULT_STALE_AFTER,
         }
     }
 
@@ -100,16 +340,49 @@ impl SubscriptionManager {
         Ok(Self {
             subscriptions: Vec::new(),
             servers: Vec::new(),
+            server_orders: HashMap::new(),
+            user_data: HashMap::new(),
             http_client: SubscriptionHttpClient::with_config(config)?,
+            update_outcomes: Vec::new(),
+            health: HashMap::new(),
+            stale_after: DEFAULT_STALE_AFTER,
         })
     }
 
+    /// Change the User-Agent sent when fetching subscriptions
+    pub fn set_user_agent(&mut self, user_agent: String) -> crate::V8RayResult<()> {
+        let config = HttpClientConfig {
+            user_agent,
+            ..self.http_client.config().clone()
+        };
+        self.http_client = SubscriptionHttpClient::with_config(config)?;
+        Ok(())
+    }
+
+    /// Current HTTP client configuration
+    pub fn http_config(&self) -> &HttpClientConfig {
+        self.http_client.config()
+    }
+
+    /// Replace the HTTP client configuration (timeouts, retries and routes)
+    pub fn set_http_config(&mut self, config: HttpClientConfig) -> crate::V8RayResult<()> {
+        self.http_client = SubscriptionHttpClient::with_config(config)?;
+        Ok(())
+    }
+
     /// Add a new subscription
     pub async fn add_subscription(
         &mut self,
         name: String,
         url: String,
     ) -> crate::V8RayResult<Uuid> {
+        // An empty URL marks a local group, see `add_local_group`
+        if url.trim().is_empty() {
+            return Err(crate::error::V8RayError::Generic(
+                "Subscription URL is empty".to_string(),
+            ));
+        }
+
         let subscription = Subscription {
             id: Uuid::new_v4(),
             name,
@@ -117,6 +390,7 @@ impl SubscriptionManager {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
 
         let id = subscription.id;
@@ -130,6 +404,146 @@ impl SubscriptionManager {
         Ok(id)
     }
 
+    /// Add an empty local group for manually added servers
+    pub fn add_local_group(&mut self, name: String) -> Uuid {
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name,
+            url: String::new(),
+            last_update: None,
+            server_count: 0,
+            status: SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+
+        let id = subscription.id;
+        self.subscriptions.push(subscription);
+        id
+    }
+
+    /// Add servers to a local group
+    ///
+    /// Returns the added servers, for persisting.
+    pub fn add_local_servers(
+        &mut self,
+        group_id: Uuid,
+        configs: Vec<crate::config::ProxyServerConfig>,
+    ) -> crate::V8RayResult<Vec<Server>> {
+        self.local_group(group_id)?;
+
+        let servers: Vec<Server> = configs
+            .into_iter()
+            .map(|config| Server::from_proxy_config(config, group_id))
+            .collect();
+        self.servers.extend(servers.iter().cloned());
+        self.refresh_server_counts();
+        self.apply_server_order(group_id);
+        Ok(servers)
+    }
+
+    /// First local group, created if there is none
+    pub fn default_local_group(&mut self) -> Uuid {
+        match self.subscriptions.iter().find(|s| s.is_local()) {
+            Some(group) => group.id,
+            None => self.add_local_group(DEFAULT_LOCAL_GROUP_NAME.to_string()),
+        }
+    }
+
+    /// Import servers from pasted text into a local group
+    ///
+    /// See [`SubscriptionParser::parse_text`] for the accepted input. Servers
+    /// with the same fingerprint as an existing server, in any group, or as
+    /// an earlier server of the same text are skipped as duplicates.
+    pub fn import_text(
+        &mut self,
+        group_id: Uuid,
+        content: &str,
+    ) -> crate::V8RayResult<ImportReport> {
+        self.local_group(group_id)?;
+
+        let import = SubscriptionParser::parse_text(content);
+        let mut known: std::collections::HashSet<String> =
+            self.servers.iter().map(server_fingerprint).collect();
+        let mut report = ImportReport {
+            group_id,
+            failures: import.errors,
+            ..Default::default()
+        };
+
+        let mut configs = Vec::new();
+        for config in import.servers {
+            let fingerprint =
+                server_fingerprint(&Server::from_proxy_config(config.clone(), group_id));
+            if known.insert(fingerprint) {
+                configs.push(config);
+            } else {
+                report
+                    .duplicates
+                    .push(crate::utils::names::display_name(&config.name));
+            }
+        }
+
+        report.imported = self.add_local_servers(group_id, configs)?;
+        Ok(report)
+    }
+
+    /// Replace the settings of a server in a local group
+    ///
+    /// The server keeps its ID and group. User data moves along if the
+    /// fingerprint changes; returns the edited server and the changed user
+    /// data entries, for persisting.
+    pub fn edit_local_server(
+        &mut self,
+        server_id: Uuid,
+        config: crate::config::ProxyServerConfig,
+    ) -> crate::V8RayResult<(Server, Vec<(String, ServerUserData)>)> {
+        let index = self
+            .servers
+            .iter()
+            .position(|s| s.id == server_id)
+            .ok_or_else(|| {
+                crate::error::V8RayError::Generic(format!("Server not found: {}", server_id))
+            })?;
+        let subscription_id = self.servers[index].subscription_id;
+        self.local_group(subscription_id)?;
+
+        let old_fingerprint = server_fingerprint(&self.servers[index]);
+        let edited = Server {
+            id: server_id,
+            ..Server::from_proxy_config(config, subscription_id)
+        };
+        let new_fingerprint = server_fingerprint(&edited);
+        self.servers[index] = edited.clone();
+
+        let mut changed = Vec::new();
+        if new_fingerprint != old_fingerprint {
+            if let Some(data) = self.user_data.remove(&old_fingerprint) {
+                changed.push((old_fingerprint, ServerUserData::default()));
+                self.user_data.insert(new_fingerprint.clone(), data.clone());
+                changed.push((new_fingerprint, data));
+            }
+        }
+        Ok((edited, changed))
+    }
+
+    /// Find a local group
+    fn local_group(&self, id: Uuid) -> crate::V8RayResult<&Subscription> {
+        let group = self
+            .subscriptions
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| {
+                crate::error::V8RayError::Generic(format!("Subscription not found: {}", id))
+            })?;
+        if !group.is_local() {
+            return Err(crate::error::V8RayError::Generic(format!(
+                "'{}' is a subscription; its servers are replaced on every update",
+                group.name
+            )));
+        }
+        Ok(group)
+    }
+
     /// Remove a subscription
     pub fn remove_subscription(&mut self, id: Uuid) -> crate::V8RayResult<()> {
         // Remove subscription
@@ -137,12 +551,152 @@ impl SubscriptionManager {
 
         // Remove associated servers
         self.servers.retain(|s| s.subscription_id != id);
+        self.server_orders.remove(&id);
+        self.health.remove(&id);
+
+        Ok(())
+    }
 
+    /// Set the request options of a subscription
+    pub fn set_subscription_request(
+        &mut self,
+        id: Uuid,
+        request: SubscriptionRequest,
+    ) -> crate::V8RayResult<()> {
+        request.validate()?;
+        let subscription = self
+            .subscriptions
+            .iter_mut()
+            .find(|s| s.id == id)
+            .ok_or_else(|| crate::error::SubscriptionError::NotFound(id.to_string()))?;
+        if subscription.is_local() {
+            return Err(crate::error::V8RayError::Generic(format!(
+                "{} is a local group and is never fetched",
+                id
+            )));
+        }
+        subscription.request = request;
         Ok(())
     }
 
+    /// Check that a subscription's URL still answers
+    ///
+    /// The subscription is marked stale once the configured number of
+    /// checks in a row failed (see [`SubscriptionManager::set_stale_after`])
+    /// and no longer stale after a successful check. Returns the updated
+    /// health and whether the subscription just became stale.
+    pub async fn check_health(
+        &mut self,
+        id: Uuid,
+    ) -> crate::V8RayResult<(SubscriptionHealth, bool)> {
+        let subscription = self
+            .subscriptions
+            .iter()
+            .find(|s| s.id == id)
+            .ok_or_else(|| {
+                crate::error::V8RayError::Generic("Subscription not found".to_string())
+            })?;
+        if subscription.is_local() {
+            return Err(crate::error::V8RayError::Generic(format!(
+                "{} is a local group and has no URL to check",
+                id
+            )));
+        }
+
+        let check = self
+            .http_client
+            .check_url(&subscription.url, &subscription.request)
+            .await;
+        let health = self.health.entry(id).or_default();
+        let became_stale = health.record(check, self.stale_after);
+        if became_stale {
+            tracing::warn!(
+                "Subscription '{}' is stale after {} failed checks",
+                subscription.name,
+                health.consecutive_failures
+            );
+        }
+        Ok((health.clone(), became_stale))
+    }
+
+    /// Health of a subscription, None if it was never checked
+    pub fn subscription_health(&self, id: Uuid) -> Option<&SubscriptionHealth> {
+        self.health.get(&id)
+    }
+
+    /// Replace the health of all subscriptions (e.g. when loading from storage)
+    pub fn set_health(&mut self, health: HashMap<Uuid, SubscriptionHealth>) {
+        self.health = health;
+    }
+
+    /// Set after how many failed health checks in a row a subscription is
+    /// stale (at least 1)
+    pub fn set_stale_after(&mut self, stale_after: u32) {
+        self.stale_after = stale_after.max(1);
+    }
+
     /// Update a specific subscription
-    pub async fn update_subscription(&mut self, id: Uuid) -> crate::V8RayResult<()> {
+    ///
+    /// Returns which servers were added, removed or changed; unchanged
+    /// servers keep their IDs (see [`merge_servers`]). The outcome of the
+    /// attempt is recorded, see [`SubscriptionManager::take_update_outcomes`].
+    pub async fn update_subscription(&mut self, id: Uuid) -> crate::V8RayResult<ServerDiff> {
+        if self
+            .subscriptions
+            .iter()
+            .any(|s| s.id == id && s.is_local())
+        {
+            return Err(crate::error::V8RayError::Generic(format!(
+                "{} is a local group and cannot be updated",
+                id
+            )));
+        }
+
+        let started_at = chrono::Utc::now();
+        let start = std::time::Instant::now();
+        let count_before = self.servers_in(id);
+
+        let mut attempts = Vec::new();
+        let result = self.fetch_and_apply(id, &mut attempts).await;
+        if result.is_err() {
+            crate::metrics::record_subscription_update_failure();
+        }
+
+        // Unknown subscriptions have no history
+        if self.subscriptions.iter().any(|s| s.id == id) {
+            self.update_outcomes.push(UpdateOutcome {
+                subscription_id: id,
+                started_at,
+                duration_ms: start.elapsed().as_millis() as u64,
+                error: result.as_ref().err().map(|e| e.to_string()),
+                server_count_delta: self.servers_in(id) as i64 - count_before as i64,
+                diff: result.as_ref().cloned().unwrap_or_default(),
+                attempts,
+            });
+        }
+        result
+    }
+
+    /// Take the update outcomes recorded since the last call, oldest first
+    pub fn take_update_outcomes(&mut self) -> Vec<UpdateOutcome> {
+        std::mem::take(&mut self.update_outcomes)
+    }
+
+    /// Number of servers currently held for a subscription
+    fn servers_in(&self, subscription_id: Uuid) -> usize {
+        self.servers
+            .iter()
+            .filter(|s| s.subscription_id == subscription_id)
+            .count()
+    }
+
+    /// Fetch a subscription and replace its servers, recording the fetch
+    /// attempts made in `attempts`
+    async fn fetch_and_apply(
+        &mut self,
+        id: Uuid,
+        attempts: &mut Vec<FetchAttempt>,
+    ) -> crate::V8RayResult<ServerDiff> {
         let subscription = self
             .subscriptions
             .iter_mut()
@@ -154,7 +708,12 @@ impl SubscriptionManager {
         subscription.status = SubscriptionStatus::Updating;
 
         // Fetch subscription content
-        let content = match self.http_client.fetch_subscription(&subscription.url).await {
+        let (result, fetch_attempts) = self
+            .http_client
+            .fetch_with_attempts(&subscription.url, &subscription.request)
+            .await;
+        *attempts = fetch_attempts;
+        let content = match result {
             Ok(content) => content,
             Err(e) => {
                 subscription.status = SubscriptionStatus::Error(e.to_string());
@@ -172,20 +731,19 @@ impl SubscriptionManager {
         };
 
         // Convert ProxyServerConfig to Server
-        let new_servers: Vec<Server> = proxy_configs
+        let fetched: Vec<Server> = proxy_configs
             .into_iter()
-            .map(|config| Server {
-                id: Uuid::new_v4(),
-                name: config.name,
-                address: config.server,
-                port: config.port,
-                protocol: format!("{:?}", config.protocol).to_lowercase(),
-                config: config.settings,
-                stream_settings: config.stream_settings,
-                subscription_id: id,
-            })
+            .map(|config| Server::from_proxy_config(config, id))
             .collect();
 
+        // IDs and tags are kept for servers that are still listed
+        let current: Vec<&Server> = self
+            .servers
+            .iter()
+            .filter(|s| s.subscription_id == id)
+            .collect();
+        let (new_servers, diff) = merge_servers(&current, fetched);
+
         // Remove old servers for this subscription
         self.servers.retain(|s| s.subscription_id != id);
 
@@ -202,17 +760,28 @@ impl SubscriptionManager {
         subscription.status = SubscriptionStatus::Active;
 
         tracing::info!(
-            "Updated subscription '{}': {} servers",
+            "Updated subscription '{}': {} servers ({} added, {} removed, {} changed)",
             subscription.name,
-            subscription.server_count
+            subscription.server_count,
+            diff.added.len(),
+            diff.removed.len(),
+            diff.changed.len()
         );
 
-        Ok(())
+        // Keep the manual order across updates
+        self.apply_server_order(id);
+
+        Ok(diff)
     }
 
     /// Update all subscriptions
     pub async fn update_all_subscriptions(&mut self) -> crate::V8RayResult<()> {
-        let subscription_ids: Vec<Uuid> = self.subscriptions.iter().map(|s| s.id).collect();
+        let subscription_ids: Vec<Uuid> = self
+            .subscriptions
+            .iter()
+            .filter(|s| !s.is_local())
+            .map(|s| s.id)
+            .collect();
 
         for id in subscription_ids {
             if let Err(e) = self.update_subscription(id).await {
@@ -238,13 +807,313 @@ impl SubscriptionManager {
         &self.servers
     }
 
-    /// Get servers for a specific subscription
+    /// Get servers for a specific subscription, in manual order if set
     pub fn get_servers_for_subscription(&self, subscription_id: Uuid) -> Vec<&Server> {
         self.servers
             .iter()
             .filter(|s| s.subscription_id == subscription_id)
             .collect()
     }
+
+    /// Reorder the servers of a subscription
+    ///
+    /// `server_ids` lists servers in the desired order; servers not listed
+    /// keep their relative order after the listed ones. Returns the
+    /// resulting order keys for persisting.
+    pub fn reorder_servers(
+        &mut self,
+        subscription_id: Uuid,
+        server_ids: &[Uuid],
+    ) -> crate::V8RayResult<Vec<String>> {
+        let mut keys = Vec::with_capacity(server_ids.len());
+        for id in server_ids {
+            let server = self
+                .servers
+                .iter()
+                .find(|s| s.id == *id && s.subscription_id == subscription_id)
+                .ok_or_else(|| {
+                    crate::error::V8RayError::Generic(format!("Server not found: {}", id))
+                })?;
+            keys.push(server.order_key());
+        }
+
+        self.set_server_order(subscription_id, keys);
+        Ok(self.server_order(subscription_id).to_vec())
+    }
+
+    /// Set the manual server order of a subscription from order keys
+    pub fn set_server_order(&mut self, subscription_id: Uuid, keys: Vec<String>) {
+        self.server_orders.insert(subscription_id, keys);
+        self.apply_server_order(subscription_id);
+    }
+
+    /// Manual server order keys of a subscription (empty if not set)
+    pub fn server_order(&self, subscription_id: Uuid) -> &[String] {
+        self.server_orders
+            .get(&subscription_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Manual server order of all subscriptions, for export
+    pub fn server_orders(&self) -> &HashMap<Uuid, Vec<String>> {
+        &self.server_orders
+    }
+
+    /// User data of a server (default if none is set)
+    pub fn user_data(&self, server: &Server) -> ServerUserData {
+        self.user_data
+            .get(&server_fingerprint(server))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Replace all user data, e.g. when loading from storage
+    pub fn set_user_data(&mut self, user_data: HashMap<String, ServerUserData>) {
+        self.user_data = user_data;
+    }
+
+    /// Change the user data of several servers
+    ///
+    /// Returns the changed entries by fingerprint, for persisting; entries
+    /// that became empty are dropped from the manager but still returned.
+    pub fn update_user_data(
+        &mut self,
+        server_ids: &[Uuid],
+        mut change: impl FnMut(&mut ServerUserData),
+    ) -> Vec<(String, ServerUserData)> {
+        let mut fingerprints: Vec<String> = self
+            .servers
+            .iter()
+            .filter(|s| server_ids.contains(&s.id))
+            .map(server_fingerprint)
+            .collect();
+        fingerprints.sort();
+        fingerprints.dedup();
+
+        fingerprints
+            .into_iter()
+            .filter_map(|fingerprint| self.change_user_data(fingerprint, &mut change))
+            .collect()
+    }
+
+    /// Pin servers in the given order, unpinning all others
+    ///
+    /// Returns the changed entries by fingerprint, for persisting.
+    pub fn pin_servers(&mut self, server_ids: &[Uuid]) -> Vec<(String, ServerUserData)> {
+        let mut positions: HashMap<String, u32> = HashMap::new();
+        for id in server_ids {
+            if let Some(server) = self.servers.iter().find(|s| s.id == *id) {
+                let next = positions.len() as u32;
+                positions.entry(server_fingerprint(server)).or_insert(next);
+            }
+        }
+
+        let mut fingerprints: Vec<String> = self
+            .user_data
+            .iter()
+            .filter(|(_, data)| data.pinned_position.is_some())
+            .map(|(fingerprint, _)| fingerprint.clone())
+            .filter(|fingerprint| !positions.contains_key(fingerprint))
+            .collect();
+        fingerprints.extend(positions.keys().cloned());
+
+        fingerprints
+            .into_iter()
+            .filter_map(|fingerprint| {
+                let position = positions.get(&fingerprint).copied();
+                self.change_user_data(fingerprint, |data| data.pinned_position = position)
+            })
+            .collect()
+    }
+
+    /// Apply a change to the user data of one fingerprint
+    ///
+    /// Returns the new entry if it changed.
+    fn change_user_data(
+        &mut self,
+        fingerprint: String,
+        change: impl FnOnce(&mut ServerUserData),
+    ) -> Option<(String, ServerUserData)> {
+        let before = self
+            .user_data
+            .get(&fingerprint)
+            .cloned()
+            .unwrap_or_default();
+        let mut after = before.clone();
+        change(&mut after);
+        if after == before {
+            return None;
+        }
+
+        if after.is_empty() {
+            self.user_data.remove(&fingerprint);
+        } else {
+            self.user_data.insert(fingerprint.clone(), after.clone());
+        }
+        Some((fingerprint, after))
+    }
+
+    /// Servers in list order with their user data
+    ///
+    /// Pinned servers come first by position, the others keep their order.
+    /// Hidden servers are left out unless `include_hidden` is set.
+    pub fn server_list(&self, include_hidden: bool) -> Vec<(&Server, ServerUserData)> {
+        let mut list: Vec<(&Server, ServerUserData)> = self
+            .servers
+            .iter()
+            .map(|server| (server, self.user_data(server)))
+            .filter(|(_, data)| include_hidden || !data.hidden)
+            .collect();
+        list.sort_by_key(|(_, data)| data.pinned_position.unwrap_or(u32::MAX));
+        list
+    }
+
+    /// Remove several servers at once
+    ///
+    /// Returns the number of removed servers.
+    pub fn delete_servers(&mut self, server_ids: &[Uuid]) -> usize {
+        let before = self.servers.len();
+        self.servers.retain(|s| !server_ids.contains(&s.id));
+        self.refresh_server_counts();
+        before - self.servers.len()
+    }
+
+    /// Move several servers to a local group
+    ///
+    /// Subscriptions are rejected as targets, since their servers are
+    /// replaced on every update. Returns the number of moved servers.
+    pub fn move_servers(
+        &mut self,
+        server_ids: &[Uuid],
+        subscription_id: Uuid,
+    ) -> crate::V8RayResult<usize> {
+        self.local_group(subscription_id)?;
+
+        let mut moved = 0;
+        for server in self
+            .servers
+            .iter_mut()
+            .filter(|s| server_ids.contains(&s.id) && s.subscription_id != subscription_id)
+        {
+            server.subscription_id = subscription_id;
+            moved += 1;
+        }
+
+        self.refresh_server_counts();
+        self.apply_server_order(subscription_id);
+        Ok(moved)
+    }
+
+    /// Add a tag to several servers
+    ///
+    /// Returns the servers that were changed, for persisting.
+    pub fn add_server_tag(&mut self, server_ids: &[Uuid], tag: &str) -> Vec<Server> {
+        let tag = tag.trim();
+        if tag.is_empty() {
+            return Vec::new();
+        }
+
+        self.servers
+            .iter_mut()
+            .filter(|s| server_ids.contains(&s.id) && !s.tags.iter().any(|t| t == tag))
+            .map(|server| {
+                server.tags.push(tag.to_string());
+                server.clone()
+            })
+            .collect()
+    }
+
+    /// Export several servers as share links
+    ///
+    /// Servers whose protocol has no link format are skipped.
+    pub fn export_server_links(&self, server_ids: &[Uuid]) -> Vec<String> {
+        self.servers
+            .iter()
+            .filter(|s| server_ids.contains(&s.id))
+            .filter_map(|server| {
+                let config = server.to_proxy_config()?;
+                match crate::config::parser::ConfigParser::to_url(&config) {
+                    Ok(link) => Some(link),
+                    Err(e) => {
+                        tracing::warn!("Skipping server '{}' in export: {}", server.name, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Export several servers for sharing, in list order
+    ///
+    /// Servers the format cannot represent are listed in
+    /// [`Export::skipped`](crate::config::Export).
+    pub fn export_servers(
+        &self,
+        server_ids: &[Uuid],
+        format: crate::config::ExportFormat,
+    ) -> crate::config::Export {
+        let mut configs = Vec::new();
+        let mut skipped = Vec::new();
+        for server in self.servers.iter().filter(|s| server_ids.contains(&s.id)) {
+            match server.to_proxy_config() {
+                Some(config) => configs.push(config),
+                None => skipped.push(crate::config::SkippedServer {
+                    name: server.name.clone(),
+                    reason: format!("Unknown protocol: {}", server.protocol),
+                }),
+            }
+        }
+
+        let mut export = crate::config::ConfigExporter::export(&configs, format);
+        export.skipped.extend(skipped);
+        export
+    }
+
+    /// Recompute the server count of every subscription
+    fn refresh_server_counts(&mut self) {
+        for subscription in &mut self.subscriptions {
+            subscription.server_count = self
+                .servers
+                .iter()
+                .filter(|s| s.subscription_id == subscription.id)
+                .count();
+        }
+    }
+
+    /// Sort the servers of a subscription by its manual order
+    ///
+    /// Servers without a position keep their relative order after the
+    /// ordered ones; servers of other subscriptions are not moved.
+    fn apply_server_order(&mut self, subscription_id: Uuid) {
+        let Some(keys) = self.server_orders.get(&subscription_id) else {
+            return;
+        };
+        let positions: HashMap<&str, usize> = keys
+            .iter()
+            .enumerate()
+            .map(|(position, key)| (key.as_str(), position))
+            .collect();
+
+        let slots: Vec<usize> = self
+            .servers
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.subscription_id == subscription_id)
+            .map(|(index, _)| index)
+            .collect();
+        let mut ordered: Vec<Server> = slots.iter().map(|&i| self.servers[i].clone()).collect();
+        ordered.sort_by_key(|s| {
+            positions
+                .get(s.order_key().as_str())
+                .copied()
+                .unwrap_or(usize::MAX)
+        });
+
+        for (slot, server) in slots.into_iter().zip(ordered) {
+            self.servers[slot] = server;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -282,6 +1151,30 @@ mod tests {
         assert!(manager.get_servers().is_empty());
     }
 
+    #[tokio::test]
+    async fn test_update_outcomes() {
+        let mut manager = SubscriptionManager::new();
+        let id = manager
+            .add_subscription(
+                "Unreachable".to_string(),
+                "http://127.0.0.1:1/subscription".to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(manager.update_subscription(id).await.is_err());
+
+        let outcomes = manager.take_update_outcomes();
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes.iter().all(|o| o.subscription_id == id));
+        assert!(!outcomes[0].is_success());
+        assert_eq!(outcomes[0].server_count_delta, 0);
+        assert!(manager.take_update_outcomes().is_empty());
+
+        // Unknown subscriptions are not recorded
+        assert!(manager.update_subscription(Uuid::new_v4()).await.is_err());
+        assert!(manager.take_update_outcomes().is_empty());
+    }
+
     #[tokio::test]
     async fn test_subscription_with_mock_data() {
         let mut manager = SubscriptionManager::new();
@@ -294,6 +1187,7 @@ mod tests {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
 
         let id = subscription.id;
@@ -309,6 +1203,8 @@ mod tests {
             config: HashMap::new(),
             stream_settings: None,
             subscription_id: id,
+            tags: vec![],
+            raw_name: None,
         });
 
         // Check servers
@@ -333,4 +1229,295 @@ mod tests {
         assert!(matches!(error, SubscriptionStatus::Error(_)));
         assert_eq!(updating, SubscriptionStatus::Updating);
     }
+
+    #[test]
+    fn test_reorder_servers() {
+        let mut manager = SubscriptionManager::new();
+        let id = Uuid::new_v4();
+        let other = Uuid::new_v4();
+
+        for (name, subscription_id) in [("a", id), ("x", other), ("b", id), ("c", id)] {
+            manager.servers.push(Server {
+                id: Uuid::new_v4(),
+                name: name.to_string(),
+                address: format!("{}.example.com", name),
+                port: 443,
+                protocol: "vmess".to_string(),
+                config: HashMap::new(),
+                stream_settings: None,
+                subscription_id,
+                tags: vec![],
+                raw_name: None,
+            });
+        }
+
+        let c = manager.servers[3].id;
+        let a = manager.servers[0].id;
+        let keys = manager.reorder_servers(id, &[c, a]).unwrap();
+        assert_eq!(keys, vec!["c|c.example.com:443", "a|a.example.com:443"]);
+
+        let names: Vec<_> = manager
+            .get_servers_for_subscription(id)
+            .iter()
+            .map(|s| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["c", "a", "b"]);
+        // Servers of other subscriptions stay in place
+        assert_eq!(manager.servers[1].name, "x");
+
+        assert!(manager.reorder_servers(other, &[c]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_groups() {
+        let mut manager = SubscriptionManager::new();
+        let group = manager.add_local_group("Mine".to_string());
+        assert!(manager.get_subscriptions()[0].is_local());
+
+        let link = "trojan://secret@example.com:443#Home";
+        let config = crate::config::parser::ConfigParser::parse_url(link).unwrap();
+        let added = manager
+            .add_local_servers(group, vec![config.clone()])
+            .unwrap();
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].name, "Home");
+        assert_eq!(manager.get_subscriptions()[0].server_count, 1);
+
+        // Editing keeps the ID and moves user data to the new fingerprint
+        let id = added[0].id;
+        manager.update_user_data(&[id], |data| data.favorite = true);
+        let mut edited = config.clone();
+        edited.port = 8443;
+        let (server, changed) = manager.edit_local_server(id, edited).unwrap();
+        assert_eq!(server.id, id);
+        assert_eq!(server.port, 8443);
+        assert_eq!(changed.len(), 2);
+        assert!(manager.user_data(&server).favorite);
+
+        // Local groups are never fetched
+        assert!(manager.update_subscription(group).await.is_err());
+        assert!(manager.take_update_outcomes().is_empty());
+        assert!(manager.update_all_subscriptions().await.is_ok());
+        assert_eq!(manager.get_servers().len(), 1);
+
+        // Subscription servers cannot be edited or added to
+        let remote = Uuid::new_v4();
+        manager.subscriptions.push(Subscription {
+            id: remote,
+            name: "Remote".to_string(),
+            url: "https://example.com/sub".to_string(),
+            last_update: None,
+            server_count: 0,
+            status: SubscriptionStatus::Inactive,
+            request: Default::default(),
+        });
+        assert!(manager.add_local_servers(remote, vec![config]).is_err());
+    }
+
+    #[test]
+    fn test_import_text() {
+        let mut manager = SubscriptionManager::new();
+        let group = manager.default_local_group();
+        assert_eq!(manager.default_local_group(), group);
+        assert_eq!(
+            manager.get_subscriptions()[0].name,
+            DEFAULT_LOCAL_GROUP_NAME
+        );
+
+        let text = "trojan://secret@a.example.com:443#A\n\
+                    trojan://secret@b.example.com:443#B\n\
+                    trojan://secret@a.example.com:443#A%20again\n\
+                    trojan://broken";
+        let report = manager.import_text(group, text).unwrap();
+        assert_eq!(report.group_id, group);
+        assert_eq!(report.imported.len(), 2);
+        assert_eq!(report.duplicates, vec!["A again"]);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].line, 4);
+
+        // Importing again finds only duplicates
+        let report = manager.import_text(group, text).unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(report.duplicates.len(), 3);
+        assert_eq!(manager.get_servers().len(), 2);
+    }
+
+    #[test]
+    fn test_server_user_data() {
+        let mut manager = SubscriptionManager::new();
+        for name in ["a", "b", "c"] {
+            manager.servers.push(Server {
+                id: Uuid::new_v4(),
+                name: name.to_string(),
+                address: format!("{}.example.com", name),
+                port: 443,
+                protocol: "vmess".to_string(),
+                config: HashMap::new(),
+                stream_settings: None,
+                subscription_id: Uuid::nil(),
+                tags: vec![],
+                raw_name: None,
+            });
+        }
+        let [a, b, c] = [0, 1, 2].map(|i| manager.servers[i].id);
+
+        let changed = manager.update_user_data(&[a, b], |data| data.favorite = true);
+        assert_eq!(changed.len(), 2);
+        // Unchanged entries are not reported again
+        assert!(manager
+            .update_user_data(&[a], |data| data.favorite = true)
+            .is_empty());
+        manager.update_user_data(&[b], |data| data.hidden = true);
+
+        let changed = manager.pin_servers(&[c, a]);
+        assert_eq!(changed.len(), 2);
+        let names: Vec<_> = manager
+            .server_list(false)
+            .iter()
+            .map(|(s, _)| s.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["c", "a"]);
+        assert_eq!(manager.server_list(true).len(), 3);
+
+        // Re-pinning unpins the servers left out
+        let changed = manager.pin_servers(&[a]);
+        assert_eq!(changed.len(), 2);
+        let c_data = manager.user_data(&manager.servers[2]);
+        assert!(c_data.is_empty());
+        assert!(!manager
+            .user_data
+            .contains_key(&server_fingerprint(&manager.servers[2])));
+
+        // User data follows the server when it comes back renamed with a new ID
+        let mut renamed = manager.servers[0].clone();
+        renamed.id = Uuid::new_v4();
+        renamed.name = "renamed".to_string();
+        let data = manager.user_data(&renamed);
+        assert!(data.favorite);
+        assert_eq!(data.pinned_position, Some(0));
+    }
+
+    #[test]
+    fn test_merge_servers() {
+        let server = |name: &str, port: u16, password: &str| Server {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            address: "example.com".to_string(),
+            port,
+            protocol: "trojan".to_string(),
+            config: HashMap::from([("password".to_string(), serde_json::json!(password))]),
+            stream_settings: None,
+            subscription_id: Uuid::nil(),
+            tags: vec![],
+            raw_name: None,
+        };
+        let mut current = [
+            server("a", 443, "p"),
+            server("b", 443, "p"),
+            server("c", 443, "p"),
+            server("e", 443, "p"),
+        ];
+        current[0].tags = vec!["fast".to_string()];
+        let fetched = vec![
+            // Unchanged
+            server("a", 443, "p"),
+            // Moved to another port
+            server("c", 8443, "p"),
+            // New credentials
+            server("e", 443, "q"),
+            server("d", 443, "p"),
+        ];
+
+        let (merged, diff) = merge_servers(&current.iter().collect::<Vec<_>>(), fetched);
+        assert_eq!(merged[0].id, current[0].id);
+        assert_eq!(merged[0].tags, vec!["fast"]);
+        assert_eq!(merged[1].id, current[2].id);
+        assert_eq!(merged[2].id, current[3].id);
+        assert!(!current.iter().any(|s| s.id == merged[3].id));
+        assert_eq!(
+            diff,
+            ServerDiff {
+                added: vec!["d".to_string()],
+                removed: vec!["b".to_string()],
+                changed: vec!["e".to_string(), "c".to_string()],
+            }
+        );
+
+        let (_, diff) = merge_servers(&[], Vec::new());
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_batch_operations() {
+        let mut manager = SubscriptionManager::new();
+        let (source, target) = (Uuid::new_v4(), Uuid::new_v4());
+        for (id, url) in [(source, "https://example.com/sub"), (target, "")] {
+            manager.subscriptions.push(Subscription {
+                id,
+                name: id.to_string(),
+                url: url.to_string(),
+                last_update: None,
+                server_count: 0,
+                status: SubscriptionStatus::Active,
+                request: Default::default(),
+            });
+        }
+
+        for (name, protocol, config) in [
+            ("a", "trojan", serde_json::json!({"password": "secret"})),
+            ("b", "socks", serde_json::json!({})),
+            ("c", "trojan", serde_json::json!({"password": "secret"})),
+        ] {
+            manager.servers.push(Server {
+                id: Uuid::new_v4(),
+                name: name.to_string(),
+                address: format!("{}.example.com", name),
+                port: 443,
+                protocol: protocol.to_string(),
+                config: serde_json::from_value(config).unwrap(),
+                stream_settings: None,
+                subscription_id: source,
+                tags: vec![],
+                raw_name: None,
+            });
+        }
+        let ids: Vec<Uuid> = manager.servers.iter().map(|s| s.id).collect();
+
+        let changed = manager.add_server_tag(&ids[..2], "fast");
+        assert_eq!(changed.len(), 2);
+        assert!(manager.add_server_tag(&ids[..2], "fast").is_empty());
+        assert_eq!(manager.servers[0].tags, vec!["fast"]);
+
+        // Links are only produced for protocols with a share-link format
+        let links = manager.export_server_links(&ids);
+        assert_eq!(links.len(), 2);
+        assert!(links[0].starts_with("trojan://secret@a.example.com:443"));
+
+        assert_eq!(manager.move_servers(&ids[1..], target).unwrap(), 2);
+        assert!(manager.move_servers(&ids, Uuid::new_v4()).is_err());
+        // Servers cannot be moved into a subscription
+        assert!(manager.move_servers(&ids[1..], source).is_err());
+        assert_eq!(manager.get_servers_for_subscription(target).len(), 2);
+        assert_eq!(manager.subscriptions[1].server_count, 2);
+
+        assert_eq!(manager.delete_servers(&ids[..2]), 2);
+        assert_eq!(manager.get_servers().len(), 1);
+        assert_eq!(manager.subscriptions[0].server_count, 0);
+        assert_eq!(manager.subscriptions[1].server_count, 1);
+    }
+
+    #[test]
+    fn test_set_user_agent() {
+        let mut manager = SubscriptionManager::new();
+        assert_eq!(
+            manager.http_client.config().user_agent,
+            crate::version::user_agent()
+        );
+
+        manager
+            .set_user_agent("ClashMeta/1.18".to_string())
+            .unwrap();
+        assert_eq!(manager.http_client.config().user_agent, "ClashMeta/1.18");
+        assert_eq!(manager.http_client.config().max_retries, 3);
+    }
 }
@@ -25,6 +25,29 @@ pub enum SubscriptionFormat {
     Auto,
 }
 
+/// Longest input kept in a [`LinkError`]
+const MAX_ERROR_INPUT_LENGTH: usize = 80;
+
+/// A piece of pasted text that could not be parsed as a server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkError {
+    /// 1-based line number in the (decoded) text
+    pub line: usize,
+    /// The offending input, shortened
+    pub input: String,
+    /// Why it could not be parsed
+    pub reason: String,
+}
+
+/// Servers found in pasted text, see [`SubscriptionParser::parse_text`]
+#[derive(Debug, Clone, Default)]
+pub struct TextImport {
+    /// Parsed servers, in the order they appear
+    pub servers: Vec<ProxyServerConfig>,
+    /// Inputs that could not be parsed
+    pub errors: Vec<LinkError>,
+}
+
 /// Subscription parser
 pub struct SubscriptionParser;
 
@@ -44,12 +67,19 @@ impl SubscriptionParser {
             return Err(SubscriptionError::Empty);
         }
 
-        match format {
+        let mut configs = match format {
             SubscriptionFormat::Base64 => Self::parse_base64(content),
             SubscriptionFormat::V2RayJson => Self::parse_v2ray_json(content),
             SubscriptionFormat::ClashYaml => Self::parse_clash_yaml(content),
             SubscriptionFormat::Auto => Self::auto_detect_and_parse(content),
+        }?;
+
+        // Provider names may carry bidi marks, control characters or
+        // hundreds of characters; normalize them once here
+        for config in &mut configs {
+            config.normalize_name();
         }
+        Ok(configs)
     }
 
     /// Auto-detect subscription format and parse
@@ -132,6 +162,69 @@ impl SubscriptionParser {
         Ok(servers)
     }
 
+    /// Parse arbitrary pasted text leniently
+    ///
+    /// Accepts a single share link, several links mixed with other text, a
+    /// base64 blob, or a full V2Ray JSON / Clash YAML subscription. Unlike
+    /// [`SubscriptionParser::parse`], every link that fails is reported
+    /// instead of failing the whole input.
+    pub fn parse_text(content: &str) -> TextImport {
+        let content = content.trim();
+        if content.is_empty() {
+            return TextImport::default();
+        }
+
+        let structured =
+            content.starts_with('{') || content.starts_with('[') || content.contains("proxies:");
+        if structured {
+            return match Self::parse(content) {
+                Ok(servers) => TextImport {
+                    servers,
+                    errors: Vec::new(),
+                },
+                Err(e) => TextImport {
+                    servers: Vec::new(),
+                    errors: vec![LinkError {
+                        line: 1,
+                        input: truncate_input(content),
+                        reason: e.to_string(),
+                    }],
+                },
+            };
+        }
+
+        // A base64 blob has no whitespace inside, but may be wrapped
+        let compact: String = content.split_whitespace().collect();
+        let text = match BASE64.decode(&compact).map(String::from_utf8) {
+            Ok(Ok(decoded)) if decoded.contains("://") => decoded,
+            _ => content.to_string(),
+        };
+
+        let mut import = TextImport::default();
+        for (index, line) in text.lines().enumerate() {
+            // Links may be surrounded by other words in chat messages
+            for token in line.split_whitespace().filter(|t| t.contains("://")) {
+                match ConfigParser::parse_url(token) {
+                    Ok(server) => import.servers.push(server),
+                    Err(e) => import.errors.push(LinkError {
+                        line: index + 1,
+                        input: truncate_input(token),
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+        }
+
+        if import.servers.is_empty() && import.errors.is_empty() {
+            import.errors.push(LinkError {
+                line: 1,
+                input: truncate_input(content),
+                reason: "No share links found".to_string(),
+            });
+        }
+        import
+    }
+
     /// Parse V2Ray JSON format subscription
     ///
     /// Format: JSON object or array containing server configurations
@@ -228,6 +321,7 @@ impl SubscriptionParser {
             tags: vec![],
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            raw_name: None,
         })
     }
 
@@ -357,6 +451,7 @@ impl SubscriptionParser {
             tags: vec![],
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            raw_name: None,
         })
     }
 
@@ -382,6 +477,8 @@ impl SubscriptionParser {
             http_settings: None,
             quic_settings: None,
             grpc_settings: None,
+            mux: None,
+            fragment: None,
         };
 
         // Parse TLS settings
@@ -389,6 +486,7 @@ impl SubscriptionParser {
             // 如果没有指定 SNI,使用服务器地址作为 SNI
             let sni = yaml["sni"]
                 .as_str()
+                .or_else(|| yaml["servername"].as_str())
                 .or_else(|| yaml["server"].as_str())
                 .map(|s| s.to_string());
 
@@ -397,8 +495,18 @@ impl SubscriptionParser {
             stream.tls_settings = Some(TlsSettings {
                 server_name: sni,
                 allow_insecure: skip_cert_verify,
-                alpn: vec![],
-                fingerprint: yaml["fingerprint"].as_str().map(|s| s.to_string()),
+                alpn: yaml["alpn"]
+                    .as_sequence()
+                    .map(|alpn| {
+                        alpn.iter()
+                            .filter_map(|a| a.as_str().map(|s| s.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+                fingerprint: yaml["client-fingerprint"]
+                    .as_str()
+                    .or_else(|| yaml["fingerprint"].as_str())
+                    .map(|s| s.to_string()),
             });
         }
 
@@ -434,14 +542,63 @@ impl SubscriptionParser {
             }
         }
 
+        // Parse mux settings (mihomo smux)
+        if yaml["smux"]["enabled"].as_bool().unwrap_or(false) {
+            let defaults = crate::config::MuxSettings::default();
+            stream.mux = Some(crate::config::MuxSettings {
+                enabled: true,
+                concurrency: yaml["smux"]["max-streams"]
+                    .as_i64()
+                    .map(|v| v as i32)
+                    .unwrap_or(defaults.concurrency),
+                xudp_concurrency: defaults.xudp_concurrency,
+            });
+        }
+
         Ok(Some(stream))
     }
 }
 
+/// Shorten an input for error reports
+fn truncate_input(input: &str) -> String {
+    crate::utils::names::truncate_name(input.trim(), MAX_ERROR_INPUT_LENGTH)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_text() {
+        let link = "vless://uuid-test@example.com:443?type=tcp&security=none#Test1";
+
+        // Single link
+        let import = SubscriptionParser::parse_text(link);
+        assert_eq!(import.servers.len(), 1);
+        assert!(import.errors.is_empty());
+
+        // Links within a message, with one broken link
+        let text = format!("Try these:\n{} (fast)\n\nand vmess://not-base64!", link);
+        let import = SubscriptionParser::parse_text(&text);
+        assert_eq!(import.servers.len(), 1);
+        assert_eq!(import.errors.len(), 1);
+        assert_eq!(import.errors[0].line, 4);
+        assert_eq!(import.errors[0].input, "vmess://not-base64!");
+
+        // Wrapped base64 blob
+        let encoded = BASE64.encode(format!("{}\n{}", link, link.replace("Test1", "Test2")));
+        let wrapped = format!("{}\n{}", &encoded[..40], &encoded[40..]);
+        let import = SubscriptionParser::parse_text(&wrapped);
+        assert_eq!(import.servers.len(), 2);
+        assert_eq!(import.servers[1].name, "Test2");
+
+        // Nothing usable
+        let import = SubscriptionParser::parse_text("hello world");
+        assert!(import.servers.is_empty());
+        assert_eq!(import.errors[0].reason, "No share links found");
+        assert!(SubscriptionParser::parse_text("  ").errors.is_empty());
+    }
+
     #[test]
     fn test_parse_base64_subscription() {
         // Create a simple Base64 encoded subscription
@@ -526,6 +683,75 @@ proxies:
         assert_eq!(servers[1].name, "Test Server 2");
     }
 
+    #[test]
+    fn test_normalize_provider_names() {
+        let long_name = format!("🇺🇸 美国 {}", "高速专线 ".repeat(60));
+        let yaml = format!(
+            r#"
+proxies:
+  - name: "\u200F\u202Bسرور 01\u202C\t|  VIP"
+    type: vmess
+    server: example.com
+    port: 443
+  - name: "Cafe\u0301 🇫🇷 👨\u200D💻"
+    type: vmess
+    server: example.fr
+    port: 443
+  - name: "\u200B\u200E"
+    type: vmess
+    server: blank.example.com
+    port: 8443
+  - name: "{}"
+    type: vmess
+    server: example.us
+    port: 443
+"#,
+            long_name
+        );
+
+        let servers = SubscriptionParser::parse(&yaml).unwrap();
+        assert_eq!(servers[0].name, "سرور 01 | VIP");
+        assert_eq!(
+            servers[0].raw_name.as_deref(),
+            Some("\u{200F}\u{202B}سرور 01\u{202C}\t|  VIP")
+        );
+        assert_eq!(servers[1].name, "Caf\u{00E9} 🇫🇷 👨\u{200D}💻");
+        assert_eq!(servers[2].name, "blank.example.com:8443");
+        assert!(servers[3].name.ends_with('…'));
+        assert!(servers[3].name.chars().count() < long_name.chars().count());
+        assert_eq!(servers[3].raw_name.as_deref(), Some(long_name.as_str()));
+
+        // Clean names are left alone
+        let url = "trojan://password@example.com:443#%F0%9F%87%AF%F0%9F%87%B5%20Tokyo";
+        let config = crate::config::parser::ConfigParser::parse_url(url).unwrap();
+        assert_eq!(config.name, "🇯🇵 Tokyo");
+        assert!(config.raw_name.is_none());
+    }
+
+    #[test]
+    fn test_parse_clash_smux() {
+        let yaml = r#"
+proxies:
+  - name: Mux Server
+    type: vless
+    server: example.com
+    port: 443
+    smux:
+      enabled: true
+      max-streams: 4
+"#;
+
+        let servers = SubscriptionParser::parse_clash_yaml(yaml).unwrap();
+        let mux = servers[0]
+            .stream_settings
+            .as_ref()
+            .unwrap()
+            .mux
+            .clone()
+            .unwrap();
+        assert_eq!(mux.concurrency, 4);
+    }
+
     #[test]
     fn test_auto_detect_json() {
         let json = r#"{
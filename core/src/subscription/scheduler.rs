@@ -1,21 +1,44 @@
 //! Subscription Auto-Update Scheduler
 //!
 //! This module provides automatic subscription update scheduling functionality.
+//!
+//! Every subscription follows the scheduler's default interval unless it has
+//! its own [`UpdateSchedule`]: a different interval, a cron-like expression
+//! evaluated in local time, or manual updates only. Due times are computed
+//! from the subscription's persisted last update time, so they survive
+//! restarts. Running the updates is left to the caller, which also decides
+//! whether the network is suitable (see [`NetworkConditions`]).
 
+use super::Subscription;
+use crate::error::ConfigError;
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDate, NaiveDateTime, TimeZone,
+    Timelike, Utc,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
+/// Days searched for the next cron match before giving up (e.g. `0 0 31 2 *`)
+const CRON_SEARCH_DAYS: u32 = 366 * 5;
+
 /// Auto-update scheduler configuration
 #[derive(Debug, Clone)]
 pub struct SchedulerConfig {
-    /// Update interval in hours
+    /// Update interval in hours (0 = no automatic updates by default)
     pub update_interval_hours: u64,
     /// Whether to update on startup
     pub update_on_startup: bool,
     /// Maximum concurrent updates
     pub max_concurrent_updates: usize,
+    /// Whether to postpone updates while on a metered network
+    pub skip_when_metered: bool,
+    /// Whether to postpone updates while offline
+    pub skip_when_offline: bool,
 }
 
 impl Default for SchedulerConfig {
@@ -24,8 +47,232 @@ impl Default for SchedulerConfig {
             update_interval_hours: 24,
             update_on_startup: true,
             max_concurrent_updates: 3,
+            skip_when_metered: true,
+            skip_when_offline: true,
+        }
+    }
+}
+
+impl From<&crate::config::SubscriptionConfig> for SchedulerConfig {
+    fn from(config: &crate::config::SubscriptionConfig) -> Self {
+        Self {
+            update_interval_hours: config.auto_update_interval as u64,
+            ..Default::default()
+        }
+    }
+}
+
+/// When a subscription is updated automatically
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UpdateSchedule {
+    /// The scheduler's default interval
+    #[default]
+    Default,
+    /// A fixed interval
+    Interval {
+        /// Hours between updates
+        hours: u32,
+    },
+    /// Cron-like expression, see [`CronSchedule`]
+    Cron {
+        /// Expression such as `0 6 * * *`
+        expression: String,
+    },
+    /// Manual updates only
+    Manual,
+}
+
+impl UpdateSchedule {
+    /// Check that the schedule can be evaluated
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        match self {
+            UpdateSchedule::Interval { hours: 0 } => Err(ConfigError::Validation(
+                "Update interval must be at least one hour".to_string(),
+            )),
+            UpdateSchedule::Cron { expression } => expression.parse::<CronSchedule>().map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Next update time, given the default interval and the last update
+    ///
+    /// A subscription that was never updated is due immediately; `None`
+    /// means it is not updated automatically.
+    pub fn next_update(
+        &self,
+        default_hours: u64,
+        last_update: Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        let hours = match self {
+            UpdateSchedule::Manual => return None,
+            UpdateSchedule::Default if default_hours == 0 => return None,
+            UpdateSchedule::Default => default_hours,
+            UpdateSchedule::Interval { hours } => *hours as u64,
+            UpdateSchedule::Cron { expression } => {
+                let cron = expression.parse::<CronSchedule>().ok()?;
+                let Some(last_update) = last_update else {
+                    return Some(DateTime::<Utc>::MIN_UTC);
+                };
+                let next = cron.next_after(last_update.with_timezone(&Local).naive_local())?;
+                // Local times skipped by a DST change fall back to UTC
+                return Some(
+                    Local
+                        .from_local_datetime(&next)
+                        .earliest()
+                        .map(|time| time.with_timezone(&Utc))
+                        .unwrap_or_else(|| next.and_utc()),
+                );
+            }
+        };
+        Some(match last_update {
+            Some(last_update) => last_update + ChronoDuration::hours(hours as i64),
+            None => DateTime::<Utc>::MIN_UTC,
+        })
+    }
+}
+
+/// Network state relevant to automatic updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkConditions {
+    /// Whether a network is available
+    pub online: bool,
+    /// Whether the network is metered (e.g. a mobile hotspot)
+    pub metered: bool,
+}
+
+/// Cron-like schedule: `minute hour day-of-month month day-of-week`
+///
+/// Each field is `*`, a number, a range `a-b`, a list `a,b` or any of
+/// these with a step `/n`. Day of week runs from 0 (Sunday) to 6; 7 is
+/// also Sunday. As in cron, when both day fields are restricted a day
+/// matching either one matches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    /// Allowed minutes, bit per minute
+    minutes: u64,
+    /// Allowed hours, bit per hour
+    hours: u64,
+    /// Allowed days of month, bit per day (1-31)
+    days: u64,
+    /// Allowed months, bit per month (1-12)
+    months: u64,
+    /// Allowed days of week, bit per day (0 = Sunday)
+    weekdays: u64,
+    /// Whether the day-of-month field was restricted
+    days_restricted: bool,
+    /// Whether the day-of-week field was restricted
+    weekdays_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = ConfigError;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(ConfigError::Validation(format!(
+                "Cron expression needs 5 fields: {}",
+                expression
+            )));
+        };
+
+        let mut weekdays = parse_cron_field(weekday, 0, 7)?;
+        // 7 is Sunday as well
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            minutes: parse_cron_field(minute, 0, 59)?,
+            hours: parse_cron_field(hour, 0, 23)?,
+            days: parse_cron_field(day, 1, 31)?,
+            months: parse_cron_field(month, 1, 12)?,
+            weekdays,
+            days_restricted: day != "*",
+            weekdays_restricted: weekday != "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    /// First matching minute strictly after `after`
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        let mut date = start.date();
+        let mut from_minute = start.hour() * 60 + start.minute();
+
+        for _ in 0..CRON_SEARCH_DAYS {
+            if self.matches_day(date) {
+                let found = (from_minute..24 * 60).find(|minute| {
+                    self.hours & (1 << (minute / 60)) != 0
+                        && self.minutes & (1 << (minute % 60)) != 0
+                });
+                if let Some(minute) = found {
+                    return date.and_hms_opt(minute / 60, minute % 60, 0);
+                }
+            }
+            date = date.succ_opt()?;
+            from_minute = 0;
+        }
+        None
+    }
+
+    /// Whether the month and day fields allow `date`
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+}
+
+/// Parse one cron field into a bit set of the allowed values
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, ConfigError> {
+    let invalid = || ConfigError::Validation(format!("Invalid cron field: {}", field));
+    let parse = |value: &str| -> Result<u32, ConfigError> {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|v| (min..=max).contains(v))
+            .ok_or_else(invalid)
+    };
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, Some(step.parse::<u32>().map_err(|_| invalid())?)),
+            None => (part, None),
+        };
+        let stepped = step.is_some();
+        let step = step.unwrap_or(1);
+        if step == 0 {
+            return Err(invalid());
+        }
+        let (start, end) = match (range, range.split_once('-')) {
+            ("*", _) => (min, max),
+            (_, Some((start, end))) => (parse(start)?, parse(end)?),
+            // "5/15" runs from 5 to the end of the range
+            (_, None) if stepped => (parse(range)?, max),
+            (_, None) => {
+                let value = parse(range)?;
+                (value, value)
+            }
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
         }
     }
+    Ok(bits)
 }
 
 /// Subscription auto-update scheduler
@@ -37,6 +284,8 @@ pub struct SubscriptionScheduler {
     config: SchedulerConfig,
     /// Last update time for each subscription
     last_updates: Arc<RwLock<std::collections::HashMap<Uuid, std::time::Instant>>>,
+    /// Schedules differing from the default interval
+    schedules: Arc<RwLock<HashMap<Uuid, UpdateSchedule>>>,
 }
 
 impl SubscriptionScheduler {
@@ -45,6 +294,7 @@ impl SubscriptionScheduler {
         Self {
             config,
             last_updates: Arc::new(RwLock::new(std::collections::HashMap::new())),
+            schedules: Arc::new(RwLock::new(HashMap::new())),
         }
     }
 
@@ -90,6 +340,91 @@ impl SubscriptionScheduler {
     pub fn config(&self) -> &SchedulerConfig {
         &self.config
     }
+
+    /// Replace the configuration, keeping per-subscription schedules
+    pub fn set_config(&mut self, config: SchedulerConfig) {
+        self.config = config;
+    }
+
+    /// Set the schedule of a subscription
+    pub async fn set_schedule(
+        &self,
+        subscription_id: Uuid,
+        schedule: UpdateSchedule,
+    ) -> Result<(), ConfigError> {
+        schedule.validate()?;
+        let mut schedules = self.schedules.write().await;
+        if schedule == UpdateSchedule::Default {
+            schedules.remove(&subscription_id);
+        } else {
+            schedules.insert(subscription_id, schedule);
+        }
+        Ok(())
+    }
+
+    /// Schedule of a subscription
+    pub async fn schedule(&self, subscription_id: Uuid) -> UpdateSchedule {
+        self.schedules
+            .read()
+            .await
+            .get(&subscription_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Forget a removed subscription
+    pub async fn remove(&self, subscription_id: Uuid) {
+        self.schedules.write().await.remove(&subscription_id);
+        self.last_updates.write().await.remove(&subscription_id);
+    }
+
+    /// Time a subscription is next due, see [`UpdateSchedule::next_update`]
+    pub async fn next_update(
+        &self,
+        subscription_id: Uuid,
+        last_update: Option<DateTime<Utc>>,
+    ) -> Option<DateTime<Utc>> {
+        self.schedule(subscription_id)
+            .await
+            .next_update(self.config.update_interval_hours, last_update)
+    }
+
+    /// Subscriptions due for an update at `now`
+    ///
+    /// With `startup` set and `update_on_startup` enabled, every
+    /// subscription that is updated automatically is due. Local groups are
+    /// never due.
+    pub async fn due_subscriptions(
+        &self,
+        subscriptions: &[Subscription],
+        now: DateTime<Utc>,
+        startup: bool,
+    ) -> Vec<Uuid> {
+        let mut due = Vec::new();
+        for subscription in subscriptions.iter().filter(|s| !s.is_local()) {
+            let Some(next) = self
+                .next_update(subscription.id, subscription.last_update)
+                .await
+            else {
+                continue;
+            };
+            if next <= now || (startup && self.config.update_on_startup) {
+                due.push(subscription.id);
+            }
+        }
+        due
+    }
+
+    /// Why updates should be postponed under `conditions`, if they should
+    pub fn skip_reason(&self, conditions: NetworkConditions) -> Option<&'static str> {
+        if self.config.skip_when_offline && !conditions.online {
+            Some("offline")
+        } else if self.config.skip_when_metered && conditions.metered {
+            Some("metered network")
+        } else {
+            None
+        }
+    }
 }
 
 #[cfg(test)]
@@ -118,6 +453,7 @@ mod tests {
             update_interval_hours: 1,
             update_on_startup: true,
             max_concurrent_updates: 3,
+            ..Default::default()
         };
         let scheduler = SubscriptionScheduler::new(config);
 
@@ -139,6 +475,7 @@ mod tests {
             update_interval_hours: 1,
             update_on_startup: true,
             max_concurrent_updates: 3,
+            ..Default::default()
         };
         let scheduler = SubscriptionScheduler::new(config);
 
@@ -162,4 +499,188 @@ mod tests {
         assert!(config.update_on_startup);
         assert_eq!(config.max_concurrent_updates, 3);
     }
+
+    fn at(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        let daily: CronSchedule = "0 6 * * *".parse().unwrap();
+        assert_eq!(
+            daily.next_after(at("2024-01-01 05:59:30")),
+            Some(at("2024-01-01 06:00:00"))
+        );
+        assert_eq!(
+            daily.next_after(at("2024-01-01 06:00:00")),
+            Some(at("2024-01-02 06:00:00"))
+        );
+
+        let quarter: CronSchedule = "*/15 9-17 * * 1-5".parse().unwrap();
+        // Friday evening to Monday morning
+        assert_eq!(
+            quarter.next_after(at("2024-01-05 17:50:00")),
+            Some(at("2024-01-08 09:00:00"))
+        );
+        assert_eq!(
+            quarter.next_after(at("2024-01-08 09:01:00")),
+            Some(at("2024-01-08 09:15:00"))
+        );
+
+        // Either day field matches when both are restricted
+        let either: CronSchedule = "30 2 1 * 0".parse().unwrap();
+        assert_eq!(
+            either.next_after(at("2024-01-02 00:00:00")),
+            Some(at("2024-01-07 02:30:00"))
+        );
+        let sunday: CronSchedule = "0 0 * * 7".parse().unwrap();
+        assert_eq!(
+            sunday.next_after(at("2024-01-01 00:00:00")),
+            Some(at("2024-01-07 00:00:00"))
+        );
+
+        assert_eq!(
+            "0 0 31 2 *"
+                .parse::<CronSchedule>()
+                .unwrap()
+                .next_after(at("2024-01-01 00:00:00")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_cron_parse_errors() {
+        for expression in [
+            "0 6 * *",
+            "60 * * * *",
+            "* * * 0 *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(
+                expression.parse::<CronSchedule>().is_err(),
+                "{}",
+                expression
+            );
+        }
+        assert!(UpdateSchedule::Interval { hours: 0 }.validate().is_err());
+        assert!(UpdateSchedule::Cron {
+            expression: "0 */6 * * *".to_string()
+        }
+        .validate()
+        .is_ok());
+    }
+
+    #[test]
+    fn test_update_schedule_next_update() {
+        let last = Utc::now();
+        assert_eq!(
+            UpdateSchedule::Default.next_update(24, Some(last)),
+            Some(last + ChronoDuration::hours(24))
+        );
+        assert_eq!(UpdateSchedule::Default.next_update(0, Some(last)), None);
+        assert_eq!(
+            UpdateSchedule::Interval { hours: 2 }.next_update(24, Some(last)),
+            Some(last + ChronoDuration::hours(2))
+        );
+        assert_eq!(UpdateSchedule::Manual.next_update(24, None), None);
+        assert_eq!(
+            UpdateSchedule::Interval { hours: 2 }.next_update(24, None),
+            Some(DateTime::<Utc>::MIN_UTC)
+        );
+
+        let hourly = UpdateSchedule::Cron {
+            expression: "0 * * * *".to_string(),
+        };
+        let next = hourly.next_update(24, Some(last)).unwrap();
+        assert!(next > last && next <= last + ChronoDuration::hours(1));
+
+        let json = serde_json::to_string(&hourly).unwrap();
+        assert_eq!(json, r#"{"type":"cron","expression":"0 * * * *"}"#);
+        assert_eq!(
+            serde_json::from_str::<UpdateSchedule>(&json).unwrap(),
+            hourly
+        );
+    }
+
+    #[tokio::test]
+    async fn test_due_subscriptions() {
+        let scheduler = SubscriptionScheduler::new(SchedulerConfig {
+            update_on_startup: false,
+            ..Default::default()
+        });
+        let now = Utc::now();
+        let subscription = |last_update: Option<DateTime<Utc>>| Subscription {
+            id: Uuid::new_v4(),
+            name: "test".to_string(),
+            url: "https://example.com/sub".to_string(),
+            last_update,
+            server_count: 0,
+            status: super::super::SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+        let never = subscription(None);
+        let stale = subscription(Some(now - ChronoDuration::hours(25)));
+        let fresh = subscription(Some(now - ChronoDuration::hours(1)));
+        let manual = subscription(None);
+        let hourly = subscription(Some(now - ChronoDuration::hours(2)));
+        scheduler
+            .set_schedule(manual.id, UpdateSchedule::Manual)
+            .await
+            .unwrap();
+        scheduler
+            .set_schedule(hourly.id, UpdateSchedule::Interval { hours: 1 })
+            .await
+            .unwrap();
+        assert!(scheduler
+            .set_schedule(fresh.id, UpdateSchedule::Interval { hours: 0 })
+            .await
+            .is_err());
+
+        let subscriptions = vec![
+            never.clone(),
+            stale.clone(),
+            fresh.clone(),
+            manual.clone(),
+            hourly.clone(),
+        ];
+        assert_eq!(
+            scheduler
+                .due_subscriptions(&subscriptions, now, false)
+                .await,
+            vec![never.id, stale.id, hourly.id]
+        );
+
+        // On startup every automatically updated subscription is due
+        let mut scheduler = scheduler;
+        scheduler.set_config(SchedulerConfig::default());
+        assert_eq!(
+            scheduler.due_subscriptions(&subscriptions, now, true).await,
+            vec![never.id, stale.id, fresh.id, hourly.id]
+        );
+
+        scheduler.remove(manual.id).await;
+        assert_eq!(scheduler.schedule(manual.id).await, UpdateSchedule::Default);
+    }
+
+    #[test]
+    fn test_skip_reason() {
+        let scheduler = SubscriptionScheduler::new(SchedulerConfig::default());
+        let conditions = |online, metered| NetworkConditions { online, metered };
+        assert_eq!(scheduler.skip_reason(conditions(true, false)), None);
+        assert_eq!(
+            scheduler.skip_reason(conditions(false, false)),
+            Some("offline")
+        );
+        assert_eq!(
+            scheduler.skip_reason(conditions(true, true)),
+            Some("metered network")
+        );
+
+        let scheduler = SubscriptionScheduler::new(SchedulerConfig {
+            skip_when_metered: false,
+            ..Default::default()
+        });
+        assert_eq!(scheduler.skip_reason(conditions(true, true)), None);
+    }
 }
@@ -1,22 +1,63 @@
 //! Subscription Storage
 //!
 //! This module provides persistent storage for subscriptions and servers using SQLite.
-
-use super::{Server, Subscription, SubscriptionStatus};
+//!
+//! Server credentials (the `config` and `stream_settings` columns) and
+//! subscription URLs, which often embed an access token, can optionally be
+//! encrypted at rest with AES-256-GCM. Encrypted values carry a prefix, so
+//! plaintext rows from an older database are detected and migrated on open.
+
+use super::{
+    Server, ServerDiff, ServerUserData, Subscription, SubscriptionHealth, SubscriptionRequest,
+    SubscriptionStatus, UpdateOutcome, UpdateSchedule,
+};
 use crate::error::{StorageError, StorageResult};
+use crate::utils::crypto::{decrypt_aes256, derive_key_from_password, encrypt_aes256};
+use crate::utils::preflight::check_writable;
 use sqlx::{
     sqlite::{SqliteConnectOptions, SqlitePool},
     Row,
 };
+use std::collections::HashMap;
 use std::path::Path;
 use std::str::FromStr;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+/// Prefix marking an encrypted column value
+const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Recompute `subscriptions.server_count` after servers were moved or deleted
+const REFRESH_SERVER_COUNTS: &str = "UPDATE subscriptions SET server_count = \
+     (SELECT COUNT(*) FROM servers WHERE servers.subscription_id = subscriptions.id)";
+
+/// Number of update outcomes kept per subscription
+pub const UPDATE_HISTORY_LIMIT: usize = 20;
+
+/// Database schema version written by this release, kept in `PRAGMA user_version`
+///
+/// Version 0 is a database from before the version was recorded.
+pub const DATABASE_SCHEMA_VERSION: u32 = 4;
+
+/// Storage usage statistics
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Number of stored subscriptions
+    pub subscription_count: u64,
+    /// Number of stored servers
+    pub server_count: u64,
+    /// Number of servers marked as favorite
+    pub favorite_count: u64,
+    /// Database size in bytes
+    pub database_size: u64,
+}
+
 /// Subscription storage manager
 pub struct SubscriptionStorage {
     /// SQLite connection pool
     pool: SqlitePool,
+    /// Key for server credential encryption (None stores plaintext)
+    key: Option<[u8; 32]>,
 }
 
 impl SubscriptionStorage {
@@ -25,32 +66,313 @@ impl SubscriptionStorage {
         let path = db_path.as_ref();
         info!("Opening subscription database: {}", path.display());
 
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            check_writable(parent)?;
+        }
+        Self::apply_pending_restore(path)?;
+
         // Use SqliteConnectOptions for better control
         let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))?
             .create_if_missing(true);
 
         let pool = SqlitePool::connect_with(options).await?;
 
-        let storage = Self { pool };
-        storage.init_tables().await?;
+        let storage = Self { pool, key: None };
+        storage.migrate(Some(path)).await?;
 
         Ok(storage)
     }
 
+    /// Replace the database at `path` with a snapshot restored from a
+    /// backup, see [`crate::config::backup::pending_restore_path`]
+    fn apply_pending_restore(path: &Path) -> StorageResult<()> {
+        let pending = crate::config::backup::pending_restore_path(path);
+        if !pending.exists() {
+            return Ok(());
+        }
+        for suffix in ["-wal", "-shm"] {
+            let mut journal = path.as_os_str().to_owned();
+            journal.push(suffix);
+            let _ = std::fs::remove_file(journal);
+        }
+        std::fs::rename(&pending, path)?;
+        info!("Subscription database restored from backup");
+        Ok(())
+    }
+
+    /// Copy the database to `dest`, which must not exist yet
+    pub async fn snapshot(&self, dest: &Path) -> StorageResult<()> {
+        sqlx::query("VACUUM INTO ?")
+            .bind(dest.to_string_lossy().into_owned())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Copy the database at `db_path` to `dest`, which must not exist yet,
+    /// without opening it for writing
+    pub async fn snapshot_file(db_path: &Path, dest: &Path) -> StorageResult<()> {
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", db_path.display()))?
+            .read_only(true);
+        let pool = SqlitePool::connect_with(options).await?;
+        let result = sqlx::query("VACUUM INTO ?")
+            .bind(dest.to_string_lossy().into_owned())
+            .execute(&pool)
+            .await;
+        pool.close().await;
+        result?;
+        Ok(())
+    }
+
+    /// Create a storage manager that encrypts server credentials
+    ///
+    /// Existing plaintext rows are encrypted in place on open.
+    pub async fn new_with_encryption<P: AsRef<Path>>(
+        db_path: P,
+        password: String,
+    ) -> StorageResult<Self> {
+        let mut storage = Self::new(db_path).await?;
+        storage.enable_encryption(&password).await?;
+        Ok(storage)
+    }
+
+    /// Enable credential encryption and migrate plaintext rows
+    ///
+    /// Returns the number of migrated servers.
+    pub async fn enable_encryption(&mut self, password: &str) -> StorageResult<usize> {
+        self.enable_encryption_with_key(derive_key_from_password(password))
+            .await
+    }
+
+    /// Enable credential encryption with a key, such as one kept in the OS
+    /// credential store, and migrate plaintext rows
+    ///
+    /// Returns the number of migrated servers.
+    pub async fn enable_encryption_with_key(&mut self, key: [u8; 32]) -> StorageResult<usize> {
+        self.verify_key(&key).await?;
+        let migrated = self.reencrypt(Some(&key), &key).await?;
+        self.key = Some(key);
+
+        if migrated > 0 {
+            info!("Encrypted {} existing servers", migrated);
+        }
+        Ok(migrated)
+    }
+
+    /// Re-encrypt all credentials with a new key
+    ///
+    /// Plaintext rows are encrypted as well. Returns the number of
+    /// re-encrypted servers.
+    pub async fn rotate_key(&mut self, new_key: [u8; 32]) -> StorageResult<usize> {
+        let rotated = self.reencrypt(self.key.as_ref(), &new_key).await?;
+        self.key = Some(new_key);
+
+        info!("Re-encrypted {} servers with a new key", rotated);
+        Ok(rotated)
+    }
+
+    /// Check that `key` decrypts the stored credentials
+    ///
+    /// Passes if nothing is encrypted yet.
+    async fn verify_key(&self, key: &[u8; 32]) -> StorageResult<()> {
+        let pattern = format!("{}%", ENCRYPTED_PREFIX);
+        let encrypted: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT config FROM servers WHERE config LIKE ?1
+            UNION ALL
+            SELECT url FROM subscriptions WHERE url LIKE ?1
+            LIMIT 1
+            "#,
+        )
+        .bind(pattern)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(value) = encrypted {
+            decode_value(Some(key), &value)
+                .map_err(|_| StorageError::Encryption("Wrong encryption password".to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Decrypt credentials with `old_key` and write them back encrypted with
+    /// `new_key`, in one transaction
+    ///
+    /// Values already encrypted with `new_key` are only checked, so a wrong
+    /// key fails here rather than on first load. Returns the number of
+    /// rewritten servers.
+    async fn reencrypt(
+        &self,
+        old_key: Option<&[u8; 32]>,
+        new_key: &[u8; 32],
+    ) -> StorageResult<usize> {
+        let unchanged = old_key == Some(new_key);
+        let recode = |value: &str| encode_value(Some(new_key), &decode_value(old_key, value)?);
+
+        let mut tx = self.pool.begin().await?;
+        let mut rewritten = 0;
+
+        let rows = sqlx::query("SELECT id, config, stream_settings FROM servers")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in rows {
+            let config: String = row.get("config");
+            if unchanged && config.starts_with(ENCRYPTED_PREFIX) {
+                decode_value(old_key, &config)?;
+                continue;
+            }
+
+            let stream_settings: Option<String> = row.try_get("stream_settings").ok().flatten();
+            let stream_settings = stream_settings.map(|value| recode(&value)).transpose()?;
+
+            sqlx::query("UPDATE servers SET config = ?, stream_settings = ? WHERE id = ?")
+                .bind(recode(&config)?)
+                .bind(stream_settings)
+                .bind(row.get::<String, _>("id"))
+                .execute(&mut *tx)
+                .await?;
+            rewritten += 1;
+        }
+
+        let rows = sqlx::query("SELECT id, url, request FROM subscriptions")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in rows {
+            let url: String = row.get("url");
+            if unchanged && url.starts_with(ENCRYPTED_PREFIX) {
+                continue;
+            }
+            let request: Option<String> = row.get("request");
+            let request = request.map(|value| recode(&value)).transpose()?;
+            sqlx::query("UPDATE subscriptions SET url = ?, request = ? WHERE id = ?")
+                .bind(recode(&url)?)
+                .bind(request)
+                .bind(row.get::<String, _>("id"))
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(rewritten)
+    }
+
+    /// Whether server credentials are encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Encrypt a column value if encryption is enabled
+    fn encode_column(&self, value: &str) -> StorageResult<String> {
+        encode_value(self.key.as_ref(), value)
+    }
+
+    /// Decrypt a column value if it is encrypted
+    fn decode_column(&self, value: &str) -> StorageResult<String> {
+        decode_value(self.key.as_ref(), value)
+    }
+
     /// Create an in-memory storage (for testing)
     pub async fn new_in_memory() -> StorageResult<Self> {
         info!("Creating in-memory subscription database");
 
         let pool = SqlitePool::connect("sqlite::memory:").await?;
 
-        let storage = Self { pool };
-        storage.init_tables().await?;
+        let storage = Self { pool, key: None };
+        storage.migrate(None).await?;
 
         Ok(storage)
     }
 
-    /// Initialize database tables
-    async fn init_tables(&self) -> StorageResult<()> {
+    /// Schema version of the open database
+    pub async fn schema_version(&self) -> StorageResult<u32> {
+        let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(version as u32)
+    }
+
+    /// Bring the database schema up to [`DATABASE_SCHEMA_VERSION`]
+    ///
+    /// Existing databases at an older version are first copied next to
+    /// `db_path` (see [`crate::config::migration::backup_path`]). Each step
+    /// runs in its own transaction together with the version bump, so an
+    /// interrupted upgrade resumes from the last completed step.
+    async fn migrate(&self, db_path: Option<&Path>) -> StorageResult<()> {
+        let found = self.schema_version().await?;
+        if found > DATABASE_SCHEMA_VERSION {
+            return Err(StorageError::UnsupportedVersion {
+                found,
+                supported: DATABASE_SCHEMA_VERSION,
+            });
+        }
+        if found == DATABASE_SCHEMA_VERSION {
+            return Ok(());
+        }
+
+        let has_tables: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'subscriptions'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if let Some(path) = db_path.filter(|_| has_tables > 0) {
+            self.backup(path, found).await?;
+        }
+
+        for version in found..DATABASE_SCHEMA_VERSION {
+            let mut tx = self.pool.begin().await?;
+            match version {
+                0 => Self::migrate_v0(&mut tx).await?,
+                1 => Self::migrate_v1(&mut tx).await?,
+                2 => Self::migrate_v2(&mut tx).await?,
+                3 => Self::migrate_v3(&mut tx).await?,
+                _ => unreachable!("no migration from schema version {}", version),
+            }
+            // PRAGMA does not take bound parameters
+            sqlx::query(&format!("PRAGMA user_version = {}", version + 1))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+
+        if has_tables > 0 {
+            info!(
+                "Subscription database upgraded from schema version {} to {}",
+                found, DATABASE_SCHEMA_VERSION
+            );
+        }
+        Ok(())
+    }
+
+    /// Copy the database before upgrading it from `version`
+    async fn backup(&self, db_path: &Path, version: u32) -> StorageResult<()> {
+        let backup = crate::config::migration::backup_path(db_path, version);
+        if backup.exists() {
+            // Left by an interrupted upgrade; it holds the older original
+            warn!("Keeping existing database backup {:?}", backup);
+            return Ok(());
+        }
+        self.snapshot(&backup).await?;
+        info!("Subscription database backed up to {:?}", backup);
+        Ok(())
+    }
+
+    /// Whether `table` has a column named `column`
+    async fn has_column(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        table: &str,
+        column: &str,
+    ) -> StorageResult<bool> {
+        let count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM pragma_table_info(?) WHERE name = ?")
+                .bind(table)
+                .bind(column)
+                .fetch_one(&mut **tx)
+                .await?;
+        Ok(count > 0)
+    }
+
+    /// v0 → v1: create the tables, adding columns unversioned databases lack
+    async fn migrate_v0(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> StorageResult<()> {
         debug!("Initializing subscription database tables");
 
         // Create subscriptions table
@@ -68,7 +390,7 @@ impl SubscriptionStorage {
             )
             "#,
         )
-        .execute(&self.pool)
+        .execute(&mut **tx)
         .await?;
 
         // Create servers table
@@ -83,22 +405,99 @@ impl SubscriptionStorage {
                 protocol TEXT NOT NULL,
                 config TEXT NOT NULL,
                 stream_settings TEXT,
+                tags TEXT,
+                raw_name TEXT,
                 created_at TEXT NOT NULL,
                 FOREIGN KEY (subscription_id) REFERENCES subscriptions(id) ON DELETE CASCADE
             )
             "#,
         )
-        .execute(&self.pool)
+        .execute(&mut **tx)
         .await?;
 
-        // Add stream_settings column if it doesn't exist (migration for existing databases)
-        let _ = sqlx::query(
+        // Columns added to servers after its first release: stream settings,
+        // tags (JSON array) and the raw (unnormalized) name
+        for column in ["stream_settings", "tags", "raw_name"] {
+            if !Self::has_column(tx, "servers", column).await? {
+                sqlx::query(&format!("ALTER TABLE servers ADD COLUMN {} TEXT", column))
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+
+        // Create manual server order table, keyed by the stable server order key
+        // so the order also applies to servers that are removed and added again
+        sqlx::query(
             r#"
-            ALTER TABLE servers ADD COLUMN stream_settings TEXT
+            CREATE TABLE IF NOT EXISTS server_order (
+                subscription_id TEXT NOT NULL,
+                server_key TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (subscription_id, server_key)
+            )
             "#,
         )
-        .execute(&self.pool)
-        .await; // Ignore error if column already exists
+        .execute(&mut **tx)
+        .await?;
+
+        // Create update schedule table; subscriptions without a row follow
+        // the default interval
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS update_schedule (
+                subscription_id TEXT PRIMARY KEY,
+                schedule TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        // Create server user data table, keyed by server fingerprint so it is
+        // independent of subscription updates and deletions
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS server_user_data (
+                fingerprint TEXT PRIMARY KEY,
+                favorite INTEGER NOT NULL DEFAULT 0,
+                alias TEXT,
+                hidden INTEGER NOT NULL DEFAULT 0,
+                pinned_position INTEGER
+            )
+            "#,
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        // Create update history table, trimmed to the latest attempts per subscription
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS update_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                subscription_id TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                error TEXT,
+                server_count_delta INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_update_history_subscription_id \
+             ON update_history(subscription_id)",
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        // Server diff column, added after the update history table
+        if !Self::has_column(tx, "update_history", "diff").await? {
+            sqlx::query("ALTER TABLE update_history ADD COLUMN diff TEXT")
+                .execute(&mut **tx)
+                .await?;
+        }
 
         // Create index on subscription_id for faster queries
         sqlx::query(
@@ -107,10 +506,47 @@ impl SubscriptionStorage {
             ON servers(subscription_id)
             "#,
         )
-        .execute(&self.pool)
+        .execute(&mut **tx)
         .await?;
 
-        info!("Database tables initialized");
+        Ok(())
+    }
+
+    /// v1 → v2: add the subscription health table
+    async fn migrate_v1(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> StorageResult<()> {
+        // Health check results as JSON (see `SubscriptionHealth`)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS subscription_health (
+                subscription_id TEXT PRIMARY KEY,
+                health TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&mut **tx)
+        .await?;
+
+        Ok(())
+    }
+
+    /// v2 → v3: add per-subscription request options (JSON, see
+    /// `SubscriptionRequest`)
+    async fn migrate_v2(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> StorageResult<()> {
+        if !Self::has_column(tx, "subscriptions", "request").await? {
+            sqlx::query("ALTER TABLE subscriptions ADD COLUMN request TEXT")
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// v3 → v4: add the fetch attempts of updates (JSON, see `FetchAttempt`)
+    async fn migrate_v3(tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> StorageResult<()> {
+        if !Self::has_column(tx, "update_history", "attempts").await? {
+            sqlx::query("ALTER TABLE update_history ADD COLUMN attempts TEXT")
+                .execute(&mut **tx)
+                .await?;
+        }
         Ok(())
     }
 
@@ -125,19 +561,30 @@ impl SubscriptionStorage {
             SubscriptionStatus::Updating => "updating",
         };
 
+        // Request options may hold credentials and are encrypted like the URL
+        let request = match subscription.request.is_default() {
+            true => None,
+            false => {
+                let request = serde_json::to_string(&subscription.request)
+                    .map_err(|e| StorageError::Serialization(e.to_string()))?;
+                Some(self.encode_column(&request)?)
+            }
+        };
+
         sqlx::query(
             r#"
             INSERT OR REPLACE INTO subscriptions 
-            (id, name, url, last_update, server_count, status, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))
+            (id, name, url, last_update, server_count, status, request, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, datetime('now'), datetime('now'))
             "#,
         )
         .bind(subscription.id.to_string())
         .bind(&subscription.name)
-        .bind(&subscription.url)
+        .bind(self.encode_column(&subscription.url)?)
         .bind(subscription.last_update.map(|dt| dt.to_rfc3339()))
         .bind(subscription.server_count as i64)
         .bind(status_str)
+        .bind(request)
         .execute(&self.pool)
         .await?;
 
@@ -175,14 +622,21 @@ impl SubscriptionStorage {
                 .and_then(|s| chrono::DateTime::parse_from_rfc3339(&s).ok())
                 .map(|dt| dt.with_timezone(&chrono::Utc));
 
+            let request = match row.get::<Option<String>, _>("request") {
+                Some(request) => serde_json::from_str(&self.decode_column(&request)?)
+                    .map_err(|e| StorageError::Parse(format!("Invalid request options: {}", e)))?,
+                None => SubscriptionRequest::default(),
+            };
+
             subscriptions.push(Subscription {
                 id: Uuid::parse_str(&id)
                     .map_err(|e| StorageError::Parse(format!("Invalid UUID: {}", e)))?,
                 name: row.get("name"),
-                url: row.get("url"),
+                url: self.decode_column(&row.get::<String, _>("url"))?,
                 last_update,
                 server_count: row.get::<i64, _>("server_count") as usize,
                 status,
+                request,
             });
         }
 
@@ -199,8 +653,20 @@ impl SubscriptionStorage {
             .execute(&self.pool)
             .await?;
 
-        // Also delete associated servers
+        // Also delete associated servers, their manual order, update history
+        // and schedule
         self.delete_servers_for_subscription(id).await?;
+        self.save_server_order(id, &[]).await?;
+        sqlx::query("DELETE FROM update_history WHERE subscription_id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
+        self.save_update_schedule(id, &UpdateSchedule::Default)
+            .await?;
+        sqlx::query("DELETE FROM subscription_health WHERE subscription_id = ?")
+            .bind(id.to_string())
+            .execute(&self.pool)
+            .await?;
 
         Ok(())
     }
@@ -211,29 +677,37 @@ impl SubscriptionStorage {
 
         let config_json = serde_json::to_string(&server.config)
             .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let config_json = self.encode_column(&config_json)?;
 
         let stream_settings_json = server
             .stream_settings
             .as_ref()
             .map(serde_json::to_string)
             .transpose()
+            .map_err(|e| StorageError::Serialization(e.to_string()))?
+            .map(|json| self.encode_column(&json))
+            .transpose()?;
+
+        let tags_json = serde_json::to_string(&server.tags)
             .map_err(|e| StorageError::Serialization(e.to_string()))?;
 
         sqlx::query(
             r#"
             INSERT OR REPLACE INTO servers
-            (id, subscription_id, name, address, port, protocol, config, stream_settings, created_at)
-            VALUES (?, ?, ?, ?, ?, ?, ?, ?, datetime('now'))
+            (id, subscription_id, name, raw_name, address, port, protocol, config, stream_settings, tags, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, datetime('now'))
             "#,
         )
         .bind(server.id.to_string())
         .bind(server.subscription_id.to_string())
         .bind(&server.name)
+        .bind(&server.raw_name)
         .bind(&server.address)
         .bind(server.port as i64)
         .bind(&server.protocol)
         .bind(config_json)
         .bind(stream_settings_json)
+        .bind(tags_json)
         .execute(&self.pool)
         .await?;
 
@@ -253,14 +727,16 @@ impl SubscriptionStorage {
         for row in rows {
             let id: String = row.get("id");
             let subscription_id: String = row.get("subscription_id");
-            let config_json: String = row.get("config");
+            let config_json = self.decode_column(&row.get::<String, _>("config"))?;
 
             let config = serde_json::from_str(&config_json)
                 .map_err(|e| StorageError::Parse(format!("Invalid config JSON: {}", e)))?;
 
             let stream_settings_json: Option<String> = row.try_get("stream_settings").ok();
-            let stream_settings =
-                stream_settings_json.and_then(|json| serde_json::from_str(&json).ok());
+            let stream_settings = stream_settings_json
+                .map(|json| self.decode_column(&json))
+                .transpose()?
+                .and_then(|json| serde_json::from_str(&json).ok());
 
             servers.push(Server {
                 id: Uuid::parse_str(&id)
@@ -273,6 +749,8 @@ impl SubscriptionStorage {
                 protocol: row.get("protocol"),
                 config,
                 stream_settings,
+                tags: parse_tags(&row),
+                raw_name: row.try_get("raw_name").ok().flatten(),
             });
         }
 
@@ -296,14 +774,16 @@ impl SubscriptionStorage {
 
         for row in rows {
             let id: String = row.get("id");
-            let config_json: String = row.get("config");
+            let config_json = self.decode_column(&row.get::<String, _>("config"))?;
 
             let config = serde_json::from_str(&config_json)
                 .map_err(|e| StorageError::Parse(format!("Invalid config JSON: {}", e)))?;
 
             let stream_settings_json: Option<String> = row.try_get("stream_settings").ok();
-            let stream_settings =
-                stream_settings_json.and_then(|json| serde_json::from_str(&json).ok());
+            let stream_settings = stream_settings_json
+                .map(|json| self.decode_column(&json))
+                .transpose()?
+                .and_then(|json| serde_json::from_str(&json).ok());
 
             servers.push(Server {
                 id: Uuid::parse_str(&id)
@@ -315,6 +795,8 @@ impl SubscriptionStorage {
                 protocol: row.get("protocol"),
                 config,
                 stream_settings,
+                tags: parse_tags(&row),
+                raw_name: row.try_get("raw_name").ok().flatten(),
             });
         }
 
@@ -335,11 +817,447 @@ impl SubscriptionStorage {
 
         Ok(())
     }
+
+    /// Save the manual server order of a subscription
+    ///
+    /// `keys` are [`Server::order_key`] values in display order; an empty
+    /// list clears the manual order.
+    pub async fn save_server_order(
+        &self,
+        subscription_id: Uuid,
+        keys: &[String],
+    ) -> StorageResult<()> {
+        debug!("Saving server order for subscription: {}", subscription_id);
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM server_order WHERE subscription_id = ?")
+            .bind(subscription_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+
+        for (position, key) in keys.iter().enumerate() {
+            sqlx::query(
+                "INSERT OR REPLACE INTO server_order (subscription_id, server_key, position) VALUES (?, ?, ?)",
+            )
+            .bind(subscription_id.to_string())
+            .bind(key)
+            .bind(position as i64)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Load the manual server order of all subscriptions
+    pub async fn load_server_orders(&self) -> StorageResult<HashMap<Uuid, Vec<String>>> {
+        let rows = sqlx::query(
+            "SELECT subscription_id, server_key FROM server_order ORDER BY subscription_id, position",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut orders: HashMap<Uuid, Vec<String>> = HashMap::new();
+        for row in rows {
+            let subscription_id: String = row.get("subscription_id");
+            let subscription_id = Uuid::parse_str(&subscription_id)
+                .map_err(|e| StorageError::Parse(format!("Invalid UUID: {}", e)))?;
+            orders
+                .entry(subscription_id)
+                .or_default()
+                .push(row.get("server_key"));
+        }
+
+        Ok(orders)
+    }
+
+    /// Delete several servers in a single transaction
+    ///
+    /// Returns the number of deleted rows.
+    pub async fn delete_servers(&self, server_ids: &[Uuid]) -> StorageResult<u64> {
+        debug!("Deleting {} servers", server_ids.len());
+
+        let mut tx = self.pool.begin().await?;
+        let mut deleted = 0;
+
+        for id in server_ids {
+            deleted += sqlx::query("DELETE FROM servers WHERE id = ?")
+                .bind(id.to_string())
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+
+        sqlx::query(REFRESH_SERVER_COUNTS).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    /// Move several servers to another subscription in a single transaction
+    ///
+    /// Returns the number of moved rows.
+    pub async fn move_servers(
+        &self,
+        server_ids: &[Uuid],
+        subscription_id: Uuid,
+    ) -> StorageResult<u64> {
+        debug!(
+            "Moving {} servers to subscription: {}",
+            server_ids.len(),
+            subscription_id
+        );
+
+        let mut tx = self.pool.begin().await?;
+        let mut moved = 0;
+
+        for id in server_ids {
+            moved += sqlx::query("UPDATE servers SET subscription_id = ? WHERE id = ?")
+                .bind(subscription_id.to_string())
+                .bind(id.to_string())
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+
+        sqlx::query(REFRESH_SERVER_COUNTS).execute(&mut *tx).await?;
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    /// Save the tags of several servers in a single transaction
+    pub async fn save_server_tags(&self, servers: &[Server]) -> StorageResult<()> {
+        debug!("Saving tags of {} servers", servers.len());
+
+        let mut tx = self.pool.begin().await?;
+
+        for server in servers {
+            let tags_json = serde_json::to_string(&server.tags)
+                .map_err(|e| StorageError::Serialization(e.to_string()))?;
+            sqlx::query("UPDATE servers SET tags = ? WHERE id = ?")
+                .bind(tags_json)
+                .bind(server.id.to_string())
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Save server user data entries in a single transaction
+    ///
+    /// Entries are `(fingerprint, data)` pairs as returned by
+    /// [`super::SubscriptionManager::update_user_data`]; empty data deletes
+    /// the row.
+    pub async fn save_server_user_data(
+        &self,
+        entries: &[(String, ServerUserData)],
+    ) -> StorageResult<()> {
+        debug!("Saving user data of {} servers", entries.len());
+
+        let mut tx = self.pool.begin().await?;
+
+        for (fingerprint, data) in entries {
+            if data.is_empty() {
+                sqlx::query("DELETE FROM server_user_data WHERE fingerprint = ?")
+                    .bind(fingerprint)
+                    .execute(&mut *tx)
+                    .await?;
+                continue;
+            }
+
+            sqlx::query(
+                r#"
+                INSERT OR REPLACE INTO server_user_data
+                (fingerprint, favorite, alias, hidden, pinned_position)
+                VALUES (?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(fingerprint)
+            .bind(data.favorite)
+            .bind(&data.alias)
+            .bind(data.hidden)
+            .bind(data.pinned_position.map(i64::from))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Load the user data of all servers by fingerprint
+    pub async fn load_server_user_data(&self) -> StorageResult<HashMap<String, ServerUserData>> {
+        let rows = sqlx::query("SELECT * FROM server_user_data")
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| {
+                let data = ServerUserData {
+                    favorite: row.get("favorite"),
+                    alias: row.get("alias"),
+                    hidden: row.get("hidden"),
+                    pinned_position: row
+                        .get::<Option<i64>, _>("pinned_position")
+                        .map(|position| position as u32),
+                };
+                (row.get("fingerprint"), data)
+            })
+            .collect())
+    }
+
+    /// Save the update schedule of a subscription
+    pub async fn save_update_schedule(
+        &self,
+        subscription_id: Uuid,
+        schedule: &UpdateSchedule,
+    ) -> StorageResult<()> {
+        if *schedule == UpdateSchedule::Default {
+            sqlx::query("DELETE FROM update_schedule WHERE subscription_id = ?")
+                .bind(subscription_id.to_string())
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        }
+
+        let schedule = serde_json::to_string(schedule)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO update_schedule (subscription_id, schedule) VALUES (?, ?)",
+        )
+        .bind(subscription_id.to_string())
+        .bind(schedule)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Load the update schedules differing from the default interval
+    pub async fn load_update_schedules(&self) -> StorageResult<HashMap<Uuid, UpdateSchedule>> {
+        let rows = sqlx::query("SELECT subscription_id, schedule FROM update_schedule")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let subscription_id: String = row.get("subscription_id");
+                let subscription_id = Uuid::parse_str(&subscription_id)
+                    .map_err(|e| StorageError::Parse(format!("Invalid UUID: {}", e)))?;
+                let schedule: String = row.get("schedule");
+                let schedule = serde_json::from_str(&schedule)
+                    .map_err(|e| StorageError::Parse(format!("Invalid schedule: {}", e)))?;
+                Ok((subscription_id, schedule))
+            })
+            .collect()
+    }
+
+    /// Record an update outcome, keeping the latest [`UPDATE_HISTORY_LIMIT`]
+    /// per subscription
+    pub async fn record_update_outcome(&self, outcome: &UpdateOutcome) -> StorageResult<()> {
+        debug!("Recording update outcome for {}", outcome.subscription_id);
+
+        let subscription_id = outcome.subscription_id.to_string();
+        let diff = serde_json::to_string(&outcome.diff)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let attempts = serde_json::to_string(&outcome.attempts)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO update_history
+            (subscription_id, started_at, duration_ms, error, server_count_delta, diff, attempts)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(&subscription_id)
+        .bind(outcome.started_at.to_rfc3339())
+        .bind(outcome.duration_ms as i64)
+        .bind(&outcome.error)
+        .bind(outcome.server_count_delta)
+        .bind(diff)
+        .bind(attempts)
+        .execute(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            DELETE FROM update_history WHERE subscription_id = ? AND id NOT IN
+            (SELECT id FROM update_history WHERE subscription_id = ? ORDER BY id DESC LIMIT ?)
+            "#,
+        )
+        .bind(&subscription_id)
+        .bind(&subscription_id)
+        .bind(UPDATE_HISTORY_LIMIT as i64)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Load the recorded update outcomes of a subscription, newest first
+    pub async fn load_update_history(
+        &self,
+        subscription_id: Uuid,
+    ) -> StorageResult<Vec<UpdateOutcome>> {
+        let rows =
+            sqlx::query("SELECT * FROM update_history WHERE subscription_id = ? ORDER BY id DESC")
+                .bind(subscription_id.to_string())
+                .fetch_all(&self.pool)
+                .await?;
+
+        rows.iter()
+            .map(|row| {
+                let started_at: String = row.get("started_at");
+                let started_at = chrono::DateTime::parse_from_rfc3339(&started_at)
+                    .map_err(|e| StorageError::Parse(format!("Invalid timestamp: {}", e)))?
+                    .with_timezone(&chrono::Utc);
+                // Rows recorded before diffs were kept have none
+                let diff = match row.get::<Option<String>, _>("diff") {
+                    Some(diff) => serde_json::from_str(&diff)
+                        .map_err(|e| StorageError::Parse(format!("Invalid server diff: {}", e)))?,
+                    None => ServerDiff::default(),
+                };
+                // Nor attempts before they were reported
+                let attempts = match row.get::<Option<String>, _>("attempts") {
+                    Some(attempts) => serde_json::from_str(&attempts).map_err(|e| {
+                        StorageError::Parse(format!("Invalid fetch attempts: {}", e))
+                    })?,
+                    None => Vec::new(),
+                };
+                Ok(UpdateOutcome {
+                    subscription_id,
+                    started_at,
+                    duration_ms: row.get::<i64, _>("duration_ms") as u64,
+                    error: row.get("error"),
+                    server_count_delta: row.get("server_count_delta"),
+                    diff,
+                    attempts,
+                })
+            })
+            .collect()
+    }
+
+    /// Save the health check results of a subscription
+    pub async fn save_subscription_health(
+        &self,
+        subscription_id: Uuid,
+        health: &SubscriptionHealth,
+    ) -> StorageResult<()> {
+        let health = serde_json::to_string(health)
+            .map_err(|e| StorageError::Serialization(e.to_string()))?;
+        sqlx::query(
+            "INSERT OR REPLACE INTO subscription_health (subscription_id, health) VALUES (?, ?)",
+        )
+        .bind(subscription_id.to_string())
+        .bind(health)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Load the health check results of all checked subscriptions
+    pub async fn load_subscription_health(
+        &self,
+    ) -> StorageResult<HashMap<Uuid, SubscriptionHealth>> {
+        let rows = sqlx::query("SELECT subscription_id, health FROM subscription_health")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let subscription_id: String = row.get("subscription_id");
+                let subscription_id = Uuid::parse_str(&subscription_id)
+                    .map_err(|e| StorageError::Parse(format!("Invalid UUID: {}", e)))?;
+                let health: String = row.get("health");
+                let health = serde_json::from_str(&health)
+                    .map_err(|e| StorageError::Parse(format!("Invalid health: {}", e)))?;
+                Ok((subscription_id, health))
+            })
+            .collect()
+    }
+
+    /// Get storage usage statistics
+    pub async fn get_stats(&self) -> StorageResult<StorageStats> {
+        let subscription_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM subscriptions")
+            .fetch_one(&self.pool)
+            .await?;
+        let server_count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM servers")
+            .fetch_one(&self.pool)
+            .await?;
+
+        let favorite_count: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM server_user_data WHERE favorite = 1")
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(StorageStats {
+            subscription_count: subscription_count as u64,
+            server_count: server_count as u64,
+            favorite_count: favorite_count as u64,
+            database_size: database_size(&self.pool).await?,
+        })
+    }
+}
+
+/// Size of the database behind `pool` in bytes
+///
+/// page_count * page_size works for both file and in-memory databases.
+pub(crate) async fn database_size(pool: &SqlitePool) -> StorageResult<u64> {
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(pool)
+        .await?;
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+        .fetch_one(pool)
+        .await?;
+    Ok((page_count * page_size) as u64)
+}
+
+/// Encrypt `value` with `key`, or keep it as it is without a key
+fn encode_value(key: Option<&[u8; 32]>, value: &str) -> StorageResult<String> {
+    match key {
+        Some(key) => {
+            let encrypted = encrypt_aes256(value.as_bytes(), key)
+                .map_err(|e| StorageError::Encryption(e.to_string()))?;
+            Ok(format!("{}{}", ENCRYPTED_PREFIX, encrypted))
+        }
+        None => Ok(value.to_string()),
+    }
+}
+
+/// Decrypt `value` with `key` if it is encrypted
+fn decode_value(key: Option<&[u8; 32]>, value: &str) -> StorageResult<String> {
+    let Some(encrypted) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok(value.to_string());
+    };
+
+    let key = key.ok_or_else(|| {
+        StorageError::Encryption("Database is encrypted but no password was given".to_string())
+    })?;
+    let decrypted =
+        decrypt_aes256(encrypted, key).map_err(|e| StorageError::Encryption(e.to_string()))?;
+
+    String::from_utf8(decrypted).map_err(|e| StorageError::Encryption(e.to_string()))
+}
+
+/// Read the tags column of a server row (missing or invalid values yield no tags)
+fn parse_tags(row: &sqlx::sqlite::SqliteRow) -> Vec<String> {
+    row.try_get::<Option<String>, _>("tags")
+        .ok()
+        .flatten()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::subscription::{FetchAttempt, FetchRoute};
     use std::collections::HashMap;
 
     #[tokio::test]
@@ -359,6 +1277,7 @@ mod tests {
             last_update: Some(chrono::Utc::now()),
             server_count: 5,
             status: SubscriptionStatus::Active,
+            request: Default::default(),
         };
 
         // Save subscription
@@ -384,6 +1303,7 @@ mod tests {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
 
         storage.save_subscription(&subscription).await.unwrap();
@@ -406,6 +1326,7 @@ mod tests {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
         storage.save_subscription(&subscription).await.unwrap();
 
@@ -419,6 +1340,8 @@ mod tests {
             protocol: "vmess".to_string(),
             config: HashMap::new(),
             stream_settings: None,
+            tags: vec![],
+            raw_name: Some("\u{200F}Test  Server".to_string()),
         };
 
         // Save server
@@ -429,6 +1352,7 @@ mod tests {
         assert_eq!(loaded.len(), 1);
         assert_eq!(loaded[0].id, server.id);
         assert_eq!(loaded[0].name, server.name);
+        assert_eq!(loaded[0].raw_name, server.raw_name);
         assert_eq!(loaded[0].address, server.address);
         assert_eq!(loaded[0].port, server.port);
     }
@@ -448,6 +1372,7 @@ mod tests {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
         storage.save_subscription(&sub1).await.unwrap();
 
@@ -458,6 +1383,7 @@ mod tests {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
         storage.save_subscription(&sub2).await.unwrap();
 
@@ -472,6 +1398,8 @@ mod tests {
                 protocol: "vmess".to_string(),
                 config: HashMap::new(),
                 stream_settings: None,
+                tags: vec![],
+                raw_name: None,
             };
             storage.save_server(&server).await.unwrap();
         }
@@ -487,6 +1415,8 @@ mod tests {
                 protocol: "vless".to_string(),
                 config: HashMap::new(),
                 stream_settings: None,
+                tags: vec![],
+                raw_name: None,
             };
             storage.save_server(&server).await.unwrap();
         }
@@ -520,6 +1450,7 @@ mod tests {
             last_update: None,
             server_count: 0,
             status: SubscriptionStatus::Inactive,
+            request: Default::default(),
         };
         storage.save_subscription(&subscription).await.unwrap();
 
@@ -534,6 +1465,8 @@ mod tests {
                 protocol: "vmess".to_string(),
                 config: HashMap::new(),
                 stream_settings: None,
+                tags: vec![],
+                raw_name: None,
             };
             storage.save_server(&server).await.unwrap();
         }
@@ -551,4 +1484,533 @@ mod tests {
             .unwrap();
         assert!(servers.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_storage_stats() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name: "Test Subscription".to_string(),
+            url: "https://example.com/sub".to_string(),
+            last_update: None,
+            server_count: 1,
+            status: SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+        storage.save_subscription(&subscription).await.unwrap();
+
+        let server = Server {
+            id: Uuid::new_v4(),
+            subscription_id: subscription.id,
+            name: "Server".to_string(),
+            address: "example.com".to_string(),
+            port: 443,
+            protocol: "vmess".to_string(),
+            config: HashMap::new(),
+            stream_settings: None,
+            tags: vec![],
+            raw_name: None,
+        };
+        storage.save_server(&server).await.unwrap();
+        let favorite = ServerUserData {
+            favorite: true,
+            ..Default::default()
+        };
+        storage
+            .save_server_user_data(&[("fingerprint".to_string(), favorite)])
+            .await
+            .unwrap();
+
+        let stats = storage.get_stats().await.unwrap();
+        assert_eq!(stats.subscription_count, 1);
+        assert_eq!(stats.server_count, 1);
+        assert_eq!(stats.favorite_count, 1);
+        assert!(stats.database_size > 0);
+    }
+
+    #[tokio::test]
+    async fn test_encryption_migrates_plaintext() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("subscriptions.db");
+
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name: "Test Subscription".to_string(),
+            url: "https://example.com/sub".to_string(),
+            last_update: None,
+            server_count: 1,
+            status: SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+        let mut config = HashMap::new();
+        config.insert("id".to_string(), serde_json::json!("secret-uuid"));
+        let server = Server {
+            id: Uuid::new_v4(),
+            subscription_id: subscription.id,
+            name: "Server".to_string(),
+            address: "example.com".to_string(),
+            port: 443,
+            protocol: "vmess".to_string(),
+            config,
+            stream_settings: None,
+            tags: vec![],
+            raw_name: None,
+        };
+
+        // Write a plaintext database first
+        let storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        storage.save_subscription(&subscription).await.unwrap();
+        storage.save_server(&server).await.unwrap();
+        drop(storage);
+
+        let mut storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        assert_eq!(storage.enable_encryption("password").await.unwrap(), 1);
+        assert!(storage.is_encrypted());
+
+        let raw: String = sqlx::query("SELECT config FROM servers")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap()
+            .get("config");
+        assert!(raw.starts_with(ENCRYPTED_PREFIX));
+        assert!(!raw.contains("secret-uuid"));
+
+        let loaded = storage.load_servers().await.unwrap();
+        assert_eq!(loaded[0].config, server.config);
+
+        // Reopening requires the password
+        drop(storage);
+        let storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        assert!(matches!(
+            storage.load_servers().await,
+            Err(StorageError::Encryption(_))
+        ));
+        assert!(
+            SubscriptionStorage::new_with_encryption(&db_path, "wrong".to_string())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wrong_key_rejected_without_servers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("subscriptions.db");
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name: "Test Subscription".to_string(),
+            url: "https://example.com/sub?token=secret".to_string(),
+            last_update: None,
+            server_count: 0,
+            status: SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+
+        let mut storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        storage.save_subscription(&subscription).await.unwrap();
+        storage.enable_encryption("password").await.unwrap();
+        drop(storage);
+
+        // Only the subscription URL is encrypted
+        let mut storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        assert!(matches!(
+            storage.enable_encryption("wrong").await,
+            Err(StorageError::Encryption(_))
+        ));
+        assert!(!storage.is_encrypted());
+
+        storage.enable_encryption("password").await.unwrap();
+        assert_eq!(
+            storage.load_subscriptions().await.unwrap()[0].url,
+            subscription.url
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotate_key() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("subscriptions.db");
+
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name: "Test Subscription".to_string(),
+            url: "https://example.com/sub?token=secret".to_string(),
+            last_update: None,
+            server_count: 0,
+            status: SubscriptionStatus::Active,
+            request: Default::default(),
+        };
+        let server = Server {
+            id: Uuid::new_v4(),
+            subscription_id: subscription.id,
+            name: "Server".to_string(),
+            address: "example.com".to_string(),
+            port: 443,
+            protocol: "vmess".to_string(),
+            config: [("id".to_string(), serde_json::json!("secret-uuid"))].into(),
+            stream_settings: None,
+            tags: vec![],
+            raw_name: None,
+        };
+
+        let old_key = crate::utils::crypto::generate_key();
+        let new_key = crate::utils::crypto::generate_key();
+        let mut storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        storage.save_subscription(&subscription).await.unwrap();
+        storage.enable_encryption_with_key(old_key).await.unwrap();
+        storage.save_server(&server).await.unwrap();
+
+        let raw: String = sqlx::query("SELECT url FROM subscriptions")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap()
+            .get("url");
+        assert!(raw.starts_with(ENCRYPTED_PREFIX));
+        assert!(!raw.contains("token"));
+
+        assert_eq!(storage.rotate_key(new_key).await.unwrap(), 1);
+        drop(storage);
+
+        let mut storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        assert!(storage.enable_encryption_with_key(old_key).await.is_err());
+        storage.enable_encryption_with_key(new_key).await.unwrap();
+        assert_eq!(
+            storage.load_subscriptions().await.unwrap()[0].url,
+            subscription.url
+        );
+        assert_eq!(
+            storage.load_servers().await.unwrap()[0].config,
+            server.config
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_order() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        let subscription_id = Uuid::new_v4();
+        let keys = vec![
+            "b|b.example.com:443".to_string(),
+            "a|a.example.com:443".to_string(),
+        ];
+
+        storage
+            .save_server_order(subscription_id, &keys)
+            .await
+            .unwrap();
+        let orders = storage.load_server_orders().await.unwrap();
+        assert_eq!(orders.get(&subscription_id), Some(&keys));
+
+        storage
+            .save_server_order(subscription_id, &[])
+            .await
+            .unwrap();
+        assert!(storage.load_server_orders().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_server_user_data() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        let favorite = ServerUserData {
+            favorite: true,
+            alias: Some("Home".to_string()),
+            ..Default::default()
+        };
+        let pinned = ServerUserData {
+            hidden: true,
+            pinned_position: Some(2),
+            ..Default::default()
+        };
+
+        storage
+            .save_server_user_data(&[
+                ("a".to_string(), favorite.clone()),
+                ("b".to_string(), pinned.clone()),
+            ])
+            .await
+            .unwrap();
+        let loaded = storage.load_server_user_data().await.unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded["a"], favorite);
+        assert_eq!(loaded["b"], pinned);
+
+        // Empty data removes the row
+        storage
+            .save_server_user_data(&[("a".to_string(), ServerUserData::default())])
+            .await
+            .unwrap();
+        let loaded = storage.load_server_user_data().await.unwrap();
+        assert!(!loaded.contains_key("a"));
+        assert_eq!(loaded.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_schedules() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        let subscription_id = Uuid::new_v4();
+        let schedule = UpdateSchedule::Cron {
+            expression: "0 6 * * *".to_string(),
+        };
+
+        storage
+            .save_update_schedule(subscription_id, &schedule)
+            .await
+            .unwrap();
+        let schedules = storage.load_update_schedules().await.unwrap();
+        assert_eq!(schedules.get(&subscription_id), Some(&schedule));
+
+        // Back to the default interval: no row kept
+        storage
+            .save_update_schedule(subscription_id, &UpdateSchedule::Default)
+            .await
+            .unwrap();
+        assert!(storage.load_update_schedules().await.unwrap().is_empty());
+
+        storage
+            .save_update_schedule(subscription_id, &UpdateSchedule::Manual)
+            .await
+            .unwrap();
+        storage.delete_subscription(subscription_id).await.unwrap();
+        assert!(storage.load_update_schedules().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_batch_server_operations() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+
+        let (source, target) = (Uuid::new_v4(), Uuid::new_v4());
+        for id in [source, target] {
+            let subscription = Subscription {
+                id,
+                name: "Test Subscription".to_string(),
+                url: "https://example.com".to_string(),
+                last_update: None,
+                server_count: 0,
+                status: SubscriptionStatus::Active,
+                request: Default::default(),
+            };
+            storage.save_subscription(&subscription).await.unwrap();
+        }
+
+        let mut servers = Vec::new();
+        for i in 0..3 {
+            let server = Server {
+                id: Uuid::new_v4(),
+                subscription_id: source,
+                name: format!("Server {}", i),
+                address: "example.com".to_string(),
+                port: 443,
+                protocol: "vmess".to_string(),
+                config: HashMap::new(),
+                stream_settings: None,
+                tags: vec![],
+                raw_name: None,
+            };
+            storage.save_server(&server).await.unwrap();
+            servers.push(server);
+        }
+        let ids: Vec<Uuid> = servers.iter().map(|s| s.id).collect();
+
+        servers[0].tags = vec!["fast".to_string()];
+        storage.save_server_tags(&servers[..1]).await.unwrap();
+
+        assert_eq!(storage.move_servers(&ids[..2], target).await.unwrap(), 2);
+        let moved = storage.load_servers_for_subscription(target).await.unwrap();
+        assert_eq!(moved.len(), 2);
+        let tagged = moved.iter().find(|s| s.id == ids[0]).unwrap();
+        assert_eq!(tagged.tags, vec!["fast"]);
+
+        assert_eq!(storage.delete_servers(&ids[1..]).await.unwrap(), 2);
+        assert_eq!(storage.load_servers().await.unwrap().len(), 1);
+
+        let counts: HashMap<Uuid, usize> = storage
+            .load_subscriptions()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.id, s.server_count))
+            .collect();
+        assert_eq!(counts[&source], 0);
+        assert_eq!(counts[&target], 1);
+    }
+
+    #[tokio::test]
+    async fn test_update_history() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        let id = Uuid::new_v4();
+        let other = Uuid::new_v4();
+
+        for i in 0..UPDATE_HISTORY_LIMIT + 5 {
+            let outcome = UpdateOutcome {
+                subscription_id: id,
+                started_at: chrono::Utc::now(),
+                duration_ms: i as u64,
+                error: (i % 2 == 1).then(|| "timeout".to_string()),
+                server_count_delta: i as i64,
+                diff: ServerDiff::default(),
+                attempts: Vec::new(),
+            };
+            storage.record_update_outcome(&outcome).await.unwrap();
+        }
+        let outcome = UpdateOutcome {
+            subscription_id: other,
+            started_at: chrono::Utc::now(),
+            duration_ms: 10,
+            error: None,
+            server_count_delta: 3,
+            diff: ServerDiff {
+                added: vec!["a".to_string(), "b".to_string()],
+                removed: vec![],
+                changed: vec!["c".to_string()],
+            },
+            attempts: vec![
+                FetchAttempt {
+                    route: FetchRoute::Direct,
+                    attempt: 1,
+                    duration_ms: 30000,
+                    http_status: None,
+                    error: Some("operation timed out".to_string()),
+                },
+                FetchAttempt {
+                    route: FetchRoute::Proxy,
+                    attempt: 1,
+                    duration_ms: 120,
+                    http_status: Some(200),
+                    error: None,
+                },
+            ],
+        };
+        storage.record_update_outcome(&outcome).await.unwrap();
+
+        let history = storage.load_update_history(id).await.unwrap();
+        assert_eq!(history.len(), UPDATE_HISTORY_LIMIT);
+        // Newest first
+        assert_eq!(history[0].duration_ms, (UPDATE_HISTORY_LIMIT + 4) as u64);
+        assert_eq!(history[0].error, None);
+        assert_eq!(history[1].error.as_deref(), Some("timeout"));
+        assert_eq!(
+            storage.load_update_history(other).await.unwrap(),
+            vec![outcome]
+        );
+
+        storage.delete_subscription(id).await.unwrap();
+        assert!(storage.load_update_history(id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscription_request_options() {
+        let mut storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        storage.enable_encryption_with_key([7; 32]).await.unwrap();
+
+        let subscription = Subscription {
+            id: Uuid::new_v4(),
+            name: "Provider".to_string(),
+            url: "https://example.com/sub".to_string(),
+            last_update: None,
+            server_count: 0,
+            status: SubscriptionStatus::Active,
+            request: SubscriptionRequest {
+                user_agent: crate::subscription::UserAgentPreset::V2rayN,
+                headers: [("X-Token".to_string(), "abc".to_string())].into(),
+                basic_auth: Some(crate::subscription::BasicAuth {
+                    username: "user".to_string(),
+                    password: "hunter2".to_string(),
+                }),
+            },
+        };
+        storage.save_subscription(&subscription).await.unwrap();
+
+        let stored: String = sqlx::query_scalar("SELECT request FROM subscriptions")
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap();
+        assert!(stored.starts_with(ENCRYPTED_PREFIX));
+        assert!(!stored.contains("hunter2"));
+
+        let loaded = storage.load_subscriptions().await.unwrap();
+        assert_eq!(loaded[0].request, subscription.request);
+
+        storage.rotate_key([8; 32]).await.unwrap();
+        let loaded = storage.load_subscriptions().await.unwrap();
+        assert_eq!(loaded[0].request, subscription.request);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_health() {
+        let storage = SubscriptionStorage::new_in_memory().await.unwrap();
+        let id = Uuid::new_v4();
+
+        let mut health = SubscriptionHealth::default();
+        health.record(
+            crate::subscription::HealthCheck {
+                checked_at: chrono::Utc::now(),
+                duration_ms: 120,
+                http_status: Some(404),
+                redirected_to: None,
+                error: Some("HTTP error: 404 Not Found".to_string()),
+                tls_error: false,
+            },
+            1,
+        );
+        storage.save_subscription_health(id, &health).await.unwrap();
+        let loaded = storage.load_subscription_health().await.unwrap();
+        assert_eq!(loaded[&id], health);
+        assert!(loaded[&id].stale);
+
+        storage.delete_subscription(id).await.unwrap();
+        assert!(storage.load_subscription_health().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_unversioned_database() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let db_path = temp_dir.path().join("subscriptions.db");
+
+        // Layout of an early release, without a schema version
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", db_path.display()))
+            .unwrap()
+            .create_if_missing(true);
+        let pool = SqlitePool::connect_with(options).await.unwrap();
+        for statement in [
+            "CREATE TABLE subscriptions (id TEXT PRIMARY KEY, name TEXT NOT NULL, \
+             url TEXT NOT NULL, last_update TEXT, server_count INTEGER NOT NULL DEFAULT 0, \
+             status TEXT NOT NULL, created_at TEXT NOT NULL, updated_at TEXT NOT NULL)",
+            "CREATE TABLE servers (id TEXT PRIMARY KEY, subscription_id TEXT NOT NULL, \
+             name TEXT NOT NULL, address TEXT NOT NULL, port INTEGER NOT NULL, \
+             protocol TEXT NOT NULL, config TEXT NOT NULL, created_at TEXT NOT NULL)",
+            "INSERT INTO subscriptions VALUES ('00000000-0000-0000-0000-000000000001', \
+             'Old', 'https://example.com/sub', NULL, 0, 'active', '', '')",
+        ] {
+            sqlx::query(statement).execute(&pool).await.unwrap();
+        }
+        pool.close().await;
+
+        let storage = SubscriptionStorage::new(&db_path).await.unwrap();
+        assert_eq!(
+            storage.schema_version().await.unwrap(),
+            DATABASE_SCHEMA_VERSION
+        );
+        assert_eq!(storage.load_subscriptions().await.unwrap().len(), 1);
+        let columns: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM pragma_table_info('servers') \
+             WHERE name IN ('stream_settings', 'tags', 'raw_name')",
+        )
+        .fetch_one(&storage.pool)
+        .await
+        .unwrap();
+        assert_eq!(columns, 3);
+        let backup = crate::config::migration::backup_path(&db_path, 0);
+        assert!(backup.exists());
+
+        // A database from a newer release is refused
+        sqlx::query("PRAGMA user_version = 99")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        storage.pool.close().await;
+        assert!(matches!(
+            SubscriptionStorage::new(&db_path).await,
+            Err(StorageError::UnsupportedVersion { found: 99, .. })
+        ));
+    }
 }
@@ -0,0 +1,124 @@
+//! Per-server user data
+//!
+//! Favorites, aliases, hidden flags and pinned positions belong to the user
+//! rather than to the subscription, so they are keyed by a fingerprint of the
+//! server endpoint and credentials instead of the server ID. They survive
+//! subscription refreshes, renames by the provider, and servers being
+//! removed and added again.
+
+use super::Server;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// User data attached to a server
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ServerUserData {
+    /// Marked as favorite
+    #[serde(default)]
+    pub favorite: bool,
+    /// Custom name shown instead of the subscription's name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+    /// Hidden from the server list
+    #[serde(default)]
+    pub hidden: bool,
+    /// Position among pinned servers, which are listed first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pinned_position: Option<u32>,
+}
+
+impl ServerUserData {
+    /// Whether nothing is set, so no row needs to be stored
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Set the alias, treating a blank alias as none
+    pub fn set_alias(&mut self, alias: Option<&str>) {
+        self.alias = alias
+            .map(str::trim)
+            .filter(|alias| !alias.is_empty())
+            .map(str::to_string);
+    }
+}
+
+/// Stable fingerprint of a server
+///
+/// Covers protocol, address, port and the user ID or password, so the name
+/// can change without losing user data while distinct accounts on one host
+/// stay apart. Credentials are hashed so they do not leak into the
+/// unencrypted user data table.
+pub fn server_fingerprint(server: &Server) -> String {
+    let credential = ["id", "password"]
+        .iter()
+        .find_map(|key| server.config.get(*key).and_then(|v| v.as_str()))
+        .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    for part in [
+        server.protocol.as_str(),
+        server.address.to_ascii_lowercase().as_str(),
+        server.port.to_string().as_str(),
+        credential,
+    ] {
+        hasher.update(part.as_bytes());
+        hasher.update([0]);
+    }
+    hex::encode(&hasher.finalize()[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn server(name: &str, address: &str, password: &str) -> Server {
+        Server {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            address: address.to_string(),
+            port: 443,
+            protocol: "trojan".to_string(),
+            config: HashMap::from([("password".to_string(), serde_json::json!(password))]),
+            stream_settings: None,
+            subscription_id: Uuid::new_v4(),
+            tags: vec![],
+            raw_name: None,
+        }
+    }
+
+    #[test]
+    fn test_server_fingerprint() {
+        let fingerprint = server_fingerprint(&server("a", "example.com", "p"));
+        assert_eq!(fingerprint.len(), 32);
+
+        // Renames and other subscriptions keep the fingerprint
+        assert_eq!(
+            server_fingerprint(&server("renamed", "Example.com", "p")),
+            fingerprint
+        );
+        assert_ne!(
+            server_fingerprint(&server("a", "example.com", "q")),
+            fingerprint
+        );
+        assert_ne!(
+            server_fingerprint(&server("a", "example.org", "p")),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn test_user_data() {
+        let mut data = ServerUserData::default();
+        assert!(data.is_empty());
+
+        data.set_alias(Some("  Home  "));
+        assert_eq!(data.alias.as_deref(), Some("Home"));
+        assert!(!data.is_empty());
+
+        data.set_alias(Some(" "));
+        assert_eq!(data.alias, None);
+        assert!(data.is_empty());
+    }
+}
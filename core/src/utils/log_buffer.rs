@@ -0,0 +1,321 @@
+//! In-memory log buffer
+//!
+//! Keeps the most recent core and Xray log records so they can be shown in
+//! the UI and attached to bug reports without reading log files. Core
+//! records are captured by [`BufferLayer`]; Xray output is added by the
+//! process reader through [`record`]. Records are also broadcast to
+//! [`subscribe`]rs as they come in, for live log viewers.
+
+use super::logger::LogLevel;
+use super::redact;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Records kept by default
+pub const DEFAULT_CAPACITY: usize = 2000;
+
+/// Records queued per live subscriber before it lags
+const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+lazy_static::lazy_static! {
+    static ref LOG_BUFFER: Mutex<LogBuffer> = Mutex::new(LogBuffer::new(DEFAULT_CAPACITY));
+    static ref LIVE_LOGS: broadcast::Sender<LogRecord> = broadcast::channel(LIVE_CHANNEL_CAPACITY).0;
+}
+
+/// Origin of a log record
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LogSource {
+    /// V8Ray core
+    Core,
+    /// Xray process output
+    Xray,
+}
+
+impl std::fmt::Display for LogSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogSource::Core => write!(f, "core"),
+            LogSource::Xray => write!(f, "xray"),
+        }
+    }
+}
+
+/// Buffered log record
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+    /// Time the record was logged
+    pub timestamp: DateTime<Utc>,
+    /// Origin
+    pub source: LogSource,
+    /// Level
+    pub level: LogLevel,
+    /// Module path for core records, `xray` for Xray output
+    pub target: String,
+    /// Message, followed by any other fields as `key=value`
+    pub message: String,
+}
+
+/// Filter for [`LogBuffer::query`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogQuery {
+    /// Only records from this source
+    pub source: Option<LogSource>,
+    /// Only records at least this severe
+    pub min_level: Option<LogLevel>,
+    /// Only records whose message contains this text (case-insensitive)
+    pub contains: Option<String>,
+    /// Most records returned; the newest are kept
+    pub limit: Option<usize>,
+}
+
+impl LogQuery {
+    /// Whether `record` passes the filter
+    fn matches(&self, record: &LogRecord) -> bool {
+        self.source.is_none_or(|source| record.source == source)
+            && self.min_level.is_none_or(|level| record.level >= level)
+            && self
+                .contains
+                .as_ref()
+                .is_none_or(|text| record.message.to_lowercase().contains(&text.to_lowercase()))
+    }
+}
+
+/// Ring buffer of log records
+#[derive(Debug)]
+pub struct LogBuffer {
+    /// Records, oldest first
+    records: VecDeque<LogRecord>,
+    /// Most records kept
+    capacity: usize,
+}
+
+impl LogBuffer {
+    /// Create a buffer keeping the latest `capacity` records
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            records: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Add a record, dropping the oldest if the buffer is full
+    pub fn push(&mut self, record: LogRecord) {
+        self.records.push_back(record);
+        self.trim();
+    }
+
+    /// Change the capacity, dropping the oldest records if needed
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.trim();
+    }
+
+    /// Records passing `query`, oldest first
+    pub fn query(&self, query: &LogQuery) -> Vec<LogRecord> {
+        let mut records: Vec<LogRecord> = self
+            .records
+            .iter()
+            .rev()
+            .filter(|record| query.matches(record))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        records.reverse();
+        records
+    }
+
+    /// Forget all records
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    /// Drop the oldest records beyond the capacity
+    fn trim(&mut self) {
+        while self.records.len() > self.capacity {
+            self.records.pop_front();
+        }
+    }
+}
+
+/// Add a record to the global buffer and send it to live subscribers
+///
+/// Secrets in the message are masked unless redaction is off.
+pub fn record(source: LogSource, level: LogLevel, target: &str, message: &str) {
+    let record = LogRecord {
+        timestamp: Utc::now(),
+        source,
+        level,
+        target: target.to_string(),
+        message: redact::redact_log(message).into_owned(),
+    };
+    if LIVE_LOGS.receiver_count() > 0 {
+        let _ = LIVE_LOGS.send(record.clone());
+    }
+    LOG_BUFFER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(record);
+}
+
+/// Receive records as they are added to the global buffer
+pub fn subscribe() -> broadcast::Receiver<LogRecord> {
+    LIVE_LOGS.subscribe()
+}
+
+/// Records in the global buffer passing `query`, oldest first
+pub fn recent_logs(query: &LogQuery) -> Vec<LogRecord> {
+    LOG_BUFFER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .query(query)
+}
+
+/// Change how many records the global buffer keeps
+pub fn set_capacity(capacity: usize) {
+    LOG_BUFFER
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .set_capacity(capacity);
+}
+
+/// Forget all records in the global buffer
+pub fn clear() {
+    LOG_BUFFER.lock().unwrap_or_else(|e| e.into_inner()).clear();
+}
+
+/// Tracing layer adding core events to the global buffer
+#[derive(Debug, Default)]
+pub struct BufferLayer;
+
+impl<S: Subscriber> Layer<S> for BufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let metadata = event.metadata();
+        record(
+            LogSource::Core,
+            LogLevel::from(*metadata.level()),
+            metadata.target(),
+            &visitor.message,
+        );
+    }
+}
+
+/// Collects the message and other fields of an event
+#[derive(Default)]
+struct MessageVisitor {
+    /// Message followed by `key=value` fields
+    message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.insert_str(0, value);
+        } else {
+            let _ = write!(self.message, " {}={}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message.insert_str(0, &format!("{:?}", value));
+        } else {
+            let _ = write!(self.message, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_at(level: LogLevel, source: LogSource, message: &str) -> LogRecord {
+        LogRecord {
+            timestamp: Utc::now(),
+            source,
+            level,
+            target: "test".to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_buffer_query() {
+        let mut buffer = LogBuffer::new(3);
+        buffer.push(record_at(LogLevel::Info, LogSource::Core, "dropped"));
+        buffer.push(record_at(LogLevel::Debug, LogSource::Core, "core debug"));
+        buffer.push(record_at(LogLevel::Warn, LogSource::Xray, "xray warning"));
+        buffer.push(record_at(LogLevel::Error, LogSource::Core, "core error"));
+
+        let all = buffer.query(&LogQuery::default());
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0].message, "core debug");
+
+        let warnings = buffer.query(&LogQuery {
+            min_level: Some(LogLevel::Warn),
+            ..Default::default()
+        });
+        assert_eq!(warnings.len(), 2);
+
+        let xray = buffer.query(&LogQuery {
+            source: Some(LogSource::Xray),
+            ..Default::default()
+        });
+        assert_eq!(xray[0].message, "xray warning");
+
+        let latest = buffer.query(&LogQuery {
+            limit: Some(1),
+            ..Default::default()
+        });
+        assert_eq!(latest[0].message, "core error");
+
+        let found = buffer.query(&LogQuery {
+            contains: Some("CORE".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(found.len(), 2);
+
+        buffer.set_capacity(1);
+        assert_eq!(buffer.query(&LogQuery::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let mut rx = subscribe();
+        record(LogSource::Core, LogLevel::Info, "test", "live log test");
+        loop {
+            let record = rx.try_recv().unwrap();
+            if record.message == "live log test" {
+                assert_eq!(record.source, LogSource::Core);
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_buffer_layer() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let subscriber = tracing_subscriber::registry().with(BufferLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(server = "a", "log buffer layer test");
+        });
+
+        let records = recent_logs(&LogQuery {
+            contains: Some("log buffer layer test".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].level, LogLevel::Warn);
+        assert_eq!(records[0].source, LogSource::Core);
+        assert_eq!(records[0].message, "log buffer layer test server=a");
+    }
+}
@@ -1,17 +1,49 @@
 //! Logging utilities for V8Ray Core
 //!
 //! This module provides logging functionality using the `tracing` crate.
-//! It supports multiple log levels and file output.
+//! It supports multiple log levels, rotated file output, changing the level
+//! at runtime, an in-memory buffer of recent records (see
+//! [`super::log_buffer`]) and exporting everything as a bug report bundle.
 
-use anyhow::Result;
+use super::log_buffer::{self, BufferLayer, LogQuery, LogSource};
+use super::redact::{self, RedactingWriter};
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 use tracing::Level;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
+use tracing_subscriber::{
+    filter::filter_fn, fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter,
+    Layer, Registry,
+};
+
+/// Default number of rotated log files kept
+pub const DEFAULT_MAX_FILES: usize = 5;
+
+/// Default age after which log files are deleted
+pub const DEFAULT_MAX_AGE_DAYS: u32 = 7;
+
+/// Most bytes of each log file included in an exported bundle
+const EXPORT_FILE_LIMIT: u64 = 1024 * 1024;
+
+/// Handle changing the level filter of the global subscriber
+static LEVEL_HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+lazy_static::lazy_static! {
+    /// Level set through [`init_logger`] or [`set_log_level`]
+    static ref CURRENT_LEVEL: Mutex<Option<LogLevel>> = Mutex::new(None);
+    /// Open log file, if file output is enabled
+    static ref LOG_FILE: Mutex<Option<LogFile>> = Mutex::new(None);
+}
 
 /// Log level configuration
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+///
+/// Levels are ordered by severity, so `Error` is the greatest.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum LogLevel {
     /// Trace level - most verbose
     Trace,
@@ -37,6 +69,34 @@ impl From<LogLevel> for Level {
     }
 }
 
+impl From<Level> for LogLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::TRACE => LogLevel::Trace,
+            Level::DEBUG => LogLevel::Debug,
+            Level::INFO => LogLevel::Info,
+            Level::WARN => LogLevel::Warn,
+            _ => LogLevel::Error,
+        }
+    }
+}
+
+impl std::str::FromStr for LogLevel {
+    type Err = anyhow::Error;
+
+    /// Parse a level name case-insensitively; accepts Xray's `warning`
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "trace" => Ok(LogLevel::Trace),
+            "debug" => Ok(LogLevel::Debug),
+            "info" => Ok(LogLevel::Info),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "error" => Ok(LogLevel::Error),
+            _ => Err(anyhow!("Unknown log level: {}", s)),
+        }
+    }
+}
+
 impl std::fmt::Display for LogLevel {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -58,12 +118,40 @@ pub struct LogConfig {
     pub console: bool,
     /// Enable file output
     pub file: bool,
-    /// Log file directory
+    /// Log file directory (default: the log directory of [`crate::paths`])
     pub file_dir: Option<PathBuf>,
     /// Log file name prefix
     pub file_prefix: String,
     /// Log file rotation
     pub rotation: LogRotation,
+    /// Rotated log files kept, including the current one
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+    /// Log files older than this many days are deleted when logging starts
+    #[serde(default = "default_max_age_days")]
+    pub max_age_days: Option<u32>,
+    /// Records kept in the in-memory buffer
+    #[serde(default = "default_buffer_capacity")]
+    pub buffer_capacity: usize,
+    /// Mask credentials (user IDs, passwords, share links) in log output
+    #[serde(default = "default_redact_secrets")]
+    pub redact_secrets: bool,
+}
+
+fn default_max_files() -> usize {
+    DEFAULT_MAX_FILES
+}
+
+fn default_max_age_days() -> Option<u32> {
+    Some(DEFAULT_MAX_AGE_DAYS)
+}
+
+fn default_buffer_capacity() -> usize {
+    log_buffer::DEFAULT_CAPACITY
+}
+
+fn default_redact_secrets() -> bool {
+    true
 }
 
 /// Log file rotation strategy
@@ -85,7 +173,8 @@ impl From<LogRotation> for Rotation {
             LogRotation::Never => Rotation::NEVER,
             LogRotation::Daily => Rotation::DAILY,
             LogRotation::Hourly => Rotation::HOURLY,
-            LogRotation::Size(_) => Rotation::DAILY, // tracing_appender doesn't support size-based rotation
+            // tracing_appender doesn't support size-based rotation, see SizeRotatingFile
+            LogRotation::Size(_) => Rotation::NEVER,
         }
     }
 }
@@ -99,61 +188,383 @@ impl Default for LogConfig {
             file_dir: None,
             file_prefix: "v8ray".to_string(),
             rotation: LogRotation::Daily,
+            max_files: DEFAULT_MAX_FILES,
+            max_age_days: Some(DEFAULT_MAX_AGE_DAYS),
+            buffer_capacity: log_buffer::DEFAULT_CAPACITY,
+            redact_secrets: true,
         }
     }
 }
 
 /// Initialize the logger with the given configuration
+///
+/// Console output, file output and the in-memory buffer are installed as
+/// separate layers; the level filter and the log file can be changed
+/// afterwards with [`set_log_level`] and [`open_log_file`].
 pub fn init_logger(config: &LogConfig) -> Result<()> {
     let filter = EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| EnvFilter::new(config.level.to_string()));
+    let (filter, handle) = reload::Layer::new(filter);
 
-    let registry = tracing_subscriber::registry().with(filter);
-
-    if config.console && config.file {
-        // Both console and file output
-        let console_layer = fmt::layer().with_writer(std::io::stdout);
+    // No output configured (shouldn't happen): default to console
+    let console = config.console || !config.file;
+    let console_layer =
+        console.then(|| fmt::layer().with_writer(|| RedactingWriter(std::io::stdout())));
+    let file_layer = fmt::layer()
+        .with_writer(|| RedactingWriter(FileSink))
+        .with_ansi(false)
+        .with_filter(filter_fn(|_| file_output_enabled()));
 
+    log_buffer::set_capacity(config.buffer_capacity);
+    redact::set_enabled(config.redact_secrets);
+    if config.file {
         let file_dir = config
             .file_dir
             .clone()
-            .unwrap_or_else(|| PathBuf::from("logs"));
+            .unwrap_or_else(|| crate::paths::app_paths().log_dir);
+        open_log_file(&file_dir, config)?;
+    }
 
-        std::fs::create_dir_all(&file_dir)?;
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(console_layer)
+        .with(file_layer)
+        .with(BufferLayer)
+        .try_init()?;
 
-        let file_appender =
-            RollingFileAppender::new(config.rotation.into(), file_dir, &config.file_prefix);
-        let file_layer = fmt::layer().with_writer(file_appender).with_ansi(false);
+    let _ = LEVEL_HANDLE.set(handle);
+    *CURRENT_LEVEL.lock().unwrap_or_else(|e| e.into_inner()) = Some(config.level);
+    tracing::info!("Logger initialized with level: {}", config.level);
+    Ok(())
+}
 
-        registry.with(console_layer).with(file_layer).init();
-    } else if config.console {
-        // Console output only
-        let console_layer = fmt::layer().with_writer(std::io::stdout);
-        registry.with(console_layer).init();
-    } else if config.file {
-        // File output only
-        let file_dir = config
-            .file_dir
-            .clone()
-            .unwrap_or_else(|| PathBuf::from("logs"));
+/// Change the level of the global logger
+///
+/// Overrides any filter taken from `RUST_LOG`. Fails if the logger was not
+/// initialized through [`init_logger`].
+pub fn set_log_level(level: LogLevel) -> Result<()> {
+    let handle = LEVEL_HANDLE
+        .get()
+        .ok_or_else(|| anyhow!("Logger not initialized"))?;
+    handle.reload(EnvFilter::new(level.to_string()))?;
+    *CURRENT_LEVEL.lock().unwrap_or_else(|e| e.into_inner()) = Some(level);
+    tracing::info!("Log level changed to {}", level);
+    Ok(())
+}
+
+/// Current level of the global logger, if initialized
+pub fn log_level() -> Option<LogLevel> {
+    *CURRENT_LEVEL.lock().unwrap_or_else(|e| e.into_inner())
+}
 
-        std::fs::create_dir_all(&file_dir)?;
+/// Open log file with its location
+struct LogFile {
+    /// Directory holding the log files
+    dir: PathBuf,
+    /// File name prefix
+    prefix: String,
+    /// Rotating writer
+    writer: Box<dyn Write + Send>,
+}
 
-        let file_appender =
-            RollingFileAppender::new(config.rotation.into(), file_dir, &config.file_prefix);
-        let file_layer = fmt::layer().with_writer(file_appender).with_ansi(false);
+/// Writer forwarding to the open log file, if any
+struct FileSink;
 
-        registry.with(file_layer).init();
-    } else {
-        // No output (shouldn't happen, but default to console)
-        let console_layer = fmt::layer().with_writer(std::io::stdout);
-        registry.with(console_layer).init();
+impl Write for FileSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            Some(file) => file.writer.write(buf),
+            None => Ok(buf.len()),
+        }
     }
 
-    tracing::info!("Logger initialized with level: {}", config.level);
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            Some(file) => file.writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a log file is open
+fn file_output_enabled() -> bool {
+    LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).is_some()
+}
+
+/// Start writing logs to files in `dir`, replacing any open log file
+///
+/// Uses the prefix, rotation and retention of `config`. Files older than
+/// `max_age_days` are deleted first.
+pub fn open_log_file(dir: &Path, config: &LogConfig) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    if let Some(days) = config.max_age_days {
+        prune_log_files(
+            dir,
+            &config.file_prefix,
+            Duration::from_secs(u64::from(days) * 24 * 60 * 60),
+        )?;
+    }
+
+    let max_files = config.max_files.max(1);
+    let writer: Box<dyn Write + Send> = match config.rotation {
+        LogRotation::Size(max_bytes) => Box::new(SizeRotatingFile::open(
+            dir,
+            &config.file_prefix,
+            max_bytes,
+            max_files,
+        )?),
+        rotation => Box::new(
+            RollingFileAppender::builder()
+                .rotation(rotation.into())
+                .filename_prefix(&config.file_prefix)
+                .filename_suffix("log")
+                .max_log_files(max_files)
+                .build(dir)?,
+        ),
+    };
+
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(LogFile {
+        dir: dir.to_path_buf(),
+        prefix: config.file_prefix.clone(),
+        writer,
+    });
+    Ok(())
+}
+
+/// Stop writing logs to files
+pub fn close_log_file() {
+    if let Some(mut file) = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).take() {
+        let _ = file.writer.flush();
+    }
+}
+
+/// Log file rotated when it grows beyond a size
+///
+/// The current file is `<prefix>.log`; on rotation it becomes
+/// `<prefix>.log.1`, older files shift up and the oldest beyond
+/// `max_files` is deleted.
+struct SizeRotatingFile {
+    /// Path of the current file
+    path: PathBuf,
+    /// Current file
+    file: File,
+    /// Bytes in the current file
+    size: u64,
+    /// Size at which the file is rotated
+    max_bytes: u64,
+    /// Files kept, including the current one
+    max_files: usize,
+}
+
+impl SizeRotatingFile {
+    /// Open or create `<prefix>.log` in `dir`
+    fn open(dir: &Path, prefix: &str, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        let path = dir.join(format!("{}.log", prefix));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_bytes,
+            max_files,
+        })
+    }
+
+    /// Path of the `index`-th rotated file
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    /// Shift the rotated files and start a new current file
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let oldest = self.max_files.saturating_sub(1);
+        if oldest == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(oldest));
+            for index in (1..oldest).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(&from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Log files with `prefix` in `dir`, newest first
+///
+/// Matches `<prefix>.log`, `<prefix>.log.N` and `<prefix>.<date>.log`.
+pub fn list_log_files(dir: &Path, prefix: &str) -> io::Result<Vec<PathBuf>> {
+    let prefix = format!("{}.", prefix);
+    let mut files: Vec<(SystemTime, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with(&prefix) && name.contains(".log")
+        })
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            Some((metadata.modified().ok()?, entry.path()))
+        })
+        .collect();
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    Ok(files.into_iter().map(|(_, path)| path).collect())
+}
+
+/// Total size of the log files being written to, 0 without file output
+pub fn log_files_size() -> io::Result<u64> {
+    let location = LOG_FILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|file| (file.dir.clone(), file.prefix.clone()));
+    let Some((dir, prefix)) = location else {
+        return Ok(0);
+    };
+    Ok(list_log_files(&dir, &prefix)?
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum())
+}
+
+/// Delete log files with `prefix` in `dir` not modified within `max_age`
+fn prune_log_files(dir: &Path, prefix: &str, max_age: Duration) -> io::Result<()> {
+    let now = SystemTime::now();
+    for path in list_log_files(dir, prefix)? {
+        let age = fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        if age.is_some_and(|age| age > max_age) {
+            let _ = fs::remove_file(&path);
+        }
+    }
     Ok(())
 }
 
+/// Write a bug report bundle to `path`
+///
+/// The bundle is a single text file with platform information, the recent
+/// core and Xray records from the in-memory buffer and the tail of each
+/// log file, if file output is enabled. Secrets are always masked, even if
+/// redaction of log output is off.
+pub fn export_logs(path: &Path) -> Result<()> {
+    export_logs_with(path, &[])
+}
+
+/// Write a bug report bundle with extra `(title, content)` sections to `path`
+///
+/// The sections, e.g. the running Xray configuration, are appended after
+/// the logs and redacted like the rest of the bundle.
+pub fn export_logs_with(path: &Path, sections: &[(String, String)]) -> Result<()> {
+    if let Some(file) = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        let _ = file.writer.flush();
+    }
+
+    let mut out = String::new();
+    out.push_str("# V8Ray log bundle\n\n");
+    out.push_str("## Platform\n\n");
+    out.push_str(&format!(
+        "generated: {}\n",
+        chrono::Local::now().to_rfc3339()
+    ));
+    let identity = crate::version::AppIdentity::current();
+    out.push_str(&format!("app: {} {}\n", identity.name, identity.version));
+    out.push_str(&format!(
+        "os: {} ({})\n",
+        identity.os,
+        std::env::consts::FAMILY
+    ));
+    out.push_str(&format!("arch: {}\n", identity.arch));
+    out.push_str(&format!(
+        "log level: {}\n",
+        log_level().map_or("not initialized".to_string(), |l| l.to_string())
+    ));
+
+    for source in [LogSource::Core, LogSource::Xray] {
+        out.push_str(&format!("\n## Recent {} log\n\n", source));
+        let records = log_buffer::recent_logs(&LogQuery {
+            source: Some(source),
+            ..Default::default()
+        });
+        for record in records {
+            out.push_str(&format!(
+                "{} {:>5} {}: {}\n",
+                record.timestamp.to_rfc3339(),
+                record.level.to_string().to_uppercase(),
+                record.target,
+                record.message
+            ));
+        }
+    }
+
+    let location = LOG_FILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|file| (file.dir.clone(), file.prefix.clone()));
+    if let Some((dir, prefix)) = location {
+        for file in list_log_files(&dir, &prefix)? {
+            out.push_str(&format!("\n## {}\n\n", file.display()));
+            out.push_str(&read_tail(&file, EXPORT_FILE_LIMIT)?);
+        }
+    }
+
+    for (title, content) in sections {
+        out.push_str(&format!("\n## {}\n\n{}\n", title, content));
+    }
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    // Log files written with redaction off may still hold secrets
+    fs::write(path, redact::redact_text(&out).as_bytes())?;
+    Ok(())
+}
+
+/// Last `limit` bytes of a file, as lossy UTF-8
+fn read_tail(path: &Path, limit: u64) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len > limit {
+        file.seek(SeekFrom::Start(len - limit))?;
+    }
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
 /// Initialize a simple logger with default settings
 pub fn init_simple_logger() -> Result<()> {
     init_logger(&LogConfig::default())
@@ -199,4 +610,63 @@ mod tests {
         let rotation: Rotation = LogRotation::Daily.into();
         assert_eq!(rotation, Rotation::DAILY);
     }
+
+    #[test]
+    fn test_log_level_order_and_parse() {
+        assert!(LogLevel::Error > LogLevel::Warn);
+        assert!(LogLevel::Trace < LogLevel::Debug);
+        assert_eq!("Warning".parse::<LogLevel>().unwrap(), LogLevel::Warn);
+        assert_eq!("INFO".parse::<LogLevel>().unwrap(), LogLevel::Info);
+        assert!("verbose".parse::<LogLevel>().is_err());
+        assert_eq!(LogLevel::from(Level::WARN), LogLevel::Warn);
+    }
+
+    #[test]
+    fn test_size_rotation() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut file = SizeRotatingFile::open(dir.path(), "test", 10, 3).unwrap();
+        for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        file.flush().unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.path().join(name)).unwrap();
+        assert_eq!(read("test.log"), "dddddddd\n");
+        assert_eq!(read("test.log.1"), "cccccccc\n");
+        assert_eq!(read("test.log.2"), "bbbbbbbb\n");
+        assert!(!dir.path().join("test.log.3").exists());
+        assert_eq!(list_log_files(dir.path(), "test").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_export_logs() {
+        let dir = tempfile::TempDir::new().unwrap();
+        log_buffer::record(LogSource::Xray, LogLevel::Warn, "xray", "export test entry");
+        let path = dir.path().join("bundle").join("logs.txt");
+        export_logs(&path).unwrap();
+
+        let bundle = fs::read_to_string(path).unwrap();
+        assert!(bundle.contains("## Platform"));
+        assert!(bundle.contains(std::env::consts::OS));
+        assert!(bundle.contains("## Recent xray log"));
+        assert!(bundle.contains("export test entry"));
+    }
+
+    #[test]
+    fn test_export_logs_redacts_secrets() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("logs.txt");
+        let config =
+            r#"{"users": [{"id": "b831381d-6324-4d53-ad4f-8cda48b30811"}], "password": "hunter2"}"#;
+        export_logs_with(
+            &path,
+            &[("Xray configuration".to_string(), config.to_string())],
+        )
+        .unwrap();
+
+        let bundle = fs::read_to_string(path).unwrap();
+        assert!(bundle.contains("## Xray configuration"));
+        assert!(!bundle.contains("b831381d-6324-4d53-ad4f-8cda48b30811"));
+        assert!(!bundle.contains("hunter2"));
+    }
 }
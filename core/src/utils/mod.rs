@@ -4,8 +4,13 @@
 //! the V8Ray core library.
 
 pub mod crypto;
+pub mod log_buffer;
 pub mod logger;
+pub mod names;
 pub mod network;
+pub mod ports;
+pub mod preflight;
+pub mod redact;
 
 pub use crypto::{decrypt_aes256, encrypt_aes256};
 pub use logger::{init_logger, LogConfig, LogLevel};
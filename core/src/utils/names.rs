@@ -0,0 +1,125 @@
+//! Server name normalization
+//!
+//! Provider node names often carry emoji flags, bidi marks, control
+//! characters or hundreds of characters of marketing text. Names are
+//! normalized when parsed so sorting, grouping and storage see a clean
+//! string, and shortened with an ellipsis only for display.
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Maximum length of a normalized name, in grapheme clusters
+pub const MAX_NAME_LENGTH: usize = 256;
+
+/// Maximum length of a display name, in grapheme clusters
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 48;
+
+/// Ellipsis appended to shortened names
+const ELLIPSIS: char = '…';
+
+/// Invisible formatting characters removed from names
+///
+/// Zero-width joiners are kept since emoji sequences and some scripts
+/// depend on them.
+fn is_invisible_format(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}' // soft hyphen
+            | '\u{061C}' // Arabic letter mark
+            | '\u{200B}' // zero-width space
+            | '\u{200E}'..='\u{200F}' // LRM, RLM
+            | '\u{202A}'..='\u{202E}' // bidi embeddings and overrides
+            | '\u{2060}' // word joiner
+            | '\u{2066}'..='\u{2069}' // bidi isolates
+            | '\u{FEFF}' // byte order mark
+    )
+}
+
+/// Normalize a server name
+///
+/// Applies NFC, drops control and invisible bidi/formatting characters,
+/// collapses whitespace and caps the length at [`MAX_NAME_LENGTH`].
+/// Returns an empty string if nothing printable is left.
+pub fn normalize_name(raw: &str) -> String {
+    let cleaned: String = raw
+        .nfc()
+        .filter(|c| !is_invisible_format(*c))
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+
+    let collapsed = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    truncate_name(&collapsed, MAX_NAME_LENGTH)
+}
+
+/// Shorten a name to at most `max_length` grapheme clusters, ending it with
+/// an ellipsis if anything was cut
+///
+/// Cuts on grapheme boundaries, so flags, emoji sequences and combining
+/// characters are never split.
+pub fn truncate_name(name: &str, max_length: usize) -> String {
+    let mut graphemes = name.grapheme_indices(true);
+    let Some((cut, _)) = graphemes.nth(max_length.saturating_sub(1)) else {
+        return name.to_string();
+    };
+    if graphemes.next().is_none() {
+        return name.to_string();
+    }
+
+    let mut shortened = name[..cut].trim_end().to_string();
+    shortened.push(ELLIPSIS);
+    shortened
+}
+
+/// Display form of a name, capped at [`MAX_DISPLAY_NAME_LENGTH`]
+pub fn display_name(name: &str) -> String {
+    truncate_name(name, MAX_DISPLAY_NAME_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_name() {
+        // Decomposed accents are composed
+        assert_eq!(normalize_name("Cafe\u{0301} Paris"), "Caf\u{00E9} Paris");
+        // Bidi marks, BOM and control characters are dropped
+        assert_eq!(
+            normalize_name("\u{FEFF}\u{200F}\u{202B}مرحبا\u{202C} 01\t|\n VIP\u{0007}"),
+            "مرحبا 01 | VIP"
+        );
+        // Emoji flags and ZWJ sequences survive untouched
+        assert_eq!(
+            normalize_name("🇭🇰 香港 01 👨\u{200D}👩\u{200D}👧 倍率1.0x"),
+            "🇭🇰 香港 01 👨\u{200D}👩\u{200D}👧 倍率1.0x"
+        );
+        assert_eq!(normalize_name(" \u{200B}\u{200E} "), "");
+
+        let long = "剩余流量：100GB ".repeat(40);
+        let normalized = normalize_name(&long);
+        assert!(normalized.graphemes(true).count() <= MAX_NAME_LENGTH);
+        assert!(normalized.ends_with(ELLIPSIS));
+        assert_eq!(normalize_name(&normalized), normalized);
+    }
+
+    #[test]
+    fn test_display_name() {
+        let short = "🇯🇵 Tokyo 02";
+        assert_eq!(display_name(short), short);
+
+        let exact = "a".repeat(MAX_DISPLAY_NAME_LENGTH);
+        assert_eq!(display_name(&exact), exact);
+
+        // Never split a flag (two regional indicators) or a ZWJ sequence
+        let flags = "🇺🇸".repeat(MAX_DISPLAY_NAME_LENGTH + 5);
+        let shown = display_name(&flags);
+        assert_eq!(shown.graphemes(true).count(), MAX_DISPLAY_NAME_LENGTH);
+        assert_eq!(shown.trim_end_matches(ELLIPSIS).chars().count() % 2, 0);
+
+        let family = "👨\u{200D}👩\u{200D}👧".repeat(MAX_DISPLAY_NAME_LENGTH + 1);
+        let shown = display_name(&family);
+        assert!(shown
+            .trim_end_matches(ELLIPSIS)
+            .ends_with("👨\u{200D}👩\u{200D}👧"));
+    }
+}
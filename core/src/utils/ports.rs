@@ -0,0 +1,92 @@
+//! Local port availability checks
+//!
+//! Used before starting Xray so that a port already taken by another
+//! program is reported (or worked around) up front, instead of Xray exiting
+//! with a bind error right after start.
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener};
+
+/// Resolve a listen address, falling back to localhost for empty or
+/// unparsable values
+fn listen_ip(listen: Option<&str>) -> IpAddr {
+    listen
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST))
+}
+
+/// Check whether `port` can be bound on `listen`
+pub fn is_port_available(listen: Option<&str>, port: u16) -> bool {
+    TcpListener::bind(SocketAddr::new(listen_ip(listen), port)).is_ok()
+}
+
+/// Ask the OS for a currently free port on `listen`
+pub fn find_free_port(listen: Option<&str>) -> std::io::Result<u16> {
+    let listener = TcpListener::bind(SocketAddr::new(listen_ip(listen), 0))?;
+    Ok(listener.local_addr()?.port())
+}
+
+/// Find the PID of the process listening on TCP `port`, if it can be determined
+#[cfg(unix)]
+pub async fn port_owner_pid(port: u16) -> Option<u32> {
+    let output = tokio::process::Command::new("lsof")
+        .args(["-nP", "-t", "-sTCP:LISTEN"])
+        .arg(format!("-iTCP:{}", port))
+        .output()
+        .await
+        .ok()?;
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().parse().ok())
+}
+
+/// Find the PID of the process listening on TCP `port`, if it can be determined
+#[cfg(windows)]
+pub async fn port_owner_pid(port: u16) -> Option<u32> {
+    let mut cmd = tokio::process::Command::new("netstat");
+    cmd.args(["-ano", "-p", "TCP"]);
+
+    // On Windows, hide the console window
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let output = cmd.output().await.ok()?;
+    let suffix = format!(":{}", port);
+
+    // Proto  Local Address  Foreign Address  State  PID
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            let columns: Vec<&str> = line.split_whitespace().collect();
+            match columns.as_slice() {
+                [_, local, _, "LISTENING", pid] if local.ends_with(&suffix) => pid.parse().ok(),
+                _ => None,
+            }
+        })
+}
+
+/// Find the PID of the process listening on TCP `port`, if it can be determined
+#[cfg(not(any(unix, windows)))]
+pub async fn port_owner_pid(_port: u16) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_availability() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert!(!is_port_available(Some("127.0.0.1"), port));
+
+        drop(listener);
+        assert!(is_port_available(None, port));
+
+        let free = find_free_port(Some("127.0.0.1")).unwrap();
+        assert_ne!(free, 0);
+    }
+}
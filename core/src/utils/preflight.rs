@@ -0,0 +1,136 @@
+//! Disk-space and permission preflight checks
+//!
+//! These checks run before downloads and file writes so that full disks and
+//! read-only directories are reported up front with an actionable error,
+//! instead of failing halfway through an operation.
+
+use crate::error::{StorageError, StorageResult};
+use std::path::Path;
+
+/// Name of the probe file created by [`check_writable`]
+const WRITE_PROBE_FILE: &str = ".v8ray_write_probe";
+
+/// Get available disk space in bytes for the filesystem containing `path`
+///
+/// If `path` does not exist yet, its nearest existing ancestor is used.
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+    platform_available_space(existing)
+}
+
+#[cfg(unix)]
+fn platform_available_space(path: &Path) -> std::io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: c_path is a valid NUL-terminated string and stat is a valid out pointer
+    let ret = unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) };
+    if ret != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn platform_available_space(path: &Path) -> std::io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::fileapi::GetDiskFreeSpaceExW;
+    use winapi::um::winnt::ULARGE_INTEGER;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+    let mut available: ULARGE_INTEGER = unsafe { std::mem::zeroed() };
+    // SAFETY: wide is NUL-terminated and available is a valid out pointer
+    let ret = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+        )
+    };
+    if ret == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(unsafe { *available.QuadPart() })
+}
+
+#[cfg(not(any(unix, windows)))]
+fn platform_available_space(_path: &Path) -> std::io::Result<u64> {
+    Ok(u64::MAX)
+}
+
+/// Check that `dir` exists (creating it if needed) and is writable
+pub fn check_writable(dir: &Path) -> StorageResult<()> {
+    let not_writable = |e: std::io::Error| StorageError::NotWritable {
+        path: dir.display().to_string(),
+        reason: e.to_string(),
+    };
+
+    std::fs::create_dir_all(dir).map_err(not_writable)?;
+
+    let probe = dir.join(WRITE_PROBE_FILE);
+    std::fs::write(&probe, b"").map_err(not_writable)?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
+/// Check that the filesystem containing `dir` has at least `required` bytes free
+pub fn check_free_space(dir: &Path, required: u64) -> StorageResult<()> {
+    let available = available_space(dir)?;
+    if available < required {
+        return Err(StorageError::InsufficientSpace {
+            path: dir.display().to_string(),
+            required,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Run both the permission and free-space checks for `dir`
+pub fn preflight(dir: &Path, required: u64) -> StorageResult<()> {
+    check_writable(dir)?;
+    check_free_space(dir, required)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_preflight_ok() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("nested");
+
+        preflight(&dir, 1).unwrap();
+        assert!(dir.exists());
+        assert!(!dir.join(WRITE_PROBE_FILE).exists());
+    }
+
+    #[test]
+    fn test_insufficient_space() {
+        let temp_dir = TempDir::new().unwrap();
+
+        let err = check_free_space(temp_dir.path(), u64::MAX).unwrap_err();
+        assert!(matches!(err, StorageError::InsufficientSpace { .. }));
+    }
+
+    #[test]
+    fn test_available_space_missing_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let missing = temp_dir.path().join("does/not/exist");
+
+        assert!(available_space(&missing).unwrap() > 0);
+    }
+}
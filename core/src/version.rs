@@ -1,7 +1,11 @@
 //! Version information for V8Ray Core
 //!
-//! This module provides centralized version information that is used
-//! throughout the application, including HTTP User-Agent strings.
+//! This module provides centralized version information and app identity
+//! that is used throughout the application, including the User-Agent sent
+//! with every outbound HTTP request.
+
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
 
 /// Application version (from Cargo.toml)
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -9,9 +13,83 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// Application name
 pub const APP_NAME: &str = "V8Ray";
 
+lazy_static::lazy_static! {
+    /// User-Agent configured by the user, replacing the default one
+    static ref USER_AGENT_OVERRIDE: RwLock<Option<String>> = RwLock::new(None);
+}
+
+/// Application identity reported to remote servers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppIdentity {
+    /// Application name
+    pub name: String,
+    /// Application version
+    pub version: String,
+    /// Operating system
+    pub os: String,
+    /// CPU architecture
+    pub arch: String,
+}
+
+impl AppIdentity {
+    /// Identity of the running build
+    pub fn current() -> Self {
+        Self {
+            name: APP_NAME.to_string(),
+            version: VERSION.to_string(),
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// Default User-Agent, e.g. `V8Ray/0.2.1 (linux; x86_64)`
+    pub fn user_agent(&self) -> String {
+        format!(
+            "{}/{} ({}; {})",
+            self.name, self.version, self.os, self.arch
+        )
+    }
+}
+
 /// User-Agent string for HTTP requests
+///
+/// Returns the configured override if set, otherwise the default built from
+/// [`AppIdentity::current`].
 pub fn user_agent() -> String {
-    format!("{}/{}", APP_NAME, VERSION)
+    let configured = USER_AGENT_OVERRIDE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    resolve_user_agent(configured.as_deref())
+}
+
+/// Override the User-Agent for subsequent HTTP requests
+///
+/// `None` or a blank value restores the default.
+pub fn set_user_agent(user_agent: Option<String>) {
+    let user_agent = user_agent
+        .map(|ua| ua.trim().to_string())
+        .filter(|ua| !ua.is_empty());
+    *USER_AGENT_OVERRIDE
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = user_agent;
+}
+
+/// Pick the configured User-Agent or fall back to the default
+fn resolve_user_agent(configured: Option<&str>) -> String {
+    match configured {
+        Some(user_agent) => user_agent.to_string(),
+        None => AppIdentity::current().user_agent(),
+    }
+}
+
+/// HTTP client builder preconfigured with the application User-Agent
+///
+/// Every outbound HTTP client should start from this builder. Long-lived
+/// clients should also set [`user_agent`] per request so later overrides
+/// apply.
+pub fn http_client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().user_agent(user_agent())
 }
 
 /// Full version string with additional information
@@ -41,6 +119,27 @@ mod tests {
         assert!(ua.contains(VERSION));
     }
 
+    #[test]
+    fn test_app_identity() {
+        let identity = AppIdentity::current();
+        assert_eq!(identity.name, APP_NAME);
+        assert_eq!(
+            identity.user_agent(),
+            format!(
+                "V8Ray/{} ({}; {})",
+                VERSION,
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            )
+        );
+
+        assert_eq!(resolve_user_agent(None), identity.user_agent());
+        assert_eq!(
+            resolve_user_agent(Some("clash-verge/1.0")),
+            "clash-verge/1.0"
+        );
+    }
+
     #[test]
     fn test_full_version() {
         let fv = full_version();
@@ -0,0 +1,462 @@
+//! Xray API Client
+//!
+//! Wraps Xray's gRPC HandlerService (AddOutbound, RemoveOutbound, AddInbound,
+//! RemoveInbound), RoutingService and StatsService through the `xray api`
+//! subcommands of the Xray binary, so the outbounds and routing of a running
+//! instance can be changed without restarting the process and its traffic
+//! counters can be read.
+
+use super::{InboundConfig, OutboundConfig, RoutingConfig, XrayConfig, XrayError};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+
+// Windows-specific imports for hiding console window
+#[cfg(windows)]
+use std::os::windows::process::CommandExt;
+
+/// Tag of the API inbound and of Xray's internal API outbound
+pub const API_TAG: &str = "api";
+
+/// Xray API services enabled in generated configurations
+pub const API_SERVICES: &[&str] = &["HandlerService", "RoutingService", "StatsService"];
+
+/// Timeout for a single API call
+pub const DEFAULT_API_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Xray `api` configuration section
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ApiConfig {
+    /// Tag of the API outbound, routed to from the API inbound
+    pub tag: String,
+    /// Enabled gRPC services
+    pub services: Vec<String>,
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            tag: API_TAG.to_string(),
+            services: API_SERVICES.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+impl ApiConfig {
+    /// Loopback dokodemo-door inbound serving the API
+    ///
+    /// Port 0 means a free port is picked when Xray is started.
+    pub fn inbound(port: u16) -> InboundConfig {
+        InboundConfig {
+            port,
+            protocol: "dokodemo-door".to_string(),
+            listen: Some("127.0.0.1".to_string()),
+            settings: Some(json!({ "address": "127.0.0.1" })),
+            sniffing: None,
+            tag: Some(API_TAG.to_string()),
+        }
+    }
+
+    /// Policy enabling the per-outbound traffic counters read by
+    /// [`XrayApiClient::query_outbound_traffic`]
+    pub fn stats_policy() -> serde_json::Value {
+        json!({
+            "system": {
+                "statsOutboundUplink": true,
+                "statsOutboundDownlink": true
+            }
+        })
+    }
+
+    /// Routing rule sending API inbound traffic to the API outbound
+    pub fn routing_rule() -> serde_json::Value {
+        json!({
+            "type": "field",
+            "inboundTag": [API_TAG],
+            "outboundTag": API_TAG
+        })
+    }
+}
+
+/// Port of the API inbound of `config`, if the API is enabled
+pub fn api_port(config: &XrayConfig) -> Option<u16> {
+    config.api.as_ref()?;
+    config
+        .inbounds
+        .iter()
+        .find(|inbound| inbound.tag.as_deref() == Some(API_TAG))
+        .map(|inbound| inbound.port)
+}
+
+/// Changes needed to turn a running configuration into a new one
+#[derive(Debug, Clone, Default)]
+pub struct LiveUpdate {
+    /// Tags of outbounds to remove (changed or dropped)
+    pub remove_outbounds: Vec<String>,
+    /// Outbounds to add (changed or new), in configuration order
+    pub add_outbounds: Vec<OutboundConfig>,
+    /// New routing, if it changed
+    pub routing: Option<RoutingConfig>,
+}
+
+impl LiveUpdate {
+    /// Plan the update from `current` to `new`
+    ///
+    /// Returns None if the change cannot be applied at runtime: the API is
+    /// not enabled, anything besides outbounds and routing differs, an
+    /// outbound has no tag, or the default (first) outbound would change.
+    pub fn plan(current: &XrayConfig, new: &XrayConfig) -> Option<Self> {
+        current.api.as_ref()?;
+
+        let fixed = |config: &XrayConfig| {
+            serde_json::to_value((
+                &config.log,
+                &config.dns,
+                &config.inbounds,
+                &config.observatory,
+                &config.fakedns,
+                &config.api,
+                &config.stats,
+                &config.policy,
+            ))
+            .ok()
+        };
+        if fixed(current)? != fixed(new)? {
+            return None;
+        }
+
+        let tag = |outbound: &OutboundConfig| outbound.tag.clone();
+        if current
+            .outbounds
+            .iter()
+            .chain(&new.outbounds)
+            .any(|o| o.tag.is_none())
+            || current.outbounds.first().and_then(tag) != new.outbounds.first().and_then(tag)
+        {
+            return None;
+        }
+
+        let as_value = |outbound: &OutboundConfig| serde_json::to_value(outbound).ok();
+        let mut update = Self::default();
+
+        for outbound in &current.outbounds {
+            let unchanged = new
+                .outbounds
+                .iter()
+                .any(|o| o.tag == outbound.tag && as_value(o) == as_value(outbound));
+            if !unchanged {
+                update.remove_outbounds.extend(tag(outbound));
+            }
+        }
+        for outbound in &new.outbounds {
+            let unchanged = current
+                .outbounds
+                .iter()
+                .any(|o| o.tag == outbound.tag && as_value(o) == as_value(outbound));
+            if !unchanged {
+                update.add_outbounds.push(outbound.clone());
+            }
+        }
+
+        if serde_json::to_value(&current.routing).ok()?
+            != serde_json::to_value(&new.routing).ok()?
+        {
+            update.routing = Some(new.routing.clone().unwrap_or(RoutingConfig {
+                domain_strategy: None,
+                rules: Vec::new(),
+                balancers: Vec::new(),
+            }));
+        }
+
+        Some(update)
+    }
+
+    /// Whether there is nothing to change
+    pub fn is_empty(&self) -> bool {
+        self.remove_outbounds.is_empty() && self.add_outbounds.is_empty() && self.routing.is_none()
+    }
+}
+
+/// Client for the API of a running Xray instance
+pub struct XrayApiClient {
+    /// Xray binary used to issue `xray api` commands
+    binary: PathBuf,
+    /// API server address
+    server: String,
+    /// Per-call timeout
+    timeout: Duration,
+}
+
+impl XrayApiClient {
+    /// Create a client for the API listening on loopback `port`
+    pub fn new(binary: PathBuf, port: u16) -> Self {
+        Self {
+            binary,
+            server: format!("127.0.0.1:{}", port),
+            timeout: DEFAULT_API_TIMEOUT,
+        }
+    }
+
+    /// Set the per-call timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Add an outbound (HandlerService.AddOutbound)
+    ///
+    /// If no default outbound is set, the added one becomes the default.
+    pub async fn add_outbound(&self, outbound: &OutboundConfig) -> Result<(), XrayError> {
+        self.run_with_config("ado", json!({ "outbounds": [outbound] }))
+            .await
+    }
+
+    /// Remove an outbound by tag (HandlerService.RemoveOutbound)
+    pub async fn remove_outbound(&self, tag: &str) -> Result<(), XrayError> {
+        self.run("rmo", &[tag]).await
+    }
+
+    /// Add an inbound (HandlerService.AddInbound)
+    pub async fn add_inbound(&self, inbound: &InboundConfig) -> Result<(), XrayError> {
+        self.run_with_config("adi", json!({ "inbounds": [inbound] }))
+            .await
+    }
+
+    /// Remove an inbound by tag (HandlerService.RemoveInbound)
+    pub async fn remove_inbound(&self, tag: &str) -> Result<(), XrayError> {
+        self.run("rmi", &[tag]).await
+    }
+
+    /// Replace an inbound with a new configuration of the same tag
+    ///
+    /// The `xray api` command line has no generic AlterInbound, so the
+    /// inbound is removed and added again.
+    pub async fn alter_inbound(&self, inbound: &InboundConfig) -> Result<(), XrayError> {
+        let tag = inbound
+            .tag
+            .as_deref()
+            .ok_or_else(|| XrayError::Config("Inbound has no tag".to_string()))?;
+        self.remove_inbound(tag).await?;
+        self.add_inbound(inbound).await
+    }
+
+    /// Replace all routing rules and balancers (RoutingService.AddRule)
+    pub async fn replace_routing(&self, routing: &RoutingConfig) -> Result<(), XrayError> {
+        self.run_with_config("adrules", json!({ "routing": routing }))
+            .await
+    }
+
+    /// Read and reset the traffic counters of all outbounds
+    /// (StatsService.QueryStats)
+    ///
+    /// Returns `(uplink, downlink)` bytes per outbound tag since the previous
+    /// query.
+    pub async fn query_outbound_traffic(&self) -> Result<HashMap<String, (u64, u64)>, XrayError> {
+        let output = self
+            .output("statsquery", &["-pattern", "outbound>>>", "-reset"])
+            .await?;
+        parse_outbound_traffic(&output)
+    }
+
+    /// Apply a planned update: removals first, then additions, then routing
+    ///
+    /// An outbound being replaced is briefly missing between its removal
+    /// and re-addition; connections opened in that window fail.
+    pub async fn apply(&self, update: &LiveUpdate) -> Result<(), XrayError> {
+        for tag in &update.remove_outbounds {
+            self.remove_outbound(tag).await?;
+        }
+        for outbound in &update.add_outbounds {
+            self.add_outbound(outbound).await?;
+        }
+        if let Some(ref routing) = update.routing {
+            self.replace_routing(routing).await?;
+        }
+        Ok(())
+    }
+
+    /// Run an API command taking a configuration file
+    async fn run_with_config(
+        &self,
+        command: &str,
+        config: serde_json::Value,
+    ) -> Result<(), XrayError> {
+        let temp_dir = crate::paths::app_paths().temp_dir();
+        tokio::fs::create_dir_all(&temp_dir).await?;
+        let path = temp_dir.join(format!("v8ray-api-{}.json", uuid::Uuid::new_v4()));
+        let content =
+            serde_json::to_string(&config).map_err(|e| XrayError::Config(e.to_string()))?;
+        tokio::fs::write(&path, content).await?;
+
+        let result = self.run(command, &[&path.to_string_lossy()]).await;
+
+        let _ = tokio::fs::remove_file(&path).await;
+        result
+    }
+
+    /// Run `xray api <command> --server=<addr> <args>`
+    async fn run(&self, command: &str, args: &[&str]) -> Result<(), XrayError> {
+        self.output(command, args).await.map(|_| ())
+    }
+
+    /// Run an API command and return its standard output
+    async fn output(&self, command: &str, args: &[&str]) -> Result<String, XrayError> {
+        let mut cmd = Command::new(&self.binary);
+        cmd.arg("api")
+            .arg(command)
+            .arg(format!("--server={}", self.server))
+            .arg(format!("--timeout={}", self.timeout.as_secs().max(1)))
+            .args(args);
+
+        // On Windows, hide the console window
+        #[cfg(windows)]
+        {
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+            cmd.creation_flags(CREATE_NO_WINDOW);
+        }
+
+        let output = tokio::time::timeout(self.timeout + Duration::from_secs(1), cmd.output())
+            .await
+            .map_err(|_| XrayError::Api(format!("{} timed out", command)))?
+            .map_err(|e| XrayError::Api(e.to_string()))?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let message = if stderr.trim().is_empty() {
+                stdout.trim()
+            } else {
+                stderr.trim()
+            };
+            Err(XrayError::Api(format!("{} failed: {}", command, message)))
+        }
+    }
+}
+
+/// Parse `statsquery` output into `(uplink, downlink)` per outbound tag
+///
+/// Counter names look like `outbound>>>proxy>>>traffic>>>uplink`; values
+/// are numbers or numeric strings, and counters at zero may have no value.
+fn parse_outbound_traffic(output: &str) -> Result<HashMap<String, (u64, u64)>, XrayError> {
+    #[derive(Deserialize)]
+    struct Stat {
+        name: String,
+        #[serde(default)]
+        value: serde_json::Value,
+    }
+    #[derive(Deserialize)]
+    struct Response {
+        #[serde(default)]
+        stat: Vec<Stat>,
+    }
+
+    let response: Response = serde_json::from_str(output)
+        .map_err(|e| XrayError::Api(format!("statsquery returned invalid output: {}", e)))?;
+    let mut traffic: HashMap<String, (u64, u64)> = HashMap::new();
+    for stat in response.stat {
+        let parts: Vec<&str> = stat.name.split(">>>").collect();
+        let [kind, tag, "traffic", direction] = parts[..] else {
+            continue;
+        };
+        if kind != "outbound" {
+            continue;
+        }
+        let value = match &stat.value {
+            serde_json::Value::Number(n) => n.as_u64().unwrap_or(0),
+            serde_json::Value::String(s) => s.parse().unwrap_or(0),
+            _ => 0,
+        };
+        let entry = traffic.entry(tag.to_string()).or_default();
+        match direction {
+            "uplink" => entry.0 += value,
+            "downlink" => entry.1 += value,
+            _ => {}
+        }
+    }
+    Ok(traffic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xray::XrayConfigGenerator;
+
+    fn proxy_config(server: &str) -> crate::config::ProxyServerConfig {
+        crate::config::ProxyServerConfig {
+            id: server.to_string(),
+            name: server.to_string(),
+            raw_name: None,
+            server: format!("{}.example.com", server),
+            port: 443,
+            protocol: crate::config::ProxyProtocol::Trojan,
+            settings: std::collections::HashMap::from([("password".to_string(), json!("secret"))]),
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_api_section() {
+        let generator = XrayConfigGenerator::new().with_api(true);
+        let config = generator.generate_with_mode(&proxy_config("a"), "smart");
+
+        assert_eq!(config.api, Some(ApiConfig::default()));
+        assert_eq!(api_port(&config), Some(0));
+        let routing = config.routing.unwrap();
+        assert_eq!(routing.rules[0], ApiConfig::routing_rule());
+
+        let config = XrayConfigGenerator::new().generate(&proxy_config("a"));
+        assert!(config.api.is_none());
+        assert!(api_port(&config).is_none());
+    }
+
+    #[test]
+    fn test_parse_outbound_traffic() {
+        let output = r#"{
+            "stat": [
+                {"name": "outbound>>>proxy>>>traffic>>>uplink", "value": "1200"},
+                {"name": "outbound>>>proxy>>>traffic>>>downlink", "value": 3400},
+                {"name": "outbound>>>direct>>>traffic>>>downlink"},
+                {"name": "inbound>>>socks-in>>>traffic>>>uplink", "value": 99}
+            ]
+        }"#;
+        let traffic = parse_outbound_traffic(output).unwrap();
+        assert_eq!(traffic.get("proxy"), Some(&(1200, 3400)));
+        assert_eq!(traffic.get("direct"), Some(&(0, 0)));
+        assert!(!traffic.contains_key("socks-in"));
+
+        assert!(parse_outbound_traffic("{}").unwrap().is_empty());
+        assert!(parse_outbound_traffic("not json").is_err());
+    }
+
+    #[test]
+    fn test_plan_live_update() {
+        let generator = XrayConfigGenerator::new().with_api(true);
+        let current = generator.generate_with_mode(&proxy_config("a"), "global");
+
+        // Same mode, different server: only the proxy outbound is swapped
+        let new = generator.generate_with_mode(&proxy_config("b"), "global");
+        let update = LiveUpdate::plan(&current, &new).unwrap();
+        assert_eq!(update.remove_outbounds, vec!["proxy"]);
+        assert_eq!(update.add_outbounds.len(), 1);
+        assert_eq!(update.add_outbounds[0].tag.as_deref(), Some("proxy"));
+        assert!(update.routing.is_none());
+
+        assert!(LiveUpdate::plan(&current, &current).unwrap().is_empty());
+
+        // Smart mode adds DNS, which cannot change at runtime
+        let smart = generator.generate_with_mode(&proxy_config("b"), "smart");
+        assert!(LiveUpdate::plan(&current, &smart).is_none());
+
+        // Without the API nothing can be changed live
+        let plain = XrayConfigGenerator::new().generate(&proxy_config("a"));
+        assert!(LiveUpdate::plan(&plain, &new).is_none());
+    }
+}
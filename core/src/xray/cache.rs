@@ -0,0 +1,314 @@
+//! Download Cache Module
+//!
+//! Content-addressed cache for downloaded Xray archives and geo assets.
+//! Files are stored under their SHA-256 checksum, and an index maps download
+//! keys (usually the source URL) to checksums, so reinstalling or rolling
+//! back does not require downloading the same file again.
+
+use super::XrayError;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+
+/// Default cache size cap (512 MiB)
+pub const DEFAULT_MAX_CACHE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Index file name inside the cache directory
+const INDEX_FILE: &str = "index.json";
+
+/// Cached file metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Download key (usually the source URL)
+    pub key: String,
+    /// SHA-256 checksum (hex)
+    pub sha256: String,
+    /// File size in bytes
+    pub size: u64,
+    /// Last access time (Unix timestamp)
+    pub last_used: i64,
+}
+
+/// Content-addressed download cache
+pub struct DownloadCache {
+    /// Cache directory
+    dir: PathBuf,
+    /// Maximum total size in bytes
+    max_size: u64,
+    /// Serializes index updates
+    lock: Mutex<()>,
+}
+
+impl DownloadCache {
+    /// Create a new cache rooted at `dir`
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            max_size: DEFAULT_MAX_CACHE_SIZE,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Set the maximum cache size in bytes
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Get the cache directory
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Look up a cached file by key, verifying its checksum
+    ///
+    /// Corrupted entries are removed and reported as a cache miss.
+    pub async fn get(&self, key: &str) -> Result<Option<PathBuf>, XrayError> {
+        let _guard = self.lock.lock().await;
+        let mut index = self.load_index().await?;
+
+        let Some(entry) = index.get(key).cloned() else {
+            return Ok(None);
+        };
+
+        let path = self.blob_path(&entry.sha256);
+        let valid = match sha256_file(&path).await {
+            Ok(checksum) => checksum == entry.sha256,
+            Err(_) => false,
+        };
+
+        if !valid {
+            tracing::warn!(
+                "Cache entry for {} is missing or corrupted, dropping it",
+                key
+            );
+            index.remove(key);
+            if !index.values().any(|e| e.sha256 == entry.sha256) {
+                let _ = fs::remove_file(&path).await;
+            }
+            self.save_index(&index).await?;
+            return Ok(None);
+        }
+
+        if let Some(e) = index.get_mut(key) {
+            e.last_used = chrono::Utc::now().timestamp();
+        }
+        self.save_index(&index).await?;
+
+        tracing::debug!("Cache hit for {}", key);
+        Ok(Some(path))
+    }
+
+    /// Add a file to the cache under `key`
+    ///
+    /// The file is copied into the cache, so `source` can be removed afterwards.
+    pub async fn insert(&self, key: &str, source: &Path) -> Result<CacheEntry, XrayError> {
+        let sha256 = sha256_file(source).await?;
+        let size = fs::metadata(source).await?.len();
+
+        let _guard = self.lock.lock().await;
+        fs::create_dir_all(&self.dir).await?;
+
+        let path = self.blob_path(&sha256);
+        if !path.exists() {
+            fs::copy(source, &path).await?;
+        }
+
+        let entry = CacheEntry {
+            key: key.to_string(),
+            sha256,
+            size,
+            last_used: chrono::Utc::now().timestamp(),
+        };
+
+        let mut index = self.load_index().await?;
+        index.insert(key.to_string(), entry.clone());
+        self.evict(&mut index, Some(&entry.sha256)).await;
+        self.save_index(&index).await?;
+
+        tracing::debug!("Cached {} as {}", key, entry.sha256);
+        Ok(entry)
+    }
+
+    /// List cached entries
+    pub async fn entries(&self) -> Result<Vec<CacheEntry>, XrayError> {
+        let _guard = self.lock.lock().await;
+        Ok(self.load_index().await?.into_values().collect())
+    }
+
+    /// Get total size of cached files in bytes
+    pub async fn total_size(&self) -> Result<u64, XrayError> {
+        let _guard = self.lock.lock().await;
+        let index = self.load_index().await?;
+        Ok(Self::unique_size(&index))
+    }
+
+    /// Remove all cached files, returning the number of bytes freed
+    pub async fn purge(&self) -> Result<u64, XrayError> {
+        let _guard = self.lock.lock().await;
+        let index = self.load_index().await?;
+        let freed = Self::unique_size(&index);
+
+        if self.dir.exists() {
+            fs::remove_dir_all(&self.dir).await?;
+        }
+
+        tracing::info!("Purged download cache, freed {} bytes", freed);
+        Ok(freed)
+    }
+
+    /// Evict least recently used entries until the cache fits its size cap
+    async fn evict(&self, index: &mut HashMap<String, CacheEntry>, keep: Option<&str>) {
+        let mut entries: Vec<CacheEntry> = index.values().cloned().collect();
+        entries.sort_by_key(|e| e.last_used);
+
+        for entry in entries {
+            if Self::unique_size(index) <= self.max_size {
+                break;
+            }
+            if Some(entry.sha256.as_str()) == keep {
+                continue;
+            }
+
+            index.remove(&entry.key);
+            if !index.values().any(|e| e.sha256 == entry.sha256) {
+                let _ = fs::remove_file(self.blob_path(&entry.sha256)).await;
+                tracing::debug!("Evicted {} from download cache", entry.key);
+            }
+        }
+    }
+
+    /// Total size of distinct blobs referenced by the index
+    fn unique_size(index: &HashMap<String, CacheEntry>) -> u64 {
+        let mut blobs: HashMap<&str, u64> = HashMap::new();
+        for entry in index.values() {
+            blobs.insert(&entry.sha256, entry.size);
+        }
+        blobs.values().sum()
+    }
+
+    /// Path of the blob with the given checksum
+    fn blob_path(&self, sha256: &str) -> PathBuf {
+        self.dir.join(sha256)
+    }
+
+    /// Load the key -> entry index
+    async fn load_index(&self) -> Result<HashMap<String, CacheEntry>, XrayError> {
+        let path = self.dir.join(INDEX_FILE);
+        if !path.exists() {
+            return Ok(HashMap::new());
+        }
+
+        let content = fs::read_to_string(&path).await?;
+        match serde_json::from_str(&content) {
+            Ok(index) => Ok(index),
+            Err(e) => {
+                tracing::warn!("Download cache index is corrupted, resetting: {}", e);
+                Ok(HashMap::new())
+            }
+        }
+    }
+
+    /// Persist the key -> entry index
+    async fn save_index(&self, index: &HashMap<String, CacheEntry>) -> Result<(), XrayError> {
+        fs::create_dir_all(&self.dir).await?;
+        let content =
+            serde_json::to_string_pretty(index).map_err(|e| XrayError::Config(e.to_string()))?;
+        fs::write(self.dir.join(INDEX_FILE), content).await?;
+        Ok(())
+    }
+}
+
+/// Compute the SHA-256 checksum of a file as lowercase hex
+pub async fn sha256_file(path: &Path) -> Result<String, XrayError> {
+    let mut file = fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let n = file.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    async fn write_file(dir: &Path, name: &str, size: usize) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, vec![name.as_bytes()[0]; size])
+            .await
+            .unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_insert_and_get() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(temp_dir.path().join("cache"));
+        let source = write_file(temp_dir.path(), "a.zip", 128).await;
+
+        let entry = cache
+            .insert("https://example.com/a.zip", &source)
+            .await
+            .unwrap();
+        assert_eq!(entry.size, 128);
+
+        let cached = cache
+            .get("https://example.com/a.zip")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cached.ends_with(&entry.sha256));
+        assert!(cache
+            .get("https://example.com/b.zip")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_entry_is_dropped() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(temp_dir.path().join("cache"));
+        let source = write_file(temp_dir.path(), "a.zip", 128).await;
+
+        let entry = cache.insert("a", &source).await.unwrap();
+        fs::write(cache.dir().join(&entry.sha256), b"tampered")
+            .await
+            .unwrap();
+
+        assert!(cache.get("a").await.unwrap().is_none());
+        assert!(cache.entries().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_size_cap_and_purge() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = DownloadCache::new(temp_dir.path().join("cache")).with_max_size(200);
+
+        let a = write_file(temp_dir.path(), "a.zip", 128).await;
+        let b = write_file(temp_dir.path(), "b.zip", 128).await;
+        cache.insert("a", &a).await.unwrap();
+        cache.insert("b", &b).await.unwrap();
+
+        // Only the most recent entry fits under the cap
+        let entries = cache.entries().await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].key, "b");
+
+        assert_eq!(cache.purge().await.unwrap(), 128);
+        assert_eq!(cache.total_size().await.unwrap(), 0);
+    }
+}
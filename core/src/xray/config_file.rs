@@ -0,0 +1,131 @@
+//! Xray configuration files
+//!
+//! Each Xray process is started with its own configuration file in the data
+//! directory. The file name carries the PID of the app that wrote it, so
+//! several app instances (or profiles) never overwrite each other's file,
+//! and files left behind by an app that crashed can be told apart from
+//! files still in use.
+
+use std::path::{Path, PathBuf};
+
+/// File name prefix of configuration files
+const PREFIX: &str = "config-";
+
+/// File name extension of configuration files
+const EXTENSION: &str = "json";
+
+/// New configuration file path in `dir`, unique to this app and process
+pub fn unique_path(dir: &Path) -> PathBuf {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    dir.join(format!(
+        "{}{}-{}.{}",
+        PREFIX,
+        std::process::id(),
+        &id[..8],
+        EXTENSION
+    ))
+}
+
+/// PID of the app that wrote the configuration file `name`
+fn owner_pid(name: &str) -> Option<u32> {
+    let stem = name.strip_prefix(PREFIX)?.strip_suffix(EXTENSION)?;
+    let (pid, _) = stem.strip_suffix('.')?.split_once('-')?;
+    pid.parse().ok()
+}
+
+/// Remove configuration files in `dir` whose app is no longer running
+///
+/// Returns the number of removed files.
+pub fn remove_stale(dir: &Path) -> usize {
+    remove_stale_with(dir, super::process::is_process_alive)
+}
+
+fn remove_stale_with(dir: &Path, is_alive: impl Fn(u32) -> bool) -> usize {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name.to_str().and_then(owner_pid) else {
+            continue;
+        };
+        if is_alive(pid) {
+            continue;
+        }
+        match std::fs::remove_file(entry.path()) {
+            Ok(()) => removed += 1,
+            Err(e) => tracing::warn!("Failed to remove stale Xray config {:?}: {}", name, e),
+        }
+    }
+    removed
+}
+
+/// Most recently written configuration file in `dir` whose app is still
+/// running
+pub fn newest_in_use(dir: &Path) -> Option<PathBuf> {
+    newest_in_use_with(dir, super::process::is_process_alive)
+}
+
+fn newest_in_use_with(dir: &Path, is_alive: impl Fn(u32) -> bool) -> Option<PathBuf> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(owner_pid)
+                .is_some_and(&is_alive)
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .max_by_key(|(modified, _)| *modified)
+        .map(|(_, path)| path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unique_path() {
+        let dir = Path::new("/data/xray");
+        let first = unique_path(dir);
+        assert_ne!(first, unique_path(dir));
+        assert_eq!(first.parent(), Some(dir));
+
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert_eq!(owner_pid(name), Some(std::process::id()));
+        assert_eq!(owner_pid("config.json"), None);
+        assert_eq!(owner_pid("config-abc-1234.json"), None);
+    }
+
+    #[test]
+    fn test_remove_stale() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in ["config-100-aaaa.json", "config-200-bbbb.json", "notes.txt"] {
+            std::fs::write(temp_dir.path().join(name), "{}").unwrap();
+        }
+
+        assert_eq!(remove_stale_with(temp_dir.path(), |pid| pid == 200), 1);
+        assert!(!temp_dir.path().join("config-100-aaaa.json").exists());
+        assert!(temp_dir.path().join("config-200-bbbb.json").exists());
+        assert!(temp_dir.path().join("notes.txt").exists());
+
+        assert_eq!(remove_stale(&temp_dir.path().join("missing")), 0);
+    }
+
+    #[test]
+    fn test_newest_in_use() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in ["config-100-aaaa.json", "config-200-bbbb.json"] {
+            std::fs::write(temp_dir.path().join(name), "{}").unwrap();
+        }
+
+        assert_eq!(
+            newest_in_use_with(temp_dir.path(), |pid| pid == 200),
+            Some(temp_dir.path().join("config-200-bbbb.json"))
+        );
+        assert_eq!(newest_in_use_with(temp_dir.path(), |_| false), None);
+    }
+}
@@ -0,0 +1,427 @@
+//! Xray geo data files
+//!
+//! `geosite.dat` and `geoip.dat` (and `ext:` files in the same format) are
+//! protobuf lists of named domain and IP lists. Only the list a matcher
+//! asks for is decoded; the rest of the file is skipped over.
+
+use super::XrayError;
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Geosite file name
+pub const GEOSITE_FILE: &str = "geosite.dat";
+
+/// GeoIP file name
+pub const GEOIP_FILE: &str = "geoip.dat";
+
+/// How a geosite domain entry matches
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GeoDomainKind {
+    /// Substring of the domain
+    Keyword,
+    /// Regular expression
+    Regex,
+    /// Domain and its subdomains
+    Domain,
+    /// Exact domain
+    Full,
+}
+
+/// Entry of a geosite list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GeoDomain {
+    /// How the entry matches
+    pub kind: GeoDomainKind,
+    /// Keyword, pattern or domain
+    pub value: String,
+    /// Attributes, as selected by `geosite:name@attribute`
+    pub attributes: Vec<String>,
+}
+
+/// IP network of a GeoIP list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    /// Network address
+    pub addr: IpAddr,
+    /// Prefix length
+    pub prefix: u8,
+}
+
+impl IpNetwork {
+    /// Parse "10.0.0.0/8" or a single address
+    pub fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse().ok()?)),
+            None => (s.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    /// Whether `ip` is in the network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// GeoIP list
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GeoIpList {
+    /// Networks of the list
+    pub networks: Vec<IpNetwork>,
+    /// Whether the list matches addresses outside its networks
+    pub reverse_match: bool,
+}
+
+impl GeoIpList {
+    /// Whether `ip` matches the list
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|net| net.contains(ip)) != self.reverse_match
+    }
+}
+
+/// Field of a protobuf message
+enum Field<'a> {
+    /// Varint
+    Varint(u64),
+    /// Length-delimited bytes
+    Bytes(&'a [u8]),
+    /// Fixed-size value, not used by geo data
+    Fixed,
+}
+
+/// Reader of the fields of a protobuf message
+struct Fields<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn varint(&mut self) -> Result<u64, XrayError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.buf.split_first().ok_or_else(truncated)?;
+            self.buf = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], XrayError> {
+        if self.buf.len() < len {
+            return Err(truncated());
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    /// Next field number and value, None at the end of the message
+    fn next_field(&mut self) -> Result<Option<(u64, Field<'a>)>, XrayError> {
+        if self.buf.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Field::Fixed
+            }
+            2 => {
+                let len = usize::try_from(self.varint()?).map_err(|_| truncated())?;
+                Field::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Field::Fixed
+            }
+            wire_type => return Err(invalid(&format!("unsupported wire type {}", wire_type))),
+        };
+        Ok(Some((key >> 3, field)))
+    }
+}
+
+fn invalid(reason: &str) -> XrayError {
+    XrayError::Config(format!("Invalid geo data file: {}", reason))
+}
+
+fn truncated() -> XrayError {
+    invalid("truncated")
+}
+
+fn string(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// Find the entry named `code` in a geo data list, ignoring case
+fn find_entry<'a>(data: &'a [u8], code: &str) -> Result<Option<&'a [u8]>, XrayError> {
+    let mut entries = Fields::new(data);
+    while let Some((number, field)) = entries.next_field()? {
+        let (1, Field::Bytes(entry)) = (number, field) else {
+            continue;
+        };
+        let mut fields = Fields::new(entry);
+        while let Some((number, field)) = fields.next_field()? {
+            if let (1, Field::Bytes(name)) = (number, field) {
+                if string(name).eq_ignore_ascii_case(code) {
+                    return Ok(Some(entry));
+                }
+                break;
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Read `file` in `dir`, with a clear error when it is missing
+fn read_file(dir: &Path, file: &str) -> Result<Vec<u8>, XrayError> {
+    let path = dir.join(file);
+    std::fs::read(&path).map_err(|e| {
+        XrayError::Config(format!(
+            "Cannot read geo data file {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Decode the geosite list `code` of `data`, None if the file has no such
+/// list
+pub fn parse_geosite(data: &[u8], code: &str) -> Result<Option<Vec<GeoDomain>>, XrayError> {
+    let Some(entry) = find_entry(data, code)? else {
+        return Ok(None);
+    };
+
+    let mut domains = Vec::new();
+    let mut fields = Fields::new(entry);
+    while let Some((number, field)) = fields.next_field()? {
+        let (2, Field::Bytes(domain)) = (number, field) else {
+            continue;
+        };
+        let mut kind = GeoDomainKind::Keyword;
+        let mut value = String::new();
+        let mut attributes = Vec::new();
+        let mut domain_fields = Fields::new(domain);
+        while let Some((number, field)) = domain_fields.next_field()? {
+            match (number, field) {
+                (1, Field::Varint(k)) => {
+                    kind = match k {
+                        1 => GeoDomainKind::Regex,
+                        2 => GeoDomainKind::Domain,
+                        3 => GeoDomainKind::Full,
+                        _ => GeoDomainKind::Keyword,
+                    }
+                }
+                (2, Field::Bytes(bytes)) => value = string(bytes),
+                (3, Field::Bytes(attribute)) => {
+                    let mut attribute_fields = Fields::new(attribute);
+                    while let Some((number, field)) = attribute_fields.next_field()? {
+                        if let (1, Field::Bytes(key)) = (number, field) {
+                            attributes.push(string(key));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        domains.push(GeoDomain {
+            kind,
+            value,
+            attributes,
+        });
+    }
+    Ok(Some(domains))
+}
+
+/// Decode the GeoIP list `code` of `data`, None if the file has no such
+/// list
+pub fn parse_geoip(data: &[u8], code: &str) -> Result<Option<GeoIpList>, XrayError> {
+    let Some(entry) = find_entry(data, code)? else {
+        return Ok(None);
+    };
+
+    let mut list = GeoIpList::default();
+    let mut fields = Fields::new(entry);
+    while let Some((number, field)) = fields.next_field()? {
+        match (number, field) {
+            (2, Field::Bytes(cidr)) => {
+                let mut addr = None;
+                let mut prefix = 0;
+                let mut cidr_fields = Fields::new(cidr);
+                while let Some((number, field)) = cidr_fields.next_field()? {
+                    match (number, field) {
+                        (1, Field::Bytes(ip)) => {
+                            addr = match ip.len() {
+                                4 => <[u8; 4]>::try_from(ip).ok().map(IpAddr::from),
+                                16 => <[u8; 16]>::try_from(ip).ok().map(IpAddr::from),
+                                _ => None,
+                            }
+                        }
+                        (2, Field::Varint(p)) => prefix = p.min(128) as u8,
+                        _ => {}
+                    }
+                }
+                if let Some(addr) = addr {
+                    list.networks.push(IpNetwork { addr, prefix });
+                }
+            }
+            (3, Field::Varint(reverse)) => list.reverse_match = reverse != 0,
+            _ => {}
+        }
+    }
+    Ok(Some(list))
+}
+
+/// Load the geosite list `code` of `file` in `dir`
+pub fn load_geosite(dir: &Path, file: &str, code: &str) -> Result<Vec<GeoDomain>, XrayError> {
+    parse_geosite(&read_file(dir, file)?, code)?
+        .ok_or_else(|| XrayError::Config(format!("No list {} in {}", code, file)))
+}
+
+/// Load the GeoIP list `code` of `file` in `dir`
+pub fn load_geoip(dir: &Path, file: &str, code: &str) -> Result<GeoIpList, XrayError> {
+    parse_geoip(&read_file(dir, file)?, code)?
+        .ok_or_else(|| XrayError::Config(format!("No list {} in {}", code, file)))
+}
+
+/// Encoders producing geo data files, for tests
+#[cfg(test)]
+pub(crate) mod encode {
+    /// Geosite entry: kind, value and attributes
+    pub(crate) type Domain<'a> = (u64, &'a str, &'a [&'a str]);
+
+    /// GeoIP network: address bytes and prefix
+    pub(crate) type Cidr<'a> = (&'a [u8], u64);
+
+    fn varint(out: &mut Vec<u8>, mut value: u64) {
+        while value >= 0x80 {
+            out.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        out.push(value as u8);
+    }
+
+    fn bytes(out: &mut Vec<u8>, number: u64, value: &[u8]) {
+        varint(out, number << 3 | 2);
+        varint(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+
+    /// Geosite file with the given lists
+    pub(crate) fn geosite(lists: &[(&str, &[Domain])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (code, domains) in lists {
+            let mut entry = Vec::new();
+            bytes(&mut entry, 1, code.to_uppercase().as_bytes());
+            for (kind, value, attributes) in domains.iter() {
+                let mut domain = Vec::new();
+                varint(&mut domain, 1 << 3);
+                varint(&mut domain, *kind);
+                bytes(&mut domain, 2, value.as_bytes());
+                for key in attributes.iter() {
+                    let mut attribute = Vec::new();
+                    bytes(&mut attribute, 1, key.as_bytes());
+                    bytes(&mut domain, 3, &attribute);
+                }
+                bytes(&mut entry, 2, &domain);
+            }
+            bytes(&mut out, 1, &entry);
+        }
+        out
+    }
+
+    /// GeoIP file with the given lists
+    pub(crate) fn geoip(lists: &[(&str, &[Cidr])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (code, cidrs) in lists {
+            let mut entry = Vec::new();
+            bytes(&mut entry, 1, code.to_uppercase().as_bytes());
+            for (ip, prefix) in cidrs.iter() {
+                let mut cidr = Vec::new();
+                bytes(&mut cidr, 1, ip);
+                varint(&mut cidr, 2 << 3);
+                varint(&mut cidr, *prefix);
+                bytes(&mut entry, 2, &cidr);
+            }
+            bytes(&mut out, 1, &entry);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_geosite() {
+        let data = encode::geosite(&[
+            ("private", &[(3, "localhost", &[])]),
+            (
+                "google",
+                &[
+                    (2, "google.com", &[]),
+                    (2, "google.cn", &["cn"]),
+                    (1, "^g\\d+\\.", &[]),
+                ],
+            ),
+        ]);
+
+        let google = parse_geosite(&data, "google").unwrap().unwrap();
+        assert_eq!(google.len(), 3);
+        assert_eq!(google[0].kind, GeoDomainKind::Domain);
+        assert_eq!(google[1].attributes, vec!["cn"]);
+        assert_eq!(google[2].kind, GeoDomainKind::Regex);
+        assert!(parse_geosite(&data, "netflix").unwrap().is_none());
+        assert!(parse_geosite(&data[..data.len() - 3], "google").is_err());
+    }
+
+    #[test]
+    fn test_parse_geoip() {
+        let data = encode::geoip(&[("cn", &[(&[1, 0, 1, 0], 24), (&[36, 0, 0, 0], 8)])]);
+        let cn = parse_geoip(&data, "CN").unwrap().unwrap();
+        assert!(cn.contains("36.1.2.3".parse().unwrap()));
+        assert!(cn.contains("1.0.1.200".parse().unwrap()));
+        assert!(!cn.contains("1.0.2.1".parse().unwrap()));
+        assert!(!cn.contains("::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_ip_network() {
+        let net = IpNetwork::parse("10.0.0.0/8").unwrap();
+        assert!(net.contains("10.20.30.40".parse().unwrap()));
+        assert!(!net.contains("11.0.0.1".parse().unwrap()));
+        assert!(IpNetwork::parse("fc00::/7")
+            .unwrap()
+            .contains("fd12::1".parse().unwrap()));
+        assert!(IpNetwork::parse("1.1.1.1")
+            .unwrap()
+            .contains("1.1.1.1".parse().unwrap()));
+        assert!(IpNetwork::parse("1.1.1.1/33").is_none());
+        assert!(IpNetwork::parse("0.0.0.0/0")
+            .unwrap()
+            .contains("8.8.8.8".parse().unwrap()));
+    }
+}
@@ -0,0 +1,289 @@
+//! Aggregated health checks
+//!
+//! Combines the status of one or more Xray instances with per-outbound
+//! reachability probes into a single summary that the UI can display.
+
+use super::instances::DEFAULT_INSTANCE_ID;
+use super::{XrayHealth, XrayStatus};
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::net::TcpStream;
+use tokio::time;
+
+/// Default timeout for a single outbound probe
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Combined health state across all instances and outbounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HealthSummary {
+    /// All instances are running and every outbound is reachable
+    Healthy,
+    /// Everything is running but some outbounds are unreachable
+    Degraded,
+    /// An instance has failed or no outbound is reachable
+    Unhealthy,
+    /// No instance is running
+    Stopped,
+}
+
+/// Health of a single Xray instance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstanceHealth {
+    /// Instance identifier
+    pub instance_id: String,
+    /// Process status
+    pub status: XrayStatus,
+    /// Process health information, if the instance is running
+    pub health: Option<XrayHealth>,
+}
+
+/// Outbound probe target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeTarget {
+    /// Outbound tag
+    pub tag: String,
+    /// Server address
+    pub server: String,
+    /// Server port
+    pub port: u16,
+}
+
+/// Result of probing a single outbound
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboundProbeResult {
+    /// Instance the outbound belongs to
+    #[serde(default = "default_instance_id")]
+    pub instance_id: String,
+    /// Outbound tag
+    pub tag: String,
+    /// Server address
+    pub server: String,
+    /// Server port
+    pub port: u16,
+    /// Whether the server accepted a TCP connection
+    pub reachable: bool,
+    /// Connect latency in milliseconds
+    pub latency_ms: Option<u64>,
+    /// Error message if the probe failed
+    pub error: Option<String>,
+    /// Time of the probe
+    pub checked_at: SystemTime,
+}
+
+fn default_instance_id() -> String {
+    DEFAULT_INSTANCE_ID.to_string()
+}
+
+/// Aggregated health of all instances and outbounds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AggregatedHealth {
+    /// Combined state
+    pub summary: HealthSummary,
+    /// Per-instance status
+    pub instances: Vec<InstanceHealth>,
+    /// Per-outbound probe results
+    pub outbounds: Vec<OutboundProbeResult>,
+    /// Time of the check
+    pub checked_at: SystemTime,
+}
+
+impl AggregatedHealth {
+    /// Build an aggregated health report from instance and probe results
+    pub fn new(instances: Vec<InstanceHealth>, outbounds: Vec<OutboundProbeResult>) -> Self {
+        let summary = Self::summarize(&instances, &outbounds);
+        Self {
+            summary,
+            instances,
+            outbounds,
+            checked_at: SystemTime::now(),
+        }
+    }
+
+    /// Compute the combined state
+    pub fn summarize(
+        instances: &[InstanceHealth],
+        outbounds: &[OutboundProbeResult],
+    ) -> HealthSummary {
+        if instances.is_empty()
+            || instances
+                .iter()
+                .all(|i| matches!(i.status, XrayStatus::Stopped))
+        {
+            return HealthSummary::Stopped;
+        }
+
+        if instances
+            .iter()
+            .any(|i| matches!(i.status, XrayStatus::Error(_)))
+        {
+            return HealthSummary::Unhealthy;
+        }
+
+        let reachable = outbounds.iter().filter(|o| o.reachable).count();
+        if !outbounds.is_empty() && reachable == 0 {
+            return HealthSummary::Unhealthy;
+        }
+
+        let all_running = instances
+            .iter()
+            .all(|i| matches!(i.status, XrayStatus::Running));
+        if all_running && reachable == outbounds.len() {
+            HealthSummary::Healthy
+        } else {
+            HealthSummary::Degraded
+        }
+    }
+}
+
+/// Probe a single outbound by opening a TCP connection to its server
+///
+/// IPv6 servers may be given with or without brackets.
+pub async fn probe_outbound(target: &ProbeTarget, timeout: Duration) -> OutboundProbeResult {
+    let start = Instant::now();
+    let host = target
+        .server
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(&target.server);
+
+    let (reachable, latency_ms, error) =
+        match time::timeout(timeout, TcpStream::connect((host, target.port))).await {
+            Ok(Ok(_)) => (true, Some(start.elapsed().as_millis() as u64), None),
+            Ok(Err(e)) => (false, None, Some(e.to_string())),
+            Err(_) => (false, None, Some(format!("Timed out after {:?}", timeout))),
+        };
+
+    OutboundProbeResult {
+        instance_id: default_instance_id(),
+        tag: target.tag.clone(),
+        server: target.server.clone(),
+        port: target.port,
+        reachable,
+        latency_ms,
+        error,
+        checked_at: SystemTime::now(),
+    }
+}
+
+/// Probe all outbounds in parallel
+pub async fn probe_outbounds(
+    targets: &[ProbeTarget],
+    timeout: Duration,
+) -> Vec<OutboundProbeResult> {
+    join_all(targets.iter().map(|t| probe_outbound(t, timeout))).await
+}
+
+/// Probe the outbounds of several instances in parallel, given as
+/// (instance ID, target) pairs
+pub async fn probe_instance_outbounds(
+    targets: &[(String, ProbeTarget)],
+    timeout: Duration,
+) -> Vec<OutboundProbeResult> {
+    join_all(targets.iter().map(|(instance_id, target)| async move {
+        OutboundProbeResult {
+            instance_id: instance_id.clone(),
+            ..probe_outbound(target, timeout).await
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(status: XrayStatus) -> InstanceHealth {
+        InstanceHealth {
+            instance_id: "default".to_string(),
+            status,
+            health: None,
+        }
+    }
+
+    fn probe(reachable: bool) -> OutboundProbeResult {
+        OutboundProbeResult {
+            instance_id: DEFAULT_INSTANCE_ID.to_string(),
+            tag: "proxy".to_string(),
+            server: "example.com".to_string(),
+            port: 443,
+            reachable,
+            latency_ms: None,
+            error: None,
+            checked_at: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        assert_eq!(
+            AggregatedHealth::summarize(&[], &[]),
+            HealthSummary::Stopped
+        );
+        assert_eq!(
+            AggregatedHealth::summarize(&[instance(XrayStatus::Stopped)], &[probe(true)]),
+            HealthSummary::Stopped
+        );
+        assert_eq!(
+            AggregatedHealth::summarize(&[instance(XrayStatus::Running)], &[probe(true)]),
+            HealthSummary::Healthy
+        );
+        assert_eq!(
+            AggregatedHealth::summarize(
+                &[instance(XrayStatus::Running)],
+                &[probe(true), probe(false)]
+            ),
+            HealthSummary::Degraded
+        );
+        assert_eq!(
+            AggregatedHealth::summarize(&[instance(XrayStatus::Running)], &[probe(false)]),
+            HealthSummary::Unhealthy
+        );
+        assert_eq!(
+            AggregatedHealth::summarize(
+                &[instance(XrayStatus::Error("crashed".to_string()))],
+                &[probe(true)]
+            ),
+            HealthSummary::Unhealthy
+        );
+    }
+
+    #[tokio::test]
+    async fn test_probe_unreachable() {
+        let targets = vec![ProbeTarget {
+            tag: "proxy-0".to_string(),
+            server: "127.0.0.1".to_string(),
+            port: 1,
+        }];
+
+        let results = probe_outbounds(&targets, Duration::from_secs(1)).await;
+        assert_eq!(results.len(), 1);
+        assert!(!results[0].reachable);
+        assert!(results[0].error.is_some());
+        assert_eq!(results[0].instance_id, DEFAULT_INSTANCE_ID);
+
+        let targets = vec![("browser".to_string(), targets[0].clone())];
+        let results = probe_instance_outbounds(&targets, Duration::from_secs(1)).await;
+        assert_eq!(results[0].instance_id, "browser");
+        assert!(!results[0].reachable);
+    }
+
+    #[tokio::test]
+    async fn test_probe_ipv6() {
+        let Ok(listener) = tokio::net::TcpListener::bind("[::1]:0").await else {
+            // No IPv6 loopback on this host
+            return;
+        };
+        let port = listener.local_addr().unwrap().port();
+        for server in ["::1", "[::1]"] {
+            let target = ProbeTarget {
+                tag: "proxy-0".to_string(),
+                server: server.to_string(),
+                port,
+            };
+            let result = probe_outbound(&target, Duration::from_secs(1)).await;
+            assert!(result.reachable, "{}: {:?}", server, result.error);
+            assert_eq!(result.server, server);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Concurrent Xray instances
+//!
+//! Besides the main connection, advanced users can run further Xray
+//! instances side by side, one per profile, e.g. a global profile for a
+//! browser next to a direct profile for games. Each instance is an
+//! independent [`XrayCore`] with its own inbound ports, configuration file
+//! and process; the registry keeps their ports apart and tags their events
+//! with the instance ID.
+
+use super::{InstanceHealth, ProbeTarget, XrayConfig, XrayCore, XrayError, XrayEvent};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+
+/// ID of the instance of the main connection
+pub const DEFAULT_INSTANCE_ID: &str = "default";
+
+/// Event of an instance
+#[derive(Debug, Clone)]
+pub struct InstanceEvent {
+    /// Instance the event comes from
+    pub instance_id: String,
+    /// Event
+    pub event: XrayEvent,
+}
+
+/// Local inbound ports of a configuration
+///
+/// Port 0 (any free port, used by the API inbound) is left out.
+pub fn inbound_ports(config: &XrayConfig) -> Vec<u16> {
+    config
+        .inbounds
+        .iter()
+        .map(|inbound| inbound.port)
+        .filter(|port| *port != 0)
+        .collect()
+}
+
+/// Registry of the instances running next to the main connection
+pub struct XrayInstances {
+    /// Instances by ID
+    instances: RwLock<HashMap<String, Arc<XrayCore>>>,
+    /// Outbounds to probe for health reports, by instance ID
+    probe_targets: RwLock<HashMap<String, Vec<ProbeTarget>>>,
+    /// Events of all instances
+    event_tx: broadcast::Sender<InstanceEvent>,
+}
+
+impl Default for XrayInstances {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl XrayInstances {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        let (event_tx, _) = broadcast::channel(100);
+        Self {
+            instances: RwLock::new(HashMap::new()),
+            probe_targets: RwLock::new(HashMap::new()),
+            event_tx,
+        }
+    }
+
+    /// Start `core` with `config` as the instance `core.instance_id()`,
+    /// whose outbounds are probed through `probe_targets`
+    ///
+    /// Fails if the ID is taken by a running instance, or if an inbound port
+    /// is used by another instance or listed in `reserved_ports` (the ports
+    /// of the main connection). A stopped or failed instance with the same
+    /// ID is replaced.
+    pub async fn start(
+        &self,
+        core: XrayCore,
+        config: XrayConfig,
+        reserved_ports: &[u16],
+        probe_targets: Vec<ProbeTarget>,
+    ) -> Result<(), XrayError> {
+        let instance_id = core.instance_id().to_string();
+        if instance_id.trim().is_empty() || instance_id == DEFAULT_INSTANCE_ID {
+            return Err(XrayError::Config(format!(
+                "Invalid instance ID: {:?}",
+                instance_id
+            )));
+        }
+
+        let mut instances = self.instances.write().await;
+        if let Some(existing) = instances.get(&instance_id) {
+            if existing.is_running().await {
+                return Err(XrayError::Config(format!(
+                    "Instance {} is already running",
+                    instance_id
+                )));
+            }
+        }
+
+        let ports = inbound_ports(&config);
+        if let Some(port) = ports.iter().find(|port| reserved_ports.contains(port)) {
+            return Err(XrayError::Config(format!(
+                "Port {} is used by the main connection",
+                port
+            )));
+        }
+        for (other_id, other) in instances.iter().filter(|(id, _)| **id != instance_id) {
+            let Some(other_config) = other.running_config().await else {
+                continue;
+            };
+            let other_ports = inbound_ports(&other_config);
+            if let Some(port) = ports.iter().find(|port| other_ports.contains(port)) {
+                return Err(XrayError::Config(format!(
+                    "Port {} is used by instance {}",
+                    port, other_id
+                )));
+            }
+        }
+
+        let core = Arc::new(core);
+        self.forward_events(&core);
+        core.start(config).await?;
+        instances.insert(instance_id.clone(), core);
+        self.probe_targets
+            .write()
+            .await
+            .insert(instance_id.clone(), probe_targets);
+
+        tracing::info!("Xray instance {} started", instance_id);
+        Ok(())
+    }
+
+    /// Send events of `core` to the registry channel, tagged with its ID
+    fn forward_events(&self, core: &XrayCore) {
+        let instance_id = core.instance_id().to_string();
+        let mut rx = core.subscribe();
+        let event_tx = self.event_tx.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => {
+                        let _ = event_tx.send(InstanceEvent {
+                            instance_id: instance_id.clone(),
+                            event,
+                        });
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Stop and remove an instance, returning whether it existed
+    pub async fn stop(&self, instance_id: &str) -> Result<bool, XrayError> {
+        let Some(core) = self.instances.write().await.remove(instance_id) else {
+            return Ok(false);
+        };
+        self.probe_targets.write().await.remove(instance_id);
+        core.stop().await?;
+        tracing::info!("Xray instance {} stopped", instance_id);
+        Ok(true)
+    }
+
+    /// Stop and remove all instances
+    pub async fn stop_all(&self) {
+        let instances: Vec<_> = self.instances.write().await.drain().collect();
+        self.probe_targets.write().await.clear();
+        for (instance_id, core) in instances {
+            if let Err(e) = core.stop().await {
+                tracing::warn!("Failed to stop Xray instance {}: {}", instance_id, e);
+            }
+        }
+    }
+
+    /// Instance with `instance_id`
+    pub async fn get(&self, instance_id: &str) -> Option<Arc<XrayCore>> {
+        self.instances.read().await.get(instance_id).cloned()
+    }
+
+    /// Status and health of all instances, ordered by ID
+    pub async fn list(&self) -> Vec<InstanceHealth> {
+        let instances: Vec<_> = self.instances.read().await.values().cloned().collect();
+        let mut health = Vec::with_capacity(instances.len());
+        for core in instances {
+            health.push(core.get_instance_health().await);
+        }
+        health.sort_by(|a, b| a.instance_id.cmp(&b.instance_id));
+        health
+    }
+
+    /// Outbounds of the running instances to probe, as (instance ID,
+    /// target) pairs
+    pub async fn probe_targets(&self) -> Vec<(String, ProbeTarget)> {
+        let instances: Vec<_> = self.instances.read().await.values().cloned().collect();
+        let probe_targets = self.probe_targets.read().await;
+        let mut targets = Vec::new();
+        for core in instances {
+            if !core.is_running().await {
+                continue;
+            }
+            let instance_id = core.instance_id();
+            for target in probe_targets.get(instance_id).into_iter().flatten() {
+                targets.push((instance_id.to_string(), target.clone()));
+            }
+        }
+        targets
+    }
+
+    /// Subscribe to events of all instances
+    pub fn subscribe(&self) -> broadcast::Receiver<InstanceEvent> {
+        self.event_tx.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ProxyProtocol, ProxyServerConfig};
+
+    fn test_core(instance_id: &str, http_port: u16, socks_port: u16) -> (XrayCore, XrayConfig) {
+        let server = ProxyServerConfig {
+            id: "server".to_string(),
+            name: "server".to_string(),
+            server: "example.com".to_string(),
+            port: 443,
+            protocol: ProxyProtocol::Vless,
+            settings: [("id".to_string(), serde_json::json!("test-uuid"))].into(),
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            raw_name: None,
+        };
+        let core = XrayCore::for_instance(instance_id);
+        core.set_inbound_ports(http_port, socks_port);
+        let config = core.generate_config_with_mode(&server, "global");
+        (core, config)
+    }
+
+    #[test]
+    fn test_inbound_ports() {
+        let (_, config) = test_core("browser", 18080, 11080);
+        let mut ports = inbound_ports(&config);
+        ports.sort_unstable();
+        assert_eq!(ports, vec![11080, 18080]);
+    }
+
+    #[tokio::test]
+    async fn test_start_rejects_conflicts() {
+        let instances = XrayInstances::new();
+
+        let (core, config) = test_core(DEFAULT_INSTANCE_ID, 18080, 11080);
+        assert!(instances.start(core, config, &[], vec![]).await.is_err());
+
+        let (core, config) = test_core("browser", 18080, 11080);
+        let error = instances
+            .start(core, config, &[11080], vec![])
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("main connection"));
+
+        assert!(instances.list().await.is_empty());
+        assert!(instances.probe_targets().await.is_empty());
+        assert!(!instances.stop("browser").await.unwrap());
+    }
+}
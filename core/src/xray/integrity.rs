@@ -0,0 +1,251 @@
+//! Integrity of downloaded Xray Core releases
+//!
+//! Every Xray release publishes a `.dgst` file with the checksums of each
+//! archive, and the archive is only installed if its SHA-256 matches.
+//! Checksums are fetched from GitHub; one served by a mirror only proves the
+//! download is not corrupted, not that the mirror is honest, so it is not
+//! trusted on its own.
+//!
+//! For that, a [minisign] public key can be configured: the archive must
+//! then also carry a valid `.minisig` signature by that key, wherever it was
+//! downloaded from, and a mirror's checksum is accepted when GitHub cannot
+//! be reached.
+//!
+//! [minisign]: https://jedisct1.github.io/minisign/
+
+use crate::error::IntegrityError;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::signature::{UnparsedPublicKey, ED25519};
+
+/// Signature algorithm over the file itself
+const ALG_PURE: &[u8; 2] = b"Ed";
+
+/// Signature algorithm over the BLAKE2b-512 hash of the file, the default
+/// of current minisign releases
+const ALG_PREHASHED: &[u8; 2] = b"ED";
+
+/// SHA-256 checksum from the `.dgst` file of a release
+///
+/// The file has one `ALGORITHM= hex` line per algorithm.
+pub fn parse_digest(content: &str) -> Option<String> {
+    content.lines().find_map(|line| {
+        let (algorithm, digest) = line.split_once('=')?;
+        let digest = digest.trim();
+        (algorithm.trim() == "SHA2-256"
+            && digest.len() == 64
+            && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| digest.to_ascii_lowercase())
+    })
+}
+
+/// Check that `data`, the release file `file_name`, has the SHA-256
+/// checksum `expected`
+pub fn verify_checksum(file_name: &str, data: &[u8], expected: &str) -> Result<(), IntegrityError> {
+    use sha2::{Digest, Sha256};
+    let actual = hex::encode(Sha256::digest(data));
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(IntegrityError::ChecksumMismatch {
+            file: file_name.to_string(),
+            expected: expected.to_string(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Minisign public key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinisignPublicKey {
+    /// Key ID, identifying the key in signatures
+    key_id: [u8; 8],
+    /// Ed25519 public key
+    key: [u8; 32],
+}
+
+impl MinisignPublicKey {
+    /// Parse a key given as its base64 line or as a whole `.pub` file
+    pub fn parse(text: &str) -> Result<Self, IntegrityError> {
+        let line = text
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+            .ok_or_else(|| IntegrityError::InvalidPublicKey("empty key".to_string()))?;
+        let bytes = BASE64
+            .decode(line)
+            .map_err(|e| IntegrityError::InvalidPublicKey(e.to_string()))?;
+        if bytes.len() != 42 || &bytes[..2] != ALG_PURE {
+            return Err(IntegrityError::InvalidPublicKey(
+                "not an Ed25519 minisign key".to_string(),
+            ));
+        }
+        let mut key_id = [0u8; 8];
+        key_id.copy_from_slice(&bytes[2..10]);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&bytes[10..]);
+        Ok(Self { key_id, key })
+    }
+
+    /// Key as the base64 line of a `.pub` file
+    pub fn encode(&self) -> String {
+        let mut bytes = ALG_PURE.to_vec();
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.key);
+        BASE64.encode(bytes)
+    }
+
+    /// Key ID as shown by minisign
+    pub fn key_id(&self) -> String {
+        let mut id = self.key_id;
+        id.reverse();
+        hex::encode_upper(id)
+    }
+
+    /// Check the `.minisig` signature `signature` of `data`, including the
+    /// signature over its trusted comment
+    pub fn verify(&self, data: &[u8], signature: &str) -> Result<(), IntegrityError> {
+        let invalid = |reason: &str| IntegrityError::InvalidSignature(reason.to_string());
+        let mut lines = signature.lines().map(str::trim_end);
+        let _untrusted_comment = lines.next();
+        let signature_line = lines.next().ok_or_else(|| invalid("missing signature"))?;
+        let trusted_comment = lines
+            .next()
+            .and_then(|line| line.strip_prefix("trusted comment: "))
+            .ok_or_else(|| invalid("missing trusted comment"))?;
+        let global_line = lines
+            .next()
+            .ok_or_else(|| invalid("missing global signature"))?;
+
+        let signature = BASE64
+            .decode(signature_line.trim())
+            .map_err(|_| invalid("malformed signature"))?;
+        if signature.len() != 74 {
+            return Err(invalid("malformed signature"));
+        }
+        let (algorithm, rest) = signature.split_at(2);
+        let (key_id, signature) = rest.split_at(8);
+        if key_id != self.key_id {
+            let mut id = [0u8; 8];
+            id.copy_from_slice(key_id);
+            id.reverse();
+            return Err(IntegrityError::InvalidSignature(format!(
+                "signed with key {}, expected {}",
+                hex::encode_upper(id),
+                self.key_id()
+            )));
+        }
+
+        let key = UnparsedPublicKey::new(&ED25519, self.key);
+        let verified = if algorithm == ALG_PREHASHED {
+            key.verify(&blake2b_512(data), signature)
+        } else if algorithm == ALG_PURE {
+            key.verify(data, signature)
+        } else {
+            return Err(invalid("unknown signature algorithm"));
+        };
+        verified.map_err(|_| invalid("signature does not match the file"))?;
+
+        let global = BASE64
+            .decode(global_line.trim())
+            .map_err(|_| invalid("malformed global signature"))?;
+        let mut signed = signature.to_vec();
+        signed.extend_from_slice(trusted_comment.as_bytes());
+        key.verify(&signed, &global)
+            .map_err(|_| invalid("trusted comment signature does not match"))?;
+        Ok(())
+    }
+}
+
+/// Unkeyed BLAKE2b-512 hash, which minisign signs
+pub fn blake2b_512(data: &[u8]) -> [u8; 64] {
+    use blake2::{Blake2b512, Digest};
+    Blake2b512::digest(data).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    #[test]
+    fn test_blake2b_512() {
+        assert_eq!(
+            hex::encode(blake2b_512(b"")),
+            "786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419\
+             d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce"
+        );
+        assert_eq!(
+            hex::encode(blake2b_512(b"abc")),
+            "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"
+        );
+        // Exactly one block, and more than one
+        assert_ne!(blake2b_512(&[0; 128]), blake2b_512(&[0; 129]));
+    }
+
+    #[test]
+    fn test_checksum() {
+        let content = format!(
+            "MD5= {}\nSHA2-256= {}\n",
+            "b".repeat(32),
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+        );
+        let digest = parse_digest(&content).unwrap();
+        assert!(verify_checksum("abc.zip", b"abc", &digest).is_ok());
+        assert!(matches!(
+            verify_checksum("abc.zip", b"abd", &digest),
+            Err(IntegrityError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(parse_digest("SHA2-256= xyz"), None);
+    }
+
+    /// Key and `.minisig` for `data` in minisign's formats
+    fn sign(data: &[u8], key_id: [u8; 8]) -> (String, String) {
+        let rng = ring::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).unwrap();
+        let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+
+        let mut public = b"Ed".to_vec();
+        public.extend_from_slice(&key_id);
+        public.extend_from_slice(pair.public_key().as_ref());
+        let public = format!(
+            "untrusted comment: minisign public key\n{}\n",
+            BASE64.encode(public)
+        );
+
+        let signature = pair.sign(&blake2b_512(data));
+        let mut line = b"ED".to_vec();
+        line.extend_from_slice(&key_id);
+        line.extend_from_slice(signature.as_ref());
+        let comment = "timestamp:1700000000\tfile:Xray-linux-64.zip\thashed";
+        let mut signed = signature.as_ref().to_vec();
+        signed.extend_from_slice(comment.as_bytes());
+        let global = pair.sign(&signed);
+        let minisig = format!(
+            "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: {}\n{}\n",
+            BASE64.encode(line),
+            comment,
+            BASE64.encode(global.as_ref())
+        );
+        (public, minisig)
+    }
+
+    #[test]
+    fn test_minisign() {
+        let data = b"xray archive";
+        let (public, minisig) = sign(data, [1, 2, 3, 4, 5, 6, 7, 8]);
+        let key = MinisignPublicKey::parse(&public).unwrap();
+        assert_eq!(key.key_id(), "0807060504030201");
+        assert_eq!(MinisignPublicKey::parse(&key.encode()).unwrap(), key);
+        assert!(key.verify(data, &minisig).is_ok());
+
+        // Tampered file, tampered trusted comment, other key
+        assert!(key.verify(b"xray archivf", &minisig).is_err());
+        let tampered = minisig.replace("hashed", "hashes");
+        assert!(key.verify(data, &tampered).is_err());
+        let (other, _) = sign(data, [9; 8]);
+        let other = MinisignPublicKey::parse(&other).unwrap();
+        assert!(other.verify(data, &minisig).is_err());
+
+        assert!(MinisignPublicKey::parse("not a key").is_err());
+    }
+}
@@ -0,0 +1,372 @@
+//! Xray process resource limits
+//!
+//! On low-RAM routers and old laptops Xray can compete with everything
+//! else on the machine. The process can run at a lower scheduling priority
+//! (`nice` on Unix, the priority class on Windows) and be pinned to some
+//! CPUs, and a memory watchdog in [`XrayCore::start_monitoring`] restarts
+//! it when its resident memory stays above a limit.
+//!
+//! [`XrayCore::start_monitoring`]: super::XrayCore::start_monitoring
+
+use serde::{Deserialize, Serialize};
+
+/// Consecutive health samples (5 seconds apart) over the memory limit after
+/// which Xray is restarted, so short spikes are tolerated
+pub const MEMORY_LIMIT_SAMPLES: u32 = 2;
+
+/// Smallest accepted memory limit; Xray alone needs more than this
+pub const MIN_MEMORY_LIMIT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Scheduling priority of the Xray process
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessPriority {
+    /// Same priority as the app
+    #[default]
+    Normal,
+    /// Below other programs (`nice` 10, `BELOW_NORMAL_PRIORITY_CLASS`)
+    BelowNormal,
+    /// Only when the machine is otherwise idle (`nice` 19,
+    /// `IDLE_PRIORITY_CLASS`)
+    Idle,
+}
+
+impl ProcessPriority {
+    /// Priority name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessPriority::Normal => "normal",
+            ProcessPriority::BelowNormal => "below_normal",
+            ProcessPriority::Idle => "idle",
+        }
+    }
+
+    /// Unix niceness
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn nice(&self) -> i32 {
+        match self {
+            ProcessPriority::Normal => 0,
+            ProcessPriority::BelowNormal => 10,
+            ProcessPriority::Idle => 19,
+        }
+    }
+}
+
+impl std::str::FromStr for ProcessPriority {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(ProcessPriority::Normal),
+            "below_normal" => Ok(ProcessPriority::BelowNormal),
+            "idle" => Ok(ProcessPriority::Idle),
+            _ => Err(format!("Invalid process priority: {}", s)),
+        }
+    }
+}
+
+/// Resource limits applied to each Xray process
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// Scheduling priority
+    #[serde(default)]
+    pub priority: ProcessPriority,
+    /// CPUs the process may run on (from 0), empty for all
+    #[serde(default)]
+    pub cpu_affinity: Vec<usize>,
+    /// Resident memory above which Xray is restarted, None for no limit
+    #[serde(default)]
+    pub memory_limit_bytes: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Whether no limit is set
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Check that the CPUs exist and the memory limit is usable
+    pub fn validate(&self) -> Result<(), String> {
+        let cpus = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        if let Some(cpu) = self.cpu_affinity.iter().find(|cpu| **cpu >= cpus) {
+            return Err(format!(
+                "CPU {} does not exist ({} CPUs available)",
+                cpu, cpus
+            ));
+        }
+        if let Some(limit) = self.memory_limit_bytes {
+            if limit < MIN_MEMORY_LIMIT_BYTES {
+                return Err(format!(
+                    "Memory limit must be at least {} MiB",
+                    MIN_MEMORY_LIMIT_BYTES / (1024 * 1024)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `memory_bytes` is over the memory limit
+    pub fn exceeds_memory(&self, memory_bytes: u64) -> bool {
+        self.memory_limit_bytes
+            .is_some_and(|limit| memory_bytes > limit)
+    }
+}
+
+/// Apply the priority and CPU affinity of `limits` to the process with `pid`
+///
+/// Normal priority and an empty CPU list restore the app's own settings,
+/// so limits can be lifted from a running process. Returns a message for
+/// each setting that could not be applied; a process is never stopped for
+/// that.
+pub fn apply(pid: u32, limits: &ResourceLimits) -> Vec<String> {
+    let mut failures = Vec::new();
+    if let Err(e) = set_priority(pid, limits.priority) {
+        failures.push(format!("Failed to set priority: {}", e));
+    }
+    if let Err(e) = set_affinity(pid, &limits.cpu_affinity) {
+        failures.push(format!("Failed to set CPU affinity: {}", e));
+    }
+    failures
+}
+
+/// Set the niceness of the process with `pid`, the app's own for normal
+/// priority
+///
+/// Unprivileged processes cannot lower the niceness again, so raising the
+/// priority of a running process may be denied; it then applies once Xray
+/// is restarted.
+#[cfg(unix)]
+fn set_priority(pid: u32, priority: ProcessPriority) -> Result<(), String> {
+    let nice = match priority {
+        // getpriority cannot fail for the calling process, so -1 is a niceness
+        ProcessPriority::Normal => unsafe { libc::getpriority(libc::PRIO_PROCESS as _, 0) },
+        priority => priority.nice(),
+    };
+    let result = unsafe { libc::setpriority(libc::PRIO_PROCESS as _, pid as _, nice) };
+    if result != 0 {
+        let error = std::io::Error::last_os_error();
+        if matches!(error.raw_os_error(), Some(libc::EACCES | libc::EPERM)) {
+            return Err(format!(
+                "lowering the niceness to {} was denied ({}); it applies when Xray restarts",
+                nice, error
+            ));
+        }
+        return Err(error.to_string());
+    }
+    Ok(())
+}
+
+/// Set the priority class of the process with `pid`
+#[cfg(windows)]
+fn set_priority(pid: u32, priority: ProcessPriority) -> Result<(), String> {
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{OpenProcess, SetPriorityClass};
+    use winapi::um::winbase::{
+        BELOW_NORMAL_PRIORITY_CLASS, IDLE_PRIORITY_CLASS, NORMAL_PRIORITY_CLASS,
+    };
+    use winapi::um::winnt::PROCESS_SET_INFORMATION;
+
+    let class = match priority {
+        ProcessPriority::Normal => NORMAL_PRIORITY_CLASS,
+        ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+        ProcessPriority::Idle => IDLE_PRIORITY_CLASS,
+    };
+    unsafe {
+        let handle = OpenProcess(PROCESS_SET_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let ok = SetPriorityClass(handle, class) != 0;
+        let error = std::io::Error::last_os_error();
+        CloseHandle(handle);
+        if ok {
+            Ok(())
+        } else {
+            Err(error.to_string())
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn set_priority(_pid: u32, priority: ProcessPriority) -> Result<(), String> {
+    if priority == ProcessPriority::Normal {
+        return Ok(());
+    }
+    Err("not supported on this platform".to_string())
+}
+
+/// Restrict the process with `pid` to `cpus`, or to the app's CPUs if
+/// `cpus` is empty
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_affinity(pid: u32, cpus: &[usize]) -> Result<(), String> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if cpus.is_empty()
+            && libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0
+        {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(format!("CPU {} is out of range", cpu));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        let result = libc::sched_setaffinity(
+            pid as libc::pid_t,
+            std::mem::size_of::<libc::cpu_set_t>(),
+            &set,
+        );
+        if result != 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Restrict the process with `pid` to `cpus`, or to the app's CPUs if
+/// `cpus` is empty
+#[cfg(windows)]
+fn set_affinity(pid: u32, cpus: &[usize]) -> Result<(), String> {
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcess};
+    use winapi::um::winbase::{GetProcessAffinityMask, SetProcessAffinityMask};
+    use winapi::um::winnt::PROCESS_SET_INFORMATION;
+
+    let mut mask: usize = 0;
+    if cpus.is_empty() {
+        let mut system_mask: usize = 0;
+        // SAFETY: both pointers are valid for the call
+        let ok =
+            unsafe { GetProcessAffinityMask(GetCurrentProcess(), &mut mask, &mut system_mask) };
+        if ok == 0 {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+    }
+    for &cpu in cpus {
+        if cpu >= usize::BITS as usize {
+            return Err(format!("CPU {} is out of range", cpu));
+        }
+        mask |= 1 << cpu;
+    }
+    unsafe {
+        let handle = OpenProcess(PROCESS_SET_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return Err(std::io::Error::last_os_error().to_string());
+        }
+        let ok = SetProcessAffinityMask(handle, mask) != 0;
+        let error = std::io::Error::last_os_error();
+        CloseHandle(handle);
+        if ok {
+            Ok(())
+        } else {
+            Err(error.to_string())
+        }
+    }
+}
+
+/// macOS has no way to pin a process to CPUs, so processes always run on
+/// all of them
+#[cfg(not(any(target_os = "linux", target_os = "android", windows)))]
+fn set_affinity(_pid: u32, cpus: &[usize]) -> Result<(), String> {
+    if cpus.is_empty() {
+        return Ok(());
+    }
+    Err("not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(ResourceLimits::default().validate().is_ok());
+        assert!(ResourceLimits {
+            cpu_affinity: vec![0],
+            memory_limit_bytes: Some(128 * 1024 * 1024),
+            ..Default::default()
+        }
+        .validate()
+        .is_ok());
+        assert!(ResourceLimits {
+            cpu_affinity: vec![usize::MAX],
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(ResourceLimits {
+            memory_limit_bytes: Some(1024),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn test_priority_names() {
+        for priority in [
+            ProcessPriority::Normal,
+            ProcessPriority::BelowNormal,
+            ProcessPriority::Idle,
+        ] {
+            assert_eq!(priority.as_str().parse::<ProcessPriority>(), Ok(priority));
+        }
+        assert!("realtime".parse::<ProcessPriority>().is_err());
+    }
+
+    #[test]
+    fn test_exceeds_memory() {
+        let limits = ResourceLimits {
+            memory_limit_bytes: Some(100),
+            ..Default::default()
+        };
+        assert!(limits.exceeds_memory(101));
+        assert!(!limits.exceeds_memory(100));
+        assert!(!ResourceLimits::default().exceeds_memory(u64::MAX));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_apply_to_child() {
+        let mut child = std::process::Command::new("sleep")
+            .arg("5")
+            .spawn()
+            .unwrap();
+        let limits = ResourceLimits {
+            priority: ProcessPriority::Idle,
+            cpu_affinity: vec![0],
+            memory_limit_bytes: None,
+        };
+        let failures = apply(child.id(), &limits);
+
+        // Lifting the limits restores the app's CPUs; the niceness cannot
+        // be lowered again without privileges
+        let lifted = apply(child.id(), &ResourceLimits::default());
+        #[cfg(target_os = "linux")]
+        let affinity = unsafe {
+            let mut app: libc::cpu_set_t = std::mem::zeroed();
+            let mut xray: libc::cpu_set_t = std::mem::zeroed();
+            let size = std::mem::size_of::<libc::cpu_set_t>();
+            libc::sched_getaffinity(0, size, &mut app);
+            libc::sched_getaffinity(child.id() as libc::pid_t, size, &mut xray);
+            libc::CPU_EQUAL(&app, &xray)
+        };
+        let _ = child.kill();
+        let _ = child.wait();
+        #[cfg(target_os = "linux")]
+        {
+            assert!(failures.is_empty(), "{:?}", failures);
+            assert!(affinity);
+            assert!(
+                lifted.iter().all(|f| f.contains("priority")),
+                "{:?}",
+                lifted
+            );
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (failures, lifted);
+    }
+}
@@ -0,0 +1,427 @@
+//! Xray log parsing and error classification
+//!
+//! Xray writes two kinds of lines to stdout:
+//!
+//! ```text
+//! 2024/01/01 12:00:00.123456 [Warning] [1873625412] proxy/vless/outbound: failed to find an available destination > ...
+//! 2024/01/01 12:00:00.123456 from 127.0.0.1:54321 accepted tcp:www.google.com:443 [http-in -> proxy]
+//! ```
+//!
+//! Error log lines carry a level, an optional session ID and the component
+//! that logged them; access log lines have neither. Lines without a
+//! timestamp (e.g. the startup banner) also occur. Error lines are
+//! classified into [`LogErrorKind`]s so callers can react to failures
+//! without matching message text themselves. [`LogClassifier`] also
+//! attributes failures to the outbound their session was routed to, so
+//! failures of direct connections are not blamed on the proxy server.
+
+use super::XrayLogEntry;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+
+/// Known kind of failure reported in an Xray log line
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum LogErrorKind {
+    /// A domain could not be resolved
+    DnsFailure,
+    /// The TLS / REALITY / protocol handshake with the server failed
+    HandshakeFailure,
+    /// The connection was rejected by routing (block outbound or rejected)
+    Rejected,
+    /// The remote side refused or reset the connection
+    ConnectionRefused,
+    /// Connecting or reading timed out
+    Timeout,
+}
+
+impl LogErrorKind {
+    /// Whether the failure points at the proxy server rather than the
+    /// destination or local routing
+    pub fn is_upstream(&self) -> bool {
+        matches!(
+            self,
+            LogErrorKind::HandshakeFailure
+                | LogErrorKind::ConnectionRefused
+                | LogErrorKind::Timeout
+        )
+    }
+}
+
+/// Failure detected in an Xray log line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DetectedError {
+    /// Kind of failure
+    pub kind: LogErrorKind,
+    /// Component that logged the failure, e.g. `proxy/vless/outbound`
+    pub component: Option<String>,
+    /// Tag of the outbound the failed connection was routed to, if known
+    #[serde(default)]
+    pub outbound: Option<String>,
+    /// Full log message
+    pub message: String,
+}
+
+impl DetectedError {
+    /// Whether the failure happened on a proxy server outbound
+    ///
+    /// Without a known outbound, failures logged by the direct, block and
+    /// DNS outbound handlers are not counted as proxy failures.
+    pub fn is_proxy_failure(&self) -> bool {
+        match &self.outbound {
+            Some(tag) => super::is_proxy_outbound(tag),
+            None => !self.component.as_deref().is_some_and(|component| {
+                NON_PROXY_COMPONENTS
+                    .iter()
+                    .any(|prefix| component.starts_with(prefix))
+            }),
+        }
+    }
+}
+
+/// Components of outbound handlers that do not use a proxy server
+const NON_PROXY_COMPONENTS: &[&str] = &["proxy/freedom", "proxy/blackhole", "proxy/dns"];
+
+/// Sessions whose outbound is remembered by [`LogClassifier`]
+const MAX_TRACKED_SESSIONS: usize = 1024;
+
+/// Message patterns per kind, checked in order on the lowercased message
+///
+/// Handshake failures come first: a failed handshake is often reported
+/// together with the connection error that followed it.
+const PATTERNS: &[(LogErrorKind, &[&str])] = &[
+    (
+        LogErrorKind::HandshakeFailure,
+        &[
+            "handshake",
+            "tls: ",
+            "x509: ",
+            "reality: ",
+            "invalid user",
+            "failed to read response header",
+        ],
+    ),
+    (
+        LogErrorKind::DnsFailure,
+        &[
+            "app/dns: ",
+            "failed to lookup",
+            "failed to resolve",
+            "no such host",
+            "server misbehaving",
+        ],
+    ),
+    (
+        LogErrorKind::ConnectionRefused,
+        &[
+            "connection refused",
+            "actively refused",
+            "connection reset",
+            "forcibly closed",
+        ],
+    ),
+    (
+        LogErrorKind::Timeout,
+        &["i/o timeout", "timed out", "deadline exceeded"],
+    ),
+    (
+        LogErrorKind::Rejected,
+        &["proxy/blackhole", "rejected", "blocked"],
+    ),
+];
+
+/// Parse a line of Xray output
+pub fn parse_log_line(line: &str) -> XrayLogEntry {
+    let line = line.trim_end();
+    let mut words = line.splitn(3, ' ');
+    let (timestamp, rest) = match (words.next(), words.next(), words.next()) {
+        (Some(date), Some(time), rest) if is_log_date(date) && is_log_time(time) => {
+            (format!("{} {}", date, time), rest.unwrap_or(""))
+        }
+        _ => (chrono::Utc::now().to_rfc3339(), line),
+    };
+
+    // Only a known level in leading brackets counts; access log lines end
+    // with "[inbound -> outbound]"
+    let Some((level, rest)) = split_level(rest) else {
+        return XrayLogEntry {
+            timestamp,
+            level: "Info".to_string(),
+            message: rest.trim().to_string(),
+            session_id: None,
+            component: None,
+        };
+    };
+
+    let (session_id, message) = match rest
+        .strip_prefix('[')
+        .and_then(|r| r.split_once(']'))
+        .and_then(|(id, message)| Some((id.parse::<u32>().ok()?, message.trim())))
+    {
+        Some((id, message)) => (Some(id), message),
+        None => (None, rest),
+    };
+
+    XrayLogEntry {
+        timestamp,
+        level: level.to_string(),
+        message: message.to_string(),
+        session_id,
+        component: component_of(message).map(str::to_string),
+    }
+}
+
+/// Classify the failure reported by an entry, if any
+///
+/// Access log lines are only classified when rejected; info and debug
+/// error log lines are not classified.
+pub fn classify(entry: &XrayLogEntry) -> Option<DetectedError> {
+    let access = crate::connection::active_connections::parse_access_log(&entry.message);
+    let (kind, outbound) = match access {
+        Some(record) => (
+            (!record.accepted).then_some(LogErrorKind::Rejected)?,
+            Some(record.outbound_tag).filter(|tag| !tag.is_empty()),
+        ),
+        None => {
+            if !matches!(entry.level.as_str(), "Warning" | "Error") {
+                return None;
+            }
+            let message = entry.message.to_lowercase();
+            let kind = PATTERNS
+                .iter()
+                .find(|(_, patterns)| patterns.iter().any(|p| message.contains(p)))
+                .map(|(kind, _)| *kind)?;
+            (kind, None)
+        }
+    };
+
+    Some(DetectedError {
+        kind,
+        component: entry.component.clone(),
+        outbound,
+        message: entry.message.clone(),
+    })
+}
+
+/// Classifies log entries, remembering the outbound of each session
+///
+/// Xray logs `taking detour [tag] for [...]` when routing a connection;
+/// later failures with the same session ID are attributed to that
+/// outbound. Detour lines are logged at the info level, so without it
+/// failures are attributed by component only.
+#[derive(Debug, Default)]
+pub struct LogClassifier {
+    /// Outbound tag by session ID
+    outbounds: HashMap<u32, String>,
+    /// Session IDs in the order they were seen, oldest first
+    order: VecDeque<u32>,
+}
+
+impl LogClassifier {
+    /// Create a classifier without known sessions
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note the detour of `entry`, if any, and classify it
+    pub fn classify(&mut self, entry: &XrayLogEntry) -> Option<DetectedError> {
+        if let (Some(session_id), Some(tag)) = (entry.session_id, detour_of(&entry.message)) {
+            if self.outbounds.insert(session_id, tag.to_string()).is_none() {
+                self.order.push_back(session_id);
+            }
+            if self.order.len() > MAX_TRACKED_SESSIONS {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.outbounds.remove(&oldest);
+                }
+            }
+        }
+
+        let mut error = classify(entry)?;
+        if error.outbound.is_none() {
+            error.outbound = entry
+                .session_id
+                .and_then(|id| self.outbounds.get(&id).cloned());
+        }
+        Some(error)
+    }
+}
+
+/// Outbound tag of a `taking detour [tag] for [...]` dispatcher message
+fn detour_of(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once("taking detour [")?;
+    let (tag, _) = rest.split_once(']')?;
+    (!tag.is_empty()).then_some(tag)
+}
+
+/// Split a leading `[Level]` off, normalizing the level name
+fn split_level(text: &str) -> Option<(&'static str, &str)> {
+    let (level, rest) = text.strip_prefix('[')?.split_once(']')?;
+    let level = match level.to_ascii_lowercase().as_str() {
+        "debug" => "Debug",
+        "info" => "Info",
+        "warning" | "warn" => "Warning",
+        "error" => "Error",
+        _ => return None,
+    };
+    Some((level, rest.trim()))
+}
+
+/// Component prefix of an error log message, e.g. `app/dns`
+fn component_of(message: &str) -> Option<&str> {
+    let (component, _) = message.split_once(": ")?;
+    let valid = component.contains('/')
+        && component
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '.'));
+    valid.then_some(component)
+}
+
+/// Whether a word is a `YYYY/MM/DD` log date
+fn is_log_date(word: &str) -> bool {
+    let parts: Vec<&str> = word.split('/').collect();
+    parts.len() == 3
+        && parts
+            .iter()
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Whether a word is a `HH:MM:SS[.ffffff]` log time
+pub(crate) fn is_log_time(word: &str) -> bool {
+    let clock = word.split('.').next().unwrap_or(word);
+    clock.len() == 8
+        && clock
+            .split(':')
+            .all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_error_line() {
+        let entry = parse_log_line(
+            "2024/01/01 12:00:00.123456 [Warning] [1873625412] proxy/vless/outbound: failed to find an available destination > common/retry: all retry attempts failed",
+        );
+        assert_eq!(entry.timestamp, "2024/01/01 12:00:00.123456");
+        assert_eq!(entry.level, "Warning");
+        assert_eq!(entry.session_id, Some(1873625412));
+        assert_eq!(entry.component.as_deref(), Some("proxy/vless/outbound"));
+        assert!(entry
+            .message
+            .starts_with("proxy/vless/outbound: failed to find"));
+
+        // Without session ID or component
+        let entry = parse_log_line("2024/01/01 12:00:00 [Info] Xray 1.8.7 started");
+        assert_eq!(entry.session_id, None);
+        assert_eq!(entry.component, None);
+        assert_eq!(entry.message, "Xray 1.8.7 started");
+    }
+
+    #[test]
+    fn test_parse_access_line() {
+        let entry = parse_log_line(
+            "2024/01/01 12:00:00 from 127.0.0.1:1 accepted tcp:a.com:443 [http-in -> proxy]",
+        );
+        assert_eq!(entry.level, "Info");
+        assert_eq!(entry.session_id, None);
+        assert_eq!(
+            entry.message,
+            "from 127.0.0.1:1 accepted tcp:a.com:443 [http-in -> proxy]"
+        );
+    }
+
+    #[test]
+    fn test_classify() {
+        let kind = |line: &str| classify(&parse_log_line(line)).map(|e| e.kind);
+
+        assert_eq!(
+            kind("2024/01/01 12:00:00 [Warning] [1] app/dns: failed to lookup ip for domain a.com"),
+            Some(LogErrorKind::DnsFailure)
+        );
+        assert_eq!(
+            kind("2024/01/01 12:00:00 [Warning] [2] proxy/vless/outbound: failed to find an available destination > common/retry: [dial tcp 1.2.3.4:443: connect: connection refused]"),
+            Some(LogErrorKind::ConnectionRefused)
+        );
+        assert_eq!(
+            kind("2024/01/01 12:00:00 [Warning] [3] transport/internet/tls: failed to handshake > remote error: tls: bad certificate"),
+            Some(LogErrorKind::HandshakeFailure)
+        );
+        assert_eq!(
+            kind("[Error] [4] proxy/freedom: failed to open connection > dial tcp: i/o timeout"),
+            Some(LogErrorKind::Timeout)
+        );
+        assert_eq!(
+            kind(
+                "2024/01/01 12:00:00 from 127.0.0.1:5 rejected tcp:ads.com:443 [http-in -> block]"
+            ),
+            Some(LogErrorKind::Rejected)
+        );
+        assert_eq!(
+            kind("2024/01/01 12:00:00 from 127.0.0.1:5 accepted tcp:refused.com:443 [proxy]"),
+            None
+        );
+        // Info lines are not classified
+        assert_eq!(
+            kind("2024/01/01 12:00:00 [Info] [5] app/dns: failed to lookup ip"),
+            None
+        );
+
+        let error = classify(&parse_log_line(
+            "2024/01/01 12:00:00 [Error] [6] app/dns: failed to resolve",
+        ))
+        .unwrap();
+        assert_eq!(error.component.as_deref(), Some("app/dns"));
+        assert!(!error.kind.is_upstream());
+        assert!(LogErrorKind::HandshakeFailure.is_upstream());
+    }
+
+    #[test]
+    fn test_classifier_attributes_outbound() {
+        let mut classifier = LogClassifier::new();
+        let mut classify = |line: &str| classifier.classify(&parse_log_line(line));
+
+        // Detour lines are not failures but name the session's outbound
+        assert!(classify(
+            "2024/01/01 12:00:00 [Info] [7] app/dispatcher: taking detour [direct] for [tcp:a.cn:443]"
+        )
+        .is_none());
+        assert!(classify(
+            "2024/01/01 12:00:00 [Info] [8] app/dispatcher: taking detour [proxy] for [tcp:b.com:443]"
+        )
+        .is_none());
+
+        let direct = classify(
+            "2024/01/01 12:00:01 [Warning] [7] transport/internet/tcp: dial tcp 1.2.3.4:443: i/o timeout",
+        )
+        .unwrap();
+        assert_eq!(direct.outbound.as_deref(), Some("direct"));
+        assert!(!direct.is_proxy_failure());
+
+        let proxy = classify(
+            "2024/01/01 12:00:01 [Warning] [8] transport/internet/tls: failed to handshake",
+        )
+        .unwrap();
+        assert_eq!(proxy.outbound.as_deref(), Some("proxy"));
+        assert!(proxy.is_proxy_failure());
+
+        // Unknown sessions are attributed by component
+        let freedom = classify(
+            "2024/01/01 12:00:02 [Warning] [9] proxy/freedom: failed to open connection > dial tcp: connection refused",
+        )
+        .unwrap();
+        assert_eq!(freedom.outbound, None);
+        assert!(!freedom.is_proxy_failure());
+        let vless = classify(
+            "2024/01/01 12:00:02 [Warning] [10] proxy/vless/outbound: failed to find an available destination > i/o timeout",
+        )
+        .unwrap();
+        assert!(vless.is_proxy_failure());
+
+        // Rejected access lines carry their outbound
+        let rejected = classify(
+            "2024/01/01 12:00:03 from 127.0.0.1:5 rejected tcp:ads.com:443 [http-in -> block]",
+        )
+        .unwrap();
+        assert_eq!(rejected.outbound.as_deref(), Some("block"));
+    }
+}
@@ -3,15 +3,51 @@
 //! This module handles integration with Xray Core, including process management,
 //! configuration generation, and status monitoring.
 
+pub mod api;
+pub mod cache;
+mod config_file;
+pub mod geodata;
+pub mod health;
+pub mod instances;
+pub mod integrity;
+pub mod limits;
+pub mod logs;
+pub mod process;
+pub mod routing_engine;
+pub mod sources;
 mod updater;
-
-pub use updater::{UpdateInfo, XrayUpdater};
-
-use crate::config::{ProxyProtocol, ProxyServerConfig};
+pub mod versions;
+
+pub use api::{ApiConfig, LiveUpdate, XrayApiClient};
+pub use cache::{CacheEntry, DownloadCache};
+pub use health::{
+    AggregatedHealth, HealthSummary, InstanceHealth, OutboundProbeResult, ProbeTarget,
+};
+pub use instances::{InstanceEvent, XrayInstances, DEFAULT_INSTANCE_ID};
+pub use limits::{ProcessPriority, ResourceLimits};
+pub use logs::{DetectedError, LogErrorKind};
+pub use routing_engine::{RouteMatch, RoutingEngine};
+pub use sources::{DownloadSettings, DownloadSource};
+pub use updater::{UpdateInfo, XrayRelease, XrayUpdater, GEO_ASSETS};
+pub use versions::{VersionSelection, VersionStore};
+
+use crate::config::dns::DnsSettings;
+use crate::config::inbound::{InboundAuth, InboundSettings};
+use crate::config::routing::{
+    MULTI_PROXY_TAG_PREFIX, OUTBOUND_BLOCK, OUTBOUND_DIRECT, OUTBOUND_PROXY,
+    PROCESS_RULES_SUPPORTED,
+};
+use crate::config::{
+    AppRule, EngineLogLevel, GfwList, PortConflictPolicy, ProxyProtocol, ProxyServerConfig,
+    RoutingRule, RoutingRuleSet,
+};
+use crate::utils::ports;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::path::PathBuf;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
@@ -39,8 +75,28 @@ pub enum XrayError {
     /// Xray Core not found
     #[error("Xray Core not found")]
     NotFound,
+    /// Disk space or permission preflight failed
+    #[error("{0}")]
+    Storage(#[from] crate::error::StorageError),
+    /// A local inbound port is already in use
+    #[error("Port {port} is already in use{}", .pid.map(|pid| format!(" by process {}", pid)).unwrap_or_default())]
+    PortInUse {
+        /// Conflicting port
+        port: u16,
+        /// PID of the process holding the port, if known
+        pid: Option<u32>,
+    },
+    /// Xray API call failed
+    #[error("Xray API error: {0}")]
+    Api(String),
+    /// Downloaded release failed verification
+    #[error("{0}")]
+    Integrity(#[from] crate::error::IntegrityError),
 }
 
+/// How long the previous instance keeps serving connections after a reload
+pub const RELOAD_DRAIN_DELAY: Duration = Duration::from_secs(30);
+
 /// Xray Core status
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum XrayStatus {
@@ -67,6 +123,12 @@ pub struct XrayHealth {
     pub last_check: std::time::SystemTime,
     /// Is process responsive
     pub is_responsive: bool,
+    /// Resident memory of the process
+    #[serde(default)]
+    pub memory_bytes: Option<u64>,
+    /// CPU usage since the previous check, in percent of one core
+    #[serde(default)]
+    pub cpu_percent: Option<f64>,
 }
 
 /// Xray log entry
@@ -78,6 +140,12 @@ pub struct XrayLogEntry {
     pub level: String,
     /// Message
     pub message: String,
+    /// Session ID of error log lines, linking lines of one connection
+    #[serde(default)]
+    pub session_id: Option<u32>,
+    /// Component that logged the line, e.g. `app/dns`
+    #[serde(default)]
+    pub component: Option<String>,
 }
 
 /// Xray status event
@@ -89,6 +157,82 @@ pub enum XrayEvent {
     LogReceived(XrayLogEntry),
     /// Health check result
     HealthCheck(XrayHealth),
+    /// An inbound port was in use and has been replaced by a free one
+    PortReassigned(PortReassignment),
+    /// A new Xray process was spawned, on start or reload
+    ProcessStarted(u32),
+    /// A log line reported a known kind of failure
+    ErrorDetected(DetectedError),
+    /// The process used too much memory and is being restarted
+    MemoryLimitExceeded(MemoryLimitExceeded),
+}
+
+/// Restart of an Xray process by the memory watchdog, see [`limits`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MemoryLimitExceeded {
+    /// Process that was over the limit
+    pub pid: u32,
+    /// Resident memory of the process
+    pub memory_bytes: u64,
+    /// Configured limit
+    pub limit_bytes: u64,
+}
+
+/// Inbound port change made by the [`PortConflictPolicy::Reassign`] policy
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PortReassignment {
+    /// Inbound protocol (http, socks)
+    pub protocol: String,
+    /// Configured port that was in use
+    pub old_port: u16,
+    /// Free port used instead
+    pub new_port: u16,
+}
+
+/// Lines of process output kept to explain an unexpected exit
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// Unexpected exit of an Xray process
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProcessExit {
+    /// Process ID
+    pub pid: u32,
+    /// Exit code, if the process exited normally
+    pub code: Option<i32>,
+    /// Signal that terminated the process (Unix only)
+    pub signal: Option<i32>,
+    /// Last lines written to stdout and stderr, oldest first
+    pub output_tail: Vec<String>,
+}
+
+impl ProcessExit {
+    /// Build from the exit status of process `pid` and its last output
+    fn new(pid: u32, status: std::process::ExitStatus, output_tail: Vec<String>) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Self {
+            pid,
+            code: status.code(),
+            signal,
+            output_tail,
+        }
+    }
+
+    /// One-line description, ending with the last output line if any
+    pub fn describe(&self) -> String {
+        let how = match (self.code, self.signal) {
+            (Some(code), _) => format!("exited with code {}", code),
+            (None, Some(signal)) => format!("was killed by signal {}", signal),
+            (None, None) => "exited".to_string(),
+        };
+        match self.output_tail.last() {
+            Some(line) => format!("Xray process {} {}: {}", self.pid, how, line),
+            None => format!("Xray process {} {}", self.pid, how),
+        }
+    }
 }
 
 /// Xray Core configuration
@@ -105,12 +249,28 @@ pub struct XrayConfig {
     pub outbounds: Vec<OutboundConfig>,
     /// Routing configuration
     pub routing: Option<RoutingConfig>,
+    /// Observatory configuration (outbound health probing for balancers)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observatory: Option<ObservatoryConfig>,
+    /// FakeDNS pools
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fakedns: Option<Vec<FakeDnsConfig>>,
+    /// API services for runtime changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api: Option<ApiConfig>,
+    /// Traffic counters, read through the API's StatsService
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Value>,
+    /// Local policy, enabling the outbound traffic counters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<Value>,
 }
 
 /// Log configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogConfig {
-    /// Log level
+    /// Log level (none, error, warning, info, debug)
+    #[serde(rename = "loglevel", alias = "level")]
     pub level: String,
     /// Access log path
     pub access: Option<String>,
@@ -121,8 +281,65 @@ pub struct LogConfig {
 /// DNS configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DnsConfig {
+    /// Static host overrides (domain -> address or address list)
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hosts: HashMap<String, Value>,
     /// DNS servers
-    pub servers: Vec<String>,
+    pub servers: Vec<DnsServerConfig>,
+    /// Client IP sent via EDNS Client Subnet
+    #[serde(rename = "clientIp", skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    /// Query strategy (UseIP, UseIPv4, UseIPv6)
+    #[serde(rename = "queryStrategy", skip_serializing_if = "Option::is_none")]
+    pub query_strategy: Option<String>,
+    /// Disable the DNS cache
+    #[serde(rename = "disableCache", default, skip_serializing_if = "is_false")]
+    pub disable_cache: bool,
+}
+
+/// DNS server entry, either a plain address or a detailed object
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DnsServerConfig {
+    /// Plain server address
+    Address(String),
+    /// Server with domain/IP constraints
+    Detailed(DnsServerObject),
+}
+
+/// Detailed DNS server configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsServerObject {
+    /// Server address
+    pub address: String,
+    /// Server port
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Domains this server is preferred for
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub domains: Vec<String>,
+    /// Expected IP ranges of answers
+    #[serde(rename = "expectIPs", default, skip_serializing_if = "Vec::is_empty")]
+    pub expect_ips: Vec<String>,
+    /// Skip this server when falling back
+    #[serde(rename = "skipFallback", default, skip_serializing_if = "is_false")]
+    pub skip_fallback: bool,
+}
+
+/// FakeDNS pool configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FakeDnsConfig {
+    /// Fake IP pool (CIDR)
+    #[serde(rename = "ipPool")]
+    pub ip_pool: String,
+    /// Pool size
+    #[serde(rename = "poolSize")]
+    pub pool_size: u32,
+}
+
+/// Serde helper for skipping `false` booleans
+fn is_false(value: &bool) -> bool {
+    !*value
 }
 
 /// Inbound configuration
@@ -136,6 +353,56 @@ pub struct InboundConfig {
     pub listen: Option<String>,
     /// Settings
     pub settings: Option<serde_json::Value>,
+    /// Traffic sniffing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sniffing: Option<SniffingConfig>,
+    /// Tag (optional identifier for routing and the API)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+impl InboundConfig {
+    /// Whether the inbound listens on an address other than loopback,
+    /// e.g. when shared on the LAN
+    pub fn listens_beyond_loopback(&self) -> bool {
+        self.listen
+            .as_deref()
+            .and_then(|listen| listen.parse::<std::net::IpAddr>().ok())
+            .is_some_and(|addr| !addr.is_loopback())
+    }
+}
+
+/// Inbound traffic sniffing configuration
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SniffingConfig {
+    /// Whether sniffing is enabled
+    pub enabled: bool,
+    /// Protocols whose sniffed destination overrides the target
+    /// (http, tls, quic, fakedns)
+    #[serde(rename = "destOverride", default)]
+    pub dest_override: Vec<String>,
+    /// Use sniffed domains for routing only, keeping the original destination
+    #[serde(rename = "routeOnly", default, skip_serializing_if = "is_false")]
+    pub route_only: bool,
+}
+
+impl SniffingConfig {
+    /// Sniff HTTP, TLS and QUIC, adding FakeDNS when a fake IP pool is in use
+    ///
+    /// Without FakeDNS the sniffed domain is only used for routing, so
+    /// connections still go to the resolved IP. With FakeDNS the destination
+    /// must be overridden, since fake IPs are not routable.
+    pub fn new(fakedns: bool) -> Self {
+        let mut dest_override = vec!["http".to_string(), "tls".to_string(), "quic".to_string()];
+        if fakedns {
+            dest_override.push("fakedns".to_string());
+        }
+        Self {
+            enabled: true,
+            dest_override,
+            route_only: !fakedns,
+        }
+    }
 }
 
 /// Outbound configuration
@@ -151,6 +418,9 @@ pub struct OutboundConfig {
     /// Stream settings
     #[serde(rename = "streamSettings")]
     pub stream_settings: Option<serde_json::Value>,
+    /// Mux settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mux: Option<serde_json::Value>,
 }
 
 /// Routing configuration
@@ -161,10 +431,94 @@ pub struct RoutingConfig {
     pub domain_strategy: Option<String>,
     /// Rules
     pub rules: Vec<serde_json::Value>,
+    /// Load balancers
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub balancers: Vec<BalancerConfig>,
+}
+
+/// Load balancing strategy for multi-outbound configurations
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum BalancerStrategy {
+    /// Prefer the outbound with the lowest observed latency
+    #[default]
+    LeastPing,
+    /// Pick a random healthy outbound
+    Random,
+    /// Rotate through healthy outbounds in order
+    RoundRobin,
+    /// Use the servers in the user's manual order: traffic goes to the first
+    /// one while it is healthy and falls back to the second otherwise
+    Manual,
+}
+
+impl BalancerStrategy {
+    /// Strategy type name as used in Xray configuration
+    ///
+    /// [`BalancerStrategy::Manual`] balances over the first server only, so
+    /// any type works; `random` needs no further settings.
+    pub fn as_xray_type(&self) -> &'static str {
+        match self {
+            BalancerStrategy::LeastPing => "leastPing",
+            BalancerStrategy::Random | BalancerStrategy::Manual => "random",
+            BalancerStrategy::RoundRobin => "roundRobin",
+        }
+    }
+
+    /// Parse a strategy name (`least_ping`, `random`, `round_robin`, `manual`)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "least_ping" => Some(BalancerStrategy::LeastPing),
+            "random" => Some(BalancerStrategy::Random),
+            "round_robin" => Some(BalancerStrategy::RoundRobin),
+            "manual" => Some(BalancerStrategy::Manual),
+            _ => None,
+        }
+    }
+}
+
+/// Balancer configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancerConfig {
+    /// Balancer tag (referenced by routing rules via `balancerTag`)
+    pub tag: String,
+    /// Outbound tag prefixes to balance between
+    pub selector: Vec<String>,
+    /// Balancing strategy
+    pub strategy: BalancerStrategyConfig,
+    /// Outbound used when none of the selected outbounds is healthy
+    #[serde(rename = "fallbackTag", skip_serializing_if = "Option::is_none")]
+    pub fallback_tag: Option<String>,
+}
+
+/// Balancer strategy configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalancerStrategyConfig {
+    /// Strategy type (leastPing, random, roundRobin)
+    #[serde(rename = "type")]
+    pub strategy_type: String,
+}
+
+/// Observatory configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ObservatoryConfig {
+    /// Outbound tag prefixes to probe
+    #[serde(rename = "subjectSelector")]
+    pub subject_selector: Vec<String>,
+    /// URL used for probing
+    #[serde(rename = "probeURL")]
+    pub probe_url: String,
+    /// Interval between probes (e.g. "30s")
+    #[serde(rename = "probeInterval")]
+    pub probe_interval: String,
+    /// Probe outbounds concurrently
+    #[serde(rename = "enableConcurrency")]
+    pub enable_concurrency: bool,
 }
 
 /// Xray Core manager
 pub struct XrayCore {
+    /// Instance ID, see [`instances`]
+    instance_id: String,
     /// Current status
     status: Arc<RwLock<XrayStatus>>,
     /// Xray process PID
@@ -174,7 +528,7 @@ pub struct XrayCore {
     /// Xray binary path
     binary_path: Arc<RwLock<Option<PathBuf>>>,
     /// Config generator
-    config_generator: Arc<XrayConfigGenerator>,
+    config_generator: Arc<std::sync::RwLock<XrayConfigGenerator>>,
     /// Updater
     updater: Arc<XrayUpdater>,
     /// Event broadcaster
@@ -183,6 +537,16 @@ pub struct XrayCore {
     start_time: Arc<RwLock<Option<std::time::Instant>>>,
     /// Health information
     health: Arc<RwLock<Option<XrayHealth>>>,
+    /// What to do when an inbound port is already in use
+    port_conflict_policy: Arc<std::sync::RwLock<PortConflictPolicy>>,
+    /// Whether traffic through the proxy succeeds, as last probed
+    responsive: Arc<AtomicBool>,
+    /// How the last unexpectedly exited process ended
+    last_exit: Arc<RwLock<Option<ProcessExit>>>,
+    /// Priority, CPU affinity and memory limit of spawned processes
+    resource_limits: Arc<std::sync::RwLock<ResourceLimits>>,
+    /// Installed Xray version to run instead of the selected one
+    pinned_version: Arc<std::sync::RwLock<Option<String>>>,
 }
 
 impl Default for XrayCore {
@@ -192,34 +556,87 @@ impl Default for XrayCore {
 }
 
 impl XrayCore {
-    /// Create a new Xray Core manager
+    /// Create a new Xray Core manager for the main connection
     pub fn new() -> Self {
-        // Determine bin directory
-        let bin_dir = if let Ok(exe_path) = std::env::current_exe() {
-            if let Some(exe_dir) = exe_path.parent() {
-                exe_dir.join("bin")
-            } else {
-                PathBuf::from("bin")
-            }
-        } else {
-            PathBuf::from("bin")
-        };
+        Self::for_instance(DEFAULT_INSTANCE_ID)
+    }
+
+    /// Create a new Xray Core manager for the instance `instance_id`
+    pub fn for_instance(instance_id: impl Into<String>) -> Self {
+        // Updates go to the data directory, the install directory may be read-only
+        let paths = crate::paths::app_paths();
+
+        let removed = config_file::remove_stale(&paths.xray_config_dir());
+        if removed > 0 {
+            tracing::info!("Removed {} stale Xray config files", removed);
+        }
 
         let (event_tx, _) = broadcast::channel(100);
 
         Self {
+            instance_id: instance_id.into(),
             status: Arc::new(RwLock::new(XrayStatus::Stopped)),
             process_pid: Arc::new(RwLock::new(None)),
             config: Arc::new(RwLock::new(None)),
             binary_path: Arc::new(RwLock::new(None)),
-            config_generator: Arc::new(XrayConfigGenerator::new()),
-            updater: Arc::new(XrayUpdater::new(bin_dir)),
+            config_generator: Arc::new(std::sync::RwLock::new(XrayConfigGenerator::new())),
+            updater: Arc::new(XrayUpdater::new(paths.xray_bin_dir(), paths.cache_dir)),
             event_tx,
             start_time: Arc::new(RwLock::new(None)),
             health: Arc::new(RwLock::new(None)),
+            port_conflict_policy: Arc::new(std::sync::RwLock::new(PortConflictPolicy::default())),
+            responsive: Arc::new(AtomicBool::new(true)),
+            last_exit: Arc::new(RwLock::new(None)),
+            resource_limits: Arc::new(std::sync::RwLock::new(ResourceLimits::default())),
+            pinned_version: Arc::new(std::sync::RwLock::new(None)),
+        }
+    }
+
+    /// Handle sharing this manager's state, for background tasks
+    fn handle(&self) -> Self {
+        Self {
+            instance_id: self.instance_id.clone(),
+            status: Arc::clone(&self.status),
+            process_pid: Arc::clone(&self.process_pid),
+            config: Arc::clone(&self.config),
+            binary_path: Arc::clone(&self.binary_path),
+            config_generator: Arc::clone(&self.config_generator),
+            updater: Arc::clone(&self.updater),
+            event_tx: self.event_tx.clone(),
+            start_time: Arc::clone(&self.start_time),
+            health: Arc::clone(&self.health),
+            port_conflict_policy: Arc::clone(&self.port_conflict_policy),
+            responsive: Arc::clone(&self.responsive),
+            last_exit: Arc::clone(&self.last_exit),
+            resource_limits: Arc::clone(&self.resource_limits),
+            pinned_version: Arc::clone(&self.pinned_version),
         }
     }
 
+    /// Instance ID
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Set the HTTP and SOCKS inbound ports of subsequently generated configs
+    pub fn set_inbound_ports(&self, http_port: u16, socks_port: u16) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.http_port = http_port;
+        generator.socks_port = socks_port;
+    }
+
+    /// HTTP and SOCKS inbound ports of subsequently generated configs
+    pub fn inbound_ports(&self) -> (u16, u16) {
+        let generator = self
+            .config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        (generator.http_port, generator.socks_port)
+    }
+
     /// Get updater reference
     pub fn updater(&self) -> &XrayUpdater {
         &self.updater
@@ -227,7 +644,7 @@ impl XrayCore {
 
     /// Generate Xray configuration from proxy config
     pub fn generate_config(&self, proxy_config: &ProxyServerConfig) -> XrayConfig {
-        self.config_generator.generate(proxy_config)
+        self.generator().generate(proxy_config)
     }
 
     /// Generate Xray configuration with specific proxy mode
@@ -236,7 +653,343 @@ impl XrayCore {
         proxy_config: &ProxyServerConfig,
         mode: &str,
     ) -> XrayConfig {
-        self.config_generator.generate_with_mode(proxy_config, mode)
+        self.generator().generate_with_mode(proxy_config, mode)
+    }
+
+    /// Generate load-balanced Xray configuration from multiple proxy configs
+    pub fn generate_multi_config_with_mode(
+        &self,
+        proxy_configs: &[ProxyServerConfig],
+        strategy: BalancerStrategy,
+        mode: &str,
+    ) -> XrayConfig {
+        self.generator()
+            .generate_multi_with_mode(proxy_configs, strategy, mode)
+    }
+
+    /// Set user-defined routing rule sets used for subsequent config generation
+    pub fn set_routing_rule_sets(&self, rule_sets: Vec<RoutingRuleSet>) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.routing_rule_sets = rule_sets;
+    }
+
+    /// Get the user-defined routing rule sets used for config generation
+    pub fn get_routing_rule_sets(&self) -> Vec<RoutingRuleSet> {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .routing_rule_sets
+            .clone()
+    }
+
+    /// Set the GFWList smart mode routes by, or None to use geosite/geoip
+    ///
+    /// Returns whether the routes changed.
+    pub fn set_gfwlist(&self, gfwlist: Option<GfwList>) -> bool {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        let changed = generator.gfwlist != gfwlist;
+        generator.gfwlist = gfwlist;
+        changed
+    }
+
+    /// Get the GFWList smart mode routes by, if any
+    pub fn gfwlist(&self) -> Option<GfwList> {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .gfwlist
+            .clone()
+    }
+
+    /// Set per-application split tunneling rules used for subsequent config generation
+    pub fn set_app_rules(&self, app_rules: Vec<AppRule>) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.app_rules = app_rules;
+    }
+
+    /// Get the per-application rules used for config generation
+    pub fn app_rules(&self) -> Vec<AppRule> {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .app_rules
+            .clone()
+    }
+
+    /// Set DNS settings used for subsequent config generation
+    pub fn set_dns_settings(&self, dns_settings: DnsSettings) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.dns_settings = dns_settings;
+    }
+
+    /// Get DNS settings used for config generation
+    pub fn dns_settings(&self) -> DnsSettings {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .dns_settings
+            .clone()
+    }
+
+    /// Set the engine log level used for subsequent config generation
+    pub fn set_engine_log_level(&self, level: EngineLogLevel) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.log_level = level;
+    }
+
+    /// Get the engine log level used for config generation
+    pub fn engine_log_level(&self) -> EngineLogLevel {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .log_level
+    }
+
+    /// Set the engine log level and apply it to the running instance
+    ///
+    /// Xray cannot change its log level through the API, so a running
+    /// instance is hot-reloaded with the updated config (see
+    /// [`XrayCore::reload`]); the port changes of the reload are returned.
+    /// When Xray is not running, the level only applies to the next start.
+    pub async fn apply_engine_log_level(
+        &self,
+        level: EngineLogLevel,
+    ) -> Result<Vec<PortReassignment>, XrayError> {
+        self.set_engine_log_level(level);
+        if *self.status.read().await != XrayStatus::Running {
+            return Ok(Vec::new());
+        }
+        let Some(mut config) = self.config.read().await.clone() else {
+            return Ok(Vec::new());
+        };
+        if config.log.level == level.as_str() {
+            return Ok(Vec::new());
+        }
+
+        config.log.level = level.as_str().to_string();
+        tracing::info!("Reloading Xray with engine log level {}", level);
+        self.reload(config).await
+    }
+
+    /// Set local inbound settings used for subsequent config generation
+    pub fn set_inbound_settings(&self, inbound_settings: InboundSettings) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.inbound_settings = inbound_settings;
+    }
+
+    /// Local inbound settings used for config generation
+    pub fn inbound_settings(&self) -> InboundSettings {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .inbound_settings
+            .clone()
+    }
+
+    /// Enable or disable the Xray API for subsequent config generation
+    ///
+    /// With the API enabled, [`XrayCore::apply_live`] can switch outbounds
+    /// and routing of the running instance without restarting it.
+    pub fn set_api_enabled(&self, enabled: bool) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.api = enabled;
+    }
+
+    /// Bind outbound connections to a network interface for subsequent config
+    /// generation
+    ///
+    /// Used while TUN mode captures the default route, so Xray's own traffic
+    /// leaves through the physical interface instead of looping back into
+    /// the tunnel.
+    pub fn set_outbound_interface(&self, interface: Option<String>) {
+        let mut generator = self
+            .config_generator
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+        generator.outbound_interface = interface;
+    }
+
+    /// Network interface outbound connections are bound to, set while TUN
+    /// mode captures the default route
+    pub fn outbound_interface(&self) -> Option<String> {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .outbound_interface
+            .clone()
+    }
+
+    /// Set the policy applied when an inbound port is already in use at start
+    pub fn set_port_conflict_policy(&self, policy: PortConflictPolicy) {
+        *self
+            .port_conflict_policy
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = policy;
+    }
+
+    /// How inbound port conflicts are handled
+    pub fn port_conflict_policy(&self) -> PortConflictPolicy {
+        *self
+            .port_conflict_policy
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the resource limits of subsequently spawned processes
+    pub fn set_resource_limits(&self, limits: ResourceLimits) {
+        *self
+            .resource_limits
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = limits;
+    }
+
+    /// Resource limits of spawned processes
+    pub fn resource_limits(&self) -> ResourceLimits {
+        self.resource_limits
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Set the resource limits and apply them to the running process
+    ///
+    /// The memory limit takes effect at the next health check. Settings the
+    /// platform does not support are only logged.
+    pub async fn apply_resource_limits(&self, limits: ResourceLimits) -> Result<(), XrayError> {
+        limits.validate().map_err(XrayError::Config)?;
+        self.set_resource_limits(limits.clone());
+        if let Some(pid) = *self.process_pid.read().await {
+            for failure in limits::apply(pid, &limits) {
+                tracing::warn!("Xray process {}: {}", pid, failure);
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the inbound ports of `config` are free
+    ///
+    /// With [`PortConflictPolicy::Fail`] the first conflict is returned as
+    /// [`XrayError::PortInUse`]. With [`PortConflictPolicy::Reassign`] each
+    /// conflicting inbound is moved to a free port, the generator is updated
+    /// so later configs keep it, and a [`XrayEvent::PortReassigned`] is sent.
+    pub async fn check_ports(
+        &self,
+        config: &mut XrayConfig,
+    ) -> Result<Vec<PortReassignment>, XrayError> {
+        let policy = *self
+            .port_conflict_policy
+            .read()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut reassignments = Vec::new();
+
+        for inbound in &mut config.inbounds {
+            let listen = inbound.listen.as_deref();
+            // Port 0 asks for any free port (used by the API inbound)
+            if inbound.port == 0 {
+                inbound.port = ports::find_free_port(listen)?;
+                continue;
+            }
+            if ports::is_port_available(listen, inbound.port) {
+                continue;
+            }
+
+            match policy {
+                PortConflictPolicy::Fail => {
+                    return Err(XrayError::PortInUse {
+                        port: inbound.port,
+                        pid: ports::port_owner_pid(inbound.port).await,
+                    });
+                }
+                PortConflictPolicy::Reassign => {
+                    let new_port = ports::find_free_port(listen)?;
+                    tracing::warn!(
+                        "{} port {} is in use, using {} instead",
+                        inbound.protocol,
+                        inbound.port,
+                        new_port
+                    );
+                    reassignments.push(PortReassignment {
+                        protocol: inbound.protocol.clone(),
+                        old_port: inbound.port,
+                        new_port,
+                    });
+                    inbound.port = new_port;
+                }
+            }
+        }
+
+        self.apply_port_reassignments(&reassignments, &config.inbounds);
+        Ok(reassignments)
+    }
+
+    /// Keep reassigned ports for later generated configs and announce them
+    ///
+    /// Only ports of untagged inbounds, the generator's own HTTP and SOCKS
+    /// inbounds, are kept; `inbounds` are the already moved inbounds.
+    fn apply_port_reassignments(
+        &self,
+        reassignments: &[PortReassignment],
+        inbounds: &[InboundConfig],
+    ) {
+        if reassignments.is_empty() {
+            return;
+        }
+
+        {
+            let mut generator = self
+                .config_generator
+                .write()
+                .unwrap_or_else(|e| e.into_inner());
+            for reassignment in reassignments.iter().filter(|reassignment| {
+                inbounds
+                    .iter()
+                    .any(|inbound| inbound.tag.is_none() && inbound.port == reassignment.new_port)
+            }) {
+                match reassignment.protocol.as_str() {
+                    "http" => generator.http_port = reassignment.new_port,
+                    "socks" => generator.socks_port = reassignment.new_port,
+                    _ => {}
+                }
+            }
+        }
+        for reassignment in reassignments {
+            let _ = self
+                .event_tx
+                .send(XrayEvent::PortReassigned(reassignment.clone()));
+        }
+    }
+
+    /// Get config generator
+    fn generator(&self) -> std::sync::RwLockReadGuard<'_, XrayConfigGenerator> {
+        self.config_generator
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// How the last unexpectedly exited Xray process ended, if any
+    pub async fn last_exit(&self) -> Option<ProcessExit> {
+        self.last_exit.read().await.clone()
     }
 
     /// Get current status
@@ -249,6 +1002,39 @@ impl XrayCore {
         &self.updater
     }
 
+    /// Run the installed Xray `version` from the next start on, or the
+    /// version selected in the updater for None
+    ///
+    /// Used for profiles that need a particular Xray release.
+    pub fn set_pinned_version(&self, version: Option<String>) {
+        *self
+            .pinned_version
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = version;
+    }
+
+    /// Xray version run instead of the selected one, if any
+    pub fn pinned_version(&self) -> Option<String> {
+        self.pinned_version
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Whether an Xray binary to run is installed
+    ///
+    /// False on a first run without a bundled binary, until
+    /// [`XrayUpdater::bootstrap`] installs one.
+    pub fn binary_available(&self) -> bool {
+        self.find_xray_binary().is_ok()
+    }
+
+    /// Version of the Xray binary that runs on the next start
+    pub async fn binary_version(&self) -> Result<String, XrayError> {
+        let binary = self.find_xray_binary()?;
+        updater::binary_version(std::path::Path::new(&binary)).await
+    }
+
     /// Set Xray binary path manually
     pub async fn set_binary_path(&self, path: PathBuf) -> Result<(), XrayError> {
         if !path.exists() {
@@ -266,13 +1052,15 @@ impl XrayCore {
     }
 
     /// Start Xray Core with configuration
-    pub async fn start(&self, config: XrayConfig) -> Result<(), XrayError> {
+    pub async fn start(&self, mut config: XrayConfig) -> Result<(), XrayError> {
         let current_status = self.status.read().await.clone();
 
         if current_status == XrayStatus::Running {
             return Ok(());
         }
 
+        self.check_ports(&mut config).await?;
+
         // Update status to Starting
         self.update_status(XrayStatus::Starting).await;
 
@@ -282,43 +1070,59 @@ impl XrayCore {
             *current_config = Some(config.clone());
         }
 
+        let pid = match self.spawn_process(&config).await {
+            Ok(pid) => pid,
+            Err(e) => {
+                self.update_status(XrayStatus::Error(e.to_string())).await;
+                return Err(e);
+            }
+        };
+
+        // Store PID
+        {
+            let mut process_pid = self.process_pid.write().await;
+            *process_pid = Some(pid);
+        }
+
+        // Record start time
+        {
+            let mut start_time = self.start_time.write().await;
+            *start_time = Some(std::time::Instant::now());
+        }
+
+        // Start health monitoring
+        self.start_monitoring();
+
+        // Update status to Running
+        self.update_status(XrayStatus::Running).await;
+
+        tracing::info!("Xray Core started successfully");
+        Ok(())
+    }
+
+    /// Write `config` and spawn an Xray process for it, returning its PID
+    ///
+    /// Logs of the new process are forwarded to the event channel. Its exit
+    /// is only reported as [`XrayStatus::Stopped`] while it is still the
+    /// current process, so replacing an instance on reload is not mistaken
+    /// for a crash.
+    async fn spawn_process(&self, config: &XrayConfig) -> Result<u32, XrayError> {
         // Find Xray Core binary
         let xray_path = self.find_xray_binary()?;
 
         // Generate configuration file
         let config_content =
-            serde_json::to_string_pretty(&config).map_err(|e| XrayError::Config(e.to_string()))?;
-
-        // Log the configuration for debugging
-        tracing::info!("Generated Xray configuration:\n{}", config_content);
-
-        // Write config to Xray binary directory
-        let xray_path_obj = std::path::Path::new(&xray_path);
-        let xray_dir = xray_path_obj
-            .parent()
-            .ok_or_else(|| XrayError::Process("Failed to get Xray directory".to_string()))?;
-        let config_path = xray_dir.join("config.json");
-
-        tracing::info!("Writing config to: {:?}", config_path);
-        tracing::info!("Xray directory: {:?}", xray_dir);
+            serde_json::to_string_pretty(config).map_err(|e| XrayError::Config(e.to_string()))?;
 
-        // 检查目录权限
-        if let Ok(metadata) = std::fs::metadata(xray_dir) {
-            tracing::info!("Xray directory permissions: {:?}", metadata.permissions());
-        } else {
-            tracing::error!("Failed to get xray directory metadata");
+        // Log the configuration for debugging, without credentials
+        if tracing::enabled!(tracing::Level::DEBUG) {
+            match crate::utils::redact::redacted_json(config) {
+                Ok(redacted) => tracing::debug!("Generated Xray configuration:\n{}", redacted),
+                Err(e) => tracing::debug!("Failed to redact Xray configuration: {}", e),
+            }
         }
 
-        std::fs::write(&config_path, &config_content).map_err(|e| {
-            tracing::error!(
-                "Failed to write config file: {} (kind: {:?}, raw_os_error: {:?})",
-                e,
-                e.kind(),
-                e.raw_os_error()
-            );
-            e
-        })?;
-        tracing::info!("Xray config written to: {:?}", config_path);
+        let config_path = self.write_config_file(&config_content).await?;
 
         // Start Xray process
         // Use nohup to detach the process completely
@@ -370,6 +1174,7 @@ impl XrayCore {
                 e.kind(),
                 e.raw_os_error()
             );
+            let _ = std::fs::remove_file(&config_path);
             XrayError::Process(format!("Failed to spawn Xray process: {}", e))
         })?;
 
@@ -379,10 +1184,11 @@ impl XrayCore {
 
         tracing::info!("Xray process spawned with PID: {}", pid);
 
-        // Store PID
-        {
-            let mut process_pid = self.process_pid.write().await;
-            *process_pid = Some(pid);
+        let resource_limits = self.resource_limits();
+        if !resource_limits.is_default() {
+            for failure in limits::apply(pid, &resource_limits) {
+                tracing::warn!("Xray process {}: {}", pid, failure);
+            }
         }
 
         // Wait a moment to check if process starts successfully
@@ -392,6 +1198,7 @@ impl XrayCore {
         match child.try_wait() {
             Ok(Some(status)) => {
                 tracing::error!("Xray process exited immediately with status: {}", status);
+                let _ = tokio::fs::remove_file(&config_path).await;
                 return Err(XrayError::Process(format!(
                     "Xray process exited immediately with status: {}",
                     status
@@ -420,16 +1227,57 @@ impl XrayCore {
             .ok_or_else(|| XrayError::Process("Failed to capture stderr".to_string()))?;
 
         // Start log monitoring
-        Self::monitor_logs(stdout, stderr, self.event_tx.clone());
+        let output_tail = Arc::new(std::sync::Mutex::new(VecDeque::new()));
+        let readers = Self::monitor_logs(
+            stdout,
+            stderr,
+            self.event_tx.clone(),
+            Arc::clone(&output_tail),
+        );
 
         // Spawn a background task to wait for the child process
         // This prevents zombie processes
         let event_tx = self.event_tx.clone();
+        let process_pid = Arc::clone(&self.process_pid);
+        let status = Arc::clone(&self.status);
+        let last_exit = Arc::clone(&self.last_exit);
         tokio::spawn(async move {
-            match child.wait().await {
-                Ok(status) => {
-                    tracing::warn!("Xray process exited with status: {}", status);
-                    let _ = event_tx.send(XrayEvent::StatusChanged(XrayStatus::Stopped));
+            let result = child.wait().await;
+            if let Err(e) = tokio::fs::remove_file(&config_path).await {
+                tracing::debug!("Failed to remove Xray config {:?}: {}", config_path, e);
+            }
+            // Let the readers pick up the last lines, e.g. a panic message
+            for reader in readers {
+                let _ = tokio::time::timeout(Duration::from_secs(1), reader).await;
+            }
+
+            match result {
+                Ok(exit_status) => {
+                    let tail: Vec<String> = output_tail
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .iter()
+                        .cloned()
+                        .collect();
+                    let exit = ProcessExit::new(pid, exit_status, tail);
+
+                    let mut current_pid = process_pid.write().await;
+                    if *current_pid != Some(pid) {
+                        tracing::info!("Xray process {} exited: {}", pid, exit_status);
+                        return;
+                    }
+                    *current_pid = None;
+                    drop(current_pid);
+
+                    let description = exit.describe();
+                    tracing::error!("{}", description);
+                    for line in &exit.output_tail {
+                        tracing::debug!("Xray output: {}", line);
+                    }
+                    let error = XrayStatus::Error(description);
+                    *status.write().await = error.clone();
+                    *last_exit.write().await = Some(exit);
+                    let _ = event_tx.send(XrayEvent::StatusChanged(error));
                 }
                 Err(e) => {
                     tracing::error!("Error waiting for Xray process: {}", e);
@@ -437,20 +1285,8 @@ impl XrayCore {
             }
         });
 
-        // Record start time
-        {
-            let mut start_time = self.start_time.write().await;
-            *start_time = Some(std::time::Instant::now());
-        }
-
-        // Start health monitoring
-        self.start_monitoring();
-
-        // Update status to Running
-        self.update_status(XrayStatus::Running).await;
-
-        tracing::info!("Xray Core started successfully");
-        Ok(())
+        let _ = self.event_tx.send(XrayEvent::ProcessStarted(pid));
+        Ok(pid)
     }
 
     /// Stop Xray Core
@@ -465,116 +1301,215 @@ impl XrayCore {
         self.update_status(XrayStatus::Stopping).await;
 
         // Kill process using PID
+        let pid = self.process_pid.write().await.take();
+        if let Some(pid) = pid {
+            Self::kill_process(pid).await;
+        }
+
+        // Clear start time
         {
-            let mut process_pid = self.process_pid.write().await;
-            if let Some(pid) = process_pid.take() {
-                tracing::info!("Killing Xray process with PID: {}", pid);
+            let mut start_time = self.start_time.write().await;
+            *start_time = None;
+        }
 
-                #[cfg(unix)]
-                {
-                    // Unix/Linux/macOS: Use libc::kill
-                    tracing::info!("Sending SIGTERM to Xray process (PID: {})", pid);
-                    let result = unsafe { libc::kill(pid as i32, libc::SIGTERM) };
-                    if result == 0 {
-                        tracing::info!("SIGTERM sent successfully");
-                    } else {
-                        tracing::warn!("Failed to send SIGTERM (result: {})", result);
-                    }
+        // Clear health info
+        {
+            let mut health = self.health.write().await;
+            *health = None;
+        }
 
-                    // Wait a bit for graceful shutdown
-                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        // Update status to Stopped
+        self.update_status(XrayStatus::Stopped).await;
 
-                    // Force kill if still running
-                    tracing::info!("Sending SIGKILL to Xray process (PID: {})", pid);
-                    let result = unsafe { libc::kill(pid as i32, libc::SIGKILL) };
-                    if result == 0 {
-                        tracing::info!("SIGKILL sent successfully, process terminated");
+        tracing::info!("Xray Core stopped");
+        Ok(())
+    }
+
+    /// Terminate an Xray process, forcing it if it does not exit in time
+    pub(crate) async fn kill_process(pid: u32) {
+        tracing::info!("Killing Xray process with PID: {}", pid);
+
+        #[cfg(unix)]
+        {
+            // Unix/Linux/macOS: Use libc::kill
+            tracing::info!("Sending SIGTERM to Xray process (PID: {})", pid);
+            let result = unsafe { libc::kill(pid as i32, libc::SIGTERM) };
+            if result == 0 {
+                tracing::info!("SIGTERM sent successfully");
+            } else {
+                tracing::warn!("Failed to send SIGTERM (result: {})", result);
+            }
+
+            // Wait a bit for graceful shutdown
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+            // Force kill if still running
+            tracing::info!("Sending SIGKILL to Xray process (PID: {})", pid);
+            let result = unsafe { libc::kill(pid as i32, libc::SIGKILL) };
+            if result == 0 {
+                tracing::info!("SIGKILL sent successfully, process terminated");
+            } else {
+                tracing::warn!(
+                    "Failed to send SIGKILL (result: {}), process may have already exited",
+                    result
+                );
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            // Windows: Use taskkill command
+            use std::process::Command;
+
+            // CREATE_NO_WINDOW flag to prevent console window from appearing
+            const CREATE_NO_WINDOW: u32 = 0x08000000;
+
+            // Try graceful termination first
+            tracing::info!(
+                "Attempting graceful termination of Xray process (PID: {})",
+                pid
+            );
+            match Command::new("taskkill")
+                .args(&["/PID", &pid.to_string(), "/T"])
+                .creation_flags(CREATE_NO_WINDOW)
+                .output()
+            {
+                Ok(output) => {
+                    if output.status.success() {
+                        tracing::info!("Graceful termination command sent successfully");
                     } else {
                         tracing::warn!(
-                            "Failed to send SIGKILL (result: {}), process may have already exited",
-                            result
+                            "Graceful termination failed: {}",
+                            String::from_utf8_lossy(&output.stderr)
                         );
                     }
                 }
+                Err(e) => {
+                    tracing::error!("Failed to execute taskkill: {}", e);
+                }
+            }
 
-                #[cfg(windows)]
-                {
-                    // Windows: Use taskkill command
-                    use std::process::Command;
-
-                    // CREATE_NO_WINDOW flag to prevent console window from appearing
-                    const CREATE_NO_WINDOW: u32 = 0x08000000;
-
-                    // Try graceful termination first
-                    tracing::info!(
-                        "Attempting graceful termination of Xray process (PID: {})",
-                        pid
-                    );
-                    match Command::new("taskkill")
-                        .args(&["/PID", &pid.to_string(), "/T"])
-                        .creation_flags(CREATE_NO_WINDOW)
-                        .output()
-                    {
-                        Ok(output) => {
-                            if output.status.success() {
-                                tracing::info!("Graceful termination command sent successfully");
-                            } else {
-                                tracing::warn!(
-                                    "Graceful termination failed: {}",
-                                    String::from_utf8_lossy(&output.stderr)
-                                );
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to execute taskkill: {}", e);
-                        }
-                    }
-
-                    // Wait a bit for graceful shutdown
-                    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-                    // Force kill if still running
-                    tracing::info!("Force killing Xray process (PID: {})", pid);
-                    match Command::new("taskkill")
-                        .args(&["/PID", &pid.to_string(), "/T", "/F"])
-                        .creation_flags(CREATE_NO_WINDOW)
-                        .output()
-                    {
-                        Ok(output) => {
-                            if output.status.success() {
-                                tracing::info!("Xray process force killed successfully");
-                            } else {
-                                tracing::warn!(
-                                    "Force kill failed (process may have already exited): {}",
-                                    String::from_utf8_lossy(&output.stderr)
-                                );
-                            }
-                        }
-                        Err(e) => {
-                            tracing::error!("Failed to execute taskkill /F: {}", e);
-                        }
+            // Wait a bit for graceful shutdown
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+            // Force kill if still running
+            tracing::info!("Force killing Xray process (PID: {})", pid);
+            match Command::new("taskkill")
+                .args(&["/PID", &pid.to_string(), "/T", "/F"])
+                .creation_flags(CREATE_NO_WINDOW)
+                .output()
+            {
+                Ok(output) => {
+                    if output.status.success() {
+                        tracing::info!("Xray process force killed successfully");
+                    } else {
+                        tracing::warn!(
+                            "Force kill failed (process may have already exited): {}",
+                            String::from_utf8_lossy(&output.stderr)
+                        );
                     }
                 }
+                Err(e) => {
+                    tracing::error!("Failed to execute taskkill /F: {}", e);
+                }
             }
         }
+    }
 
-        // Clear start time
-        {
-            let mut start_time = self.start_time.write().await;
-            *start_time = None;
+    /// Replace the running configuration without dropping the proxy
+    ///
+    /// The new instance is started next to the old one on fresh (staggered)
+    /// inbound ports. Only once it is running does it become the current
+    /// process; the old instance keeps serving in-flight connections for
+    /// [`RELOAD_DRAIN_DELAY`] and is then stopped. The port changes are
+    /// returned and announced via [`XrayEvent::PortReassigned`] before the
+    /// drain starts, so the system proxy can be pointed at the new ports
+    /// while the old ones still work. If the new instance fails
+    /// to start, the old one keeps running untouched.
+    ///
+    /// When Xray is not running this is the same as [`XrayCore::start`].
+    pub async fn reload(&self, mut config: XrayConfig) -> Result<Vec<PortReassignment>, XrayError> {
+        if *self.status.read().await != XrayStatus::Running {
+            self.start(config).await?;
+            return Ok(Vec::new());
         }
 
-        // Clear health info
+        let mut reassignments = Vec::new();
+        for inbound in &mut config.inbounds {
+            let new_port = ports::find_free_port(inbound.listen.as_deref())?;
+            reassignments.push(PortReassignment {
+                protocol: inbound.protocol.clone(),
+                old_port: inbound.port,
+                new_port,
+            });
+            inbound.port = new_port;
+        }
+
+        let new_pid = self.spawn_process(&config).await?;
+        let old_pid = self.process_pid.write().await.replace(new_pid);
+        self.apply_port_reassignments(&reassignments, &config.inbounds);
+        *self.config.write().await = Some(config);
+        *self.start_time.write().await = Some(std::time::Instant::now());
+        tracing::info!("Xray reloaded, new process PID: {}", new_pid);
+
+        if let Some(old_pid) = old_pid {
+            tokio::spawn(async move {
+                tokio::time::sleep(RELOAD_DRAIN_DELAY).await;
+                tracing::info!("Stopping previous Xray process (PID: {})", old_pid);
+                Self::kill_process(old_pid).await;
+            });
+        }
+
+        Ok(reassignments)
+    }
+
+    /// Apply `config` to the running instance through the Xray API
+    ///
+    /// Only outbounds and routing can change at runtime. Returns `Ok(false)`
+    /// without touching the instance if Xray is not running, the API is not
+    /// enabled, or anything else differs, so the caller can fall back to
+    /// [`XrayCore::reload`]. An `Err` means the instance may be partially
+    /// updated and should be reloaded.
+    pub async fn apply_live(&self, mut config: XrayConfig) -> Result<bool, XrayError> {
+        if *self.status.read().await != XrayStatus::Running {
+            return Ok(false);
+        }
+        let Some(current) = self.config.read().await.clone() else {
+            return Ok(false);
+        };
+        let Some(port) = api::api_port(&current) else {
+            return Ok(false);
+        };
+
+        // The API inbound keeps the port it was started with
+        for inbound in config
+            .inbounds
+            .iter_mut()
+            .filter(|inbound| inbound.tag.as_deref() == Some(api::API_TAG))
         {
-            let mut health = self.health.write().await;
-            *health = None;
+            inbound.port = port;
         }
 
-        // Update status to Stopped
-        self.update_status(XrayStatus::Stopped).await;
+        let Some(update) = LiveUpdate::plan(&current, &config) else {
+            return Ok(false);
+        };
 
-        tracing::info!("Xray Core stopped");
-        Ok(())
+        if !update.is_empty() {
+            let client = XrayApiClient::new(PathBuf::from(self.find_xray_binary()?), port);
+            client.apply(&update).await?;
+            tracing::info!(
+                "Applied live update: {} outbounds replaced, routing {}",
+                update.add_outbounds.len(),
+                if update.routing.is_some() {
+                    "updated"
+                } else {
+                    "unchanged"
+                }
+            );
+        }
+
+        *self.config.write().await = Some(config);
+        Ok(true)
     }
 
     /// Restart Xray Core
@@ -587,19 +1522,73 @@ impl XrayCore {
         self.stop().await?;
 
         if let Some(config) = config {
+            crate::metrics::record_xray_restart();
             self.start(config).await?;
         }
 
         Ok(())
     }
 
+    /// Replace the running process with a new one for the same config
+    ///
+    /// The old process is stopped first so the new one can bind the same
+    /// ports. If the new process fails to start, the status becomes an
+    /// error, which the connection manager treats like a crash.
+    async fn respawn(&self) -> Result<u32, XrayError> {
+        let config = self
+            .config
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| XrayError::Process("Xray is not running".to_string()))?;
+
+        if let Some(pid) = self.process_pid.write().await.take() {
+            Self::kill_process(pid).await;
+        }
+        match self.spawn_process(&config).await {
+            Ok(pid) => {
+                *self.process_pid.write().await = Some(pid);
+                *self.start_time.write().await = Some(std::time::Instant::now());
+                crate::metrics::record_xray_restart();
+                Ok(pid)
+            }
+            Err(e) => {
+                self.update_status(XrayStatus::Error(e.to_string())).await;
+                Err(e)
+            }
+        }
+    }
+
     /// Find bundled Xray binary in application directory
-    fn find_xray_binary(&self) -> Result<String, XrayError> {
+    pub(crate) fn find_xray_binary(&self) -> Result<String, XrayError> {
         #[cfg(windows)]
         let binary_name = "xray.exe";
         #[cfg(not(windows))]
         let binary_name = "xray";
 
+        // Priority 0: installed by the updater, the pinned version first
+        if let Some(version) = self.pinned_version() {
+            let versions = self.updater.versions();
+            if versions.is_installed(&version) {
+                let pinned_binary = versions.binary_path(&version);
+                tracing::info!("Found pinned Xray binary: {:?}", pinned_binary);
+                return Ok(pinned_binary.to_string_lossy().to_string());
+            }
+            tracing::warn!(
+                "Pinned Xray Core {} is not installed, using the selected version",
+                version
+            );
+        }
+        if let Some(selected_binary) = self.updater.versions().active_binary() {
+            tracing::info!("Found selected Xray binary: {:?}", selected_binary);
+            return Ok(selected_binary.to_string_lossy().to_string());
+        }
+        let updated_binary = self.updater.bin_dir().join(binary_name);
+        if updated_binary.exists() {
+            tracing::info!("Found updated Xray binary: {:?}", updated_binary);
+            return Ok(updated_binary.to_string_lossy().to_string());
+        }
+
         // Check application directory (bundled Xray Core)
         if let Ok(exe_path) = std::env::current_exe() {
             tracing::info!("Current executable path: {:?}", exe_path);
@@ -632,18 +1621,27 @@ impl XrayCore {
         Err(XrayError::NotFound)
     }
 
-    /// Write configuration to temporary file
-    #[allow(dead_code)]
-    async fn write_config_file(&self, config: &XrayConfig) -> Result<PathBuf, XrayError> {
-        let config_content =
-            serde_json::to_string_pretty(config).map_err(|e| XrayError::Config(e.to_string()))?;
+    /// Write the configuration file of a new Xray process to the data directory
+    ///
+    /// Every process gets its own file, removed again when the process exits.
+    async fn write_config_file(&self, config_content: &str) -> Result<PathBuf, XrayError> {
+        let config_dir = crate::paths::app_paths().xray_config_dir();
+        crate::utils::preflight::preflight(&config_dir, config_content.len() as u64)?;
+        let config_path = config_file::unique_path(&config_dir);
 
-        let config_path = std::env::temp_dir().join("v8ray_xray_config.json");
         tokio::fs::write(&config_path, config_content)
             .await
-            .map_err(XrayError::Io)?;
+            .map_err(|e| {
+                tracing::error!(
+                    "Failed to write config file {:?}: {} (kind: {:?})",
+                    config_path,
+                    e,
+                    e.kind()
+                );
+                XrayError::Io(e)
+            })?;
 
-        tracing::debug!("Xray config written to: {:?}", config_path);
+        tracing::info!("Xray config written to: {:?}", config_path);
         Ok(config_path)
     }
 
@@ -653,6 +1651,47 @@ impl XrayCore {
         *status == XrayStatus::Running
     }
 
+    /// Client for the API of the running instance, if the API is enabled
+    pub async fn api_client(&self) -> Option<XrayApiClient> {
+        if !self.is_running().await {
+            return None;
+        }
+        let port = api::api_port(self.config.read().await.as_ref()?)?;
+        let binary = self.find_xray_binary().ok()?;
+        Some(XrayApiClient::new(PathBuf::from(binary), port))
+    }
+
+    /// Get the configuration Xray was last started or reloaded with
+    pub async fn running_config(&self) -> Option<XrayConfig> {
+        self.config.read().await.clone()
+    }
+
+    /// Directory Xray reads its geo data files from: `XRAY_LOCATION_ASSET`
+    /// if set, otherwise the directory of the Xray binary
+    pub fn asset_dir(&self) -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("XRAY_LOCATION_ASSET").filter(|d| !d.is_empty()) {
+            return Some(PathBuf::from(dir));
+        }
+        let binary = self.find_xray_binary().ok()?;
+        Path::new(&binary).parent().map(Path::to_path_buf)
+    }
+
+    /// Update the geo data files in the [asset directory](Self::asset_dir)
+    pub async fn update_geo_assets(&self) -> Result<(), XrayError> {
+        let dir = self.asset_dir().ok_or(XrayError::NotFound)?;
+        self.updater.update_geo_assets(&dir).await
+    }
+
+    /// Routing engine for the running configuration, to test where targets
+    /// would be routed
+    pub async fn routing_engine(&self) -> Result<RoutingEngine, XrayError> {
+        let config = self
+            .running_config()
+            .await
+            .ok_or_else(|| XrayError::Config("Xray is not running".to_string()))?;
+        Ok(RoutingEngine::new(&config, self.asset_dir()))
+    }
+
     /// Get Xray version
     pub async fn get_version(&self) -> Result<String, XrayError> {
         let binary_path = self.find_xray_binary()?;
@@ -690,15 +1729,45 @@ impl XrayCore {
         self.health.read().await.clone()
     }
 
+    /// Get status and health of this instance for aggregated health reports
+    pub async fn get_instance_health(&self) -> InstanceHealth {
+        InstanceHealth {
+            instance_id: self.instance_id.clone(),
+            status: self.get_status().await,
+            health: self.get_health().await,
+        }
+    }
+
+    /// Record whether traffic through the proxy succeeds
+    ///
+    /// Reported as [`XrayHealth::is_responsive`]; set by the connectivity
+    /// health probe.
+    pub async fn set_responsive(&self, responsive: bool) {
+        self.responsive.store(responsive, Ordering::SeqCst);
+        if let Some(health) = self.health.write().await.as_mut() {
+            health.is_responsive = responsive;
+        }
+    }
+
     /// Start health monitoring
+    ///
+    /// Every 5 seconds while running, checks that the Xray process still
+    /// exists and samples its memory and CPU usage. A vanished process is
+    /// reported as an error status. A process over the memory limit for
+    /// [`limits::MEMORY_LIMIT_SAMPLES`] samples in a row is restarted.
     pub fn start_monitoring(&self) {
         let status = self.status.clone();
         let health = self.health.clone();
         let start_time = self.start_time.clone();
         let event_tx = self.event_tx.clone();
+        let responsive = Arc::clone(&self.responsive);
+        let process_pid = Arc::clone(&self.process_pid);
+        let core = self.handle();
 
         tokio::spawn(async move {
             let mut interval = time::interval(Duration::from_secs(5));
+            let mut previous: Option<(u32, process::ProcessUsage, std::time::Instant)> = None;
+            let mut over_limit = 0;
 
             loop {
                 interval.tick().await;
@@ -708,16 +1777,72 @@ impl XrayCore {
                     continue;
                 }
 
+                let pid = *process_pid.read().await;
+                if let Some(pid) = pid {
+                    if !process::is_process_alive(pid) {
+                        tracing::error!("Xray process (PID: {}) is no longer running", pid);
+                        let error = XrayStatus::Error(format!("Xray process {} exited", pid));
+                        *status.write().await = error.clone();
+                        let _ = event_tx.send(XrayEvent::StatusChanged(error));
+                        continue;
+                    }
+                }
+
                 let uptime = {
                     let start = start_time.read().await;
                     start.as_ref().map(|t| t.elapsed().as_secs()).unwrap_or(0)
                 };
 
+                let usage = pid.and_then(process::process_usage);
+                let sampled_at = std::time::Instant::now();
+                let cpu_percent = match (previous, pid, usage) {
+                    (Some((last_pid, last, last_at)), Some(pid), Some(usage))
+                        if last_pid == pid =>
+                    {
+                        Some(process::cpu_percent(&last, &usage, sampled_at - last_at))
+                    }
+                    _ => None,
+                };
+                previous = pid.zip(usage).map(|(pid, usage)| (pid, usage, sampled_at));
+
+                let limits = core.resource_limits();
+                match (pid, usage, limits.memory_limit_bytes) {
+                    (Some(pid), Some(usage), Some(limit_bytes))
+                        if limits.exceeds_memory(usage.memory_bytes) =>
+                    {
+                        over_limit += 1;
+                        if over_limit >= limits::MEMORY_LIMIT_SAMPLES {
+                            over_limit = 0;
+                            previous = None;
+                            tracing::warn!(
+                                "Xray process {} uses {} bytes, over the limit of {}, restarting",
+                                pid,
+                                usage.memory_bytes,
+                                limit_bytes
+                            );
+                            let _ = event_tx.send(XrayEvent::MemoryLimitExceeded(
+                                MemoryLimitExceeded {
+                                    pid,
+                                    memory_bytes: usage.memory_bytes,
+                                    limit_bytes,
+                                },
+                            ));
+                            if let Err(e) = core.respawn().await {
+                                tracing::error!("Failed to restart Xray: {}", e);
+                            }
+                            continue;
+                        }
+                    }
+                    _ => over_limit = 0,
+                }
+
                 let health_info = XrayHealth {
-                    pid: None, // Will be set by process check
+                    pid,
                     uptime,
                     last_check: std::time::SystemTime::now(),
-                    is_responsive: true,
+                    is_responsive: responsive.load(Ordering::SeqCst),
+                    memory_bytes: usage.map(|usage| usage.memory_bytes),
+                    cpu_percent,
                 };
 
                 // Update health
@@ -733,68 +1858,65 @@ impl XrayCore {
     }
 
     /// Monitor process logs
+    ///
+    /// Each line is parsed and broadcast, and kept in `output_tail`. Returns
+    /// the reader tasks, which end when the process closes its output.
     fn monitor_logs(
         stdout: tokio::process::ChildStdout,
         stderr: tokio::process::ChildStderr,
         event_tx: broadcast::Sender<XrayEvent>,
-    ) {
-        // Monitor stdout
-        let event_tx_clone = event_tx.clone();
-        tokio::spawn(async move {
-            let reader = BufReader::new(stdout);
-            let mut lines = reader.lines();
-
-            while let Ok(Some(line)) = lines.next_line().await {
-                let log_entry = Self::parse_log_line(&line);
-                let _ = event_tx_clone.send(XrayEvent::LogReceived(log_entry));
-            }
-        });
+        output_tail: Arc<std::sync::Mutex<VecDeque<String>>>,
+    ) -> [tokio::task::JoinHandle<()>; 2] {
+        [
+            Self::read_output(stdout, event_tx.clone(), Arc::clone(&output_tail)),
+            Self::read_output(stderr, event_tx, output_tail),
+        ]
+    }
 
-        // Monitor stderr
+    /// Forward the lines of one output stream
+    fn read_output(
+        output: impl tokio::io::AsyncRead + Unpin + Send + 'static,
+        event_tx: broadcast::Sender<XrayEvent>,
+        output_tail: Arc<std::sync::Mutex<VecDeque<String>>>,
+    ) -> tokio::task::JoinHandle<()> {
         tokio::spawn(async move {
-            let reader = BufReader::new(stderr);
+            let reader = BufReader::new(output);
             let mut lines = reader.lines();
+            let mut classifier = logs::LogClassifier::new();
 
             while let Ok(Some(line)) = lines.next_line().await {
+                {
+                    let mut tail = output_tail.lock().unwrap_or_else(|e| e.into_inner());
+                    if tail.len() == OUTPUT_TAIL_LINES {
+                        tail.pop_front();
+                    }
+                    tail.push_back(line.clone());
+                }
+
                 let log_entry = Self::parse_log_line(&line);
+                Self::buffer_log(&log_entry);
+                let error = classifier.classify(&log_entry);
                 let _ = event_tx.send(XrayEvent::LogReceived(log_entry));
+                if let Some(error) = error {
+                    let _ = event_tx.send(XrayEvent::ErrorDetected(error));
+                }
             }
-        });
+        })
+    }
+
+    /// Add an Xray log entry to the in-memory log buffer
+    fn buffer_log(entry: &XrayLogEntry) {
+        crate::utils::log_buffer::record(
+            crate::utils::log_buffer::LogSource::Xray,
+            entry.level.parse().unwrap_or(crate::utils::LogLevel::Info),
+            "xray",
+            &entry.message,
+        );
     }
 
-    /// Parse log line
+    /// Parse log line, see [`logs::parse_log_line`]
     pub fn parse_log_line(line: &str) -> XrayLogEntry {
-        // Simple log parsing - can be enhanced based on Xray log format
-        // Example: "2024/01/01 12:00:00 [Info] message"
-        let parts: Vec<&str> = line.splitn(3, ' ').collect();
-
-        if parts.len() >= 3 {
-            let timestamp = format!("{} {}", parts[0], parts.get(1).unwrap_or(&""));
-            let level_and_msg = parts[2];
-
-            if let Some(level_end) = level_and_msg.find(']') {
-                let level = level_and_msg[1..level_end].to_string();
-                let message = level_and_msg[level_end + 1..].trim().to_string();
-
-                XrayLogEntry {
-                    timestamp,
-                    level,
-                    message,
-                }
-            } else {
-                XrayLogEntry {
-                    timestamp,
-                    level: "Info".to_string(),
-                    message: level_and_msg.to_string(),
-                }
-            }
-        } else {
-            XrayLogEntry {
-                timestamp: chrono::Utc::now().to_rfc3339(),
-                level: "Info".to_string(),
-                message: line.to_string(),
-            }
-        }
+        logs::parse_log_line(line)
     }
 
     /// Update status and broadcast event
@@ -820,12 +1942,16 @@ impl Default for XrayConfig {
                     protocol: "http".to_string(),
                     listen: Some("127.0.0.1".to_string()),
                     settings: None,
+                    sniffing: None,
+                    tag: None,
                 },
                 InboundConfig {
                     port: 1080,
                     protocol: "socks".to_string(),
                     listen: Some("127.0.0.1".to_string()),
                     settings: None,
+                    sniffing: None,
+                    tag: None,
                 },
             ],
             outbounds: vec![OutboundConfig {
@@ -833,17 +1959,51 @@ impl Default for XrayConfig {
                 protocol: "freedom".to_string(),
                 settings: None,
                 stream_settings: None,
+                mux: None,
             }],
             routing: None,
+            observatory: None,
+            fakedns: None,
+            api: None,
+            stats: None,
+            policy: None,
         }
     }
 }
 
+/// Whether `tag` is the outbound of a proxy server, as opposed to direct,
+/// block, DNS, API or fragmenting dialer outbounds
+pub fn is_proxy_outbound(tag: &str) -> bool {
+    tag == OUTBOUND_PROXY || tag.starts_with(MULTI_PROXY_TAG_PREFIX)
+}
+
+/// Balancer tag used in load-balanced configurations
+const BALANCER_TAG: &str = "balancer";
+
+/// Outbound tag prefix for fragmenting dialer outbounds
+///
+/// Must not start with [`MULTI_PROXY_TAG_PREFIX`], or the balancer would
+/// select the fragment outbounds as well.
+const FRAGMENT_TAG_PREFIX: &str = "fragment-";
+
+/// Outbound tag answering hijacked DNS queries with Xray's resolver
+const DNS_OUTBOUND_TAG: &str = "dns-out";
+
+/// Inbound tag of the SOCKS inbound when DNS queries are hijacked
+const SOCKS_INBOUND_TAG: &str = "socks-in";
+
 /// Xray configuration generator
 pub struct XrayConfigGenerator {
     http_port: u16,
     socks_port: u16,
-    log_level: String,
+    log_level: EngineLogLevel,
+    routing_rule_sets: Vec<RoutingRuleSet>,
+    app_rules: Vec<AppRule>,
+    dns_settings: DnsSettings,
+    inbound_settings: InboundSettings,
+    api: bool,
+    outbound_interface: Option<String>,
+    gfwlist: Option<GfwList>,
 }
 
 impl Default for XrayConfigGenerator {
@@ -858,7 +2018,14 @@ impl XrayConfigGenerator {
         Self {
             http_port: 8080,
             socks_port: 1080,
-            log_level: "warning".to_string(),
+            log_level: EngineLogLevel::default(),
+            routing_rule_sets: Vec::new(),
+            app_rules: Vec::new(),
+            dns_settings: DnsSettings::default(),
+            inbound_settings: InboundSettings::default(),
+            api: false,
+            outbound_interface: None,
+            gfwlist: None,
         }
     }
 
@@ -874,12 +2041,55 @@ impl XrayConfigGenerator {
         self
     }
 
-    /// Set log level
-    pub fn with_log_level(mut self, level: String) -> Self {
+    /// Set the engine log level
+    pub fn with_log_level(mut self, level: EngineLogLevel) -> Self {
         self.log_level = level;
         self
     }
 
+    /// Set user-defined routing rule sets (applied ahead of built-in rules)
+    pub fn with_routing_rule_sets(mut self, rule_sets: Vec<RoutingRuleSet>) -> Self {
+        self.routing_rule_sets = rule_sets;
+        self
+    }
+
+    /// Set per-application split tunneling rules (applied ahead of all other rules)
+    pub fn with_app_rules(mut self, app_rules: Vec<AppRule>) -> Self {
+        self.app_rules = app_rules;
+        self
+    }
+
+    /// Set DNS settings (built-in defaults are used if no servers are configured)
+    pub fn with_dns_settings(mut self, dns_settings: DnsSettings) -> Self {
+        self.dns_settings = dns_settings;
+        self
+    }
+
+    /// Set local inbound settings (listen address, authentication, UDP)
+    pub fn with_inbound_settings(mut self, inbound_settings: InboundSettings) -> Self {
+        self.inbound_settings = inbound_settings;
+        self
+    }
+
+    /// Route smart mode by a GFWList instead of geosite/geoip
+    pub fn with_gfwlist(mut self, gfwlist: Option<GfwList>) -> Self {
+        self.gfwlist = gfwlist;
+        self
+    }
+
+    /// Enable the Xray API (HandlerService, RoutingService) on a loopback
+    /// inbound so outbounds and routing can be changed at runtime
+    pub fn with_api(mut self, enabled: bool) -> Self {
+        self.api = enabled;
+        self
+    }
+
+    /// Bind outbound connections to a network interface (`sockopt.interface`)
+    pub fn with_outbound_interface(mut self, interface: Option<String>) -> Self {
+        self.outbound_interface = interface;
+        self
+    }
+
     /// Generate Xray configuration from ProxyServerConfig
     pub fn generate(&self, proxy_config: &ProxyServerConfig) -> XrayConfig {
         self.generate_with_mode(proxy_config, "global")
@@ -887,64 +2097,511 @@ impl XrayConfigGenerator {
 
     /// Generate Xray configuration with specific proxy mode
     pub fn generate_with_mode(&self, proxy_config: &ProxyServerConfig, mode: &str) -> XrayConfig {
-        let log = LogConfig {
-            level: self.log_level.clone(),
-            access: None,
-            error: None,
+        let mut outbound = self.generate_outbound(proxy_config);
+        outbound.tag = Some(OUTBOUND_PROXY.to_string());
+        let fragment = self.attach_fragment(&mut outbound, proxy_config);
+
+        let mut outbounds = vec![outbound, self.generate_direct_outbound()];
+        outbounds.extend(fragment);
+
+        let routing = self.generate_routing(mode);
+        if Self::routes_to_block(&routing) {
+            outbounds.push(self.generate_block_outbound());
+        }
+        let routing = Some(routing);
+
+        let mut config = XrayConfig {
+            log: self.generate_log(),
+            dns: self.generate_dns(mode),
+            inbounds: self.generate_inbounds(mode),
+            outbounds,
+            routing,
+            observatory: None,
+            fakedns: self.generate_fakedns(mode),
+            api: None,
+            stats: None,
+            policy: None,
         };
+        self.attach_dns_hijack(&mut config);
+        self.attach_api(&mut config);
+        self.bind_outbounds(&mut config);
+        config
+    }
 
-        // Add DNS configuration for smart mode
-        let dns = if mode == "smart" {
-            Some(DnsConfig {
-                servers: vec!["1.1.1.1".to_string(), "8.8.8.8".to_string()],
+    /// Generate load-balanced Xray configuration from multiple proxy configs
+    pub fn generate_multi(
+        &self,
+        proxy_configs: &[ProxyServerConfig],
+        strategy: BalancerStrategy,
+    ) -> XrayConfig {
+        self.generate_multi_with_mode(proxy_configs, strategy, "global")
+    }
+
+    /// Generate load-balanced Xray configuration with specific proxy mode
+    ///
+    /// Each proxy gets its own `proxy-<index>` outbound. Traffic that would
+    /// normally go through the proxy is sent to a balancer over those
+    /// outbounds, with the observatory probing them for health. If every
+    /// outbound is unhealthy, the balancer falls back to the first proxy.
+    /// With [`BalancerStrategy::Manual`] the balancer selects only the first
+    /// proxy and falls back to the second, so `proxy_configs` must be in the
+    /// user's order.
+    pub fn generate_multi_with_mode(
+        &self,
+        proxy_configs: &[ProxyServerConfig],
+        strategy: BalancerStrategy,
+        mode: &str,
+    ) -> XrayConfig {
+        let mut fragments = Vec::new();
+        let mut outbounds: Vec<OutboundConfig> = proxy_configs
+            .iter()
+            .enumerate()
+            .map(|(index, proxy_config)| {
+                let mut outbound = self.generate_outbound(proxy_config);
+                outbound.tag = Some(format!("{}{}", MULTI_PROXY_TAG_PREFIX, index));
+                fragments.extend(self.attach_fragment(&mut outbound, proxy_config));
+                outbound
             })
-        } else {
-            None
+            .collect();
+
+        let primary_tag = outbounds.first().and_then(|o| o.tag.clone());
+        let (selector, fallback_tag) = match strategy {
+            // Tags are numbered from 0, so no other tag starts with the
+            // first one
+            BalancerStrategy::Manual => (
+                primary_tag.clone(),
+                outbounds
+                    .get(1)
+                    .and_then(|o| o.tag.clone())
+                    .or(primary_tag.clone()),
+            ),
+            _ => (
+                Some(MULTI_PROXY_TAG_PREFIX.to_string()),
+                primary_tag.clone(),
+            ),
+        };
+        outbounds.push(self.generate_direct_outbound());
+        outbounds.extend(fragments);
+
+        let mut routing = self.generate_routing(mode);
+        if Self::routes_to_block(&routing) {
+            outbounds.push(self.generate_block_outbound());
+        }
+        let mut observatory = None;
+
+        // Without any proxy outbound there is nothing to balance
+        if let (Some(selector), Some(fallback_tag)) =
+            (selector.filter(|_| mode != "direct"), fallback_tag)
+        {
+            routing.balancers.push(BalancerConfig {
+                tag: BALANCER_TAG.to_string(),
+                selector: vec![selector],
+                strategy: BalancerStrategyConfig {
+                    strategy_type: strategy.as_xray_type().to_string(),
+                },
+                fallback_tag: Some(fallback_tag),
+            });
+            // User rules targeting the proxy go to the balancer instead
+            for rule in routing.rules.iter_mut() {
+                if rule["outboundTag"] == OUTBOUND_PROXY {
+                    if let Some(obj) = rule.as_object_mut() {
+                        obj.remove("outboundTag");
+                        obj.insert("balancerTag".to_string(), json!(BALANCER_TAG));
+                    }
+                }
+            }
+            // Catch-all rule: everything not matched above goes to the balancer
+            routing.rules.push(json!({
+                "type": "field",
+                "network": "tcp,udp",
+                "balancerTag": BALANCER_TAG
+            }));
+            observatory = Some(ObservatoryConfig {
+                subject_selector: vec![MULTI_PROXY_TAG_PREFIX.to_string()],
+                probe_url: "https://www.google.com/generate_204".to_string(),
+                probe_interval: "30s".to_string(),
+                enable_concurrency: true,
+            });
+        }
+
+        let mut config = XrayConfig {
+            log: self.generate_log(),
+            dns: self.generate_dns(mode),
+            inbounds: self.generate_inbounds(mode),
+            outbounds,
+            routing: Some(routing),
+            observatory,
+            fakedns: self.generate_fakedns(mode),
+            api: None,
+            stats: None,
+            policy: None,
+        };
+        self.attach_dns_hijack(&mut config);
+        self.attach_api(&mut config);
+        self.bind_outbounds(&mut config);
+        config
+    }
+
+    /// Add the API section, inbound and routing rule if the API is enabled
+    ///
+    /// The API inbound uses port 0, replaced by a free port when Xray starts.
+    fn attach_api(&self, config: &mut XrayConfig) {
+        if !self.api {
+            return;
+        }
+
+        config.api = Some(ApiConfig::default());
+        config.stats = Some(json!({}));
+        config.policy = Some(ApiConfig::stats_policy());
+        config.inbounds.push(ApiConfig::inbound(0));
+        config
+            .routing
+            .get_or_insert_with(|| RoutingConfig {
+                domain_strategy: None,
+                rules: Vec::new(),
+                balancers: Vec::new(),
+            })
+            .rules
+            .insert(0, ApiConfig::routing_rule());
+    }
+
+    /// Answer DNS queries arriving through the tunnel with Xray's resolver
+    ///
+    /// In TUN mode every lookup of the system reaches the SOCKS inbound.
+    /// Sending port 53 from that inbound to a `dns` outbound lets split DNS
+    /// rules apply system-wide, not only to Xray's own lookups. Xray's own
+    /// queries carry no inbound tag, so they are not hijacked again.
+    fn attach_dns_hijack(&self, config: &mut XrayConfig) {
+        if self.outbound_interface.is_none()
+            || self.dns_settings.split_rules.is_empty()
+            || config.dns.is_none()
+        {
+            return;
+        }
+        let Some(socks) = config.inbounds.iter_mut().find(|i| i.protocol == "socks") else {
+            return;
         };
+        let inbound_tag = socks
+            .tag
+            .get_or_insert_with(|| SOCKS_INBOUND_TAG.to_string())
+            .clone();
+
+        config.outbounds.push(OutboundConfig {
+            tag: Some(DNS_OUTBOUND_TAG.to_string()),
+            protocol: "dns".to_string(),
+            settings: None,
+            stream_settings: None,
+            mux: None,
+        });
+        if let Some(ref mut routing) = config.routing {
+            routing.rules.insert(
+                0,
+                json!({
+                    "type": "field",
+                    "inboundTag": [inbound_tag],
+                    "network": "udp",
+                    "port": "53",
+                    "outboundTag": DNS_OUTBOUND_TAG
+                }),
+            );
+        }
+    }
+
+    /// Bind every dialing outbound to the configured interface
+    ///
+    /// Outbounds that dial through another one via `sockopt.dialerProxy`
+    /// are left alone; the dialer is bound instead.
+    fn bind_outbounds(&self, config: &mut XrayConfig) {
+        let Some(ref interface) = self.outbound_interface else {
+            return;
+        };
+
+        for outbound in config.outbounds.iter_mut() {
+            if outbound.protocol == "blackhole" {
+                continue;
+            }
+            let stream_settings = outbound.stream_settings.get_or_insert_with(|| json!({}));
+            if !stream_settings["sockopt"]["dialerProxy"].is_null() {
+                continue;
+            }
+            stream_settings["sockopt"]["interface"] = json!(interface);
+        }
+    }
+
+    /// Generate log configuration
+    fn generate_log(&self) -> LogConfig {
+        LogConfig {
+            level: self.log_level.as_str().to_string(),
+            access: None,
+            error: None,
+        }
+    }
+
+    /// Generate DNS configuration
+    ///
+    /// User DNS settings apply in every mode except direct. Without them,
+    /// only smart mode gets the built-in DNS servers, and other modes only
+    /// get a DNS section for split DNS rules.
+    fn generate_dns(&self, mode: &str) -> Option<DnsConfig> {
+        let settings = &self.dns_settings;
+        if mode == "direct" {
+            return None;
+        }
+        if !settings.is_configured() {
+            if mode != "smart" && settings.split_rules.is_empty() {
+                return None;
+            }
+            // Without user servers, global mode keeps using the system
+            // resolver, except in TUN mode where the system resolver is
+            // answered by Xray itself
+            let defaults: &[&str] = if mode == "smart" || self.outbound_interface.is_some() {
+                &["1.1.1.1", "8.8.8.8"]
+            } else {
+                &["localhost"]
+            };
+            let mut servers = self.generate_split_dns_servers();
+            servers.extend(
+                defaults
+                    .iter()
+                    .map(|address| DnsServerConfig::Address(address.to_string())),
+            );
+            return Some(DnsConfig {
+                hosts: HashMap::new(),
+                servers,
+                client_ip: None,
+                query_strategy: None,
+                disable_cache: false,
+            });
+        }
+
+        let mut servers = Vec::new();
+        if settings.fake_dns.is_some() {
+            servers.push(DnsServerConfig::Address("fakedns".to_string()));
+        }
+        servers.extend(self.generate_split_dns_servers());
+        servers.extend(settings.servers.iter().map(|server| {
+            if server.is_simple() {
+                DnsServerConfig::Address(server.address.clone())
+            } else {
+                DnsServerConfig::Detailed(DnsServerObject {
+                    address: server.address.clone(),
+                    port: server.port,
+                    domains: server.domains.clone(),
+                    expect_ips: server.expect_ips.clone(),
+                    skip_fallback: server.skip_fallback,
+                })
+            }
+        }));
+
+        let hosts = settings
+            .hosts
+            .iter()
+            .map(|(domain, addresses)| {
+                let value = match addresses.as_slice() {
+                    [single] => json!(single),
+                    _ => json!(addresses),
+                };
+                (domain.clone(), value)
+            })
+            .collect();
+
+        Some(DnsConfig {
+            hosts,
+            servers,
+            client_ip: settings.client_ip.clone(),
+            query_strategy: Some(settings.query_strategy.as_xray_str().to_string()),
+            disable_cache: settings.disable_cache,
+        })
+    }
+
+    /// Generate the resolvers of split DNS rules
+    ///
+    /// Xray prefers servers whose domains match the query, so these take
+    /// precedence over FakeDNS and the general servers for their domains.
+    fn generate_split_dns_servers(&self) -> Vec<DnsServerConfig> {
+        self.dns_settings
+            .split_rules
+            .iter()
+            .flat_map(|rule| {
+                let domains = rule.xray_domains();
+                rule.servers.iter().map(move |server| {
+                    DnsServerConfig::Detailed(DnsServerObject {
+                        address: server.to_string(),
+                        port: None,
+                        domains: domains.clone(),
+                        expect_ips: Vec::new(),
+                        skip_fallback: true,
+                    })
+                })
+            })
+            .collect()
+    }
 
-        let inbounds = vec![
+    /// Generate direct routing rules for split DNS domains and resolvers
+    fn generate_split_dns_routing_rules(&self) -> Vec<serde_json::Value> {
+        let rules = &self.dns_settings.split_rules;
+        if rules.is_empty() {
+            return Vec::new();
+        }
+
+        let domains: Vec<String> = rules.iter().flat_map(|r| r.xray_domains()).collect();
+        let servers: Vec<String> = rules
+            .iter()
+            .flat_map(|r| r.servers.iter().map(|ip| ip.to_string()))
+            .collect();
+        vec![
+            json!({
+                "type": "field",
+                "domain": domains,
+                "outboundTag": "direct"
+            }),
+            json!({
+                "type": "field",
+                "ip": servers,
+                "port": "53",
+                "outboundTag": "direct"
+            }),
+        ]
+    }
+
+    /// Generate FakeDNS pool configuration
+    fn generate_fakedns(&self, mode: &str) -> Option<Vec<FakeDnsConfig>> {
+        if mode == "direct" || !self.dns_settings.is_configured() {
+            return None;
+        }
+        self.dns_settings.fake_dns.as_ref().map(|fake_dns| {
+            vec![FakeDnsConfig {
+                ip_pool: fake_dns.ip_pool.clone(),
+                pool_size: fake_dns.pool_size,
+            }]
+        })
+    }
+
+    /// Generate local HTTP and SOCKS inbounds
+    ///
+    /// Sniffing is enabled in every mode that routes traffic, so domain rules
+    /// match connections made by IP (TUN and transparent setups). FakeDNS
+    /// additionally requires sniffing to map fake IPs back to domains.
+    fn generate_inbounds(&self, mode: &str) -> Vec<InboundConfig> {
+        let sniffing =
+            (mode != "direct").then(|| SniffingConfig::new(self.generate_fakedns(mode).is_some()));
+
+        let inbound = &self.inbound_settings;
+        let listen = Some(inbound.listen_address().to_string());
+
+        let http_settings = inbound
+            .http_auth
+            .as_ref()
+            .map(|auth| json!({ "accounts": [Self::account(auth)] }));
+
+        let socks_settings = (inbound.socks_auth.is_some() || inbound.udp).then(|| {
+            let mut settings = json!({
+                "auth": if inbound.socks_auth.is_some() { "password" } else { "noauth" },
+                "udp": inbound.udp,
+            });
+            if let Some(ref auth) = inbound.socks_auth {
+                settings["accounts"] = json!([Self::account(auth)]);
+            }
+            settings
+        });
+
+        vec![
             InboundConfig {
                 port: self.http_port,
                 protocol: "http".to_string(),
-                listen: Some("127.0.0.1".to_string()),
-                settings: None,
+                listen: listen.clone(),
+                settings: http_settings,
+                sniffing: sniffing.clone(),
+                tag: None,
             },
             InboundConfig {
                 port: self.socks_port,
                 protocol: "socks".to_string(),
-                listen: Some("127.0.0.1".to_string()),
-                settings: None,
+                listen,
+                settings: socks_settings,
+                sniffing,
+                tag: None,
             },
-        ];
-
-        let mut outbound = self.generate_outbound(proxy_config);
-        outbound.tag = Some("proxy".to_string());
+        ]
+    }
 
-        let outbounds = vec![
-            outbound,
-            // Add direct outbound for routing rules
-            OutboundConfig {
-                tag: Some("direct".to_string()),
-                protocol: "freedom".to_string(),
-                settings: None,
-                stream_settings: None,
-            },
-        ];
+    /// Inbound account entry for HTTP/SOCKS authentication
+    fn account(auth: &InboundAuth) -> Value {
+        json!({ "user": auth.username, "pass": auth.password })
+    }
 
-        let routing = Some(self.generate_routing(mode));
+    /// Generate direct outbound used by routing rules
+    fn generate_direct_outbound(&self) -> OutboundConfig {
+        OutboundConfig {
+            tag: Some(OUTBOUND_DIRECT.to_string()),
+            protocol: "freedom".to_string(),
+            settings: None,
+            stream_settings: None,
+            mux: None,
+        }
+    }
 
-        XrayConfig {
-            log,
-            dns,
-            inbounds,
-            outbounds,
-            routing,
+    /// Generate blackhole outbound used by blocking rules
+    fn generate_block_outbound(&self) -> OutboundConfig {
+        OutboundConfig {
+            tag: Some(OUTBOUND_BLOCK.to_string()),
+            protocol: "blackhole".to_string(),
+            settings: None,
+            stream_settings: None,
+            mux: None,
         }
     }
 
+    /// Check whether any routing rule targets the block outbound
+    fn routes_to_block(routing: &RoutingConfig) -> bool {
+        routing
+            .rules
+            .iter()
+            .any(|rule| rule["outboundTag"] == OUTBOUND_BLOCK)
+    }
+
+    /// Generate routing rules from enabled app rules and user-defined rule sets
+    ///
+    /// Rules that match on process names are dropped on platforms where Xray
+    /// cannot resolve the originating process.
+    fn generate_user_routing_rules(&self) -> Vec<serde_json::Value> {
+        let app_rules = self
+            .app_rules
+            .iter()
+            .filter(|r| r.enabled)
+            .map(AppRule::to_xray_rule);
+        let rule_sets = self
+            .routing_rule_sets
+            .iter()
+            .flat_map(RoutingRuleSet::to_xray_rules);
+
+        app_rules
+            .chain(rule_sets)
+            .filter(|rule| {
+                let uses_process = rule.get("process").is_some();
+                if uses_process && !PROCESS_RULES_SUPPORTED {
+                    tracing::debug!("Skipping process routing rule on this platform: {}", rule);
+                }
+                !uses_process || PROCESS_RULES_SUPPORTED
+            })
+            .collect()
+    }
+
     /// Generate routing configuration based on proxy mode
+    ///
+    /// Split DNS domains always go direct; user-defined rules come next,
+    /// ahead of the built-in rules, except in direct mode where all traffic
+    /// goes direct.
     fn generate_routing(&self, mode: &str) -> RoutingConfig {
-        let rules = match mode {
+        let mut rules = if mode == "direct" {
+            vec![]
+        } else {
+            let mut rules = self.generate_split_dns_routing_rules();
+            rules.extend(self.generate_user_routing_rules());
+            rules
+        };
+
+        rules.extend(match mode {
             "smart" => self.generate_smart_routing_rules(),
             "global" => vec![], // Global mode: all traffic goes through proxy
             "direct" => vec![
@@ -956,16 +2613,42 @@ impl XrayConfigGenerator {
                 }),
             ],
             _ => vec![], // Default to global mode
-        };
+        });
 
         RoutingConfig {
             domain_strategy: Some("IPIfNonMatch".to_string()),
             rules,
+            balancers: vec![],
         }
     }
 
     /// Generate smart routing rules (China direct, others proxy)
+    ///
+    /// With a GFWList, listed hosts go through the proxy and everything
+    /// else goes direct instead.
     fn generate_smart_routing_rules(&self) -> Vec<serde_json::Value> {
+        if let Some(gfwlist) = &self.gfwlist {
+            let mut rules = vec![json!({
+                "type": "field",
+                "outboundTag": "direct",
+                "ip": [
+                    "geoip:private"
+                ]
+            })];
+            rules.extend(
+                gfwlist
+                    .to_routing_rules()
+                    .iter()
+                    .map(RoutingRule::to_xray_rule),
+            );
+            rules.push(json!({
+                "type": "field",
+                "outboundTag": "direct",
+                "network": "tcp,udp"
+            }));
+            return rules;
+        }
+
         vec![
             // Private IP addresses go direct
             json!({
@@ -997,7 +2680,7 @@ impl XrayConfigGenerator {
 
     /// Generate outbound configuration based on protocol
     fn generate_outbound(&self, proxy_config: &ProxyServerConfig) -> OutboundConfig {
-        match proxy_config.protocol {
+        let mut outbound = match proxy_config.protocol {
             ProxyProtocol::Vmess => self.generate_vmess_outbound(proxy_config),
             ProxyProtocol::Vless => self.generate_vless_outbound(proxy_config),
             ProxyProtocol::Trojan => self.generate_trojan_outbound(proxy_config),
@@ -1007,8 +2690,80 @@ impl XrayConfigGenerator {
                 protocol: "freedom".to_string(),
                 settings: None,
                 stream_settings: None,
+                mux: None,
             },
+        };
+        outbound.mux = self.generate_mux(proxy_config);
+        outbound
+    }
+
+    /// Generate mux settings for the proxy outbound
+    ///
+    /// Mux cannot be combined with XTLS flows, so it is skipped for them.
+    fn generate_mux(&self, proxy_config: &ProxyServerConfig) -> Option<Value> {
+        let mux = proxy_config
+            .stream_settings
+            .as_ref()?
+            .mux
+            .as_ref()
+            .filter(|mux| mux.enabled)?;
+
+        let has_flow = proxy_config
+            .settings
+            .get("flow")
+            .and_then(|v| v.as_str())
+            .is_some_and(|flow| !flow.is_empty());
+        if has_flow {
+            tracing::warn!(
+                "Ignoring mux for {}: not supported with XTLS flow",
+                proxy_config.name
+            );
+            return None;
         }
+
+        Some(json!({
+            "enabled": true,
+            "concurrency": mux.concurrency,
+            "xudpConcurrency": mux.xudp_concurrency,
+        }))
+    }
+
+    /// Route the outbound through a fragmenting freedom outbound if configured
+    ///
+    /// Returns the extra outbound, which the proxy outbound dials through via
+    /// `sockopt.dialerProxy`.
+    fn attach_fragment(
+        &self,
+        outbound: &mut OutboundConfig,
+        proxy_config: &ProxyServerConfig,
+    ) -> Option<OutboundConfig> {
+        let fragment = proxy_config.stream_settings.as_ref()?.fragment.as_ref()?;
+        let tag = format!(
+            "{}{}",
+            FRAGMENT_TAG_PREFIX,
+            outbound.tag.as_deref().unwrap_or(OUTBOUND_PROXY)
+        );
+
+        // Other socket options (mark, interface, ...) are kept
+        let stream_settings = outbound.stream_settings.get_or_insert_with(|| json!({}));
+        if !stream_settings["sockopt"].is_object() {
+            stream_settings["sockopt"] = json!({});
+        }
+        stream_settings["sockopt"]["dialerProxy"] = json!(tag);
+
+        Some(OutboundConfig {
+            tag: Some(tag),
+            protocol: "freedom".to_string(),
+            settings: Some(json!({
+                "fragment": {
+                    "packets": fragment.packets,
+                    "length": fragment.length,
+                    "interval": fragment.interval,
+                }
+            })),
+            stream_settings: None,
+            mux: None,
+        })
     }
 
     /// Generate VMess outbound configuration
@@ -1034,6 +2789,7 @@ impl XrayConfigGenerator {
             protocol: "vmess".to_string(),
             settings: Some(settings),
             stream_settings,
+            mux: None,
         }
     }
 
@@ -1060,6 +2816,7 @@ impl XrayConfigGenerator {
             protocol: "vless".to_string(),
             settings: Some(settings),
             stream_settings,
+            mux: None,
         }
     }
 
@@ -1082,6 +2839,7 @@ impl XrayConfigGenerator {
             protocol: "trojan".to_string(),
             settings: Some(settings),
             stream_settings,
+            mux: None,
         }
     }
 
@@ -1103,6 +2861,7 @@ impl XrayConfigGenerator {
             protocol: "shadowsocks".to_string(),
             settings: Some(settings),
             stream_settings: None,
+            mux: None,
         }
     }
 
@@ -1218,6 +2977,8 @@ mod tests {
             uptime: 60,
             last_check: std::time::SystemTime::now(),
             is_responsive: true,
+            memory_bytes: Some(32 * 1024 * 1024),
+            cpu_percent: Some(1.5),
         };
 
         {
@@ -1238,6 +2999,49 @@ mod tests {
 
         assert_eq!(entry.level, "Info");
         assert!(entry.message.contains("Xray started"));
+
+        let line = "2024/01/01 12:00:00.123456 from 127.0.0.1:5000 accepted tcp:a.com:443 [http-in -> proxy]";
+        let entry = XrayCore::parse_log_line(line);
+        assert_eq!(entry.level, "Info");
+        assert_eq!(
+            entry.message,
+            "from 127.0.0.1:5000 accepted tcp:a.com:443 [http-in -> proxy]"
+        );
+
+        let entry = XrayCore::parse_log_line("2024/01/01 12:00:00.5 [warning] dns timeout\r");
+        assert_eq!(entry.timestamp, "2024/01/01 12:00:00.5");
+        assert_eq!(entry.level, "Warning");
+        assert_eq!(entry.message, "dns timeout");
+
+        // Banner without timestamp
+        let entry = XrayCore::parse_log_line("Xray 1.8.7 (Xray, Penetrates Everything.)");
+        assert_eq!(entry.level, "Info");
+        assert_eq!(entry.message, "Xray 1.8.7 (Xray, Penetrates Everything.)");
+
+        let entry = XrayCore::parse_log_line("[Error] failed to start");
+        assert_eq!(entry.level, "Error");
+        assert_eq!(entry.message, "failed to start");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_process_exit_describe() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let exit = ProcessExit::new(
+            42,
+            std::process::ExitStatus::from_raw(1 << 8),
+            vec!["panic: runtime error".to_string()],
+        );
+        assert_eq!(exit.code, Some(1));
+        assert_eq!(
+            exit.describe(),
+            "Xray process 42 exited with code 1: panic: runtime error"
+        );
+
+        let exit = ProcessExit::new(42, std::process::ExitStatus::from_raw(9), Vec::new());
+        assert_eq!(exit.signal, Some(9));
+        assert_eq!(exit.describe(), "Xray process 42 was killed by signal 9");
     }
 
     #[test]
@@ -1247,6 +3051,8 @@ mod tests {
             uptime: 60,
             last_check: std::time::SystemTime::now(),
             is_responsive: true,
+            memory_bytes: Some(32 * 1024 * 1024),
+            cpu_percent: Some(1.5),
         };
 
         let json = serde_json::to_string(&health).unwrap();
@@ -1261,6 +3067,8 @@ mod tests {
             timestamp: "2024/01/01 12:00:00".to_string(),
             level: "Info".to_string(),
             message: "Test message".to_string(),
+            session_id: None,
+            component: None,
         };
 
         let json = serde_json::to_string(&entry).unwrap();
@@ -1268,4 +3076,510 @@ mod tests {
 
         let _parsed: XrayLogEntry = serde_json::from_str(&json).unwrap();
     }
+
+    fn create_test_proxy_config(name: &str) -> ProxyServerConfig {
+        let mut settings = std::collections::HashMap::new();
+        settings.insert("id".to_string(), json!("test-uuid"));
+
+        ProxyServerConfig {
+            id: name.to_string(),
+            name: name.to_string(),
+            server: format!("{}.example.com", name),
+            port: 443,
+            protocol: ProxyProtocol::Vless,
+            settings,
+            stream_settings: None,
+            tags: vec![],
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            raw_name: None,
+        }
+    }
+
+    #[test]
+    fn test_generate_multi_balancer() {
+        let generator = XrayConfigGenerator::new();
+        let configs = vec![create_test_proxy_config("a"), create_test_proxy_config("b")];
+
+        let config =
+            generator.generate_multi_with_mode(&configs, BalancerStrategy::RoundRobin, "smart");
+
+        let tags: Vec<_> = config
+            .outbounds
+            .iter()
+            .map(|o| o.tag.clone().unwrap_or_default())
+            .collect();
+        assert_eq!(tags, vec!["proxy-0", "proxy-1", "direct"]);
+
+        let routing = config.routing.as_ref().unwrap();
+        assert_eq!(routing.balancers.len(), 1);
+        assert_eq!(routing.balancers[0].strategy.strategy_type, "roundRobin");
+        assert_eq!(
+            routing.balancers[0].fallback_tag.as_deref(),
+            Some("proxy-0")
+        );
+        // Smart rules come first, the balancer rule is the catch-all
+        assert_eq!(
+            routing.rules.last().unwrap()["balancerTag"],
+            json!("balancer")
+        );
+        assert!(config.observatory.is_some());
+
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["routing"]["balancers"][0]["fallbackTag"], "proxy-0");
+        assert_eq!(value["observatory"]["subjectSelector"][0], "proxy-");
+        assert!(value["observatory"]["probeURL"].is_string());
+    }
+
+    #[test]
+    fn test_generate_multi_manual_order() {
+        let generator = XrayConfigGenerator::new();
+        let configs = vec![create_test_proxy_config("b"), create_test_proxy_config("a")];
+
+        let config =
+            generator.generate_multi_with_mode(&configs, BalancerStrategy::Manual, "global");
+
+        // The first server in the given order, failing over to the second
+        let routing = config.routing.as_ref().unwrap();
+        assert_eq!(routing.balancers.len(), 1);
+        assert_eq!(routing.balancers[0].selector, vec!["proxy-0"]);
+        assert_eq!(
+            routing.balancers[0].fallback_tag.as_deref(),
+            Some("proxy-1")
+        );
+        assert_eq!(routing.rules.last().unwrap()["balancerTag"], "balancer");
+        assert_eq!(config.outbounds[0].tag.as_deref(), Some("proxy-0"));
+        assert_eq!(
+            config.outbounds[0].settings.as_ref().unwrap()["vnext"][0]["address"],
+            "b.example.com"
+        );
+        assert!(config.observatory.is_some());
+
+        // A single server falls back to itself
+        let config =
+            generator.generate_multi_with_mode(&configs[..1], BalancerStrategy::Manual, "global");
+        let routing = config.routing.as_ref().unwrap();
+        assert_eq!(
+            routing.balancers[0].fallback_tag.as_deref(),
+            Some("proxy-0")
+        );
+
+        assert_eq!(
+            BalancerStrategy::parse("manual"),
+            Some(BalancerStrategy::Manual)
+        );
+        assert_eq!(
+            BalancerStrategy::parse("least_ping"),
+            Some(BalancerStrategy::LeastPing)
+        );
+        assert_eq!(BalancerStrategy::parse("fastest"), None);
+    }
+
+    #[test]
+    fn test_generate_multi_empty() {
+        let generator = XrayConfigGenerator::new();
+        let config = generator.generate_multi(&[], BalancerStrategy::default());
+
+        assert_eq!(config.outbounds.len(), 1);
+        assert!(config.routing.unwrap().balancers.is_empty());
+        assert!(config.observatory.is_none());
+    }
+
+    #[test]
+    fn test_user_routing_rules_precede_smart_rules() {
+        use crate::config::RoutingRule;
+
+        let rule_set = RoutingRuleSet {
+            id: "custom".to_string(),
+            name: "Custom".to_string(),
+            enabled: true,
+            rules: vec![RoutingRule {
+                domains: vec!["domain:ads.example.com".to_string()],
+                ips: vec![],
+                port: None,
+                processes: vec![],
+                protocols: vec![],
+                outbound_tag: "block".to_string(),
+            }],
+        };
+        let generator = XrayConfigGenerator::new().with_routing_rule_sets(vec![rule_set]);
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "smart");
+        let routing = config.routing.unwrap();
+        assert_eq!(routing.rules.len(), 4);
+        assert_eq!(routing.rules[0]["outboundTag"], "block");
+        assert_eq!(routing.rules[0]["domain"][0], "domain:ads.example.com");
+        assert!(config
+            .outbounds
+            .iter()
+            .any(|o| o.tag.as_deref() == Some("block") && o.protocol == "blackhole"));
+
+        // Direct mode ignores user rules
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "direct");
+        assert_eq!(config.routing.unwrap().rules.len(), 1);
+    }
+
+    #[test]
+    fn test_gfwlist_replaces_smart_geo_rules() {
+        let gfwlist = GfwList::parse(
+            "[AutoProxy 0.2.9]\n||google.com\n@@||cn.google.com\n|http://1.2.3.4/\n",
+        )
+        .unwrap();
+        let generator = XrayConfigGenerator::new().with_gfwlist(Some(gfwlist));
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "smart");
+        let rules = config.routing.unwrap().rules;
+        assert_eq!(rules.len(), 5);
+        assert_eq!(rules[0]["ip"][0], "geoip:private");
+        assert_eq!(rules[1]["outboundTag"], "direct");
+        assert_eq!(rules[1]["domain"][0], "domain:cn.google.com");
+        assert_eq!(rules[2]["outboundTag"], "proxy");
+        assert_eq!(rules[2]["domain"][0], "domain:google.com");
+        assert_eq!(rules[3]["outboundTag"], "proxy");
+        assert_eq!(rules[3]["ip"][0], "1.2.3.4");
+        assert_eq!(rules[4]["outboundTag"], "direct");
+        assert_eq!(rules[4]["network"], "tcp,udp");
+        assert!(!rules.iter().any(|r| r.to_string().contains("geosite:cn")));
+
+        // Global mode is unaffected
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "global");
+        assert!(config.routing.unwrap().rules.is_empty());
+    }
+
+    #[test]
+    fn test_app_rules_follow_platform_support() {
+        let app_rule = AppRule {
+            id: "telegram".to_string(),
+            process_name: "Telegram".to_string(),
+            outbound_tag: "proxy".to_string(),
+            enabled: true,
+        };
+        let generator = XrayConfigGenerator::new().with_app_rules(vec![app_rule]);
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "global");
+        let rules = config.routing.unwrap().rules;
+        if PROCESS_RULES_SUPPORTED {
+            assert_eq!(rules.len(), 1);
+            assert_eq!(rules[0]["process"][0], "Telegram");
+        } else {
+            assert!(rules.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_engine_log_level() {
+        let proxy_config = create_test_proxy_config("a");
+        let config = XrayConfigGenerator::new().generate(&proxy_config);
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["log"]["loglevel"], "warning");
+
+        let config = XrayConfigGenerator::new()
+            .with_log_level(EngineLogLevel::Debug)
+            .generate(&proxy_config);
+        assert_eq!(config.log.level, "debug");
+    }
+
+    #[test]
+    fn test_dns_settings() {
+        use crate::config::dns::{DnsServer, FakeDnsSettings, QueryStrategy};
+
+        let mut cn_server = DnsServer::new("223.5.5.5");
+        cn_server.domains = vec!["geosite:cn".to_string()];
+        cn_server.expect_ips = vec!["geoip:cn".to_string()];
+
+        let mut hosts = HashMap::new();
+        hosts.insert("router.local".to_string(), vec!["192.168.1.1".to_string()]);
+
+        let settings = DnsSettings {
+            servers: vec![DnsServer::new("https://1.1.1.1/dns-query"), cn_server],
+            hosts,
+            query_strategy: QueryStrategy::UseIPv4,
+            client_ip: None,
+            fake_dns: Some(FakeDnsSettings::default()),
+            disable_cache: false,
+            split_rules: vec![],
+        };
+        let generator = XrayConfigGenerator::new().with_dns_settings(settings);
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "global");
+        let value = serde_json::to_value(&config).unwrap();
+        let dns = &value["dns"];
+        assert_eq!(dns["servers"][0], "fakedns");
+        assert_eq!(dns["servers"][1], "https://1.1.1.1/dns-query");
+        assert_eq!(dns["servers"][2]["address"], "223.5.5.5");
+        assert_eq!(dns["servers"][2]["expectIPs"][0], "geoip:cn");
+        assert_eq!(dns["hosts"]["router.local"], "192.168.1.1");
+        assert_eq!(dns["queryStrategy"], "UseIPv4");
+        assert!(dns.get("disableCache").is_none());
+        assert_eq!(value["fakedns"][0]["ipPool"], "198.18.0.0/15");
+        assert_eq!(
+            value["inbounds"][0]["sniffing"]["destOverride"][3],
+            "fakedns"
+        );
+        assert!(value["inbounds"][0]["sniffing"].get("routeOnly").is_none());
+
+        // Direct mode never emits DNS
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "direct");
+        assert!(config.dns.is_none());
+        assert!(config.fakedns.is_none());
+        assert!(config.inbounds[0].sniffing.is_none());
+    }
+
+    #[test]
+    fn test_split_dns() {
+        use crate::config::dns::SplitDnsRule;
+
+        let settings = DnsSettings {
+            split_rules: vec![SplitDnsRule::new(
+                vec!["*.corp.internal".to_string()],
+                vec!["10.0.0.53".parse().unwrap()],
+            )],
+            ..Default::default()
+        };
+        let generator = XrayConfigGenerator::new().with_dns_settings(settings);
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "global");
+        let value = serde_json::to_value(&config).unwrap();
+        let dns = &value["dns"];
+        assert_eq!(dns["servers"][0]["address"], "10.0.0.53");
+        assert_eq!(dns["servers"][0]["domains"][0], "domain:corp.internal");
+        assert_eq!(dns["servers"][0]["skipFallback"], true);
+        assert_eq!(dns["servers"][1], "localhost");
+
+        let rules = &value["routing"]["rules"];
+        assert_eq!(rules[0]["domain"][0], "domain:corp.internal");
+        assert_eq!(rules[0]["outboundTag"], "direct");
+        assert_eq!(rules[1]["ip"][0], "10.0.0.53");
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "smart");
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["dns"]["servers"][1], "1.1.1.1");
+
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "direct");
+        assert!(config.dns.is_none());
+
+        // TUN mode: lookups from the tunnel are answered by Xray
+        let generator = generator.with_outbound_interface(Some("eth0".to_string()));
+        let config = generator.generate_with_mode(&create_test_proxy_config("a"), "global");
+        let value = serde_json::to_value(&config).unwrap();
+        assert_eq!(value["dns"]["servers"][1], "1.1.1.1");
+        assert_eq!(value["inbounds"][1]["tag"], "socks-in");
+        let rules = &value["routing"]["rules"];
+        assert_eq!(rules[0]["inboundTag"][0], "socks-in");
+        assert_eq!(rules[0]["outboundTag"], "dns-out");
+        assert!(config.outbounds.iter().any(|o| o.protocol == "dns"));
+    }
+
+    #[test]
+    fn test_sniffing_by_mode() {
+        let generator = XrayConfigGenerator::new();
+        let proxy_config = create_test_proxy_config("a");
+
+        for mode in ["global", "smart"] {
+            let config = generator.generate_with_mode(&proxy_config, mode);
+            for inbound in &config.inbounds {
+                assert_eq!(inbound.sniffing, Some(SniffingConfig::new(false)));
+            }
+        }
+
+        let value =
+            serde_json::to_value(generator.generate_with_mode(&proxy_config, "smart")).unwrap();
+        let sniffing = &value["inbounds"][1]["sniffing"];
+        assert_eq!(sniffing["enabled"], true);
+        assert_eq!(sniffing["destOverride"], json!(["http", "tls", "quic"]));
+        assert_eq!(sniffing["routeOnly"], true);
+
+        let config = generator.generate_with_mode(&proxy_config, "direct");
+        assert!(config.inbounds.iter().all(|i| i.sniffing.is_none()));
+    }
+
+    #[test]
+    fn test_mux_and_fragment() {
+        use crate::config::{FragmentSettings, MuxSettings, StreamSettings};
+
+        let mut proxy_config = create_test_proxy_config("a");
+        proxy_config.stream_settings = Some(StreamSettings {
+            network: "tcp".to_string(),
+            security: "tls".to_string(),
+            tls_settings: None,
+            tcp_settings: None,
+            ws_settings: None,
+            http_settings: None,
+            quic_settings: None,
+            grpc_settings: None,
+            mux: Some(MuxSettings::default()),
+            fragment: Some(FragmentSettings::default()),
+        });
+        let generator = XrayConfigGenerator::new();
+
+        let value = serde_json::to_value(generator.generate(&proxy_config)).unwrap();
+        let outbounds = value["outbounds"].as_array().unwrap();
+        assert_eq!(outbounds[0]["mux"]["concurrency"], 8);
+        assert_eq!(outbounds[0]["mux"]["xudpConcurrency"], 16);
+        assert_eq!(
+            outbounds[0]["streamSettings"]["sockopt"]["dialerProxy"],
+            "fragment-proxy"
+        );
+        let fragment = outbounds
+            .iter()
+            .find(|o| o["tag"] == "fragment-proxy")
+            .unwrap();
+        assert_eq!(fragment["protocol"], "freedom");
+        assert_eq!(fragment["settings"]["fragment"]["packets"], "tlshello");
+
+        // Multi mode: fragment outbounds are not picked up by the balancer
+        let config = generator.generate_multi(&[proxy_config.clone()], BalancerStrategy::LeastPing);
+        assert!(config
+            .outbounds
+            .iter()
+            .any(|o| o.tag.as_deref() == Some("fragment-proxy-0")));
+
+        // Existing socket options are kept, and missing stream settings are
+        // created
+        let mut outbound = generator.generate_outbound(&proxy_config);
+        outbound.tag = Some(OUTBOUND_PROXY.to_string());
+        outbound.stream_settings.as_mut().unwrap()["sockopt"] = json!({ "mark": 255 });
+        assert!(generator
+            .attach_fragment(&mut outbound, &proxy_config)
+            .is_some());
+        let sockopt = &outbound.stream_settings.as_ref().unwrap()["sockopt"];
+        assert_eq!(sockopt["mark"], 255);
+        assert_eq!(sockopt["dialerProxy"], "fragment-proxy");
+
+        outbound.stream_settings = None;
+        assert!(generator
+            .attach_fragment(&mut outbound, &proxy_config)
+            .is_some());
+        assert_eq!(
+            outbound.stream_settings.unwrap()["sockopt"]["dialerProxy"],
+            "fragment-proxy"
+        );
+
+        // Mux is skipped for XTLS flows
+        proxy_config
+            .settings
+            .insert("flow".to_string(), json!("xtls-rprx-vision"));
+        let config = generator.generate(&proxy_config);
+        assert!(config.outbounds[0].mux.is_none());
+    }
+
+    #[test]
+    fn test_outbound_interface() {
+        use crate::config::{FragmentSettings, StreamSettings};
+
+        let proxy_config = create_test_proxy_config("a");
+        let generator = XrayConfigGenerator::new().with_outbound_interface(Some("en0".to_string()));
+        let value = serde_json::to_value(generator.generate(&proxy_config)).unwrap();
+        for outbound in value["outbounds"].as_array().unwrap() {
+            assert_eq!(outbound["streamSettings"]["sockopt"]["interface"], "en0");
+        }
+
+        // With a fragment dialer, only the dialer is bound
+        let mut fragmented = proxy_config.clone();
+        fragmented.stream_settings = Some(StreamSettings {
+            network: "tcp".to_string(),
+            security: "tls".to_string(),
+            tls_settings: None,
+            tcp_settings: None,
+            ws_settings: None,
+            http_settings: None,
+            quic_settings: None,
+            grpc_settings: None,
+            mux: None,
+            fragment: Some(FragmentSettings::default()),
+        });
+        let value = serde_json::to_value(generator.generate(&fragmented)).unwrap();
+        let outbounds = value["outbounds"].as_array().unwrap();
+        assert!(outbounds[0]["streamSettings"]["sockopt"]["interface"].is_null());
+        let fragment = outbounds
+            .iter()
+            .find(|o| o["tag"] == "fragment-proxy")
+            .unwrap();
+        assert_eq!(fragment["streamSettings"]["sockopt"]["interface"], "en0");
+
+        let config = XrayConfigGenerator::new().generate(&proxy_config);
+        assert!(config.outbounds[1].stream_settings.is_none());
+    }
+
+    #[test]
+    fn test_inbound_settings() {
+        let proxy_config = create_test_proxy_config("a");
+
+        // Defaults keep loopback-only inbounds without settings
+        let config = XrayConfigGenerator::new().generate(&proxy_config);
+        for inbound in &config.inbounds {
+            assert_eq!(inbound.listen.as_deref(), Some("127.0.0.1"));
+            assert!(inbound.settings.is_none());
+        }
+
+        let auth = InboundAuth {
+            username: "user".to_string(),
+            password: "secret".to_string(),
+        };
+        let generator = XrayConfigGenerator::new().with_inbound_settings(InboundSettings {
+            allow_lan: true,
+            http_auth: Some(auth.clone()),
+            socks_auth: Some(auth),
+            udp: true,
+        });
+
+        let value = serde_json::to_value(generator.generate(&proxy_config)).unwrap();
+        let http = &value["inbounds"][0];
+        let socks = &value["inbounds"][1];
+        assert_eq!(http["listen"], "0.0.0.0");
+        assert_eq!(http["settings"]["accounts"][0]["user"], "user");
+        assert_eq!(socks["settings"]["auth"], "password");
+        assert_eq!(socks["settings"]["udp"], true);
+        assert_eq!(socks["settings"]["accounts"][0]["pass"], "secret");
+    }
+
+    #[tokio::test]
+    async fn test_check_ports() {
+        let xray = XrayCore::new();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let taken = listener.local_addr().unwrap().port();
+
+        let mut config = xray.generate_config(&create_test_proxy_config("a"));
+        config.inbounds[0].port = taken;
+        config.inbounds[1].port = ports::find_free_port(None).unwrap();
+
+        match xray.check_ports(&mut config.clone()).await {
+            Err(XrayError::PortInUse { port, .. }) => assert_eq!(port, taken),
+            other => panic!("Expected PortInUse, got {:?}", other),
+        }
+
+        xray.set_port_conflict_policy(PortConflictPolicy::Reassign);
+        let mut events = xray.subscribe();
+        let reassignments = xray.check_ports(&mut config).await.unwrap();
+        assert_eq!(reassignments.len(), 1);
+        assert_eq!(reassignments[0].protocol, "http");
+        assert_eq!(reassignments[0].old_port, taken);
+        assert_ne!(config.inbounds[0].port, taken);
+        assert_eq!(config.inbounds[0].port, reassignments[0].new_port);
+
+        match events.try_recv().unwrap() {
+            XrayEvent::PortReassigned(event) => assert_eq!(event, reassignments[0]),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+
+        // Later configs keep the reassigned port
+        let regenerated = xray.generate_config(&create_test_proxy_config("a"));
+        assert_eq!(regenerated.inbounds[0].port, reassignments[0].new_port);
+    }
+
+    #[tokio::test]
+    async fn test_reload_when_stopped_starts() {
+        let xray = XrayCore::new();
+        let mut config = xray.generate_config(&create_test_proxy_config("a"));
+        for inbound in &mut config.inbounds {
+            inbound.port = ports::find_free_port(None).unwrap();
+        }
+
+        // Without a running instance reload behaves like start
+        match xray.reload(config).await {
+            Err(XrayError::NotFound) => {}
+            other => panic!("Expected NotFound, got {:?}", other),
+        }
+        assert!(xray.config.read().await.is_some());
+    }
 }
@@ -0,0 +1,285 @@
+//! Xray process inspection
+//!
+//! Liveness and resource usage of the spawned Xray process, reported in
+//! [`XrayHealth`](super::XrayHealth), and identification of Xray processes
+//! left behind by a session that crashed.
+
+use std::time::Duration;
+
+/// Resource usage of a process
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessUsage {
+    /// Resident memory
+    pub memory_bytes: u64,
+    /// CPU time consumed since the process started (user + system)
+    pub cpu_time: Duration,
+}
+
+/// CPU usage in percent of one core between two samples taken
+/// `elapsed` apart
+pub fn cpu_percent(previous: &ProcessUsage, current: &ProcessUsage, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    let used = current.cpu_time.saturating_sub(previous.cpu_time);
+    used.as_secs_f64() / elapsed.as_secs_f64() * 100.0
+}
+
+/// Whether a process with `pid` exists
+#[cfg(unix)]
+pub fn is_process_alive(pid: u32) -> bool {
+    // Signal 0 only checks that the process exists; EPERM means it exists
+    // but belongs to another user
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Whether a process with `pid` exists
+#[cfg(windows)]
+pub fn is_process_alive(pid: u32) -> bool {
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    /// Exit code reported while a process is running
+    const STILL_ACTIVE: u32 = 259;
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return false;
+        }
+        let mut code = 0;
+        let alive = GetExitCodeProcess(handle, &mut code) != 0 && code == STILL_ACTIVE;
+        CloseHandle(handle);
+        alive
+    }
+}
+
+/// Resource usage of the process with `pid`, `None` if it cannot be read
+#[cfg(target_os = "linux")]
+pub fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let statm = std::fs::read_to_string(format!("/proc/{}/statm", pid)).ok()?;
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    parse_proc_usage(&stat, &statm, ticks as u64, page_size as u64)
+}
+
+/// Resource usage of the process with `pid`, `None` if it cannot be read
+#[cfg(target_os = "macos")]
+pub fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "rss=,time=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    parse_ps_usage(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Resource usage of the process with `pid`, `None` if it cannot be read
+#[cfg(windows)]
+pub fn process_usage(pid: u32) -> Option<ProcessUsage> {
+    use winapi::shared::minwindef::FILETIME;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetProcessTimes, OpenProcess};
+    use winapi::um::psapi::{GetProcessMemoryInfo, PROCESS_MEMORY_COUNTERS};
+    use winapi::um::winnt::{PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_VM_READ};
+
+    /// FILETIME in 100 ns units
+    fn filetime(time: &FILETIME) -> Duration {
+        let units = (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+        Duration::from_nanos(units * 100)
+    }
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ, 0, pid);
+        if handle.is_null() {
+            return None;
+        }
+
+        let mut created: FILETIME = std::mem::zeroed();
+        let mut exited: FILETIME = std::mem::zeroed();
+        let mut kernel: FILETIME = std::mem::zeroed();
+        let mut user: FILETIME = std::mem::zeroed();
+        let mut memory: PROCESS_MEMORY_COUNTERS = std::mem::zeroed();
+        memory.cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+
+        let usage = if GetProcessTimes(handle, &mut created, &mut exited, &mut kernel, &mut user)
+            != 0
+            && GetProcessMemoryInfo(handle, &mut memory, memory.cb) != 0
+        {
+            Some(ProcessUsage {
+                memory_bytes: memory.WorkingSetSize as u64,
+                cpu_time: filetime(&kernel) + filetime(&user),
+            })
+        } else {
+            None
+        };
+        CloseHandle(handle);
+        usage
+    }
+}
+
+/// Resource usage of the process with `pid`, `None` if it cannot be read
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn process_usage(_pid: u32) -> Option<ProcessUsage> {
+    None
+}
+
+/// Executable name of the process with `pid`, `None` if it cannot be read
+#[cfg(target_os = "linux")]
+pub fn process_name(pid: u32) -> Option<String> {
+    let comm = std::fs::read_to_string(format!("/proc/{}/comm", pid)).ok()?;
+    Some(comm.trim_end().to_string())
+}
+
+/// Executable name of the process with `pid`, `None` if it cannot be read
+#[cfg(target_os = "macos")]
+pub fn process_name(pid: u32) -> Option<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "comm=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Executable name of the process with `pid`, `None` if it cannot be read
+#[cfg(windows)]
+pub fn process_name(pid: u32) -> Option<String> {
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::OpenProcess;
+    use winapi::um::winbase::QueryFullProcessImageNameW;
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    unsafe {
+        let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if handle.is_null() {
+            return None;
+        }
+        let mut buffer = [0u16; 1024];
+        let mut size = buffer.len() as u32;
+        let ok = QueryFullProcessImageNameW(handle, 0, buffer.as_mut_ptr(), &mut size) != 0;
+        CloseHandle(handle);
+        ok.then(|| String::from_utf16_lossy(&buffer[..size as usize]))
+    }
+}
+
+/// Executable name of the process with `pid`, `None` if it cannot be read
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn process_name(_pid: u32) -> Option<String> {
+    None
+}
+
+/// Whether the process with `pid` is running the Xray binary
+///
+/// A PID recorded by an earlier session may have been reused by an
+/// unrelated process, so it is only treated as Xray if the name matches.
+pub fn is_xray_process(pid: u32) -> bool {
+    is_process_alive(pid) && process_name(pid).is_some_and(|name| is_xray_name(&name))
+}
+
+/// Whether an executable name or path refers to the Xray binary
+fn is_xray_name(name: &str) -> bool {
+    let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let file_name = file_name.to_ascii_lowercase();
+    file_name.strip_suffix(".exe").unwrap_or(&file_name) == "xray"
+}
+
+/// Parse `/proc/<pid>/stat` and `/proc/<pid>/statm`
+///
+/// The command name in `stat` is parenthesized and may contain spaces, so
+/// fields are counted from the closing parenthesis.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_usage(stat: &str, statm: &str, ticks: u64, page_size: u64) -> Option<ProcessUsage> {
+    if ticks == 0 {
+        return None;
+    }
+    let fields: Vec<&str> = stat
+        .get(stat.rfind(')')? + 1..)?
+        .split_whitespace()
+        .collect();
+    // utime and stime are fields 14 and 15; state (field 3) is the first
+    // after the command name
+    let utime: u64 = fields.get(11)?.parse().ok()?;
+    let stime: u64 = fields.get(12)?.parse().ok()?;
+    let resident: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+    Some(ProcessUsage {
+        memory_bytes: resident * page_size,
+        cpu_time: Duration::from_millis((utime + stime) * 1000 / ticks),
+    })
+}
+
+/// Parse `ps -o rss=,time=` output: resident KiB and `[[dd-]hh:]mm:ss.ss`
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+fn parse_ps_usage(output: &str) -> Option<ProcessUsage> {
+    let mut fields = output.split_whitespace();
+    let rss_kib: u64 = fields.next()?.parse().ok()?;
+    let time = fields.next()?;
+
+    let (days, clock) = match time.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, time),
+    };
+    let mut seconds = 0.0;
+    for part in clock.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    seconds += days as f64 * 86400.0;
+
+    Some(ProcessUsage {
+        memory_bytes: rss_kib * 1024,
+        cpu_time: Duration::from_secs_f64(seconds),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_process_alive() {
+        assert!(is_process_alive(std::process::id()));
+        assert!(!is_xray_process(std::process::id()));
+    }
+
+    #[test]
+    fn test_is_xray_name() {
+        assert!(is_xray_name("xray"));
+        assert!(is_xray_name(
+            "/Applications/V8Ray.app/Contents/MacOS/bin/xray"
+        ));
+        assert!(is_xray_name("C:\\Program Files\\V8Ray\\bin\\Xray.exe"));
+        assert!(!is_xray_name("xray-helper"));
+        assert!(!is_xray_name("/usr/bin/sleep"));
+    }
+
+    #[test]
+    fn test_parse_usage() {
+        let stat =
+            "4242 (xray (v25)) S 1 4242 4242 0 -1 4194560 1 0 0 0 250 50 0 0 20 0 12 0 1 0 0";
+        let usage = parse_proc_usage(stat, "50000 3000 800 1 0 4000 0", 100, 4096).unwrap();
+        assert_eq!(usage.cpu_time, Duration::from_secs(3));
+        assert_eq!(usage.memory_bytes, 3000 * 4096);
+
+        let usage = parse_ps_usage(" 20480   1:02.50\n").unwrap();
+        assert_eq!(usage.memory_bytes, 20480 * 1024);
+        assert_eq!(usage.cpu_time, Duration::from_millis(62_500));
+        let usage = parse_ps_usage("1024 1-01:00:00.00").unwrap();
+        assert_eq!(usage.cpu_time, Duration::from_secs(90_000));
+
+        let previous = ProcessUsage {
+            memory_bytes: 0,
+            cpu_time: Duration::from_secs(1),
+        };
+        let current = ProcessUsage {
+            memory_bytes: 0,
+            cpu_time: Duration::from_millis(1500),
+        };
+        assert_eq!(
+            cpu_percent(&previous, &current, Duration::from_secs(5)),
+            10.0
+        );
+    }
+}
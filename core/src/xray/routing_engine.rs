@@ -0,0 +1,644 @@
+//! Local evaluation of Xray routing rules
+//!
+//! Answers "which outbound would this go to" for a domain or IP without
+//! sending traffic, to debug why a site is or is not proxied. Rules are
+//! evaluated in order as Xray does, with `geosite:`, `geoip:` and `ext:`
+//! lists read from Xray's geo data files.
+//!
+//! Traffic is assumed to enter through the local HTTP or SOCKS inbound.
+//! Conditions that depend on the connecting application (process, source
+//! address, user) cannot be known in advance; rules using them are
+//! reported as skipped. Domains are resolved with the system resolver where
+//! the domain strategy asks for IPs, which may differ from Xray's own DNS.
+
+use super::geodata::{self, GeoDomain, GeoDomainKind, GeoIpList, IpNetwork};
+use super::{config_file, XrayConfig, XrayError};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Rule fields that are not conditions
+const RULE_FIELDS: &[&str] = &["type", "outboundTag", "balancerTag", "ruleTag"];
+
+/// Where a target would be routed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteMatch {
+    /// Index of the rule that matched, None if no rule matched and the
+    /// default outbound is used
+    pub rule_index: Option<usize>,
+    /// The rule that matched
+    pub rule: Option<Value>,
+    /// Outbound tag the traffic goes to, None when routed to a balancer
+    pub outbound_tag: Option<String>,
+    /// Balancer the traffic goes to
+    pub balancer_tag: Option<String>,
+    /// Addresses the domain resolved to, if the domain strategy resolved it
+    pub resolved_ips: Vec<IpAddr>,
+    /// Indexes of earlier rules that could have matched, depending on the
+    /// connecting application
+    pub skipped_rules: Vec<usize>,
+}
+
+/// Compiled matcher of a geosite list entry
+#[derive(Debug)]
+enum DomainMatcher {
+    Keyword(String),
+    Regex(regex::Regex),
+    Domain(String),
+    Full(String),
+}
+
+impl DomainMatcher {
+    fn from_geo(domain: &GeoDomain) -> Option<Self> {
+        let value = domain.value.to_lowercase();
+        Some(match domain.kind {
+            GeoDomainKind::Keyword => DomainMatcher::Keyword(value),
+            GeoDomainKind::Regex => DomainMatcher::Regex(regex::Regex::new(&domain.value).ok()?),
+            GeoDomainKind::Domain => DomainMatcher::Domain(value),
+            GeoDomainKind::Full => DomainMatcher::Full(value),
+        })
+    }
+
+    fn matches(&self, domain: &str) -> bool {
+        match self {
+            DomainMatcher::Keyword(keyword) => domain.contains(keyword.as_str()),
+            DomainMatcher::Regex(regex) => regex.is_match(domain),
+            DomainMatcher::Domain(suffix) => {
+                domain == suffix
+                    || domain
+                        .strip_suffix(suffix.as_str())
+                        .is_some_and(|rest| rest.ends_with('.'))
+            }
+            DomainMatcher::Full(full) => domain == full,
+        }
+    }
+}
+
+/// Target being routed
+struct Target<'a> {
+    domain: Option<&'a str>,
+    ips: &'a [IpAddr],
+    port: u16,
+    network: &'a str,
+    protocol: Option<&'a str>,
+}
+
+/// Outcome of one rule
+enum RuleOutcome {
+    Match,
+    NoMatch,
+    /// Every known condition matched, but others cannot be evaluated
+    Unknown,
+}
+
+/// Routing rules of an Xray configuration, evaluated locally
+pub struct RoutingEngine {
+    /// Rules in evaluation order
+    rules: Vec<Value>,
+    /// Xray domain strategy
+    domain_strategy: String,
+    /// Outbound used when no rule matches
+    default_outbound: Option<String>,
+    /// Tags of the HTTP and SOCKS inbounds traffic enters through
+    inbound_tags: Vec<String>,
+    /// Directory holding the geo data files
+    asset_dir: Option<PathBuf>,
+    /// Loaded geosite lists by "file:list@attribute"
+    sites: Mutex<HashMap<String, Arc<Vec<DomainMatcher>>>>,
+    /// Loaded GeoIP lists by "file:list"
+    ips: Mutex<HashMap<String, Arc<GeoIpList>>>,
+}
+
+impl RoutingEngine {
+    /// Create an engine for the routing of `config`, reading geo data files
+    /// from `asset_dir`
+    pub fn new(config: &XrayConfig, asset_dir: Option<PathBuf>) -> Self {
+        let routing = config.routing.as_ref();
+        Self {
+            rules: routing.map(|r| r.rules.clone()).unwrap_or_default(),
+            domain_strategy: routing
+                .and_then(|r| r.domain_strategy.clone())
+                .unwrap_or_else(|| "AsIs".to_string()),
+            default_outbound: config.outbounds.first().and_then(|o| o.tag.clone()),
+            inbound_tags: config
+                .inbounds
+                .iter()
+                .filter(|inbound| matches!(inbound.protocol.as_str(), "http" | "socks"))
+                .filter_map(|inbound| inbound.tag.clone())
+                // Traffic the routing script picked an outbound for
+                .filter(|tag| {
+                    !tag.starts_with(crate::connection::script_routing::SCRIPT_INBOUND_TAG_PREFIX)
+                })
+                .collect(),
+            asset_dir,
+            sites: Mutex::new(HashMap::new()),
+            ips: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Create an engine for the newest Xray configuration file in
+    /// `config_dir` still used by a running app or CLI
+    pub fn load_running(config_dir: &Path, asset_dir: Option<PathBuf>) -> Result<Self, XrayError> {
+        let path = config_file::newest_in_use(config_dir)
+            .ok_or_else(|| XrayError::Config("No running Xray configuration found".to_string()))?;
+        let content = std::fs::read_to_string(&path)?;
+        let config: XrayConfig = serde_json::from_str(&content).map_err(|e| {
+            XrayError::Config(format!(
+                "Invalid Xray configuration {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Self::new(&config, asset_dir))
+    }
+
+    /// Find the rule and outbound `target` (a domain or IP) would be routed
+    /// to on `port`
+    ///
+    /// `protocol` is "tcp" or "udp" for the network, or a sniffed protocol
+    /// ("http", "tls", "bittorrent") for TCP traffic; None means TCP of an
+    /// unknown protocol.
+    pub async fn match_target(
+        &self,
+        target: &str,
+        port: u16,
+        protocol: Option<&str>,
+    ) -> Result<RouteMatch, XrayError> {
+        let target = target.trim().trim_end_matches('.').to_lowercase();
+        let target = target
+            .strip_prefix('[')
+            .and_then(|t| t.strip_suffix(']'))
+            .unwrap_or(&target);
+        let (network, protocol) = match protocol.map(str::to_lowercase).as_deref() {
+            Some("udp") => ("udp", None),
+            Some("tcp") | None => ("tcp", None),
+            Some(protocol) => ("tcp", Some(protocol.to_string())),
+        };
+
+        if let Ok(ip) = target.parse::<IpAddr>() {
+            return self.evaluate(&Target {
+                domain: None,
+                ips: &[ip],
+                port,
+                network,
+                protocol: protocol.as_deref(),
+            });
+        }
+
+        let mut query = Target {
+            domain: Some(target),
+            ips: &[],
+            port,
+            network,
+            protocol: protocol.as_deref(),
+        };
+        match self.domain_strategy.as_str() {
+            "IPOnDemand" if self.has_ip_rules() => {
+                let ips = resolve(target).await;
+                query.ips = &ips;
+                let mut result = self.evaluate(&query)?;
+                result.resolved_ips = ips.clone();
+                Ok(result)
+            }
+            "IPIfNonMatch" => {
+                let result = self.evaluate(&query)?;
+                if result.rule_index.is_some() {
+                    return Ok(result);
+                }
+                let ips = resolve(target).await;
+                if ips.is_empty() {
+                    return Ok(result);
+                }
+                query.ips = &ips;
+                let mut second = self.evaluate(&query)?;
+                second.resolved_ips = ips.clone();
+                second.skipped_rules.extend(result.skipped_rules);
+                second.skipped_rules.sort_unstable();
+                second.skipped_rules.dedup();
+                Ok(second)
+            }
+            _ => self.evaluate(&query),
+        }
+    }
+
+    /// Whether any rule has an IP condition
+    fn has_ip_rules(&self) -> bool {
+        self.rules.iter().any(|rule| rule.get("ip").is_some())
+    }
+
+    /// Evaluate the rules in order for `target`
+    fn evaluate(&self, target: &Target) -> Result<RouteMatch, XrayError> {
+        let mut skipped_rules = Vec::new();
+        for (index, rule) in self.rules.iter().enumerate() {
+            match self.rule_matches(rule, target)? {
+                RuleOutcome::NoMatch => {}
+                RuleOutcome::Unknown => skipped_rules.push(index),
+                RuleOutcome::Match => {
+                    let tag = |key: &str| rule.get(key).and_then(Value::as_str).map(String::from);
+                    return Ok(RouteMatch {
+                        rule_index: Some(index),
+                        rule: Some(rule.clone()),
+                        outbound_tag: tag("outboundTag"),
+                        balancer_tag: tag("balancerTag"),
+                        resolved_ips: Vec::new(),
+                        skipped_rules,
+                    });
+                }
+            }
+        }
+        Ok(RouteMatch {
+            rule_index: None,
+            rule: None,
+            outbound_tag: self.default_outbound.clone(),
+            balancer_tag: None,
+            resolved_ips: Vec::new(),
+            skipped_rules,
+        })
+    }
+
+    /// Evaluate every condition of `rule`, all of which must match
+    fn rule_matches(&self, rule: &Value, target: &Target) -> Result<RuleOutcome, XrayError> {
+        let Some(fields) = rule.as_object() else {
+            return Ok(RuleOutcome::NoMatch);
+        };
+
+        let mut unknown = false;
+        for (key, value) in fields {
+            let matched = match key.as_str() {
+                "domain" | "domains" => match target.domain {
+                    Some(domain) => self.any_domain_matches(value, domain)?,
+                    None => false,
+                },
+                "ip" => self.any_ip_matches(value, target.ips)?,
+                "port" => port_matches(value, target.port),
+                "network" => list(value).iter().any(|n| n == target.network),
+                "protocol" => target
+                    .protocol
+                    .is_some_and(|p| list(value).iter().any(|v| v.eq_ignore_ascii_case(p))),
+                "inboundTag" => list(value)
+                    .iter()
+                    .any(|tag| self.inbound_tags.contains(tag)),
+                key if RULE_FIELDS.contains(&key) => true,
+                // Conditions on the connecting application (process,
+                // source, user, …) or unknown to this version
+                _ => {
+                    unknown = true;
+                    true
+                }
+            };
+            if !matched {
+                return Ok(RuleOutcome::NoMatch);
+            }
+        }
+        Ok(if unknown {
+            RuleOutcome::Unknown
+        } else {
+            RuleOutcome::Match
+        })
+    }
+
+    /// Whether `domain` matches any matcher of the `domain` list
+    fn any_domain_matches(&self, matchers: &Value, domain: &str) -> Result<bool, XrayError> {
+        for matcher in list(matchers) {
+            if self.domain_matches(&matcher, domain)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn domain_matches(&self, matcher: &str, domain: &str) -> Result<bool, XrayError> {
+        let (prefix, value) = matcher.split_once(':').unwrap_or(("", matcher));
+        let value = value.to_lowercase();
+        Ok(match prefix {
+            "domain" => DomainMatcher::Domain(value).matches(domain),
+            "full" => DomainMatcher::Full(value).matches(domain),
+            "keyword" => DomainMatcher::Keyword(value).matches(domain),
+            "regexp" => regex::Regex::new(matcher.split_once(':').map_or("", |(_, r)| r))
+                .map_err(|e| XrayError::Config(format!("Invalid domain regexp: {}", e)))?
+                .is_match(domain),
+            "dotless" => !domain.contains('.') && domain.contains(value.as_str()),
+            "geosite" => self
+                .site(geodata::GEOSITE_FILE, &value)?
+                .iter()
+                .any(|m| m.matches(domain)),
+            "ext" => {
+                let (file, list) = value.split_once(':').ok_or_else(|| {
+                    XrayError::Config(format!("Invalid domain matcher: {}", matcher))
+                })?;
+                self.site(file, list)?.iter().any(|m| m.matches(domain))
+            }
+            // No prefix, or one Xray reads as part of a keyword
+            _ => DomainMatcher::Keyword(matcher.to_lowercase()).matches(domain),
+        })
+    }
+
+    /// Whether any of `ips` matches any matcher of the `ip` list
+    fn any_ip_matches(&self, matchers: &Value, ips: &[IpAddr]) -> Result<bool, XrayError> {
+        if ips.is_empty() {
+            return Ok(false);
+        }
+        for matcher in list(matchers) {
+            let matcher = matcher.to_lowercase();
+            let (prefix, value) = matcher.split_once(':').unwrap_or(("", &matcher));
+            let matched = match prefix {
+                "geoip" => {
+                    let (negate, code) = match value.strip_prefix('!') {
+                        Some(code) => (true, code),
+                        None => (false, value),
+                    };
+                    let list = self.ip_list(geodata::GEOIP_FILE, code)?;
+                    ips.iter().any(|&ip| list.contains(ip) != negate)
+                }
+                "ext" => {
+                    let (file, code) = value.split_once(':').ok_or_else(|| {
+                        XrayError::Config(format!("Invalid IP matcher: {}", matcher))
+                    })?;
+                    let list = self.ip_list(file, code)?;
+                    ips.iter().any(|&ip| list.contains(ip))
+                }
+                _ => match IpNetwork::parse(&matcher) {
+                    Some(net) => ips.iter().any(|&ip| net.contains(ip)),
+                    None => {
+                        return Err(XrayError::Config(format!(
+                            "Invalid IP matcher: {}",
+                            matcher
+                        )))
+                    }
+                },
+            };
+            if matched {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn asset_dir(&self) -> Result<&Path, XrayError> {
+        self.asset_dir
+            .as_deref()
+            .ok_or_else(|| XrayError::Config("Xray geo data directory not found".to_string()))
+    }
+
+    /// Geosite list `name[@attribute]` of `file`, loaded once
+    fn site(&self, file: &str, name: &str) -> Result<Arc<Vec<DomainMatcher>>, XrayError> {
+        let key = format!("{}:{}", file, name);
+        if let Some(site) = self
+            .sites
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+        {
+            return Ok(Arc::clone(site));
+        }
+
+        let (code, attribute) = match name.split_once('@') {
+            Some((code, attribute)) => (code, Some(attribute)),
+            None => (name, None),
+        };
+        let matchers: Vec<DomainMatcher> = geodata::load_geosite(self.asset_dir()?, file, code)?
+            .iter()
+            .filter(|domain| {
+                attribute.is_none_or(|attribute| {
+                    domain
+                        .attributes
+                        .iter()
+                        .any(|a| a.eq_ignore_ascii_case(attribute))
+                })
+            })
+            .filter_map(DomainMatcher::from_geo)
+            .collect();
+        let matchers = Arc::new(matchers);
+        self.sites
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, Arc::clone(&matchers));
+        Ok(matchers)
+    }
+
+    /// GeoIP list `code` of `file`, loaded once
+    fn ip_list(&self, file: &str, code: &str) -> Result<Arc<GeoIpList>, XrayError> {
+        let key = format!("{}:{}", file, code);
+        if let Some(list) = self.ips.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+            return Ok(Arc::clone(list));
+        }
+
+        let list = Arc::new(geodata::load_geoip(self.asset_dir()?, file, code)?);
+        self.ips
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, Arc::clone(&list));
+        Ok(list)
+    }
+}
+
+/// Values of a rule field given as a list or a comma-separated string
+fn list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .filter_map(|v| match v {
+                Value::String(s) => Some(s.clone()),
+                Value::Number(n) => Some(n.to_string()),
+                _ => None,
+            })
+            .collect(),
+        Value::String(s) => s.split(',').map(|s| s.trim().to_string()).collect(),
+        Value::Number(n) => vec![n.to_string()],
+        _ => Vec::new(),
+    }
+}
+
+/// Whether `port` is in a port list such as "53,443,1000-2000"
+fn port_matches(value: &Value, port: u16) -> bool {
+    list(value).iter().any(|range| match range.split_once('-') {
+        Some((start, end)) => match (start.trim().parse::<u16>(), end.trim().parse::<u16>()) {
+            (Ok(start), Ok(end)) => (start..=end).contains(&port),
+            _ => false,
+        },
+        None => range.parse::<u16>() == Ok(port),
+    })
+}
+
+/// Resolve `domain` with the system resolver, no addresses on failure
+async fn resolve(domain: &str) -> Vec<IpAddr> {
+    match tokio::net::lookup_host((domain, 0)).await {
+        Ok(addrs) => {
+            let mut ips: Vec<IpAddr> = addrs.map(|addr| addr.ip()).collect();
+            ips.dedup();
+            ips
+        }
+        Err(e) => {
+            tracing::debug!("Failed to resolve {} for route test: {}", domain, e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::xray::geodata::encode;
+    use serde_json::json;
+
+    fn config(rules: Vec<Value>, domain_strategy: &str) -> XrayConfig {
+        serde_json::from_value(json!({
+            "log": {"loglevel": "warning"},
+            "inbounds": [{"port": 1080, "protocol": "socks", "tag": "socks-in"}],
+            "outbounds": [
+                {"tag": "proxy", "protocol": "vless"},
+                {"tag": "direct", "protocol": "freedom"},
+                {"tag": "block", "protocol": "blackhole"}
+            ],
+            "routing": {"domainStrategy": domain_strategy, "rules": rules}
+        }))
+        .unwrap()
+    }
+
+    fn asset_dir() -> tempfile::TempDir {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            dir.path().join(geodata::GEOSITE_FILE),
+            encode::geosite(&[(
+                "cn",
+                &[
+                    (2, "baidu.com", &[]),
+                    (3, "qq.com", &[]),
+                    (2, "apple.cn", &["ads"]),
+                ],
+            )]),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join(geodata::GEOIP_FILE),
+            encode::geoip(&[
+                ("cn", &[(&[36, 0, 0, 0], 8)]),
+                ("private", &[(&[127, 0, 0, 0], 8), (&[10, 0, 0, 0], 8)]),
+            ]),
+        )
+        .unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_match_domains_and_ips() {
+        let dir = asset_dir();
+        let engine = RoutingEngine::new(
+            &config(
+                vec![
+                    json!({"type": "field", "process": ["chrome.exe"], "outboundTag": "block"}),
+                    json!({"type": "field", "domain": ["domain:example.com", "full:ads.test"], "port": "443", "outboundTag": "block"}),
+                    json!({"type": "field", "protocol": ["bittorrent"], "outboundTag": "direct"}),
+                    json!({"type": "field", "ip": ["geoip:private"], "outboundTag": "direct"}),
+                    json!({"type": "field", "domain": ["geosite:cn"], "outboundTag": "direct"}),
+                    json!({"type": "field", "domain": ["geosite:cn@ads"], "outboundTag": "block"}),
+                    json!({"type": "field", "ip": ["geoip:cn"], "outboundTag": "direct"}),
+                ],
+                "AsIs",
+            ),
+            Some(dir.path().to_path_buf()),
+        );
+
+        let result = engine
+            .match_target("www.Example.com", 443, None)
+            .await
+            .unwrap();
+        assert_eq!(result.rule_index, Some(1));
+        assert_eq!(result.outbound_tag.as_deref(), Some("block"));
+        assert_eq!(result.skipped_rules, vec![0]);
+
+        // Wrong port, falls through to the default outbound
+        let result = engine.match_target("example.com", 80, None).await.unwrap();
+        assert_eq!(result.rule_index, None);
+        assert_eq!(result.outbound_tag.as_deref(), Some("proxy"));
+
+        let result = engine
+            .match_target("tracker.test", 6881, Some("bittorrent"))
+            .await
+            .unwrap();
+        assert_eq!(result.rule_index, Some(2));
+
+        let result = engine
+            .match_target("map.baidu.com", 443, None)
+            .await
+            .unwrap();
+        assert_eq!(result.rule_index, Some(4));
+        assert!(engine
+            .match_target("notqq.com", 443, None)
+            .await
+            .unwrap()
+            .rule_index
+            .is_none());
+        assert_eq!(
+            engine
+                .match_target("x.apple.cn", 443, None)
+                .await
+                .unwrap()
+                .rule_index,
+            Some(4)
+        );
+
+        let result = engine
+            .match_target("36.1.1.1", 443, Some("udp"))
+            .await
+            .unwrap();
+        assert_eq!(result.rule_index, Some(6));
+        let result = engine.match_target("10.1.1.1", 22, None).await.unwrap();
+        assert_eq!(result.rule_index, Some(3));
+    }
+
+    #[tokio::test]
+    async fn test_ip_if_non_match_resolves() {
+        let dir = asset_dir();
+        let engine = RoutingEngine::new(
+            &config(
+                vec![
+                    json!({"type": "field", "inboundTag": ["api"], "outboundTag": "api"}),
+                    json!({"type": "field", "inboundTag": ["socks-in"], "network": "udp", "port": 53, "outboundTag": "dns-out"}),
+                    json!({"type": "field", "ip": ["127.0.0.0/8", "::1"], "outboundTag": "direct"}),
+                    json!({"type": "field", "balancerTag": "balancer", "port": "443"}),
+                ],
+                "IPIfNonMatch",
+            ),
+            Some(dir.path().to_path_buf()),
+        );
+
+        let result = engine.match_target("localhost", 80, None).await.unwrap();
+        assert_eq!(result.rule_index, Some(2));
+        assert!(!result.resolved_ips.is_empty());
+
+        let result = engine
+            .match_target("1.2.3.4", 53, Some("udp"))
+            .await
+            .unwrap();
+        assert_eq!(result.outbound_tag.as_deref(), Some("dns-out"));
+
+        let result = engine.match_target("1.2.3.4", 443, None).await.unwrap();
+        assert_eq!(result.outbound_tag, None);
+        assert_eq!(result.balancer_tag.as_deref(), Some("balancer"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_geo_data() {
+        let engine = RoutingEngine::new(
+            &config(
+                vec![json!({"type": "field", "domain": ["geosite:cn"], "outboundTag": "direct"})],
+                "AsIs",
+            ),
+            None,
+        );
+        assert!(engine.match_target("baidu.com", 443, None).await.is_err());
+        // Rules without geo data still work for IPs
+        assert!(engine.match_target("1.1.1.1", 443, None).await.is_ok());
+    }
+
+    #[test]
+    fn test_port_list() {
+        assert!(port_matches(&json!("53,443,1000-2000"), 1500));
+        assert!(port_matches(&json!(443), 443));
+        assert!(!port_matches(&json!("53,443"), 80));
+    }
+}
@@ -0,0 +1,190 @@
+//! Download sources for Xray Core updates
+//!
+//! github.com is often slow or unreachable from mainland China, so releases
+//! can also be downloaded through a mirror that takes the GitHub URL after
+//! a prefix (ghproxy style), from a server laid out like GitHub's release
+//! downloads, or from GitHub through the app's own proxy. Sources are tried
+//! in the configured order until one succeeds. Whatever the source, the
+//! download is verified before it is installed (see [`super::integrity`]).
+
+use serde::{Deserialize, Serialize};
+
+/// Base URL of Xray Core release downloads on GitHub
+pub const GITHUB_DOWNLOAD_BASE: &str = "https://github.com/XTLS/Xray-core/releases/download";
+
+/// Where Xray Core releases are downloaded from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DownloadSource {
+    /// github.com, directly
+    Github,
+    /// github.com through the proxy (see [`DownloadSettings::proxy_url`])
+    Proxy,
+    /// Mirror fetching the GitHub URL put after `prefix`, like
+    /// `https://ghproxy.net/`
+    Mirror {
+        /// URL prefix
+        prefix: String,
+    },
+    /// Server with the same layout as GitHub's release downloads:
+    /// `<base_url>/v<version>/<file>`
+    CustomBase {
+        /// Base URL
+        base_url: String,
+    },
+}
+
+impl DownloadSource {
+    /// URL of the release file `file_name` of `version`
+    pub fn url(&self, version: &str, file_name: &str) -> String {
+        let github = github_url(version, file_name);
+        match self {
+            DownloadSource::Github | DownloadSource::Proxy => github,
+            DownloadSource::Mirror { prefix } => {
+                format!("{}/{}", prefix.trim_end_matches('/'), github)
+            }
+            DownloadSource::CustomBase { base_url } => format!(
+                "{}/v{}/{}",
+                base_url.trim_end_matches('/'),
+                version,
+                file_name
+            ),
+        }
+    }
+
+    /// Whether files come from GitHub itself, so checksums and API
+    /// responses from this source can be trusted
+    pub fn is_github(&self) -> bool {
+        matches!(self, DownloadSource::Github | DownloadSource::Proxy)
+    }
+
+    /// Short description for logs
+    pub fn describe(&self) -> String {
+        match self {
+            DownloadSource::Github => "GitHub".to_string(),
+            DownloadSource::Proxy => "GitHub through the proxy".to_string(),
+            DownloadSource::Mirror { prefix } => format!("mirror {}", prefix),
+            DownloadSource::CustomBase { base_url } => format!("custom source {}", base_url),
+        }
+    }
+
+    /// Check the mirror or custom URL
+    pub fn validate(&self) -> Result<(), String> {
+        let url = match self {
+            DownloadSource::Github | DownloadSource::Proxy => return Ok(()),
+            DownloadSource::Mirror { prefix } => prefix,
+            DownloadSource::CustomBase { base_url } => base_url,
+        };
+        match url::Url::parse(url) {
+            Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+            _ => Err(format!("Invalid download source URL: {}", url)),
+        }
+    }
+}
+
+/// Download sources of Xray Core updates
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DownloadSettings {
+    /// Sources in the order they are tried
+    pub sources: Vec<DownloadSource>,
+    /// Proxy for [`DownloadSource::Proxy`], None for the app's local HTTP
+    /// inbound while connected
+    #[serde(default)]
+    pub proxy_url: Option<String>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            sources: vec![DownloadSource::Github, DownloadSource::Proxy],
+            proxy_url: None,
+        }
+    }
+}
+
+impl DownloadSettings {
+    /// Check every source and the proxy URL
+    pub fn validate(&self) -> Result<(), String> {
+        if self.sources.is_empty() {
+            return Err("At least one download source is needed".to_string());
+        }
+        for source in &self.sources {
+            source.validate()?;
+        }
+        if let Some(proxy_url) = &self.proxy_url {
+            reqwest::Proxy::all(proxy_url)
+                .map_err(|e| format!("Invalid proxy URL {}: {}", proxy_url, e))?;
+        }
+        Ok(())
+    }
+
+    /// Sources serving files from GitHub itself, in order, or direct
+    /// GitHub if none is configured
+    pub fn github_sources(&self) -> Vec<DownloadSource> {
+        let sources: Vec<DownloadSource> = self
+            .sources
+            .iter()
+            .filter(|source| source.is_github())
+            .cloned()
+            .collect();
+        if sources.is_empty() {
+            vec![DownloadSource::Github]
+        } else {
+            sources
+        }
+    }
+}
+
+/// GitHub URL of the release file `file_name` of `version`
+pub fn github_url(version: &str, file_name: &str) -> String {
+    format!("{}/v{}/{}", GITHUB_DOWNLOAD_BASE, version, file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_urls() {
+        let file = "Xray-linux-64.zip";
+        assert_eq!(
+            DownloadSource::Github.url("1.8.24", file),
+            "https://github.com/XTLS/Xray-core/releases/download/v1.8.24/Xray-linux-64.zip"
+        );
+        assert_eq!(
+            DownloadSource::Mirror {
+                prefix: "https://ghproxy.net/".to_string()
+            }
+            .url("1.8.24", file),
+            "https://ghproxy.net/https://github.com/XTLS/Xray-core/releases/download/v1.8.24/Xray-linux-64.zip"
+        );
+        assert_eq!(
+            DownloadSource::CustomBase {
+                base_url: "https://mirror.example.com/xray/".to_string()
+            }
+            .url("1.8.24", file),
+            "https://mirror.example.com/xray/v1.8.24/Xray-linux-64.zip"
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(DownloadSettings::default().validate().is_ok());
+        let settings = DownloadSettings {
+            sources: vec![DownloadSource::Mirror {
+                prefix: "ftp://mirror".to_string(),
+            }],
+            proxy_url: None,
+        };
+        assert!(settings.validate().is_err());
+        assert!(DownloadSettings {
+            sources: vec![],
+            proxy_url: None,
+        }
+        .validate()
+        .is_err());
+
+        // Mirrors only fall back to direct GitHub for checksums
+        assert_eq!(settings.github_sources(), vec![DownloadSource::Github]);
+    }
+}
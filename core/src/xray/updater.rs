@@ -1,13 +1,36 @@
 //! Xray Core Updater Module
 //!
 //! This module handles downloading and updating Xray Core binary.
-
+//! Releases are installed side by side (see [`VersionStore`]), so updating
+//! selects the new version and rolling back selects the previous one again.
+//! Downloads fall back across the configured [`DownloadSource`]s and are
+//! verified (see [`super::integrity`]) before anything is installed.
+
+use super::cache::DownloadCache;
+use super::integrity::{parse_digest, verify_checksum, MinisignPublicKey};
+use super::sources::{github_url, DownloadSettings, DownloadSource};
+use super::versions::{is_valid_version, VersionSelection, VersionStore};
 use super::XrayError;
+use crate::error::IntegrityError;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
+/// Minimum free space required in the bin directory to install an update
+const MIN_INSTALL_SPACE: u64 = 64 * 1024 * 1024;
+
+/// Timeout of downloads and GitHub API requests
+const DOWNLOAD_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// Release the geo data files are updated from; each file is published
+/// with a `.sha256sum` beside it
+const GEO_ASSETS_BASE_URL: &str =
+    "https://github.com/Loyalsoldier/v2ray-rules-dat/releases/latest/download";
+
+/// Geo data files updated by [`XrayUpdater::update_geo_assets`]
+pub const GEO_ASSETS: [&str; 2] = [super::geodata::GEOIP_FILE, super::geodata::GEOSITE_FILE];
+
 // Windows-specific imports for hiding console window
 #[cfg(windows)]
 use std::os::windows::process::CommandExt;
@@ -27,30 +50,135 @@ pub struct UpdateInfo {
     pub file_size: u64,
 }
 
+/// Published Xray Core release
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct XrayRelease {
+    /// Version without the "v" prefix
+    pub version: String,
+    /// Publication time (RFC 3339)
+    pub published_at: Option<String>,
+    /// Whether this is a pre-release
+    pub prerelease: bool,
+}
+
 /// Xray Core updater
 pub struct XrayUpdater {
     /// Binary directory path
     bin_dir: PathBuf,
+    /// Directory for downloads
+    cache_dir: PathBuf,
     /// HTTP client
     client: reqwest::Client,
     /// Download progress (0.0 to 1.0)
     progress: std::sync::Arc<tokio::sync::RwLock<f64>>,
+    /// Version being installed, if any
+    installing: std::sync::RwLock<Option<String>>,
+    /// Downloaded archive cache
+    cache: DownloadCache,
+    /// Installed versions
+    versions: VersionStore,
+    /// Download sources
+    download_settings: std::sync::RwLock<DownloadSettings>,
+    /// Local HTTP inbound of the running connection, for
+    /// [`DownloadSource::Proxy`]
+    active_proxy: std::sync::RwLock<Option<reqwest::Proxy>>,
+    /// Key releases must be signed with, if any
+    minisign_key: std::sync::RwLock<Option<MinisignPublicKey>>,
 }
 
 impl XrayUpdater {
-    /// Create a new updater
-    pub fn new(bin_dir: PathBuf) -> Self {
+    /// Create a new updater installing into `bin_dir` and downloading into
+    /// `cache_dir`
+    pub fn new(bin_dir: PathBuf, cache_dir: PathBuf) -> Self {
         Self {
+            cache: DownloadCache::new(cache_dir.join("downloads")),
+            versions: VersionStore::new(bin_dir.join("versions")),
             bin_dir,
-            client: reqwest::Client::builder()
-                .user_agent(crate::version::user_agent())
-                .timeout(std::time::Duration::from_secs(300))
+            cache_dir,
+            client: crate::version::http_client_builder()
+                .timeout(DOWNLOAD_TIMEOUT)
                 .build()
                 .unwrap(),
             progress: std::sync::Arc::new(tokio::sync::RwLock::new(0.0)),
+            installing: std::sync::RwLock::new(None),
+            download_settings: std::sync::RwLock::new(DownloadSettings::default()),
+            active_proxy: std::sync::RwLock::new(None),
+            minisign_key: std::sync::RwLock::new(None),
         }
     }
 
+    /// Require releases to carry a minisign signature by `key` (the base64
+    /// key line or a whole `.pub` file), or stop requiring one for None
+    pub fn set_minisign_public_key(&self, key: Option<&str>) -> Result<(), XrayError> {
+        let key = key.map(MinisignPublicKey::parse).transpose()?;
+        *self.minisign_key.write().unwrap_or_else(|e| e.into_inner()) = key;
+        Ok(())
+    }
+
+    /// Key releases must be signed with, if any
+    pub fn minisign_public_key(&self) -> Option<MinisignPublicKey> {
+        self.minisign_key
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Download sources
+    pub fn download_settings(&self) -> DownloadSettings {
+        self.download_settings
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Set the download sources
+    pub fn set_download_settings(&self, settings: DownloadSettings) -> Result<(), XrayError> {
+        settings.validate().map_err(XrayError::Config)?;
+        *self
+            .download_settings
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = settings;
+        Ok(())
+    }
+
+    /// Set the proxy used by [`DownloadSource::Proxy`] when no proxy URL is
+    /// configured, normally the local HTTP inbound while connected
+    pub fn set_active_proxy(&self, proxy: Option<reqwest::Proxy>) {
+        *self.active_proxy.write().unwrap_or_else(|e| e.into_inner()) = proxy;
+    }
+
+    /// HTTP client for `source`, None for the proxy source when there is no
+    /// proxy
+    fn client_for(
+        &self,
+        source: &DownloadSource,
+        settings: &DownloadSettings,
+    ) -> Result<Option<reqwest::Client>, XrayError> {
+        if *source != DownloadSource::Proxy {
+            return Ok(Some(self.client.clone()));
+        }
+        let proxy = match &settings.proxy_url {
+            Some(url) => Some(
+                reqwest::Proxy::all(url)
+                    .map_err(|e| XrayError::Config(format!("Invalid proxy URL {}: {}", url, e)))?,
+            ),
+            None => self
+                .active_proxy
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        };
+        let Some(proxy) = proxy else {
+            return Ok(None);
+        };
+        crate::version::http_client_builder()
+            .timeout(DOWNLOAD_TIMEOUT)
+            .proxy(proxy)
+            .build()
+            .map(Some)
+            .map_err(|e| XrayError::Process(format!("Failed to create HTTP client: {}", e)))
+    }
+
     /// Get current Xray Core version
     pub async fn get_current_version(&self) -> Result<String, XrayError> {
         let binary_path = self.get_binary_path();
@@ -59,42 +187,66 @@ impl XrayUpdater {
             return Ok("not installed".to_string());
         }
 
-        let mut cmd = tokio::process::Command::new(&binary_path);
-        cmd.arg("version");
+        binary_version(&binary_path).await
+    }
 
-        // On Windows, hide the console window
-        #[cfg(windows)]
-        {
-            const CREATE_NO_WINDOW: u32 = 0x08000000;
-            cmd.creation_flags(CREATE_NO_WINDOW);
-        }
+    /// Fetch latest version from GitHub
+    pub async fn fetch_latest_version(&self) -> Result<String, XrayError> {
+        let release = self
+            .github_get("https://api.github.com/repos/XTLS/Xray-core/releases/latest")
+            .await?;
 
-        let output = cmd
-            .output()
-            .await
-            .map_err(|e| XrayError::Process(e.to_string()))?;
-
-        if output.status.success() {
-            let version_output = String::from_utf8_lossy(&output.stdout);
-            // Parse version from output like "Xray 1.8.7 (Xray, Penetrates Everything.) Custom"
-            if let Some(line) = version_output.lines().next() {
-                if let Some(version) = line.split_whitespace().nth(1) {
-                    return Ok(version.to_string());
+        let tag_name = release["tag_name"]
+            .as_str()
+            .ok_or_else(|| XrayError::Process("No tag_name in response".to_string()))?;
+
+        // Remove 'v' prefix if present
+        let version = tag_name.trim_start_matches('v').to_string();
+        Ok(version)
+    }
+
+    /// Fetch the `limit` most recent releases from GitHub, newest first
+    pub async fn fetch_releases(&self, limit: usize) -> Result<Vec<XrayRelease>, XrayError> {
+        let url = format!(
+            "https://api.github.com/repos/XTLS/Xray-core/releases?per_page={}",
+            limit.clamp(1, 100)
+        );
+        let releases = self.github_get(&url).await?;
+        let releases = releases
+            .as_array()
+            .ok_or_else(|| XrayError::Process("Unexpected releases response".to_string()))?;
+        Ok(releases.iter().filter_map(parse_release).collect())
+    }
+
+    /// GET a GitHub API URL, trying the sources serving from GitHub in turn
+    async fn github_get(&self, url: &str) -> Result<serde_json::Value, XrayError> {
+        let settings = self.download_settings();
+        let mut last_error = None;
+        for source in settings.github_sources() {
+            let Some(client) = self.client_for(&source, &settings)? else {
+                continue;
+            };
+            match Self::get_json(&client, url).await {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    tracing::warn!("GitHub API request via {} failed: {}", source.describe(), e);
+                    last_error = Some(e);
                 }
             }
         }
-
-        Ok("unknown".to_string())
+        Err(last_error.unwrap_or_else(|| {
+            XrayError::Process("No download source can reach GitHub".to_string())
+        }))
     }
 
-    /// Fetch latest version from GitHub
-    pub async fn fetch_latest_version(&self) -> Result<String, XrayError> {
-        let url = "https://api.github.com/repos/XTLS/Xray-core/releases/latest";
-
-        let response =
-            self.client.get(url).send().await.map_err(|e| {
-                XrayError::Process(format!("Failed to fetch latest version: {}", e))
-            })?;
+    /// GET `url` with `client` and parse the JSON response
+    async fn get_json(client: &reqwest::Client, url: &str) -> Result<serde_json::Value, XrayError> {
+        let response = client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, crate::version::user_agent())
+            .send()
+            .await
+            .map_err(|e| XrayError::Process(format!("Failed to fetch releases: {}", e)))?;
 
         if !response.status().is_success() {
             return Err(XrayError::Process(format!(
@@ -103,18 +255,10 @@ impl XrayUpdater {
             )));
         }
 
-        let release: serde_json::Value = response
+        response
             .json()
             .await
-            .map_err(|e| XrayError::Process(format!("Failed to parse response: {}", e)))?;
-
-        let tag_name = release["tag_name"]
-            .as_str()
-            .ok_or_else(|| XrayError::Process("No tag_name in response".to_string()))?;
-
-        // Remove 'v' prefix if present
-        let version = tag_name.trim_start_matches('v').to_string();
-        Ok(version)
+            .map_err(|e| XrayError::Process(format!("Failed to parse response: {}", e)))
     }
 
     /// Check for updates
@@ -135,16 +279,15 @@ impl XrayUpdater {
         })
     }
 
-    /// Get download URL for specific version
+    /// Get the GitHub download URL for specific version
     fn get_download_url(&self, version: &str) -> Result<String, XrayError> {
-        let (os, arch, ext) = self.get_platform_info()?;
-
-        let url = format!(
-            "https://github.com/XTLS/Xray-core/releases/download/v{}/Xray-{}-{}.{}",
-            version, os, arch, ext
-        );
+        Ok(github_url(version, &self.archive_name()?))
+    }
 
-        Ok(url)
+    /// File name of the release archive for this platform
+    fn archive_name(&self) -> Result<String, XrayError> {
+        let (os, arch, ext) = self.get_platform_info()?;
+        Ok(format!("Xray-{}-{}.{}", os, arch, ext))
     }
 
     /// Get platform information for download
@@ -188,37 +331,182 @@ impl XrayUpdater {
     }
 
     /// Download and install Xray Core update
+    ///
+    /// The version is installed beside the others and selected; the one
+    /// running before stays installed for [`rollback`](Self::rollback).
     pub async fn update(&self, version: &str) -> Result<(), XrayError> {
         tracing::info!("Starting Xray Core update to version {}", version);
+        self.install_version(version).await?;
+        self.versions.select(Some(version)).await?;
+        tracing::info!("Xray Core updated successfully to version {}", version);
+        Ok(())
+    }
 
-        // 1. Download to temporary file
-        let temp_path = self.download_xray(version).await?;
+    /// Install the latest release and select it, for a first run without
+    /// any Xray binary
+    ///
+    /// Returns the installed version. Progress is reported by
+    /// [`get_progress`](Self::get_progress).
+    pub async fn bootstrap(&self) -> Result<String, XrayError> {
+        *self.progress.write().await = 0.0;
+        let version = self.fetch_latest_version().await?;
+        tracing::info!("No Xray binary found, installing Xray Core {}", version);
+        self.update(&version).await?;
+        Ok(version)
+    }
 
-        // 2. Backup current binary
-        self.backup_current_binary().await?;
+    /// Download and install `version` without selecting it
+    ///
+    /// Installed versions are not downloaded again.
+    pub async fn install_version(&self, version: &str) -> Result<(), XrayError> {
+        let version = version.trim_start_matches('v');
+        if !is_valid_version(version) {
+            return Err(XrayError::Config(format!(
+                "Invalid Xray Core version: {}",
+                version
+            )));
+        }
+        if self.versions.is_installed(version) {
+            tracing::info!("Xray Core {} is already installed", version);
+            return Ok(());
+        }
 
-        // 3. Extract and install
-        self.install_binary(&temp_path).await?;
+        *self.installing.write().unwrap_or_else(|e| e.into_inner()) = Some(version.to_string());
+        let result = self.install(version).await;
+        *self.installing.write().unwrap_or_else(|e| e.into_inner()) = None;
+        result
+    }
 
-        // 4. Verify installation
-        self.verify_installation().await?;
+    /// Download, check and move `version` into place
+    async fn install(&self, version: &str) -> Result<(), XrayError> {
+        // 0. Make sure the binary can be written
+        fs::create_dir_all(self.versions.root())
+            .await
+            .map_err(XrayError::Io)?;
+        crate::utils::preflight::preflight(self.versions.root(), MIN_INSTALL_SPACE)?;
+
+        // 1. Download to temporary file
+        let temp_path = self.download_xray(version).await?;
 
-        // 5. Cleanup
+        // 2. Extract next to the final directory, so a failed install leaves
+        //    nothing behind
+        let staging = self.versions.root().join(format!(".{}.tmp", version));
+        let _ = fs::remove_dir_all(&staging).await;
+        let result = self.install_binary(&temp_path, &staging).await;
         let _ = fs::remove_file(&temp_path).await;
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&staging).await;
+            return Err(e);
+        }
 
-        tracing::info!("Xray Core updated successfully to version {}", version);
+        // 3. Move into place
+        let version_dir = self.versions.version_dir(version);
+        let _ = fs::remove_dir_all(&version_dir).await;
+        fs::rename(&staging, &version_dir)
+            .await
+            .map_err(XrayError::Io)?;
+
+        tracing::info!("Installed Xray Core {} to {:?}", version, version_dir);
         Ok(())
     }
 
-    /// Download Xray Core binary
+    /// Download Xray Core binary, trying each download source in turn
     async fn download_xray(&self, version: &str) -> Result<PathBuf, XrayError> {
-        let download_url = self.get_download_url(version)?;
+        let file_name = self.archive_name()?;
+        // Cached under the GitHub URL whichever source it came from
+        let download_url = github_url(version, &file_name);
+
+        fs::create_dir_all(&self.cache_dir)
+            .await
+            .map_err(XrayError::Io)?;
+        let temp_path = self.cache_dir.join(format!("xray-{}.zip", version));
+
+        // Reuse a previously downloaded archive if available. The cache
+        // directory is writable by the user, so the archive is verified
+        // again like a fresh download.
+        if let Some(cached) = self.cache.get(&download_url).await? {
+            tracing::info!("Using cached Xray Core archive for {}", download_url);
+            fs::copy(&cached, &temp_path).await.map_err(XrayError::Io)?;
+            match self.verify_archive(version, &file_name, &temp_path).await {
+                Ok(()) => {
+                    *self.progress.write().await = 1.0;
+                    return Ok(temp_path);
+                }
+                Err(XrayError::Integrity(IntegrityError::ChecksumMismatch { .. }))
+                | Err(XrayError::Integrity(IntegrityError::InvalidSignature(_))) => {
+                    tracing::warn!(
+                        "Cached Xray Core archive failed verification, downloading it again"
+                    );
+                    let _ = fs::remove_file(&temp_path).await;
+                }
+                Err(e) => {
+                    let _ = fs::remove_file(&temp_path).await;
+                    return Err(e);
+                }
+            }
+        }
+
+        let settings = self.download_settings();
+        let mut failures = Vec::new();
+        let mut integrity_failure = None;
+        for source in &settings.sources {
+            let Some(client) = self.client_for(source, &settings)? else {
+                tracing::debug!("No proxy available, skipping {}", source.describe());
+                continue;
+            };
+            let url = source.url(version, &file_name);
+            let mut result = self.download_file(&client, &url, &temp_path).await;
+            if result.is_ok() {
+                result = self.verify_archive(version, &file_name, &temp_path).await;
+            }
+            match result {
+                Ok(()) => {
+                    *self.progress.write().await = 1.0;
+                    // A failed cache write should not fail the update
+                    if let Err(e) = self.cache.insert(&download_url, &temp_path).await {
+                        tracing::warn!("Failed to cache Xray Core archive: {}", e);
+                    }
+                    return Ok(temp_path);
+                }
+                Err(e) => {
+                    tracing::warn!("Download from {} failed: {}", source.describe(), e);
+                    let _ = fs::remove_file(&temp_path).await;
+                    failures.push(format!("{}: {}", source.describe(), e));
+                    if let XrayError::Integrity(e) = e {
+                        integrity_failure = Some(e);
+                    }
+                }
+            }
+        }
+
+        // A tampered download matters more than unreachable sources
+        if let Some(e) = integrity_failure {
+            return Err(e.into());
+        }
 
-        tracing::info!("Downloading Xray Core from: {}", download_url);
+        if failures.is_empty() {
+            return Err(XrayError::Process(
+                "No download source is available".to_string(),
+            ));
+        }
+        Err(XrayError::Process(format!(
+            "Download failed from every source ({})",
+            failures.join("; ")
+        )))
+    }
 
-        let response = self
-            .client
-            .get(&download_url)
+    /// Download `url` with `client` to `path`
+    async fn download_file(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        path: &Path,
+    ) -> Result<(), XrayError> {
+        tracing::info!("Downloading {}", url);
+
+        let mut response = client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, crate::version::user_agent())
             .send()
             .await
             .map_err(|e| XrayError::Process(format!("Download failed: {}", e)))?;
@@ -230,72 +518,273 @@ impl XrayUpdater {
             )));
         }
 
-        let _total_size = response.content_length().unwrap_or(0);
-        let temp_path = std::env::temp_dir().join(format!("xray-{}.zip", version));
+        let total_size = response.content_length().unwrap_or(0);
+        crate::utils::preflight::preflight(&self.cache_dir, total_size)?;
 
-        let mut file = fs::File::create(&temp_path).await.map_err(XrayError::Io)?;
-        let bytes = response
-            .bytes()
+        let mut file = fs::File::create(path).await.map_err(XrayError::Io)?;
+        let mut downloaded = 0u64;
+        *self.progress.write().await = 0.0;
+        while let Some(chunk) = response
+            .chunk()
             .await
-            .map_err(|e| XrayError::Process(format!("Download error: {}", e)))?;
-
-        file.write_all(&bytes).await.map_err(XrayError::Io)?;
-
-        let downloaded = bytes.len() as u64;
+            .map_err(|e| XrayError::Process(format!("Download error: {}", e)))?
+        {
+            file.write_all(&chunk).await.map_err(XrayError::Io)?;
+            downloaded += chunk.len() as u64;
+            if total_size > 0 {
+                *self.progress.write().await =
+                    downloaded.min(total_size) as f64 / total_size as f64;
+            }
+        }
+        file.flush().await.map_err(XrayError::Io)?;
 
-        let mut p = self.progress.write().await;
-        *p = 1.0;
+        tracing::info!("Downloaded {} bytes to {:?}", downloaded, path);
+        Ok(())
+    }
 
-        file.flush().await.map_err(XrayError::Io)?;
+    /// Check the archive at `path` against the release checksum and, if a
+    /// key is set, its minisign signature
+    ///
+    /// The checksum is fetched from GitHub. A checksum served by a mirror
+    /// only counts when GitHub cannot be reached and a minisign key is set,
+    /// since the signature then vouches for the archive; otherwise the
+    /// archive is rejected.
+    async fn verify_archive(
+        &self,
+        version: &str,
+        file_name: &str,
+        path: &Path,
+    ) -> Result<(), XrayError> {
+        let data = fs::read(path).await.map_err(XrayError::Io)?;
+        let key = self.minisign_public_key();
+
+        let digest_file = format!("{}.dgst", file_name);
+        let mut expected = self
+            .fetch_text_from_github(&github_url(version, &digest_file))
+            .await
+            .as_deref()
+            .and_then(parse_digest);
+        if expected.is_none() && key.is_some() {
+            expected = self
+                .fetch_text_from_mirrors(version, &digest_file)
+                .await
+                .as_deref()
+                .and_then(parse_digest);
+            if expected.is_some() {
+                tracing::warn!(
+                    "GitHub unreachable, checking {} against a mirror's checksum and its signature",
+                    file_name
+                );
+            }
+        }
+        let expected = expected.ok_or_else(|| match key {
+            Some(_) => IntegrityError::ChecksumUnavailable(file_name.to_string()),
+            None => IntegrityError::UntrustedChecksum(file_name.to_string()),
+        })?;
+        verify_checksum(file_name, &data, &expected)?;
+        tracing::info!("Verified checksum of {}", file_name);
+
+        if let Some(key) = key {
+            let signature_file = format!("{}.minisig", file_name);
+            let mut signature = self
+                .fetch_text_from_github(&github_url(version, &signature_file))
+                .await;
+            if signature.is_none() {
+                signature = self.fetch_text_from_mirrors(version, &signature_file).await;
+            }
+            let signature = signature
+                .ok_or_else(|| IntegrityError::SignatureUnavailable(file_name.to_string()))?;
+            key.verify(&data, &signature)?;
+            tracing::info!(
+                "Verified signature of {} by key {}",
+                file_name,
+                key.key_id()
+            );
+        }
+        Ok(())
+    }
 
-        tracing::info!("Downloaded {} bytes to {:?}", downloaded, temp_path);
-        Ok(temp_path)
+    /// Fetch the release file `file_name` of `version` from the first
+    /// source not serving from GitHub that has it
+    async fn fetch_text_from_mirrors(&self, version: &str, file_name: &str) -> Option<String> {
+        let settings = self.download_settings();
+        for source in settings.sources.iter().filter(|source| !source.is_github()) {
+            let Ok(Some(client)) = self.client_for(source, &settings) else {
+                continue;
+            };
+            if let Some(text) = fetch_text(&client, &source.url(version, file_name)).await {
+                return Some(text);
+            }
+        }
+        None
     }
 
-    /// Backup current binary
-    async fn backup_current_binary(&self) -> Result<(), XrayError> {
-        let binary_path = self.get_binary_path();
+    /// Fetch a small text file from GitHub through the sources serving
+    /// from GitHub, None if none of them can
+    async fn fetch_text_from_github(&self, url: &str) -> Option<String> {
+        let settings = self.download_settings();
+        for source in settings.github_sources() {
+            let Ok(Some(client)) = self.client_for(&source, &settings) else {
+                continue;
+            };
+            if let Some(text) = fetch_text(&client, url).await {
+                return Some(text);
+            }
+        }
+        None
+    }
 
-        if binary_path.exists() {
-            let backup_path = binary_path.with_extension("bak");
-            fs::copy(&binary_path, &backup_path)
+    /// Update the geo data files in `dir` to the latest published ones
+    ///
+    /// Files are fetched from GitHub through the GitHub sources and checked
+    /// against the published checksum. Downloads go through the download
+    /// cache, so a file already downloaded is not downloaded again.
+    pub async fn update_geo_assets(&self, dir: &Path) -> Result<(), XrayError> {
+        fs::create_dir_all(dir).await.map_err(XrayError::Io)?;
+        for name in GEO_ASSETS {
+            let url = format!("{}/{}", GEO_ASSETS_BASE_URL, name);
+            let checksum_url = format!("{}.sha256sum", url);
+            let sha256 = self
+                .fetch_text_from_github(&checksum_url)
                 .await
-                .map_err(XrayError::Io)?;
-            tracing::info!("Backed up current binary to {:?}", backup_path);
+                .as_deref()
+                .and_then(parse_sha256sum)
+                .ok_or_else(|| IntegrityError::ChecksumUnavailable(name.to_string()))?;
+            self.install_geo_asset(&url, name, &sha256, dir).await?;
         }
-
         Ok(())
     }
 
-    /// Install binary from downloaded archive
-    async fn install_binary(&self, _archive_path: &Path) -> Result<(), XrayError> {
-        // TODO: Implement ZIP extraction
-        // For now, this is a placeholder
-        tracing::warn!("Binary installation not yet implemented");
+    /// Install the geo data file `name` with checksum `sha256` into `dir`,
+    /// from the cache or else downloaded from `url`
+    async fn install_geo_asset(
+        &self,
+        url: &str,
+        name: &str,
+        sha256: &str,
+        dir: &Path,
+    ) -> Result<(), XrayError> {
+        // The URL always serves the latest file, so the checksum is part of
+        // the key
+        let key = format!("{}#{}", url, sha256);
+        let staging = dir.join(format!(".{}.tmp", name));
+
+        if let Some(cached) = self.cache.get(&key).await? {
+            tracing::info!("Using cached {}", name);
+            fs::copy(&cached, &staging).await.map_err(XrayError::Io)?;
+        } else {
+            self.download_geo_asset(url, &staging).await?;
+            let data = fs::read(&staging).await.map_err(XrayError::Io)?;
+            if let Err(e) = verify_checksum(name, &data, sha256) {
+                let _ = fs::remove_file(&staging).await;
+                return Err(e.into());
+            }
+            if let Err(e) = self.cache.insert(&key, &staging).await {
+                tracing::warn!("Failed to cache {}: {}", name, e);
+            }
+        }
+
+        fs::rename(&staging, dir.join(name))
+            .await
+            .map_err(XrayError::Io)?;
+        tracing::info!("Updated {} in {:?}", name, dir);
         Ok(())
     }
 
-    /// Verify installation
-    async fn verify_installation(&self) -> Result<(), XrayError> {
-        let version = self.get_current_version().await?;
-        if version == "not installed" || version == "unknown" {
+    /// Download a geo data file through the sources serving from GitHub
+    async fn download_geo_asset(&self, url: &str, path: &Path) -> Result<(), XrayError> {
+        let settings = self.download_settings();
+        let mut failures = Vec::new();
+        for source in settings.github_sources() {
+            let Some(client) = self.client_for(&source, &settings)? else {
+                continue;
+            };
+            match self.download_file(&client, url, path).await {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    let _ = fs::remove_file(path).await;
+                    failures.push(format!("{}: {}", source.describe(), e));
+                }
+            }
+        }
+        Err(XrayError::Process(format!(
+            "Download of {} failed ({})",
+            url,
+            failures.join("; ")
+        )))
+    }
+
+    /// Extract the archive into `dir` and check the binary runs
+    async fn install_binary(&self, archive_path: &Path, dir: &Path) -> Result<(), XrayError> {
+        fs::create_dir_all(dir).await.map_err(XrayError::Io)?;
+        extract_archive(archive_path, dir).await?;
+
+        let binary = dir.join(super::versions::binary_name());
+        if !binary.is_file() {
             return Err(XrayError::Process(
-                "Installation verification failed".to_string(),
+                "Archive does not contain the Xray binary".to_string(),
             ));
         }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755))
+                .await
+                .map_err(XrayError::Io)?;
+        }
 
+        let version = binary_version(&binary).await?;
+        if version == "unknown" {
+            return Err(XrayError::Process(
+                "Installation verification failed".to_string(),
+            ));
+        }
         tracing::info!("Installation verified, version: {}", version);
         Ok(())
     }
 
-    /// Get binary path
+    /// Directory updated binaries are installed into
+    pub fn bin_dir(&self) -> &Path {
+        &self.bin_dir
+    }
+
+    /// Get binary path: the selected version, or the binary in the bin
+    /// directory when none is selected
     fn get_binary_path(&self) -> PathBuf {
-        #[cfg(windows)]
-        let binary_name = "xray.exe";
-        #[cfg(not(windows))]
-        let binary_name = "xray";
+        self.versions
+            .active_binary()
+            .unwrap_or_else(|| self.bin_dir.join(super::versions::binary_name()))
+    }
+
+    /// Installed versions
+    pub fn versions(&self) -> &VersionStore {
+        &self.versions
+    }
+
+    /// Version being downloaded and installed, None when idle
+    pub fn installing_version(&self) -> Option<String> {
+        self.installing
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Run the installed `version`, or the bundled binary for None
+    pub async fn select_version(
+        &self,
+        version: Option<&str>,
+    ) -> Result<VersionSelection, XrayError> {
+        self.versions.select(version).await
+    }
+
+    /// Get the download cache
+    pub fn cache(&self) -> &DownloadCache {
+        &self.cache
+    }
 
-        self.bin_dir.join(binary_name)
+    /// Remove all cached downloads, returning the number of bytes freed
+    pub async fn purge_cache(&self) -> Result<u64, XrayError> {
+        self.cache.purge().await
     }
 
     /// Get download progress
@@ -303,20 +792,211 @@ impl XrayUpdater {
         *self.progress.read().await
     }
 
-    /// Rollback to backup
-    pub async fn rollback(&self) -> Result<(), XrayError> {
-        let binary_path = self.get_binary_path();
-        let backup_path = binary_path.with_extension("bak");
+    /// Switch back to the version selected before the last update or
+    /// selection, returning it (None for the bundled binary)
+    pub async fn rollback(&self) -> Result<Option<String>, XrayError> {
+        self.versions.rollback().await
+    }
+}
 
-        if !backup_path.exists() {
-            return Err(XrayError::Process("No backup found".to_string()));
+/// Version reported by the Xray binary at `path`, "unknown" if it cannot be
+/// parsed
+pub(crate) async fn binary_version(path: &Path) -> Result<String, XrayError> {
+    let mut cmd = tokio::process::Command::new(path);
+    cmd.arg("version");
+
+    // On Windows, hide the console window
+    #[cfg(windows)]
+    {
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+    }
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| XrayError::Process(e.to_string()))?;
+
+    if output.status.success() {
+        let version_output = String::from_utf8_lossy(&output.stdout);
+        // Parse version from output like "Xray 1.8.7 (Xray, Penetrates Everything.) Custom"
+        if let Some(line) = version_output.lines().next() {
+            if let Some(version) = line.split_whitespace().nth(1) {
+                return Ok(version.to_string());
+            }
         }
+    }
+
+    Ok("unknown".to_string())
+}
 
-        fs::copy(&backup_path, &binary_path)
+/// Fetch a small text file, None if it cannot be fetched
+async fn fetch_text(client: &reqwest::Client, url: &str) -> Option<String> {
+    let response = client
+        .get(url)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    match response {
+        Ok(response) => response.text().await.ok(),
+        Err(e) => {
+            tracing::debug!("Failed to fetch {}: {}", url, e);
+            None
+        }
+    }
+}
+
+/// SHA-256 checksum from a `sha256sum` output line
+fn parse_sha256sum(content: &str) -> Option<String> {
+    let digest = content.split_whitespace().next()?;
+    (digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| digest.to_ascii_lowercase())
+}
+
+/// Release from a GitHub releases response
+fn parse_release(release: &serde_json::Value) -> Option<XrayRelease> {
+    let version = release["tag_name"].as_str()?.trim_start_matches('v');
+    if !is_valid_version(version) || release["draft"].as_bool() == Some(true) {
+        return None;
+    }
+    Some(XrayRelease {
+        version: version.to_string(),
+        published_at: release["published_at"].as_str().map(str::to_string),
+        prerelease: release["prerelease"].as_bool().unwrap_or(false),
+    })
+}
+
+/// Extract the ZIP archive at `archive` into `dir` with the system's tools
+///
+/// `tar` ships with Windows 10 and reads ZIP archives; elsewhere `unzip` is
+/// used.
+async fn extract_archive(archive: &Path, dir: &Path) -> Result<(), XrayError> {
+    #[cfg(windows)]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("tar");
+        cmd.arg("-xf").arg(archive).arg("-C").arg(dir);
+        const CREATE_NO_WINDOW: u32 = 0x08000000;
+        cmd.creation_flags(CREATE_NO_WINDOW);
+        cmd
+    };
+    #[cfg(not(windows))]
+    let mut cmd = {
+        let mut cmd = tokio::process::Command::new("unzip");
+        cmd.arg("-o").arg("-q").arg(archive).arg("-d").arg(dir);
+        cmd
+    };
+
+    let output = cmd
+        .output()
+        .await
+        .map_err(|e| XrayError::Process(format!("Failed to extract archive: {}", e)))?;
+    if !output.status.success() {
+        return Err(XrayError::Process(format!(
+            "Failed to extract archive: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_release() {
+        let release = serde_json::json!({
+            "tag_name": "v25.1.30",
+            "published_at": "2025-01-30T12:00:00Z",
+            "prerelease": true,
+            "draft": false,
+        });
+        assert_eq!(
+            parse_release(&release),
+            Some(XrayRelease {
+                version: "25.1.30".to_string(),
+                published_at: Some("2025-01-30T12:00:00Z".to_string()),
+                prerelease: true,
+            })
+        );
+        assert_eq!(
+            parse_release(&serde_json::json!({"tag_name": "nightly"})),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_sha256sum() {
+        let digest = "a".repeat(64);
+        assert_eq!(
+            parse_sha256sum(&format!("{}  geoip.dat\n", digest.to_uppercase())),
+            Some(digest)
+        );
+        assert_eq!(parse_sha256sum("not a checksum"), None);
+    }
+
+    #[tokio::test]
+    async fn test_geo_asset_installed_from_cache() {
+        use sha2::{Digest, Sha256};
+
+        let dir = tempfile::tempdir().unwrap();
+        let updater = XrayUpdater::new(dir.path().join("bin"), dir.path().join("cache"));
+        let source = dir.path().join("geoip.dat");
+        fs::write(&source, b"geo data").await.unwrap();
+        let sha256 = hex::encode(Sha256::digest(b"geo data"));
+        // Nothing listens on this URL, so the file must come from the cache
+        let url = "http://127.0.0.1:9/geoip.dat";
+        updater
+            .cache()
+            .insert(&format!("{}#{}", url, sha256), &source)
             .await
-            .map_err(XrayError::Io)?;
+            .unwrap();
 
-        tracing::info!("Rolled back to backup version");
-        Ok(())
+        let assets = dir.path().join("assets");
+        fs::create_dir_all(&assets).await.unwrap();
+        updater
+            .install_geo_asset(url, "geoip.dat", &sha256, &assets)
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read(assets.join("geoip.dat")).await.unwrap(),
+            b"geo data"
+        );
+        assert!(!assets.join(".geoip.dat.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn test_cached_archive_needs_trusted_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let updater = XrayUpdater::new(dir.path().join("bin"), dir.path().join("cache"));
+        // GitHub only through a proxy nothing listens on
+        updater
+            .set_download_settings(DownloadSettings {
+                sources: vec![DownloadSource::Proxy],
+                proxy_url: Some("http://127.0.0.1:9".to_string()),
+            })
+            .unwrap();
+        let archive = dir.path().join("xray.zip");
+        fs::write(&archive, b"not verified").await.unwrap();
+        let url = github_url("25.1.30", &updater.archive_name().unwrap());
+        updater.cache().insert(&url, &archive).await.unwrap();
+
+        assert!(matches!(
+            updater.download_xray("25.1.30").await,
+            Err(XrayError::Integrity(IntegrityError::UntrustedChecksum(_)))
+        ));
+        assert!(!dir.path().join("cache").join("xray-25.1.30.zip").exists());
+    }
+
+    #[tokio::test]
+    async fn test_install_version_rejects_invalid_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let updater = XrayUpdater::new(dir.path().join("bin"), dir.path().join("cache"));
+        assert!(matches!(
+            updater.install_version("../../etc").await,
+            Err(XrayError::Config(_))
+        ));
+        assert!(!dir.path().join("bin").exists());
     }
 }
@@ -0,0 +1,284 @@
+//! Installed Xray Core versions
+//!
+//! Every version installed by the updater lives in its own directory,
+//! `bin/versions/<version>/`, so several versions can be kept side by side.
+//! Which one runs is recorded in `bin/versions/selection.json` together with
+//! the previously selected version: switching versions or rolling back only
+//! rewrites that file and never downloads anything.
+
+use super::XrayError;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+/// File recording the selected version
+const SELECTION_FILE: &str = "selection.json";
+
+/// Selected and previously selected version
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionSelection {
+    /// Version that runs, None for the bundled binary
+    #[serde(default)]
+    pub active: Option<String>,
+    /// Version selected before, which rolling back returns to
+    #[serde(default)]
+    pub previous: Option<String>,
+}
+
+/// Xray Core versions installed side by side
+#[derive(Debug, Clone)]
+pub struct VersionStore {
+    /// `bin/versions` directory
+    root: PathBuf,
+}
+
+impl VersionStore {
+    /// Store in `root`
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    /// Directory holding the versions
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory of `version`
+    pub fn version_dir(&self, version: &str) -> PathBuf {
+        self.root.join(version)
+    }
+
+    /// Xray binary of `version`
+    pub fn binary_path(&self, version: &str) -> PathBuf {
+        self.version_dir(version).join(binary_name())
+    }
+
+    /// Whether `version` is installed
+    pub fn is_installed(&self, version: &str) -> bool {
+        is_valid_version(version) && self.binary_path(version).is_file()
+    }
+
+    /// Installed versions, newest first
+    pub fn installed(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.root) else {
+            return Vec::new();
+        };
+        let mut versions: Vec<String> = entries
+            .flatten()
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|version| self.is_installed(version))
+            .collect();
+        versions.sort_by(|a, b| compare_versions(b, a));
+        versions
+    }
+
+    /// Selected and previously selected version
+    ///
+    /// Versions that were removed since are reported as None.
+    pub fn selection(&self) -> VersionSelection {
+        let selection: VersionSelection = std::fs::read(self.root.join(SELECTION_FILE))
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let installed = |version: Option<String>| version.filter(|v| self.is_installed(v));
+        VersionSelection {
+            active: installed(selection.active),
+            previous: installed(selection.previous),
+        }
+    }
+
+    /// Binary of the selected version, None when the bundled binary runs
+    pub fn active_binary(&self) -> Option<PathBuf> {
+        self.selection()
+            .active
+            .map(|version| self.binary_path(&version))
+    }
+
+    /// Run `version`, or the bundled binary for None
+    pub async fn select(&self, version: Option<&str>) -> Result<VersionSelection, XrayError> {
+        if let Some(version) = version {
+            if !self.is_installed(version) {
+                return Err(XrayError::Config(format!(
+                    "Xray Core {} is not installed",
+                    version
+                )));
+            }
+        }
+        let current = self.selection();
+        if current.active.as_deref() == version {
+            return Ok(current);
+        }
+        let selection = VersionSelection {
+            active: version.map(str::to_string),
+            previous: current.active,
+        };
+        self.save_selection(&selection).await?;
+        tracing::info!(
+            "Selected Xray Core {}",
+            version.unwrap_or("bundled with the app")
+        );
+        Ok(selection)
+    }
+
+    /// Switch back to the previously selected version
+    ///
+    /// Returns the version now running, None for the bundled binary.
+    pub async fn rollback(&self) -> Result<Option<String>, XrayError> {
+        let current = self.selection();
+        if current.previous.is_none() && current.active.is_none() {
+            return Err(XrayError::Process(
+                "No previous Xray Core version to roll back to".to_string(),
+            ));
+        }
+        let selection = VersionSelection {
+            active: current.previous,
+            previous: current.active,
+        };
+        self.save_selection(&selection).await?;
+        tracing::info!(
+            "Rolled back to Xray Core {}",
+            selection
+                .active
+                .as_deref()
+                .unwrap_or("bundled with the app")
+        );
+        Ok(selection.active)
+    }
+
+    /// Remove `version`, which must not be selected
+    pub async fn remove(&self, version: &str) -> Result<(), XrayError> {
+        if !self.is_installed(version) {
+            return Err(XrayError::NotFound);
+        }
+        let mut selection = self.selection();
+        if selection.active.as_deref() == Some(version) {
+            return Err(XrayError::Config(format!(
+                "Xray Core {} is selected and cannot be removed",
+                version
+            )));
+        }
+        fs::remove_dir_all(self.version_dir(version)).await?;
+        if selection.previous.as_deref() == Some(version) {
+            selection.previous = None;
+            self.save_selection(&selection).await?;
+        }
+        tracing::info!("Removed Xray Core {}", version);
+        Ok(())
+    }
+
+    /// Write the selection file
+    async fn save_selection(&self, selection: &VersionSelection) -> Result<(), XrayError> {
+        fs::create_dir_all(&self.root).await?;
+        let data =
+            serde_json::to_vec_pretty(selection).map_err(|e| XrayError::Config(e.to_string()))?;
+        let path = self.root.join(SELECTION_FILE);
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, data).await?;
+        fs::rename(&temp, &path).await?;
+        Ok(())
+    }
+}
+
+/// File name of the Xray binary
+pub(crate) fn binary_name() -> &'static str {
+    #[cfg(windows)]
+    return "xray.exe";
+    #[cfg(not(windows))]
+    return "xray";
+}
+
+/// Whether `version` is a release version like "1.8.24"
+///
+/// Versions name directories, so anything else is rejected.
+pub fn is_valid_version(version: &str) -> bool {
+    let mut parts = 0;
+    for part in version.split('.') {
+        if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+            return false;
+        }
+        parts += 1;
+    }
+    (2..=4).contains(&parts)
+}
+
+/// Compare two release versions numerically, part by part
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    (0..a.len().max(b.len()))
+        .map(|i| {
+            let a = a.get(i).copied().unwrap_or(0);
+            let b = b.get(i).copied().unwrap_or(0);
+            a.cmp(&b)
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn install(store: &VersionStore, version: &str) {
+        std::fs::create_dir_all(store.version_dir(version)).unwrap();
+        std::fs::write(store.binary_path(version), b"").unwrap();
+    }
+
+    #[test]
+    fn test_versions() {
+        assert!(is_valid_version("1.8.24"));
+        assert!(is_valid_version("25.1.30"));
+        assert!(!is_valid_version("1"));
+        assert!(!is_valid_version("../1.8"));
+        assert!(!is_valid_version("1.8.0-rc1"));
+
+        assert_eq!(compare_versions("1.8.10", "1.8.9"), Ordering::Greater);
+        assert_eq!(compare_versions("v1.8", "1.8.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.8.24", "25.1.30"), Ordering::Less);
+    }
+
+    #[tokio::test]
+    async fn test_select_and_rollback() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = VersionStore::new(dir.path().join("versions"));
+        assert!(store.installed().is_empty());
+        assert!(store.select(Some("1.8.24")).await.is_err());
+
+        install(&store, "1.8.9");
+        install(&store, "1.8.24");
+        std::fs::create_dir_all(store.version_dir("broken")).unwrap();
+        assert_eq!(store.installed(), vec!["1.8.24", "1.8.9"]);
+
+        store.select(Some("1.8.9")).await.unwrap();
+        store.select(Some("1.8.24")).await.unwrap();
+        assert_eq!(store.active_binary(), Some(store.binary_path("1.8.24")));
+
+        // Rolling back swaps the selection
+        assert_eq!(store.rollback().await.unwrap().as_deref(), Some("1.8.9"));
+        assert_eq!(
+            store.selection(),
+            VersionSelection {
+                active: Some("1.8.9".to_string()),
+                previous: Some("1.8.24".to_string()),
+            }
+        );
+
+        // The selected version cannot be removed, the previous one can
+        assert!(store.remove("1.8.9").await.is_err());
+        store.remove("1.8.24").await.unwrap();
+        assert_eq!(store.selection().previous, None);
+        assert_eq!(store.installed(), vec!["1.8.9"]);
+
+        // Back to the bundled binary
+        store.select(None).await.unwrap();
+        assert_eq!(store.active_binary(), None);
+        assert_eq!(store.rollback().await.unwrap().as_deref(), Some("1.8.9"));
+    }
+}
@@ -181,9 +181,13 @@ async fn test_backup_restore_workflow() {
     let config = manager.get_config().await;
     assert_eq!(config.proxy.http_port, 8080);
 
+    // Restoring backs up the replaced state first
+    let backups = manager.list_backups().unwrap();
+    assert_eq!(backups.len(), 3);
+
     // Cleanup old backups
     let deleted = manager.cleanup_old_backups(1).unwrap();
-    assert_eq!(deleted, 1);
+    assert_eq!(deleted, 2);
 
     let backups = manager.list_backups().unwrap();
     assert_eq!(backups.len(), 1);
@@ -273,5 +277,6 @@ fn create_test_proxy(id: &str, name: &str, server: &str, port: u16) -> ProxyServ
         tags: vec![],
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        raw_name: None,
     }
 }
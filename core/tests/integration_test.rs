@@ -72,6 +72,7 @@ async fn test_connection_integration() {
         tags: vec![],
         created_at: chrono::Utc::now(),
         updated_at: chrono::Utc::now(),
+        raw_name: None,
     };
 
     // Test connection flow (will fail without Xray binary)
@@ -117,6 +117,7 @@ async fn test_subscription_scheduler_integration() {
         update_interval_hours: 24,
         update_on_startup: false,
         max_concurrent_updates: 3,
+        ..Default::default()
     };
     let scheduler = SubscriptionScheduler::new(config);
 
@@ -59,7 +59,7 @@ async fn test_parse_real_trojan_url() {
         vec!["http/1.1".to_string()],
         "ALPN should be http/1.1"
     );
-    assert_eq!(tls.allow_insecure, false);
+    assert!(!tls.allow_insecure);
 }
 
 #[tokio::test]
@@ -93,7 +93,6 @@ async fn test_trojan_url_parsing_minimal() {
 
 #[tokio::test]
 async fn test_trojan_storage_preserves_stream_settings() {
-    use chrono::Utc;
     use uuid::Uuid;
     use v8ray_core::subscription::{Subscription, SubscriptionStatus, SubscriptionStorage};
 
@@ -111,6 +110,7 @@ async fn test_trojan_storage_preserves_stream_settings() {
         last_update: None,
         server_count: 0,
         status: SubscriptionStatus::Inactive,
+        request: Default::default(),
     };
     storage
         .save_subscription(&subscription)
@@ -131,6 +131,8 @@ async fn test_trojan_storage_preserves_stream_settings() {
         config: config.settings.clone(),
         stream_settings: config.stream_settings.clone(),
         subscription_id: sub_id,
+        tags: vec![],
+        raw_name: None,
     };
 
     // Save the server
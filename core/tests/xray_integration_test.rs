@@ -46,6 +46,7 @@ async fn test_xray_config_generation() {
         tags: vec![],
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        raw_name: None,
     };
 
     let config = xray.generate_config(&vmess_config);
@@ -76,6 +77,7 @@ async fn test_xray_config_generation() {
         tags: vec![],
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        raw_name: None,
     };
 
     let config = xray.generate_config(&vless_config);
@@ -96,6 +98,7 @@ async fn test_xray_config_generation() {
         tags: vec![],
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        raw_name: None,
     };
 
     let config = xray.generate_config(&trojan_config);
@@ -117,6 +120,7 @@ async fn test_xray_config_generation() {
         tags: vec![],
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        raw_name: None,
     };
 
     let config = xray.generate_config(&ss_config);
@@ -171,6 +175,8 @@ async fn test_xray_health_monitoring() {
         uptime: 120,
         last_check: std::time::SystemTime::now(),
         is_responsive: true,
+        memory_bytes: None,
+        cpu_percent: None,
     };
 
     let json = serde_json::to_string(&health).unwrap();
@@ -268,6 +274,8 @@ async fn test_xray_config_with_stream_settings() {
         http_settings: None,
         quic_settings: None,
         grpc_settings: None,
+        mux: None,
+        fragment: None,
     };
 
     let config = ProxyServerConfig {
@@ -281,6 +289,7 @@ async fn test_xray_config_with_stream_settings() {
         tags: vec!["test".to_string()],
         created_at: Utc::now(),
         updated_at: Utc::now(),
+        raw_name: None,
     };
 
     let xray_config = xray.generate_config(&config);